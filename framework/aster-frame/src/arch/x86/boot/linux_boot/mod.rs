@@ -15,7 +15,7 @@ use crate::{
         memory_region::{non_overlapping_regions_from, MemoryRegion, MemoryRegionType},
         BootloaderAcpiArg, BootloaderFramebufferArg,
     },
-    mm::kspace::{paddr_to_vaddr, LINEAR_MAPPING_BASE_VADDR},
+    mm::kspace::{paddr_to_vaddr, BOOT_LINEAR_MAPPING_BASE_VADDR},
 };
 
 static BOOT_PARAMS: Once<BootParams> = Once::new();
@@ -73,10 +73,12 @@ fn init_initramfs(initramfs: &'static Once<&'static [u8]>) {
         return;
     }
     // We must return a slice composed by VA since kernel should read everything in VA.
-    let base_va = if ptr < LINEAR_MAPPING_BASE_VADDR {
+    let base_va = if ptr < BOOT_LINEAR_MAPPING_BASE_VADDR {
         paddr_to_vaddr(ptr)
     } else {
-        ptr
+        // The address is in the linear mapping of the boot page table, which is
+        // not the one used by the kernel.
+        paddr_to_vaddr(ptr - BOOT_LINEAR_MAPPING_BASE_VADDR)
     };
     let length = hdr.ramdisk_size as usize;
     if length == 0 {
@@ -149,6 +151,9 @@ fn init_memory_regions(memory_regions: &'static Once<Vec<MemoryRegion>>) {
 /// The entry point of the Rust code portion of Asterinas.
 #[no_mangle]
 unsafe extern "sysv64" fn __linux_boot(params_ptr: *const BootParams) -> ! {
+    // SAFETY: this is the entry of the Rust code, and no address in the linear mapping
+    // has been taken.
+    unsafe { crate::mm::kspace::randomize_linear_mapping() };
     let params = *params_ptr;
     assert_eq!({ params.hdr.header }, LINUX_BOOT_HEADER_MAGIC);
     BOOT_PARAMS.call_once(|| params);
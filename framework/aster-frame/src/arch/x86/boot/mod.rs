@@ -21,7 +21,9 @@
 mod linux_boot;
 mod multiboot;
 mod multiboot2;
+pub(crate) mod smp;
 
 use core::arch::global_asm;
 
 global_asm!(include_str!("boot.S"));
+global_asm!(include_str!("ap_boot.S"));
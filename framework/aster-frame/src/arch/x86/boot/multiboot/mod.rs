@@ -12,7 +12,7 @@ use crate::{
         memory_region::{non_overlapping_regions_from, MemoryRegion, MemoryRegionType},
         BootloaderAcpiArg, BootloaderFramebufferArg,
     },
-    mm::kspace::{paddr_to_vaddr, LINEAR_MAPPING_BASE_VADDR},
+    mm::kspace::{paddr_to_vaddr, BOOT_LINEAR_MAPPING_BASE_VADDR},
 };
 
 global_asm!(include_str!("header.S"));
@@ -76,10 +76,12 @@ fn init_initramfs(initramfs: &'static Once<&'static [u8]>) {
         )
     };
     // We must return a slice composed by VA since kernel should read every in VA.
-    let base_va = if start < LINEAR_MAPPING_BASE_VADDR {
+    let base_va = if start < BOOT_LINEAR_MAPPING_BASE_VADDR {
         paddr_to_vaddr(start)
     } else {
-        start
+        // The address is in the linear mapping of the boot page table, which is
+        // not the one used by the kernel.
+        paddr_to_vaddr(start - BOOT_LINEAR_MAPPING_BASE_VADDR)
     };
     let length = end - start;
     initramfs.call_once(|| unsafe { core::slice::from_raw_parts(base_va as *const u8, length) });
@@ -330,6 +332,9 @@ static MB1_INFO: Once<&'static MultibootLegacyInfo> = Once::new();
 /// The entry point of Rust code called by inline asm.
 #[no_mangle]
 unsafe extern "sysv64" fn __multiboot_entry(boot_magic: u32, boot_params: u64) -> ! {
+    // SAFETY: this is the entry of the Rust code, and no address in the linear mapping
+    // has been taken.
+    unsafe { crate::mm::kspace::randomize_linear_mapping() };
     assert_eq!(boot_magic, MULTIBOOT_ENTRY_MAGIC);
     MB1_INFO.call_once(|| &*(paddr_to_vaddr(boot_params as usize) as *const MultibootLegacyInfo));
     crate::boot::register_boot_init_callbacks(
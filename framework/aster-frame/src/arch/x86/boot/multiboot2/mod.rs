@@ -156,6 +156,9 @@ fn init_memory_regions(memory_regions: &'static Once<Vec<MemoryRegion>>) {
 /// The entry point of Rust code called by inline asm.
 #[no_mangle]
 unsafe extern "sysv64" fn __multiboot2_entry(boot_magic: u32, boot_params: u64) -> ! {
+    // SAFETY: this is the entry of the Rust code, and no address in the linear mapping
+    // has been taken.
+    unsafe { crate::mm::kspace::randomize_linear_mapping() };
     assert_eq!(boot_magic, MULTIBOOT2_ENTRY_MAGIC);
     MB2_INFO.call_once(|| unsafe {
         BootInformation::load(boot_params as *const BootInformationHeader).unwrap()
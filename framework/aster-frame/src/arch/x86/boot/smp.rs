@@ -0,0 +1,304 @@
+// SPDX-License-Identifier: MPL-2.0
+
+//! Starting the application processors (APs).
+//!
+//! The bootstrap processor (BSP) starts the APs described by the ACPI MADT one by one
+//! with the INIT-SIPI-SIPI sequence, after the kernel page table is activated. The APs
+//! start in the real mode at the trampoline in `ap_boot.S`, which is copied to a page below
+//! 1 MiB that is reserved in the boot stage. The CPU IDs of the APs are assigned in the
+//! order that they come online, and the BSP is the CPU 0.
+//!
+//! An AP runs its idle loop once it is initialized, so it picks the tasks as soon as the
+//! scheduler is set.
+
+use alloc::vec::Vec;
+use core::{
+    mem::size_of,
+    sync::atomic::{fence, AtomicBool, Ordering},
+};
+
+use acpi::{platform::ProcessorState, PlatformInfo};
+use log::{info, warn};
+use spin::Once;
+
+use crate::{
+    arch::x86::{
+        cpu::{has_rdtscp, set_apic_id, set_num_cpus, set_this_cpu_id},
+        enable_common_cpu_features,
+        kernel::{
+            acpi::ACPI_TABLES,
+            apic::{Apic, APIC_INSTANCE},
+        },
+        mm::{current_page_table_paddr, tlb_flush_all_including_global},
+        read_tsc, timer, tsc_freq,
+    },
+    cpu::MAX_CPUS,
+    mm::{kspace::kernel_loaded_offset, memblock, paddr_to_vaddr, FrameAllocOptions, Paddr},
+    task::run_idle_loop,
+    trap,
+};
+
+/// The trampoline must be below 1 MiB, since its page number is given by the 8-bit vector
+/// of the startup IPI.
+const TRAMPOLINE_LIMIT: Paddr = 0x10_0000;
+
+/// The number of the frames of the stack of each AP.
+const AP_STACK_FRAMES: usize = 64;
+
+/// The time to wait after the INIT IPI, as recommended by Intel.
+const INIT_DELAY_US: u64 = 10_000;
+/// The time to wait for an AP after each startup IPI, as recommended by Intel.
+const STARTUP_DELAY_US: u64 = 200;
+/// The time to wait for an AP to come online before giving up.
+const ONLINE_TIMEOUT_US: u64 = 100_000;
+
+/// The physical address of the page of the trampoline.
+static TRAMPOLINE: Once<Paddr> = Once::new();
+
+/// Whether the AP that is being started has come online.
+static AP_ONLINE: AtomicBool = AtomicBool::new(false);
+
+/// The boot information of an AP, which is at `__ap_boot_info` in the trampoline.
+#[repr(C)]
+struct ApBootInfo {
+    /// The physical address of the page table set up in `boot.S`.
+    boot_cr3: u64,
+    /// The physical address of the kernel page table.
+    kernel_cr3: u64,
+    /// The virtual address of the top of the stack of the AP.
+    stack_top: u64,
+    /// The CPU ID of the AP.
+    cpu_id: u64,
+    /// The virtual address of `ap_long_mode`.
+    long_mode: u64,
+}
+
+extern "C" {
+    fn __ap_trampoline_start();
+    fn __ap_trampoline_end();
+    fn __ap_boot_info();
+    fn ap_long_mode();
+    fn boot_page_table_start();
+}
+
+/// Reserves a page below 1 MiB for the trampoline.
+///
+/// This function should be called right after the boot memory allocator is initialized,
+/// before the low memory is taken by other allocations.
+pub(crate) fn reserve_trampoline() {
+    match memblock::alloc_below(1, TRAMPOLINE_LIMIT) {
+        Some(paddr) => {
+            TRAMPOLINE.call_once(|| paddr);
+        }
+        None => warn!("[SMP]: No memory below 1 MiB for the trampoline of the APs"),
+    }
+}
+
+/// Starts all the APs and waits for them to come online.
+///
+/// This function should be called on the BSP after the kernel page table is activated
+/// and the timer is initialized.
+pub(crate) fn boot_all_aps() {
+    let Some(&trampoline) = TRAMPOLINE.get() else {
+        return;
+    };
+    let Some(apic) = APIC_INSTANCE.get() else {
+        return;
+    };
+    // The CPU IDs are read with `RDTSCP`.
+    if !has_rdtscp() {
+        warn!("[SMP]: The APs are not started since RDTSCP is not supported");
+        return;
+    }
+
+    let bsp_apic_id = apic.lock_irq_disabled().id();
+    let ap_apic_ids = ap_apic_ids(bsp_apic_id);
+    if ap_apic_ids.is_empty() {
+        return;
+    }
+
+    set_apic_id(0, bsp_apic_id);
+    set_this_cpu_id(0);
+
+    let trampoline_len = __ap_trampoline_end as usize - __ap_trampoline_start as usize;
+    assert!(trampoline_len <= crate::mm::PAGE_SIZE);
+    // SAFETY: The trampoline page is reserved for the trampoline, and the trampoline is
+    // in the kernel image, which is readable.
+    unsafe {
+        core::ptr::copy_nonoverlapping(
+            __ap_trampoline_start as usize as *const u8,
+            paddr_to_vaddr(trampoline) as *mut u8,
+            trampoline_len,
+        );
+    }
+
+    let mut nr_cpus = 1;
+    for apic_id in ap_apic_ids {
+        if nr_cpus as usize == MAX_CPUS {
+            warn!("[SMP]: The CPUs beyond the first {} are ignored", MAX_CPUS);
+            break;
+        }
+        if !start_ap(trampoline, nr_cpus, apic_id) {
+            warn!(
+                "[SMP]: The AP with the APIC ID {} does not respond",
+                apic_id
+            );
+            break;
+        }
+        nr_cpus += 1;
+        set_num_cpus(nr_cpus);
+    }
+    info!("[SMP]: {} CPUs are online", nr_cpus);
+}
+
+/// Returns the local APIC IDs of the APs that can be started, according to the MADT.
+fn ap_apic_ids(bsp_apic_id: u32) -> Vec<u32> {
+    let Some(tables) = ACPI_TABLES.get() else {
+        return Vec::new();
+    };
+    let tables = tables.lock();
+    let Some(processor_info) = PlatformInfo::new(&*tables)
+        .ok()
+        .and_then(|platform_info| platform_info.processor_info)
+    else {
+        return Vec::new();
+    };
+
+    core::iter::once(&processor_info.boot_processor)
+        .chain(processor_info.application_processors.iter())
+        .filter(|processor| processor.state != ProcessorState::Disabled)
+        .map(|processor| processor.local_apic_id)
+        .filter(|apic_id| *apic_id != bsp_apic_id)
+        .collect()
+}
+
+/// Starts the AP with the local APIC ID as the CPU `cpu_id`.
+///
+/// Returns whether the AP has come online.
+fn start_ap(trampoline: Paddr, cpu_id: u32, apic_id: u32) -> bool {
+    let stack = match FrameAllocOptions::new(AP_STACK_FRAMES).alloc_contiguous() {
+        Ok(stack) => stack,
+        Err(_) => return false,
+    };
+    let stack_top = paddr_to_vaddr(stack.end_paddr());
+    // The stack is used by the AP forever.
+    core::mem::forget(stack);
+
+    let boot_info = ApBootInfo {
+        boot_cr3: (boot_page_table_start as usize - kernel_loaded_offset()) as u64,
+        kernel_cr3: current_page_table_paddr() as u64,
+        stack_top: stack_top as u64,
+        cpu_id: cpu_id as u64,
+        long_mode: ap_long_mode as usize as u64,
+    };
+    let boot_info_offset = __ap_boot_info as usize - __ap_trampoline_start as usize;
+    debug_assert!(boot_info_offset + size_of::<ApBootInfo>() <= crate::mm::PAGE_SIZE);
+    // SAFETY: The boot information is in the trampoline page, which is not used by any
+    // AP now, and it is properly aligned by `ap_boot.S`.
+    unsafe {
+        ((paddr_to_vaddr(trampoline) + boot_info_offset) as *mut ApBootInfo).write(boot_info);
+    }
+
+    set_apic_id(cpu_id, apic_id);
+    AP_ONLINE.store(false, Ordering::Relaxed);
+    // The AP must see the boot information, and the writes to the x2APIC ICR, which is a
+    // MSR, are not ordered with the memory writes.
+    fence(Ordering::SeqCst);
+
+    let page = (trampoline / crate::mm::PAGE_SIZE) as u8;
+    let apic = APIC_INSTANCE.get().unwrap();
+    apic.lock_irq_disabled().send_init_ipi(apic_id);
+    delay_us(INIT_DELAY_US);
+    for _ in 0..2 {
+        apic.lock_irq_disabled().send_startup_ipi(apic_id, page);
+        if wait_online(STARTUP_DELAY_US) {
+            return true;
+        }
+    }
+    if wait_online(ONLINE_TIMEOUT_US) {
+        return true;
+    }
+
+    // Reset the AP so that it does not run with the boot information of another AP.
+    apic.lock_irq_disabled().send_init_ipi(apic_id);
+    false
+}
+
+/// Waits at most `us` microseconds for the AP that is being started to come online.
+fn wait_online(us: u64) -> bool {
+    let deadline = read_tsc() + tsc_freq() * us / 1_000_000;
+    while read_tsc() < deadline {
+        if AP_ONLINE.load(Ordering::Acquire) {
+            return true;
+        }
+        core::hint::spin_loop();
+    }
+    AP_ONLINE.load(Ordering::Acquire)
+}
+
+/// Busy-waits for `us` microseconds.
+fn delay_us(us: u64) {
+    let deadline = read_tsc() + tsc_freq() * us / 1_000_000;
+    while read_tsc() < deadline {
+        core::hint::spin_loop();
+    }
+}
+
+/// The Rust entrypoint of the APs, which is called by `ap_long_mode` in `ap_boot.S` with
+/// the kernel page table and the stack of the AP.
+#[no_mangle]
+extern "sysv64" fn __ap_entry(cpu_id: u32) -> ! {
+    // The CPU ID must be set before any CPU-local object is accessed, e.g., by a lock.
+    set_this_cpu_id(cpu_id);
+
+    // The TLB may still have the global entries of the page table set up in `boot.S`.
+    tlb_flush_all_including_global();
+    enable_common_cpu_features();
+    trap::init_on_ap();
+    APIC_INSTANCE.get().unwrap().lock_irq_disabled().enable();
+    timer::init_on_ap();
+
+    AP_ONLINE.store(true, Ordering::Release);
+    run_idle_loop()
+}
+
+#[cfg(ktest)]
+mod test {
+    use core::sync::atomic::AtomicU32;
+
+    use super::*;
+    use crate::{
+        arch::x86::cpu::{apic_id, num_cpus, send_ipi, this_cpu},
+        cpu_local,
+        trap::IrqLine,
+    };
+
+    cpu_local! {
+        static SEEN_CPU_ID: AtomicU32 = AtomicU32::new(u32::MAX);
+    }
+
+    #[ktest]
+    fn distinct_apic_ids() {
+        let mut apic_ids: Vec<u32> = (0..num_cpus()).map(apic_id).collect();
+        apic_ids.sort_unstable();
+        apic_ids.dedup();
+        assert_eq!(apic_ids.len(), num_cpus() as usize);
+    }
+
+    #[ktest]
+    fn ipi_reaches_each_cpu() {
+        let mut irq = IrqLine::alloc().unwrap();
+        irq.on_active(|_| SEEN_CPU_ID.store(this_cpu(), Ordering::Release));
+
+        for cpu_id in 0..num_cpus() {
+            send_ipi(cpu_id, irq.num());
+        }
+        for cpu_id in 0..num_cpus() {
+            let deadline = read_tsc() + tsc_freq();
+            while SEEN_CPU_ID.get_on_cpu(cpu_id).load(Ordering::Acquire) != cpu_id {
+                assert!(read_tsc() < deadline, "CPU {} misses the IPI", cpu_id);
+                core::hint::spin_loop();
+            }
+        }
+    }
+}
@@ -4,8 +4,12 @@
 
 use alloc::vec::Vec;
 use core::{
-    arch::x86_64::{_fxrstor, _fxsave},
+    arch::{
+        asm,
+        x86_64::{__cpuid, __cpuid_count, _fxrstor, _fxsave},
+    },
     fmt::Debug,
+    sync::atomic::{AtomicBool, AtomicU32, Ordering},
 };
 
 use bitflags::bitflags;
@@ -14,32 +18,157 @@ use bitvec::{
     slice::IterOnes,
 };
 use log::debug;
+use spin::Once;
 #[cfg(feature = "intel_tdx")]
 use tdx_guest::tdcall;
 use trapframe::{GeneralRegs, UserContext as RawUserContext};
-use x86_64::registers::rflags::RFlags;
+use x86_64::registers::{
+    control::{Cr4, Cr4Flags},
+    rflags::RFlags,
+};
 
 #[cfg(feature = "intel_tdx")]
 use crate::arch::tdx_guest::{handle_virtual_exception, TdxTrapFrame};
 use crate::{
+    arch::x86::kernel::{acpi::srat::Srat, apic::APIC_INSTANCE},
+    cpu::MAX_CPUS,
     trap::call_irq_callback_functions,
     user::{ReturnReason, UserContextApi, UserContextApiInternal},
 };
 
+/// The number of the CPUs that are online.
+static NUM_CPUS: AtomicU32 = AtomicU32::new(1);
+/// The local APIC IDs of the CPUs, indexed by the CPU IDs.
+static APIC_IDS: [AtomicU32; MAX_CPUS] = {
+    #[allow(clippy::declare_interior_mutable_const)]
+    const ZERO: AtomicU32 = AtomicU32::new(0);
+    [ZERO; MAX_CPUS]
+};
+/// Whether the CPU IDs are stored in the `IA32_TSC_AUX` MSRs.
+///
+/// This is set before the application processors are started, so that all the CPUs
+/// except the bootstrap processor, whose ID is 0, can read their IDs by `RDTSCP`.
+static CPU_ID_IN_TSC_AUX: AtomicBool = AtomicBool::new(false);
+
 /// Returns the number of CPUs.
 pub fn num_cpus() -> u32 {
-    // FIXME: we only start one cpu now.
-    1
+    NUM_CPUS.load(Ordering::Acquire)
 }
 
 /// Returns the ID of this CPU.
 pub fn this_cpu() -> u32 {
-    // FIXME: we only start one cpu now.
-    0
+    if !CPU_ID_IN_TSC_AUX.load(Ordering::Relaxed) {
+        return 0;
+    }
+    let mut cpu_id = 0;
+    // SAFETY: `RDTSCP` is supported since the CPU IDs are stored in `IA32_TSC_AUX`.
+    unsafe { core::arch::x86_64::__rdtscp(&mut cpu_id) };
+    cpu_id
+}
+
+/// Returns whether the `RDTSCP` instruction is supported, which is required to start
+/// the application processors.
+pub(crate) fn has_rdtscp() -> bool {
+    // SAFETY: It is safe to read the extended processor features.
+    unsafe { __cpuid(0x8000_0000).eax >= 0x8000_0001 && __cpuid(0x8000_0001).edx & (1 << 27) != 0 }
+}
+
+/// Stores the ID of this CPU, which is then returned by [`this_cpu`].
+///
+/// This must be called on each CPU before any CPU-local object is accessed on it.
+pub(crate) fn set_this_cpu_id(cpu_id: u32) {
+    // SAFETY: `IA32_TSC_AUX` is only used to store the CPU ID.
+    unsafe { x86::msr::wrmsr(x86::msr::IA32_TSC_AUX, cpu_id as u64) };
+    CPU_ID_IN_TSC_AUX.store(true, Ordering::Relaxed);
+}
+
+/// Returns the local APIC ID of the CPU.
+pub(crate) fn apic_id(cpu_id: u32) -> u32 {
+    debug_assert!(cpu_id < num_cpus());
+    APIC_IDS[cpu_id as usize].load(Ordering::Relaxed)
+}
+
+/// Records the local APIC ID of the CPU.
+pub(crate) fn set_apic_id(cpu_id: u32, apic_id: u32) {
+    APIC_IDS[cpu_id as usize].store(apic_id, Ordering::Relaxed);
+}
+
+/// Records that the CPUs whose IDs are less than `num_cpus` are online.
+pub(crate) fn set_num_cpus(num_cpus: u32) {
+    debug_assert!(num_cpus as usize <= MAX_CPUS);
+    NUM_CPUS.store(num_cpus, Ordering::Release);
+}
+
+/// Sends an inter-processor interrupt of the IRQ number to the CPU.
+pub(crate) fn send_ipi(cpu_id: u32, irq_num: u8) {
+    let apic_id = apic_id(cpu_id);
+    if let Some(apic) = APIC_INSTANCE.get() {
+        apic.lock_irq_disabled().send_ipi(apic_id, irq_num);
+    }
+}
+
+/// The number of the low bits of the local APIC ID, which tell the CPUs that share a
+/// last-level cache apart.
+static LLC_ID_SHIFT: Once<u32> = Once::new();
+/// The NUMA nodes of the CPUs, which are described by the SRAT.
+static CPU_NODES: Once<Vec<(u32, u32)>> = Once::new();
+
+/// Initializes the topology of the CPUs, i.e., the caches and the NUMA nodes.
+pub(crate) fn init_topology() {
+    LLC_ID_SHIFT.call_once(|| {
+        // SAFETY: It is safe to read the deterministic cache parameters.
+        if unsafe { __cpuid(0).eax } < 4 {
+            return 0;
+        }
+        // The caches are enumerated by the sub-leaves of the leaf 4, until the type is 0.
+        let mut level_and_shift = (0, 0);
+        for sub_leaf in 0.. {
+            // SAFETY: It is safe to read the deterministic cache parameters.
+            let cache = unsafe { __cpuid_count(4, sub_leaf) };
+            if cache.eax & 0x1f == 0 {
+                break;
+            }
+            let level = (cache.eax >> 5) & 0x7;
+            let nr_sharing_ids = ((cache.eax >> 14) & 0xfff) + 1;
+            let shift = nr_sharing_ids.next_power_of_two().trailing_zeros();
+            level_and_shift = level_and_shift.max((level, shift));
+        }
+        level_and_shift.1
+    });
+    CPU_NODES.call_once(|| {
+        Srat::new()
+            .map(|srat| {
+                srat.cpu_affinities()
+                    .iter()
+                    .map(|affinity| (affinity.apic_id, affinity.node))
+                    .collect()
+            })
+            .unwrap_or_default()
+    });
+}
+
+/// Returns the ID of the last-level cache of the CPU, which is the same for the CPUs that
+/// share the cache.
+///
+/// The CPUs share no cache if the cache parameters are not enumerated by CPUID, e.g., on
+/// some AMD processors.
+pub fn llc_id(cpu_id: u32) -> u32 {
+    apic_id(cpu_id) >> LLC_ID_SHIFT.get().copied().unwrap_or(0)
+}
+
+/// Returns the NUMA node of the CPU.
+///
+/// The CPUs are all in the node 0 if the NUMA nodes are not described by the firmware.
+pub fn numa_node(cpu_id: u32) -> u32 {
+    let apic_id = apic_id(cpu_id);
+    CPU_NODES
+        .get()
+        .and_then(|nodes| nodes.iter().find(|(id, _)| *id == apic_id))
+        .map_or(0, |(_, node)| *node)
 }
 
 /// A set of CPUs.
-#[derive(Default)]
+#[derive(Debug, Clone, Default)]
 pub struct CpuSet {
     bitset: BitVec,
 }
@@ -113,6 +242,7 @@ pub struct UserContext {
     user_context: RawUserContext,
     fp_regs: FpRegs,
     cpu_exception_info: CpuExceptionInfo,
+    pkru: u32,
 }
 
 /// CPU exception information.
@@ -227,24 +357,83 @@ impl TdxTrapFrame for GeneralRegs {
     }
 }
 
+/// The number of protection keys.
+pub const NR_PKEYS: usize = 16;
+
+static PKEYS_ENABLED: AtomicBool = AtomicBool::new(false);
+
+/// Returns whether protection keys for user-mode pages are enabled.
+///
+/// If so, the accesses to a user page are further restricted by the access rights
+/// in the PKRU register that are selected by the protection key of the page. The
+/// PKRU register is switched along with the [`UserContext`].
+pub fn has_pkeys() -> bool {
+    PKEYS_ENABLED.load(Ordering::Relaxed)
+}
+
+/// Enables protection keys for user-mode pages if the CPU supports them.
+pub(crate) fn enable_pkeys() {
+    // SAFETY: CPUID is always available in the 64-bit mode.
+    let has_pku = unsafe { __cpuid(0).eax >= 7 && __cpuid_count(7, 0).ecx & (1 << 3) != 0 };
+    if !has_pku {
+        return;
+    }
+    // SAFETY: enabling protection keys does not affect the kernel pages, and the
+    // access rights of all the keys are granted in the kernel mode.
+    unsafe {
+        Cr4::update(|cr4| *cr4 |= Cr4Flags::PROTECTION_KEY_USER);
+    }
+    write_pkru(0);
+    PKEYS_ENABLED.store(true, Ordering::Relaxed);
+}
+
+fn read_pkru() -> u32 {
+    let pkru: u32;
+    // SAFETY: reading the PKRU register has no side effects, and protection keys
+    // are enabled.
+    unsafe {
+        asm!(
+            "rdpkru",
+            in("ecx") 0,
+            out("eax") pkru,
+            out("edx") _,
+            options(nomem, nostack, preserves_flags),
+        );
+    }
+    pkru
+}
+
+fn write_pkru(pkru: u32) {
+    // SAFETY: the PKRU register only restricts the accesses to the user pages, and
+    // protection keys are enabled.
+    unsafe {
+        asm!(
+            "wrpkru",
+            in("eax") pkru,
+            in("ecx") 0,
+            in("edx") 0,
+            options(nostack, preserves_flags),
+        );
+    }
+}
+
 /// User Preemption.
+///
+/// The user mode is preempted when the scheduler asks for it in the timer interrupts,
+/// e.g., when the time slice of the current task runs out.
 pub struct UserPreemption {
-    count: u32,
+    _private: (),
 }
 
 impl UserPreemption {
-    const PREEMPTION_INTERVAL: u32 = 100;
-
     /// Creates a new instance of `UserPreemption`.
     pub const fn new() -> Self {
-        UserPreemption { count: 0 }
+        UserPreemption { _private: () }
     }
 
     /// Checks if preemption might occur and takes necessary actions.
     pub fn might_preempt(&mut self) {
-        self.count = (self.count + 1) % Self::PREEMPTION_INTERVAL;
-
-        if self.count == 0 {
+        if crate::task::need_resched() {
             crate::arch::irq::enable_local();
             crate::task::schedule();
             crate::arch::irq::disable_local();
@@ -277,6 +466,20 @@ impl UserContext {
     pub fn fp_regs_mut(&mut self) -> &mut FpRegs {
         &mut self.fp_regs
     }
+
+    /// Returns the value of the PKRU register, which holds the access rights of the
+    /// user pages for each protection key.
+    ///
+    /// The value is meaningless if protection keys are not supported.
+    pub fn pkru(&self) -> u32 {
+        self.pkru
+    }
+
+    /// Sets the value of the PKRU register, which takes effect once the user mode is
+    /// entered.
+    pub fn set_pkru(&mut self, pkru: u32) {
+        self.pkru = pkru;
+    }
 }
 
 impl UserContextApiInternal for UserContext {
@@ -292,9 +495,19 @@ impl UserContextApiInternal for UserContext {
         const SYSCALL_TRAPNUM: u16 = 0x100;
 
         let mut user_preemption = UserPreemption::new();
+        let has_pkeys = has_pkeys();
         // return when it is syscall or cpu exception type is Fault or Trap.
         loop {
+            if has_pkeys {
+                write_pkru(self.pkru);
+            }
             self.user_context.run();
+            if has_pkeys {
+                // The user mode may have changed the PKRU register with `WRPKRU`. The
+                // kernel accesses the user pages regardless of the protection keys.
+                self.pkru = read_pkru();
+                write_pkru(0);
+            }
             match CpuException::to_cpu_exception(self.user_context.trap_num as u16) {
                 Some(exception) => {
                     #[cfg(feature = "intel_tdx")]
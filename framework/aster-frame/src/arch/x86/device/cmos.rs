@@ -3,13 +3,17 @@
 #![allow(unused_variables)]
 
 use acpi::{fadt::Fadt, sdt::Signature};
-use x86_64::instructions::port::{ReadOnlyAccess, WriteOnlyAccess};
 
-use super::io_port::IoPort;
 use crate::arch::x86::kernel::acpi::ACPI_TABLES;
 
-pub static CMOS_ADDRESS: IoPort<u8, WriteOnlyAccess> = unsafe { IoPort::new(0x70) };
-pub static CMOS_DATA: IoPort<u8, ReadOnlyAccess> = unsafe { IoPort::new(0x71) };
+/// The I/O port to select the CMOS register, which can be acquired as an [`IoPort`].
+///
+/// [`IoPort`]: super::io_port::IoPort
+pub const CMOS_ADDRESS_PORT: u16 = 0x70;
+/// The I/O port to access the selected CMOS register, which can be acquired as an [`IoPort`].
+///
+/// [`IoPort`]: super::io_port::IoPort
+pub const CMOS_DATA_PORT: u16 = 0x71;
 
 pub fn get_century_register() -> Option<u8> {
     if !ACPI_TABLES.is_completed() {
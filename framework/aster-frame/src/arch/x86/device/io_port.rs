@@ -1,7 +1,10 @@
 // SPDX-License-Identifier: MPL-2.0
 
-use core::marker::PhantomData;
+use alloc::sync::Arc;
+use core::{marker::PhantomData, mem::size_of, ops::Range};
 
+#[cfg(feature = "intel_tdx")]
+use ::tdx_guest::tdx_is_enabled;
 pub use x86_64::{
     instructions::port::{
         PortReadAccess as IoPortReadAccess, PortWriteAccess as IoPortWriteAccess, ReadOnlyAccess,
@@ -10,50 +13,146 @@ pub use x86_64::{
     structures::port::{PortRead, PortWrite},
 };
 
+#[cfg(feature = "intel_tdx")]
+use crate::arch::tdx_guest;
+use crate::{
+    io_resource::{self, IoResourceKind, IoToken},
+    Result,
+};
+
+/// The I/O ports used by the framework itself, which cannot be acquired by drivers.
+pub(crate) const FRAMEWORK_IO_PORTS: &[(Range<u16>, &str)] = &[
+    (0x20..0x22, "pic1"),
+    (0x40..0x44, "timer0"),
+    (0xA0..0xA2, "pic2"),
+    (0xF4..0xF8, "qemu exit"),
+    (0x3F8..0x400, "serial"),
+    (0xCF8..0xD00, "PCI conf1"),
+];
+
 /// An I/O port, representing a specific address in the I/O address of x86.
 ///
-/// The following code shows and example to read and write u32 value to an I/O port:
+/// Drivers acquire the I/O ports of their devices with [`IoPort::acquire`], which fails
+/// if the ports are used by others. The following code shows an example to read and
+/// write u32 value to an I/O port:
 ///
 /// ```rust
-/// static PORT: IoPort<u32, ReadWriteAccess> = unsafe { IoPort::new(0x12) };
-///
-/// fn port_value_increase(){
-///     PORT.write(PORT.read() + 1)
-/// }
+/// let port: IoPort<u32, ReadWriteAccess> = IoPort::acquire(0x12, "example")?;
+/// port.write(port.read() + 1);
 /// ```
 ///
 pub struct IoPort<T, A> {
     port: u16,
+    /// The token of the acquired port, which is `None` for the ports of the framework.
+    token: Option<Arc<IoToken>>,
     value_marker: PhantomData<T>,
     access_marker: PhantomData<A>,
 }
 
 impl<T, A> IoPort<T, A> {
-    /// Create an I/O port.
+    /// Create an I/O port used by the framework itself.
     ///
     /// # Safety
     ///
     /// This function is marked unsafe as creating an I/O port is considered
-    /// a privileged operation.
-    pub const unsafe fn new(port: u16) -> Self {
+    /// a privileged operation. The port should be in [`FRAMEWORK_IO_PORTS`].
+    pub(crate) const unsafe fn new(port: u16) -> Self {
         Self {
             port,
+            token: None,
             value_marker: PhantomData,
             access_marker: PhantomData,
         }
     }
+
+    /// Acquires the I/O port for the owner.
+    ///
+    /// The port is released once the returned handle is dropped.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`crate::Error::AccessDenied`] if the port is used by others.
+    pub fn acquire(port: u16, owner: &'static str) -> Result<Self> {
+        let start = port as usize;
+        let token =
+            io_resource::acquire(IoResourceKind::Port, start..start + size_of::<T>(), owner)?;
+        Ok(Self {
+            port,
+            token: Some(token),
+            value_marker: PhantomData,
+            access_marker: PhantomData,
+        })
+    }
+
+    /// Returns the port number.
+    pub fn port(&self) -> u16 {
+        self.port
+    }
+
+    /// Returns the token of the port, which is `None` for the ports of the framework.
+    pub fn token(&self) -> Option<&Arc<IoToken>> {
+        self.token.as_ref()
+    }
 }
 
 impl<T: PortRead, A: IoPortReadAccess> IoPort<T, A> {
     #[inline]
     pub fn read(&self) -> T {
-        unsafe { PortRead::read_from_port(self.port) }
+        unsafe { read_port(self.port) }
     }
 }
 
 impl<T: PortWrite, A: IoPortWriteAccess> IoPort<T, A> {
     #[inline]
     pub fn write(&self, value: T) {
-        unsafe { PortWrite::write_to_port(self.port, value) }
+        unsafe { write_port(self.port, value) }
+    }
+}
+
+/// Reads a value from the port.
+///
+/// In TDX guests, the port is read with a TDVMCALL instead of the `in` instruction,
+/// which would trigger a virtual exception (#VE).
+///
+/// # Safety
+///
+/// The caller must ensure that reading the port does not break memory safety.
+#[inline]
+pub(crate) unsafe fn read_port<T: PortRead>(port: u16) -> T {
+    #[cfg(feature = "intel_tdx")]
+    if tdx_is_enabled() {
+        let value = tdx_guest::read_port(size_of::<T>(), port);
+        // SAFETY: `T` is one of `u8`, `u16` and `u32`, whose value is in the lower bytes
+        // of `value` on little-endian x86.
+        return unsafe { core::mem::transmute_copy(&value) };
+    }
+    T::read_from_port(port)
+}
+
+/// Writes a value to the port.
+///
+/// In TDX guests, the port is written with a TDVMCALL instead of the `out` instruction,
+/// which would trigger a virtual exception (#VE).
+///
+/// # Safety
+///
+/// The caller must ensure that writing the port does not break memory safety.
+#[inline]
+pub(crate) unsafe fn write_port<T: PortWrite>(port: u16, value: T) {
+    #[cfg(feature = "intel_tdx")]
+    if tdx_is_enabled() {
+        let mut raw = 0u32;
+        // SAFETY: `T` is one of `u8`, `u16` and `u32`, whose value fits in the lower bytes
+        // of `raw` on little-endian x86.
+        unsafe {
+            core::ptr::copy_nonoverlapping(
+                &value as *const T as *const u8,
+                &mut raw as *mut u32 as *mut u8,
+                size_of::<T>(),
+            );
+        }
+        tdx_guest::write_port(size_of::<T>(), port, raw);
+        return;
     }
+    T::write_to_port(port, value)
 }
@@ -128,6 +128,19 @@ impl RootTable {
         device_id: PciDeviceLocation,
         page_table: PageTable<DeviceMode, PageTableEntry, PagingConsts>,
     ) {
+        if self.replace_device_page_table(device_id, page_table) {
+            warn!("IOMMU: Overwritting the existing device page table");
+        }
+    }
+
+    /// Replaces the device page table, returning whether there is an original one.
+    ///
+    /// The context-cache and the IOTLB must be invalidated if there is an original one.
+    pub fn replace_device_page_table(
+        &mut self,
+        device_id: PciDeviceLocation,
+        page_table: PageTable<DeviceMode, PageTableEntry, PagingConsts>,
+    ) -> bool {
         let context_table = self.get_or_create_context_table(device_id);
 
         let bus_entry = context_table
@@ -137,9 +150,6 @@ impl RootTable {
                     * size_of::<ContextEntry>(),
             )
             .unwrap();
-        if bus_entry.is_present() {
-            warn!("IOMMU: Overwritting the existing device page table");
-        }
         let address = unsafe { page_table.root_paddr() };
         context_table.page_tables.insert(address, page_table);
         let entry = ContextEntry(address as u128 | 1 | 0x1_0000_0000_0000_0000);
@@ -151,7 +161,7 @@ impl RootTable {
                 &entry,
             )
             .unwrap();
-        context_table.page_tables.get_mut(&address).unwrap();
+        bus_entry.is_present()
     }
 }
 
@@ -302,6 +312,7 @@ impl ContextTable {
                     flags: PageFlags::RW,
                     cache: CachePolicy::Uncacheable,
                     priv_flags: PrivFlags::empty(),
+                    pkey: 0,
                 },
             )
             .unwrap();
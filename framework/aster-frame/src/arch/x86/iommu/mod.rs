@@ -7,6 +7,8 @@ mod fault;
 mod remapping;
 mod second_stage;
 
+use alloc::collections::BTreeMap;
+
 use log::info;
 pub use second_stage::DeviceMode;
 use second_stage::{PageTableEntry, PagingConsts};
@@ -15,7 +17,12 @@ use spin::Once;
 use crate::{
     arch::iommu::context_table::RootTable,
     bus::pci::PciDeviceLocation,
-    mm::{dma::Daddr, page_table::PageTableError, Paddr, PageTable},
+    mm::{
+        dma::Daddr,
+        page_prop::{CachePolicy, PageProperty, PrivilegedPageFlags as PrivFlags},
+        page_table::PageTableError,
+        Paddr, PageFlags, PageTable, PAGE_SIZE,
+    },
     sync::Mutex,
 };
 
@@ -28,51 +35,197 @@ pub enum IommuError {
     ModificationError(PageTableError),
 }
 
+type DevicePageTable = PageTable<DeviceMode, PageTableEntry, PagingConsts>;
+
+/// The page tables of the IOMMU.
+///
+/// The devices share a page table by default. A device that maps frames for itself, i.e.,
+/// with [`map_device`], gets its own page table, i.e., a protection domain, so that the other
+/// devices cannot access the frames. The mappings in the shared page table are also in
+/// the page tables of the protection domains, since they are the buffers of the drivers.
+struct IommuTables {
+    root_table: RootTable,
+    shared_table: DevicePageTable,
+    /// The mapped pages in the shared page table.
+    shared_pages: BTreeMap<Daddr, Paddr>,
+    domains: BTreeMap<PciDeviceLocation, Domain>,
+}
+
+/// The protection domain of a device.
+struct Domain {
+    page_table: DevicePageTable,
+    /// The pages mapped only for the device, and the numbers of the times they are mapped.
+    pages: BTreeMap<Daddr, usize>,
+}
+
+/// Maps a page for all the devices.
 ///
 /// # Safety
 ///
 /// Mapping an incorrect address may lead to a kernel data leak.
 pub(crate) unsafe fn map(daddr: Daddr, paddr: Paddr) -> Result<(), IommuError> {
-    let Some(table) = PAGE_TABLE.get() else {
+    let Some(tables) = PAGE_TABLE.get() else {
         return Err(IommuError::NoIommu);
     };
-    // The page table of all devices is the same. So we can use any device ID.
-    table
-        .lock()
-        .map(PciDeviceLocation::zero(), daddr, paddr)
-        .map_err(|err| match err {
-            context_table::ContextTableError::InvalidDeviceId => unreachable!(),
-            context_table::ContextTableError::ModificationError(err) => {
-                IommuError::ModificationError(err)
-            }
-        })
+    let mut tables = tables.lock();
+    map_page(&tables.shared_table, daddr, paddr)?;
+    for domain in tables.domains.values() {
+        if !domain.pages.contains_key(&daddr) {
+            map_page(&domain.page_table, daddr, paddr)?;
+        }
+    }
+    tables.shared_pages.insert(daddr, paddr);
+    Ok(())
 }
 
+/// Unmaps a page that is mapped with [`map`].
 pub(crate) fn unmap(daddr: Daddr) -> Result<(), IommuError> {
-    let Some(table) = PAGE_TABLE.get() else {
+    let Some(tables) = PAGE_TABLE.get() else {
         return Err(IommuError::NoIommu);
     };
-    // The page table of all devices is the same. So we can use any device ID.
-    table
-        .lock()
-        .unmap(PciDeviceLocation::zero(), daddr)
-        .map_err(|err| match err {
-            context_table::ContextTableError::InvalidDeviceId => unreachable!(),
-            context_table::ContextTableError::ModificationError(err) => {
-                IommuError::ModificationError(err)
+    let mut tables = tables.lock();
+    unmap_page(&tables.shared_table, daddr)?;
+    for domain in tables.domains.values() {
+        if !domain.pages.contains_key(&daddr) {
+            unmap_page(&domain.page_table, daddr)?;
+        }
+    }
+    tables.shared_pages.remove(&daddr);
+    invalidate();
+    Ok(())
+}
+
+/// Maps a page for the device only, which is mapped in the protection domain of the device.
+///
+/// The same page can be mapped multiple times, which must be unmapped as many times.
+///
+/// # Safety
+///
+/// Mapping an incorrect address may lead to a kernel data leak.
+pub(crate) unsafe fn map_device(
+    device: PciDeviceLocation,
+    daddr: Daddr,
+    paddr: Paddr,
+) -> Result<(), IommuError> {
+    let Some(tables) = PAGE_TABLE.get() else {
+        return Err(IommuError::NoIommu);
+    };
+    let mut tables = tables.lock();
+    let is_shared = tables.shared_pages.contains_key(&daddr);
+    let domain = tables.domain_mut(device)?;
+    let count = domain.pages.get(&daddr).copied().unwrap_or(0);
+    if count == 0 && !is_shared {
+        map_page(&domain.page_table, daddr, paddr)?;
+    }
+    domain.pages.insert(daddr, count + 1);
+    Ok(())
+}
+
+/// Unmaps a page that is mapped with [`map_device`].
+pub(crate) fn unmap_device(device: PciDeviceLocation, daddr: Daddr) -> Result<(), IommuError> {
+    let Some(tables) = PAGE_TABLE.get() else {
+        return Err(IommuError::NoIommu);
+    };
+    let mut tables = tables.lock();
+    let is_shared = tables.shared_pages.contains_key(&daddr);
+    let domain = tables.domain_mut(device)?;
+    let Some(count) = domain.pages.get_mut(&daddr) else {
+        return Ok(());
+    };
+    *count -= 1;
+    if *count > 0 {
+        return Ok(());
+    }
+    domain.pages.remove(&daddr);
+    if !is_shared {
+        unmap_page(&domain.page_table, daddr)?;
+        invalidate();
+    }
+    Ok(())
+}
+
+impl IommuTables {
+    /// Returns the protection domain of the device, which is created if it does not exist.
+    fn domain_mut(&mut self, device: PciDeviceLocation) -> Result<&mut Domain, IommuError> {
+        if !self.domains.contains_key(&device) {
+            let page_table = DevicePageTable::empty();
+            for (daddr, paddr) in self.shared_pages.iter() {
+                // SAFETY: The pages are mapped in the shared page table, which are valid.
+                unsafe { map_page(&page_table, *daddr, *paddr)? };
             }
-        })
+            // SAFETY: The page table is kept in the domain, which is never dropped.
+            let shallow_copy = unsafe { page_table.shallow_copy() };
+            if self
+                .root_table
+                .replace_device_page_table(device, shallow_copy)
+            {
+                invalidate();
+            }
+            self.domains.insert(
+                device,
+                Domain {
+                    page_table,
+                    pages: BTreeMap::new(),
+                },
+            );
+        }
+        Ok(self.domains.get_mut(&device).unwrap())
+    }
+}
+
+/// # Safety
+///
+/// Mapping an incorrect address may lead to a kernel data leak.
+unsafe fn map_page(
+    page_table: &DevicePageTable,
+    daddr: Daddr,
+    paddr: Paddr,
+) -> Result<(), IommuError> {
+    let prop = PageProperty {
+        flags: PageFlags::RW,
+        cache: CachePolicy::Uncacheable,
+        priv_flags: PrivFlags::empty(),
+        pkey: 0,
+    };
+    page_table
+        .map(
+            &(daddr..daddr + PAGE_SIZE),
+            &(paddr..paddr + PAGE_SIZE),
+            prop,
+        )
+        .map_err(IommuError::ModificationError)
+}
+
+fn unmap_page(page_table: &DevicePageTable, daddr: Daddr) -> Result<(), IommuError> {
+    // SAFETY: The device page tables map no kernel virtual addresses.
+    unsafe { page_table.unmap(&(daddr..daddr + PAGE_SIZE)) }
+        .map(|_| ())
+        .map_err(IommuError::ModificationError)
+}
+
+/// Invalidates the stale translations cached by the hardware.
+fn invalidate() {
+    if let Some(remapping_regs) = remapping::REMAPPING_REGS.get() {
+        remapping_regs.invalidate_all();
+    }
 }
 
 pub(crate) fn init() -> Result<(), IommuError> {
     let mut root_table = RootTable::new();
     // For all PCI Device, use the same page table.
-    let page_table = PageTable::<DeviceMode, PageTableEntry, PagingConsts>::empty();
+    let page_table = DevicePageTable::empty();
     for table in PciDeviceLocation::all() {
         root_table.specify_device_page_table(table, unsafe { page_table.shallow_copy() })
     }
     remapping::init(&root_table)?;
-    PAGE_TABLE.call_once(|| Mutex::new(root_table));
+    PAGE_TABLE.call_once(|| {
+        Mutex::new(IommuTables {
+            root_table,
+            shared_table: page_table,
+            shared_pages: BTreeMap::new(),
+            domains: BTreeMap::new(),
+        })
+    });
     info!("IOMMU enabled");
     Ok(())
 }
@@ -81,4 +234,4 @@ pub(crate) fn has_iommu() -> bool {
     PAGE_TABLE.get().is_some()
 }
 
-static PAGE_TABLE: Once<Mutex<RootTable>> = Once::new();
+static PAGE_TABLE: Once<Mutex<IommuTables>> = Once::new();
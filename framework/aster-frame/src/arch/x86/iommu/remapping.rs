@@ -32,6 +32,8 @@ pub struct RemappingRegisters {
     global_status: Volatile<&'static u32, ReadOnly>,
     root_table_address: Volatile<&'static mut u64, ReadWrite>,
     context_command: Volatile<&'static mut u64, ReadWrite>,
+    /// The virtual address of the registers.
+    base_vaddr: usize,
 }
 
 impl RemappingRegisters {
@@ -39,6 +41,36 @@ impl RemappingRegisters {
         Capability::from_bits_truncate(self.capability.read())
     }
 
+    /// Invalidates the context-cache and the IOTLB globally.
+    ///
+    /// It must be done after a present context entry is changed or a mapping is removed, so
+    /// that the hardware does not use the stale translations.
+    pub fn invalidate_all(&self) {
+        /// Invalidate Context-Cache, and the global invalidation in Context Command Register.
+        const ICC_GLOBAL: u64 = (1 << 63) | (1 << 61);
+        /// IOTLB Invalidate, and the global invalidation in IOTLB Invalidate Register.
+        const IVT_GLOBAL: u64 = (1 << 63) | (1 << 60);
+
+        // The IOTLB Invalidate Register is the second register at the offset of 16 times
+        // the IOTLB Register Offset, which is in the bits 17:8 of the extended capability.
+        let iro = ((self.extended_capability.read() >> 8) & 0x3FF) as usize;
+        let context_command = (self.base_vaddr + 0x28) as *mut u64;
+        let iotlb_invalidate = (self.base_vaddr + iro * 16 + 8) as *mut u64;
+        // SAFETY: The registers are in the remapping hardware unit, whose offsets are
+        // strictly adhered to in the manual. The callers serialize the invalidations with
+        // the lock of the page tables.
+        unsafe {
+            context_command.write_volatile(ICC_GLOBAL);
+            while context_command.read_volatile() & (1 << 63) != 0 {
+                core::hint::spin_loop();
+            }
+            iotlb_invalidate.write_volatile(IVT_GLOBAL);
+            while iotlb_invalidate.read_volatile() & (1 << 63) != 0 {
+                core::hint::spin_loop();
+            }
+        }
+    }
+
     /// Create a instance from base address
     fn new(root_table: &RootTable) -> Option<Self> {
         let dmar = Dmar::new()?;
@@ -78,6 +110,7 @@ impl RemappingRegisters {
                 global_status,
                 root_table_address,
                 context_command,
+                base_vaddr: vaddr,
             }
         };
 
@@ -18,8 +18,8 @@ use crate::mm::{
 pub struct DeviceMode {}
 
 impl PageTableMode for DeviceMode {
-    /// The device address space is 32-bit.
-    const VADDR_RANGE: Range<Vaddr> = 0..0x1_0000_0000;
+    /// The device address space is 39-bit, which is translated by the 3-level page tables.
+    const VADDR_RANGE: Range<Vaddr> = 0..0x80_0000_0000;
 }
 
 #[derive(Clone, Debug, Default)]
@@ -117,6 +117,7 @@ impl PageTableEntryTrait for PageTableEntry {
             flags,
             cache,
             priv_flags: PrivFlags::empty(),
+            pkey: 0,
         }
     }
 
@@ -48,6 +48,14 @@ pub(crate) fn enable_local() {
     x86_64::instructions::nop();
 }
 
+/// Enables the local IRQs and halts the CPU until the next interrupt.
+///
+/// The CPU does not handle any interrupt between the two, so an interrupt that arrives
+/// after the IRQs were checked with them disabled still wakes the CPU.
+pub(crate) fn enable_local_and_halt() {
+    x86_64::instructions::interrupts::enable_and_hlt();
+}
+
 pub(crate) fn disable_local() {
     x86_64::instructions::interrupts::disable();
 }
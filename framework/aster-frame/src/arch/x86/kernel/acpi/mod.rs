@@ -4,6 +4,7 @@
 
 pub mod dmar;
 pub mod remapping;
+pub mod srat;
 
 use alloc::borrow::ToOwned;
 use core::{
@@ -0,0 +1,117 @@
+// SPDX-License-Identifier: MPL-2.0
+
+use alloc::vec::Vec;
+use core::mem::size_of;
+
+use acpi::{sdt::Signature, AcpiTable};
+
+use super::SdtHeaderWrapper;
+use crate::mm::paddr_to_vaddr;
+
+/// System Resource Affinity Table, which tells the NUMA node, i.e., the proximity domain,
+/// of each CPU and each memory range.
+///
+/// Only the affinities of the CPUs are parsed.
+#[derive(Debug)]
+pub struct Srat {
+    cpu_affinities: Vec<CpuAffinity>,
+}
+
+/// The NUMA node of a CPU.
+#[derive(Debug, Clone, Copy)]
+pub struct CpuAffinity {
+    /// The local APIC ID of the CPU.
+    pub apic_id: u32,
+    /// The proximity domain of the CPU.
+    pub node: u32,
+}
+
+#[derive(Debug, Clone, Copy)]
+#[repr(C)]
+struct SratHeader {
+    header: SdtHeaderWrapper,
+    reserved: [u8; 12],
+}
+
+impl AcpiTable for SratHeader {
+    fn header(&self) -> &acpi::sdt::SdtHeader {
+        &self.header.0
+    }
+}
+
+/// The type of the Processor Local APIC Affinity structure.
+const LOCAL_APIC_AFFINITY: u8 = 0;
+/// The type of the Processor Local x2APIC Affinity structure.
+const LOCAL_X2APIC_AFFINITY: u8 = 2;
+/// The flag of an affinity structure, which tells that the structure is used.
+const AFFINITY_ENABLED: u32 = 1;
+
+impl Srat {
+    /// Creates a instance from ACPI table.
+    pub fn new() -> Option<Self> {
+        if !super::ACPI_TABLES.is_completed() {
+            return None;
+        }
+        let acpi_table_lock = super::ACPI_TABLES.get().unwrap().lock();
+        // SAFETY: The SratHeader is the header for the SRAT structure, it fits all the field
+        // described in the ACPI specification.
+        let srat_mapping = unsafe {
+            acpi_table_lock
+                .get_sdt::<SratHeader>(Signature::SRAT)
+                .unwrap()?
+        };
+
+        let physical_address = srat_mapping.physical_start();
+        let len = srat_mapping.mapped_length() - size_of::<SratHeader>();
+        // SAFETY: The target address is the start of the affinity structures, and the length
+        // is valid since the value is read from the length field in SDTHeader minus the size
+        // of SRAT header.
+        let srat_slice = unsafe {
+            core::slice::from_raw_parts(
+                paddr_to_vaddr(physical_address + size_of::<SratHeader>()) as *const u8,
+                len,
+            )
+        };
+
+        let read_u32 = |bytes: &[u8], offset: usize| {
+            u32::from_le_bytes(bytes[offset..offset + 4].try_into().unwrap())
+        };
+        let mut cpu_affinities = Vec::new();
+        let mut index = 0;
+        // Common header: type: u8, length: u8
+        while index + 2 <= len {
+            let typ = srat_slice[index];
+            let length = srat_slice[index + 1] as usize;
+            if length < 2 || index + length > len {
+                break;
+            }
+            let bytes = &srat_slice[index..index + length];
+            let affinity = match typ {
+                LOCAL_APIC_AFFINITY if length >= 16 => {
+                    // The proximity domain is split into bits 0-7 and bits 8-31.
+                    let node = bytes[2] as u32 | (read_u32(bytes, 8) & 0xffff_ff00);
+                    (read_u32(bytes, 4) & AFFINITY_ENABLED != 0).then_some(CpuAffinity {
+                        apic_id: bytes[3] as u32,
+                        node,
+                    })
+                }
+                LOCAL_X2APIC_AFFINITY if length >= 24 => {
+                    (read_u32(bytes, 12) & AFFINITY_ENABLED != 0).then_some(CpuAffinity {
+                        apic_id: read_u32(bytes, 8),
+                        node: read_u32(bytes, 4),
+                    })
+                }
+                _ => None,
+            };
+            cpu_affinities.extend(affinity);
+            index += length;
+        }
+
+        Some(Srat { cpu_affinities })
+    }
+
+    /// Returns the NUMA nodes of the CPUs.
+    pub fn cpu_affinities(&self) -> &[CpuAffinity] {
+        &self.cpu_affinities
+    }
+}
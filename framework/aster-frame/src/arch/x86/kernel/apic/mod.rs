@@ -15,13 +15,36 @@ pub mod xapic;
 
 pub static APIC_INSTANCE: Once<Arc<SpinLock<dyn Apic + 'static>>> = Once::new();
 
+/// The level bit of the interrupt command register, which must be set for the interrupts
+/// other than INIT level de-assert.
+const ICR_LEVEL_ASSERT: u64 = 1 << 14;
+/// The INIT delivery mode of the interrupt command register.
+const ICR_DELIVERY_MODE_INIT: u64 = 0b101 << 8;
+/// The start-up delivery mode of the interrupt command register.
+const ICR_DELIVERY_MODE_STARTUP: u64 = 0b110 << 8;
+
 pub trait Apic: ApicTimer + Sync + Send {
+    /// Enables the local APIC of the current CPU.
+    fn enable(&mut self);
+
+    /// Gets the local APIC ID.
     fn id(&self) -> u32;
 
     fn version(&self) -> u32;
 
     /// End of Interrupt, this function will inform APIC that this interrupt has been processed.
     fn eoi(&mut self);
+
+    /// Sends an inter-processor interrupt of the vector to the CPU with the local APIC ID.
+    fn send_ipi(&mut self, apic_id: u32, vector: u8);
+
+    /// Sends an INIT inter-processor interrupt to the CPU with the local APIC ID, which
+    /// resets the CPU to wait for a start-up IPI.
+    fn send_init_ipi(&mut self, apic_id: u32);
+
+    /// Sends a start-up inter-processor interrupt to the CPU with the local APIC ID, which
+    /// starts the CPU in the real mode at the physical address `page << 12`.
+    fn send_startup_ipi(&mut self, apic_id: u32, page: u8);
 }
 
 pub trait ApicTimer: Sync + Send {
@@ -2,11 +2,11 @@
 
 use x86::msr::{
     rdmsr, wrmsr, IA32_APIC_BASE, IA32_X2APIC_APICID, IA32_X2APIC_CUR_COUNT, IA32_X2APIC_DIV_CONF,
-    IA32_X2APIC_EOI, IA32_X2APIC_INIT_COUNT, IA32_X2APIC_LVT_TIMER, IA32_X2APIC_SIVR,
-    IA32_X2APIC_VERSION,
+    IA32_X2APIC_EOI, IA32_X2APIC_ICR, IA32_X2APIC_INIT_COUNT, IA32_X2APIC_LVT_TIMER,
+    IA32_X2APIC_SIVR, IA32_X2APIC_VERSION,
 };
 
-use super::ApicTimer;
+use super::{ApicTimer, ICR_DELIVERY_MODE_INIT, ICR_DELIVERY_MODE_STARTUP, ICR_LEVEL_ASSERT};
 
 pub struct X2Apic {}
 
@@ -23,8 +23,10 @@ impl X2Apic {
         let value = unsafe { core::arch::x86_64::__cpuid(1) };
         value.ecx & 0x20_0000 != 0
     }
+}
 
-    pub fn enable(&mut self) {
+impl super::Apic for X2Apic {
+    fn enable(&mut self) {
         const X2APIC_ENABLE_BITS: u64 = {
             // IA32_APIC_BASE MSR's EN bit: xAPIC global enable/disable
             const EN_BIT_IDX: u8 = 11;
@@ -50,9 +52,7 @@ impl X2Apic {
             wrmsr(IA32_X2APIC_SIVR, svr);
         }
     }
-}
 
-impl super::Apic for X2Apic {
     fn id(&self) -> u32 {
         unsafe { rdmsr(IA32_X2APIC_APICID) as u32 }
     }
@@ -66,6 +66,30 @@ impl super::Apic for X2Apic {
             wrmsr(IA32_X2APIC_EOI, 0);
         }
     }
+
+    fn send_ipi(&mut self, apic_id: u32, vector: u8) {
+        // The destination is in bits 32-63, and the fixed delivery mode in the physical
+        // destination mode is 0.
+        let icr = (apic_id as u64) << 32 | ICR_LEVEL_ASSERT | vector as u64;
+        unsafe {
+            wrmsr(IA32_X2APIC_ICR, icr);
+        }
+    }
+
+    fn send_init_ipi(&mut self, apic_id: u32) {
+        let icr = (apic_id as u64) << 32 | ICR_LEVEL_ASSERT | ICR_DELIVERY_MODE_INIT;
+        unsafe {
+            wrmsr(IA32_X2APIC_ICR, icr);
+        }
+    }
+
+    fn send_startup_ipi(&mut self, apic_id: u32, page: u8) {
+        let icr =
+            (apic_id as u64) << 32 | ICR_LEVEL_ASSERT | ICR_DELIVERY_MODE_STARTUP | page as u64;
+        unsafe {
+            wrmsr(IA32_X2APIC_ICR, icr);
+        }
+    }
 }
 
 impl ApicTimer for X2Apic {
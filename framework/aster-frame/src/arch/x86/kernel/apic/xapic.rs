@@ -5,7 +5,7 @@
 use spin::Once;
 use x86::apic::xapic;
 
-use super::ApicTimer;
+use super::{ApicTimer, ICR_DELIVERY_MODE_INIT, ICR_DELIVERY_MODE_STARTUP, ICR_LEVEL_ASSERT};
 use crate::{mm, sync::Mutex};
 
 const IA32_APIC_BASE_MSR: u32 = 0x1B;
@@ -13,6 +13,8 @@ const IA32_APIC_BASE_MSR_BSP: u32 = 0x100; // Processor is a BSP
 const IA32_APIC_BASE_MSR_ENABLE: u64 = 0x800;
 
 const APIC_LVT_MASK_BITS: u32 = 1 << 16;
+/// The delivery status bit of the ICR, which is set until the interrupt is accepted.
+const ICR_DELIVERY_PENDING: u32 = 1 << 12;
 
 pub static XAPIC_INSTANCE: Once<Mutex<XApic>> = Once::new();
 
@@ -47,13 +49,15 @@ impl XApic {
         unsafe { core::ptr::write_volatile(&mut self.mmio_region[index], val) }
     }
 
-    pub fn enable(&mut self) {
-        // Enable xAPIC
-        set_apic_base_address(get_apic_base_address());
-
-        // Set SVR, Enable APIC and set Spurious Vector to 15 (Reserved irq number)
-        let svr: u32 = 1 << 8 | 15;
-        self.write(xapic::XAPIC_SVR, svr);
+    /// Sends an inter-processor interrupt with the low half of the ICR.
+    fn send_icr(&mut self, apic_id: u32, icr: u32) {
+        // The destination is in bits 56-63, i.e., bits 24-31 of the high half, which must
+        // be written first since writing the low half sends the interrupt.
+        self.write(xapic::XAPIC_ICR1, apic_id << 24);
+        self.write(xapic::XAPIC_ICR0, icr);
+        while self.read(xapic::XAPIC_ICR0) & ICR_DELIVERY_PENDING != 0 {
+            core::hint::spin_loop();
+        }
     }
 
     pub fn has_xapic() -> bool {
@@ -63,8 +67,18 @@ impl XApic {
 }
 
 impl super::Apic for XApic {
+    fn enable(&mut self) {
+        // Enable xAPIC
+        set_apic_base_address(get_apic_base_address());
+
+        // Set SVR, Enable APIC and set Spurious Vector to 15 (Reserved irq number)
+        let svr: u32 = 1 << 8 | 15;
+        self.write(xapic::XAPIC_SVR, svr);
+    }
+
     fn id(&self) -> u32 {
-        self.read(xapic::XAPIC_ID)
+        // The xAPIC ID is in bits 24-31 of the register.
+        self.read(xapic::XAPIC_ID) >> 24
     }
 
     fn version(&self) -> u32 {
@@ -74,6 +88,21 @@ impl super::Apic for XApic {
     fn eoi(&mut self) {
         self.write(xapic::XAPIC_EOI, 0);
     }
+
+    fn send_ipi(&mut self, apic_id: u32, vector: u8) {
+        self.send_icr(apic_id, ICR_LEVEL_ASSERT as u32 | vector as u32);
+    }
+
+    fn send_init_ipi(&mut self, apic_id: u32) {
+        self.send_icr(apic_id, (ICR_LEVEL_ASSERT | ICR_DELIVERY_MODE_INIT) as u32);
+    }
+
+    fn send_startup_ipi(&mut self, apic_id: u32, page: u8) {
+        self.send_icr(
+            apic_id,
+            (ICR_LEVEL_ASSERT | ICR_DELIVERY_MODE_STARTUP) as u32 | page as u32,
+        );
+    }
 }
 
 impl ApicTimer for XApic {
@@ -3,19 +3,35 @@
 #![allow(dead_code)]
 
 use alloc::fmt;
-use core::ops::Range;
+use core::{
+    ops::Range,
+    sync::atomic::{AtomicU64, Ordering},
+};
 
 use pod::Pod;
+use spin::Once;
 use x86_64::{instructions::tlb, structures::paging::PhysFrame, VirtAddr};
 
-use crate::mm::{
-    page_prop::{CachePolicy, PageFlags, PageProperty, PrivilegedPageFlags as PrivFlags},
-    page_table::PageTableEntryTrait,
-    Paddr, PagingConstsTrait, PagingLevel, Vaddr, PAGE_SIZE,
+use crate::{
+    arch::x86::cpu::{num_cpus, send_ipi, this_cpu},
+    cpu::MAX_CPUS,
+    cpu_local,
+    mm::{
+        page_prop::{CachePolicy, PageFlags, PageProperty, PrivilegedPageFlags as PrivFlags},
+        page_table::PageTableEntryTrait,
+        Paddr, PagingConstsTrait, PagingLevel, Vaddr, PAGE_SIZE,
+    },
+    task::disable_preempt,
+    trap::IrqLine,
 };
 
 pub(crate) const NR_ENTRIES_PER_PAGE: usize = 512;
 
+/// The physical memory below this address is linear mapped by the page table set up in
+/// `boot.S`, so it is the only memory that can be accessed before the kernel page table
+/// is activated.
+pub(crate) const BOOT_LINEAR_MAPPED_END: Paddr = 0x1_0000_0000;
+
 #[derive(Clone, Debug, Default)]
 pub struct PagingConsts {}
 
@@ -70,6 +86,91 @@ pub(crate) fn tlb_flush_addr_range(range: &Range<Vaddr>) {
     }
 }
 
+/// Flushes the TLB entries of the range on all the CPUs, which is needed when the pages
+/// may be accessed by other CPUs after they are unmapped or their permissions are reduced.
+pub(crate) fn tlb_shootdown_addr_range(range: &Range<Vaddr>) {
+    tlb_flush_addr_range(range);
+    flush_remote_tlbs();
+}
+
+/// Flushes the TLB entries of the pages starting at the addresses on all the CPUs.
+///
+/// Unlike [`tlb_shootdown_addr_range`], only the given pages are flushed locally, which
+/// is cheaper when they are a few pages scattered in a large range.
+pub(crate) fn tlb_shootdown_addrs(vaddrs: impl IntoIterator<Item = Vaddr>) {
+    for vaddr in vaddrs {
+        tlb_flush_addr(vaddr);
+    }
+    flush_remote_tlbs();
+}
+
+/// Flushes the TLB entries except the global ones on all the CPUs.
+pub(crate) fn tlb_shootdown_all_excluding_global() {
+    tlb_flush_all_excluding_global();
+    flush_remote_tlbs();
+}
+
+/// The IRQ line of the inter-processor interrupts that ask the CPUs to flush their TLBs.
+static TLB_FLUSH_IRQ: Once<IrqLine> = Once::new();
+
+cpu_local! {
+    /// The number of the TLB flushes that have been requested to the CPU.
+    static TLB_FLUSH_REQUESTED: AtomicU64 = AtomicU64::new(0);
+    /// The number of the requested TLB flushes that the CPU has done.
+    static TLB_FLUSH_DONE: AtomicU64 = AtomicU64::new(0);
+}
+
+/// Initializes the TLB shootdowns, which must be done before the application processors
+/// are started.
+pub(crate) fn init_tlb_shootdown() {
+    let mut irq = IrqLine::alloc().unwrap();
+    irq.on_active(|_| flush_requested());
+    TLB_FLUSH_IRQ.call_once(|| irq);
+}
+
+/// Does the TLB flushes that have been requested to the current CPU, if any.
+fn flush_requested() {
+    let requested = TLB_FLUSH_REQUESTED.load(Ordering::Acquire);
+    if TLB_FLUSH_DONE.load(Ordering::Relaxed) >= requested {
+        return;
+    }
+    // The other CPUs do not tell which entries to flush, so flush all of them, which
+    // also covers the kernel mappings.
+    tlb_flush_all_including_global();
+    TLB_FLUSH_DONE.store(requested, Ordering::Release);
+}
+
+/// Asks the other CPUs to flush their TLBs and waits until they have done so.
+fn flush_remote_tlbs() {
+    let nr_cpus = num_cpus();
+    if nr_cpus == 1 {
+        return;
+    }
+    let Some(irq) = TLB_FLUSH_IRQ.get() else {
+        return;
+    };
+
+    let _guard = disable_preempt();
+    let this_cpu = this_cpu();
+    let mut tickets = [0; MAX_CPUS];
+    for cpu_id in (0..nr_cpus).filter(|cpu_id| *cpu_id != this_cpu) {
+        // The page table updates are visible to the CPU before it sees the request.
+        tickets[cpu_id as usize] = TLB_FLUSH_REQUESTED
+            .get_on_cpu(cpu_id)
+            .fetch_add(1, Ordering::AcqRel)
+            + 1;
+        send_ipi(cpu_id, irq.num());
+    }
+    for cpu_id in (0..nr_cpus).filter(|cpu_id| *cpu_id != this_cpu) {
+        while TLB_FLUSH_DONE.get_on_cpu(cpu_id).load(Ordering::Acquire) < tickets[cpu_id as usize] {
+            // The other CPU may be waiting for this CPU in the same way, possibly with the
+            // local IRQs disabled, so the requests to this CPU must be done meanwhile.
+            flush_requested();
+            core::hint::spin_loop();
+        }
+    }
+}
+
 pub(crate) fn tlb_flush_all_excluding_global() {
     tlb::flush_all();
 }
@@ -130,6 +231,9 @@ impl PageTableEntry {
     #[cfg(feature = "intel_tdx")]
     const PHYS_ADDR_MASK: usize = 0x7_FFFF_FFFF_F000;
     const PROP_MASK: usize = !Self::PHYS_ADDR_MASK & !PageTableFlags::HUGE.bits();
+    /// 62:59, the protection key of a page.
+    const PKEY_SHIFT: u32 = 59;
+    const PKEY_MASK: usize = 0xF << Self::PKEY_SHIFT;
 }
 
 /// Parse a bit-flag bits `val` in the representation of `from` to `to` in bits.
@@ -190,6 +294,7 @@ impl PageTableEntryTrait for PageTableEntry {
             flags: PageFlags::from_bits(flags as u8).unwrap(),
             cache,
             priv_flags: PrivFlags::from_bits(priv_flags as u8).unwrap(),
+            pkey: ((self.0 & Self::PKEY_MASK) >> Self::PKEY_SHIFT) as u8,
         }
     }
 
@@ -231,6 +336,7 @@ impl PageTableEntryTrait for PageTableEntry {
             }
             _ => panic!("unsupported cache policy"),
         }
+        flags |= ((prop.pkey as usize) << Self::PKEY_SHIFT) & Self::PKEY_MASK;
         self.0 = self.0 & !Self::PROP_MASK | flags;
     }
 
@@ -18,7 +18,10 @@ pub(crate) mod tdx_guest;
 pub mod timer;
 pub mod trap;
 
-use core::{arch::x86_64::_rdtsc, sync::atomic::Ordering};
+use core::{
+    arch::x86_64::{_rdrand64_step, _rdseed64_step, _rdtsc},
+    sync::atomic::Ordering,
+};
 
 #[cfg(feature = "intel_tdx")]
 use ::tdx_guest::tdx_is_enabled;
@@ -42,6 +45,7 @@ pub(crate) fn after_all_init() {
             kernel::pic::enable();
         }
     }
+    cpu::init_topology();
     console::callback_init();
     timer::init();
     #[cfg(feature = "intel_tdx")]
@@ -58,6 +62,7 @@ pub(crate) fn after_all_init() {
     }
     // Some driver like serial may use PIC
     kernel::pic::init();
+    mm::init_tlb_shootdown();
 }
 
 pub(crate) fn interrupts_ack() {
@@ -78,6 +83,52 @@ pub fn read_tsc() -> u64 {
     unsafe { _rdtsc() }
 }
 
+/// The number of retries of `RDRAND` and `RDSEED`, as recommended by Intel.
+const RANDOM_RETRIES: usize = 10;
+
+/// Reads a random number with `RDRAND`, which is from a CSPRNG seeded by the hardware.
+///
+/// Returns `None` if the instruction is not supported or no random number is available.
+pub fn read_random() -> Option<u64> {
+    let has_rdrand = x86::cpuid::CpuId::new()
+        .get_feature_info()
+        .is_some_and(|info| info.has_rdrand());
+    if !has_rdrand {
+        return None;
+    }
+
+    let mut value = 0;
+    for _ in 0..RANDOM_RETRIES {
+        // SAFETY: `RDRAND` is supported, and it is safe to read a random number.
+        if unsafe { _rdrand64_step(&mut value) } == 1 {
+            return Some(value);
+        }
+    }
+    None
+}
+
+/// Reads a random number with `RDSEED`, which is directly from the hardware entropy source.
+///
+/// Returns `None` if the instruction is not supported or no random number is available.
+pub fn read_random_seed() -> Option<u64> {
+    let has_rdseed = x86::cpuid::CpuId::new()
+        .get_extended_feature_info()
+        .is_some_and(|info| info.has_rdseed());
+    if !has_rdseed {
+        return None;
+    }
+
+    let mut value = 0;
+    for _ in 0..RANDOM_RETRIES {
+        // SAFETY: `RDSEED` is supported, and it is safe to read a random number.
+        if unsafe { _rdseed64_step(&mut value) } == 1 {
+            return Some(value);
+        }
+        core::hint::spin_loop();
+    }
+    None
+}
+
 fn enable_common_cpu_features() {
     use x86_64::registers::{control::Cr4Flags, model_specific::EferFlags, xcontrol::XCr0Flags};
     let mut cr4 = x86_64::registers::control::Cr4::read();
@@ -102,4 +153,6 @@ fn enable_common_cpu_features() {
             *efer |= EferFlags::NO_EXECUTE_ENABLE;
         });
     }
+
+    cpu::enable_pkeys();
 }
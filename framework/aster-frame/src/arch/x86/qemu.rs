@@ -2,6 +2,8 @@
 
 //! Provides the ability to exit QEMU and return a value as debug result.
 
+use super::device::io_port::{IoPort, WriteOnlyAccess};
+
 /// The exit code of x86 QEMU isa debug device. In `qemu-system-x86_64` the
 /// exit code will be `(code << 1) | 1`. So you could never let QEMU invoke
 /// `exit(0)`. We also need to check if the exit code is returned by the
@@ -22,13 +24,9 @@ pub enum QemuExitCode {
 /// QEMU command line arguments that specifies the ISA debug exit device:
 /// `-device isa-debug-exit,iobase=0xf4,iosize=0x04`.
 pub fn exit_qemu(exit_code: QemuExitCode) -> ! {
-    use x86_64::instructions::port::Port;
-    let mut port = Port::new(0xf4);
-
     // SAFETY: The write to the ISA debug exit port is safe and `0xf4` should
     // be the port number.
-    unsafe {
-        port.write(exit_code as u32);
-    }
+    let port: IoPort<u32, WriteOnlyAccess> = unsafe { IoPort::new(0xf4) };
+    port.write(exit_code as u32);
     unreachable!()
 }
@@ -164,6 +164,49 @@ fn handle_io(trapframe: &mut dyn TdxTrapFrame, ve_info: &tdcall::TdgVeInfo) -> b
     true
 }
 
+fn io_size(len: usize) -> IoSize {
+    match len {
+        1 => IoSize::Size1,
+        2 => IoSize::Size2,
+        4 => IoSize::Size4,
+        8 => IoSize::Size8,
+        _ => panic!("Invalid size value"),
+    }
+}
+
+/// Reads `len` bytes from the I/O port with a TDVMCALL, which does not trigger a #VE
+/// like the `in` instruction.
+pub(crate) fn read_port(len: usize, port: u16) -> u32 {
+    tdvmcall::io_read(io_size(len), port).unwrap()
+}
+
+/// Writes the lower `len` bytes of `value` to the I/O port with a TDVMCALL, which does
+/// not trigger a #VE like the `out` instruction.
+pub(crate) fn write_port(len: usize, port: u16, value: u32) {
+    tdvmcall::io_write(io_size(len), port, value).unwrap();
+}
+
+/// Reads `len` bytes from the MMIO at the physical address with a TDVMCALL, which does
+/// not trigger a #VE like the memory access.
+///
+/// # Safety
+///
+/// The physical address must be in the I/O memory.
+pub(crate) unsafe fn read_mmio_at(len: usize, paddr: Paddr) -> u64 {
+    // The MMIO is emulated by the VMM, so it is always shared.
+    unsafe { read_mmio(io_size(len), paddr as u64 | SHARED_MASK).unwrap() }
+}
+
+/// Writes the lower `len` bytes of `value` to the MMIO at the physical address with a
+/// TDVMCALL, which does not trigger a #VE like the memory access.
+///
+/// # Safety
+///
+/// The physical address must be in the I/O memory.
+pub(crate) unsafe fn write_mmio_at(len: usize, paddr: Paddr, value: u64) {
+    unsafe { write_mmio(io_size(len), paddr as u64 | SHARED_MASK, value).unwrap() }
+}
+
 fn is_protected_gpa(gpa: TdxGpa) -> bool {
     (gpa as u64 & SHARED_MASK) == 0
 }
@@ -417,6 +460,7 @@ pub unsafe fn unprotect_gpa_range(gpa: Paddr, page_num: usize) -> Result<(), Pag
             flags: prop.flags,
             cache: prop.cache,
             priv_flags: prop.priv_flags | PrivFlags::SHARED,
+            pkey: prop.pkey,
         }
     };
     let vaddr = paddr_to_vaddr(gpa);
@@ -460,6 +504,7 @@ pub unsafe fn protect_gpa_range(gpa: Paddr, page_num: usize) -> Result<(), PageC
             flags: prop.flags,
             cache: prop.cache,
             priv_flags: prop.priv_flags - PrivFlags::SHARED,
+            pkey: prop.pkey,
         }
     };
     let vaddr = paddr_to_vaddr(gpa);
@@ -2,14 +2,12 @@
 
 #![allow(unused_variables)]
 
-use alloc::sync::Arc;
 use core::{
     arch::x86_64::_rdtsc,
     sync::atomic::{AtomicBool, AtomicU64, Ordering},
 };
 
 use log::info;
-use spin::Once;
 use trapframe::TrapFrame;
 use x86::{
     cpuid::cpuid,
@@ -26,6 +24,7 @@ use crate::{
             tsc::TSC_FREQ,
         },
     },
+    cpu_local,
     trap::IrqLine,
 };
 
@@ -42,7 +41,38 @@ pub(super) fn init() -> IrqLine {
     }
 }
 
-pub(super) static APIC_TIMER_CALLBACK: Once<Arc<dyn Fn() + Sync + Send>> = Once::new();
+/// Initializes the APIC timer of an application processor in the same mode as the one of
+/// the bootstrap processor, which fires the interrupts of `timer_irq_num`.
+pub(super) fn init_on_ap(timer_irq_num: u8) {
+    let mut apic_lock = APIC_INSTANCE.get().unwrap().lock_irq_disabled();
+    if IS_TSC_DEADLINE_MODE.load(Ordering::Acquire) {
+        apic_lock.set_lvt_timer(timer_irq_num as u64 | (1 << 18));
+        drop(apic_lock);
+        // SAFETY: Reading the TSC has no side effects.
+        NEXT_TICK_DEADLINE.store(
+            unsafe { _rdtsc() } + TSC_TICK_STEP.load(Ordering::Relaxed),
+            Ordering::Relaxed,
+        );
+        program_deadline(None);
+    } else {
+        apic_lock.set_timer_div_config(DivideConfig::Divide64);
+        apic_lock.set_lvt_timer(timer_irq_num as u64 | (1 << 17));
+        apic_lock.set_timer_init_count(PERIODIC_INIT_COUNT.load(Ordering::Acquire));
+    }
+}
+
+static IS_TSC_DEADLINE_MODE: AtomicBool = AtomicBool::new(false);
+
+/// The TSC cycles between two ticks in the TSC-deadline mode.
+static TSC_TICK_STEP: AtomicU64 = AtomicU64::new(0);
+
+cpu_local! {
+    /// The TSC deadline of the next tick of the CPU in the TSC-deadline mode.
+    static NEXT_TICK_DEADLINE: AtomicU64 = AtomicU64::new(0);
+}
+
+/// The initial count of the APIC timer in the periodic mode.
+static PERIODIC_INIT_COUNT: AtomicU64 = AtomicU64::new(0);
 
 /// Determines if the current system supports tsc_deadline mode APIC timer
 fn is_tsc_deadline_mode_supported() -> bool {
@@ -58,17 +88,52 @@ fn init_tsc_mode() -> IrqLine {
     apic_lock.set_lvt_timer(timer_irq.num() as u64 | (1 << 18));
     drop(apic_lock);
     let tsc_step = TSC_FREQ.load(Ordering::Relaxed) / TIMER_FREQ;
+    TSC_TICK_STEP.store(tsc_step, Ordering::Relaxed);
+    // SAFETY: Reading the TSC has no side effects.
+    NEXT_TICK_DEADLINE.store(unsafe { _rdtsc() } + tsc_step, Ordering::Relaxed);
+    IS_TSC_DEADLINE_MODE.store(true, Ordering::Release);
+    program_deadline(None);
 
-    let callback = move || unsafe {
-        let tsc_value = _rdtsc();
-        let next_tsc_value = tsc_step + tsc_value;
-        wrmsr(IA32_TSC_DEADLINE, next_tsc_value);
-    };
+    timer_irq
+}
 
-    callback.call(());
-    APIC_TIMER_CALLBACK.call_once(|| Arc::new(callback));
+/// Returns whether a tick is due at this timer interrupt, and if so, schedules the next tick.
+///
+/// In the TSC-deadline mode, the timer interrupt also fires for the high-resolution timers,
+/// which may be before the tick is due. In the other modes, each timer interrupt is a tick.
+pub(super) fn take_tick() -> bool {
+    if !IS_TSC_DEADLINE_MODE.load(Ordering::Acquire) {
+        return true;
+    }
 
-    timer_irq
+    // SAFETY: Reading the TSC has no side effects.
+    let now = unsafe { _rdtsc() };
+    if now < NEXT_TICK_DEADLINE.load(Ordering::Relaxed) {
+        return false;
+    }
+    NEXT_TICK_DEADLINE.store(
+        now + TSC_TICK_STEP.load(Ordering::Relaxed),
+        Ordering::Relaxed,
+    );
+    true
+}
+
+/// Programs the timer interrupt to fire at the earlier one of the next tick and the
+/// `hrtimer_deadline` in TSC cycles, if the APIC timer is in the TSC-deadline mode.
+///
+/// This should be called with the local interrupts disabled.
+pub(super) fn program_deadline(hrtimer_deadline: Option<u64>) {
+    if !IS_TSC_DEADLINE_MODE.load(Ordering::Acquire) {
+        return;
+    }
+
+    let tick_deadline = NEXT_TICK_DEADLINE.load(Ordering::Relaxed);
+    let deadline = hrtimer_deadline.map_or(tick_deadline, |hrtimer_deadline| {
+        hrtimer_deadline.min(tick_deadline)
+    });
+    // SAFETY: Writing the TSC deadline only sets when the timer interrupt fires. A deadline
+    // that has passed makes the interrupt fire at once.
+    unsafe { wrmsr(IA32_TSC_DEADLINE, deadline) };
 }
 
 fn init_periodic_mode() -> IrqLine {
@@ -87,7 +152,6 @@ fn init_periodic_mode() -> IrqLine {
     drop(apic_lock);
 
     static IS_FINISH: AtomicBool = AtomicBool::new(false);
-    static INIT_COUNT: AtomicU64 = AtomicU64::new(0);
 
     x86_64::instructions::interrupts::enable();
     while !IS_FINISH.load(Ordering::Acquire) {
@@ -100,7 +164,7 @@ fn init_periodic_mode() -> IrqLine {
     let timer_irq = IrqLine::alloc().unwrap();
 
     let mut apic_lock = APIC_INSTANCE.get().unwrap().lock_irq_disabled();
-    apic_lock.set_timer_init_count(INIT_COUNT.load(Ordering::Relaxed));
+    apic_lock.set_timer_init_count(PERIODIC_INIT_COUNT.load(Ordering::Relaxed));
     apic_lock.set_lvt_timer(timer_irq.num() as u64 | (1 << 17));
     apic_lock.set_timer_div_config(DivideConfig::Divide64);
 
@@ -133,7 +197,7 @@ fn init_periodic_mode() -> IrqLine {
             "APIC Timer ticks count:{:x}, remain ticks: {:x},Timer Freq:{} Hz",
             ticks, remain_ticks, TIMER_FREQ
         );
-        INIT_COUNT.store(ticks, Ordering::Release);
+        PERIODIC_INIT_COUNT.store(ticks, Ordering::Release);
         IS_FINISH.store(true, Ordering::Release);
     }
 }
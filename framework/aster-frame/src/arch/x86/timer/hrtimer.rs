@@ -0,0 +1,142 @@
+// SPDX-License-Identifier: MPL-2.0
+
+//! High-resolution timers.
+//!
+//! A high-resolution timer expires at a deadline in TSC cycles rather than at a tick. In the
+//! TSC-deadline mode of the APIC timer, the timer interrupt is programmed to fire at the
+//! earlier one of the next tick and the earliest pending timer, so the timers expire
+//! precisely without raising the tick frequency. In the other modes, the timers are checked
+//! at each tick.
+
+use alloc::{
+    boxed::Box,
+    collections::BTreeMap,
+    sync::{Arc, Weak},
+    vec::Vec,
+};
+use core::{
+    arch::x86_64::_rdtsc,
+    sync::atomic::{AtomicU64, Ordering},
+    time::Duration,
+};
+
+use super::apic;
+use crate::{arch::x86::kernel::tsc::TSC_FREQ, sync::SpinLock};
+
+/// The pending timers, in the order of their deadlines and then their IDs.
+static PENDING_TIMERS: SpinLock<BTreeMap<(u64, u64), Weak<HrTimer>>> =
+    SpinLock::new(BTreeMap::new());
+
+static NEXT_ID: AtomicU64 = AtomicU64::new(0);
+
+const NANOS_PER_SECOND: u128 = 1_000_000_000;
+const NO_DEADLINE: u64 = u64::MAX;
+
+/// A one-shot high-resolution timer.
+///
+/// The timer is cancelled when it is dropped.
+pub struct HrTimer {
+    id: u64,
+    /// The deadline in TSC cycles if the timer is pending, which is only modified with
+    /// `PENDING_TIMERS` locked.
+    deadline: AtomicU64,
+    callback: Box<dyn Fn() + Send + Sync>,
+}
+
+impl HrTimer {
+    /// Creates a timer that calls `callback` when it expires.
+    ///
+    /// Note that the callback is called in the interrupt context, so it must not sleep.
+    pub fn new<F>(callback: F) -> Arc<Self>
+    where
+        F: Fn() + Send + Sync + 'static,
+    {
+        Arc::new(Self {
+            id: NEXT_ID.fetch_add(1, Ordering::Relaxed),
+            deadline: AtomicU64::new(NO_DEADLINE),
+            callback: Box::new(callback),
+        })
+    }
+
+    /// Sets the timer to expire after `timeout`.
+    ///
+    /// If the timer is pending, the previous timeout is overridden.
+    pub fn set_timeout(self: &Arc<Self>, timeout: Duration) {
+        let tsc_freq = TSC_FREQ.load(Ordering::Relaxed) as u128;
+        let cycles = (timeout.as_nanos() * tsc_freq / NANOS_PER_SECOND).min(u64::MAX as u128);
+        // SAFETY: Reading the TSC has no side effects.
+        let deadline = unsafe { _rdtsc() }
+            .saturating_add(cycles as u64)
+            .min(NO_DEADLINE - 1);
+
+        let mut pending_timers = PENDING_TIMERS.lock_irq_disabled();
+        self.remove_locked(&mut pending_timers);
+        pending_timers.insert((deadline, self.id), Arc::downgrade(self));
+        self.deadline.store(deadline, Ordering::Relaxed);
+
+        // The timer interrupt is programmed with the interrupts disabled, so that it is not
+        // reprogrammed by the interrupt handler meanwhile.
+        if let Some(((earliest, _), _)) = pending_timers.first_key_value() {
+            if *earliest == deadline {
+                apic::program_deadline(Some(deadline));
+            }
+        }
+    }
+
+    /// Cancels the timer if it is pending.
+    pub fn cancel(&self) {
+        let mut pending_timers = PENDING_TIMERS.lock_irq_disabled();
+        self.remove_locked(&mut pending_timers);
+    }
+
+    /// Returns whether the timer is pending.
+    pub fn is_pending(&self) -> bool {
+        self.deadline.load(Ordering::Relaxed) != NO_DEADLINE
+    }
+
+    fn remove_locked(&self, pending_timers: &mut BTreeMap<(u64, u64), Weak<HrTimer>>) {
+        let deadline = self.deadline.swap(NO_DEADLINE, Ordering::Relaxed);
+        if deadline != NO_DEADLINE {
+            pending_timers.remove(&(deadline, self.id));
+        }
+    }
+}
+
+impl Drop for HrTimer {
+    fn drop(&mut self) {
+        self.cancel();
+    }
+}
+
+/// Calls the callbacks of the expired timers.
+pub(super) fn process_expired_timers() {
+    let expired_timers = {
+        let mut pending_timers = PENDING_TIMERS.lock_irq_disabled();
+        // SAFETY: Reading the TSC has no side effects.
+        let now = unsafe { _rdtsc() };
+
+        let mut expired_timers = Vec::new();
+        while let Some(entry) = pending_timers.first_entry() {
+            if entry.key().0 > now {
+                break;
+            }
+            if let Some(timer) = entry.remove().upgrade() {
+                timer.deadline.store(NO_DEADLINE, Ordering::Relaxed);
+                expired_timers.push(timer);
+            }
+        }
+        expired_timers
+    };
+
+    for timer in expired_timers {
+        (timer.callback)();
+    }
+}
+
+/// Returns the deadline of the earliest pending timer in TSC cycles.
+pub(super) fn next_deadline() -> Option<u64> {
+    let pending_timers = PENDING_TIMERS.lock_irq_disabled();
+    pending_timers
+        .first_key_value()
+        .map(|((deadline, _), _)| *deadline)
+}
@@ -4,18 +4,23 @@
 
 mod apic;
 mod hpet;
+mod hrtimer;
 mod jiffies;
 pub(crate) mod pit;
 
 use alloc::{boxed::Box, vec::Vec};
-use core::{cell::RefCell, sync::atomic::Ordering};
+use core::sync::atomic::Ordering;
 
+pub use hrtimer::HrTimer;
 pub use jiffies::Jiffies;
 use spin::Once;
 use trapframe::TrapFrame;
 
-use self::apic::APIC_TIMER_CALLBACK;
-use crate::{arch::x86::kernel, cpu_local, trap::IrqLine, CpuLocal};
+use crate::{
+    arch::x86::{cpu::this_cpu, kernel},
+    sync::SpinLock,
+    trap::IrqLine,
+};
 
 /// The timer frequency (Hz). Here we choose 1000Hz since 1000Hz is easier for unit conversion and
 /// convenient for timer. What's more, the frequency cannot be set too high or too low, 1000Hz is
@@ -48,30 +53,42 @@ pub(super) fn init() {
     TIMER_IRQ.call_once(|| timer_irq);
 }
 
-cpu_local! {
-    static INTERRUPT_CALLBACKS: RefCell<Vec<Box<dyn Fn() + Sync + Send>>> = RefCell::new(Vec::new());
+/// Initializes the timer of an application processor, which fires the interrupts of the
+/// same IRQ line as the one of the bootstrap processor.
+pub(crate) fn init_on_ap() {
+    if let Some(timer_irq) = TIMER_IRQ.get() {
+        apic::init_on_ap(timer_irq.num());
+    }
 }
 
+static INTERRUPT_CALLBACKS: SpinLock<Vec<Box<dyn Fn() + Sync + Send>>> = SpinLock::new(Vec::new());
+
 /// Registers a function that will be executed during the system timer interruption.
+///
+/// The function is executed on the bootstrap processor only, once per tick.
 pub fn register_callback<F>(func: F)
 where
     F: Fn() + Sync + Send + 'static,
 {
-    CpuLocal::borrow_with(&INTERRUPT_CALLBACKS, |callbacks| {
-        callbacks.borrow_mut().push(Box::new(func));
-    });
+    INTERRUPT_CALLBACKS.lock_irq_disabled().push(Box::new(func));
 }
 
 fn timer_callback(_: &TrapFrame) {
-    jiffies::ELAPSED.fetch_add(1, Ordering::SeqCst);
+    hrtimer::process_expired_timers();
 
-    CpuLocal::borrow_with(&INTERRUPT_CALLBACKS, |callbacks| {
-        for callback in callbacks.borrow().iter() {
-            (callback)();
+    if apic::take_tick() {
+        // The jiffies and the callbacks are driven by the ticks of the bootstrap processor,
+        // while the scheduler accounts the ticks of each CPU.
+        if this_cpu() == 0 {
+            jiffies::ELAPSED.fetch_add(1, Ordering::SeqCst);
+
+            for callback in INTERRUPT_CALLBACKS.lock_irq_disabled().iter() {
+                (callback)();
+            }
         }
-    });
 
-    if APIC_TIMER_CALLBACK.is_completed() {
-        APIC_TIMER_CALLBACK.get().unwrap().call(());
+        crate::task::scheduler_tick();
     }
+
+    apic::program_deadline(hrtimer::next_deadline());
 }
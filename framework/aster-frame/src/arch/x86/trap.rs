@@ -16,7 +16,7 @@ use crate::{
     cpu::{CpuException, PageFaultErrorCode, PAGE_FAULT},
     cpu_local,
     mm::{
-        kspace::{KERNEL_PAGE_TABLE, LINEAR_MAPPING_BASE_VADDR, LINEAR_MAPPING_VADDR_RANGE},
+        kspace::{linear_mapping_base_vaddr, linear_mapping_vaddr_range, KERNEL_PAGE_TABLE},
         page_prop::{CachePolicy, PageProperty},
         PageFlags, PrivilegedPageFlags as PrivFlags, PAGE_SIZE,
     },
@@ -72,7 +72,7 @@ fn handle_kernel_page_fault(f: &TrapFrame) {
     );
 
     assert!(
-        LINEAR_MAPPING_VADDR_RANGE.contains(&(page_fault_vaddr as usize)),
+        linear_mapping_vaddr_range().contains(&(page_fault_vaddr as usize)),
         "kernel page fault: the address is outside the range of the linear mapping",
     );
 
@@ -98,7 +98,7 @@ fn handle_kernel_page_fault(f: &TrapFrame) {
         .get()
         .expect("kernel page fault: the kernel page table is not initialized");
     let vaddr = (page_fault_vaddr as usize).align_down(PAGE_SIZE);
-    let paddr = vaddr - LINEAR_MAPPING_BASE_VADDR;
+    let paddr = vaddr - linear_mapping_base_vaddr();
 
     // SAFETY:
     // 1. We have checked that the page fault address falls within the address range of the direct
@@ -117,6 +117,7 @@ fn handle_kernel_page_fault(f: &TrapFrame) {
                     priv_flags: PrivFlags::GLOBAL,
                     #[cfg(feature = "intel_tdx")]
                     priv_flags: PrivFlags::SHARED | PrivFlags::GLOBAL,
+                    pkey: 0,
                 },
             )
             .unwrap();
@@ -106,6 +106,8 @@ define_global_static_boot_arguments!(
 /// mappings are cancelled.
 pub fn init() {
     call_all_boot_init_callbacks();
+    // The bootloader may not provide an initramfs, which is treated as an empty one.
+    INITRAMFS.call_once(|| &[]);
 }
 
 /// Calls the framework-user defined entrypoint of the actual kernel.
@@ -6,8 +6,9 @@ use log::info;
 use super::VIRTIO_MMIO_MAGIC;
 use crate::{
     io_mem::IoMem,
-    mm::{paddr_to_vaddr, Paddr, VmIo},
+    mm::{Paddr, VmIo},
     trap::IrqLine,
+    Result,
 };
 
 /// MMIO Common device.
@@ -20,14 +21,10 @@ pub struct MmioCommonDevice {
 }
 
 impl MmioCommonDevice {
-    pub(super) fn new(paddr: Paddr, handle: IrqLine) -> Self {
+    pub(super) fn new(paddr: Paddr, handle: IrqLine) -> Result<Self> {
+        let io_mem = IoMem::acquire(paddr..paddr + 0x200, "virtio-mmio")?;
         // Read magic value
-        // SAFETY: It only read the value and judge if the magic value fit 0x74726976
-        unsafe {
-            debug_assert_eq!(*(paddr_to_vaddr(paddr) as *const u32), VIRTIO_MMIO_MAGIC);
-        }
-        // SAFETY: This range is virtio-mmio device space.
-        let io_mem = unsafe { IoMem::new(paddr..paddr + 0x200) };
+        debug_assert_eq!(io_mem.read_val::<u32>(0).unwrap(), VIRTIO_MMIO_MAGIC);
         let res = Self {
             io_mem,
             irq: handle,
@@ -37,7 +34,7 @@ impl MmioCommonDevice {
             res.device_id(),
             res.irq.num()
         );
-        res
+        Ok(res)
     }
 
     pub fn address(&self) -> Paddr {
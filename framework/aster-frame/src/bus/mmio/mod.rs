@@ -12,13 +12,13 @@ use core::ops::Range;
 
 #[cfg(feature = "intel_tdx")]
 use ::tdx_guest::tdx_is_enabled;
-use log::debug;
+use log::{debug, warn};
 
 use self::bus::MmioBus;
 #[cfg(feature = "intel_tdx")]
 use crate::arch::tdx_guest;
 use crate::{
-    arch::kernel::IO_APIC, bus::mmio::device::MmioCommonDevice, mm::paddr_to_vaddr, sync::SpinLock,
+    arch::kernel::IO_APIC, bus::mmio::device::MmioCommonDevice, io_mem::IoMem, sync::SpinLock,
     trap::IrqLine,
 };
 
@@ -57,11 +57,14 @@ fn iter_range(range: Range<usize>) {
     let mut device_count = 0;
     while current > range.start {
         current -= 0x100;
-        // SAFETY: It only read the value and judge if the magic value fit 0x74726976
-        let value = unsafe { *(paddr_to_vaddr(current) as *const u32) };
+        let Ok(io_mem) = IoMem::acquire(current..current + 0x100, "virtio-mmio") else {
+            continue;
+        };
+        let value = io_mem.read_val::<u32>(0).unwrap();
         if value == VIRTIO_MMIO_MAGIC {
-            // SAFETY: It only read the device id
-            let device_id = unsafe { *(paddr_to_vaddr(current + 8) as *const u32) };
+            let device_id = io_mem.read_val::<u32>(8).unwrap();
+            // Release the probed range for the device.
+            drop(io_mem);
             device_count += 1;
             if device_id == 0 {
                 continue;
@@ -70,8 +73,13 @@ fn iter_range(range: Range<usize>) {
             // If has two IOApic, then start: 24 (0 in IOApic2), end 47 (23 in IOApic2)
             // If one IOApic, then start: 16, end 23
             io_apic.enable(24 - device_count, handle.clone()).unwrap();
-            let device = MmioCommonDevice::new(current, handle);
-            lock.register_mmio_device(device);
+            match MmioCommonDevice::new(current, handle) {
+                Ok(device) => lock.register_mmio_device(device),
+                Err(err) => warn!(
+                    "[Virtio]: Failed to acquire MMIO device at {:#x}: {:?}",
+                    current, err
+                ),
+            }
         }
     }
 }
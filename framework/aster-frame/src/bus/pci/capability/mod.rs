@@ -4,13 +4,14 @@
 
 use alloc::vec::Vec;
 
-use self::{msix::CapabilityMsixData, vendor::CapabilityVndrData};
+use self::{msi::CapabilityMsiData, msix::CapabilityMsixData, vendor::CapabilityVndrData};
 use super::{
     cfg_space::{PciDeviceCommonCfgOffset, Status},
     common_device::PciCommonDevice,
     PciDeviceLocation,
 };
 
+pub mod msi;
 pub mod msix;
 pub mod vendor;
 
@@ -37,7 +38,7 @@ pub enum CapabilityData {
     /// Id:0x04, Slot Identification
     SlotId,
     /// Id:0x05, Message Signalled Interrupts
-    Msi,
+    Msi(CapabilityMsiData),
     /// Id:0x06, CompactPCI HotSwap
     Chswp,
     /// Id:0x07, PCI-X
@@ -110,7 +111,7 @@ impl Capability {
                 0x02 => CapabilityData::Agp,
                 0x03 => CapabilityData::Vpd,
                 0x04 => CapabilityData::SlotId,
-                0x05 => CapabilityData::Msi,
+                0x05 => CapabilityData::Msi(CapabilityMsiData::new(dev, cap_ptr)),
                 0x06 => CapabilityData::Chswp,
                 0x07 => CapabilityData::PciX,
                 0x08 => CapabilityData::Hp,
@@ -141,3 +142,10 @@ impl Capability {
         capabilities
     }
 }
+
+/// Returns the message address of MSI and MSI-X that delivers the interrupts to the CPU.
+fn message_address(cpu_id: u32) -> u32 {
+    // TODO: Different architecture seems to have different, so we should set different address here.
+    // The destination ID is in bits 12-19, with the physical destination mode.
+    0xFEE0_0000u32 | ((crate::arch::cpu::apic_id(cpu_id) & 0xFF) << 12)
+}
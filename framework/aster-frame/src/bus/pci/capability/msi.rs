@@ -0,0 +1,124 @@
+// SPDX-License-Identifier: MPL-2.0
+
+#![allow(dead_code)]
+
+use alloc::boxed::Box;
+
+use super::message_address;
+use crate::{
+    bus::pci::{
+        cfg_space::{Command, PciDeviceCommonCfgOffset},
+        common_device::PciCommonDevice,
+        device_info::PciDeviceLocation,
+    },
+    trap::{register_router, IrqLine},
+};
+
+/// MSI capability.
+///
+/// Only one vector is used even if the device is capable of multiple messages, since
+/// the vectors of multiple messages must be contiguous and aligned, which the IRQ
+/// allocator does not provide. It is the same as what Linux does on x86 without the
+/// interrupt remapping.
+#[derive(Debug, Clone)]
+pub struct CapabilityMsiData {
+    loc: PciDeviceLocation,
+    ptr: u16,
+    /// Whether the message address is 64-bit.
+    is_64bit: bool,
+    /// Whether the vectors can be masked.
+    is_maskable: bool,
+    /// The number of the vectors that the device is capable of.
+    nr_capable_vectors: u16,
+    irq: Option<IrqLine>,
+}
+
+impl CapabilityMsiData {
+    /// The bit of Message Control that enables MSI.
+    const ENABLE: u16 = 1 << 0;
+    /// The bits of Message Control that are the number of the enabled vectors.
+    const MULTIPLE_MESSAGE_ENABLE: u16 = 0b111 << 4;
+    const ADDRESS_64BIT: u16 = 1 << 7;
+    const PER_VECTOR_MASKING: u16 = 1 << 8;
+
+    pub(super) fn new(dev: &mut PciCommonDevice, cap_ptr: u16) -> Self {
+        let control = dev.location().read16(cap_ptr + 2);
+        // MSI stays disabled until a vector is set, so the device raises no interrupts
+        // that nobody handles.
+        dev.location().write16(cap_ptr + 2, control & !Self::ENABLE);
+
+        Self {
+            loc: *dev.location(),
+            ptr: cap_ptr,
+            is_64bit: control & Self::ADDRESS_64BIT != 0,
+            is_maskable: control & Self::PER_VECTOR_MASKING != 0,
+            // Bits 3:1 are the log2 of the number of the capable vectors.
+            nr_capable_vectors: 1 << ((control >> 1) & 0b111),
+            irq: None,
+        }
+    }
+
+    /// Returns the number of the vectors that the device is capable of.
+    pub fn nr_capable_vectors(&self) -> u16 {
+        self.nr_capable_vectors
+    }
+
+    /// Returns whether MSI is enabled, i.e., a vector is set.
+    pub fn is_enabled(&self) -> bool {
+        self.loc.read16(self.ptr + 2) & Self::ENABLE != 0
+    }
+
+    /// Sets the IRQ of the vector and enables MSI, which is handled by the first CPU at
+    /// first.
+    ///
+    /// The CPUs that handle the IRQ can be changed with [`IrqLine::set_affinity`].
+    pub fn set_interrupt_vector(&mut self, handle: IrqLine) {
+        let irq_num = handle.num();
+        let loc = self.loc;
+        let (data_offset, mask_offset) = if self.is_64bit {
+            (self.ptr + 0xC, self.ptr + 0x10)
+        } else {
+            (self.ptr + 0x8, self.ptr + 0xC)
+        };
+
+        loc.write32(self.ptr + 4, message_address(0));
+        if self.is_64bit {
+            loc.write32(self.ptr + 8, 0);
+        }
+        loc.write16(data_offset, irq_num as u16);
+        if self.is_maskable {
+            loc.write32(mask_offset, 0);
+        }
+        self.irq = Some(handle);
+
+        // Enable MSI with one vector, and disable INTx.
+        let control = loc.read16(self.ptr + 2) & !Self::MULTIPLE_MESSAGE_ENABLE;
+        loc.write16(self.ptr + 2, control | Self::ENABLE);
+        let command_offset = PciDeviceCommonCfgOffset::Command as u16;
+        let command = loc.read16(command_offset);
+        loc.write16(
+            command_offset,
+            command | (Command::INTERRUPT_DISABLE | Command::BUS_MASTER).bits(),
+        );
+
+        let is_maskable = self.is_maskable;
+        let address_offset = self.ptr + 4;
+        register_router(
+            irq_num,
+            Box::new(move |cpu| {
+                // Mask the vector while changing its address, if it can be masked.
+                if is_maskable {
+                    loc.write32(mask_offset, 1);
+                }
+                loc.write32(address_offset, message_address(cpu));
+                if is_maskable {
+                    loc.write32(mask_offset, 0);
+                }
+            }),
+        );
+    }
+
+    pub fn irq_mut(&mut self) -> Option<&mut IrqLine> {
+        self.irq.as_mut()
+    }
+}
@@ -3,11 +3,12 @@
 #![allow(dead_code)]
 #![allow(unused_variables)]
 
-use alloc::{sync::Arc, vec::Vec};
+use alloc::{boxed::Box, sync::Arc, vec::Vec};
 
 #[cfg(feature = "intel_tdx")]
 use ::tdx_guest::tdx_is_enabled;
 
+use super::message_address;
 #[cfg(feature = "intel_tdx")]
 use crate::arch::tdx_guest;
 use crate::{
@@ -17,7 +18,7 @@ use crate::{
         device_info::PciDeviceLocation,
     },
     mm::VmIo,
-    trap::IrqLine,
+    trap::{register_router, IrqLine},
 };
 
 /// MSI-X capability. It will set the BAR space it uses to be hidden.
@@ -92,8 +93,7 @@ impl CapabilityMsixData {
         let table_offset = (table_info & !(0b111u32)) as usize;
 
         let table_size = (dev.location().read16(cap_ptr + 2) & 0b11_1111_1111) + 1;
-        // TODO: Different architecture seems to have different, so we should set different address here.
-        let message_address = 0xFEE0_0000u32;
+        let message_address = message_address(0);
         let message_upper_address = 0u32;
 
         // Set message address 0xFEE0_0000
@@ -155,10 +155,14 @@ impl CapabilityMsixData {
         (self.loc.read16(self.ptr + 2) & 0b11_1111_1111) + 1
     }
 
+    /// Sets the IRQ of the MSI-X vector, which is handled by the first CPU at first.
+    ///
+    /// The CPUs that handle the IRQ can be changed with [`IrqLine::set_affinity`].
     pub fn set_interrupt_vector(&mut self, handle: IrqLine, index: u16) {
         if index >= self.table_size {
             return;
         }
+        let irq_num = handle.num();
         self.table_bar
             .io_mem()
             .write_val(
@@ -172,6 +176,21 @@ impl CapabilityMsixData {
             .io_mem()
             .write_val((16 * index + 12) as usize + self.table_offset, &0_u32)
             .unwrap();
+
+        let table_bar = self.table_bar.clone();
+        let entry_offset = (16 * index) as usize + self.table_offset;
+        register_router(
+            irq_num,
+            Box::new(move |cpu| {
+                let io_mem = table_bar.io_mem();
+                // Mask the vector while changing its address.
+                io_mem.write_val(entry_offset + 12, &1_u32).unwrap();
+                io_mem
+                    .write_val(entry_offset, &message_address(cpu))
+                    .unwrap();
+                io_mem.write_val(entry_offset + 12, &0_u32).unwrap();
+            }),
+        );
     }
 
     pub fn irq_mut(&mut self, index: usize) -> Option<&mut IrqLine> {
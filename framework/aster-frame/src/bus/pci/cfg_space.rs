@@ -7,8 +7,9 @@ use bitflags::bitflags;
 
 use super::PciDeviceLocation;
 use crate::{
-    arch::device::io_port::{PortRead, PortWrite},
+    arch::device::io_port::{read_port, write_port, PortRead, PortWrite},
     io_mem::IoMem,
+    io_resource::{self, IoResourceKind, IoToken},
     Error, Result,
 };
 
@@ -167,7 +168,7 @@ impl MemoryBar {
             size,
             prefetchable,
             address_length,
-            io_memory: unsafe { IoMem::new((base as usize)..((base + size as u64) as usize)) },
+            io_memory: IoMem::acquire((base as usize)..((base + size as u64) as usize), "pci")?,
         })
     }
 }
@@ -179,10 +180,11 @@ pub enum AddrLen {
     Bits64,
 }
 
-#[derive(Debug, Clone, Copy)]
+#[derive(Debug, Clone)]
 pub struct IoBar {
     base: u32,
     size: u32,
+    token: Arc<IoToken>,
 }
 
 impl IoBar {
@@ -194,6 +196,11 @@ impl IoBar {
         self.size
     }
 
+    /// Returns the token of the acquired I/O ports.
+    pub fn token(&self) -> &Arc<IoToken> {
+        &self.token
+    }
+
     pub fn read<T: PortRead>(&self, offset: u32) -> Result<T> {
         // Check alignment
         if (self.base + offset) % size_of::<T>() as u32 != 0 {
@@ -205,7 +212,7 @@ impl IoBar {
         }
         // SAFETY: The range of ports accessed is within the scope managed by the IoBar and
         // an out-of-bounds check is performed.
-        unsafe { Ok(T::read_from_port((self.base + offset) as u16)) }
+        unsafe { Ok(read_port((self.base + offset) as u16)) }
     }
 
     pub fn write<T: PortWrite>(&self, offset: u32, value: T) -> Result<()> {
@@ -219,7 +226,7 @@ impl IoBar {
         }
         // SAFETY: The range of ports accessed is within the scope managed by the IoBar and
         // an out-of-bounds check is performed.
-        unsafe { T::write_to_port((self.base + offset) as u16, value) }
+        unsafe { write_port((self.base + offset) as u16, value) }
         Ok(())
     }
 
@@ -229,10 +236,18 @@ impl IoBar {
         location.write32(offset, !0);
         let len_encoded = location.read32(offset);
         location.write32(offset, raw);
-        let len = !(len_encoded & !0x3) + 1;
+        // Only the lower 16 bits are decoded in the I/O space of x86.
+        let len = (!(len_encoded & !0x3) + 1) & 0xFFFF;
+        let base = raw & !0x3;
+        let token = io_resource::acquire(
+            IoResourceKind::Port,
+            base as usize..(base as usize + len as usize),
+            "pci",
+        )?;
         Ok(Self {
-            base: raw & !0x3,
+            base,
             size: len,
+            token,
         })
     }
 }
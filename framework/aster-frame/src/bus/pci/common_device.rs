@@ -143,6 +143,16 @@ impl BarManager {
         };
     }
 
+    /// Gains access to the BAR space that holds the MSI-X table or the pending bits
+    /// together with the registers of the device, and returns None if that BAR is absent.
+    ///
+    /// Some devices, e.g., NVMe controllers, place their registers and the MSI-X structures
+    /// in the same BAR. The drivers of such devices must not access the MSI-X structures
+    /// through the returned BAR.
+    pub fn bar_shared_with_msix(&self, idx: u8) -> Option<Bar> {
+        self.bar_space_without_invisible(idx)
+    }
+
     /// Gain access to the BAR space and return None if that BAR is absent.
     pub(super) fn bar_space_without_invisible(&self, idx: u8) -> Option<Bar> {
         if let Some((bar, _)) = self.bars[idx as usize].clone() {
@@ -6,6 +6,9 @@ use core::{cell::UnsafeCell, ops::Deref};
 
 use crate::trap::disable_local;
 
+/// The maximum number of CPUs, which is the number of copies of each CPU-local object.
+pub const MAX_CPUS: usize = 64;
+
 cfg_if::cfg_if! {
     if #[cfg(target_arch = "x86_64")]{
         pub use trapframe::GeneralRegs;
@@ -39,42 +42,52 @@ macro_rules! cpu_local {
 
     // multiple declarations
     ($(#[$attr:meta])* $vis:vis static $name:ident: $t:ty = $init:expr; $($rest:tt)*) => {
-        $(#[$attr])* $vis static $name: $crate::CpuLocal<$t> = unsafe { $crate::CpuLocal::new($init) };
+        $crate::cpu_local!($(#[$attr])* $vis static $name: $t = $init);
         $crate::cpu_local!($($rest)*);
     };
 
     // single declaration
     ($(#[$attr:meta])* $vis:vis static $name:ident: $t:ty = $init:expr) => (
-        // TODO: reimplement cpu-local variable to support multi-core
-        $(#[$attr])* $vis static $name: $crate::CpuLocal<$t> = $crate::CpuLocal::new($init);
+        $(#[$attr])* $vis static $name: $crate::CpuLocal<$t> = {
+            // Each CPU gets its own copy of the initial value.
+            #[allow(clippy::declare_interior_mutable_const)]
+            const INIT: $t = $init;
+            // SAFETY: The CPU-local object is only declared by this macro.
+            unsafe { $crate::CpuLocal::new([INIT; $crate::cpu::MAX_CPUS]) }
+        };
     );
 }
 
 /// CPU-local objects.
 ///
+/// Each CPU has its own copy of a CPU-local object, and the copy of the current CPU is
+/// accessed.
+///
 /// A CPU-local object only gives you immutable references to the underlying value.
 /// To mutate the value, one can use atomic values (e.g., [`AtomicU32`]) or internally mutable
 /// objects (e.g., [`RefCell`]).
 ///
-/// The `CpuLocal<T: Sync>` can be used directly.
+/// The `CpuLocal<T: Sync>` can be used directly. Note that the task may be moved to another
+/// CPU afterwards unless the preemption is disabled, so the object may not belong to the
+/// current CPU any more when it is used.
 /// Otherwise, the `CpuLocal<T>` must be used through [`borrow_with`].
 ///
-/// TODO: re-implement `CpuLocal`
-///
 /// [`AtomicU32`]: core::sync::atomic::AtomicU32
 /// [`RefCell`]: core::cell::RefCell
 /// [`borrow_with`]: CpuLocal::borrow_with
-pub struct CpuLocal<T>(UnsafeCell<T>);
+pub struct CpuLocal<T>(UnsafeCell<[T; MAX_CPUS]>);
 
-// SAFETY: At any given time, only one task can access the inner value T of a cpu-local variable.
+// SAFETY: At any given time, only one task can access the inner value T of a cpu-local variable,
+// since each CPU only accesses its own copy through `borrow_with` with the local IRQs disabled.
+// The copies of other CPUs are only accessed if `T` is `Sync`.
 unsafe impl<T> Sync for CpuLocal<T> {}
 
 impl<T> CpuLocal<T> {
     /// Initialize CPU-local object
     /// Developer cannot construct a valid CpuLocal object arbitrarily
     #[allow(clippy::missing_safety_doc)]
-    pub const unsafe fn new(val: T) -> Self {
-        Self(UnsafeCell::new(val))
+    pub const unsafe fn new(vals: [T; MAX_CPUS]) -> Self {
+        Self(UnsafeCell::new(vals))
     }
 
     /// Borrow an immutable reference to the underlying value and feed it to a closure.
@@ -83,18 +96,35 @@ impl<T> CpuLocal<T> {
     /// the CPU-local object is only accessed by the current task or IRQ handler.
     /// As local IRQs are disabled, one should keep the closure as short as possible.
     pub fn borrow_with<'a, U, F: FnOnce(&'a T) -> U>(this: &'a Self, f: F) -> U {
-        // FIXME: implement disable preemption
-        // Disable interrupts when accessing cpu-local variable
+        // Disable interrupts when accessing cpu-local variable, which also keeps the
+        // current task on this CPU.
         let _guard = disable_local();
         // SAFETY: Now that the local IRQs are disabled, this CPU-local object can only be
         // accessed by the current task/thread. So it is safe to get its immutable reference
         // regardless of whether `T` implements `Sync` or not.
-        let val_ref = unsafe { this.do_borrow() };
+        let val_ref = unsafe { this.do_borrow(this_cpu()) };
         f(val_ref)
     }
 
-    unsafe fn do_borrow(&self) -> &T {
-        &*self.0.get()
+    /// # Safety
+    ///
+    /// The copy of the CPU must be only accessed by the CPU, unless `T` is `Sync`.
+    unsafe fn do_borrow(&self, cpu_id: u32) -> &T {
+        let vals = self.0.get() as *const T;
+        &*vals.add(cpu_id as usize)
+    }
+}
+
+impl<T: Sync> CpuLocal<T> {
+    /// Gets the copy of the given CPU.
+    ///
+    /// # Panics
+    ///
+    /// This method panics if the CPU ID is out of bounds.
+    pub fn get_on_cpu(&self, cpu_id: u32) -> &T {
+        assert!((cpu_id as usize) < MAX_CPUS);
+        // SAFETY: `T` is `Sync`, so the copy can be accessed from any CPU.
+        unsafe { self.do_borrow(cpu_id) }
     }
 }
 
@@ -102,6 +132,6 @@ impl<T: Sync> Deref for CpuLocal<T> {
     type Target = T;
 
     fn deref(&self) -> &T {
-        unsafe { &*self.0.get() }
+        self.get_on_cpu(this_cpu())
     }
 }
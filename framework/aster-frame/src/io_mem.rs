@@ -2,25 +2,44 @@
 
 //! I/O memory.
 
+use alloc::sync::Arc;
 use core::{mem::size_of, ops::Range};
 
+#[cfg(feature = "intel_tdx")]
+use ::tdx_guest::tdx_is_enabled;
 use pod::Pod;
 
+#[cfg(feature = "intel_tdx")]
+use crate::arch::tdx_guest;
 use crate::{
-    mm::{kspace::LINEAR_MAPPING_BASE_VADDR, paddr_to_vaddr, HasPaddr, Paddr, Vaddr, VmIo},
+    io_resource::{self, IoResourceKind, IoToken},
+    mm::{kspace::linear_mapping_base_vaddr, paddr_to_vaddr, HasPaddr, Paddr, Vaddr, VmIo},
     Error, Result,
 };
 
 /// I/O memory.
+///
+/// Drivers acquire the I/O memory of their devices with [`IoMem::acquire`], which fails
+/// if the memory is used by others. The clones of an `IoMem` share the acquired range,
+/// which is released once all of them are dropped.
 #[derive(Debug, Clone)]
 pub struct IoMem {
     virtual_address: Vaddr,
     limit: usize,
+    token: Arc<IoToken>,
 }
 
 impl VmIo for IoMem {
     fn read_bytes(&self, offset: usize, buf: &mut [u8]) -> crate::Result<()> {
         self.check_range(offset, buf.len())?;
+        #[cfg(feature = "intel_tdx")]
+        if tdx_is_enabled() {
+            for (i, byte) in buf.iter_mut().enumerate() {
+                // SAFETY: The address is in the acquired I/O memory.
+                *byte = unsafe { tdx_guest::read_mmio_at(1, self.paddr() + offset + i) } as u8;
+            }
+            return Ok(());
+        }
         unsafe {
             core::ptr::copy(
                 (self.virtual_address + offset) as *const u8,
@@ -33,6 +52,14 @@ impl VmIo for IoMem {
 
     fn write_bytes(&self, offset: usize, buf: &[u8]) -> crate::Result<()> {
         self.check_range(offset, buf.len())?;
+        #[cfg(feature = "intel_tdx")]
+        if tdx_is_enabled() {
+            for (i, byte) in buf.iter().enumerate() {
+                // SAFETY: The address is in the acquired I/O memory.
+                unsafe { tdx_guest::write_mmio_at(1, self.paddr() + offset + i, *byte as u64) };
+            }
+            return Ok(());
+        }
         unsafe {
             core::ptr::copy(
                 buf.as_ptr(),
@@ -45,11 +72,27 @@ impl VmIo for IoMem {
 
     fn read_val<T: Pod>(&self, offset: usize) -> crate::Result<T> {
         self.check_range(offset, size_of::<T>())?;
+        #[cfg(feature = "intel_tdx")]
+        if tdx_is_enabled() && matches!(size_of::<T>(), 1 | 2 | 4 | 8) {
+            // SAFETY: The address is in the acquired I/O memory.
+            let value = unsafe { tdx_guest::read_mmio_at(size_of::<T>(), self.paddr() + offset) };
+            // SAFETY: `T` is a POD type, whose value is in the lower bytes of `value` on
+            // little-endian x86.
+            return Ok(unsafe { core::mem::transmute_copy(&value) });
+        }
         Ok(unsafe { core::ptr::read_volatile((self.virtual_address + offset) as *const T) })
     }
 
     fn write_val<T: Pod>(&self, offset: usize, new_val: &T) -> crate::Result<()> {
         self.check_range(offset, size_of::<T>())?;
+        #[cfg(feature = "intel_tdx")]
+        if tdx_is_enabled() && matches!(size_of::<T>(), 1 | 2 | 4 | 8) {
+            let mut value = 0u64;
+            value.as_bytes_mut()[..size_of::<T>()].copy_from_slice(new_val.as_bytes());
+            // SAFETY: The address is in the acquired I/O memory.
+            unsafe { tdx_guest::write_mmio_at(size_of::<T>(), self.paddr() + offset, value) };
+            return Ok(());
+        }
         unsafe { core::ptr::write_volatile((self.virtual_address + offset) as *mut T, *new_val) };
         Ok(())
     }
@@ -57,26 +100,36 @@ impl VmIo for IoMem {
 
 impl HasPaddr for IoMem {
     fn paddr(&self) -> Paddr {
-        self.virtual_address - LINEAR_MAPPING_BASE_VADDR
+        self.virtual_address - linear_mapping_base_vaddr()
     }
 }
 
 impl IoMem {
-    /// Creates a new `IoMem`.
+    /// Acquires the I/O memory in the physical range for the owner.
     ///
-    /// # Safety
+    /// # Errors
     ///
-    /// User must ensure the given physical range is in the I/O memory region.
-    pub(crate) unsafe fn new(range: Range<Paddr>) -> IoMem {
-        IoMem {
+    /// Returns [`Error::InvalidArgs`] if the range is empty or overlaps the RAM, and
+    /// [`Error::AccessDenied`] if some of the range is used by others.
+    pub fn acquire(range: Range<Paddr>, owner: &'static str) -> Result<IoMem> {
+        let token = io_resource::acquire(IoResourceKind::Memory, range.clone(), owner)?;
+        Ok(IoMem {
             virtual_address: paddr_to_vaddr(range.start),
             limit: range.len(),
-        }
+            token,
+        })
+    }
+
+    /// Returns the token of the acquired I/O memory.
+    ///
+    /// The range of the token may be larger than that of this `IoMem` after [`Self::resize`].
+    pub fn token(&self) -> &Arc<IoToken> {
+        &self.token
     }
 
     /// Returns the physical address of the I/O memory.
     pub fn paddr(&self) -> Paddr {
-        self.virtual_address - LINEAR_MAPPING_BASE_VADDR
+        self.virtual_address - linear_mapping_base_vaddr()
     }
 
     /// Returns the length of the I/O memory region.
@@ -0,0 +1,236 @@
+// SPDX-License-Identifier: MPL-2.0
+
+//! The central manager of the I/O resources, i.e., the I/O ports and the I/O memory.
+//!
+//! Device drivers do not access their devices through raw port numbers or physical
+//! addresses. Instead, a driver acquires typed handles, i.e., [`IoPort`]s and
+//! [`IoMem`]s, for the ranges of its device. This manager hands out each range to one
+//! owner at a time and records the owner, so that conflicting drivers are detected and
+//! the accesses to the devices can be audited. A handle keeps an [`IoToken`] of its
+//! range, which returns the range to the manager once all the handles are dropped.
+//!
+//! [`IoPort`]: crate::arch::device::io_port::IoPort
+//! [`IoMem`]: crate::io_mem::IoMem
+
+use alloc::{collections::BTreeMap, sync::Arc, vec::Vec};
+use core::ops::Range;
+
+use log::warn;
+
+use crate::{boot::memory_region::MemoryRegionType, sync::SpinLock, Error, Result};
+
+/// The kinds of the I/O resources.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum IoResourceKind {
+    /// The I/O ports, which range from 0 to 0xFFFF.
+    Port,
+    /// The I/O memory, whose ranges are physical addresses.
+    Memory,
+}
+
+/// The capability of a driver to access a range of I/O resources.
+///
+/// The range is released when the token is dropped.
+#[derive(Debug)]
+pub struct IoToken {
+    kind: IoResourceKind,
+    range: Range<usize>,
+    owner: &'static str,
+}
+
+impl IoToken {
+    /// Returns the kind of the resources.
+    pub fn kind(&self) -> IoResourceKind {
+        self.kind
+    }
+
+    /// Returns the range of the resources.
+    pub fn range(&self) -> Range<usize> {
+        self.range.clone()
+    }
+
+    /// Returns the name of the owner.
+    pub fn owner(&self) -> &'static str {
+        self.owner
+    }
+}
+
+impl Drop for IoToken {
+    fn drop(&mut self) {
+        resources(self.kind).lock().remove(self.range.start);
+    }
+}
+
+/// Acquires a range of I/O resources for the owner.
+///
+/// # Errors
+///
+/// Returns [`Error::InvalidArgs`] if the range is empty or out of the valid range of
+/// the resources, and [`Error::AccessDenied`] if some of the resources are acquired by
+/// others.
+pub(crate) fn acquire(
+    kind: IoResourceKind,
+    range: Range<usize>,
+    owner: &'static str,
+) -> Result<Arc<IoToken>> {
+    check_range(kind, &range)?;
+    resources(kind).lock().insert(&range, owner)?;
+    Ok(Arc::new(IoToken { kind, range, owner }))
+}
+
+/// Returns the acquired ranges of the resources and their owners, in ascending order.
+pub fn acquired_ranges(kind: IoResourceKind) -> Vec<(Range<usize>, &'static str)> {
+    resources(kind)
+        .lock()
+        .ranges
+        .iter()
+        .map(|(start, (end, owner))| (*start..*end, *owner))
+        .collect()
+}
+
+pub(crate) fn init() {
+    // The ports used by the framework itself are never released.
+    for (ports, owner) in crate::arch::device::io_port::FRAMEWORK_IO_PORTS {
+        let range = ports.start as usize..ports.end as usize;
+        resources(IoResourceKind::Port)
+            .lock()
+            .insert(&range, owner)
+            .unwrap();
+    }
+}
+
+/// The maximum port number plus one.
+const NR_IO_PORTS: usize = 0x10000;
+
+fn check_range(kind: IoResourceKind, range: &Range<usize>) -> Result<()> {
+    if range.is_empty() {
+        return Err(Error::InvalidArgs);
+    }
+    match kind {
+        IoResourceKind::Port => {
+            if range.end > NR_IO_PORTS {
+                return Err(Error::InvalidArgs);
+            }
+        }
+        IoResourceKind::Memory => {
+            // The I/O memory must not be the RAM, which is managed by the frame allocator.
+            let overlaps_ram = crate::boot::memory_regions().iter().any(|region| {
+                matches!(
+                    region.typ(),
+                    MemoryRegionType::Kernel
+                        | MemoryRegionType::Module
+                        | MemoryRegionType::Reclaimable
+                        | MemoryRegionType::Usable
+                ) && region.base() < range.end
+                    && range.start < region.base() + region.len()
+            });
+            if overlaps_ram {
+                warn!("I/O memory {:#x?} overlaps the RAM", range);
+                return Err(Error::InvalidArgs);
+            }
+        }
+    }
+    Ok(())
+}
+
+/// The acquired ranges of a kind of resources.
+struct AcquiredRanges {
+    /// The start of each range, mapped to the end and the owner.
+    ranges: BTreeMap<usize, (usize, &'static str)>,
+}
+
+impl AcquiredRanges {
+    const fn new() -> Self {
+        Self {
+            ranges: BTreeMap::new(),
+        }
+    }
+
+    fn insert(&mut self, range: &Range<usize>, owner: &'static str) -> Result<()> {
+        // Since the ranges do not overlap, only the last range that starts before the end
+        // of the new range may overlap it.
+        if let Some((start, (end, other))) = self.ranges.range(..range.end).next_back()
+            && *end > range.start
+        {
+            warn!(
+                "{} cannot acquire {:#x?}, which conflicts with {:#x?} of {}",
+                owner,
+                range,
+                *start..*end,
+                other
+            );
+            return Err(Error::AccessDenied);
+        }
+        self.ranges.insert(range.start, (range.end, owner));
+        Ok(())
+    }
+
+    fn remove(&mut self, start: usize) {
+        self.ranges.remove(&start);
+    }
+}
+
+static IO_PORTS: SpinLock<AcquiredRanges> = SpinLock::new(AcquiredRanges::new());
+static IO_MEMORY: SpinLock<AcquiredRanges> = SpinLock::new(AcquiredRanges::new());
+
+fn resources(kind: IoResourceKind) -> &'static SpinLock<AcquiredRanges> {
+    match kind {
+        IoResourceKind::Port => &IO_PORTS,
+        IoResourceKind::Memory => &IO_MEMORY,
+    }
+}
+
+#[cfg(ktest)]
+mod test {
+    use super::*;
+
+    #[ktest]
+    fn acquire_and_release() {
+        let range = 0xE000..0xE010;
+        let token = acquire(IoResourceKind::Port, range.clone(), "test").unwrap();
+        assert_eq!(token.range(), range);
+        assert!(acquired_ranges(IoResourceKind::Port).contains(&(range.clone(), "test")));
+
+        // The overlapping ranges cannot be acquired until the token is dropped.
+        assert_eq!(
+            acquire(IoResourceKind::Port, 0xE008..0xE018, "other").unwrap_err(),
+            Error::AccessDenied
+        );
+        assert_eq!(
+            acquire(IoResourceKind::Port, 0xDFFF..0xE001, "other").unwrap_err(),
+            Error::AccessDenied
+        );
+        let adjacent = acquire(IoResourceKind::Port, 0xE010..0xE018, "other").unwrap();
+        drop(token);
+        let token = acquire(IoResourceKind::Port, 0xE008..0xE010, "other").unwrap();
+
+        drop(adjacent);
+        drop(token);
+        assert!(acquired_ranges(IoResourceKind::Port)
+            .iter()
+            .all(|(range, _)| range.end <= 0xE000 || range.start >= 0xE018));
+    }
+
+    #[ktest]
+    fn invalid_ranges() {
+        assert_eq!(
+            acquire(IoResourceKind::Port, 0xFFFF..0x10001, "test").unwrap_err(),
+            Error::InvalidArgs
+        );
+        assert_eq!(
+            acquire(IoResourceKind::Port, 0x10..0x10, "test").unwrap_err(),
+            Error::InvalidArgs
+        );
+        // The framework's own ports are reserved.
+        let (ports, _) = &crate::arch::device::io_port::FRAMEWORK_IO_PORTS[0];
+        assert_eq!(
+            acquire(
+                IoResourceKind::Port,
+                ports.start as usize..ports.end as usize,
+                "test"
+            )
+            .unwrap_err(),
+            Error::AccessDenied
+        );
+    }
+}
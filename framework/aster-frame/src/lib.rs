@@ -36,6 +36,7 @@ pub mod console;
 pub mod cpu;
 mod error;
 pub mod io_mem;
+pub mod io_resource;
 pub mod logger;
 pub mod mm;
 pub mod panicking;
@@ -75,16 +76,24 @@ pub fn init() {
 
     boot::init();
 
-    mm::page::allocator::init();
+    mm::memblock::init();
+    arch::boot::smp::reserve_trampoline();
     mm::kspace::init_boot_page_table();
-    mm::kspace::init_kernel_page_table(mm::init_page_meta());
+    let meta_sections = mm::init_page_meta();
+    mm::page::allocator::init();
+    mm::kspace::init_kernel_page_table(meta_sections);
     mm::misc_init();
 
+    io_resource::init();
+
     trap::init();
     arch::after_all_init();
+    task::init();
     bus::init();
 
     mm::kspace::activate_kernel_page_table();
+    mm::kspace::enforce_wx();
+    arch::boot::smp::boot_all_aps();
 
     invoke_ffi_init_funcs();
 }
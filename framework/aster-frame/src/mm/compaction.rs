@@ -0,0 +1,229 @@
+// SPDX-License-Identifier: MPL-2.0
+
+//! Memory compaction.
+//!
+//! On a long-running system, the free memory is fragmented into frames scattered among
+//! the frames in use, so the allocations of contiguous frames, e.g., for huge pages and
+//! DMA buffers, may fail even if there are plenty of free frames. Compaction creates a
+//! contiguous free block by migrating the frames in use within the block elsewhere.
+//!
+//! The frames can only be migrated by their owners, which know where the frames are
+//! referenced, e.g., the VMOs and the page tables that map the frames. So the owners of
+//! movable frames register [`Migrator`]s, which are asked to migrate the frames within
+//! the block to the frames allocated outside the block.
+//!
+//! Compaction is done when an allocation of contiguous frames fails (see
+//! [`FrameAllocOptions::alloc_contiguous`]), or on request (see [`compact_all`]).
+//!
+//! [`FrameAllocOptions::alloc_contiguous`]: crate::mm::FrameAllocOptions::alloc_contiguous
+
+use alloc::{sync::Weak, vec::Vec};
+use core::ops::Range;
+
+use super::{
+    page::{
+        allocator,
+        meta::{page_usage, FrameMeta, PageUsage},
+    },
+    zeroed, Frame, Paddr, PAGE_SIZE,
+};
+use crate::sync::SpinLock;
+
+/// An owner of frames that can move the contents of its frames to other frames.
+pub trait Migrator: Send + Sync {
+    /// Tries to migrate the frames within the physical address range to the frames
+    /// allocated by `alloc`, which may fail if there are no free frames.
+    ///
+    /// Returns the number of frames that have been migrated. The method must not
+    /// block, since compaction may be done in the atomic context.
+    fn migrate(&self, range: &Range<Paddr>, alloc: &mut dyn FnMut() -> Option<Frame>) -> usize;
+}
+
+static MIGRATORS: SpinLock<Vec<Weak<dyn Migrator>>> = SpinLock::new(Vec::new());
+
+/// Registers a migrator.
+///
+/// The migrator is unregistered automatically once it is dropped.
+pub fn register_migrator(migrator: Weak<dyn Migrator>) {
+    let mut migrators = MIGRATORS.lock_irq_disabled();
+    migrators.retain(|migrator| migrator.strong_count() > 0);
+    migrators.push(migrator);
+}
+
+/// The maximum number of blocks that are tried to be compacted for an allocation.
+const MAX_NR_CANDIDATES: usize = 8;
+
+/// Tries to create a free block of `nframes` contiguous frames by compaction.
+///
+/// Returns whether such a block is free after the compaction, though it may be taken by
+/// other allocations before the caller allocates it.
+pub fn compact(nframes: usize) -> bool {
+    // The buddy allocator allocates blocks of the power-of-two sizes.
+    let nframes = nframes.next_power_of_two();
+    // The pre-zeroed frames are free memory as well.
+    zeroed::drain();
+
+    let Some(candidates) = find_candidates(nframes, MAX_NR_CANDIDATES) else {
+        return true;
+    };
+    candidates.into_iter().any(compact_block)
+}
+
+/// Compacts all the memory, like writing to `/proc/sys/vm/compact_memory` in Linux.
+///
+/// The frames in use are migrated from the blocks with few frames in use, so that the
+/// free memory is gathered in large blocks. Returns the number of migrated frames.
+pub fn compact_all() -> usize {
+    const BLOCK_FRAMES: usize = 512;
+
+    zeroed::drain();
+    let Some(candidates) = find_candidates(BLOCK_FRAMES, usize::MAX) else {
+        return 0;
+    };
+    // Only the blocks that are at least half free are compacted, and the frames in use
+    // are moved to the fuller blocks.
+    let blocks: Vec<_> = candidates
+        .into_iter()
+        .filter(|block| nr_free_frames_in(block) >= BLOCK_FRAMES / 2)
+        .collect();
+    migrate_blocks(&blocks)
+}
+
+/// Finds the aligned blocks of `nframes` frames that are the easiest to compact, i.e.,
+/// those with the most free frames, in the descending order of the free frames.
+///
+/// The blocks that contain any frames that cannot be migrated, e.g., page tables, are
+/// skipped. Returns `None` if there is already a free block.
+fn find_candidates(nframes: usize, max_nr_candidates: usize) -> Option<Vec<Range<usize>>> {
+    let mut candidates = Vec::new();
+    for region in allocator::managed_regions() {
+        let mut start = region.start.next_multiple_of(nframes);
+        while start + nframes <= region.end {
+            let block = start..start + nframes;
+            start += nframes;
+
+            let mut nr_free = 0;
+            let is_movable = block.clone().all(|idx| {
+                let usage = page_usage(idx * PAGE_SIZE);
+                if usage == PageUsage::Unused as u8 {
+                    nr_free += 1;
+                }
+                usage == PageUsage::Unused as u8 || usage == PageUsage::Frame as u8
+            });
+            if nr_free == nframes {
+                return None;
+            }
+            if is_movable {
+                candidates.push((nr_free, block));
+            }
+        }
+    }
+
+    candidates.sort_by_key(|(nr_free, _)| core::cmp::Reverse(*nr_free));
+    Some(
+        candidates
+            .into_iter()
+            .take(max_nr_candidates)
+            .map(|(_, block)| block)
+            .collect(),
+    )
+}
+
+/// Migrates the frames in use within the block of frames, and returns whether the whole
+/// block is free then.
+fn compact_block(block: Range<usize>) -> bool {
+    migrate_blocks(core::slice::from_ref(&block));
+    nr_free_frames_in(&block) == block.len()
+}
+
+/// Migrates the frames in use within the disjoint blocks of frames to the frames outside
+/// them, and returns the number of migrated frames.
+fn migrate_blocks(blocks: &[Range<usize>]) -> usize {
+    let mut blocks = blocks.to_vec();
+    blocks.sort_by_key(|block| block.start);
+    let is_in_blocks = |idx: usize| {
+        let pos = blocks.partition_point(|block| block.end <= idx);
+        blocks.get(pos).is_some_and(|block| block.contains(&idx))
+    };
+
+    // The free frames within the blocks that are allocated as the targets are held until
+    // the migration is done, so that they are not allocated again.
+    let mut captured = Vec::new();
+    let mut alloc = || loop {
+        let page = allocator::alloc_single::<FrameMeta>()?;
+        let frame = Frame { page };
+        if !is_in_blocks(frame.start_paddr() / PAGE_SIZE) {
+            return Some(frame);
+        }
+        captured.push(frame);
+    };
+
+    let migrators: Vec<_> = MIGRATORS
+        .lock_irq_disabled()
+        .iter()
+        .filter_map(Weak::upgrade)
+        .collect();
+    let mut nr_migrated = 0;
+    for block in blocks.iter() {
+        let range = block.start * PAGE_SIZE..block.end * PAGE_SIZE;
+        for migrator in migrators.iter() {
+            nr_migrated += migrator.migrate(&range, &mut alloc);
+        }
+    }
+    nr_migrated
+}
+
+fn nr_free_frames_in(block: &Range<usize>) -> usize {
+    block
+        .clone()
+        .filter(|idx| page_usage(idx * PAGE_SIZE) == PageUsage::Unused as u8)
+        .count()
+}
+
+#[cfg(ktest)]
+mod test {
+    use alloc::sync::Arc;
+
+    use super::*;
+    use crate::mm::{FrameAllocOptions, VmIo};
+
+    /// Owns some frames, which it migrates by replacing them with the copies.
+    struct OwningMigrator {
+        frames: SpinLock<Vec<Frame>>,
+    }
+
+    impl Migrator for OwningMigrator {
+        fn migrate(&self, range: &Range<Paddr>, alloc: &mut dyn FnMut() -> Option<Frame>) -> usize {
+            let mut nr_migrated = 0;
+            for frame in self.frames.lock().iter_mut() {
+                if !range.contains(&frame.start_paddr()) {
+                    continue;
+                }
+                let Some(new_frame) = alloc() else {
+                    break;
+                };
+                new_frame.copy_from(frame);
+                *frame = new_frame;
+                nr_migrated += 1;
+            }
+            nr_migrated
+        }
+    }
+
+    #[ktest]
+    fn migrate_owned_frame() {
+        let frame = FrameAllocOptions::new(1).alloc_single().unwrap();
+        frame.write_val(0, &0xdeadbeefu32).unwrap();
+        let idx = frame.start_paddr() / PAGE_SIZE;
+
+        let migrator = Arc::new(OwningMigrator {
+            frames: SpinLock::new(alloc::vec![frame]),
+        });
+        register_migrator(Arc::downgrade(&migrator) as _);
+
+        assert!(compact_block(idx..idx + 1));
+        let frames = migrator.frames.lock();
+        assert_ne!(frames[0].start_paddr(), idx * PAGE_SIZE);
+        assert_eq!(frames[0].read_val::<u32>(0).unwrap(), 0xdeadbeef);
+    }
+}
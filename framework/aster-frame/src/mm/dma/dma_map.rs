@@ -0,0 +1,374 @@
+// SPDX-License-Identifier: MPL-2.0
+
+//! The DMA mappings of the frames for specific devices.
+//!
+//! Unlike [`DmaStream`] and [`DmaCoherent`], which are the buffers owned by the drivers and
+//! accessible to all the devices, the frames mapped by [`dma_map`] are only accessible to the
+//! device that they are mapped for, if there is an IOMMU. So these frames can be those not
+//! owned by the drivers, e.g., the pages of the user programs or the page cache.
+//!
+//! If a device cannot access the frames, e.g., the frames are beyond its addressing
+//! capability or the memory is private in a TDX guest, the data is bounced through the
+//! buffers that the device can access.
+//!
+//! [`DmaCoherent`]: super::DmaCoherent
+
+use core::ops::Range;
+
+#[cfg(feature = "intel_tdx")]
+use ::tdx_guest::tdx_is_enabled;
+use id_alloc::IdAlloc;
+use log::warn;
+use spin::Once;
+
+use super::{dma_type, Daddr, DmaDirection, DmaError, DmaStream, DmaType, HasDaddr};
+use crate::{
+    arch::iommu,
+    bus::pci::PciDeviceLocation,
+    mm::{FrameAllocOptions, Segment, PAGE_SIZE},
+    sync::SpinLock,
+};
+
+/// A device that performs DMA.
+///
+/// If there is an IOMMU, each device has its own protection domain, where the frames
+/// mapped for it are accessible.
+#[derive(Debug, Clone, Copy)]
+pub struct DmaDevice {
+    location: PciDeviceLocation,
+    /// The end of the DMA addresses that the device can access.
+    daddr_limit: Daddr,
+}
+
+impl DmaDevice {
+    /// Creates a device at `location`, which can access the DMA addresses of
+    /// `addr_width` bits.
+    pub fn new(location: PciDeviceLocation, addr_width: u32) -> Self {
+        Self {
+            location,
+            daddr_limit: 1usize.checked_shl(addr_width).unwrap_or(usize::MAX),
+        }
+    }
+
+    /// Returns the location of the device.
+    pub fn location(&self) -> PciDeviceLocation {
+        self.location
+    }
+
+    /// Returns whether the device can access the physical memory of `paddr_range`
+    /// directly, without bouncing.
+    fn can_access(&self, paddr_range: &Range<usize>) -> bool {
+        #[cfg(feature = "intel_tdx")]
+        // Without an IOMMU, the private memory of a TDX guest is inaccessible to the devices.
+        if tdx_is_enabled() && dma_type() == DmaType::Direct {
+            return false;
+        }
+        paddr_range.end <= self.daddr_limit
+    }
+}
+
+/// A DMA mapping of a segment for a device, which is created by [`dma_map`].
+///
+/// Users must synchronize the data with [`sync_for_device`] before the device accesses the
+/// mapping, and with [`sync_for_cpu`] after that, since the data may be bounced.
+///
+/// The mapping is destroyed when this object is dropped, or with [`dma_unmap`]. The bounced
+/// data are synchronized for the CPU as a whole before that, like Linux does on unmapping.
+///
+/// [`sync_for_device`]: Self::sync_for_device
+/// [`sync_for_cpu`]: Self::sync_for_cpu
+#[derive(Debug)]
+pub struct DmaMapping {
+    device: DmaDevice,
+    segment: Segment,
+    direction: DmaDirection,
+    daddr: Daddr,
+    /// The range of the pages in the bounce pool, if the data is bounced.
+    bounce_pages: Option<Range<usize>>,
+}
+
+/// Maps `segment` for `device`, and returns the mapping whose DMA address is for the device.
+///
+/// The same frames can be mapped multiple times, even for different devices.
+pub fn dma_map(
+    device: &DmaDevice,
+    segment: Segment,
+    direction: DmaDirection,
+) -> Result<DmaMapping, DmaError> {
+    let nframes = segment.nframes();
+    let start_paddr = segment.start_paddr();
+    let paddr_range = start_paddr..start_paddr.checked_add(nframes * PAGE_SIZE).unwrap();
+
+    if !device.can_access(&paddr_range) {
+        let bounce_pages = BouncePool::get()
+            .filter(|pool| pool.paddr_range().end <= device.daddr_limit)
+            .and_then(|pool| pool.alloc(nframes))
+            .ok_or(DmaError::NoBounceBuffer)?;
+        let daddr = BouncePool::get().unwrap().stream.daddr() + bounce_pages.start * PAGE_SIZE;
+        return Ok(DmaMapping {
+            device: *device,
+            segment,
+            direction,
+            daddr,
+            bounce_pages: Some(bounce_pages),
+        });
+    }
+
+    if dma_type() == DmaType::Iommu {
+        for i in 0..nframes {
+            let paddr = start_paddr + i * PAGE_SIZE;
+            // SAFETY: The `paddr` is restricted by the `start_paddr` and `nframes` of the
+            // `segment`, which is kept alive by the mapping.
+            let result = unsafe { iommu::map_device(device.location, paddr as Daddr, paddr) };
+            if result.is_err() {
+                for j in 0..i {
+                    let paddr = start_paddr + j * PAGE_SIZE;
+                    iommu::unmap_device(device.location, paddr as Daddr).unwrap();
+                }
+                return Err(DmaError::InvalidArgs);
+            }
+        }
+    }
+    Ok(DmaMapping {
+        device: *device,
+        segment,
+        direction,
+        daddr: start_paddr as Daddr,
+        bounce_pages: None,
+    })
+}
+
+/// Destroys the mapping, which is the same as dropping it.
+pub fn dma_unmap(mapping: DmaMapping) {
+    drop(mapping);
+}
+
+impl DmaMapping {
+    /// Returns the mapped segment.
+    pub fn segment(&self) -> &Segment {
+        &self.segment
+    }
+
+    /// Returns the number of bytes.
+    pub fn nbytes(&self) -> usize {
+        self.segment.nbytes()
+    }
+
+    /// Returns whether the data is bounced.
+    pub fn is_bounced(&self) -> bool {
+        self.bounce_pages.is_some()
+    }
+
+    /// Synchronizes the data in `byte_range` that is written by the CPU, before the device
+    /// reads it.
+    pub fn sync_for_device(&self, byte_range: Range<usize>) -> Result<(), DmaError> {
+        if byte_range.end > self.nbytes() {
+            return Err(DmaError::InvalidArgs);
+        }
+        let Some(bounce_pages) = self.bounce_pages.as_ref() else {
+            return Ok(());
+        };
+        if self.direction == DmaDirection::FromDevice {
+            return Ok(());
+        }
+        let stream = &BouncePool::get().unwrap().stream;
+        let mut reader = self
+            .segment
+            .reader()
+            .skip(byte_range.start)
+            .limit(byte_range.len());
+        let mut writer = stream
+            .writer()
+            .unwrap()
+            .skip(bounce_pages.start * PAGE_SIZE + byte_range.start)
+            .limit(byte_range.len());
+        writer.write(&mut reader);
+        Ok(())
+    }
+
+    /// Synchronizes the data in `byte_range` that is written by the device, before the CPU
+    /// reads it.
+    pub fn sync_for_cpu(&self, byte_range: Range<usize>) -> Result<(), DmaError> {
+        if byte_range.end > self.nbytes() {
+            return Err(DmaError::InvalidArgs);
+        }
+        let Some(bounce_pages) = self.bounce_pages.as_ref() else {
+            return Ok(());
+        };
+        if self.direction == DmaDirection::ToDevice {
+            return Ok(());
+        }
+        let stream = &BouncePool::get().unwrap().stream;
+        let mut reader = stream
+            .reader()
+            .unwrap()
+            .skip(bounce_pages.start * PAGE_SIZE + byte_range.start)
+            .limit(byte_range.len());
+        let mut writer = self
+            .segment
+            .writer()
+            .skip(byte_range.start)
+            .limit(byte_range.len());
+        reader.read(&mut writer);
+        Ok(())
+    }
+}
+
+impl HasDaddr for DmaMapping {
+    fn daddr(&self) -> Daddr {
+        self.daddr
+    }
+}
+
+impl Drop for DmaMapping {
+    fn drop(&mut self) {
+        if self.bounce_pages.is_some() {
+            self.sync_for_cpu(0..self.nbytes()).unwrap();
+            let bounce_pages = self.bounce_pages.take().unwrap();
+            BouncePool::get().unwrap().free(bounce_pages);
+            return;
+        }
+        if dma_type() == DmaType::Iommu {
+            let start_paddr = self.segment.start_paddr();
+            for i in 0..self.segment.nframes() {
+                let paddr = start_paddr + i * PAGE_SIZE;
+                iommu::unmap_device(self.device.location, paddr as Daddr).unwrap();
+            }
+        }
+    }
+}
+
+/// The pool of the bounce buffers, which is accessible to all the devices.
+///
+/// It is like the SWIOTLB of Linux.
+struct BouncePool {
+    stream: DmaStream,
+    allocator: SpinLock<IdAlloc>,
+}
+
+impl BouncePool {
+    /// The number of the pages in the pool, i.e., 4 MiB.
+    const NR_PAGES: usize = 1024;
+
+    /// Returns the pool, which is created at the first use.
+    ///
+    /// Returns `None` if the pool cannot be created.
+    fn get() -> Option<&'static Self> {
+        static BOUNCE_POOL: Once<Option<BouncePool>> = Once::new();
+        BOUNCE_POOL
+            .call_once(|| {
+                let pool = Self::new();
+                if pool.is_none() {
+                    warn!("Failed to create the pool of the DMA bounce buffers");
+                }
+                pool
+            })
+            .as_ref()
+    }
+
+    fn new() -> Option<Self> {
+        let segment = FrameAllocOptions::new(Self::NR_PAGES)
+            .is_contiguous(true)
+            .alloc_contiguous()
+            .ok()?;
+        let stream = DmaStream::map(segment, DmaDirection::Bidirectional, true).ok()?;
+        Some(Self {
+            stream,
+            allocator: SpinLock::new(IdAlloc::with_capacity(Self::NR_PAGES)),
+        })
+    }
+
+    fn paddr_range(&self) -> Range<usize> {
+        let start_paddr = self.stream.vm_segment().start_paddr();
+        start_paddr..start_paddr + self.stream.nbytes()
+    }
+
+    fn alloc(&self, nr_pages: usize) -> Option<Range<usize>> {
+        self.allocator
+            .lock_irq_disabled()
+            .alloc_consecutive(nr_pages)
+    }
+
+    fn free(&self, pages: Range<usize>) {
+        self.allocator.lock_irq_disabled().free_consecutive(pages);
+    }
+}
+
+#[cfg(ktest)]
+mod test {
+    use alloc::vec::Vec;
+
+    use super::*;
+    use crate::mm::{HasPaddr, Paddr, VmIo};
+
+    /// Allocates a frame beyond `paddr`.
+    fn alloc_frame_beyond(paddr: Paddr) -> Segment {
+        // The chunks below `paddr` are kept allocated, so that the allocator moves on to the
+        // higher memory.
+        let mut chunks = Vec::new();
+        loop {
+            let chunk = FrameAllocOptions::new(BouncePool::NR_PAGES)
+                .is_contiguous(true)
+                .alloc_contiguous()
+                .unwrap();
+            if chunk.start_paddr() >= paddr {
+                return chunk.range(0..1);
+            }
+            chunks.push(chunk);
+        }
+    }
+
+    #[ktest]
+    fn map_twice() {
+        let segment = FrameAllocOptions::new(1)
+            .is_contiguous(true)
+            .alloc_contiguous()
+            .unwrap();
+        let device = DmaDevice::new(PciDeviceLocation::zero(), 64);
+        let mapping1 = dma_map(&device, segment.clone(), DmaDirection::ToDevice).unwrap();
+        let mapping2 = dma_map(&device, segment.clone(), DmaDirection::FromDevice).unwrap();
+        if !mapping1.is_bounced() {
+            assert_eq!(mapping1.daddr(), segment.paddr());
+        }
+        dma_unmap(mapping1);
+        dma_unmap(mapping2);
+    }
+
+    #[ktest]
+    fn inaccessible_device() {
+        let segment = FrameAllocOptions::new(1)
+            .is_contiguous(true)
+            .alloc_contiguous()
+            .unwrap();
+        // No memory is below the first page, including the bounce buffers.
+        let device = DmaDevice::new(PciDeviceLocation::zero(), 12);
+        let result = dma_map(&device, segment, DmaDirection::Bidirectional);
+        assert!(matches!(result, Err(DmaError::NoBounceBuffer)));
+    }
+
+    #[ktest]
+    fn bounce_and_copy_back() {
+        let pool = BouncePool::get().unwrap();
+        let pool_end = pool.paddr_range().end;
+        let segment = alloc_frame_beyond(pool_end);
+        segment.write_val(0, &0x1234_5678u32).unwrap();
+        // The device can access the bounce pool, but not the segment.
+        let device = DmaDevice {
+            location: PciDeviceLocation::zero(),
+            daddr_limit: pool_end,
+        };
+
+        let mapping = dma_map(&device, segment.clone(), DmaDirection::Bidirectional).unwrap();
+        assert!(mapping.is_bounced());
+        let offset = mapping.daddr() - pool.stream.daddr();
+        assert!(offset + PAGE_SIZE <= pool.stream.nbytes());
+
+        mapping.sync_for_device(0..PAGE_SIZE).unwrap();
+        assert_eq!(pool.stream.read_val::<u32>(offset).unwrap(), 0x1234_5678);
+
+        // What the device writes to the bounce buffer is copied back on unmapping.
+        pool.stream.write_val(offset, &0x9abc_def0u32).unwrap();
+        dma_unmap(mapping);
+        assert_eq!(segment.read_val::<u32>(0).unwrap(), 0x9abc_def0);
+    }
+}
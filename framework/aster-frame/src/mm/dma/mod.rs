@@ -1,11 +1,13 @@
 // SPDX-License-Identifier: MPL-2.0
 
 mod dma_coherent;
+mod dma_map;
 mod dma_stream;
 
 use alloc::collections::BTreeSet;
 
 pub use dma_coherent::DmaCoherent;
+pub use dma_map::{dma_map, dma_unmap, DmaDevice, DmaMapping};
 pub use dma_stream::{DmaDirection, DmaStream, DmaStreamSlice};
 use inherit_methods_macro::inherit_methods;
 use spin::Once;
@@ -31,6 +33,8 @@ pub enum DmaType {
 pub enum DmaError {
     InvalidArgs,
     AlreadyMapped,
+    /// No bounce buffer is available for the frames that the device cannot access.
+    NoBounceBuffer,
 }
 
 /// A trait for types that have mapped address in the device address space.
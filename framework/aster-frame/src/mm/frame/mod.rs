@@ -12,7 +12,10 @@ pub mod frame_vec;
 pub mod options;
 pub mod segment;
 
-use core::mem::ManuallyDrop;
+use core::{
+    mem::ManuallyDrop,
+    sync::atomic::{AtomicU32, Ordering},
+};
 
 pub use frame_vec::{FrameVec, FrameVecIter};
 pub use segment::Segment;
@@ -77,6 +80,14 @@ impl Frame {
         PAGE_SIZE
     }
 
+    /// Returns the number of handles to the page frame, including the ones
+    /// held by page tables.
+    ///
+    /// Note that the count can be changed by other threads at any time.
+    pub fn reference_count(&self) -> u32 {
+        self.page.count()
+    }
+
     /// Returns a raw pointer to the starting virtual address of the frame.
     pub fn as_ptr(&self) -> *const u8 {
         paddr_to_vaddr(self.start_paddr()) as *const u8
@@ -97,6 +108,30 @@ impl Frame {
             core::ptr::copy_nonoverlapping(src.as_ptr(), self.as_mut_ptr(), self.size());
         }
     }
+
+    /// Atomically replaces the `u32` at `offset` with `new` if it equals `current`.
+    ///
+    /// It returns the previous value, which is `Ok` if the value is replaced. Since the
+    /// frame may be mapped to the user space as well, it is atomic with the accesses of
+    /// the users, like the `lock cmpxchg` of a futex implementation in the user space.
+    pub fn compare_exchange_u32(
+        &self,
+        offset: usize,
+        current: u32,
+        new: u32,
+    ) -> Result<core::result::Result<u32, u32>> {
+        if offset % core::mem::size_of::<u32>() != 0 {
+            return Err(Error::InvalidArgs);
+        }
+        if offset >= self.size() {
+            return Err(Error::InvalidArgs);
+        }
+        // SAFETY: the address is aligned and within the frame, which is valid while `self`
+        // is alive. Frames are untyped memory that is only accessed through raw pointers
+        // or atomically, so it never aliases a Rust reference.
+        let atomic = unsafe { &*(self.as_ptr().add(offset) as *const AtomicU32) };
+        Ok(atomic.compare_exchange(current, new, Ordering::SeqCst, Ordering::SeqCst))
+    }
 }
 
 impl<'a> Frame {
@@ -3,7 +3,16 @@
 //! Options for allocating frames
 
 use super::{Frame, FrameVec, Segment};
-use crate::{mm::page::allocator, prelude::*, Error};
+use crate::{
+    mm::{
+        compaction,
+        page::allocator,
+        reclaim::{self, OomVerdict},
+        zeroed,
+    },
+    prelude::*,
+    Error,
+};
 
 /// Options for allocating physical memory pages (or frames).
 ///
@@ -49,17 +58,15 @@ impl FrameAllocOptions {
 
     /// Allocates a collection of page frames according to the given options.
     pub fn alloc(&self) -> Result<FrameVec> {
-        let frames = if self.is_contiguous {
-            allocator::alloc(self.nframes).ok_or(Error::NoMemory)?
-        } else {
+        if !self.is_contiguous {
             let mut frame_list = Vec::new();
             for _ in 0..self.nframes {
-                let page = allocator::alloc_single().ok_or(Error::NoMemory)?;
-                let frame = Frame { page };
-                frame_list.push(frame);
+                frame_list.push(self.alloc_frame()?);
             }
-            FrameVec(frame_list)
-        };
+            return Ok(FrameVec(frame_list));
+        }
+
+        let frames = alloc_contiguous_with_compaction(self.nframes, allocator::alloc)?;
         if !self.uninit {
             for frame in frames.iter() {
                 frame.writer().fill(0);
@@ -75,13 +82,27 @@ impl FrameAllocOptions {
             return Err(Error::InvalidArgs);
         }
 
-        let page = allocator::alloc_single().ok_or(Error::NoMemory)?;
-        let frame = Frame { page };
-        if !self.uninit {
-            frame.writer().fill(0);
-        }
+        self.alloc_frame()
+    }
 
-        Ok(frame)
+    /// Allocates a collection of page frames according to the given options,
+    /// reclaiming memory or waiting for frames to be freed if there is not
+    /// enough free memory.
+    ///
+    /// Unlike [`Self::alloc`], this method fails due to a lack of memory only if
+    /// the OOM handler cannot free any memory (see [`reclaim::register_oom_handler`]),
+    /// but it may sleep. So it must not be called in the atomic context.
+    pub fn alloc_wait(&self) -> Result<FrameVec> {
+        alloc_with_reclaim(self.nframes, || self.alloc())
+    }
+
+    /// Allocates a single page frame according to the given options,
+    /// reclaiming memory or waiting for frames to be freed if there is not
+    /// enough free memory.
+    ///
+    /// See [`Self::alloc_wait`] for more details.
+    pub fn alloc_single_wait(&self) -> Result<Frame> {
+        alloc_with_reclaim(self.nframes, || self.alloc_single())
     }
 
     /// Allocates a contiguous range of page frames according to the given options.
@@ -93,13 +114,76 @@ impl FrameAllocOptions {
             return Err(Error::InvalidArgs);
         }
 
-        let segment = allocator::alloc_contiguous(self.nframes).ok_or(Error::NoMemory)?;
+        let segment = alloc_contiguous_with_compaction(self.nframes, allocator::alloc_contiguous)?;
         if !self.uninit {
             segment.writer().fill(0);
         }
 
         Ok(segment)
     }
+
+    /// Allocates a page frame, which is taken from the pool of pre-zeroed frames
+    /// if it should be zeroed.
+    fn alloc_frame(&self) -> Result<Frame> {
+        if !self.uninit
+            && let Some(frame) = zeroed::take()
+        {
+            return Ok(frame);
+        }
+
+        let page = allocator::alloc_single().ok_or(Error::NoMemory)?;
+        let frame = Frame { page };
+        if !self.uninit {
+            frame.writer().fill(0);
+        }
+
+        Ok(frame)
+    }
+}
+
+/// Allocates `nframes` contiguous frames with `alloc`, and retries once after compacting
+/// the memory if there is no free block large enough while there may be enough free frames.
+fn alloc_contiguous_with_compaction<T>(
+    nframes: usize,
+    alloc: impl Fn(usize) -> Option<T>,
+) -> Result<T> {
+    if let Some(frames) = alloc(nframes) {
+        return Ok(frames);
+    }
+    if nframes > 1 && nframes <= allocator::nr_free_frames() && compaction::compact(nframes) {
+        return alloc(nframes).ok_or(Error::NoMemory);
+    }
+    Err(Error::NoMemory)
+}
+
+/// Retries `alloc` until it succeeds or fails with errors other than [`Error::NoMemory`].
+///
+/// Memory is reclaimed with the registered shrinkers before each retry. If that is not
+/// enough, the OOM handler is invoked, which may sleep until some memory is freed, e.g.,
+/// by a killed process. The sleep is bounded, so the handler can select another victim if
+/// the killed process does not exit, e.g., because it is waiting for memory as well.
+fn alloc_with_reclaim<T>(nframes: usize, alloc: impl Fn() -> Result<T>) -> Result<T> {
+    if nframes > allocator::nr_total_frames() {
+        return Err(Error::NoMemory);
+    }
+
+    loop {
+        match alloc() {
+            Err(Error::NoMemory) => (),
+            result => return result,
+        }
+
+        // The pre-zeroed frames are the cheapest to give back.
+        let nr_drained = zeroed::drain();
+        if nr_drained >= nframes || nr_drained + reclaim::reclaim(nframes - nr_drained) >= nframes {
+            continue;
+        }
+
+        match reclaim::out_of_memory(nframes) {
+            OomVerdict::Retry => (),
+            OomVerdict::CurrentKilled | OomVerdict::NoVictim => return Err(Error::NoMemory),
+        }
+    }
 }
 
 #[cfg(ktest)]
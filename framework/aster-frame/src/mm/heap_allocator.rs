@@ -3,6 +3,7 @@
 use core::{
     alloc::{GlobalAlloc, Layout},
     ptr::NonNull,
+    sync::atomic::{AtomicUsize, Ordering},
 };
 
 use align_ext::AlignExt;
@@ -18,8 +19,9 @@ use crate::{
     Error,
 };
 
-#[global_allocator]
-static HEAP_ALLOCATOR: LockedHeapWithRescue<32> = LockedHeapWithRescue::new(rescue);
+// With the `mm_poison` feature, the global allocator wraps the heap in `super::poison`.
+#[cfg_attr(not(feature = "mm_poison"), global_allocator)]
+pub(super) static HEAP_ALLOCATOR: LockedHeapWithRescue<32> = LockedHeapWithRescue::new(rescue);
 
 #[alloc_error_handler]
 pub fn handle_alloc_error(layout: core::alloc::Layout) -> ! {
@@ -30,6 +32,14 @@ const INIT_KERNEL_HEAP_SIZE: usize = PAGE_SIZE * 256;
 
 static mut HEAP_SPACE: [u8; INIT_KERNEL_HEAP_SIZE] = [0; INIT_KERNEL_HEAP_SIZE];
 
+/// The number of frames that are taken from the frame allocator to enlarge the heap.
+static NR_HEAP_FRAMES: AtomicUsize = AtomicUsize::new(0);
+
+/// Returns the number of frames used by the kernel heap, excluding the initial heap space.
+pub fn nr_heap_frames() -> usize {
+    NR_HEAP_FRAMES.load(Ordering::Relaxed)
+}
+
 pub fn init() {
     // SAFETY: The HEAP_SPACE is a static memory range, so it's always valid.
     unsafe {
@@ -37,7 +47,7 @@ pub fn init() {
     }
 }
 
-struct LockedHeapWithRescue<const ORDER: usize> {
+pub(super) struct LockedHeapWithRescue<const ORDER: usize> {
     heap: SpinLock<Heap<ORDER>>,
     rescue: fn(&Self, &Layout) -> Result<()>,
 }
@@ -132,6 +142,7 @@ fn rescue<const ORDER: usize>(heap: &LockedHeapWithRescue<ORDER>, layout: &Layou
         );
         heap.add_to_heap(vaddr, PAGE_SIZE * num_frames);
     }
+    NR_HEAP_FRAMES.fetch_add(num_frames, Ordering::Relaxed);
 
     Ok(())
 }
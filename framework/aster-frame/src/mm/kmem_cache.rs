@@ -0,0 +1,453 @@
+// SPDX-License-Identifier: MPL-2.0
+
+//! Object caches for fixed-size kernel objects.
+//!
+//! A [`KmemCache`] serves objects of the same layout, e.g., inodes or sockets, from
+//! slabs, each of which is a contiguous range of frames divided into object slots.
+//! Compared with the global heap, objects of the same size are packed together, so
+//! the memory is less fragmented.
+//!
+//! Each CPU caches a small number of free objects, so that most allocations and
+//! deallocations only touch the cache of the current CPU, without contending for the
+//! slabs with other CPUs. The objects are moved between the CPU caches and the slabs
+//! in batches.
+//!
+//! An optional constructor initializes the objects when a slab is created, rather than
+//! every time an object is allocated. So the users are expected to return an object in
+//! its constructed state when freeing it.
+//!
+//! Each cache is registered as a [`Shrinker`], which returns the empty slabs to the
+//! frame allocator under memory pressure.
+
+use alloc::{
+    collections::{BTreeMap, BTreeSet},
+    sync::Arc,
+    vec::Vec,
+};
+use core::{
+    alloc::Layout,
+    fmt,
+    marker::PhantomData,
+    ops::{Deref, DerefMut},
+    ptr::NonNull,
+};
+
+use align_ext::AlignExt;
+
+use super::{
+    reclaim::{register_shrinker, Shrinker},
+    FrameAllocOptions, Segment, Vaddr, PAGE_SIZE,
+};
+use crate::{
+    cpu::{num_cpus, this_cpu},
+    sync::SpinLock,
+    Error, Result,
+};
+
+/// The minimum number of objects in a slab.
+const MIN_OBJECTS_PER_SLAB: usize = 8;
+/// The number of objects moved between a CPU cache and the slabs at a time.
+const CPU_CACHE_BATCH: usize = 16;
+/// The maximum number of objects in a CPU cache.
+const CPU_CACHE_LIMIT: usize = CPU_CACHE_BATCH * 2;
+/// The maximum number of empty slabs that are kept for later allocations.
+const MAX_EMPTY_SLABS: usize = 1;
+
+/// The constructor of the objects, which is given the address of an object slot.
+pub type KmemCtor = fn(NonNull<u8>);
+
+/// A cache of kernel objects with the same layout.
+pub struct KmemCache {
+    name: &'static str,
+    layout: Layout,
+    ctor: Option<KmemCtor>,
+    /// The size of each object slot, which is the object size rounded up to the alignment.
+    slot_size: usize,
+    nframes_per_slab: usize,
+    nr_objects_per_slab: usize,
+    /// The free objects cached by each CPU, indexed by the CPU IDs.
+    cpu_caches: Vec<SpinLock<Vec<Vaddr>>>,
+    slabs: SpinLock<SlabLists>,
+}
+
+struct SlabLists {
+    /// All the slabs, indexed by their start addresses.
+    slabs: BTreeMap<Vaddr, Slab>,
+    /// The start addresses of the slabs that have free objects, including the empty slabs.
+    partial: BTreeSet<Vaddr>,
+    nr_empty: usize,
+}
+
+struct Slab {
+    segment: Segment,
+    /// The indexes of the free object slots.
+    free_slots: Vec<u16>,
+}
+
+impl KmemCache {
+    /// Creates a cache for the objects of `layout`.
+    ///
+    /// If `ctor` is given, it is invoked on each object slot when the slab is created.
+    /// The cache is registered as a shrinker, which is unregistered once it is dropped.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`Error::InvalidArgs`] if the alignment is larger than the page size.
+    pub fn new(name: &'static str, layout: Layout, ctor: Option<KmemCtor>) -> Result<Arc<Self>> {
+        if layout.align() > PAGE_SIZE {
+            return Err(Error::InvalidArgs);
+        }
+        let slot_size = layout.size().max(1).align_up(layout.align());
+        let nframes_per_slab = (slot_size * MIN_OBJECTS_PER_SLAB).div_ceil(PAGE_SIZE);
+        let nr_objects_per_slab = nframes_per_slab * PAGE_SIZE / slot_size;
+        debug_assert!(nr_objects_per_slab <= u16::MAX as usize);
+
+        let cache = Arc::new(Self {
+            name,
+            layout,
+            ctor,
+            slot_size,
+            nframes_per_slab,
+            nr_objects_per_slab,
+            cpu_caches: (0..num_cpus())
+                .map(|_| SpinLock::new(Vec::with_capacity(CPU_CACHE_LIMIT + 1)))
+                .collect(),
+            slabs: SpinLock::new(SlabLists {
+                slabs: BTreeMap::new(),
+                partial: BTreeSet::new(),
+                nr_empty: 0,
+            }),
+        });
+        register_shrinker(Arc::downgrade(&cache) as _);
+        Ok(cache)
+    }
+
+    /// Creates a cache for the objects of type `T`, without a constructor.
+    pub fn new_for<T>(name: &'static str) -> Result<Arc<Self>> {
+        Self::new(name, Layout::new::<T>(), None)
+    }
+
+    /// Allocates an object.
+    ///
+    /// The object is in its constructed state if there is a constructor, or is
+    /// uninitialized otherwise.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`Error::NoMemory`] if there are no free objects and no frames for a new slab.
+    pub fn alloc(&self) -> Result<NonNull<u8>> {
+        let mut cpu_cache = self.cpu_caches[this_cpu() as usize].lock_irq_disabled();
+        if cpu_cache.is_empty() {
+            self.refill(&mut cpu_cache)?;
+        }
+        let addr = cpu_cache.pop().unwrap();
+        Ok(NonNull::new(addr as *mut u8).unwrap())
+    }
+
+    /// Frees an object.
+    ///
+    /// # Safety
+    ///
+    /// The object must be allocated from this cache and must not be used afterwards.
+    /// If there is a constructor, the object must be in its constructed state.
+    pub unsafe fn dealloc(&self, ptr: NonNull<u8>) {
+        let mut cpu_cache = self.cpu_caches[this_cpu() as usize].lock_irq_disabled();
+        cpu_cache.push(ptr.as_ptr() as Vaddr);
+        if cpu_cache.len() > CPU_CACHE_LIMIT {
+            // Return the objects that are cached for the longest time.
+            self.flush(cpu_cache.drain(..CPU_CACHE_BATCH));
+        }
+    }
+
+    /// Returns the name of the cache.
+    pub fn name(&self) -> &'static str {
+        self.name
+    }
+
+    /// Returns the layout of the objects.
+    pub fn layout(&self) -> Layout {
+        self.layout
+    }
+
+    /// Returns the number of slabs.
+    pub fn nr_slabs(&self) -> usize {
+        self.slabs.lock_irq_disabled().slabs.len()
+    }
+
+    /// Returns the number of objects that are allocated from the slabs, including the
+    /// objects cached by the CPUs.
+    pub fn nr_active_objects(&self) -> usize {
+        let slabs = self.slabs.lock_irq_disabled();
+        slabs
+            .slabs
+            .values()
+            .map(|slab| self.nr_objects_per_slab - slab.free_slots.len())
+            .sum()
+    }
+
+    /// Moves a batch of free objects from the slabs to the CPU cache, creating a new
+    /// slab if there are not enough free objects.
+    fn refill(&self, cpu_cache: &mut Vec<Vaddr>) -> Result<()> {
+        let mut slabs = self.slabs.lock_irq_disabled();
+        while cpu_cache.len() < CPU_CACHE_BATCH {
+            let first_partial = slabs.partial.first().copied();
+            let start = match first_partial {
+                Some(start) => start,
+                None => match self.new_slab() {
+                    Ok(slab) => slabs.insert(slab, self.nr_objects_per_slab),
+                    Err(err) if cpu_cache.is_empty() => return Err(err),
+                    Err(_) => break,
+                },
+            };
+
+            let SlabLists {
+                slabs: all_slabs,
+                partial,
+                nr_empty,
+            } = &mut *slabs;
+            let slab = all_slabs.get_mut(&start).unwrap();
+            if slab.free_slots.len() == self.nr_objects_per_slab {
+                *nr_empty -= 1;
+            }
+            let nr_taken = slab.free_slots.len().min(CPU_CACHE_BATCH - cpu_cache.len());
+            let remaining = slab.free_slots.len() - nr_taken;
+            for slot in slab.free_slots.drain(remaining..) {
+                cpu_cache.push(start + slot as usize * self.slot_size);
+            }
+            if slab.free_slots.is_empty() {
+                partial.remove(&start);
+            }
+        }
+        Ok(())
+    }
+
+    /// Returns the objects to their slabs, and frees the empty slabs beyond
+    /// [`MAX_EMPTY_SLABS`].
+    fn flush(&self, objects: impl Iterator<Item = Vaddr>) {
+        let mut slabs = self.slabs.lock_irq_disabled();
+        for addr in objects {
+            let SlabLists {
+                slabs: all_slabs,
+                partial,
+                nr_empty,
+            } = &mut *slabs;
+            let (start, slab) = all_slabs.range_mut(..=addr).next_back().unwrap();
+            debug_assert!(addr < start + slab.segment.nbytes());
+            slab.free_slots
+                .push(((addr - start) / self.slot_size) as u16);
+            partial.insert(*start);
+            if slab.free_slots.len() == self.nr_objects_per_slab {
+                *nr_empty += 1;
+            }
+        }
+        self.free_empty_slabs(&mut slabs, MAX_EMPTY_SLABS, usize::MAX);
+    }
+
+    /// Frees the empty slabs until `nr_kept` empty slabs are left or `max_nframes`
+    /// frames are freed.
+    ///
+    /// Returns the number of frames that are freed.
+    fn free_empty_slabs(&self, slabs: &mut SlabLists, nr_kept: usize, max_nframes: usize) -> usize {
+        let mut nr_freed = 0;
+        while slabs.nr_empty > nr_kept && nr_freed < max_nframes {
+            let start = *slabs
+                .partial
+                .iter()
+                .find(|start| slabs.slabs[*start].free_slots.len() == self.nr_objects_per_slab)
+                .unwrap();
+            slabs.partial.remove(&start);
+            slabs.slabs.remove(&start);
+            slabs.nr_empty -= 1;
+            nr_freed += self.nframes_per_slab;
+        }
+        nr_freed
+    }
+
+    fn new_slab(&self) -> Result<Slab> {
+        let segment = FrameAllocOptions::new(self.nframes_per_slab)
+            .uninit(true)
+            .alloc_contiguous()?;
+        if let Some(ctor) = self.ctor {
+            for slot in 0..self.nr_objects_per_slab {
+                // SAFETY: The slot is within the segment, which is mapped in the kernel space.
+                let ptr = unsafe { segment.as_mut_ptr().add(slot * self.slot_size) };
+                ctor(NonNull::new(ptr).unwrap());
+            }
+        }
+        Ok(Slab {
+            segment,
+            free_slots: (0..self.nr_objects_per_slab as u16).rev().collect(),
+        })
+    }
+}
+
+impl SlabLists {
+    /// Inserts an empty slab and returns its start address.
+    fn insert(&mut self, slab: Slab, nr_objects_per_slab: usize) -> Vaddr {
+        debug_assert_eq!(slab.free_slots.len(), nr_objects_per_slab);
+        let start = slab.segment.as_ptr() as Vaddr;
+        self.slabs.insert(start, slab);
+        self.partial.insert(start);
+        self.nr_empty += 1;
+        start
+    }
+}
+
+impl Shrinker for KmemCache {
+    fn nr_reclaimable(&self) -> usize {
+        let nr_cached: usize = self
+            .cpu_caches
+            .iter()
+            .map(|cpu_cache| cpu_cache.lock_irq_disabled().len())
+            .sum();
+        let nr_empty = self.slabs.lock_irq_disabled().nr_empty;
+        (nr_empty + nr_cached / self.nr_objects_per_slab) * self.nframes_per_slab
+    }
+
+    fn shrink(&self, nr_to_reclaim: usize) -> usize {
+        for cpu_cache in self.cpu_caches.iter() {
+            let mut cpu_cache = cpu_cache.lock_irq_disabled();
+            self.flush(cpu_cache.drain(..));
+        }
+        let mut slabs = self.slabs.lock_irq_disabled();
+        self.free_empty_slabs(&mut slabs, 0, nr_to_reclaim)
+    }
+}
+
+impl fmt::Debug for KmemCache {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("KmemCache")
+            .field("name", &self.name)
+            .field("layout", &self.layout)
+            .field("nr_objects_per_slab", &self.nr_objects_per_slab)
+            .finish_non_exhaustive()
+    }
+}
+
+/// A pointer type that owns an object allocated from a [`KmemCache`].
+pub struct KmemBox<T> {
+    ptr: NonNull<T>,
+    cache: Arc<KmemCache>,
+    _marker: PhantomData<T>,
+}
+
+impl<T> KmemBox<T> {
+    /// Allocates an object from the cache and moves `val` into it.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the layout of `T` does not fit the objects of the cache.
+    pub fn new(cache: &Arc<KmemCache>, val: T) -> Result<Self> {
+        let layout = Layout::new::<T>();
+        assert!(
+            layout.size() <= cache.slot_size && layout.align() <= cache.layout.align(),
+            "the layout does not fit the objects of the cache"
+        );
+        let ptr = cache.alloc()?.cast::<T>();
+        // SAFETY: The object is newly allocated and fits `T`.
+        unsafe { ptr.as_ptr().write(val) };
+        Ok(Self {
+            ptr,
+            cache: cache.clone(),
+            _marker: PhantomData,
+        })
+    }
+}
+
+impl<T> Deref for KmemBox<T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        // SAFETY: The object is initialized and owned by the box.
+        unsafe { self.ptr.as_ref() }
+    }
+}
+
+impl<T> DerefMut for KmemBox<T> {
+    fn deref_mut(&mut self) -> &mut T {
+        // SAFETY: The object is initialized and owned by the box.
+        unsafe { self.ptr.as_mut() }
+    }
+}
+
+impl<T> Drop for KmemBox<T> {
+    fn drop(&mut self) {
+        // SAFETY: The object is initialized and owned by the box, and is allocated
+        // from the cache.
+        unsafe {
+            self.ptr.as_ptr().drop_in_place();
+            self.cache.dealloc(self.ptr.cast());
+        }
+    }
+}
+
+impl<T: fmt::Debug> fmt::Debug for KmemBox<T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        fmt::Debug::fmt(&**self, f)
+    }
+}
+
+// SAFETY: The box owns the object, like a `Box<T>`.
+unsafe impl<T: Send> Send for KmemBox<T> {}
+// SAFETY: The box owns the object, like a `Box<T>`.
+unsafe impl<T: Sync> Sync for KmemBox<T> {}
+
+#[cfg(ktest)]
+mod test {
+    use core::sync::atomic::{AtomicUsize, Ordering};
+
+    use super::*;
+
+    #[ktest]
+    fn alloc_and_reuse() {
+        let cache = KmemCache::new_for::<[u64; 4]>("test").unwrap();
+        let obj1 = KmemBox::new(&cache, [1u64; 4]).unwrap();
+        let obj2 = KmemBox::new(&cache, [2u64; 4]).unwrap();
+        assert_eq!(*obj1, [1; 4]);
+        assert_eq!(*obj2, [2; 4]);
+        assert_eq!(cache.nr_slabs(), 1);
+
+        let addr = &*obj1 as *const _ as Vaddr;
+        drop(obj1);
+        // The object is reused from the CPU cache.
+        let obj3 = KmemBox::new(&cache, [3u64; 4]).unwrap();
+        assert_eq!(&*obj3 as *const _ as Vaddr, addr);
+    }
+
+    #[ktest]
+    fn ctor_is_called_per_slot() {
+        static NR_CONSTRUCTED: AtomicUsize = AtomicUsize::new(0);
+        fn ctor(ptr: NonNull<u8>) {
+            // SAFETY: The slot is large enough for a `u32`.
+            unsafe { ptr.cast::<u32>().as_ptr().write(0xdead) };
+            NR_CONSTRUCTED.fetch_add(1, Ordering::Relaxed);
+        }
+
+        let cache = KmemCache::new("test_ctor", Layout::new::<u32>(), Some(ctor)).unwrap();
+        let ptr = cache.alloc().unwrap();
+        // SAFETY: The object is constructed by `ctor`.
+        assert_eq!(unsafe { ptr.cast::<u32>().as_ptr().read() }, 0xdead);
+        assert_eq!(
+            NR_CONSTRUCTED.load(Ordering::Relaxed),
+            cache.nr_objects_per_slab
+        );
+        // SAFETY: The object is allocated from the cache and is in its constructed state.
+        unsafe { cache.dealloc(ptr) };
+    }
+
+    #[ktest]
+    fn shrink_frees_empty_slabs() {
+        let cache = KmemCache::new_for::<[u8; 512]>("test_shrink").unwrap();
+        let objs: Vec<_> = (0..cache.nr_objects_per_slab * 3)
+            .map(|_| KmemBox::new(&cache, [0u8; 512]).unwrap())
+            .collect();
+        assert!(cache.nr_slabs() >= 3);
+        assert!(cache.nr_active_objects() >= objs.len());
+
+        drop(objs);
+        let nr_freed = cache.shrink(usize::MAX);
+        assert!(nr_freed > 0);
+        assert_eq!(cache.nr_slabs(), 0);
+        assert_eq!(cache.nr_active_objects(), 0);
+    }
+}
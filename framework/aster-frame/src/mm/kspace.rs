@@ -8,8 +8,8 @@
 //! address width is 48 bits (with 47 bits kernel space).
 //!
 //! TODO: the cap of linear mapping (the start of vm alloc) are raised
-//! to workaround for high IO in TDX. We need actual vm alloc API to have
-//! a proper fix.
+//! to workaround for high IO in TDX. The I/O areas should be mapped with
+//! the vm alloc API (see [`crate::mm::vmalloc`]) to have a proper fix.
 //!
 //! ```text
 //! +-+ <- the highest used address (0xffff_ffff_ffff_0000)
@@ -27,8 +27,8 @@
 //! | |
 //! | |
 //! | |
-//! | |         For linear mappings.
-//! | |         Mapped physical addresses are untracked.
+//! | |         For linear mappings, 64 TiB at a random base aligned
+//! | |         to 512 GiB. Mapped physical addresses are untracked.
 //! | |
 //! | |
 //! | |
@@ -39,10 +39,14 @@
 //! 39 bits or 57 bits, the memory space just adjust porportionally.
 
 use alloc::vec::Vec;
-use core::{mem::ManuallyDrop, ops::Range};
+use core::{
+    mem::ManuallyDrop,
+    ops::Range,
+    sync::atomic::{AtomicUsize, Ordering},
+};
 
 use align_ext::AlignExt;
-use log::info;
+use log::{info, warn};
 use spin::Once;
 
 use super::{
@@ -52,11 +56,16 @@ use super::{
         Page,
     },
     page_prop::{CachePolicy, PageFlags, PageProperty, PrivilegedPageFlags},
-    page_table::{boot_pt::BootPageTable, KernelMode, PageTable},
+    page_size,
+    page_table::{boot_pt::BootPageTable, KernelMode, PageTable, PageTableEntryTrait},
     MemoryRegionType, Paddr, PagingConstsTrait, Vaddr, PAGE_SIZE,
 };
 use crate::{
-    arch::mm::{PageTableEntry, PagingConsts},
+    arch::{
+        mm::{current_page_table_paddr, tlb_flush_addr_range, PageTableEntry, PagingConsts},
+        read_random, read_random_seed, read_tsc,
+    },
+    boot::kcmdline::ModuleArg,
     sync::SpinLock,
 };
 
@@ -90,15 +99,89 @@ pub(in crate::mm) const FRAME_METADATA_RANGE: Range<Vaddr> =
 const VMALLOC_BASE_VADDR: Vaddr = 0xffff_fd00_0000_0000 << ADDR_WIDTH_SHIFT;
 pub const VMALLOC_VADDR_RANGE: Range<Vaddr> = VMALLOC_BASE_VADDR..FRAME_METADATA_BASE_VADDR;
 
-/// The base address of the linear mapping of all physical
+/// The base address of the linear mapping set up by the boot page tables.
+///
+/// All the boot protocols, including the EFI stub, linearly map the low physical
+/// memory here. The linear mapping is moved to a random base at the entry of the
+/// Rust code (see [`randomize_linear_mapping`]), but the pointers handed over by
+/// the loader may still point into this mapping.
+pub(crate) const BOOT_LINEAR_MAPPING_BASE_VADDR: Vaddr = 0xffff_8000_0000_0000 << ADDR_WIDTH_SHIFT;
+
+/// The maximum size of the physical address space that can be linearly mapped.
+const LINEAR_MAPPING_MAX_SIZE: usize = 0x4000_0000_0000 << ADDR_WIDTH_SHIFT;
+
+static LINEAR_MAPPING_BASE_VADDR: AtomicUsize = AtomicUsize::new(BOOT_LINEAR_MAPPING_BASE_VADDR);
+
+/// Returns the base address of the linear mapping of all physical
 /// memory in the kernel address space.
-pub const LINEAR_MAPPING_BASE_VADDR: Vaddr = 0xffff_8000_0000_0000 << ADDR_WIDTH_SHIFT;
-pub const LINEAR_MAPPING_VADDR_RANGE: Range<Vaddr> = LINEAR_MAPPING_BASE_VADDR..VMALLOC_BASE_VADDR;
+pub fn linear_mapping_base_vaddr() -> Vaddr {
+    LINEAR_MAPPING_BASE_VADDR.load(Ordering::Relaxed)
+}
+
+/// Returns the virtual address range of the linear mapping of all physical
+/// memory in the kernel address space.
+pub fn linear_mapping_vaddr_range() -> Range<Vaddr> {
+    let base = linear_mapping_base_vaddr();
+    base..base + LINEAR_MAPPING_MAX_SIZE
+}
 
 /// Convert physical address to virtual address using offset, only available inside aster-frame
 pub fn paddr_to_vaddr(pa: Paddr) -> usize {
-    debug_assert!(pa < VMALLOC_BASE_VADDR - LINEAR_MAPPING_BASE_VADDR);
-    pa + LINEAR_MAPPING_BASE_VADDR
+    debug_assert!(pa < LINEAR_MAPPING_MAX_SIZE);
+    pa + linear_mapping_base_vaddr()
+}
+
+/// Moves the linear mapping to a random base address.
+///
+/// The base is chosen at the granularity of the entries of the root page table, with
+/// the randomness from `RDSEED`, `RDRAND` or, if neither is available, the TSC. The
+/// root entries of the boot linear mapping are copied to the chosen place in the boot
+/// page table, so both the boot and the random linear mappings work until the kernel
+/// page table, which only contains the random one, is activated.
+///
+/// # Safety
+///
+/// This function must be called only once on the bootstrap processor, before any
+/// address in the linear mapping is taken with [`paddr_to_vaddr`].
+pub(crate) unsafe fn randomize_linear_mapping() {
+    let root_entry_size = page_size::<PagingConsts>(PagingConsts::NR_LEVELS);
+    let root_pt =
+        (BOOT_LINEAR_MAPPING_BASE_VADDR + current_page_table_paddr()) as *mut PageTableEntry;
+    let root_pte_ptr = |va: Vaddr| {
+        let index = va / root_entry_size % nr_subpage_per_huge::<PagingConsts>();
+        // SAFETY: the index is within the root page table, which is accessible
+        // in the boot linear mapping.
+        unsafe { root_pt.add(index) }
+    };
+
+    // The random linear mapping must neither overlap with the boot one, which is
+    // still in use, nor go beyond the range of the linear mappings.
+    let max_slot = (VMALLOC_BASE_VADDR - BOOT_LINEAR_MAPPING_BASE_VADDR - LINEAR_MAPPING_MAX_SIZE)
+        / root_entry_size;
+    let nr_boot_entries = (0..max_slot)
+        .take_while(|i| {
+            let pte_ptr = root_pte_ptr(BOOT_LINEAR_MAPPING_BASE_VADDR + i * root_entry_size);
+            // SAFETY: the pointer is valid as explained above.
+            unsafe { pte_ptr.read() }.is_present()
+        })
+        .count();
+    assert!(nr_boot_entries > 0 && nr_boot_entries < max_slot);
+
+    let random = read_random_seed()
+        .or_else(read_random)
+        .unwrap_or_else(read_tsc) as usize;
+    let slot = nr_boot_entries + random % (max_slot - nr_boot_entries + 1);
+    let base = BOOT_LINEAR_MAPPING_BASE_VADDR + slot * root_entry_size;
+
+    for i in 0..nr_boot_entries {
+        let from = root_pte_ptr(BOOT_LINEAR_MAPPING_BASE_VADDR + i * root_entry_size);
+        let to = root_pte_ptr(base + i * root_entry_size);
+        // SAFETY: the target entries are not present and not used. Sharing the lower
+        // level page tables of the boot linear mapping maps the same physical memory.
+        unsafe { to.write(from.read()) };
+    }
+
+    LINEAR_MAPPING_BASE_VADDR.store(base, Ordering::Relaxed);
 }
 
 /// The boot page table instance.
@@ -128,11 +211,12 @@ pub(crate) fn init_boot_page_table() {
 ///
 /// This function should be called before:
 ///  - any initializer that modifies the kernel page table.
-pub fn init_kernel_page_table(meta_pages: Vec<Range<Paddr>>) {
+pub fn init_kernel_page_table(meta_sections: Vec<(Vaddr, Range<Paddr>)>) {
     info!("Initializing the kernel page table");
 
     let regions = crate::boot::memory_regions();
     let phys_mem_cap = regions.iter().map(|r| r.base() + r.len()).max().unwrap();
+    assert!(phys_mem_cap <= LINEAR_MAPPING_MAX_SIZE);
 
     // Start to initialize the kernel page table.
     let kpt = PageTable::<KernelMode>::empty();
@@ -145,12 +229,14 @@ pub fn init_kernel_page_table(meta_pages: Vec<Range<Paddr>>) {
 
     // Do linear mappings for the kernel.
     {
-        let from = LINEAR_MAPPING_BASE_VADDR..LINEAR_MAPPING_BASE_VADDR + phys_mem_cap;
+        let base = linear_mapping_base_vaddr();
+        let from = base..base + phys_mem_cap;
         let to = 0..phys_mem_cap;
         let prop = PageProperty {
             flags: PageFlags::RW,
             cache: CachePolicy::Writeback,
             priv_flags: PrivilegedPageFlags::GLOBAL,
+            pkey: 0,
         };
         // SAFETY: we are doing the linear mapping for the kernel.
         unsafe {
@@ -158,21 +244,19 @@ pub fn init_kernel_page_table(meta_pages: Vec<Range<Paddr>>) {
         }
     }
 
-    // Map the metadata pages.
-    {
-        let start_va = mapping::page_to_meta::<PagingConsts>(0);
-        let from = start_va..start_va + meta_pages.len() * PAGE_SIZE;
+    // Map the metadata pages of the present sections.
+    for (start_va, meta_pages) in meta_sections {
+        let from = start_va..start_va + meta_pages.len();
         let prop = PageProperty {
             flags: PageFlags::RW,
             cache: CachePolicy::Writeback,
             priv_flags: PrivilegedPageFlags::GLOBAL,
+            pkey: 0,
         };
         let mut cursor = kpt.cursor_mut(&from).unwrap();
-        for frame in meta_pages {
-            // SAFETY: we are doing the metadata mappings for the kernel.
-            unsafe {
-                cursor.map_pa(&frame, prop);
-            }
+        // SAFETY: we are doing the metadata mappings for the kernel.
+        unsafe {
+            cursor.map_pa(&meta_pages, prop);
         }
     }
 
@@ -181,11 +265,13 @@ pub fn init_kernel_page_table(meta_pages: Vec<Range<Paddr>>) {
     // the I/O areas, rather than doing it using the linear mappings.
     {
         let to = 0x8_0000_0000..0x9_0000_0000;
-        let from = LINEAR_MAPPING_BASE_VADDR + to.start..LINEAR_MAPPING_BASE_VADDR + to.end;
+        let base = linear_mapping_base_vaddr();
+        let from = base + to.start..base + to.end;
         let prop = PageProperty {
             flags: PageFlags::RW,
             cache: CachePolicy::Uncacheable,
             priv_flags: PrivilegedPageFlags::GLOBAL,
+            pkey: 0,
         };
         // SAFETY: we are doing I/O mappings for the kernel.
         unsafe {
@@ -193,8 +279,7 @@ pub fn init_kernel_page_table(meta_pages: Vec<Range<Paddr>>) {
         }
     }
 
-    // Map for the kernel code itself.
-    // TODO: set separated permissions for each segments in the kernel.
+    // Map for the kernel code itself, with the permissions of the sections.
     {
         let region = regions
             .iter()
@@ -204,15 +289,16 @@ pub fn init_kernel_page_table(meta_pages: Vec<Range<Paddr>>) {
         let to =
             region.base().align_down(PAGE_SIZE)..(region.base() + region.len()).align_up(PAGE_SIZE);
         let from = to.start + offset..to.end + offset;
-        let prop = PageProperty {
-            flags: PageFlags::RWX,
-            cache: CachePolicy::Writeback,
-            priv_flags: PrivilegedPageFlags::GLOBAL,
-        };
         let mut cursor = kpt.cursor_mut(&from).unwrap();
         for frame_paddr in to.step_by(PAGE_SIZE) {
             let page = Page::<KernelMeta>::from_unused(frame_paddr);
             let paddr = page.into_raw();
+            let prop = PageProperty {
+                flags: kernel_image_flags(frame_paddr + offset),
+                cache: CachePolicy::Writeback,
+                priv_flags: PrivilegedPageFlags::GLOBAL,
+                pkey: 0,
+            };
             // SAFETY: we are doing mappings for the kernel.
             unsafe {
                 cursor.map_pa(&(paddr..paddr + PAGE_SIZE), prop);
@@ -223,6 +309,77 @@ pub fn init_kernel_page_table(meta_pages: Vec<Range<Paddr>>) {
     KERNEL_PAGE_TABLE.call_once(|| kpt);
 }
 
+/// Returns the permissions of the page of the kernel image at the virtual address.
+///
+/// The code is executable, and the read-only data is read-only. The others are
+/// writable but not executable, including the boot section, whose code is no longer
+/// executed once the kernel page table is activated.
+fn kernel_image_flags(va: Vaddr) -> PageFlags {
+    // These are virtual addresses provided by the linker script.
+    extern "C" {
+        fn __text();
+        fn __rodata();
+        fn __rodata_end();
+    }
+    if (__text as usize..__rodata as usize).contains(&va) {
+        PageFlags::RX
+    } else if (__rodata as usize..__rodata_end as usize).contains(&va) {
+        PageFlags::R
+    } else {
+        PageFlags::RW
+    }
+}
+
+/// Removes the executable permission of the kernel pages that are also writable.
+///
+/// No kernel page should be writable and executable at the same time (W^X). This
+/// pass audits the whole kernel space after the initialization, and the violations
+/// are reported and fixed. If the kernel command line option `mm.wx=panic` is given,
+/// a violation causes a panic instead, which helps to find the violating mappings.
+pub(crate) fn enforce_wx() {
+    let kpt = KERNEL_PAGE_TABLE.get().unwrap();
+    let range = KERNEL_BASE_VADDR..KERNEL_END_VADDR;
+    // SAFETY: no code is executed from the writable pages, so removing the executable
+    // permission of them does not affect the memory safety.
+    let violations = unsafe {
+        kpt.cursor_mut(&range).unwrap().protect(
+            range.len(),
+            |prop| {
+                if prop.flags.contains(PageFlags::W | PageFlags::X) {
+                    prop.flags -= PageFlags::X;
+                }
+            },
+            true,
+        )
+    }
+    .unwrap();
+    if violations.is_empty() {
+        return;
+    }
+
+    let panics = crate::boot::kernel_cmdline()
+        .get_module_args("mm")
+        .is_some_and(|args| {
+            args.iter().any(|arg| {
+                matches!(arg, ModuleArg::KeyVal(key, value)
+                    if key.as_bytes() == b"wx" && value.as_bytes() == b"panic")
+            })
+        });
+    for range in violations.iter() {
+        if panics {
+            panic!(
+                "W^X violation: the kernel pages at {:#x?} are writable and executable",
+                range
+            );
+        }
+        warn!(
+            "W^X violation: the kernel pages at {:#x?} are no longer executable",
+            range
+        );
+        tlb_flush_addr_range(range);
+    }
+}
+
 pub fn activate_kernel_page_table() {
     let kpt = KERNEL_PAGE_TABLE
         .get()
@@ -238,3 +395,42 @@ pub fn activate_kernel_page_table() {
     let mut boot_pt = BOOT_PAGE_TABLE.lock().take().unwrap();
     unsafe { ManuallyDrop::drop(&mut boot_pt) };
 }
+
+#[cfg(ktest)]
+mod test {
+    use core::sync::atomic::{AtomicUsize, Ordering};
+
+    use super::*;
+
+    static DATA: AtomicUsize = AtomicUsize::new(0);
+    static RODATA: [u8; 4] = *b"ro\0\0";
+
+    #[ktest]
+    fn linear_mapping_randomized() {
+        let base = linear_mapping_base_vaddr();
+        let root_entry_size = page_size::<PagingConsts>(PagingConsts::NR_LEVELS);
+        assert_eq!(base % root_entry_size, 0);
+        assert!(base > BOOT_LINEAR_MAPPING_BASE_VADDR);
+        assert!(base + LINEAR_MAPPING_MAX_SIZE <= VMALLOC_BASE_VADDR);
+
+        let kpt = KERNEL_PAGE_TABLE.get().unwrap();
+        let frame = crate::mm::FrameAllocOptions::new(1).alloc_single().unwrap();
+        let va = paddr_to_vaddr(frame.start_paddr());
+        assert_eq!(kpt.query(va).unwrap().0, frame.start_paddr());
+        assert!(kpt.query(BOOT_LINEAR_MAPPING_BASE_VADDR).is_none());
+    }
+
+    #[ktest]
+    fn kernel_image_wx() {
+        DATA.fetch_add(1, Ordering::Relaxed);
+        let kpt = KERNEL_PAGE_TABLE.get().unwrap();
+        let flags_of = |va: Vaddr| kpt.query(va).unwrap().1.flags & PageFlags::RWX;
+
+        assert_eq!(flags_of(kernel_image_wx as usize), PageFlags::RX);
+        assert_eq!(flags_of(RODATA.as_ptr() as usize), PageFlags::R);
+        assert_eq!(
+            flags_of(&DATA as *const AtomicUsize as usize),
+            PageFlags::RW
+        );
+    }
+}
@@ -0,0 +1,277 @@
+// SPDX-License-Identifier: MPL-2.0
+
+//! The boot memory allocator.
+//!
+//! Before the page allocator is initialized, the physical memory needed in the boot stage,
+//! e.g., the page metadata arrays and the frames of the boot page table, is allocated from
+//! the boot memory allocator. It records the usable memory regions reported by the
+//! bootloader and the regions that are reserved from them, and an allocation simply
+//! reserves a free region. The memory allocated from it cannot be deallocated.
+//!
+//! Since the memory is accessed through the linear mapping of the boot page table before
+//! the kernel page table is activated, the allocations are limited to the memory below
+//! [`BOOT_LINEAR_MAPPED_END`].
+//!
+//! Once the boot stage allocations are done, the memory that is not reserved is handed off
+//! to the page allocator by [`handoff`], after which the boot memory allocator is disabled.
+
+use alloc::vec::Vec;
+use core::ops::Range;
+
+use align_ext::AlignExt;
+use log::info;
+
+use crate::{
+    arch::mm::BOOT_LINEAR_MAPPED_END,
+    boot::memory_region::MemoryRegionType,
+    mm::{Paddr, PAGE_SIZE},
+    sync::SpinLock,
+};
+
+/// The maximum number of disjoint regions that can be recorded in a [`RegionArray`].
+const MAX_REGIONS: usize = 128;
+
+static MEMBLOCK: SpinLock<Option<MemBlock>> = SpinLock::new(None);
+
+struct MemBlock {
+    usable: RegionArray,
+    reserved: RegionArray,
+}
+
+/// A sorted array of disjoint and non-adjacent physical memory regions.
+///
+/// It does not use the heap so that it can be used as early as possible.
+struct RegionArray {
+    regions: [Range<Paddr>; MAX_REGIONS],
+    len: usize,
+}
+
+impl RegionArray {
+    fn new() -> Self {
+        const EMPTY: Range<Paddr> = 0..0;
+        Self {
+            regions: [EMPTY; MAX_REGIONS],
+            len: 0,
+        }
+    }
+
+    fn as_slice(&self) -> &[Range<Paddr>] {
+        &self.regions[..self.len]
+    }
+
+    /// Inserts a region, which is merged with the overlapping and adjacent regions.
+    ///
+    /// # Panics
+    ///
+    /// This method panics if there are too many disjoint regions.
+    fn insert(&mut self, region: Range<Paddr>) {
+        if region.is_empty() {
+            return;
+        }
+        let (mut start, mut end) = (region.start, region.end);
+        let first = self.as_slice().partition_point(|r| r.end < start);
+        let mut last = first;
+        while last < self.len && self.regions[last].start <= end {
+            start = start.min(self.regions[last].start);
+            end = end.max(self.regions[last].end);
+            last += 1;
+        }
+
+        let nr_merged = last - first;
+        if nr_merged == 0 {
+            assert!(self.len < MAX_REGIONS, "too many memory regions");
+            self.regions[first..=self.len].rotate_right(1);
+            self.len += 1;
+        } else {
+            self.regions[first + 1..self.len].rotate_left(nr_merged - 1);
+            self.len -= nr_merged - 1;
+        }
+        self.regions[first] = start..end;
+    }
+}
+
+impl MemBlock {
+    /// Returns the usable regions that are not reserved, in ascending order.
+    fn free_regions(&self) -> Vec<Range<Paddr>> {
+        let mut free = Vec::new();
+        let reserved = self.reserved.as_slice();
+        for usable in self.usable.as_slice() {
+            let mut cursor = usable.start;
+            let first = reserved.partition_point(|r| r.end <= usable.start);
+            for r in reserved[first..]
+                .iter()
+                .take_while(|r| r.start < usable.end)
+            {
+                if r.start > cursor {
+                    free.push(cursor..r.start);
+                }
+                cursor = cursor.max(r.end);
+            }
+            if cursor < usable.end {
+                free.push(cursor..usable.end);
+            }
+        }
+        free
+    }
+
+    /// Allocates from the top of the highest free region below `limit` that fits, to
+    /// leave the low memory for the devices that can only address it.
+    fn alloc(&mut self, nframes: usize, limit: Paddr) -> Option<Paddr> {
+        let size = nframes * PAGE_SIZE;
+        let end = self
+            .free_regions()
+            .into_iter()
+            .rev()
+            .map(|r| r.start..r.end.min(limit))
+            .find(|r| r.end >= r.start + size)?
+            .end;
+        let start = end - size;
+        self.reserved.insert(start..end);
+        Some(start)
+    }
+}
+
+/// Initializes the boot memory allocator with the usable memory regions.
+///
+/// This function should be called after the memory regions are initialized.
+pub(crate) fn init() {
+    let mut memblock = MemBlock {
+        usable: RegionArray::new(),
+        reserved: RegionArray::new(),
+    };
+    for region in crate::boot::memory_regions() {
+        if region.typ() != MemoryRegionType::Usable {
+            continue;
+        }
+        // Make the memory region page-aligned, and skip if it is too small.
+        let start = region.base().align_up(PAGE_SIZE);
+        let end = region
+            .base()
+            .checked_add(region.len())
+            .unwrap()
+            .align_down(PAGE_SIZE);
+        if end > start {
+            memblock.usable.insert(start..end);
+        }
+    }
+    *MEMBLOCK.lock() = Some(memblock);
+}
+
+/// Returns whether the boot memory allocator is available.
+pub(crate) fn is_active() -> bool {
+    MEMBLOCK.lock().is_some()
+}
+
+/// Allocates `nframes` contiguous frames and returns the physical address of the first one.
+///
+/// The frames are below [`BOOT_LINEAR_MAPPED_END`] and are not zeroed. It returns `None`
+/// if there is not enough memory or the boot memory allocator is no longer available.
+pub(crate) fn alloc(nframes: usize) -> Option<Paddr> {
+    MEMBLOCK
+        .lock()
+        .as_mut()?
+        .alloc(nframes, BOOT_LINEAR_MAPPED_END)
+}
+
+/// Allocates `nframes` contiguous frames below `limit` and returns the physical address of
+/// the first one, like [`alloc`].
+///
+/// This is for the memory that must be at low addresses, e.g., the boot code of the
+/// application processors, which should be allocated before any other boot memory.
+pub(crate) fn alloc_below(nframes: usize, limit: Paddr) -> Option<Paddr> {
+    MEMBLOCK
+        .lock()
+        .as_mut()?
+        .alloc(nframes, limit.min(BOOT_LINEAR_MAPPED_END))
+}
+
+/// Reserves a physical memory region so that it will not be allocated.
+///
+/// # Panics
+///
+/// This function panics if the boot memory allocator is no longer available.
+pub(crate) fn reserve(region: Range<Paddr>) {
+    let mut memblock = MEMBLOCK.lock();
+    let memblock = memblock
+        .as_mut()
+        .expect("the boot memory allocator is not available");
+    memblock
+        .reserved
+        .insert(region.start.align_down(PAGE_SIZE)..region.end.align_up(PAGE_SIZE));
+}
+
+/// Disables the boot memory allocator and returns the page-aligned memory regions
+/// that are not reserved, which should be managed by the page allocator since then.
+///
+/// # Panics
+///
+/// This function panics if the boot memory allocator is no longer available.
+pub(crate) fn handoff() -> Vec<Range<Paddr>> {
+    let memblock = MEMBLOCK
+        .lock()
+        .take()
+        .expect("the boot memory allocator is not available");
+    let nr_reserved_bytes: usize = memblock.reserved.as_slice().iter().map(|r| r.len()).sum();
+    info!(
+        "Boot memory allocator hands off, {} KiB reserved",
+        nr_reserved_bytes / 1024
+    );
+    memblock.free_regions()
+}
+
+#[cfg(ktest)]
+mod test {
+    use super::*;
+
+    #[ktest]
+    fn insert_regions() {
+        let mut array = RegionArray::new();
+        array.insert(0x5000..0x6000);
+        array.insert(0x1000..0x2000);
+        array.insert(0x3000..0x4000);
+        assert_eq!(
+            array.as_slice(),
+            [0x1000..0x2000, 0x3000..0x4000, 0x5000..0x6000]
+        );
+
+        // Adjacent and overlapping regions are merged.
+        array.insert(0x2000..0x3000);
+        assert_eq!(array.as_slice(), [0x1000..0x4000, 0x5000..0x6000]);
+        array.insert(0x3800..0x5800);
+        assert_eq!(array.as_slice(), [0x1000..0x6000]);
+        array.insert(0x0..0x8000);
+        assert_eq!(array.as_slice(), [0x0..0x8000]);
+    }
+
+    #[ktest]
+    fn alloc_and_handoff() {
+        let mut memblock = MemBlock {
+            usable: RegionArray::new(),
+            reserved: RegionArray::new(),
+        };
+        memblock.usable.insert(0x1000..0x4000);
+        memblock.usable.insert(0x10000..0x12000);
+        memblock.reserved.insert(0x11000..0x12000);
+
+        // It allocates from the top of the highest region that fits.
+        assert_eq!(memblock.alloc(1, usize::MAX), Some(0x10000));
+        assert_eq!(memblock.alloc(2, usize::MAX), Some(0x2000));
+        assert_eq!(memblock.alloc(2, usize::MAX), None);
+        assert_eq!(memblock.free_regions(), [0x1000..0x2000]);
+    }
+
+    #[ktest]
+    fn alloc_below_limit() {
+        let mut memblock = MemBlock {
+            usable: RegionArray::new(),
+            reserved: RegionArray::new(),
+        };
+        memblock.usable.insert(0x1000..0x4000);
+        memblock.usable.insert(0x10000..0x12000);
+
+        assert_eq!(memblock.alloc(1, 0x11000), Some(0x10000));
+        assert_eq!(memblock.alloc(1, 0x11000), Some(0x3000));
+        assert_eq!(memblock.alloc(3, 0x11000), None);
+        assert_eq!(memblock.free_regions(), [0x1000..0x3000, 0x11000..0x12000]);
+    }
+}
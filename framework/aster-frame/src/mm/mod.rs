@@ -10,16 +10,24 @@ pub type Vaddr = usize;
 /// Physical addresses.
 pub type Paddr = usize;
 
+pub mod compaction;
 pub(crate) mod dma;
 pub mod frame;
 pub(crate) mod heap_allocator;
 mod io;
+pub mod kmem_cache;
 pub(crate) mod kspace;
+pub(crate) mod memblock;
 mod offset;
 pub(crate) mod page;
 pub(crate) mod page_prop;
 pub(crate) mod page_table;
+#[cfg(feature = "mm_poison")]
+pub mod poison;
+pub mod reclaim;
 mod space;
+pub mod vmalloc;
+pub mod zeroed;
 
 use alloc::vec::Vec;
 use core::{fmt::Debug, ops::Range};
@@ -27,11 +35,18 @@ use core::{fmt::Debug, ops::Range};
 use spin::Once;
 
 pub use self::{
-    dma::{Daddr, DmaCoherent, DmaDirection, DmaStream, DmaStreamSlice, HasDaddr},
+    dma::{
+        dma_map, dma_unmap, Daddr, DmaCoherent, DmaDevice, DmaDirection, DmaError, DmaMapping,
+        DmaStream, DmaStreamSlice, HasDaddr,
+    },
     frame::{options::FrameAllocOptions, Frame, FrameVec, FrameVecIter, Segment},
+    heap_allocator::nr_heap_frames,
     io::{VmIo, VmReader, VmWriter},
+    kmem_cache::{KmemBox, KmemCache},
+    page::allocator::{nr_free_frames, nr_total_frames},
     page_prop::{CachePolicy, PageFlags, PageProperty},
-    space::{VmMapOptions, VmSpace},
+    space::{VmMapOptions, VmQueryIter, VmQueryResult, VmSpace},
+    vmalloc::{vmalloc, vmap, vunmap, VmallocArea},
 };
 pub(crate) use self::{
     kspace::paddr_to_vaddr, page::meta::init as init_page_meta, page_prop::PrivilegedPageFlags,
@@ -74,6 +89,9 @@ pub(crate) trait PagingConstsTrait: Clone + Debug + Default + Sync + 'static {
 /// The page size
 pub const PAGE_SIZE: usize = page_size::<PagingConsts>(1);
 
+/// The size of the smallest huge page, e.g., 2 MiB on x86-64.
+pub const HUGE_PAGE_SIZE: usize = page_size::<PagingConsts>(2);
+
 /// The page size at a given level.
 pub(crate) const fn page_size<C: PagingConstsTrait>(level: PagingLevel) -> usize {
     C::BASE_PAGE_SIZE << (nr_subpage_per_huge::<C>().ilog2() as usize * (level as usize - 1))
@@ -122,6 +140,8 @@ pub static FRAMEBUFFER_REGIONS: Once<Vec<MemoryRegion>> = Once::new();
 
 pub(crate) fn misc_init() {
     dma::init();
+    #[cfg(feature = "mm_poison")]
+    poison::init();
 
     let mut framebuffer_regions = Vec::new();
     for i in crate::boot::memory_regions() {
@@ -6,8 +6,8 @@
 //! allocating pages rather untyped memory from this module.
 
 use alloc::vec::Vec;
+use core::ops::Range;
 
-use align_ext::AlignExt;
 use buddy_system_allocator::FrameAllocator;
 use log::info;
 use spin::Once;
@@ -16,47 +16,97 @@ use super::{
     meta::{FrameMeta, PageMeta},
     Page,
 };
+#[cfg(feature = "mm_poison")]
+use crate::mm::poison;
 use crate::{
-    boot::memory_region::MemoryRegionType,
-    mm::{Frame, FrameVec, Segment, PAGE_SIZE},
+    mm::{memblock, reclaim, Frame, FrameVec, Segment, PAGE_SIZE},
     sync::SpinLock,
 };
 
-pub(in crate::mm) static FRAME_ALLOCATOR: Once<SpinLock<FrameAllocator>> = Once::new();
+/// A buddy frame allocator that keeps track of the number of free frames.
+pub(in crate::mm) struct CountingFrameAllocator {
+    allocator: FrameAllocator<32>,
+    /// The ranges of the indexes of the frames managed by the allocator.
+    regions: Vec<Range<usize>>,
+    total: usize,
+    allocated: usize,
+}
+
+impl CountingFrameAllocator {
+    pub(in crate::mm) fn alloc(&mut self, count: usize) -> Option<usize> {
+        let start = self.allocator.alloc(count)?;
+        self.allocated += count;
+        Some(start)
+    }
+
+    pub(in crate::mm) fn dealloc(&mut self, start_frame: usize, count: usize) {
+        self.allocator.dealloc(start_frame, count);
+        self.allocated -= count;
+    }
+
+    fn nr_free(&self) -> usize {
+        self.total - self.allocated
+    }
+}
+
+pub(in crate::mm) static FRAME_ALLOCATOR: Once<SpinLock<CountingFrameAllocator>> = Once::new();
+
+/// Returns the total number of frames managed by the frame allocator.
+pub fn nr_total_frames() -> usize {
+    FRAME_ALLOCATOR.get().unwrap().lock().total
+}
+
+/// Returns the number of frames that are currently free.
+pub fn nr_free_frames() -> usize {
+    FRAME_ALLOCATOR.get().unwrap().lock().nr_free()
+}
+
+/// Returns the ranges of the indexes of the frames managed by the frame allocator.
+pub(in crate::mm) fn managed_regions() -> Vec<Range<usize>> {
+    FRAME_ALLOCATOR.get().unwrap().lock().regions.clone()
+}
+
+/// Allocates `nframes` contiguous frames and returns the index of the first one.
+///
+/// The low-memory notification is fired after the allocator lock is released
+/// if the number of free frames drops below the low watermark.
+fn alloc_frames(nframes: usize) -> Option<usize> {
+    let (start, nr_free, total) = {
+        let mut allocator = FRAME_ALLOCATOR.get().unwrap().lock();
+        let start = allocator.alloc(nframes);
+        (start, allocator.nr_free(), allocator.total)
+    };
+
+    if start.is_none() || nr_free < reclaim::low_watermark(total) {
+        reclaim::notify_low_memory();
+    }
+
+    start
+}
 
 pub(crate) fn alloc(nframes: usize) -> Option<FrameVec> {
-    FRAME_ALLOCATOR
-        .get()
-        .unwrap()
-        .lock()
-        .alloc(nframes)
-        .map(|start| {
-            let mut vector = Vec::new();
-            for i in 0..nframes {
-                let paddr = (start + i) * PAGE_SIZE;
-                let frame = Frame {
-                    page: Page::<FrameMeta>::from_unused(paddr),
-                };
-                vector.push(frame);
-            }
-            FrameVec(vector)
-        })
+    alloc_frames(nframes).map(|start| {
+        let mut vector = Vec::new();
+        for i in 0..nframes {
+            let paddr = (start + i) * PAGE_SIZE;
+            let frame = Frame {
+                page: Page::<FrameMeta>::from_unused(paddr),
+            };
+            vector.push(frame);
+        }
+        FrameVec(vector)
+    })
 }
 
 pub(crate) fn alloc_single<T: PageMeta>() -> Option<Page<T>> {
-    FRAME_ALLOCATOR.get().unwrap().lock().alloc(1).map(|idx| {
+    alloc_frames(1).map(|idx| {
         let paddr = idx * PAGE_SIZE;
         Page::<T>::from_unused(paddr)
     })
 }
 
 pub(crate) fn alloc_contiguous(nframes: usize) -> Option<Segment> {
-    FRAME_ALLOCATOR
-        .get()
-        .unwrap()
-        .lock()
-        .alloc(nframes)
-        .map(|start|
+    alloc_frames(nframes).map(|start|
             // SAFETY: The range of page frames is contiguous and valid.
             unsafe {
             Segment::new(
@@ -73,6 +123,19 @@ pub(crate) fn alloc_contiguous(nframes: usize) -> Option<Segment> {
 /// User should ensure the range of page frames is valid.
 ///
 pub(crate) unsafe fn dealloc(start_index: usize, nframes: usize) {
+    #[cfg(feature = "mm_poison")]
+    let Some((start_index, nframes)) = poison::quarantine_frames(start_index, nframes) else {
+        return;
+    };
+    free_frames(start_index, nframes);
+}
+
+/// Returns a contiguous range of page frames to the frame allocator.
+///
+/// # Safety
+///
+/// User should ensure the range of page frames is valid and no longer used.
+pub(in crate::mm) unsafe fn free_frames(start_index: usize, nframes: usize) {
     FRAME_ALLOCATOR
         .get()
         .unwrap()
@@ -80,26 +143,43 @@ pub(crate) unsafe fn dealloc(start_index: usize, nframes: usize) {
         .dealloc(start_index, nframes);
 }
 
+/// Releases the frames allocated from the boot memory allocator to the page allocator.
+pub(in crate::mm) fn release_boot_frames(start_frame: usize, nframes: usize) {
+    let mut allocator = FRAME_ALLOCATOR.get().unwrap().lock();
+    allocator
+        .allocator
+        .add_frame(start_frame, start_frame + nframes);
+    match allocator.regions.last_mut() {
+        Some(last) if last.end == start_frame => last.end += nframes,
+        _ => allocator.regions.push(start_frame..start_frame + nframes),
+    }
+    allocator.total += nframes;
+}
+
+/// Initializes the page allocator with the memory that is not reserved by the boot
+/// memory allocator, which is disabled then.
 pub(crate) fn init() {
-    let regions = crate::boot::memory_regions();
     let mut allocator = FrameAllocator::<32>::new();
-    for region in regions.iter() {
-        if region.typ() == MemoryRegionType::Usable {
-            // Make the memory region page-aligned, and skip if it is too small.
-            let start = region.base().align_up(PAGE_SIZE) / PAGE_SIZE;
-            let region_end = region.base().checked_add(region.len()).unwrap();
-            let end = region_end.align_down(PAGE_SIZE) / PAGE_SIZE;
-            if end <= start {
-                continue;
-            }
-            // Add global free pages to the frame allocator.
-            allocator.add_frame(start, end);
-            info!(
-                "Found usable region, start:{:x}, end:{:x}",
-                region.base(),
-                region.base() + region.len()
-            );
-        }
+    let mut regions = Vec::new();
+    let mut total = 0;
+    for region in memblock::handoff() {
+        let start = region.start / PAGE_SIZE;
+        let end = region.end / PAGE_SIZE;
+        // Add global free pages to the frame allocator.
+        allocator.add_frame(start, end);
+        regions.push(start..end);
+        total += end - start;
+        info!(
+            "Found free region, start:{:x}, end:{:x}",
+            region.start, region.end
+        );
     }
-    FRAME_ALLOCATOR.call_once(|| SpinLock::new(allocator));
+    FRAME_ALLOCATOR.call_once(|| {
+        SpinLock::new(CountingFrameAllocator {
+            allocator,
+            regions,
+            total,
+            allocated: 0,
+        })
+    });
 }
@@ -13,6 +13,11 @@
 //! In the implemetation level, the slots are placed in the metadata pages mapped to a certain virtual
 //! address. It is faster, simpler, safer and more versatile compared with an actual static array
 //! implementation.
+//!
+//! The physical address space may have large holes, e.g., the MMIO holes or the sparse memory layout
+//! of confidential VMs. So the array is divided into sections, each of which holds the slots of
+//! [`SECTION_SIZE`] bytes of physical memory. Only the sections containing some memory regions are
+//! backed with metadata pages, and the slots of the other sections are never accessed.
 
 pub mod mapping {
     //! The metadata of each physical page is linear mapped to fixed virtual addresses
@@ -46,16 +51,18 @@ use core::{
     sync::atomic::{AtomicU32, AtomicU8, Ordering},
 };
 
+use bitvec::prelude::BitVec;
 use log::info;
+use spin::Once;
 use static_assertions::const_assert_eq;
 
 use super::Page;
 use crate::{
     arch::mm::{PageTableEntry, PagingConsts},
     mm::{
-        kspace::BOOT_PAGE_TABLE, paddr_to_vaddr, page::allocator::FRAME_ALLOCATOR, page_size,
-        page_table::PageTableEntryTrait, CachePolicy, Paddr, PageFlags, PageProperty,
-        PagingConstsTrait, PagingLevel, PrivilegedPageFlags, PAGE_SIZE,
+        kspace::BOOT_PAGE_TABLE, memblock, paddr_to_vaddr, page_table::PageTableEntryTrait,
+        CachePolicy, Paddr, PageFlags, PageProperty, PagingConstsTrait, PagingLevel,
+        PrivilegedPageFlags, Vaddr, PAGE_SIZE,
     },
 };
 
@@ -185,14 +192,51 @@ impl PageMeta for KernelMeta {
 
 // ======== End of all the specific metadata structures definitions ===========
 
+/// The size of the physical memory covered by a section of metadata slots.
+pub(in crate::mm) const SECTION_SIZE: usize = 128 * 1024 * 1024;
+
+/// The number of metadata pages of a section.
+const NR_META_PAGES_PER_SECTION: usize =
+    SECTION_SIZE / PAGE_SIZE * size_of::<MetaSlot>() / PAGE_SIZE;
+
+// The metadata of a section should occupy whole pages so that the sections can be mapped
+// independently.
+const_assert_eq!(
+    SECTION_SIZE / PAGE_SIZE * size_of::<MetaSlot>() % PAGE_SIZE,
+    0
+);
+
+/// The sections whose metadata slots are present, indexed by the section number.
+static PRESENT_SECTIONS: Once<BitVec> = Once::new();
+
+/// Returns whether the metadata slot of the page at the physical address is present.
+pub(in crate::mm) fn is_meta_present(paddr: Paddr) -> bool {
+    PRESENT_SECTIONS
+        .get()
+        .and_then(|sections| sections.get(paddr / SECTION_SIZE).map(|present| *present))
+        .unwrap_or(false)
+}
+
+/// Returns the usage of the page at the physical address as a raw [`PageUsage`].
+///
+/// The metadata slot of the page must be present.
+pub(in crate::mm) fn page_usage(paddr: Paddr) -> u8 {
+    debug_assert!(is_meta_present(paddr));
+    let slot = mapping::page_to_meta::<PagingConsts>(paddr) as *const MetaSlot;
+    // SAFETY: The metadata slot is present, so it is mapped and initialized.
+    unsafe { &(*slot).usage }.load(Ordering::Relaxed)
+}
+
 /// Initializes the metadata of all physical pages.
 ///
-/// The function returns a list of `Page`s containing the metadata.
-pub(crate) fn init() -> Vec<Range<Paddr>> {
-    let max_paddr = {
-        let regions = crate::boot::memory_regions();
-        regions.iter().map(|r| r.base() + r.len()).max().unwrap()
-    };
+/// The metadata pages are allocated from the boot memory allocator, so this function
+/// should be called before the page allocator is initialized.
+///
+/// The function returns a list of the virtual addresses where the metadata of the
+/// present sections are mapped, with the physical addresses of the metadata pages.
+pub(crate) fn init() -> Vec<(Vaddr, Range<Paddr>)> {
+    let regions = crate::boot::memory_regions();
+    let max_paddr = regions.iter().map(|r| r.base() + r.len()).max().unwrap();
 
     info!(
         "Initializing page metadata for physical memory up to {:x}",
@@ -201,49 +245,82 @@ pub(crate) fn init() -> Vec<Range<Paddr>> {
 
     super::MAX_PADDR.store(max_paddr, Ordering::Relaxed);
 
-    let num_pages = max_paddr / page_size::<PagingConsts>(1);
-    let num_meta_pages = (num_pages * size_of::<MetaSlot>()).div_ceil(PAGE_SIZE);
-    let meta_pages = alloc_meta_pages(num_meta_pages);
-    // Map the metadata pages.
+    // Find out the sections that contain some memory regions.
+    let mut present_sections = BitVec::repeat(false, max_paddr.div_ceil(SECTION_SIZE));
+    for region in regions.iter().filter(|r| r.len() > 0) {
+        let first = region.base() / SECTION_SIZE;
+        let last = (region.base() + region.len() - 1) / SECTION_SIZE;
+        present_sections[first..=last].fill(true);
+    }
+    let nr_present = present_sections.count_ones();
+    info!(
+        "{} of {} page metadata sections are present",
+        nr_present,
+        present_sections.len()
+    );
+
+    let mut meta_sections = Vec::with_capacity(nr_present);
     let mut boot_pt_lock = BOOT_PAGE_TABLE.lock();
     let boot_pt = boot_pt_lock
         .as_mut()
         .expect("boot page table not initialized");
-    for (i, frame_paddr) in meta_pages.iter().enumerate() {
-        let vaddr = mapping::page_to_meta::<PagingConsts>(0) + i * PAGE_SIZE;
-        let prop = PageProperty {
-            flags: PageFlags::RW,
-            cache: CachePolicy::Writeback,
-            priv_flags: PrivilegedPageFlags::GLOBAL,
-        };
-        // SAFETY: we are doing the metadata mappings for the kernel.
-        unsafe { boot_pt.map_base_page(vaddr, frame_paddr / PAGE_SIZE, prop) };
+    for section in present_sections.iter_ones() {
+        let vaddr = mapping::page_to_meta::<PagingConsts>(section * SECTION_SIZE);
+        let meta_paddr = alloc_meta_pages(NR_META_PAGES_PER_SECTION);
+        // Map the metadata pages.
+        for i in 0..NR_META_PAGES_PER_SECTION {
+            let prop = PageProperty {
+                flags: PageFlags::RW,
+                cache: CachePolicy::Writeback,
+                priv_flags: PrivilegedPageFlags::GLOBAL,
+                pkey: 0,
+            };
+            // SAFETY: we are doing the metadata mappings for the kernel.
+            unsafe {
+                boot_pt.map_base_page(vaddr + i * PAGE_SIZE, meta_paddr / PAGE_SIZE + i, prop)
+            };
+        }
+        meta_sections.push((
+            vaddr,
+            meta_paddr..meta_paddr + NR_META_PAGES_PER_SECTION * PAGE_SIZE,
+        ));
     }
+    drop(boot_pt_lock);
+    PRESENT_SECTIONS.call_once(|| present_sections);
+
     // Now the metadata pages are mapped, we can initialize the metadata.
-    meta_pages
-        .into_iter()
-        .map(|paddr| {
-            let pa = Page::<MetaPageMeta>::from_unused(paddr).into_raw();
-            pa..pa + PAGE_SIZE
-        })
-        .collect()
-}
-
-fn alloc_meta_pages(nframes: usize) -> Vec<Paddr> {
-    let mut meta_pages = Vec::new();
-    let start_frame = FRAME_ALLOCATOR
-        .get()
-        .unwrap()
-        .lock()
-        .alloc(nframes)
-        .unwrap()
-        * PAGE_SIZE;
+    for (_, meta_pages) in meta_sections.iter() {
+        for paddr in meta_pages.clone().step_by(PAGE_SIZE) {
+            let _ = Page::<MetaPageMeta>::from_unused(paddr).into_raw();
+        }
+    }
+    meta_sections
+}
+
+/// Allocates zeroed contiguous metadata pages and returns the physical address of the first one.
+fn alloc_meta_pages(nframes: usize) -> Paddr {
+    let start_frame =
+        memblock::alloc(nframes).expect("not enough boot memory for the page metadata");
     // Zero them out as initialization.
     let vaddr = paddr_to_vaddr(start_frame) as *mut u8;
     unsafe { core::ptr::write_bytes(vaddr, 0, PAGE_SIZE * nframes) };
-    for i in 0..nframes {
-        let paddr = start_frame + i * PAGE_SIZE;
-        meta_pages.push(paddr);
+    start_frame
+}
+
+#[cfg(ktest)]
+mod test {
+    use align_ext::AlignExt;
+
+    use super::*;
+    use crate::mm::FrameAllocOptions;
+
+    #[ktest]
+    fn allocated_frames_have_meta() {
+        let frame = FrameAllocOptions::new(1).alloc_single().unwrap();
+        assert!(is_meta_present(frame.start_paddr()));
+
+        let max_paddr = super::super::MAX_PADDR.load(Ordering::Relaxed);
+        let beyond = max_paddr.align_up(SECTION_SIZE);
+        assert!(!is_meta_present(beyond));
     }
-    meta_pages
 }
@@ -83,7 +83,7 @@ impl<M: PageMeta> Page<M> {
         if paddr % PAGE_SIZE != 0 {
             return Err(PageHandleError::NotAligned);
         }
-        if paddr > MAX_PADDR.load(Ordering::Relaxed) {
+        if paddr > MAX_PADDR.load(Ordering::Relaxed) || !meta::is_meta_present(paddr) {
             return Err(PageHandleError::OutOfRange);
         }
 
@@ -14,6 +14,12 @@ pub struct PageProperty {
     /// The cache policy for the page.
     pub cache: CachePolicy,
     pub(crate) priv_flags: PrivilegedPageFlags,
+    /// The protection key of the page, which selects the access rights in the PKRU
+    /// register that further restrict the accesses to the user page.
+    ///
+    /// It is ignored if the CPU does not support protection keys. See
+    /// [`crate::cpu::has_pkeys`].
+    pub pkey: u8,
 }
 
 impl PageProperty {
@@ -23,6 +29,7 @@ impl PageProperty {
             flags,
             cache,
             priv_flags: PrivilegedPageFlags::USER,
+            pkey: 0,
         }
     }
     /// Creates a page property that implies an invalid page without mappings.
@@ -31,6 +38,7 @@ impl PageProperty {
             flags: PageFlags::empty(),
             cache: CachePolicy::Writeback,
             priv_flags: PrivilegedPageFlags::empty(),
+            pkey: 0,
         }
     }
 }
@@ -10,8 +10,9 @@ use super::{pte_index, PageTableEntryTrait};
 use crate::{
     arch::mm::{PageTableEntry, PagingConsts},
     mm::{
-        nr_subpage_per_huge, paddr_to_vaddr, page::allocator::FRAME_ALLOCATOR, PageProperty,
-        PagingConstsTrait, Vaddr, PAGE_SIZE,
+        memblock, nr_subpage_per_huge, paddr_to_vaddr,
+        page::allocator::{self, FRAME_ALLOCATOR},
+        PageProperty, PagingConstsTrait, Vaddr, PAGE_SIZE,
     },
 };
 
@@ -29,6 +30,9 @@ pub struct BootPageTable<
     // metadata [`crate::mm::frame::meta`]. Here is a record of it
     // for deallocation.
     frames: Vec<FrameNumber>,
+    // The frames allocated from the boot memory allocator, which are
+    // not managed by the page allocator until they are released.
+    boot_frames: Vec<FrameNumber>,
     _pretend_to_use: core::marker::PhantomData<(E, C)>,
 }
 
@@ -39,6 +43,7 @@ impl<E: PageTableEntryTrait, C: PagingConstsTrait> BootPageTable<E, C> {
         Self {
             root_pt: root_paddr / C::BASE_PAGE_SIZE,
             frames: Vec::new(),
+            boot_frames: Vec::new(),
             _pretend_to_use: core::marker::PhantomData,
         }
     }
@@ -144,8 +149,15 @@ impl<E: PageTableEntryTrait, C: PagingConstsTrait> BootPageTable<E, C> {
     }
 
     fn alloc_frame(&mut self) -> FrameNumber {
-        let frame = FRAME_ALLOCATOR.get().unwrap().lock().alloc(1).unwrap();
-        self.frames.push(frame);
+        let frame = if memblock::is_active() {
+            let frame = memblock::alloc(1).unwrap() / PAGE_SIZE;
+            self.boot_frames.push(frame);
+            frame
+        } else {
+            let frame = FRAME_ALLOCATOR.get().unwrap().lock().alloc(1).unwrap();
+            self.frames.push(frame);
+            frame
+        };
         // Zero it out.
         let vaddr = paddr_to_vaddr(frame * PAGE_SIZE) as *mut u8;
         unsafe { core::ptr::write_bytes(vaddr, 0, PAGE_SIZE) };
@@ -158,6 +170,9 @@ impl<E: PageTableEntryTrait, C: PagingConstsTrait> Drop for BootPageTable<E, C>
         for frame in &self.frames {
             FRAME_ALLOCATOR.get().unwrap().lock().dealloc(*frame, 1);
         }
+        for frame in &self.boot_frames {
+            allocator::release_boot_frames(*frame, 1);
+        }
     }
 }
 
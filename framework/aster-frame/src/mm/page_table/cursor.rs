@@ -53,6 +53,7 @@
 //! required. The cursor unlock all locks, then lock all the way down to `B`, then
 //! check if `B` is empty, and finally recycle all the resources on the way back.
 
+use alloc::vec::Vec;
 use core::{any::TypeId, ops::Range};
 
 use align_ext::AlignExt;
@@ -61,7 +62,7 @@ use super::{
     page_size, pte_index, Child, KernelMode, PageTable, PageTableEntryTrait, PageTableError,
     PageTableMode, PageTableNode, PagingConstsTrait, PagingLevel,
 };
-use crate::mm::{Frame, Paddr, PageProperty, Vaddr};
+use crate::mm::{nr_base_per_page, Frame, Paddr, PageFlags, PageProperty, Vaddr};
 
 #[derive(Clone, Debug)]
 pub(crate) enum PageTableQueryResult {
@@ -392,6 +393,8 @@ where
 
     /// Maps the range starting from the current address to a [`Frame`].
     ///
+    /// Returns whether an existing mapping is replaced by the new one.
+    ///
     /// # Panics
     ///
     /// This function will panic if
@@ -403,7 +406,7 @@ where
     ///
     /// The caller should ensure that the virtual range being mapped does
     /// not affect kernel's memory safety.
-    pub(crate) unsafe fn map(&mut self, frame: Frame, prop: PageProperty) {
+    pub(crate) unsafe fn map(&mut self, frame: Frame, prop: PageProperty) -> bool {
         let end = self.0.va + frame.size();
         assert!(end <= self.0.barrier_va.end);
         debug_assert!(!self.0.in_untracked_range());
@@ -426,8 +429,10 @@ where
         // Map the current page.
         let idx = self.0.cur_idx();
         let level = self.0.level;
+        let is_replaced = self.0.read_cur_pte().is_present();
         self.cur_node_mut().set_child_frame(idx, frame, prop);
         self.0.move_forward();
+        is_replaced
     }
 
     /// Maps the range starting from the current address to a physical address range.
@@ -495,6 +500,8 @@ where
 
     /// Unmaps the range starting from the current address with the given length of virtual address.
     ///
+    /// Returns the number of base pages that were mapped in the range.
+    ///
     /// # Safety
     ///
     /// The caller should ensure that the range being unmapped does not affect kernel's memory safety.
@@ -504,10 +511,11 @@ where
     /// This function will panic if:
     ///  - the range to be unmapped is out of the range where the cursor is required to operate;
     ///  - the range covers only a part of a page.
-    pub(crate) unsafe fn unmap(&mut self, len: usize) {
+    pub(crate) unsafe fn unmap(&mut self, len: usize) -> usize {
         let end = self.0.va + len;
         assert!(end <= self.0.barrier_va.end);
         assert!(end % C::BASE_PAGE_SIZE == 0);
+        let mut nr_unmapped = 0;
         while self.0.va < end {
             let cur_pte = self.0.read_cur_pte();
             let untracked = self.0.in_untracked_range();
@@ -542,12 +550,72 @@ where
             // Unmap the current page.
             let idx = self.0.cur_idx();
             self.cur_node_mut().unset_child(idx, untracked);
+            nr_unmapped += nr_base_per_page::<C>(self.0.level);
             self.0.move_forward();
         }
+        nr_unmapped
+    }
+
+    /// Moves the mappings in the range starting from the current address to the range starting
+    /// from `dst`, without copying the contents of the mapped pages.
+    ///
+    /// Both ranges must be within the range where the cursor is required to operate. Moving with
+    /// another cursor on the same page table may deadlock if the two cursors lock the same page
+    /// table node, so the cursor must be created with a range that covers both of them.
+    ///
+    /// Returns the number of base pages that were moved. Gaps in the source range are skipped.
+    ///
+    /// # Safety
+    ///
+    /// The caller should ensure that the ranges being moved from and to do not affect kernel's
+    /// memory safety.
+    ///
+    /// # Panics
+    ///
+    /// This function will panic if:
+    ///  - either range is out of the range where the cursor is required to operate;
+    ///  - the two ranges overlap;
+    ///  - the destination range is not unmapped;
+    ///  - the source range covers only a part of a page, or it contains untracked mappings.
+    pub(crate) unsafe fn move_to(&mut self, dst: Vaddr, len: usize) -> usize {
+        let src = self.0.va;
+        assert!(src + len <= self.0.barrier_va.end);
+        assert!(self.0.barrier_va.start <= dst && dst + len <= self.0.barrier_va.end);
+        assert!(src + len <= dst || dst + len <= src);
+        assert!(dst % C::BASE_PAGE_SIZE == 0 && len % C::BASE_PAGE_SIZE == 0);
+        let mut nr_moved = 0;
+        let mut offset = 0;
+        while offset < len {
+            self.jump(src + offset);
+            match self.0.query().unwrap() {
+                PageTableQueryResult::NotMapped { va, len } => {
+                    offset = va.align_down(len) + len - src;
+                }
+                PageTableQueryResult::Mapped { va, frame, prop } => {
+                    let size = frame.size();
+                    assert!(va % size == 0 && offset + size <= len);
+                    self.unmap(size);
+                    self.jump(dst + offset);
+                    let is_replaced = self.map(frame, prop);
+                    debug_assert!(!is_replaced);
+                    nr_moved += size / C::BASE_PAGE_SIZE;
+                    offset += size;
+                }
+                PageTableQueryResult::MappedUntracked { .. } => {
+                    panic!("Moving untracked mappings");
+                }
+            }
+        }
+        nr_moved
     }
 
     /// Applies the given operation to all the mappings within the range.
     ///
+    /// The absent page table nodes are skipped as a whole, and the PTEs whose properties are
+    /// not changed by the operation are left untouched. It returns the virtual address ranges
+    /// of the changed mappings, in which the adjacent ranges are merged, so that the caller
+    /// can flush the TLB entries of them only.
+    ///
     /// The funtction will return an error if it is not allowed to protect an invalid range and
     /// it does so, or if the range to be protected only covers a part of a page.
     ///
@@ -564,9 +632,10 @@ where
         len: usize,
         mut op: impl FnMut(&mut PageProperty),
         allow_protect_absent: bool,
-    ) -> Result<(), PageTableError> {
+    ) -> Result<Vec<Range<Vaddr>>, PageTableError> {
         let end = self.0.va + len;
         assert!(end <= self.0.barrier_va.end);
+        let mut protected: Vec<Range<Vaddr>> = Vec::new();
         while self.0.va < end {
             let cur_pte = self.0.read_cur_pte();
             if !cur_pte.is_present() {
@@ -593,12 +662,68 @@ where
             }
             let idx = self.0.cur_idx();
             let level = self.0.level;
-            let mut pte_prop = cur_pte.prop();
+            let old_prop = cur_pte.prop();
+            let mut pte_prop = old_prop;
             op(&mut pte_prop);
-            self.cur_node_mut().protect(idx, pte_prop);
+            if pte_prop != old_prop {
+                self.cur_node_mut().protect(idx, pte_prop);
+                let va = self.0.va;
+                let va_end = va + page_size::<C>(level);
+                match protected.last_mut() {
+                    Some(last) if last.end == va => last.end = va_end,
+                    _ => protected.push(va..va_end),
+                }
+            }
+            self.0.move_forward();
+        }
+        Ok(protected)
+    }
+
+    /// Takes the given bits set by the MMU, i.e., the accessed bits and (or) the dirty bits,
+    /// of the pages in the range from the current virtual address, with the given length.
+    ///
+    /// The bits of the mapped pages are cleared atomically with respect to the MMU. The
+    /// starting virtual addresses of the pages whose given bits were set are returned along
+    /// with those bits, so the caller can find the dirty pages and the working set without
+    /// unmapping or write-protecting the pages. Absent pages are skipped, and a huge page
+    /// that is partially covered by the range is taken as a whole.
+    ///
+    /// It is the caller's responsibility to flush the TLB entries of the returned pages.
+    /// Otherwise, the MMU may not set the bits again on the following accesses.
+    ///
+    /// # Safety
+    ///
+    /// The caller should ensure that the range being harvested does not affect kernel's
+    /// memory safety.
+    pub(crate) unsafe fn take_flags(
+        &mut self,
+        len: usize,
+        flags: PageFlags,
+    ) -> Vec<(Vaddr, PageFlags)> {
+        debug_assert!((PageFlags::ACCESSED | PageFlags::DIRTY).contains(flags));
+        let end = self.0.va + len;
+        assert!(end <= self.0.barrier_va.end);
+        let mut taken = Vec::new();
+        while self.0.va < end {
+            let cur_pte = self.0.read_cur_pte();
+            if !cur_pte.is_present() {
+                self.0.move_forward();
+                continue;
+            }
+            // Go down if it's not a last node.
+            if !cur_pte.is_last(self.0.level) {
+                self.0.level_down();
+                continue;
+            }
+            let idx = self.0.cur_idx();
+            let taken_flags = self.cur_node_mut().take_flags(idx, flags).flags & flags;
+            if !taken_flags.is_empty() {
+                let page_va = self.0.va.align_down(page_size::<C>(self.0.level));
+                taken.push((page_va, taken_flags));
+            }
             self.0.move_forward();
         }
-        Ok(())
+        taken
     }
 
     /// Consumes itself and leak the root guard for the caller if it locked the root level.
@@ -2,6 +2,7 @@
 
 #![allow(dead_code)]
 
+use alloc::vec::Vec;
 use core::{fmt::Debug, marker::PhantomData, ops::Range};
 
 use pod::Pod;
@@ -100,18 +101,22 @@ impl PageTable<UserMode> {
     /// Remove all write permissions from the user page table and create a cloned
     /// new page table.
     ///
+    /// It also returns the virtual address ranges whose write permissions are removed,
+    /// whose TLB entries should be flushed by the caller. The mappings that are already
+    /// read-only are not included.
+    ///
     /// TODO: We may consider making the page table itself copy-on-write.
-    pub(crate) fn fork_copy_on_write(&self) -> Self {
+    pub(crate) fn fork_copy_on_write(&self) -> (Self, Vec<Range<Vaddr>>) {
         let mut cursor = self.cursor_mut(&UserMode::VADDR_RANGE).unwrap();
         // SAFETY: Protecting the user page table is safe.
-        unsafe {
+        let protected = unsafe {
             cursor
                 .protect(
                     UserMode::VADDR_RANGE.len(),
                     |p: &mut PageProperty| p.flags -= PageFlags::W,
                     true,
                 )
-                .unwrap();
+                .unwrap()
         };
         let root_frame = cursor.leak_root_guard().unwrap();
         const NR_PTES_PER_NODE: usize = nr_subpage_per_huge::<PagingConsts>();
@@ -121,10 +126,11 @@ impl PageTable<UserMode> {
                 NR_PTES_PER_NODE / 2..NR_PTES_PER_NODE,
             )
         };
-        PageTable::<UserMode> {
+        let new_pt = PageTable::<UserMode> {
             root: new_root_frame.into_raw(),
             _phantom: PhantomData,
-        }
+        };
+        (new_pt, protected)
     }
 }
 
@@ -207,9 +213,9 @@ where
         Ok(())
     }
 
-    pub(crate) unsafe fn unmap(&self, vaddr: &Range<Vaddr>) -> Result<(), PageTableError> {
-        self.cursor_mut(vaddr)?.unmap(vaddr.len());
-        Ok(())
+    /// Unmaps the virtual address range, returning the number of base pages that were mapped.
+    pub(crate) unsafe fn unmap(&self, vaddr: &Range<Vaddr>) -> Result<usize, PageTableError> {
+        Ok(self.cursor_mut(vaddr)?.unmap(vaddr.len()))
     }
 
     pub(crate) unsafe fn protect(
@@ -223,6 +229,18 @@ where
         Ok(())
     }
 
+    /// Takes the given accessed and (or) dirty bits of the mapped pages in the virtual
+    /// address range.
+    ///
+    /// See [`CursorMut::take_flags`] for the details.
+    pub(crate) unsafe fn take_flags(
+        &self,
+        vaddr: &Range<Vaddr>,
+        flags: PageFlags,
+    ) -> Result<Vec<(Vaddr, PageFlags)>, PageTableError> {
+        Ok(self.cursor_mut(vaddr)?.take_flags(vaddr.len(), flags))
+    }
+
     /// Query about the mapping of a single byte at the given virtual address.
     ///
     /// Note that this function may fail reflect an accurate result if there are
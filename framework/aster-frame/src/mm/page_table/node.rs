@@ -26,7 +26,15 @@
 //! the initialization of the entity that the PTE points to. This is taken care in this module.
 //!
 
-use core::{marker::PhantomData, mem::ManuallyDrop, ops::Range, panic, sync::atomic::Ordering};
+use core::{
+    marker::PhantomData,
+    mem::ManuallyDrop,
+    ops::Range,
+    panic,
+    sync::atomic::{AtomicUsize, Ordering},
+};
+
+use pod::Pod;
 
 use super::{nr_subpage_per_huge, page_size, PageTableEntryTrait};
 use crate::{
@@ -38,7 +46,7 @@ use crate::{
             meta::{FrameMeta, PageMeta, PageTablePageMeta, PageUsage},
             Page,
         },
-        page_prop::PageProperty,
+        page_prop::{PageFlags, PageProperty},
         Frame, Paddr, PagingConstsTrait, PagingLevel, PAGE_SIZE,
     },
 };
@@ -434,6 +442,44 @@ where
         }
     }
 
+    /// Clears the given bits, which are set by the MMU, e.g., the accessed and dirty bits,
+    /// of an already mapped child at a given index, returning the property before clearing.
+    ///
+    /// The MMU may set the bits concurrently, so the PTE is updated atomically to avoid
+    /// losing the bits set between the read and the write.
+    pub(super) fn take_flags(&mut self, idx: usize, flags: PageFlags) -> PageProperty {
+        // It should be ensured by the cursor.
+        debug_assert!(idx < nr_subpage_per_huge::<C>());
+        debug_assert_eq!(core::mem::size_of::<E>(), core::mem::size_of::<usize>());
+        // SAFETY: the index is within the bound, and the PTE is aligned and as large as a
+        // `usize`, which is what the MMU updates atomically.
+        let atomic_pte = unsafe { &*(self.as_ptr().add(idx) as *const AtomicUsize) };
+        let mut old_bits = atomic_pte.load(Ordering::Relaxed);
+        loop {
+            let old_pte = E::from_bytes(old_bits.as_bytes());
+            debug_assert!(old_pte.is_present()); // This should be ensured by the cursor.
+            let old_prop = old_pte.prop();
+            if !old_prop.flags.intersects(flags) {
+                return old_prop;
+            }
+
+            let mut new_pte = old_pte;
+            let mut new_prop = old_prop;
+            new_prop.flags -= flags;
+            new_pte.set_prop(new_prop);
+            let new_bits = usize::from_bytes(new_pte.as_bytes());
+            match atomic_pte.compare_exchange_weak(
+                old_bits,
+                new_bits,
+                Ordering::AcqRel,
+                Ordering::Relaxed,
+            ) {
+                Ok(_) => return old_prop,
+                Err(cur_bits) => old_bits = cur_bits,
+            }
+        }
+    }
+
     pub(super) fn read_pte(&self, idx: usize) -> E {
         // It should be ensured by the cursor.
         debug_assert!(idx < nr_subpage_per_huge::<C>());
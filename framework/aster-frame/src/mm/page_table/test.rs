@@ -4,7 +4,7 @@ use core::mem::ManuallyDrop;
 
 use super::*;
 use crate::mm::{
-    kspace::LINEAR_MAPPING_BASE_VADDR,
+    kspace::linear_mapping_base_vaddr,
     page_prop::{CachePolicy, PageFlags},
     FrameAllocOptions,
 };
@@ -16,7 +16,7 @@ fn test_range_check() {
     let pt = PageTable::<UserMode>::empty();
     let good_va = 0..PAGE_SIZE;
     let bad_va = 0..PAGE_SIZE + 1;
-    let bad_va2 = LINEAR_MAPPING_BASE_VADDR..LINEAR_MAPPING_BASE_VADDR + PAGE_SIZE;
+    let bad_va2 = linear_mapping_base_vaddr()..linear_mapping_base_vaddr() + PAGE_SIZE;
     let to = FrameAllocOptions::new(1).alloc().unwrap();
     assert!(pt.cursor_mut(&good_va).is_ok());
     assert!(pt.cursor_mut(&bad_va).is_err());
@@ -40,10 +40,55 @@ fn test_tracked_map_unmap() {
     assert!(pt.query(from.start + 10).is_none());
 }
 
+#[ktest]
+fn test_count_mapped_pages() {
+    let pt = PageTable::<UserMode>::empty();
+
+    let from = PAGE_SIZE..PAGE_SIZE * 2;
+    let frame = FrameAllocOptions::new(1).alloc_single().unwrap();
+    let prop = PageProperty::new(PageFlags::RW, CachePolicy::Writeback);
+    assert!(!unsafe { pt.cursor_mut(&from).unwrap().map(frame.clone(), prop) });
+    assert!(unsafe { pt.cursor_mut(&from).unwrap().map(frame.clone(), prop) });
+    assert_eq!(unsafe { pt.unmap(&(0..PAGE_SIZE * 4)).unwrap() }, 1);
+    assert_eq!(unsafe { pt.unmap(&from).unwrap() }, 0);
+}
+
+#[ktest]
+fn test_move_mappings() {
+    let pt = PageTable::<UserMode>::empty();
+
+    let from = PAGE_SIZE..PAGE_SIZE * 4;
+    let to = PAGE_SIZE * 1024..PAGE_SIZE * 1027;
+    let frame = FrameAllocOptions::new(1).alloc_single().unwrap();
+    let start_paddr = frame.start_paddr();
+    let prop = PageProperty::new(PageFlags::RW, CachePolicy::Writeback);
+    // Leave a gap in the middle of the source range.
+    unsafe { pt.cursor_mut(&from).unwrap().map(frame.clone(), prop) };
+    unsafe {
+        let mut cursor = pt.cursor_mut(&from).unwrap();
+        cursor.jump(from.start + PAGE_SIZE * 2);
+        cursor.map(frame.clone(), prop);
+    }
+
+    let mut cursor = pt.cursor_mut(&(from.start..to.end)).unwrap();
+    assert_eq!(unsafe { cursor.move_to(to.start, from.len()) }, 2);
+    drop(cursor);
+
+    assert!(pt.query(from.start + 10).is_none());
+    assert!(pt.query(from.start + PAGE_SIZE * 2 + 10).is_none());
+    assert_eq!(pt.query(to.start + 10).unwrap().0, start_paddr + 10);
+    assert!(pt.query(to.start + PAGE_SIZE + 10).is_none());
+    assert_eq!(
+        pt.query(to.start + PAGE_SIZE * 2 + 10).unwrap().0,
+        start_paddr + 10
+    );
+    assert_eq!(unsafe { pt.unmap(&to).unwrap() }, 2);
+}
+
 #[ktest]
 fn test_untracked_map_unmap() {
     let pt = PageTable::<KernelMode>::empty();
-    const UNTRACKED_OFFSET: usize = crate::mm::kspace::LINEAR_MAPPING_BASE_VADDR;
+    const UNTRACKED_OFFSET: usize = crate::mm::kspace::BOOT_LINEAR_MAPPING_BASE_VADDR;
 
     let from_ppn = 13245..512 * 512 + 23456;
     let to_ppn = from_ppn.start - 11010..from_ppn.end - 11010;
@@ -85,14 +130,16 @@ fn test_user_copy_on_write() {
     unsafe { pt.cursor_mut(&from).unwrap().map(frame.clone(), prop) };
     assert_eq!(pt.query(from.start + 10).unwrap().0, start_paddr + 10);
 
-    let child_pt = pt.fork_copy_on_write();
+    let (child_pt, protected) = pt.fork_copy_on_write();
+    assert_eq!(protected, [from.clone()]);
     assert_eq!(pt.query(from.start + 10).unwrap().0, start_paddr + 10);
     assert_eq!(child_pt.query(from.start + 10).unwrap().0, start_paddr + 10);
     unsafe { pt.unmap(&from).unwrap() };
     assert!(pt.query(from.start + 10).is_none());
     assert_eq!(child_pt.query(from.start + 10).unwrap().0, start_paddr + 10);
 
-    let sibling_pt = pt.fork_copy_on_write();
+    let (sibling_pt, protected) = pt.fork_copy_on_write();
+    assert!(protected.is_empty());
     assert!(sibling_pt.query(from.start + 10).is_none());
     assert_eq!(child_pt.query(from.start + 10).unwrap().0, start_paddr + 10);
     drop(pt);
@@ -158,6 +205,61 @@ fn test_base_protect_query() {
     }
 }
 
+#[ktest]
+fn test_take_dirty() {
+    let pt = PageTable::<UserMode>::empty();
+
+    let from = PAGE_SIZE..PAGE_SIZE * 5;
+    let to = FrameAllocOptions::new(3).alloc().unwrap();
+    let prop = PageProperty::new(PageFlags::RW, CachePolicy::Writeback);
+    // Leave the last page absent.
+    unsafe {
+        let mut cursor = pt.cursor_mut(&from).unwrap();
+        for frame in to {
+            cursor.map(frame.clone(), prop);
+        }
+    }
+    // Pretend that the MMU has accessed the first two pages and written the second one.
+    unsafe {
+        pt.protect(&(PAGE_SIZE..PAGE_SIZE * 2), |p| {
+            p.flags |= PageFlags::ACCESSED
+        })
+        .unwrap();
+        pt.protect(&(PAGE_SIZE * 2..PAGE_SIZE * 3), |p| {
+            p.flags |= PageFlags::ACCESSED | PageFlags::DIRTY
+        })
+        .unwrap();
+    }
+
+    let dirty = PageFlags::ACCESSED | PageFlags::DIRTY;
+    let taken = unsafe { pt.take_flags(&from, dirty).unwrap() };
+    assert_eq!(
+        taken,
+        [
+            (PAGE_SIZE, PageFlags::ACCESSED),
+            (PAGE_SIZE * 2, PageFlags::ACCESSED | PageFlags::DIRTY),
+        ]
+    );
+    for qr in pt.cursor(&(PAGE_SIZE..PAGE_SIZE * 4)).unwrap() {
+        let Qr::Mapped { prop, .. } = qr else {
+            panic!("Expected Mapped, got {:#x?}", qr);
+        };
+        assert_eq!(prop.flags, PageFlags::RW);
+    }
+    assert!(unsafe { pt.take_flags(&from, dirty).unwrap() }.is_empty());
+
+    // Taking the accessed bits keeps the dirty bits.
+    unsafe {
+        pt.protect(&from, |p| p.flags |= PageFlags::ACCESSED | PageFlags::DIRTY)
+            .unwrap();
+    }
+    let taken = unsafe { pt.take_flags(&from, PageFlags::ACCESSED).unwrap() };
+    assert_eq!(taken.len(), 3);
+    assert!(taken.iter().all(|(_, flags)| *flags == PageFlags::ACCESSED));
+    let taken = unsafe { pt.take_flags(&from, dirty).unwrap() };
+    assert!(taken.iter().all(|(_, flags)| *flags == PageFlags::DIRTY));
+}
+
 #[derive(Clone, Debug, Default)]
 struct VeryHugePagingConsts {}
 
@@ -172,7 +274,7 @@ impl PagingConstsTrait for VeryHugePagingConsts {
 #[ktest]
 fn test_untracked_large_protect_query() {
     let pt = PageTable::<KernelMode, PageTableEntry, VeryHugePagingConsts>::empty();
-    const UNTRACKED_OFFSET: usize = crate::mm::kspace::LINEAR_MAPPING_BASE_VADDR;
+    const UNTRACKED_OFFSET: usize = crate::mm::kspace::BOOT_LINEAR_MAPPING_BASE_VADDR;
 
     let gmult = 512 * 512;
     let from_ppn = gmult - 512..gmult + gmult + 514;
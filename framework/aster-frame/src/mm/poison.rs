@@ -0,0 +1,321 @@
+// SPDX-License-Identifier: MPL-2.0
+
+//! Memory poisoning, which detects use-after-free and out-of-bounds writes.
+//!
+//! It is enabled by the `mm_poison` feature for testing. Each heap object is surrounded
+//! by redzones filled with [`POISON_REDZONE`], which are checked when the object is
+//! freed to detect out-of-bounds writes. The freed heap objects and frames are filled
+//! with [`POISON_FREE`], which is checked before the memory is reused to detect the
+//! writes after free.
+//!
+//! Since freed memory tends to be reused soon, the freed heap objects and frames can be
+//! put in quarantines to delay the reuse. A quarantine holds the most recently freed
+//! ones, and its length is given by the kernel command line option `mm.quarantine`,
+//! e.g., `mm.quarantine=512`. The quarantines are disabled by default.
+
+use core::{
+    alloc::{GlobalAlloc, Layout},
+    ptr,
+    sync::atomic::{AtomicUsize, Ordering},
+};
+
+use super::{heap_allocator::HEAP_ALLOCATOR, paddr_to_vaddr, page::allocator, PAGE_SIZE};
+use crate::{boot::kcmdline::ModuleArg, sync::SpinLock};
+
+/// The pattern that fills the freed memory.
+pub const POISON_FREE: u8 = 0x6b;
+
+/// The pattern that fills the redzones of the heap objects.
+pub const POISON_REDZONE: u8 = 0xcc;
+
+/// The minimum size of a redzone. A redzone is at least as large as the alignment of
+/// the object, so that the object is still aligned.
+const MIN_REDZONE_SIZE: usize = 16;
+
+const HEAP_QUARANTINE_CAPACITY: usize = 1024;
+const FRAME_QUARANTINE_CAPACITY: usize = 256;
+
+/// Returns the maximum number of the heap objects, or the frame ranges, that a
+/// quarantine holds.
+pub fn quarantine_len() -> usize {
+    QUARANTINE_LEN.load(Ordering::Relaxed)
+}
+
+/// Sets the maximum number of the heap objects, or the frame ranges, that a quarantine
+/// holds. The length is capped by the capacity of the quarantine.
+///
+/// The quarantines are flushed, so that the length takes effect at once.
+pub fn set_quarantine_len(len: usize) {
+    QUARANTINE_LEN.store(len, Ordering::Relaxed);
+    flush_quarantine();
+}
+
+/// Releases all the quarantined heap objects and frames for reuse.
+///
+/// # Panics
+///
+/// Panics if any of them is written after being freed.
+pub fn flush_quarantine() {
+    loop {
+        let Some(object) = HEAP_QUARANTINE.lock_irq_disabled().pop() else {
+            break;
+        };
+        // SAFETY: the object is freed and poisoned, and only the quarantine owns it.
+        unsafe { release_heap_object(object) };
+    }
+    loop {
+        let Some((start_index, nframes)) = FRAME_QUARANTINE.lock_irq_disabled().pop() else {
+            break;
+        };
+        check_frames(start_index, nframes);
+        // SAFETY: the frames are freed and poisoned, and only the quarantine owns them.
+        unsafe { allocator::free_frames(start_index, nframes) };
+    }
+}
+
+pub(super) fn init() {
+    let Some(args) = crate::boot::kernel_cmdline().get_module_args("mm") else {
+        return;
+    };
+    for arg in args {
+        if let ModuleArg::KeyVal(key, value) = arg
+            && key.as_bytes() == b"quarantine"
+        {
+            match value.to_str().ok().and_then(|len| len.parse().ok()) {
+                Some(len) => set_quarantine_len(len),
+                None => log::warn!("invalid quarantine length {:?}", value),
+            }
+        }
+    }
+}
+
+/// Poisons the freed frames and puts them in the quarantine.
+///
+/// Returns the frames that leave the quarantine, which are ready to be reused.
+///
+/// # Safety
+///
+/// The frames must be freed and must not be used by others.
+pub(super) unsafe fn quarantine_frames(
+    start_index: usize,
+    nframes: usize,
+) -> Option<(usize, usize)> {
+    let ptr = paddr_to_vaddr(start_index * PAGE_SIZE) as *mut u8;
+    ptr.write_bytes(POISON_FREE, nframes * PAGE_SIZE);
+
+    let evicted = FRAME_QUARANTINE
+        .lock_irq_disabled()
+        .push((start_index, nframes), quarantine_len())?;
+    check_frames(evicted.0, evicted.1);
+    Some(evicted)
+}
+
+static QUARANTINE_LEN: AtomicUsize = AtomicUsize::new(0);
+
+/// The quarantined heap objects, as the addresses and the layouts.
+static HEAP_QUARANTINE: SpinLock<Quarantine<(usize, Layout), HEAP_QUARANTINE_CAPACITY>> =
+    SpinLock::new(Quarantine::new());
+
+/// The quarantined frames, as the indexes of the first frames and the numbers of frames.
+static FRAME_QUARANTINE: SpinLock<Quarantine<(usize, usize), FRAME_QUARANTINE_CAPACITY>> =
+    SpinLock::new(Quarantine::new());
+
+#[global_allocator]
+static POISONED_HEAP_ALLOCATOR: PoisonedHeap = PoisonedHeap;
+
+/// The kernel heap that surrounds the objects with redzones and poisons the freed ones.
+struct PoisonedHeap;
+
+unsafe impl GlobalAlloc for PoisonedHeap {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        let Some((outer_layout, redzone_size)) = with_redzones(layout) else {
+            return ptr::null_mut();
+        };
+        let outer_ptr = HEAP_ALLOCATOR.alloc(outer_layout);
+        if outer_ptr.is_null() {
+            return outer_ptr;
+        }
+
+        let ptr = outer_ptr.add(redzone_size);
+        outer_ptr.write_bytes(POISON_REDZONE, redzone_size);
+        ptr.add(layout.size())
+            .write_bytes(POISON_REDZONE, redzone_size);
+        ptr
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        let (_, redzone_size) = with_redzones(layout).unwrap();
+        let redzones = [
+            ("before", ptr.sub(redzone_size)),
+            ("after", ptr.add(layout.size())),
+        ];
+        for (side, redzone) in redzones {
+            if let Some(offset) = find_mismatch(redzone, redzone_size, POISON_REDZONE) {
+                panic!(
+                    "out-of-bounds write {} the heap object at {:p} ({:?}): redzone corrupted at offset {}",
+                    side, ptr, layout, offset
+                );
+            }
+        }
+
+        ptr.write_bytes(POISON_FREE, layout.size());
+        let evicted = HEAP_QUARANTINE
+            .lock_irq_disabled()
+            .push((ptr as usize, layout), quarantine_len());
+        if let Some(object) = evicted {
+            release_heap_object(object);
+        }
+    }
+}
+
+/// Returns the layout of a heap object with its redzones, and the size of a redzone.
+fn with_redzones(layout: Layout) -> Option<(Layout, usize)> {
+    let redzone_size = layout.align().max(MIN_REDZONE_SIZE);
+    let size = layout.size().checked_add(2 * redzone_size)?;
+    let outer_layout = Layout::from_size_align(size, layout.align()).ok()?;
+    Some((outer_layout, redzone_size))
+}
+
+/// Checks the poison of a freed heap object, and returns it to the heap.
+///
+/// # Safety
+///
+/// The object must be freed and poisoned, and must not be used by others.
+unsafe fn release_heap_object((addr, layout): (usize, Layout)) {
+    let ptr = addr as *mut u8;
+    if let Some(offset) = find_mismatch(ptr, layout.size(), POISON_FREE) {
+        panic!(
+            "use after free of the heap object at {:p} ({:?}): written at offset {}",
+            ptr, layout, offset
+        );
+    }
+    let (outer_layout, redzone_size) = with_redzones(layout).unwrap();
+    HEAP_ALLOCATOR.dealloc(ptr.sub(redzone_size), outer_layout);
+}
+
+fn check_frames(start_index: usize, nframes: usize) {
+    let ptr = paddr_to_vaddr(start_index * PAGE_SIZE) as *const u8;
+    // SAFETY: the frames are poisoned and owned by the quarantine.
+    if let Some(offset) = unsafe { find_mismatch(ptr, nframes * PAGE_SIZE, POISON_FREE) } {
+        panic!(
+            "use after free of the frames at {:#x}: written at offset {:#x}",
+            start_index * PAGE_SIZE,
+            offset
+        );
+    }
+}
+
+/// Returns the offset of the first byte that differs from the pattern.
+///
+/// # Safety
+///
+/// The memory range must be valid for reads.
+unsafe fn find_mismatch(ptr: *const u8, len: usize, pattern: u8) -> Option<usize> {
+    core::slice::from_raw_parts(ptr, len)
+        .iter()
+        .position(|byte| *byte != pattern)
+}
+
+/// A FIFO queue of the freed memory whose reuse is delayed.
+///
+/// The entries are stored in place, since the queue is used by the heap allocator.
+struct Quarantine<T: Copy, const N: usize> {
+    entries: [Option<T>; N],
+    head: usize,
+    len: usize,
+}
+
+impl<T: Copy, const N: usize> Quarantine<T, N> {
+    const fn new() -> Self {
+        Self {
+            entries: [None; N],
+            head: 0,
+            len: 0,
+        }
+    }
+
+    /// Pushes an entry, and pops the oldest entry if more than `limit` entries are
+    /// held. The entry itself is returned if `limit` is zero.
+    fn push(&mut self, entry: T, limit: usize) -> Option<T> {
+        let limit = limit.min(N);
+        if limit == 0 {
+            return Some(entry);
+        }
+        let evicted = if self.len >= limit { self.pop() } else { None };
+        self.entries[(self.head + self.len) % N] = Some(entry);
+        self.len += 1;
+        evicted
+    }
+
+    fn pop(&mut self) -> Option<T> {
+        if self.len == 0 {
+            return None;
+        }
+        let entry = self.entries[self.head].take();
+        self.head = (self.head + 1) % N;
+        self.len -= 1;
+        entry
+    }
+}
+
+#[cfg(ktest)]
+mod test {
+    use alloc::boxed::Box;
+
+    use super::*;
+    use crate::mm::FrameAllocOptions;
+
+    #[ktest]
+    fn quarantine_evicts_oldest() {
+        let mut quarantine = Quarantine::<usize, 4>::new();
+        assert_eq!(quarantine.push(1, 0), Some(1));
+        assert_eq!(quarantine.push(1, 2), None);
+        assert_eq!(quarantine.push(2, 2), None);
+        assert_eq!(quarantine.push(3, 2), Some(1));
+        // The limit is capped by the capacity.
+        for i in 4..6 {
+            assert_eq!(quarantine.push(i, 8), None);
+        }
+        assert_eq!(quarantine.push(6, 8), Some(2));
+        assert_eq!(quarantine.pop(), Some(3));
+    }
+
+    #[ktest]
+    fn heap_object_poisoned() {
+        let old_len = quarantine_len();
+        set_quarantine_len(HEAP_QUARANTINE_CAPACITY);
+
+        let object = Box::new([0x5au8; 24]);
+        let ptr = &*object as *const [u8; 24] as *const u8;
+        // SAFETY: the redzones are allocated along with the object.
+        unsafe {
+            assert_eq!(
+                find_mismatch(ptr.sub(MIN_REDZONE_SIZE), MIN_REDZONE_SIZE, POISON_REDZONE),
+                None
+            );
+            assert_eq!(
+                find_mismatch(ptr.add(24), MIN_REDZONE_SIZE, POISON_REDZONE),
+                None
+            );
+        }
+        drop(object);
+        // SAFETY: the object is held by the quarantine, so it is not reused.
+        assert_eq!(unsafe { find_mismatch(ptr, 24, POISON_FREE) }, None);
+
+        set_quarantine_len(old_len);
+    }
+
+    #[ktest]
+    fn frame_poisoned() {
+        let old_len = quarantine_len();
+        set_quarantine_len(FRAME_QUARANTINE_CAPACITY);
+
+        let frame = FrameAllocOptions::new(1).alloc_single().unwrap();
+        let ptr = paddr_to_vaddr(frame.start_paddr()) as *const u8;
+        drop(frame);
+        // SAFETY: the frame is held by the quarantine, so it is not reused.
+        assert_eq!(unsafe { find_mismatch(ptr, PAGE_SIZE, POISON_FREE) }, None);
+
+        set_quarantine_len(old_len);
+    }
+}
@@ -0,0 +1,193 @@
+// SPDX-License-Identifier: MPL-2.0
+
+//! Memory reclamation under memory pressure.
+//!
+//! Subsystems that cache memory which can be dropped or written back on demand,
+//! e.g., the page cache, can register [`Shrinker`]s. The shrinkers are invoked
+//! when an allocation that is allowed to sleep (see
+//! [`FrameAllocOptions::alloc_wait`]) fails, or by a background reclaimer that
+//! waits for the number of free frames to fall below the low watermark (see
+//! [`wait_for_low_memory`]).
+//!
+//! If the shrinkers cannot release enough frames for an allocation, the
+//! out-of-memory (OOM) handler registered by the kernel decides whether some
+//! memory will be freed, e.g., by killing a process (see [`register_oom_handler`]).
+//!
+//! [`FrameAllocOptions::alloc_wait`]: crate::mm::FrameAllocOptions::alloc_wait
+
+use alloc::{sync::Weak, vec::Vec};
+use core::sync::atomic::{AtomicBool, Ordering};
+
+use spin::Once;
+
+use super::page::allocator;
+use crate::sync::{SpinLock, WaitQueue};
+
+/// A memory consumer that can release frames on request.
+pub trait Shrinker: Send + Sync {
+    /// Returns the number of frames that can possibly be reclaimed.
+    fn nr_reclaimable(&self) -> usize;
+
+    /// Tries to release `nr_to_reclaim` frames.
+    ///
+    /// Returns the number of frames that have actually been released.
+    /// The method may sleep.
+    fn shrink(&self, nr_to_reclaim: usize) -> usize;
+}
+
+static SHRINKERS: SpinLock<Vec<Weak<dyn Shrinker>>> = SpinLock::new(Vec::new());
+
+/// Registers a shrinker.
+///
+/// The shrinker is unregistered automatically once it is dropped.
+pub fn register_shrinker(shrinker: Weak<dyn Shrinker>) {
+    let mut shrinkers = SHRINKERS.lock_irq_disabled();
+    shrinkers.retain(|shrinker| shrinker.strong_count() > 0);
+    shrinkers.push(shrinker);
+}
+
+/// Invokes the registered shrinkers until `nr_to_reclaim` frames are released
+/// or no shrinker can release more.
+///
+/// Returns the number of frames that have been released. The function may sleep.
+pub fn reclaim(nr_to_reclaim: usize) -> usize {
+    let shrinkers: Vec<_> = SHRINKERS
+        .lock_irq_disabled()
+        .iter()
+        .filter_map(Weak::upgrade)
+        .collect();
+
+    let mut nr_reclaimed = 0;
+    for shrinker in shrinkers {
+        if nr_reclaimed >= nr_to_reclaim {
+            break;
+        }
+        if shrinker.nr_reclaimable() == 0 {
+            continue;
+        }
+        nr_reclaimed += shrinker.shrink(nr_to_reclaim - nr_reclaimed);
+    }
+    nr_reclaimed
+}
+
+/// The decision of the OOM handler.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OomVerdict {
+    /// Some frames may have been freed, e.g., by a killed process that has exited.
+    ///
+    /// The allocation is retried. The handler should wait for a while before returning
+    /// this verdict, since it is invoked again if the allocation still fails.
+    Retry,
+    /// The allocating task is killed.
+    ///
+    /// The allocation fails, so that the task can exit and free its memory.
+    CurrentKilled,
+    /// No frames can be freed.
+    ///
+    /// The allocation fails.
+    NoVictim,
+}
+
+/// The OOM handler, which is given the number of frames to allocate.
+pub type OomHandler = dyn Fn(usize) -> OomVerdict + Send + Sync + 'static;
+
+static OOM_HANDLER: Once<&'static OomHandler> = Once::new();
+
+/// Registers the OOM handler.
+///
+/// The handler is invoked when an allocation that is allowed to sleep fails and
+/// the shrinkers cannot release enough frames. Only the first registered handler
+/// takes effect.
+pub fn register_oom_handler(handler: &'static OomHandler) {
+    OOM_HANDLER.call_once(|| handler);
+}
+
+/// Invokes the OOM handler for an allocation of `nframes` frames.
+///
+/// Without a handler, no frames can be freed.
+pub(super) fn out_of_memory(nframes: usize) -> OomVerdict {
+    OOM_HANDLER
+        .get()
+        .map_or(OomVerdict::NoVictim, |handler| handler(nframes))
+}
+
+/// The number of free frames below which the system is considered low on memory.
+pub(super) fn low_watermark(nr_total_frames: usize) -> usize {
+    // Keep 1/64 of the memory free, but no less than 256 frames.
+    (nr_total_frames / 64).max(256)
+}
+
+/// The number of free frames that the background reclaimer tries to reach.
+pub fn high_watermark() -> usize {
+    low_watermark(allocator::nr_total_frames()) * 2
+}
+
+/// Returns whether the number of free frames is below the low watermark.
+pub fn is_low_on_memory() -> bool {
+    allocator::nr_free_frames() < low_watermark(allocator::nr_total_frames())
+}
+
+static LOW_MEMORY: AtomicBool = AtomicBool::new(false);
+static LOW_MEMORY_WAIT_QUEUE: WaitQueue = WaitQueue::new();
+
+/// Waits until the number of free frames falls below the low watermark.
+///
+/// This is intended for a background reclaimer, which should call [`reclaim`]
+/// after this function returns.
+pub fn wait_for_low_memory() {
+    LOW_MEMORY_WAIT_QUEUE.wait_until(|| {
+        if LOW_MEMORY.swap(false, Ordering::Relaxed) {
+            Some(())
+        } else {
+            None
+        }
+    })
+}
+
+/// Called by the frame allocator when an allocation fails or the number of
+/// free frames falls below the low watermark.
+pub(super) fn notify_low_memory() {
+    // Do not wake the reclaimer over and over again while it is still working.
+    if !LOW_MEMORY.swap(true, Ordering::Relaxed) {
+        LOW_MEMORY_WAIT_QUEUE.wake_all();
+    }
+}
+
+#[cfg(ktest)]
+mod test {
+    use alloc::sync::Arc;
+    use core::sync::atomic::AtomicUsize;
+
+    use super::*;
+
+    struct CountingShrinker {
+        nr_shrunk: AtomicUsize,
+    }
+
+    impl Shrinker for CountingShrinker {
+        fn nr_reclaimable(&self) -> usize {
+            usize::MAX
+        }
+
+        fn shrink(&self, nr_to_reclaim: usize) -> usize {
+            self.nr_shrunk.fetch_add(nr_to_reclaim, Ordering::Relaxed);
+            nr_to_reclaim
+        }
+    }
+
+    #[ktest]
+    fn register_and_drop_shrinker() {
+        let shrinker = Arc::new(CountingShrinker {
+            nr_shrunk: AtomicUsize::new(0),
+        });
+        register_shrinker(Arc::downgrade(&shrinker) as _);
+
+        assert!(reclaim(16) >= 16);
+        assert!(shrinker.nr_shrunk.load(Ordering::Relaxed) > 0);
+
+        let weak = Arc::downgrade(&shrinker);
+        drop(shrinker);
+        reclaim(16);
+        assert!(weak.upgrade().is_none());
+    }
+}
@@ -1,6 +1,9 @@
 // SPDX-License-Identifier: MPL-2.0
 
-use core::ops::Range;
+use core::{
+    ops::Range,
+    sync::atomic::{AtomicUsize, Ordering},
+};
 
 use super::{
     is_page_aligned,
@@ -11,7 +14,8 @@ use super::{
 };
 use crate::{
     arch::mm::{
-        tlb_flush_addr_range, tlb_flush_all_excluding_global, PageTableEntry, PagingConsts,
+        tlb_flush_addr_range, tlb_shootdown_addr_range, tlb_shootdown_addrs,
+        tlb_shootdown_all_excluding_global, PageTableEntry, PagingConsts,
     },
     mm::{
         page_table::{Cursor, PageTableQueryResult as PtQr},
@@ -21,6 +25,10 @@ use crate::{
     Error,
 };
 
+/// The number of pages above which flushing the whole TLB is cheaper than
+/// flushing the TLB entries of the pages one by one.
+const TLB_FLUSH_ALL_THRESHOLD: usize = 32;
+
 /// Virtual memory space.
 ///
 /// A virtual memory space (`VmSpace`) can be created and assigned to a user space so that
@@ -35,6 +43,8 @@ use crate::{
 #[derive(Debug)]
 pub struct VmSpace {
     pt: PageTable<UserMode>,
+    /// The number of pages that are mapped, i.e., the resident set size in pages.
+    nr_mapped_pages: AtomicUsize,
 }
 
 // Notes on TLB flushing:
@@ -43,17 +53,25 @@ pub struct VmSpace {
 // 1. `VmSpace` _might_ be activated on the current CPU and the user memory _might_ be used
 //    immediately after we make changes to the page table entries. So we must invalidate the
 //    corresponding TLB caches accordingly.
-// 2. `VmSpace` must _not_ be activated on another CPU. This assumption is trivial, since SMP
-//    support is not yet available. But we need to consider this situation in the future (TODO).
+// 2. `VmSpace` _might_ be activated on other CPUs as well, since the threads of a process may
+//    run on different CPUs. So the TLB entries are shot down from all the CPUs whenever a
+//    mapping is removed, moved, or has its permissions reduced. Only a mapping that is newly
+//    created in an empty slot is flushed locally, since no TLB caches the absent entry.
 
 impl VmSpace {
     /// Creates a new VM address space.
     pub fn new() -> Self {
         Self {
             pt: KERNEL_PAGE_TABLE.get().unwrap().create_user_page_table(),
+            nr_mapped_pages: AtomicUsize::new(0),
         }
     }
 
+    /// Returns the number of pages that are mapped in the VM space.
+    pub fn nr_mapped_pages(&self) -> usize {
+        self.nr_mapped_pages.load(Ordering::Relaxed)
+    }
+
     /// Activates the page table.
     pub(crate) fn activate(&self) {
         self.pt.activate();
@@ -98,19 +116,28 @@ impl VmSpace {
 
         let prop = PageProperty {
             flags: options.flags,
-            cache: CachePolicy::Writeback,
+            cache: options.cache,
             priv_flags: PrivilegedPageFlags::USER,
+            pkey: options.pkey,
         };
 
+        let nr_frames = frames.len();
+        let mut nr_mapped = 0;
         for frame in frames.into_iter() {
             // SAFETY: mapping in the user space with `Frame` is safe.
-            unsafe {
-                cursor.map(frame, prop);
+            let is_replaced = unsafe { cursor.map(frame, prop) };
+            if !is_replaced {
+                nr_mapped += 1;
             }
         }
 
         drop(cursor);
-        tlb_flush_addr_range(&va_range);
+        self.nr_mapped_pages.fetch_add(nr_mapped, Ordering::Relaxed);
+        if nr_mapped < nr_frames {
+            tlb_shootdown_addr_range(&va_range);
+        } else {
+            tlb_flush_addr_range(&va_range);
+        }
 
         Ok(addr)
     }
@@ -139,8 +166,8 @@ impl VmSpace {
     /// Unmaps the physical memory pages within the VM address range.
     ///
     /// The range is allowed to contain gaps, where no physical memory pages
-    /// are mapped.
-    pub fn unmap(&self, range: &Range<Vaddr>) -> Result<()> {
+    /// are mapped. Returns the number of pages that are unmapped.
+    pub fn unmap(&self, range: &Range<Vaddr>) -> Result<usize> {
         if !is_page_aligned(range.start) || !is_page_aligned(range.end) {
             return Err(Error::InvalidArgs);
         }
@@ -149,10 +176,53 @@ impl VmSpace {
         }
 
         // SAFETY: unmapping in the user space is safe.
-        unsafe {
-            self.pt.unmap(range)?;
+        let nr_unmapped = unsafe { self.pt.unmap(range)? };
+        self.nr_mapped_pages
+            .fetch_sub(nr_unmapped, Ordering::Relaxed);
+        tlb_shootdown_addr_range(range);
+
+        Ok(nr_unmapped)
+    }
+
+    /// Moves the physical memory pages mapped within the VM address range to
+    /// the range starting from `new_addr`.
+    ///
+    /// The page table entries are moved, so the contents of the pages are not
+    /// copied. Any existing mappings in the destination range are unmapped
+    /// first. The two ranges must not overlap.
+    pub fn remap(&self, range: &Range<Vaddr>, new_addr: Vaddr) -> Result<()> {
+        if !is_page_aligned(range.start) || !is_page_aligned(range.end) {
+            return Err(Error::InvalidArgs);
         }
-        tlb_flush_addr_range(range);
+        if !is_page_aligned(new_addr) {
+            return Err(Error::InvalidArgs);
+        }
+        let new_end = new_addr
+            .checked_add(range.len())
+            .ok_or(Error::InvalidArgs)?;
+        let new_range = new_addr..new_end;
+        if !UserMode::covers(range) || !UserMode::covers(&new_range) {
+            return Err(Error::InvalidArgs);
+        }
+        if range.start < new_range.end && new_range.start < range.end {
+            return Err(Error::InvalidArgs);
+        }
+        if range.is_empty() {
+            return Ok(());
+        }
+
+        self.unmap(&new_range)?;
+
+        // A single cursor covering both ranges is used to follow the page table lock protocol.
+        let covering_range = range.start.min(new_range.start)..range.end.max(new_range.end);
+        let mut cursor = self.pt.cursor_mut(&covering_range)?;
+        cursor.jump(range.start);
+        // SAFETY: moving mappings in the user space is safe.
+        unsafe { cursor.move_to(new_range.start, range.len()) };
+        drop(cursor);
+
+        tlb_shootdown_addr_range(range);
+        tlb_shootdown_addr_range(&new_range);
 
         Ok(())
     }
@@ -164,7 +234,8 @@ impl VmSpace {
         unsafe {
             self.pt.unmap(&(0..MAX_USERSPACE_VADDR)).unwrap();
         }
-        tlb_flush_all_excluding_global();
+        self.nr_mapped_pages.store(0, Ordering::Relaxed);
+        tlb_shootdown_all_excluding_global();
     }
 
     /// Updates the VM protection permissions within the VM address range.
@@ -191,21 +262,80 @@ impl VmSpace {
         unsafe {
             self.pt.protect(range, op)?;
         }
-        tlb_flush_addr_range(range);
+        tlb_shootdown_addr_range(range);
 
         Ok(())
     }
 
+    /// Takes the accessed and dirty bits of the mapped pages in the range.
+    ///
+    /// The bits are read and cleared atomically, and the TLB entries of the pages
+    /// whose bits are cleared are flushed, so that the following accesses will set
+    /// the bits again. It returns the starting addresses of the pages that were accessed
+    /// or dirtied since the last time, along with which of the two bits were set.
+    ///
+    /// This allows tracking the dirty pages and the working set of the VM space
+    /// without unmapping or write-protecting the pages.
+    pub fn take_dirty(&self, range: &Range<Vaddr>) -> Result<Vec<(Vaddr, PageFlags)>> {
+        self.take_flags(range, PageFlags::ACCESSED | PageFlags::DIRTY)
+    }
+
+    /// Takes the accessed bits of the mapped pages in the range.
+    ///
+    /// Unlike [`Self::take_dirty`], the dirty bits are kept. It returns the starting
+    /// addresses of the pages that were accessed since the last time, which allows
+    /// sampling the working set without affecting the tracking of the dirty pages.
+    pub fn take_accessed(&self, range: &Range<Vaddr>) -> Result<Vec<Vaddr>> {
+        let taken = self.take_flags(range, PageFlags::ACCESSED)?;
+        Ok(taken.into_iter().map(|(va, _)| va).collect())
+    }
+
+    fn take_flags(
+        &self,
+        range: &Range<Vaddr>,
+        flags: PageFlags,
+    ) -> Result<Vec<(Vaddr, PageFlags)>> {
+        if !is_page_aligned(range.start) || !is_page_aligned(range.end) {
+            return Err(Error::InvalidArgs);
+        }
+        if !UserMode::covers(range) {
+            return Err(Error::InvalidArgs);
+        }
+
+        // SAFETY: harvesting the bits in the user space is safe.
+        let taken = unsafe { self.pt.take_flags(range, flags)? };
+        // Only the pages whose bits are cleared may have stale TLB entries.
+        if taken.len() > TLB_FLUSH_ALL_THRESHOLD {
+            tlb_shootdown_all_excluding_global();
+        } else if !taken.is_empty() {
+            tlb_shootdown_addrs(taken.iter().map(|(va, _)| *va));
+        }
+
+        Ok(taken)
+    }
+
     /// Forks a new VM space with copy-on-write semantics.
     ///
     /// Both the parent and the newly forked VM space will be marked as
     /// read-only. And both the VM space will take handles to the same
     /// physical memory pages.
+    ///
+    /// Only the TLB entries of the mappings that were writable are flushed,
+    /// unless there are too many of them.
     pub fn fork_copy_on_write(&self) -> Self {
+        let (pt, protected) = self.pt.fork_copy_on_write();
         let new_space = Self {
-            pt: self.pt.fork_copy_on_write(),
+            pt,
+            nr_mapped_pages: AtomicUsize::new(self.nr_mapped_pages()),
         };
-        tlb_flush_all_excluding_global();
+        let nr_protected_pages: usize = protected.iter().map(|range| range.len() / PAGE_SIZE).sum();
+        if nr_protected_pages > TLB_FLUSH_ALL_THRESHOLD {
+            tlb_shootdown_all_excluding_global();
+        } else {
+            for range in protected.iter() {
+                tlb_shootdown_addr_range(range);
+            }
+        }
         new_space
     }
 }
@@ -228,6 +358,10 @@ pub struct VmMapOptions {
     flags: PageFlags,
     /// Can overwrite
     can_overwrite: bool,
+    /// Protection key
+    pkey: u8,
+    /// Cache policy
+    cache: CachePolicy,
 }
 
 impl VmMapOptions {
@@ -238,6 +372,8 @@ impl VmMapOptions {
             align: PagingConsts::BASE_PAGE_SIZE,
             flags: PageFlags::empty(),
             can_overwrite: false,
+            pkey: 0,
+            cache: CachePolicy::Writeback,
         }
     }
 
@@ -261,6 +397,24 @@ impl VmMapOptions {
         self
     }
 
+    /// Sets the protection key of the mapping, which further restricts the
+    /// accesses to the mapping according to the PKRU register.
+    ///
+    /// The default value of this option is zero.
+    pub fn pkey(&mut self, pkey: u8) -> &mut Self {
+        self.pkey = pkey;
+        self
+    }
+
+    /// Sets the cache policy of the mapping, e.g., the write-combining policy for
+    /// the memory that is accessed by a device.
+    ///
+    /// The default value of this option is `CachePolicy::Writeback`.
+    pub fn cache(&mut self, cache: CachePolicy) -> &mut Self {
+        self.cache = cache;
+        self
+    }
+
     /// Sets the address of the new mapping.
     ///
     /// The default value of this option is `None`.
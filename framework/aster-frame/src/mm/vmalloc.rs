@@ -0,0 +1,274 @@
+// SPDX-License-Identifier: MPL-2.0
+
+//! Virtually contiguous kernel memory.
+//!
+//! Large buffers, e.g., multi-megabyte buffers of drivers, can hardly be served by
+//! contiguous frames. Instead, [`vmalloc`] allocates scattered frames and maps them to
+//! a virtually contiguous area in the vm alloc region of the kernel space (see
+//! [`crate::mm::kspace`]). [`vmap`] maps given frames in the same way.
+//!
+//! Each area is surrounded by unmapped guard pages, so that overflowing an area
+//! triggers a page fault rather than corrupting another area. An area is unmapped
+//! once it is dropped, or explicitly with [`vunmap`].
+
+use alloc::collections::BTreeMap;
+use core::ops::Range;
+
+use align_ext::AlignExt;
+
+use super::{
+    kspace::{KERNEL_PAGE_TABLE, VMALLOC_VADDR_RANGE},
+    CachePolicy, Frame, FrameAllocOptions, PageFlags, PageProperty, PrivilegedPageFlags, Vaddr,
+    VmIo, VmReader, VmWriter, PAGE_SIZE,
+};
+use crate::{arch::mm::tlb_shootdown_addr_range, sync::SpinLock, Error, Result};
+
+/// The number of guard pages on each side of an area.
+const NR_GUARD_PAGES: usize = 1;
+
+/// A virtually contiguous area in the kernel space.
+///
+/// The mapped frames are kept alive by the kernel page table until the area is dropped.
+#[derive(Debug)]
+pub struct VmallocArea {
+    /// The mapped range, excluding the guard pages.
+    range: Range<Vaddr>,
+}
+
+/// Allocates a virtually contiguous area of at least `size` bytes.
+///
+/// The area is zeroed, and its size is rounded up to a multiple of the page size.
+///
+/// # Errors
+///
+/// Returns [`Error::InvalidArgs`] if `size` is zero, and [`Error::NoMemory`] if there
+/// are not enough frames or virtual addresses.
+pub fn vmalloc(size: usize) -> Result<VmallocArea> {
+    if size == 0 {
+        return Err(Error::InvalidArgs);
+    }
+    let nframes = size.align_up(PAGE_SIZE) / PAGE_SIZE;
+    let frames = FrameAllocOptions::new(nframes).alloc()?;
+    let area = VmallocArea::new(nframes * PAGE_SIZE)?;
+    // SAFETY: the area is newly allocated, so nothing else uses the range.
+    unsafe { area.map_frames(frames.iter(), CachePolicy::Writeback) };
+    Ok(area)
+}
+
+/// Maps the frames to a virtually contiguous area in order.
+///
+/// # Errors
+///
+/// Returns [`Error::InvalidArgs`] if `frames` is empty, and [`Error::NoMemory`] if
+/// there are not enough virtual addresses.
+pub fn vmap(frames: &[Frame], cache: CachePolicy) -> Result<VmallocArea> {
+    if frames.is_empty() {
+        return Err(Error::InvalidArgs);
+    }
+    let area = VmallocArea::new(frames.len() * PAGE_SIZE)?;
+    // SAFETY: the area is newly allocated, so nothing else uses the range.
+    unsafe { area.map_frames(frames.iter(), cache) };
+    Ok(area)
+}
+
+/// Unmaps the area, which is the same as dropping it.
+pub fn vunmap(area: VmallocArea) {
+    drop(area);
+}
+
+impl VmallocArea {
+    fn new(size: usize) -> Result<Self> {
+        let guard_size = NR_GUARD_PAGES * PAGE_SIZE;
+        let va = VA_ALLOCATOR.lock().alloc(size + 2 * guard_size)?;
+        Ok(Self {
+            range: va.start + guard_size..va.end - guard_size,
+        })
+    }
+
+    /// Maps the frames to the area from its start.
+    ///
+    /// # Safety
+    ///
+    /// The area must not be used by others.
+    unsafe fn map_frames<'a>(&self, frames: impl Iterator<Item = &'a Frame>, cache: CachePolicy) {
+        let prop = PageProperty {
+            flags: PageFlags::RW,
+            cache,
+            priv_flags: PrivilegedPageFlags::GLOBAL,
+            pkey: 0,
+        };
+        let mut cursor = KERNEL_PAGE_TABLE
+            .get()
+            .unwrap()
+            .cursor_mut(&self.range)
+            .unwrap();
+        for frame in frames {
+            // SAFETY: the range is in the vm alloc region, which only contains the areas.
+            unsafe { cursor.map(frame.clone(), prop) };
+        }
+    }
+
+    /// Returns the start virtual address of the area.
+    pub fn start_vaddr(&self) -> Vaddr {
+        self.range.start
+    }
+
+    /// Returns the size of the area in bytes.
+    pub fn size(&self) -> usize {
+        self.range.len()
+    }
+
+    /// Returns a raw pointer to the start of the area.
+    pub fn as_ptr(&self) -> *const u8 {
+        self.range.start as *const u8
+    }
+
+    /// Returns a mutable raw pointer to the start of the area.
+    pub fn as_mut_ptr(&self) -> *mut u8 {
+        self.range.start as *mut u8
+    }
+}
+
+impl<'a> VmallocArea {
+    /// Returns a reader to read data from it.
+    pub fn reader(&'a self) -> VmReader<'a> {
+        // SAFETY: the memory of the area is mapped and is valid during `'a`.
+        unsafe { VmReader::from_raw_parts(self.as_ptr(), self.size()) }
+    }
+
+    /// Returns a writer to write data into it.
+    pub fn writer(&'a self) -> VmWriter<'a> {
+        // SAFETY: the memory of the area is mapped and is valid during `'a`.
+        unsafe { VmWriter::from_raw_parts_mut(self.as_mut_ptr(), self.size()) }
+    }
+}
+
+impl VmIo for VmallocArea {
+    fn read_bytes(&self, offset: usize, buf: &mut [u8]) -> Result<()> {
+        // Do bound check with potential integer overflow in mind
+        let max_offset = offset.checked_add(buf.len()).ok_or(Error::Overflow)?;
+        if max_offset > self.size() {
+            return Err(Error::InvalidArgs);
+        }
+        let len = self.reader().skip(offset).read(&mut buf.into());
+        debug_assert!(len == buf.len());
+        Ok(())
+    }
+
+    fn write_bytes(&self, offset: usize, buf: &[u8]) -> Result<()> {
+        // Do bound check with potential integer overflow in mind
+        let max_offset = offset.checked_add(buf.len()).ok_or(Error::Overflow)?;
+        if max_offset > self.size() {
+            return Err(Error::InvalidArgs);
+        }
+        let len = self.writer().skip(offset).write(&mut buf.into());
+        debug_assert!(len == buf.len());
+        Ok(())
+    }
+}
+
+impl Drop for VmallocArea {
+    fn drop(&mut self) {
+        let mut cursor = KERNEL_PAGE_TABLE
+            .get()
+            .unwrap()
+            .cursor_mut(&self.range)
+            .unwrap();
+        // SAFETY: the area is owned by this handle, and the handle is being dropped.
+        unsafe { cursor.unmap(self.range.len()) };
+        drop(cursor);
+        tlb_shootdown_addr_range(&self.range);
+
+        let guard_size = NR_GUARD_PAGES * PAGE_SIZE;
+        VA_ALLOCATOR.lock().free(self.range.start - guard_size);
+    }
+}
+
+static VA_ALLOCATOR: SpinLock<VaAllocator> = SpinLock::new(VaAllocator {
+    allocated: BTreeMap::new(),
+});
+
+/// The allocator of the virtual addresses in the vm alloc region.
+struct VaAllocator {
+    /// The start of each allocated range, mapped to the end.
+    allocated: BTreeMap<Vaddr, Vaddr>,
+}
+
+impl VaAllocator {
+    /// Allocates a range with the first fit strategy.
+    fn alloc(&mut self, size: usize) -> Result<Range<Vaddr>> {
+        let mut start = VMALLOC_VADDR_RANGE.start;
+        for (allocated_start, allocated_end) in self.allocated.iter() {
+            if allocated_start - start >= size {
+                break;
+            }
+            start = *allocated_end;
+        }
+        if VMALLOC_VADDR_RANGE.end - start < size {
+            return Err(Error::NoMemory);
+        }
+        self.allocated.insert(start, start + size);
+        Ok(start..start + size)
+    }
+
+    fn free(&mut self, start: Vaddr) {
+        self.allocated.remove(&start);
+    }
+}
+
+#[cfg(ktest)]
+mod test {
+    use super::*;
+
+    #[ktest]
+    fn vmalloc_read_write() {
+        let area = vmalloc(3 * PAGE_SIZE - 1).unwrap();
+        assert_eq!(area.size(), 3 * PAGE_SIZE);
+        assert!(VMALLOC_VADDR_RANGE.contains(&area.start_vaddr()));
+
+        // The area is zeroed and can be accessed across pages.
+        assert_eq!(area.read_val::<u64>(PAGE_SIZE - 4).unwrap(), 0);
+        area.write_val(PAGE_SIZE - 4, &0x1234_5678_9abc_def0u64)
+            .unwrap();
+        assert_eq!(
+            area.read_val::<u64>(PAGE_SIZE - 4).unwrap(),
+            0x1234_5678_9abc_def0
+        );
+        assert!(area.write_val(3 * PAGE_SIZE - 4, &0u64).is_err());
+    }
+
+    #[ktest]
+    fn vmap_aliases_frames() {
+        let frames = FrameAllocOptions::new(2).alloc().unwrap();
+        let frames: alloc::vec::Vec<Frame> = frames.iter().rev().cloned().collect();
+        frames[0].write_val(0, &1u32).unwrap();
+        frames[1].write_val(0, &2u32).unwrap();
+
+        let area = vmap(&frames, CachePolicy::Writeback).unwrap();
+        assert_eq!(area.read_val::<u32>(0).unwrap(), 1);
+        assert_eq!(area.read_val::<u32>(PAGE_SIZE).unwrap(), 2);
+        area.write_val(PAGE_SIZE + 4, &3u32).unwrap();
+        assert_eq!(frames[1].read_val::<u32>(4).unwrap(), 3);
+
+        // The page table holds the frames until the area is unmapped.
+        let nr_refs = frames[0].reference_count();
+        vunmap(area);
+        assert_eq!(frames[0].reference_count(), nr_refs - 1);
+    }
+
+    #[ktest]
+    fn areas_are_separated_by_guard_pages() {
+        let area1 = vmalloc(PAGE_SIZE).unwrap();
+        let area2 = vmalloc(PAGE_SIZE).unwrap();
+        let (lower, upper) = if area1.start_vaddr() < area2.start_vaddr() {
+            (&area1, &area2)
+        } else {
+            (&area2, &area1)
+        };
+        assert!(lower.start_vaddr() + lower.size() + PAGE_SIZE <= upper.start_vaddr());
+        let page_table = KERNEL_PAGE_TABLE.get().unwrap();
+        assert!(page_table
+            .query(lower.start_vaddr() + lower.size())
+            .is_none());
+    }
+}
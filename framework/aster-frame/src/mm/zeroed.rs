@@ -0,0 +1,146 @@
+// SPDX-License-Identifier: MPL-2.0
+
+//! A pool of pre-zeroed frames.
+//!
+//! Zeroing a frame is a significant part of the latency of allocating zeroed
+//! frames, e.g., when handling the page faults of anonymous memory. So the
+//! allocations of single zeroed frames (see [`FrameAllocOptions::alloc_single`])
+//! take frames from the pool first, and only zero the frames synchronously if
+//! the pool is empty.
+//!
+//! The pool is refilled by a background scrubber, which should wait for the pool
+//! to be drained (see [`wait_for_scrub`]) and then call [`scrub`]. The scrubber is
+//! expected to run with a low priority, so that the zeroing is done when the CPU
+//! is otherwise idle.
+//!
+//! [`FrameAllocOptions::alloc_single`]: crate::mm::FrameAllocOptions::alloc_single
+
+use alloc::vec::Vec;
+use core::sync::atomic::{AtomicBool, Ordering};
+
+use super::{page::allocator, reclaim::is_low_on_memory, Frame};
+use crate::sync::{SpinLock, WaitQueue};
+
+/// The maximum number of frames in the pool.
+const MAX_NR_ZEROED_FRAMES: usize = 1024;
+
+/// The number of frames zeroed by the scrubber before it checks whether to stop.
+const SCRUB_BATCH: usize = 16;
+
+static ZEROED_FRAMES: SpinLock<Vec<Frame>> = SpinLock::new(Vec::new());
+
+static NEED_SCRUB: AtomicBool = AtomicBool::new(false);
+static SCRUB_WAIT_QUEUE: WaitQueue = WaitQueue::new();
+
+/// Returns the number of frames that the pool tries to hold.
+///
+/// It is a small fraction of the memory, so the pool hardly puts pressure on
+/// the memory.
+pub fn target_nr_zeroed_frames() -> usize {
+    (allocator::nr_total_frames() / 256).min(MAX_NR_ZEROED_FRAMES)
+}
+
+/// Returns the number of frames in the pool.
+pub fn nr_zeroed_frames() -> usize {
+    ZEROED_FRAMES.lock_irq_disabled().len()
+}
+
+/// Waits until the pool has fallen below half of its target size.
+///
+/// This is intended for a background scrubber, which should call [`scrub`]
+/// after this function returns.
+pub fn wait_for_scrub() {
+    SCRUB_WAIT_QUEUE.wait_until(|| {
+        if NEED_SCRUB.swap(false, Ordering::Relaxed) {
+            Some(())
+        } else {
+            None
+        }
+    })
+}
+
+/// Zeroes some free frames and puts them into the pool, until the pool reaches
+/// its target size or `should_stop` returns `true`.
+///
+/// The function checks `should_stop` every few frames, so that the scrubber can
+/// give up the CPU in time. It stops early if the system is low on memory, since
+/// the free frames are more useful to the others then.
+///
+/// Returns the number of frames that are put into the pool.
+pub fn scrub(mut should_stop: impl FnMut() -> bool) -> usize {
+    let target = target_nr_zeroed_frames();
+    let mut nr_scrubbed = 0;
+    loop {
+        if should_stop() || is_low_on_memory() {
+            return nr_scrubbed;
+        }
+
+        let nr_to_scrub = target.saturating_sub(nr_zeroed_frames()).min(SCRUB_BATCH);
+        if nr_to_scrub == 0 {
+            return nr_scrubbed;
+        }
+
+        let mut frames = Vec::with_capacity(nr_to_scrub);
+        for _ in 0..nr_to_scrub {
+            let Some(page) = allocator::alloc_single() else {
+                break;
+            };
+            let frame = Frame { page };
+            frame.writer().fill(0);
+            frames.push(frame);
+        }
+        if frames.is_empty() {
+            return nr_scrubbed;
+        }
+
+        nr_scrubbed += frames.len();
+        ZEROED_FRAMES.lock_irq_disabled().extend(frames);
+    }
+}
+
+/// Takes a zeroed frame from the pool.
+///
+/// The scrubber is woken up if the pool has fallen below half of its target size.
+pub(super) fn take() -> Option<Frame> {
+    let (frame, nr_left) = {
+        let mut frames = ZEROED_FRAMES.lock_irq_disabled();
+        (frames.pop(), frames.len())
+    };
+    // Do not wake the scrubber over and over again while it is still working.
+    if nr_left < target_nr_zeroed_frames() / 2 && !NEED_SCRUB.swap(true, Ordering::Relaxed) {
+        SCRUB_WAIT_QUEUE.wake_all();
+    }
+    frame
+}
+
+/// Releases all the frames in the pool to the frame allocator.
+///
+/// Returns the number of frames that are released.
+pub(super) fn drain() -> usize {
+    let frames = core::mem::take(&mut *ZEROED_FRAMES.lock_irq_disabled());
+    frames.len()
+}
+
+#[cfg(ktest)]
+mod test {
+    use super::*;
+    use crate::mm::{FrameAllocOptions, VmIo};
+
+    #[ktest]
+    fn scrubbed_frames_are_zeroed() {
+        let frame = FrameAllocOptions::new(1)
+            .uninit(true)
+            .alloc_single()
+            .unwrap();
+        frame.write_val(0, &0xdeadbeefu32).unwrap();
+        drop(frame);
+
+        scrub(|| false);
+        let nr_zeroed = nr_zeroed_frames();
+        let frame = FrameAllocOptions::new(1).alloc_single().unwrap();
+        if nr_zeroed > 0 {
+            assert_eq!(nr_zeroed_frames(), nr_zeroed - 1);
+        }
+        assert_eq!(frame.read_val::<u32>(0).unwrap(), 0);
+    }
+}
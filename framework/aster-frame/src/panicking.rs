@@ -3,13 +3,14 @@
 //! Panic support.
 
 use alloc::{boxed::Box, string::ToString};
-use core::ffi::c_void;
+use core::{ffi::c_void, sync::atomic::Ordering};
 
 use log::error;
 
 use crate::{
     arch::qemu::{exit_qemu, QemuExitCode},
     early_print, early_println,
+    task::current_task,
 };
 
 extern crate cfg_if;
@@ -20,7 +21,7 @@ use unwinding::{
         UnwindContext, UnwindReasonCode, _Unwind_Backtrace, _Unwind_FindEnclosingFunction,
         _Unwind_GetGR, _Unwind_GetIP,
     },
-    panic::begin_panic,
+    panic::{begin_panic, catch_unwind},
 };
 
 /// The panic handler must be defined in the binary crate or in the crate that the binary
@@ -35,6 +36,13 @@ pub fn panic_handler(info: &core::panic::PanicInfo) -> ! {
         line: info.location().unwrap().line() as usize,
         col: info.location().unwrap().column() as usize,
     };
+    // The stack trace is lost once the panic is caught by `catch_panic`, so print it now.
+    if current_task().is_some_and(|task| task.is_catching_panic()) {
+        error!("Kernel oops!");
+        early_println!("{}", info);
+        early_println!("printing stack trace:");
+        print_stack_trace();
+    }
     // Throw an exception and expecting it to be caught.
     begin_panic(Box::new(throw_info.clone()));
     // If the exception is not caught (e.g. by ktest) and resumed,
@@ -46,6 +54,34 @@ pub fn panic_handler(info: &core::panic::PanicInfo) -> ! {
     abort();
 }
 
+/// Calls `f` and catches the panic that occurs in it on the current task.
+///
+/// A caught panic is returned as an error after the stack is unwound, which drops the
+/// guards owned by the unwound frames. The states that the guards protect may be left
+/// inconsistent, so the caller should stop using them after a panic is caught.
+///
+/// The panic cannot be caught if the kernel is built with `panic = "abort"`.
+///
+/// # Panics
+///
+/// This function panics if it is not called in the context of a task.
+pub fn catch_panic<R>(f: impl FnOnce() -> R) -> Result<R, ktest::PanicInfo> {
+    let task = current_task().expect("`catch_panic` is called without a task");
+    task.nr_panic_catchers().fetch_add(1, Ordering::Relaxed);
+    let result = catch_unwind(f);
+    task.nr_panic_catchers().fetch_sub(1, Ordering::Relaxed);
+
+    result.map_err(|payload| match payload.downcast::<ktest::PanicInfo>() {
+        Ok(info) => *info,
+        Err(_) => ktest::PanicInfo {
+            message: "unknown panic payload".to_string(),
+            file: "unknown".to_string(),
+            line: 0,
+            col: 0,
+        },
+    })
+}
+
 /// Aborts the QEMU
 pub fn abort() -> ! {
     exit_qemu(QemuExitCode::Failed);
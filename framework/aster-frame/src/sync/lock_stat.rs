@@ -0,0 +1,432 @@
+// SPDX-License-Identifier: MPL-2.0
+
+//! The contention statistics of the locks.
+//!
+//! The statistics are collected only if the `lock_stat` feature is enabled. They are
+//! aggregated per lock class, which consists of the locks of the same kind that protect
+//! the data of the same type, e.g., all the `SpinLock<FrameAllocator>`s. For each class,
+//! the number of acquisitions, the number of contentions, i.e., the acquisitions that
+//! cannot succeed at once, and a histogram of the wait time of the contentions are
+//! recorded, as well as the call sites that contend for the locks the most.
+//!
+//! The recording does not allocate memory or acquire locks, so that the locks used by
+//! the allocator and the recording itself can be measured as well. The number of lock
+//! classes and the number of call sites per class are bounded. Once the bounds are
+//! reached, the new classes or call sites are not recorded.
+
+use alloc::vec::Vec;
+use core::panic::Location;
+
+/// The kind of a lock.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum LockKind {
+    /// [`SpinLock`](super::SpinLock)
+    SpinLock,
+    /// [`Mutex`](super::Mutex)
+    Mutex,
+    /// [`RwLock`](super::RwLock)
+    RwLock,
+    /// [`RwMutex`](super::RwMutex)
+    RwMutex,
+}
+
+impl LockKind {
+    /// Returns the name of the kind.
+    pub fn name(&self) -> &'static str {
+        match self {
+            LockKind::SpinLock => "SpinLock",
+            LockKind::Mutex => "Mutex",
+            LockKind::RwLock => "RwLock",
+            LockKind::RwMutex => "RwMutex",
+        }
+    }
+}
+
+/// The number of buckets of the wait-time histograms.
+///
+/// The `i`-th bucket counts the wait time in `[2^i, 2^(i+1))` nanoseconds, except that
+/// the first bucket counts from zero and the last bucket counts to infinity.
+pub const NR_WAIT_BUCKETS: usize = 32;
+
+/// The statistics of a lock class.
+#[derive(Debug, Clone)]
+pub struct LockClassStat {
+    /// The kind of the locks.
+    pub kind: LockKind,
+    /// The type name of the data protected by the locks.
+    pub name: &'static str,
+    /// The number of acquisitions.
+    pub acquisitions: u64,
+    /// The number of acquisitions that have to wait.
+    pub contentions: u64,
+    /// The total wait time in nanoseconds.
+    pub total_wait_ns: u64,
+    /// The maximum wait time in nanoseconds.
+    pub max_wait_ns: u64,
+    /// The histogram of the wait time, see [`NR_WAIT_BUCKETS`].
+    pub wait_histogram: [u64; NR_WAIT_BUCKETS],
+    /// The call sites that have to wait, in descending order of the total wait time.
+    pub call_sites: Vec<CallSiteStat>,
+}
+
+/// The statistics of a call site that contends for the locks of a class.
+#[derive(Debug, Clone)]
+pub struct CallSiteStat {
+    /// The location of the call site.
+    pub location: &'static Location<'static>,
+    /// The number of acquisitions that have to wait.
+    pub contentions: u64,
+    /// The total wait time in nanoseconds.
+    pub total_wait_ns: u64,
+}
+
+/// Returns whether the lock statistics are collected.
+pub fn is_enabled() -> bool {
+    cfg!(feature = "lock_stat")
+}
+
+/// Returns the statistics of the lock classes, in descending order of the total wait time.
+///
+/// The result is empty if the lock statistics are not collected.
+pub fn lock_stats() -> Vec<LockClassStat> {
+    imp::lock_stats()
+}
+
+/// Clears the statistics of all the lock classes.
+pub fn reset_lock_stats() {
+    imp::reset_lock_stats()
+}
+
+pub(super) use self::imp::acquire;
+
+#[cfg(not(feature = "lock_stat"))]
+mod imp {
+    use alloc::vec::Vec;
+
+    use super::{LockClassStat, LockKind};
+
+    /// Acquires a lock by `acquire`.
+    #[inline(always)]
+    pub(in crate::sync) fn acquire<T: ?Sized, G>(
+        _kind: LockKind,
+        _try_acquire: impl FnOnce() -> Option<G>,
+        acquire: impl FnOnce() -> G,
+    ) -> G {
+        acquire()
+    }
+
+    pub(super) fn lock_stats() -> Vec<LockClassStat> {
+        Vec::new()
+    }
+
+    pub(super) fn reset_lock_stats() {}
+}
+
+#[cfg(feature = "lock_stat")]
+mod imp {
+    use alloc::vec::Vec;
+    use core::{
+        any::type_name,
+        panic::Location,
+        ptr,
+        sync::atomic::{AtomicPtr, AtomicU64, AtomicUsize, Ordering},
+    };
+
+    use super::{CallSiteStat, LockClassStat, LockKind, NR_WAIT_BUCKETS};
+    use crate::arch::{read_tsc, tsc_freq};
+
+    /// Acquires a lock by `try_acquire`, or by `acquire` if the try fails, and records
+    /// the acquisition to the lock class of the data type `T`.
+    #[track_caller]
+    #[inline]
+    pub(in crate::sync) fn acquire<T: ?Sized, G>(
+        kind: LockKind,
+        try_acquire: impl FnOnce() -> Option<G>,
+        acquire: impl FnOnce() -> G,
+    ) -> G {
+        let class = LockClass::get_or_register(kind, type_name::<T>());
+        if let Some(guard) = try_acquire() {
+            if let Some(class) = class {
+                class.acquisitions.fetch_add(1, Ordering::Relaxed);
+            }
+            return guard;
+        }
+
+        let start = read_tsc();
+        let guard = acquire();
+        let wait_ns = cycles_to_ns(read_tsc().saturating_sub(start));
+        if let Some(class) = class {
+            class.acquisitions.fetch_add(1, Ordering::Relaxed);
+            class.record_contention(wait_ns, Location::caller());
+        }
+        guard
+    }
+
+    pub(super) fn lock_stats() -> Vec<LockClassStat> {
+        let mut stats: Vec<LockClassStat> = Vec::new();
+        for (kind, classes) in CLASSES.iter().enumerate() {
+            for class in classes.iter() {
+                let Some(stat) = class.snapshot(KINDS[kind]) else {
+                    continue;
+                };
+                // The same type name may be placed at different addresses, which are
+                // merged into one class.
+                match stats
+                    .iter_mut()
+                    .find(|merged| merged.kind == stat.kind && merged.name == stat.name)
+                {
+                    Some(merged) => merged.merge(stat),
+                    None => stats.push(stat),
+                }
+            }
+        }
+        for stat in stats.iter_mut() {
+            stat.call_sites
+                .sort_by(|a, b| b.total_wait_ns.cmp(&a.total_wait_ns));
+        }
+        stats.sort_by(|a, b| {
+            b.total_wait_ns
+                .cmp(&a.total_wait_ns)
+                .then(b.acquisitions.cmp(&a.acquisitions))
+        });
+        stats
+    }
+
+    pub(super) fn reset_lock_stats() {
+        for class in CLASSES.iter().flatten() {
+            class.reset();
+        }
+    }
+
+    /// The number of lock classes of each kind.
+    const NR_CLASSES: usize = 128;
+    /// The number of call sites recorded per lock class.
+    const NR_CALL_SITES: usize = 8;
+
+    const KINDS: [LockKind; 4] = [
+        LockKind::SpinLock,
+        LockKind::Mutex,
+        LockKind::RwLock,
+        LockKind::RwMutex,
+    ];
+
+    #[allow(clippy::declare_interior_mutable_const)]
+    const EMPTY_CLASS: LockClass = LockClass::new();
+    #[allow(clippy::declare_interior_mutable_const)]
+    const EMPTY_CLASSES: [LockClass; NR_CLASSES] = [EMPTY_CLASS; NR_CLASSES];
+
+    /// The lock classes of each kind, which form open-addressing hash tables keyed by
+    /// the addresses of the type names.
+    static CLASSES: [[LockClass; NR_CLASSES]; KINDS.len()] = [EMPTY_CLASSES; KINDS.len()];
+
+    struct LockClass {
+        /// The address of the type name, or zero if the class is not registered.
+        name_addr: AtomicUsize,
+        /// The length of the type name, which is set after the class is registered.
+        name_len: AtomicUsize,
+        acquisitions: AtomicU64,
+        contentions: AtomicU64,
+        total_wait_ns: AtomicU64,
+        max_wait_ns: AtomicU64,
+        wait_histogram: [AtomicU64; NR_WAIT_BUCKETS],
+        call_sites: [CallSite; NR_CALL_SITES],
+    }
+
+    struct CallSite {
+        /// The location of the call site, or null if the slot is free.
+        location: AtomicPtr<Location<'static>>,
+        contentions: AtomicU64,
+        total_wait_ns: AtomicU64,
+    }
+
+    #[allow(clippy::declare_interior_mutable_const)]
+    const ZERO: AtomicU64 = AtomicU64::new(0);
+    #[allow(clippy::declare_interior_mutable_const)]
+    const EMPTY_CALL_SITE: CallSite = CallSite {
+        location: AtomicPtr::new(ptr::null_mut()),
+        contentions: AtomicU64::new(0),
+        total_wait_ns: AtomicU64::new(0),
+    };
+
+    impl LockClass {
+        const fn new() -> Self {
+            Self {
+                name_addr: AtomicUsize::new(0),
+                name_len: AtomicUsize::new(0),
+                acquisitions: AtomicU64::new(0),
+                contentions: AtomicU64::new(0),
+                total_wait_ns: AtomicU64::new(0),
+                max_wait_ns: AtomicU64::new(0),
+                wait_histogram: [ZERO; NR_WAIT_BUCKETS],
+                call_sites: [EMPTY_CALL_SITE; NR_CALL_SITES],
+            }
+        }
+
+        /// Returns the class of the kind and the type name, which is registered if it
+        /// has not been. Returns `None` if the table of the kind is full.
+        fn get_or_register(kind: LockKind, name: &'static str) -> Option<&'static LockClass> {
+            let classes = &CLASSES[kind as usize];
+            let name_addr = name.as_ptr() as usize;
+            let hash = (name_addr as u64).wrapping_mul(0x9e37_79b9_7f4a_7c15) >> 32;
+            for probe in 0..NR_CLASSES {
+                let class = &classes[(hash as usize + probe) % NR_CLASSES];
+                match class.name_addr.compare_exchange(
+                    0,
+                    name_addr,
+                    Ordering::Relaxed,
+                    Ordering::Relaxed,
+                ) {
+                    Ok(_) => {
+                        class.name_len.store(name.len(), Ordering::Release);
+                        return Some(class);
+                    }
+                    Err(addr) if addr == name_addr => return Some(class),
+                    Err(_) => continue,
+                }
+            }
+            None
+        }
+
+        fn record_contention(&self, wait_ns: u64, location: &'static Location<'static>) {
+            self.contentions.fetch_add(1, Ordering::Relaxed);
+            self.total_wait_ns.fetch_add(wait_ns, Ordering::Relaxed);
+            self.max_wait_ns.fetch_max(wait_ns, Ordering::Relaxed);
+            let bucket = (u64::BITS - 1).saturating_sub(wait_ns.leading_zeros()) as usize;
+            self.wait_histogram[bucket.min(NR_WAIT_BUCKETS - 1)].fetch_add(1, Ordering::Relaxed);
+
+            let location_ptr = location as *const Location<'static> as *mut Location<'static>;
+            for call_site in self.call_sites.iter() {
+                let is_matched = match call_site.location.compare_exchange(
+                    ptr::null_mut(),
+                    location_ptr,
+                    Ordering::Relaxed,
+                    Ordering::Relaxed,
+                ) {
+                    Ok(_) => true,
+                    Err(current) => current == location_ptr,
+                };
+                if is_matched {
+                    call_site.contentions.fetch_add(1, Ordering::Relaxed);
+                    call_site
+                        .total_wait_ns
+                        .fetch_add(wait_ns, Ordering::Relaxed);
+                    return;
+                }
+            }
+        }
+
+        fn snapshot(&self, kind: LockKind) -> Option<LockClassStat> {
+            let name_len = self.name_len.load(Ordering::Acquire);
+            if name_len == 0 {
+                return None;
+            }
+            let name_addr = self.name_addr.load(Ordering::Relaxed);
+            // SAFETY: The address and the length are of a type name, which is a static
+            // string.
+            let name = unsafe {
+                core::str::from_utf8_unchecked(core::slice::from_raw_parts(
+                    name_addr as *const u8,
+                    name_len,
+                ))
+            };
+
+            let call_sites = self
+                .call_sites
+                .iter()
+                .filter_map(|call_site| {
+                    let location = call_site.location.load(Ordering::Relaxed);
+                    // SAFETY: A non-null location is from `Location::caller`, which is static.
+                    let location = unsafe { location.as_ref() }?;
+                    Some(CallSiteStat {
+                        location,
+                        contentions: call_site.contentions.load(Ordering::Relaxed),
+                        total_wait_ns: call_site.total_wait_ns.load(Ordering::Relaxed),
+                    })
+                })
+                .collect();
+            Some(LockClassStat {
+                kind,
+                name,
+                acquisitions: self.acquisitions.load(Ordering::Relaxed),
+                contentions: self.contentions.load(Ordering::Relaxed),
+                total_wait_ns: self.total_wait_ns.load(Ordering::Relaxed),
+                max_wait_ns: self.max_wait_ns.load(Ordering::Relaxed),
+                wait_histogram: core::array::from_fn(|bucket| {
+                    self.wait_histogram[bucket].load(Ordering::Relaxed)
+                }),
+                call_sites,
+            })
+        }
+
+        /// Clears the statistics. The registration of the class is kept.
+        fn reset(&self) {
+            self.acquisitions.store(0, Ordering::Relaxed);
+            self.contentions.store(0, Ordering::Relaxed);
+            self.total_wait_ns.store(0, Ordering::Relaxed);
+            self.max_wait_ns.store(0, Ordering::Relaxed);
+            for count in self.wait_histogram.iter() {
+                count.store(0, Ordering::Relaxed);
+            }
+            for call_site in self.call_sites.iter() {
+                call_site.location.store(ptr::null_mut(), Ordering::Relaxed);
+                call_site.contentions.store(0, Ordering::Relaxed);
+                call_site.total_wait_ns.store(0, Ordering::Relaxed);
+            }
+        }
+    }
+
+    impl LockClassStat {
+        fn merge(&mut self, other: LockClassStat) {
+            self.acquisitions += other.acquisitions;
+            self.contentions += other.contentions;
+            self.total_wait_ns += other.total_wait_ns;
+            self.max_wait_ns = self.max_wait_ns.max(other.max_wait_ns);
+            for (count, other_count) in self.wait_histogram.iter_mut().zip(other.wait_histogram) {
+                *count += other_count;
+            }
+            for other_call_site in other.call_sites {
+                match self
+                    .call_sites
+                    .iter_mut()
+                    .find(|call_site| ptr::eq(call_site.location, other_call_site.location))
+                {
+                    Some(call_site) => {
+                        call_site.contentions += other_call_site.contentions;
+                        call_site.total_wait_ns += other_call_site.total_wait_ns;
+                    }
+                    None => self.call_sites.push(other_call_site),
+                }
+            }
+        }
+    }
+
+    fn cycles_to_ns(cycles: u64) -> u64 {
+        match tsc_freq() {
+            0 => 0,
+            freq => (cycles as u128 * 1_000_000_000 / freq as u128) as u64,
+        }
+    }
+}
+
+#[cfg(all(ktest, feature = "lock_stat"))]
+mod test {
+    use super::*;
+    use crate::sync::SpinLock;
+
+    struct LockStatTestData;
+
+    #[ktest]
+    fn acquisitions_are_counted() {
+        let lock = SpinLock::new(LockStatTestData);
+        for _ in 0..3 {
+            drop(lock.lock());
+        }
+        let name = core::any::type_name::<LockStatTestData>();
+        let stat = lock_stats()
+            .into_iter()
+            .find(|stat| stat.kind == LockKind::SpinLock && stat.name == name)
+            .unwrap();
+        assert!(stat.acquisitions >= 3);
+        assert_eq!(stat.contentions, 0);
+    }
+}
@@ -3,6 +3,7 @@
 //! Useful synchronization primitives.
 
 mod atomic_bits;
+pub mod lock_stat;
 mod mutex;
 // TODO: refactor this rcu implementation
 // Comment out this module since it raises lint error
@@ -8,7 +8,10 @@ use core::{
     sync::atomic::{AtomicBool, Ordering},
 };
 
-use super::WaitQueue;
+use super::{
+    lock_stat::{self, LockKind},
+    WaitQueue,
+};
 
 /// A mutex with waitqueue.
 pub struct Mutex<T: ?Sized> {
@@ -32,8 +35,13 @@ impl<T: ?Sized> Mutex<T> {
     /// Acquires the mutex.
     ///
     /// This method runs in a block way until the mutex can be acquired.
+    #[cfg_attr(feature = "lock_stat", track_caller)]
     pub fn lock(&self) -> MutexGuard<T> {
-        self.queue.wait_until(|| self.try_lock())
+        lock_stat::acquire::<T, _>(
+            LockKind::Mutex,
+            || self.try_lock(),
+            || self.queue.wait_until(|| self.try_lock()),
+        )
     }
 
     /// Acquires the mutex through an [`Arc`].
@@ -42,8 +50,13 @@ impl<T: ?Sized> Mutex<T> {
     /// for compile-time checked lifetimes of the mutex guard.
     ///
     /// [`lock`]: Self::lock
+    #[cfg_attr(feature = "lock_stat", track_caller)]
     pub fn lock_arc(self: &Arc<Self>) -> ArcMutexGuard<T> {
-        self.queue.wait_until(|| self.try_lock_arc())
+        lock_stat::acquire::<T, _>(
+            LockKind::Mutex,
+            || self.try_lock_arc(),
+            || self.queue.wait_until(|| self.try_lock_arc()),
+        )
     }
 
     /// Tries Acquire the mutex immedidately.
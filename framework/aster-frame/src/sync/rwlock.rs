@@ -13,6 +13,7 @@ use core::{
     },
 };
 
+use super::lock_stat::{self, LockKind};
 use crate::{
     task::{disable_preempt, DisablePreemptGuard},
     trap::{disable_local, DisabledLocalIrqGuard},
@@ -134,14 +135,13 @@ impl<T: ?Sized> RwLock<T> {
     /// in which other readers or writers waiting simultaneously will
     /// obtain the lock. Once this lock is acquired, the calling thread
     /// will not be interrupted.
+    #[cfg_attr(feature = "lock_stat", track_caller)]
     pub fn read_irq_disabled(&self) -> RwLockReadGuard<T> {
-        loop {
-            if let Some(readguard) = self.try_read_irq_disabled() {
-                return readguard;
-            } else {
-                core::hint::spin_loop();
-            }
-        }
+        lock_stat::acquire::<T, _>(
+            LockKind::RwLock,
+            || self.try_read_irq_disabled(),
+            || spin_until(|| self.try_read_irq_disabled()),
+        )
     }
 
     /// Acquires a write lock while disabling the local IRQs and spin-wait
@@ -152,14 +152,13 @@ impl<T: ?Sized> RwLock<T> {
     /// in which other readers or writers waiting simultaneously will
     /// obtain the lock. Once this lock is acquired, the calling thread
     /// will not be interrupted.
+    #[cfg_attr(feature = "lock_stat", track_caller)]
     pub fn write_irq_disabled(&self) -> RwLockWriteGuard<T> {
-        loop {
-            if let Some(writeguard) = self.try_write_irq_disabled() {
-                return writeguard;
-            } else {
-                core::hint::spin_loop();
-            }
-        }
+        lock_stat::acquire::<T, _>(
+            LockKind::RwLock,
+            || self.try_write_irq_disabled(),
+            || spin_until(|| self.try_write_irq_disabled()),
+        )
     }
 
     /// Acquires an upgradeable reader (upreader) while disabling local IRQs
@@ -174,14 +173,13 @@ impl<T: ?Sized> RwLock<T> {
     /// and reader do not differ before invoking the upgread method. However,
     /// only one upreader can exist at any time to avoid deadlock in the
     /// upgread method.
+    #[cfg_attr(feature = "lock_stat", track_caller)]
     pub fn upread_irq_disabled(&self) -> RwLockUpgradeableGuard<T> {
-        loop {
-            if let Some(guard) = self.try_upread_irq_disabled() {
-                return guard;
-            } else {
-                core::hint::spin_loop();
-            }
-        }
+        lock_stat::acquire::<T, _>(
+            LockKind::RwLock,
+            || self.try_upread_irq_disabled(),
+            || spin_until(|| self.try_upread_irq_disabled()),
+        )
     }
 
     /// Attempts to acquire a read lock while disabling local IRQs.
@@ -260,14 +258,13 @@ impl<T: ?Sized> RwLock<T> {
     /// method as it has a higher efficiency.
     ///
     /// [`read_irq_disabled`]: Self::read_irq_disabled
+    #[cfg_attr(feature = "lock_stat", track_caller)]
     pub fn read(&self) -> RwLockReadGuard<T> {
-        loop {
-            if let Some(readguard) = self.try_read() {
-                return readguard;
-            } else {
-                core::hint::spin_loop();
-            }
-        }
+        lock_stat::acquire::<T, _>(
+            LockKind::RwLock,
+            || self.try_read(),
+            || spin_until(|| self.try_read()),
+        )
     }
 
     /// Acquires a read lock through an [`Arc`].
@@ -276,14 +273,13 @@ impl<T: ?Sized> RwLock<T> {
     /// for compile-time checked lifetimes of the read guard.
     ///
     /// [`read`]: Self::read
+    #[cfg_attr(feature = "lock_stat", track_caller)]
     pub fn read_arc(self: &Arc<Self>) -> ArcRwLockReadGuard<T> {
-        loop {
-            if let Some(readguard) = self.try_read_arc() {
-                return readguard;
-            } else {
-                core::hint::spin_loop();
-            }
-        }
+        lock_stat::acquire::<T, _>(
+            LockKind::RwLock,
+            || self.try_read_arc(),
+            || spin_until(|| self.try_read_arc()),
+        )
     }
 
     /// Acquires a write lock and spin-wait until it can be acquired.
@@ -300,14 +296,13 @@ impl<T: ?Sized> RwLock<T> {
     /// method as it has a higher efficiency.
     ///
     /// [`write_irq_disabled`]: Self::write_irq_disabled
+    #[cfg_attr(feature = "lock_stat", track_caller)]
     pub fn write(&self) -> RwLockWriteGuard<T> {
-        loop {
-            if let Some(writeguard) = self.try_write() {
-                return writeguard;
-            } else {
-                core::hint::spin_loop();
-            }
-        }
+        lock_stat::acquire::<T, _>(
+            LockKind::RwLock,
+            || self.try_write(),
+            || spin_until(|| self.try_write()),
+        )
     }
 
     /// Acquires a write lock through an [`Arc`].
@@ -316,14 +311,13 @@ impl<T: ?Sized> RwLock<T> {
     /// for compile-time checked lifetimes of the lock guard.
     ///
     /// [`write`]: Self::write
+    #[cfg_attr(feature = "lock_stat", track_caller)]
     pub fn write_arc(self: &Arc<Self>) -> ArcRwLockWriteGuard<T> {
-        loop {
-            if let Some(writeguard) = self.try_write_arc() {
-                return writeguard;
-            } else {
-                core::hint::spin_loop();
-            }
-        }
+        lock_stat::acquire::<T, _>(
+            LockKind::RwLock,
+            || self.try_write_arc(),
+            || spin_until(|| self.try_write_arc()),
+        )
     }
 
     /// Acquires an upreader and spin-wait until it can be acquired.
@@ -344,14 +338,13 @@ impl<T: ?Sized> RwLock<T> {
     /// method as it has a higher efficiency.
     ///
     /// [`upread_irq_disabled`]: Self::upread_irq_disabled
+    #[cfg_attr(feature = "lock_stat", track_caller)]
     pub fn upread(&self) -> RwLockUpgradeableGuard<T> {
-        loop {
-            if let Some(guard) = self.try_upread() {
-                return guard;
-            } else {
-                core::hint::spin_loop();
-            }
-        }
+        lock_stat::acquire::<T, _>(
+            LockKind::RwLock,
+            || self.try_upread(),
+            || spin_until(|| self.try_upread()),
+        )
     }
 
     /// Acquires an upgradeable read lock through an [`Arc`].
@@ -360,14 +353,13 @@ impl<T: ?Sized> RwLock<T> {
     /// for compile-time checked lifetimes of the lock guard.
     ///
     /// [`upread`]: Self::upread
+    #[cfg_attr(feature = "lock_stat", track_caller)]
     pub fn upread_arc(self: &Arc<Self>) -> ArcRwLockUpgradeableGuard<T> {
-        loop {
-            if let Some(guard) = self.try_upread_arc() {
-                return guard;
-            } else {
-                core::hint::spin_loop();
-            }
-        }
+        lock_stat::acquire::<T, _>(
+            LockKind::RwLock,
+            || self.try_upread_arc(),
+            || spin_until(|| self.try_upread_arc()),
+        )
     }
 
     /// Attempts to acquire a read lock.
@@ -513,6 +505,16 @@ impl<T: ?Sized> RwLock<T> {
     }
 }
 
+/// Spin-waits until the lock is acquired by `try_acquire`.
+fn spin_until<G>(mut try_acquire: impl FnMut() -> Option<G>) -> G {
+    loop {
+        if let Some(guard) = try_acquire() {
+            return guard;
+        }
+        core::hint::spin_loop();
+    }
+}
+
 impl<T: ?Sized + fmt::Debug> fmt::Debug for RwLock<T> {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         fmt::Debug::fmt(&self.val, f)
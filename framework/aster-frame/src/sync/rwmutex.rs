@@ -11,7 +11,10 @@ use core::{
     },
 };
 
-use super::WaitQueue;
+use super::{
+    lock_stat::{self, LockKind},
+    WaitQueue,
+};
 
 /// A mutex that provides data access to either one writer or many readers.
 ///
@@ -121,8 +124,13 @@ impl<T: ?Sized> RwMutex<T> {
     /// upreaders present. The implementation of [`WaitQueue`] guarantees the
     /// order in which other concurrent readers or writers waiting simultaneously
     /// will acquire the mutex.
+    #[cfg_attr(feature = "lock_stat", track_caller)]
     pub fn read(&self) -> RwMutexReadGuard<T> {
-        self.queue.wait_until(|| self.try_read())
+        lock_stat::acquire::<T, _>(
+            LockKind::RwMutex,
+            || self.try_read(),
+            || self.queue.wait_until(|| self.try_read()),
+        )
     }
 
     /// Acquires a write mutex and sleep until it can be acquired.
@@ -131,8 +139,13 @@ impl<T: ?Sized> RwMutex<T> {
     /// or readers present. The implementation of [`WaitQueue`] guarantees the
     /// order in which other concurrent readers or writers waiting simultaneously
     /// will acquire the mutex.
+    #[cfg_attr(feature = "lock_stat", track_caller)]
     pub fn write(&self) -> RwMutexWriteGuard<T> {
-        self.queue.wait_until(|| self.try_write())
+        lock_stat::acquire::<T, _>(
+            LockKind::RwMutex,
+            || self.try_write(),
+            || self.queue.wait_until(|| self.try_write()),
+        )
     }
 
     /// Acquires a upread mutex and sleep until it can be acquired.
@@ -145,8 +158,13 @@ impl<T: ?Sized> RwMutex<T> {
     /// and reader do not differ before invoking the upgread method. However,
     /// only one upreader can exist at any time to avoid deadlock in the
     /// upgread method.
+    #[cfg_attr(feature = "lock_stat", track_caller)]
     pub fn upread(&self) -> RwMutexUpgradeableGuard<T> {
-        self.queue.wait_until(|| self.try_upread())
+        lock_stat::acquire::<T, _>(
+            LockKind::RwMutex,
+            || self.try_upread(),
+            || self.queue.wait_until(|| self.try_upread()),
+        )
     }
 
     /// Attempts to acquire a read mutex.
@@ -10,6 +10,7 @@ use core::{
     sync::atomic::{AtomicBool, Ordering},
 };
 
+use super::lock_stat::{self, LockKind};
 use crate::{
     task::{disable_preempt, DisablePreemptGuard},
     trap::{disable_local, DisabledLocalIrqGuard},
@@ -37,6 +38,7 @@ impl<T: ?Sized> SpinLock<T> {
     ///
     /// This method runs in a busy loop until the lock can be acquired.
     /// After acquiring the spin lock, all interrupts are disabled.
+    #[cfg_attr(feature = "lock_stat", track_caller)]
     pub fn lock_irq_disabled(&self) -> SpinLockGuard<T> {
         let guard = disable_local();
         self.acquire_lock();
@@ -69,6 +71,7 @@ impl<T: ?Sized> SpinLock<T> {
     /// in the process context.
     ///
     /// [`lock_irq_disabled`]: Self::lock_irq_disabled
+    #[cfg_attr(feature = "lock_stat", track_caller)]
     pub fn lock(&self) -> SpinLockGuard<T> {
         let guard = disable_preempt();
         self.acquire_lock();
@@ -84,6 +87,7 @@ impl<T: ?Sized> SpinLock<T> {
     /// for compile-time checked lifetimes of the lock guard.
     ///
     /// [`lock`]: Self::lock
+    #[cfg_attr(feature = "lock_stat", track_caller)]
     pub fn lock_arc(self: &Arc<Self>) -> ArcSpinLockGuard<T> {
         let guard = disable_preempt();
         self.acquire_lock();
@@ -107,10 +111,17 @@ impl<T: ?Sized> SpinLock<T> {
     }
 
     /// Acquires the spin lock, otherwise busy waiting
+    #[cfg_attr(feature = "lock_stat", track_caller)]
     fn acquire_lock(&self) {
-        while !self.try_acquire_lock() {
-            core::hint::spin_loop();
-        }
+        lock_stat::acquire::<T, _>(
+            LockKind::SpinLock,
+            || self.try_acquire_lock().then_some(()),
+            || {
+                while !self.try_acquire_lock() {
+                    core::hint::spin_loop();
+                }
+            },
+        )
     }
 
     fn try_acquire_lock(&self) -> bool {
@@ -5,12 +5,21 @@
 mod priority;
 mod processor;
 mod scheduler;
+mod scope;
 #[allow(clippy::module_inception)]
 mod task;
 
+pub(crate) use self::processor::{init, run_idle_loop, scheduler_tick};
 pub use self::{
-    priority::Priority,
-    processor::{current_task, disable_preempt, preempt, schedule, DisablePreemptGuard},
-    scheduler::{add_task, set_scheduler, FifoScheduler, Scheduler},
-    task::{Task, TaskAdapter, TaskContextApi, TaskOptions, TaskStatus},
+    priority::{Priority, SchedPolicy},
+    processor::{
+        current_task, disable_preempt, need_resched, preempt, resched_cpu, schedule,
+        DisablePreemptGuard,
+    },
+    scheduler::{add_task, set_scheduler, FifoScheduler, SchedEntity, Scheduler},
+    scope::{scope, Scope, ScopedFn},
+    task::{
+        Task, TaskAdapter, TaskContextApi, TaskOptions, TaskStatus, KERNEL_STACK_SIZE,
+        LARGE_KERNEL_STACK_SIZE, MAX_KERNEL_STACK_SIZE,
+    },
 };
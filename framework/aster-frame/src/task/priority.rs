@@ -6,8 +6,9 @@ pub const REAL_TIME_TASK_PRIORITY: u16 = 100;
 ///
 /// Similar to Linux, a larger value represents a lower priority,
 /// with a range of 0 to 139. Priorities ranging from 0 to 99 are considered real-time,
-/// while those ranging from 100 to 139 are considered normal.
-#[derive(Copy, Clone)]
+/// while those ranging from 100 to 139 are considered normal. A normal priority is 120 plus
+/// the nice value, which ranges from -20 to 19.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, PartialOrd, Ord)]
 pub struct Priority(u16);
 
 impl Priority {
@@ -26,14 +27,14 @@ impl Priority {
         Self::new(139)
     }
 
-    /// Returns a `Priority` representing a low priority.
+    /// Returns a `Priority` representing a low priority, i.e., that of the nice value 10.
     pub const fn low() -> Self {
-        Self::new(110)
+        Self::new(130)
     }
 
-    /// Returns a `Priority` representing a normal priority.
+    /// Returns a `Priority` representing a normal priority, i.e., that of the nice value 0.
     pub const fn normal() -> Self {
-        Self::new(100)
+        Self::new(120)
     }
 
     /// Returns a `Priority` representing a high priority.
@@ -61,3 +62,37 @@ impl Priority {
         self.0 < REAL_TIME_TASK_PRIORITY
     }
 }
+
+/// The scheduling policy of a task.
+///
+/// The policy decides how a task shares the CPU with the tasks of the same priority. The
+/// real-time tasks are scheduled with [`SchedPolicy::Fifo`] or [`SchedPolicy::RoundRobin`],
+/// while the normal tasks are scheduled with [`SchedPolicy::Normal`].
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+#[repr(u8)]
+pub enum SchedPolicy {
+    /// The tasks share the CPU in proportion to their weights, which are given by their
+    /// priorities.
+    #[default]
+    Normal = 0,
+    /// A task runs until it sleeps, yields, or is preempted by a task of a higher priority.
+    Fifo = 1,
+    /// Like [`SchedPolicy::Fifo`], but a task also gives the CPU to the tasks of the same
+    /// priority when its time slice runs out.
+    RoundRobin = 2,
+}
+
+impl SchedPolicy {
+    /// Checks if the policy is a real-time policy.
+    pub const fn is_real_time(&self) -> bool {
+        !matches!(self, Self::Normal)
+    }
+
+    pub(super) const fn from_raw(raw: u8) -> Self {
+        match raw {
+            1 => Self::Fifo,
+            2 => Self::RoundRobin,
+            _ => Self::Normal,
+        }
+    }
+}
@@ -5,20 +5,30 @@
 use alloc::sync::Arc;
 use core::{
     cell::RefCell,
-    sync::atomic::{AtomicUsize, Ordering::Relaxed},
+    sync::atomic::{AtomicBool, AtomicUsize, Ordering::Relaxed},
 };
 
+use spin::Once;
+
 use super::{
-    scheduler::{fetch_task, GLOBAL_SCHEDULER},
+    scheduler::{fetch_task, scheduler},
     task::{context_switch, TaskContext},
     Task, TaskStatus,
 };
-use crate::{cpu_local, CpuLocal};
+use crate::{
+    arch::{
+        cpu::{send_ipi, this_cpu},
+        irq::{disable_local, enable_local, enable_local_and_halt},
+    },
+    cpu_local,
+    trap::IrqLine,
+    CpuLocal,
+};
 
 pub struct Processor {
     current: Option<Arc<Task>>,
-    /// A temporary variable used in [`switch_to_task`] to avoid dropping `current` while running
-    /// as `current`.
+    /// The task that was switched out, which is handled by [`finish_switch`] after the
+    /// context switch, i.e., after its context is saved.
     prev_task: Option<Arc<Task>>,
     idle_task_ctx: TaskContext,
 }
@@ -66,10 +76,71 @@ pub(crate) fn get_idle_task_ctx_ptr() -> *mut TaskContext {
     })
 }
 
-/// Calls this function to switch to other task by using GLOBAL_SCHEDULER
+cpu_local! {
+    /// Whether the current task should be switched out at the next preemption point, which
+    /// is set by the scheduler in the timer interrupts.
+    static NEED_RESCHED: AtomicBool = AtomicBool::new(false);
+}
+
+/// Accounts a timer tick to the current task, which is called in the timer interrupts.
+pub(crate) fn scheduler_tick() {
+    let Some(current_task) = current_task() else {
+        return;
+    };
+    if scheduler().tick(&current_task) {
+        set_need_resched();
+    }
+}
+
+/// Returns whether the current task should be switched out at the next preemption point.
+pub fn need_resched() -> bool {
+    NEED_RESCHED.load(Relaxed)
+}
+
+/// Asks the current task to be switched out at the next preemption point.
+pub(crate) fn set_need_resched() {
+    NEED_RESCHED.store(true, Relaxed);
+}
+
+/// The IRQ line of the inter-processor interrupts that ask the CPUs to reschedule.
+static RESCHED_IRQ: Once<IrqLine> = Once::new();
+
+pub(crate) fn init() {
+    let mut irq = IrqLine::alloc().unwrap();
+    irq.on_active(|_| set_need_resched());
+    RESCHED_IRQ.call_once(|| irq);
+}
+
+/// Asks the current task of the CPU to be switched out at the next preemption point.
+///
+/// If the CPU is another one, it is interrupted to reschedule, e.g., because a task is
+/// enqueued to its idle runqueue, or to preempt its current task.
+pub fn resched_cpu(cpu_id: u32) {
+    if cpu_id == this_cpu() {
+        set_need_resched();
+    } else if let Some(irq) = RESCHED_IRQ.get() {
+        send_ipi(cpu_id, irq.num());
+    }
+}
+
+/// Calls this function to switch to other task by using the global scheduler
+///
+/// If there is no current task, i.e., in the boot context of the CPU, the CPU runs its idle
+/// loop and this function does not return.
 pub fn schedule() {
+    NEED_RESCHED.store(false, Relaxed);
+    let Some(current) = current_task() else {
+        run_idle_loop();
+    };
+    let is_runnable = current.status() == TaskStatus::Runnable;
+    // The current task may be exiting, so the reference must not be left on its stack.
+    drop(current);
+
     if let Some(task) = fetch_task() {
-        switch_to_task(task);
+        switch_to_task(Some(task));
+    } else if !is_runnable {
+        // The current task is going to sleep or exit, and there is no other task to run.
+        switch_to_task(None);
     }
 }
 
@@ -82,25 +153,45 @@ pub fn schedule() {
 pub fn preempt(task: &Arc<Task>) {
     // TODO: Refactor `preempt` and `schedule`
     // after the Atomic mode and `might_break` is enabled.
-    let mut scheduler = GLOBAL_SCHEDULER.lock_irq_disabled();
-    if !scheduler.should_preempt(task) {
+    let scheduler = scheduler();
+    if !NEED_RESCHED.swap(false, Relaxed) && !scheduler.should_preempt(task) {
         return;
     }
-    let Some(next_task) = scheduler.dequeue() else {
+    let Some(next_task) = scheduler.pick_next() else {
         return;
     };
-    drop(scheduler);
-    switch_to_task(next_task);
+    switch_to_task(Some(next_task));
 }
 
-/// Calls this function to switch to other task
+/// Runs the idle loop of the current CPU, which switches to the tasks picked from the
+/// scheduler and halts the CPU if there is none.
 ///
-/// if current task is none, then it will use the default task context and it will not return to this function again
+/// The idle loop runs in the boot context of the CPU, which is switched back to when a
+/// task is switched out and there is no other task to run.
+pub(crate) fn run_idle_loop() -> ! {
+    loop {
+        // Disable the local IRQs so that a wakeup between picking a task and halting
+        // is not missed, since the IRQs are only enabled again by the halt.
+        disable_local();
+        NEED_RESCHED.store(false, Relaxed);
+        match fetch_task() {
+            Some(task) => {
+                enable_local();
+                switch_to_task(Some(task));
+            }
+            None => enable_local_and_halt(),
+        }
+    }
+}
+
+/// Switches from the current task to `next_task`, or to the idle loop if it is `None`.
 ///
-/// if current task status is exit, then it will not add to the scheduler
+/// If there is no current task, the context is saved as the one of the idle loop.
 ///
-/// before context switch, current task will switch to the next task
-fn switch_to_task(next_task: Arc<Task>) {
+/// The current task is enqueued again or put to sleep by [`finish_switch`] after its
+/// context is saved, since otherwise it might be picked and run on another CPU with the
+/// same kernel stack before the context switch completes.
+fn switch_to_task(next_task: Option<Arc<Task>>) {
     if !PREEMPT_COUNT.is_preemptive() {
         panic!(
             "Calling schedule() while holding {} locks",
@@ -111,28 +202,21 @@ fn switch_to_task(next_task: Arc<Task>) {
     let current_task_ctx_ptr = match current_task() {
         None => get_idle_task_ctx_ptr(),
         Some(current_task) => {
-            let ctx_ptr = current_task.ctx().get();
-
-            let mut task_inner = current_task.inner_exclusive_access();
+            debug_assert_ne!(current_task.status(), TaskStatus::Sleeping);
+            current_task.ctx().get()
+        }
+    };
 
-            debug_assert_ne!(task_inner.task_status, TaskStatus::Sleeping);
-            if task_inner.task_status == TaskStatus::Runnable {
-                drop(task_inner);
-                GLOBAL_SCHEDULER.lock_irq_disabled().enqueue(current_task);
-            } else if task_inner.task_status == TaskStatus::Sleepy {
-                task_inner.task_status = TaskStatus::Sleeping;
+    let next_task_ctx_ptr = match &next_task {
+        None => get_idle_task_ctx_ptr().cast_const(),
+        Some(next_task) => {
+            if let Some(next_user_space) = next_task.user_space() {
+                next_user_space.vm_space().activate();
             }
-
-            ctx_ptr
+            next_task.ctx().get().cast_const()
         }
     };
 
-    let next_task_ctx_ptr = next_task.ctx().get().cast_const();
-
-    if let Some(next_user_space) = next_task.user_space() {
-        next_user_space.vm_space().activate();
-    }
-
     // Change the current task to the next task.
     CpuLocal::borrow_with(&PROCESSOR, |processor| {
         let mut processor = processor.borrow_mut();
@@ -140,7 +224,8 @@ fn switch_to_task(next_task: Arc<Task>) {
         // We cannot directly overwrite `current` at this point. Since we are running as `current`,
         // we must avoid dropping `current`. Otherwise, the kernel stack may be unmapped, leading
         // to soundness problems.
-        let old_current = processor.current.replace(next_task);
+        let old_current = core::mem::replace(&mut processor.current, next_task);
+        debug_assert!(processor.prev_task.is_none());
         processor.prev_task = old_current;
     });
 
@@ -154,10 +239,33 @@ fn switch_to_task(next_task: Arc<Task>) {
         context_switch(current_task_ctx_ptr, next_task_ctx_ptr);
     }
 
-    // Now it's fine to drop `prev_task`. However, we choose not to do this because it is not
-    // always possible. For example, `context_switch` can switch directly to the entry point of the
-    // next task. Not dropping is just fine because the only consequence is that we delay the drop
-    // to the next task switching.
+    // The context switch may also go directly to the entry point of a new task, which
+    // calls this function instead.
+    finish_switch();
+}
+
+/// Handles the task that was switched out from the current CPU, whose context has been
+/// saved.
+///
+/// A runnable task is enqueued again, and a task going to sleep is marked as sleeping so
+/// that it is enqueued when it is woken up. An exited task is dropped here, which is fine
+/// since the CPU no longer runs on its kernel stack.
+pub(crate) fn finish_switch() {
+    let Some(prev_task) = CpuLocal::borrow_with(&PROCESSOR, |processor| {
+        processor.borrow_mut().prev_task.take()
+    }) else {
+        return;
+    };
+
+    let mut task_inner = prev_task.inner_exclusive_access();
+    match task_inner.task_status {
+        TaskStatus::Runnable => {
+            drop(task_inner);
+            prev_task.enqueue_to(scheduler());
+        }
+        TaskStatus::Sleepy => task_inner.task_status = TaskStatus::Sleeping,
+        TaskStatus::Sleeping | TaskStatus::Exited => (),
+    }
 }
 
 cpu_local! {
@@ -227,3 +335,62 @@ impl Drop for DisablePreemptGuard {
 pub fn disable_preempt() -> DisablePreemptGuard {
     DisablePreemptGuard::new()
 }
+
+#[cfg(ktest)]
+mod test {
+    use core::sync::atomic::{AtomicU32, Ordering};
+
+    use super::*;
+    use crate::{
+        arch::{read_tsc, tsc_freq},
+        cpu::num_cpus,
+        task::TaskOptions,
+    };
+
+    /// Spins until `cond` holds, and returns whether it holds within a second.
+    fn spin_until(cond: impl Fn() -> bool) -> bool {
+        let deadline = read_tsc() + tsc_freq();
+        while !cond() {
+            if read_tsc() >= deadline {
+                return false;
+            }
+            core::hint::spin_loop();
+        }
+        true
+    }
+
+    #[ktest]
+    fn resched_ipi_reaches_busy_cpu() {
+        // The task spawned below must run on another CPU while this one keeps running.
+        if num_cpus() < 2 {
+            return;
+        }
+
+        static TASK_CPU: AtomicU32 = AtomicU32::new(u32::MAX);
+        static RESCHEDULED: AtomicBool = AtomicBool::new(false);
+
+        let _ = TaskOptions::new(|| {
+            TASK_CPU.store(this_cpu(), Ordering::Release);
+            // The FIFO scheduler of the ktests never asks for a reschedule in the ticks,
+            // so the flag can only be set by a resched IPI.
+            while !need_resched() {
+                core::hint::spin_loop();
+            }
+            RESCHEDULED.store(true, Ordering::Release);
+        })
+        .data(())
+        .spawn();
+
+        assert!(spin_until(|| TASK_CPU.load(Ordering::Acquire) != u32::MAX));
+        let task_cpu = TASK_CPU.load(Ordering::Acquire);
+        assert_ne!(task_cpu, this_cpu());
+        assert!(!RESCHEDULED.load(Ordering::Acquire));
+
+        resched_cpu(task_cpu);
+        assert!(
+            spin_until(|| RESCHEDULED.load(Ordering::Acquire)),
+            "CPU {} misses the resched IPI",
+            task_cpu
+        );
+    }
+}
@@ -3,69 +3,157 @@
 #![allow(dead_code)]
 
 use alloc::collections::VecDeque;
+use core::sync::atomic::{AtomicU32, AtomicU64, Ordering::Relaxed};
 
-use lazy_static::lazy_static;
+use spin::Once;
 
+use super::processor::{current_task, set_need_resched};
 use crate::{prelude::*, sync::SpinLock, task::Task};
 
-lazy_static! {
-    pub(crate) static ref GLOBAL_SCHEDULER: SpinLock<GlobalScheduler> =
-        SpinLock::new(GlobalScheduler { scheduler: None });
-}
+/// The scheduler of all the tasks, which is set once before any task is spawned.
+///
+/// The scheduler serializes its operations itself, e.g., with per-CPU runqueues, so the
+/// CPUs do not contend for a global lock when they pick the tasks.
+static SCHEDULER: Once<&'static dyn Scheduler> = Once::new();
 
-/// A scheduler for tasks.
+/// A scheduling policy for tasks.
+///
+/// The tasks in a scheduler are runnable but not running. The running task of a CPU is
+/// picked out of the scheduler by [`Scheduler::pick_next`], and is enqueued again when it
+/// is switched out while still runnable. An implementation of scheduler can keep its
+/// per-task information in the [`SchedEntity`] returned from `task.sched_entity()`.
 ///
-/// An implementation of scheduler can attach scheduler-related information
-/// with the `TypeMap` returned from `task.data()`.
+/// The methods may be called in the interrupt context, e.g., [`Scheduler::tick`] and
+/// [`Scheduler::enqueue`] of a woken task, so the locks of an implementation must
+/// disable the local IRQs.
 pub trait Scheduler: Sync + Send {
-    /// Enqueues a task to the scheduler.
+    /// Enqueues a runnable task to the scheduler.
     fn enqueue(&self, task: Arc<Task>);
 
-    /// Dequeues a task from the scheduler.
-    fn dequeue(&self) -> Option<Arc<Task>>;
-
-    /// Tells whether the given task should be preempted by other tasks in the queue.
-    fn should_preempt(&self, task: &Arc<Task>) -> bool;
-}
-
-pub struct GlobalScheduler {
-    scheduler: Option<&'static dyn Scheduler>,
-}
+    /// Picks the next task to run on the current CPU, and removes it from the scheduler.
+    fn pick_next(&self) -> Option<Arc<Task>>;
 
-impl GlobalScheduler {
-    pub fn new() -> Self {
-        Self { scheduler: None }
-    }
+    /// Removes the task from the scheduler.
+    ///
+    /// Returns whether the task was in the scheduler.
+    fn dequeue(&self, task: &Arc<Task>) -> bool;
 
-    /// dequeue a task using scheduler
-    /// require the scheduler is not none
-    pub fn dequeue(&mut self) -> Option<Arc<Task>> {
-        self.scheduler.unwrap().dequeue()
-    }
-    /// enqueue a task using scheduler
-    /// require the scheduler is not none
-    pub fn enqueue(&mut self, task: Arc<Task>) {
-        self.scheduler.unwrap().enqueue(task)
-    }
+    /// Accounts a timer tick to the task that is running on the current CPU.
+    ///
+    /// Returns whether the task should be switched out at the next preemption point.
+    fn tick(&self, current: &Arc<Task>) -> bool;
 
-    pub fn should_preempt(&self, task: &Arc<Task>) -> bool {
-        self.scheduler.unwrap().should_preempt(task)
-    }
+    /// Tells whether the given task should be preempted by other tasks in the scheduler.
+    fn should_preempt(&self, task: &Arc<Task>) -> bool;
 }
+
 /// Set the global task scheduler.
 ///
-/// This must be called before invoking `Task::spawn`.
+/// This must be called before invoking `Task::spawn`. Only the first scheduler that is
+/// set takes effect.
 pub fn set_scheduler(scheduler: &'static dyn Scheduler) {
-    GLOBAL_SCHEDULER.lock_irq_disabled().scheduler = Some(scheduler);
+    SCHEDULER.call_once(|| scheduler);
+}
+
+/// Returns the global task scheduler.
+///
+/// # Panics
+///
+/// Panics if the scheduler is not set yet.
+pub(crate) fn scheduler() -> &'static dyn Scheduler {
+    *SCHEDULER.get().expect("the scheduler is not set")
 }
 
+/// Picks the next task to run on the current CPU.
+///
+/// Returns `None` if the scheduler is not set yet, which happens in the idle loops of the
+/// CPUs that are started early.
 pub fn fetch_task() -> Option<Arc<Task>> {
-    GLOBAL_SCHEDULER.lock_irq_disabled().dequeue()
+    SCHEDULER.get()?.pick_next()
 }
 
 /// Adds a task to the global scheduler.
+///
+/// The current task is switched out at the next preemption point if the added task, e.g.,
+/// a woken one, should preempt it.
 pub fn add_task(task: Arc<Task>) {
-    GLOBAL_SCHEDULER.lock_irq_disabled().enqueue(task);
+    let scheduler = scheduler();
+    task.enqueue_to(scheduler);
+    if current_task().is_some_and(|current| scheduler.should_preempt(&current)) {
+        set_need_resched();
+    }
+}
+
+/// The scheduling information of a task, which is maintained by the scheduler.
+///
+/// The times are in nanoseconds. The accesses are serialized by the scheduler, so the
+/// fields are only atomic to be shared with the task.
+#[derive(Debug, Default)]
+pub struct SchedEntity {
+    /// The virtual runtime, i.e., the runtime weighted by the priority.
+    vruntime: AtomicU64,
+    /// The time when the task is picked to run, or is accounted the last time.
+    exec_start: AtomicU64,
+    /// The total runtime.
+    sum_exec_runtime: AtomicU64,
+    /// The total runtime when the task is picked to run.
+    prev_sum_exec_runtime: AtomicU64,
+    /// The CPU whose runqueue the task is, or was last, in.
+    cpu: AtomicU32,
+}
+
+impl SchedEntity {
+    /// Returns the virtual runtime.
+    pub fn vruntime(&self) -> u64 {
+        self.vruntime.load(Relaxed)
+    }
+
+    /// Sets the virtual runtime.
+    pub fn set_vruntime(&self, vruntime: u64) {
+        self.vruntime.store(vruntime, Relaxed);
+    }
+
+    /// Returns the time when the task is picked to run, or is accounted the last time.
+    pub fn exec_start(&self) -> u64 {
+        self.exec_start.load(Relaxed)
+    }
+
+    /// Sets the time when the task is picked to run, or is accounted.
+    pub fn set_exec_start(&self, now: u64) {
+        self.exec_start.store(now, Relaxed);
+    }
+
+    /// Returns the total runtime.
+    pub fn sum_exec_runtime(&self) -> u64 {
+        self.sum_exec_runtime.load(Relaxed)
+    }
+
+    /// Adds the runtime since the last accounting to the total runtime.
+    pub fn add_exec_runtime(&self, delta: u64) {
+        self.sum_exec_runtime.fetch_add(delta, Relaxed);
+    }
+
+    /// Returns the runtime since the task is picked to run.
+    pub fn slice_runtime(&self) -> u64 {
+        self.sum_exec_runtime() - self.prev_sum_exec_runtime.load(Relaxed)
+    }
+
+    /// Starts a new time slice, which begins when the task is picked to run.
+    pub fn start_slice(&self, now: u64) {
+        self.set_exec_start(now);
+        self.prev_sum_exec_runtime
+            .store(self.sum_exec_runtime(), Relaxed);
+    }
+
+    /// Returns the CPU whose runqueue the task is, or was last, in.
+    pub fn cpu(&self) -> u32 {
+        self.cpu.load(Relaxed)
+    }
+
+    /// Sets the CPU whose runqueue the task is in.
+    pub fn set_cpu(&self, cpu: u32) {
+        self.cpu.store(cpu, Relaxed);
+    }
 }
 
 /// A simple FIFO (First-In-First-Out) task scheduler.
@@ -94,10 +182,26 @@ impl Scheduler for FifoScheduler {
     fn enqueue(&self, task: Arc<Task>) {
         self.task_queue.lock_irq_disabled().push_back(task);
     }
-    /// Dequeues a task from the front of the queue, if any.
-    fn dequeue(&self) -> Option<Arc<Task>> {
+    /// Picks the task at the front of the queue, if any.
+    fn pick_next(&self) -> Option<Arc<Task>> {
         self.task_queue.lock_irq_disabled().pop_front()
     }
+    /// Removes the task from wherever it is in the queue.
+    fn dequeue(&self, task: &Arc<Task>) -> bool {
+        let mut task_queue = self.task_queue.lock_irq_disabled();
+        let Some(index) = task_queue
+            .iter()
+            .position(|queued| Arc::ptr_eq(queued, task))
+        else {
+            return false;
+        };
+        task_queue.remove(index);
+        true
+    }
+    /// In this simple implementation, there are no time slices.
+    fn tick(&self, _current: &Arc<Task>) -> bool {
+        false
+    }
     /// In this simple implementation, task preemption is not supported.
     /// Once a task starts running, it will continue to run until completion.
     fn should_preempt(&self, _task: &Arc<Task>) -> bool {
@@ -0,0 +1,210 @@
+// SPDX-License-Identifier: MPL-2.0
+
+//! Scoped closures that can borrow the data on the stack.
+//!
+//! The executors of the kernel, e.g., new tasks or the work queues, only accept `'static`
+//! closures, since they may run the closures at any time later. A [`scope`] lifts this
+//! restriction for the closures spawned in it, by not returning until all of them have
+//! been run or dropped, even if the scope is unwound by a panic.
+//!
+//! # Examples
+//!
+//! ```rust
+//! use aster_frame::{
+//!     sync::SpinLock,
+//!     task::{scope, TaskOptions},
+//! };
+//!
+//! let mut chunks = [[0u8; 4096]; 4];
+//! scope(|s| {
+//!     for chunk in chunks.iter_mut() {
+//!         s.spawn(move || chunk.fill(1), |func| {
+//!             let func = SpinLock::new(Some(func));
+//!             TaskOptions::new(move || func.lock().take().unwrap()())
+//!                 .data(())
+//!                 .spawn()
+//!                 .unwrap();
+//!         });
+//!     }
+//! });
+//! ```
+
+use alloc::{boxed::Box, sync::Arc};
+use core::{
+    marker::PhantomData,
+    sync::atomic::{AtomicUsize, Ordering},
+};
+
+use crate::sync::WaitQueue;
+
+/// A closure spawned in a [`Scope`], which is handed to an executor.
+pub type ScopedFn = Box<dyn FnOnce() + Send + 'static>;
+
+/// Creates a scope where the spawned closures can borrow the non-`'static` data.
+///
+/// All the closures spawned in the scope are run or dropped before this function
+/// returns. If `f` panics, this function still waits for them before the panic
+/// propagates.
+pub fn scope<'env, F, R>(f: F) -> R
+where
+    F: for<'scope> FnOnce(&'scope Scope<'scope, 'env>) -> R,
+{
+    let scope = Scope {
+        state: Arc::new(ScopeState::new()),
+        scope: PhantomData,
+        env: PhantomData,
+    };
+    // Wait in the destructor, which also runs when the stack is unwound.
+    let _guard = WaitGuard(&scope.state);
+    f(&scope)
+}
+
+/// A scope of closures, which is created by [`scope`].
+pub struct Scope<'scope, 'env: 'scope> {
+    state: Arc<ScopeState>,
+    scope: PhantomData<&'scope mut &'scope ()>,
+    env: PhantomData<&'env mut &'env ()>,
+}
+
+impl<'scope, 'env> Scope<'scope, 'env> {
+    /// Spawns `f` with `spawner`, e.g., by submitting it to a work queue.
+    ///
+    /// The spawner is given a `'static` closure, which should be run or dropped
+    /// eventually. Otherwise, the scope never ends.
+    pub fn spawn<F, S>(&'scope self, f: F, spawner: S)
+    where
+        F: FnOnce() + Send + 'scope,
+        S: FnOnce(ScopedFn),
+    {
+        self.state.nr_running.fetch_add(1, Ordering::Relaxed);
+
+        let f: Box<dyn FnOnce() + Send + 'scope> = Box::new(f);
+        // SAFETY: The closure is owned by `ScopedClosure`, which decreases the number of
+        // the running closures only after the closure has been run or dropped. `scope`
+        // does not return or unwind past the borrowed data until the number drops to zero,
+        // so the data outlive any use of the closure.
+        let f: Box<dyn FnOnce() + Send + 'static> = unsafe { core::mem::transmute(f) };
+        let mut closure = ScopedClosure {
+            f: Some(f),
+            state: self.state.clone(),
+        };
+        spawner(Box::new(move || closure.run()));
+    }
+}
+
+/// A spawned closure, which tells the scope that it is done once it is dropped.
+struct ScopedClosure {
+    f: Option<Box<dyn FnOnce() + Send + 'static>>,
+    state: Arc<ScopeState>,
+}
+
+impl ScopedClosure {
+    fn run(&mut self) {
+        if let Some(f) = self.f.take() {
+            f();
+        }
+    }
+}
+
+impl Drop for ScopedClosure {
+    fn drop(&mut self) {
+        // Drop the closure, and the data that it borrows, before telling the scope.
+        drop(self.f.take());
+        self.state.finish_one();
+    }
+}
+
+struct ScopeState {
+    nr_running: AtomicUsize,
+    wait_queue: WaitQueue,
+}
+
+impl ScopeState {
+    fn new() -> Self {
+        Self {
+            nr_running: AtomicUsize::new(0),
+            wait_queue: WaitQueue::new(),
+        }
+    }
+
+    fn finish_one(&self) {
+        if self.nr_running.fetch_sub(1, Ordering::Release) == 1 {
+            self.wait_queue.wake_all();
+        }
+    }
+}
+
+struct WaitGuard<'a>(&'a ScopeState);
+
+impl Drop for WaitGuard<'_> {
+    fn drop(&mut self) {
+        let state = self.0;
+        state
+            .wait_queue
+            .wait_until(|| (state.nr_running.load(Ordering::Acquire) == 0).then_some(()));
+    }
+}
+
+#[cfg(ktest)]
+mod test {
+    use core::sync::atomic::AtomicBool;
+
+    use super::*;
+    use crate::{
+        panicking::catch_panic,
+        sync::SpinLock,
+        task::{Task, TaskOptions},
+    };
+
+    /// Runs the closure in a new task after the current task yields a few times.
+    fn spawn_task(func: ScopedFn) {
+        let func = SpinLock::new(Some(func));
+        TaskOptions::new(move || {
+            for _ in 0..10 {
+                Task::yield_now();
+            }
+            if let Some(func) = func.lock().take() {
+                func();
+            }
+        })
+        .data(())
+        .spawn()
+        .unwrap();
+    }
+
+    #[ktest]
+    fn join_before_return() {
+        let mut chunks = [[0u8; 64]; 4];
+        scope(|s| {
+            for (i, chunk) in chunks.iter_mut().enumerate() {
+                s.spawn(move || chunk.fill(i as u8 + 1), spawn_task);
+            }
+        });
+        for (i, chunk) in chunks.iter().enumerate() {
+            assert!(chunk.iter().all(|byte| *byte == i as u8 + 1));
+        }
+    }
+
+    #[ktest]
+    fn join_before_unwind() {
+        let is_done = AtomicBool::new(false);
+        let result = catch_panic(|| {
+            scope(|s| {
+                s.spawn(|| is_done.store(true, Ordering::Relaxed), spawn_task);
+                panic!("the scope panics");
+            })
+        });
+        assert!(result.is_err());
+        // The closure has finished before the panic is caught.
+        assert!(is_done.load(Ordering::Relaxed));
+    }
+
+    #[ktest]
+    fn dropped_closure_ends_scope() {
+        let is_run = AtomicBool::new(false);
+        scope(|s| {
+            s.spawn(|| is_run.store(true, Ordering::Relaxed), drop);
+        });
+        assert!(!is_run.load(Ordering::Relaxed));
+    }
+}
@@ -5,14 +5,18 @@
 #![allow(missing_docs)]
 #![allow(dead_code)]
 
-use core::cell::UnsafeCell;
+use core::{
+    cell::UnsafeCell,
+    sync::atomic::{AtomicU16, AtomicU8, AtomicUsize, Ordering},
+};
 
 use intrusive_collections::{intrusive_adapter, LinkedListAtomicLink};
 
 use super::{
     add_task,
-    priority::Priority,
-    processor::{current_task, schedule},
+    priority::{Priority, SchedPolicy},
+    processor::{current_task, finish_switch, schedule, set_need_resched},
+    scheduler::{scheduler, SchedEntity, Scheduler},
 };
 pub(crate) use crate::arch::task::{context_switch, TaskContext};
 use crate::{
@@ -21,9 +25,24 @@ use crate::{
     prelude::*,
     sync::{SpinLock, SpinLockGuard},
     user::UserSpace,
+    Error,
 };
 
+/// The default size of the kernel stack of a task.
 pub const KERNEL_STACK_SIZE: usize = PAGE_SIZE * 64;
+/// The size of the kernel stack of a task that runs deep call chains, e.g., a flusher of
+/// file systems or a worker that does crypto.
+pub const LARGE_KERNEL_STACK_SIZE: usize = PAGE_SIZE * 128;
+/// The maximum size of the kernel stack of a task.
+pub const MAX_KERNEL_STACK_SIZE: usize = PAGE_SIZE * 1024;
+
+/// The pattern that the kernel stacks are filled with in the debug builds, so that the
+/// high-water marks can be found by the first words that are overwritten.
+#[cfg(debug_assertions)]
+const STACK_FILL_PATTERN: u64 = 0x5354_4143_4b5f_5553;
+/// The ratio of the usage of a kernel stack, in percent, above which the usage is warned
+/// at the task exit.
+const STACK_USAGE_WARN_PERCENT: usize = 75;
 
 /// Trait for manipulating the task context.
 pub trait TaskContextApi {
@@ -46,18 +65,21 @@ pub struct KernelStack {
 }
 
 impl KernelStack {
-    pub fn new() -> Result<Self> {
-        Ok(Self {
-            segment: FrameAllocOptions::new(KERNEL_STACK_SIZE / PAGE_SIZE).alloc_contiguous()?,
+    /// Generates a kernel stack of `size` bytes, which are rounded up to pages.
+    pub fn new(size: usize) -> Result<Self> {
+        let stack = Self {
+            segment: FrameAllocOptions::new(size.div_ceil(PAGE_SIZE)).alloc_contiguous()?,
             has_guard_page: false,
-        })
+        };
+        stack.fill_pattern();
+        Ok(stack)
     }
 
-    /// Generates a kernel stack with a guard page.
-    /// An additional page is allocated and be regarded as a guard page, which should not be accessed.  
-    pub fn new_with_guard_page() -> Result<Self> {
+    /// Generates a kernel stack of `size` bytes with a guard page.
+    /// An additional page is allocated and be regarded as a guard page, which should not be accessed.
+    pub fn new_with_guard_page(size: usize) -> Result<Self> {
         let stack_segment =
-            FrameAllocOptions::new(KERNEL_STACK_SIZE / PAGE_SIZE + 1).alloc_contiguous()?;
+            FrameAllocOptions::new(size.div_ceil(PAGE_SIZE) + 1).alloc_contiguous()?;
         // FIXME: modifying the the linear mapping is bad.
         let page_table = KERNEL_PAGE_TABLE.get().unwrap();
         let guard_page_vaddr = {
@@ -72,15 +94,88 @@ impl KernelStack {
                 })
                 .unwrap();
         }
-        Ok(Self {
+        let stack = Self {
             segment: stack_segment,
             has_guard_page: true,
-        })
+        };
+        stack.fill_pattern();
+        Ok(stack)
     }
 
     pub fn end_paddr(&self) -> Paddr {
         self.segment.end_paddr()
     }
+
+    /// Returns the size of the usable stack, which excludes the guard page.
+    pub fn size(&self) -> usize {
+        self.usable_range().len()
+    }
+
+    /// Returns the number of bytes of the stack that have ever been used, i.e., the
+    /// high-water mark, which is only tracked in the debug builds.
+    pub fn max_usage(&self) -> Option<usize> {
+        #[cfg(debug_assertions)]
+        {
+            let range = self.usable_range();
+            let words = range.len() / core::mem::size_of::<u64>();
+            // SAFETY: the words are in the stack, which is mapped as long as `self` is alive.
+            // The stack grows downwards, so the untouched words, if any, are at the bottom,
+            // which is not used by anyone if the stack is in use.
+            let untouched_words = (0..words)
+                .take_while(|&i| unsafe {
+                    core::ptr::read_volatile((range.start as *const u64).add(i))
+                        == STACK_FILL_PATTERN
+                })
+                .count();
+            Some(range.len() - untouched_words * core::mem::size_of::<u64>())
+        }
+        #[cfg(not(debug_assertions))]
+        None
+    }
+
+    /// Returns the virtual addresses of the usable stack.
+    fn usable_range(&self) -> core::ops::Range<Vaddr> {
+        let start_paddr = if self.has_guard_page {
+            self.segment.start_paddr() + PAGE_SIZE
+        } else {
+            self.segment.start_paddr()
+        };
+        crate::mm::paddr_to_vaddr(start_paddr)..crate::mm::paddr_to_vaddr(self.end_paddr())
+    }
+
+    fn fill_pattern(&self) {
+        #[cfg(debug_assertions)]
+        {
+            let range = self.usable_range();
+            let words = range.len() / core::mem::size_of::<u64>();
+            // SAFETY: the stack is newly allocated and not used by anyone.
+            unsafe {
+                core::slice::from_raw_parts_mut(range.start as *mut u64, words)
+                    .fill(STACK_FILL_PATTERN);
+            }
+        }
+    }
+
+    /// Reports the high-water mark of the stack, and warns if it is close to the size.
+    fn report_usage(&self) {
+        let Some(max_usage) = self.max_usage() else {
+            return;
+        };
+        let size = self.size();
+        if max_usage * 100 >= size * STACK_USAGE_WARN_PERCENT {
+            log::warn!(
+                "a task has used {} of its {} bytes of kernel stack",
+                max_usage,
+                size
+            );
+            return;
+        }
+        log::debug!(
+            "a task has used {} of its {} bytes of kernel stack",
+            max_usage,
+            size
+        );
+    }
 }
 
 impl Drop for KernelStack {
@@ -118,9 +213,23 @@ pub struct Task {
     /// kernel stack, note that the top is SyscallFrame/TrapFrame
     kstack: KernelStack,
     link: LinkedListAtomicLink,
-    priority: Priority,
-    // TODO: add multiprocessor support
-    cpu_affinity: CpuSet,
+    /// The value of the base [`Priority`], which is changed with [`Task::set_priority`] or
+    /// [`Task::set_sched_policy`].
+    priority: AtomicU16,
+    /// The value of the [`Priority`] set with [`Task::inherit_priority`], or
+    /// [`NO_INHERITED_PRIORITY`].
+    inherited_priority: AtomicU16,
+    /// The value of the base [`SchedPolicy`].
+    policy: AtomicU8,
+    sched_entity: SchedEntity,
+    /// The CPUs that the task can run on, which is changed with [`Task::set_cpu_affinity`].
+    cpu_affinity: SpinLock<CpuSet>,
+    /// The lock that serializes the enqueueing of the task with the updates of its
+    /// scheduling attributes, so that the task is never enqueued with the attributes that
+    /// are being changed.
+    requeue_lock: SpinLock<()>,
+    /// The number of the ongoing calls of [`crate::panicking::catch_panic`] in the task.
+    nr_panic_catchers: AtomicUsize,
 }
 
 // TaskAdapter struct is implemented for building relationships between doubly linked list and Task struct
@@ -130,6 +239,10 @@ intrusive_adapter!(pub TaskAdapter = Arc<Task>: Task { link: LinkedListAtomicLin
 // we have exclusive access to the field.
 unsafe impl Sync for Task {}
 
+/// The value of the inherited priority if the task inherits no priority, which is lower
+/// than any priority.
+const NO_INHERITED_PRIORITY: u16 = u16::MAX;
+
 pub(crate) struct TaskInner {
     pub task_status: TaskStatus,
 }
@@ -189,6 +302,7 @@ impl Task {
     /// **NOTE:** If there is anything left on the stack, it will be forgotten. This behavior may
     /// lead to resource leakage.
     fn exit(self: Arc<Self>) -> ! {
+        self.kstack.report_usage();
         self.inner_exclusive_access().task_status = TaskStatus::Exited;
 
         // `current_task()` still holds a strong reference, so nothing is destroyed at this point,
@@ -199,9 +313,162 @@ impl Task {
         unreachable!()
     }
 
+    /// Returns the size of the kernel stack of the task.
+    pub fn kernel_stack_size(&self) -> usize {
+        self.kstack.size()
+    }
+
+    /// Returns the number of bytes of the kernel stack that the task has ever used.
+    ///
+    /// The usage is only tracked in the debug builds, where the kernel stacks are filled
+    /// with a pattern when they are created. `None` is returned in the release builds.
+    pub fn kernel_stack_max_usage(&self) -> Option<usize> {
+        self.kstack.max_usage()
+    }
+
+    /// Returns whether a panic of the task is going to be caught by
+    /// [`crate::panicking::catch_panic`].
+    pub(crate) fn is_catching_panic(&self) -> bool {
+        self.nr_panic_catchers.load(Ordering::Relaxed) > 0
+    }
+
+    pub(crate) fn nr_panic_catchers(&self) -> &AtomicUsize {
+        &self.nr_panic_catchers
+    }
+
+    /// Returns the priority of the task, which is the higher one of its base priority and
+    /// its inherited priority.
+    pub fn priority(&self) -> Priority {
+        let priority = self.priority.load(Ordering::Relaxed);
+        let inherited_priority = self.inherited_priority.load(Ordering::Relaxed);
+        Priority::new(priority.min(inherited_priority))
+    }
+
+    /// Returns the base priority of the task, regardless of its inherited priority.
+    pub fn base_priority(&self) -> Priority {
+        Priority::new(self.priority.load(Ordering::Relaxed))
+    }
+
+    /// Sets the base priority of the task.
+    ///
+    /// If the task is in the scheduler, it is enqueued again to be scheduled with the new
+    /// priority.
+    ///
+    /// It fails with [`Error::InvalidArgs`] if the priority does not match the base
+    /// scheduling policy, i.e., only one of them is real-time. The policy is checked and
+    /// the priority is updated atomically with respect to [`Task::set_sched_policy`].
+    pub fn set_priority(self: &Arc<Self>, priority: Priority) -> Result<()> {
+        self.requeue_with(|| {
+            if self.base_sched_policy().is_real_time() != priority.is_real_time() {
+                return Err(Error::InvalidArgs);
+            }
+            self.priority.store(priority.get(), Ordering::Relaxed);
+            Ok(())
+        })
+    }
+
+    /// Returns the scheduling policy of the task.
+    ///
+    /// A normal task that inherits a real-time priority is scheduled with
+    /// [`SchedPolicy::Fifo`].
+    pub fn sched_policy(&self) -> SchedPolicy {
+        let policy = self.base_sched_policy();
+        if !policy.is_real_time() && self.priority().is_real_time() {
+            return SchedPolicy::Fifo;
+        }
+        policy
+    }
+
+    /// Returns the base scheduling policy of the task, regardless of its inherited priority.
+    pub fn base_sched_policy(&self) -> SchedPolicy {
+        SchedPolicy::from_raw(self.policy.load(Ordering::Relaxed))
+    }
+
+    /// Sets the base scheduling policy and the base priority of the task.
+    ///
+    /// It fails with [`Error::InvalidArgs`] if only one of the policy and the priority is
+    /// real-time.
+    pub fn set_sched_policy(
+        self: &Arc<Self>,
+        policy: SchedPolicy,
+        priority: Priority,
+    ) -> Result<()> {
+        if policy.is_real_time() != priority.is_real_time() {
+            return Err(Error::InvalidArgs);
+        }
+        self.requeue_with(|| {
+            self.policy.store(policy as u8, Ordering::Relaxed);
+            self.priority.store(priority.get(), Ordering::Relaxed);
+        });
+        Ok(())
+    }
+
+    /// Lets the task inherit the priority, or stop inheriting any priority if `priority` is
+    /// `None`.
+    ///
+    /// This is the hook of the priority inheritance. For example, the owner of a lock
+    /// inherits the highest priority of the waiters, so that it is not preempted by the
+    /// tasks whose priorities are between, while the waiters wait.
+    pub fn inherit_priority(self: &Arc<Self>, priority: Option<Priority>) {
+        let inherited_priority = priority.map_or(NO_INHERITED_PRIORITY, Priority::get);
+        self.requeue_with(|| {
+            self.inherited_priority
+                .store(inherited_priority, Ordering::Relaxed)
+        });
+    }
+
+    /// Enqueues the task to the scheduler.
+    pub(super) fn enqueue_to(self: &Arc<Self>, scheduler: &dyn Scheduler) {
+        let _guard = self.requeue_lock.lock_irq_disabled();
+        scheduler.enqueue(self.clone());
+    }
+
+    /// Updates the scheduling attributes of the task with `update`, which is serialized
+    /// with the other updates and the enqueueing of the task.
+    ///
+    /// If the task is in the scheduler, it is enqueued again with the new attributes. If the
+    /// task is running, it may be preempted at the next preemption point.
+    fn requeue_with<T>(self: &Arc<Self>, update: impl FnOnce() -> T) -> T {
+        let _guard = self.requeue_lock.lock_irq_disabled();
+        let scheduler = scheduler();
+        let is_queued = scheduler.dequeue(self);
+        let res = update();
+        if is_queued {
+            scheduler.enqueue(self.clone());
+        } else if current_task().is_some_and(|current| Arc::ptr_eq(&current, self))
+            && scheduler.should_preempt(self)
+        {
+            set_need_resched();
+        }
+        res
+    }
+
+    /// Returns the set of the CPUs that the task can run on.
+    pub fn cpu_affinity(&self) -> CpuSet {
+        self.cpu_affinity.lock_irq_disabled().clone()
+    }
+
+    /// Returns whether the task can run on the CPU.
+    pub fn can_run_on(&self, cpu_id: u32) -> bool {
+        self.cpu_affinity.lock_irq_disabled().contains(cpu_id)
+    }
+
+    /// Sets the set of the CPUs that the task can run on.
+    ///
+    /// If the task is in the scheduler, it is enqueued again to be placed on one of the
+    /// CPUs. If the task is running, it is placed when it is switched out.
+    pub fn set_cpu_affinity(self: &Arc<Self>, cpu_affinity: CpuSet) {
+        self.requeue_with(|| *self.cpu_affinity.lock_irq_disabled() = cpu_affinity);
+    }
+
     /// Checks if the task has a real-time priority.
     pub fn is_real_time(&self) -> bool {
-        self.priority.is_real_time()
+        self.priority().is_real_time()
+    }
+
+    /// Returns the scheduling information of the task, which is maintained by the scheduler.
+    pub fn sched_entity(&self) -> &SchedEntity {
+        &self.sched_entity
     }
 }
 
@@ -225,6 +492,7 @@ pub struct TaskOptions {
     user_space: Option<Arc<UserSpace>>,
     priority: Priority,
     cpu_affinity: CpuSet,
+    kernel_stack_size: usize,
 }
 
 impl TaskOptions {
@@ -240,6 +508,7 @@ impl TaskOptions {
             user_space: None,
             priority: Priority::normal(),
             cpu_affinity,
+            kernel_stack_size: KERNEL_STACK_SIZE,
         }
     }
 
@@ -268,6 +537,9 @@ impl TaskOptions {
     }
 
     /// Sets the priority of the task.
+    ///
+    /// A task with a real-time priority is scheduled with [`SchedPolicy::Fifo`], which
+    /// can be changed with [`Task::set_sched_policy`].
     pub fn priority(mut self, priority: Priority) -> Self {
         self.priority = priority;
         self
@@ -282,17 +554,35 @@ impl TaskOptions {
         self
     }
 
+    /// Sets the size of the kernel stack of the task, which is rounded up to pages.
+    ///
+    /// The default size is [`KERNEL_STACK_SIZE`]. The tasks that run deep call chains
+    /// may need a larger one, e.g., [`LARGE_KERNEL_STACK_SIZE`]. The size is clamped to
+    /// [`MAX_KERNEL_STACK_SIZE`].
+    pub fn kernel_stack_size(mut self, size: usize) -> Self {
+        self.kernel_stack_size = size.clamp(PAGE_SIZE, MAX_KERNEL_STACK_SIZE);
+        self
+    }
+
     /// Builds a new task without running it immediately.
     pub fn build(self) -> Result<Arc<Task>> {
         /// all task will entering this function
         /// this function is mean to executing the task_fn in Task
         extern "C" fn kernel_task_entry() {
+            finish_switch();
+
             let current_task = current_task()
                 .expect("no current task, it should have current task in kernel task entry");
             current_task.func.call(());
             current_task.exit();
         }
 
+        // The real-time tasks are scheduled in the FIFO order by default.
+        let policy = if self.priority.is_real_time() {
+            SchedPolicy::Fifo
+        } else {
+            SchedPolicy::Normal
+        };
         let mut new_task = Task {
             func: self.func.unwrap(),
             data: self.data.unwrap(),
@@ -301,10 +591,15 @@ impl TaskOptions {
                 task_status: TaskStatus::Runnable,
             }),
             ctx: UnsafeCell::new(TaskContext::default()),
-            kstack: KernelStack::new_with_guard_page()?,
+            kstack: KernelStack::new_with_guard_page(self.kernel_stack_size)?,
             link: LinkedListAtomicLink::new(),
-            priority: self.priority,
-            cpu_affinity: self.cpu_affinity,
+            priority: AtomicU16::new(self.priority.get()),
+            inherited_priority: AtomicU16::new(NO_INHERITED_PRIORITY),
+            policy: AtomicU8::new(policy as u8),
+            sched_entity: SchedEntity::default(),
+            cpu_affinity: SpinLock::new(self.cpu_affinity),
+            nr_panic_catchers: AtomicUsize::new(0),
+            requeue_lock: SpinLock::new(()),
         };
 
         let ctx = new_task.ctx.get_mut();
@@ -351,4 +646,67 @@ mod test {
         };
         let _ = crate::task::TaskOptions::new(task).data(()).spawn();
     }
+
+    #[ktest]
+    fn kernel_stack_size() {
+        use crate::{
+            mm::PAGE_SIZE,
+            task::{TaskOptions, KERNEL_STACK_SIZE, LARGE_KERNEL_STACK_SIZE},
+        };
+
+        let task = TaskOptions::new(|| {}).data(()).build().unwrap();
+        assert_eq!(task.kernel_stack_size(), KERNEL_STACK_SIZE);
+
+        let task = TaskOptions::new(|| {})
+            .data(())
+            .kernel_stack_size(LARGE_KERNEL_STACK_SIZE + 1)
+            .build()
+            .unwrap();
+        assert_eq!(
+            task.kernel_stack_size(),
+            LARGE_KERNEL_STACK_SIZE + PAGE_SIZE
+        );
+    }
+
+    #[ktest]
+    fn kernel_stack_max_usage() {
+        let task = crate::task::TaskOptions::new(|| {})
+            .data(())
+            .build()
+            .unwrap();
+        // The task has not run, so only the initial context may be on the stack.
+        if let Some(max_usage) = task.kernel_stack_max_usage() {
+            assert!(max_usage < crate::mm::PAGE_SIZE);
+        }
+    }
+
+    #[ktest]
+    fn sleep_and_wake_across_schedule() {
+        use alloc::sync::Arc;
+        use core::sync::atomic::{AtomicBool, Ordering};
+
+        use crate::{
+            sync::WaitQueue,
+            task::{current_task, TaskOptions, TaskStatus},
+        };
+
+        let wait_queue = Arc::new(WaitQueue::new());
+        let is_woken = Arc::new(AtomicBool::new(false));
+
+        let waker_task = {
+            let wait_queue = wait_queue.clone();
+            let is_woken = is_woken.clone();
+            move || {
+                is_woken.store(true, Ordering::Release);
+                wait_queue.wake_all();
+            }
+        };
+        TaskOptions::new(waker_task).data(()).spawn().unwrap();
+
+        // The current task sleeps in `schedule()` until the spawned task wakes it up.
+        wait_queue.wait_until(|| is_woken.load(Ordering::Acquire).then_some(()));
+
+        assert!(is_woken.load(Ordering::Acquire));
+        assert_eq!(current_task().unwrap().status(), TaskStatus::Runnable);
+    }
 }
@@ -0,0 +1,192 @@
+// SPDX-License-Identifier: MPL-2.0
+
+//! The affinity of the IRQs, i.e., the CPUs that handle the IRQs.
+//!
+//! An IRQ can be routed to a CPU only if its source supports it, e.g., an MSI-X
+//! vector. Such a source registers a router for the IRQ, which directs the IRQ to a
+//! given CPU. The affinity of an IRQ is a set of CPUs, and the IRQ is routed to the
+//! first online CPU in the set.
+//!
+//! The IRQs that are handled frequently, e.g., the queue IRQs of network and block
+//! devices, can be marked as balanced. If the balance policy is
+//! [`IrqBalancePolicy::Spread`], each of these IRQs is routed to the CPU that handles
+//! the fewest balanced IRQs, unless its affinity is set explicitly. The policy is
+//! given by the kernel command line option `irq.balance`, e.g., `irq.balance=spread`.
+
+use alloc::{boxed::Box, collections::BTreeMap, vec, vec::Vec};
+use core::sync::atomic::{AtomicBool, Ordering};
+
+use crate::{
+    boot::kcmdline::ModuleArg,
+    cpu::{num_cpus, CpuSet},
+    sync::SpinLock,
+    Error, Result,
+};
+
+/// The policy to balance the IRQs among CPUs.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum IrqBalancePolicy {
+    /// All the IRQs are routed to the first CPU, unless their affinities are set.
+    None,
+    /// The balanced IRQs are spread across CPUs.
+    Spread,
+}
+
+/// Returns the current balance policy.
+pub fn balance_policy() -> IrqBalancePolicy {
+    if SPREAD.load(Ordering::Relaxed) {
+        IrqBalancePolicy::Spread
+    } else {
+        IrqBalancePolicy::None
+    }
+}
+
+/// Sets the balance policy, which rebalances the balanced IRQs.
+pub fn set_balance_policy(policy: IrqBalancePolicy) {
+    let mut routes = ROUTES.lock();
+    SPREAD.store(policy == IrqBalancePolicy::Spread, Ordering::Relaxed);
+    let balanced_irqs: Vec<u8> = routes
+        .iter()
+        .filter(|(_, route)| route.is_balanced && !route.is_pinned)
+        .map(|(irq_num, _)| *irq_num)
+        .collect();
+    for irq_num in balanced_irqs {
+        rebalance(&mut routes, irq_num);
+    }
+}
+
+/// Returns the IRQ numbers of the IRQs that can be routed to CPUs, in ascending order.
+pub fn routable_irqs() -> Vec<u8> {
+    ROUTES.lock().keys().copied().collect()
+}
+
+/// Returns the affinity of the IRQ, or `None` if the IRQ cannot be routed.
+pub fn irq_affinity(irq_num: u8) -> Option<CpuSet> {
+    ROUTES
+        .lock()
+        .get(&irq_num)
+        .map(|route| route.affinity.clone())
+}
+
+/// Sets the affinity of the IRQ.
+///
+/// # Errors
+///
+/// Returns [`Error::InvalidArgs`] if the IRQ cannot be routed or the affinity does not
+/// contain any online CPU.
+pub fn set_irq_affinity(irq_num: u8, affinity: &CpuSet) -> Result<()> {
+    let mut routes = ROUTES.lock();
+    let route = routes.get_mut(&irq_num).ok_or(Error::InvalidArgs)?;
+    let cpu = first_online_cpu(affinity).ok_or(Error::InvalidArgs)?;
+    route.affinity = affinity.clone();
+    route.is_pinned = true;
+    route.route_to(cpu);
+    Ok(())
+}
+
+/// Registers the router of the IRQ, which routes the IRQ to a given CPU.
+///
+/// The IRQ is routed to the first CPU at once.
+pub(crate) fn register_router(irq_num: u8, router: Box<dyn Fn(u32) + Send + Sync>) {
+    router(0);
+    let route = IrqRoute {
+        affinity: CpuSet::new_full(),
+        cpu: 0,
+        is_balanced: false,
+        is_pinned: false,
+        router,
+    };
+    ROUTES.lock().insert(irq_num, route);
+}
+
+/// Unregisters the router of the IRQ.
+pub(crate) fn unregister_router(irq_num: u8) {
+    ROUTES.lock().remove(&irq_num);
+}
+
+/// Marks the IRQ as balanced, and balances it according to the policy.
+pub(crate) fn balance(irq_num: u8) {
+    let mut routes = ROUTES.lock();
+    let Some(route) = routes.get_mut(&irq_num) else {
+        return;
+    };
+    route.is_balanced = true;
+    if !route.is_pinned {
+        rebalance(&mut routes, irq_num);
+    }
+}
+
+pub(super) fn init() {
+    let Some(args) = crate::boot::kernel_cmdline().get_module_args("irq") else {
+        return;
+    };
+    for arg in args {
+        if let ModuleArg::KeyVal(key, value) = arg
+            && key.as_bytes() == b"balance"
+        {
+            match value.as_bytes() {
+                b"spread" => set_balance_policy(IrqBalancePolicy::Spread),
+                b"none" => set_balance_policy(IrqBalancePolicy::None),
+                _ => log::warn!("unknown IRQ balance policy {:?}", value),
+            }
+        }
+    }
+}
+
+static SPREAD: AtomicBool = AtomicBool::new(false);
+
+/// The routes of the routable IRQs, indexed by the IRQ numbers.
+static ROUTES: SpinLock<BTreeMap<u8, IrqRoute>> = SpinLock::new(BTreeMap::new());
+
+/// Routes the IRQ according to the balance policy.
+fn rebalance(routes: &mut BTreeMap<u8, IrqRoute>, irq_num: u8) {
+    let (affinity, cpu) = if SPREAD.load(Ordering::Relaxed) {
+        let mut nr_irqs = vec![0usize; num_cpus() as usize];
+        for (_, route) in routes
+            .iter()
+            .filter(|(num, route)| **num != irq_num && route.is_balanced)
+        {
+            if let Some(nr) = nr_irqs.get_mut(route.cpu as usize) {
+                *nr += 1;
+            }
+        }
+        let cpu = (0..num_cpus())
+            .min_by_key(|cpu| nr_irqs[*cpu as usize])
+            .unwrap();
+        let mut affinity = CpuSet::new_empty();
+        affinity.add(cpu);
+        (affinity, cpu)
+    } else {
+        (CpuSet::new_full(), 0)
+    };
+
+    let route = routes.get_mut(&irq_num).unwrap();
+    route.affinity = affinity;
+    route.route_to(cpu);
+}
+
+struct IrqRoute {
+    affinity: CpuSet,
+    /// The CPU that handles the IRQ now.
+    cpu: u32,
+    /// Whether the IRQ is balanced among CPUs.
+    is_balanced: bool,
+    /// Whether the affinity is set explicitly, which disables the balancing.
+    is_pinned: bool,
+    router: Box<dyn Fn(u32) + Send + Sync>,
+}
+
+impl IrqRoute {
+    fn route_to(&mut self, cpu: u32) {
+        if self.cpu != cpu {
+            (self.router)(cpu);
+            self.cpu = cpu;
+        }
+    }
+}
+
+fn first_online_cpu(cpus: &CpuSet) -> Option<u32> {
+    cpus.iter()
+        .map(|cpu| cpu as u32)
+        .find(|cpu| *cpu < num_cpus())
+}
@@ -1,6 +1,6 @@
 // SPDX-License-Identifier: MPL-2.0
 
-use core::sync::atomic::{AtomicBool, Ordering};
+use core::sync::atomic::{AtomicBool, AtomicU64, Ordering};
 
 use trapframe::TrapFrame;
 
@@ -13,6 +13,8 @@ pub(crate) fn call_irq_callback_functions(trap_frame: &TrapFrame) {
     // FIXME: For arch that supports re-entrant interrupts, we may need to record nested level here.
     IN_INTERRUPT_CONTEXT.store(true, Ordering::Release);
 
+    IRQ_COUNTS[trap_frame.trap_num].fetch_add(1, Ordering::Relaxed);
+
     let irq_line = IRQ_LIST.get().unwrap().get(trap_frame.trap_num).unwrap();
     let callback_functions = irq_line.callback_list();
     for callback_function in callback_functions.iter() {
@@ -30,8 +32,18 @@ pub(crate) fn call_irq_callback_functions(trap_frame: &TrapFrame) {
     crate::trap::softirq::process_pending();
 }
 
+#[allow(clippy::declare_interior_mutable_const)]
+const ZERO_COUNT: AtomicU64 = AtomicU64::new(0);
+
 cpu_local! {
     static IN_INTERRUPT_CONTEXT: AtomicBool = AtomicBool::new(false);
+    /// The number of the interrupts of each IRQ number that the CPU has handled.
+    static IRQ_COUNTS: [AtomicU64; 256] = [ZERO_COUNT; 256];
+}
+
+/// Returns the number of the interrupts of the IRQ that the CPU has handled.
+pub fn irq_count(irq_num: u8, cpu_id: u32) -> u64 {
+    IRQ_COUNTS.get_on_cpu(cpu_id)[irq_num as usize].load(Ordering::Relaxed)
 }
 
 /// Returns whether we are in the interrupt context.
@@ -6,8 +6,10 @@ use core::fmt::Debug;
 
 use trapframe::TrapFrame;
 
+use super::affinity;
 use crate::{
     arch::irq::{self, IrqCallbackHandle, IRQ_ALLOCATOR},
+    cpu::CpuSet,
     prelude::*,
     task::{disable_preempt, DisablePreemptGuard},
     Error,
@@ -81,6 +83,26 @@ impl IrqLine {
     pub fn is_empty(&self) -> bool {
         self.callbacks.is_empty()
     }
+
+    /// Gets the CPUs that may handle the IRQ, or `None` if the IRQ cannot be routed.
+    pub fn affinity(&self) -> Option<CpuSet> {
+        affinity::irq_affinity(self.irq_num)
+    }
+
+    /// Sets the CPUs that may handle the IRQ.
+    ///
+    /// Only the IRQs whose sources support routing, e.g., MSI-X vectors, can be set.
+    pub fn set_affinity(&self, cpus: &CpuSet) -> Result<()> {
+        affinity::set_irq_affinity(self.irq_num, cpus)
+    }
+
+    /// Balances the IRQ among CPUs according to the balance policy.
+    ///
+    /// This is intended for the IRQs that are handled frequently, e.g., the queue IRQs of
+    /// network and block devices.
+    pub fn balance(&self) {
+        affinity::balance(self.irq_num);
+    }
 }
 
 impl Clone for IrqLine {
@@ -96,6 +118,7 @@ impl Clone for IrqLine {
 impl Drop for IrqLine {
     fn drop(&mut self) {
         if Arc::strong_count(&self.irq) == 1 {
+            affinity::unregister_router(self.irq_num);
             IRQ_ALLOCATOR
                 .get()
                 .unwrap()
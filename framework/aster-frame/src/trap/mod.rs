@@ -2,17 +2,22 @@
 
 //! Handles trap across kernel and user space.
 
+mod affinity;
 mod handler;
 mod irq;
 pub mod softirq;
 
-pub use handler::in_interrupt_context;
+pub use handler::{in_interrupt_context, irq_count};
 pub use softirq::SoftIrqLine;
 pub use trapframe::TrapFrame;
 
-pub(crate) use self::handler::call_irq_callback_functions;
-pub use self::irq::{
-    disable_local, enable_local, DisabledLocalIrqGuard, IrqCallbackFunction, IrqLine,
+pub(crate) use self::{affinity::register_router, handler::call_irq_callback_functions};
+pub use self::{
+    affinity::{
+        balance_policy, irq_affinity, routable_irqs, set_balance_policy, set_irq_affinity,
+        IrqBalancePolicy,
+    },
+    irq::{disable_local, enable_local, DisabledLocalIrqGuard, IrqCallbackFunction, IrqLine},
 };
 
 pub(crate) fn init() {
@@ -20,4 +25,12 @@ pub(crate) fn init() {
         trapframe::init();
     }
     softirq::init();
+    affinity::init();
+}
+
+/// Initializes the trap handling on an application processor.
+pub(crate) fn init_on_ap() {
+    unsafe {
+        trapframe::init();
+    }
 }
@@ -0,0 +1,88 @@
+// SPDX-License-Identifier: MPL-2.0
+
+//! Passes the kernel configuration file to the kernel.
+//!
+//! The file is in the format of the Linux `.config` file. It is `kernel/configs/default.config`,
+//! and the options in it can be overridden by the file given with the `ASTER_KCONFIG`
+//! environment variable. Each option is passed as a compile-time environment variable of the
+//! same name, which is read in `src/config.rs`.
+
+use std::{
+    collections::BTreeMap,
+    env, fs,
+    path::{Path, PathBuf},
+};
+
+/// The options that can be set in the configuration file.
+///
+/// The optional subsystems are not among them because they are selected with the Cargo
+/// features of this crate.
+const OPTIONS: &[&str] = &["CONFIG_DEFAULT_HOSTNAME", "CONFIG_LOCALVERSION"];
+
+fn main() {
+    let default_path =
+        PathBuf::from(env::var_os("CARGO_MANIFEST_DIR").unwrap()).join("../configs/default.config");
+    let mut values = BTreeMap::new();
+    read_config(&default_path, &mut values);
+
+    println!("cargo:rerun-if-env-changed=ASTER_KCONFIG");
+    if let Some(path) = env::var_os("ASTER_KCONFIG").filter(|path| !path.is_empty()) {
+        read_config(Path::new(&path), &mut values);
+    }
+
+    for option in OPTIONS {
+        let Some(value) = values.get(option) else {
+            panic!("the option `{}` is not set", option);
+        };
+        println!("cargo:rustc-env={}={}", option, value);
+    }
+}
+
+/// Reads the options in the configuration file at `path` into `values`.
+fn read_config(path: &Path, values: &mut BTreeMap<&'static str, String>) {
+    println!("cargo:rerun-if-changed={}", path.display());
+
+    let content = fs::read_to_string(path)
+        .unwrap_or_else(|err| panic!("failed to read `{}`: {}", path.display(), err));
+    for (index, line) in content.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+
+        let location = || format!("{}:{}", path.display(), index + 1);
+        let Some((name, value)) = line.split_once('=') else {
+            panic!("{}: expected `CONFIG_<NAME>=<value>`", location());
+        };
+        let Some(option) = OPTIONS.iter().find(|option| **option == name) else {
+            panic!(
+                "{}: unknown option `{}` (the optional subsystems are selected with the Cargo features)",
+                location(),
+                name
+            );
+        };
+        let Some(value) = parse_string(value) else {
+            panic!(
+                "{}: expected a quoted string, found `{}`",
+                location(),
+                value
+            );
+        };
+        values.insert(option, value);
+    }
+}
+
+/// Parses a string value, which is quoted and escaped in the same way as in Linux.
+fn parse_string(value: &str) -> Option<String> {
+    let value = value.strip_prefix('"')?.strip_suffix('"')?;
+    let mut string = String::new();
+    let mut chars = value.chars();
+    while let Some(c) = chars.next() {
+        match c {
+            '\\' => string.push(chars.next()?),
+            '"' => return None,
+            c => string.push(c),
+        }
+    }
+    Some(string)
+}
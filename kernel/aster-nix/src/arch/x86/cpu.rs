@@ -1,6 +1,6 @@
 // SPDX-License-Identifier: MPL-2.0
 
-use aster_frame::cpu::UserContext;
+use aster_frame::{cpu::UserContext, user::UserContextApi};
 
 use crate::cpu::LinuxAbi;
 
@@ -28,6 +28,13 @@ impl LinuxAbi for UserContext {
         ]
     }
 
+    fn restart_syscall(&mut self, syscall_num: usize) {
+        // The `syscall` instruction is 2 bytes long, and the arguments are still in place.
+        const SYSCALL_INSN_LEN: usize = 2;
+        self.set_rax(syscall_num);
+        self.set_instruction_pointer(self.instruction_pointer() - SYSCALL_INSN_LEN);
+    }
+
     fn set_tls_pointer(&mut self, tls: usize) {
         self.set_fsbase(tls);
     }
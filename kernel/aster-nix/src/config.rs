@@ -0,0 +1,57 @@
+// SPDX-License-Identifier: MPL-2.0
+
+//! The kernel configuration.
+//!
+//! The optional subsystems are selected with the Cargo features of this crate, which are
+//! enabled by default. A subsystem that is compiled out is stubbed where it is registered,
+//! e.g., its syscalls are unimplemented and its file systems are unknown to `mount`.
+//! The presets of the features for the smaller builds are provided as the OSDK schemes.
+//!
+//! The other options are set in the kernel configuration file, which is
+//! `kernel/configs/default.config` unless overridden with `make KCONFIG=<path>`. They are
+//! passed here by the build script of this crate.
+
+use static_assertions::const_assert;
+
+/// An optional subsystem of the kernel.
+pub struct ConfigOption {
+    /// The name of the option, in the style of Linux.
+    pub name: &'static str,
+    pub is_enabled: bool,
+}
+
+impl ConfigOption {
+    const fn new(name: &'static str, is_enabled: bool) -> Self {
+        Self { name, is_enabled }
+    }
+}
+
+/// The optional subsystems and whether they are built into the kernel.
+pub const CONFIG_OPTIONS: &[ConfigOption] = &[
+    ConfigOption::new("CONFIG_NET", cfg!(feature = "net")),
+    ConfigOption::new("CONFIG_EXT2_FS", cfg!(feature = "ext2")),
+    ConfigOption::new("CONFIG_EXFAT_FS", cfg!(feature = "exfat")),
+    ConfigOption::new("CONFIG_VFAT_FS", cfg!(feature = "vfat")),
+    ConfigOption::new("CONFIG_OVERLAY_FS", cfg!(feature = "overlayfs")),
+    ConfigOption::new("CONFIG_VIRTIO_FS", cfg!(feature = "virtiofs")),
+    ConfigOption::new("CONFIG_9P_FS", cfg!(feature = "v9fs")),
+    ConfigOption::new("CONFIG_VIRTIO_BALLOON", cfg!(feature = "virtio-balloon")),
+    ConfigOption::new("CONFIG_HW_RANDOM_VIRTIO", cfg!(feature = "virtio-rng")),
+    ConfigOption::new("CONFIG_DRM_VIRTIO_GPU", cfg!(feature = "virtio-gpu")),
+    ConfigOption::new("CONFIG_INTEL_TDX_GUEST", cfg!(feature = "intel_tdx")),
+];
+
+/// The hostname before it is changed by the user space.
+pub const DEFAULT_HOSTNAME: &str = env!("CONFIG_DEFAULT_HOSTNAME");
+
+// The hostname must fit in the fields of `uname`, as in Linux.
+const_assert!(DEFAULT_HOSTNAME.len() <= 64);
+
+/// The string appended to the kernel release reported by `uname`.
+pub const LOCALVERSION: &str = env!("CONFIG_LOCALVERSION");
+
+/// The options set in the kernel configuration file and their values.
+pub const CONFIG_VALUES: &[(&str, &str)] = &[
+    ("CONFIG_DEFAULT_HOSTNAME", DEFAULT_HOSTNAME),
+    ("CONFIG_LOCALVERSION", LOCALVERSION),
+];
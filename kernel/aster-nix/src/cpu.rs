@@ -1,5 +1,7 @@
 // SPDX-License-Identifier: MPL-2.0
 
+use crate::events::NotifierChain;
+
 pub trait LinuxAbi {
     /// Get number of syscall
     fn syscall_num(&self) -> usize;
@@ -13,9 +15,28 @@ pub trait LinuxAbi {
     /// Get syscall args
     fn syscall_args(&self) -> [usize; 6];
 
+    /// Rewind the context to execute the syscall again when returning to the user space
+    fn restart_syscall(&mut self, syscall_num: usize);
+
     /// Set thread-local storage pointer
     fn set_tls_pointer(&mut self, tls: usize);
 
     /// Get thread-local storage pointer
     fn tls_pointer(&self) -> usize;
 }
+
+/// The events of the CPU hotplug.
+///
+/// A notifier may veto a `DownPrepare` event, which keeps the CPU online.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CpuEvent {
+    /// The CPU is online.
+    Online(u32),
+    /// The CPU is going offline.
+    DownPrepare(u32),
+    /// The CPU is offline.
+    Dead(u32),
+}
+
+/// The notifier chain that is notified of the CPU hotplug events.
+pub static CPU_NOTIFIER_CHAIN: NotifierChain<CpuEvent> = NotifierChain::new();
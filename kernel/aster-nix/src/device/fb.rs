@@ -0,0 +1,361 @@
+// SPDX-License-Identifier: MPL-2.0
+
+#![allow(unused_variables)]
+
+//! The framebuffer devices, i.e., `/dev/fb*`.
+//!
+//! The pixels can be accessed with `read` and `write`, or be mapped with `mmap`. Since
+//! the users can write to the mappings at any time, the framebuffer is flushed
+//! periodically while it is mapped writable, like the deferred I/O of Linux.
+
+use core::time::Duration;
+
+use aster_frame::{
+    mm::{Frame, VmIo},
+    sync::WaitQueue,
+};
+use aster_framebuffer::{AnyFramebufferDevice, PixelFormat, Rect};
+use aster_rights::Rights;
+use spin::Once;
+
+use super::*;
+use crate::{
+    events::IoEvents,
+    fs::{file_handle::MmapRegion, inode_handle::FileIo, utils::IoctlCmd},
+    prelude::*,
+    process::signal::Poller,
+    thread::{
+        kernel_thread::{KernelThreadExt, ThreadOptions},
+        Thread,
+    },
+    util::{read_val_from_user, write_val_to_user},
+    vm::{
+        perms::VmPerms,
+        vmar::SharedMem,
+        vmo::{Pager, VmoOptions},
+    },
+};
+
+/// The same major number as `/dev/fb*` in Linux.
+const FB_MAJOR: u32 = 29;
+
+/// The interval to flush the pixels written through the mappings, which is the same as
+/// that of the deferred I/O in Linux.
+const FLUSH_INTERVAL: Duration = Duration::from_millis(50);
+
+/// Adds a device node for each framebuffer device.
+pub fn init() -> Result<()> {
+    for (index, (_, device)) in aster_framebuffer::all_devices().into_iter().enumerate() {
+        let fb = Framebuffer::new(index as u32, device)?;
+        add_node(fb, &format!("fb{}", index))?;
+    }
+    Ok(())
+}
+
+pub struct Framebuffer {
+    index: u32,
+    device: Arc<dyn AnyFramebufferDevice>,
+    /// The pixels, which are shared by all the shared mappings.
+    shared_mem: Arc<SharedMem>,
+    /// Whether the thread that flushes the mappings is spawned.
+    is_flusher_spawned: Once<()>,
+    weak_self: Weak<Self>,
+}
+
+impl Framebuffer {
+    fn new(index: u32, device: Arc<dyn AnyFramebufferDevice>) -> Result<Arc<Self>> {
+        let size = device.info().size();
+        let vmo = VmoOptions::<Rights>::new(size)
+            .pager(Arc::new(FramebufferPager(device.clone())))
+            .alloc()?;
+        Ok(Arc::new_cyclic(|weak_self| Self {
+            index,
+            device,
+            shared_mem: SharedMem::from_vmo(vmo),
+            is_flusher_spawned: Once::new(),
+            weak_self: weak_self.clone(),
+        }))
+    }
+
+    fn size(&self) -> usize {
+        self.device.info().size()
+    }
+
+    fn var_screen_info(&self) -> FbVarScreenInfo {
+        let info = self.device.info();
+        let (red, green, blue) = match info.format {
+            PixelFormat::Xrgb8888 => (
+                FbBitfield::new(16, 8),
+                FbBitfield::new(8, 8),
+                FbBitfield::new(0, 8),
+            ),
+        };
+        FbVarScreenInfo {
+            xres: info.width as u32,
+            yres: info.height as u32,
+            xres_virtual: info.width as u32,
+            yres_virtual: info.height as u32,
+            bits_per_pixel: (info.format.bytes_per_pixel() * 8) as u32,
+            red,
+            green,
+            blue,
+            // No display timings are known, as with the virtual framebuffers in Linux.
+            height: u32::MAX,
+            width: u32::MAX,
+            ..Default::default()
+        }
+    }
+
+    fn fix_screen_info(&self) -> FbFixScreenInfo {
+        let mut id = [0u8; 16];
+        let name = b"asterinasfb";
+        id[..name.len()].copy_from_slice(name);
+        FbFixScreenInfo {
+            id,
+            smem_len: self.size() as u32,
+            type_: FB_TYPE_PACKED_PIXELS,
+            visual: FB_VISUAL_TRUECOLOR,
+            line_length: self.device.info().line_length as u32,
+            ..Default::default()
+        }
+    }
+
+    /// Flushes the framebuffer periodically while it is mapped writable.
+    fn run_flusher(&self) {
+        let wait_queue = WaitQueue::new();
+        loop {
+            wait_queue.wait_until_or_timeout(|| None::<()>, &FLUSH_INTERVAL);
+            if self.shared_mem.is_mapped_writable() {
+                self.device.flush(self.device.info().rect());
+            }
+        }
+    }
+}
+
+impl Device for Framebuffer {
+    fn type_(&self) -> DeviceType {
+        DeviceType::CharDevice
+    }
+
+    fn id(&self) -> DeviceId {
+        DeviceId::new(FB_MAJOR, self.index)
+    }
+
+    fn open(&self) -> Result<Option<Arc<dyn FileIo>>> {
+        // The framebuffer is accessed at the offset of each file handle.
+        Ok(Some(self.weak_self.upgrade().unwrap() as Arc<dyn FileIo>))
+    }
+}
+
+impl FileIo for Framebuffer {
+    fn read(&self, buf: &mut [u8]) -> Result<usize> {
+        self.read_at(0, buf)
+    }
+
+    fn write(&self, buf: &[u8]) -> Result<usize> {
+        self.write_at(0, buf)
+    }
+
+    fn is_seekable(&self) -> bool {
+        true
+    }
+
+    fn read_at(&self, offset: usize, buf: &mut [u8]) -> Result<usize> {
+        let len = buf.len().min(self.size().saturating_sub(offset));
+        if len == 0 {
+            return Ok(0);
+        }
+        self.device.frames().read_bytes(offset, &mut buf[..len])?;
+        Ok(len)
+    }
+
+    fn write_at(&self, offset: usize, buf: &[u8]) -> Result<usize> {
+        if buf.is_empty() {
+            return Ok(0);
+        }
+        let size = self.size();
+        if offset >= size {
+            return_errno_with_message!(Errno::ENOSPC, "the offset is beyond the framebuffer");
+        }
+        let len = buf.len().min(size - offset);
+        self.device.frames().write_bytes(offset, &buf[..len])?;
+
+        // Flush the lines that are written.
+        let info = self.device.info();
+        let start_line = offset / info.line_length;
+        let end_line = (offset + len).div_ceil(info.line_length);
+        self.device.flush(Rect {
+            x: 0,
+            y: start_line,
+            width: info.width,
+            height: end_line - start_line,
+        });
+        Ok(len)
+    }
+
+    fn poll(&self, mask: IoEvents, poller: Option<&Poller>) -> IoEvents {
+        let events = IoEvents::IN | IoEvents::OUT;
+        events & mask
+    }
+
+    fn ioctl(&self, cmd: IoctlCmd, arg: usize) -> Result<i32> {
+        match cmd {
+            IoctlCmd::FBIOGET_VSCREENINFO => {
+                write_val_to_user(arg, &self.var_screen_info())?;
+                Ok(0)
+            }
+            IoctlCmd::FBIOPUT_VSCREENINFO => {
+                // Only the current mode is supported. Like Linux, the modes that fit in it
+                // are accepted and adjusted to it.
+                let var: FbVarScreenInfo = read_val_from_user(arg)?;
+                let current = self.var_screen_info();
+                if var.xres > current.xres
+                    || var.yres > current.yres
+                    || var.bits_per_pixel != current.bits_per_pixel
+                {
+                    return_errno_with_message!(Errno::EINVAL, "the mode is not supported");
+                }
+                write_val_to_user(arg, &current)?;
+                Ok(0)
+            }
+            IoctlCmd::FBIOGET_FSCREENINFO => {
+                write_val_to_user(arg, &self.fix_screen_info())?;
+                Ok(0)
+            }
+            _ => return_errno_with_message!(Errno::EINVAL, "the ioctl is not supported"),
+        }
+    }
+
+    fn mmap(
+        &self,
+        offset: usize,
+        len: usize,
+        is_shared: bool,
+        perms: VmPerms,
+    ) -> Result<MmapRegion> {
+        let size = self.shared_mem.size();
+        if offset.checked_add(len).map_or(true, |end| end > size) {
+            return_errno_with_message!(Errno::EINVAL, "the mapping is beyond the framebuffer");
+        }
+        if !is_shared {
+            let vmo = self.shared_mem.vmo().dup()?;
+            return MmapRegion::from_vmo_cow(vmo, offset..(offset + len));
+        }
+
+        if perms.contains(VmPerms::WRITE) {
+            self.is_flusher_spawned.call_once(|| {
+                let fb = self.weak_self.upgrade().unwrap();
+                Thread::spawn_kernel_thread(ThreadOptions::new(move || fb.run_flusher()));
+            });
+        }
+        Ok(MmapRegion::from_shared_mem(self.shared_mem.clone(), offset))
+    }
+}
+
+/// Provides the frames of a framebuffer device to the VMO of the framebuffer.
+struct FramebufferPager(Arc<dyn AnyFramebufferDevice>);
+
+impl Pager for FramebufferPager {
+    fn commit_page(&self, idx: usize) -> Result<Frame> {
+        self.0
+            .frames()
+            .get(idx)
+            .cloned()
+            .ok_or_else(|| Error::with_message(Errno::EINVAL, "the page is beyond the framebuffer"))
+    }
+
+    fn update_page(&self, idx: usize) -> Result<()> {
+        Ok(())
+    }
+
+    fn decommit_page(&self, idx: usize) -> Result<()> {
+        Ok(())
+    }
+
+    fn commit_overwrite(&self, idx: usize) -> Result<Frame> {
+        self.commit_page(idx)
+    }
+}
+
+const FB_TYPE_PACKED_PIXELS: u32 = 0;
+const FB_VISUAL_TRUECOLOR: u32 = 2;
+
+/// The position of a color in a pixel.
+#[derive(Debug, Clone, Copy, Default, Pod)]
+#[repr(C)]
+struct FbBitfield {
+    offset: u32,
+    length: u32,
+    msb_right: u32,
+}
+
+impl FbBitfield {
+    fn new(offset: u32, length: u32) -> Self {
+        Self {
+            offset,
+            length,
+            msb_right: 0,
+        }
+    }
+}
+
+/// The variable information of a framebuffer, i.e., `struct fb_var_screeninfo` in Linux.
+#[derive(Debug, Clone, Copy, Default, Pod)]
+#[repr(C)]
+struct FbVarScreenInfo {
+    xres: u32,
+    yres: u32,
+    xres_virtual: u32,
+    yres_virtual: u32,
+    xoffset: u32,
+    yoffset: u32,
+    bits_per_pixel: u32,
+    grayscale: u32,
+    red: FbBitfield,
+    green: FbBitfield,
+    blue: FbBitfield,
+    transp: FbBitfield,
+    nonstd: u32,
+    activate: u32,
+    /// The height of the picture, in millimeters.
+    height: u32,
+    /// The width of the picture, in millimeters.
+    width: u32,
+    accel_flags: u32,
+    pixclock: u32,
+    left_margin: u32,
+    right_margin: u32,
+    upper_margin: u32,
+    lower_margin: u32,
+    hsync_len: u32,
+    vsync_len: u32,
+    sync: u32,
+    vmode: u32,
+    rotate: u32,
+    colorspace: u32,
+    reserved: [u32; 4],
+}
+
+/// The fixed information of a framebuffer, i.e., `struct fb_fix_screeninfo` in Linux.
+#[derive(Debug, Clone, Copy, Default, Pod)]
+#[repr(C)]
+struct FbFixScreenInfo {
+    id: [u8; 16],
+    smem_start: u64,
+    smem_len: u32,
+    type_: u32,
+    type_aux: u32,
+    visual: u32,
+    xpanstep: u16,
+    ypanstep: u16,
+    ywrapstep: u16,
+    _padding0: u16,
+    line_length: u32,
+    _padding1: u32,
+    mmio_start: u64,
+    mmio_len: u32,
+    accel: u32,
+    capabilities: u16,
+    reserved: [u16; 2],
+    _padding2: u16,
+}
@@ -1,5 +1,6 @@
 // SPDX-License-Identifier: MPL-2.0
 
+mod fb;
 mod null;
 mod pty;
 mod random;
@@ -30,6 +31,7 @@ pub fn init() -> Result<()> {
     tty::init();
     let console = get_n_tty().clone();
     add_node(console, "console")?;
+    tty::hvc::init()?;
     let tty = Arc::new(tty::TtyDevice);
     add_node(tty, "tty")?;
     #[cfg(feature = "intel_tdx")]
@@ -40,6 +42,7 @@ pub fn init() -> Result<()> {
     add_node(random, "random")?;
     let urandom = Arc::new(urandom::Urandom);
     add_node(urandom, "urandom")?;
+    fb::init()?;
     pty::init()?;
     Ok(())
 }
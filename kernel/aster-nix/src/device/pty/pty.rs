@@ -13,7 +13,7 @@ use crate::{
         file_table::FdFlags,
         fs_resolver::FsPath,
         inode_handle::FileIo,
-        utils::{AccessMode, Inode, InodeMode, IoctlCmd},
+        utils::{AccessMode, CreationFlags, Inode, InodeMode, IoctlCmd},
     },
     prelude::*,
     process::{
@@ -190,8 +190,8 @@ impl FileIo for PtyMaster {
 
                 let fd = {
                     let mut file_table = current.file_table().lock();
-                    // TODO: deal with the O_CLOEXEC flag
-                    file_table.insert(slave, FdFlags::empty())
+                    let fd_flags = FdFlags::from(CreationFlags::from_bits_truncate(arg as u32));
+                    file_table.insert(slave, fd_flags)
                 };
                 Ok(fd)
             }
@@ -10,13 +10,15 @@ use crate::{
     },
     prelude::*,
     process::signal::Poller,
-    util::random::getrandom,
+    util::random::{self, add_entropy, getrandom},
 };
 
 pub struct Random;
 
 impl Random {
+    /// Fills `buf` with random bytes, waiting until the RNG is seeded with enough entropy.
     pub fn getrandom(buf: &mut [u8]) -> Result<usize> {
+        random::wait_until_ready()?;
         getrandom(buf)?;
         Ok(buf.len())
     }
@@ -39,11 +41,17 @@ impl FileIo for Random {
     }
 
     fn write(&self, buf: &[u8]) -> Result<usize> {
+        // The written data is mixed into the entropy pool, but not credited.
+        add_entropy(buf, 0);
         Ok(buf.len())
     }
 
     fn poll(&self, mask: IoEvents, poller: Option<&Poller>) -> IoEvents {
-        let events = IoEvents::IN | IoEvents::OUT;
+        let events = if random::is_ready() {
+            IoEvents::IN | IoEvents::OUT
+        } else {
+            IoEvents::OUT
+        };
         events & mask
     }
 }
@@ -0,0 +1,48 @@
+// SPDX-License-Identifier: MPL-2.0
+
+//! The TTYs of the virtio-console ports, i.e., `/dev/hvc*`.
+
+use aster_console::{AnyConsoleDevice, ConsoleCallback};
+use aster_frame::mm::VmReader;
+use aster_virtio::device::console::all_ports;
+
+use super::{get_n_tty, Tty};
+use crate::{
+    fs::device::{add_node, DeviceId},
+    prelude::*,
+};
+
+/// The same major number as `/dev/hvc*` in linux.
+const HVC_MAJOR: u32 = 229;
+
+/// Adds a TTY device node for each port of the virtio-console devices.
+pub fn init() -> Result<()> {
+    for (index, port) in all_ports().into_iter().enumerate() {
+        let name = format!("hvc{}", index);
+
+        // Port 0 of a device is a kernel console, whose input is already pushed to N_TTY,
+        // so its TTY is N_TTY. Otherwise, the input would be echoed twice.
+        if port.id() == 0 {
+            add_node(get_n_tty().clone(), &name)?;
+            continue;
+        }
+
+        let tty = Tty::new_with_console(
+            CString::new(name.clone())?,
+            DeviceId::new(HVC_MAJOR, index as u32),
+            Some(port.clone() as Arc<dyn AnyConsoleDevice>),
+        );
+        // The callback lives as long as the port, which is never removed.
+        let callback: &'static ConsoleCallback = {
+            let tty = tty.clone();
+            Box::leak(Box::new(move |mut reader: VmReader| {
+                while reader.remain() > 0 {
+                    tty.push_char(reader.read_val());
+                }
+            }))
+        };
+        port.register_callback(callback);
+        add_node(tty, &name)?;
+    }
+    Ok(())
+}
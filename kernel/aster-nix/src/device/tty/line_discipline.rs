@@ -234,7 +234,7 @@ impl LineDiscipline {
                 let backspace: &str = core::str::from_utf8(&[b'\x08', b' ', b'\x08']).unwrap();
                 echo_callback(backspace);
             }
-            ch if is_printable_char(ch) => print!("{}", char::from(ch)),
+            ch if is_printable_char(ch) => echo_callback(core::str::from_utf8(&[ch]).unwrap()),
             ch if is_ctrl_char(ch) && termios.contains_echo_ctl() => {
                 let ctrl_char = format!("^{}", get_printable_char(ch));
                 echo_callback(&ctrl_char);
@@ -260,7 +260,7 @@ impl LineDiscipline {
     }
 
     /// read all bytes buffered to dst, return the actual read length.
-    fn try_read(&self, dst: &mut [u8]) -> Result<usize> {
+    pub fn try_read(&self, dst: &mut [u8]) -> Result<usize> {
         let (vmin, vtime) = {
             let termios = self.termios.lock_irq_disabled();
             let vmin = *termios.get_special_char(CC_C_CHAR::VMIN);
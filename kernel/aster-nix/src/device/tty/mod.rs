@@ -2,6 +2,7 @@
 
 #![allow(dead_code)]
 
+use aster_console::AnyConsoleDevice;
 use aster_frame::early_print;
 use spin::Once;
 
@@ -23,6 +24,7 @@ use crate::{
 
 mod device;
 pub mod driver;
+pub mod hvc;
 pub mod line_discipline;
 pub mod termio;
 
@@ -40,22 +42,38 @@ pub(super) fn init() {
 pub struct Tty {
     /// tty_name
     name: CString,
+    /// device id
+    id: DeviceId,
     /// line discipline
     ldisc: Arc<LineDiscipline>,
     job_control: Arc<JobControl>,
     /// driver
     driver: SpinLock<Weak<TtyDriver>>,
+    /// The console device that the output goes to, or all the console devices if it is `None`.
+    console: Option<Arc<dyn AnyConsoleDevice>>,
     weak_self: Weak<Self>,
 }
 
 impl Tty {
     pub fn new(name: CString) -> Arc<Self> {
+        // The same value as /dev/console in linux.
+        Self::new_with_console(name, DeviceId::new(88, 0), None)
+    }
+
+    /// Creates a TTY whose output goes to the console device.
+    pub fn new_with_console(
+        name: CString,
+        id: DeviceId,
+        console: Option<Arc<dyn AnyConsoleDevice>>,
+    ) -> Arc<Self> {
         let (job_control, ldisc) = new_job_control_and_ldisc();
         Arc::new_cyclic(move |weak_ref| Tty {
             name,
+            id,
             ldisc,
             job_control,
             driver: SpinLock::new(Weak::new()),
+            console,
             weak_self: weak_ref.clone(),
         })
     }
@@ -65,11 +83,25 @@ impl Tty {
     }
 
     pub fn push_char(&self, ch: u8) {
+        if let Some(console) = &self.console {
+            self.ldisc
+                .push_char(ch, |content| console.send(content.as_bytes()));
+            return;
+        }
+
         // FIXME: Use `early_print` to avoid calling virtio-console.
         // This is only a workaround
         self.ldisc
             .push_char(ch, |content| early_print!("{}", content))
     }
+
+    /// Reads the buffered input without blocking.
+    ///
+    /// Unlike `read`, this does not need the current thread to be a POSIX thread,
+    /// so kernel threads can use it to read from the console.
+    pub fn try_read(&self, buf: &mut [u8]) -> Result<usize> {
+        self.ldisc.try_read(buf)
+    }
 }
 
 impl FileIo for Tty {
@@ -79,6 +111,11 @@ impl FileIo for Tty {
     }
 
     fn write(&self, buf: &[u8]) -> Result<usize> {
+        if let Some(console) = &self.console {
+            console.send(buf);
+            return Ok(buf.len());
+        }
+
         if let Ok(content) = alloc::str::from_utf8(buf) {
             print!("{content}");
         } else {
@@ -182,8 +219,7 @@ impl Device for Tty {
     }
 
     fn id(&self) -> DeviceId {
-        // The same value as /dev/console in linux.
-        DeviceId::new(88, 0)
+        self.id
     }
 }
 
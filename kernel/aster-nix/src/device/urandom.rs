@@ -10,7 +10,7 @@ use crate::{
     },
     prelude::*,
     process::signal::Poller,
-    util::random::getrandom,
+    util::random::{add_entropy, getrandom},
 };
 
 pub struct Urandom;
@@ -39,6 +39,8 @@ impl FileIo for Urandom {
     }
 
     fn write(&self, buf: &[u8]) -> Result<usize> {
+        // The written data is mixed into the entropy pool, but not credited.
+        add_entropy(buf, 0);
         Ok(buf.len())
     }
 
@@ -2,8 +2,20 @@
 
 #![allow(unused_variables)]
 
+use aster_rights::Rights;
+
 use super::*;
-use crate::{events::IoEvents, fs::inode_handle::FileIo, prelude::*, process::signal::Poller};
+use crate::{
+    events::IoEvents,
+    fs::{file_handle::MmapRegion, inode_handle::FileIo},
+    prelude::*,
+    process::signal::Poller,
+    vm::{
+        perms::VmPerms,
+        vmar::SharedMem,
+        vmo::{VmoFlags, VmoOptions},
+    },
+};
 
 pub struct Zero;
 
@@ -34,4 +46,22 @@ impl FileIo for Zero {
         let events = IoEvents::IN | IoEvents::OUT;
         events & mask
     }
+
+    fn mmap(
+        &self,
+        offset: usize,
+        len: usize,
+        is_shared: bool,
+        perms: VmPerms,
+    ) -> Result<MmapRegion> {
+        // Like Linux, mapping `/dev/zero` is the same as the anonymous mapping, regardless
+        // of the offset.
+        if is_shared {
+            return Ok(MmapRegion::from_shared_mem(SharedMem::new(len)?, 0));
+        }
+        let vmo = VmoOptions::<Rights>::new(len)
+            .flags(VmoFlags::RESIZABLE)
+            .alloc()?;
+        Ok(MmapRegion::from_vmo(vmo))
+    }
 }
@@ -7,4 +7,17 @@ pub fn init() {
     for (name, _) in aster_input::all_devices() {
         info!("Found Input device, name:{}", name);
     }
+    // print all the NVMe namespaces to make sure nvme crate will compile
+    for name in aster_nvme::all_namespaces() {
+        info!("Found NVMe namespace, name:{}", name);
+    }
+    // print all the SATA disks to make sure ahci crate will compile
+    for name in aster_ahci::all_disks() {
+        info!("Found SATA disk, name:{}", name);
+    }
+    // print all the e1000 devices to make sure e1000 crate will compile
+    #[cfg(feature = "net")]
+    for name in aster_e1000::all_devices() {
+        info!("Found e1000 device, name:{}", name);
+    }
 }
@@ -2,9 +2,11 @@
 
 #![allow(dead_code)]
 
+use int_to_c_enum::TryFromInt;
+
 /// Error number.
 #[repr(i32)]
-#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, TryFromInt)]
 pub enum Errno {
     EPERM = 1,    /* Operation not permitted */
     ENOENT = 2,   /* No such file or directory */
@@ -150,6 +152,9 @@ pub enum Errno {
     ERFKILL = 132, /* Operation not possible due to RF-kill */
 
     EHWPOISON = 133, /* Memory page has hardware error */
+
+    /* Kernel-internal errors, which are never seen by the user space */
+    ERESTARTSYS = 512, /* Interrupted system call that may be restarted */
 }
 
 /// error used in this crate
@@ -3,6 +3,7 @@
 #[allow(clippy::module_inception)]
 mod events;
 mod io_events;
+mod notifier;
 mod observer;
 mod subject;
 
@@ -10,6 +11,7 @@ pub use io_events::IoEvents;
 
 pub use self::{
     events::{Events, EventsFilter},
+    notifier::{Notifier, NotifierChain, NotifyResult},
     observer::Observer,
     subject::Subject,
 };
@@ -0,0 +1,145 @@
+// SPDX-License-Identifier: MPL-2.0
+
+use crate::prelude::*;
+
+/// A notifier, which reacts to the events of a [`NotifierChain`].
+pub trait Notifier<E>: Send + Sync {
+    /// Handles the event, and returns whether the following notifiers should be called.
+    fn on_event(&self, event: &E) -> NotifyResult;
+}
+
+/// The result of a [`Notifier`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum NotifyResult {
+    /// The notifier is not interested in the event.
+    Done,
+    /// The notifier has handled the event.
+    Ok,
+    /// The notifier has handled the event, and the following notifiers must not be called.
+    Stop,
+    /// The notifier vetoes the event, and the following notifiers must not be called.
+    Bad,
+}
+
+/// A chain of notifiers, through which a subsystem notifies the others of its state
+/// changes without calling them directly.
+///
+/// Unlike a [`Subject`], a chain calls the notifiers in the order of their priorities,
+/// allows a notifier to stop the chain or to veto the event, and keeps the notifiers alive
+/// until they are unregistered, since most notifiers are owned by nothing but the chain.
+///
+/// [`Subject`]: super::Subject
+pub struct NotifierChain<E> {
+    /// The notifiers sorted by their priorities in descending order.
+    notifiers: RwLock<Vec<(i32, Arc<dyn Notifier<E>>)>>,
+}
+
+impl<E> NotifierChain<E> {
+    pub const fn new() -> Self {
+        Self {
+            notifiers: RwLock::new(Vec::new()),
+        }
+    }
+
+    /// Registers a notifier with the priority.
+    ///
+    /// The notifiers with higher priorities are called first. The notifiers with the same
+    /// priority are called in the order in which they are registered.
+    pub fn register(&self, notifier: Arc<dyn Notifier<E>>, priority: i32) {
+        let mut notifiers = self.notifiers.write();
+        let pos = notifiers.partition_point(|(p, _)| *p >= priority);
+        notifiers.insert(pos, (priority, notifier));
+    }
+
+    /// Unregisters a notifier.
+    ///
+    /// Returns whether the notifier has been registered.
+    pub fn unregister(&self, notifier: &Arc<dyn Notifier<E>>) -> bool {
+        let mut notifiers = self.notifiers.write();
+        let Some(pos) = notifiers
+            .iter()
+            .position(|(_, registered)| Arc::ptr_eq(registered, notifier))
+        else {
+            return false;
+        };
+        notifiers.remove(pos);
+        true
+    }
+
+    /// Notifies the event to the registered notifiers.
+    ///
+    /// Returns the result of the last called notifier, or `NotifyResult::Done` if no
+    /// notifier is registered.
+    pub fn notify(&self, event: &E) -> NotifyResult {
+        // The notifiers may register or unregister notifiers, so they are called without
+        // holding the lock.
+        let notifiers: Vec<_> = self
+            .notifiers
+            .read()
+            .iter()
+            .map(|(_, notifier)| notifier.clone())
+            .collect();
+
+        let mut result = NotifyResult::Done;
+        for notifier in notifiers {
+            result = notifier.on_event(event);
+            if matches!(result, NotifyResult::Stop | NotifyResult::Bad) {
+                break;
+            }
+        }
+        result
+    }
+}
+
+impl<E> Default for NotifierChain<E> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(ktest)]
+mod test {
+    use super::*;
+
+    struct Recorder {
+        id: usize,
+        result: NotifyResult,
+        calls: Arc<Mutex<Vec<usize>>>,
+    }
+
+    impl Notifier<()> for Recorder {
+        fn on_event(&self, _event: &()) -> NotifyResult {
+            self.calls.lock().push(self.id);
+            self.result
+        }
+    }
+
+    #[ktest]
+    fn priority_and_stop() {
+        let calls = Arc::new(Mutex::new(Vec::new()));
+        let new_recorder = |id, result| -> Arc<dyn Notifier<()>> {
+            Arc::new(Recorder {
+                id,
+                result,
+                calls: calls.clone(),
+            })
+        };
+
+        let chain = NotifierChain::new();
+        chain.register(new_recorder(0, NotifyResult::Ok), 0);
+        chain.register(new_recorder(1, NotifyResult::Done), 10);
+        chain.register(new_recorder(2, NotifyResult::Ok), 0);
+        let stopper = new_recorder(3, NotifyResult::Stop);
+        chain.register(stopper.clone(), 5);
+        chain.register(new_recorder(4, NotifyResult::Ok), -5);
+
+        assert_eq!(chain.notify(&()), NotifyResult::Stop);
+        assert_eq!(*calls.lock(), vec![1, 3]);
+
+        calls.lock().clear();
+        assert!(chain.unregister(&stopper));
+        assert!(!chain.unregister(&stopper));
+        assert_eq!(chain.notify(&()), NotifyResult::Ok);
+        assert_eq!(*calls.lock(), vec![1, 0, 2, 4]);
+    }
+}
@@ -0,0 +1,221 @@
+// SPDX-License-Identifier: MPL-2.0
+
+//! The file system to register the miscellaneous binary formats, which is usually mounted
+//! at `/proc/sys/fs/binfmt_misc`.
+//!
+//! A format is registered by writing its description to `register`, and each format has
+//! a file with its name. Reading `status` or the file of a format shows whether it is
+//! enabled, and writing `1`, `0` or `-1` to it enables, disables or removes the format,
+//! or all of the formats for `status`.
+//!
+//! The inodes are built from the templates of procfs.
+
+use super::{
+    procfs::{
+        template::{DirOps, FileOps, ProcDir, ProcDirBuilder, ProcFileBuilder},
+        ProcFS, PROC_ROOT_INO,
+    },
+    utils::{DirEntryVecExt, FileSystem, Inode, InodeMode},
+};
+use crate::{
+    prelude::*,
+    process::binfmt_misc::{self, BinaryFormat},
+};
+
+/// Magic number.
+const BINFMTFS_MAGIC: u64 = 0x42494e4d;
+
+/// Creates a binfmt_misc file system.
+///
+/// The formats are global, so all the binfmt_misc file systems show the same formats.
+pub fn new() -> Arc<dyn FileSystem> {
+    ProcFS::new_with_root(BINFMTFS_MAGIC, RootDirOps::new_inode)
+}
+
+/// Represents the root inode of binfmt_misc.
+struct RootDirOps;
+
+impl RootDirOps {
+    pub fn new_inode(fs: Weak<ProcFS>) -> Arc<dyn Inode> {
+        ProcDirBuilder::new(Self)
+            .fs(fs)
+            .ino(PROC_ROOT_INO)
+            .build()
+            .unwrap()
+    }
+}
+
+impl DirOps for RootDirOps {
+    fn lookup_child(&self, this_ptr: Weak<dyn Inode>, name: &str) -> Result<Arc<dyn Inode>> {
+        let inode = match name {
+            "register" => RegisterFileOps::new_inode(this_ptr),
+            "status" => StatusFileOps::new_inode(this_ptr),
+            name => {
+                if binfmt_misc::get_format(name).is_none() {
+                    return_errno!(Errno::ENOENT);
+                }
+                FormatFileOps::new_inode(name, this_ptr)
+            }
+        };
+        Ok(inode)
+    }
+
+    fn populate_children(&self, this_ptr: Weak<dyn Inode>) {
+        let this = {
+            let this = this_ptr.upgrade().unwrap();
+            this.downcast_ref::<ProcDir<RootDirOps>>().unwrap().this()
+        };
+        remove_stale_children(&this);
+        let mut cached_children = this.cached_children().write();
+        cached_children
+            .put_entry_if_not_found("register", || RegisterFileOps::new_inode(this_ptr.clone()));
+        cached_children
+            .put_entry_if_not_found("status", || StatusFileOps::new_inode(this_ptr.clone()));
+        for format in binfmt_misc::formats() {
+            cached_children.put_entry_if_not_found(format.name(), || {
+                FormatFileOps::new_inode(format.name(), this_ptr.clone())
+            });
+        }
+    }
+}
+
+/// Removes the files of the formats that are removed from the root directory.
+fn remove_stale_children(root: &ProcDir<RootDirOps>) {
+    let mut cached_children = root.cached_children().write();
+    let stale_names: Vec<String> = cached_children
+        .iter()
+        .map(|(name, _)| name.clone())
+        .filter(|name| {
+            !matches!(name.as_str(), "register" | "status")
+                && binfmt_misc::get_format(name).is_none()
+        })
+        .collect();
+    for name in stale_names {
+        cached_children.remove_entry_by_name(&name);
+    }
+}
+
+fn remove_stale_children_of(root: &Weak<dyn Inode>) {
+    if let Some(root) = root.upgrade() {
+        remove_stale_children(root.downcast_ref::<ProcDir<RootDirOps>>().unwrap());
+    }
+}
+
+/// Parses the action written to `status` or the file of a format: `1` to enable, `0` to
+/// disable, or `-1` to remove.
+fn parse_action(buf: &[u8]) -> Result<i32> {
+    match core::str::from_utf8(buf).map(str::trim) {
+        Ok("1") => Ok(1),
+        Ok("0") => Ok(0),
+        Ok("-1") => Ok(-1),
+        _ => return_errno_with_message!(Errno::EINVAL, "only 1, 0 or -1 can be written"),
+    }
+}
+
+fn status_str(is_enabled: bool) -> &'static str {
+    if is_enabled {
+        "enabled\n"
+    } else {
+        "disabled\n"
+    }
+}
+
+/// Represents the inode at `binfmt_misc/register`.
+struct RegisterFileOps;
+
+impl RegisterFileOps {
+    pub fn new_inode(parent: Weak<dyn Inode>) -> Arc<dyn Inode> {
+        ProcFileBuilder::new(Self)
+            .parent(parent)
+            .mode(InodeMode::from_bits_truncate(0o200))
+            .build()
+            .unwrap()
+    }
+}
+
+impl FileOps for RegisterFileOps {
+    fn data(&self) -> Result<Vec<u8>> {
+        return_errno_with_message!(Errno::EINVAL, "the file is write-only");
+    }
+
+    fn write(&self, buf: &[u8]) -> Result<usize> {
+        let rule = core::str::from_utf8(buf)
+            .map_err(|_| Error::with_message(Errno::EINVAL, "invalid registration string"))?;
+        let current = current!();
+        binfmt_misc::register(rule, &current.fs().read())?;
+        Ok(buf.len())
+    }
+}
+
+/// Represents the inode at `binfmt_misc/status`.
+struct StatusFileOps(Weak<dyn Inode>);
+
+impl StatusFileOps {
+    pub fn new_inode(parent: Weak<dyn Inode>) -> Arc<dyn Inode> {
+        ProcFileBuilder::new(Self(parent.clone()))
+            .parent(parent)
+            .mode(InodeMode::from_bits_truncate(0o644))
+            .build()
+            .unwrap()
+    }
+}
+
+impl FileOps for StatusFileOps {
+    fn data(&self) -> Result<Vec<u8>> {
+        Ok(status_str(binfmt_misc::is_enabled()).as_bytes().to_vec())
+    }
+
+    fn write(&self, buf: &[u8]) -> Result<usize> {
+        match parse_action(buf)? {
+            -1 => {
+                binfmt_misc::unregister_all();
+                remove_stale_children_of(&self.0);
+            }
+            action => binfmt_misc::set_enabled(action == 1),
+        }
+        Ok(buf.len())
+    }
+}
+
+/// Represents the inode at `binfmt_misc/[name]`.
+struct FormatFileOps {
+    name: String,
+    parent: Weak<dyn Inode>,
+}
+
+impl FormatFileOps {
+    pub fn new_inode(name: &str, parent: Weak<dyn Inode>) -> Arc<dyn Inode> {
+        ProcFileBuilder::new(Self {
+            name: name.to_string(),
+            parent: parent.clone(),
+        })
+        .parent(parent)
+        .mode(InodeMode::from_bits_truncate(0o644))
+        // The files must be volatile, because the formats may be removed.
+        .volatile()
+        .build()
+        .unwrap()
+    }
+
+    fn format(&self) -> Result<Arc<BinaryFormat>> {
+        binfmt_misc::get_format(&self.name)
+            .ok_or_else(|| Error::with_message(Errno::ENOENT, "the format is removed"))
+    }
+}
+
+impl FileOps for FormatFileOps {
+    fn data(&self) -> Result<Vec<u8>> {
+        Ok(self.format()?.describe().into_bytes())
+    }
+
+    fn write(&self, buf: &[u8]) -> Result<usize> {
+        match parse_action(buf)? {
+            -1 => {
+                binfmt_misc::unregister(&self.name)?;
+                remove_stale_children_of(&self.parent);
+            }
+            action => self.format()?.set_enabled(action == 1),
+        }
+        Ok(buf.len())
+    }
+}
@@ -4,7 +4,7 @@
 #![allow(unused_variables)]
 
 use alloc::string::String;
-use core::{cmp::Ordering, time::Duration};
+use core::{cmp::Ordering, ops::Range, time::Duration};
 
 pub(super) use align_ext::AlignExt;
 use aster_block::{
@@ -1201,6 +1201,18 @@ impl Inode for ExfatInode {
         Some(self.inner.read().page_cache.pages().dup())
     }
 
+    fn readahead(&self, range: Range<usize>) -> Result<()> {
+        self.inner.read().page_cache.readahead(range)
+    }
+
+    fn drop_cache(&self, range: Range<usize>) -> Result<()> {
+        self.inner.read().page_cache.drop_range(range)
+    }
+
+    fn demote_cache(&self, range: Range<usize>) {
+        self.inner.read().page_cache.demote_range(range)
+    }
+
     fn read_at(&self, offset: usize, buf: &mut [u8]) -> Result<usize> {
         let inner = self.inner.upread();
         if inner.inode_type.is_directory() {
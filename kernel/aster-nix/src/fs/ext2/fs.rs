@@ -348,6 +348,15 @@ impl Ext2 {
         Ok(waiter)
     }
 
+    /// Flushes the volatile write cache of the block device, so that the completed writes
+    /// are persistent.
+    pub(super) fn flush_device(&self) -> Result<()> {
+        match self.block_device.flush_sync()? {
+            BioStatus::Complete => Ok(()),
+            err_status => Err(Error::from(err_status)),
+        }
+    }
+
     /// Writes back the metadata to the block device.
     pub fn sync_metadata(&self) -> Result<()> {
         // If the superblock is clean, the block groups must be clean.
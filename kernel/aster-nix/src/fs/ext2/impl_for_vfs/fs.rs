@@ -14,6 +14,7 @@ impl FileSystem for Ext2 {
     fn sync(&self) -> Result<()> {
         self.sync_all_inodes()?;
         self.sync_metadata()?;
+        self.flush_device()?;
         Ok(())
     }
 
@@ -32,18 +33,27 @@ impl FileSystem for Ext2 {
 
 impl From<RwMutexReadGuard<'_, Dirty<Ext2SuperBlock>>> for SuperBlock {
     fn from(ext2_sb: RwMutexReadGuard<Dirty<Ext2SuperBlock>>) -> Self {
+        // The FS ID is folded from the uuid of the volume, just like Linux does.
+        let fsid = {
+            let uuid = ext2_sb.uuid();
+            let low = u64::from_le_bytes(uuid[..8].try_into().unwrap());
+            let high = u64::from_le_bytes(uuid[8..].try_into().unwrap());
+            low ^ high
+        };
         Self {
             magic: EXT2_MAGIC as _,
             bsize: ext2_sb.block_size(),
             blocks: ext2_sb.total_blocks() as _,
             bfree: ext2_sb.free_blocks_count() as _,
-            bavail: ext2_sb.free_blocks_count() as _,
+            bavail: ext2_sb
+                .free_blocks_count()
+                .saturating_sub(ext2_sb.reserved_blocks_count()) as _,
             files: ext2_sb.total_inodes() as _,
             ffree: ext2_sb.free_inodes_count() as _,
-            fsid: 0, // TODO
+            fsid,
             namelen: NAME_MAX,
             frsize: ext2_sb.fragment_size(),
-            flags: 0, // TODO
+            flags: 0,
         }
     }
 }
@@ -2,15 +2,19 @@
 
 #![allow(unused_variables)]
 
-use core::time::Duration;
+use core::{ops::Range, time::Duration};
 
+use aster_frame::mm::Frame;
 use aster_rights::Full;
 
 use crate::{
     fs::{
         device::Device,
-        ext2::{FilePerm, FileType, Inode as Ext2Inode},
-        utils::{DirentVisitor, FileSystem, Inode, InodeMode, InodeType, IoctlCmd, Metadata},
+        ext2::{block_ptr::Ext2Bid, FilePerm, FileType, Inode as Ext2Inode},
+        utils::{
+            DirentVisitor, FallocMode, FileSystem, Inode, InodeMode, InodeType, IoctlCmd, Metadata,
+            XattrSetFlags,
+        },
     },
     prelude::*,
     process::{Gid, Uid},
@@ -32,7 +36,7 @@ impl Inode for Ext2Inode {
             ino: self.ino() as _,
             size: self.file_size() as _,
             blk_size: self.fs().super_block().block_size(),
-            blocks: self.blocks_count() as _,
+            blocks: self.allocated_blocks_count() as _,
             atime: self.atime(),
             mtime: self.mtime(),
             ctime: self.ctime(),
@@ -100,6 +104,46 @@ impl Inode for Ext2Inode {
         Some(self.page_cache())
     }
 
+    fn readahead(&self, range: Range<usize>) -> Result<()> {
+        self.readahead(range)
+    }
+
+    fn drop_cache(&self, range: Range<usize>) -> Result<()> {
+        self.drop_cache(range)
+    }
+
+    fn demote_cache(&self, range: Range<usize>) {
+        self.demote_cache(range)
+    }
+
+    fn fallocate(&self, mode: FallocMode, offset: usize, len: usize) -> Result<()> {
+        self.fallocate(mode, offset, len)
+    }
+
+    fn seek_data(&self, offset: usize) -> Result<usize> {
+        self.seek_data(offset)
+    }
+
+    fn seek_hole(&self, offset: usize) -> Result<usize> {
+        self.seek_hole(offset)
+    }
+
+    fn get_xattr(&self, name: &str) -> Result<Vec<u8>> {
+        self.get_xattr(name)
+    }
+
+    fn set_xattr(&self, name: &str, value: &[u8], flags: XattrSetFlags) -> Result<()> {
+        self.set_xattr(name, value, flags)
+    }
+
+    fn list_xattr(&self) -> Result<Vec<String>> {
+        self.list_xattr()
+    }
+
+    fn remove_xattr(&self, name: &str) -> Result<()> {
+        self.remove_xattr(name)
+    }
+
     fn read_at(&self, offset: usize, buf: &mut [u8]) -> Result<usize> {
         self.read_at(offset, buf)
     }
@@ -116,6 +160,19 @@ impl Inode for Ext2Inode {
         self.write_direct_at(offset, buf)
     }
 
+    // The blocks are of the same size as the pages.
+    fn read_page_direct(&self, idx: usize, frame: &Frame) -> Result<()> {
+        let bid = Ext2Bid::try_from(idx)
+            .map_err(|_| Error::with_message(Errno::EINVAL, "the page is out of range"))?;
+        self.read_block_direct(bid, frame)
+    }
+
+    fn write_page_direct(&self, idx: usize, frame: &Frame) -> Result<()> {
+        let bid = Ext2Bid::try_from(idx)
+            .map_err(|_| Error::with_message(Errno::EINVAL, "the page is out of range"))?;
+        self.write_block_direct(bid, frame)
+    }
+
     fn create(&self, name: &str, type_: InodeType, mode: InodeMode) -> Result<Arc<dyn Inode>> {
         Ok(self.create(name, type_.into(), mode.into())?)
     }
@@ -12,6 +12,7 @@ use super::{
     fs::Ext2,
     indirect_block_cache::{IndirectBlock, IndirectBlockCache},
     prelude::*,
+    xattr,
 };
 
 /// Max length of file name.
@@ -69,10 +70,32 @@ impl Inode {
         Ok(())
     }
 
+    pub fn fallocate(&self, mode: FallocMode, offset: usize, len: usize) -> Result<()> {
+        let inner = self.inner.upread();
+        if inner.file_type() != FileType::File {
+            return_errno!(Errno::EISDIR);
+        }
+
+        let mut inner = inner.upgrade();
+        inner.fallocate(mode, offset..offset + len)
+    }
+
     pub fn page_cache(&self) -> Vmo<Full> {
         self.inner.read().page_cache.pages().dup()
     }
 
+    pub fn readahead(&self, range: Range<usize>) -> Result<()> {
+        self.inner.read().page_cache.readahead(range)
+    }
+
+    pub fn drop_cache(&self, range: Range<usize>) -> Result<()> {
+        self.inner.read().page_cache.drop_range(range)
+    }
+
+    pub fn demote_cache(&self, range: Range<usize>) {
+        self.inner.read().page_cache.demote_range(range)
+    }
+
     pub fn create(
         &self,
         name: &str,
@@ -493,6 +516,48 @@ impl Inode {
         inner.read_direct_at(offset, buf)
     }
 
+    /// Reads the block of the file to the frame directly, bypassing the page cache.
+    ///
+    /// The block is read to the frame without any buffers, so this can be used under
+    /// memory pressure, e.g., to read a page back from a swap file.
+    pub fn read_block_direct(&self, bid: Ext2Bid, frame: &Frame) -> Result<()> {
+        let inner = self.inner.read();
+        if inner.file_type() != FileType::File {
+            return_errno!(Errno::EISDIR);
+        }
+        if bid >= inner.blocks_count() {
+            return_errno_with_message!(Errno::EINVAL, "the block is beyond the end of file");
+        }
+
+        match inner.inode_impl.read_block_async(bid, frame)?.wait() {
+            Some(BioStatus::Complete) => Ok(()),
+            _ => return_errno!(Errno::EIO),
+        }
+    }
+
+    /// Writes the frame to the block of the file directly, bypassing the page cache.
+    ///
+    /// Like [`Self::read_block_direct`], this does not allocate any buffers. Nor does it
+    /// allocate the block, so it fails if the block is a hole.
+    pub fn write_block_direct(&self, bid: Ext2Bid, frame: &Frame) -> Result<()> {
+        let inner = self.inner.read();
+        if inner.file_type() != FileType::File {
+            return_errno!(Errno::EISDIR);
+        }
+        if bid >= inner.blocks_count() {
+            return_errno_with_message!(Errno::EINVAL, "the block is beyond the end of file");
+        }
+
+        match inner
+            .inode_impl
+            .write_allocated_block_async(bid, frame)?
+            .wait()
+        {
+            Some(BioStatus::Complete) => Ok(()),
+            _ => return_errno!(Errno::EIO),
+        }
+    }
+
     pub fn write_at(&self, offset: usize, buf: &[u8]) -> Result<usize> {
         let inner = self.inner.upread();
         if inner.file_type() != FileType::File {
@@ -539,10 +604,22 @@ impl Inode {
         Ok(())
     }
 
+    /// Writes back the data and the metadata of the inode to the block device, which is
+    /// what `fsync` does.
+    ///
+    /// The writes are ordered so that the on-disk filesystem never refers to the blocks that
+    /// have not been written: the data blocks go first, then the indirect blocks and the
+    /// inode that refer to them, and then the bitmaps, the group descriptors and the
+    /// superblock that account for the allocations. Finally, the block device is flushed.
     pub fn sync_all(&self) -> Result<()> {
         let inner = self.inner.read();
         inner.sync_data()?;
         inner.sync_metadata()?;
+        drop(inner);
+
+        let fs = self.fs();
+        fs.sync_metadata()?;
+        fs.flush_device()?;
         Ok(())
     }
 }
@@ -557,12 +634,16 @@ impl Inode {
     pub fn file_flags(&self) -> FileFlags;
     pub fn hard_links(&self) -> u16;
     pub fn blocks_count(&self) -> Ext2Bid;
-    pub fn acl(&self) -> Option<Bid>;
+    pub fn allocated_blocks_count(&self) -> Ext2Bid;
     pub fn atime(&self) -> Duration;
     pub fn mtime(&self) -> Duration;
     pub fn ctime(&self) -> Duration;
     pub fn sync_data(&self) -> Result<()>;
     pub fn sync_metadata(&self) -> Result<()>;
+    pub fn seek_data(&self, offset: usize) -> Result<usize>;
+    pub fn seek_hole(&self, offset: usize) -> Result<usize>;
+    pub fn get_xattr(&self, name: &str) -> Result<Vec<u8>>;
+    pub fn list_xattr(&self) -> Result<Vec<String>>;
 }
 
 #[inherit_methods(from = "self.inner.write()")]
@@ -572,6 +653,8 @@ impl Inode {
     pub fn set_gid(&self, gid: u32);
     pub fn set_atime(&self, time: Duration);
     pub fn set_mtime(&self, time: Duration);
+    pub fn set_xattr(&self, name: &str, value: &[u8], flags: XattrSetFlags) -> Result<()>;
+    pub fn remove_xattr(&self, name: &str) -> Result<()>;
 }
 
 impl Debug for Inode {
@@ -603,7 +686,7 @@ impl Inner {
     pub fn inc_hard_links(&mut self);
     pub fn dec_hard_links(&mut self);
     pub fn blocks_count(&self) -> Ext2Bid;
-    pub fn acl(&self) -> Option<Bid>;
+    pub fn allocated_blocks_count(&self) -> Ext2Bid;
     pub fn atime(&self) -> Duration;
     pub fn set_atime(&mut self, time: Duration);
     pub fn mtime(&self) -> Duration;
@@ -612,6 +695,10 @@ impl Inner {
     pub fn set_device_id(&mut self, device_id: u64);
     pub fn device_id(&self) -> u64;
     pub fn sync_metadata(&self) -> Result<()>;
+    pub fn get_xattr(&self, name: &str) -> Result<Vec<u8>>;
+    pub fn set_xattr(&mut self, name: &str, value: &[u8], flags: XattrSetFlags) -> Result<()>;
+    pub fn list_xattr(&self) -> Result<Vec<String>>;
+    pub fn remove_xattr(&mut self, name: &str) -> Result<()>;
 }
 
 impl Inner {
@@ -626,11 +713,109 @@ impl Inner {
     }
 
     pub fn resize(&mut self, new_size: usize) -> Result<()> {
+        let old_size = self.inode_impl.file_size();
+        if new_size < old_size && new_size % BLOCK_SIZE != 0 {
+            // Zeroes the truncated part of the last block, which would be exposed again
+            // if the file is extended later.
+            let tail_end = new_size.align_up(BLOCK_SIZE).min(old_size);
+            self.page_cache.pages().clear(new_size..tail_end)?;
+        }
+
         self.inode_impl.resize(new_size)?;
         self.page_cache.pages().resize(new_size)?;
         Ok(())
     }
 
+    pub fn fallocate(&mut self, mode: FallocMode, range: Range<usize>) -> Result<()> {
+        if mode.contains(FallocMode::PUNCH_HOLE) {
+            return self.punch_hole(range);
+        }
+        if mode.contains(FallocMode::ZERO_RANGE) {
+            self.punch_hole(range.clone())?;
+        }
+
+        // Only the blocks within the file are allocated, so the range beyond the end
+        // of the file is ignored if the size is kept.
+        let file_size = self.inode_impl.file_size();
+        if range.end > file_size && !mode.contains(FallocMode::KEEP_SIZE) {
+            self.resize(range.end)?;
+        }
+        let end = range.end.min(self.inode_impl.file_size());
+        if range.start < end {
+            self.inode_impl.alloc_range(range.start..end)?;
+        }
+        Ok(())
+    }
+
+    /// Deallocates the range of the file, which is read as zeros afterwards.
+    fn punch_hole(&mut self, range: Range<usize>) -> Result<()> {
+        let file_size = self.inode_impl.file_size();
+        let range = range.start.min(file_size)..range.end.min(file_size);
+        if range.is_empty() {
+            return Ok(());
+        }
+
+        // The blocks that are fully in the range are freed, including the last block
+        // of the file, whose bytes beyond the end of the file are zeros.
+        let block_range = {
+            let start = range.start.align_up(BLOCK_SIZE);
+            let end = if range.end == file_size {
+                file_size.align_up(BLOCK_SIZE)
+            } else {
+                range.end.align_down(BLOCK_SIZE)
+            };
+            start..end.max(start)
+        };
+        if block_range.is_empty() {
+            self.page_cache.pages().clear(range)?;
+            return Ok(());
+        }
+
+        // The cached pages are dropped without being written back, which would
+        // allocate the blocks again.
+        self.page_cache.discard_range(block_range.clone());
+        self.page_cache.pages().decommit(block_range.clone())?;
+        self.inode_impl.free_range(bid_range_of(&block_range))?;
+
+        // The partial blocks at both ends are zeroed.
+        if range.start < block_range.start {
+            self.page_cache
+                .pages()
+                .clear(range.start..block_range.start)?;
+        }
+        if block_range.end < range.end {
+            self.page_cache.pages().clear(block_range.end..range.end)?;
+        }
+        Ok(())
+    }
+
+    pub fn seek_data(&self, offset: usize) -> Result<usize> {
+        self.seek_block(offset, true)
+    }
+
+    pub fn seek_hole(&self, offset: usize) -> Result<usize> {
+        self.seek_block(offset, false)
+    }
+
+    /// Returns the offset of the first data if `is_data` is true, or the first hole
+    /// otherwise, at or after the offset.
+    fn seek_block(&self, offset: usize, is_data: bool) -> Result<usize> {
+        let file_size = self.inode_impl.file_size();
+        if offset >= file_size {
+            return_errno_with_message!(Errno::ENXIO, "the offset is beyond the end of the file");
+        }
+
+        let bid_range = bid_range_of(&(offset..file_size));
+        let Some(bid) = self.inode_impl.find_block(bid_range, is_data)? else {
+            // The end of the file is regarded as a hole.
+            if is_data {
+                return_errno_with_message!(Errno::ENXIO, "no data after the offset");
+            }
+            return Ok(file_size);
+        };
+        Ok((bid as usize * BLOCK_SIZE).clamp(offset, file_size))
+    }
+
     pub fn read_at(&self, offset: usize, buf: &mut [u8]) -> Result<usize> {
         let (offset, read_len) = {
             let file_size = self.inode_impl.file_size();
@@ -654,26 +839,53 @@ impl Inner {
         };
         self.page_cache.discard_range(offset..offset + read_len);
 
-        let mut buf_offset = 0;
+        // All the reads are submitted before waiting for any of them, so that the
+        // block device can merge and reorder them.
+        let mut frames = Vec::new();
+        let mut bio_waiter = BioWaiter::new();
+        let mut result = Ok(());
         for bid in Bid::from_offset(offset)..Bid::from_offset(offset + read_len) {
             let frame = FrameAllocOptions::new(1)
                 .uninit(true)
                 .alloc_single()
                 .unwrap();
-            self.inode_impl
-                .read_block_sync(bid.to_raw() as Ext2Bid, &frame)?;
-            frame.read_bytes(0, &mut buf[buf_offset..buf_offset + BLOCK_SIZE])?;
-            buf_offset += BLOCK_SIZE;
+            match self
+                .inode_impl
+                .read_block_async(bid.to_raw() as Ext2Bid, &frame)
+            {
+                Ok(waiter) => bio_waiter.concat(waiter),
+                Err(err) => {
+                    result = Err(err);
+                    break;
+                }
+            }
+            frames.push(frame);
+        }
+        // Waits for the submitted reads even if the submission fails, since the frames
+        // are in use.
+        if bio_waiter.wait() != Some(BioStatus::Complete) && result.is_ok() {
+            result = Err(Error::new(Errno::EIO));
+        }
+        result?;
+
+        for (idx, frame) in frames.iter().enumerate() {
+            frame.read_bytes(0, &mut buf[idx * BLOCK_SIZE..(idx + 1) * BLOCK_SIZE])?;
         }
         Ok(read_len)
     }
 
     pub fn write_at(&self, offset: usize, buf: &[u8]) -> Result<()> {
+        // Allocates the blocks in advance, so that running out of space is reported
+        // by the write instead of the writeback.
+        self.inode_impl.alloc_range(offset..offset + buf.len())?;
         self.page_cache.pages().write_bytes(offset, buf)?;
         Ok(())
     }
 
     pub fn extend_write_at(&mut self, offset: usize, buf: &[u8]) -> Result<()> {
+        let file_size = self.inode_impl.file_size();
+        self.inode_impl
+            .alloc_range(offset.min(file_size)..file_size)?;
         let new_size = offset + buf.len();
         self.page_cache.pages().resize(new_size)?;
         self.page_cache.pages().write_bytes(offset, buf)?;
@@ -694,7 +906,11 @@ impl Inner {
             self.inode_impl.resize(end_offset)?;
         }
 
+        // All the writes are submitted before waiting for any of them, so that the
+        // block device can merge and reorder them.
         let mut buf_offset = 0;
+        let mut bio_waiter = BioWaiter::new();
+        let mut result = Ok(());
         for bid in Bid::from_offset(offset)..Bid::from_offset(end_offset) {
             let frame = {
                 let frame = FrameAllocOptions::new(1)
@@ -704,12 +920,22 @@ impl Inner {
                 frame.write_bytes(0, &buf[buf_offset..buf_offset + BLOCK_SIZE])?;
                 frame
             };
-            self.inode_impl
-                .write_block_sync(bid.to_raw() as Ext2Bid, &frame)?;
+            match self
+                .inode_impl
+                .write_block_async(bid.to_raw() as Ext2Bid, &frame)
+            {
+                Ok(waiter) => bio_waiter.concat(waiter),
+                Err(err) => {
+                    result = Err(err);
+                    break;
+                }
+            }
             buf_offset += BLOCK_SIZE;
         }
-
-        Ok(())
+        if bio_waiter.wait() != Some(BioStatus::Complete) && result.is_ok() {
+            result = Err(Error::new(Errno::EIO));
+        }
+        result
     }
 
     pub fn write_link(&mut self, target: &str) -> Result<()> {
@@ -824,6 +1050,8 @@ struct InodeImpl_ {
     indirect_blocks: RwMutex<IndirectBlockCache>,
     is_freed: bool,
     last_alloc_device_bid: Option<Ext2Bid>,
+    /// The cached extended attributes, which are loaded from the xattr block on demand.
+    xattrs: RwLock<Option<Xattrs>>,
     weak_self: Weak<Inode>,
 }
 
@@ -835,6 +1063,7 @@ impl InodeImpl_ {
             indirect_blocks: RwMutex::new(IndirectBlockCache::new(fs)),
             is_freed: false,
             last_alloc_device_bid: None,
+            xattrs: RwLock::new(None),
             weak_self,
         }
     }
@@ -847,6 +1076,31 @@ impl InodeImpl_ {
         self.inode().fs()
     }
 
+    fn xattrs(&self) -> Result<Xattrs> {
+        let Some(xattr_bid) = self.desc.xattr_bid else {
+            return Ok(Xattrs::new());
+        };
+        if let Some(xattrs) = self.xattrs.read().as_ref() {
+            return Ok(xattrs.clone());
+        }
+
+        let xattrs = xattr::read_xattrs(&self.fs(), xattr_bid)?;
+        *self.xattrs.write() = Some(xattrs.clone());
+        Ok(xattrs)
+    }
+
+    fn set_xattrs(&mut self, xattrs: Xattrs) -> Result<()> {
+        let inode = self.inode();
+        self.desc.xattr_bid = xattr::write_xattrs(
+            &inode.fs(),
+            inode.block_group_idx(),
+            self.desc.xattr_bid,
+            &xattrs,
+        )?;
+        *self.xattrs.write() = Some(xattrs);
+        Ok(())
+    }
+
     pub fn read_block_async(&self, bid: Ext2Bid, block: &Frame) -> Result<BioWaiter> {
         if bid >= self.desc.blocks_count() {
             return_errno!(Errno::EINVAL);
@@ -858,14 +1112,12 @@ impl InodeImpl_ {
         }
 
         let device_range = DeviceRangeReader::new(self, bid..bid + 1)?.read()?;
-        self.fs().read_block_async(device_range.start, block)
-    }
-
-    pub fn read_block_sync(&self, bid: Ext2Bid, block: &Frame) -> Result<()> {
-        match self.read_block_async(bid, block)?.wait() {
-            Some(BioStatus::Complete) => Ok(()),
-            _ => return_errno!(Errno::EIO),
+        if device_range.start == 0 {
+            // The unallocated blocks are read as zeros.
+            block.writer().fill(0);
+            return Ok(BioWaiter::new());
         }
+        self.fs().read_block_async(device_range.start, block)
     }
 
     pub fn write_block_async(&self, bid: Ext2Bid, block: &Frame) -> Result<BioWaiter> {
@@ -874,6 +1126,9 @@ impl InodeImpl_ {
         }
 
         let device_range = DeviceRangeReader::new(self, bid..bid + 1)?.read()?;
+        if device_range.start == 0 {
+            return_errno_with_message!(Errno::EINVAL, "the block is not allocated");
+        }
         let waiter = self.fs().write_block_async(device_range.start, block)?;
 
         // FIXME: Unset the block hole in the callback function of bio.
@@ -964,13 +1219,7 @@ impl InodeImpl_ {
             (max_cnt, indirect_cnt)
         };
 
-        // Calculates the block_group_idx to advise the filesystem on which group
-        // to prioritize for allocation.
-        let block_group_idx = self
-            .last_alloc_device_bid
-            .map_or(self.inode().block_group_idx, |id| {
-                ((id + 1) / self.fs().blocks_per_group()) as usize
-            });
+        let block_group_idx = self.alloc_group_idx();
 
         // Allocates the blocks only, no indirect blocks are required.
         if indirect_cnt == 0 {
@@ -978,7 +1227,7 @@ impl InodeImpl_ {
                 .fs()
                 .alloc_blocks(block_group_idx, max_cnt)
                 .ok_or_else(|| Error::new(Errno::ENOSPC))?;
-            if let Err(e) = self.set_device_range(range.start, device_range.clone()) {
+            if let Err(e) = self.set_device_bids(range.start, device_range.clone()) {
                 self.fs().free_blocks(device_range).unwrap();
                 return Err(e);
             }
@@ -1022,7 +1271,7 @@ impl InodeImpl_ {
             return Err(e);
         }
 
-        if let Err(e) = self.set_device_range(range.start, device_range.clone()) {
+        if let Err(e) = self.set_device_bids(range.start, device_range.clone()) {
             self.fs().free_blocks(device_range).unwrap();
             self.free_indirect_blocks_required_by(range.start).unwrap();
             return Err(e);
@@ -1033,15 +1282,31 @@ impl InodeImpl_ {
         Ok(device_range.len() as Ext2Bid)
     }
 
+    /// Calculates the block_group_idx to advise the filesystem on which group
+    /// to prioritize for allocation.
+    fn alloc_group_idx(&self) -> usize {
+        self.last_alloc_device_bid
+            .map_or(self.inode().block_group_idx, |id| {
+                ((id + 1) / self.fs().blocks_per_group()) as usize
+            })
+    }
+
     /// Sets the device block IDs for a specified range.
     ///
     /// It updates the mapping between the file's block IDs and the device's block IDs
     /// starting from `start_bid`. It maps each block ID in the file to the corresponding
-    /// block ID on the device based on the provided `device_range`.
-    fn set_device_range(&mut self, start_bid: Ext2Bid, device_range: Range<Ext2Bid>) -> Result<()> {
+    /// block ID on the device based on the provided `device_bids`, where a zero block ID
+    /// means that the block is not allocated.
+    ///
+    /// The range must not cross the boundary of an indirect block.
+    fn set_device_bids(
+        &mut self,
+        start_bid: Ext2Bid,
+        device_bids: impl IntoIterator<Item = Ext2Bid>,
+    ) -> Result<()> {
         match BidPath::from(start_bid) {
             BidPath::Direct(idx) => {
-                for (i, bid) in device_range.enumerate() {
+                for (i, bid) in device_bids.into_iter().enumerate() {
                     self.desc.block_ptrs.set_direct(idx as usize + i, bid);
                 }
             }
@@ -1050,7 +1315,7 @@ impl InodeImpl_ {
                 assert!(indirect_bid != 0);
                 let mut indirect_blocks = self.indirect_blocks.write();
                 let indirect_block = indirect_blocks.find_mut(indirect_bid)?;
-                for (i, bid) in device_range.enumerate() {
+                for (i, bid) in device_bids.into_iter().enumerate() {
                     indirect_block.write_bid(idx as usize + i, &bid)?;
                 }
             }
@@ -1065,7 +1330,7 @@ impl InodeImpl_ {
                 assert!(lvl1_indirect_bid != 0);
 
                 let lvl1_indirect_block = indirect_blocks.find_mut(lvl1_indirect_bid)?;
-                for (i, bid) in device_range.enumerate() {
+                for (i, bid) in device_bids.into_iter().enumerate() {
                     lvl1_indirect_block.write_bid(lvl2_idx as usize + i, &bid)?;
                 }
             }
@@ -1085,7 +1350,7 @@ impl InodeImpl_ {
                 assert!(lvl2_indirect_bid != 0);
 
                 let lvl2_indirect_block = indirect_blocks.find_mut(lvl2_indirect_bid)?;
-                for (i, bid) in device_range.enumerate() {
+                for (i, bid) in device_bids.into_iter().enumerate() {
                     lvl2_indirect_block.write_bid(lvl3_idx as usize + i, &bid)?;
                 }
             }
@@ -1182,13 +1447,12 @@ impl InodeImpl_ {
         self.last_alloc_device_bid = if range.start == 0 {
             None
         } else {
-            Some(
-                DeviceRangeReader::new(self, (range.start - 1)..range.start)
-                    .unwrap()
-                    .read()
-                    .unwrap()
-                    .start,
-            )
+            let device_bid = DeviceRangeReader::new(self, (range.start - 1)..range.start)
+                .unwrap()
+                .read()
+                .unwrap()
+                .start;
+            (device_bid != 0).then_some(device_bid)
         };
     }
 
@@ -1208,13 +1472,111 @@ impl InodeImpl_ {
         let fs = self.fs();
         let device_range_reader = DeviceRangeReader::new(self, range.clone()).unwrap();
         for device_range in device_range_reader {
-            fs.free_blocks(device_range.clone()).unwrap();
+            if device_range.start != 0 {
+                fs.free_blocks(device_range.clone()).unwrap();
+            }
         }
 
         self.free_indirect_blocks_required_by(range.start).unwrap();
         range.len() as Ext2Bid
     }
 
+    /// Returns the runs of the consecutive blocks within the range, each of which is
+    /// the ID of its first block and the corresponding device range.
+    ///
+    /// The device range of a run of unallocated blocks starts from zero.
+    fn device_runs(&self, range: Range<Ext2Bid>) -> Result<Vec<(Ext2Bid, Range<Ext2Bid>)>> {
+        let mut runs = Vec::new();
+        if range.is_empty() {
+            return Ok(runs);
+        }
+
+        let mut bid = range.start;
+        let mut reader = DeviceRangeReader::new(self, range.clone())?;
+        while bid < range.end {
+            let device_range = reader.read()?;
+            let cnt = device_range.len() as Ext2Bid;
+            runs.push((bid, device_range));
+            bid += cnt;
+        }
+        Ok(runs)
+    }
+
+    /// Returns the ranges of the unallocated blocks within the range.
+    fn unallocated_ranges(&self, range: Range<Ext2Bid>) -> Result<Vec<Range<Ext2Bid>>> {
+        let mut ranges: Vec<Range<Ext2Bid>> = Vec::new();
+        for (bid, device_range) in self.device_runs(range)? {
+            if device_range.start != 0 {
+                continue;
+            }
+            let end = bid + device_range.len() as Ext2Bid;
+            match ranges.last_mut() {
+                Some(last) if last.end == bid => last.end = end,
+                _ => ranges.push(bid..end),
+            }
+        }
+        Ok(ranges)
+    }
+
+    /// Allocates the unallocated blocks within the range.
+    ///
+    /// The newly allocated blocks are marked as holes, so they are read as zeros
+    /// before being written.
+    fn alloc_blocks_in(&mut self, range: Range<Ext2Bid>) -> Result<()> {
+        let unallocated_ranges = self.unallocated_ranges(range)?;
+        let cnt: usize = unallocated_ranges.iter().map(|range| range.len()).sum();
+        if cnt as Ext2Bid > self.fs().super_block().free_blocks_count() {
+            return_errno_with_message!(Errno::ENOSPC, "not enough free blocks");
+        }
+
+        for mut current_range in unallocated_ranges {
+            while !current_range.is_empty() {
+                // The device blocks of a run are set in the same indirect block.
+                let max_cnt = (current_range.len() as Ext2Bid)
+                    .min(BidPath::from(current_range.start).cnt_to_next_indirect());
+                let device_range = self
+                    .fs()
+                    .alloc_blocks(self.alloc_group_idx(), max_cnt)
+                    .ok_or_else(|| Error::new(Errno::ENOSPC))?;
+                if let Err(e) = self.set_device_bids(current_range.start, device_range.clone()) {
+                    self.fs().free_blocks(device_range).unwrap();
+                    return Err(e);
+                }
+
+                let cnt = device_range.len() as Ext2Bid;
+                let mut blocks_hole_desc = self.blocks_hole_desc.write();
+                for bid in current_range.start..current_range.start + cnt {
+                    blocks_hole_desc.set(bid as usize);
+                }
+                drop(blocks_hole_desc);
+                self.last_alloc_device_bid = Some(device_range.end - 1);
+                current_range.start += cnt;
+            }
+        }
+        Ok(())
+    }
+
+    /// Frees the data blocks within the range, which become unallocated.
+    ///
+    /// The indirect blocks are kept until the file is shrunk.
+    fn free_blocks_in(&mut self, range: Range<Ext2Bid>) -> Result<()> {
+        let fs = self.fs();
+        for (bid, device_range) in self.device_runs(range)? {
+            if device_range.start == 0 {
+                continue;
+            }
+            let cnt = device_range.len() as Ext2Bid;
+            self.set_device_bids(bid, core::iter::repeat(0).take(cnt as usize))?;
+            fs.free_blocks(device_range).unwrap();
+
+            let mut blocks_hole_desc = self.blocks_hole_desc.write();
+            for bid in bid..bid + cnt {
+                blocks_hole_desc.unset(bid as usize);
+            }
+        }
+        Ok(())
+    }
+
     /// Frees the indirect blocks required by the specified block ID.
     ///
     /// It ensures that the indirect blocks that are required by the block ID
@@ -1311,6 +1673,11 @@ impl InodeImpl_ {
     }
 }
 
+/// Returns the range of the blocks that cover the range of bytes.
+fn bid_range_of(range: &Range<usize>) -> Range<Ext2Bid> {
+    (range.start / BLOCK_SIZE) as Ext2Bid..range.end.div_ceil(BLOCK_SIZE) as Ext2Bid
+}
+
 /// A reader to get the corresponding device block IDs for a specified range.
 ///
 /// It calculates and returns the range of block IDs on the device that would map to
@@ -1345,7 +1712,8 @@ impl<'a> DeviceRangeReader<'a> {
     /// Reads the corresponding device block IDs for a specified range.
     ///
     /// Note that the returned device range size may be smaller than the requested range
-    /// due to possible inconsecutive block allocation.
+    /// due to possible inconsecutive block allocation. A run of unallocated blocks is
+    /// returned as a device range starting from zero.
     pub fn read(&mut self) -> Result<Range<Ext2Bid>> {
         let bid_path = BidPath::from(self.range.start);
         let max_cnt = self
@@ -1363,7 +1731,12 @@ impl<'a> DeviceRangeReader<'a> {
             };
             match device_range {
                 Some(ref mut range) => {
-                    if device_bid == range.end {
+                    let is_consecutive = if range.start == 0 {
+                        device_bid == 0
+                    } else {
+                        device_bid == range.end
+                    };
+                    if is_consecutive {
                         range.end += 1;
                     } else {
                         break;
@@ -1378,7 +1751,7 @@ impl<'a> DeviceRangeReader<'a> {
 
         // Updates the range
         self.range.start += device_range.len() as Ext2Bid;
-        if device_range.len() == max_cnt {
+        if device_range.len() == max_cnt && !self.range.is_empty() {
             // Updates the indirect block
             self.update_indirect_block()?;
         }
@@ -1508,8 +1881,26 @@ impl InodeImpl {
         self.0.read().desc.blocks_count()
     }
 
-    pub fn acl(&self) -> Option<Bid> {
-        self.0.read().desc.acl
+    pub fn get_xattr(&self, name: &str) -> Result<Vec<u8>> {
+        self.0.read().xattrs()?.get(name)
+    }
+
+    pub fn set_xattr(&self, name: &str, value: &[u8], flags: XattrSetFlags) -> Result<()> {
+        let mut inner = self.0.write();
+        let mut xattrs = inner.xattrs()?;
+        xattrs.set(name, value, flags)?;
+        inner.set_xattrs(xattrs)
+    }
+
+    pub fn list_xattr(&self) -> Result<Vec<String>> {
+        Ok(self.0.read().xattrs()?.names())
+    }
+
+    pub fn remove_xattr(&self, name: &str) -> Result<()> {
+        let mut inner = self.0.write();
+        let mut xattrs = inner.xattrs()?;
+        xattrs.remove(name)?;
+        inner.set_xattrs(xattrs)
     }
 
     pub fn atime(&self) -> Duration {
@@ -1534,20 +1925,64 @@ impl InodeImpl {
         self.0.read().desc.ctime
     }
 
-    pub fn read_block_sync(&self, bid: Ext2Bid, block: &Frame) -> Result<()> {
-        self.0.read().read_block_sync(bid, block)
-    }
-
     pub fn read_block_async(&self, bid: Ext2Bid, block: &Frame) -> Result<BioWaiter> {
         self.0.read().read_block_async(bid, block)
     }
 
-    pub fn write_block_sync(&self, bid: Ext2Bid, block: &Frame) -> Result<()> {
-        self.0.read().write_block_sync(bid, block)
+    /// Writes the block, which fails if it is unallocated.
+    pub fn write_allocated_block_async(&self, bid: Ext2Bid, block: &Frame) -> Result<BioWaiter> {
+        self.0.read().write_block_async(bid, block)
     }
 
+    /// Writes the block, which is allocated first if it is unallocated.
     pub fn write_block_async(&self, bid: Ext2Bid, block: &Frame) -> Result<BioWaiter> {
-        self.0.read().write_block_async(bid, block)
+        let inner = self.0.upread();
+        if bid < inner.desc.blocks_count() && !inner.unallocated_ranges(bid..bid + 1)?.is_empty() {
+            let mut inner = inner.upgrade();
+            inner.alloc_blocks_in(bid..bid + 1)?;
+            return inner.write_block_async(bid, block);
+        }
+        inner.write_block_async(bid, block)
+    }
+
+    /// Allocates the unallocated blocks within the range of bytes.
+    pub fn alloc_range(&self, range: Range<usize>) -> Result<()> {
+        if range.is_empty() {
+            return Ok(());
+        }
+        let bid_range = bid_range_of(&range);
+        let inner = self.0.upread();
+        if inner.unallocated_ranges(bid_range.clone())?.is_empty() {
+            return Ok(());
+        }
+        inner.upgrade().alloc_blocks_in(bid_range)
+    }
+
+    /// Frees the data blocks within the range of block IDs.
+    pub fn free_range(&self, range: Range<Ext2Bid>) -> Result<()> {
+        self.0.write().free_blocks_in(range)
+    }
+
+    /// Returns the number of the allocated data blocks.
+    pub fn allocated_blocks_count(&self) -> Ext2Bid {
+        let inner = self.0.read();
+        let blocks_count = inner.desc.blocks_count();
+        let unallocated_ranges = inner.unallocated_ranges(0..blocks_count).unwrap();
+        blocks_count
+            - unallocated_ranges
+                .iter()
+                .map(|range| range.len() as Ext2Bid)
+                .sum::<Ext2Bid>()
+    }
+
+    /// Returns the ID of the first block within the range that is allocated if
+    /// `is_allocated` is true, or unallocated otherwise.
+    pub fn find_block(&self, range: Range<Ext2Bid>, is_allocated: bool) -> Result<Option<Ext2Bid>> {
+        let runs = self.0.read().device_runs(range)?;
+        Ok(runs
+            .into_iter()
+            .find(|(_, device_range)| (device_range.start != 0) == is_allocated)
+            .map(|(bid, _)| bid))
     }
 
     pub fn set_device_id(&self, device_id: u64) {
@@ -1606,6 +2041,9 @@ impl InodeImpl {
             inner.resize(0)?;
             // Adds the check here to prevent double-free.
             if !inner.is_freed {
+                if let Some(xattr_bid) = inner.desc.xattr_bid.take() {
+                    xattr::release_xattr_block(&inode.fs(), xattr_bid)?;
+                }
                 inode
                     .fs()
                     .free_inode(inode.ino(), inner.desc.type_ == FileType::Dir)?;
@@ -1671,8 +2109,8 @@ pub(super) struct InodeDesc {
     flags: FileFlags,
     /// Pointers to blocks.
     block_ptrs: BlockPtrs,
-    /// File or directory acl block.
-    acl: Option<Bid>,
+    /// Block of extended attributes.
+    xattr_bid: Option<Ext2Bid>,
 }
 
 impl TryFrom<RawInode> for InodeDesc {
@@ -1699,11 +2137,7 @@ impl TryFrom<RawInode> for InodeDesc {
             flags: FileFlags::from_bits(inode.flags)
                 .ok_or(Error::with_message(Errno::EINVAL, "invalid file flags"))?,
             block_ptrs: inode.block_ptrs,
-            acl: match file_type {
-                FileType::File => Some(Bid::new(inode.file_acl as _)),
-                FileType::Dir => Some(Bid::new(inode.size_high as _)),
-                _ => None,
-            },
+            xattr_bid: (inode.file_acl != 0).then_some(inode.file_acl),
         })
     }
 }
@@ -1724,10 +2158,7 @@ impl InodeDesc {
             blocks_count: 0,
             flags: FileFlags::empty(),
             block_ptrs: BlockPtrs::default(),
-            acl: match type_ {
-                FileType::File | FileType::Dir => Some(Bid::new(0)),
-                _ => None,
-            },
+            xattr_bid: None,
         })
     }
 
@@ -1920,14 +2351,7 @@ impl From<&InodeDesc> for RawInode {
             blocks_count: inode.blocks_count,
             flags: inode.flags.bits(),
             block_ptrs: inode.block_ptrs,
-            file_acl: match inode.acl {
-                Some(acl) if inode.type_ == FileType::File => acl.to_raw() as u32,
-                _ => Default::default(),
-            },
-            size_high: match inode.acl {
-                Some(acl) if inode.type_ == FileType::Dir => acl.to_raw() as u32,
-                _ => Default::default(),
-            },
+            file_acl: inode.xattr_bid.unwrap_or_default(),
             os_dependent_2: Osd2 {
                 uid_high: (inode.uid >> 16) as u16,
                 gid_high: (inode.gid >> 16) as u16,
@@ -20,7 +20,10 @@ pub(super) use static_assertions::const_assert;
 
 pub(super) use super::utils::{Dirty, IsPowerOf};
 pub(super) use crate::{
-    fs::utils::{CStr256, DirentVisitor, InodeType, PageCache, PageCacheBackend, Str16, Str64},
+    fs::utils::{
+        CStr256, DirentVisitor, FallocMode, InodeType, PageCache, PageCacheBackend, Str16, Str64,
+        XattrSetFlags, Xattrs,
+    },
     prelude::*,
     time::UnixTime,
     vm::vmo::Vmo,
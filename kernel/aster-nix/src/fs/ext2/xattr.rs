@@ -0,0 +1,323 @@
+// SPDX-License-Identifier: MPL-2.0
+
+//! Extended attributes of the Ext2 filesystem.
+//!
+//! The extended attributes of an inode are stored in a single block, which is referred
+//! by the `file_acl` field of the inode and may be shared by several inodes. The block
+//! starts with a header, followed by the entries sorted by their names, and the values
+//! are packed from the end of the block.
+//!
+//! The POSIX ACLs are stored in the compact format of Ext2 rather than the format used
+//! by the extended attributes of the VFS, so they are converted when read or written.
+
+use super::{block_ptr::Ext2Bid, fs::Ext2, prelude::*};
+use crate::fs::utils::{XATTR_NAME_POSIX_ACL_ACCESS, XATTR_NAME_POSIX_ACL_DEFAULT};
+
+/// The magic number of the block of extended attributes.
+const XATTR_MAGIC: u32 = 0xEA02_0000;
+const HEADER_LEN: usize = core::mem::size_of::<RawXattrHeader>();
+const ENTRY_HEADER_LEN: usize = core::mem::size_of::<RawXattrEntry>();
+/// The entries and the values are aligned to 4 bytes.
+const XATTR_ALIGN: usize = 4;
+
+const ACL_ACCESS_INDEX: u8 = 2;
+const ACL_DEFAULT_INDEX: u8 = 3;
+/// The name indexes on the device and the prefixes of the names they stand for.
+const NAME_INDEXES: [(u8, &str); 5] = [
+    (1, "user."),
+    (ACL_ACCESS_INDEX, XATTR_NAME_POSIX_ACL_ACCESS),
+    (ACL_DEFAULT_INDEX, XATTR_NAME_POSIX_ACL_DEFAULT),
+    (4, "trusted."),
+    (6, "security."),
+];
+
+/// Reads the extended attributes stored in the block.
+pub(super) fn read_xattrs(fs: &Ext2, bid: Ext2Bid) -> Result<Xattrs> {
+    let (frame, _) = read_xattr_block(fs, bid)?;
+    let mut xattrs = Xattrs::new();
+    let mut offset = HEADER_LEN;
+    while offset + XATTR_ALIGN <= BLOCK_SIZE && frame.read_val::<u32>(offset)? != 0 {
+        if offset + ENTRY_HEADER_LEN > BLOCK_SIZE {
+            return_errno_with_message!(Errno::EIO, "the xattr entry is out of the block");
+        }
+        let entry = frame.read_val::<RawXattrEntry>(offset)?;
+        let name_offset = offset + ENTRY_HEADER_LEN;
+        let value_range = {
+            let start = entry.value_offs as usize;
+            start..start + entry.value_size as usize
+        };
+        if name_offset + entry.name_len as usize > BLOCK_SIZE
+            || value_range.end > BLOCK_SIZE
+            || entry.value_block != 0
+        {
+            return_errno_with_message!(Errno::EIO, "the xattr entry is corrupted");
+        }
+
+        let mut suffix = vec![0; entry.name_len as usize];
+        frame.read_bytes(name_offset, &mut suffix)?;
+        let mut value = vec![0; value_range.len()];
+        frame.read_bytes(value_range.start, &mut value)?;
+        // The entries in unknown namespaces are kept on the device but invisible.
+        if let Some(&(index, prefix)) = NAME_INDEXES
+            .iter()
+            .find(|(index, _)| *index == entry.name_index)
+        {
+            let name = format!("{}{}", prefix, String::from_utf8_lossy(&suffix));
+            let value = if is_acl_index(index) {
+                acl_from_disk(&value)?
+            } else {
+                value
+            };
+            xattrs.set(&name, &value, XattrSetFlags::empty())?;
+        }
+        offset = name_offset + (entry.name_len as usize).align_up(XATTR_ALIGN);
+    }
+    Ok(xattrs)
+}
+
+/// Writes the extended attributes of an inode whose block is `old_bid`.
+///
+/// The block is rewritten in place unless it is shared with other inodes, in which case
+/// a new block is allocated. Returns the block of the attributes, or `None` if there are
+/// no attributes.
+pub(super) fn write_xattrs(
+    fs: &Ext2,
+    block_group_idx: usize,
+    old_bid: Option<Ext2Bid>,
+    xattrs: &Xattrs,
+) -> Result<Option<Ext2Bid>> {
+    if xattrs.is_empty() {
+        if let Some(old_bid) = old_bid {
+            release_xattr_block(fs, old_bid)?;
+        }
+        return Ok(None);
+    }
+
+    let frame = encode_xattrs(xattrs)?;
+    if let Some(old_bid) = old_bid {
+        let (_, header) = read_xattr_block(fs, old_bid)?;
+        if header.refcount == 1 {
+            fs.write_block(old_bid, &frame)?;
+            return Ok(Some(old_bid));
+        }
+    }
+
+    let bid = fs
+        .alloc_blocks(block_group_idx, 1)
+        .ok_or_else(|| Error::with_message(Errno::ENOSPC, "no space for the xattr block"))?
+        .start;
+    if let Err(e) = fs.write_block(bid, &frame) {
+        fs.free_blocks(bid..bid + 1)?;
+        return Err(e);
+    }
+    if let Some(old_bid) = old_bid {
+        release_xattr_block(fs, old_bid)?;
+    }
+    Ok(Some(bid))
+}
+
+/// Drops a reference to the block, which is freed if no other inodes share it.
+pub(super) fn release_xattr_block(fs: &Ext2, bid: Ext2Bid) -> Result<()> {
+    let (frame, mut header) = read_xattr_block(fs, bid)?;
+    if header.refcount > 1 {
+        header.refcount -= 1;
+        frame.write_val(0, &header)?;
+        return fs.write_block(bid, &frame);
+    }
+    fs.free_blocks(bid..bid + 1)
+}
+
+fn read_xattr_block(fs: &Ext2, bid: Ext2Bid) -> Result<(Frame, RawXattrHeader)> {
+    let frame = FrameAllocOptions::new(1).uninit(true).alloc_single()?;
+    fs.read_block(bid, &frame)?;
+    let header = frame.read_val::<RawXattrHeader>(0)?;
+    if header.magic != XATTR_MAGIC || header.blocks != 1 {
+        return_errno_with_message!(Errno::EIO, "invalid xattr block");
+    }
+    Ok((frame, header))
+}
+
+fn encode_xattrs(xattrs: &Xattrs) -> Result<Frame> {
+    let mut entries = Vec::new();
+    for (name, value) in xattrs.iter() {
+        let (index, suffix) = split_name(name)?;
+        let value = if is_acl_index(index) {
+            acl_to_disk(value)?
+        } else {
+            value.clone()
+        };
+        entries.push((index, suffix.as_bytes(), value));
+    }
+    // The entries are sorted in the same order as Linux.
+    entries.sort_by(|(lhs_index, lhs_suffix, _), (rhs_index, rhs_suffix, _)| {
+        (lhs_index, lhs_suffix.len(), lhs_suffix).cmp(&(rhs_index, rhs_suffix.len(), rhs_suffix))
+    });
+
+    let frame = FrameAllocOptions::new(1).alloc_single()?;
+    let mut entry_offset = HEADER_LEN;
+    let mut value_offset = BLOCK_SIZE;
+    let mut block_hash = 0u32;
+    for (index, suffix, value) in entries {
+        let entry_end = entry_offset + ENTRY_HEADER_LEN + suffix.len().align_up(XATTR_ALIGN);
+        let value_len = value.len().align_up(XATTR_ALIGN);
+        // The entries are terminated by a zeroed `u32`.
+        if entry_end + XATTR_ALIGN + value_len > value_offset {
+            return_errno_with_message!(Errno::ENOSPC, "the xattrs do not fit in a block");
+        }
+        value_offset -= value_len;
+
+        let hash = entry_hash(suffix, &value);
+        let entry = RawXattrEntry {
+            name_len: suffix.len() as u8,
+            name_index: index,
+            value_offs: value_offset as u16,
+            value_block: 0,
+            value_size: value.len() as u32,
+            hash,
+        };
+        frame.write_val(entry_offset, &entry)?;
+        frame.write_bytes(entry_offset + ENTRY_HEADER_LEN, suffix)?;
+        frame.write_bytes(value_offset, &value)?;
+        block_hash = (block_hash << 16) ^ (block_hash >> 16) ^ hash;
+        entry_offset = entry_end;
+    }
+
+    let header = RawXattrHeader {
+        magic: XATTR_MAGIC,
+        refcount: 1,
+        blocks: 1,
+        hash: block_hash,
+        ..Default::default()
+    };
+    frame.write_val(0, &header)?;
+    Ok(frame)
+}
+
+/// Splits the name into the name index and the suffix after the prefix.
+fn split_name(name: &str) -> Result<(u8, &str)> {
+    NAME_INDEXES
+        .iter()
+        .find_map(|&(index, prefix)| {
+            let suffix = name.strip_prefix(prefix)?;
+            // The names of ACLs are fully represented by the name indexes.
+            (suffix.is_empty() == is_acl_index(index)).then_some((index, suffix))
+        })
+        .ok_or_else(|| Error::with_message(Errno::EOPNOTSUPP, "the xattr is not supported"))
+}
+
+fn is_acl_index(index: u8) -> bool {
+    index == ACL_ACCESS_INDEX || index == ACL_DEFAULT_INDEX
+}
+
+/// Computes the hash of an entry in the same way as Linux.
+fn entry_hash(name: &[u8], value: &[u8]) -> u32 {
+    let mut hash = 0u32;
+    for &byte in name {
+        // The characters are signed in Linux.
+        hash = (hash << 5) ^ (hash >> 27) ^ (byte as i8 as u32);
+    }
+    for word in value.chunks(XATTR_ALIGN) {
+        let mut bytes = [0u8; XATTR_ALIGN];
+        bytes[..word.len()].copy_from_slice(word);
+        hash = (hash << 16) ^ (hash >> 16) ^ u32::from_le_bytes(bytes);
+    }
+    hash
+}
+
+/// The version of the ACLs on the device.
+const ACL_DISK_VERSION: u32 = 1;
+/// The version of the ACLs in the extended attributes of the VFS.
+const ACL_XATTR_VERSION: u32 = 2;
+const ACL_HEADER_LEN: usize = 4;
+const ACL_XATTR_ENTRY_LEN: usize = 8;
+/// The entries of the owner, the group, the mask and the others have no IDs on the device.
+const ACL_SHORT_ENTRY_LEN: usize = 4;
+const ACL_USER_TAG: u16 = 0x02;
+const ACL_GROUP_TAG: u16 = 0x08;
+
+fn acl_to_disk(value: &[u8]) -> Result<Vec<u8>> {
+    if value.len() < ACL_HEADER_LEN || (value.len() - ACL_HEADER_LEN) % ACL_XATTR_ENTRY_LEN != 0 {
+        return_errno_with_message!(Errno::EINVAL, "invalid ACL size");
+    }
+    let mut disk_value = Vec::with_capacity(value.len());
+    disk_value.extend_from_slice(&ACL_DISK_VERSION.to_le_bytes());
+    for entry in value[ACL_HEADER_LEN..].chunks_exact(ACL_XATTR_ENTRY_LEN) {
+        let tag = u16::from_le_bytes([entry[0], entry[1]]);
+        let len = if tag == ACL_USER_TAG || tag == ACL_GROUP_TAG {
+            ACL_XATTR_ENTRY_LEN
+        } else {
+            ACL_SHORT_ENTRY_LEN
+        };
+        disk_value.extend_from_slice(&entry[..len]);
+    }
+    Ok(disk_value)
+}
+
+fn acl_from_disk(disk_value: &[u8]) -> Result<Vec<u8>> {
+    if disk_value.len() < ACL_HEADER_LEN
+        || u32::from_le_bytes(disk_value[..ACL_HEADER_LEN].try_into().unwrap()) != ACL_DISK_VERSION
+    {
+        return_errno_with_message!(Errno::EIO, "invalid ACL on the device");
+    }
+    let mut value = Vec::with_capacity(disk_value.len() * 2);
+    value.extend_from_slice(&ACL_XATTR_VERSION.to_le_bytes());
+    let mut entries = &disk_value[ACL_HEADER_LEN..];
+    while !entries.is_empty() {
+        if entries.len() < ACL_SHORT_ENTRY_LEN {
+            return_errno_with_message!(Errno::EIO, "invalid ACL on the device");
+        }
+        let tag = u16::from_le_bytes([entries[0], entries[1]]);
+        let len = if tag == ACL_USER_TAG || tag == ACL_GROUP_TAG {
+            ACL_XATTR_ENTRY_LEN
+        } else {
+            ACL_SHORT_ENTRY_LEN
+        };
+        if entries.len() < len {
+            return_errno_with_message!(Errno::EIO, "invalid ACL on the device");
+        }
+        value.extend_from_slice(&entries[..len]);
+        // The entries without IDs have the undefined ID.
+        if len == ACL_SHORT_ENTRY_LEN {
+            value.extend_from_slice(&u32::MAX.to_le_bytes());
+        }
+        entries = &entries[len..];
+    }
+    Ok(value)
+}
+
+const_assert!(core::mem::size_of::<RawXattrHeader>() == 32);
+
+/// The header of the block of extended attributes on device.
+#[repr(C)]
+#[derive(Clone, Copy, Default, Debug, Pod)]
+struct RawXattrHeader {
+    /// Magic number for identification.
+    magic: u32,
+    /// Number of inodes referring to the block.
+    refcount: u32,
+    /// Number of blocks used, which is always 1.
+    blocks: u32,
+    /// Hash of all the entries.
+    hash: u32,
+    reserved: [u32; 4],
+}
+
+const_assert!(core::mem::size_of::<RawXattrEntry>() == 16);
+
+/// The entry of an extended attribute on device, which is followed by the name.
+#[repr(C)]
+#[derive(Clone, Copy, Default, Debug, Pod)]
+struct RawXattrEntry {
+    /// Length of the name without the prefix.
+    name_len: u8,
+    /// Index of the prefix of the name.
+    name_index: u8,
+    /// Offset of the value in the block.
+    value_offs: u16,
+    /// Block of the value, which is always 0 as the values are in the same block.
+    value_block: u32,
+    /// Size of the value.
+    value_size: u32,
+    /// Hash of the name and the value.
+    hash: u32,
+}
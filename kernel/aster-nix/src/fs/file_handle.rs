@@ -4,15 +4,25 @@
 
 //! Opend File Handle
 
+use core::ops::Range;
+
+use aster_frame::mm::CachePolicy;
+
+#[cfg(feature = "net")]
+use crate::net::socket::Socket;
 use crate::{
     events::{IoEvents, Observer},
     fs::{
         device::Device,
         utils::{AccessMode, InodeMode, IoctlCmd, Metadata, SeekFrom, StatusFlags},
     },
-    net::socket::Socket,
     prelude::*,
     process::{signal::Poller, Gid, Uid},
+    vm::{
+        perms::VmPerms,
+        vmar::SharedMem,
+        vmo::{Vmo, VmoChildOptions},
+    },
 };
 
 /// The basic operations defined on a file
@@ -127,6 +137,7 @@ pub trait FileLike: Send + Sync + Any {
         None
     }
 
+    #[cfg(feature = "net")]
     fn as_socket(self: Arc<Self>) -> Option<Arc<dyn Socket>> {
         None
     }
@@ -134,6 +145,21 @@ pub trait FileLike: Send + Sync + Any {
     fn as_device(&self) -> Option<Arc<dyn Device>> {
         None
     }
+
+    /// Provides the memory to map `len` bytes of the file at `offset` with `mmap`.
+    ///
+    /// The file decides what backs the mapping, e.g., the memory accessed by a device or
+    /// the memory shared with the kernel. `None` means that the mapping is backed by the
+    /// page cache of the inode, as regular files do.
+    fn mmap(
+        &self,
+        offset: usize,
+        len: usize,
+        is_shared: bool,
+        perms: VmPerms,
+    ) -> Result<Option<MmapRegion>> {
+        return_errno_with_message!(Errno::ENODEV, "mmap is not supported");
+    }
 }
 
 impl dyn FileLike {
@@ -141,3 +167,60 @@ impl dyn FileLike {
         (self as &dyn Any).downcast_ref::<T>()
     }
 }
+
+/// The memory provided by a file to back a mapping created by `mmap`.
+pub struct MmapRegion {
+    target: MmapTarget,
+    cache_policy: CachePolicy,
+}
+
+/// The object that backs a mapping.
+pub enum MmapTarget {
+    /// A VMO that is mapped from its start.
+    Vmo(Vmo),
+    /// A shared memory object that is mapped from the offset of its VMO.
+    SharedMem(Arc<SharedMem>, usize),
+}
+
+impl MmapRegion {
+    /// Creates a region backed by `vmo`, which is mapped from its start.
+    pub fn from_vmo(vmo: Vmo) -> Self {
+        Self {
+            target: MmapTarget::Vmo(vmo),
+            cache_policy: CachePolicy::Writeback,
+        }
+    }
+
+    /// Creates a region backed by a copy-on-write child of `vmo` within `range`, which
+    /// suits the private mappings.
+    pub fn from_vmo_cow(vmo: Vmo, range: Range<usize>) -> Result<Self> {
+        let child = VmoChildOptions::new_cow(vmo, range).alloc()?;
+        Ok(Self::from_vmo(child))
+    }
+
+    /// Creates a region backed by `shared_mem`, which is mapped from `vmo_offset` of its
+    /// VMO.
+    ///
+    /// All the mappings of the same object are coherent.
+    pub fn from_shared_mem(shared_mem: Arc<SharedMem>, vmo_offset: usize) -> Self {
+        Self {
+            target: MmapTarget::SharedMem(shared_mem, vmo_offset),
+            cache_policy: CachePolicy::Writeback,
+        }
+    }
+
+    /// Sets the cache policy of the pages in the mapping.
+    ///
+    /// The default value is `CachePolicy::Writeback`, which suits the normal memory.
+    /// The memory accessed by a device may require another policy, e.g., a framebuffer is
+    /// usually mapped with `CachePolicy::WriteCombining`.
+    pub fn cache_policy(mut self, cache_policy: CachePolicy) -> Self {
+        self.cache_policy = cache_policy;
+        self
+    }
+
+    /// Returns the object that backs the mapping and the cache policy of the pages.
+    pub fn into_parts(self) -> (MmapTarget, CachePolicy) {
+        (self.target, self.cache_policy)
+    }
+}
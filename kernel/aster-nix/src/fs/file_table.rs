@@ -9,11 +9,12 @@ use aster_util::slot_vec::SlotVec;
 use super::{
     file_handle::FileLike,
     fs_resolver::{FsPath, FsResolver, AT_FDCWD},
-    utils::{AccessMode, InodeMode},
+    utils::{AccessMode, CreationFlags, InodeMode},
 };
+#[cfg(feature = "net")]
+use crate::net::socket::Socket;
 use crate::{
     events::{Events, Observer, Subject},
-    net::socket::Socket,
     prelude::*,
 };
 
@@ -136,6 +137,23 @@ impl FileTable {
         closed_files
     }
 
+    /// Creates a copy of the file table for a child process created by `fork()`.
+    ///
+    /// Unlike [`Clone::clone`], the file descriptors with the close-on-fork flag
+    /// (i.e., [`FdFlags::CLOFORK`]) are not inherited by the child.
+    pub fn clone_for_fork(&self) -> Self {
+        let mut table = SlotVec::new();
+        for (idx, entry) in self.table.idxes_and_items() {
+            if !entry.flags().contains(FdFlags::CLOFORK) {
+                table.put_at(idx, entry.clone());
+            }
+        }
+        Self {
+            table,
+            subject: Subject::new(),
+        }
+    }
+
     pub fn close_files_on_exec(&mut self) -> Vec<Arc<dyn FileLike>> {
         let mut closed_files = Vec::new();
         let closed_fds: Vec<FileDesc> = self
@@ -166,6 +184,7 @@ impl FileTable {
             .ok_or(Error::with_message(Errno::EBADF, "fd not exits"))
     }
 
+    #[cfg(feature = "net")]
     pub fn get_socket(&self, sockfd: FileDesc) -> Result<Arc<dyn Socket>> {
         let file_like = self.get_file(sockfd)?.clone();
         file_like
@@ -276,5 +295,20 @@ bitflags! {
     pub struct FdFlags: u8 {
         /// Close on exec
         const CLOEXEC = 1;
+        /// Close on fork
+        const CLOFORK = 2;
+    }
+}
+
+impl From<CreationFlags> for FdFlags {
+    fn from(flags: CreationFlags) -> Self {
+        let mut fd_flags = FdFlags::empty();
+        if flags.contains(CreationFlags::O_CLOEXEC) {
+            fd_flags |= FdFlags::CLOEXEC;
+        }
+        if flags.contains(CreationFlags::O_CLOFORK) {
+            fd_flags |= FdFlags::CLOFORK;
+        }
+        fd_flags
     }
 }
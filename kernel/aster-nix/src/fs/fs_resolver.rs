@@ -7,7 +7,10 @@ use super::{
     inode_handle::InodeHandle,
     path::Dentry,
     rootfs::root_mount,
-    utils::{AccessMode, CreationFlags, InodeMode, InodeType, StatusFlags, PATH_MAX, SYMLINKS_MAX},
+    utils::{
+        AccessMode, CreationFlags, InodeMode, InodeType, Permission, StatusFlags, PATH_MAX,
+        SYMLINKS_MAX,
+    },
 };
 use crate::prelude::*;
 
@@ -67,6 +70,10 @@ impl FsResolver {
         let access_mode = AccessMode::from_u32(flags)?;
         let inode_mode = InodeMode::from_bits_truncate(mode);
 
+        if creation_flags.contains(CreationFlags::_O_TMPFILE) {
+            return self.open_tmpfile(path, creation_flags, access_mode, status_flags, inode_mode);
+        }
+
         let follow_tail_link = !(creation_flags.contains(CreationFlags::O_NOFOLLOW)
             || creation_flags.contains(CreationFlags::O_CREAT)
                 && creation_flags.contains(CreationFlags::O_EXCL));
@@ -106,9 +113,9 @@ impl FsResolver {
                 if file_name.ends_with('/') {
                     return_errno_with_message!(Errno::EISDIR, "path refers to a directory");
                 }
-                if !dir_dentry.mode()?.is_writable() {
-                    return_errno_with_message!(Errno::EACCES, "file cannot be created");
-                }
+                dir_dentry
+                    .inode()
+                    .check_permission(Permission::MAY_WRITE | Permission::MAY_EXEC)?;
                 dir_dentry.new_fs_child(&file_name, InodeType::File, inode_mode)?
             }
             Err(e) => return Err(e),
@@ -123,6 +130,32 @@ impl FsResolver {
         Ok(inode_handle)
     }
 
+    /// Open an unnamed regular file in the directory of the path for `O_TMPFILE`.
+    fn open_tmpfile(
+        &self,
+        path: &FsPath,
+        creation_flags: CreationFlags,
+        access_mode: AccessMode,
+        status_flags: StatusFlags,
+        inode_mode: InodeMode,
+    ) -> Result<InodeHandle> {
+        if !creation_flags.contains(CreationFlags::O_DIRECTORY) {
+            return_errno_with_message!(Errno::EINVAL, "O_TMPFILE requires O_DIRECTORY");
+        }
+        if !access_mode.is_writable() {
+            return_errno_with_message!(Errno::EINVAL, "O_TMPFILE requires write access");
+        }
+        let dir_dentry = self.lookup_inner(path, true)?;
+        if dir_dentry.type_() != InodeType::Dir {
+            return_errno_with_message!(Errno::ENOTDIR, "the path is not a directory");
+        }
+        dir_dentry
+            .inode()
+            .check_permission(Permission::MAY_WRITE | Permission::MAY_EXEC)?;
+        let dentry = dir_dentry.new_tmpfile(inode_mode)?;
+        InodeHandle::new(dentry, access_mode, status_flags)
+    }
+
     /// Lookup dentry according to FsPath, always follow symlinks
     pub fn lookup(&self, path: &FsPath) -> Result<Arc<Dentry>> {
         self.lookup_inner(path, true)
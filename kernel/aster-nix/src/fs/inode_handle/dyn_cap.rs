@@ -13,15 +13,18 @@ impl InodeHandle<Rights> {
         status_flags: StatusFlags,
     ) -> Result<Self> {
         let inode = dentry.inode();
-        if access_mode.is_readable() && !inode.mode()?.is_readable() {
-            return_errno_with_message!(Errno::EACCES, "File is not readable");
+        let mut perm = Permission::empty();
+        if access_mode.is_readable() {
+            perm |= Permission::MAY_READ;
         }
-        if access_mode.is_writable() && !inode.mode()?.is_writable() {
-            return_errno_with_message!(Errno::EACCES, "File is not writable");
+        if access_mode.is_writable() {
+            perm |= Permission::MAY_WRITE;
         }
+        inode.check_permission(perm)?;
         if access_mode.is_writable() && inode.type_() == InodeType::Dir {
             return_errno_with_message!(Errno::EISDIR, "Directory cannot open to write");
         }
+        inode.open(access_mode)?;
 
         let file_io = if let Some(device) = inode.as_device() {
             device.open()?
@@ -35,7 +38,10 @@ impl InodeHandle<Rights> {
             offset: Mutex::new(0),
             access_mode,
             status_flags: AtomicU32::new(status_flags.bits()),
+            readahead: Mutex::new(ReadaheadState::new()),
+            no_reuse: AtomicBool::new(false),
         });
+        inner.dentry.notify(InotifyEvents::IN_OPEN);
         Ok(Self(inner, Rights::from(access_mode)))
     }
 
@@ -125,6 +131,9 @@ impl FileLike for InodeHandle<Rights> {
     }
 
     fn clean_for_close(&self) -> Result<()> {
+        // The POSIX locks of the process on the file are released once it closes any file
+        // descriptor of the file.
+        file_lock::release_posix_locks(self.dentry().inode(), current!().pid());
         // Close does not guarantee that the data has been successfully saved to disk.
         Ok(())
     }
@@ -132,4 +141,20 @@ impl FileLike for InodeHandle<Rights> {
     fn as_device(&self) -> Option<Arc<dyn Device>> {
         self.dentry().inode().as_device()
     }
+
+    fn mmap(
+        &self,
+        offset: usize,
+        len: usize,
+        is_shared: bool,
+        perms: VmPerms,
+    ) -> Result<Option<MmapRegion>> {
+        if !self.1.contains(Rights::READ) {
+            return_errno_with_message!(Errno::EACCES, "file is not readable");
+        }
+        if is_shared && perms.contains(VmPerms::WRITE) && !self.1.contains(Rights::WRITE) {
+            return_errno_with_message!(Errno::EACCES, "file is not writable");
+        }
+        self.0.mmap(offset, len, is_shared, perms)
+    }
 }
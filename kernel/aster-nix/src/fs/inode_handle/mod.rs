@@ -7,7 +7,10 @@
 mod dyn_cap;
 mod static_cap;
 
-use core::sync::atomic::{AtomicU32, Ordering};
+use core::{
+    ops::Range,
+    sync::atomic::{AtomicBool, AtomicU32, Ordering},
+};
 
 use aster_rights::Rights;
 use inherit_methods_macro::inherit_methods;
@@ -16,15 +19,18 @@ use crate::{
     events::IoEvents,
     fs::{
         device::Device,
-        file_handle::FileLike,
+        file_handle::{FileLike, MmapRegion},
+        inotify::InotifyEvents,
         path::Dentry,
         utils::{
-            AccessMode, DirentVisitor, InodeMode, InodeType, IoctlCmd, Metadata, SeekFrom,
-            StatusFlags,
+            file_lock, AccessMode, AccessPattern, DirentVisitor, FallocMode, FileAdvice,
+            FileLockType, InodeMode, InodeType, IoctlCmd, Metadata, Permission, ReadaheadState,
+            SeekFrom, StatusFlags,
         },
     },
     prelude::*,
     process::{signal::Poller, Gid, Uid},
+    vm::perms::VmPerms,
 };
 
 #[derive(Debug)]
@@ -39,11 +45,16 @@ struct InodeHandle_ {
     offset: Mutex<usize>,
     access_mode: AccessMode,
     status_flags: AtomicU32,
+    readahead: Mutex<ReadaheadState>,
+    /// Whether the data are advised to be accessed only once (`POSIX_FADV_NOREUSE`).
+    no_reuse: AtomicBool,
 }
 
 impl InodeHandle_ {
     pub fn read(&self, buf: &mut [u8]) -> Result<usize> {
-        if let Some(ref file_io) = self.file_io {
+        if let Some(ref file_io) = self.file_io
+            && !file_io.is_seekable()
+        {
             return file_io.read(buf);
         }
 
@@ -56,7 +67,9 @@ impl InodeHandle_ {
     }
 
     pub fn write(&self, buf: &[u8]) -> Result<usize> {
-        if let Some(ref file_io) = self.file_io {
+        if let Some(ref file_io) = self.file_io
+            && !file_io.is_seekable()
+        {
             return file_io.write(buf);
         }
 
@@ -74,19 +87,33 @@ impl InodeHandle_ {
 
     pub fn read_at(&self, offset: usize, buf: &mut [u8]) -> Result<usize> {
         if let Some(ref file_io) = self.file_io {
-            todo!("support read_at for FileIo");
+            return file_io.read_at(offset, buf);
         }
 
+        let inode = self.dentry.inode();
         if self.status_flags().contains(StatusFlags::O_DIRECT) {
-            self.dentry.inode().read_direct_at(offset, buf)
-        } else {
-            self.dentry.inode().read_at(offset, buf)
+            return inode.read_direct_at(offset, buf);
+        }
+
+        let readahead_range = self.readahead.lock().on_read(offset, buf.len());
+        if let Some(range) = readahead_range {
+            // Read-ahead is only an optimization, so its errors are left to the read below.
+            let _ = inode.readahead(range);
+        }
+
+        let len = inode.read_at(offset, buf)?;
+        if self.no_reuse.load(Ordering::Relaxed) {
+            inode.demote_cache(offset..offset + len);
+        }
+        if len > 0 {
+            self.dentry.notify(InotifyEvents::IN_ACCESS);
         }
+        Ok(len)
     }
 
     pub fn write_at(&self, mut offset: usize, buf: &[u8]) -> Result<usize> {
         if let Some(ref file_io) = self.file_io {
-            todo!("support write_at for FileIo");
+            return file_io.write_at(offset, buf);
         }
 
         if self.status_flags().contains(StatusFlags::O_APPEND) {
@@ -94,11 +121,15 @@ impl InodeHandle_ {
             offset = self.dentry.size();
         }
 
-        if self.status_flags().contains(StatusFlags::O_DIRECT) {
-            self.dentry.inode().write_direct_at(offset, buf)
+        let len = if self.status_flags().contains(StatusFlags::O_DIRECT) {
+            self.dentry.inode().write_direct_at(offset, buf)?
         } else {
-            self.dentry.inode().write_at(offset, buf)
+            self.dentry.inode().write_at(offset, buf)?
+        };
+        if len > 0 {
+            self.dentry.notify(InotifyEvents::IN_MODIFY);
         }
+        Ok(len)
     }
 
     pub fn read_to_end(&self, buf: &mut Vec<u8>) -> Result<usize> {
@@ -133,6 +164,8 @@ impl InodeHandle_ {
             SeekFrom::Current(off /* as isize */) => (*offset as isize)
                 .checked_add(off)
                 .ok_or_else(|| Error::with_message(Errno::EOVERFLOW, "file offset overflow"))?,
+            SeekFrom::Data(off) => self.dentry.inode().seek_data(off)? as isize,
+            SeekFrom::Hole(off) => self.dentry.inode().seek_hole(off)? as isize,
         };
         if new_offset < 0 {
             return_errno_with_message!(Errno::EINVAL, "file offset must not be negative");
@@ -152,7 +185,24 @@ impl InodeHandle_ {
         if self.status_flags().contains(StatusFlags::O_APPEND) {
             return_errno_with_message!(Errno::EPERM, "can not resize append-only file");
         }
-        self.dentry.resize(new_size)
+        self.dentry.resize(new_size)?;
+        self.dentry.notify(InotifyEvents::IN_MODIFY);
+        Ok(())
+    }
+
+    pub fn fallocate(&self, mode: FallocMode, offset: usize, len: usize) -> Result<()> {
+        if !self.access_mode.is_writable() {
+            return_errno_with_message!(Errno::EBADF, "the file is not opened for writing");
+        }
+        let inode = self.dentry.inode();
+        match inode.type_() {
+            InodeType::File if self.file_io.is_none() => (),
+            InodeType::Dir => return_errno_with_message!(Errno::EISDIR, "the file is a directory"),
+            _ => return_errno_with_message!(Errno::ENODEV, "the file is not a regular file"),
+        }
+        inode.fallocate(mode, offset, len)?;
+        self.dentry.notify(InotifyEvents::IN_MODIFY);
+        Ok(())
     }
 
     pub fn access_mode(&self) -> AccessMode {
@@ -169,6 +219,34 @@ impl InodeHandle_ {
             .store(new_status_flags.bits(), Ordering::Relaxed);
     }
 
+    pub fn fadvise(&self, range: Range<usize>, advice: FileAdvice) -> Result<()> {
+        if self.file_io.is_some() {
+            return Ok(());
+        }
+
+        let inode = self.dentry.inode();
+        let range = range.start..range.end.min(inode.size());
+        match advice {
+            FileAdvice::Normal => self.readahead.lock().set_pattern(AccessPattern::Normal),
+            FileAdvice::Random => self.readahead.lock().set_pattern(AccessPattern::Random),
+            FileAdvice::Sequential => self.readahead.lock().set_pattern(AccessPattern::Sequential),
+            FileAdvice::NoReuse => self.no_reuse.store(true, Ordering::Relaxed),
+            // Like Linux, the errors are not reported, since the advice is only a hint.
+            FileAdvice::WillNeed => {
+                let _ = inode.readahead(range);
+            }
+            FileAdvice::DontNeed => {
+                let _ = inode.drop_cache(range);
+            }
+        }
+        Ok(())
+    }
+
+    /// Returns the owner of the `flock` lock of the open file.
+    fn flock_owner(&self) -> usize {
+        self as *const Self as usize
+    }
+
     pub fn readdir(&self, visitor: &mut dyn DirentVisitor) -> Result<usize> {
         let mut offset = self.offset.lock();
         let read_cnt = self.dentry.inode().readdir_at(*offset, visitor)?;
@@ -191,6 +269,25 @@ impl InodeHandle_ {
 
         self.dentry.inode().ioctl(cmd, arg)
     }
+
+    fn mmap(
+        &self,
+        offset: usize,
+        len: usize,
+        is_shared: bool,
+        perms: VmPerms,
+    ) -> Result<Option<MmapRegion>> {
+        if let Some(ref file_io) = self.file_io {
+            return file_io.mmap(offset, len, is_shared, perms).map(Some);
+        }
+
+        if let Some(device) = self.dentry.inode().as_device() {
+            return device.mmap(offset, len, is_shared, perms).map(Some);
+        }
+
+        // The mapping is backed by the page cache of the inode.
+        Ok(None)
+    }
 }
 
 #[inherit_methods(from = "self.dentry")]
@@ -205,6 +302,19 @@ impl InodeHandle_ {
     pub fn set_group(&self, gid: Gid) -> Result<()>;
 }
 
+impl Drop for InodeHandle_ {
+    fn drop(&mut self) {
+        file_lock::release_flock(self.dentry.inode(), self.flock_owner());
+
+        let events = if self.access_mode.is_writable() {
+            InotifyEvents::IN_CLOSE_WRITE
+        } else {
+            InotifyEvents::IN_CLOSE_NOWRITE
+        };
+        self.dentry.notify(events);
+    }
+}
+
 impl Debug for InodeHandle_ {
     fn fmt(&self, f: &mut core::fmt::Formatter) -> core::fmt::Result {
         f.debug_struct("InodeHandle_")
@@ -221,6 +331,26 @@ impl<R> InodeHandle<R> {
     pub fn dentry(&self) -> &Arc<Dentry> {
         &self.0.dentry
     }
+
+    /// Gives advice about how the data within the range are going to be accessed.
+    pub fn fadvise(&self, range: Range<usize>, advice: FileAdvice) -> Result<()> {
+        self.0.fadvise(range, advice)
+    }
+
+    /// Allocates or deallocates the space of the range of the file.
+    pub fn fallocate(&self, mode: FallocMode, offset: usize, len: usize) -> Result<()> {
+        self.0.fallocate(mode, offset, len)
+    }
+
+    /// Sets or unlocks the `flock` lock of the open file.
+    pub fn set_flock(&self, type_: FileLockType, is_blocking: bool) -> Result<()> {
+        file_lock::set_flock(
+            self.0.dentry.inode(),
+            self.0.flock_owner(),
+            type_,
+            is_blocking,
+        )
+    }
 }
 
 pub trait FileIo: Send + Sync + 'static {
@@ -230,7 +360,36 @@ pub trait FileIo: Send + Sync + 'static {
 
     fn poll(&self, mask: IoEvents, poller: Option<&Poller>) -> IoEvents;
 
+    /// Returns whether the file is accessed at the offset of the file handle, e.g., a
+    /// framebuffer.
+    ///
+    /// If so, `read` and `write` of the file handle are done with `read_at` and `write_at`.
+    fn is_seekable(&self) -> bool {
+        false
+    }
+
+    fn read_at(&self, offset: usize, buf: &mut [u8]) -> Result<usize> {
+        return_errno_with_message!(Errno::ESPIPE, "read_at is not supported");
+    }
+
+    fn write_at(&self, offset: usize, buf: &[u8]) -> Result<usize> {
+        return_errno_with_message!(Errno::ESPIPE, "write_at is not supported");
+    }
+
     fn ioctl(&self, cmd: IoctlCmd, arg: usize) -> Result<i32> {
         return_errno_with_message!(Errno::EINVAL, "ioctl is not supported");
     }
+
+    /// Provides the memory to map `len` bytes at `offset` with `mmap`.
+    ///
+    /// See [`FileLike::mmap`] for more details.
+    fn mmap(
+        &self,
+        offset: usize,
+        len: usize,
+        is_shared: bool,
+        perms: VmPerms,
+    ) -> Result<MmapRegion> {
+        return_errno_with_message!(Errno::ENODEV, "mmap is not supported");
+    }
 }
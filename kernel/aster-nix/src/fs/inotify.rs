@@ -0,0 +1,521 @@
+// SPDX-License-Identifier: MPL-2.0
+
+//! The inotify files, which monitor the file system events on the watched inodes.
+//!
+//! A watch is added to an inode with `inotify_add_watch`, and the events generated by
+//! the VFS on the inode are queued to the inotify file as `struct inotify_event`s, which
+//! can be read from the file. The events on the children of a watched directory are
+//! also reported with the names of the children. A watch pins its inode, so the inode
+//! can be found by the same pointer as long as it is watched. For more detailed
+//! information, refer to the man 7 inotify documentation.
+
+use core::sync::atomic::{AtomicBool, AtomicI32, AtomicU32, AtomicUsize, Ordering};
+
+use align_ext::AlignExt;
+use keyable_arc::KeyableWeak;
+
+use super::{
+    file_handle::FileLike,
+    utils::{CreationFlags, Inode, InodeMode, InodeType, IoctlCmd, Metadata, StatusFlags},
+};
+use crate::{
+    events::{IoEvents, Observer},
+    prelude::*,
+    process::{
+        signal::{Pollee, Poller},
+        Gid, Uid,
+    },
+    time::clocks::RealTimeClock,
+    util::write_val_to_user,
+};
+
+bitflags! {
+    /// The events and the flags of inotify.
+    pub struct InotifyEvents: u32 {
+        /// The file is accessed.
+        const IN_ACCESS = 0x0000_0001;
+        /// The file is modified.
+        const IN_MODIFY = 0x0000_0002;
+        /// The metadata of the file is changed.
+        const IN_ATTRIB = 0x0000_0004;
+        /// The file opened for writing is closed.
+        const IN_CLOSE_WRITE = 0x0000_0008;
+        /// The file not opened for writing is closed.
+        const IN_CLOSE_NOWRITE = 0x0000_0010;
+        /// The file is opened.
+        const IN_OPEN = 0x0000_0020;
+        /// A file is moved out of the watched directory.
+        const IN_MOVED_FROM = 0x0000_0040;
+        /// A file is moved into the watched directory.
+        const IN_MOVED_TO = 0x0000_0080;
+        /// A file is created in the watched directory.
+        const IN_CREATE = 0x0000_0100;
+        /// A file is deleted from the watched directory.
+        const IN_DELETE = 0x0000_0200;
+        /// The watched file is deleted.
+        const IN_DELETE_SELF = 0x0000_0400;
+        /// The watched file is moved.
+        const IN_MOVE_SELF = 0x0000_0800;
+        /// The file system of the watched file is unmounted.
+        const IN_UNMOUNT = 0x0000_2000;
+        /// The event queue overflowed.
+        const IN_Q_OVERFLOW = 0x0000_4000;
+        /// The watch is removed.
+        const IN_IGNORED = 0x0000_8000;
+        /// Only watches the path if it is a directory.
+        const IN_ONLYDIR = 0x0100_0000;
+        /// Does not follow the path if it is a symbolic link.
+        const IN_DONT_FOLLOW = 0x0200_0000;
+        /// Does not report the events on the children after they are unlinked.
+        const IN_EXCL_UNLINK = 0x0400_0000;
+        /// Fails if the path is already watched.
+        const IN_MASK_CREATE = 0x1000_0000;
+        /// Adds the events to the mask of the existing watch, instead of replacing it.
+        const IN_MASK_ADD = 0x2000_0000;
+        /// The subject of the event is a directory.
+        const IN_ISDIR = 0x4000_0000;
+        /// Removes the watch after the first event.
+        const IN_ONESHOT = 0x8000_0000;
+
+        const IN_CLOSE = Self::IN_CLOSE_WRITE.bits | Self::IN_CLOSE_NOWRITE.bits;
+        const IN_MOVE = Self::IN_MOVED_FROM.bits | Self::IN_MOVED_TO.bits;
+        const IN_ALL_EVENTS = 0x0000_0fff;
+    }
+}
+
+impl InotifyEvents {
+    /// The events that are always reported, regardless of the mask of a watch.
+    const ALWAYS_REPORTED: Self = Self::from_bits_truncate(
+        Self::IN_UNMOUNT.bits | Self::IN_Q_OVERFLOW.bits | Self::IN_IGNORED.bits,
+    );
+}
+
+bitflags! {
+    /// The flags of `inotify_init1`.
+    pub struct InotifyFlags: u32 {
+        const IN_CLOEXEC = CreationFlags::O_CLOEXEC.bits();
+        const IN_NONBLOCK = StatusFlags::O_NONBLOCK.bits();
+    }
+}
+
+/// The maximum number of the events queued in an inotify file, which is the default
+/// `max_queued_events` in Linux.
+const MAX_QUEUED_EVENTS: usize = 16384;
+/// The maximum number of the watches of an inotify file, which is the default
+/// `max_user_watches` in Linux.
+const MAX_WATCHES: usize = 8192;
+
+/// The watches on the inodes, keyed by the watched inodes.
+static WATCHES: RwLock<BTreeMap<KeyableWeak<dyn Inode>, Vec<Arc<Watch>>>> =
+    RwLock::new(BTreeMap::new());
+/// The number of the watches, which makes the VFS hooks cheap if there are no watches.
+static NR_WATCHES: AtomicUsize = AtomicUsize::new(0);
+/// The cookie to relate the `IN_MOVED_FROM` and `IN_MOVED_TO` events of a rename.
+static NEXT_COOKIE: AtomicU32 = AtomicU32::new(1);
+
+/// Returns whether any inode is watched.
+pub fn has_watches() -> bool {
+    NR_WATCHES.load(Ordering::Relaxed) > 0
+}
+
+/// Returns a new cookie for the events of a rename.
+pub fn new_cookie() -> u32 {
+    NEXT_COOKIE.fetch_add(1, Ordering::Relaxed)
+}
+
+/// Notifies the watches on the inode of the events.
+pub fn notify_inode(inode: &Arc<dyn Inode>, events: InotifyEvents) {
+    notify(inode, events, 0, None);
+}
+
+/// Notifies the watches on the directory of the events on its child with the name.
+pub fn notify_child(dir: &Arc<dyn Inode>, name: &str, events: InotifyEvents, cookie: u32) {
+    notify(dir, events, cookie, Some(name));
+}
+
+/// Notifies the watches on the inode that it loses a link, and the watches on the
+/// directory that the name of the inode is deleted, if the directory is given.
+///
+/// If the inode has no links any more, the watches on it are notified that it is
+/// deleted, and then removed. Unlike Linux, this does not wait for the open files of
+/// the inode to be closed.
+pub fn notify_unlinked(inode: &Arc<dyn Inode>, dir_and_name: Option<(&Arc<dyn Inode>, &str)>) {
+    if !has_watches() {
+        return;
+    }
+    let is_dir = inode.type_() == InodeType::Dir;
+    if !is_dir {
+        notify_inode(inode, InotifyEvents::IN_ATTRIB);
+    }
+    if let Some((dir, name)) = dir_and_name {
+        let events = if is_dir {
+            InotifyEvents::IN_DELETE | InotifyEvents::IN_ISDIR
+        } else {
+            InotifyEvents::IN_DELETE
+        };
+        notify_child(dir, name, events, 0);
+    }
+    if !is_dir && inode.metadata().nlinks > 0 {
+        return;
+    }
+    notify_inode(inode, InotifyEvents::IN_DELETE_SELF);
+
+    let Some(watches) = WATCHES.write().remove(&key_of(inode)) else {
+        return;
+    };
+    for watch in watches {
+        NR_WATCHES.fetch_sub(1, Ordering::Relaxed);
+        if let Some(file) = watch.file.upgrade() {
+            file.forget_watch(&watch);
+        }
+    }
+}
+
+fn notify(inode: &Arc<dyn Inode>, events: InotifyEvents, cookie: u32, name: Option<&str>) {
+    if !has_watches() {
+        return;
+    }
+    let watches = match WATCHES.read().get(&key_of(inode)) {
+        Some(watches) => watches.clone(),
+        None => return,
+    };
+    for watch in watches {
+        watch.deliver(events, cookie, name);
+    }
+}
+
+fn key_of(inode: &Arc<dyn Inode>) -> KeyableWeak<dyn Inode> {
+    KeyableWeak::from(Arc::downgrade(inode))
+}
+
+/// A watch of an inotify file on an inode.
+struct Watch {
+    wd: i32,
+    mask: AtomicU32,
+    /// The watched inode, which is pinned by the watch.
+    inode: Arc<dyn Inode>,
+    file: Weak<InotifyFile>,
+}
+
+impl Watch {
+    fn mask(&self) -> InotifyEvents {
+        InotifyEvents::from_bits_truncate(self.mask.load(Ordering::Relaxed))
+    }
+
+    fn deliver(&self, events: InotifyEvents, cookie: u32, name: Option<&str>) {
+        let Some(file) = self.file.upgrade() else {
+            return;
+        };
+        let mask = self.mask();
+        let matched = events & (mask | InotifyEvents::ALWAYS_REPORTED);
+        if matched.is_empty() {
+            return;
+        }
+        file.push_event(InotifyEvent {
+            wd: self.wd,
+            mask: matched | (events & InotifyEvents::IN_ISDIR),
+            cookie,
+            name: name.map(String::from),
+        });
+        if mask.contains(InotifyEvents::IN_ONESHOT) {
+            let _ = file.remove_watch(self.wd);
+        }
+    }
+}
+
+#[derive(Debug, PartialEq, Eq)]
+struct InotifyEvent {
+    wd: i32,
+    mask: InotifyEvents,
+    cookie: u32,
+    name: Option<String>,
+}
+
+/// The header of `struct inotify_event`, which is followed by the name.
+#[derive(Debug, Clone, Copy, Pod)]
+#[repr(C)]
+struct InotifyEventHeader {
+    wd: i32,
+    mask: u32,
+    cookie: u32,
+    len: u32,
+}
+
+impl InotifyEvent {
+    /// Returns the length of the name, which is null-terminated and padded to the
+    /// alignment of the header.
+    fn name_len(&self) -> usize {
+        self.name.as_ref().map_or(0, |name| {
+            (name.len() + 1).align_up(core::mem::size_of::<InotifyEventHeader>())
+        })
+    }
+
+    fn len(&self) -> usize {
+        core::mem::size_of::<InotifyEventHeader>() + self.name_len()
+    }
+
+    fn write_to(&self, buf: &mut [u8]) {
+        let header = InotifyEventHeader {
+            wd: self.wd,
+            mask: self.mask.bits(),
+            cookie: self.cookie,
+            len: self.name_len() as u32,
+        };
+        let (header_buf, name_buf) = buf[..self.len()].split_at_mut(header.as_bytes().len());
+        header_buf.copy_from_slice(header.as_bytes());
+        name_buf.fill(0);
+        if let Some(name) = &self.name {
+            name_buf[..name.len()].copy_from_slice(name.as_bytes());
+        }
+    }
+}
+
+pub struct InotifyFile {
+    watches: Mutex<BTreeMap<i32, Arc<Watch>>>,
+    next_wd: AtomicI32,
+    events: Mutex<VecDeque<InotifyEvent>>,
+    pollee: Pollee,
+    is_nonblocking: AtomicBool,
+    this: Weak<InotifyFile>,
+}
+
+impl InotifyFile {
+    pub fn new(is_nonblocking: bool) -> Arc<Self> {
+        Arc::new_cyclic(|weak_self| Self {
+            watches: Mutex::new(BTreeMap::new()),
+            next_wd: AtomicI32::new(1),
+            events: Mutex::new(VecDeque::new()),
+            pollee: Pollee::new(IoEvents::empty()),
+            is_nonblocking: AtomicBool::new(is_nonblocking),
+            this: weak_self.clone(),
+        })
+    }
+
+    /// Adds a watch on the inode, or modifies the mask of the existing watch on it.
+    ///
+    /// Returns the watch descriptor.
+    pub fn add_watch(&self, inode: Arc<dyn Inode>, mask: InotifyEvents) -> Result<i32> {
+        if (mask & InotifyEvents::IN_ALL_EVENTS).is_empty() {
+            return_errno_with_message!(Errno::EINVAL, "no events are specified");
+        }
+        if mask.contains(InotifyEvents::IN_MASK_ADD | InotifyEvents::IN_MASK_CREATE) {
+            return_errno_with_message!(Errno::EINVAL, "IN_MASK_ADD and IN_MASK_CREATE conflict");
+        }
+        if mask.contains(InotifyEvents::IN_ONLYDIR) && inode.type_() != InodeType::Dir {
+            return_errno_with_message!(Errno::ENOTDIR, "the inode is not a directory");
+        }
+
+        let mut watches = self.watches.lock();
+        let key = key_of(&inode);
+        if let Some(watch) = watches.values().find(|watch| key_of(&watch.inode) == key) {
+            if mask.contains(InotifyEvents::IN_MASK_CREATE) {
+                return_errno_with_message!(Errno::EEXIST, "the inode is already watched");
+            }
+            if mask.contains(InotifyEvents::IN_MASK_ADD) {
+                watch.mask.fetch_or(mask.bits(), Ordering::Relaxed);
+            } else {
+                watch.mask.store(mask.bits(), Ordering::Relaxed);
+            }
+            return Ok(watch.wd);
+        }
+
+        if watches.len() >= MAX_WATCHES {
+            return_errno_with_message!(Errno::ENOSPC, "too many watches");
+        }
+        let wd = self.next_wd.fetch_add(1, Ordering::Relaxed);
+        let watch = Arc::new(Watch {
+            wd,
+            mask: AtomicU32::new(mask.bits()),
+            inode,
+            file: self.this.clone(),
+        });
+        watches.insert(wd, watch.clone());
+        WATCHES.write().entry(key).or_default().push(watch);
+        NR_WATCHES.fetch_add(1, Ordering::Relaxed);
+        Ok(wd)
+    }
+
+    /// Removes the watch with the watch descriptor, and queues an `IN_IGNORED` event.
+    pub fn remove_watch(&self, wd: i32) -> Result<()> {
+        let Some(watch) = self.watches.lock().remove(&wd) else {
+            return_errno_with_message!(Errno::EINVAL, "the watch descriptor is invalid");
+        };
+        unregister_watch(&watch);
+        self.push_ignored(wd);
+        Ok(())
+    }
+
+    /// Forgets the watch that has been removed from the inode.
+    fn forget_watch(&self, watch: &Arc<Watch>) {
+        if self.watches.lock().remove(&watch.wd).is_some() {
+            self.push_ignored(watch.wd);
+        }
+    }
+
+    fn push_ignored(&self, wd: i32) {
+        self.push_event(InotifyEvent {
+            wd,
+            mask: InotifyEvents::IN_IGNORED,
+            cookie: 0,
+            name: None,
+        });
+    }
+
+    fn push_event(&self, event: InotifyEvent) {
+        let mut events = self.events.lock();
+        // Like Linux, an event is merged into the last one if they are the same.
+        if events.back() == Some(&event) {
+            return;
+        }
+        if events.len() >= MAX_QUEUED_EVENTS {
+            let overflow = InotifyEvent {
+                wd: -1,
+                mask: InotifyEvents::IN_Q_OVERFLOW,
+                cookie: 0,
+                name: None,
+            };
+            if events.back() != Some(&overflow) {
+                events.push_back(overflow);
+            }
+            return;
+        }
+        events.push_back(event);
+        self.pollee.add_events(IoEvents::IN);
+    }
+
+    fn is_nonblocking(&self) -> bool {
+        self.is_nonblocking.load(Ordering::Relaxed)
+    }
+
+    /// Reads the queued events that fit in the buffer.
+    fn try_read(&self, buf: &mut [u8]) -> Result<usize> {
+        let mut events = self.events.lock();
+        let Some(first) = events.front() else {
+            return_errno_with_message!(Errno::EAGAIN, "no events are queued");
+        };
+        if first.len() > buf.len() {
+            return_errno_with_message!(Errno::EINVAL, "the buffer is too small for an event");
+        }
+
+        let mut read_len = 0;
+        while let Some(event) = events.front() {
+            if read_len + event.len() > buf.len() {
+                break;
+            }
+            event.write_to(&mut buf[read_len..]);
+            read_len += event.len();
+            events.pop_front();
+        }
+        if events.is_empty() {
+            self.pollee.del_events(IoEvents::IN);
+        }
+        Ok(read_len)
+    }
+}
+
+fn unregister_watch(watch: &Arc<Watch>) {
+    let mut all_watches = WATCHES.write();
+    let key = key_of(&watch.inode);
+    let Some(watches) = all_watches.get_mut(&key) else {
+        return;
+    };
+    let len = watches.len();
+    watches.retain(|other| !Arc::ptr_eq(other, watch));
+    if watches.len() < len {
+        NR_WATCHES.fetch_sub(1, Ordering::Relaxed);
+    }
+    if watches.is_empty() {
+        all_watches.remove(&key);
+    }
+}
+
+impl Drop for InotifyFile {
+    fn drop(&mut self) {
+        for watch in self.watches.lock().values() {
+            unregister_watch(watch);
+        }
+    }
+}
+
+impl FileLike for InotifyFile {
+    fn read(&self, buf: &mut [u8]) -> Result<usize> {
+        loop {
+            match self.try_read(buf) {
+                Err(err) if err.error() == Errno::EAGAIN && !self.is_nonblocking() => {}
+                result => return result,
+            }
+
+            let poller = Poller::new();
+            if self.pollee.poll(IoEvents::IN, Some(&poller)).is_empty() {
+                poller.wait()?;
+            }
+        }
+    }
+
+    fn ioctl(&self, cmd: IoctlCmd, arg: usize) -> Result<i32> {
+        match cmd {
+            IoctlCmd::FIONREAD => {
+                let len: usize = self.events.lock().iter().map(InotifyEvent::len).sum();
+                write_val_to_user(arg, &(len as i32))?;
+                Ok(0)
+            }
+            _ => return_errno_with_message!(Errno::EINVAL, "unsupported ioctl"),
+        }
+    }
+
+    fn poll(&self, mask: IoEvents, poller: Option<&Poller>) -> IoEvents {
+        self.pollee.poll(mask, poller)
+    }
+
+    fn status_flags(&self) -> StatusFlags {
+        if self.is_nonblocking() {
+            StatusFlags::O_NONBLOCK
+        } else {
+            StatusFlags::empty()
+        }
+    }
+
+    fn set_status_flags(&self, new_flags: StatusFlags) -> Result<()> {
+        self.is_nonblocking.store(
+            new_flags.contains(StatusFlags::O_NONBLOCK),
+            Ordering::Relaxed,
+        );
+        Ok(())
+    }
+
+    fn register_observer(
+        &self,
+        observer: Weak<dyn Observer<IoEvents>>,
+        mask: IoEvents,
+    ) -> Result<()> {
+        self.pollee.register_observer(observer, mask);
+        Ok(())
+    }
+
+    fn unregister_observer(
+        &self,
+        observer: &Weak<dyn Observer<IoEvents>>,
+    ) -> Option<Weak<dyn Observer<IoEvents>>> {
+        self.pollee.unregister_observer(observer)
+    }
+
+    fn metadata(&self) -> Metadata {
+        let now = RealTimeClock::get().read_time();
+        Metadata {
+            dev: 0,
+            ino: 0,
+            size: 0,
+            blk_size: 0,
+            blocks: 0,
+            atime: now,
+            mtime: now,
+            ctime: now,
+            type_: InodeType::NamedPipe,
+            mode: InodeMode::from_bits_truncate(0o600),
+            nlinks: 1,
+            uid: Uid::new_root(),
+            gid: Gid::new_root(),
+            rdev: 0,
+        }
+    }
+}
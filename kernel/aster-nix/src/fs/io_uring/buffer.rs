@@ -0,0 +1,243 @@
+// SPDX-License-Identifier: MPL-2.0
+
+#![allow(dead_code)]
+
+//! The buffers provided by the user space.
+//!
+//! Instead of specifying a buffer in each SQE, the user space can provide groups of
+//! buffers in advance, and the operations with `IOSQE_BUFFER_SELECT` select a buffer from
+//! a group only when the data arrives. The ID of the selected buffer is reported in the
+//! CQE. A group is either a list of buffers added by `IORING_OP_PROVIDE_BUFFERS`, or a
+//! ring in the user space registered by `IORING_REGISTER_PBUF_RING`, which the user space
+//! refills by moving its tail without any system call.
+
+use core::{
+    mem::size_of,
+    sync::atomic::{fence, Ordering},
+};
+
+use aster_frame::mm::VmIo;
+use aster_rights::Full;
+
+use crate::{prelude::*, vm::vmar::Vmar};
+
+/// The maximum number of buffers in a group, which is the same as Linux.
+const MAX_BIDS_PER_GROUP: u32 = 1 << 16;
+
+/// The offset of the tail in a buffer ring, which overlaps the reserved field of the
+/// first entry.
+const BUF_RING_TAIL: usize = 14;
+
+/// An entry of a buffer ring, i.e., `struct io_uring_buf` in Linux.
+#[repr(C)]
+#[derive(Debug, Clone, Copy, Pod)]
+struct BufRingEntry {
+    addr: u64,
+    len: u32,
+    bid: u16,
+    resv: u16,
+}
+
+/// The argument of `IORING_REGISTER_PBUF_RING`, i.e., `struct io_uring_buf_reg` in Linux.
+#[repr(C)]
+#[derive(Debug, Clone, Copy, Pod)]
+pub struct BufRingReg {
+    pub ring_addr: u64,
+    pub ring_entries: u32,
+    pub bgid: u16,
+    pub flags: u16,
+    pub resv: [u64; 3],
+}
+
+/// A buffer selected from a group.
+#[derive(Debug, Clone, Copy)]
+pub(super) struct ProvidedBuffer {
+    pub(super) addr: Vaddr,
+    pub(super) len: usize,
+    pub(super) id: u16,
+}
+
+/// The buffer groups of an io_uring instance.
+pub(super) struct BufferGroups {
+    groups: Mutex<BTreeMap<u16, BufferGroup>>,
+}
+
+#[derive(Default)]
+struct BufferGroup {
+    /// The buffers added by `IORING_OP_PROVIDE_BUFFERS`, or the buffers taken from the
+    /// ring but not used, which are selected before the ring.
+    buffers: VecDeque<ProvidedBuffer>,
+    ring: Option<BufRing>,
+}
+
+/// A buffer ring in the user space.
+///
+/// The entries and the tail are written by the user space and must not be trusted. The
+/// head is only known to the kernel.
+struct BufRing {
+    addr: Vaddr,
+    nr_entries: u16,
+    head: u16,
+}
+
+impl BufferGroups {
+    pub(super) fn new() -> Self {
+        Self {
+            groups: Mutex::new(BTreeMap::new()),
+        }
+    }
+
+    /// Adds `nr_buffers` buffers of `len` bytes that start at `addr` to the group.
+    ///
+    /// The IDs of the buffers start at `first_id`.
+    pub(super) fn provide(
+        &self,
+        group_id: u16,
+        addr: Vaddr,
+        len: u32,
+        nr_buffers: u32,
+        first_id: u16,
+    ) -> Result<()> {
+        if nr_buffers == 0 || nr_buffers > MAX_BIDS_PER_GROUP {
+            return_errno_with_message!(Errno::E2BIG, "invalid number of buffers");
+        }
+        if first_id as u32 + nr_buffers > MAX_BIDS_PER_GROUP {
+            return_errno_with_message!(Errno::EINVAL, "the buffer IDs overflow");
+        }
+        if (len as usize)
+            .checked_mul(nr_buffers as usize)
+            .and_then(|size| addr.checked_add(size))
+            .is_none()
+        {
+            return_errno_with_message!(Errno::EOVERFLOW, "the buffers overflow");
+        }
+
+        let mut groups = self.groups.lock();
+        let group = groups.entry(group_id).or_default();
+        if group.ring.is_some() {
+            return_errno_with_message!(Errno::EINVAL, "the group is a buffer ring");
+        }
+        group
+            .buffers
+            .extend((0..nr_buffers).map(|i| ProvidedBuffer {
+                addr: addr + i as usize * len as usize,
+                len: len as usize,
+                id: first_id + i as u16,
+            }));
+        Ok(())
+    }
+
+    /// Removes at most `nr_buffers` buffers from the group.
+    ///
+    /// Returns the number of the removed buffers.
+    pub(super) fn remove(&self, group_id: u16, nr_buffers: u32) -> Result<usize> {
+        if nr_buffers == 0 || nr_buffers > MAX_BIDS_PER_GROUP {
+            return_errno_with_message!(Errno::EINVAL, "invalid number of buffers");
+        }
+
+        let mut groups = self.groups.lock();
+        let Some(group) = groups.get_mut(&group_id) else {
+            return_errno_with_message!(Errno::ENOENT, "the group does not exist");
+        };
+        if group.ring.is_some() {
+            return_errno_with_message!(Errno::EINVAL, "the group is a buffer ring");
+        }
+        let nr_removed = group.buffers.len().min(nr_buffers as usize);
+        group.buffers.drain(..nr_removed);
+        if group.buffers.is_empty() {
+            groups.remove(&group_id);
+        }
+        Ok(nr_removed)
+    }
+
+    /// Registers a buffer ring as a group.
+    pub(super) fn register_ring(&self, reg: &BufRingReg) -> Result<()> {
+        if reg.flags != 0 {
+            return_errno_with_message!(Errno::EINVAL, "the ring flags are not supported");
+        }
+        if reg.resv.iter().any(|resv| *resv != 0) {
+            return_errno_with_message!(Errno::EINVAL, "the reserved fields are not zero");
+        }
+        if !reg.ring_entries.is_power_of_two() || reg.ring_entries >= MAX_BIDS_PER_GROUP {
+            return_errno_with_message!(Errno::EINVAL, "invalid number of ring entries");
+        }
+        if reg.ring_addr == 0 {
+            return_errno_with_message!(Errno::EFAULT, "the ring address is null");
+        }
+        if reg.ring_addr as usize % PAGE_SIZE != 0 {
+            return_errno_with_message!(Errno::EINVAL, "the ring is not page-aligned");
+        }
+
+        let mut groups = self.groups.lock();
+        if groups.contains_key(&reg.bgid) {
+            return_errno_with_message!(Errno::EEXIST, "the group already exists");
+        }
+        let ring = BufRing {
+            addr: reg.ring_addr as Vaddr,
+            nr_entries: reg.ring_entries as u16,
+            head: 0,
+        };
+        groups.insert(
+            reg.bgid,
+            BufferGroup {
+                buffers: VecDeque::new(),
+                ring: Some(ring),
+            },
+        );
+        Ok(())
+    }
+
+    /// Unregisters the buffer ring of the group.
+    pub(super) fn unregister_ring(&self, group_id: u16) -> Result<()> {
+        let mut groups = self.groups.lock();
+        let Some(group) = groups.get(&group_id) else {
+            return_errno_with_message!(Errno::ENOENT, "the group does not exist");
+        };
+        if group.ring.is_none() {
+            return_errno_with_message!(Errno::EINVAL, "the group is not a buffer ring");
+        }
+        groups.remove(&group_id);
+        Ok(())
+    }
+
+    /// Selects a buffer from the group.
+    ///
+    /// The buffer ring, if any, is accessed in `vmar`. If the buffer is not used in the
+    /// end, it should be given back with [`Self::recycle`].
+    pub(super) fn select(&self, group_id: u16, vmar: &Vmar<Full>) -> Result<ProvidedBuffer> {
+        let mut groups = self.groups.lock();
+        let Some(group) = groups.get_mut(&group_id) else {
+            return_errno_with_message!(Errno::ENOBUFS, "the group does not exist");
+        };
+        if let Some(buffer) = group.buffers.pop_front() {
+            return Ok(buffer);
+        }
+        let Some(ring) = group.ring.as_mut() else {
+            return_errno_with_message!(Errno::ENOBUFS, "no buffers are left in the group");
+        };
+
+        let tail: u16 = vmar.read_val(ring.addr + BUF_RING_TAIL)?;
+        // The entries must be read after the tail.
+        fence(Ordering::Acquire);
+        if tail == ring.head {
+            return_errno_with_message!(Errno::ENOBUFS, "no buffers are left in the ring");
+        }
+        let index = (ring.head & (ring.nr_entries - 1)) as usize;
+        let entry: BufRingEntry = vmar.read_val(ring.addr + index * size_of::<BufRingEntry>())?;
+        ring.head = ring.head.wrapping_add(1);
+        Ok(ProvidedBuffer {
+            addr: entry.addr as Vaddr,
+            len: entry.len as usize,
+            id: entry.bid,
+        })
+    }
+
+    /// Gives back a selected buffer that is not used.
+    pub(super) fn recycle(&self, group_id: u16, buffer: ProvidedBuffer) {
+        let mut groups = self.groups.lock();
+        // The group may have been removed meanwhile, and the buffer goes with it.
+        if let Some(group) = groups.get_mut(&group_id) {
+            group.buffers.push_front(buffer);
+        }
+    }
+}
@@ -0,0 +1,610 @@
+// SPDX-License-Identifier: MPL-2.0
+
+//! Asynchronous I/O with io_uring.
+//!
+//! An io_uring instance has a submission queue (SQ) and a completion queue (CQ), which
+//! are rings shared with the user space. The user space fills the submission queue
+//! entries (SQEs) and submits them with `io_uring_enter`. The result of each operation
+//! is posted as a completion queue entry (CQE), which can be waited for with
+//! `io_uring_enter`, polled with `epoll`, or notified by a registered eventfd.
+//!
+//! Unlike Linux, there is no thread polling the SQ (`IORING_SETUP_SQPOLL`), so the SQEs
+//! are only consumed by `io_uring_enter`. The operations on the file table, e.g.,
+//! `IORING_OP_OPENAT` and `IORING_OP_CLOSE`, are executed at once in the submitting
+//! thread. The I/O operations are executed by the kernel worker threads. If such an
+//! operation may block, e.g., reading a socket without data, it is only executed after
+//! the events that it waits for are polled, so it never blocks the worker threads.
+//!
+//! A multishot operation, e.g., accepting with `IORING_ACCEPT_MULTISHOT`, stays
+//! registered as an observer of its file after it completes, and posts a CQE with
+//! `IORING_CQE_F_MORE` each time it is executed again, until it fails. The data can be
+//! received into the buffers provided by the user space (see [`buffer`]), so that no
+//! buffer is pinned by the operations waiting for data.
+//!
+//! The CQ ring never drops CQEs (`IORING_FEAT_NODROP`). If it is full, the CQEs are
+//! kept in the kernel until there is room, and new submissions fail with `EBUSY`. The
+//! multishot operations are terminated instead of filling the kernel with CQEs.
+
+use core::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+
+pub use self::{
+    buffer::BufRingReg,
+    ring::{
+        CqringOffsets, IoUringParams, SqringOffsets, IORING_OFF_CQ_RING, IORING_OFF_SQES,
+        IORING_OFF_SQ_RING,
+    },
+};
+use self::{
+    buffer::BufferGroups,
+    op::{Completion, Op},
+    ring::{Cqe, Rings, Sqe},
+};
+use super::{
+    file_handle::{FileLike, MmapRegion},
+    utils::{InodeMode, InodeType, Metadata},
+};
+use crate::{
+    events::{IoEvents, Observer},
+    prelude::*,
+    process::{
+        signal::{Pauser, Pollee, Poller},
+        Gid, Process, Uid,
+    },
+    thread::work_queue::{submit_work_item, work_item::WorkItem, WorkPriority},
+    time::clocks::RealTimeClock,
+    vm::perms::VmPerms,
+};
+
+mod buffer;
+mod op;
+mod ring;
+
+/// The maximum number of the SQ entries, which is the same as Linux.
+const IORING_MAX_ENTRIES: u32 = 32768;
+/// The maximum number of the CQ entries, which is the same as Linux.
+const IORING_MAX_CQ_ENTRIES: u32 = 2 * IORING_MAX_ENTRIES;
+
+/// The SQE flag that forces the operation to be executed asynchronously, which is a hint.
+const IOSQE_ASYNC: u8 = 1 << 4;
+/// The SQE flag that makes the operation select a buffer from the group in `buf_group`.
+const IOSQE_BUFFER_SELECT: u8 = 1 << 5;
+
+/// The CQE flag that indicates that the upper 16 bits of the flags are a buffer ID.
+const IORING_CQE_F_BUFFER: u32 = 1 << 0;
+/// The CQE flag that indicates that the multishot operation will post more CQEs.
+const IORING_CQE_F_MORE: u32 = 1 << 1;
+const IORING_CQE_BUFFER_SHIFT: u32 = 16;
+
+bitflags! {
+    /// The flags of `io_uring_setup`.
+    pub struct SetupFlags: u32 {
+        const IORING_SETUP_IOPOLL = 1 << 0;
+        const IORING_SETUP_SQPOLL = 1 << 1;
+        const IORING_SETUP_SQ_AFF = 1 << 2;
+        const IORING_SETUP_CQSIZE = 1 << 3;
+        const IORING_SETUP_CLAMP = 1 << 4;
+        const IORING_SETUP_ATTACH_WQ = 1 << 5;
+        const IORING_SETUP_R_DISABLED = 1 << 6;
+    }
+}
+
+bitflags! {
+    /// The flags of `io_uring_enter`.
+    pub struct EnterFlags: u32 {
+        const IORING_ENTER_GETEVENTS = 1 << 0;
+        const IORING_ENTER_SQ_WAKEUP = 1 << 1;
+        const IORING_ENTER_SQ_WAIT = 1 << 2;
+        const IORING_ENTER_EXT_ARG = 1 << 3;
+    }
+}
+
+bitflags! {
+    /// The features reported by `io_uring_setup`.
+    struct Features: u32 {
+        const IORING_FEAT_SINGLE_MMAP = 1 << 0;
+        const IORING_FEAT_NODROP = 1 << 1;
+        const IORING_FEAT_SUBMIT_STABLE = 1 << 2;
+        const IORING_FEAT_RW_CUR_POS = 1 << 3;
+    }
+}
+
+/// The file of an io_uring instance.
+pub struct IoUringFile {
+    io_uring: Arc<IoUring>,
+}
+
+impl IoUringFile {
+    /// Creates an io_uring instance for the current process.
+    ///
+    /// The number of entries is rounded up to a power of two. The actual numbers of
+    /// entries, the offsets of the rings and the features are written back to `params`.
+    pub fn new(entries: u32, params: &mut IoUringParams) -> Result<Self> {
+        let flags = SetupFlags::from_bits(params.flags)
+            .ok_or_else(|| Error::with_message(Errno::EINVAL, "invalid setup flags"))?;
+        if !(SetupFlags::IORING_SETUP_CQSIZE | SetupFlags::IORING_SETUP_CLAMP).contains(flags) {
+            return_errno_with_message!(Errno::EINVAL, "the setup flags are not supported");
+        }
+        if params.resv.iter().any(|resv| *resv != 0) {
+            return_errno_with_message!(Errno::EINVAL, "the reserved fields are not zero");
+        }
+
+        let is_clamped = flags.contains(SetupFlags::IORING_SETUP_CLAMP);
+        let clamp = |entries: u32, max_entries: u32| {
+            if entries == 0 {
+                return_errno_with_message!(Errno::EINVAL, "the number of entries is zero");
+            }
+            if entries > max_entries {
+                if !is_clamped {
+                    return_errno_with_message!(Errno::EINVAL, "too many entries");
+                }
+                return Ok(max_entries);
+            }
+            Ok(entries.next_power_of_two())
+        };
+        let sq_entries = clamp(entries, IORING_MAX_ENTRIES)?;
+        let cq_entries = if flags.contains(SetupFlags::IORING_SETUP_CQSIZE) {
+            let cq_entries = clamp(params.cq_entries, IORING_MAX_CQ_ENTRIES)?;
+            if cq_entries < sq_entries {
+                return_errno_with_message!(Errno::EINVAL, "the CQ is smaller than the SQ");
+            }
+            cq_entries
+        } else {
+            2 * sq_entries
+        };
+
+        let rings = Rings::new(sq_entries, cq_entries)?;
+        rings.fill_offsets(params);
+        params.features = Features::all().bits();
+
+        let io_uring = Arc::new(IoUring {
+            rings,
+            process: Arc::downgrade(&current!()),
+            sq_head: Mutex::new(0),
+            cq: Mutex::new(CompletionQueue {
+                tail: 0,
+                overflowed_cqes: VecDeque::new(),
+            }),
+            requests: Mutex::new(BTreeMap::new()),
+            next_request_id: AtomicU64::new(0),
+            eventfd: Mutex::new(None),
+            buffer_groups: BufferGroups::new(),
+            pollee: Pollee::new(IoEvents::OUT),
+            cq_pauser: Pauser::new(),
+        });
+        Ok(Self { io_uring })
+    }
+
+    /// Submits at most `to_submit` SQEs and waits for `min_complete` CQEs if
+    /// `IORING_ENTER_GETEVENTS` is in `flags`.
+    ///
+    /// Returns the number of the SQEs consumed.
+    pub fn enter(&self, to_submit: u32, min_complete: u32, flags: EnterFlags) -> Result<u32> {
+        let nr_submitted = if to_submit > 0 {
+            self.io_uring.submit(to_submit)?
+        } else {
+            0
+        };
+
+        if flags.contains(EnterFlags::IORING_ENTER_GETEVENTS) && min_complete > 0 {
+            let min_complete = min_complete.min(self.io_uring.rings.cq_entries());
+            let res = self
+                .io_uring
+                .cq_pauser
+                .pause_until(|| (self.io_uring.nr_ready_cqes() >= min_complete).then_some(()));
+            // The submissions have been done and cannot be undone.
+            if let Err(err) = res
+                && nr_submitted == 0
+            {
+                return Err(err);
+            }
+        }
+
+        Ok(nr_submitted)
+    }
+
+    /// Registers the eventfd that is signaled when a CQE is posted.
+    pub fn register_eventfd(&self, eventfd: Arc<dyn FileLike>) -> Result<()> {
+        let mut registered = self.io_uring.eventfd.lock();
+        if registered.is_some() {
+            return_errno_with_message!(Errno::EBUSY, "an eventfd has been registered");
+        }
+        *registered = Some(eventfd);
+        Ok(())
+    }
+
+    pub fn unregister_eventfd(&self) -> Result<()> {
+        if self.io_uring.eventfd.lock().take().is_none() {
+            return_errno_with_message!(Errno::ENXIO, "no eventfd has been registered");
+        }
+        Ok(())
+    }
+
+    /// Registers a ring of the buffers provided by the user space.
+    pub fn register_buf_ring(&self, reg: &BufRingReg) -> Result<()> {
+        self.io_uring.buffer_groups.register_ring(reg)
+    }
+
+    pub fn unregister_buf_ring(&self, group_id: u16) -> Result<()> {
+        self.io_uring.buffer_groups.unregister_ring(group_id)
+    }
+}
+
+impl FileLike for IoUringFile {
+    fn poll(&self, mask: IoEvents, poller: Option<&Poller>) -> IoEvents {
+        // The user space consumes the CQEs without notifying the kernel.
+        if self.io_uring.nr_ready_cqes() > 0 {
+            self.io_uring.pollee.add_events(IoEvents::IN);
+        } else {
+            self.io_uring.pollee.del_events(IoEvents::IN);
+        }
+        self.io_uring.pollee.poll(mask, poller)
+    }
+
+    fn register_observer(
+        &self,
+        observer: Weak<dyn Observer<IoEvents>>,
+        mask: IoEvents,
+    ) -> Result<()> {
+        self.io_uring.pollee.register_observer(observer, mask);
+        Ok(())
+    }
+
+    fn unregister_observer(
+        &self,
+        observer: &Weak<dyn Observer<IoEvents>>,
+    ) -> Option<Weak<dyn Observer<IoEvents>>> {
+        self.io_uring.pollee.unregister_observer(observer)
+    }
+
+    fn mmap(
+        &self,
+        offset: usize,
+        len: usize,
+        is_shared: bool,
+        _perms: VmPerms,
+    ) -> Result<Option<MmapRegion>> {
+        if !is_shared {
+            return_errno_with_message!(Errno::EINVAL, "the rings must be mapped shared");
+        }
+        // The `mmap` offset selects the region to map.
+        let rings = &self.io_uring.rings;
+        let shared_mem = rings.shared_mem();
+        let region = match offset {
+            IORING_OFF_SQ_RING | IORING_OFF_CQ_RING => 0..rings.sqes_offset(),
+            IORING_OFF_SQES => rings.sqes_offset()..shared_mem.size(),
+            _ => return_errno_with_message!(Errno::EINVAL, "invalid io_uring mmap offset"),
+        };
+        if len > region.len() {
+            return_errno_with_message!(Errno::EINVAL, "the length exceeds the region");
+        }
+        Ok(Some(MmapRegion::from_shared_mem(
+            shared_mem.clone(),
+            region.start,
+        )))
+    }
+
+    fn metadata(&self) -> Metadata {
+        let now = RealTimeClock::get().read_time();
+        Metadata {
+            dev: 0,
+            ino: 0,
+            size: 0,
+            blk_size: 0,
+            blocks: 0,
+            atime: now,
+            mtime: now,
+            ctime: now,
+            type_: InodeType::NamedPipe,
+            mode: InodeMode::from_bits_truncate(0o600),
+            nlinks: 1,
+            uid: Uid::new_root(),
+            gid: Gid::new_root(),
+            rdev: 0,
+        }
+    }
+}
+
+struct IoUring {
+    rings: Rings,
+    process: Weak<Process>,
+    /// The head of the SQ ring. The lock also serializes the submissions.
+    sq_head: Mutex<u32>,
+    cq: Mutex<CompletionQueue>,
+    /// The requests that are being executed by the kernel worker threads.
+    requests: Mutex<BTreeMap<u64, Arc<Request>>>,
+    next_request_id: AtomicU64,
+    eventfd: Mutex<Option<Arc<dyn FileLike>>>,
+    buffer_groups: BufferGroups,
+    pollee: Pollee,
+    cq_pauser: Arc<Pauser>,
+}
+
+struct CompletionQueue {
+    /// The tail of the CQ ring.
+    tail: u32,
+    /// The CQEs that are posted when the CQ ring is full.
+    overflowed_cqes: VecDeque<Cqe>,
+}
+
+impl IoUring {
+    fn submit(self: &Arc<Self>, to_submit: u32) -> Result<u32> {
+        if !self.flush_cqes(&mut self.cq.lock()) {
+            return_errno_with_message!(Errno::EBUSY, "the CQ ring has overflowed");
+        }
+
+        let mut sq_head = self.sq_head.lock();
+        let nr_pending = self
+            .rings
+            .sq_tail()
+            .wrapping_sub(*sq_head)
+            .min(self.rings.sq_entries());
+        let mut nr_submitted = 0;
+        for _ in 0..to_submit.min(nr_pending) {
+            let index = self.rings.sq_index(*sq_head);
+            *sq_head = sq_head.wrapping_add(1);
+            if index >= self.rings.sq_entries() {
+                self.rings.inc_sq_dropped();
+                continue;
+            }
+            // The SQE is copied, so the user space can reuse it once it is consumed.
+            let sqe = self.rings.read_sqe(index);
+            self.submit_sqe(&sqe);
+            nr_submitted += 1;
+        }
+        self.rings.set_sq_head(*sq_head);
+        Ok(nr_submitted)
+    }
+
+    fn submit_sqe(self: &Arc<Self>, sqe: &Sqe) {
+        let op = if sqe.flags & !(IOSQE_ASYNC | IOSQE_BUFFER_SELECT) != 0 {
+            Err(Error::with_message(
+                Errno::EINVAL,
+                "the SQE flags are not supported",
+            ))
+        } else {
+            Op::parse(sqe)
+        };
+        let op = match op {
+            Ok(op) => op,
+            Err(err) => return self.post_cqe(sqe.user_data, Err(err)),
+        };
+
+        if op.is_inline() {
+            let res = op.execute_inline(&self.buffer_groups);
+            self.post_cqe(sqe.user_data, res.map(Completion::new));
+            return;
+        }
+
+        let request = Request::new(self, op, sqe.user_data);
+        self.requests.lock().insert(request.id, request.clone());
+        request.queue();
+    }
+
+    fn complete_request(&self, request: &Request, res: Result<Completion>) {
+        if self.requests.lock().remove(&request.id).is_some() {
+            self.post_cqe(request.user_data, res);
+        }
+    }
+
+    fn post_cqe(&self, user_data: u64, res: Result<Completion>) {
+        let cqe = new_cqe(user_data, res, 0);
+        {
+            let mut cq = self.cq.lock();
+            cq.overflowed_cqes.push_back(cqe);
+            self.flush_cqes(&mut cq);
+        }
+        self.notify_cqe();
+    }
+
+    /// Posts a CQE of a multishot request that goes on.
+    ///
+    /// Returns `false` if the CQE is not posted, since the request has been canceled or
+    /// the CQ ring is full, in which case the request should be completed.
+    fn post_more_cqe(&self, request: &Request, completion: Completion) -> bool {
+        if !self.requests.lock().contains_key(&request.id) {
+            return false;
+        }
+        {
+            let mut cq = self.cq.lock();
+            if !self.flush_cqes(&mut cq)
+                || cq.tail.wrapping_sub(self.rings.cq_head()) >= self.rings.cq_entries()
+            {
+                return false;
+            }
+            let cqe = new_cqe(request.user_data, Ok(completion), IORING_CQE_F_MORE);
+            self.rings.write_cqe(cq.tail, &cqe);
+            cq.tail = cq.tail.wrapping_add(1);
+            self.rings.set_cq_tail(cq.tail);
+        }
+        self.notify_cqe();
+        true
+    }
+
+    fn notify_cqe(&self) {
+        self.pollee.add_events(IoEvents::IN);
+        self.cq_pauser.resume_all();
+        let eventfd = self.eventfd.lock().clone();
+        if let Some(eventfd) = eventfd {
+            let _ = eventfd.write(&1u64.to_ne_bytes());
+        }
+    }
+
+    /// Moves the overflowed CQEs to the CQ ring as many as possible.
+    ///
+    /// Returns whether all the overflowed CQEs are moved.
+    fn flush_cqes(&self, cq: &mut CompletionQueue) -> bool {
+        if cq.overflowed_cqes.is_empty() {
+            return true;
+        }
+
+        let head = self.rings.cq_head();
+        while cq.tail.wrapping_sub(head) < self.rings.cq_entries() {
+            let Some(cqe) = cq.overflowed_cqes.pop_front() else {
+                break;
+            };
+            self.rings.write_cqe(cq.tail, &cqe);
+            cq.tail = cq.tail.wrapping_add(1);
+        }
+        self.rings.set_cq_tail(cq.tail);
+
+        let is_flushed = cq.overflowed_cqes.is_empty();
+        self.rings.set_cq_overflow_flag(!is_flushed);
+        is_flushed
+    }
+
+    /// Returns the number of the CQEs that have not been consumed by the user space.
+    fn nr_ready_cqes(&self) -> u32 {
+        let mut cq = self.cq.lock();
+        self.flush_cqes(&mut cq);
+        cq.tail
+            .wrapping_sub(self.rings.cq_head())
+            .min(self.rings.cq_entries())
+    }
+}
+
+fn new_cqe(user_data: u64, res: Result<Completion>, flags: u32) -> Cqe {
+    let (res, flags) = match res {
+        Ok(Completion {
+            res,
+            buffer_id: Some(buffer_id),
+        }) => (
+            res as i32,
+            flags | IORING_CQE_F_BUFFER | (buffer_id as u32) << IORING_CQE_BUFFER_SHIFT,
+        ),
+        Ok(Completion { res, .. }) => (res as i32, flags),
+        Err(err) => (-(err.error() as i32), flags),
+    };
+    Cqe {
+        user_data,
+        res,
+        flags,
+    }
+}
+
+impl Drop for IoUring {
+    fn drop(&mut self) {
+        // The pending requests are canceled silently.
+        for request in self.requests.lock().values() {
+            request.stop_polling();
+        }
+    }
+}
+
+/// A request executed by the kernel worker threads.
+struct Request {
+    id: u64,
+    user_data: u64,
+    op: Op,
+    io_uring: Weak<IoUring>,
+    work_item: Arc<WorkItem>,
+    /// Whether the request has completed. The lock also serializes the executions.
+    is_completed: Mutex<bool>,
+    /// Whether the request is an observer of the file that it polls.
+    is_polling: AtomicBool,
+    weak_self: Weak<Self>,
+}
+
+impl Request {
+    fn new(io_uring: &Arc<IoUring>, op: Op, user_data: u64) -> Arc<Self> {
+        Arc::new_cyclic(|weak_self: &Weak<Self>| {
+            let weak_request = weak_self.clone();
+            let work_item = Arc::new(WorkItem::new(Box::new(move || {
+                if let Some(request) = weak_request.upgrade() {
+                    request.run();
+                }
+            })));
+            Self {
+                id: io_uring.next_request_id.fetch_add(1, Ordering::Relaxed),
+                user_data,
+                op,
+                io_uring: Arc::downgrade(io_uring),
+                work_item,
+                is_completed: Mutex::new(false),
+                is_polling: AtomicBool::new(false),
+                weak_self: weak_self.clone(),
+            }
+        })
+    }
+
+    fn queue(&self) {
+        submit_work_item(self.work_item.clone(), WorkPriority::Normal);
+    }
+
+    fn run(&self) {
+        // The request is canceled if the io_uring instance or the process is gone.
+        let Some(io_uring) = self.io_uring.upgrade() else {
+            return;
+        };
+        let Some(process) = io_uring.process.upgrade() else {
+            return;
+        };
+
+        let mut is_completed = self.is_completed.lock();
+        if *is_completed {
+            return;
+        }
+
+        let file_to_poll = self.op.file_to_poll();
+        let res = loop {
+            let res = match file_to_poll {
+                Some((file, events)) if file.poll(events, None).is_empty() => {
+                    Err(Error::new(Errno::EAGAIN))
+                }
+                _ => self.op.execute(&process, &io_uring.buffer_groups),
+            };
+            match (res, file_to_poll) {
+                // A non-blocking file may be not ready even if the events have been polled,
+                // since others may have taken the events.
+                (Err(err), Some((file, events))) if err.error() == Errno::EAGAIN => {
+                    match self.poll(file, events) {
+                        Ok(()) => return,
+                        Err(err) => break Err(err),
+                    }
+                }
+                (Ok(completion), _)
+                    if self.op.is_continued(&completion)
+                        && io_uring.post_more_cqe(self, completion) =>
+                {
+                    continue
+                }
+                (res, _) => break res,
+            }
+        };
+
+        *is_completed = true;
+        self.stop_polling();
+        io_uring.complete_request(self, res);
+    }
+
+    /// Executes the request again when the events of the file are polled.
+    fn poll(&self, file: &dyn FileLike, events: IoEvents) -> Result<()> {
+        if !self.is_polling.swap(true, Ordering::Relaxed) {
+            let observer = self.weak_self.clone() as Weak<dyn Observer<IoEvents>>;
+            if let Err(err) = file.register_observer(observer, events) {
+                self.is_polling.store(false, Ordering::Relaxed);
+                return Err(err);
+            }
+        }
+        // The events may come before the observer is registered.
+        if !file.poll(events, None).is_empty() {
+            self.queue();
+        }
+        Ok(())
+    }
+
+    fn stop_polling(&self) {
+        if !self.is_polling.swap(false, Ordering::Relaxed) {
+            return;
+        }
+        if let Some((file, _)) = self.op.file_to_poll() {
+            let observer = self.weak_self.clone() as Weak<dyn Observer<IoEvents>>;
+            let _ = file.unregister_observer(&observer);
+        }
+    }
+}
+
+impl Observer<IoEvents> for Request {
+    fn on_events(&self, _events: &IoEvents) {
+        self.queue();
+    }
+}
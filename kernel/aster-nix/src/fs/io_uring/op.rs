@@ -0,0 +1,556 @@
+// SPDX-License-Identifier: MPL-2.0
+
+//! The operations submitted to an io_uring instance.
+
+use aster_frame::mm::VmIo;
+use aster_rights::Full;
+
+use super::{buffer::BufferGroups, ring::Sqe, IoUringFile, IOSQE_BUFFER_SELECT};
+use crate::{
+    events::IoEvents,
+    fs::{
+        file_handle::FileLike,
+        file_table::{FdFlags, FileDesc},
+        fs_resolver::FsPath,
+        inode_handle::InodeHandle,
+        memfd::MemfdFile,
+        utils::{CreationFlags, InodeType, PATH_MAX},
+    },
+    prelude::*,
+    process::Process,
+    util::read_cstring_from_user,
+    vm::vmar::Vmar,
+};
+#[cfg(feature = "net")]
+use crate::{
+    fs::utils::StatusFlags,
+    net::socket::{SendRecvFlags, Socket},
+    util::net::socket_addr_to_bytes,
+};
+
+// The opcodes of the SQEs, which are the same as Linux.
+const IORING_OP_NOP: u8 = 0;
+#[cfg(feature = "net")]
+const IORING_OP_ACCEPT: u8 = 13;
+const IORING_OP_OPENAT: u8 = 18;
+const IORING_OP_CLOSE: u8 = 19;
+const IORING_OP_READ: u8 = 22;
+const IORING_OP_WRITE: u8 = 23;
+#[cfg(feature = "net")]
+const IORING_OP_SEND: u8 = 26;
+#[cfg(feature = "net")]
+const IORING_OP_RECV: u8 = 27;
+const IORING_OP_PROVIDE_BUFFERS: u8 = 31;
+const IORING_OP_REMOVE_BUFFERS: u8 = 32;
+
+// The flags of `IORING_OP_ACCEPT` and `IORING_OP_RECV` in the `ioprio` field of the SQEs.
+#[cfg(feature = "net")]
+const IORING_ACCEPT_MULTISHOT: u16 = 1 << 0;
+#[cfg(feature = "net")]
+const IORING_RECVSEND_POLL_FIRST: u16 = 1 << 0;
+#[cfg(feature = "net")]
+const IORING_RECV_MULTISHOT: u16 = 1 << 1;
+
+/// An operation parsed from an SQE.
+///
+/// The files are looked up when the SQE is submitted, while the user buffers are only
+/// accessed when the operation is executed, so they must stay valid until then.
+pub(super) enum Op {
+    Nop,
+    OpenAt {
+        dirfd: FileDesc,
+        path: CString,
+        flags: u32,
+        mode: u16,
+    },
+    Close {
+        fd: FileDesc,
+    },
+    Read {
+        file: Arc<dyn FileLike>,
+        buf: ReadBuf,
+        /// The offset to read at, or `None` to read at the file offset.
+        offset: Option<usize>,
+    },
+    Write {
+        file: Arc<dyn FileLike>,
+        buf: Vaddr,
+        len: usize,
+        /// The offset to write at, or `None` to write at the file offset.
+        offset: Option<usize>,
+    },
+    #[cfg(feature = "net")]
+    Send {
+        file: Arc<dyn FileLike>,
+        socket: Arc<dyn Socket>,
+        buf: Vaddr,
+        len: usize,
+        flags: SendRecvFlags,
+    },
+    #[cfg(feature = "net")]
+    Recv {
+        file: Arc<dyn FileLike>,
+        socket: Arc<dyn Socket>,
+        buf: ReadBuf,
+        flags: SendRecvFlags,
+        /// Whether the operation receives over and over again with the selected buffers.
+        is_multishot: bool,
+    },
+    #[cfg(feature = "net")]
+    Accept {
+        file: Arc<dyn FileLike>,
+        socket: Arc<dyn Socket>,
+        addr: Vaddr,
+        addrlen_ptr: Vaddr,
+        flags: u32,
+        /// Whether the operation accepts the connections over and over again.
+        is_multishot: bool,
+    },
+    ProvideBuffers {
+        group_id: u16,
+        addr: Vaddr,
+        len: u32,
+        nr_buffers: u32,
+        first_id: u16,
+    },
+    RemoveBuffers {
+        group_id: u16,
+        nr_buffers: u32,
+    },
+}
+
+/// The buffer that an operation reads into.
+#[derive(Debug, Clone, Copy)]
+pub(super) enum ReadBuf {
+    /// The buffer given in the SQE.
+    Given { addr: Vaddr, len: usize },
+    /// A buffer selected from the group when the operation is executed (`IOSQE_BUFFER_SELECT`).
+    ///
+    /// At most `max_len` bytes are read unless it is zero.
+    Selected { group_id: u16, max_len: usize },
+}
+
+/// The result of an operation executed by the kernel worker threads.
+#[derive(Debug, Clone, Copy)]
+pub(super) struct Completion {
+    pub(super) res: usize,
+    /// The ID of the provided buffer that the operation has used.
+    pub(super) buffer_id: Option<u16>,
+}
+
+impl Completion {
+    fn new(res: usize) -> Self {
+        Self {
+            res,
+            buffer_id: None,
+        }
+    }
+}
+
+impl Op {
+    /// Parses the operation of `sqe` in the context of the current process.
+    pub(super) fn parse(sqe: &Sqe) -> Result<Self> {
+        let is_buffer_select = sqe.flags & IOSQE_BUFFER_SELECT != 0;
+        let read_buf = if is_buffer_select {
+            ReadBuf::Selected {
+                group_id: sqe.buf_index,
+                max_len: sqe.len as usize,
+            }
+        } else {
+            ReadBuf::Given {
+                addr: sqe.addr as Vaddr,
+                len: sqe.len as usize,
+            }
+        };
+
+        let op = match sqe.opcode {
+            IORING_OP_NOP => Self::Nop,
+            IORING_OP_OPENAT => Self::OpenAt {
+                dirfd: sqe.fd,
+                path: read_cstring_from_user(sqe.addr as Vaddr, PATH_MAX)?,
+                flags: sqe.op_flags,
+                mode: sqe.len as u16,
+            },
+            IORING_OP_CLOSE => Self::Close { fd: sqe.fd },
+            IORING_OP_READ | IORING_OP_WRITE => {
+                let file = get_file(sqe.fd)?;
+                // Like Linux, the offset is ignored for the files that are not seekable.
+                let offset = if sqe.off == u64::MAX || !is_regular_file(file.as_ref()) {
+                    None
+                } else if sqe.off > isize::MAX as u64 {
+                    return_errno_with_message!(Errno::EINVAL, "the offset is too large");
+                } else {
+                    Some(sqe.off as usize)
+                };
+                if sqe.opcode == IORING_OP_READ {
+                    Self::Read {
+                        file,
+                        buf: read_buf,
+                        offset,
+                    }
+                } else {
+                    Self::Write {
+                        file,
+                        buf: sqe.addr as Vaddr,
+                        len: sqe.len as usize,
+                        offset,
+                    }
+                }
+            }
+            #[cfg(feature = "net")]
+            IORING_OP_SEND => {
+                let (file, socket) = get_socket(sqe.fd)?;
+                Self::Send {
+                    file,
+                    socket,
+                    buf: sqe.addr as Vaddr,
+                    len: sqe.len as usize,
+                    flags: SendRecvFlags::from_bits_truncate(sqe.op_flags as i32),
+                }
+            }
+            #[cfg(feature = "net")]
+            IORING_OP_RECV => {
+                if sqe.ioprio & !(IORING_RECVSEND_POLL_FIRST | IORING_RECV_MULTISHOT) != 0 {
+                    return_errno_with_message!(Errno::EINVAL, "invalid receiving flags");
+                }
+                let flags = SendRecvFlags::from_bits_truncate(sqe.op_flags as i32);
+                let is_multishot = sqe.ioprio & IORING_RECV_MULTISHOT != 0;
+                // Like Linux, a multishot operation must select the buffers, whose lengths
+                // limit the lengths of the messages.
+                if is_multishot
+                    && (!is_buffer_select
+                        || sqe.len != 0
+                        || flags.contains(SendRecvFlags::MSG_WAITALL))
+                {
+                    return_errno_with_message!(Errno::EINVAL, "invalid multishot receiving");
+                }
+                let (file, socket) = get_socket(sqe.fd)?;
+                Self::Recv {
+                    file,
+                    socket,
+                    buf: read_buf,
+                    flags,
+                    is_multishot,
+                }
+            }
+            #[cfg(feature = "net")]
+            IORING_OP_ACCEPT => {
+                if sqe.ioprio & !IORING_ACCEPT_MULTISHOT != 0 {
+                    return_errno_with_message!(Errno::EINVAL, "invalid accepting flags");
+                }
+                let (file, socket) = get_socket(sqe.fd)?;
+                Self::Accept {
+                    file,
+                    socket,
+                    addr: sqe.addr as Vaddr,
+                    addrlen_ptr: sqe.off as Vaddr,
+                    flags: sqe.op_flags,
+                    is_multishot: sqe.ioprio & IORING_ACCEPT_MULTISHOT != 0,
+                }
+            }
+            // The number of the buffers is in the `fd` field, and the ID of the first
+            // buffer is in the `off` field.
+            IORING_OP_PROVIDE_BUFFERS => {
+                if sqe.op_flags != 0 {
+                    return_errno_with_message!(Errno::EINVAL, "the flags are not supported");
+                }
+                if sqe.off > u16::MAX as u64 {
+                    return_errno_with_message!(Errno::E2BIG, "the buffer ID is too large");
+                }
+                Self::ProvideBuffers {
+                    group_id: sqe.buf_index,
+                    addr: sqe.addr as Vaddr,
+                    len: sqe.len,
+                    nr_buffers: sqe.fd as u32,
+                    first_id: sqe.off as u16,
+                }
+            }
+            IORING_OP_REMOVE_BUFFERS => {
+                if sqe.op_flags != 0 || sqe.addr != 0 || sqe.len != 0 || sqe.off != 0 {
+                    return_errno_with_message!(Errno::EINVAL, "the fields are not zero");
+                }
+                Self::RemoveBuffers {
+                    group_id: sqe.buf_index,
+                    nr_buffers: sqe.fd as u32,
+                }
+            }
+            _ => return_errno_with_message!(Errno::EINVAL, "the opcode is not supported"),
+        };
+
+        // Only the operations that read into a buffer can select one.
+        let reads_to_buf = match &op {
+            Self::Read { .. } => true,
+            #[cfg(feature = "net")]
+            Self::Recv { .. } => true,
+            _ => false,
+        };
+        if is_buffer_select && !reads_to_buf {
+            return_errno_with_message!(Errno::EINVAL, "the opcode cannot select buffers");
+        }
+        Ok(op)
+    }
+
+    /// Returns whether the operation is executed when it is submitted.
+    ///
+    /// The operations on the file table and the file system never block for long, and they
+    /// must be done with the credentials of the submitting thread, which the kernel worker
+    /// threads do not have.
+    pub(super) fn is_inline(&self) -> bool {
+        matches!(
+            self,
+            Self::Nop
+                | Self::OpenAt { .. }
+                | Self::Close { .. }
+                | Self::ProvideBuffers { .. }
+                | Self::RemoveBuffers { .. }
+        )
+    }
+
+    /// Returns whether the operation goes on after it completes with `completion`.
+    ///
+    /// A multishot operation posts a CQE for each completion, until it fails or, for
+    /// receiving, reaches the end of the stream.
+    pub(super) fn is_continued(&self, completion: &Completion) -> bool {
+        match self {
+            #[cfg(feature = "net")]
+            Self::Accept { is_multishot, .. } => *is_multishot,
+            #[cfg(feature = "net")]
+            Self::Recv { is_multishot, .. } => *is_multishot && completion.res > 0,
+            _ => false,
+        }
+    }
+
+    /// Executes an inline operation in the context of the current process.
+    pub(super) fn execute_inline(&self, buffer_groups: &BufferGroups) -> Result<usize> {
+        debug_assert!(self.is_inline());
+
+        let current = current!();
+        match self {
+            Self::OpenAt {
+                dirfd,
+                path,
+                flags,
+                mode,
+            } => {
+                let inode_handle = {
+                    let path = path.to_string_lossy();
+                    let fs_path = FsPath::new(*dirfd, path.as_ref())?;
+                    let mask_mode = mode & !current.umask().read().get();
+                    current.fs().read().open(&fs_path, *flags, mask_mode)?
+                };
+                let fd_flags = FdFlags::from(CreationFlags::from_bits_truncate(*flags));
+                let fd = current
+                    .file_table()
+                    .lock()
+                    .insert(Arc::new(inode_handle), fd_flags);
+                Ok(fd as usize)
+            }
+            Self::Close { fd } => {
+                let file = {
+                    let mut file_table = current.file_table().lock();
+                    if file_table
+                        .get_file(*fd)?
+                        .downcast_ref::<IoUringFile>()
+                        .is_some()
+                    {
+                        return_errno_with_message!(Errno::EBADF, "cannot close an io_uring");
+                    }
+                    file_table.close_file(*fd).unwrap()
+                };
+                file.clean_for_close()?;
+                Ok(0)
+            }
+            Self::ProvideBuffers {
+                group_id,
+                addr,
+                len,
+                nr_buffers,
+                first_id,
+            } => {
+                buffer_groups.provide(*group_id, *addr, *len, *nr_buffers, *first_id)?;
+                Ok(0)
+            }
+            Self::RemoveBuffers {
+                group_id,
+                nr_buffers,
+            } => buffer_groups.remove(*group_id, *nr_buffers),
+            _ => Ok(0),
+        }
+    }
+
+    /// Returns the file and the events that the operation waits for, if the operation
+    /// may block.
+    ///
+    /// Such operations are only executed when the events are polled, so that they do not
+    /// block the kernel worker threads.
+    pub(super) fn file_to_poll(&self) -> Option<(&dyn FileLike, IoEvents)> {
+        match self {
+            Self::Read { file, .. } if !is_regular_file(file.as_ref()) => {
+                Some((file.as_ref(), IoEvents::IN))
+            }
+            Self::Write { file, .. } if !is_regular_file(file.as_ref()) => {
+                Some((file.as_ref(), IoEvents::OUT))
+            }
+            #[cfg(feature = "net")]
+            Self::Recv { file, .. } | Self::Accept { file, .. } => {
+                Some((file.as_ref(), IoEvents::IN))
+            }
+            #[cfg(feature = "net")]
+            Self::Send { file, .. } => Some((file.as_ref(), IoEvents::OUT)),
+            _ => None,
+        }
+    }
+
+    /// Executes the operation on behalf of `process` in a kernel worker thread.
+    pub(super) fn execute(
+        &self,
+        process: &Process,
+        buffer_groups: &BufferGroups,
+    ) -> Result<Completion> {
+        let vmar = process.root_vmar();
+        match self {
+            Self::Read { file, buf, offset } => {
+                read_to_user(vmar, buf, buffer_groups, |buffer| match offset {
+                    Some(offset) => file.read_at(*offset, buffer),
+                    None => file.read(buffer),
+                })
+            }
+            Self::Write {
+                file,
+                buf,
+                len,
+                offset,
+            } => {
+                let mut buffer = vec![0u8; *len];
+                vmar.read_bytes(*buf, &mut buffer)?;
+                let write_len = match offset {
+                    Some(offset) => file.write_at(*offset, &buffer)?,
+                    None => file.write(&buffer)?,
+                };
+                Ok(Completion::new(write_len))
+            }
+            #[cfg(feature = "net")]
+            Self::Send {
+                socket,
+                buf,
+                len,
+                flags,
+                ..
+            } => {
+                let mut buffer = vec![0u8; *len];
+                vmar.read_bytes(*buf, &mut buffer)?;
+                let send_len = socket.sendto(&buffer, None, *flags)?;
+                Ok(Completion::new(send_len))
+            }
+            #[cfg(feature = "net")]
+            Self::Recv {
+                socket, buf, flags, ..
+            } => read_to_user(vmar, buf, buffer_groups, |buffer| {
+                let (recv_len, _) = socket.recvfrom(buffer, *flags)?;
+                Ok(recv_len)
+            }),
+            #[cfg(feature = "net")]
+            Self::Accept {
+                socket,
+                addr,
+                addrlen_ptr,
+                flags,
+                ..
+            } => {
+                let (connected_socket, socket_addr) = socket.accept()?;
+                if *flags & StatusFlags::O_NONBLOCK.bits() != 0 {
+                    connected_socket.set_status_flags(StatusFlags::O_NONBLOCK)?;
+                }
+                if *addr != 0 {
+                    let max_len = vmar.read_val::<i32>(*addrlen_ptr)?.max(0) as usize;
+                    let bytes = socket_addr_to_bytes(&socket_addr)?;
+                    let write_len = bytes.len().min(max_len);
+                    vmar.write_bytes(*addr, &bytes[..write_len])?;
+                    vmar.write_val(*addrlen_ptr, &(bytes.len() as i32))?;
+                }
+                let fd_flags = FdFlags::from(CreationFlags::from_bits_truncate(*flags));
+                let fd = process
+                    .file_table()
+                    .lock()
+                    .insert(connected_socket, fd_flags);
+                Ok(Completion::new(fd as usize))
+            }
+            _ => unreachable!("the inline operations are not executed in the workers"),
+        }
+    }
+}
+
+/// Reads into the buffer in `vmar` with `read`.
+///
+/// A selected buffer is given back to its group unless some data are read into it.
+fn read_to_user(
+    vmar: &Vmar<Full>,
+    buf: &ReadBuf,
+    buffer_groups: &BufferGroups,
+    read: impl FnOnce(&mut [u8]) -> Result<usize>,
+) -> Result<Completion> {
+    let (addr, len, selected) = match *buf {
+        ReadBuf::Given { addr, len } => (addr, len, None),
+        ReadBuf::Selected { group_id, max_len } => {
+            let selected = buffer_groups.select(group_id, vmar)?;
+            let len = if max_len == 0 {
+                selected.len
+            } else {
+                selected.len.min(max_len)
+            };
+            (selected.addr, len, Some((group_id, selected)))
+        }
+    };
+
+    let mut buffer = vec![0u8; len];
+    let res = read(&mut buffer).and_then(|read_len| {
+        vmar.write_bytes(addr, &buffer[..read_len])?;
+        Ok(read_len)
+    });
+    match (res, selected) {
+        (Ok(read_len), Some((_, selected))) if read_len > 0 => Ok(Completion {
+            res: read_len,
+            buffer_id: Some(selected.id),
+        }),
+        (res, Some((group_id, selected))) => {
+            buffer_groups.recycle(group_id, selected);
+            res.map(Completion::new)
+        }
+        (res, None) => res.map(Completion::new),
+    }
+}
+
+fn get_file(fd: FileDesc) -> Result<Arc<dyn FileLike>> {
+    let current = current!();
+    let file_table = current.file_table().lock();
+    let file = file_table.get_file(fd)?;
+    // A request that refers to its own io_uring instance would keep the instance alive.
+    if file.downcast_ref::<IoUringFile>().is_some() {
+        return_errno_with_message!(Errno::EBADF, "the file is an io_uring");
+    }
+    Ok(file.clone())
+}
+
+#[cfg(feature = "net")]
+fn get_socket(fd: FileDesc) -> Result<(Arc<dyn FileLike>, Arc<dyn Socket>)> {
+    let file = get_file(fd)?;
+    let socket = file
+        .clone()
+        .as_socket()
+        .ok_or_else(|| Error::with_message(Errno::ENOTSOCK, "the fd is not a socket"))?;
+    Ok((file, socket))
+}
+
+/// Returns whether the file is a regular file or a block device, which never blocks
+/// waiting for events and has its own offsets.
+fn is_regular_file(file: &dyn FileLike) -> bool {
+    if file.downcast_ref::<MemfdFile>().is_some() {
+        return true;
+    }
+    file.downcast_ref::<InodeHandle>().is_some_and(|handle| {
+        matches!(
+            handle.dentry().type_(),
+            InodeType::File | InodeType::BlockDevice
+        )
+    })
+}
@@ -0,0 +1,278 @@
+// SPDX-License-Identifier: MPL-2.0
+
+#![allow(dead_code)]
+
+//! The rings of an io_uring instance shared with the user space.
+//!
+//! The layout is compatible with that of Linux. The rings region starts with the heads,
+//! tails and other fields of both rings, followed by the CQEs and the SQ array, which
+//! holds the indexes of the submitted SQEs. The SQEs are in a separate region that
+//! starts at the next page. Both regions are in the same [`SharedMem`], so a single
+//! `mmap` maps both the SQ ring and the CQ ring (`IORING_FEAT_SINGLE_MMAP`).
+
+use core::{
+    mem::size_of,
+    sync::atomic::{fence, Ordering},
+};
+
+use align_ext::AlignExt;
+use aster_frame::mm::VmIo;
+
+use crate::{prelude::*, vm::vmar::SharedMem};
+
+/// The `mmap` offset of the SQ ring.
+pub const IORING_OFF_SQ_RING: usize = 0;
+/// The `mmap` offset of the CQ ring, which is mapped together with the SQ ring.
+pub const IORING_OFF_CQ_RING: usize = 0x8000000;
+/// The `mmap` offset of the SQEs.
+pub const IORING_OFF_SQES: usize = 0x10000000;
+
+/// The flag in the SQ ring flags that indicates that the CQ ring has overflowed.
+const IORING_SQ_CQ_OVERFLOW: u32 = 1 << 1;
+
+const SQ_HEAD: usize = 0;
+const SQ_TAIL: usize = 4;
+const CQ_HEAD: usize = 8;
+const CQ_TAIL: usize = 12;
+const SQ_RING_MASK: usize = 16;
+const CQ_RING_MASK: usize = 20;
+const SQ_RING_ENTRIES: usize = 24;
+const CQ_RING_ENTRIES: usize = 28;
+const SQ_DROPPED: usize = 32;
+const SQ_FLAGS: usize = 36;
+const CQ_FLAGS: usize = 40;
+const CQ_OVERFLOW: usize = 44;
+const CQES: usize = 64;
+
+/// The parameters of `io_uring_setup`, i.e., `struct io_uring_params` in Linux.
+#[repr(C)]
+#[derive(Debug, Default, Clone, Copy, Pod)]
+pub struct IoUringParams {
+    pub sq_entries: u32,
+    pub cq_entries: u32,
+    pub flags: u32,
+    pub sq_thread_cpu: u32,
+    pub sq_thread_idle: u32,
+    pub features: u32,
+    pub wq_fd: u32,
+    pub resv: [u32; 3],
+    pub sq_off: SqringOffsets,
+    pub cq_off: CqringOffsets,
+}
+
+/// The offsets of the fields of the SQ ring in the rings region.
+#[repr(C)]
+#[derive(Debug, Default, Clone, Copy, Pod)]
+pub struct SqringOffsets {
+    pub head: u32,
+    pub tail: u32,
+    pub ring_mask: u32,
+    pub ring_entries: u32,
+    pub flags: u32,
+    pub dropped: u32,
+    pub array: u32,
+    pub resv1: u32,
+    pub user_addr: u64,
+}
+
+/// The offsets of the fields of the CQ ring in the rings region.
+#[repr(C)]
+#[derive(Debug, Default, Clone, Copy, Pod)]
+pub struct CqringOffsets {
+    pub head: u32,
+    pub tail: u32,
+    pub ring_mask: u32,
+    pub ring_entries: u32,
+    pub overflow: u32,
+    pub cqes: u32,
+    pub flags: u32,
+    pub resv1: u32,
+    pub user_addr: u64,
+}
+
+/// A submission queue entry, i.e., `struct io_uring_sqe` in Linux.
+#[repr(C)]
+#[derive(Debug, Clone, Copy, Pod)]
+pub(super) struct Sqe {
+    pub(super) opcode: u8,
+    pub(super) flags: u8,
+    pub(super) ioprio: u16,
+    pub(super) fd: i32,
+    /// The file offset, or the address of the address length for `IORING_OP_ACCEPT`.
+    pub(super) off: u64,
+    /// The address of the buffer or the path.
+    pub(super) addr: u64,
+    /// The length of the buffer, or the mode for `IORING_OP_OPENAT`.
+    pub(super) len: u32,
+    /// The flags specific to the opcode, e.g., the open flags or the message flags.
+    pub(super) op_flags: u32,
+    pub(super) user_data: u64,
+    /// The buffer group for `IOSQE_BUFFER_SELECT` and `IORING_OP_PROVIDE_BUFFERS`.
+    pub(super) buf_index: u16,
+    pub(super) personality: u16,
+    pub(super) file_index: i32,
+    pub(super) addr3: u64,
+    pub(super) pad: u64,
+}
+
+/// A completion queue entry, i.e., `struct io_uring_cqe` in Linux.
+#[repr(C)]
+#[derive(Debug, Clone, Copy, Pod)]
+pub(super) struct Cqe {
+    pub(super) user_data: u64,
+    pub(super) res: i32,
+    pub(super) flags: u32,
+}
+
+/// The rings of an io_uring instance.
+///
+/// The heads of the SQ ring and the tail of the CQ ring are owned by the kernel, while
+/// the others are owned by the user space and must not be trusted.
+pub(super) struct Rings {
+    shared_mem: Arc<SharedMem>,
+    sq_entries: u32,
+    cq_entries: u32,
+    sq_array_offset: usize,
+    /// The offset of the SQEs region, which is also the size of the rings region.
+    sqes_offset: usize,
+}
+
+impl Rings {
+    /// Allocates the rings with the number of entries, which must be powers of two.
+    pub(super) fn new(sq_entries: u32, cq_entries: u32) -> Result<Self> {
+        debug_assert!(sq_entries.is_power_of_two() && cq_entries.is_power_of_two());
+
+        let sq_array_offset = CQES + cq_entries as usize * size_of::<Cqe>();
+        let sqes_offset =
+            (sq_array_offset + sq_entries as usize * size_of::<u32>()).align_up(PAGE_SIZE);
+        let size = sqes_offset + (sq_entries as usize * size_of::<Sqe>()).align_up(PAGE_SIZE);
+
+        let shared_mem = SharedMem::new(size)?;
+        // The pages are committed in advance, so that accessing the rings never fails.
+        shared_mem.vmo().commit(0..size)?;
+
+        let rings = Self {
+            shared_mem,
+            sq_entries,
+            cq_entries,
+            sq_array_offset,
+            sqes_offset,
+        };
+        rings.write(SQ_RING_MASK, sq_entries - 1);
+        rings.write(CQ_RING_MASK, cq_entries - 1);
+        rings.write(SQ_RING_ENTRIES, sq_entries);
+        rings.write(CQ_RING_ENTRIES, cq_entries);
+        Ok(rings)
+    }
+
+    pub(super) fn shared_mem(&self) -> &Arc<SharedMem> {
+        &self.shared_mem
+    }
+
+    pub(super) fn sq_entries(&self) -> u32 {
+        self.sq_entries
+    }
+
+    pub(super) fn cq_entries(&self) -> u32 {
+        self.cq_entries
+    }
+
+    pub(super) fn sqes_offset(&self) -> usize {
+        self.sqes_offset
+    }
+
+    /// Fills the offsets of the fields of the rings in `params`.
+    pub(super) fn fill_offsets(&self, params: &mut IoUringParams) {
+        params.sq_entries = self.sq_entries;
+        params.cq_entries = self.cq_entries;
+        params.sq_off = SqringOffsets {
+            head: SQ_HEAD as u32,
+            tail: SQ_TAIL as u32,
+            ring_mask: SQ_RING_MASK as u32,
+            ring_entries: SQ_RING_ENTRIES as u32,
+            flags: SQ_FLAGS as u32,
+            dropped: SQ_DROPPED as u32,
+            array: self.sq_array_offset as u32,
+            ..Default::default()
+        };
+        params.cq_off = CqringOffsets {
+            head: CQ_HEAD as u32,
+            tail: CQ_TAIL as u32,
+            ring_mask: CQ_RING_MASK as u32,
+            ring_entries: CQ_RING_ENTRIES as u32,
+            overflow: CQ_OVERFLOW as u32,
+            cqes: CQES as u32,
+            flags: CQ_FLAGS as u32,
+            ..Default::default()
+        };
+    }
+
+    /// Returns the tail of the SQ ring written by the user space.
+    pub(super) fn sq_tail(&self) -> u32 {
+        let tail = self.read(SQ_TAIL);
+        // The SQEs must be read after the tail.
+        fence(Ordering::Acquire);
+        tail
+    }
+
+    /// Publishes the head of the SQ ring, i.e., the SQEs before it can be reused.
+    pub(super) fn set_sq_head(&self, head: u32) {
+        fence(Ordering::Release);
+        self.write(SQ_HEAD, head);
+    }
+
+    /// Returns the index of the SQE at the position of the SQ array.
+    pub(super) fn sq_index(&self, pos: u32) -> u32 {
+        let offset = self.sq_array_offset + (pos & (self.sq_entries - 1)) as usize * 4;
+        self.read(offset)
+    }
+
+    pub(super) fn read_sqe(&self, index: u32) -> Sqe {
+        debug_assert!(index < self.sq_entries);
+        let offset = self.sqes_offset + index as usize * size_of::<Sqe>();
+        self.shared_mem.vmo().read_val(offset).unwrap()
+    }
+
+    pub(super) fn inc_sq_dropped(&self) {
+        let dropped = self.read(SQ_DROPPED);
+        self.write(SQ_DROPPED, dropped.wrapping_add(1));
+    }
+
+    pub(super) fn set_cq_overflow_flag(&self, is_overflowed: bool) {
+        let flags = self.read(SQ_FLAGS);
+        let flags = if is_overflowed {
+            flags | IORING_SQ_CQ_OVERFLOW
+        } else {
+            flags & !IORING_SQ_CQ_OVERFLOW
+        };
+        self.write(SQ_FLAGS, flags);
+    }
+
+    /// Returns the head of the CQ ring written by the user space.
+    pub(super) fn cq_head(&self) -> u32 {
+        let head = self.read(CQ_HEAD);
+        // The CQEs must not be overwritten before the user space reads them.
+        fence(Ordering::Acquire);
+        head
+    }
+
+    pub(super) fn write_cqe(&self, pos: u32, cqe: &Cqe) {
+        let offset = CQES + (pos & (self.cq_entries - 1)) as usize * size_of::<Cqe>();
+        self.shared_mem.vmo().write_val(offset, cqe).unwrap();
+    }
+
+    /// Publishes the tail of the CQ ring, i.e., the CQEs before it can be read.
+    pub(super) fn set_cq_tail(&self, tail: u32) {
+        fence(Ordering::Release);
+        self.write(CQ_TAIL, tail);
+    }
+
+    fn read(&self, offset: usize) -> u32 {
+        // The pages have been committed, so reading the VMO never fails.
+        self.shared_mem.vmo().read_val(offset).unwrap()
+    }
+
+    fn write(&self, offset: usize, val: u32) {
+        self.shared_mem.vmo().write_val(offset, &val).unwrap();
+    }
+}
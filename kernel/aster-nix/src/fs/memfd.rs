@@ -0,0 +1,292 @@
+// SPDX-License-Identifier: MPL-2.0
+
+//! Anonymous memory files created by `memfd_create`.
+//!
+//! A memory file behaves like a regular file, but its content lives only in memory
+//! and is provided by a [`SharedMem`], so all the shared mappings of the file see
+//! the same pages. The file can be sealed with `fcntl(F_ADD_SEALS)` to prevent
+//! further modifications, which is checked when the file is written, resized or
+//! mapped. For more detailed information, refer to the man 2 memfd_create
+//! documentation.
+
+use core::sync::atomic::{AtomicU32, AtomicUsize, Ordering};
+
+use align_ext::AlignExt;
+use aster_frame::mm::VmIo;
+use aster_rights::Rights;
+
+use super::{
+    file_handle::{FileLike, MmapRegion},
+    utils::{AccessMode, InodeMode, InodeType, Metadata, SeekFrom, StatusFlags},
+};
+use crate::{
+    prelude::*,
+    process::{Gid, Uid},
+    time::clocks::RealTimeClock,
+    vm::{
+        perms::VmPerms,
+        vmar::SharedMem,
+        vmo::{Vmo, VmoFlags, VmoOptions},
+    },
+};
+
+/// The maximum length of the name of a memory file, excluding the terminating null byte.
+pub const MAX_MEMFD_NAME_LEN: usize = 249;
+
+bitflags! {
+    /// The seals of a memory file.
+    pub struct FileSeals: u32 {
+        /// Prevents further seals from being added.
+        const F_SEAL_SEAL = 0x0001;
+        /// Prevents the file from being shrunk.
+        const F_SEAL_SHRINK = 0x0002;
+        /// Prevents the file from being grown.
+        const F_SEAL_GROW = 0x0004;
+        /// Prevents the content of the file from being modified.
+        const F_SEAL_WRITE = 0x0008;
+    }
+}
+
+pub struct MemfdFile {
+    name: String,
+    shared_mem: Arc<SharedMem>,
+    offset: Mutex<usize>,
+    /// The seals of the file. The lock is also held when the file is written or
+    /// resized, so that no modification can sneak in after a seal is added.
+    seals: Mutex<FileSeals>,
+    /// The size of the file in bytes. The size of the VMO is page-aligned.
+    size: AtomicUsize,
+    status_flags: AtomicU32,
+}
+
+impl MemfdFile {
+    /// Creates an empty memory file.
+    ///
+    /// If `allow_sealing` is `false`, the file is sealed with `F_SEAL_SEAL`, so no
+    /// seals can be added.
+    pub fn new(name: String, allow_sealing: bool) -> Result<Self> {
+        let vmo = VmoOptions::<Rights>::new(0)
+            .flags(VmoFlags::RESIZABLE)
+            .alloc()?;
+        let seals = if allow_sealing {
+            FileSeals::empty()
+        } else {
+            FileSeals::F_SEAL_SEAL
+        };
+        Ok(Self {
+            name,
+            shared_mem: SharedMem::from_vmo(vmo),
+            offset: Mutex::new(0),
+            seals: Mutex::new(seals),
+            size: AtomicUsize::new(0),
+            status_flags: AtomicU32::new(0),
+        })
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    pub fn seals(&self) -> FileSeals {
+        *self.seals.lock()
+    }
+
+    /// Adds the seals to the file.
+    pub fn add_seals(&self, new_seals: FileSeals) -> Result<()> {
+        let mut seals = self.seals.lock();
+        if seals.contains(FileSeals::F_SEAL_SEAL) {
+            return_errno_with_message!(Errno::EPERM, "the file is sealed against sealing");
+        }
+        if new_seals.contains(FileSeals::F_SEAL_WRITE) && !seals.contains(FileSeals::F_SEAL_WRITE) {
+            // The check of the writable mappings and the revocation are atomic against
+            // the new mappings, so the existing mappings and the later ones cannot
+            // become writable.
+            self.shared_mem.try_restrict_perms(!VmPerms::WRITE)?;
+        }
+        *seals |= new_seals;
+        Ok(())
+    }
+
+    /// Returns the VMO that holds the content of the file.
+    pub fn vmo(&self) -> &Vmo<Rights> {
+        self.shared_mem.vmo()
+    }
+
+    fn size(&self) -> usize {
+        self.size.load(Ordering::Relaxed)
+    }
+
+    fn do_write_at(&self, offset: usize, buf: &[u8]) -> Result<usize> {
+        let seals = self.seals.lock();
+        if seals.contains(FileSeals::F_SEAL_WRITE) {
+            return_errno_with_message!(Errno::EPERM, "the file is sealed against writing");
+        }
+        let end = offset
+            .checked_add(buf.len())
+            .ok_or_else(|| Error::with_message(Errno::EFBIG, "the file is too large"))?;
+        if end > self.size() {
+            if seals.contains(FileSeals::F_SEAL_GROW) {
+                return_errno_with_message!(Errno::EPERM, "the file is sealed against growing");
+            }
+            self.vmo().resize(end)?;
+            self.size.store(end, Ordering::Relaxed);
+        }
+        self.vmo().write_bytes(offset, buf)?;
+        Ok(buf.len())
+    }
+
+    fn do_read_at(&self, offset: usize, buf: &mut [u8]) -> Result<usize> {
+        let size = self.size();
+        if offset >= size {
+            return Ok(0);
+        }
+        let len = buf.len().min(size - offset);
+        self.vmo().read_bytes(offset, &mut buf[..len])?;
+        Ok(len)
+    }
+}
+
+impl FileLike for MemfdFile {
+    fn read(&self, buf: &mut [u8]) -> Result<usize> {
+        let mut offset = self.offset.lock();
+        let len = self.do_read_at(*offset, buf)?;
+        *offset += len;
+        Ok(len)
+    }
+
+    fn write(&self, buf: &[u8]) -> Result<usize> {
+        let mut offset = self.offset.lock();
+        if self.status_flags().contains(StatusFlags::O_APPEND) {
+            *offset = self.size();
+        }
+        let len = self.do_write_at(*offset, buf)?;
+        *offset += len;
+        Ok(len)
+    }
+
+    fn read_at(&self, offset: usize, buf: &mut [u8]) -> Result<usize> {
+        self.do_read_at(offset, buf)
+    }
+
+    fn write_at(&self, offset: usize, buf: &[u8]) -> Result<usize> {
+        let offset = if self.status_flags().contains(StatusFlags::O_APPEND) {
+            self.size()
+        } else {
+            offset
+        };
+        self.do_write_at(offset, buf)
+    }
+
+    fn resize(&self, new_size: usize) -> Result<()> {
+        let seals = self.seals.lock();
+        let old_size = self.size();
+        if new_size < old_size && seals.contains(FileSeals::F_SEAL_SHRINK) {
+            return_errno_with_message!(Errno::EPERM, "the file is sealed against shrinking");
+        }
+        if new_size > old_size && seals.contains(FileSeals::F_SEAL_GROW) {
+            return_errno_with_message!(Errno::EPERM, "the file is sealed against growing");
+        }
+        self.vmo().resize(new_size)?;
+        self.size.store(new_size, Ordering::Relaxed);
+        if new_size < old_size {
+            // The truncated bytes in the last page are read as zeros if the file grows again.
+            let page_end = new_size.align_up(PAGE_SIZE).min(old_size);
+            self.vmo()
+                .write_bytes(new_size, &vec![0u8; page_end - new_size])?;
+            // The truncated pages should no longer be accessible through the mappings.
+            if page_end < old_size {
+                self.shared_mem.unmap_pages(page_end..old_size)?;
+            }
+        }
+        Ok(())
+    }
+
+    fn metadata(&self) -> Metadata {
+        let now = RealTimeClock::get().read_time();
+        let size = self.size();
+        Metadata {
+            dev: 0,
+            ino: 0,
+            size,
+            blk_size: PAGE_SIZE,
+            blocks: size.div_ceil(PAGE_SIZE),
+            atime: now,
+            mtime: now,
+            ctime: now,
+            type_: InodeType::File,
+            mode: InodeMode::from_bits_truncate(0o777),
+            nlinks: 1,
+            uid: Uid::new_root(),
+            gid: Gid::new_root(),
+            rdev: 0,
+        }
+    }
+
+    fn status_flags(&self) -> StatusFlags {
+        StatusFlags::from_bits_truncate(self.status_flags.load(Ordering::Relaxed))
+    }
+
+    fn set_status_flags(&self, new_flags: StatusFlags) -> Result<()> {
+        self.status_flags.store(new_flags.bits(), Ordering::Relaxed);
+        Ok(())
+    }
+
+    fn access_mode(&self) -> AccessMode {
+        AccessMode::O_RDWR
+    }
+
+    fn seek(&self, seek_from: SeekFrom) -> Result<usize> {
+        let mut offset = self.offset.lock();
+        let new_offset = match seek_from {
+            SeekFrom::Start(off) => {
+                if off > isize::MAX as usize {
+                    return_errno_with_message!(Errno::EINVAL, "file offset is too large");
+                }
+                off as isize
+            }
+            SeekFrom::End(off) => (self.size() as isize)
+                .checked_add(off)
+                .ok_or_else(|| Error::with_message(Errno::EOVERFLOW, "file offset overflow"))?,
+            SeekFrom::Current(off) => (*offset as isize)
+                .checked_add(off)
+                .ok_or_else(|| Error::with_message(Errno::EOVERFLOW, "file offset overflow"))?,
+            // The whole file is data.
+            SeekFrom::Data(off) | SeekFrom::Hole(off) if off >= self.size() => {
+                return_errno_with_message!(
+                    Errno::ENXIO,
+                    "the offset is beyond the end of the file"
+                );
+            }
+            SeekFrom::Data(off) => off as isize,
+            SeekFrom::Hole(_) => self.size() as isize,
+        };
+        if new_offset < 0 {
+            return_errno_with_message!(Errno::EINVAL, "file offset must not be negative");
+        }
+        *offset = new_offset as usize;
+        Ok(*offset)
+    }
+
+    fn mmap(
+        &self,
+        offset: usize,
+        len: usize,
+        is_shared: bool,
+        perms: VmPerms,
+    ) -> Result<Option<MmapRegion>> {
+        if !is_shared {
+            let vmo = self.vmo().dup()?;
+            return MmapRegion::from_vmo_cow(vmo, offset..(offset + len)).map(Some);
+        }
+        // The seal revokes the write permission of the shared memory, which is checked
+        // again when the mapping is registered. This check only fails early with the
+        // errno of Linux.
+        if perms.contains(VmPerms::WRITE) && self.seals().contains(FileSeals::F_SEAL_WRITE) {
+            return_errno_with_message!(Errno::EPERM, "the file is sealed against writing");
+        }
+        Ok(Some(MmapRegion::from_shared_mem(
+            self.shared_mem.clone(),
+            0,
+        )))
+    }
+}
@@ -1,44 +1,68 @@
 // SPDX-License-Identifier: MPL-2.0
+pub mod binfmt_misc;
 pub mod device;
 pub mod devpts;
 pub mod epoll;
+#[cfg(feature = "exfat")]
 pub mod exfat;
+#[cfg(feature = "ext2")]
 pub mod ext2;
 pub mod file_handle;
 pub mod file_table;
 pub mod fs_resolver;
 pub mod inode_handle;
+pub mod inotify;
+pub mod io_uring;
+pub mod memfd;
+#[cfg(feature = "overlayfs")]
+pub mod overlayfs;
 pub mod path;
 pub mod pipe;
 pub mod procfs;
 pub mod ramfs;
 pub mod rootfs;
+pub mod tracefs;
 pub mod utils;
+#[cfg(feature = "v9fs")]
+pub mod v9fs;
+#[cfg(feature = "vfat")]
+pub mod vfat;
+#[cfg(feature = "virtiofs")]
+pub mod virtiofs;
 
+#[cfg(any(feature = "ext2", feature = "exfat"))]
 use aster_block::BlockDevice;
+#[cfg(any(feature = "ext2", feature = "exfat"))]
 use aster_virtio::device::block::device::BlockDevice as VirtIoBlockDevice;
 
-use crate::{
-    fs::{
-        exfat::{ExfatFS, ExfatMountOptions},
-        ext2::Ext2,
-        fs_resolver::FsPath,
-    },
-    prelude::*,
-    thread::kernel_thread::KernelThreadExt,
-};
+#[cfg(feature = "exfat")]
+use crate::fs::exfat::{ExfatFS, ExfatMountOptions};
+#[cfg(feature = "ext2")]
+use crate::fs::ext2::Ext2;
+#[cfg(any(feature = "ext2", feature = "exfat"))]
+use crate::{fs::fs_resolver::FsPath, prelude::*, thread::kernel_thread::KernelThreadExt};
 
+#[cfg(any(feature = "ext2", feature = "exfat"))]
 fn start_block_device(device_name: &str) -> Result<Arc<dyn BlockDevice>> {
     if let Some(device) = aster_block::get_device(device_name) {
-        let cloned_device = device.clone();
-        let task_fn = move || {
-            info!("spawn the virt-io-block thread");
-            let virtio_block_device = cloned_device.downcast_ref::<VirtIoBlockDevice>().unwrap();
-            loop {
-                virtio_block_device.handle_requests();
+        // The other devices, e.g., NVMe namespaces and SATA disks, submit the bios to the
+        // hardware directly, so only the VirtIO block devices need a thread to handle the
+        // requests.
+        if let Some(virtio_block_device) = device.downcast_ref::<VirtIoBlockDevice>() {
+            // Each thread submits the requests to the virtqueue of the CPU it runs on.
+            for _ in 0..virtio_block_device.num_queues() {
+                let cloned_device = device.clone();
+                let task_fn = move || {
+                    info!("spawn the virt-io-block thread");
+                    let virtio_block_device =
+                        cloned_device.downcast_ref::<VirtIoBlockDevice>().unwrap();
+                    loop {
+                        virtio_block_device.handle_requests();
+                    }
+                };
+                crate::Thread::spawn_kernel_thread(crate::ThreadOptions::new(task_fn));
             }
-        };
-        crate::Thread::spawn_kernel_thread(crate::ThreadOptions::new(task_fn));
+        }
         Ok(device)
     } else {
         return_errno_with_message!(Errno::ENOENT, "Device does not exist")
@@ -46,18 +70,19 @@ fn start_block_device(device_name: &str) -> Result<Arc<dyn BlockDevice>> {
 }
 
 pub fn lazy_init() {
-    //The device name is specified in qemu args as --serial={device_name}
-    let ext2_device_name = "vext2";
-    let exfat_device_name = "vexfat";
+    utils::spawn_flusher_thread();
 
-    if let Ok(block_device_ext2) = start_block_device(ext2_device_name) {
+    //The device name is specified in qemu args as --serial={device_name}
+    #[cfg(feature = "ext2")]
+    if let Ok(block_device_ext2) = start_block_device("vext2") {
         let ext2_fs = Ext2::open(block_device_ext2).unwrap();
         let target_path = FsPath::try_from("/ext2").unwrap();
         println!("[kernel] Mount Ext2 fs at {:?} ", target_path);
         self::rootfs::mount_fs_at(ext2_fs, &target_path).unwrap();
     }
 
-    if let Ok(block_device_exfat) = start_block_device(exfat_device_name) {
+    #[cfg(feature = "exfat")]
+    if let Ok(block_device_exfat) = start_block_device("vexfat") {
         let exfat_fs = ExfatFS::open(block_device_exfat, ExfatMountOptions::default()).unwrap();
         let target_path = FsPath::try_from("/exfat").unwrap();
         println!("[kernel] Mount ExFat fs at {:?} ", target_path);
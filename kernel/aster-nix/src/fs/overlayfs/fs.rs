@@ -0,0 +1,177 @@
+// SPDX-License-Identifier: MPL-2.0
+
+use core::sync::atomic::{AtomicU64, Ordering};
+
+use super::inode::OverlayInode;
+use crate::{
+    fs::{
+        fs_resolver::{FsPath, AT_FDCWD},
+        utils::{FileSystem, FsFlags, Inode, InodeType, SuperBlock},
+    },
+    prelude::*,
+};
+
+/// The magic number reported by statfs (`OVERLAYFS_SUPER_MAGIC` in Linux).
+const OVERLAYFS_SUPER_MAGIC: u64 = 0x794c7630;
+
+/// A file system that overlays an upper directory onto one or more lower directories.
+///
+/// The lower layers are never modified. The files in them are copied up to the upper
+/// layer when they are modified, and the removals of them are recorded in the upper
+/// layer as whiteouts. If there is no upper layer, the file system is read-only.
+#[derive(Debug)]
+pub struct OverlayFS {
+    root: Arc<OverlayInode>,
+    /// The directory where the files are prepared before they are copied up, which is on
+    /// the same file system as the upper layer. It is `None` if there is no upper layer.
+    work: Option<Arc<dyn Inode>>,
+    next_tmp_id: AtomicU64,
+}
+
+impl OverlayFS {
+    /// Creates an overlay of the directories.
+    ///
+    /// The `lowers` are the lower layers, the top-most first. The `upper` is the upper
+    /// layer with the work directory, or `None` for a read-only overlay.
+    pub fn new(
+        lowers: Vec<Arc<dyn Inode>>,
+        upper: Option<(Arc<dyn Inode>, Arc<dyn Inode>)>,
+    ) -> Result<Arc<Self>> {
+        if lowers.is_empty() {
+            return_errno_with_message!(Errno::EINVAL, "no lower layer");
+        }
+        let dirs = lowers
+            .iter()
+            .chain(upper.iter().flat_map(|(upper, work)| [upper, work]));
+        for dir in dirs {
+            if dir.type_() != InodeType::Dir {
+                return_errno_with_message!(Errno::ENOTDIR, "a layer is not a directory");
+            }
+        }
+        let (upper, work) = match upper {
+            Some((upper, work)) => {
+                // The prepared files are renamed to the upper layer.
+                if !is_same_fs(&upper, &work) {
+                    return_errno_with_message!(
+                        Errno::EXDEV,
+                        "the upper and work directories are on different file systems"
+                    );
+                }
+                (Some(upper), Some(work))
+            }
+            None => (None, None),
+        };
+
+        Ok(Arc::new_cyclic(|weak_fs| Self {
+            root: OverlayInode::new_root(upper, lowers, weak_fs.clone()),
+            work,
+            next_tmp_id: AtomicU64::new(0),
+        }))
+    }
+
+    /// Creates an overlay of the directories in the mount options, which are resolved
+    /// by the current process.
+    pub fn open(options: &OverlayMountOptions) -> Result<Arc<Self>> {
+        let current = current!();
+        let lookup_dir = |path: &str| -> Result<Arc<dyn Inode>> {
+            let dentry = current.fs().read().lookup(&FsPath::new(AT_FDCWD, path)?)?;
+            Ok(dentry.inode().clone())
+        };
+
+        let lowers = options
+            .lowerdirs
+            .iter()
+            .map(|path| lookup_dir(path))
+            .collect::<Result<Vec<_>>>()?;
+        let upper = match (&options.upperdir, &options.workdir) {
+            (Some(upperdir), Some(workdir)) => Some((lookup_dir(upperdir)?, lookup_dir(workdir)?)),
+            (None, None) => None,
+            _ => return_errno_with_message!(
+                Errno::EINVAL,
+                "the upper and work directories must be given together"
+            ),
+        };
+        Self::new(lowers, upper)
+    }
+
+    /// Returns the work directory, or `EROFS` if there is no upper layer.
+    pub(super) fn work(&self) -> Result<&Arc<dyn Inode>> {
+        self.work
+            .as_ref()
+            .ok_or_else(|| Error::with_message(Errno::EROFS, "no upper layer"))
+    }
+
+    /// Allocates a name in the work directory for a file that is being copied up.
+    pub(super) fn alloc_tmp_name(&self) -> String {
+        format!("#{:x}", self.next_tmp_id.fetch_add(1, Ordering::Relaxed))
+    }
+}
+
+impl FileSystem for OverlayFS {
+    fn sync(&self) -> Result<()> {
+        match &self.work {
+            Some(work) => work.fs().sync(),
+            None => Ok(()),
+        }
+    }
+
+    fn root_inode(&self) -> Arc<dyn Inode> {
+        self.root.clone()
+    }
+
+    fn sb(&self) -> SuperBlock {
+        // The space is that of the upper layer, where the new data go.
+        let mut sb = self.root.real_inode().fs().sb();
+        sb.magic = OVERLAYFS_SUPER_MAGIC;
+        sb
+    }
+
+    fn flags(&self) -> FsFlags {
+        FsFlags::empty()
+    }
+}
+
+/// The mount options of overlayfs, e.g.,
+/// `lowerdir=/lower1:/lower2,upperdir=/upper,workdir=/work`.
+#[derive(Debug, Default)]
+pub struct OverlayMountOptions {
+    /// The paths of the lower layers, the top-most first.
+    lowerdirs: Vec<String>,
+    upperdir: Option<String>,
+    workdir: Option<String>,
+}
+
+impl OverlayMountOptions {
+    pub fn parse(options: &str) -> Result<Self> {
+        let mut mount_options = Self::default();
+        for option in options.split(',').filter(|option| !option.is_empty()) {
+            let (key, value) = option.split_once('=').unwrap_or((option, ""));
+            match key {
+                "lowerdir" => {
+                    mount_options.lowerdirs = value
+                        .split(':')
+                        .filter(|path| !path.is_empty())
+                        .map(String::from)
+                        .collect();
+                }
+                "upperdir" => mount_options.upperdir = Some(value.to_string()),
+                "workdir" => mount_options.workdir = Some(value.to_string()),
+                // The features that need extended attributes are not supported.
+                "redirect_dir" | "index" | "xino" | "metacopy" if value == "off" => {}
+                _ => return_errno_with_message!(Errno::EINVAL, "unknown overlay option"),
+            }
+        }
+        if mount_options.lowerdirs.is_empty() {
+            return_errno_with_message!(Errno::EINVAL, "no lower directory");
+        }
+        Ok(mount_options)
+    }
+}
+
+fn is_same_fs(this: &Arc<dyn Inode>, other: &Arc<dyn Inode>) -> bool {
+    // The pointers are compared without the metadata, which may differ for the same type.
+    core::ptr::eq(
+        Arc::as_ptr(&this.fs()) as *const (),
+        Arc::as_ptr(&other.fs()) as *const (),
+    )
+}
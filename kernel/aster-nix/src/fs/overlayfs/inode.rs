@@ -0,0 +1,714 @@
+// SPDX-License-Identifier: MPL-2.0
+
+use core::time::Duration;
+
+use aster_rights::Full;
+
+use super::fs::OverlayFS;
+use crate::{
+    events::IoEvents,
+    fs::{
+        device::Device,
+        utils::{AccessMode, DirentVisitor, FileSystem, Inode, InodeMode, InodeType, Metadata},
+    },
+    prelude::*,
+    process::{signal::Poller, Gid, Uid},
+    vm::vmo::Vmo,
+};
+
+/// The prefix of the name of a whiteout, which is an empty file named `.wh.<name>` that
+/// hides the file `<name>` in the lower layers.
+///
+/// The whiteouts and the opaque directories are marked by files as in the layers of the
+/// OCI images, since the layers may not support extended attributes.
+const WHITEOUT_PREFIX: &str = ".wh.";
+/// The name of the file that makes a directory opaque, i.e., hides the directories with
+/// the same path in the lower layers.
+const OPAQUE_MARKER: &str = ".wh..wh..opq";
+/// The size of the buffer to copy up the data of a file.
+const COPY_UP_BUF_SIZE: usize = 16 * PAGE_SIZE;
+
+/// An inode of overlayfs, which merges the inodes with the same path in the layers.
+pub struct OverlayInode {
+    type_: InodeType,
+    /// The inode in the upper layer, which is created by copy-up if the file is only in
+    /// the lower layers.
+    upper: Mutex<Option<Arc<dyn Inode>>>,
+    /// The inodes in the lower layers, the top-most first. Only the directories may have
+    /// more than one, whose entries are merged.
+    lowers: Vec<Arc<dyn Inode>>,
+    /// The parent directory and the name in it, which are `None` for the root.
+    parent: Mutex<Option<(Arc<OverlayInode>, String)>>,
+    /// The children that are in use, so that a file has the same inode in each lookup.
+    children: Mutex<BTreeMap<String, Weak<OverlayInode>>>,
+    this: Weak<OverlayInode>,
+    fs: Weak<OverlayFS>,
+}
+
+impl OverlayInode {
+    pub(super) fn new_root(
+        upper: Option<Arc<dyn Inode>>,
+        lowers: Vec<Arc<dyn Inode>>,
+        fs: Weak<OverlayFS>,
+    ) -> Arc<Self> {
+        Arc::new_cyclic(|weak_self| Self {
+            type_: InodeType::Dir,
+            upper: Mutex::new(upper),
+            lowers,
+            parent: Mutex::new(None),
+            children: Mutex::new(BTreeMap::new()),
+            this: weak_self.clone(),
+            fs,
+        })
+    }
+
+    fn this(&self) -> Arc<Self> {
+        self.this.upgrade().unwrap()
+    }
+
+    fn overlay_fs(&self) -> Arc<OverlayFS> {
+        self.fs.upgrade().unwrap()
+    }
+
+    fn upper(&self) -> Option<Arc<dyn Inode>> {
+        self.upper.lock().clone()
+    }
+
+    /// Returns the inode that the file is read from, i.e., the upper one if it exists.
+    pub(super) fn real_inode(&self) -> Arc<dyn Inode> {
+        self.upper().unwrap_or_else(|| self.lowers[0].clone())
+    }
+
+    /// Copies up the file to the upper layer if it is only in the lower layers, and
+    /// returns the inode in the upper layer.
+    ///
+    /// A file other than a directory is prepared in the work directory and then renamed
+    /// to the upper layer, so a partially copied file is never visible.
+    fn copy_up(&self) -> Result<Arc<dyn Inode>> {
+        let mut upper = self.upper.lock();
+        if let Some(upper) = upper.as_ref() {
+            return Ok(upper.clone());
+        }
+
+        let fs = self.overlay_fs();
+        let work = fs.work()?;
+        // The root always has an upper inode if there is an upper layer.
+        let (parent, name) = self.parent.lock().clone().unwrap();
+        let upper_dir = parent.copy_up()?;
+        let lower = &self.lowers[0];
+        let metadata = lower.metadata();
+
+        let new_upper = if self.type_ == InodeType::Dir {
+            let new_upper = upper_dir.create(&name, InodeType::Dir, metadata.mode)?;
+            copy_up_attrs(&new_upper, &metadata)?;
+            new_upper
+        } else {
+            let tmp_name = fs.alloc_tmp_name();
+            let tmp = match self.type_ {
+                InodeType::CharDevice | InodeType::BlockDevice => {
+                    let device = lower
+                        .as_device()
+                        .ok_or_else(|| Error::with_message(Errno::EPERM, "not a device"))?;
+                    work.mknod(&tmp_name, metadata.mode, device)?
+                }
+                type_ => work.create(&tmp_name, type_, metadata.mode)?,
+            };
+            let res = copy_up_data(lower, &tmp)
+                .and_then(|_| copy_up_attrs(&tmp, &metadata))
+                .and_then(|_| work.rename(&tmp_name, &upper_dir, &name));
+            if let Err(e) = res {
+                let _ = work.unlink(&tmp_name);
+                return Err(e);
+            }
+            tmp
+        };
+
+        *upper = Some(new_upper.clone());
+        Ok(new_upper)
+    }
+
+    /// Looks up the child, whose inode is kept as long as it is in use.
+    fn lookup_child(&self, name: &str) -> Result<Arc<Self>> {
+        if self.type_ != InodeType::Dir {
+            return_errno!(Errno::ENOTDIR);
+        }
+        if name.starts_with(WHITEOUT_PREFIX) {
+            return_errno_with_message!(Errno::ENOENT, "whiteouts are hidden");
+        }
+
+        let mut children = self.children.lock();
+        if let Some(child) = children.get(name).and_then(Weak::upgrade) {
+            return Ok(child);
+        }
+        let Some((upper, lowers)) = self.resolve_child(name)? else {
+            return_errno_with_message!(Errno::ENOENT, "the file does not exist");
+        };
+        let child = self.new_child(name, upper, lowers);
+        children.insert(name.to_string(), Arc::downgrade(&child));
+        Ok(child)
+    }
+
+    fn new_child(
+        &self,
+        name: &str,
+        upper: Option<Arc<dyn Inode>>,
+        lowers: Vec<Arc<dyn Inode>>,
+    ) -> Arc<Self> {
+        let type_ = upper.as_ref().unwrap_or_else(|| &lowers[0]).type_();
+        Arc::new_cyclic(|weak_self| Self {
+            type_,
+            upper: Mutex::new(upper),
+            lowers,
+            parent: Mutex::new(Some((self.this(), name.to_string()))),
+            children: Mutex::new(BTreeMap::new()),
+            this: weak_self.clone(),
+            fs: self.fs.clone(),
+        })
+    }
+
+    /// Finds the inodes of the child in the layers, from the top down.
+    ///
+    /// The search stops at a whiteout of the child, at a file other than a directory, or
+    /// at an opaque directory. The directories found before that are merged.
+    #[allow(clippy::type_complexity)]
+    fn resolve_child(
+        &self,
+        name: &str,
+    ) -> Result<Option<(Option<Arc<dyn Inode>>, Vec<Arc<dyn Inode>>)>> {
+        let whiteout_name = whiteout_name(name);
+        let mut type_ = None;
+
+        let mut upper_child = None;
+        if let Some(upper_dir) = self.upper() {
+            match lookup_entry(&upper_dir, name)? {
+                Some(child) => {
+                    if child.type_() != InodeType::Dir || is_opaque(&child)? {
+                        return Ok(Some((Some(child), Vec::new())));
+                    }
+                    type_ = Some(InodeType::Dir);
+                    upper_child = Some(child);
+                }
+                None if lookup_entry(&upper_dir, &whiteout_name)?.is_some() => return Ok(None),
+                None => {}
+            }
+        }
+
+        let mut lower_children = Vec::new();
+        for lower_dir in self.lowers.iter() {
+            let Some(child) = lookup_entry(lower_dir, name)? else {
+                if lookup_entry(lower_dir, &whiteout_name)?.is_some() {
+                    break;
+                }
+                continue;
+            };
+            let is_dir = child.type_() == InodeType::Dir;
+            match type_ {
+                None => type_ = Some(child.type_()),
+                Some(InodeType::Dir) if is_dir => {}
+                // A directory hides the files other than directories below it.
+                Some(_) => break,
+            }
+            let is_opaque = is_dir && is_opaque(&child)?;
+            lower_children.push(child);
+            if !is_dir || is_opaque {
+                break;
+            }
+        }
+
+        if upper_child.is_none() && lower_children.is_empty() {
+            return Ok(None);
+        }
+        Ok(Some((upper_child, lower_children)))
+    }
+
+    /// Returns the entries of the directory, which are merged from the layers.
+    ///
+    /// An entry in a layer hides the entries with the same name in the layers below, and
+    /// so does a whiteout.
+    fn merged_entries(&self) -> Result<Vec<DirEntry>> {
+        let upper = self.upper();
+        let layers = upper.iter().chain(self.lowers.iter());
+
+        let mut seen_names = BTreeSet::new();
+        let mut merged_entries = Vec::new();
+        for layer in layers {
+            let mut whiteout_names = Vec::new();
+            for entry in read_entries(layer)? {
+                if entry.name == OPAQUE_MARKER {
+                    continue;
+                }
+                if let Some(name) = entry.name.strip_prefix(WHITEOUT_PREFIX) {
+                    whiteout_names.push(name.to_string());
+                    continue;
+                }
+                if seen_names.insert(entry.name.clone()) {
+                    merged_entries.push(entry);
+                }
+            }
+            seen_names.extend(whiteout_names);
+        }
+        Ok(merged_entries)
+    }
+
+    /// Checks that the directory is empty in the merged view.
+    fn check_empty(&self) -> Result<()> {
+        let entries = self.merged_entries()?;
+        if entries
+            .iter()
+            .any(|entry| entry.name != "." && entry.name != "..")
+        {
+            return_errno_with_message!(Errno::ENOTEMPTY, "the directory is not empty");
+        }
+        Ok(())
+    }
+
+    /// Removes the whiteouts and the opaque marker in the upper inode of the directory,
+    /// which is empty in the merged view, so that it can be removed or replaced.
+    fn clear_whiteouts(&self) -> Result<()> {
+        let Some(upper) = self.upper() else {
+            return Ok(());
+        };
+        for entry in read_entries(&upper)? {
+            if entry.name.starts_with(WHITEOUT_PREFIX) {
+                upper.unlink(&entry.name)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Prepares the upper inode of the directory for a new child, and returns it with
+    /// whether the name is whited out.
+    fn prepare_new_child(&self, name: &str) -> Result<(Arc<dyn Inode>, bool)> {
+        if name.starts_with(WHITEOUT_PREFIX) {
+            return_errno_with_message!(Errno::EPERM, "the name is reserved for whiteouts");
+        }
+        match self.lookup_child(name) {
+            Ok(_) => return_errno_with_message!(Errno::EEXIST, "the file exists"),
+            Err(e) if e.error() == Errno::ENOENT => {}
+            Err(e) => return Err(e),
+        }
+        let upper_dir = self.copy_up()?;
+        let is_whited_out = lookup_entry(&upper_dir, &whiteout_name(name))?.is_some();
+        Ok((upper_dir, is_whited_out))
+    }
+
+    /// Records the new child that is created in the upper layer.
+    fn finish_new_child(
+        &self,
+        upper_dir: &Arc<dyn Inode>,
+        name: &str,
+        upper_child: Arc<dyn Inode>,
+        is_whited_out: bool,
+    ) -> Result<Arc<Self>> {
+        if is_whited_out {
+            // The new directory must not be merged with the removed one.
+            if upper_child.type_() == InodeType::Dir {
+                make_opaque(&upper_child)?;
+            }
+            upper_dir.unlink(&whiteout_name(name))?;
+        }
+        let child = self.new_child(name, Some(upper_child), Vec::new());
+        self.children
+            .lock()
+            .insert(name.to_string(), Arc::downgrade(&child));
+        Ok(child)
+    }
+
+    /// Removes the child, whose upper inode is removed by `remove_upper`, and whose lower
+    /// inodes are hidden by a whiteout.
+    fn remove_child(
+        &self,
+        name: &str,
+        child: &OverlayInode,
+        remove_upper: impl FnOnce(&Arc<dyn Inode>) -> Result<()>,
+    ) -> Result<()> {
+        let upper_dir = self.copy_up()?;
+        if child.upper().is_some() {
+            remove_upper(&upper_dir)?;
+        }
+        if self.is_lower_positive(name)? {
+            create_whiteout(&upper_dir, name)?;
+        }
+        self.children.lock().remove(name);
+        Ok(())
+    }
+
+    /// Checks whether the name exists in the lower layers of the directory.
+    ///
+    /// It may exist even if the child has no lower inodes, e.g., after a file is removed
+    /// and then created again in the upper layer.
+    fn is_lower_positive(&self, name: &str) -> Result<bool> {
+        for lower_dir in self.lowers.iter() {
+            if lookup_entry(lower_dir, name)?.is_some() {
+                return Ok(true);
+            }
+            if lookup_entry(lower_dir, &whiteout_name(name))?.is_some() {
+                return Ok(false);
+            }
+        }
+        Ok(false)
+    }
+}
+
+impl Inode for OverlayInode {
+    fn size(&self) -> usize {
+        self.real_inode().size()
+    }
+
+    fn resize(&self, new_size: usize) -> Result<()> {
+        self.copy_up()?.resize(new_size)
+    }
+
+    fn metadata(&self) -> Metadata {
+        let mut metadata = self.real_inode().metadata();
+        metadata.ino = self.ino();
+        metadata
+    }
+
+    fn ino(&self) -> u64 {
+        // The inode number of a file in the lower layers is kept after it is copied up.
+        match self.lowers.first() {
+            Some(lower) => lower.ino(),
+            None => self.real_inode().ino(),
+        }
+    }
+
+    fn type_(&self) -> InodeType {
+        self.type_
+    }
+
+    fn mode(&self) -> Result<InodeMode> {
+        self.real_inode().mode()
+    }
+
+    fn set_mode(&self, mode: InodeMode) -> Result<()> {
+        self.copy_up()?.set_mode(mode)
+    }
+
+    fn owner(&self) -> Result<Uid> {
+        self.real_inode().owner()
+    }
+
+    fn set_owner(&self, uid: Uid) -> Result<()> {
+        self.copy_up()?.set_owner(uid)
+    }
+
+    fn group(&self) -> Result<Gid> {
+        self.real_inode().group()
+    }
+
+    fn set_group(&self, gid: Gid) -> Result<()> {
+        self.copy_up()?.set_group(gid)
+    }
+
+    fn atime(&self) -> Duration {
+        self.real_inode().atime()
+    }
+
+    fn set_atime(&self, time: Duration) {
+        // Reading a file does not copy it up.
+        if let Some(upper) = self.upper() {
+            upper.set_atime(time);
+        }
+    }
+
+    fn mtime(&self) -> Duration {
+        self.real_inode().mtime()
+    }
+
+    fn set_mtime(&self, time: Duration) {
+        match self.copy_up() {
+            Ok(upper) => upper.set_mtime(time),
+            Err(e) => warn!("overlayfs: failed to copy up to set the mtime: {:?}", e),
+        }
+    }
+
+    fn open(&self, access_mode: AccessMode) -> Result<()> {
+        // The file is copied up before it is opened for writing, so that the writable
+        // shared mappings, which need a writable handle, write to the upper layer.
+        if access_mode.is_writable() && self.type_ == InodeType::File {
+            self.copy_up()?;
+        }
+        Ok(())
+    }
+
+    fn page_cache(&self) -> Option<Vmo<Full>> {
+        // The handles opened before the file is copied up keep reading the page cache of
+        // the file in the lower layer, like the read-only handles in Linux.
+        self.real_inode().page_cache()
+    }
+
+    fn read_at(&self, offset: usize, buf: &mut [u8]) -> Result<usize> {
+        self.real_inode().read_at(offset, buf)
+    }
+
+    fn read_direct_at(&self, offset: usize, buf: &mut [u8]) -> Result<usize> {
+        self.real_inode().read_direct_at(offset, buf)
+    }
+
+    fn write_at(&self, offset: usize, buf: &[u8]) -> Result<usize> {
+        self.copy_up()?.write_at(offset, buf)
+    }
+
+    fn write_direct_at(&self, offset: usize, buf: &[u8]) -> Result<usize> {
+        self.copy_up()?.write_direct_at(offset, buf)
+    }
+
+    fn create(&self, name: &str, type_: InodeType, mode: InodeMode) -> Result<Arc<dyn Inode>> {
+        let (upper_dir, is_whited_out) = self.prepare_new_child(name)?;
+        let upper_child = upper_dir.create(name, type_, mode)?;
+        Ok(self.finish_new_child(&upper_dir, name, upper_child, is_whited_out)?)
+    }
+
+    fn mknod(&self, name: &str, mode: InodeMode, dev: Arc<dyn Device>) -> Result<Arc<dyn Inode>> {
+        let (upper_dir, is_whited_out) = self.prepare_new_child(name)?;
+        let upper_child = upper_dir.mknod(name, mode, dev)?;
+        Ok(self.finish_new_child(&upper_dir, name, upper_child, is_whited_out)?)
+    }
+
+    fn as_device(&self) -> Option<Arc<dyn Device>> {
+        self.real_inode().as_device()
+    }
+
+    fn readdir_at(&self, offset: usize, visitor: &mut dyn DirentVisitor) -> Result<usize> {
+        if self.type_ != InodeType::Dir {
+            return_errno!(Errno::ENOTDIR);
+        }
+        let entries = self.merged_entries()?;
+
+        let mut iterate_offset = offset;
+        for entry in entries.iter().skip(offset) {
+            if let Err(e) = visitor.visit(&entry.name, entry.ino, entry.type_, iterate_offset + 1) {
+                if iterate_offset == offset {
+                    return Err(e);
+                }
+                break;
+            }
+            iterate_offset += 1;
+        }
+        Ok(iterate_offset - offset)
+    }
+
+    fn link(&self, old: &Arc<dyn Inode>, name: &str) -> Result<()> {
+        let old = old
+            .downcast_ref::<OverlayInode>()
+            .ok_or_else(|| Error::with_message(Errno::EXDEV, "not same fs"))?;
+        if !Weak::ptr_eq(&old.fs, &self.fs) {
+            return_errno_with_message!(Errno::EXDEV, "not same fs");
+        }
+        let old_upper = old.copy_up()?;
+        let (upper_dir, is_whited_out) = self.prepare_new_child(name)?;
+        upper_dir.link(&old_upper, name)?;
+        if is_whited_out {
+            upper_dir.unlink(&whiteout_name(name))?;
+        }
+        Ok(())
+    }
+
+    fn unlink(&self, name: &str) -> Result<()> {
+        let child = self.lookup_child(name)?;
+        if child.type_ == InodeType::Dir {
+            return_errno!(Errno::EISDIR);
+        }
+        self.remove_child(name, &child, |upper_dir| upper_dir.unlink(name))
+    }
+
+    fn rmdir(&self, name: &str) -> Result<()> {
+        let child = self.lookup_child(name)?;
+        if child.type_ != InodeType::Dir {
+            return_errno!(Errno::ENOTDIR);
+        }
+        child.check_empty()?;
+        child.clear_whiteouts()?;
+        self.remove_child(name, &child, |upper_dir| upper_dir.rmdir(name))
+    }
+
+    fn lookup(&self, name: &str) -> Result<Arc<dyn Inode>> {
+        Ok(self.lookup_child(name)?)
+    }
+
+    fn rename(&self, old_name: &str, target: &Arc<dyn Inode>, new_name: &str) -> Result<()> {
+        let target = target
+            .downcast_ref::<OverlayInode>()
+            .ok_or_else(|| Error::with_message(Errno::EXDEV, "not same fs"))?;
+        if !Weak::ptr_eq(&target.fs, &self.fs) {
+            return_errno_with_message!(Errno::EXDEV, "not same fs");
+        }
+        if new_name.starts_with(WHITEOUT_PREFIX) {
+            return_errno_with_message!(Errno::EPERM, "the name is reserved for whiteouts");
+        }
+
+        let child = self.lookup_child(old_name)?;
+        let is_dir = child.type_ == InodeType::Dir;
+        // The directories in the lower layers cannot be moved without copying up all the
+        // files in them. The callers, e.g., `mv`, fall back to copying on `EXDEV`.
+        if is_dir && !child.lowers.is_empty() {
+            return_errno_with_message!(Errno::EXDEV, "the directory is in a lower layer");
+        }
+        let replaced = match target.lookup_child(new_name) {
+            Ok(replaced) if Arc::ptr_eq(&replaced, &child) => return Ok(()),
+            Ok(replaced) => Some(replaced),
+            Err(e) if e.error() == Errno::ENOENT => None,
+            Err(e) => return Err(e),
+        };
+        if let Some(replaced) = &replaced {
+            match (is_dir, replaced.type_ == InodeType::Dir) {
+                (true, true) => {
+                    replaced.check_empty()?;
+                    replaced.clear_whiteouts()?;
+                }
+                (true, false) => return_errno!(Errno::ENOTDIR),
+                (false, true) => return_errno!(Errno::EISDIR),
+                (false, false) => {}
+            }
+        }
+
+        let child_upper = child.copy_up()?;
+        let old_upper_dir = self.copy_up()?;
+        let new_upper_dir = target.copy_up()?;
+        let is_whited_out = lookup_entry(&new_upper_dir, &whiteout_name(new_name))?.is_some();
+        let hides_lower = target.is_lower_positive(new_name)?;
+        let leaves_lower = self.is_lower_positive(old_name)?;
+        old_upper_dir.rename(old_name, &new_upper_dir, new_name)?;
+        if is_dir && hides_lower {
+            make_opaque(&child_upper)?;
+        }
+        if is_whited_out {
+            new_upper_dir.unlink(&whiteout_name(new_name))?;
+        }
+        if leaves_lower {
+            create_whiteout(&old_upper_dir, old_name)?;
+        }
+
+        self.children.lock().remove(old_name);
+        *child.parent.lock() = Some((target.this(), new_name.to_string()));
+        target
+            .children
+            .lock()
+            .insert(new_name.to_string(), Arc::downgrade(&child));
+        Ok(())
+    }
+
+    fn read_link(&self) -> Result<String> {
+        self.real_inode().read_link()
+    }
+
+    fn write_link(&self, target: &str) -> Result<()> {
+        self.copy_up()?.write_link(target)
+    }
+
+    fn sync(&self) -> Result<()> {
+        match self.upper() {
+            Some(upper) => upper.sync(),
+            None => Ok(()),
+        }
+    }
+
+    fn poll(&self, mask: IoEvents, poller: Option<&Poller>) -> IoEvents {
+        self.real_inode().poll(mask, poller)
+    }
+
+    fn fs(&self) -> Arc<dyn FileSystem> {
+        self.overlay_fs()
+    }
+}
+
+impl Debug for OverlayInode {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        f.debug_struct("OverlayInode")
+            .field("type_", &self.type_)
+            .field("has_upper", &self.upper().is_some())
+            .field("nr_lowers", &self.lowers.len())
+            .finish()
+    }
+}
+
+/// An entry of a directory.
+struct DirEntry {
+    name: String,
+    ino: u64,
+    type_: InodeType,
+}
+
+impl DirentVisitor for Vec<DirEntry> {
+    fn visit(&mut self, name: &str, ino: u64, type_: InodeType, _offset: usize) -> Result<()> {
+        self.push(DirEntry {
+            name: name.to_string(),
+            ino,
+            type_,
+        });
+        Ok(())
+    }
+}
+
+/// Reads all the entries of a directory in a layer.
+fn read_entries(dir: &Arc<dyn Inode>) -> Result<Vec<DirEntry>> {
+    let mut entries = Vec::new();
+    let mut offset = 0;
+    loop {
+        let nr_read = dir.readdir_at(offset, &mut entries)?;
+        if nr_read == 0 {
+            return Ok(entries);
+        }
+        offset += nr_read;
+    }
+}
+
+/// Looks up an entry of a directory in a layer, which is `None` if it does not exist.
+fn lookup_entry(dir: &Arc<dyn Inode>, name: &str) -> Result<Option<Arc<dyn Inode>>> {
+    match dir.lookup(name) {
+        Ok(inode) => Ok(Some(inode)),
+        Err(e) if e.error() == Errno::ENOENT => Ok(None),
+        Err(e) => Err(e),
+    }
+}
+
+fn whiteout_name(name: &str) -> String {
+    format!("{}{}", WHITEOUT_PREFIX, name)
+}
+
+fn create_whiteout(dir: &Arc<dyn Inode>, name: &str) -> Result<()> {
+    dir.create(&whiteout_name(name), InodeType::File, InodeMode::empty())?;
+    Ok(())
+}
+
+fn is_opaque(dir: &Arc<dyn Inode>) -> Result<bool> {
+    Ok(lookup_entry(dir, OPAQUE_MARKER)?.is_some())
+}
+
+fn make_opaque(dir: &Arc<dyn Inode>) -> Result<()> {
+    if !is_opaque(dir)? {
+        dir.create(OPAQUE_MARKER, InodeType::File, InodeMode::empty())?;
+    }
+    Ok(())
+}
+
+/// Copies the data of a file in a lower layer to the new file.
+fn copy_up_data(lower: &Arc<dyn Inode>, new: &Arc<dyn Inode>) -> Result<()> {
+    match lower.type_() {
+        InodeType::File => {
+            let mut buf = vec![0u8; COPY_UP_BUF_SIZE];
+            let mut offset = 0;
+            loop {
+                let read_len = lower.read_at(offset, &mut buf)?;
+                if read_len == 0 {
+                    return Ok(());
+                }
+                new.write_at(offset, &buf[..read_len])?;
+                offset += read_len;
+            }
+        }
+        InodeType::SymLink => new.write_link(&lower.read_link()?),
+        _ => Ok(()),
+    }
+}
+
+/// Copies the attributes of a file in a lower layer to the new file.
+fn copy_up_attrs(new: &Arc<dyn Inode>, metadata: &Metadata) -> Result<()> {
+    new.set_mode(metadata.mode)?;
+    new.set_owner(metadata.uid)?;
+    new.set_group(metadata.gid)?;
+    new.set_atime(metadata.atime);
+    new.set_mtime(metadata.mtime);
+    Ok(())
+}
@@ -0,0 +1,157 @@
+// SPDX-License-Identifier: MPL-2.0
+
+//! The overlay file system, which merges the directories of several layers into one.
+//!
+//! The lower layers are read-only, e.g., the layers of a container image, and the
+//! changes go to the upper layer. A file in the lower layers is copied up to the upper
+//! layer before it is modified, through the work directory on the same file system as
+//! the upper layer. Since there are no extended attributes, the removed files and the
+//! opaque directories are marked as in the OCI images: an empty file `.wh.<name>` hides
+//! `<name>` in the layers below, and a directory with the file `.wh..wh..opq` hides the
+//! directories with the same path below it.
+//!
+//! It is mounted with the layers in the options, the top-most lower layer first, e.g.,
+//! `mount -t overlay overlay -o lowerdir=/l1:/l2,upperdir=/u,workdir=/w /merged`.
+
+mod fs;
+mod inode;
+
+pub use fs::{OverlayFS, OverlayMountOptions};
+pub use inode::OverlayInode;
+
+#[cfg(ktest)]
+mod test {
+    use aster_frame::mm::VmIo;
+
+    use crate::{
+        fs::{
+            overlayfs::OverlayFS,
+            ramfs::RamFS,
+            utils::{AccessMode, FileSystem, Inode, InodeMode, InodeType},
+        },
+        prelude::*,
+    };
+
+    fn mode() -> InodeMode {
+        InodeMode::from_bits_truncate(0o755)
+    }
+
+    fn create_file(dir: &Arc<dyn Inode>, name: &str, data: &[u8]) -> Arc<dyn Inode> {
+        let file = dir.create(name, InodeType::File, mode()).unwrap();
+        file.write_at(0, data).unwrap();
+        file
+    }
+
+    fn read_file(file: &Arc<dyn Inode>) -> Vec<u8> {
+        let mut buf = vec![0u8; file.size()];
+        file.read_at(0, &mut buf).unwrap();
+        buf
+    }
+
+    fn list(dir: &Arc<dyn Inode>) -> Vec<String> {
+        let mut names = Vec::<String>::new();
+        dir.readdir_at(0, &mut names).unwrap();
+        names.retain(|name| name != "." && name != "..");
+        names.sort();
+        names
+    }
+
+    /// Returns the overlay, the lower layer and the upper layer.
+    fn new_overlay() -> (Arc<OverlayFS>, Arc<dyn Inode>, Arc<dyn Inode>) {
+        let lower = RamFS::new().root_inode();
+        let upper_fs = RamFS::new();
+        let upper = upper_fs
+            .root_inode()
+            .create("upper", InodeType::Dir, mode())
+            .unwrap();
+        let work = upper_fs
+            .root_inode()
+            .create("work", InodeType::Dir, mode())
+            .unwrap();
+        let overlay = OverlayFS::new(vec![lower.clone()], Some((upper.clone(), work))).unwrap();
+        (overlay, lower, upper)
+    }
+
+    #[ktest]
+    fn copy_up_on_write() {
+        let (overlay, lower, upper) = new_overlay();
+        let lower_dir = lower.create("dir", InodeType::Dir, mode()).unwrap();
+        create_file(&lower_dir, "file", b"lower");
+
+        let root = overlay.root_inode();
+        let file = root.lookup("dir").unwrap().lookup("file").unwrap();
+        assert_eq!(read_file(&file), b"lower");
+        assert!(upper.lookup("dir").is_err());
+
+        file.write_at(0, b"UPPER").unwrap();
+        assert_eq!(read_file(&file), b"UPPER");
+        assert_eq!(read_file(&lower_dir.lookup("file").unwrap()), b"lower");
+        let upper_file = upper.lookup("dir").unwrap().lookup("file").unwrap();
+        assert_eq!(read_file(&upper_file), b"UPPER");
+    }
+
+    #[ktest]
+    fn copy_up_on_open_for_write() {
+        let (overlay, lower, upper) = new_overlay();
+        create_file(&lower, "file", b"lower");
+
+        let file = overlay.root_inode().lookup("file").unwrap();
+        file.open(AccessMode::O_RDONLY).unwrap();
+        assert!(upper.lookup("file").is_err());
+
+        // The shared writable mappings write to the page cache of the upper file.
+        file.open(AccessMode::O_RDWR).unwrap();
+        let upper_file = upper.lookup("file").unwrap();
+        file.page_cache().unwrap().write_bytes(0, b"UPPER").unwrap();
+        assert_eq!(read_file(&upper_file), b"UPPER");
+        assert_eq!(read_file(&file), b"UPPER");
+        assert_eq!(read_file(&lower.lookup("file").unwrap()), b"lower");
+    }
+
+    #[ktest]
+    fn whiteouts() {
+        let (overlay, lower, upper) = new_overlay();
+        create_file(&lower, "file", b"lower");
+        let lower_dir = lower.create("dir", InodeType::Dir, mode()).unwrap();
+        create_file(&lower_dir, "old", b"");
+
+        let root = overlay.root_inode();
+        root.unlink("file").unwrap();
+        assert_eq!(root.lookup("file").unwrap_err().error(), Errno::ENOENT);
+        assert!(lower.lookup("file").is_ok());
+        assert!(upper.lookup(".wh.file").is_ok());
+
+        // The lower file stays hidden after a new file in its place is removed.
+        create_file(&root, "file", b"upper");
+        assert_eq!(read_file(&root.lookup("file").unwrap()), b"upper");
+        root.unlink("file").unwrap();
+        assert_eq!(root.lookup("file").unwrap_err().error(), Errno::ENOENT);
+
+        // A directory created in place of a removed one does not show the old entries.
+        assert_eq!(root.rmdir("dir").unwrap_err().error(), Errno::ENOTEMPTY);
+        root.lookup("dir").unwrap().unlink("old").unwrap();
+        root.rmdir("dir").unwrap();
+        let dir = root.create("dir", InodeType::Dir, mode()).unwrap();
+        assert!(list(&dir).is_empty());
+        assert!(upper.lookup(".wh.dir").is_err());
+    }
+
+    #[ktest]
+    fn merged_readdir() {
+        let lower2 = RamFS::new().root_inode();
+        create_file(&lower2, "a", b"lower2");
+        create_file(&lower2, "b", b"lower2");
+        let lower1 = RamFS::new().root_inode();
+        create_file(&lower1, "b", b"lower1");
+        create_file(&lower1, "c", b"lower1");
+        create_file(&lower1, ".wh.a", b"");
+        let overlay = OverlayFS::new(vec![lower1, lower2], None).unwrap();
+
+        let root = overlay.root_inode();
+        assert_eq!(list(&root), vec!["b", "c"]);
+        assert_eq!(read_file(&root.lookup("b").unwrap()), b"lower1");
+        // There is no upper layer, so the overlay is read-only.
+        let file = root.lookup("c").unwrap();
+        assert_eq!(file.write_at(0, b"x").unwrap_err().error(), Errno::EROFS);
+    }
+}
@@ -13,8 +13,11 @@ use inherit_methods_macro::inherit_methods;
 use crate::{
     fs::{
         device::Device,
+        inotify::{self, InotifyEvents},
         path::mount::MountNode,
-        utils::{FileSystem, Inode, InodeMode, InodeType, Metadata, NAME_MAX},
+        utils::{
+            posix_acl, FileSystem, Inode, InodeMode, InodeType, Metadata, Permission, NAME_MAX,
+        },
     },
     prelude::*,
     process::{Gid, Uid},
@@ -136,6 +139,35 @@ impl Dentry_ {
         self.name_and_parent.read().as_ref().is_none()
     }
 
+    /// Notifies the inotify watches on the inode and on the parent directory of the events.
+    pub fn notify(&self, events: InotifyEvents) {
+        if !inotify::has_watches() {
+            return;
+        }
+        let events = inotify_events_of(&self.inode, events);
+        inotify::notify_inode(&self.inode, events);
+        if let Some((name, parent)) = self.name_and_parent.read().as_ref() {
+            inotify::notify_child(&parent.inode, name, events, 0);
+        }
+    }
+
+    /// Gets the inode of the child to notify the inotify watches on it.
+    ///
+    /// The inode is only looked up if there are watches.
+    fn child_inode_to_notify(
+        &self,
+        child: Option<&Arc<Dentry_>>,
+        name: &str,
+    ) -> Option<Arc<dyn Inode>> {
+        if !inotify::has_watches() {
+            return None;
+        }
+        match child {
+            Some(child) => Some(child.inode.clone()),
+            None => self.inode.lookup(name).ok(),
+        }
+    }
+
     /// Create a Dentry_ by making inode.
     pub fn create(&self, name: &str, type_: InodeType, mode: InodeMode) -> Result<Arc<Self>> {
         if self.inode.type_() != InodeType::Dir {
@@ -148,6 +180,7 @@ impl Dentry_ {
 
         let child = {
             let inode = self.inode.create(name, type_, mode)?;
+            posix_acl::inherit(self.inode.as_ref(), inode.as_ref())?;
             let dentry = Self::new(
                 inode,
                 DentryOptions::Leaf((String::from(name), self.this())),
@@ -155,9 +188,29 @@ impl Dentry_ {
             children.insert_dentry(&dentry);
             dentry
         };
+        child.notify_created();
         Ok(child)
     }
 
+    /// Create a Dentry_ of an unnamed regular file in the directory.
+    ///
+    /// The Dentry_ is not a child of the directory, so it cannot be found by lookup.
+    pub fn create_tmpfile(&self, mode: InodeMode) -> Result<Arc<Self>> {
+        if self.inode.type_() != InodeType::Dir {
+            return_errno!(Errno::ENOTDIR);
+        }
+        let inode = self.inode.create_tmpfile(mode)?;
+        posix_acl::inherit(self.inode.as_ref(), inode.as_ref())?;
+        let name = format!("#{}", inode.ino());
+        Ok(Self::new(inode, DentryOptions::Leaf((name, self.this()))))
+    }
+
+    /// Set the mode of the inode, and update its access ACL accordingly.
+    pub fn set_mode(&self, mode: InodeMode) -> Result<()> {
+        self.inode.set_mode(mode)?;
+        posix_acl::chmod(self.inode.as_ref(), mode)
+    }
+
     /// Lookup a Dentry_ from DCACHE.
     pub fn lookup_via_cache(&self, name: &str) -> Option<Arc<Dentry_>> {
         let mut children = self.children.lock();
@@ -200,6 +253,7 @@ impl Dentry_ {
             children.insert_dentry(&dentry);
             dentry
         };
+        child.notify_created();
         Ok(child)
     }
 
@@ -219,18 +273,39 @@ impl Dentry_ {
             DentryOptions::Leaf((String::from(name), self.this())),
         );
         children.insert_dentry(&dentry);
+        inotify::notify_inode(old_inode, InotifyEvents::IN_ATTRIB);
+        dentry.notify_created();
         Ok(())
     }
 
+    /// Notifies the inotify watches on the parent directory that the Dentry_ is created.
+    fn notify_created(&self) {
+        if !inotify::has_watches() {
+            return;
+        }
+        if let Some((name, parent)) = self.name_and_parent.read().as_ref() {
+            inotify::notify_child(
+                &parent.inode,
+                name,
+                inotify_events_of(&self.inode, InotifyEvents::IN_CREATE),
+                0,
+            );
+        }
+    }
+
     /// Delete a Dentry_ by unlinking inode.
     pub fn unlink(&self, name: &str) -> Result<()> {
         if self.inode.type_() != InodeType::Dir {
             return_errno!(Errno::ENOTDIR);
         }
         let mut children = self.children.lock();
-        let _ = children.find_dentry_with_checking_mountpoint(name)?;
+        let child = children.find_dentry_with_checking_mountpoint(name)?;
+        let child_inode = self.child_inode_to_notify(child.as_ref(), name);
         self.inode.unlink(name)?;
         children.delete_dentry(name);
+        if let Some(child_inode) = child_inode {
+            inotify::notify_unlinked(&child_inode, Some((&self.inode, name)));
+        }
         Ok(())
     }
 
@@ -240,9 +315,13 @@ impl Dentry_ {
             return_errno!(Errno::ENOTDIR);
         }
         let mut children = self.children.lock();
-        let _ = children.find_dentry_with_checking_mountpoint(name)?;
+        let child = children.find_dentry_with_checking_mountpoint(name)?;
+        let child_inode = self.child_inode_to_notify(child.as_ref(), name);
         self.inode.rmdir(name)?;
         children.delete_dentry(name);
+        if let Some(child_inode) = child_inode {
+            inotify::notify_unlinked(&child_inode, Some((&self.inode, name)));
+        }
         Ok(())
     }
 
@@ -262,8 +341,11 @@ impl Dentry_ {
             }
             let mut children = self.children.lock();
             let old_dentry = children.find_dentry_with_checking_mountpoint(old_name)?;
-            let _ = children.find_dentry_with_checking_mountpoint(new_name)?;
+            let new_dentry = children.find_dentry_with_checking_mountpoint(new_name)?;
+            let moved_inode = self.child_inode_to_notify(old_dentry.as_ref(), old_name);
+            let replaced_inode = self.child_inode_to_notify(new_dentry.as_ref(), new_name);
             self.inode.rename(old_name, &self.inode, new_name)?;
+            self.notify_renamed(old_name, new_dir, new_name, moved_inode, replaced_inode);
             match old_dentry.as_ref() {
                 Some(dentry) => {
                     children.delete_dentry(old_name);
@@ -279,8 +361,11 @@ impl Dentry_ {
             let (mut self_children, mut new_dir_children) =
                 write_lock_children_on_two_dentries(self, new_dir);
             let old_dentry = self_children.find_dentry_with_checking_mountpoint(old_name)?;
-            let _ = new_dir_children.find_dentry_with_checking_mountpoint(new_name)?;
+            let new_dentry = new_dir_children.find_dentry_with_checking_mountpoint(new_name)?;
+            let moved_inode = self.child_inode_to_notify(old_dentry.as_ref(), old_name);
+            let replaced_inode = new_dir.child_inode_to_notify(new_dentry.as_ref(), new_name);
             self.inode.rename(old_name, &new_dir.inode, new_name)?;
+            self.notify_renamed(old_name, new_dir, new_name, moved_inode, replaced_inode);
             match old_dentry.as_ref() {
                 Some(dentry) => {
                     self_children.delete_dentry(old_name);
@@ -294,6 +379,52 @@ impl Dentry_ {
         }
         Ok(())
     }
+
+    /// Notifies the inotify watches of a rename.
+    ///
+    /// The two directories are notified with the same cookie, and the inode replaced by
+    /// the rename, if any, loses a link.
+    fn notify_renamed(
+        &self,
+        old_name: &str,
+        new_dir: &Arc<Self>,
+        new_name: &str,
+        moved_inode: Option<Arc<dyn Inode>>,
+        replaced_inode: Option<Arc<dyn Inode>>,
+    ) {
+        let Some(moved_inode) = moved_inode else {
+            return;
+        };
+        let cookie = inotify::new_cookie();
+        let events_of = |events| inotify_events_of(&moved_inode, events);
+        inotify::notify_child(
+            &self.inode,
+            old_name,
+            events_of(InotifyEvents::IN_MOVED_FROM),
+            cookie,
+        );
+        inotify::notify_child(
+            &new_dir.inode,
+            new_name,
+            events_of(InotifyEvents::IN_MOVED_TO),
+            cookie,
+        );
+        inotify::notify_inode(&moved_inode, events_of(InotifyEvents::IN_MOVE_SELF));
+        if let Some(replaced_inode) = replaced_inode
+            && !Arc::ptr_eq(&replaced_inode, &moved_inode)
+        {
+            inotify::notify_unlinked(&replaced_inode, None);
+        }
+    }
+}
+
+/// Adds `IN_ISDIR` to the inotify events if the inode is a directory.
+fn inotify_events_of(inode: &Arc<dyn Inode>, events: InotifyEvents) -> InotifyEvents {
+    if inode.type_() == InodeType::Dir {
+        events | InotifyEvents::IN_ISDIR
+    } else {
+        events
+    }
 }
 
 #[inherit_methods(from = "self.inode")]
@@ -303,7 +434,6 @@ impl Dentry_ {
     pub fn metadata(&self) -> Metadata;
     pub fn type_(&self) -> InodeType;
     pub fn mode(&self) -> Result<InodeMode>;
-    pub fn set_mode(&self, mode: InodeMode) -> Result<()>;
     pub fn size(&self) -> usize;
     pub fn resize(&self, size: usize) -> Result<()>;
     pub fn owner(&self) -> Result<Uid>;
@@ -451,6 +581,12 @@ impl Dentry {
         Ok(Self::new(self.mount_node.clone(), new_child_dentry.clone()))
     }
 
+    /// Create a new Dentry to represent an unnamed regular file in the directory.
+    pub fn new_tmpfile(&self, mode: InodeMode) -> Result<Arc<Self>> {
+        let tmpfile_dentry = self.inner.create_tmpfile(mode)?;
+        Ok(Self::new(self.mount_node.clone(), tmpfile_dentry))
+    }
+
     /// Internal constructor.
     fn new(mount_node: Arc<MountNode>, inner: Arc<Dentry_>) -> Arc<Self> {
         Arc::new_cyclic(|weak_self| Self {
@@ -465,9 +601,7 @@ impl Dentry {
         if self.inner.inode().type_() != InodeType::Dir {
             return_errno!(Errno::ENOTDIR);
         }
-        if !self.inner.inode().mode()?.is_executable() {
-            return_errno!(Errno::EACCES);
-        }
+        self.inner.inode().check_permission(Permission::MAY_EXEC)?;
         if name.len() > NAME_MAX {
             return_errno!(Errno::ENAMETOOLONG);
         }
@@ -600,21 +734,25 @@ impl Dentry {
 
     /// Unmount and return the mounted child mount.
     ///
+    /// If `is_lazy` is false, the mount must not be busy, i.e., have no submounts and not
+    /// be used by any open files or working directories other than this Dentry. Otherwise,
+    /// the mount is detached at once and cleaned up after it is no longer used.
+    ///
     /// Note that the root mount cannot be unmounted.
-    pub fn unmount(&self) -> Result<Arc<MountNode>> {
+    pub fn unmount(&self, is_lazy: bool) -> Result<Arc<MountNode>> {
         if !self.inner.is_root_of_mount() {
             return_errno_with_message!(Errno::EINVAL, "not mounted");
         }
 
-        let mount_node = self.mount_node.clone();
-        let Some(mountpoint_dentry) = mount_node.mountpoint_dentry() else {
+        let Some(mountpoint_dentry) = self.mount_node.mountpoint_dentry() else {
             return_errno_with_message!(Errno::EINVAL, "cannot umount root mount");
         };
 
-        let mountpoint_mount_node = mount_node.parent().unwrap().upgrade().unwrap();
+        let mountpoint_mount_node = self.mount_node.parent().unwrap().upgrade().unwrap();
         let mountpoint = Self::new(mountpoint_mount_node.clone(), mountpoint_dentry.clone());
 
-        let child_mount = mountpoint_mount_node.unmount(&mountpoint)?;
+        // This Dentry is the only reference to the mount held here.
+        let child_mount = mountpoint_mount_node.unmount(&mountpoint, 1, is_lazy)?;
         mountpoint_dentry.clear_mountpoint();
         Ok(child_mount)
     }
@@ -695,6 +833,7 @@ impl Dentry {
     pub fn set_mtime(&self, time: Duration);
     pub fn key(&self) -> DentryKey;
     pub fn inode(&self) -> &Arc<dyn Inode>;
+    pub fn notify(&self, events: InotifyEvents);
     pub fn is_root_of_mount(&self) -> bool;
     pub fn is_mountpoint(&self) -> bool;
 }
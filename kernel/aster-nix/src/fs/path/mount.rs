@@ -1,11 +1,17 @@
 // SPDX-License-Identifier: MPL-2.0
 
+use core::sync::atomic::{AtomicBool, Ordering};
+
 use crate::{
     fs::{
         path::dentry::{Dentry, DentryKey, Dentry_},
         utils::{FileSystem, InodeType},
     },
     prelude::*,
+    thread::work_queue::{
+        job::{self, ScopeExt, ScopedJobHandle},
+        WorkPriority,
+    },
 };
 
 /// The MountNode can form a mount tree to maintain the mount information.
@@ -17,10 +23,15 @@ pub struct MountNode {
     mountpoint_dentry: RwLock<Option<Arc<Dentry_>>>,
     /// The associated FS.
     fs: Arc<dyn FileSystem>,
+    /// The per-mount flags.
+    flags: RwLock<PerMountFlags>,
     /// The parent mount node.
     parent: RwLock<Option<Weak<MountNode>>>,
     /// Child mount nodes which are mounted on one dentry of self.
     children: Mutex<BTreeMap<DentryKey, Arc<Self>>>,
+    /// Whether the mount node is detached from the mount tree by a lazy unmount, which
+    /// makes the FS flushed when the mount node is no longer used.
+    is_lazily_unmounted: AtomicBool,
     /// Reference to self.
     this: Weak<Self>,
 }
@@ -52,6 +63,8 @@ impl MountNode {
             parent: RwLock::new(parent_mount),
             children: Mutex::new(BTreeMap::new()),
             fs,
+            flags: RwLock::new(PerMountFlags::empty()),
+            is_lazily_unmounted: AtomicBool::new(false),
             this: weak_self.clone(),
         })
     }
@@ -84,17 +97,65 @@ impl MountNode {
     /// Unmount a child mount node from the mountpoint and return it.
     ///
     /// The mountpoint should belong to this mount node, or an error is returned.
-    pub fn unmount(&self, mountpoint: &Dentry) -> Result<Arc<Self>> {
+    ///
+    /// Unless `is_lazy` is true, the child mount node must not be busy, i.e., have no
+    /// child mount nodes and no users other than the `nr_caller_refs` references held by
+    /// the caller, or `EBUSY` is returned. Its FS is flushed before it is detached.
+    ///
+    /// If `is_lazy` is true, the child mount node is detached at once along with its
+    /// descendants. They remain usable by the open files and the working directories in
+    /// them, and their FSes are flushed after the last user is gone.
+    pub fn unmount(
+        &self,
+        mountpoint: &Dentry,
+        nr_caller_refs: usize,
+        is_lazy: bool,
+    ) -> Result<Arc<Self>> {
         if !Arc::ptr_eq(mountpoint.mount_node(), &self.this()) {
             return_errno_with_message!(Errno::EINVAL, "mountpoint not belongs to this");
         }
 
-        let child_mount = self
-            .children
-            .lock()
-            .remove(&mountpoint.key())
+        let mut children = self.children.lock();
+        let child_mount = children
+            .get(&mountpoint.key())
             .ok_or_else(|| Error::with_message(Errno::ENOENT, "can not find child mount"))?;
-        Ok(child_mount)
+        if is_lazy {
+            child_mount.mark_lazily_unmounted();
+        } else {
+            // The map of children holds one more reference to the child mount node.
+            if Arc::strong_count(child_mount) > nr_caller_refs + 1
+                || !child_mount.children.lock().is_empty()
+            {
+                return_errno_with_message!(Errno::EBUSY, "the mount is busy");
+            }
+            child_mount.fs.sync()?;
+        }
+        Ok(children.remove(&mountpoint.key()).unwrap())
+    }
+
+    /// Unmounts all the descendant mount nodes and flushes the FSes at shutdown.
+    ///
+    /// A mount node is unmounted only after the mount nodes mounted on it, so an FS is
+    /// always flushed after the FSes that depend on it. The mount nodes are never busy
+    /// here, since no user is expected to run any more.
+    pub fn unmount_all(&self) {
+        let children = core::mem::take(&mut *self.children.lock());
+        for child_mount in children.into_values() {
+            child_mount.unmount_all();
+            if let Some(mountpoint_dentry) = child_mount.mountpoint_dentry() {
+                mountpoint_dentry.clear_mountpoint();
+            }
+        }
+        if let Err(err) = self.fs.sync() {
+            warn!("failed to flush {:?} at shutdown: {:?}", self.fs, err);
+        }
+    }
+
+    fn mark_lazily_unmounted(&self) {
+        self.is_lazily_unmounted.store(true, Ordering::Relaxed);
+        for child_mount in self.children.lock().values() {
+            child_mount.mark_lazily_unmounted();
+        }
     }
 
     /// Clone a mount node with the an root `Dentry_`.
@@ -108,6 +169,8 @@ impl MountNode {
             parent: RwLock::new(None),
             children: Mutex::new(BTreeMap::new()),
             fs: self.fs.clone(),
+            flags: RwLock::new(self.flags()),
+            is_lazily_unmounted: AtomicBool::new(false),
             this: weak_self.clone(),
         })
     }
@@ -218,15 +281,31 @@ impl MountNode {
     }
 
     /// Flushes all pending filesystem metadata and cached file data to the device.
+    ///
+    /// The FSes in the mount tree are synced concurrently in the work queues.
     pub fn sync(&self) -> Result<()> {
-        let children = self.children.lock();
-        for child in children.values() {
-            child.sync()?;
-        }
-        drop(children);
+        let mut fs_list = Vec::new();
+        self.collect_fs(&mut fs_list);
 
-        self.fs.sync()?;
-        Ok(())
+        let results = job::scope(|s| {
+            let handles: Vec<_> = fs_list
+                .iter()
+                .map(|fs| s.submit(move || fs.sync(), WorkPriority::Normal))
+                .collect();
+            handles
+                .into_iter()
+                .map(ScopedJobHandle::wait)
+                .collect::<Vec<_>>()
+        });
+        results.into_iter().collect()
+    }
+
+    /// Collects the FSes of this mount node and all its descendants.
+    fn collect_fs(&self, fs_list: &mut Vec<Arc<dyn FileSystem>>) {
+        fs_list.push(self.fs.clone());
+        for child in self.children.lock().values() {
+            child.collect_fs(fs_list);
+        }
     }
 
     /// Try to get the parent mount node.
@@ -252,6 +331,58 @@ impl MountNode {
     pub fn fs(&self) -> &Arc<dyn FileSystem> {
         &self.fs
     }
+
+    /// Get the per-mount flags.
+    pub fn flags(&self) -> PerMountFlags {
+        *self.flags.read()
+    }
+
+    /// Set the per-mount flags.
+    pub fn set_flags(&self, flags: PerMountFlags) {
+        *self.flags.write() = flags;
+    }
+}
+
+bitflags! {
+    /// The flags that are specific to a mount rather than to the mounted FS.
+    ///
+    /// The values are the same as the corresponding `MS_*` flags of the mount syscall.
+    pub struct PerMountFlags: u32 {
+        /// Mount read-only.
+        const RDONLY = 1 << 0;
+        /// Ignore suid and sgid bits.
+        const NOSUID = 1 << 1;
+        /// Disallow access to device special files.
+        const NODEV = 1 << 2;
+        /// Disallow program execution.
+        const NOEXEC = 1 << 3;
+        /// Do not follow symlinks.
+        const NOSYMFOLLOW = 1 << 8;
+        /// Do not update access times.
+        const NOATIME = 1 << 10;
+        /// Do not update directory access times.
+        const NODIRATIME = 1 << 11;
+        /// Update atime relative to mtime/ctime.
+        const RELATIME = 1 << 21;
+    }
+}
+
+impl Drop for MountNode {
+    fn drop(&mut self) {
+        if !self.is_lazily_unmounted.load(Ordering::Relaxed) {
+            return;
+        }
+
+        // Flush the FSes that depend on this one first.
+        let children = core::mem::take(&mut *self.children.lock());
+        drop(children);
+        if let Err(err) = self.fs.sync() {
+            warn!(
+                "failed to flush {:?} after lazy unmount: {:?}",
+                self.fs, err
+            );
+        }
+    }
 }
 
 impl Debug for MountNode {
@@ -260,6 +391,7 @@ impl Debug for MountNode {
             .field("root", &self.root_dentry)
             .field("mountpoint", &self.mountpoint_dentry)
             .field("fs", &self.fs)
+            .field("flags", &self.flags())
             .finish()
     }
 }
@@ -12,6 +12,9 @@ use crate::{
     process::{signal::Poller, Gid, Uid},
 };
 
+/// The maximum number of bytes moved by a single round of splicing.
+const MAX_SPLICE_LEN: usize = 16 * PAGE_SIZE;
+
 pub struct PipeReader {
     consumer: Consumer<u8>,
 }
@@ -20,6 +23,104 @@ impl PipeReader {
     pub fn new(consumer: Consumer<u8>) -> Self {
         Self { consumer }
     }
+
+    /// Moves at most `len` bytes from this pipe to the pipe of `writer`.
+    ///
+    /// The bytes never leave the kernel. The call blocks until some bytes can be moved,
+    /// unless `is_nonblocking` is true or either pipe is nonblocking.
+    pub fn splice_to(
+        &self,
+        writer: &PipeWriter,
+        len: usize,
+        is_nonblocking: bool,
+    ) -> Result<usize> {
+        self.transfer_to(writer, len, true, is_nonblocking)
+    }
+
+    /// Copies at most `len` bytes from this pipe to the pipe of `writer`, without
+    /// consuming them from this pipe.
+    pub fn tee_to(&self, writer: &PipeWriter, len: usize, is_nonblocking: bool) -> Result<usize> {
+        self.transfer_to(writer, len, false, is_nonblocking)
+    }
+
+    /// Moves at most `len` bytes from this pipe to `consume`, which is called with the
+    /// bytes at the head of this pipe and returns how many of them it takes.
+    ///
+    /// The bytes that are not taken stay in this pipe. The call blocks until this pipe
+    /// has some bytes, unless `is_nonblocking` is true or this pipe is nonblocking. If
+    /// the write end is closed and this pipe is empty, zero is returned.
+    pub fn splice_with(
+        &self,
+        len: usize,
+        is_nonblocking: bool,
+        consume: impl FnOnce(&[u8]) -> Result<usize>,
+    ) -> Result<usize> {
+        let is_nonblocking = is_nonblocking || self.consumer.is_nonblocking();
+        let mut buffer = vec![0u8; len.min(MAX_SPLICE_LEN)];
+        let peeked_len = wait_until(&[(self, IoEvents::IN)], is_nonblocking, || {
+            self.try_peek(&mut buffer)
+        })?;
+        if peeked_len == 0 {
+            return Ok(0);
+        }
+
+        let consumed_len = consume(&buffer[..peeked_len])?;
+        self.consumer.skip(consumed_len);
+        Ok(consumed_len)
+    }
+
+    fn transfer_to(
+        &self,
+        writer: &PipeWriter,
+        len: usize,
+        consumes: bool,
+        is_nonblocking: bool,
+    ) -> Result<usize> {
+        if writer.producer.is_paired_with(&self.consumer) {
+            return_errno_with_message!(Errno::EINVAL, "the pipes are the same");
+        }
+
+        let is_nonblocking =
+            is_nonblocking || self.consumer.is_nonblocking() || writer.producer.is_nonblocking();
+        let mut buffer = vec![0u8; len.min(MAX_SPLICE_LEN)];
+        wait_until(
+            &[(self, IoEvents::IN), (writer, IoEvents::OUT)],
+            is_nonblocking,
+            || {
+                if writer.producer.is_peer_shutdown() {
+                    return_errno_with_message!(Errno::EPIPE, "the pipe has no reader");
+                }
+                let max_len = buffer.len().min(writer.producer.free_len());
+                let peeked_len = self.try_peek(&mut buffer[..max_len])?;
+                if peeked_len == 0 {
+                    return Ok(0);
+                }
+                let written_len = writer.producer.try_write(&buffer[..peeked_len])?;
+                if consumes {
+                    self.consumer.skip(written_len);
+                }
+                Ok(written_len)
+            },
+        )
+    }
+
+    /// Peeks the bytes at the head of this pipe.
+    ///
+    /// This method returns zero if the write end is closed and this pipe is empty. It
+    /// fails with `EAGAIN` if no bytes are peeked otherwise, e.g., when `buf` is empty.
+    fn try_peek(&self, buf: &mut [u8]) -> Result<usize> {
+        // No more bytes can be written after the write end is closed, so the emptiness
+        // checked afterwards means the end of the pipe.
+        let is_peer_shutdown = self.consumer.is_peer_shutdown();
+        let peeked_len = self.consumer.peek(buf);
+        if peeked_len > 0 {
+            return Ok(peeked_len);
+        }
+        if is_peer_shutdown && self.consumer.is_empty() {
+            return Ok(0);
+        }
+        return_errno_with_message!(Errno::EAGAIN, "try peek later");
+    }
 }
 
 impl FileLike for PipeReader {
@@ -86,6 +187,40 @@ impl PipeWriter {
     pub fn new(producer: Producer<u8>) -> Self {
         Self { producer }
     }
+
+    /// Fills this pipe with at most `len` bytes from `produce`, which is called with a
+    /// buffer and returns how many bytes it fills.
+    ///
+    /// The buffer is never larger than the free space of this pipe. The call blocks
+    /// until this pipe has some free space, unless `is_nonblocking` is true or this pipe
+    /// is nonblocking.
+    pub fn splice_with(
+        &self,
+        len: usize,
+        is_nonblocking: bool,
+        produce: impl FnOnce(&mut [u8]) -> Result<usize>,
+    ) -> Result<usize> {
+        let is_nonblocking = is_nonblocking || self.producer.is_nonblocking();
+        let free_len = wait_until(&[(self, IoEvents::OUT)], is_nonblocking, || {
+            if self.producer.is_shutdown() || self.producer.is_peer_shutdown() {
+                return_errno_with_message!(Errno::EPIPE, "the pipe has no reader");
+            }
+            match self.producer.free_len() {
+                0 => return_errno_with_message!(Errno::EAGAIN, "the pipe is full"),
+                free_len => Ok(free_len),
+            }
+        })?;
+
+        let mut buffer = vec![0u8; len.min(free_len).min(MAX_SPLICE_LEN)];
+        let produced_len = produce(&mut buffer)?;
+        let mut written_len = 0;
+        // The bytes fit in this pipe unless the other writers take the free space first,
+        // in which case the rest of the bytes wait for the space to be released.
+        while written_len < produced_len {
+            written_len += self.producer.write(&buffer[written_len..produced_len])?;
+        }
+        Ok(produced_len)
+    }
 }
 
 impl FileLike for PipeWriter {
@@ -144,6 +279,32 @@ impl FileLike for PipeWriter {
     }
 }
 
+/// Calls `try_op` until it does not fail with `EAGAIN`, waiting for the events of the
+/// pipe ends in between.
+fn wait_until(
+    ends: &[(&dyn FileLike, IoEvents)],
+    is_nonblocking: bool,
+    mut try_op: impl FnMut() -> Result<usize>,
+) -> Result<usize> {
+    let poller = Poller::new();
+    loop {
+        let res = try_op();
+        if should_io_return(&res, is_nonblocking) {
+            return res;
+        }
+
+        let mut is_ready = true;
+        for (end, mask) in ends {
+            if end.poll(*mask, Some(&poller)).is_empty() {
+                is_ready = false;
+            }
+        }
+        if !is_ready {
+            poller.wait()?;
+        }
+    }
+}
+
 fn should_io_return(res: &Result<usize>, is_nonblocking: bool) -> bool {
     if is_nonblocking {
         return true;
@@ -0,0 +1,37 @@
+// SPDX-License-Identifier: MPL-2.0
+
+use core::fmt::Write;
+
+use super::template::{FileOps, ProcFileBuilder};
+use crate::{
+    config::{CONFIG_OPTIONS, CONFIG_VALUES},
+    fs::utils::Inode,
+    prelude::*,
+};
+
+/// Represents the inode at `/proc/config`, which lists the optional subsystems and the
+/// other options of the kernel configuration in the format of the Linux `.config` file.
+pub struct ConfigFileOps;
+
+impl ConfigFileOps {
+    pub fn new_inode(parent: Weak<dyn Inode>) -> Arc<dyn Inode> {
+        ProcFileBuilder::new(Self).parent(parent).build().unwrap()
+    }
+}
+
+impl FileOps for ConfigFileOps {
+    fn data(&self) -> Result<Vec<u8>> {
+        let mut output = String::new();
+        for option in CONFIG_OPTIONS {
+            if option.is_enabled {
+                let _ = writeln!(output, "{}=y", option.name);
+            } else {
+                let _ = writeln!(output, "# {} is not set", option.name);
+            }
+        }
+        for (name, value) in CONFIG_VALUES {
+            let _ = writeln!(output, "{}={:?}", name, value);
+        }
+        Ok(output.into_bytes())
+    }
+}
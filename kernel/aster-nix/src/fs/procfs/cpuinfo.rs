@@ -0,0 +1,34 @@
+// SPDX-License-Identifier: MPL-2.0
+
+use aster_frame::cpu::num_cpus;
+
+use super::template::{FileOps, ProcFileBuilder};
+use crate::{fs::utils::Inode, prelude::*};
+
+/// Represents the inode at `/proc/cpuinfo`.
+///
+/// There is an entry for each CPU, which is what the programs that size themselves by
+/// the number of CPUs count.
+pub struct CpuInfoFileOps;
+
+impl CpuInfoFileOps {
+    pub fn new_inode(parent: Weak<dyn Inode>) -> Arc<dyn Inode> {
+        ProcFileBuilder::new(Self).parent(parent).build().unwrap()
+    }
+}
+
+impl FileOps for CpuInfoFileOps {
+    fn data(&self) -> Result<Vec<u8>> {
+        // TODO: List only the CPUs in the cpuset of the caller once cgroups are supported.
+        let nr_cpus = num_cpus();
+        let mut cpuinfo_output = String::new();
+        for cpu in 0..nr_cpus {
+            cpuinfo_output.push_str(&format!("processor\t: {}\n", cpu));
+            cpuinfo_output.push_str("physical id\t: 0\n");
+            cpuinfo_output.push_str(&format!("siblings\t: {}\n", nr_cpus));
+            cpuinfo_output.push_str(&format!("core id\t\t: {}\n", cpu));
+            cpuinfo_output.push_str(&format!("cpu cores\t: {}\n\n", nr_cpus));
+        }
+        Ok(cpuinfo_output.into_bytes())
+    }
+}
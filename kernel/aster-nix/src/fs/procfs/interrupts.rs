@@ -0,0 +1,52 @@
+// SPDX-License-Identifier: MPL-2.0
+
+use aster_frame::{
+    cpu::num_cpus,
+    trap::{irq_count, routable_irqs},
+};
+
+use super::template::{FileOps, ProcFileBuilder};
+use crate::{fs::utils::Inode, prelude::*};
+
+/// Represents the inode at `/proc/interrupts`.
+///
+/// Each line gives the number of the interrupts of an IRQ that each CPU has handled. Only
+/// the IRQs that can be routed or have been handled are listed.
+pub struct InterruptsFileOps;
+
+impl InterruptsFileOps {
+    pub fn new_inode(parent: Weak<dyn Inode>) -> Arc<dyn Inode> {
+        ProcFileBuilder::new(Self).parent(parent).build().unwrap()
+    }
+}
+
+impl FileOps for InterruptsFileOps {
+    fn data(&self) -> Result<Vec<u8>> {
+        // The first 32 IRQ numbers are the CPU exceptions.
+        const FIRST_IRQ_NUM: u8 = 32;
+
+        let nr_cpus = num_cpus();
+        let routable_irqs = routable_irqs();
+
+        let mut output = String::from("     ");
+        for cpu_id in 0..nr_cpus {
+            output.push_str(&format!(" {:>10}", format!("CPU{}", cpu_id)));
+        }
+        output.push('\n');
+
+        for irq_num in FIRST_IRQ_NUM..=u8::MAX {
+            let counts: Vec<u64> = (0..nr_cpus)
+                .map(|cpu_id| irq_count(irq_num, cpu_id))
+                .collect();
+            if !routable_irqs.contains(&irq_num) && counts.iter().all(|count| *count == 0) {
+                continue;
+            }
+            output.push_str(&format!("{:>4}:", irq_num));
+            for count in counts {
+                output.push_str(&format!(" {:>10}", count));
+            }
+            output.push('\n');
+        }
+        Ok(output.into_bytes())
+    }
+}
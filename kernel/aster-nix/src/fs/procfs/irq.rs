@@ -0,0 +1,162 @@
+// SPDX-License-Identifier: MPL-2.0
+
+use aster_frame::{
+    cpu::{num_cpus, CpuSet},
+    trap::{irq_affinity, routable_irqs, set_irq_affinity},
+};
+
+use super::template::{DirOps, FileOps, ProcDir, ProcDirBuilder, ProcFileBuilder};
+use crate::{
+    fs::utils::{DirEntryVecExt, Inode, InodeMode},
+    prelude::*,
+};
+
+/// Represents the inode at `/proc/irq`.
+pub struct IrqDirOps;
+
+impl IrqDirOps {
+    pub fn new_inode(parent: Weak<dyn Inode>) -> Arc<dyn Inode> {
+        ProcDirBuilder::new(Self).parent(parent).build().unwrap()
+    }
+}
+
+impl DirOps for IrqDirOps {
+    fn lookup_child(&self, this_ptr: Weak<dyn Inode>, name: &str) -> Result<Arc<dyn Inode>> {
+        let Ok(irq_num) = name.parse::<u8>() else {
+            return_errno!(Errno::ENOENT);
+        };
+        if irq_affinity(irq_num).is_none() {
+            return_errno!(Errno::ENOENT);
+        }
+        Ok(IrqNumDirOps::new_inode(irq_num, this_ptr))
+    }
+
+    fn populate_children(&self, this_ptr: Weak<dyn Inode>) {
+        let this = {
+            let this = this_ptr.upgrade().unwrap();
+            this.downcast_ref::<ProcDir<IrqDirOps>>().unwrap().this()
+        };
+        let irqs = routable_irqs();
+        let mut cached_children = this.cached_children().write();
+        let stale_names: Vec<String> = cached_children
+            .iter()
+            .map(|(name, _)| name.clone())
+            .filter(|name| {
+                name.parse::<u8>()
+                    .map_or(true, |irq_num| !irqs.contains(&irq_num))
+            })
+            .collect();
+        for name in stale_names {
+            cached_children.remove_entry_by_name(&name);
+        }
+        for irq_num in irqs {
+            cached_children.put_entry_if_not_found(&irq_num.to_string(), || {
+                IrqNumDirOps::new_inode(irq_num, this_ptr.clone())
+            });
+        }
+    }
+}
+
+/// Represents the inode at `/proc/irq/[n]`.
+struct IrqNumDirOps(u8);
+
+impl IrqNumDirOps {
+    pub fn new_inode(irq_num: u8, parent: Weak<dyn Inode>) -> Arc<dyn Inode> {
+        ProcDirBuilder::new(Self(irq_num))
+            .parent(parent)
+            // The IRQ directories must be volatile, because the IRQ may be freed.
+            .volatile()
+            .build()
+            .unwrap()
+    }
+}
+
+impl DirOps for IrqNumDirOps {
+    fn lookup_child(&self, this_ptr: Weak<dyn Inode>, name: &str) -> Result<Arc<dyn Inode>> {
+        let inode = match name {
+            "smp_affinity" => SmpAffinityFileOps::new_inode(self.0, this_ptr),
+            _ => return_errno!(Errno::ENOENT),
+        };
+        Ok(inode)
+    }
+
+    fn populate_children(&self, this_ptr: Weak<dyn Inode>) {
+        let this = {
+            let this = this_ptr.upgrade().unwrap();
+            this.downcast_ref::<ProcDir<IrqNumDirOps>>().unwrap().this()
+        };
+        let mut cached_children = this.cached_children().write();
+        cached_children.put_entry_if_not_found("smp_affinity", || {
+            SmpAffinityFileOps::new_inode(self.0, this_ptr.clone())
+        });
+    }
+}
+
+/// Represents the inode at `/proc/irq/[n]/smp_affinity`.
+///
+/// The content is the CPUs that may handle the IRQ, as a hexadecimal bitmask.
+struct SmpAffinityFileOps(u8);
+
+impl SmpAffinityFileOps {
+    pub fn new_inode(irq_num: u8, parent: Weak<dyn Inode>) -> Arc<dyn Inode> {
+        ProcFileBuilder::new(Self(irq_num))
+            .parent(parent)
+            .mode(InodeMode::from_bits_truncate(0o644))
+            .build()
+            .unwrap()
+    }
+}
+
+impl FileOps for SmpAffinityFileOps {
+    fn data(&self) -> Result<Vec<u8>> {
+        let Some(affinity) = irq_affinity(self.0) else {
+            return_errno_with_message!(Errno::ENOENT, "the IRQ is freed");
+        };
+        Ok(format!("{}\n", format_cpu_mask(&affinity)).into_bytes())
+    }
+
+    fn write(&self, buf: &[u8]) -> Result<usize> {
+        let affinity = core::str::from_utf8(buf)
+            .ok()
+            .and_then(|str| parse_cpu_mask(str.trim()))
+            .ok_or_else(|| Error::with_message(Errno::EINVAL, "invalid CPU mask"))?;
+        set_irq_affinity(self.0, &affinity)?;
+        Ok(buf.len())
+    }
+}
+
+/// Formats the CPUs as a hexadecimal bitmask, whose 32-bit words are separated by commas.
+pub(super) fn format_cpu_mask(cpus: &CpuSet) -> String {
+    let nr_digits = (num_cpus() as usize).div_ceil(4);
+    let mut mask = String::new();
+    for digit_idx in (0..nr_digits).rev() {
+        let digit = (0..4)
+            .filter(|bit| cpus.contains((digit_idx * 4 + bit) as u32))
+            .fold(0, |digit, bit| digit | (1 << bit));
+        mask.push(char::from_digit(digit, 16).unwrap());
+        if digit_idx % 8 == 0 && digit_idx != 0 {
+            mask.push(',');
+        }
+    }
+    mask
+}
+
+/// Parses a hexadecimal bitmask of CPUs. The CPUs that do not exist are ignored.
+fn parse_cpu_mask(mask: &str) -> Option<CpuSet> {
+    if mask.is_empty() {
+        return None;
+    }
+
+    let mut cpus = CpuSet::new_empty();
+    let digits = mask.chars().rev().filter(|ch| *ch != ',');
+    for (digit_idx, ch) in digits.enumerate() {
+        let digit = ch.to_digit(16)?;
+        for bit in 0..4 {
+            let cpu = digit_idx * 4 + bit;
+            if digit & (1 << bit) != 0 && cpu < num_cpus() as usize {
+                cpus.add(cpu as u32);
+            }
+        }
+    }
+    Some(cpus)
+}
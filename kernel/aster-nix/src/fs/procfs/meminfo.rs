@@ -0,0 +1,64 @@
+// SPDX-License-Identifier: MPL-2.0
+
+use aster_frame::mm::{nr_free_frames, nr_heap_frames, nr_total_frames, PAGE_SIZE};
+
+use super::template::{FileOps, ProcFileBuilder};
+use crate::{
+    fs::utils::{nr_cached_pages, nr_dirty_pages, Inode},
+    prelude::*,
+    vm::swap::swap_areas_info,
+};
+
+/// Represents the inode at `/proc/meminfo`.
+pub struct MemInfoFileOps;
+
+impl MemInfoFileOps {
+    pub fn new_inode(parent: Weak<dyn Inode>) -> Arc<dyn Inode> {
+        ProcFileBuilder::new(Self).parent(parent).build().unwrap()
+    }
+}
+
+impl FileOps for MemInfoFileOps {
+    fn data(&self) -> Result<Vec<u8>> {
+        // TODO: Take the `memory.max` of the cgroup of the caller as the total memory
+        // once cgroups are supported.
+        let total = nr_total_frames();
+        let free = nr_free_frames();
+        let cached = nr_cached_pages();
+        let slab = nr_heap_frames();
+        // The page cache can be reclaimed under memory pressure, so it is available as well.
+        let available = (free + cached).min(total);
+        let (swap_total, swap_free) = swap_areas_info()
+            .iter()
+            .fold((0, 0), |(total, free), info| {
+                (total + info.nr_pages, free + info.nr_pages - info.nr_used)
+            });
+
+        let meminfo_output: String = [
+            ("MemTotal", total),
+            ("MemFree", free),
+            ("MemAvailable", available),
+            ("Buffers", 0),
+            ("Cached", cached),
+            ("SwapCached", 0),
+            ("Shmem", 0),
+            ("Slab", slab),
+            ("SReclaimable", 0),
+            ("SUnreclaim", slab),
+            ("SwapTotal", swap_total),
+            ("SwapFree", swap_free),
+            ("Dirty", nr_dirty_pages()),
+        ]
+        .into_iter()
+        .map(|(name, nr_pages)| {
+            format!(
+                "{:<16}{:>8} kB\n",
+                format!("{}:", name),
+                nr_pages * PAGE_SIZE / 1024
+            )
+        })
+        .collect();
+
+        Ok(meminfo_output.into_bytes())
+    }
+}
@@ -2,10 +2,21 @@
 
 use core::sync::atomic::{AtomicU64, Ordering};
 
+#[cfg(feature = "net")]
+use self::net::NetDirOps;
 use self::{
+    config::ConfigFileOps,
+    cpuinfo::CpuInfoFileOps,
+    interrupts::InterruptsFileOps,
+    irq::IrqDirOps,
+    meminfo::MemInfoFileOps,
     pid::PidDirOps,
     self_::SelfSymOps,
+    swaps::SwapsFileOps,
+    sys::SysDirOps,
     template::{DirOps, ProcDir, ProcDirBuilder, ProcSymBuilder, SymOps},
+    unsupported_syscalls::UnsupportedSyscallsFileOps,
+    uptime::UptimeFileOps,
 };
 use crate::{
     events::Observer,
@@ -14,14 +25,25 @@ use crate::{
     process::{process_table, process_table::PidEvent, Pid},
 };
 
+mod config;
+mod cpuinfo;
+mod interrupts;
+mod irq;
+mod meminfo;
+#[cfg(feature = "net")]
+mod net;
 mod pid;
 mod self_;
-mod template;
+mod swaps;
+mod sys;
+pub(in crate::fs) mod template;
+mod unsupported_syscalls;
+mod uptime;
 
 /// Magic number.
 const PROC_MAGIC: u64 = 0x9fa0;
 /// Root Inode ID.
-const PROC_ROOT_INO: u64 = 1;
+pub(in crate::fs) const PROC_ROOT_INO: u64 = 1;
 /// Block size.
 const BLOCK_SIZE: usize = 1024;
 
@@ -33,9 +55,18 @@ pub struct ProcFS {
 
 impl ProcFS {
     pub fn new() -> Arc<Self> {
+        Self::new_with_root(PROC_MAGIC, RootDirOps::new_inode)
+    }
+
+    /// Creates a file system whose inodes are built from the templates of procfs, e.g.,
+    /// tracefs. The root inode is created by `new_root`, with the ID `PROC_ROOT_INO`.
+    pub(in crate::fs) fn new_with_root(
+        magic: u64,
+        new_root: fn(Weak<ProcFS>) -> Arc<dyn Inode>,
+    ) -> Arc<Self> {
         Arc::new_cyclic(|weak_fs| Self {
-            sb: SuperBlock::new(PROC_MAGIC, BLOCK_SIZE, NAME_MAX),
-            root: RootDirOps::new_inode(weak_fs.clone()),
+            sb: SuperBlock::new(magic, BLOCK_SIZE, NAME_MAX),
+            root: new_root(weak_fs.clone()),
             inode_allocator: AtomicU64::new(PROC_ROOT_INO + 1),
         })
     }
@@ -89,8 +120,31 @@ impl Observer<PidEvent> for ProcDir<RootDirOps> {
 
 impl DirOps for RootDirOps {
     fn lookup_child(&self, this_ptr: Weak<dyn Inode>, name: &str) -> Result<Arc<dyn Inode>> {
+        #[cfg(feature = "net")]
+        if name == "net" {
+            return Ok(NetDirOps::new_inode(this_ptr));
+        }
+
         let child = if name == "self" {
             SelfSymOps::new_inode(this_ptr.clone())
+        } else if name == "config" {
+            ConfigFileOps::new_inode(this_ptr.clone())
+        } else if name == "cpuinfo" {
+            CpuInfoFileOps::new_inode(this_ptr.clone())
+        } else if name == "meminfo" {
+            MemInfoFileOps::new_inode(this_ptr.clone())
+        } else if name == "swaps" {
+            SwapsFileOps::new_inode(this_ptr.clone())
+        } else if name == "interrupts" {
+            InterruptsFileOps::new_inode(this_ptr.clone())
+        } else if name == "irq" {
+            IrqDirOps::new_inode(this_ptr.clone())
+        } else if name == "sys" {
+            SysDirOps::new_inode(this_ptr.clone())
+        } else if name == "unsupported_syscalls" {
+            UnsupportedSyscallsFileOps::new_inode(this_ptr.clone())
+        } else if name == "uptime" {
+            UptimeFileOps::new_inode(this_ptr.clone())
         } else if let Ok(pid) = name.parse::<Pid>() {
             let process_ref =
                 process_table::get_process(pid).ok_or_else(|| Error::new(Errno::ENOENT))?;
@@ -108,6 +162,26 @@ impl DirOps for RootDirOps {
         };
         let mut cached_children = this.cached_children().write();
         cached_children.put_entry_if_not_found("self", || SelfSymOps::new_inode(this_ptr.clone()));
+        cached_children
+            .put_entry_if_not_found("config", || ConfigFileOps::new_inode(this_ptr.clone()));
+        cached_children
+            .put_entry_if_not_found("cpuinfo", || CpuInfoFileOps::new_inode(this_ptr.clone()));
+        cached_children
+            .put_entry_if_not_found("meminfo", || MemInfoFileOps::new_inode(this_ptr.clone()));
+        cached_children
+            .put_entry_if_not_found("swaps", || SwapsFileOps::new_inode(this_ptr.clone()));
+        cached_children.put_entry_if_not_found("interrupts", || {
+            InterruptsFileOps::new_inode(this_ptr.clone())
+        });
+        cached_children.put_entry_if_not_found("irq", || IrqDirOps::new_inode(this_ptr.clone()));
+        #[cfg(feature = "net")]
+        cached_children.put_entry_if_not_found("net", || NetDirOps::new_inode(this_ptr.clone()));
+        cached_children.put_entry_if_not_found("sys", || SysDirOps::new_inode(this_ptr.clone()));
+        cached_children.put_entry_if_not_found("unsupported_syscalls", || {
+            UnsupportedSyscallsFileOps::new_inode(this_ptr.clone())
+        });
+        cached_children
+            .put_entry_if_not_found("uptime", || UptimeFileOps::new_inode(this_ptr.clone()));
 
         for process in process_table::process_table().iter() {
             let pid = process.pid().to_string();
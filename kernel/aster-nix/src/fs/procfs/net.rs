@@ -0,0 +1,223 @@
+// SPDX-License-Identifier: MPL-2.0
+
+use core::fmt::Write;
+
+use super::template::{DirOps, FileOps, ProcDir, ProcDirBuilder, ProcFileBuilder};
+use crate::{
+    fs::utils::{DirEntryVecExt, Inode, InodeMode},
+    net::{
+        iface::{start_pktgen, Iface},
+        IFACES,
+    },
+    prelude::*,
+};
+
+/// Represents the inode at `/proc/net`.
+pub struct NetDirOps;
+
+impl NetDirOps {
+    pub fn new_inode(parent: Weak<dyn Inode>) -> Arc<dyn Inode> {
+        ProcDirBuilder::new(Self).parent(parent).build().unwrap()
+    }
+}
+
+impl DirOps for NetDirOps {
+    fn lookup_child(&self, this_ptr: Weak<dyn Inode>, name: &str) -> Result<Arc<dyn Inode>> {
+        let inode = match name {
+            "pktgen" => PktgenDirOps::new_inode(this_ptr),
+            _ => return_errno!(Errno::ENOENT),
+        };
+        Ok(inode)
+    }
+
+    fn populate_children(&self, this_ptr: Weak<dyn Inode>) {
+        let this = {
+            let this = this_ptr.upgrade().unwrap();
+            this.downcast_ref::<ProcDir<NetDirOps>>().unwrap().this()
+        };
+        let mut cached_children = this.cached_children().write();
+        cached_children
+            .put_entry_if_not_found("pktgen", || PktgenDirOps::new_inode(this_ptr.clone()));
+    }
+}
+
+/// Represents the inode at `/proc/net/pktgen`.
+///
+/// The directory has a file for the packet generator of each iface, and the `pgctrl`
+/// file to start or stop the generators.
+struct PktgenDirOps;
+
+impl PktgenDirOps {
+    pub fn new_inode(parent: Weak<dyn Inode>) -> Arc<dyn Inode> {
+        ProcDirBuilder::new(Self).parent(parent).build().unwrap()
+    }
+}
+
+impl DirOps for PktgenDirOps {
+    fn lookup_child(&self, this_ptr: Weak<dyn Inode>, name: &str) -> Result<Arc<dyn Inode>> {
+        if name == "pgctrl" {
+            return Ok(PgctrlFileOps::new_inode(this_ptr));
+        }
+        let iface = find_iface(name)?;
+        Ok(PktgenIfaceFileOps::new_inode(iface, this_ptr))
+    }
+
+    fn populate_children(&self, this_ptr: Weak<dyn Inode>) {
+        let this = {
+            let this = this_ptr.upgrade().unwrap();
+            this.downcast_ref::<ProcDir<PktgenDirOps>>().unwrap().this()
+        };
+        let mut cached_children = this.cached_children().write();
+        cached_children
+            .put_entry_if_not_found("pgctrl", || PgctrlFileOps::new_inode(this_ptr.clone()));
+        for iface in ifaces() {
+            cached_children.put_entry_if_not_found(iface.name(), || {
+                PktgenIfaceFileOps::new_inode(iface.clone(), this_ptr.clone())
+            });
+        }
+    }
+}
+
+/// Represents the inode at `/proc/net/pktgen/pgctrl`.
+///
+/// Writing `start` or `stop` starts or stops the generators of all the ifaces, and
+/// writing `start <iface>` or `stop <iface>` does so for a single iface.
+struct PgctrlFileOps;
+
+impl PgctrlFileOps {
+    pub fn new_inode(parent: Weak<dyn Inode>) -> Arc<dyn Inode> {
+        ProcFileBuilder::new(Self)
+            .parent(parent)
+            .mode(InodeMode::from_bits_truncate(0o600))
+            .build()
+            .unwrap()
+    }
+}
+
+impl FileOps for PgctrlFileOps {
+    fn data(&self) -> Result<Vec<u8>> {
+        let mut output = String::new();
+        for iface in ifaces() {
+            let state = if iface.pktgen().is_running() {
+                "running"
+            } else {
+                "stopped"
+            };
+            let _ = writeln!(output, "{}: {}", iface.name(), state);
+        }
+        Ok(output.into_bytes())
+    }
+
+    fn write(&self, buf: &[u8]) -> Result<usize> {
+        let command = core::str::from_utf8(buf)
+            .map_err(|_| Error::with_message(Errno::EINVAL, "the command is not valid UTF-8"))?;
+        let mut words = command.split_whitespace();
+        let (Some(action), iface_name, None) = (words.next(), words.next(), words.next()) else {
+            return_errno_with_message!(Errno::EINVAL, "the command is not `start|stop [iface]`");
+        };
+        let ifaces = match iface_name {
+            Some(name) => vec![find_iface(name)?],
+            None => ifaces().to_vec(),
+        };
+
+        match action {
+            "start" => {
+                for iface in ifaces {
+                    start_pktgen(iface)?;
+                }
+            }
+            "stop" => {
+                for iface in ifaces {
+                    iface.pktgen().stop();
+                }
+            }
+            _ => return_errno_with_message!(Errno::EINVAL, "unknown action"),
+        }
+        Ok(buf.len())
+    }
+}
+
+/// Represents the inode at `/proc/net/pktgen/[iface]`.
+///
+/// Reading the file shows the configuration and the counters of the generator. Writing
+/// the file applies the configuration commands, one per line, e.g., `count 10000`.
+struct PktgenIfaceFileOps(Arc<dyn Iface>);
+
+impl PktgenIfaceFileOps {
+    pub fn new_inode(iface: Arc<dyn Iface>, parent: Weak<dyn Inode>) -> Arc<dyn Inode> {
+        ProcFileBuilder::new(Self(iface))
+            .parent(parent)
+            .mode(InodeMode::from_bits_truncate(0o600))
+            .build()
+            .unwrap()
+    }
+}
+
+impl FileOps for PktgenIfaceFileOps {
+    fn data(&self) -> Result<Vec<u8>> {
+        let pktgen = self.0.pktgen();
+        let config = pktgen.config();
+        let stats = pktgen.stats();
+
+        let mut output = String::new();
+        let _ = writeln!(
+            output,
+            "Params: count {}  rate {}  pkt_size {}",
+            config.count, config.rate, config.pkt_size
+        );
+        let _ = match config.src_ip {
+            Some(src_ip) => write!(output, "     src {}", src_ip),
+            None => write!(output, "     src (iface)"),
+        };
+        let _ = writeln!(
+            output,
+            "  dst {}  dst_mac {}",
+            config.dst_ip, config.dst_mac
+        );
+        let _ = writeln!(
+            output,
+            "     udp_src_port {}  udp_dst_port {}",
+            config.udp_src_port, config.udp_dst_port
+        );
+        let state = if pktgen.is_running() {
+            "running"
+        } else {
+            "stopped"
+        };
+        let _ = writeln!(output, "State: {}", state);
+
+        let elapsed_us = stats.elapsed.as_micros();
+        let pps = if elapsed_us == 0 {
+            0
+        } else {
+            stats.nr_generated as u128 * 1_000_000 / elapsed_us
+        };
+        let _ = writeln!(
+            output,
+            "Result: generated {}  dropped {}  elapsed {}us  {}pps",
+            stats.nr_generated, stats.nr_dropped, elapsed_us, pps
+        );
+        Ok(output.into_bytes())
+    }
+
+    fn write(&self, buf: &[u8]) -> Result<usize> {
+        let commands = core::str::from_utf8(buf)
+            .map_err(|_| Error::with_message(Errno::EINVAL, "the command is not valid UTF-8"))?;
+        for command in commands.lines().filter(|line| !line.trim().is_empty()) {
+            self.0.pktgen().apply(command)?;
+        }
+        Ok(buf.len())
+    }
+}
+
+fn ifaces() -> &'static [Arc<dyn Iface>] {
+    IFACES.get().map_or(&[], |ifaces| ifaces.as_slice())
+}
+
+fn find_iface(name: &str) -> Result<Arc<dyn Iface>> {
+    ifaces()
+        .iter()
+        .find(|iface| iface.name() == name)
+        .cloned()
+        .ok_or_else(|| Error::with_message(Errno::ENOENT, "the iface does not exist"))
+}
@@ -0,0 +1,58 @@
+// SPDX-License-Identifier: MPL-2.0
+
+use crate::{
+    fs::{
+        procfs::template::{FileOps, ProcFileBuilder},
+        utils::{Inode, InodeMode},
+    },
+    prelude::*,
+    process::{credentials, credentials::capabilities::CapSet},
+    vm::damon,
+    Process,
+};
+
+/// Represents the inode at `/proc/[pid]/damon_regions`.
+///
+/// Each line shows a region of the address space that is monitored by [`crate::vm::damon`],
+/// with the number of the accesses found in the last aggregation interval and the age of
+/// the region. Writing `1` or `0` starts or stops monitoring the process.
+pub struct DamonRegionsFileOps(Arc<Process>);
+
+impl DamonRegionsFileOps {
+    pub fn new_inode(process_ref: Arc<Process>, parent: Weak<dyn Inode>) -> Arc<dyn Inode> {
+        ProcFileBuilder::new(Self(process_ref))
+            .parent(parent)
+            .mode(InodeMode::from_bits_truncate(0o644))
+            .build()
+            .unwrap()
+    }
+}
+
+impl FileOps for DamonRegionsFileOps {
+    fn data(&self) -> Result<Vec<u8>> {
+        let mut data = String::new();
+        for region in damon::regions_of(&self.0).unwrap_or_default() {
+            data.push_str(&format!(
+                "{:x}-{:x} {} {}\n",
+                region.range().start,
+                region.range().end,
+                region.nr_accesses(),
+                region.age()
+            ));
+        }
+        Ok(data.into_bytes())
+    }
+
+    fn write(&self, buf: &[u8]) -> Result<usize> {
+        if !credentials().effective_capset().contains(CapSet::SYS_ADMIN) {
+            return_errno_with_message!(Errno::EPERM, "monitoring requires CAP_SYS_ADMIN");
+        }
+
+        match core::str::from_utf8(buf).map(|str| str.trim()) {
+            Ok("1") => damon::start_monitoring(&self.0),
+            Ok("0") => damon::stop_monitoring(&self.0),
+            _ => return_errno_with_message!(Errno::EINVAL, "invalid damon_regions command"),
+        }
+        Ok(buf.len())
+    }
+}
@@ -0,0 +1,90 @@
+// SPDX-License-Identifier: MPL-2.0
+
+use core::fmt::Write;
+
+use crate::{
+    fs::{
+        device::DeviceId,
+        procfs::template::{FileOps, ProcFileBuilder},
+        utils::Inode,
+    },
+    prelude::*,
+    vm::{perms::VmPerms, vmar::vm_mapping::VmMapping},
+    Process,
+};
+
+/// Represents the inode at `/proc/[pid]/maps`.
+///
+/// Each line describes a mapping of the process, with its address range, permissions,
+/// and the file that backs it, if any.
+pub struct MapsFileOps(Arc<Process>);
+
+impl MapsFileOps {
+    pub fn new_inode(process_ref: Arc<Process>, parent: Weak<dyn Inode>) -> Arc<dyn Inode> {
+        ProcFileBuilder::new(Self(process_ref))
+            .parent(parent)
+            .build()
+            .unwrap()
+    }
+}
+
+impl FileOps for MapsFileOps {
+    fn data(&self) -> Result<Vec<u8>> {
+        let mut maps_output = String::new();
+        for vm_mapping in self.0.root_vmar().mappings() {
+            write_mapping_line(&mut maps_output, &vm_mapping);
+        }
+        Ok(maps_output.into_bytes())
+    }
+}
+
+/// The column where the pathname of a mapping starts, the same as Linux.
+const PATHNAME_COLUMN: usize = 73;
+
+/// Writes the line that describes the mapping, in the format of `/proc/[pid]/maps`:
+///
+/// ```text
+/// 55d0f6a2e000-55d0f6a30000 r--p 00000000 08:01 1048602    /usr/bin/cat
+/// ```
+pub(super) fn write_mapping_line(output: &mut String, vm_mapping: &VmMapping) {
+    let range = vm_mapping.range();
+    let perms = vm_mapping.perms();
+    let perm_char = |perm, ch| if perms.contains(perm) { ch } else { '-' };
+    let line_start = output.len();
+    let _ = write!(
+        output,
+        "{:08x}-{:08x} {}{}{}{} ",
+        range.start,
+        range.end,
+        perm_char(VmPerms::READ, 'r'),
+        perm_char(VmPerms::WRITE, 'w'),
+        perm_char(VmPerms::EXEC, 'x'),
+        if vm_mapping.is_shared() { 's' } else { 'p' },
+    );
+
+    let pathname = if let Some((dentry, file_offset)) = vm_mapping.file() {
+        let metadata = dentry.inode().metadata();
+        let dev = DeviceId::from(metadata.dev);
+        let _ = write!(
+            output,
+            "{:08x} {:02x}:{:02x} {} ",
+            file_offset,
+            dev.major(),
+            dev.minor(),
+            metadata.ino
+        );
+        Some(dentry.abs_path())
+    } else {
+        let _ = write!(output, "{:08x} 00:00 0 ", 0);
+        vm_mapping.name().map(String::from)
+    };
+
+    if let Some(pathname) = pathname {
+        let line_len = output.len() - line_start;
+        if line_len < PATHNAME_COLUMN {
+            output.extend(core::iter::repeat(' ').take(PATHNAME_COLUMN - line_len));
+        }
+        output.push_str(&pathname);
+    }
+    output.push('\n');
+}
@@ -1,6 +1,10 @@
 // SPDX-License-Identifier: MPL-2.0
 
-use self::{cmdline::CmdlineFileOps, comm::CommFileOps, exe::ExeSymOps, fd::FdDirOps};
+use self::{
+    cmdline::CmdlineFileOps, comm::CommFileOps, damon_regions::DamonRegionsFileOps, exe::ExeSymOps,
+    fd::FdDirOps, maps::MapsFileOps, oom_score::OomScoreFileOps, oom_score_adj::OomScoreAdjFileOps,
+    smaps::SmapsFileOps, statm::StatmFileOps, status::StatusFileOps,
+};
 use super::template::{DirOps, ProcDir, ProcDirBuilder};
 use crate::{
     events::Observer,
@@ -14,8 +18,15 @@ use crate::{
 
 mod cmdline;
 mod comm;
+mod damon_regions;
 mod exe;
 mod fd;
+mod maps;
+mod oom_score;
+mod oom_score_adj;
+mod smaps;
+mod statm;
+mod status;
 
 /// Represents the inode at `/proc/[pid]`.
 pub struct PidDirOps(Arc<Process>);
@@ -51,6 +62,13 @@ impl DirOps for PidDirOps {
             "comm" => CommFileOps::new_inode(self.0.clone(), this_ptr.clone()),
             "fd" => FdDirOps::new_inode(self.0.clone(), this_ptr.clone()),
             "cmdline" => CmdlineFileOps::new_inode(self.0.clone(), this_ptr.clone()),
+            "status" => StatusFileOps::new_inode(self.0.clone(), this_ptr.clone()),
+            "statm" => StatmFileOps::new_inode(self.0.clone(), this_ptr.clone()),
+            "maps" => MapsFileOps::new_inode(self.0.clone(), this_ptr.clone()),
+            "smaps" => SmapsFileOps::new_inode(self.0.clone(), this_ptr.clone()),
+            "oom_score" => OomScoreFileOps::new_inode(self.0.clone(), this_ptr.clone()),
+            "oom_score_adj" => OomScoreAdjFileOps::new_inode(self.0.clone(), this_ptr.clone()),
+            "damon_regions" => DamonRegionsFileOps::new_inode(self.0.clone(), this_ptr.clone()),
             _ => return_errno!(Errno::ENOENT),
         };
         Ok(inode)
@@ -74,5 +92,26 @@ impl DirOps for PidDirOps {
         cached_children.put_entry_if_not_found("cmdline", || {
             CmdlineFileOps::new_inode(self.0.clone(), this_ptr.clone())
         });
+        cached_children.put_entry_if_not_found("status", || {
+            StatusFileOps::new_inode(self.0.clone(), this_ptr.clone())
+        });
+        cached_children.put_entry_if_not_found("statm", || {
+            StatmFileOps::new_inode(self.0.clone(), this_ptr.clone())
+        });
+        cached_children.put_entry_if_not_found("maps", || {
+            MapsFileOps::new_inode(self.0.clone(), this_ptr.clone())
+        });
+        cached_children.put_entry_if_not_found("smaps", || {
+            SmapsFileOps::new_inode(self.0.clone(), this_ptr.clone())
+        });
+        cached_children.put_entry_if_not_found("oom_score", || {
+            OomScoreFileOps::new_inode(self.0.clone(), this_ptr.clone())
+        });
+        cached_children.put_entry_if_not_found("oom_score_adj", || {
+            OomScoreAdjFileOps::new_inode(self.0.clone(), this_ptr.clone())
+        });
+        cached_children.put_entry_if_not_found("damon_regions", || {
+            DamonRegionsFileOps::new_inode(self.0.clone(), this_ptr.clone())
+        });
     }
 }
@@ -0,0 +1,31 @@
+// SPDX-License-Identifier: MPL-2.0
+
+use crate::{
+    fs::{
+        procfs::template::{FileOps, ProcFileBuilder},
+        utils::Inode,
+    },
+    prelude::*,
+    vm::oom::oom_score,
+    Process,
+};
+
+/// Represents the inode at `/proc/[pid]/oom_score`.
+///
+/// It reports the badness score of the process for the OOM killer, see [`crate::vm::oom`].
+pub struct OomScoreFileOps(Arc<Process>);
+
+impl OomScoreFileOps {
+    pub fn new_inode(process_ref: Arc<Process>, parent: Weak<dyn Inode>) -> Arc<dyn Inode> {
+        ProcFileBuilder::new(Self(process_ref))
+            .parent(parent)
+            .build()
+            .unwrap()
+    }
+}
+
+impl FileOps for OomScoreFileOps {
+    fn data(&self) -> Result<Vec<u8>> {
+        Ok(format!("{}\n", oom_score(&self.0)).into_bytes())
+    }
+}
@@ -0,0 +1,59 @@
+// SPDX-License-Identifier: MPL-2.0
+
+use core::sync::atomic::Ordering;
+
+use crate::{
+    fs::{
+        procfs::template::{FileOps, ProcFileBuilder},
+        utils::{Inode, InodeMode},
+    },
+    prelude::*,
+    process::{credentials, credentials::capabilities::CapSet},
+    vm::oom::{OOM_SCORE_ADJ_MAX, OOM_SCORE_ADJ_MIN},
+    Process,
+};
+
+/// Represents the inode at `/proc/[pid]/oom_score_adj`.
+///
+/// It adjusts the badness score of the process for the OOM killer, see [`crate::vm::oom`].
+pub struct OomScoreAdjFileOps(Arc<Process>);
+
+impl OomScoreAdjFileOps {
+    pub fn new_inode(process_ref: Arc<Process>, parent: Weak<dyn Inode>) -> Arc<dyn Inode> {
+        ProcFileBuilder::new(Self(process_ref))
+            .parent(parent)
+            .mode(InodeMode::from_bits_truncate(0o644))
+            .build()
+            .unwrap()
+    }
+}
+
+impl FileOps for OomScoreAdjFileOps {
+    fn data(&self) -> Result<Vec<u8>> {
+        let oom_score_adj = self.0.oom_score_adj().load(Ordering::Relaxed);
+        Ok(format!("{}\n", oom_score_adj).into_bytes())
+    }
+
+    fn write(&self, buf: &[u8]) -> Result<usize> {
+        let oom_score_adj = core::str::from_utf8(buf)
+            .ok()
+            .and_then(|str| str.trim().parse::<i16>().ok())
+            .filter(|adj| (OOM_SCORE_ADJ_MIN..=OOM_SCORE_ADJ_MAX).contains(adj))
+            .ok_or_else(|| Error::with_message(Errno::EINVAL, "invalid oom_score_adj"))?;
+
+        // Like Linux, lowering the value requires `CAP_SYS_RESOURCE`.
+        let old_oom_score_adj = self.0.oom_score_adj().load(Ordering::Relaxed);
+        if oom_score_adj < old_oom_score_adj
+            && !credentials()
+                .effective_capset()
+                .contains(CapSet::SYS_RESOURCE)
+        {
+            return_errno_with_message!(Errno::EACCES, "lowering oom_score_adj is not allowed");
+        }
+
+        self.0
+            .oom_score_adj()
+            .store(oom_score_adj, Ordering::Relaxed);
+        Ok(buf.len())
+    }
+}
@@ -0,0 +1,159 @@
+// SPDX-License-Identifier: MPL-2.0
+
+use core::fmt::Write;
+
+use aster_frame::{
+    cpu::has_pkeys,
+    mm::{PageFlags, VmQueryResult, VmSpace},
+};
+
+use super::maps::write_mapping_line;
+use crate::{
+    fs::{
+        procfs::template::{FileOps, ProcFileBuilder},
+        utils::Inode,
+    },
+    prelude::*,
+    vm::vmar::{
+        fault_stats::{FaultStats, FAULT_LATENCY_BOUNDS_US},
+        vm_mapping::VmMapping,
+    },
+    Process,
+};
+
+/// Represents the inode at `/proc/[pid]/smaps`.
+///
+/// For each mapping of the process, it reports the line in `/proc/[pid]/maps`,
+/// followed by the memory usage of the mapping, which is found by querying the
+/// resident pages in the page table, and the statistics of the page faults on it.
+pub struct SmapsFileOps(Arc<Process>);
+
+impl SmapsFileOps {
+    pub fn new_inode(process_ref: Arc<Process>, parent: Weak<dyn Inode>) -> Arc<dyn Inode> {
+        ProcFileBuilder::new(Self(process_ref))
+            .parent(parent)
+            .build()
+            .unwrap()
+    }
+}
+
+impl FileOps for SmapsFileOps {
+    fn data(&self) -> Result<Vec<u8>> {
+        let root_vmar = self.0.root_vmar();
+        let mut smaps_output = String::new();
+        for vm_mapping in root_vmar.mappings() {
+            write_mapping_line(&mut smaps_output, &vm_mapping);
+            let usage = MemoryUsage::of_mapping(&vm_mapping, root_vmar.vm_space())?;
+            usage.write_to(&mut smaps_output, &vm_mapping);
+        }
+        Ok(smaps_output.into_bytes())
+    }
+}
+
+/// The fixed-point shift of the proportional set size, which avoids losing the
+/// fractions of the pages that are shared.
+const PSS_SHIFT: u32 = 12;
+
+/// The memory usage of a mapping, in bytes.
+#[derive(Default)]
+struct MemoryUsage {
+    resident: usize,
+    /// The proportional set size, shifted left by [`PSS_SHIFT`].
+    pss: u64,
+    shared_clean: usize,
+    shared_dirty: usize,
+    private_clean: usize,
+    private_dirty: usize,
+    referenced: usize,
+}
+
+impl MemoryUsage {
+    fn of_mapping(vm_mapping: &VmMapping, vm_space: &VmSpace) -> Result<Self> {
+        let mut usage = Self::default();
+        for query_result in vm_space.query_range(&vm_mapping.range())? {
+            let VmQueryResult::Mapped { frame, prop, .. } = query_result else {
+                continue;
+            };
+            // The frame is referenced by the VMO that commits it, by the page tables
+            // that map it, and by the query itself, so the rest are the page tables.
+            // FIXME: The count is inexact, since a VMO may not commit the frames that
+            // its children map, and other kernel objects may hold the frame as well.
+            let nr_mappers = (frame.reference_count() as u64).saturating_sub(2).max(1);
+            let is_dirty = prop.flags.contains(PageFlags::DIRTY);
+
+            usage.resident += PAGE_SIZE;
+            usage.pss += ((PAGE_SIZE as u64) << PSS_SHIFT) / nr_mappers;
+            match (nr_mappers > 1, is_dirty) {
+                (true, false) => usage.shared_clean += PAGE_SIZE,
+                (true, true) => usage.shared_dirty += PAGE_SIZE,
+                (false, false) => usage.private_clean += PAGE_SIZE,
+                (false, true) => usage.private_dirty += PAGE_SIZE,
+            }
+            if prop.flags.contains(PageFlags::ACCESSED) {
+                usage.referenced += PAGE_SIZE;
+            }
+        }
+        Ok(usage)
+    }
+
+    fn write_to(&self, output: &mut String, vm_mapping: &VmMapping) {
+        let range = vm_mapping.range();
+        let pss = (self.pss >> PSS_SHIFT) as usize;
+        let anonymous = if !vm_mapping.is_shared() && vm_mapping.vmo().is_anonymous() {
+            self.resident
+        } else {
+            0
+        };
+        let locked = if vm_mapping.is_locked() { pss } else { 0 };
+
+        let fields = [
+            ("Size", range.len()),
+            ("KernelPageSize", PAGE_SIZE),
+            ("MMUPageSize", PAGE_SIZE),
+            ("Rss", self.resident),
+            ("Pss", pss),
+            ("Shared_Clean", self.shared_clean),
+            ("Shared_Dirty", self.shared_dirty),
+            ("Private_Clean", self.private_clean),
+            ("Private_Dirty", self.private_dirty),
+            ("Referenced", self.referenced),
+            ("Anonymous", anonymous),
+            ("Swap", vm_mapping.swapped_size(&range)),
+            ("Locked", locked),
+        ];
+        for (name, size) in fields {
+            let _ = writeln!(output, "{:<16}{:>8} kB", format!("{}:", name), size / 1024);
+        }
+        if has_pkeys() {
+            let _ = writeln!(output, "{:<16}{:>8}", "ProtectionKey:", vm_mapping.pkey());
+        }
+        write_fault_stats(output, vm_mapping.fault_stats());
+    }
+}
+
+/// Writes the page fault statistics of a mapping, which are not in Linux.
+///
+/// The histogram is of the pairs of the upper bound of a bucket in microseconds and the
+/// number of the faults in the bucket, e.g., `4:10` for ten faults in `[1us, 4us)`.
+fn write_fault_stats(output: &mut String, fault_stats: &FaultStats) {
+    let _ = writeln!(output, "{:<16}{:>8}", "Faults:", fault_stats.nr_faults());
+    let _ = writeln!(
+        output,
+        "{:<16}{:>8}",
+        "WriteFaults:",
+        fault_stats.nr_write_faults()
+    );
+    let _ = writeln!(
+        output,
+        "{:<16}{:>8} us",
+        "FaultLatency:",
+        fault_stats.avg_latency().as_micros()
+    );
+
+    let _ = write!(output, "{:<16}", "FaultLatencyHist:");
+    let histogram = fault_stats.latency_histogram();
+    for (bound, count) in FAULT_LATENCY_BOUNDS_US.iter().zip(histogram.iter()) {
+        let _ = write!(output, " {}:{}", bound, count);
+    }
+    let _ = writeln!(output, " inf:{}", histogram.last().unwrap());
+}
@@ -0,0 +1,44 @@
+// SPDX-License-Identifier: MPL-2.0
+
+use crate::{
+    fs::{
+        procfs::template::{FileOps, ProcFileBuilder},
+        utils::Inode,
+    },
+    prelude::*,
+    vm::vmar::RssType,
+    Process,
+};
+
+/// Represents the inode at `/proc/[pid]/statm`.
+///
+/// It reports the memory usage of the process in pages, see [`RssCounters`].
+///
+/// [`RssCounters`]: crate::vm::vmar::RssCounters
+pub struct StatmFileOps(Arc<Process>);
+
+impl StatmFileOps {
+    pub fn new_inode(process_ref: Arc<Process>, parent: Weak<dyn Inode>) -> Arc<dyn Inode> {
+        ProcFileBuilder::new(Self(process_ref))
+            .parent(parent)
+            .build()
+            .unwrap()
+    }
+}
+
+impl FileOps for StatmFileOps {
+    fn data(&self) -> Result<Vec<u8>> {
+        let root_vmar = self.0.root_vmar();
+        let size = {
+            let vmar_range = root_vmar.base()..root_vmar.base() + root_vmar.size();
+            root_vmar.mapped_size(&vmar_range) / PAGE_SIZE
+        };
+        let rss = root_vmar.rss();
+        let resident = rss.total();
+        let shared = rss.get(RssType::File) + rss.get(RssType::Shmem);
+
+        // FIXME: Report the sizes of the text and the data segments.
+        let statm_output = format!("{} {} {} {} {} {} {}\n", size, resident, shared, 0, 0, 0, 0);
+        Ok(statm_output.into_bytes())
+    }
+}
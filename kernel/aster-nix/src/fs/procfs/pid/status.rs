@@ -0,0 +1,105 @@
+// SPDX-License-Identifier: MPL-2.0
+
+use aster_frame::{cpu::CpuSet, mm::PAGE_SIZE};
+
+use crate::{
+    fs::{
+        procfs::{
+            irq::format_cpu_mask,
+            template::{FileOps, ProcFileBuilder},
+        },
+        utils::Inode,
+    },
+    prelude::*,
+    vm::vmar::RssType,
+    Process,
+};
+
+/// Represents the inode at `/proc/[pid]/status`.
+pub struct StatusFileOps(Arc<Process>);
+
+impl StatusFileOps {
+    pub fn new_inode(process_ref: Arc<Process>, parent: Weak<dyn Inode>) -> Arc<dyn Inode> {
+        ProcFileBuilder::new(Self(process_ref))
+            .parent(parent)
+            .build()
+            .unwrap()
+    }
+}
+
+impl FileOps for StatusFileOps {
+    fn data(&self) -> Result<Vec<u8>> {
+        let process = &self.0;
+
+        let name = {
+            let exe_path = process.executable_path();
+            let last_component = exe_path.rsplit('/').next().unwrap_or(&exe_path);
+            last_component
+                .chars()
+                .take(TASK_COMM_LEN - 1)
+                .collect::<String>()
+        };
+        // FIXME: Report sleeping and stopped processes.
+        let state = if process.is_zombie() {
+            "Z (zombie)"
+        } else {
+            "R (running)"
+        };
+        let ppid = process.parent().map_or(0, |parent| parent.pid());
+        let nr_threads = process.threads().lock().len();
+        let root_vmar = process.root_vmar();
+        let vmar_range = root_vmar.base()..root_vmar.base() + root_vmar.size();
+        let size_kb = root_vmar.mapped_size(&vmar_range) / 1024;
+        let locked_kb = root_vmar.locked_size(&vmar_range) / 1024;
+        let swap_kb = root_vmar.swapped_size(&vmar_range) / 1024;
+        let rss = root_vmar.rss();
+        let rss_kb = |nr_pages: usize| nr_pages * PAGE_SIZE / 1024;
+        let cpus_allowed = process
+            .main_thread()
+            .map_or_else(CpuSet::new_full, |thread| thread.cpu_affinity());
+
+        let status_output = format!(
+            "Name:\t{}\nState:\t{}\nTgid:\t{}\nPid:\t{}\nPPid:\t{}\nVmSize:\t{:>8} kB\nVmLck:\t{:>8} kB\nVmRSS:\t{:>8} kB\nRssAnon:\t{:>8} kB\nRssFile:\t{:>8} kB\nRssShmem:\t{:>8} kB\nVmSwap:\t{:>8} kB\nThreads:\t{}\nCpus_allowed:\t{}\nCpus_allowed_list:\t{}\n",
+            name,
+            state,
+            process.pid(),
+            process.pid(),
+            ppid,
+            size_kb,
+            locked_kb,
+            rss_kb(rss.total()),
+            rss_kb(rss.get(RssType::Anon)),
+            rss_kb(rss.get(RssType::File)),
+            rss_kb(rss.get(RssType::Shmem)),
+            swap_kb,
+            nr_threads,
+            format_cpu_mask(&cpus_allowed),
+            format_cpu_list(&cpus_allowed)
+        );
+        Ok(status_output.into_bytes())
+    }
+}
+
+const TASK_COMM_LEN: usize = 16;
+
+/// Formats the CPUs as a list of ranges, e.g., `0-3,6`.
+fn format_cpu_list(cpus: &CpuSet) -> String {
+    let mut ranges: Vec<(usize, usize)> = Vec::new();
+    for cpu in cpus.iter() {
+        match ranges.last_mut() {
+            Some((_, end)) if *end + 1 == cpu => *end = cpu,
+            _ => ranges.push((cpu, cpu)),
+        }
+    }
+    ranges
+        .iter()
+        .map(|&(start, end)| {
+            if start == end {
+                format!("{}", start)
+            } else {
+                format!("{}-{}", start, end)
+            }
+        })
+        .collect::<Vec<_>>()
+        .join(",")
+}
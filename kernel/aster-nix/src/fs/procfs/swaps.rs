@@ -0,0 +1,32 @@
+// SPDX-License-Identifier: MPL-2.0
+
+use aster_frame::mm::PAGE_SIZE;
+
+use super::template::{FileOps, ProcFileBuilder};
+use crate::{fs::utils::Inode, prelude::*, vm::swap::swap_areas_info};
+
+/// Represents the inode at `/proc/swaps`.
+pub struct SwapsFileOps;
+
+impl SwapsFileOps {
+    pub fn new_inode(parent: Weak<dyn Inode>) -> Arc<dyn Inode> {
+        ProcFileBuilder::new(Self).parent(parent).build().unwrap()
+    }
+}
+
+impl FileOps for SwapsFileOps {
+    fn data(&self) -> Result<Vec<u8>> {
+        let mut swaps_output = String::from("Filename\t\t\t\tType\t\tSize\t\tUsed\t\tPriority\n");
+        for info in swap_areas_info() {
+            swaps_output.push_str(&format!(
+                "{:<40}{}\t{}\t\t{}\t\t{}\n",
+                info.path,
+                info.type_name,
+                info.nr_pages * PAGE_SIZE / 1024,
+                info.nr_used * PAGE_SIZE / 1024,
+                info.priority
+            ));
+        }
+        Ok(swaps_output.into_bytes())
+    }
+}
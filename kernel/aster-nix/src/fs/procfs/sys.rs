@@ -0,0 +1,363 @@
+// SPDX-License-Identifier: MPL-2.0
+
+use core::time::Duration;
+
+use aster_frame::mm::compaction::compact_all;
+
+use super::template::{DirOps, FileOps, ProcDir, ProcDirBuilder, ProcFileBuilder};
+use crate::{
+    fs::utils::{
+        dirty_background_ratio, dirty_expire_centisecs, read_ahead_kb, set_dirty_background_ratio,
+        set_dirty_expire_centisecs, set_read_ahead_kb, set_writeback_interval_centisecs,
+        writeback_interval_centisecs, DirEntryVecExt, Inode, InodeMode,
+    },
+    prelude::*,
+    thread::oops,
+    time::virtual_time,
+};
+
+/// Represents the inode at `/proc/sys`.
+pub struct SysDirOps;
+
+impl SysDirOps {
+    pub fn new_inode(parent: Weak<dyn Inode>) -> Arc<dyn Inode> {
+        ProcDirBuilder::new(Self).parent(parent).build().unwrap()
+    }
+}
+
+impl DirOps for SysDirOps {
+    fn lookup_child(&self, this_ptr: Weak<dyn Inode>, name: &str) -> Result<Arc<dyn Inode>> {
+        let inode = match name {
+            "fs" => FsDirOps::new_inode(this_ptr),
+            "kernel" => KernelDirOps::new_inode(this_ptr),
+            "vm" => VmDirOps::new_inode(this_ptr),
+            _ => return_errno!(Errno::ENOENT),
+        };
+        Ok(inode)
+    }
+
+    fn populate_children(&self, this_ptr: Weak<dyn Inode>) {
+        let this = {
+            let this = this_ptr.upgrade().unwrap();
+            this.downcast_ref::<ProcDir<SysDirOps>>().unwrap().this()
+        };
+        let mut cached_children = this.cached_children().write();
+        cached_children.put_entry_if_not_found("fs", || FsDirOps::new_inode(this_ptr.clone()));
+        cached_children
+            .put_entry_if_not_found("kernel", || KernelDirOps::new_inode(this_ptr.clone()));
+        cached_children.put_entry_if_not_found("vm", || VmDirOps::new_inode(this_ptr.clone()));
+    }
+}
+
+/// Represents the inode at `/proc/sys/fs`.
+struct FsDirOps;
+
+impl FsDirOps {
+    pub fn new_inode(parent: Weak<dyn Inode>) -> Arc<dyn Inode> {
+        ProcDirBuilder::new(Self).parent(parent).build().unwrap()
+    }
+}
+
+impl DirOps for FsDirOps {
+    fn lookup_child(&self, this_ptr: Weak<dyn Inode>, name: &str) -> Result<Arc<dyn Inode>> {
+        let inode = match name {
+            "binfmt_misc" => BinfmtMiscDirOps::new_inode(this_ptr),
+            _ => return_errno!(Errno::ENOENT),
+        };
+        Ok(inode)
+    }
+
+    fn populate_children(&self, this_ptr: Weak<dyn Inode>) {
+        let this = {
+            let this = this_ptr.upgrade().unwrap();
+            this.downcast_ref::<ProcDir<FsDirOps>>().unwrap().this()
+        };
+        let mut cached_children = this.cached_children().write();
+        cached_children.put_entry_if_not_found("binfmt_misc", || {
+            BinfmtMiscDirOps::new_inode(this_ptr.clone())
+        });
+    }
+}
+
+/// Represents the inode at `/proc/sys/fs/binfmt_misc`, which is empty until the
+/// binfmt_misc file system is mounted on it.
+struct BinfmtMiscDirOps;
+
+impl BinfmtMiscDirOps {
+    pub fn new_inode(parent: Weak<dyn Inode>) -> Arc<dyn Inode> {
+        ProcDirBuilder::new(Self).parent(parent).build().unwrap()
+    }
+}
+
+impl DirOps for BinfmtMiscDirOps {}
+
+/// Represents the inode at `/proc/sys/kernel`.
+struct KernelDirOps;
+
+/// The tunables in `/proc/sys/kernel`.
+const KERNEL_TUNABLES: &[(&str, TunableFileOps)] = &[
+    (
+        "panic_on_oops",
+        TunableFileOps {
+            get: || oops::panic_on_oops() as usize,
+            set: |panic_on_oops| {
+                oops::set_panic_on_oops(panic_on_oops != 0);
+                Ok(())
+            },
+        },
+    ),
+    (
+        "tainted",
+        TunableFileOps {
+            get: oops::tainted,
+            set: |flags| {
+                oops::add_taint(flags);
+                Ok(())
+            },
+        },
+    ),
+];
+
+impl KernelDirOps {
+    pub fn new_inode(parent: Weak<dyn Inode>) -> Arc<dyn Inode> {
+        ProcDirBuilder::new(Self).parent(parent).build().unwrap()
+    }
+}
+
+impl DirOps for KernelDirOps {
+    fn lookup_child(&self, this_ptr: Weak<dyn Inode>, name: &str) -> Result<Arc<dyn Inode>> {
+        let inode = match name {
+            "degraded_subsystems" => DegradedSubsystemsFileOps::new_inode(this_ptr),
+            "virtual_time_ns" if virtual_time::is_enabled() => {
+                VirtualTimeFileOps::new_inode(this_ptr)
+            }
+            _ => {
+                let Some((_, ops)) = KERNEL_TUNABLES.iter().find(|(tunable, _)| *tunable == name)
+                else {
+                    return_errno!(Errno::ENOENT);
+                };
+                ops.new_inode(this_ptr)
+            }
+        };
+        Ok(inode)
+    }
+
+    fn populate_children(&self, this_ptr: Weak<dyn Inode>) {
+        let this = {
+            let this = this_ptr.upgrade().unwrap();
+            this.downcast_ref::<ProcDir<KernelDirOps>>().unwrap().this()
+        };
+        let mut cached_children = this.cached_children().write();
+        cached_children.put_entry_if_not_found("degraded_subsystems", || {
+            DegradedSubsystemsFileOps::new_inode(this_ptr.clone())
+        });
+        for (name, ops) in KERNEL_TUNABLES {
+            cached_children.put_entry_if_not_found(name, || ops.new_inode(this_ptr.clone()));
+        }
+        if virtual_time::is_enabled() {
+            cached_children.put_entry_if_not_found("virtual_time_ns", || {
+                VirtualTimeFileOps::new_inode(this_ptr.clone())
+            });
+        }
+    }
+}
+
+/// Represents the inode at `/proc/sys/kernel/degraded_subsystems`.
+///
+/// Each line of the file has the name of a subsystem that has oopsed and the number of
+/// the oopses in it.
+struct DegradedSubsystemsFileOps;
+
+impl DegradedSubsystemsFileOps {
+    pub fn new_inode(parent: Weak<dyn Inode>) -> Arc<dyn Inode> {
+        ProcFileBuilder::new(Self)
+            .parent(parent)
+            .mode(InodeMode::from_bits_truncate(0o444))
+            .build()
+            .unwrap()
+    }
+}
+
+impl FileOps for DegradedSubsystemsFileOps {
+    fn data(&self) -> Result<Vec<u8>> {
+        let mut output = String::new();
+        for (name, nr_oopses) in oops::degraded_subsystems() {
+            output.push_str(&format!("{} {}\n", name, nr_oopses));
+        }
+        Ok(output.into_bytes())
+    }
+}
+
+/// Represents the inode at `/proc/sys/kernel/virtual_time_ns`, which only exists in the
+/// virtual time mode.
+///
+/// Reading the file gets the virtual monotonic time in nanoseconds, and writing a number
+/// of nanoseconds to the file advances the virtual time by that much.
+struct VirtualTimeFileOps;
+
+impl VirtualTimeFileOps {
+    pub fn new_inode(parent: Weak<dyn Inode>) -> Arc<dyn Inode> {
+        ProcFileBuilder::new(Self)
+            .parent(parent)
+            .mode(InodeMode::from_bits_truncate(0o644))
+            .build()
+            .unwrap()
+    }
+}
+
+impl FileOps for VirtualTimeFileOps {
+    fn data(&self) -> Result<Vec<u8>> {
+        Ok(format!("{}\n", virtual_time::now().as_nanos()).into_bytes())
+    }
+
+    fn write(&self, buf: &[u8]) -> Result<usize> {
+        let nanos = core::str::from_utf8(buf)
+            .ok()
+            .and_then(|str| str.trim().parse::<u64>().ok())
+            .ok_or_else(|| Error::with_message(Errno::EINVAL, "invalid integer"))?;
+        virtual_time::advance(Duration::from_nanos(nanos))?;
+        Ok(buf.len())
+    }
+}
+
+/// Represents the inode at `/proc/sys/vm`.
+struct VmDirOps;
+
+/// The tunables in `/proc/sys/vm`.
+///
+/// Unlike Linux, `read_ahead_kb` is here rather than in the sysfs directories of the
+/// block devices, since the read-ahead window is not configured per device.
+const VM_TUNABLES: &[(&str, TunableFileOps)] = &[
+    (
+        "dirty_background_ratio",
+        TunableFileOps {
+            get: dirty_background_ratio,
+            set: set_dirty_background_ratio,
+        },
+    ),
+    (
+        "dirty_expire_centisecs",
+        TunableFileOps {
+            get: dirty_expire_centisecs,
+            set: |centisecs| {
+                set_dirty_expire_centisecs(centisecs);
+                Ok(())
+            },
+        },
+    ),
+    (
+        "dirty_writeback_centisecs",
+        TunableFileOps {
+            get: writeback_interval_centisecs,
+            set: |centisecs| {
+                set_writeback_interval_centisecs(centisecs);
+                Ok(())
+            },
+        },
+    ),
+    (
+        "read_ahead_kb",
+        TunableFileOps {
+            get: read_ahead_kb,
+            set: |kb| {
+                set_read_ahead_kb(kb);
+                Ok(())
+            },
+        },
+    ),
+];
+
+impl VmDirOps {
+    pub fn new_inode(parent: Weak<dyn Inode>) -> Arc<dyn Inode> {
+        ProcDirBuilder::new(Self).parent(parent).build().unwrap()
+    }
+}
+
+impl DirOps for VmDirOps {
+    fn lookup_child(&self, this_ptr: Weak<dyn Inode>, name: &str) -> Result<Arc<dyn Inode>> {
+        if name == "compact_memory" {
+            return Ok(CompactMemoryFileOps::new_inode(this_ptr));
+        }
+        let Some((_, ops)) = VM_TUNABLES.iter().find(|(tunable, _)| *tunable == name) else {
+            return_errno!(Errno::ENOENT);
+        };
+        Ok(ops.new_inode(this_ptr))
+    }
+
+    fn populate_children(&self, this_ptr: Weak<dyn Inode>) {
+        let this = {
+            let this = this_ptr.upgrade().unwrap();
+            this.downcast_ref::<ProcDir<VmDirOps>>().unwrap().this()
+        };
+        let mut cached_children = this.cached_children().write();
+        cached_children.put_entry_if_not_found("compact_memory", || {
+            CompactMemoryFileOps::new_inode(this_ptr.clone())
+        });
+        for (name, ops) in VM_TUNABLES {
+            cached_children.put_entry_if_not_found(name, || ops.new_inode(this_ptr.clone()));
+        }
+    }
+}
+
+/// Represents the inode of a tunable, whose content is a decimal integer.
+#[derive(Clone, Copy)]
+struct TunableFileOps {
+    get: fn() -> usize,
+    set: fn(usize) -> Result<()>,
+}
+
+impl TunableFileOps {
+    pub fn new_inode(self, parent: Weak<dyn Inode>) -> Arc<dyn Inode> {
+        ProcFileBuilder::new(self)
+            .parent(parent)
+            .mode(InodeMode::from_bits_truncate(0o644))
+            .build()
+            .unwrap()
+    }
+}
+
+impl FileOps for TunableFileOps {
+    fn data(&self) -> Result<Vec<u8>> {
+        Ok(format!("{}\n", (self.get)()).into_bytes())
+    }
+
+    fn write(&self, buf: &[u8]) -> Result<usize> {
+        let value = core::str::from_utf8(buf)
+            .ok()
+            .and_then(|str| str.trim().parse::<usize>().ok())
+            .ok_or_else(|| Error::with_message(Errno::EINVAL, "invalid integer"))?;
+        (self.set)(value)?;
+        Ok(buf.len())
+    }
+}
+
+/// Represents the inode at `/proc/sys/vm/compact_memory`.
+///
+/// Writing an integer to the file compacts all the memory.
+struct CompactMemoryFileOps;
+
+impl CompactMemoryFileOps {
+    pub fn new_inode(parent: Weak<dyn Inode>) -> Arc<dyn Inode> {
+        ProcFileBuilder::new(Self)
+            .parent(parent)
+            .mode(InodeMode::from_bits_truncate(0o200))
+            .build()
+            .unwrap()
+    }
+}
+
+impl FileOps for CompactMemoryFileOps {
+    fn data(&self) -> Result<Vec<u8>> {
+        return_errno_with_message!(Errno::EACCES, "the file is write-only");
+    }
+
+    fn write(&self, buf: &[u8]) -> Result<usize> {
+        core::str::from_utf8(buf)
+            .ok()
+            .and_then(|str| str.trim().parse::<usize>().ok())
+            .ok_or_else(|| Error::with_message(Errno::EINVAL, "invalid integer"))?;
+        let nr_migrated = compact_all();
+        debug!("compact_memory: {} pages migrated", nr_migrated);
+        Ok(buf.len())
+    }
+}
@@ -0,0 +1,35 @@
+// SPDX-License-Identifier: MPL-2.0
+
+use super::template::{FileOps, ProcFileBuilder};
+use crate::{
+    fs::utils::{Inode, InodeMode},
+    prelude::*,
+    syscall::{clear_unsupported_syscalls, unsupported_syscalls_report},
+};
+
+/// Represents the inode at `/proc/unsupported_syscalls`.
+///
+/// Reading the file gets the syscalls that have failed with `ENOSYS` or `EOPNOTSUPP`, and
+/// writing anything to the file clears the records.
+pub struct UnsupportedSyscallsFileOps;
+
+impl UnsupportedSyscallsFileOps {
+    pub fn new_inode(parent: Weak<dyn Inode>) -> Arc<dyn Inode> {
+        ProcFileBuilder::new(Self)
+            .parent(parent)
+            .mode(InodeMode::from_bits_truncate(0o644))
+            .build()
+            .unwrap()
+    }
+}
+
+impl FileOps for UnsupportedSyscallsFileOps {
+    fn data(&self) -> Result<Vec<u8>> {
+        Ok(unsupported_syscalls_report().into_bytes())
+    }
+
+    fn write(&self, buf: &[u8]) -> Result<usize> {
+        clear_unsupported_syscalls();
+        Ok(buf.len())
+    }
+}
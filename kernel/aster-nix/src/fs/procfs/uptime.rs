@@ -0,0 +1,27 @@
+// SPDX-License-Identifier: MPL-2.0
+
+use super::template::{FileOps, ProcFileBuilder};
+use crate::{fs::utils::Inode, prelude::*, time::clocks::BootTimeClock};
+
+/// Represents the inode at `/proc/uptime`.
+pub struct UptimeFileOps;
+
+impl UptimeFileOps {
+    pub fn new_inode(parent: Weak<dyn Inode>) -> Arc<dyn Inode> {
+        ProcFileBuilder::new(Self).parent(parent).build().unwrap()
+    }
+}
+
+impl FileOps for UptimeFileOps {
+    fn data(&self) -> Result<Vec<u8>> {
+        // TODO: Report the time since the creation of the time namespace of the caller,
+        // and the idle time, once they are supported.
+        let uptime = BootTimeClock::get().read_time();
+        let output = format!(
+            "{}.{:02} 0.00\n",
+            uptime.as_secs(),
+            uptime.subsec_millis() / 10
+        );
+        Ok(output.into_bytes())
+    }
+}
@@ -1,13 +1,15 @@
 // SPDX-License-Identifier: MPL-2.0
 
+use alloc::collections::BTreeMap;
 use core::{
-    sync::atomic::{AtomicU64, Ordering},
+    sync::atomic::{AtomicU64, AtomicUsize, Ordering},
     time::Duration,
 };
 
+use align_ext::AlignExt;
 use aster_block::bio::BioWaiter;
 use aster_frame::{
-    mm::{Frame, VmIo},
+    mm::{nr_total_frames, Frame, VmIo},
     sync::RwMutexWriteGuard,
 };
 use aster_rights::Full;
@@ -20,12 +22,16 @@ use crate::{
         device::Device,
         utils::{
             CStr256, DirentVisitor, FileSystem, FsFlags, Inode, InodeMode, InodeType, IoctlCmd,
-            Metadata, PageCache, PageCacheBackend, SuperBlock,
+            Metadata, PageCache, PageCacheBackend, SuperBlock, XattrSetFlags, Xattrs,
         },
     },
     prelude::*,
     process::{signal::Poller, Gid, Uid},
-    vm::vmo::Vmo,
+    vm::{
+        swap::{self, SwapSlot, SwapUser},
+        vmar::SharedMem,
+        vmo::Vmo,
+    },
 };
 
 /// A volatile file system whose data and metadata exists only in memory.
@@ -36,10 +42,45 @@ pub struct RamFS {
     root: Arc<RamInode>,
     /// An inode allocator
     inode_allocator: AtomicU64,
+    /// The number of live inodes
+    nr_inodes: AtomicUsize,
+    /// The number of blocks occupied by the data of regular files
+    nr_used_blocks: AtomicUsize,
+    /// The maximum number of blocks, which is enforced for tmpfs
+    max_blocks: Option<usize>,
+    /// The maximum number of inodes, which is enforced for tmpfs
+    max_inodes: Option<usize>,
 }
 
 impl RamFS {
     pub fn new() -> Arc<Self> {
+        Self::new_with_limits(None, None)
+    }
+
+    /// Creates a RamFS that is used as tmpfs, e.g., for `/tmp` and `/dev/shm`.
+    ///
+    /// Unlike [`Self::new`], the size and the number of inodes of the file system are
+    /// limited unless they are given as zero. Growing the files beyond the size or
+    /// creating more inodes fails with `ENOSPC`.
+    pub fn new_tmpfs(options: &TmpfsMountOptions) -> Arc<Self> {
+        let max_blocks = match options.size {
+            Some(0) => None,
+            Some(size) => Some(size.div_ceil(BLOCK_SIZE)),
+            None => Some(nr_total_frames() / 2),
+        };
+        let max_inodes = match options.nr_inodes {
+            Some(0) => None,
+            Some(nr_inodes) => Some(nr_inodes),
+            None => Some(nr_total_frames() / 2),
+        };
+        let fs = Self::new_with_limits(max_blocks, max_inodes);
+        if let Some(mode) = options.mode {
+            fs.root.node.write().metadata.mode = mode;
+        }
+        fs
+    }
+
+    fn new_with_limits(max_blocks: Option<usize>, max_inodes: Option<usize>) -> Arc<Self> {
         Arc::new_cyclic(|weak_fs| Self {
             sb: SuperBlock::new(RAMFS_MAGIC, BLOCK_SIZE, NAME_MAX),
             root: Arc::new_cyclic(|weak_root| RamInode {
@@ -54,8 +95,13 @@ impl RamFS {
                 typ: InodeType::Dir,
                 this: weak_root.clone(),
                 fs: weak_fs.clone(),
+                swap_slots: Mutex::new(BTreeMap::new()),
             }),
             inode_allocator: AtomicU64::new(ROOT_INO + 1),
+            nr_inodes: AtomicUsize::new(1),
+            nr_used_blocks: AtomicUsize::new(0),
+            max_blocks,
+            max_inodes,
         })
     }
 
@@ -63,6 +109,62 @@ impl RamFS {
         self.inode_allocator.fetch_add(1, Ordering::SeqCst)
     }
 
+    /// Accounts for a new inode, which is released when the inode is dropped.
+    fn reserve_inode(&self) -> Result<()> {
+        let Some(max_inodes) = self.max_inodes else {
+            self.nr_inodes.fetch_add(1, Ordering::Relaxed);
+            return Ok(());
+        };
+        self.nr_inodes
+            .fetch_update(Ordering::Relaxed, Ordering::Relaxed, |nr_inodes| {
+                (nr_inodes < max_inodes).then_some(nr_inodes + 1)
+            })
+            .map_err(|_| Error::with_message(Errno::ENOSPC, "no free inode in the tmpfs"))?;
+        Ok(())
+    }
+
+    fn update_used_blocks(&self, old_blocks: usize, new_blocks: usize) -> Result<()> {
+        if new_blocks > old_blocks {
+            self.alloc_blocks(new_blocks - old_blocks)
+        } else {
+            self.free_blocks(old_blocks - new_blocks);
+            Ok(())
+        }
+    }
+
+    fn alloc_blocks(&self, nr_blocks: usize) -> Result<()> {
+        let Some(max_blocks) = self.max_blocks else {
+            self.nr_used_blocks.fetch_add(nr_blocks, Ordering::Relaxed);
+            return Ok(());
+        };
+        self.nr_used_blocks
+            .fetch_update(Ordering::Relaxed, Ordering::Relaxed, |nr_used_blocks| {
+                nr_used_blocks
+                    .checked_add(nr_blocks)
+                    .filter(|new_used_blocks| *new_used_blocks <= max_blocks)
+            })
+            .map_err(|_| Error::with_message(Errno::ENOSPC, "the tmpfs is full"))?;
+        Ok(())
+    }
+
+    fn free_blocks(&self, nr_blocks: usize) {
+        self.nr_used_blocks.fetch_sub(nr_blocks, Ordering::Relaxed);
+    }
+
+    /// Returns the maximum number of blocks.
+    ///
+    /// Like the default of tmpfs in Linux, it is limited to half of the physical memory,
+    /// unless the size of a tmpfs is given. The limit is only enforced for tmpfs.
+    fn budget(&self) -> usize {
+        self.max_blocks.unwrap_or(nr_total_frames() / 2)
+    }
+
+    /// Returns the maximum number of inodes, which is reported to be the same as the
+    /// maximum number of blocks if it is not limited.
+    fn inode_budget(&self) -> usize {
+        self.max_inodes.unwrap_or_else(|| self.budget())
+    }
+
     fn device_id(&self) -> u64 {
         0
     }
@@ -79,7 +181,17 @@ impl FileSystem for RamFS {
     }
 
     fn sb(&self) -> SuperBlock {
-        self.sb.clone()
+        let budget = self.budget();
+        let nr_used_blocks = self.nr_used_blocks.load(Ordering::Relaxed);
+        let nr_inodes = self.nr_inodes.load(Ordering::Relaxed);
+
+        let mut sb = self.sb.clone();
+        sb.blocks = budget;
+        sb.bfree = budget.saturating_sub(nr_used_blocks);
+        sb.bavail = sb.bfree;
+        sb.files = self.inode_budget();
+        sb.ffree = sb.files.saturating_sub(nr_inodes);
+        sb
     }
 
     fn flags(&self) -> FsFlags {
@@ -87,6 +199,72 @@ impl FileSystem for RamFS {
     }
 }
 
+/// The options of a tmpfs, which are given by the `data` argument of the mount syscall.
+///
+/// The options are comma-separated, e.g., `size=64m,nr_inodes=1k,mode=1777`.
+#[derive(Debug, Default)]
+pub struct TmpfsMountOptions {
+    /// The size limit in bytes, which defaults to half of the physical memory.
+    /// A zero size means no limit.
+    size: Option<usize>,
+    /// The maximum number of inodes, which defaults to half of the number of the
+    /// physical pages. A zero number means no limit.
+    nr_inodes: Option<usize>,
+    /// The mode of the root directory.
+    mode: Option<InodeMode>,
+}
+
+impl TmpfsMountOptions {
+    pub fn parse(options: &str) -> Result<Self> {
+        let mut mount_options = Self::default();
+        for option in options.split(',').filter(|option| !option.is_empty()) {
+            let (key, value) = option.split_once('=').unwrap_or((option, ""));
+            match key {
+                "size" => mount_options.size = Some(parse_size(value)?),
+                "nr_inodes" => {
+                    let nr_inodes = parse_with_suffix(value).ok_or_else(|| {
+                        Error::with_message(Errno::EINVAL, "invalid number of inodes")
+                    })?;
+                    mount_options.nr_inodes = Some(nr_inodes);
+                }
+                "mode" => {
+                    let mode = u16::from_str_radix(value, 8)
+                        .map_err(|_| Error::with_message(Errno::EINVAL, "invalid mode"))?;
+                    mount_options.mode = Some(InodeMode::from_bits_truncate(mode));
+                }
+                // The memory policy and the IDs of the root directory are ignored.
+                "mpol" | "uid" | "gid" => {}
+                _ => return_errno_with_message!(Errno::EINVAL, "unknown tmpfs option"),
+            }
+        }
+        Ok(mount_options)
+    }
+}
+
+/// Parses the size of a tmpfs, which is in bytes with an optional `k`, `m` or `g`
+/// suffix, or in the percentage of the physical memory with a `%` suffix.
+fn parse_size(size: &str) -> Result<usize> {
+    let invalid_size = || Error::with_message(Errno::EINVAL, "invalid tmpfs size");
+    if let Some(percent) = size.strip_suffix('%') {
+        let percent: usize = percent.parse().map_err(|_| invalid_size())?;
+        return Ok(nr_total_frames() * PAGE_SIZE / 100 * percent);
+    }
+    parse_with_suffix(size).ok_or_else(invalid_size)
+}
+
+/// Parses a number with an optional `k`, `m` or `g` suffix, which multiplies it by
+/// 2^10, 2^20 or 2^30 respectively.
+fn parse_with_suffix(number: &str) -> Option<usize> {
+    let (number, multiplier) = match number.as_bytes().last() {
+        Some(b'k' | b'K') => (&number[..number.len() - 1], 1 << 10),
+        Some(b'm' | b'M') => (&number[..number.len() - 1], 1 << 20),
+        Some(b'g' | b'G') => (&number[..number.len() - 1], 1 << 30),
+        _ => (number, 1),
+    };
+    let number: usize = number.parse().ok()?;
+    number.checked_mul(multiplier)
+}
+
 struct RamInode {
     /// The mutable part of the inode
     node: RwMutex<Node>,
@@ -98,11 +276,14 @@ struct RamInode {
     this: Weak<RamInode>,
     /// Reference to fs
     fs: Weak<RamFS>,
+    /// The swap slots that hold the reclaimed pages of a regular file
+    swap_slots: Mutex<BTreeMap<usize, Arc<SwapSlot>>>,
 }
 
 struct Node {
     inner: Inner,
     metadata: InodeMeta,
+    xattrs: Xattrs,
 }
 
 impl Node {
@@ -116,13 +297,15 @@ impl Node {
         Self {
             inner: Inner::Dir(DirEntry::new(this, parent)),
             metadata: InodeMeta::new_dir(mode, uid, gid),
+            xattrs: Xattrs::new(),
         }
     }
 
     pub fn new_file(mode: InodeMode, uid: Uid, gid: Gid, this: Weak<RamInode>) -> Self {
         Self {
-            inner: Inner::File(PageCache::new(this).unwrap()),
+            inner: Inner::File(PageCache::new_swap_backed(this).unwrap()),
             metadata: InodeMeta::new(mode, uid, gid),
+            xattrs: Xattrs::new(),
         }
     }
 
@@ -130,6 +313,7 @@ impl Node {
         Self {
             inner: Inner::SymLink(String::from("")),
             metadata: InodeMeta::new(mode, uid, gid),
+            xattrs: Xattrs::new(),
         }
     }
 
@@ -137,6 +321,7 @@ impl Node {
         Self {
             inner: Inner::Socket,
             metadata: InodeMeta::new(mode, uid, gid),
+            xattrs: Xattrs::new(),
         }
     }
 
@@ -144,6 +329,7 @@ impl Node {
         Self {
             inner: Inner::Device(device),
             metadata: InodeMeta::new(mode, uid, gid),
+            xattrs: Xattrs::new(),
         }
     }
 
@@ -388,6 +574,7 @@ impl RamInode {
             typ: InodeType::Dir,
             this: weak_self.clone(),
             fs: Arc::downgrade(fs),
+            swap_slots: Mutex::new(BTreeMap::new()),
         })
     }
 
@@ -398,6 +585,7 @@ impl RamInode {
             typ: InodeType::File,
             this: weak_self.clone(),
             fs: Arc::downgrade(fs),
+            swap_slots: Mutex::new(BTreeMap::new()),
         })
     }
 
@@ -408,6 +596,7 @@ impl RamInode {
             typ: InodeType::SymLink,
             this: weak_self.clone(),
             fs: Arc::downgrade(fs),
+            swap_slots: Mutex::new(BTreeMap::new()),
         })
     }
 
@@ -418,6 +607,7 @@ impl RamInode {
             typ: InodeType::Socket,
             this: weak_self.clone(),
             fs: Arc::downgrade(fs),
+            swap_slots: Mutex::new(BTreeMap::new()),
         })
     }
 
@@ -434,6 +624,7 @@ impl RamInode {
             typ: InodeType::from(device.type_()),
             this: weak_self.clone(),
             fs: Arc::downgrade(fs),
+            swap_slots: Mutex::new(BTreeMap::new()),
         })
     }
 
@@ -454,14 +645,22 @@ impl RamInode {
 }
 
 impl PageCacheBackend for RamInode {
-    fn read_page(&self, _idx: usize, frame: &Frame) -> Result<BioWaiter> {
-        // Initially, any block/page in a RamFs inode contains all zeros
-        frame.writer().fill(0);
+    fn read_page(&self, idx: usize, frame: &Frame) -> Result<BioWaiter> {
+        if let Some(slot) = self.swap_slots.lock().get(&idx) {
+            slot.read(frame)?;
+        } else {
+            // Initially, any block/page in a RamFs inode contains all zeros
+            frame.writer().fill(0);
+        }
         Ok(BioWaiter::new())
     }
 
-    fn write_page(&self, _idx: usize, _frame: &Frame) -> Result<BioWaiter> {
-        // do nothing
+    // The page cache is swap-backed, so a page is only written here when it is
+    // reclaimed, which fails if there is no free swap slot.
+    fn write_page(&self, idx: usize, frame: &Frame) -> Result<BioWaiter> {
+        let slot = swap::swap_out(frame)?;
+        self.swap_slots.lock().insert(idx, slot);
+        swap::register_swap_user(self.this.clone());
         Ok(BioWaiter::new())
     }
 
@@ -470,6 +669,54 @@ impl PageCacheBackend for RamInode {
     }
 }
 
+impl SwapUser for RamInode {
+    fn swap_in(&self, filter: &dyn Fn(&SwapSlot) -> bool) -> Result<()> {
+        let slots: Vec<(usize, Arc<SwapSlot>)> = self
+            .swap_slots
+            .lock()
+            .iter()
+            .filter(|(_, slot)| filter(slot))
+            .map(|(idx, slot)| (*idx, slot.clone()))
+            .collect();
+        if slots.is_empty() {
+            return Ok(());
+        }
+
+        let self_inode = self.node.read();
+        let page_cache = self_inode.inner.as_file().unwrap();
+        let mut buf = vec![0u8; PAGE_SIZE];
+        for (idx, slot) in slots {
+            let offset = idx * PAGE_SIZE;
+            let len = PAGE_SIZE.min(self_inode.metadata.size.saturating_sub(offset));
+            if len > 0 {
+                // Writing the page back makes it dirty, so it no longer needs the slot.
+                page_cache.pages().read_bytes(offset, &mut buf[..len])?;
+                page_cache.pages().write_bytes(offset, &buf[..len])?;
+            }
+            let mut swap_slots = self.swap_slots.lock();
+            if swap_slots
+                .get(&idx)
+                .is_some_and(|current| Arc::ptr_eq(current, &slot))
+            {
+                swap_slots.remove(&idx);
+            }
+        }
+        Ok(())
+    }
+}
+
+impl Drop for RamInode {
+    fn drop(&mut self) {
+        let Some(fs) = self.fs.upgrade() else {
+            return;
+        };
+        fs.nr_inodes.fetch_sub(1, Ordering::Relaxed);
+        if self.typ == InodeType::File {
+            fs.free_blocks(self.node.read().metadata.blocks);
+        }
+    }
+}
+
 impl Inode for RamInode {
     fn page_cache(&self) -> Option<Vmo<Full>> {
         self.node
@@ -479,6 +726,14 @@ impl Inode for RamInode {
             .map(|page_cache| page_cache.pages().dup())
     }
 
+    fn shared_mem(&self) -> Option<Arc<SharedMem>> {
+        self.node
+            .read()
+            .inner
+            .as_file()
+            .map(|page_cache| page_cache.shared_mem().clone())
+    }
+
     fn read_at(&self, offset: usize, buf: &mut [u8]) -> Result<usize> {
         let self_inode = self.node.read();
 
@@ -519,9 +774,21 @@ impl Inode for RamInode {
         let new_size = offset + buf.len();
         let should_expand_size = new_size > file_size;
         if should_expand_size {
-            page_cache.pages().resize(new_size)?;
+            // Allocate the blocks first, so that a tmpfs does not exceed its size.
+            let fs = self.fs.upgrade().unwrap();
+            let new_blocks = new_size.div_ceil(BLOCK_SIZE) - self_inode.metadata.blocks;
+            fs.alloc_blocks(new_blocks)?;
+            let res = page_cache.pages().resize(new_size).and_then(|_| {
+                page_cache.pages().write_bytes(offset, buf)?;
+                Ok(())
+            });
+            if let Err(err) = res {
+                fs.free_blocks(new_blocks);
+                return Err(err);
+            }
+        } else {
+            page_cache.pages().write_bytes(offset, buf)?;
         }
-        page_cache.pages().write_bytes(offset, buf)?;
         if should_expand_size {
             // Turn the read guard into a write guard without releasing the lock.
             let mut self_inode = self_inode.upgrade();
@@ -550,10 +817,24 @@ impl Inode for RamInode {
         }
 
         let mut self_inode = self_inode.upgrade();
+        let old_blocks = self_inode.metadata.blocks;
+        self.fs
+            .upgrade()
+            .unwrap()
+            .update_used_blocks(old_blocks, new_size.div_ceil(BLOCK_SIZE))?;
         self_inode.resize(new_size);
         let self_inode = self_inode.downgrade();
+        self.swap_slots
+            .lock()
+            .retain(|idx, _| *idx < new_size.div_ceil(PAGE_SIZE));
         let page_cache = self_inode.inner.as_file().unwrap();
         page_cache.pages().resize(new_size)?;
+        if new_size < file_size {
+            // The truncated pages should no longer be accessible through the mappings.
+            page_cache
+                .shared_mem()
+                .unmap_pages(new_size.align_up(PAGE_SIZE)..file_size)?;
+        }
 
         Ok(())
     }
@@ -609,6 +890,22 @@ impl Inode for RamInode {
         Ok(())
     }
 
+    fn get_xattr(&self, name: &str) -> Result<Vec<u8>> {
+        self.node.read().xattrs.get(name)
+    }
+
+    fn set_xattr(&self, name: &str, value: &[u8], flags: XattrSetFlags) -> Result<()> {
+        self.node.write().xattrs.set(name, value, flags)
+    }
+
+    fn list_xattr(&self) -> Result<Vec<String>> {
+        Ok(self.node.read().xattrs.names())
+    }
+
+    fn remove_xattr(&self, name: &str) -> Result<()> {
+        self.node.write().xattrs.remove(name)
+    }
+
     fn mknod(
         &self,
         name: &str,
@@ -626,13 +923,10 @@ impl Inode for RamInode {
         if self_inode.inner.as_direntry().unwrap().contains_entry(name) {
             return_errno_with_message!(Errno::EEXIST, "entry exists");
         }
-        let device_inode = RamInode::new_device(
-            &self.fs.upgrade().unwrap(),
-            mode,
-            Uid::new_root(),
-            Gid::new_root(),
-            device,
-        );
+        let fs = self.fs.upgrade().unwrap();
+        fs.reserve_inode()?;
+        let device_inode =
+            RamInode::new_device(&fs, mode, Uid::new_root(), Gid::new_root(), device);
 
         let mut self_inode = self_inode.upgrade();
         self_inode
@@ -661,6 +955,7 @@ impl Inode for RamInode {
             return_errno_with_message!(Errno::EEXIST, "entry exists");
         }
         let fs = self.fs.upgrade().unwrap();
+        fs.reserve_inode()?;
         let new_inode = match type_ {
             InodeType::File => RamInode::new_file(&fs, mode, Uid::new_root(), Gid::new_root()),
             InodeType::SymLink => {
@@ -688,6 +983,19 @@ impl Inode for RamInode {
         Ok(new_inode)
     }
 
+    fn create_tmpfile(&self, mode: InodeMode) -> Result<Arc<dyn Inode>> {
+        if self.typ != InodeType::Dir {
+            return_errno_with_message!(Errno::ENOTDIR, "self is not dir");
+        }
+
+        let fs = self.fs.upgrade().unwrap();
+        fs.reserve_inode()?;
+        let new_inode = RamInode::new_file(&fs, mode, Uid::new_root(), Gid::new_root());
+        // The file has no name until it is linked to a directory.
+        new_inode.node.write().dec_nlinks();
+        Ok(new_inode)
+    }
+
     fn readdir_at(&self, offset: usize, visitor: &mut dyn DirentVisitor) -> Result<usize> {
         if self.typ != InodeType::Dir {
             return_errno_with_message!(Errno::ENOTDIR, "self is not dir");
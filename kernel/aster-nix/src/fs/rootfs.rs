@@ -7,19 +7,78 @@ use spin::Once;
 
 use super::{
     fs_resolver::{FsPath, FsResolver},
-    path::MountNode,
+    path::{MountNode, PerMountFlags},
     procfs::ProcFS,
-    ramfs::RamFS,
+    ramfs::{RamFS, TmpfsMountOptions},
+    tracefs,
     utils::{FileSystem, InodeMode, InodeType},
 };
-use crate::prelude::*;
+use crate::{
+    events::{Notifier, NotifyResult},
+    power::{RebootEvent, REBOOT_NOTIFIER_CHAIN},
+    prelude::*,
+};
 
 /// Unpack and prepare the rootfs from the initramfs CPIO buffer.
+///
+/// An empty buffer means that no initramfs is provided. The rootfs is then left with
+/// the mount points only, so the kernel can still fall back to its built-in shell.
 pub fn init(initramfs_buf: &[u8]) -> Result<()> {
     init_root_mount();
 
-    println!("[kernel] unpacking the initramfs.cpio.gz to rootfs ...");
     let fs = FsResolver::new();
+    if initramfs_buf.is_empty() {
+        println!("[kernel] no initramfs is provided, the rootfs is empty");
+    } else {
+        println!("[kernel] unpacking the initramfs.cpio.gz to rootfs ...");
+        unpack_initramfs(&fs, initramfs_buf)?;
+    }
+
+    // The initramfs may not contain the mount points.
+    for (path, mode) in MOUNT_POINTS {
+        ensure_dir(&fs, path, InodeMode::from_bits_truncate(mode))?;
+    }
+    // Mount ProcFS
+    let proc_dentry = fs.lookup(&FsPath::try_from("/proc")?)?;
+    proc_dentry.mount(ProcFS::new())?;
+    // Mount DevFS
+    let dev_dentry = fs.lookup(&FsPath::try_from("/dev")?)?;
+    dev_dentry.mount(RamFS::new())?;
+    // Mount a tmpfs at /dev/shm for the POSIX shared memory
+    let shm_mode = InodeMode::from_bits_truncate(0o1777);
+    let shm_dentry =
+        fs.lookup(&FsPath::try_from("/dev")?)?
+            .new_fs_child("shm", InodeType::Dir, shm_mode)?;
+    let shm_mount = shm_dentry.mount(RamFS::new_tmpfs(&TmpfsMountOptions::default()))?;
+    shm_mount.set_flags(PerMountFlags::NOSUID | PerMountFlags::NODEV);
+    fs.lookup(&FsPath::try_from("/dev/shm")?)?
+        .set_mode(shm_mode)?;
+    // Mount TraceFS
+    let tracing_dentry = fs.lookup(&FsPath::try_from("/sys/kernel/tracing")?)?;
+    tracing_dentry.mount(tracefs::new())?;
+    // FIXME: Mount SysFS at /sys once it is supported.
+
+    // The FSes are unmounted after the other subsystems are notified.
+    REBOOT_NOTIFIER_CHAIN.register(Arc::new(UnmountNotifier), i32::MIN);
+
+    println!("[kernel] rootfs is ready");
+
+    Ok(())
+}
+
+/// The directories that must exist in the rootfs and their modes.
+///
+/// A directory must come after its parent directory.
+const MOUNT_POINTS: [(&str, u16); 6] = [
+    ("/proc", 0o555),
+    ("/dev", 0o755),
+    ("/sys", 0o555),
+    ("/sys/kernel", 0o555),
+    ("/sys/kernel/tracing", 0o700),
+    ("/tmp", 0o1777),
+];
+
+fn unpack_initramfs(fs: &FsResolver, initramfs_buf: &[u8]) -> Result<()> {
     let mut decoder = CpioDecoder::new(
         GZipDecoder::new(initramfs_buf)
             .map_err(|_| Error::with_message(Errno::EINVAL, "invalid gzip buffer"))?,
@@ -75,18 +134,22 @@ pub fn init(initramfs_buf: &[u8]) -> Result<()> {
             }
         }
     }
-    // Mount ProcFS
-    let proc_dentry = fs.lookup(&FsPath::try_from("/proc")?)?;
-    proc_dentry.mount(ProcFS::new())?;
-    // Mount DevFS
-    let dev_dentry = fs.lookup(&FsPath::try_from("/dev")?)?;
-    dev_dentry.mount(RamFS::new())?;
-
-    println!("[kernel] rootfs is ready");
-
     Ok(())
 }
 
+/// Creates the directory if it does not exist. Its parent directory must exist.
+fn ensure_dir(fs: &FsResolver, path: &str, mode: InodeMode) -> Result<()> {
+    match fs.lookup(&FsPath::try_from(path)?) {
+        Ok(_) => Ok(()),
+        Err(e) if e.error() == Errno::ENOENT => {
+            let (dir, name) = fs.lookup_dir_and_base_name(&FsPath::try_from(path)?)?;
+            dir.new_fs_child(&name, InodeType::Dir, mode)?;
+            Ok(())
+        }
+        Err(e) => Err(e),
+    }
+}
+
 pub fn mount_fs_at(fs: Arc<dyn FileSystem>, fs_path: &FsPath) -> Result<()> {
     let target_dentry = FsResolver::new().lookup(fs_path)?;
     target_dentry.mount(fs)?;
@@ -105,3 +168,15 @@ pub fn init_root_mount() {
 pub fn root_mount() -> &'static Arc<MountNode> {
     ROOT_MOUNT.get().unwrap()
 }
+
+/// Unmounts all the FSes and flushes their dirty data before the system shuts down.
+struct UnmountNotifier;
+
+impl Notifier<RebootEvent> for UnmountNotifier {
+    fn on_event(&self, _event: &RebootEvent) -> NotifyResult {
+        if let Some(root_mount) = ROOT_MOUNT.get() {
+            root_mount.unmount_all();
+        }
+        NotifyResult::Ok
+    }
+}
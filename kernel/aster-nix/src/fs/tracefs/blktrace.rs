@@ -0,0 +1,68 @@
+// SPDX-License-Identifier: MPL-2.0
+
+use aster_block::trace;
+use aster_frame::task::current_task;
+
+use crate::{
+    fs::{
+        procfs::template::{FileOps, ProcFileBuilder},
+        utils::{Inode, InodeMode},
+    },
+    prelude::*,
+    process::posix_thread::PosixThreadExt,
+    thread::Thread,
+};
+
+/// Represents the inode at `/sys/kernel/tracing/blktrace`.
+///
+/// The content is the block I/O events recorded by [`aster_block::trace`] in the binary
+/// format of `blktrace`, which can be parsed by `blkparse -i`. Writing `1` to the file
+/// discards the recorded events and starts tracing, and writing `0` stops tracing.
+pub struct BlkTraceFileOps;
+
+impl BlkTraceFileOps {
+    pub fn new_inode(parent: Weak<dyn Inode>) -> Arc<dyn Inode> {
+        ProcFileBuilder::new(Self)
+            .parent(parent)
+            .mode(InodeMode::from_bits_truncate(0o644))
+            .build()
+            .unwrap()
+    }
+}
+
+impl FileOps for BlkTraceFileOps {
+    fn data(&self) -> Result<Vec<u8>> {
+        Ok(trace::events_as_bytes())
+    }
+
+    fn write(&self, buf: &[u8]) -> Result<usize> {
+        match core::str::from_utf8(buf).map(str::trim) {
+            Ok("1") => {
+                trace::set_pid_fn(current_pid);
+                trace::enable();
+            }
+            Ok("0") => trace::disable(),
+            _ => return_errno_with_message!(Errno::EINVAL, "only 0 or 1 can be written"),
+        }
+        Ok(buf.len())
+    }
+}
+
+/// Returns the ID of the current process, or zero if the current task is not a thread
+/// of a process.
+///
+/// This can be called in the IRQ context, where the bios are completed.
+fn current_pid() -> u32 {
+    let Some(task) = current_task() else {
+        return 0;
+    };
+    task.data()
+        .downcast_ref::<Weak<Thread>>()
+        .and_then(Weak::upgrade)
+        .and_then(|thread| {
+            thread
+                .as_posix_thread()
+                .map(|posix_thread| posix_thread.process().pid())
+        })
+        .unwrap_or(0)
+}
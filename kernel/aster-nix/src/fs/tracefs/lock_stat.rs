@@ -0,0 +1,86 @@
+// SPDX-License-Identifier: MPL-2.0
+
+use core::fmt::Write;
+
+use aster_frame::sync::lock_stat::{self, LockClassStat, NR_WAIT_BUCKETS};
+
+use crate::{
+    fs::{
+        procfs::template::{FileOps, ProcFileBuilder},
+        utils::{Inode, InodeMode},
+    },
+    prelude::*,
+};
+
+/// Represents the inode at `/sys/kernel/tracing/lock_stat`.
+///
+/// The content is the contention statistics of the lock classes, in descending order
+/// of the total wait time. Writing `0` to the file clears the statistics.
+pub struct LockStatFileOps;
+
+impl LockStatFileOps {
+    pub fn new_inode(parent: Weak<dyn Inode>) -> Arc<dyn Inode> {
+        ProcFileBuilder::new(Self)
+            .parent(parent)
+            .mode(InodeMode::from_bits_truncate(0o644))
+            .build()
+            .unwrap()
+    }
+}
+
+impl FileOps for LockStatFileOps {
+    fn data(&self) -> Result<Vec<u8>> {
+        if !lock_stat::is_enabled() {
+            return Ok(b"# the kernel is built without the `lock_stat` feature\n".to_vec());
+        }
+
+        let mut output = String::new();
+        for stat in lock_stat::lock_stats() {
+            format_class(&mut output, &stat).unwrap();
+        }
+        Ok(output.into_bytes())
+    }
+
+    fn write(&self, buf: &[u8]) -> Result<usize> {
+        if core::str::from_utf8(buf).map(str::trim) != Ok("0") {
+            return_errno_with_message!(Errno::EINVAL, "only 0 can be written to clear the stats");
+        }
+        lock_stat::reset_lock_stats();
+        Ok(buf.len())
+    }
+}
+
+fn format_class(output: &mut String, stat: &LockClassStat) -> core::fmt::Result {
+    writeln!(output, "{}<{}>", stat.kind.name(), stat.name)?;
+    writeln!(
+        output,
+        "  acquisitions: {}, contentions: {}, wait-total: {} ns, wait-max: {} ns",
+        stat.acquisitions, stat.contentions, stat.total_wait_ns, stat.max_wait_ns
+    )?;
+    if stat.contentions == 0 {
+        return writeln!(output);
+    }
+
+    writeln!(output, "  wait-time histogram:")?;
+    for (bucket, count) in stat.wait_histogram.iter().enumerate() {
+        if *count == 0 {
+            continue;
+        }
+        let start = if bucket == 0 { 0 } else { 1u64 << bucket };
+        if bucket == NR_WAIT_BUCKETS - 1 {
+            writeln!(output, "    [{:>10} ns, {:>13}): {}", start, "inf", count)?;
+        } else {
+            let end = 1u64 << (bucket + 1);
+            writeln!(output, "    [{:>10} ns, {:>10} ns): {}", start, end, count)?;
+        }
+    }
+    writeln!(output, "  hottest call sites:")?;
+    for call_site in stat.call_sites.iter() {
+        writeln!(
+            output,
+            "    {}: contentions: {}, wait-total: {} ns",
+            call_site.location, call_site.contentions, call_site.total_wait_ns
+        )?;
+    }
+    writeln!(output)
+}
@@ -0,0 +1,62 @@
+// SPDX-License-Identifier: MPL-2.0
+
+//! The tracing file system, which is mounted at `/sys/kernel/tracing`.
+//!
+//! The inodes are built from the templates of procfs.
+
+use self::{blktrace::BlkTraceFileOps, lock_stat::LockStatFileOps};
+use super::{
+    procfs::{
+        template::{DirOps, ProcDir, ProcDirBuilder},
+        ProcFS, PROC_ROOT_INO,
+    },
+    utils::{DirEntryVecExt, FileSystem, Inode},
+};
+use crate::prelude::*;
+
+mod blktrace;
+mod lock_stat;
+
+/// Magic number.
+const TRACEFS_MAGIC: u64 = 0x74726163;
+
+/// Creates a tracefs.
+pub fn new() -> Arc<dyn FileSystem> {
+    ProcFS::new_with_root(TRACEFS_MAGIC, RootDirOps::new_inode)
+}
+
+/// Represents the inode at `/sys/kernel/tracing`.
+struct RootDirOps;
+
+impl RootDirOps {
+    pub fn new_inode(fs: Weak<ProcFS>) -> Arc<dyn Inode> {
+        ProcDirBuilder::new(Self)
+            .fs(fs)
+            .ino(PROC_ROOT_INO)
+            .build()
+            .unwrap()
+    }
+}
+
+impl DirOps for RootDirOps {
+    fn lookup_child(&self, this_ptr: Weak<dyn Inode>, name: &str) -> Result<Arc<dyn Inode>> {
+        let inode = match name {
+            "blktrace" => BlkTraceFileOps::new_inode(this_ptr),
+            "lock_stat" => LockStatFileOps::new_inode(this_ptr),
+            _ => return_errno!(Errno::ENOENT),
+        };
+        Ok(inode)
+    }
+
+    fn populate_children(&self, this_ptr: Weak<dyn Inode>) {
+        let this = {
+            let this = this_ptr.upgrade().unwrap();
+            this.downcast_ref::<ProcDir<RootDirOps>>().unwrap().this()
+        };
+        let mut cached_children = this.cached_children().write();
+        cached_children
+            .put_entry_if_not_found("blktrace", || BlkTraceFileOps::new_inode(this_ptr.clone()));
+        cached_children
+            .put_entry_if_not_found("lock_stat", || LockStatFileOps::new_inode(this_ptr.clone()));
+    }
+}
@@ -15,6 +15,14 @@ use crate::{
     process::signal::{Pollee, Poller},
 };
 
+/// The maximum number of items that are written to a channel atomically, which is
+/// `PIPE_BUF` in Linux.
+///
+/// A write of no more items than this is never interleaved with the other writes: it
+/// waits until all the items fit in the channel. Accordingly, the write end of a channel
+/// is only reported as writable when there is space for such a write.
+pub const PIPE_BUF: usize = 4096;
+
 /// A unidirectional communication channel, intended to implement IPC, e.g., pipe,
 /// unix domain sockets, etc.
 pub struct Channel<T> {
@@ -125,7 +133,7 @@ impl<T> Producer<T> {
         self.0.common.lock_event();
 
         let rb = this_end.rb();
-        if rb.is_full() {
+        if !has_atomic_write_space(rb.free_len(), rb.capacity()) {
             this_end.pollee.del_events(IoEvents::OUT);
         }
         if !rb.is_empty() {
@@ -162,7 +170,10 @@ impl<T: Copy> Producer<T> {
         }
     }
 
-    fn try_write(&self, buf: &[T]) -> Result<usize> {
+    /// Writes the items without blocking.
+    ///
+    /// If the items cannot be written, this method fails with `EAGAIN`.
+    pub fn try_write(&self, buf: &[T]) -> Result<usize> {
         if self.is_shutdown() || self.is_peer_shutdown() {
             return_errno!(Errno::EPIPE);
         }
@@ -184,6 +195,16 @@ impl<T: Copy> Producer<T> {
 }
 
 impl<T> Producer<T> {
+    /// Returns the number of items that can be written without blocking.
+    pub fn free_len(&self) -> usize {
+        self.this_end().rb().free_len()
+    }
+
+    /// Returns whether `self` writes to the channel that `consumer` reads from.
+    pub fn is_paired_with(&self, consumer: &Consumer<T>) -> bool {
+        Arc::ptr_eq(&self.0.common, &consumer.0.common)
+    }
+
     /// Pushes an item into the producer.
     ///
     /// On failure, this method returns `Err` containing
@@ -267,7 +288,7 @@ impl<T> Consumer<T> {
         if rb.is_empty() {
             this_end.pollee.del_events(IoEvents::IN);
         }
-        if !rb.is_full() {
+        if has_atomic_write_space(rb.free_len(), rb.capacity()) {
             peer_end.pollee.add_events(IoEvents::OUT);
         }
     }
@@ -323,9 +344,31 @@ impl<T: Copy> Consumer<T> {
             return_errno_with_message!(Errno::EAGAIN, "try read later");
         }
     }
+
+    /// Copies the items at the head of the channel to `buf` without consuming them.
+    ///
+    /// This method never blocks. It returns the number of items copied, which is zero if
+    /// the channel is empty.
+    pub fn peek(&self, buf: &mut [T]) -> usize {
+        self.0.peek(buf)
+    }
 }
 
 impl<T> Consumer<T> {
+    /// Returns whether the channel has no items to read.
+    pub fn is_empty(&self) -> bool {
+        self.this_end().rb().is_empty()
+    }
+
+    /// Consumes at most `count` items at the head of the channel without reading them.
+    ///
+    /// This method never blocks. It returns the number of items consumed.
+    pub fn skip(&self, count: usize) -> usize {
+        let skipped_len = self.0.skip(count);
+        self.update_pollee();
+        skipped_len
+    }
+
     /// Pops an item from the consumer
     pub fn pop(&self) -> Result<T> {
         let is_nonblocking = self.is_nonblocking();
@@ -403,9 +446,24 @@ impl<T: Copy, R: TRights> EndPoint<T, R> {
         rb.pop_slice(buf)
     }
 
+    #[require(R > Read)]
+    pub fn peek(&self, buf: &mut [T]) -> usize {
+        let rb = self.common.consumer.rb();
+        let (first, second) = rb.as_slices();
+        let first_len = first.len().min(buf.len());
+        buf[..first_len].copy_from_slice(&first[..first_len]);
+        let second_len = second.len().min(buf.len() - first_len);
+        buf[first_len..first_len + second_len].copy_from_slice(&second[..second_len]);
+        first_len + second_len
+    }
+
     #[require(R > Write)]
     pub fn write(&self, buf: &[T]) -> usize {
         let mut rb = self.common.producer.rb();
+        // A small write either writes all the items or nothing.
+        if buf.len() <= PIPE_BUF.min(rb.capacity()) && rb.free_len() < buf.len() {
+            return 0;
+        }
         rb.push_slice(buf)
     }
 }
@@ -426,6 +484,13 @@ impl<T, R: TRights> EndPoint<T, R> {
         let mut rb = self.common.consumer.rb();
         rb.pop()
     }
+
+    /// Drops at most `count` items from the endpoint.
+    #[require(R > Read)]
+    pub fn skip(&self, count: usize) -> usize {
+        let mut rb = self.common.consumer.rb();
+        rb.skip(count)
+    }
 }
 
 struct Common<T> {
@@ -506,6 +571,12 @@ impl<T> EndPointInner<T> {
     }
 }
 
+/// Returns whether a write of [`PIPE_BUF`] items, or of the whole capacity if it is
+/// smaller, can be done without blocking.
+fn has_atomic_write_space(free_len: usize, capacity: usize) -> bool {
+    free_len >= PIPE_BUF.min(capacity)
+}
+
 fn check_status_flags(flags: StatusFlags) -> Result<()> {
     let valid_flags: StatusFlags = StatusFlags::O_NONBLOCK | StatusFlags::O_DIRECT;
     if !valid_flags.contains(flags) {
@@ -541,7 +612,7 @@ impl<T> AsRef<Error> for (Error, T) {
 mod test {
     use alloc::sync::Arc;
 
-    use crate::fs::utils::Channel;
+    use crate::fs::utils::{Channel, StatusFlags};
 
     #[ktest]
     fn test_non_copy() {
@@ -563,4 +634,22 @@ mod test {
             assert_eq!(data, expected_data);
         }
     }
+
+    #[ktest]
+    fn test_atomic_write() {
+        let channel = Channel::with_capacity_and_flags(16, StatusFlags::O_NONBLOCK).unwrap();
+        let (producer, consumer) = channel.split();
+
+        assert_eq!(producer.write(&[1u8; 10]).unwrap(), 10);
+        // The small write does not fit, so nothing is written.
+        assert!(producer.write(&[2u8; 10]).is_err());
+        // The large write cannot be atomic, so it writes as much as possible.
+        assert_eq!(producer.write(&[3u8; 20]).unwrap(), 6);
+
+        let mut buf = [0u8; 16];
+        assert_eq!(consumer.peek(&mut buf), 16);
+        assert_eq!(consumer.skip(10), 10);
+        assert_eq!(consumer.read(&mut buf).unwrap(), 6);
+        assert_eq!(buf[..6], [3u8; 6]);
+    }
 }
@@ -21,5 +21,7 @@ bitflags! {
         /// create an unnamed temporary regular file
         /// O_TMPFILE is (_O_TMPFILE | O_DIRECTORY)
         const _O_TMPFILE = 1 << 22;
+        /// close on fork
+        const O_CLOFORK = 1 << 23;
     }
 }
@@ -0,0 +1,23 @@
+// SPDX-License-Identifier: MPL-2.0
+
+use bitflags::bitflags;
+
+bitflags! {
+    /// The modes of `fallocate`.
+    pub struct FallocMode: u32 {
+        /// keep the file size unchanged
+        const KEEP_SIZE = 1 << 0;
+        /// deallocate the range, which is read as zeros
+        const PUNCH_HOLE = 1 << 1;
+        /// reserved, no longer supported by Linux
+        const NO_HIDE_STALE = 1 << 2;
+        /// remove the range without leaving a hole
+        const COLLAPSE_RANGE = 1 << 3;
+        /// zero the range, which is allocated
+        const ZERO_RANGE = 1 << 4;
+        /// insert a hole without overwriting the existing data
+        const INSERT_RANGE = 1 << 5;
+        /// unshare the shared blocks of the range
+        const UNSHARE_RANGE = 1 << 6;
+    }
+}
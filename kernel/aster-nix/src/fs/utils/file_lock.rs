@@ -0,0 +1,325 @@
+// SPDX-License-Identifier: MPL-2.0
+
+//! Advisory file locks.
+//!
+//! Like Linux, there are two kinds of locks, which do not interact with each other:
+//! - The POSIX record locks set by `fcntl`, which lock byte ranges of a file and are
+//!   owned by processes. All the locks of a process on a file are released once the
+//!   process closes any file descriptor of the file.
+//! - The `flock` locks, which lock whole files and are owned by open files. The lock of
+//!   an open file is released once all the file descriptors of it are closed.
+//!
+//! The locks of an inode are kept in a table keyed by the inode, since the inodes are
+//! implemented by each file system.
+
+use core::ops::Range;
+
+use keyable_arc::KeyableWeak;
+
+use super::Inode;
+use crate::{
+    prelude::*,
+    process::{signal::Pauser, Pid},
+};
+
+/// The maximum length of the chain of the waiting owners to check for deadlocks, which
+/// is the same as that in Linux.
+const MAX_DEADLOCK_DEPTH: usize = 10;
+
+/// The locks of the inodes, which are removed once unused.
+static FILE_LOCKS: Mutex<BTreeMap<KeyableWeak<dyn Inode>, Arc<FileLocks>>> =
+    Mutex::new(BTreeMap::new());
+/// The owners of the POSIX locks that are waiting for the locks of other owners.
+static WAITS_FOR: Mutex<BTreeMap<Pid, Pid>> = Mutex::new(BTreeMap::new());
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FileLockType {
+    ReadLock,
+    WriteLock,
+    Unlock,
+}
+
+impl FileLockType {
+    fn conflicts_with(&self, other: &Self) -> bool {
+        *self == Self::WriteLock || *other == Self::WriteLock
+    }
+}
+
+/// A POSIX record lock.
+#[derive(Debug, Clone)]
+pub struct PosixLock {
+    pub type_: FileLockType,
+    /// The locked bytes, where the end of `usize::MAX` means to the end of the file.
+    pub range: Range<usize>,
+    pub owner: Pid,
+}
+
+impl PosixLock {
+    fn overlaps(&self, range: &Range<usize>) -> bool {
+        self.range.start < range.end && range.start < self.range.end
+    }
+
+    fn conflicts_with(&self, other: &PosixLock) -> bool {
+        self.owner != other.owner
+            && self.overlaps(&other.range)
+            && self.type_.conflicts_with(&other.type_)
+    }
+}
+
+/// A `flock` lock.
+#[derive(Debug)]
+struct Flock {
+    type_: FileLockType,
+    owner: usize,
+}
+
+struct FileLocks {
+    inner: Mutex<FileLocksInner>,
+    pauser: Arc<Pauser>,
+}
+
+#[derive(Default)]
+struct FileLocksInner {
+    posix_locks: Vec<PosixLock>,
+    flocks: Vec<Flock>,
+}
+
+/// Returns the POSIX lock that conflicts with the lock, if any.
+pub fn get_posix_lock(inode: &Arc<dyn Inode>, lock: &PosixLock) -> Option<PosixLock> {
+    let locks = FILE_LOCKS.lock().get(&key_of(inode)).cloned()?;
+    let inner = locks.inner.lock();
+    inner
+        .posix_locks
+        .iter()
+        .find(|other| other.conflicts_with(lock))
+        .cloned()
+}
+
+/// Sets the POSIX lock, or unlocks the range for the owner if the lock type is
+/// [`FileLockType::Unlock`].
+///
+/// The existing locks of the owner in the range are replaced. If the lock conflicts
+/// with the locks of other owners, this waits for them to be released if `is_blocking`
+/// is true, unless the wait would deadlock.
+pub fn set_posix_lock(inode: &Arc<dyn Inode>, lock: PosixLock, is_blocking: bool) -> Result<()> {
+    with_locks(inode, |locks| {
+        if lock.type_ == FileLockType::Unlock {
+            locks
+                .inner
+                .lock()
+                .unlock_posix_range(lock.owner, &lock.range);
+            locks.pauser.resume_all();
+            return Ok(());
+        }
+
+        let result = locks.pauser.pause_until(|| {
+            let mut inner = locks.inner.lock();
+            let blocker = inner
+                .posix_locks
+                .iter()
+                .find(|other| other.conflicts_with(&lock))
+                .map(|other| other.owner);
+            let Some(blocker) = blocker else {
+                inner.insert_posix_lock(lock.clone());
+                return Some(Ok(()));
+            };
+            if !is_blocking {
+                return Some(Err(Error::with_message(
+                    Errno::EAGAIN,
+                    "the range is locked by others",
+                )));
+            }
+            let mut waits_for = WAITS_FOR.lock();
+            if would_deadlock(&waits_for, lock.owner, blocker) {
+                return Some(Err(Error::with_message(
+                    Errno::EDEADLK,
+                    "waiting for the lock would deadlock",
+                )));
+            }
+            waits_for.insert(lock.owner, blocker);
+            None
+        });
+        WAITS_FOR.lock().remove(&lock.owner);
+        // Other owners may wait for the locks that are replaced by the new lock.
+        locks.pauser.resume_all();
+        result?
+    })
+}
+
+/// Releases all the POSIX locks of the owner.
+pub fn release_posix_locks(inode: &Arc<dyn Inode>, owner: Pid) {
+    release_with(inode, |inner| {
+        inner.posix_locks.retain(|lock| lock.owner != owner);
+    });
+}
+
+/// Sets the `flock` lock of the owner, or unlocks it if the lock type is
+/// [`FileLockType::Unlock`].
+///
+/// Like Linux, converting a lock is not atomic, i.e., the existing lock is released
+/// before the new lock is set.
+pub fn set_flock(
+    inode: &Arc<dyn Inode>,
+    owner: usize,
+    type_: FileLockType,
+    is_blocking: bool,
+) -> Result<()> {
+    with_locks(inode, |locks| {
+        {
+            let mut inner = locks.inner.lock();
+            if inner
+                .flocks
+                .iter()
+                .any(|flock| flock.owner == owner && flock.type_ == type_)
+            {
+                return Ok(());
+            }
+            inner.flocks.retain(|flock| flock.owner != owner);
+        }
+        locks.pauser.resume_all();
+        if type_ == FileLockType::Unlock {
+            return Ok(());
+        }
+
+        locks.pauser.pause_until(|| {
+            let mut inner = locks.inner.lock();
+            if !inner
+                .flocks
+                .iter()
+                .any(|flock| flock.type_.conflicts_with(&type_))
+            {
+                inner.flocks.push(Flock { type_, owner });
+                return Some(Ok(()));
+            }
+            if !is_blocking {
+                return Some(Err(Error::with_message(
+                    Errno::EAGAIN,
+                    "the file is locked by others",
+                )));
+            }
+            None
+        })?
+    })
+}
+
+/// Releases the `flock` lock of the owner.
+pub fn release_flock(inode: &Arc<dyn Inode>, owner: usize) {
+    release_with(inode, |inner| {
+        inner.flocks.retain(|flock| flock.owner != owner);
+    });
+}
+
+fn key_of(inode: &Arc<dyn Inode>) -> KeyableWeak<dyn Inode> {
+    KeyableWeak::from(Arc::downgrade(inode))
+}
+
+/// Runs the closure with the locks of the inode, which are removed from the table
+/// afterwards if they are unused.
+fn with_locks<R>(inode: &Arc<dyn Inode>, f: impl FnOnce(&Arc<FileLocks>) -> R) -> R {
+    let key = key_of(inode);
+    let locks = FILE_LOCKS
+        .lock()
+        .entry(key.clone())
+        .or_insert_with(|| Arc::new(FileLocks::new()))
+        .clone();
+    let result = f(&locks);
+    drop(locks);
+    remove_if_unused(key);
+    result
+}
+
+/// Releases some locks of the inode with the closure, if the inode has any locks.
+fn release_with(inode: &Arc<dyn Inode>, f: impl FnOnce(&mut FileLocksInner)) {
+    let key = key_of(inode);
+    let Some(locks) = FILE_LOCKS.lock().get(&key).cloned() else {
+        return;
+    };
+    f(&mut locks.inner.lock());
+    locks.pauser.resume_all();
+    drop(locks);
+    remove_if_unused(key);
+}
+
+fn remove_if_unused(key: KeyableWeak<dyn Inode>) {
+    let mut file_locks = FILE_LOCKS.lock();
+    // The locks are in use if some threads are waiting for them.
+    if let Some(locks) = file_locks.get(&key)
+        && Arc::strong_count(locks) == 1
+        && locks.inner.lock().is_empty()
+    {
+        file_locks.remove(&key);
+    }
+}
+
+/// Returns whether the owner would deadlock if it waits for the blocker, i.e., the
+/// blocker is waiting for the owner, directly or indirectly.
+fn would_deadlock(waits_for: &BTreeMap<Pid, Pid>, owner: Pid, blocker: Pid) -> bool {
+    let mut current = blocker;
+    for _ in 0..MAX_DEADLOCK_DEPTH {
+        if current == owner {
+            return true;
+        }
+        match waits_for.get(&current) {
+            Some(next) => current = *next,
+            None => return false,
+        }
+    }
+    false
+}
+
+impl FileLocks {
+    fn new() -> Self {
+        Self {
+            inner: Mutex::new(FileLocksInner::default()),
+            pauser: Pauser::new(),
+        }
+    }
+}
+
+impl FileLocksInner {
+    fn is_empty(&self) -> bool {
+        self.posix_locks.is_empty() && self.flocks.is_empty()
+    }
+
+    /// Removes the range from the locks of the owner, which may split a lock into two.
+    fn unlock_posix_range(&mut self, owner: Pid, range: &Range<usize>) {
+        let mut remaining_locks = Vec::with_capacity(self.posix_locks.len() + 1);
+        for lock in self.posix_locks.drain(..) {
+            if lock.owner != owner || !lock.overlaps(range) {
+                remaining_locks.push(lock);
+                continue;
+            }
+            if lock.range.start < range.start {
+                remaining_locks.push(PosixLock {
+                    range: lock.range.start..range.start,
+                    ..lock
+                });
+            }
+            if range.end < lock.range.end {
+                remaining_locks.push(PosixLock {
+                    range: range.end..lock.range.end,
+                    ..lock
+                });
+            }
+        }
+        self.posix_locks = remaining_locks;
+    }
+
+    /// Inserts the lock, which replaces the locks of the owner in its range, and is
+    /// merged with the adjacent locks of the owner of the same type.
+    fn insert_posix_lock(&mut self, mut lock: PosixLock) {
+        self.unlock_posix_range(lock.owner, &lock.range);
+        self.posix_locks.retain(|other| {
+            let is_mergeable = other.owner == lock.owner
+                && other.type_ == lock.type_
+                && other.range.start <= lock.range.end
+                && lock.range.start <= other.range.end;
+            if is_mergeable {
+                lock.range =
+                    other.range.start.min(lock.range.start)..other.range.end.max(lock.range.end);
+            }
+            !is_mergeable
+        });
+        self.posix_locks.push(lock);
+    }
+}
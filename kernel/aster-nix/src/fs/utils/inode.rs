@@ -2,18 +2,23 @@
 
 #![allow(unused_variables)]
 
-use core::time::Duration;
+use core::{ops::Range, time::Duration};
 
+use aster_frame::mm::Frame;
 use aster_rights::Full;
 use core2::io::{Error as IoError, ErrorKind as IoErrorKind, Result as IoResult, Write};
 
-use super::{DirentVisitor, FileSystem, IoctlCmd};
+use super::{
+    posix_acl, AccessMode, DirentVisitor, FallocMode, FileSystem, IoctlCmd, XattrSetFlags,
+};
 use crate::{
     events::IoEvents,
     fs::device::{Device, DeviceType},
     prelude::*,
-    process::{signal::Poller, Gid, Uid},
-    vm::vmo::Vmo,
+    process::{
+        credentials::capabilities::CapSet, posix_thread::PosixThreadExt, signal::Poller, Gid, Uid,
+    },
+    vm::{vmar::SharedMem, vmo::Vmo},
 };
 
 #[repr(u32)]
@@ -117,6 +122,19 @@ impl InodeMode {
     }
 }
 
+bitflags! {
+    /// The permissions to access an inode, which are also those in the file mode
+    /// and the POSIX ACLs.
+    pub struct Permission: u16 {
+        /// execute/search
+        const MAY_EXEC = 0o1;
+        /// write
+        const MAY_WRITE = 0o2;
+        /// read
+        const MAY_READ = 0o4;
+    }
+}
+
 #[derive(Debug, Clone, Copy)]
 pub struct Metadata {
     pub dev: u64,
@@ -262,10 +280,82 @@ pub trait Inode: Any + Sync + Send {
 
     fn set_mtime(&self, time: Duration);
 
+    /// Prepares the inode before it is opened with the access mode.
+    ///
+    /// A stacked file system can get the file ready to be written here, e.g., by copying
+    /// it up, so that the page cache is that of the file to be written.
+    fn open(&self, access_mode: AccessMode) -> Result<()> {
+        Ok(())
+    }
+
     fn page_cache(&self) -> Option<Vmo<Full>> {
         None
     }
 
+    /// Returns the shared memory object to map the file with `MAP_SHARED`.
+    ///
+    /// If it is `None`, the shared mappings are backed by the page cache directly.
+    fn shared_mem(&self) -> Option<Arc<SharedMem>> {
+        None
+    }
+
+    /// Reads the data within the range into the page cache ahead of time.
+    fn readahead(&self, range: Range<usize>) -> Result<()> {
+        Ok(())
+    }
+
+    /// Writes back the cached data within the range and drops them from the page cache.
+    fn drop_cache(&self, range: Range<usize>) -> Result<()> {
+        Ok(())
+    }
+
+    /// Makes the cached data within the range the first to be reclaimed.
+    fn demote_cache(&self, range: Range<usize>) {}
+
+    /// Allocates the space of the range, or deallocates it if the mode contains
+    /// [`FallocMode::PUNCH_HOLE`].
+    fn fallocate(&self, mode: FallocMode, offset: usize, len: usize) -> Result<()> {
+        return_errno_with_message!(Errno::EOPNOTSUPP, "fallocate is not supported");
+    }
+
+    /// Returns the offset of the first data at or after the offset.
+    ///
+    /// The file systems without holes regard the whole file as data.
+    fn seek_data(&self, offset: usize) -> Result<usize> {
+        if offset >= self.size() {
+            return_errno_with_message!(Errno::ENXIO, "the offset is beyond the end of the file");
+        }
+        Ok(offset)
+    }
+
+    /// Returns the offset of the first hole at or after the offset, where the end of
+    /// the file is regarded as a hole.
+    fn seek_hole(&self, offset: usize) -> Result<usize> {
+        if offset >= self.size() {
+            return_errno_with_message!(Errno::ENXIO, "the offset is beyond the end of the file");
+        }
+        Ok(self.size())
+    }
+
+    /// Returns the value of the extended attribute.
+    fn get_xattr(&self, name: &str) -> Result<Vec<u8>> {
+        return_errno_with_message!(Errno::EOPNOTSUPP, "extended attributes are not supported");
+    }
+
+    /// Sets the value of the extended attribute, whose name has been validated.
+    fn set_xattr(&self, name: &str, value: &[u8], flags: XattrSetFlags) -> Result<()> {
+        return_errno_with_message!(Errno::EOPNOTSUPP, "extended attributes are not supported");
+    }
+
+    /// Returns the names of the extended attributes.
+    fn list_xattr(&self) -> Result<Vec<String>> {
+        return_errno_with_message!(Errno::EOPNOTSUPP, "extended attributes are not supported");
+    }
+
+    fn remove_xattr(&self, name: &str) -> Result<()> {
+        return_errno_with_message!(Errno::EOPNOTSUPP, "extended attributes are not supported");
+    }
+
     fn read_at(&self, offset: usize, buf: &mut [u8]) -> Result<usize> {
         Err(Error::new(Errno::EISDIR))
     }
@@ -278,6 +368,24 @@ pub trait Inode: Any + Sync + Send {
         Err(Error::new(Errno::EISDIR))
     }
 
+    /// Reads the page of the file at the index to the frame directly, bypassing the page
+    /// cache.
+    ///
+    /// Unlike [`Inode::read_direct_at`], the page is read to the frame without any
+    /// buffers, so this can be used under memory pressure, e.g., by swapping.
+    fn read_page_direct(&self, idx: usize, frame: &Frame) -> Result<()> {
+        return_errno_with_message!(Errno::EINVAL, "direct page I/O is not supported");
+    }
+
+    /// Writes the frame to the page of the file at the index directly, bypassing the
+    /// page cache.
+    ///
+    /// Like [`Inode::read_page_direct`], this does not allocate any buffers. The page must
+    /// have been allocated, e.g., by writing the file.
+    fn write_page_direct(&self, idx: usize, frame: &Frame) -> Result<()> {
+        return_errno_with_message!(Errno::EINVAL, "direct page I/O is not supported");
+    }
+
     fn write_direct_at(&self, offset: usize, buf: &[u8]) -> Result<usize> {
         Err(Error::new(Errno::EISDIR))
     }
@@ -290,6 +398,12 @@ pub trait Inode: Any + Sync + Send {
         Err(Error::new(Errno::ENOTDIR))
     }
 
+    /// Creates an unnamed regular file in the directory, which is removed when it is
+    /// no longer used unless it is linked to a directory.
+    fn create_tmpfile(&self, mode: InodeMode) -> Result<Arc<dyn Inode>> {
+        Err(Error::new(Errno::EOPNOTSUPP))
+    }
+
     fn as_device(&self) -> Option<Arc<dyn Device>> {
         None
     }
@@ -392,6 +506,60 @@ impl dyn Inode {
         self.read_direct_at(0, &mut buf[..file_size])
     }
 
+    /// Checks whether the current thread has the permission to access the inode.
+    ///
+    /// The permission is granted by the access ACL of the inode if any, or by the file
+    /// mode otherwise. Like Linux, `CAP_DAC_OVERRIDE` bypasses the check except for
+    /// executing a file without any execute bits, and `CAP_DAC_READ_SEARCH` bypasses
+    /// the check of reading files and reading or searching directories.
+    pub fn check_permission(&self, perm: Permission) -> Result<()> {
+        let current_thread = current_thread!();
+        // The kernel threads are always permitted.
+        let Some(posix_thread) = current_thread.as_posix_thread() else {
+            return Ok(());
+        };
+        let credentials = posix_thread.credentials();
+        let mode = self.mode()?;
+        let (owner, group) = (self.owner()?, self.group()?);
+        let fsuid = credentials.fsuid();
+        let in_group = |gid: Gid| gid == credentials.fsgid() || credentials.groups().contains(&gid);
+
+        let is_permitted = match posix_acl::access_acl(self) {
+            Some(acl) => acl.permits(fsuid, in_group, owner, group, perm),
+            None => {
+                let shift = if fsuid == owner {
+                    6
+                } else if in_group(group) {
+                    3
+                } else {
+                    0
+                };
+                Permission::from_bits_truncate(mode.bits() >> shift).contains(perm)
+            }
+        };
+        if is_permitted {
+            return Ok(());
+        }
+
+        let capset = credentials.effective_capset();
+        let is_dir = self.type_() == InodeType::Dir;
+        let is_executable = mode.bits() & 0o111 != 0;
+        if capset.contains(CapSet::DAC_OVERRIDE)
+            && (!perm.contains(Permission::MAY_EXEC) || is_dir || is_executable)
+        {
+            return Ok(());
+        }
+        let read_search = if is_dir {
+            Permission::MAY_READ | Permission::MAY_EXEC
+        } else {
+            Permission::MAY_READ
+        };
+        if capset.contains(CapSet::DAC_READ_SEARCH) && read_search.contains(perm) {
+            return Ok(());
+        }
+        return_errno_with_message!(Errno::EACCES, "permission denied");
+    }
+
     pub fn writer(&self, from_offset: usize) -> InodeWriter {
         InodeWriter {
             inner: self,
@@ -31,6 +31,32 @@ pub enum IoctlCmd {
     TIOCSPTLCK = 0x40045431,
     /// Safely open the slave
     TIOCGPTPEER = 0x40045441,
+    /// Get the address of a network interface
+    SIOCGIFADDR = 0x8915,
+    /// Set the address of a network interface
+    SIOCSIFADDR = 0x8916,
+    /// Get the netmask of a network interface
+    SIOCGIFNETMASK = 0x891b,
+    /// Set the netmask of a network interface
+    SIOCSIFNETMASK = 0x891c,
+    /// Get the variable information of a framebuffer, e.g., the resolution
+    FBIOGET_VSCREENINFO = 0x4600,
+    /// Set the variable information of a framebuffer
+    FBIOPUT_VSCREENINFO = 0x4601,
+    /// Get the fixed information of a framebuffer, e.g., the length of a line
+    FBIOGET_FSCREENINFO = 0x4602,
     /// Get tdx report using TDCALL
     TDXGETREPORT = 0xc4405401,
+    /// Enable the userfaultfd API
+    UFFDIO_API = 0xc018aa3f,
+    /// Register a memory range for userfaultfd
+    UFFDIO_REGISTER = 0xc020aa00,
+    /// Unregister a memory range from userfaultfd
+    UFFDIO_UNREGISTER = 0x8010aa01,
+    /// Wake up the threads waiting for the page faults in a memory range
+    UFFDIO_WAKE = 0x8010aa02,
+    /// Resolve the page faults in a memory range by copying pages
+    UFFDIO_COPY = 0xc028aa03,
+    /// Resolve the page faults in a memory range with zero pages
+    UFFDIO_ZEROPAGE = 0xc020aa04,
 }
@@ -3,30 +3,49 @@
 //! VFS components
 
 pub use access_mode::AccessMode;
-pub use channel::{Channel, Consumer, Producer};
+pub use channel::{Channel, Consumer, Producer, PIPE_BUF};
 pub use creation_flags::CreationFlags;
 pub use dirent_visitor::DirentVisitor;
 pub use direntry_vec::DirEntryVecExt;
+pub use falloc_mode::FallocMode;
 pub use file_creation_mask::FileCreationMask;
+pub use file_lock::{FileLockType, PosixLock};
 pub use fs::{FileSystem, FsFlags, SuperBlock};
-pub use inode::{Inode, InodeMode, InodeType, Metadata};
+pub use inode::{Inode, InodeMode, InodeType, Metadata, Permission};
 pub use ioctl::IoctlCmd;
-pub use page_cache::{PageCache, PageCacheBackend};
+pub use page_cache::{nr_cached_pages, PageCache, PageCacheBackend};
+pub use posix_acl::PosixAcl;
 pub use random_test::{generate_random_operation, new_fs_in_memory};
+pub use readahead::{read_ahead_kb, set_read_ahead_kb, AccessPattern, FileAdvice, ReadaheadState};
 pub use status_flags::StatusFlags;
+pub(super) use writeback::spawn_flusher_thread;
+pub use writeback::{
+    dirty_background_ratio, dirty_expire_centisecs, nr_dirty_pages, set_dirty_background_ratio,
+    set_dirty_expire_centisecs, set_writeback_interval_centisecs, writeback_interval_centisecs,
+};
+pub use xattr::{
+    XattrNamespace, XattrSetFlags, Xattrs, XATTR_LIST_MAX_LEN, XATTR_NAME_MAX_LEN,
+    XATTR_NAME_POSIX_ACL_ACCESS, XATTR_NAME_POSIX_ACL_DEFAULT, XATTR_VALUE_MAX_LEN,
+};
 
 mod access_mode;
 mod channel;
 mod creation_flags;
 mod dirent_visitor;
 mod direntry_vec;
+mod falloc_mode;
 mod file_creation_mask;
+pub mod file_lock;
 mod fs;
 mod inode;
 mod ioctl;
 mod page_cache;
+pub mod posix_acl;
 mod random_test;
+mod readahead;
 mod status_flags;
+mod writeback;
+mod xattr;
 
 use crate::prelude::*;
 
@@ -35,6 +54,10 @@ pub enum SeekFrom {
     Start(usize),
     End(isize),
     Current(isize),
+    /// The next data at or after the offset, which is `SEEK_DATA`.
+    Data(usize),
+    /// The next hole at or after the offset, which is `SEEK_HOLE`.
+    Hole(usize),
 }
 
 /// Maximum bytes in a path
@@ -1,31 +1,42 @@
 // SPDX-License-Identifier: MPL-2.0
 
-use core::ops::Range;
+use core::{
+    ops::Range,
+    sync::atomic::{AtomicUsize, Ordering},
+    time::Duration,
+};
 
 use aster_block::bio::{BioStatus, BioWaiter};
-use aster_frame::mm::{Frame, FrameAllocOptions};
+use aster_frame::mm::{
+    reclaim::{register_shrinker, Shrinker},
+    Frame, FrameAllocOptions, PageFlags,
+};
 use aster_rights::Full;
 use lru::LruCache;
 
+use super::writeback;
 use crate::{
     prelude::*,
-    vm::vmo::{get_page_idx_range, Pager, Vmo, VmoFlags, VmoOptions},
+    vm::{
+        swap,
+        vmar::SharedMem,
+        vmo::{get_page_idx_range, Pager, Vmo, VmoFlags, VmoOptions, VmoRightsOp},
+    },
 };
 
 pub struct PageCache {
     pages: Vmo<Full>,
     manager: Arc<PageCacheManager>,
+    /// The shared memory object for the shared mappings of the cached pages.
+    shared_mem: Arc<SharedMem>,
+    /// Keeps the shrinker registered as long as the page cache is alive.
+    _shrinker: Arc<PageCacheShrinker>,
 }
 
 impl PageCache {
     /// Creates an empty size page cache associated with a new backend.
     pub fn new(backend: Weak<dyn PageCacheBackend>) -> Result<Self> {
-        let manager = Arc::new(PageCacheManager::new(backend));
-        let pages = VmoOptions::<Full>::new(0)
-            .flags(VmoFlags::RESIZABLE)
-            .pager(manager.clone())
-            .alloc()?;
-        Ok(Self { pages, manager })
+        Self::with_capacity(0, backend)
     }
 
     /// Creates a page cache associated with an existing backend.
@@ -33,12 +44,44 @@ impl PageCache {
     /// The `capacity` is the initial cache size required by the backend.
     /// This size usually corresponds to the size of the backend.
     pub fn with_capacity(capacity: usize, backend: Weak<dyn PageCacheBackend>) -> Result<Self> {
-        let manager = Arc::new(PageCacheManager::new(backend));
+        Self::new_inner(capacity, backend, false)
+    }
+
+    /// Creates an empty page cache whose backend only holds the pages that are reclaimed,
+    /// e.g., in the swap areas for tmpfs.
+    ///
+    /// The dirty pages are neither written back by the flusher nor on sync. Instead, they
+    /// are written to the backend only when they are reclaimed under memory pressure, and
+    /// are kept in memory if the backend fails to hold them.
+    pub fn new_swap_backed(backend: Weak<dyn PageCacheBackend>) -> Result<Self> {
+        Self::new_inner(0, backend, true)
+    }
+
+    fn new_inner(
+        capacity: usize,
+        backend: Weak<dyn PageCacheBackend>,
+        is_swap_backed: bool,
+    ) -> Result<Self> {
+        let manager = Arc::new(PageCacheManager::new(backend, is_swap_backed));
+        if !is_swap_backed {
+            writeback::register_page_cache(Arc::downgrade(&manager));
+        }
         let pages = VmoOptions::<Full>::new(capacity)
             .flags(VmoFlags::RESIZABLE)
             .pager(manager.clone())
             .alloc()?;
-        Ok(Self { pages, manager })
+        let shrinker = Arc::new(PageCacheShrinker {
+            pages: pages.dup(),
+            manager: manager.clone(),
+        });
+        register_shrinker(Arc::downgrade(&shrinker) as _);
+        let shared_mem = SharedMem::from_vmo(pages.dup().to_dyn());
+        Ok(Self {
+            pages,
+            manager,
+            shared_mem,
+            _shrinker: shrinker,
+        })
     }
 
     /// Returns the Vmo object.
@@ -48,6 +91,11 @@ impl PageCache {
         &self.pages
     }
 
+    /// Returns the shared memory object, through which the pages are mapped with `MAP_SHARED`.
+    pub fn shared_mem(&self) -> &Arc<SharedMem> {
+        &self.shared_mem
+    }
+
     /// Evict the data within a specified range from the page cache and persist
     /// them to the backend.
     pub fn evict_range(&self, range: Range<usize>) -> Result<()> {
@@ -60,6 +108,36 @@ impl PageCache {
         self.manager.discard_range(range)
     }
 
+    /// Reads the data within a specified range from the backend into the page cache
+    /// ahead of time.
+    ///
+    /// The pages that are not cached yet are read with a batch of asynchronous I/O
+    /// requests, which is much faster than committing them one by one on demand.
+    pub fn readahead(&self, range: Range<usize>) -> Result<()> {
+        self.manager.readahead(range)
+    }
+
+    /// Persist the data within a specified range to the backend and drop them from
+    /// the page cache.
+    ///
+    /// The pages that are in use, e.g., mapped to user space, are kept in the page cache.
+    pub fn drop_range(&self, range: Range<usize>) -> Result<()> {
+        let range = range.start..range.end.min(self.pages.size());
+        self.manager.evict_range(range.clone())?;
+        for idx in get_page_idx_range(&range) {
+            if self.manager.is_page_up_to_date(idx) {
+                try_evict_unused_page(&self.pages, &self.manager, idx);
+            }
+        }
+        Ok(())
+    }
+
+    /// Makes the pages within a specified range the first to be reclaimed under
+    /// memory pressure.
+    pub fn demote_range(&self, range: Range<usize>) {
+        self.manager.demote_range(range)
+    }
+
     /// Returns the backend.
     pub fn backend(&self) -> Arc<dyn PageCacheBackend> {
         self.manager.backend()
@@ -85,19 +163,122 @@ impl Debug for PageCache {
     }
 }
 
-struct PageCacheManager {
+/// Reclaims clean pages of a page cache under memory pressure.
+struct PageCacheShrinker {
+    pages: Vmo<Full>,
+    manager: Arc<PageCacheManager>,
+}
+
+impl Shrinker for PageCacheShrinker {
+    fn nr_reclaimable(&self) -> usize {
+        let pages = self.manager.pages.lock();
+        if !self.manager.is_swap_backed {
+            return pages.len();
+        }
+        let nr_dirty = pages
+            .iter()
+            .filter(|(_, page)| matches!(page.state(), PageState::Dirty))
+            .count();
+        pages.len() - nr_dirty + nr_dirty.min(swap::nr_free_slots())
+    }
+
+    fn shrink(&self, nr_to_reclaim: usize) -> usize {
+        let is_swap_backed = self.manager.is_swap_backed;
+        // Pick the least recently used pages that are consistent with the backend,
+        // so that they can be dropped without any I/O. The dirty pages of a swap-backed
+        // page cache are picked as well, since they are never written back otherwise.
+        let candidates: Vec<usize> = self
+            .manager
+            .pages
+            .lock()
+            .iter()
+            .rev()
+            .filter(|(_, page)| match page.state() {
+                PageState::UpToDate => true,
+                PageState::Dirty => is_swap_backed,
+                PageState::Uninit => false,
+            })
+            .map(|(idx, _)| *idx)
+            .take(nr_to_reclaim)
+            .collect();
+
+        let nr_reclaimed = candidates
+            .into_iter()
+            .filter(|idx| {
+                if is_swap_backed
+                    && !self.manager.is_page_up_to_date(*idx)
+                    && self.manager.write_pages([*idx]).is_err()
+                {
+                    return false;
+                }
+                try_evict_unused_page(&self.pages, &self.manager, *idx)
+            })
+            .count();
+
+        // The dirty pages can be reclaimed only after being written back, which is left
+        // to the flusher rather than done in the reclaim path.
+        if !is_swap_backed && nr_reclaimed < nr_to_reclaim && self.manager.dirtied_at().is_some() {
+            writeback::wake_flusher();
+        }
+        nr_reclaimed
+    }
+}
+
+/// Evicts a page from the page cache if it is not being accessed.
+///
+/// The page is unmapped from user space first, unless it has been accessed via the
+/// mappings since the last scan. If it has been written via the mappings, it is kept
+/// and marked dirty instead, so that it is written back before being evicted.
+fn try_evict_unused_page(pages: &Vmo<Full>, manager: &PageCacheManager, idx: usize) -> bool {
+    // The LRU list does not see the accesses via the mappings, so the pages accessed in
+    // this way are given a second chance.
+    if !matches!(pages.try_clear_accessed(idx), Ok(false)) {
+        return false;
+    }
+    match pages.try_unmap_page(idx) {
+        Ok(flags) if flags.contains(PageFlags::DIRTY) => {
+            let _ = manager.update_page(idx);
+            return false;
+        }
+        Ok(_) => {}
+        Err(_) => return false,
+    }
+    // A page that is only referenced by the VMO and the page cache itself
+    // is neither mapped to user space nor being accessed.
+    pages.try_evict_page(idx, |frame| frame.reference_count() == 2)
+}
+
+pub(super) struct PageCacheManager {
     pages: Mutex<LruCache<usize, Page>>,
     backend: Weak<dyn PageCacheBackend>,
+    /// The time since boot when the page cache became dirty, or `None` if it is clean.
+    dirtied_at: SpinLock<Option<Duration>>,
+    /// Whether the dirty pages are written to the backend only when they are reclaimed.
+    is_swap_backed: bool,
 }
 
 impl PageCacheManager {
-    pub fn new(backend: Weak<dyn PageCacheBackend>) -> Self {
+    pub fn new(backend: Weak<dyn PageCacheBackend>, is_swap_backed: bool) -> Self {
         Self {
             pages: Mutex::new(LruCache::unbounded()),
             backend,
+            dirtied_at: SpinLock::new(None),
+            is_swap_backed,
         }
     }
 
+    /// Returns the time since boot when the page cache became dirty.
+    ///
+    /// The pages dirtied via the mappings are only known when they are unmapped, so a
+    /// page cache may have dirty pages even if it returns `None`.
+    pub fn dirtied_at(&self) -> Option<Duration> {
+        *self.dirtied_at.lock()
+    }
+
+    fn mark_dirty(&self) {
+        self.dirtied_at.lock().get_or_insert_with(writeback::now);
+    }
+
     pub fn backend(&self) -> Arc<dyn PageCacheBackend> {
         self.backend.upgrade().unwrap()
     }
@@ -110,35 +291,132 @@ impl PageCacheManager {
         }
     }
 
-    pub fn evict_range(&self, range: Range<usize>) -> Result<()> {
+    pub fn readahead(&self, range: Range<usize>) -> Result<()> {
+        let backend = self.backend();
         let page_idx_range = get_page_idx_range(&range);
+        let page_idx_range = page_idx_range.start..page_idx_range.end.min(backend.npages());
+
+        let mut pages_and_waiters: Vec<(usize, Page, BioWaiter)> = Vec::new();
+        for idx in page_idx_range {
+            if self.pages.lock().contains(&idx) {
+                continue;
+            }
+            let page = Page::alloc()?;
+            let waiter = backend.read_page(idx, page.frame())?;
+            pages_and_waiters.push((idx, page, waiter));
+        }
+
+        let mut is_complete = true;
+        for (idx, mut page, waiter) in pages_and_waiters {
+            // Wait for all the requests even if some of them fail, since the frames are in use.
+            if !matches!(waiter.wait(), Some(BioStatus::Complete)) {
+                is_complete = false;
+                continue;
+            }
+            page.set_state(PageState::UpToDate);
+            // If the page has been committed in the meantime, the committed one is perserved.
+            self.pages.lock().get_or_insert(idx, || page);
+        }
+
+        if !is_complete {
+            return_errno!(Errno::EIO);
+        }
+        Ok(())
+    }
+
+    pub fn demote_range(&self, range: Range<usize>) {
+        let mut pages = self.pages.lock();
+        for idx in get_page_idx_range(&range) {
+            pages.demote(&idx);
+        }
+    }
+
+    fn is_page_up_to_date(&self, idx: usize) -> bool {
+        self.pages
+            .lock()
+            .peek(&idx)
+            .is_some_and(|page| matches!(page.state(), PageState::UpToDate))
+    }
+
+    pub fn evict_range(&self, range: Range<usize>) -> Result<()> {
+        if self.is_swap_backed {
+            return Ok(());
+        }
+        self.write_pages(get_page_idx_range(&range))
+    }
+
+    /// Writes all the dirty pages back to the backend.
+    pub fn write_back(&self) -> Result<()> {
+        if self.is_swap_backed {
+            return Ok(());
+        }
+        // The pages dirtied from now on make the page cache dirty again.
+        self.dirtied_at.lock().take();
+        let dirty_indices: Vec<usize> = self
+            .pages
+            .lock()
+            .iter()
+            .filter(|(_, page)| matches!(page.state(), PageState::Dirty))
+            .map(|(idx, _)| *idx)
+            .collect();
+        self.write_pages(dirty_indices)
+    }
+
+    fn write_pages(&self, indices: impl IntoIterator<Item = usize>) -> Result<()> {
+        // The flusher may write back a page cache whose backend is being dropped.
+        let Some(backend) = self.backend.upgrade() else {
+            return Ok(());
+        };
 
         //TODO: When there are many pages, we should submit them in batches of folios rather than all at once.
         let mut indices_and_waiters: Vec<(usize, BioWaiter)> = Vec::new();
+        let mut result = Ok(());
 
-        for idx in page_idx_range {
-            if let Some(page) = self.pages.lock().get_mut(&idx) {
-                if let PageState::Dirty = page.state() {
-                    let backend = self.backend();
-                    if idx < backend.npages() {
-                        indices_and_waiters.push((idx, backend.write_page(idx, page.frame())?));
-                    }
+        for idx in indices {
+            if idx >= backend.npages() {
+                continue;
+            }
+            // Peeking keeps the positions of the pages in the LRU list, since writing
+            // back is not an access.
+            let mut pages = self.pages.lock();
+            let Some(page) = pages.peek_mut(&idx) else {
+                continue;
+            };
+            if !matches!(page.state(), PageState::Dirty) {
+                continue;
+            }
+            // The page is marked up-to-date before the I/O, so that it is dirty again
+            // if it is written during the I/O.
+            page.set_state(PageState::UpToDate);
+            match backend.write_page(idx, page.frame()) {
+                Ok(waiter) => indices_and_waiters.push((idx, waiter)),
+                Err(err) => {
+                    page.set_state(PageState::Dirty);
+                    result = Err(err);
+                    break;
                 }
             }
         }
 
-        for (idx, waiter) in indices_and_waiters.iter() {
+        // Wait for all the submitted requests even if some of them fail, since the
+        // frames are in use.
+        for (idx, waiter) in indices_and_waiters {
             if matches!(waiter.wait(), Some(BioStatus::Complete)) {
-                if let Some(page) = self.pages.lock().get_mut(idx) {
-                    page.set_state(PageState::UpToDate)
-                }
-            } else {
-                // TODO: We may need an error handler here.
-                return_errno!(Errno::EIO)
+                continue;
+            }
+            // TODO: We may need an error handler here.
+            if let Some(page) = self.pages.lock().peek_mut(&idx) {
+                page.set_state(PageState::Dirty);
+            }
+            if result.is_ok() {
+                result = Err(Error::new(Errno::EIO));
             }
         }
 
-        Ok(())
+        if result.is_err() {
+            self.mark_dirty();
+        }
+        result
     }
 }
 
@@ -165,6 +443,7 @@ impl Pager for PageCacheManager {
 
             page
         } else {
+            self.mark_dirty();
             Page::alloc_zero()?
         };
 
@@ -176,6 +455,7 @@ impl Pager for PageCacheManager {
         let mut pages = self.pages.lock();
         if let Some(page) = pages.get_mut(&idx) {
             page.set_state(PageState::Dirty);
+            self.mark_dirty();
         } else {
             warn!("The page {} is not in page cache", idx);
         }
@@ -205,8 +485,21 @@ impl Pager for PageCacheManager {
         }
 
         let page = Page::alloc_zero()?;
+        self.mark_dirty();
         Ok(self.pages.lock().get_or_insert(idx, || page).frame.clone())
     }
+
+    fn prefetch(&self, idx_range: Range<usize>) -> Result<()> {
+        self.readahead(idx_range.start * PAGE_SIZE..idx_range.end * PAGE_SIZE)
+    }
+}
+
+/// The number of pages in all page caches.
+static NR_CACHED_PAGES: AtomicUsize = AtomicUsize::new(0);
+
+/// Returns the number of pages in all page caches.
+pub fn nr_cached_pages() -> usize {
+    NR_CACHED_PAGES.load(Ordering::Relaxed)
 }
 
 #[derive(Debug)]
@@ -217,7 +510,8 @@ struct Page {
 
 impl Page {
     pub fn alloc() -> Result<Self> {
-        let frame = FrameAllocOptions::new(1).uninit(true).alloc_single()?;
+        let frame = FrameAllocOptions::new(1).uninit(true).alloc_single_wait()?;
+        NR_CACHED_PAGES.fetch_add(1, Ordering::Relaxed);
         Ok(Self {
             frame,
             state: PageState::Uninit,
@@ -225,7 +519,9 @@ impl Page {
     }
 
     pub fn alloc_zero() -> Result<Self> {
-        let frame = FrameAllocOptions::new(1).alloc_single()?;
+        let frame = FrameAllocOptions::new(1).alloc_single_wait()?;
+        NR_CACHED_PAGES.fetch_add(1, Ordering::Relaxed);
+        writeback::inc_dirty_pages();
         Ok(Self {
             frame,
             state: PageState::Dirty,
@@ -241,10 +537,25 @@ impl Page {
     }
 
     pub fn set_state(&mut self, new_state: PageState) {
+        match (&self.state, &new_state) {
+            (PageState::Dirty, PageState::Dirty) => {}
+            (PageState::Dirty, _) => writeback::dec_dirty_pages(),
+            (_, PageState::Dirty) => writeback::inc_dirty_pages(),
+            _ => {}
+        }
         self.state = new_state;
     }
 }
 
+impl Drop for Page {
+    fn drop(&mut self) {
+        NR_CACHED_PAGES.fetch_sub(1, Ordering::Relaxed);
+        if let PageState::Dirty = self.state {
+            writeback::dec_dirty_pages();
+        }
+    }
+}
+
 #[derive(Debug)]
 enum PageState {
     /// `Uninit` indicates a new allocated page which content has not been initialized.
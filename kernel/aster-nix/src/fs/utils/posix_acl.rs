@@ -0,0 +1,293 @@
+// SPDX-License-Identifier: MPL-2.0
+
+//! POSIX access control lists (ACLs).
+//!
+//! An ACL grants permissions to named users and groups besides the owner, the group and
+//! the others of a file. The access ACL of an inode is kept in the extended attribute
+//! `system.posix_acl_access`, and the default ACL of a directory, which is inherited by
+//! the inodes created in it, is kept in `system.posix_acl_default`.
+//!
+//! The raw definitions are from https://elixir.bootlin.com/linux/v6.0.9/source/include/uapi/linux/posix_acl_xattr.h
+
+use super::{
+    Inode, InodeMode, InodeType, Permission, XattrSetFlags, XATTR_NAME_POSIX_ACL_ACCESS,
+    XATTR_NAME_POSIX_ACL_DEFAULT,
+};
+use crate::{
+    prelude::*,
+    process::{credentials, credentials::capabilities::CapSet, Gid, Uid},
+};
+
+/// The version of the ACLs in extended attributes.
+const POSIX_ACL_XATTR_VERSION: u32 = 2;
+const HEADER_LEN: usize = 4;
+const ENTRY_LEN: usize = 8;
+
+/// The tags of ACL entries, which are sorted in the order to be checked.
+#[repr(u16)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, TryFromInt)]
+pub enum AclTag {
+    UserObj = 0x01,
+    User = 0x02,
+    GroupObj = 0x04,
+    Group = 0x08,
+    Mask = 0x10,
+    Other = 0x20,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct AclEntry {
+    pub tag: AclTag,
+    pub perm: Permission,
+    /// The ID of the user or the group, which is only meaningful for
+    /// [`AclTag::User`] and [`AclTag::Group`].
+    pub id: u32,
+}
+
+/// A valid ACL, whose entries are sorted by their tags and IDs.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PosixAcl(Vec<AclEntry>);
+
+impl PosixAcl {
+    /// Parses and validates the ACL in the format of extended attributes.
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self> {
+        if bytes.len() < HEADER_LEN || (bytes.len() - HEADER_LEN) % ENTRY_LEN != 0 {
+            return_errno_with_message!(Errno::EINVAL, "invalid ACL size");
+        }
+        let version = u32::from_le_bytes(bytes[..HEADER_LEN].try_into().unwrap());
+        if version != POSIX_ACL_XATTR_VERSION {
+            return_errno_with_message!(Errno::EOPNOTSUPP, "unsupported ACL version");
+        }
+
+        let mut entries = Vec::with_capacity((bytes.len() - HEADER_LEN) / ENTRY_LEN);
+        for raw_entry in bytes[HEADER_LEN..].chunks_exact(ENTRY_LEN) {
+            let tag = AclTag::try_from(u16::from_le_bytes([raw_entry[0], raw_entry[1]]))?;
+            let perm = Permission::from_bits(u16::from_le_bytes([raw_entry[2], raw_entry[3]]))
+                .ok_or_else(|| Error::with_message(Errno::EINVAL, "invalid ACL permission"))?;
+            let id = match tag {
+                AclTag::User | AclTag::Group => {
+                    u32::from_le_bytes(raw_entry[4..].try_into().unwrap())
+                }
+                _ => u32::MAX,
+            };
+            entries.push(AclEntry { tag, perm, id });
+        }
+        Self::new(entries)
+    }
+
+    /// Creates an ACL from the entries, which must contain exactly one entry for the
+    /// owner, the group and the others, and a mask if there are named users or groups.
+    pub fn new(mut entries: Vec<AclEntry>) -> Result<Self> {
+        entries.sort_by_key(|entry| (entry.tag, entry.id));
+        let count = |tag| entries.iter().filter(|entry| entry.tag == tag).count();
+        let has_named = count(AclTag::User) + count(AclTag::Group) > 0;
+        let is_valid = count(AclTag::UserObj) == 1
+            && count(AclTag::GroupObj) == 1
+            && count(AclTag::Other) == 1
+            && count(AclTag::Mask) <= 1
+            && (count(AclTag::Mask) == 1 || !has_named)
+            && entries
+                .windows(2)
+                .all(|pair| (pair[0].tag, pair[0].id) != (pair[1].tag, pair[1].id));
+        if !is_valid {
+            return_errno_with_message!(Errno::EINVAL, "invalid ACL entries");
+        }
+        Ok(Self(entries))
+    }
+
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut bytes = Vec::with_capacity(HEADER_LEN + self.0.len() * ENTRY_LEN);
+        bytes.extend_from_slice(&POSIX_ACL_XATTR_VERSION.to_le_bytes());
+        for entry in self.0.iter() {
+            bytes.extend_from_slice(&(entry.tag as u16).to_le_bytes());
+            bytes.extend_from_slice(&entry.perm.bits().to_le_bytes());
+            bytes.extend_from_slice(&entry.id.to_le_bytes());
+        }
+        bytes
+    }
+
+    /// Returns whether the ACL can be represented by the mode only.
+    pub fn is_equiv_to_mode(&self) -> bool {
+        self.0.len() == 3
+    }
+
+    /// Returns the permission bits of the mode, where the group class is the mask if
+    /// there is one.
+    pub fn mode_bits(&self) -> u16 {
+        let perm_of = |tag| self.find(tag).map_or(0, |entry| entry.perm.bits());
+        let group_perm = self
+            .find(AclTag::Mask)
+            .map_or_else(|| perm_of(AclTag::GroupObj), |mask| mask.perm.bits());
+        perm_of(AclTag::UserObj) << 6 | group_perm << 3 | perm_of(AclTag::Other)
+    }
+
+    /// Updates the ACL with the permission bits of the mode, which is done by `chmod`.
+    pub fn chmod(&mut self, mode: InodeMode) {
+        let bits = mode.bits();
+        let has_mask = self.find(AclTag::Mask).is_some();
+        for entry in self.0.iter_mut() {
+            let shift = match entry.tag {
+                AclTag::UserObj => 6,
+                AclTag::GroupObj if !has_mask => 3,
+                AclTag::Mask => 3,
+                AclTag::Other => 0,
+                _ => continue,
+            };
+            entry.perm = Permission::from_bits_truncate((bits >> shift) & 0o7);
+        }
+    }
+
+    /// Restricts the inherited ACL with the mode of a new inode, and the mode with the ACL.
+    pub fn create_masq(&mut self, mode: InodeMode) -> InodeMode {
+        let bits = mode.bits();
+        let has_mask = self.find(AclTag::Mask).is_some();
+        for entry in self.0.iter_mut() {
+            let shift = match entry.tag {
+                AclTag::UserObj => 6,
+                AclTag::GroupObj if !has_mask => 3,
+                AclTag::Mask => 3,
+                AclTag::Other => 0,
+                _ => continue,
+            };
+            entry.perm &= Permission::from_bits_truncate((bits >> shift) & 0o7);
+        }
+        InodeMode::from_bits_truncate((bits & !0o777) | self.mode_bits())
+    }
+
+    /// Returns whether the ACL grants the permission to the user, which is checked in
+    /// the same way as Linux.
+    pub fn permits(
+        &self,
+        fsuid: Uid,
+        in_group: impl Fn(Gid) -> bool,
+        owner: Uid,
+        group: Gid,
+        perm: Permission,
+    ) -> bool {
+        let mask = self.find(AclTag::Mask).map(|entry| entry.perm);
+        let masked = |entry_perm: Permission| {
+            entry_perm.contains(perm) && mask.map_or(true, |mask| mask.contains(perm))
+        };
+
+        let mut is_group_found = false;
+        for entry in self.0.iter() {
+            match entry.tag {
+                AclTag::UserObj if fsuid == owner => return entry.perm.contains(perm),
+                AclTag::User if fsuid == Uid::new(entry.id) => return masked(entry.perm),
+                AclTag::GroupObj if in_group(group) => {
+                    if masked(entry.perm) {
+                        return true;
+                    }
+                    is_group_found = true;
+                }
+                AclTag::Group if in_group(Gid::new(entry.id)) => {
+                    if masked(entry.perm) {
+                        return true;
+                    }
+                    is_group_found = true;
+                }
+                AclTag::Other => return !is_group_found && entry.perm.contains(perm),
+                _ => (),
+            }
+        }
+        false
+    }
+
+    fn find(&self, tag: AclTag) -> Option<&AclEntry> {
+        self.0.iter().find(|entry| entry.tag == tag)
+    }
+}
+
+/// Returns the access ACL of the inode, if any.
+pub fn access_acl(inode: &dyn Inode) -> Option<PosixAcl> {
+    let value = inode.get_xattr(XATTR_NAME_POSIX_ACL_ACCESS).ok()?;
+    PosixAcl::from_bytes(&value).ok()
+}
+
+/// Sets the access ACL or the default ACL of the inode.
+///
+/// Setting the access ACL also updates the permission bits of the mode. If the ACL is
+/// equivalent to the mode, it is not stored as an extended attribute.
+pub fn set_acl(inode: &dyn Inode, name: &str, value: &[u8], flags: XattrSetFlags) -> Result<()> {
+    check_owner(inode)?;
+    let is_default = name == XATTR_NAME_POSIX_ACL_DEFAULT;
+    if is_default && inode.type_() != InodeType::Dir {
+        return_errno_with_message!(Errno::EACCES, "only directories have default ACLs");
+    }
+    // Like Linux, setting an empty default ACL removes it.
+    if is_default && value.is_empty() {
+        return remove_acl(inode, name);
+    }
+
+    let acl = PosixAcl::from_bytes(value)?;
+    if is_default {
+        return inode.set_xattr(name, &acl.to_bytes(), flags);
+    }
+    let mode = inode.mode()?;
+    let new_mode = InodeMode::from_bits_truncate((mode.bits() & !0o777) | acl.mode_bits());
+    if acl.is_equiv_to_mode() {
+        remove_acl(inode, name)?;
+    } else {
+        inode.set_xattr(name, &acl.to_bytes(), flags)?;
+    }
+    inode.set_mode(new_mode)
+}
+
+/// Removes the access ACL or the default ACL of the inode.
+pub fn remove_acl(inode: &dyn Inode, name: &str) -> Result<()> {
+    check_owner(inode)?;
+    match inode.remove_xattr(name) {
+        Err(err) if err.error() == Errno::ENODATA => Ok(()),
+        result => result,
+    }
+}
+
+/// Updates the access ACL of the inode after its mode is changed.
+pub fn chmod(inode: &dyn Inode, mode: InodeMode) -> Result<()> {
+    let Some(mut acl) = access_acl(inode) else {
+        return Ok(());
+    };
+    acl.chmod(mode);
+    inode.set_xattr(
+        XATTR_NAME_POSIX_ACL_ACCESS,
+        &acl.to_bytes(),
+        XattrSetFlags::empty(),
+    )
+}
+
+/// Initializes the ACLs of a new inode with the default ACL of its parent directory.
+///
+/// The new inode gets the default ACL as its access ACL, restricted by its mode, and
+/// a new directory also inherits the default ACL.
+pub fn inherit(dir: &dyn Inode, inode: &dyn Inode) -> Result<()> {
+    let Ok(value) = dir.get_xattr(XATTR_NAME_POSIX_ACL_DEFAULT) else {
+        return Ok(());
+    };
+    let Ok(mut acl) = PosixAcl::from_bytes(&value) else {
+        return Ok(());
+    };
+
+    if inode.type_() == InodeType::Dir {
+        inode.set_xattr(XATTR_NAME_POSIX_ACL_DEFAULT, &value, XattrSetFlags::empty())?;
+    }
+    let mode = acl.create_masq(inode.mode()?);
+    if !acl.is_equiv_to_mode() {
+        inode.set_xattr(
+            XATTR_NAME_POSIX_ACL_ACCESS,
+            &acl.to_bytes(),
+            XattrSetFlags::empty(),
+        )?;
+    }
+    inode.set_mode(mode)
+}
+
+/// Checks whether the current thread owns the inode or has `CAP_FOWNER`.
+pub fn check_owner(inode: &dyn Inode) -> Result<()> {
+    let credentials = credentials();
+    if credentials.fsuid() != inode.owner()?
+        && !credentials.effective_capset().contains(CapSet::FOWNER)
+    {
+        return_errno_with_message!(Errno::EPERM, "the inode is not owned by the user");
+    }
+    Ok(())
+}
@@ -0,0 +1,130 @@
+// SPDX-License-Identifier: MPL-2.0
+
+use core::{
+    ops::Range,
+    sync::atomic::{AtomicUsize, Ordering},
+};
+
+use crate::prelude::*;
+
+/// The advice about how a file is going to be accessed, given by `posix_fadvise`.
+///
+/// The raw definition is from https://elixir.bootlin.com/linux/v6.0.9/source/include/uapi/linux/fadvise.h
+#[repr(i32)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, TryFromInt)]
+pub enum FileAdvice {
+    /// No special treatment.
+    Normal = 0,
+    /// The data will be accessed randomly, so do not read ahead.
+    Random = 1,
+    /// The data will be accessed sequentially, so read ahead aggressively.
+    Sequential = 2,
+    /// The data will be accessed in the near future.
+    WillNeed = 3,
+    /// The data will not be accessed in the near future.
+    DontNeed = 4,
+    /// The data will be accessed only once.
+    NoReuse = 5,
+}
+
+/// The access pattern of an open file, which determines how the data are read ahead.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum AccessPattern {
+    #[default]
+    Normal,
+    Random,
+    Sequential,
+}
+
+/// The number of pages that are read ahead at the start of sequential reads.
+const INIT_WINDOW_PAGES: usize = 4;
+/// The maximum size of the read-ahead window in KiB, which can be changed via
+/// `/proc/sys/vm/read_ahead_kb`.
+///
+/// The default is the same as the default `read_ahead_kb` of block devices in Linux,
+/// which is configured per device in sysfs instead.
+static READ_AHEAD_KB: AtomicUsize = AtomicUsize::new(128);
+
+/// Returns the maximum size of the read-ahead window in KiB.
+pub fn read_ahead_kb() -> usize {
+    READ_AHEAD_KB.load(Ordering::Relaxed)
+}
+
+/// Sets the maximum size of the read-ahead window in KiB. Zero disables read-ahead.
+pub fn set_read_ahead_kb(kb: usize) {
+    READ_AHEAD_KB.store(kb, Ordering::Relaxed);
+}
+
+/// The read-ahead state of an open file.
+///
+/// When the file is read sequentially, the read-ahead window starts small and is doubled
+/// until it reaches the maximum size, which is doubled again for files advised to be
+/// accessed sequentially. Like Linux, the next window is read as soon as a read reaches
+/// the second half of the current window, so that the data are ready before they are needed.
+///
+/// When the file is read randomly, only the pages that are requested are read.
+#[derive(Debug, Default)]
+pub struct ReadaheadState {
+    pattern: AccessPattern,
+    /// The page index range of the current read-ahead window.
+    window: Range<usize>,
+    /// The end offset of the previous read.
+    prev_end: usize,
+}
+
+impl ReadaheadState {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn set_pattern(&mut self, pattern: AccessPattern) {
+        self.pattern = pattern;
+        self.window = 0..0;
+    }
+
+    /// Updates the state with a read of `len` bytes at `offset`.
+    ///
+    /// Returns the range of bytes that should be read into the page cache beforehand, if any.
+    pub fn on_read(&mut self, offset: usize, len: usize) -> Option<Range<usize>> {
+        let end = offset.saturating_add(len);
+        let is_sequential = offset == self.prev_end;
+        self.prev_end = end;
+        if len == 0 {
+            return None;
+        }
+
+        let first_page = offset / PAGE_SIZE;
+        let end_page = end.div_ceil(PAGE_SIZE);
+
+        let max_window_pages = self.max_window_pages();
+        if self.pattern == AccessPattern::Random || !is_sequential || max_window_pages == 0 {
+            self.window = first_page..end_page;
+            return Some(Self::to_byte_range(&self.window));
+        }
+
+        // The pages up to the second half of the window have been read.
+        let async_start = self.window.start + self.window.len() / 2;
+        if end_page <= async_start {
+            return None;
+        }
+
+        let size = (self.window.len() * 2)
+            .clamp(INIT_WINDOW_PAGES.min(max_window_pages), max_window_pages)
+            .max(end_page - first_page);
+        let start = self.window.end.max(first_page);
+        self.window = start..(start + size).max(end_page);
+        Some(Self::to_byte_range(&self.window))
+    }
+
+    fn max_window_pages(&self) -> usize {
+        let max_window_pages = read_ahead_kb().saturating_mul(1024) / PAGE_SIZE;
+        match self.pattern {
+            AccessPattern::Sequential => max_window_pages.saturating_mul(2),
+            _ => max_window_pages,
+        }
+    }
+
+    fn to_byte_range(page_idx_range: &Range<usize>) -> Range<usize> {
+        page_idx_range.start.saturating_mul(PAGE_SIZE)..page_idx_range.end.saturating_mul(PAGE_SIZE)
+    }
+}
@@ -0,0 +1,153 @@
+// SPDX-License-Identifier: MPL-2.0
+
+//! The periodic writeback of the dirty pages in the page caches.
+//!
+//! A flusher thread wakes up every `dirty_writeback_centisecs` and writes back the page
+//! caches that have been dirty for longer than `dirty_expire_centisecs`, so that the data
+//! written to files reach the disks in time even if they are never synced. Besides, the
+//! flusher is woken up to write back all the page caches when the dirty pages exceed
+//! `dirty_background_ratio` percent of the memory, or when the page cache shrinker cannot
+//! find enough clean pages to reclaim. The tunables are the same as those in Linux, and
+//! can be changed via `/proc/sys/vm`.
+
+use core::{
+    sync::atomic::{AtomicBool, AtomicUsize, Ordering},
+    time::Duration,
+};
+
+use aster_frame::{mm::nr_total_frames, sync::WaitQueue, task::LARGE_KERNEL_STACK_SIZE};
+
+use super::page_cache::PageCacheManager;
+use crate::{
+    prelude::*,
+    thread::{
+        kernel_thread::{KernelThreadExt, ThreadOptions},
+        Thread,
+    },
+    time::{clocks::JiffiesClock, wait::WaitTimeout},
+};
+
+/// The interval between the periodic writebacks, in centiseconds. Zero disables them.
+static WRITEBACK_INTERVAL_CENTISECS: AtomicUsize = AtomicUsize::new(500);
+/// How long a page cache is dirty before it is written back, in centiseconds.
+static DIRTY_EXPIRE_CENTISECS: AtomicUsize = AtomicUsize::new(3000);
+/// The percentage of the memory that can be dirty before all the page caches are
+/// written back in the background.
+static DIRTY_BACKGROUND_RATIO: AtomicUsize = AtomicUsize::new(10);
+
+pub fn writeback_interval_centisecs() -> usize {
+    WRITEBACK_INTERVAL_CENTISECS.load(Ordering::Relaxed)
+}
+
+pub fn set_writeback_interval_centisecs(centisecs: usize) {
+    WRITEBACK_INTERVAL_CENTISECS.store(centisecs, Ordering::Relaxed);
+    // Let the flusher sleep with the new interval.
+    FLUSHER_WAIT_QUEUE.wake_all();
+}
+
+pub fn dirty_expire_centisecs() -> usize {
+    DIRTY_EXPIRE_CENTISECS.load(Ordering::Relaxed)
+}
+
+pub fn set_dirty_expire_centisecs(centisecs: usize) {
+    DIRTY_EXPIRE_CENTISECS.store(centisecs, Ordering::Relaxed);
+}
+
+pub fn dirty_background_ratio() -> usize {
+    DIRTY_BACKGROUND_RATIO.load(Ordering::Relaxed)
+}
+
+pub fn set_dirty_background_ratio(ratio: usize) -> Result<()> {
+    if ratio > 100 {
+        return_errno_with_message!(Errno::EINVAL, "the ratio exceeds 100");
+    }
+    DIRTY_BACKGROUND_RATIO.store(ratio, Ordering::Relaxed);
+    Ok(())
+}
+
+/// The number of dirty pages in all page caches.
+static NR_DIRTY_PAGES: AtomicUsize = AtomicUsize::new(0);
+
+/// Returns the number of dirty pages in all page caches.
+pub fn nr_dirty_pages() -> usize {
+    NR_DIRTY_PAGES.load(Ordering::Relaxed)
+}
+
+pub(super) fn inc_dirty_pages() {
+    let nr_dirty = NR_DIRTY_PAGES.fetch_add(1, Ordering::Relaxed) + 1;
+    if nr_dirty > background_threshold() {
+        wake_flusher();
+    }
+}
+
+pub(super) fn dec_dirty_pages() {
+    NR_DIRTY_PAGES.fetch_sub(1, Ordering::Relaxed);
+}
+
+fn background_threshold() -> usize {
+    nr_total_frames() * dirty_background_ratio() / 100
+}
+
+/// The page caches, which are written back by the flusher.
+static PAGE_CACHES: Mutex<Vec<Weak<PageCacheManager>>> = Mutex::new(Vec::new());
+
+pub(super) fn register_page_cache(manager: Weak<PageCacheManager>) {
+    let mut page_caches = PAGE_CACHES.lock();
+    page_caches.retain(|manager| manager.strong_count() > 0);
+    page_caches.push(manager);
+}
+
+fn page_caches() -> Vec<Arc<PageCacheManager>> {
+    PAGE_CACHES
+        .lock()
+        .iter()
+        .filter_map(Weak::upgrade)
+        .collect()
+}
+
+static FLUSHER_WAIT_QUEUE: WaitQueue = WaitQueue::new();
+static FLUSH_ALL_REQUESTED: AtomicBool = AtomicBool::new(false);
+
+/// Wakes up the flusher to write back all the page caches.
+pub(super) fn wake_flusher() {
+    if !FLUSH_ALL_REQUESTED.swap(true, Ordering::Relaxed) {
+        FLUSHER_WAIT_QUEUE.wake_all();
+    }
+}
+
+/// Returns the time since boot, which the dirty time of the page caches is based on.
+pub(super) fn now() -> Duration {
+    JiffiesClock::elapsed()
+}
+
+pub(in crate::fs) fn spawn_flusher_thread() {
+    let task_fn = || loop {
+        let interval = Duration::from_millis(writeback_interval_centisecs() as u64 * 10);
+        let is_requested = || FLUSH_ALL_REQUESTED.load(Ordering::Relaxed).then_some(());
+        if interval.is_zero() {
+            FLUSHER_WAIT_QUEUE.wait_until(is_requested);
+        } else {
+            FLUSHER_WAIT_QUEUE.wait_until_or_timeout(is_requested, &interval);
+        }
+
+        let flush_all = FLUSH_ALL_REQUESTED.swap(false, Ordering::Relaxed);
+        let expire = Duration::from_millis(dirty_expire_centisecs() as u64 * 10);
+        let now = now();
+        for manager in page_caches() {
+            let is_expired = manager
+                .dirtied_at()
+                .is_some_and(|dirtied_at| now.saturating_sub(dirtied_at) >= expire);
+            if !flush_all && !is_expired {
+                continue;
+            }
+            if let Err(err) = manager.write_back() {
+                warn!("flusher: failed to write back a page cache: {:?}", err);
+            }
+        }
+    };
+
+    // Writing back goes through the whole stack of a file system and a block device.
+    Thread::spawn_kernel_thread(
+        ThreadOptions::new(task_fn).kernel_stack_size(LARGE_KERNEL_STACK_SIZE),
+    );
+}
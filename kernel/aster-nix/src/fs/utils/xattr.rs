@@ -0,0 +1,119 @@
+// SPDX-License-Identifier: MPL-2.0
+
+//! Extended attributes, which are name-value pairs associated with inodes.
+//!
+//! The name of an extended attribute is prefixed with its namespace, e.g., `user.mime_type`.
+//! The raw definitions are from https://elixir.bootlin.com/linux/v6.0.9/source/include/uapi/linux/xattr.h
+
+use bitflags::bitflags;
+
+use crate::prelude::*;
+
+/// The maximum length of the name of an extended attribute.
+pub const XATTR_NAME_MAX_LEN: usize = 255;
+/// The maximum size of the value of an extended attribute.
+pub const XATTR_VALUE_MAX_LEN: usize = 65536;
+/// The maximum size of the list of the names of extended attributes.
+pub const XATTR_LIST_MAX_LEN: usize = 65536;
+
+/// The name of the extended attribute for the access ACL.
+pub const XATTR_NAME_POSIX_ACL_ACCESS: &str = "system.posix_acl_access";
+/// The name of the extended attribute for the default ACL of a directory.
+pub const XATTR_NAME_POSIX_ACL_DEFAULT: &str = "system.posix_acl_default";
+
+bitflags! {
+    /// The flags of `setxattr`.
+    pub struct XattrSetFlags: u32 {
+        /// fail if the attribute exists
+        const CREATE = 1 << 0;
+        /// fail if the attribute does not exist
+        const REPLACE = 1 << 1;
+    }
+}
+
+/// The namespaces of extended attributes.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum XattrNamespace {
+    /// The attributes of users, which are protected by the file permissions.
+    User,
+    /// The attributes only visible to the processes with `CAP_SYS_ADMIN`.
+    Trusted,
+    /// The attributes of security modules, e.g., `security.capability`.
+    Security,
+    /// The attributes of the kernel, e.g., the POSIX ACLs.
+    System,
+}
+
+impl XattrNamespace {
+    /// Returns the namespace of the full name, validating the name.
+    pub fn of(name: &str) -> Result<Self> {
+        if name.is_empty() || name.len() > XATTR_NAME_MAX_LEN {
+            return_errno_with_message!(Errno::ERANGE, "the name is empty or too long");
+        }
+        let namespace = [Self::User, Self::Trusted, Self::Security, Self::System]
+            .into_iter()
+            .find(|namespace| name.starts_with(namespace.prefix()))
+            .ok_or_else(|| Error::with_message(Errno::EOPNOTSUPP, "unknown namespace"))?;
+        if name.len() == namespace.prefix().len() {
+            return_errno_with_message!(Errno::EINVAL, "the name in the namespace is empty");
+        }
+        Ok(namespace)
+    }
+
+    pub fn prefix(&self) -> &'static str {
+        match self {
+            Self::User => "user.",
+            Self::Trusted => "trusted.",
+            Self::Security => "security.",
+            Self::System => "system.",
+        }
+    }
+}
+
+/// The extended attributes of an inode that are kept in memory.
+#[derive(Debug, Clone, Default)]
+pub struct Xattrs(BTreeMap<String, Vec<u8>>);
+
+impl Xattrs {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn get(&self, name: &str) -> Result<Vec<u8>> {
+        self.0
+            .get(name)
+            .cloned()
+            .ok_or_else(|| Error::with_message(Errno::ENODATA, "the attribute does not exist"))
+    }
+
+    pub fn set(&mut self, name: &str, value: &[u8], flags: XattrSetFlags) -> Result<()> {
+        let exists = self.0.contains_key(name);
+        if exists && flags.contains(XattrSetFlags::CREATE) {
+            return_errno_with_message!(Errno::EEXIST, "the attribute exists");
+        }
+        if !exists && flags.contains(XattrSetFlags::REPLACE) {
+            return_errno_with_message!(Errno::ENODATA, "the attribute does not exist");
+        }
+        self.0.insert(String::from(name), value.to_vec());
+        Ok(())
+    }
+
+    pub fn remove(&mut self, name: &str) -> Result<()> {
+        self.0
+            .remove(name)
+            .map(|_| ())
+            .ok_or_else(|| Error::with_message(Errno::ENODATA, "the attribute does not exist"))
+    }
+
+    pub fn names(&self) -> Vec<String> {
+        self.0.keys().cloned().collect()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (&String, &Vec<u8>)> {
+        self.0.iter()
+    }
+}
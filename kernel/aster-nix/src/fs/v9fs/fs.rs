@@ -0,0 +1,287 @@
+// SPDX-License-Identifier: MPL-2.0
+
+use core::sync::atomic::{AtomicU16, AtomicU32, Ordering};
+
+use aster_virtio::device::transport_9p::{device::Transport9PDevice, get_device};
+
+use super::{
+    inode::V9fsInode,
+    protocol::{
+        Attr, MessageReader, MessageType, MessageWriter, Qid, GETATTR_BASIC, HEADER_LEN, NOFID,
+        NOTAG, QID_LEN, VERSION_9P2000_L,
+    },
+};
+use crate::{
+    fs::utils::{FileSystem, FsFlags, Inode, SuperBlock, NAME_MAX},
+    prelude::*,
+    process::posix_thread::PosixThreadExt,
+};
+
+/// The magic number reported by statfs (`V9FS_MAGIC` in Linux).
+const V9FS_MAGIC: u64 = 0x01021997;
+/// The maximum size of the messages that is proposed to the server.
+const MAX_MSIZE: u32 = 64 * 1024;
+/// The size of the headers of `Tread`, `Twrite` and their replies, which is subtracted from
+/// the maximum size of the messages to get the maximum size of the data in them.
+const IO_HEADER_LEN: usize = 24;
+/// The tag of `Tattach`, which is the only request in flight when it is sent.
+const ATTACH_TAG: u16 = 0;
+/// The FID of the root directory.
+const ROOT_FID: u32 = 0;
+
+/// A file system exported by the host through a virtio-9p device, which is spoken to in
+/// 9P2000.L.
+///
+/// Every operation is forwarded to the server, and nothing is cached in the guest, so the
+/// changes made by the host are visible at once.
+#[derive(Debug)]
+pub struct V9FS {
+    device: Arc<Transport9PDevice>,
+    root: Arc<V9fsInode>,
+    /// The maximum size of the messages, which is negotiated with the server.
+    msize: usize,
+    next_tag: AtomicU16,
+    next_fid: AtomicU32,
+    /// The inodes that are in use, indexed by the paths of their QIDs.
+    inodes: Mutex<BTreeMap<u64, Weak<V9fsInode>>>,
+}
+
+impl V9FS {
+    /// Opens the file system of the virtio-9p device with the mount tag.
+    pub fn open(tag: &str) -> Result<Arc<Self>> {
+        let Some(device) = get_device(tag) else {
+            return_errno_with_message!(Errno::ENOENT, "no virtio-9p device with the tag");
+        };
+
+        let reply = send_request(
+            &device,
+            MessageType::Tversion,
+            NOTAG,
+            |msg| {
+                msg.put_u32(MAX_MSIZE).put_str(VERSION_9P2000_L);
+            },
+            MAX_MSIZE as usize,
+        )?;
+        let mut reader = MessageReader::new(&reply);
+        let msize = reader.get_u32()?.min(MAX_MSIZE) as usize;
+        if reader.get_str()? != VERSION_9P2000_L {
+            return_errno_with_message!(Errno::EPROTO, "the server does not speak 9P2000.L");
+        }
+        if msize <= IO_HEADER_LEN {
+            return_errno_with_message!(Errno::EPROTO, "the message size is too small");
+        }
+
+        let uid = match current_thread!().as_posix_thread() {
+            Some(posix_thread) => posix_thread.credentials().fsuid().as_u32(),
+            None => 0,
+        };
+        let reply = send_request(
+            &device,
+            MessageType::Tattach,
+            ATTACH_TAG,
+            |msg| {
+                msg.put_u32(ROOT_FID)
+                    .put_u32(NOFID)
+                    .put_str("")
+                    .put_str("")
+                    .put_u32(uid);
+            },
+            QID_LEN,
+        )?;
+        let qid = MessageReader::new(&reply).get_qid()?;
+
+        let fs = Arc::new_cyclic(|weak_fs| Self {
+            device,
+            root: V9fsInode::new_root(ROOT_FID, qid, weak_fs.clone()),
+            msize,
+            next_tag: AtomicU16::new(ATTACH_TAG + 1),
+            next_fid: AtomicU32::new(ROOT_FID + 1),
+            inodes: Mutex::new(BTreeMap::new()),
+        });
+        Ok(fs)
+    }
+
+    /// Returns the maximum size of the data in a `Tread` or a `Twrite`.
+    pub(super) fn max_io_len(&self) -> usize {
+        self.msize - IO_HEADER_LEN
+    }
+
+    /// Sends a T-message whose fields are written by `build`, and returns the fields of the
+    /// R-message, which fit in a page.
+    pub(super) fn request(
+        &self,
+        type_: MessageType,
+        build: impl FnOnce(&mut MessageWriter),
+    ) -> Result<Vec<u8>> {
+        self.request_with_reply_len(type_, build, PAGE_SIZE - HEADER_LEN)
+    }
+
+    /// Sends a T-message like `request`, but whose R-message has at most `max_reply_len`
+    /// bytes of fields, e.g., `Rread`.
+    pub(super) fn request_with_reply_len(
+        &self,
+        type_: MessageType,
+        build: impl FnOnce(&mut MessageWriter),
+        max_reply_len: usize,
+    ) -> Result<Vec<u8>> {
+        let tag = loop {
+            let tag = self.next_tag.fetch_add(1, Ordering::Relaxed);
+            if tag != NOTAG {
+                break tag;
+            }
+        };
+        send_request(&self.device, type_, tag, build, max_reply_len)
+    }
+
+    pub(super) fn alloc_fid(&self) -> u32 {
+        self.next_fid.fetch_add(1, Ordering::Relaxed)
+    }
+
+    /// Walks from the file of `fid` through `names` to a new FID, and returns the new FID
+    /// with the QID of the destination. An empty `names` clones `fid`.
+    pub(super) fn walk(&self, fid: u32, names: &[&str]) -> Result<(u32, Option<Qid>)> {
+        let newfid = self.alloc_fid();
+        let reply = self.request(MessageType::Twalk, |msg| {
+            msg.put_u32(fid).put_u32(newfid).put_u16(names.len() as u16);
+            for name in names {
+                msg.put_str(name);
+            }
+        })?;
+        let mut reader = MessageReader::new(&reply);
+        let nwqid = reader.get_u16()? as usize;
+        // The new FID is not created if the walk stops halfway.
+        if nwqid < names.len() {
+            return_errno_with_message!(Errno::ENOENT, "the file does not exist");
+        }
+        let mut qid = None;
+        for _ in 0..nwqid {
+            qid = Some(reader.get_qid()?);
+        }
+        Ok((newfid, qid))
+    }
+
+    /// Releases the FID on the server.
+    pub(super) fn clunk(&self, fid: u32) {
+        if let Err(e) = self.request(MessageType::Tclunk, |msg| {
+            msg.put_u32(fid);
+        }) {
+            warn!("9p: failed to clunk the FID {}: {:?}", fid, e);
+        }
+    }
+
+    pub(super) fn getattr(&self, fid: u32) -> Result<Attr> {
+        let reply = self.request(MessageType::Tgetattr, |msg| {
+            msg.put_u32(fid).put_u64(GETATTR_BASIC);
+        })?;
+        MessageReader::new(&reply).get_attr()
+    }
+
+    /// Returns the inode of the file with the QID path if it is in use, or builds and
+    /// records one.
+    ///
+    /// The files with the same QID path are the same file, e.g., the hard links of a file,
+    /// so they share the inode.
+    pub(super) fn get_or_insert_inode(
+        &self,
+        path: u64,
+        build: impl FnOnce() -> Result<Arc<V9fsInode>>,
+    ) -> Result<Arc<V9fsInode>> {
+        if path == self.root.qid().path {
+            return Ok(self.root.clone());
+        }
+
+        let mut inodes = self.inodes.lock();
+        if let Some(inode) = inodes.get(&path).and_then(Weak::upgrade) {
+            return Ok(inode);
+        }
+        let inode = build()?;
+        inodes.insert(path, Arc::downgrade(&inode));
+        Ok(inode)
+    }
+
+    /// Forgets the inode of the file if it has been dropped.
+    pub(super) fn remove_inode(&self, path: u64) {
+        let mut inodes = self.inodes.lock();
+        if inodes
+            .get(&path)
+            .is_some_and(|inode| inode.strong_count() == 0)
+        {
+            inodes.remove(&path);
+        }
+    }
+}
+
+impl FileSystem for V9FS {
+    fn sync(&self) -> Result<()> {
+        // Nothing is cached in the guest.
+        Ok(())
+    }
+
+    fn root_inode(&self) -> Arc<dyn Inode> {
+        self.root.clone()
+    }
+
+    fn sb(&self) -> SuperBlock {
+        let statfs = self
+            .request(MessageType::Tstatfs, |msg| {
+                msg.put_u32(ROOT_FID);
+            })
+            .and_then(|reply| MessageReader::new(&reply).get_statfs());
+        let Ok(statfs) = statfs else {
+            return SuperBlock::new(V9FS_MAGIC, PAGE_SIZE, NAME_MAX);
+        };
+        let mut sb = SuperBlock::new(V9FS_MAGIC, statfs.bsize as usize, statfs.namelen as usize);
+        sb.blocks = statfs.blocks as usize;
+        sb.bfree = statfs.bfree as usize;
+        sb.bavail = statfs.bavail as usize;
+        sb.files = statfs.files as usize;
+        sb.ffree = statfs.ffree as usize;
+        sb.fsid = statfs.fsid;
+        sb
+    }
+
+    fn flags(&self) -> FsFlags {
+        FsFlags::empty()
+    }
+}
+
+impl Drop for V9FS {
+    fn drop(&mut self) {
+        self.clunk(ROOT_FID);
+    }
+}
+
+/// Sends a T-message whose fields are written by `build`, and returns the fields of the
+/// R-message, which are at most `max_reply_len` bytes.
+fn send_request(
+    device: &Transport9PDevice,
+    type_: MessageType,
+    tag: u16,
+    build: impl FnOnce(&mut MessageWriter),
+    max_reply_len: usize,
+) -> Result<Vec<u8>> {
+    let mut msg = MessageWriter::new(type_, tag);
+    build(&mut msg);
+    let request = msg.finish();
+
+    let mut reply = vec![0u8; max_reply_len + HEADER_LEN];
+    let reply_len = device.request(&request, &mut reply);
+    let mut reader = MessageReader::new(&reply[..reply_len]);
+    let size = reader.get_u32()? as usize;
+    let reply_type = reader.get_u8()?;
+    if reader.get_u16()? != tag {
+        return_errno_with_message!(Errno::EIO, "the reply does not match the request");
+    }
+    if reply_type == MessageType::Rlerror as u8 {
+        let ecode = reader.get_u32()?;
+        let errno = Errno::try_from(ecode as i32).unwrap_or(Errno::EIO);
+        return_errno_with_message!(errno, "the 9P request fails");
+    }
+    if reply_type != type_ as u8 + 1 || size < HEADER_LEN || size > reply_len {
+        return_errno_with_message!(Errno::EIO, "invalid 9P reply");
+    }
+
+    reply.truncate(size);
+    reply.drain(..HEADER_LEN);
+    Ok(reply)
+}
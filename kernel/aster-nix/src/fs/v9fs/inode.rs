@@ -0,0 +1,547 @@
+// SPDX-License-Identifier: MPL-2.0
+
+use core::time::Duration;
+
+use super::{
+    fs::V9FS,
+    protocol::{Attr, MessageReader, MessageType, Qid, SetattrValid, AT_REMOVEDIR},
+};
+use crate::{
+    events::IoEvents,
+    fs::utils::{AccessMode, DirentVisitor, FileSystem, Inode, InodeMode, InodeType, Metadata},
+    prelude::*,
+    process::{posix_thread::PosixThreadExt, signal::Poller, Gid, Uid},
+};
+
+/// The `O_DIRECTORY` flag of `Tlopen`.
+const O_DIRECTORY: u32 = 0o200000;
+/// The size of the units of `Attr::blocks`.
+const BLOCK_SIZE_9P: usize = 512;
+
+/// An inode of a 9P file system, which is a file on the server.
+#[derive(Debug)]
+pub struct V9fsInode {
+    /// The FID that refers to the file, which is used for the operations other than I/O.
+    fid: u32,
+    qid: Qid,
+    type_: InodeType,
+    /// The attributes in the latest reply of the server, which are used if the server
+    /// fails to reply the up-to-date ones.
+    attr: SpinLock<Attr>,
+    /// The FID that is opened for the I/O of the inode.
+    handle: Mutex<Option<FileHandle>>,
+    fs: Weak<V9FS>,
+}
+
+#[derive(Debug, Clone, Copy)]
+struct FileHandle {
+    fid: u32,
+    is_writable: bool,
+}
+
+impl V9fsInode {
+    pub(super) fn new_root(fid: u32, qid: Qid, fs: Weak<V9FS>) -> Arc<Self> {
+        let attr = Attr {
+            qid,
+            mode: InodeType::Dir as u32 | 0o755,
+            nlink: 2,
+            ..Default::default()
+        };
+        Arc::new(Self {
+            fid,
+            qid,
+            type_: InodeType::Dir,
+            attr: SpinLock::new(attr),
+            handle: Mutex::new(None),
+            fs,
+        })
+    }
+
+    fn new(fid: u32, attr: Attr, fs: Weak<V9FS>) -> Result<Arc<Self>> {
+        let type_ = InodeType::try_from(attr.mode & 0o170000)
+            .map_err(|_| Error::with_message(Errno::EIO, "invalid file type"))?;
+        Ok(Arc::new(Self {
+            fid,
+            qid: attr.qid,
+            type_,
+            attr: SpinLock::new(attr),
+            handle: Mutex::new(None),
+            fs,
+        }))
+    }
+
+    pub(super) fn qid(&self) -> Qid {
+        self.qid
+    }
+
+    fn v9fs(&self) -> Arc<V9FS> {
+        self.fs.upgrade().unwrap()
+    }
+
+    /// Gets the up-to-date attributes from the server.
+    fn getattr(&self) -> Result<Attr> {
+        let attr = self.v9fs().getattr(self.fid)?;
+        *self.attr.lock() = attr;
+        Ok(attr)
+    }
+
+    /// Gets the attributes, which are the cached ones if the server fails to reply.
+    fn attr(&self) -> Attr {
+        self.getattr().unwrap_or_else(|e| {
+            warn!("9p: failed to get the attributes: {:?}", e);
+            *self.attr.lock()
+        })
+    }
+
+    fn setattr(&self, valid: SetattrValid, attr: &Attr) -> Result<()> {
+        self.v9fs().request(MessageType::Tsetattr, |msg| {
+            msg.put_u32(self.fid)
+                .put_u32(valid.bits())
+                .put_u32(attr.mode)
+                .put_u32(attr.uid)
+                .put_u32(attr.gid)
+                .put_u64(attr.size)
+                .put_u64(attr.atime.as_secs())
+                .put_u64(attr.atime.subsec_nanos() as u64)
+                .put_u64(attr.mtime.as_secs())
+                .put_u64(attr.mtime.subsec_nanos() as u64);
+        })?;
+        Ok(())
+    }
+
+    /// Opens a new FID of the file with the flags of `Tlopen`.
+    fn open_fid(&self, flags: u32) -> Result<u32> {
+        let fs = self.v9fs();
+        let (fid, _) = fs.walk(self.fid, &[])?;
+        if let Err(e) = fs.request(MessageType::Tlopen, |msg| {
+            msg.put_u32(fid).put_u32(flags);
+        }) {
+            fs.clunk(fid);
+            return Err(e);
+        }
+        Ok(fid)
+    }
+
+    /// Opens the file for I/O, for both reading and writing if permitted.
+    fn open_handle(&self) -> Result<FileHandle> {
+        let mut handle = self.handle.lock();
+        if let Some(handle) = *handle {
+            return Ok(handle);
+        }
+
+        let new_handle = match self.open_fid(AccessMode::O_RDWR as u32) {
+            Ok(fid) => FileHandle {
+                fid,
+                is_writable: true,
+            },
+            Err(e) if matches!(e.error(), Errno::EACCES | Errno::EROFS | Errno::ETXTBSY) => {
+                FileHandle {
+                    fid: self.open_fid(AccessMode::O_RDONLY as u32)?,
+                    is_writable: false,
+                }
+            }
+            Err(e) => return Err(e),
+        };
+        *handle = Some(new_handle);
+        Ok(new_handle)
+    }
+
+    fn lookup_child(&self, name: &str) -> Result<Arc<Self>> {
+        if self.type_ != InodeType::Dir {
+            return_errno!(Errno::ENOTDIR);
+        }
+        let fs = self.v9fs();
+        let (fid, qid) = fs.walk(self.fid, &[name])?;
+        let qid = qid.unwrap();
+        let mut is_fid_used = false;
+        let res = fs.get_or_insert_inode(qid.path, || {
+            is_fid_used = true;
+            let attr = fs.getattr(fid)?;
+            Self::new(fid, attr, self.fs.clone())
+        });
+        if res.is_err() || !is_fid_used {
+            fs.clunk(fid);
+        }
+        res
+    }
+
+    /// Sends a request that creates a file named `name` in this directory, and looks it up.
+    fn make_node(&self, type_: MessageType, name: &str, mode: u32) -> Result<Arc<Self>> {
+        if self.type_ != InodeType::Dir {
+            return_errno!(Errno::ENOTDIR);
+        }
+        let gid = current_fsgid();
+        self.v9fs().request(type_, |msg| {
+            msg.put_u32(self.fid).put_str(name).put_u32(mode);
+            if type_ == MessageType::Tmknod {
+                // The major and minor device numbers.
+                msg.put_u32(0).put_u32(0);
+            }
+            msg.put_u32(gid);
+        })?;
+        self.lookup_child(name)
+    }
+
+    fn unlinkat(&self, name: &str, flags: u32) -> Result<()> {
+        if self.type_ != InodeType::Dir {
+            return_errno!(Errno::ENOTDIR);
+        }
+        self.v9fs().request(MessageType::Tunlinkat, |msg| {
+            msg.put_u32(self.fid).put_str(name).put_u32(flags);
+        })?;
+        Ok(())
+    }
+
+    /// Reads the entries from the `offset`-th one, which are visited with the offsets of
+    /// the next entries.
+    ///
+    /// The offsets in the replies of the server are opaque, so the entries before the
+    /// `offset`-th one are read and skipped.
+    fn try_readdir(
+        &self,
+        fid: u32,
+        offset: &mut usize,
+        visitor: &mut dyn DirentVisitor,
+    ) -> Result<()> {
+        let fs = self.v9fs();
+        let count = fs.max_io_len().min(PAGE_SIZE) as u32;
+        let mut idx = 0;
+        let mut server_offset = 0;
+        loop {
+            let reply = fs.request(MessageType::Treaddir, |msg| {
+                msg.put_u32(fid).put_u64(server_offset).put_u32(count);
+            })?;
+            let mut reader = MessageReader::new(&reply);
+            let data_len = reader.get_u32()? as usize;
+            if data_len == 0 {
+                return Ok(());
+            }
+
+            let mut entries = MessageReader::new(reader.get_bytes(data_len)?);
+            while !entries.is_empty() {
+                let qid = entries.get_qid()?;
+                server_offset = entries.get_u64()?;
+                let type_ = entries.get_u8()?;
+                let name = entries.get_str()?;
+                if idx >= *offset {
+                    let type_ =
+                        InodeType::try_from((type_ as u32) << 12).unwrap_or(InodeType::File);
+                    visitor.visit(name, qid.path, type_, idx + 1)?;
+                    *offset = idx + 1;
+                }
+                idx += 1;
+            }
+        }
+    }
+
+    fn set_time(&self, valid: SetattrValid, time: Duration) {
+        let attr = Attr {
+            atime: time,
+            mtime: time,
+            ..Default::default()
+        };
+        if let Err(e) = self.setattr(valid, &attr) {
+            warn!("9p: failed to set the time: {:?}", e);
+        }
+    }
+}
+
+impl Inode for V9fsInode {
+    fn size(&self) -> usize {
+        self.attr().size as usize
+    }
+
+    fn resize(&self, new_size: usize) -> Result<()> {
+        if self.type_ == InodeType::Dir {
+            return_errno!(Errno::EISDIR);
+        }
+        let attr = Attr {
+            size: new_size as u64,
+            ..Default::default()
+        };
+        self.setattr(SetattrValid::SIZE, &attr)
+    }
+
+    fn metadata(&self) -> Metadata {
+        let attr = self.attr();
+        let blk_size = if attr.blksize == 0 {
+            PAGE_SIZE
+        } else {
+            attr.blksize as usize
+        };
+        Metadata {
+            dev: 0,
+            ino: self.qid.path,
+            size: attr.size as usize,
+            blk_size,
+            blocks: (attr.blocks as usize * BLOCK_SIZE_9P).div_ceil(blk_size),
+            atime: attr.atime,
+            mtime: attr.mtime,
+            ctime: attr.ctime,
+            type_: self.type_,
+            mode: InodeMode::from_bits_truncate(attr.mode as u16),
+            nlinks: attr.nlink as usize,
+            uid: Uid::new(attr.uid),
+            gid: Gid::new(attr.gid),
+            rdev: attr.rdev,
+        }
+    }
+
+    fn ino(&self) -> u64 {
+        self.qid.path
+    }
+
+    fn type_(&self) -> InodeType {
+        self.type_
+    }
+
+    fn mode(&self) -> Result<InodeMode> {
+        Ok(InodeMode::from_bits_truncate(self.getattr()?.mode as u16))
+    }
+
+    fn set_mode(&self, mode: InodeMode) -> Result<()> {
+        let attr = Attr {
+            mode: mode.bits() as u32,
+            ..Default::default()
+        };
+        self.setattr(SetattrValid::MODE, &attr)
+    }
+
+    fn owner(&self) -> Result<Uid> {
+        Ok(Uid::new(self.getattr()?.uid))
+    }
+
+    fn set_owner(&self, uid: Uid) -> Result<()> {
+        let attr = Attr {
+            uid: uid.as_u32(),
+            ..Default::default()
+        };
+        self.setattr(SetattrValid::UID, &attr)
+    }
+
+    fn group(&self) -> Result<Gid> {
+        Ok(Gid::new(self.getattr()?.gid))
+    }
+
+    fn set_group(&self, gid: Gid) -> Result<()> {
+        let attr = Attr {
+            gid: gid.as_u32(),
+            ..Default::default()
+        };
+        self.setattr(SetattrValid::GID, &attr)
+    }
+
+    fn atime(&self) -> Duration {
+        self.attr().atime
+    }
+
+    fn set_atime(&self, time: Duration) {
+        self.set_time(SetattrValid::ATIME | SetattrValid::ATIME_SET, time);
+    }
+
+    fn mtime(&self) -> Duration {
+        self.attr().mtime
+    }
+
+    fn set_mtime(&self, time: Duration) {
+        self.set_time(SetattrValid::MTIME | SetattrValid::MTIME_SET, time);
+    }
+
+    fn read_at(&self, offset: usize, buf: &mut [u8]) -> Result<usize> {
+        if self.type_ == InodeType::Dir {
+            return_errno!(Errno::EISDIR);
+        }
+        let handle = self.open_handle()?;
+        let fs = self.v9fs();
+
+        let mut read_len = 0;
+        while read_len < buf.len() {
+            let count = (buf.len() - read_len).min(fs.max_io_len());
+            let reply = fs.request_with_reply_len(
+                MessageType::Tread,
+                |msg| {
+                    msg.put_u32(handle.fid)
+                        .put_u64((offset + read_len) as u64)
+                        .put_u32(count as u32);
+                },
+                count + 4,
+            )?;
+            let mut reader = MessageReader::new(&reply);
+            let data_len = (reader.get_u32()? as usize).min(count);
+            let data = reader.get_bytes(data_len)?;
+            buf[read_len..read_len + data_len].copy_from_slice(data);
+            read_len += data_len;
+            if data_len < count {
+                break;
+            }
+        }
+        Ok(read_len)
+    }
+
+    fn read_direct_at(&self, offset: usize, buf: &mut [u8]) -> Result<usize> {
+        self.read_at(offset, buf)
+    }
+
+    fn write_at(&self, offset: usize, buf: &[u8]) -> Result<usize> {
+        if self.type_ == InodeType::Dir {
+            return_errno!(Errno::EISDIR);
+        }
+        let handle = self.open_handle()?;
+        if !handle.is_writable {
+            return_errno_with_message!(Errno::EACCES, "the file cannot be written");
+        }
+        let fs = self.v9fs();
+
+        let mut written_len = 0;
+        while written_len < buf.len() {
+            let count = (buf.len() - written_len).min(fs.max_io_len());
+            let reply = fs.request(MessageType::Twrite, |msg| {
+                msg.put_u32(handle.fid)
+                    .put_u64((offset + written_len) as u64)
+                    .put_u32(count as u32)
+                    .put_bytes(&buf[written_len..written_len + count]);
+            })?;
+            let count_written = (MessageReader::new(&reply).get_u32()? as usize).min(count);
+            written_len += count_written;
+            if count_written < count {
+                break;
+            }
+        }
+        Ok(written_len)
+    }
+
+    fn write_direct_at(&self, offset: usize, buf: &[u8]) -> Result<usize> {
+        self.write_at(offset, buf)
+    }
+
+    fn create(&self, name: &str, type_: InodeType, mode: InodeMode) -> Result<Arc<dyn Inode>> {
+        let inode = match type_ {
+            InodeType::Dir => self.make_node(MessageType::Tmkdir, name, mode.bits() as u32)?,
+            InodeType::File | InodeType::NamedPipe | InodeType::Socket => {
+                let mode = type_ as u32 | mode.bits() as u32;
+                self.make_node(MessageType::Tmknod, name, mode)?
+            }
+            // The target of a symbolic link is given when the link is created in 9P,
+            // which is later than this in the VFS.
+            _ => return_errno_with_message!(Errno::EPERM, "unsupported file type"),
+        };
+        Ok(inode)
+    }
+
+    fn readdir_at(&self, offset: usize, visitor: &mut dyn DirentVisitor) -> Result<usize> {
+        if self.type_ != InodeType::Dir {
+            return_errno!(Errno::ENOTDIR);
+        }
+        let fid = self.open_fid(AccessMode::O_RDONLY as u32 | O_DIRECTORY)?;
+        let mut iterate_offset = offset;
+        let res = self.try_readdir(fid, &mut iterate_offset, visitor);
+        self.v9fs().clunk(fid);
+
+        match res {
+            Err(e) if iterate_offset == offset => Err(e),
+            _ => Ok(iterate_offset - offset),
+        }
+    }
+
+    fn link(&self, old: &Arc<dyn Inode>, name: &str) -> Result<()> {
+        let old = old
+            .downcast_ref::<V9fsInode>()
+            .ok_or_else(|| Error::with_message(Errno::EXDEV, "not same fs"))?;
+        if !Weak::ptr_eq(&old.fs, &self.fs) {
+            return_errno_with_message!(Errno::EXDEV, "not same fs");
+        }
+        if self.type_ != InodeType::Dir {
+            return_errno!(Errno::ENOTDIR);
+        }
+        self.v9fs().request(MessageType::Tlink, |msg| {
+            msg.put_u32(self.fid).put_u32(old.fid).put_str(name);
+        })?;
+        Ok(())
+    }
+
+    fn unlink(&self, name: &str) -> Result<()> {
+        self.unlinkat(name, 0)
+    }
+
+    fn rmdir(&self, name: &str) -> Result<()> {
+        self.unlinkat(name, AT_REMOVEDIR)
+    }
+
+    fn lookup(&self, name: &str) -> Result<Arc<dyn Inode>> {
+        Ok(self.lookup_child(name)?)
+    }
+
+    fn rename(&self, old_name: &str, target: &Arc<dyn Inode>, new_name: &str) -> Result<()> {
+        let target = target
+            .downcast_ref::<V9fsInode>()
+            .ok_or_else(|| Error::with_message(Errno::EXDEV, "not same fs"))?;
+        if !Weak::ptr_eq(&target.fs, &self.fs) {
+            return_errno_with_message!(Errno::EXDEV, "not same fs");
+        }
+        if self.type_ != InodeType::Dir || target.type_ != InodeType::Dir {
+            return_errno!(Errno::ENOTDIR);
+        }
+        self.v9fs().request(MessageType::Trenameat, |msg| {
+            msg.put_u32(self.fid)
+                .put_str(old_name)
+                .put_u32(target.fid)
+                .put_str(new_name);
+        })?;
+        Ok(())
+    }
+
+    fn read_link(&self) -> Result<String> {
+        if self.type_ != InodeType::SymLink {
+            return_errno!(Errno::EINVAL);
+        }
+        let reply = self.v9fs().request(MessageType::Treadlink, |msg| {
+            msg.put_u32(self.fid);
+        })?;
+        Ok(MessageReader::new(&reply).get_str()?.to_string())
+    }
+
+    fn write_link(&self, _target: &str) -> Result<()> {
+        return_errno_with_message!(Errno::EPERM, "the target of a link cannot be changed");
+    }
+
+    fn sync(&self) -> Result<()> {
+        let Some(handle) = *self.handle.lock() else {
+            return Ok(());
+        };
+        self.v9fs().request(MessageType::Tfsync, |msg| {
+            msg.put_u32(handle.fid).put_u32(0);
+        })?;
+        Ok(())
+    }
+
+    fn poll(&self, mask: IoEvents, _poller: Option<&Poller>) -> IoEvents {
+        let events = IoEvents::IN | IoEvents::OUT;
+        events & mask
+    }
+
+    fn fs(&self) -> Arc<dyn FileSystem> {
+        self.v9fs()
+    }
+}
+
+impl Drop for V9fsInode {
+    fn drop(&mut self) {
+        let Some(fs) = self.fs.upgrade() else {
+            return;
+        };
+
+        if let Some(handle) = *self.handle.get_mut() {
+            fs.clunk(handle.fid);
+        }
+        fs.remove_inode(self.qid.path);
+        fs.clunk(self.fid);
+    }
+}
+
+/// Returns the GID of the files created by the current thread.
+fn current_fsgid() -> u32 {
+    match current_thread!().as_posix_thread() {
+        Some(posix_thread) => posix_thread.credentials().fsgid().as_u32(),
+        None => 0,
+    }
+}
@@ -0,0 +1,16 @@
+// SPDX-License-Identifier: MPL-2.0
+
+//! The client of 9P2000.L over virtio-9p, which shares a directory of the host with the
+//! guest.
+//!
+//! It is an alternative to virtio-fs, which is what many QEMU setups export with
+//! `-virtfs`. The file system is mounted with the mount tag of the device as the device
+//! name, e.g., `mount -t 9p hostshare /mnt`. The files are identified by the paths of
+//! their QIDs, so the hard links of a file share an inode.
+
+mod fs;
+mod inode;
+mod protocol;
+
+pub use fs::V9FS;
+pub use inode::V9fsInode;
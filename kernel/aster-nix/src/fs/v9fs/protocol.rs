@@ -0,0 +1,272 @@
+// SPDX-License-Identifier: MPL-2.0
+
+//! The messages of 9P2000.L.
+//!
+//! A message is `size[4] type[1] tag[2]` followed by the fields of the type, all of
+//! which are in little endian. A string is `len[2]` followed by the UTF-8 bytes.
+
+#![allow(dead_code)]
+
+use core::time::Duration;
+
+use crate::prelude::*;
+
+pub const VERSION_9P2000_L: &str = "9P2000.L";
+/// The tag of `Tversion`.
+pub const NOTAG: u16 = !0;
+/// The FID that means no FID, e.g., the `afid` of `Tattach` without authentication.
+pub const NOFID: u32 = !0;
+/// The length of the header of a message.
+pub const HEADER_LEN: usize = 7;
+/// The length of a QID on the wire.
+pub const QID_LEN: usize = 13;
+
+/// The types of the messages. Each R-message is of the type of its T-message plus one.
+#[repr(u8)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MessageType {
+    Rlerror = 7,
+    Tstatfs = 8,
+    Tlopen = 12,
+    Tlcreate = 14,
+    Tsymlink = 16,
+    Tmknod = 18,
+    Treadlink = 22,
+    Tgetattr = 24,
+    Tsetattr = 26,
+    Treaddir = 40,
+    Tfsync = 50,
+    Tlink = 70,
+    Tmkdir = 72,
+    Trenameat = 74,
+    Tunlinkat = 76,
+    Tversion = 100,
+    Tattach = 104,
+    Twalk = 110,
+    Tread = 116,
+    Twrite = 118,
+    Tclunk = 120,
+}
+
+/// The unique identifier of a file on the server.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Qid {
+    pub type_: u8,
+    pub version: u32,
+    /// The number that is unique among the files on the server, like an inode number.
+    pub path: u64,
+}
+
+/// The `request_mask` of `Tgetattr` for the basic fields, i.e., the ones of `stat`.
+pub const GETATTR_BASIC: u64 = 0x7ff;
+
+bitflags! {
+    /// The `valid` of `Tsetattr`.
+    pub struct SetattrValid: u32 {
+        const MODE = 1 << 0;
+        const UID = 1 << 1;
+        const GID = 1 << 2;
+        const SIZE = 1 << 3;
+        const ATIME = 1 << 4;
+        const MTIME = 1 << 5;
+        const CTIME = 1 << 6;
+        /// The access time is the one in the message instead of the current time.
+        const ATIME_SET = 1 << 7;
+        /// The modification time is the one in the message instead of the current time.
+        const MTIME_SET = 1 << 8;
+    }
+}
+
+/// The `flags` of `Tunlinkat` to remove a directory.
+pub const AT_REMOVEDIR: u32 = 0x200;
+
+/// The attributes in `Rgetattr`.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Attr {
+    pub qid: Qid,
+    pub mode: u32,
+    pub uid: u32,
+    pub gid: u32,
+    pub nlink: u64,
+    pub rdev: u64,
+    pub size: u64,
+    pub blksize: u64,
+    pub blocks: u64,
+    pub atime: Duration,
+    pub mtime: Duration,
+    pub ctime: Duration,
+}
+
+/// The file system information in `Rstatfs`.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Statfs {
+    pub type_: u32,
+    pub bsize: u32,
+    pub blocks: u64,
+    pub bfree: u64,
+    pub bavail: u64,
+    pub files: u64,
+    pub ffree: u64,
+    pub fsid: u64,
+    pub namelen: u32,
+}
+
+/// The builder of a T-message.
+pub struct MessageWriter {
+    buf: Vec<u8>,
+}
+
+impl MessageWriter {
+    /// Starts a T-message, whose size is filled in by `finish`.
+    pub fn new(type_: MessageType, tag: u16) -> Self {
+        let mut writer = Self {
+            buf: Vec::with_capacity(HEADER_LEN),
+        };
+        writer.put_u32(0).put_u8(type_ as u8).put_u16(tag);
+        writer
+    }
+
+    pub fn put_u8(&mut self, val: u8) -> &mut Self {
+        self.buf.push(val);
+        self
+    }
+
+    pub fn put_u16(&mut self, val: u16) -> &mut Self {
+        self.buf.extend_from_slice(&val.to_le_bytes());
+        self
+    }
+
+    pub fn put_u32(&mut self, val: u32) -> &mut Self {
+        self.buf.extend_from_slice(&val.to_le_bytes());
+        self
+    }
+
+    pub fn put_u64(&mut self, val: u64) -> &mut Self {
+        self.buf.extend_from_slice(&val.to_le_bytes());
+        self
+    }
+
+    pub fn put_str(&mut self, val: &str) -> &mut Self {
+        self.put_u16(val.len() as u16);
+        self.buf.extend_from_slice(val.as_bytes());
+        self
+    }
+
+    pub fn put_bytes(&mut self, val: &[u8]) -> &mut Self {
+        self.buf.extend_from_slice(val);
+        self
+    }
+
+    pub fn finish(mut self) -> Vec<u8> {
+        let size = self.buf.len() as u32;
+        self.buf[..4].copy_from_slice(&size.to_le_bytes());
+        self.buf
+    }
+}
+
+/// The parser of the fields of an R-message.
+pub struct MessageReader<'a> {
+    buf: &'a [u8],
+}
+
+impl<'a> MessageReader<'a> {
+    pub fn new(buf: &'a [u8]) -> Self {
+        Self { buf }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.buf.is_empty()
+    }
+
+    pub fn get_bytes(&mut self, len: usize) -> Result<&'a [u8]> {
+        if self.buf.len() < len {
+            return_errno_with_message!(Errno::EIO, "the 9P message is too short");
+        }
+        let (bytes, rest) = self.buf.split_at(len);
+        self.buf = rest;
+        Ok(bytes)
+    }
+
+    pub fn get_u8(&mut self) -> Result<u8> {
+        Ok(self.get_bytes(1)?[0])
+    }
+
+    pub fn get_u16(&mut self) -> Result<u16> {
+        Ok(u16::from_le_bytes(self.get_bytes(2)?.try_into().unwrap()))
+    }
+
+    pub fn get_u32(&mut self) -> Result<u32> {
+        Ok(u32::from_le_bytes(self.get_bytes(4)?.try_into().unwrap()))
+    }
+
+    pub fn get_u64(&mut self) -> Result<u64> {
+        Ok(u64::from_le_bytes(self.get_bytes(8)?.try_into().unwrap()))
+    }
+
+    pub fn get_str(&mut self) -> Result<&'a str> {
+        let len = self.get_u16()? as usize;
+        core::str::from_utf8(self.get_bytes(len)?)
+            .map_err(|_| Error::with_message(Errno::EIO, "invalid string in the 9P message"))
+    }
+
+    pub fn get_qid(&mut self) -> Result<Qid> {
+        Ok(Qid {
+            type_: self.get_u8()?,
+            version: self.get_u32()?,
+            path: self.get_u64()?,
+        })
+    }
+
+    fn get_time(&mut self) -> Result<Duration> {
+        let secs = self.get_u64()?;
+        let nsecs = self.get_u64()?;
+        Ok(Duration::new(secs, nsecs as u32))
+    }
+
+    /// Parses the fields of `Rgetattr`.
+    pub fn get_attr(&mut self) -> Result<Attr> {
+        let _valid = self.get_u64()?;
+        let qid = self.get_qid()?;
+        let mode = self.get_u32()?;
+        let uid = self.get_u32()?;
+        let gid = self.get_u32()?;
+        let nlink = self.get_u64()?;
+        let rdev = self.get_u64()?;
+        let size = self.get_u64()?;
+        let blksize = self.get_u64()?;
+        let blocks = self.get_u64()?;
+        let atime = self.get_time()?;
+        let mtime = self.get_time()?;
+        let ctime = self.get_time()?;
+        // The birth time, the generation and the data version are not used.
+        Ok(Attr {
+            qid,
+            mode,
+            uid,
+            gid,
+            nlink,
+            rdev,
+            size,
+            blksize,
+            blocks,
+            atime,
+            mtime,
+            ctime,
+        })
+    }
+
+    /// Parses the fields of `Rstatfs`.
+    pub fn get_statfs(&mut self) -> Result<Statfs> {
+        Ok(Statfs {
+            type_: self.get_u32()?,
+            bsize: self.get_u32()?,
+            blocks: self.get_u64()?,
+            bfree: self.get_u64()?,
+            bavail: self.get_u64()?,
+            files: self.get_u64()?,
+            ffree: self.get_u64()?,
+            fsid: self.get_u64()?,
+            namelen: self.get_u32()?,
+        })
+    }
+}
@@ -0,0 +1,370 @@
+// SPDX-License-Identifier: MPL-2.0
+
+use pod::Pod;
+
+use super::fat::ClusterId;
+use crate::prelude::*;
+
+pub(super) const DENTRY_SIZE: usize = 32;
+/// The maximum length of a long name, in UTF-16 code units.
+pub(super) const MAX_NAME_LENGTH: usize = 255;
+
+pub(super) const SHORT_NAME_LEN: usize = 11;
+const LONG_NAME_CHARS_PER_DENTRY: usize = 13;
+const LAST_LONG_DENTRY_FLAG: u8 = 0x40;
+pub(super) const DELETED_MARK: u8 = 0xE5;
+/// The first byte of the names that start with 0xE5 is stored as 0x05.
+const ESCAPED_DELETED_MARK: u8 = 0x05;
+
+// The flags in `ShortDentry::case_flags` set by Windows NT for the lower-case names.
+const LOWER_CASE_BASE: u8 = 0x08;
+const LOWER_CASE_EXT: u8 = 0x10;
+
+bitflags! {
+    pub struct FatAttr: u8 {
+        const READ_ONLY = 0x01;
+        const HIDDEN    = 0x02;
+        const SYSTEM    = 0x04;
+        const VOLUME_ID = 0x08;
+        const DIRECTORY = 0x10;
+        const ARCHIVE   = 0x20;
+        /// The attributes of a long name entry.
+        const LONG_NAME = 0x0F;
+    }
+}
+
+/// The short (8.3) entry, which holds the metadata of a file.
+#[repr(C, packed)]
+#[derive(Clone, Copy, Debug, Default, Pod)]
+pub(super) struct ShortDentry {
+    pub name: [u8; SHORT_NAME_LEN],
+    pub attr: u8,
+    pub case_flags: u8,
+    /// The creation time in 10 ms, within two seconds.
+    pub create_time_cs: u8,
+    pub create_time: u16,
+    pub create_date: u16,
+    pub access_date: u16,
+    pub first_cluster_hi: u16,
+    pub modify_time: u16,
+    pub modify_date: u16,
+    pub first_cluster_lo: u16,
+    pub size: u32,
+}
+
+impl ShortDentry {
+    pub fn attr(&self) -> FatAttr {
+        FatAttr::from_bits_truncate(self.attr)
+    }
+
+    pub fn first_cluster(&self) -> ClusterId {
+        ((self.first_cluster_hi as u32) << 16) | self.first_cluster_lo as u32
+    }
+
+    pub fn set_first_cluster(&mut self, cluster: ClusterId) {
+        self.first_cluster_hi = (cluster >> 16) as u16;
+        self.first_cluster_lo = cluster as u16;
+    }
+
+    pub fn is_dot(&self) -> bool {
+        self.name == *b".          " || self.name == *b"..         "
+    }
+
+    /// Returns the name made from the short name, for the files without long names.
+    pub fn name(&self) -> String {
+        let mut name = self.name;
+        if name[0] == ESCAPED_DELETED_MARK {
+            name[0] = DELETED_MARK;
+        }
+        // The bytes above 0x7F are in an OEM code page, which is not supported, so they
+        // are taken as Latin-1.
+        let to_string = |bytes: &[u8], lower_case: bool| -> String {
+            let len = bytes
+                .iter()
+                .rposition(|&b| b != b' ')
+                .map_or(0, |pos| pos + 1);
+            bytes[..len]
+                .iter()
+                .map(|&b| {
+                    let c = char::from(b);
+                    if lower_case {
+                        c.to_ascii_lowercase()
+                    } else {
+                        c
+                    }
+                })
+                .collect()
+        };
+
+        let mut result = to_string(&name[..8], self.case_flags & LOWER_CASE_BASE != 0);
+        let ext = to_string(&name[8..], self.case_flags & LOWER_CASE_EXT != 0);
+        if !ext.is_empty() {
+            result.push('.');
+            result.push_str(&ext);
+        }
+        result
+    }
+}
+
+/// The long name entry, which holds 13 UTF-16 code units of a long name.
+///
+/// The long name entries precede the short entry of a file in the reverse order, i.e.,
+/// the one with the end of the name comes first.
+#[repr(C, packed)]
+#[derive(Clone, Copy, Debug, Default, Pod)]
+pub(super) struct LongDentry {
+    pub order: u8,
+    pub name1: [u8; 10],
+    pub attr: u8,
+    pub type_: u8,
+    pub checksum: u8,
+    pub name2: [u8; 12],
+    pub first_cluster: u16,
+    pub name3: [u8; 4],
+}
+
+impl LongDentry {
+    fn new(order: u8, checksum: u8, units: &[u16; LONG_NAME_CHARS_PER_DENTRY]) -> Self {
+        let mut bytes = [0u8; LONG_NAME_CHARS_PER_DENTRY * 2];
+        for (chunk, unit) in bytes.chunks_exact_mut(2).zip(units.iter()) {
+            chunk.copy_from_slice(&unit.to_le_bytes());
+        }
+        let mut dentry = Self {
+            order,
+            attr: FatAttr::LONG_NAME.bits(),
+            checksum,
+            ..Default::default()
+        };
+        dentry.name1.copy_from_slice(&bytes[..10]);
+        dentry.name2.copy_from_slice(&bytes[10..22]);
+        dentry.name3.copy_from_slice(&bytes[22..]);
+        dentry
+    }
+
+    fn units(&self) -> impl Iterator<Item = u16> + '_ {
+        self.name1
+            .chunks_exact(2)
+            .chain(self.name2.chunks_exact(2))
+            .chain(self.name3.chunks_exact(2))
+            .map(|chunk| u16::from_le_bytes([chunk[0], chunk[1]]))
+    }
+}
+
+/// A directory entry slot.
+#[derive(Clone, Copy, Debug)]
+pub(super) enum RawDentry {
+    /// The slot is free, and so are all the following slots.
+    End,
+    Deleted,
+    Long(LongDentry),
+    Short(ShortDentry),
+}
+
+impl RawDentry {
+    pub fn parse(buf: &[u8; DENTRY_SIZE]) -> Self {
+        match buf[0] {
+            0 => RawDentry::End,
+            DELETED_MARK => RawDentry::Deleted,
+            _ if buf[11] & FatAttr::LONG_NAME.bits() == FatAttr::LONG_NAME.bits() => {
+                RawDentry::Long(LongDentry::from_bytes(buf))
+            }
+            _ => RawDentry::Short(ShortDentry::from_bytes(buf)),
+        }
+    }
+}
+
+/// Assembles the long name from the long name entries before a short entry.
+///
+/// The long name is dropped if the entries are out of order or do not belong to the
+/// short entry, e.g., if they were left by a system that does not know long names.
+#[derive(Debug, Default)]
+pub(super) struct LongNameBuilder {
+    units: Vec<u16>,
+    checksum: u8,
+    /// The order of the next expected entry, which is zero if there is no long name.
+    next_order: u8,
+    first_slot: usize,
+}
+
+impl LongNameBuilder {
+    pub fn push(&mut self, slot: usize, dentry: &LongDentry) {
+        let order = dentry.order & !LAST_LONG_DENTRY_FLAG;
+        if dentry.order & LAST_LONG_DENTRY_FLAG != 0 {
+            self.units.clear();
+            self.checksum = dentry.checksum;
+            self.first_slot = slot;
+        } else if self.next_order == 0
+            || order != self.next_order
+            || dentry.checksum != self.checksum
+        {
+            self.reset();
+            return;
+        }
+        if order == 0 {
+            self.reset();
+            return;
+        }
+
+        // The entries come in the reverse order, so the units are prepended.
+        let units: Vec<u16> = dentry.units().collect();
+        self.units.splice(0..0, units);
+        self.next_order = order - 1;
+    }
+
+    pub fn reset(&mut self) {
+        self.units.clear();
+        self.next_order = 0;
+    }
+
+    /// Returns the name of the file of the short entry, and the first slot of its entries.
+    pub fn finish(&mut self, slot: usize, dentry: &ShortDentry) -> (String, usize) {
+        let is_complete = !self.units.is_empty()
+            && self.next_order == 0
+            && self.checksum == checksum(&dentry.name);
+        let result = if is_complete {
+            let len = self
+                .units
+                .iter()
+                .position(|&unit| unit == 0)
+                .unwrap_or(self.units.len());
+            let name = char::decode_utf16(self.units[..len].iter().copied())
+                .map(|c| c.unwrap_or(char::REPLACEMENT_CHARACTER))
+                .collect();
+            (name, self.first_slot)
+        } else {
+            (dentry.name(), slot)
+        };
+        self.reset();
+        result
+    }
+}
+
+/// Returns the checksum of a short name, which is kept in its long name entries.
+pub(super) fn checksum(short_name: &[u8; SHORT_NAME_LEN]) -> u8 {
+    short_name.iter().fold(0u8, |sum, &b| {
+        (sum >> 1).wrapping_add(sum << 7).wrapping_add(b)
+    })
+}
+
+/// Makes the long name entries of `name` in the order they are stored.
+pub(super) fn make_long_dentries(name: &str, checksum: u8) -> Vec<LongDentry> {
+    let units: Vec<u16> = name.encode_utf16().collect();
+    let num_dentries = units.len().div_ceil(LONG_NAME_CHARS_PER_DENTRY);
+    let mut dentries: Vec<LongDentry> = (0..num_dentries)
+        .map(|idx| {
+            // The name is terminated by a zero if there is room, and padded with 0xFFFF.
+            let mut chunk = [0xFFFF; LONG_NAME_CHARS_PER_DENTRY];
+            let start = idx * LONG_NAME_CHARS_PER_DENTRY;
+            let end = units.len().min(start + LONG_NAME_CHARS_PER_DENTRY);
+            chunk[..end - start].copy_from_slice(&units[start..end]);
+            if end - start < LONG_NAME_CHARS_PER_DENTRY {
+                chunk[end - start] = 0;
+            }
+
+            let mut order = idx as u8 + 1;
+            if idx == num_dentries - 1 {
+                order |= LAST_LONG_DENTRY_FLAG;
+            }
+            LongDentry::new(order, checksum, &chunk)
+        })
+        .collect();
+    dentries.reverse();
+    dentries
+}
+
+/// Checks whether `name` is a valid long name.
+pub(super) fn check_name(name: &str) -> Result<()> {
+    if name.is_empty() || name == "." || name == ".." {
+        return_errno_with_message!(Errno::EINVAL, "invalid name");
+    }
+    if name.encode_utf16().count() > MAX_NAME_LENGTH {
+        return_errno!(Errno::ENAMETOOLONG);
+    }
+    if name
+        .chars()
+        .any(|c| (c as u32) < 0x20 || "\"*/:<>?\\|".contains(c))
+    {
+        return_errno_with_message!(Errno::EINVAL, "invalid character in name");
+    }
+    Ok(())
+}
+
+/// Strips the trailing dots, which are ignored by the VFAT file systems.
+pub(super) fn strip_name(name: &str) -> &str {
+    match name {
+        "." | ".." => name,
+        name => name.trim_end_matches('.'),
+    }
+}
+
+/// Returns whether two names are the same, which are compared case-insensitively.
+pub(super) fn name_eq(a: &str, b: &str) -> bool {
+    a.to_uppercase() == b.to_uppercase()
+}
+
+fn is_short_name_char(c: char) -> bool {
+    c.is_ascii_uppercase() || c.is_ascii_digit() || "$%'-_@~`!(){}^#&".contains(c)
+}
+
+/// Returns the short name of `name` if it is a valid upper-case 8.3 name, which is stored
+/// without long name entries.
+pub(super) fn exact_short_name(name: &str) -> Option<[u8; SHORT_NAME_LEN]> {
+    let (base, ext) = name.split_once('.').unwrap_or((name, ""));
+    if base.is_empty()
+        || base.len() > 8
+        || ext.len() > 3
+        || !base.chars().chain(ext.chars()).all(is_short_name_char)
+    {
+        return None;
+    }
+
+    let mut short_name = [b' '; SHORT_NAME_LEN];
+    short_name[..base.len()].copy_from_slice(base.as_bytes());
+    short_name[8..8 + ext.len()].copy_from_slice(ext.as_bytes());
+    Some(short_name)
+}
+
+/// Generates a unique short name like `LONGNA~1.TXT` for a long name.
+pub(super) fn generate_short_name(
+    name: &str,
+    mut is_taken: impl FnMut(&[u8; SHORT_NAME_LEN]) -> Result<bool>,
+) -> Result<[u8; SHORT_NAME_LEN]> {
+    let to_short = |s: &str, max_len: usize| -> Vec<u8> {
+        s.chars()
+            .filter(|&c| c != ' ' && c != '.')
+            .map(|c| {
+                let c = c.to_ascii_uppercase();
+                if is_short_name_char(c) {
+                    c as u8
+                } else {
+                    b'_'
+                }
+            })
+            .take(max_len)
+            .collect()
+    };
+
+    let name = name.trim_start_matches('.');
+    let (base, ext) = match name.rfind('.') {
+        Some(pos) => (&name[..pos], &name[pos + 1..]),
+        None => (name, ""),
+    };
+    let mut base = to_short(base, 8);
+    if base.is_empty() {
+        base.push(b'_');
+    }
+    let ext = to_short(ext, 3);
+
+    for num in 1..1_000_000 {
+        let tail = format!("~{}", num);
+        let base_len = base.len().min(8 - tail.len());
+        let mut short_name = [b' '; SHORT_NAME_LEN];
+        short_name[..base_len].copy_from_slice(&base[..base_len]);
+        short_name[base_len..base_len + tail.len()].copy_from_slice(tail.as_bytes());
+        short_name[8..8 + ext.len()].copy_from_slice(&ext);
+        if !is_taken(&short_name)? {
+            return Ok(short_name);
+        }
+    }
+    return_errno_with_message!(Errno::EEXIST, "no short name is available")
+}
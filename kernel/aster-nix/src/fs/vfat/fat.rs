@@ -0,0 +1,271 @@
+// SPDX-License-Identifier: MPL-2.0
+
+use super::{fs::VfatFS, super_block::FatType};
+use crate::prelude::*;
+
+pub type ClusterId = u32;
+
+/// Cluster 0 and 1 are reserved, so the first cluster of the data region is 2.
+pub const FIRST_DATA_CLUSTER: ClusterId = 2;
+
+/// The value of a FAT entry.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum FatEntry {
+    Free,
+    Next(ClusterId),
+    Bad,
+    EndOfChain,
+}
+
+impl FatType {
+    fn bad_value(&self) -> u32 {
+        match self {
+            FatType::Fat12 => 0xFF7,
+            FatType::Fat16 => 0xFFF7,
+            FatType::Fat32 => 0x0FFF_FFF7,
+        }
+    }
+
+    fn end_of_chain_value(&self) -> u32 {
+        match self {
+            FatType::Fat12 => 0xFFF,
+            FatType::Fat16 => 0xFFFF,
+            FatType::Fat32 => 0x0FFF_FFFF,
+        }
+    }
+
+    fn decode(&self, raw: u32) -> FatEntry {
+        let bad = self.bad_value();
+        match raw {
+            0 => FatEntry::Free,
+            raw if raw == bad => FatEntry::Bad,
+            // The reserved values below the bad cluster mark are treated as the ends too.
+            raw if raw > bad || raw < FIRST_DATA_CLUSTER => FatEntry::EndOfChain,
+            raw => FatEntry::Next(raw),
+        }
+    }
+
+    fn encode(&self, entry: FatEntry) -> u32 {
+        match entry {
+            FatEntry::Free => 0,
+            FatEntry::Next(cluster) => cluster,
+            FatEntry::Bad => self.bad_value(),
+            FatEntry::EndOfChain => self.end_of_chain_value(),
+        }
+    }
+}
+
+/// The hints for the cluster allocation.
+#[derive(Debug)]
+pub(super) struct FatAllocator {
+    /// Where to start searching for free clusters.
+    pub next_free: ClusterId,
+    pub num_free: u32,
+}
+
+impl VfatFS {
+    /// Returns the position of the entry of `cluster` in the FAT copy `fat_idx`.
+    fn fat_entry_pos(&self, fat_idx: usize, cluster: ClusterId) -> usize {
+        let sb = self.super_block();
+        let offset = match sb.fat_type {
+            FatType::Fat12 => cluster as usize + cluster as usize / 2,
+            FatType::Fat16 => cluster as usize * 2,
+            FatType::Fat32 => cluster as usize * 4,
+        };
+        sb.fat_start + fat_idx * sb.fat_size + offset
+    }
+
+    pub(super) fn read_fat(&self, cluster: ClusterId) -> Result<FatEntry> {
+        if !self.is_valid_cluster(cluster) {
+            return_errno_with_message!(Errno::EIO, "invalid access to FAT");
+        }
+
+        let pos = self.fat_entry_pos(0, cluster);
+        let fat_type = self.super_block().fat_type;
+        let raw = match fat_type {
+            FatType::Fat12 => {
+                // The 12-bit entries are packed, so an entry straddles two bytes.
+                let mut buf = [0u8; 2];
+                self.read_bytes_at(pos, &mut buf)?;
+                let value = u16::from_le_bytes(buf);
+                if cluster % 2 == 0 {
+                    (value & 0xFFF) as u32
+                } else {
+                    (value >> 4) as u32
+                }
+            }
+            FatType::Fat16 => {
+                let mut buf = [0u8; 2];
+                self.read_bytes_at(pos, &mut buf)?;
+                u16::from_le_bytes(buf) as u32
+            }
+            FatType::Fat32 => {
+                let mut buf = [0u8; 4];
+                self.read_bytes_at(pos, &mut buf)?;
+                // The high 4 bits are reserved.
+                u32::from_le_bytes(buf) & 0x0FFF_FFFF
+            }
+        };
+        Ok(fat_type.decode(raw))
+    }
+
+    /// Writes the entry of `cluster` to all the FAT copies.
+    pub(super) fn write_fat(&self, cluster: ClusterId, entry: FatEntry) -> Result<()> {
+        if !self.is_valid_cluster(cluster) {
+            return_errno_with_message!(Errno::EIO, "invalid access to FAT");
+        }
+
+        let fat_type = self.super_block().fat_type;
+        let value = fat_type.encode(entry);
+        for fat_idx in 0..self.super_block().num_fats {
+            let pos = self.fat_entry_pos(fat_idx, cluster);
+            match fat_type {
+                FatType::Fat12 => {
+                    let mut buf = [0u8; 2];
+                    self.read_bytes_at(pos, &mut buf)?;
+                    let old = u16::from_le_bytes(buf);
+                    let new = if cluster % 2 == 0 {
+                        (old & 0xF000) | value as u16
+                    } else {
+                        (old & 0x000F) | ((value as u16) << 4)
+                    };
+                    self.write_bytes_at(pos, &new.to_le_bytes())?;
+                }
+                FatType::Fat16 => {
+                    self.write_bytes_at(pos, &(value as u16).to_le_bytes())?;
+                }
+                FatType::Fat32 => {
+                    let mut buf = [0u8; 4];
+                    self.read_bytes_at(pos, &mut buf)?;
+                    let old = u32::from_le_bytes(buf);
+                    let new = (old & 0xF000_0000) | value;
+                    self.write_bytes_at(pos, &new.to_le_bytes())?;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Returns the clusters of the chain that starts from `start`.
+    pub(super) fn read_chain(&self, start: ClusterId) -> Result<Vec<ClusterId>> {
+        let mut clusters = Vec::new();
+        if start == 0 {
+            return Ok(clusters);
+        }
+
+        let mut cluster = start;
+        loop {
+            if clusters.len() >= self.super_block().num_clusters as usize {
+                return_errno_with_message!(Errno::EIO, "the cluster chain has a loop");
+            }
+            clusters.push(cluster);
+            match self.read_fat(cluster)? {
+                FatEntry::Next(next) => cluster = next,
+                FatEntry::EndOfChain => break,
+                FatEntry::Free | FatEntry::Bad => {
+                    return_errno_with_message!(Errno::EIO, "the cluster chain is broken");
+                }
+            }
+        }
+        Ok(clusters)
+    }
+
+    /// Counts the free clusters by scanning the FAT.
+    pub(super) fn count_free_clusters(&self) -> Result<u32> {
+        let mut num_free = 0;
+        for cluster in self.cluster_range() {
+            if self.read_fat(cluster)? == FatEntry::Free {
+                num_free += 1;
+            }
+        }
+        Ok(num_free)
+    }
+
+    /// Allocates `num` clusters and appends them to the chain whose last cluster is `last`,
+    /// or makes them a new chain if `last` is `None`.
+    ///
+    /// The allocated clusters are zeroed. The caller must hold the lock of the file system.
+    pub(super) fn alloc_clusters(
+        &self,
+        last: Option<ClusterId>,
+        num: usize,
+    ) -> Result<Vec<ClusterId>> {
+        let mut allocator = self.allocator().lock();
+        if (allocator.num_free as usize) < num {
+            return_errno_with_message!(Errno::ENOSPC, "no free clusters");
+        }
+
+        let range = self.cluster_range();
+        let mut clusters = Vec::with_capacity(num);
+        let mut candidate = allocator.next_free.clamp(range.start, range.end - 1);
+        let mut nr_scanned = 0;
+        while clusters.len() < num {
+            if nr_scanned == range.len() {
+                // The free count was wrong. Give back what has been taken.
+                for &cluster in clusters.iter() {
+                    self.write_fat(cluster, FatEntry::Free)?;
+                }
+                allocator.num_free = clusters.len() as u32;
+                return_errno_with_message!(Errno::ENOSPC, "no free clusters");
+            }
+            if self.read_fat(candidate)? == FatEntry::Free {
+                self.write_fat(candidate, FatEntry::EndOfChain)?;
+                if let Some(&prev) = clusters.last() {
+                    self.write_fat(prev, FatEntry::Next(candidate))?;
+                }
+                clusters.push(candidate);
+            }
+            nr_scanned += 1;
+            candidate += 1;
+            if candidate == range.end {
+                candidate = range.start;
+            }
+        }
+        allocator.next_free = candidate;
+        allocator.num_free -= num as u32;
+        drop(allocator);
+
+        let cluster_size = self.cluster_size();
+        for &cluster in clusters.iter() {
+            self.zero_bytes_at(self.cluster_to_pos(cluster), cluster_size)?;
+        }
+        if let (Some(last), Some(&first)) = (last, clusters.first()) {
+            self.write_fat(last, FatEntry::Next(first))?;
+        }
+        Ok(clusters)
+    }
+
+    /// Frees the chain that starts from `start`.
+    ///
+    /// The caller must hold the lock of the file system.
+    pub(super) fn free_chain(&self, start: Option<ClusterId>) -> Result<()> {
+        let Some(start) = start else {
+            return Ok(());
+        };
+        let clusters = self.read_chain(start)?;
+        for &cluster in clusters.iter() {
+            self.write_fat(cluster, FatEntry::Free)?;
+        }
+
+        let mut allocator = self.allocator().lock();
+        allocator.num_free += clusters.len() as u32;
+        allocator.next_free = allocator.next_free.min(start);
+        Ok(())
+    }
+
+    /// Frees the clusters after `last`, which becomes the end of the chain.
+    ///
+    /// The caller must hold the lock of the file system.
+    pub(super) fn truncate_chain(&self, last: ClusterId) -> Result<()> {
+        let next = match self.read_fat(last)? {
+            FatEntry::Next(next) => Some(next),
+            _ => None,
+        };
+        self.write_fat(last, FatEntry::EndOfChain)?;
+        self.free_chain(next)
+    }
+
+    fn cluster_range(&self) -> core::ops::Range<ClusterId> {
+        FIRST_DATA_CLUSTER..FIRST_DATA_CLUSTER + self.super_block().num_clusters
+    }
+}
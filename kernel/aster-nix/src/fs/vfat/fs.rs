@@ -0,0 +1,266 @@
+// SPDX-License-Identifier: MPL-2.0
+
+use aster_block::{
+    bio::{BioStatus, BioWaiter},
+    id::BlockId,
+    BlockDevice,
+};
+use aster_frame::mm::{Frame, VmIo};
+use spin::Once;
+
+use super::{
+    dentry::MAX_NAME_LENGTH,
+    fat::{ClusterId, FatAllocator, FIRST_DATA_CLUSTER},
+    inode::VfatInode,
+    super_block::{FatType, VfatBootSector, VfatFsInfo, VfatSuperBlock},
+};
+use crate::{
+    fs::utils::{FileSystem, FsFlags, Inode, PageCache, PageCacheBackend, SuperBlock},
+    prelude::*,
+};
+
+/// The magic number reported by statfs (`MSDOS_SUPER_MAGIC` in Linux).
+const VFAT_MAGIC: u64 = 0x4d44;
+
+/// A VFAT file system, i.e., FAT12, FAT16 or FAT32 with long file names.
+#[derive(Debug)]
+pub struct VfatFS {
+    block_device: Arc<dyn BlockDevice>,
+    super_block: VfatSuperBlock,
+    allocator: Mutex<FatAllocator>,
+    root: Once<Arc<VfatInode>>,
+    /// The opened inodes, indexed by the positions of their short entries.
+    inodes: Mutex<BTreeMap<usize, Weak<VfatInode>>>,
+    /// The first clusters of the files that are deleted while they were opened, which
+    /// are freed after the files are closed.
+    orphans: SpinLock<Vec<ClusterId>>,
+    /// The cache of the whole device.
+    ///
+    /// The FAT, the directories and the file contents are all accessed through it. A
+    /// cluster may be smaller than a page, so caching the contents per file would let a
+    /// page be cached twice with different contents.
+    cache: PageCache,
+    /// A global lock, which must be held to modify the FAT or the directories.
+    mutex: Mutex<()>,
+}
+
+impl VfatFS {
+    pub fn open(block_device: Arc<dyn BlockDevice>) -> Result<Arc<Self>> {
+        let boot_sector = block_device.read_val::<VfatBootSector>(0)?;
+        let signature = block_device.read_val::<u16>(510)?;
+        let super_block = VfatSuperBlock::parse(&boot_sector, signature)?;
+
+        let fs = Arc::new_cyclic(|weak_self| VfatFS {
+            block_device,
+            super_block,
+            allocator: Mutex::new(FatAllocator {
+                next_free: FIRST_DATA_CLUSTER,
+                num_free: 0,
+            }),
+            root: Once::new(),
+            inodes: Mutex::new(BTreeMap::new()),
+            orphans: SpinLock::new(Vec::new()),
+            cache: PageCache::with_capacity(super_block.fs_size(), weak_self.clone() as _).unwrap(),
+            mutex: Mutex::new(()),
+        });
+
+        // Trust the hints in the FSInfo sector, which are maintained by the other systems
+        // as well, or count the free clusters if they are missing.
+        let fs_info = super_block
+            .fs_info_start
+            .map(|pos| fs.read_val::<VfatFsInfo>(pos))
+            .transpose()?
+            .filter(|fs_info| fs_info.is_valid() && fs_info.free_count <= super_block.num_clusters);
+        let allocator = match fs_info {
+            Some(fs_info) => FatAllocator {
+                next_free: fs_info.next_free,
+                num_free: fs_info.free_count,
+            },
+            None => FatAllocator {
+                next_free: FIRST_DATA_CLUSTER,
+                num_free: fs.count_free_clusters()?,
+            },
+        };
+        *fs.allocator.lock() = allocator;
+
+        let root = VfatInode::new_root(&fs)?;
+        fs.root.call_once(|| root);
+        Ok(fs)
+    }
+
+    pub(super) fn super_block(&self) -> &VfatSuperBlock {
+        &self.super_block
+    }
+
+    pub(super) fn allocator(&self) -> &Mutex<FatAllocator> {
+        &self.allocator
+    }
+
+    pub(super) fn cluster_size(&self) -> usize {
+        self.super_block.cluster_size
+    }
+
+    pub(super) fn is_valid_cluster(&self, cluster: ClusterId) -> bool {
+        cluster >= FIRST_DATA_CLUSTER
+            && cluster < FIRST_DATA_CLUSTER + self.super_block.num_clusters
+    }
+
+    /// Returns the position of a cluster on the device.
+    pub(super) fn cluster_to_pos(&self, cluster: ClusterId) -> usize {
+        self.super_block.data_start
+            + (cluster - FIRST_DATA_CLUSTER) as usize * self.super_block.cluster_size
+    }
+
+    pub(super) fn read_bytes_at(&self, pos: usize, buf: &mut [u8]) -> Result<()> {
+        self.cache.pages().read_bytes(pos, buf)?;
+        Ok(())
+    }
+
+    pub(super) fn write_bytes_at(&self, pos: usize, buf: &[u8]) -> Result<()> {
+        self.cache.pages().write_bytes(pos, buf)?;
+        Ok(())
+    }
+
+    pub(super) fn zero_bytes_at(&self, pos: usize, len: usize) -> Result<()> {
+        self.cache.pages().clear(pos..pos + len)?;
+        Ok(())
+    }
+
+    fn read_val<T: Pod>(&self, pos: usize) -> Result<T> {
+        Ok(self.cache.pages().read_val(pos)?)
+    }
+
+    /// Locks the file system, and frees the clusters of the closed orphans meanwhile.
+    pub(super) fn lock(&self) -> MutexGuard<()> {
+        let guard = self.mutex.lock();
+        let orphans = core::mem::take(&mut *self.orphans.lock());
+        for cluster in orphans {
+            if let Err(e) = self.free_chain(Some(cluster)) {
+                warn!("failed to free the clusters of an orphan: {:?}", e);
+            }
+        }
+        guard
+    }
+
+    pub(super) fn add_orphan(&self, cluster: ClusterId) {
+        self.orphans.lock().push(cluster);
+    }
+
+    /// Returns the opened inode of the short entry at `pos`, or builds and records one.
+    pub(super) fn get_or_insert_inode(
+        &self,
+        pos: usize,
+        build: impl FnOnce() -> Result<Arc<VfatInode>>,
+    ) -> Result<Arc<VfatInode>> {
+        let mut inodes = self.inodes.lock();
+        if let Some(inode) = inodes.get(&pos).and_then(Weak::upgrade) {
+            return Ok(inode);
+        }
+        let inode = build()?;
+        inodes.insert(pos, Arc::downgrade(&inode));
+        Ok(inode)
+    }
+
+    pub(super) fn insert_inode(&self, pos: usize, inode: &Arc<VfatInode>) {
+        self.inodes.lock().insert(pos, Arc::downgrade(inode));
+    }
+
+    /// Records that the short entry of an opened inode has moved from `old_pos` to `new_pos`.
+    pub(super) fn move_inode(&self, old_pos: usize, new_pos: usize) {
+        let mut inodes = self.inodes.lock();
+        if let Some(inode) = inodes.remove(&old_pos) {
+            inodes.insert(new_pos, inode);
+        }
+    }
+
+    /// Forgets the inode of the short entry at `pos` if it has been dropped.
+    pub(super) fn remove_inode(&self, pos: usize) {
+        let mut inodes = self.inodes.lock();
+        if inodes
+            .get(&pos)
+            .is_some_and(|inode| inode.strong_count() == 0)
+        {
+            inodes.remove(&pos);
+        }
+    }
+
+    /// Forgets the inode of the short entry at `pos`, which has been deleted.
+    pub(super) fn forget_inode(&self, pos: usize) {
+        self.inodes.lock().remove(&pos);
+    }
+
+    fn write_fs_info(&self) -> Result<()> {
+        let Some(pos) = self.super_block.fs_info_start else {
+            return Ok(());
+        };
+        let fs_info = {
+            let allocator = self.allocator.lock();
+            VfatFsInfo::new(allocator.num_free, allocator.next_free)
+        };
+        self.cache.pages().write_val(pos, &fs_info)?;
+        Ok(())
+    }
+
+    fn num_free_clusters(&self) -> u32 {
+        self.allocator.lock().num_free
+    }
+}
+
+impl PageCacheBackend for VfatFS {
+    fn read_page(&self, idx: usize, frame: &Frame) -> Result<BioWaiter> {
+        if idx >= self.npages() {
+            return_errno_with_message!(Errno::EINVAL, "invalid read size")
+        }
+        let waiter = self
+            .block_device
+            .read_block(BlockId::new(idx as u64), frame)?;
+        Ok(waiter)
+    }
+
+    fn write_page(&self, idx: usize, frame: &Frame) -> Result<BioWaiter> {
+        if idx >= self.npages() {
+            return_errno_with_message!(Errno::EINVAL, "invalid write size")
+        }
+        let waiter = self
+            .block_device
+            .write_block(BlockId::new(idx as u64), frame)?;
+        Ok(waiter)
+    }
+
+    fn npages(&self) -> usize {
+        self.super_block.fs_size().div_ceil(PAGE_SIZE)
+    }
+}
+
+impl FileSystem for VfatFS {
+    fn sync(&self) -> Result<()> {
+        let _guard = self.lock();
+        if self.super_block.fat_type == FatType::Fat32 {
+            self.write_fs_info()?;
+        }
+        self.cache.evict_range(0..self.super_block.fs_size())?;
+        match self.block_device.flush_sync()? {
+            BioStatus::Complete => Ok(()),
+            err_status => Err(Error::from(err_status)),
+        }
+    }
+
+    fn root_inode(&self) -> Arc<dyn Inode> {
+        self.root.get().unwrap().clone()
+    }
+
+    fn sb(&self) -> SuperBlock {
+        // Count the clusters of the closed orphans as free.
+        drop(self.lock());
+
+        let mut sb = SuperBlock::new(VFAT_MAGIC, self.cluster_size(), MAX_NAME_LENGTH);
+        sb.blocks = self.super_block.num_clusters as usize;
+        sb.bfree = self.num_free_clusters() as usize;
+        sb.bavail = sb.bfree;
+        sb
+    }
+
+    fn flags(&self) -> FsFlags {
+        FsFlags::empty()
+    }
+}
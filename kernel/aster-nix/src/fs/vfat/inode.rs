@@ -0,0 +1,947 @@
+// SPDX-License-Identifier: MPL-2.0
+
+use core::{ops::Range, time::Duration};
+
+use super::{
+    dentry::{
+        check_name, checksum, exact_short_name, generate_short_name, make_long_dentries, name_eq,
+        strip_name, FatAttr, LongNameBuilder, RawDentry, ShortDentry, DELETED_MARK, DENTRY_SIZE,
+        SHORT_NAME_LEN,
+    },
+    fat::ClusterId,
+    fs::VfatFS,
+    utils::DosTimestamp,
+};
+use crate::{
+    events::IoEvents,
+    fs::{
+        device::Device,
+        utils::{DirentVisitor, FileSystem, Inode, InodeMode, InodeType, Metadata},
+    },
+    prelude::*,
+    process::{signal::Poller, Gid, Uid},
+};
+
+const ROOT_INO: u64 = 1;
+
+/// The maximum size of a file, which is recorded in 32 bits.
+const MAX_FILE_SIZE: usize = u32::MAX as usize;
+
+/// An inode of a VFAT file system.
+///
+/// The metadata of a file is kept in its short entry, so an inode records where the entry
+/// is, and writes it back there when the metadata changes. The inode number is made from
+/// the position as well, so it does not change unless the file is renamed.
+///
+/// The file contents are accessed through the cache of the device, so the inodes have no
+/// page caches and the files cannot be mapped to memory.
+#[derive(Debug)]
+pub struct VfatInode {
+    ino: u64,
+    fs: Weak<VfatFS>,
+    this: Weak<VfatInode>,
+    inner: RwMutex<Inner>,
+}
+
+#[derive(Debug)]
+struct Inner {
+    type_: InodeType,
+    /// Where the entries are in the parent directory, which is `None` for the root.
+    entry: Option<EntryPos>,
+    /// The short entry, which holds the metadata.
+    dentry: ShortDentry,
+    clusters: Vec<ClusterId>,
+    /// The size of the file, or the size of the slots of the directory.
+    size: usize,
+    is_deleted: bool,
+}
+
+#[derive(Clone, Debug)]
+struct EntryPos {
+    parent: Arc<VfatInode>,
+    /// The slot of the first entry, i.e., the first long name entry if there is one.
+    first_slot: usize,
+    /// The slot of the short entry.
+    slot: usize,
+    /// The position of the short entry on the device.
+    pos: usize,
+}
+
+/// A file in a directory.
+#[derive(Debug)]
+struct DirEntry {
+    name: String,
+    dentry: ShortDentry,
+    first_slot: usize,
+    slot: usize,
+}
+
+impl VfatInode {
+    pub(super) fn new_root(fs: &Arc<VfatFS>) -> Result<Arc<Self>> {
+        let sb = fs.super_block();
+        let (clusters, size) = if sb.root_dir_size != 0 {
+            (Vec::new(), sb.root_dir_size)
+        } else {
+            let clusters = fs.read_chain(sb.root_cluster)?;
+            let size = clusters.len() * fs.cluster_size();
+            (clusters, size)
+        };
+        let mut dentry = ShortDentry {
+            attr: FatAttr::DIRECTORY.bits(),
+            ..Default::default()
+        };
+        dentry.set_first_cluster(clusters.first().copied().unwrap_or(0));
+
+        Ok(Arc::new_cyclic(|this| Self {
+            ino: ROOT_INO,
+            fs: Arc::downgrade(fs),
+            this: this.clone(),
+            inner: RwMutex::new(Inner {
+                type_: InodeType::Dir,
+                entry: None,
+                dentry,
+                clusters,
+                size,
+                is_deleted: false,
+            }),
+        }))
+    }
+
+    fn new(fs: &Arc<VfatFS>, entry: EntryPos, dentry: ShortDentry) -> Result<Arc<Self>> {
+        let clusters = fs.read_chain(dentry.first_cluster())?;
+        let allocated_size = clusters.len() * fs.cluster_size();
+        let (type_, size) = if dentry.attr().contains(FatAttr::DIRECTORY) {
+            (InodeType::Dir, allocated_size)
+        } else {
+            (InodeType::File, (dentry.size as usize).min(allocated_size))
+        };
+
+        Ok(Arc::new_cyclic(|this| Self {
+            ino: (entry.pos / DENTRY_SIZE) as u64,
+            fs: Arc::downgrade(fs),
+            this: this.clone(),
+            inner: RwMutex::new(Inner {
+                type_,
+                entry: Some(entry),
+                dentry,
+                clusters,
+                size,
+                is_deleted: false,
+            }),
+        }))
+    }
+
+    fn fs(&self) -> Arc<VfatFS> {
+        self.fs.upgrade().unwrap()
+    }
+
+    fn this(&self) -> Arc<Self> {
+        self.this.upgrade().unwrap()
+    }
+
+    /// Returns the inode of a file in this directory.
+    fn get_inode(&self, fs: &Arc<VfatFS>, inner: &Inner, entry: &DirEntry) -> Result<Arc<Self>> {
+        let pos = inner.slot_pos(fs, entry.slot);
+        fs.get_or_insert_inode(pos, || {
+            let entry_pos = EntryPos {
+                parent: self.this(),
+                first_slot: entry.first_slot,
+                slot: entry.slot,
+                pos,
+            };
+            Self::new(fs, entry_pos, entry.dentry)
+        })
+    }
+
+    /// Finds the file named `name` in this directory, and returns its entry and inode.
+    fn lookup_entry(&self, fs: &Arc<VfatFS>, name: &str) -> Result<(DirEntry, Arc<Self>)> {
+        let inner = self.inner.read();
+        if inner.type_ != InodeType::Dir {
+            return_errno!(Errno::ENOTDIR);
+        }
+        let Some(entry) = inner.find_entry(fs, name)? else {
+            return_errno!(Errno::ENOENT);
+        };
+        let inode = self.get_inode(fs, &inner, &entry)?;
+        Ok((entry, inode))
+    }
+
+    /// Adds the entries of a file to this directory, and returns the inode of the file.
+    fn add_entry(
+        &self,
+        fs: &Arc<VfatFS>,
+        name: &str,
+        mut dentry: ShortDentry,
+    ) -> Result<Arc<Self>> {
+        let mut inner = self.inner.write();
+        let (first_slot, slot) = inner.add_entry(fs, name, &mut dentry)?;
+        inner.touch(fs)?;
+
+        let pos = inner.slot_pos(fs, slot);
+        let entry_pos = EntryPos {
+            parent: self.this(),
+            first_slot,
+            slot,
+            pos,
+        };
+        let inode = Self::new(fs, entry_pos, dentry)?;
+        fs.insert_inode(pos, &inode);
+        Ok(inode)
+    }
+
+    /// Removes the entries of a file from this directory.
+    ///
+    /// If `is_deleted` is true, the file is deleted, whose clusters are freed once the
+    /// inode is dropped. Otherwise, the file is being moved elsewhere.
+    fn remove_entry(
+        &self,
+        fs: &Arc<VfatFS>,
+        entry: &DirEntry,
+        inode: &VfatInode,
+        is_deleted: bool,
+    ) -> Result<()> {
+        let mut inner = self.inner.write();
+        for slot in entry.first_slot..=entry.slot {
+            fs.write_bytes_at(inner.slot_pos(fs, slot), &[DELETED_MARK])?;
+        }
+        inner.touch(fs)?;
+
+        if is_deleted {
+            inode.inner.write().is_deleted = true;
+            fs.forget_inode(inner.slot_pos(fs, entry.slot));
+        }
+        Ok(())
+    }
+
+    /// Returns whether `inode` is this directory or one of its ancestors.
+    fn is_descendant_of(&self, inode: &VfatInode) -> bool {
+        let mut current = self.this();
+        loop {
+            if core::ptr::eq(current.as_ref(), inode) {
+                return true;
+            }
+            let parent = match current.inner.read().entry.as_ref() {
+                Some(entry) => entry.parent.clone(),
+                None => return false,
+            };
+            current = parent;
+        }
+    }
+
+    /// Returns the cluster that the `..` entries of the subdirectories point to.
+    fn dir_cluster(&self) -> ClusterId {
+        let inner = self.inner.read();
+        match inner.entry {
+            // The `..` entries point to cluster 0 for the root, even on FAT32.
+            None => 0,
+            Some(_) => inner.dentry.first_cluster(),
+        }
+    }
+
+    fn new_dentry(attr: FatAttr) -> ShortDentry {
+        let now = DosTimestamp::now();
+        ShortDentry {
+            attr: attr.bits(),
+            create_time_cs: now.time_cs,
+            create_time: now.time,
+            create_date: now.date,
+            access_date: now.date,
+            modify_time: now.time,
+            modify_date: now.date,
+            ..Default::default()
+        }
+    }
+
+    fn create_dir(&self, fs: &Arc<VfatFS>, name: &str) -> Result<Arc<Self>> {
+        let cluster = fs.alloc_clusters(None, 1)?[0];
+        let mut dentry = Self::new_dentry(FatAttr::DIRECTORY);
+        dentry.set_first_cluster(cluster);
+
+        // Every directory except the root starts with the `.` and `..` entries.
+        let mut dot = dentry;
+        dot.name = *b".          ";
+        let mut dot_dot = dentry;
+        dot_dot.name = *b"..         ";
+        dot_dot.set_first_cluster(self.dir_cluster());
+        let pos = fs.cluster_to_pos(cluster);
+        let result = fs
+            .write_bytes_at(pos, dot.as_bytes())
+            .and_then(|_| fs.write_bytes_at(pos + DENTRY_SIZE, dot_dot.as_bytes()))
+            .and_then(|_| self.add_entry(fs, name, dentry));
+        if result.is_err() {
+            fs.free_chain(Some(cluster))?;
+        }
+        result
+    }
+}
+
+impl Inner {
+    fn is_dir(&self) -> bool {
+        self.type_ == InodeType::Dir
+    }
+
+    /// Returns the position of the byte at `offset` on the device.
+    fn offset_to_pos(&self, fs: &VfatFS, offset: usize) -> usize {
+        if self.entry.is_none() && self.clusters.is_empty() {
+            // The root directory of FAT12/16 is in a fixed region.
+            return fs.super_block().root_dir_start + offset;
+        }
+        let cluster_size = fs.cluster_size();
+        fs.cluster_to_pos(self.clusters[offset / cluster_size]) + offset % cluster_size
+    }
+
+    fn mode(&self) -> InodeMode {
+        // There are no permissions but the read-only attribute.
+        let mut mode = InodeMode::from_bits_truncate(0o755);
+        if self.dentry.attr().contains(FatAttr::READ_ONLY) {
+            mode.remove(InodeMode::S_IWUSR | InodeMode::S_IWGRP | InodeMode::S_IWOTH);
+        }
+        mode
+    }
+
+    fn atime(&self) -> Duration {
+        // Only the date is recorded.
+        DosTimestamp {
+            date: self.dentry.access_date,
+            ..Default::default()
+        }
+        .as_duration()
+    }
+
+    fn mtime(&self) -> Duration {
+        DosTimestamp {
+            date: self.dentry.modify_date,
+            time: self.dentry.modify_time,
+            time_cs: 0,
+        }
+        .as_duration()
+    }
+
+    fn slot_pos(&self, fs: &VfatFS, slot: usize) -> usize {
+        self.offset_to_pos(fs, slot * DENTRY_SIZE)
+    }
+
+    fn num_slots(&self) -> usize {
+        self.size / DENTRY_SIZE
+    }
+
+    /// Splits the range of the file into the ranges on the device, merging the
+    /// adjacent clusters.
+    fn device_ranges(&self, fs: &VfatFS, range: Range<usize>) -> Vec<Range<usize>> {
+        let cluster_size = fs.cluster_size();
+        let mut ranges: Vec<Range<usize>> = Vec::new();
+        let mut offset = range.start;
+        while offset < range.end {
+            let len = (cluster_size - offset % cluster_size).min(range.end - offset);
+            let pos = self.offset_to_pos(fs, offset);
+            match ranges.last_mut() {
+                Some(last) if last.end == pos => last.end += len,
+                _ => ranges.push(pos..pos + len),
+            }
+            offset += len;
+        }
+        ranges
+    }
+
+    /// Visits the files from `start_slot` on, until `visit` returns `false`.
+    fn visit_entries(
+        &self,
+        fs: &VfatFS,
+        start_slot: usize,
+        mut visit: impl FnMut(DirEntry) -> Result<bool>,
+    ) -> Result<()> {
+        let mut builder = LongNameBuilder::default();
+        let mut buf = [0u8; DENTRY_SIZE];
+        for slot in start_slot..self.num_slots() {
+            fs.read_bytes_at(self.slot_pos(fs, slot), &mut buf)?;
+            let dentry = match RawDentry::parse(&buf) {
+                RawDentry::End => break,
+                RawDentry::Deleted => {
+                    builder.reset();
+                    continue;
+                }
+                RawDentry::Long(dentry) => {
+                    builder.push(slot, &dentry);
+                    continue;
+                }
+                RawDentry::Short(dentry) => dentry,
+            };
+            if dentry.attr().contains(FatAttr::VOLUME_ID) || dentry.is_dot() {
+                builder.reset();
+                continue;
+            }
+
+            let (name, first_slot) = builder.finish(slot, &dentry);
+            let entry = DirEntry {
+                name,
+                dentry,
+                first_slot,
+                slot,
+            };
+            if !visit(entry)? {
+                break;
+            }
+        }
+        Ok(())
+    }
+
+    /// Finds a file by its long name or by its short name.
+    fn find_entry(&self, fs: &VfatFS, name: &str) -> Result<Option<DirEntry>> {
+        let name = strip_name(name);
+        let mut result = None;
+        self.visit_entries(fs, 0, |entry| {
+            if name_eq(&entry.name, name) || name_eq(&entry.dentry.name(), name) {
+                result = Some(entry);
+                return Ok(false);
+            }
+            Ok(true)
+        })?;
+        Ok(result)
+    }
+
+    fn is_empty_dir(&self, fs: &VfatFS) -> Result<bool> {
+        let mut is_empty = true;
+        self.visit_entries(fs, 0, |_| {
+            is_empty = false;
+            Ok(false)
+        })?;
+        Ok(is_empty)
+    }
+
+    fn num_subdirs(&self, fs: &VfatFS) -> Result<usize> {
+        let mut num_subdirs = 0;
+        self.visit_entries(fs, 0, |entry| {
+            if entry.dentry.attr().contains(FatAttr::DIRECTORY) {
+                num_subdirs += 1;
+            }
+            Ok(true)
+        })?;
+        Ok(num_subdirs)
+    }
+
+    /// Finds `num` consecutive free slots, and extends the directory if there are not.
+    fn find_free_slots(&mut self, fs: &VfatFS, num: usize) -> Result<usize> {
+        let mut buf = [0u8; DENTRY_SIZE];
+        let mut run_start = 0;
+        let mut run_len = 0;
+        let mut slot = 0;
+        loop {
+            while slot < self.num_slots() {
+                fs.read_bytes_at(self.slot_pos(fs, slot), &mut buf)?;
+                match RawDentry::parse(&buf) {
+                    RawDentry::End | RawDentry::Deleted => {
+                        if run_len == 0 {
+                            run_start = slot;
+                        }
+                        run_len += 1;
+                        if run_len == num {
+                            return Ok(run_start);
+                        }
+                    }
+                    _ => run_len = 0,
+                }
+                slot += 1;
+            }
+
+            if self.entry.is_none() && self.clusters.is_empty() {
+                return_errno_with_message!(Errno::ENOSPC, "the root directory is full");
+            }
+            // The new clusters are zeroed, which are free slots.
+            let clusters = fs.alloc_clusters(self.clusters.last().copied(), 1)?;
+            self.clusters.extend(clusters);
+            self.size += fs.cluster_size();
+        }
+    }
+
+    /// Adds the entries of a file named `name`, and sets the short name of `dentry`.
+    ///
+    /// Returns the first slot and the slot of the short entry.
+    fn add_entry(
+        &mut self,
+        fs: &VfatFS,
+        name: &str,
+        dentry: &mut ShortDentry,
+    ) -> Result<(usize, usize)> {
+        let is_short_name_taken = |short_name: &[u8; SHORT_NAME_LEN]| -> Result<bool> {
+            let mut is_taken = false;
+            self.visit_entries(fs, 0, |entry| {
+                is_taken = entry.dentry.name == *short_name;
+                Ok(!is_taken)
+            })?;
+            Ok(is_taken)
+        };
+
+        // A long name is needed unless the name is a valid 8.3 name in upper case.
+        let exact_short_name = match exact_short_name(name) {
+            Some(short_name) if !is_short_name_taken(&short_name)? => Some(short_name),
+            _ => None,
+        };
+        let long_dentries = match exact_short_name {
+            Some(short_name) => {
+                dentry.name = short_name;
+                Vec::new()
+            }
+            None => {
+                dentry.name = generate_short_name(name, is_short_name_taken)?;
+                make_long_dentries(name, checksum(&dentry.name))
+            }
+        };
+
+        let first_slot = self.find_free_slots(fs, long_dentries.len() + 1)?;
+        for (slot, long_dentry) in (first_slot..).zip(long_dentries.iter()) {
+            fs.write_bytes_at(self.slot_pos(fs, slot), long_dentry.as_bytes())?;
+        }
+        let slot = first_slot + long_dentries.len();
+        fs.write_bytes_at(self.slot_pos(fs, slot), dentry.as_bytes())?;
+        Ok((first_slot, slot))
+    }
+
+    /// Writes the metadata back to the short entry.
+    fn write_back(&self, fs: &VfatFS) -> Result<()> {
+        let Some(entry) = self.entry.as_ref() else {
+            return Ok(());
+        };
+        if self.is_deleted {
+            return Ok(());
+        }
+        fs.write_bytes_at(entry.pos, self.dentry.as_bytes())
+    }
+
+    /// Updates the modification time, and writes it back.
+    fn touch(&mut self, fs: &VfatFS) -> Result<()> {
+        let now = DosTimestamp::now();
+        self.dentry.modify_date = now.date;
+        self.dentry.modify_time = now.time;
+        if !self.is_dir() {
+            self.dentry.attr |= FatAttr::ARCHIVE.bits();
+        }
+        self.write_back(fs)
+    }
+
+    fn resize(&mut self, fs: &VfatFS, new_size: usize) -> Result<()> {
+        if new_size > MAX_FILE_SIZE {
+            return_errno!(Errno::EFBIG);
+        }
+
+        let cluster_size = fs.cluster_size();
+        let num_clusters = new_size.div_ceil(cluster_size);
+        if num_clusters > self.clusters.len() {
+            let new_clusters = fs.alloc_clusters(
+                self.clusters.last().copied(),
+                num_clusters - self.clusters.len(),
+            )?;
+            if self.clusters.is_empty() {
+                self.dentry.set_first_cluster(new_clusters[0]);
+            }
+            self.clusters.extend(new_clusters);
+        } else if num_clusters < self.clusters.len() {
+            if num_clusters == 0 {
+                fs.free_chain(Some(self.clusters[0]))?;
+                self.dentry.set_first_cluster(0);
+            } else {
+                fs.truncate_chain(self.clusters[num_clusters - 1])?;
+            }
+            self.clusters.truncate(num_clusters);
+        }
+
+        // The new clusters are zeroed, but the tail of the last old cluster may not be.
+        if new_size > self.size {
+            let tail_end = new_size.min(self.size.next_multiple_of(cluster_size));
+            for range in self.device_ranges(fs, self.size..tail_end) {
+                fs.zero_bytes_at(range.start, range.len())?;
+            }
+        }
+
+        self.size = new_size;
+        self.dentry.size = new_size as u32;
+        Ok(())
+    }
+}
+
+impl Drop for VfatInode {
+    fn drop(&mut self) {
+        let Some(fs) = self.fs.upgrade() else {
+            return;
+        };
+        let inner = self.inner.read();
+        if let Some(entry) = inner.entry.as_ref() {
+            fs.remove_inode(entry.pos);
+        }
+        // The clusters cannot be freed here, since the file system may be locked.
+        if inner.is_deleted && !inner.clusters.is_empty() {
+            fs.add_orphan(inner.clusters[0]);
+        }
+    }
+}
+
+impl Inode for VfatInode {
+    fn size(&self) -> usize {
+        self.inner.read().size
+    }
+
+    fn resize(&self, new_size: usize) -> Result<()> {
+        let fs = self.fs();
+        let _guard = fs.lock();
+        let mut inner = self.inner.write();
+        if inner.is_dir() {
+            return_errno!(Errno::EISDIR);
+        }
+
+        inner.resize(&fs, new_size)?;
+        inner.touch(&fs)
+    }
+
+    fn metadata(&self) -> Metadata {
+        let fs = self.fs();
+        let inner = self.inner.read();
+        let nlinks = if inner.is_dir() {
+            2 + inner.num_subdirs(&fs).unwrap_or(0)
+        } else {
+            1
+        };
+        Metadata {
+            dev: 0,
+            ino: self.ino,
+            size: inner.size,
+            blk_size: fs.cluster_size(),
+            blocks: inner.clusters.len(),
+            atime: inner.atime(),
+            mtime: inner.mtime(),
+            // The change time is not recorded.
+            ctime: inner.mtime(),
+            type_: inner.type_,
+            mode: inner.mode(),
+            nlinks,
+            uid: Uid::new_root(),
+            gid: Gid::new_root(),
+            rdev: 0,
+        }
+    }
+
+    fn ino(&self) -> u64 {
+        self.ino
+    }
+
+    fn type_(&self) -> InodeType {
+        self.inner.read().type_
+    }
+
+    fn mode(&self) -> Result<InodeMode> {
+        Ok(self.inner.read().mode())
+    }
+
+    fn set_mode(&self, mode: InodeMode) -> Result<()> {
+        let fs = self.fs();
+        let mut inner = self.inner.write();
+        if mode.contains(InodeMode::S_IWUSR) {
+            inner.dentry.attr &= !FatAttr::READ_ONLY.bits();
+        } else {
+            inner.dentry.attr |= FatAttr::READ_ONLY.bits();
+        }
+        inner.write_back(&fs)
+    }
+
+    fn owner(&self) -> Result<Uid> {
+        Ok(Uid::new_root())
+    }
+
+    fn set_owner(&self, _uid: Uid) -> Result<()> {
+        // Pass through.
+        Ok(())
+    }
+
+    fn group(&self) -> Result<Gid> {
+        Ok(Gid::new_root())
+    }
+
+    fn set_group(&self, _gid: Gid) -> Result<()> {
+        // Pass through.
+        Ok(())
+    }
+
+    fn atime(&self) -> Duration {
+        self.inner.read().atime()
+    }
+
+    fn set_atime(&self, time: Duration) {
+        let fs = self.fs();
+        let mut inner = self.inner.write();
+        inner.dentry.access_date = DosTimestamp::from_duration(time).date;
+        let _ = inner.write_back(&fs);
+    }
+
+    fn mtime(&self) -> Duration {
+        self.inner.read().mtime()
+    }
+
+    fn set_mtime(&self, time: Duration) {
+        let fs = self.fs();
+        let mut inner = self.inner.write();
+        let timestamp = DosTimestamp::from_duration(time);
+        inner.dentry.modify_date = timestamp.date;
+        inner.dentry.modify_time = timestamp.time;
+        let _ = inner.write_back(&fs);
+    }
+
+    fn read_at(&self, offset: usize, buf: &mut [u8]) -> Result<usize> {
+        let fs = self.fs();
+        let inner = self.inner.read();
+        if inner.is_dir() {
+            return_errno!(Errno::EISDIR);
+        }
+
+        let start = offset.min(inner.size);
+        let end = offset.saturating_add(buf.len()).min(inner.size);
+        let mut buf_offset = 0;
+        for range in inner.device_ranges(&fs, start..end) {
+            let len = range.len();
+            fs.read_bytes_at(range.start, &mut buf[buf_offset..buf_offset + len])?;
+            buf_offset += len;
+        }
+        Ok(buf_offset)
+    }
+
+    fn read_direct_at(&self, offset: usize, buf: &mut [u8]) -> Result<usize> {
+        self.read_at(offset, buf)
+    }
+
+    fn write_at(&self, offset: usize, buf: &[u8]) -> Result<usize> {
+        if buf.is_empty() {
+            return Ok(0);
+        }
+        let fs = self.fs();
+        let _guard = fs.lock();
+        let mut inner = self.inner.write();
+        if inner.is_dir() {
+            return_errno!(Errno::EISDIR);
+        }
+
+        let end = offset
+            .checked_add(buf.len())
+            .filter(|&end| end <= MAX_FILE_SIZE)
+            .ok_or(Error::new(Errno::EFBIG))?;
+        if end > inner.size {
+            inner.resize(&fs, end)?;
+        }
+        let mut buf_offset = 0;
+        for range in inner.device_ranges(&fs, offset..end) {
+            let len = range.len();
+            fs.write_bytes_at(range.start, &buf[buf_offset..buf_offset + len])?;
+            buf_offset += len;
+        }
+        inner.touch(&fs)?;
+        Ok(buf_offset)
+    }
+
+    fn write_direct_at(&self, offset: usize, buf: &[u8]) -> Result<usize> {
+        self.write_at(offset, buf)
+    }
+
+    fn create(&self, name: &str, type_: InodeType, mode: InodeMode) -> Result<Arc<dyn Inode>> {
+        let name = strip_name(name);
+        check_name(name)?;
+        let fs = self.fs();
+        let _guard = fs.lock();
+        {
+            let inner = self.inner.read();
+            if !inner.is_dir() {
+                return_errno!(Errno::ENOTDIR);
+            }
+            if inner.find_entry(&fs, name)?.is_some() {
+                return_errno!(Errno::EEXIST);
+            }
+        }
+
+        let inode = match type_ {
+            InodeType::File => {
+                let mut attr = FatAttr::ARCHIVE;
+                if !mode.contains(InodeMode::S_IWUSR) {
+                    attr |= FatAttr::READ_ONLY;
+                }
+                self.add_entry(&fs, name, Self::new_dentry(attr))?
+            }
+            InodeType::Dir => self.create_dir(&fs, name)?,
+            _ => return_errno_with_message!(Errno::EPERM, "unsupported file type"),
+        };
+        Ok(inode)
+    }
+
+    fn mknod(
+        &self,
+        _name: &str,
+        _mode: InodeMode,
+        _dev: Arc<dyn Device>,
+    ) -> Result<Arc<dyn Inode>> {
+        return_errno_with_message!(Errno::EPERM, "device files are not supported")
+    }
+
+    fn readdir_at(&self, offset: usize, visitor: &mut dyn DirentVisitor) -> Result<usize> {
+        let fs = self.fs();
+        let inner = self.inner.read();
+        if !inner.is_dir() {
+            return_errno!(Errno::ENOTDIR);
+        }
+
+        // The offsets 0 and 1 are for `.` and `..`, and the offset of a file is its slot
+        // plus 2.
+        let try_readdir = |offset: &mut usize, visitor: &mut dyn DirentVisitor| -> Result<()> {
+            if *offset == 0 {
+                visitor.visit(".", self.ino, InodeType::Dir, 1)?;
+                *offset = 1;
+            }
+            if *offset == 1 {
+                let parent_ino = inner
+                    .entry
+                    .as_ref()
+                    .map_or(self.ino, |entry| entry.parent.ino);
+                visitor.visit("..", parent_ino, InodeType::Dir, 2)?;
+                *offset = 2;
+            }
+
+            inner.visit_entries(&fs, *offset - 2, |entry| {
+                let pos = inner.slot_pos(&fs, entry.slot);
+                let type_ = if entry.dentry.attr().contains(FatAttr::DIRECTORY) {
+                    InodeType::Dir
+                } else {
+                    InodeType::File
+                };
+                let next_offset = entry.slot + 3;
+                visitor.visit(&entry.name, (pos / DENTRY_SIZE) as u64, type_, next_offset)?;
+                *offset = next_offset;
+                Ok(true)
+            })
+        };
+
+        let mut iterate_offset = offset;
+        match try_readdir(&mut iterate_offset, visitor) {
+            Err(e) if iterate_offset == offset => Err(e),
+            _ => Ok(iterate_offset - offset),
+        }
+    }
+
+    fn link(&self, _old: &Arc<dyn Inode>, _name: &str) -> Result<()> {
+        return_errno_with_message!(Errno::EPERM, "hard links are not supported")
+    }
+
+    fn unlink(&self, name: &str) -> Result<()> {
+        let fs = self.fs();
+        let _guard = fs.lock();
+        let (entry, inode) = self.lookup_entry(&fs, name)?;
+        if inode.type_() == InodeType::Dir {
+            return_errno!(Errno::EISDIR);
+        }
+
+        self.remove_entry(&fs, &entry, &inode, true)
+    }
+
+    fn rmdir(&self, name: &str) -> Result<()> {
+        let fs = self.fs();
+        let _guard = fs.lock();
+        let (entry, inode) = self.lookup_entry(&fs, name)?;
+        {
+            let inner = inode.inner.read();
+            if !inner.is_dir() {
+                return_errno!(Errno::ENOTDIR);
+            }
+            if !inner.is_empty_dir(&fs)? {
+                return_errno!(Errno::ENOTEMPTY);
+            }
+        }
+
+        self.remove_entry(&fs, &entry, &inode, true)
+    }
+
+    fn lookup(&self, name: &str) -> Result<Arc<dyn Inode>> {
+        let fs = self.fs();
+        let (_, inode) = self.lookup_entry(&fs, name)?;
+        Ok(inode)
+    }
+
+    fn rename(&self, old_name: &str, target: &Arc<dyn Inode>, new_name: &str) -> Result<()> {
+        let new_name = strip_name(new_name);
+        check_name(new_name)?;
+        let Some(target) = target.downcast_ref::<VfatInode>() else {
+            return_errno_with_message!(Errno::EXDEV, "not a VFAT inode");
+        };
+        if !target.inner.read().is_dir() {
+            return_errno!(Errno::ENOTDIR);
+        }
+
+        let fs = self.fs();
+        let _guard = fs.lock();
+        let (old_entry, old_inode) = self.lookup_entry(&fs, old_name)?;
+        let is_dir = old_inode.type_() == InodeType::Dir;
+        if is_dir && target.is_descendant_of(&old_inode) {
+            return_errno_with_message!(Errno::EINVAL, "move a directory into itself");
+        }
+
+        let is_same_dir = core::ptr::eq(self, target);
+        match target.lookup_entry(&fs, new_name) {
+            // Only the case of the name changes.
+            Ok((entry, _)) if is_same_dir && entry.slot == old_entry.slot => {
+                if entry.name == new_name {
+                    return Ok(());
+                }
+            }
+            Ok((entry, inode)) => {
+                let inner = inode.inner.read();
+                if is_dir && !inner.is_dir() {
+                    return_errno!(Errno::ENOTDIR);
+                }
+                if !is_dir && inner.is_dir() {
+                    return_errno!(Errno::EISDIR);
+                }
+                if inner.is_dir() && !inner.is_empty_dir(&fs)? {
+                    return_errno!(Errno::ENOTEMPTY);
+                }
+                drop(inner);
+                target.remove_entry(&fs, &entry, &inode, true)?;
+            }
+            Err(e) if e.error() == Errno::ENOENT => {}
+            Err(e) => return Err(e),
+        }
+
+        // Move the entries, with the short name generated again for the new name. The new
+        // entries are added first, so the file is not lost if there is no space for them.
+        let mut inner = old_inode.inner.write();
+        let (first_slot, slot, pos) = {
+            let mut target_inner = target.inner.write();
+            let (first_slot, slot) = target_inner.add_entry(&fs, new_name, &mut inner.dentry)?;
+            target_inner.touch(&fs)?;
+            (first_slot, slot, target_inner.slot_pos(&fs, slot))
+        };
+        self.remove_entry(&fs, &old_entry, &old_inode, false)?;
+        let old_pos = inner.entry.as_ref().unwrap().pos;
+        fs.move_inode(old_pos, pos);
+        inner.entry = Some(EntryPos {
+            parent: target.this(),
+            first_slot,
+            slot,
+            pos,
+        });
+
+        if is_dir && !is_same_dir {
+            // The `..` entry is the second slot.
+            let mut buf = [0u8; DENTRY_SIZE];
+            let dot_dot_pos = inner.slot_pos(&fs, 1);
+            fs.read_bytes_at(dot_dot_pos, &mut buf)?;
+            let mut dot_dot = ShortDentry::from_bytes(&buf);
+            dot_dot.set_first_cluster(target.dir_cluster());
+            fs.write_bytes_at(dot_dot_pos, dot_dot.as_bytes())?;
+        }
+        Ok(())
+    }
+
+    fn sync(&self) -> Result<()> {
+        self.fs().sync()
+    }
+
+    fn poll(&self, mask: IoEvents, _poller: Option<&Poller>) -> IoEvents {
+        let events = IoEvents::IN | IoEvents::OUT;
+        events & mask
+    }
+
+    fn fs(&self) -> Arc<dyn FileSystem> {
+        self.fs()
+    }
+}
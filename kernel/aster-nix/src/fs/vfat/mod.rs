@@ -0,0 +1,361 @@
+// SPDX-License-Identifier: MPL-2.0
+
+//! The VFAT file system, i.e., FAT12, FAT16 and FAT32 with long file names.
+//!
+//! It is what the EFI system partitions and most removable disks are formatted with.
+//! The file names are case-insensitive, and a file with a name that is not a valid 8.3
+//! upper-case name has a generated short name like `LONGNA~1.TXT` besides its long name.
+//! There are no permissions, owners, links or special files.
+
+mod dentry;
+mod fat;
+mod fs;
+mod inode;
+mod super_block;
+mod utils;
+
+pub use fs::VfatFS;
+pub use inode::VfatInode;
+
+#[cfg(ktest)]
+mod test {
+    use alloc::fmt::Debug;
+
+    use aster_block::{
+        bio::{BioEnqueueError, BioStatus, BioType, SubmittedBio},
+        BlockDevice,
+    };
+    use aster_frame::mm::{FrameAllocOptions, Segment, VmIo};
+
+    use super::super_block::VfatBootSector;
+    use crate::{
+        fs::{
+            utils::{DirentVisitor, FileSystem, Inode, InodeMode, InodeType},
+            vfat::VfatFS,
+        },
+        prelude::*,
+    };
+
+    const SECTOR_SIZE: usize = 512;
+
+    /// A block device in memory.
+    struct VfatMemoryDisk(Segment);
+
+    impl Debug for VfatMemoryDisk {
+        fn fmt(&self, f: &mut core::fmt::Formatter) -> core::fmt::Result {
+            f.debug_struct("VfatMemoryDisk")
+                .field("nframes", &self.0.nframes())
+                .finish()
+        }
+    }
+
+    impl BlockDevice for VfatMemoryDisk {
+        fn enqueue(&self, bio: SubmittedBio) -> core::result::Result<(), BioEnqueueError> {
+            let mut cur_device_ofs = bio.sid_range().start.to_raw() as usize * SECTOR_SIZE;
+            for seg in bio.segments() {
+                let size = match bio.type_() {
+                    BioType::Read => seg
+                        .writer()
+                        .write(&mut self.0.reader().skip(cur_device_ofs)),
+                    BioType::Write => self
+                        .0
+                        .writer()
+                        .skip(cur_device_ofs)
+                        .write(&mut seg.reader()),
+                    _ => 0,
+                };
+                cur_device_ofs += size;
+            }
+            bio.complete(BioStatus::Complete);
+            Ok(())
+        }
+
+        fn max_nr_segments_per_bio(&self) -> usize {
+            usize::MAX
+        }
+    }
+
+    #[derive(Clone, Copy, PartialEq, Eq)]
+    enum FatBits {
+        Fat12 = 12,
+        Fat16 = 16,
+        Fat32 = 32,
+    }
+
+    /// Formats a disk with one sector per cluster, like `mkfs.vfat`.
+    fn format(fat_bits: FatBits, num_sectors: usize, root_entries: usize) -> Arc<VfatMemoryDisk> {
+        let segment = FrameAllocOptions::new(num_sectors * SECTOR_SIZE / PAGE_SIZE)
+            .is_contiguous(true)
+            .alloc_contiguous()
+            .unwrap();
+
+        let is_fat32 = fat_bits == FatBits::Fat32;
+        let reserved_sectors = if is_fat32 { 32 } else { 1 };
+        let root_sectors = root_entries * 32 / SECTOR_SIZE;
+        let mut fat_sectors = 1;
+        loop {
+            let num_clusters = num_sectors - reserved_sectors - root_sectors - 2 * fat_sectors;
+            let needed = ((num_clusters + 2) * fat_bits as usize).div_ceil(8 * SECTOR_SIZE);
+            if fat_sectors >= needed {
+                break;
+            }
+            fat_sectors = needed;
+        }
+
+        let mut boot_sector = VfatBootSector::new_zeroed();
+        boot_sector.jump_boot = [0xEB, 0x3C, 0x90];
+        boot_sector.oem_name = *b"MSWIN4.1";
+        boot_sector.bytes_per_sector = SECTOR_SIZE as u16;
+        boot_sector.sectors_per_cluster = 1;
+        boot_sector.reserved_sectors = reserved_sectors as u16;
+        boot_sector.num_fats = 2;
+        boot_sector.root_entries = root_entries as u16;
+        boot_sector.media = 0xF8;
+        boot_sector.total_sectors_32 = num_sectors as u32;
+        if is_fat32 {
+            boot_sector.fat_size_32 = fat_sectors as u32;
+            boot_sector.root_cluster = 2;
+            boot_sector.fs_info_sector = 1;
+        } else {
+            boot_sector.fat_size_16 = fat_sectors as u16;
+        }
+        segment.write_val(0, &boot_sector).unwrap();
+        segment.write_val(510, &0xAA55u16).unwrap();
+
+        // The entries of cluster 0 and 1, and that of the root directory of FAT32.
+        let fat_head: &[u8] = match fat_bits {
+            FatBits::Fat12 => &[0xF8, 0xFF, 0xFF],
+            FatBits::Fat16 => &[0xF8, 0xFF, 0xFF, 0xFF],
+            FatBits::Fat32 => &[
+                0xF8, 0xFF, 0xFF, 0x0F, 0xFF, 0xFF, 0xFF, 0x0F, 0xFF, 0xFF, 0xFF, 0x0F,
+            ],
+        };
+        for fat_idx in 0..2 {
+            let fat_start = (reserved_sectors + fat_idx * fat_sectors) * SECTOR_SIZE;
+            segment.write_bytes(fat_start, fat_head).unwrap();
+        }
+        // Leave the FSInfo sector of FAT32 invalid, so the free clusters are counted.
+
+        Arc::new(VfatMemoryDisk(segment))
+    }
+
+    fn mount(disk: &Arc<VfatMemoryDisk>) -> Arc<VfatFS> {
+        let fs = VfatFS::open(disk.clone() as _);
+        assert!(fs.is_ok(), "Fs failed to init:{:?}", fs.unwrap_err());
+        fs.unwrap()
+    }
+
+    /// Collects the names of the files in a directory.
+    #[derive(Default)]
+    struct NameCollector(Vec<String>);
+
+    impl DirentVisitor for NameCollector {
+        fn visit(
+            &mut self,
+            name: &str,
+            _ino: u64,
+            _type_: InodeType,
+            _offset: usize,
+        ) -> Result<()> {
+            if name != "." && name != ".." {
+                self.0.push(name.to_string());
+            }
+            Ok(())
+        }
+    }
+
+    fn list(dir: &Arc<dyn Inode>) -> Vec<String> {
+        let mut collector = NameCollector::default();
+        dir.readdir_at(0, &mut collector).unwrap();
+        collector.0
+    }
+
+    fn create_file(dir: &Arc<dyn Inode>, name: &str) -> Arc<dyn Inode> {
+        dir.create(name, InodeType::File, InodeMode::from_bits_truncate(0o644))
+            .unwrap()
+    }
+
+    fn check_read_write(fs: Arc<VfatFS>) {
+        let root = fs.root_inode();
+        let nr_free = fs.sb().bfree;
+
+        // Cover several non-contiguous clusters, which are smaller than a page.
+        let data: Vec<u8> = (0..3 * PAGE_SIZE + 100).map(|i| (i % 251) as u8).collect();
+        let file = create_file(&root, "DATA.BIN");
+        let other = create_file(&root, "OTHER.BIN");
+        for chunk in data.chunks(700) {
+            let offset = file.size();
+            file.write_at(offset, chunk).unwrap();
+            other.write_at(offset, chunk).unwrap();
+        }
+        let mut buf = vec![0u8; data.len()];
+        assert_eq!(file.read_at(0, &mut buf).unwrap(), data.len());
+        assert_eq!(buf, data);
+        assert!(fs.sb().bfree < nr_free);
+
+        // The truncated part reads as zeros after the file grows.
+        file.resize(1000).unwrap();
+        file.resize(2000).unwrap();
+        assert_eq!(file.read_at(0, &mut buf).unwrap(), 2000);
+        assert_eq!(&buf[..1000], &data[..1000]);
+        assert!(buf[1000..2000].iter().all(|&b| b == 0));
+
+        root.unlink("DATA.BIN").unwrap();
+        root.unlink("OTHER.BIN").unwrap();
+        drop(file);
+        drop(other);
+        assert_eq!(fs.sb().bfree, nr_free);
+    }
+
+    #[ktest]
+    fn read_write_fat12() {
+        check_read_write(mount(&format(FatBits::Fat12, 2048, 64)));
+    }
+
+    #[ktest]
+    fn read_write_fat16() {
+        check_read_write(mount(&format(FatBits::Fat16, 16384, 512)));
+    }
+
+    #[ktest]
+    fn read_write_fat32() {
+        check_read_write(mount(&format(FatBits::Fat32, 68000, 0)));
+    }
+
+    #[ktest]
+    fn long_names() {
+        let fs = mount(&format(FatBits::Fat16, 16384, 512));
+        let root = fs.root_inode();
+
+        let long_name = "A file with a rather long name, longer than 26 chars.txt";
+        create_file(&root, long_name);
+        create_file(&root, "lower.txt");
+        create_file(&root, "UPPER.TXT");
+        assert_eq!(list(&root), vec![long_name, "lower.txt", "UPPER.TXT"]);
+
+        // The names are case-insensitive, and the short names can be looked up too.
+        let file = root.lookup(&long_name.to_uppercase()).unwrap();
+        assert_eq!(root.lookup("AFILEW~1.TXT").unwrap().ino(), file.ino());
+        assert_eq!(
+            root.lookup("Lower.TXT").unwrap().ino(),
+            root.lookup("lower.txt").unwrap().ino()
+        );
+        assert_eq!(
+            root.create(
+                "upper.txt",
+                InodeType::File,
+                InodeMode::from_bits_truncate(0o644)
+            )
+            .unwrap_err()
+            .error(),
+            Errno::EEXIST
+        );
+        assert_eq!(
+            root.create("a:b", InodeType::File, InodeMode::from_bits_truncate(0o644))
+                .unwrap_err()
+                .error(),
+            Errno::EINVAL
+        );
+
+        // The short names are unique.
+        create_file(&root, "A file with another long name.txt");
+        assert!(root.lookup("AFILEW~2.TXT").is_ok());
+    }
+
+    #[ktest]
+    fn directories() {
+        let disk = format(FatBits::Fat32, 68000, 0);
+        let fs = mount(&disk);
+        let root = fs.root_inode();
+        let mode = InodeMode::from_bits_truncate(0o755);
+
+        let dir = root.create("Some Directory", InodeType::Dir, mode).unwrap();
+        let other_dir = root.create("OTHER", InodeType::Dir, mode).unwrap();
+        // More files than fit in a cluster.
+        let names: Vec<String> = (0..40).map(|i| format!("file number {}", i)).collect();
+        for name in names.iter() {
+            create_file(&dir, name);
+        }
+        assert_eq!(list(&dir), names);
+        assert_eq!(
+            root.rmdir("some directory").unwrap_err().error(),
+            Errno::ENOTEMPTY
+        );
+
+        // Move files and directories.
+        dir.rename("file number 0", &other_dir, "Moved File")
+            .unwrap();
+        root.rename("Some Directory", &other_dir, "Moved Directory")
+            .unwrap();
+        root.rename("OTHER", &root, "other").unwrap();
+        assert_eq!(list(&root), vec!["other"]);
+        assert_eq!(list(&other_dir), vec!["Moved File", "Moved Directory"]);
+        assert_eq!(
+            other_dir
+                .rename("Moved Directory", &dir, "Into Itself")
+                .unwrap_err()
+                .error(),
+            Errno::EINVAL
+        );
+
+        // Everything is on the disk after syncing.
+        fs.sync().unwrap();
+        drop(dir);
+        drop(other_dir);
+        drop(root);
+        drop(fs);
+        let fs = mount(&disk);
+        let root = fs.root_inode();
+        let other_dir = root.lookup("OTHER").unwrap();
+        assert_eq!(list(&other_dir), vec!["Moved File", "Moved Directory"]);
+        let dir = other_dir.lookup("moved directory").unwrap();
+        assert_eq!(list(&dir), &names[1..]);
+        for name in names[1..].iter() {
+            dir.unlink(name).unwrap();
+        }
+        other_dir.rmdir("Moved Directory").unwrap();
+        assert_eq!(
+            other_dir.lookup("Moved Directory").unwrap_err().error(),
+            Errno::ENOENT
+        );
+    }
+
+    #[ktest]
+    fn full_root_directory() {
+        let fs = mount(&format(FatBits::Fat16, 16384, 32));
+        let root = fs.root_inode();
+
+        // The root directory of FAT16 cannot grow.
+        for i in 0..32 {
+            create_file(&root, &format!("F{}", i));
+        }
+        assert_eq!(
+            root.create("F32", InodeType::File, InodeMode::from_bits_truncate(0o644))
+                .unwrap_err()
+                .error(),
+            Errno::ENOSPC
+        );
+        root.unlink("F0").unwrap();
+        create_file(&root, "F32");
+    }
+
+    #[ktest]
+    fn unlink_opened_file() {
+        let fs = mount(&format(FatBits::Fat16, 16384, 512));
+        let root = fs.root_inode();
+        let nr_free = fs.sb().bfree;
+
+        let file = create_file(&root, "opened");
+        file.write_at(0, &[1u8; 2048]).unwrap();
+        root.unlink("opened").unwrap();
+        assert!(root.lookup("opened").is_err());
+
+        // The content is kept until the file is closed.
+        let mut buf = [0u8; 2048];
+        assert_eq!(file.read_at(0, &mut buf).unwrap(), 2048);
+        assert!(buf.iter().all(|&b| b == 1));
+        assert!(fs.sb().bfree < nr_free);
+        drop(file);
+        assert_eq!(fs.sb().bfree, nr_free);
+    }
+}
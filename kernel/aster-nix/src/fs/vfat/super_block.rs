@@ -0,0 +1,222 @@
+// SPDX-License-Identifier: MPL-2.0
+
+use pod::Pod;
+
+use super::fat::{ClusterId, FIRST_DATA_CLUSTER};
+use crate::prelude::*;
+
+const BOOT_SIGNATURE: u16 = 0xAA55;
+const FSINFO_LEAD_SIGNATURE: u32 = 0x4161_5252;
+const FSINFO_STRUCT_SIGNATURE: u32 = 0x6141_7272;
+const FSINFO_TRAIL_SIGNATURE: u32 = 0xAA55_0000;
+
+/// The FAT variants, which are told apart by the number of clusters.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum FatType {
+    Fat12,
+    Fat16,
+    Fat32,
+}
+
+impl FatType {
+    fn from_num_clusters(num_clusters: u32) -> Self {
+        if num_clusters < 4085 {
+            FatType::Fat12
+        } else if num_clusters < 65525 {
+            FatType::Fat16
+        } else {
+            FatType::Fat32
+        }
+    }
+}
+
+/// The boot sector with the BIOS parameter block.
+///
+/// The fields after `total_sectors_32` are only valid for FAT32. FAT12/16 have the
+/// extended boot record there instead, which is not used.
+#[repr(C, packed)]
+#[derive(Clone, Copy, Debug, Pod)]
+pub(super) struct VfatBootSector {
+    pub jump_boot: [u8; 3],
+    pub oem_name: [u8; 8],
+    pub bytes_per_sector: u16,
+    pub sectors_per_cluster: u8,
+    pub reserved_sectors: u16,
+    pub num_fats: u8,
+    pub root_entries: u16,
+    pub total_sectors_16: u16,
+    pub media: u8,
+    pub fat_size_16: u16,
+    pub sectors_per_track: u16,
+    pub num_heads: u16,
+    pub hidden_sectors: u32,
+    pub total_sectors_32: u32,
+    pub fat_size_32: u32,
+    pub ext_flags: u16,
+    pub fs_version: u16,
+    pub root_cluster: u32,
+    pub fs_info_sector: u16,
+    pub backup_boot_sector: u16,
+    pub reserved: [u8; 12],
+    pub drive_number: u8,
+    pub reserved1: u8,
+    pub boot_signature: u8,
+    pub volume_id: u32,
+    pub volume_label: [u8; 11],
+    pub fs_type: [u8; 8],
+}
+
+/// The in-memory superblock, with all the positions in bytes.
+#[derive(Clone, Copy, Debug)]
+pub struct VfatSuperBlock {
+    pub fat_type: FatType,
+    pub sector_size: usize,
+    pub cluster_size: usize,
+    /// The start of the first FAT.
+    pub fat_start: usize,
+    /// The size of one FAT.
+    pub fat_size: usize,
+    pub num_fats: usize,
+    /// The fixed root directory region of FAT12/16, which is empty for FAT32.
+    pub root_dir_start: usize,
+    pub root_dir_size: usize,
+    /// The first cluster of the root directory of FAT32.
+    pub root_cluster: ClusterId,
+    pub data_start: usize,
+    /// The number of clusters in the data region.
+    pub num_clusters: u32,
+    /// The position of the FSInfo sector of FAT32.
+    pub fs_info_start: Option<usize>,
+}
+
+impl VfatSuperBlock {
+    pub(super) fn parse(boot_sector: &VfatBootSector, signature: u16) -> Result<Self> {
+        if signature != BOOT_SIGNATURE {
+            return_errno_with_message!(Errno::EINVAL, "invalid boot sector signature");
+        }
+
+        let sector_size = boot_sector.bytes_per_sector as usize;
+        if !sector_size.is_power_of_two() || !(512..=4096).contains(&sector_size) {
+            return_errno_with_message!(Errno::EINVAL, "bogus sector size");
+        }
+        let sectors_per_cluster = boot_sector.sectors_per_cluster as usize;
+        if !sectors_per_cluster.is_power_of_two() {
+            return_errno_with_message!(Errno::EINVAL, "bogus sectors per cluster");
+        }
+        if boot_sector.reserved_sectors == 0 {
+            return_errno_with_message!(Errno::EINVAL, "no reserved sectors");
+        }
+        if boot_sector.num_fats == 0 {
+            return_errno_with_message!(Errno::EINVAL, "no FAT");
+        }
+
+        let total_sectors = if boot_sector.total_sectors_16 != 0 {
+            boot_sector.total_sectors_16 as usize
+        } else {
+            boot_sector.total_sectors_32 as usize
+        };
+        let fat_sectors = if boot_sector.fat_size_16 != 0 {
+            boot_sector.fat_size_16 as usize
+        } else {
+            boot_sector.fat_size_32 as usize
+        };
+        let root_dir_size = boot_sector.root_entries as usize * super::dentry::DENTRY_SIZE;
+        let root_dir_sectors = root_dir_size.div_ceil(sector_size);
+
+        let fat_start = boot_sector.reserved_sectors as usize;
+        let root_dir_start = fat_start + boot_sector.num_fats as usize * fat_sectors;
+        let data_start = root_dir_start + root_dir_sectors;
+        if fat_sectors == 0 || data_start >= total_sectors {
+            return_errno_with_message!(Errno::EINVAL, "bogus FAT layout");
+        }
+        let num_clusters = ((total_sectors - data_start) / sectors_per_cluster) as u32;
+        if num_clusters == 0 {
+            return_errno_with_message!(Errno::EINVAL, "no data clusters");
+        }
+
+        let fat_type = FatType::from_num_clusters(num_clusters);
+        let fat_bits = match fat_type {
+            FatType::Fat12 => 12,
+            FatType::Fat16 => 16,
+            FatType::Fat32 => 32,
+        };
+        let fat_entries = num_clusters as usize + FIRST_DATA_CLUSTER as usize;
+        if fat_sectors * sector_size * 8 < fat_entries * fat_bits {
+            return_errno_with_message!(Errno::EINVAL, "the FAT is too small");
+        }
+
+        let (root_cluster, fs_info_start) = if fat_type == FatType::Fat32 {
+            if boot_sector.fat_size_16 != 0 || root_dir_size != 0 {
+                return_errno_with_message!(Errno::EINVAL, "bogus FAT32 parameters");
+            }
+            let root_cluster = boot_sector.root_cluster;
+            if root_cluster < FIRST_DATA_CLUSTER
+                || root_cluster >= num_clusters + FIRST_DATA_CLUSTER
+            {
+                return_errno_with_message!(Errno::EINVAL, "bogus root cluster");
+            }
+            let fs_info_sector = boot_sector.fs_info_sector as usize;
+            let fs_info_start = (fs_info_sector != 0 && fs_info_sector < fat_start)
+                .then_some(fs_info_sector * sector_size);
+            (root_cluster, fs_info_start)
+        } else {
+            if root_dir_size == 0 {
+                return_errno_with_message!(Errno::EINVAL, "no root directory");
+            }
+            (0, None)
+        };
+
+        Ok(Self {
+            fat_type,
+            sector_size,
+            cluster_size: sectors_per_cluster * sector_size,
+            fat_start: fat_start * sector_size,
+            fat_size: fat_sectors * sector_size,
+            num_fats: boot_sector.num_fats as usize,
+            root_dir_start: root_dir_start * sector_size,
+            root_dir_size,
+            root_cluster,
+            data_start: data_start * sector_size,
+            num_clusters,
+            fs_info_start,
+        })
+    }
+
+    /// Returns the size of the area that the file system occupies on the device.
+    pub fn fs_size(&self) -> usize {
+        self.data_start + self.num_clusters as usize * self.cluster_size
+    }
+}
+
+/// The FSInfo sector of FAT32, which holds the hints for the cluster allocation.
+#[repr(C, packed)]
+#[derive(Clone, Copy, Debug, Pod)]
+pub(super) struct VfatFsInfo {
+    pub lead_signature: u32,
+    pub reserved: [u8; 480],
+    pub struct_signature: u32,
+    pub free_count: u32,
+    pub next_free: u32,
+    pub reserved1: [u8; 12],
+    pub trail_signature: u32,
+}
+
+impl VfatFsInfo {
+    pub fn new(free_count: u32, next_free: ClusterId) -> Self {
+        Self {
+            lead_signature: FSINFO_LEAD_SIGNATURE,
+            reserved: [0; 480],
+            struct_signature: FSINFO_STRUCT_SIGNATURE,
+            free_count,
+            next_free,
+            reserved1: [0; 12],
+            trail_signature: FSINFO_TRAIL_SIGNATURE,
+        }
+    }
+
+    pub fn is_valid(&self) -> bool {
+        self.lead_signature == FSINFO_LEAD_SIGNATURE
+            && self.struct_signature == FSINFO_STRUCT_SIGNATURE
+            && self.trail_signature == FSINFO_TRAIL_SIGNATURE
+    }
+}
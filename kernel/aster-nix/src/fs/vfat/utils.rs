@@ -0,0 +1,95 @@
+// SPDX-License-Identifier: MPL-2.0
+
+use core::time::Duration;
+
+use time::{Date, Month, OffsetDateTime, PrimitiveDateTime, Time};
+
+use crate::prelude::*;
+
+/// The earliest time that can be stored, 1980-01-01 00:00:00.
+const MIN_TIMESTAMP_SECS: u64 = 315_532_800;
+/// The latest time that can be stored, 2107-12-31 23:59:59.
+const MAX_TIMESTAMP_SECS: u64 = 4_354_819_199;
+
+/// A timestamp in the DOS format, which is in the local time of the system that writes it.
+///
+/// The time zone is not recorded, so the timestamps are taken as UTC.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub(super) struct DosTimestamp {
+    /// The date, with the year since 1980 in bits 9-15, the month in bits 5-8 and the day
+    /// in bits 0-4.
+    pub date: u16,
+    /// The time, with the hour in bits 11-15, the minute in bits 5-10 and the second
+    /// divided by two in bits 0-4.
+    pub time: u16,
+    /// The time in 10 ms within the two seconds of `time`.
+    pub time_cs: u8,
+}
+
+impl DosTimestamp {
+    pub fn now() -> Self {
+        #[cfg(not(ktest))]
+        {
+            use crate::time::clocks::RealTimeClock;
+
+            Self::from_duration(RealTimeClock::get().read_time())
+        }
+
+        // When ktesting, the time module has not been initialized yet, return a fake value instead.
+        #[cfg(ktest)]
+        {
+            Self::from_duration(Duration::ZERO)
+        }
+    }
+
+    /// Converts the time since the UNIX epoch, which is clamped into the DOS time range.
+    pub fn from_duration(duration: Duration) -> Self {
+        let secs = duration
+            .as_secs()
+            .clamp(MIN_TIMESTAMP_SECS, MAX_TIMESTAMP_SECS);
+        let nanos = if secs == duration.as_secs() {
+            duration.subsec_nanos()
+        } else {
+            0
+        };
+        let date_time = OffsetDateTime::from_unix_timestamp(secs as i64).unwrap();
+
+        let date = (((date_time.year() - 1980) as u16) << 9)
+            | ((date_time.month() as u16) << 5)
+            | date_time.day() as u16;
+        let time = ((date_time.hour() as u16) << 11)
+            | ((date_time.minute() as u16) << 5)
+            | (date_time.second() as u16 / 2);
+        let time_cs = ((date_time.second() % 2) as u32 * 100 + nanos / 10_000_000) as u8;
+        Self {
+            date,
+            time,
+            time_cs,
+        }
+    }
+
+    /// Converts to the time since the UNIX epoch.
+    ///
+    /// An invalid timestamp, e.g., one that is never set, is taken as the earliest time.
+    pub fn as_duration(&self) -> Duration {
+        let year = 1980 + (self.date >> 9) as i32;
+        let Ok(month) = Month::try_from(((self.date >> 5) & 0xF) as u8) else {
+            return Duration::from_secs(MIN_TIMESTAMP_SECS);
+        };
+        let Ok(date) = Date::from_calendar_date(year, month, (self.date & 0x1F) as u8) else {
+            return Duration::from_secs(MIN_TIMESTAMP_SECS);
+        };
+        let hour = (self.time >> 11) as u8;
+        let minute = ((self.time >> 5) & 0x3F) as u8;
+        let second = ((self.time & 0x1F) * 2) as u8;
+        let Ok(time) = Time::from_hms(hour, minute, second) else {
+            return Duration::from_secs(MIN_TIMESTAMP_SECS);
+        };
+
+        let secs = PrimitiveDateTime::new(date, time)
+            .assume_utc()
+            .unix_timestamp() as u64;
+        let time_cs = self.time_cs.min(199) as u64;
+        Duration::from_secs(secs + time_cs / 100) + Duration::from_millis(time_cs % 100 * 10)
+    }
+}
@@ -0,0 +1,271 @@
+// SPDX-License-Identifier: MPL-2.0
+
+use core::sync::atomic::{AtomicU64, Ordering};
+
+use aster_virtio::device::filesystem::{device::FileSystemDevice, get_device};
+
+use super::{
+    fuse::{
+        FuseForgetIn, FuseInHeader, FuseInitIn, FuseInitOut, FuseKstatfs, FuseOpcode,
+        FuseOutHeader, FUSE_KERNEL_MINOR_VERSION, FUSE_KERNEL_VERSION, FUSE_ROOT_ID,
+    },
+    inode::VirtioFsInode,
+};
+use crate::{
+    fs::utils::{FileSystem, FsFlags, Inode, SuperBlock, NAME_MAX},
+    prelude::*,
+    process::posix_thread::PosixThreadExt,
+};
+
+/// The magic number reported by statfs (`FUSE_SUPER_MAGIC` in Linux).
+const FUSE_SUPER_MAGIC: u64 = 0x65735546;
+/// The unique ID of the `FUSE_INIT` request. Zero is reserved for the notifications.
+const INIT_UNIQUE: u64 = 1;
+/// The maximum size of the data in a read request.
+pub(super) const MAX_READ: usize = 128 * 1024;
+
+/// A file system exported by the host through a virtio-fs device.
+///
+/// Every operation is forwarded to the host as a FUSE request, and nothing is cached in
+/// the guest, so the changes made by the host are visible at once.
+#[derive(Debug)]
+pub struct VirtioFS {
+    device: Arc<FileSystemDevice>,
+    root: Arc<VirtioFsInode>,
+    /// The maximum size of the data in a write request, which is chosen by the host.
+    max_write: usize,
+    next_unique: AtomicU64,
+    /// The inodes that are in use, indexed by their node IDs.
+    inodes: Mutex<BTreeMap<u64, Weak<VirtioFsInode>>>,
+}
+
+impl VirtioFS {
+    /// Opens the file system of the virtio-fs device with the tag.
+    pub fn open(tag: &str) -> Result<Arc<Self>> {
+        let Some(device) = get_device(tag) else {
+            return_errno_with_message!(Errno::ENOENT, "no virtio-fs device with the tag");
+        };
+
+        let init_in = FuseInitIn {
+            major: FUSE_KERNEL_VERSION,
+            minor: FUSE_KERNEL_MINOR_VERSION,
+            max_readahead: 0,
+            flags: 0,
+        };
+        let mut reply = [0u8; core::mem::size_of::<FuseInitOut>()];
+        let reply = send_request(
+            &device,
+            FuseOpcode::Init,
+            0,
+            INIT_UNIQUE,
+            &[init_in.as_bytes()],
+            &mut reply,
+        )?;
+        if reply.len() < core::mem::size_of::<FuseInitOut>() {
+            return_errno_with_message!(Errno::EPROTO, "the reply of FUSE_INIT is too short");
+        }
+        let init_out = FuseInitOut::from_bytes(reply);
+        if init_out.major != FUSE_KERNEL_VERSION || init_out.minor < FUSE_KERNEL_MINOR_VERSION {
+            return_errno_with_message!(Errno::EPROTO, "unsupported FUSE protocol version");
+        }
+
+        let fs = Arc::new_cyclic(|weak_fs| Self {
+            device,
+            root: VirtioFsInode::new_root(weak_fs.clone()),
+            max_write: (init_out.max_write as usize).max(PAGE_SIZE),
+            next_unique: AtomicU64::new(INIT_UNIQUE + 1),
+            inodes: Mutex::new(BTreeMap::new()),
+        });
+        Ok(fs)
+    }
+
+    pub(super) fn max_write(&self) -> usize {
+        self.max_write
+    }
+
+    /// Sends a request, which is the concatenation of `args`, on the node `nodeid`, and
+    /// returns the reply of at most `max_reply_len` bytes.
+    pub(super) fn request(
+        &self,
+        opcode: FuseOpcode,
+        nodeid: u64,
+        args: &[&[u8]],
+        max_reply_len: usize,
+    ) -> Result<Vec<u8>> {
+        let unique = self.next_unique.fetch_add(1, Ordering::Relaxed);
+        let mut reply = vec![0u8; max_reply_len];
+        let len = send_request(&self.device, opcode, nodeid, unique, args, &mut reply)?.len();
+        reply.truncate(len);
+        Ok(reply)
+    }
+
+    /// Sends a request whose reply is a value of `T`.
+    pub(super) fn request_val<T: Pod>(
+        &self,
+        opcode: FuseOpcode,
+        nodeid: u64,
+        args: &[&[u8]],
+    ) -> Result<T> {
+        let reply = self.request(opcode, nodeid, args, core::mem::size_of::<T>())?;
+        if reply.len() < core::mem::size_of::<T>() {
+            return_errno_with_message!(Errno::EIO, "the reply is too short");
+        }
+        Ok(T::from_bytes(&reply))
+    }
+
+    /// Tells the host that the node has been looked up `nlookup` times, and is no longer in
+    /// use by the guest.
+    pub(super) fn forget(&self, nodeid: u64, nlookup: u64) {
+        let unique = self.next_unique.fetch_add(1, Ordering::Relaxed);
+        let forget_in = FuseForgetIn { nlookup };
+        let header = new_in_header(
+            FuseOpcode::Forget,
+            nodeid,
+            unique,
+            core::mem::size_of::<FuseForgetIn>(),
+        );
+        self.device
+            .request_without_reply(&[header.as_bytes(), forget_in.as_bytes()]);
+    }
+
+    /// Returns the inode of the node that has been looked up, or builds and records one.
+    ///
+    /// Each lookup of a node should be matched by a forget, so the number of the lookups
+    /// is counted in the inode.
+    pub(super) fn get_or_insert_inode(
+        &self,
+        nodeid: u64,
+        build: impl FnOnce() -> Arc<VirtioFsInode>,
+    ) -> Arc<VirtioFsInode> {
+        if nodeid == FUSE_ROOT_ID {
+            return self.root.clone();
+        }
+
+        let mut inodes = self.inodes.lock();
+        if let Some(inode) = inodes.get(&nodeid).and_then(Weak::upgrade) {
+            inode.inc_nlookup();
+            return inode;
+        }
+        let inode = build();
+        inodes.insert(nodeid, Arc::downgrade(&inode));
+        inode
+    }
+
+    /// Forgets the inode of the node if it has been dropped.
+    pub(super) fn remove_inode(&self, nodeid: u64) {
+        let mut inodes = self.inodes.lock();
+        if inodes
+            .get(&nodeid)
+            .is_some_and(|inode| inode.strong_count() == 0)
+        {
+            inodes.remove(&nodeid);
+        }
+    }
+}
+
+impl FileSystem for VirtioFS {
+    fn sync(&self) -> Result<()> {
+        // Nothing is cached in the guest.
+        Ok(())
+    }
+
+    fn root_inode(&self) -> Arc<dyn Inode> {
+        self.root.clone()
+    }
+
+    fn sb(&self) -> SuperBlock {
+        let Ok(kstatfs) = self.request_val::<FuseKstatfs>(FuseOpcode::Statfs, FUSE_ROOT_ID, &[])
+        else {
+            return SuperBlock::new(FUSE_SUPER_MAGIC, PAGE_SIZE, NAME_MAX);
+        };
+        let mut sb = SuperBlock::new(
+            FUSE_SUPER_MAGIC,
+            kstatfs.bsize as usize,
+            kstatfs.namelen as usize,
+        );
+        sb.blocks = kstatfs.blocks as usize;
+        sb.bfree = kstatfs.bfree as usize;
+        sb.bavail = kstatfs.bavail as usize;
+        sb.files = kstatfs.files as usize;
+        sb.ffree = kstatfs.ffree as usize;
+        sb.frsize = kstatfs.frsize as usize;
+        sb
+    }
+
+    fn flags(&self) -> FsFlags {
+        FsFlags::empty()
+    }
+}
+
+impl Drop for VirtioFS {
+    fn drop(&mut self) {
+        let unique = self.next_unique.fetch_add(1, Ordering::Relaxed);
+        let _ = send_request(&self.device, FuseOpcode::Destroy, 0, unique, &[], &mut []);
+    }
+}
+
+/// Builds the header of a request, which is made by the current thread.
+fn new_in_header(opcode: FuseOpcode, nodeid: u64, unique: u64, args_len: usize) -> FuseInHeader {
+    // The requests that are not made by user threads, e.g., `FUSE_FORGET` when an inode
+    // is evicted, are made as root.
+    let (uid, gid, pid) = match current_thread!().as_posix_thread() {
+        Some(posix_thread) => {
+            let credentials = posix_thread.credentials();
+            (
+                credentials.fsuid().as_u32(),
+                credentials.fsgid().as_u32(),
+                posix_thread.process().pid(),
+            )
+        }
+        None => (0, 0, 0),
+    };
+    FuseInHeader {
+        len: (core::mem::size_of::<FuseInHeader>() + args_len) as u32,
+        opcode: opcode as u32,
+        unique,
+        nodeid,
+        uid,
+        gid,
+        pid,
+        total_extlen: 0,
+        padding: 0,
+    }
+}
+
+/// Sends a request and returns the payload of the reply in `reply`.
+fn send_request<'a>(
+    device: &FileSystemDevice,
+    opcode: FuseOpcode,
+    nodeid: u64,
+    unique: u64,
+    args: &[&[u8]],
+    reply: &'a mut [u8],
+) -> Result<&'a [u8]> {
+    const OUT_HEADER_LEN: usize = core::mem::size_of::<FuseOutHeader>();
+
+    let args_len = args.iter().map(|arg| arg.len()).sum();
+    let header = new_in_header(opcode, nodeid, unique, args_len);
+    let mut request = Vec::with_capacity(args.len() + 1);
+    request.push(header.as_bytes());
+    request.extend_from_slice(args);
+
+    let mut reply_buf = vec![0u8; OUT_HEADER_LEN + reply.len()];
+    let reply_len = device.request(&request, &mut reply_buf);
+    if reply_len < OUT_HEADER_LEN {
+        return_errno_with_message!(Errno::EIO, "the reply is too short");
+    }
+    let out_header = FuseOutHeader::from_bytes(&reply_buf[..OUT_HEADER_LEN]);
+    if out_header.unique != unique {
+        return_errno_with_message!(Errno::EIO, "the reply does not match the request");
+    }
+    if out_header.error != 0 {
+        let errno = Errno::try_from(out_header.error.saturating_neg()).unwrap_or(Errno::EIO);
+        return_errno_with_message!(errno, "the FUSE request fails");
+    }
+
+    let payload_len = (out_header.len as usize)
+        .min(reply_len)
+        .saturating_sub(OUT_HEADER_LEN);
+    reply[..payload_len].copy_from_slice(&reply_buf[OUT_HEADER_LEN..OUT_HEADER_LEN + payload_len]);
+    Ok(&reply[..payload_len])
+}
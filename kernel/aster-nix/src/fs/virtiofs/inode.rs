@@ -0,0 +1,625 @@
+// SPDX-License-Identifier: MPL-2.0
+
+use core::{
+    sync::atomic::{AtomicU64, Ordering},
+    time::Duration,
+};
+
+use align_ext::AlignExt;
+
+use super::{
+    fs::{VirtioFS, MAX_READ},
+    fuse::{
+        FuseAttr, FuseAttrOut, FuseDirent, FuseEntryOut, FuseFsyncIn, FuseGetattrIn, FuseLinkIn,
+        FuseMkdirIn, FuseMknodIn, FuseOpcode, FuseOpenIn, FuseOpenOut, FuseReadIn, FuseReleaseIn,
+        FuseRenameIn, FuseSetattrIn, FuseSetattrValid, FuseWriteIn, FuseWriteOut, FUSE_ROOT_ID,
+    },
+};
+use crate::{
+    events::IoEvents,
+    fs::utils::{AccessMode, DirentVisitor, FileSystem, Inode, InodeMode, InodeType, Metadata},
+    prelude::*,
+    process::{signal::Poller, Gid, Uid},
+};
+
+/// The maximum length of the target of a symbolic link.
+const MAX_LINK_LEN: usize = 4096;
+/// The size of the units of `FuseAttr::blocks`.
+const FUSE_BLOCK_SIZE: usize = 512;
+
+/// An inode of a virtio-fs file system, which is a node on the host.
+#[derive(Debug)]
+pub struct VirtioFsInode {
+    nodeid: u64,
+    type_: InodeType,
+    /// The number of the lookups of the node, which is returned to the host when the inode
+    /// is dropped.
+    nlookup: AtomicU64,
+    /// The attributes in the latest reply of the host, which are used if the host fails to
+    /// reply the up-to-date ones.
+    attr: SpinLock<FuseAttr>,
+    /// The file that is opened on the host for the I/O of the inode.
+    handle: Mutex<Option<FileHandle>>,
+    fs: Weak<VirtioFS>,
+}
+
+#[derive(Debug, Clone, Copy)]
+struct FileHandle {
+    fh: u64,
+    is_writable: bool,
+}
+
+impl VirtioFsInode {
+    pub(super) fn new_root(fs: Weak<VirtioFS>) -> Arc<Self> {
+        let mut attr = FuseAttr::new_zeroed();
+        attr.ino = FUSE_ROOT_ID;
+        attr.mode = InodeType::Dir as u32 | 0o755;
+        attr.nlink = 2;
+        Arc::new(Self {
+            nodeid: FUSE_ROOT_ID,
+            type_: InodeType::Dir,
+            // The root is never forgotten.
+            nlookup: AtomicU64::new(0),
+            attr: SpinLock::new(attr),
+            handle: Mutex::new(None),
+            fs,
+        })
+    }
+
+    fn new(entry: &FuseEntryOut, type_: InodeType, fs: Weak<VirtioFS>) -> Arc<Self> {
+        Arc::new(Self {
+            nodeid: entry.nodeid,
+            type_,
+            nlookup: AtomicU64::new(1),
+            attr: SpinLock::new(entry.attr),
+            handle: Mutex::new(None),
+            fs,
+        })
+    }
+
+    pub(super) fn inc_nlookup(&self) {
+        self.nlookup.fetch_add(1, Ordering::Relaxed);
+    }
+
+    fn virtio_fs(&self) -> Arc<VirtioFS> {
+        self.fs.upgrade().unwrap()
+    }
+
+    /// Gets the inode of an entry that the host has replied, which counts as a lookup of
+    /// the node.
+    fn get_inode(&self, entry: &FuseEntryOut) -> Result<Arc<Self>> {
+        if entry.nodeid == 0 {
+            return_errno_with_message!(Errno::ENOENT, "the entry does not exist");
+        }
+        let fs = self.virtio_fs();
+        let Ok(type_) = InodeType::try_from(entry.attr.mode & 0o170000) else {
+            fs.forget(entry.nodeid, 1);
+            return_errno_with_message!(Errno::EIO, "invalid file type");
+        };
+        let inode =
+            fs.get_or_insert_inode(entry.nodeid, || Self::new(entry, type_, self.fs.clone()));
+        *inode.attr.lock() = entry.attr;
+        Ok(inode)
+    }
+
+    /// Gets the up-to-date attributes from the host.
+    fn getattr(&self) -> Result<FuseAttr> {
+        let getattr_in = FuseGetattrIn::new_zeroed();
+        let attr_out = self.virtio_fs().request_val::<FuseAttrOut>(
+            FuseOpcode::Getattr,
+            self.nodeid,
+            &[getattr_in.as_bytes()],
+        )?;
+        *self.attr.lock() = attr_out.attr;
+        Ok(attr_out.attr)
+    }
+
+    /// Gets the attributes, which are the cached ones if the host fails to reply.
+    fn attr(&self) -> FuseAttr {
+        self.getattr().unwrap_or_else(|e| {
+            warn!("virtio-fs: failed to get the attributes: {:?}", e);
+            *self.attr.lock()
+        })
+    }
+
+    fn setattr(&self, setattr_in: &mut FuseSetattrIn) -> Result<()> {
+        if let Some(handle) = *self.handle.lock() {
+            setattr_in.valid |= FuseSetattrValid::FH.bits();
+            setattr_in.fh = handle.fh;
+        }
+        let attr_out = self.virtio_fs().request_val::<FuseAttrOut>(
+            FuseOpcode::Setattr,
+            self.nodeid,
+            &[setattr_in.as_bytes()],
+        )?;
+        *self.attr.lock() = attr_out.attr;
+        Ok(())
+    }
+
+    fn set_time(&self, valid: FuseSetattrValid, time: Duration) {
+        let mut setattr_in = FuseSetattrIn::new_zeroed();
+        setattr_in.valid = valid.bits();
+        if valid.contains(FuseSetattrValid::ATIME) {
+            setattr_in.atime = time.as_secs();
+            setattr_in.atimensec = time.subsec_nanos();
+        } else {
+            setattr_in.mtime = time.as_secs();
+            setattr_in.mtimensec = time.subsec_nanos();
+        }
+        if let Err(e) = self.setattr(&mut setattr_in) {
+            warn!("virtio-fs: failed to set the time: {:?}", e);
+        }
+    }
+
+    /// Opens the file on the host, for both reading and writing if permitted.
+    fn open_handle(&self) -> Result<FileHandle> {
+        let mut handle = self.handle.lock();
+        if let Some(handle) = *handle {
+            return Ok(handle);
+        }
+
+        let fs = self.virtio_fs();
+        let open = |access_mode: AccessMode| {
+            let open_in = FuseOpenIn {
+                flags: access_mode as u32,
+                open_flags: 0,
+            };
+            fs.request_val::<FuseOpenOut>(FuseOpcode::Open, self.nodeid, &[open_in.as_bytes()])
+        };
+        let new_handle = match open(AccessMode::O_RDWR) {
+            Ok(open_out) => FileHandle {
+                fh: open_out.fh,
+                is_writable: true,
+            },
+            Err(e) if matches!(e.error(), Errno::EACCES | Errno::EROFS | Errno::ETXTBSY) => {
+                let open_out = open(AccessMode::O_RDONLY)?;
+                FileHandle {
+                    fh: open_out.fh,
+                    is_writable: false,
+                }
+            }
+            Err(e) => return Err(e),
+        };
+        *handle = Some(new_handle);
+        Ok(new_handle)
+    }
+
+    /// Sends a request that creates a node named `name` in this directory.
+    fn make_node(&self, opcode: FuseOpcode, arg: &[u8], name: &str) -> Result<Arc<Self>> {
+        if self.type_ != InodeType::Dir {
+            return_errno!(Errno::ENOTDIR);
+        }
+        let entry = self.virtio_fs().request_val::<FuseEntryOut>(
+            opcode,
+            self.nodeid,
+            &[arg, name.as_bytes(), &[0]],
+        )?;
+        self.get_inode(&entry)
+    }
+
+    fn lookup_child(&self, name: &str) -> Result<Arc<Self>> {
+        self.make_node(FuseOpcode::Lookup, &[], name)
+    }
+
+    fn remove_node(&self, opcode: FuseOpcode, name: &str) -> Result<()> {
+        if self.type_ != InodeType::Dir {
+            return_errno!(Errno::ENOTDIR);
+        }
+        self.virtio_fs()
+            .request(opcode, self.nodeid, &[name.as_bytes(), &[0]], 0)?;
+        Ok(())
+    }
+
+    /// Reads the entries from the `offset`-th one, which are visited with the offsets of
+    /// the next entries.
+    ///
+    /// The offsets in the replies of the host are opaque, so the entries before the
+    /// `offset`-th one are read and skipped.
+    fn try_readdir(
+        &self,
+        fh: u64,
+        offset: &mut usize,
+        visitor: &mut dyn DirentVisitor,
+    ) -> Result<()> {
+        const DIRENT_LEN: usize = core::mem::size_of::<FuseDirent>();
+
+        let fs = self.virtio_fs();
+        let mut idx = 0;
+        let mut host_offset = 0;
+        loop {
+            let read_in = FuseReadIn {
+                fh,
+                offset: host_offset,
+                size: PAGE_SIZE as u32,
+                read_flags: 0,
+                lock_owner: 0,
+                flags: 0,
+                padding: 0,
+            };
+            let reply = fs.request(
+                FuseOpcode::Readdir,
+                self.nodeid,
+                &[read_in.as_bytes()],
+                PAGE_SIZE,
+            )?;
+            if reply.is_empty() {
+                return Ok(());
+            }
+
+            let mut pos = 0;
+            while pos + DIRENT_LEN <= reply.len() {
+                let dirent = FuseDirent::from_bytes(&reply[pos..pos + DIRENT_LEN]);
+                let name_end = pos + DIRENT_LEN + dirent.namelen as usize;
+                if name_end > reply.len() {
+                    return_errno_with_message!(Errno::EIO, "invalid directory entry");
+                }
+                if idx >= *offset {
+                    let name = core::str::from_utf8(&reply[pos + DIRENT_LEN..name_end])
+                        .map_err(|_| Error::with_message(Errno::EIO, "invalid file name"))?;
+                    let type_ = InodeType::try_from(dirent.type_ << 12).unwrap_or(InodeType::File);
+                    visitor.visit(name, dirent.ino, type_, idx + 1)?;
+                    *offset = idx + 1;
+                }
+                idx += 1;
+                host_offset = dirent.off;
+                pos = name_end.align_up(8);
+            }
+        }
+    }
+}
+
+impl Inode for VirtioFsInode {
+    fn size(&self) -> usize {
+        self.attr().size as usize
+    }
+
+    fn resize(&self, new_size: usize) -> Result<()> {
+        if self.type_ == InodeType::Dir {
+            return_errno!(Errno::EISDIR);
+        }
+        let mut setattr_in = FuseSetattrIn::new_zeroed();
+        setattr_in.valid = FuseSetattrValid::SIZE.bits();
+        setattr_in.size = new_size as u64;
+        self.setattr(&mut setattr_in)
+    }
+
+    fn metadata(&self) -> Metadata {
+        let attr = self.attr();
+        let blk_size = if attr.blksize == 0 {
+            PAGE_SIZE
+        } else {
+            attr.blksize as usize
+        };
+        Metadata {
+            dev: 0,
+            ino: attr.ino,
+            size: attr.size as usize,
+            blk_size,
+            blocks: (attr.blocks as usize * FUSE_BLOCK_SIZE).div_ceil(blk_size),
+            atime: Duration::new(attr.atime, attr.atimensec),
+            mtime: Duration::new(attr.mtime, attr.mtimensec),
+            ctime: Duration::new(attr.ctime, attr.ctimensec),
+            type_: self.type_,
+            mode: InodeMode::from_bits_truncate(attr.mode as u16),
+            nlinks: attr.nlink as usize,
+            uid: Uid::new(attr.uid),
+            gid: Gid::new(attr.gid),
+            rdev: attr.rdev as u64,
+        }
+    }
+
+    fn ino(&self) -> u64 {
+        self.attr.lock().ino
+    }
+
+    fn type_(&self) -> InodeType {
+        self.type_
+    }
+
+    fn mode(&self) -> Result<InodeMode> {
+        Ok(InodeMode::from_bits_truncate(self.getattr()?.mode as u16))
+    }
+
+    fn set_mode(&self, mode: InodeMode) -> Result<()> {
+        let mut setattr_in = FuseSetattrIn::new_zeroed();
+        setattr_in.valid = FuseSetattrValid::MODE.bits();
+        setattr_in.mode = self.type_ as u32 | mode.bits() as u32;
+        self.setattr(&mut setattr_in)
+    }
+
+    fn owner(&self) -> Result<Uid> {
+        Ok(Uid::new(self.getattr()?.uid))
+    }
+
+    fn set_owner(&self, uid: Uid) -> Result<()> {
+        let mut setattr_in = FuseSetattrIn::new_zeroed();
+        setattr_in.valid = FuseSetattrValid::UID.bits();
+        setattr_in.uid = uid.as_u32();
+        self.setattr(&mut setattr_in)
+    }
+
+    fn group(&self) -> Result<Gid> {
+        Ok(Gid::new(self.getattr()?.gid))
+    }
+
+    fn set_group(&self, gid: Gid) -> Result<()> {
+        let mut setattr_in = FuseSetattrIn::new_zeroed();
+        setattr_in.valid = FuseSetattrValid::GID.bits();
+        setattr_in.gid = gid.as_u32();
+        self.setattr(&mut setattr_in)
+    }
+
+    fn atime(&self) -> Duration {
+        let attr = self.attr();
+        Duration::new(attr.atime, attr.atimensec)
+    }
+
+    fn set_atime(&self, time: Duration) {
+        self.set_time(FuseSetattrValid::ATIME, time);
+    }
+
+    fn mtime(&self) -> Duration {
+        let attr = self.attr();
+        Duration::new(attr.mtime, attr.mtimensec)
+    }
+
+    fn set_mtime(&self, time: Duration) {
+        self.set_time(FuseSetattrValid::MTIME, time);
+    }
+
+    fn read_at(&self, offset: usize, buf: &mut [u8]) -> Result<usize> {
+        if self.type_ == InodeType::Dir {
+            return_errno!(Errno::EISDIR);
+        }
+        let handle = self.open_handle()?;
+        let fs = self.virtio_fs();
+
+        let mut read_len = 0;
+        while read_len < buf.len() {
+            let size = (buf.len() - read_len).min(MAX_READ);
+            let read_in = FuseReadIn {
+                fh: handle.fh,
+                offset: (offset + read_len) as u64,
+                size: size as u32,
+                read_flags: 0,
+                lock_owner: 0,
+                flags: 0,
+                padding: 0,
+            };
+            let reply = fs.request(FuseOpcode::Read, self.nodeid, &[read_in.as_bytes()], size)?;
+            buf[read_len..read_len + reply.len()].copy_from_slice(&reply);
+            read_len += reply.len();
+            if reply.len() < size {
+                break;
+            }
+        }
+        Ok(read_len)
+    }
+
+    fn read_direct_at(&self, offset: usize, buf: &mut [u8]) -> Result<usize> {
+        self.read_at(offset, buf)
+    }
+
+    fn write_at(&self, offset: usize, buf: &[u8]) -> Result<usize> {
+        if self.type_ == InodeType::Dir {
+            return_errno!(Errno::EISDIR);
+        }
+        let handle = self.open_handle()?;
+        if !handle.is_writable {
+            return_errno_with_message!(Errno::EACCES, "the file cannot be written");
+        }
+        let fs = self.virtio_fs();
+
+        let mut written_len = 0;
+        while written_len < buf.len() {
+            let size = (buf.len() - written_len).min(fs.max_write());
+            let write_in = FuseWriteIn {
+                fh: handle.fh,
+                offset: (offset + written_len) as u64,
+                size: size as u32,
+                write_flags: 0,
+                lock_owner: 0,
+                flags: 0,
+                padding: 0,
+            };
+            let write_out = fs.request_val::<FuseWriteOut>(
+                FuseOpcode::Write,
+                self.nodeid,
+                &[write_in.as_bytes(), &buf[written_len..written_len + size]],
+            )?;
+            let size_written = (write_out.size as usize).min(size);
+            written_len += size_written;
+            if size_written < size {
+                break;
+            }
+        }
+        Ok(written_len)
+    }
+
+    fn write_direct_at(&self, offset: usize, buf: &[u8]) -> Result<usize> {
+        self.write_at(offset, buf)
+    }
+
+    fn create(&self, name: &str, type_: InodeType, mode: InodeMode) -> Result<Arc<dyn Inode>> {
+        let inode = match type_ {
+            InodeType::Dir => {
+                let mkdir_in = FuseMkdirIn {
+                    mode: mode.bits() as u32,
+                    umask: 0,
+                };
+                self.make_node(FuseOpcode::Mkdir, mkdir_in.as_bytes(), name)?
+            }
+            InodeType::File | InodeType::NamedPipe | InodeType::Socket => {
+                let mknod_in = FuseMknodIn {
+                    mode: type_ as u32 | mode.bits() as u32,
+                    rdev: 0,
+                    umask: 0,
+                    padding: 0,
+                };
+                self.make_node(FuseOpcode::Mknod, mknod_in.as_bytes(), name)?
+            }
+            // The target of a symbolic link is given when the link is created in FUSE,
+            // which is later than this in the VFS.
+            _ => return_errno_with_message!(Errno::EPERM, "unsupported file type"),
+        };
+        Ok(inode)
+    }
+
+    fn readdir_at(&self, offset: usize, visitor: &mut dyn DirentVisitor) -> Result<usize> {
+        if self.type_ != InodeType::Dir {
+            return_errno!(Errno::ENOTDIR);
+        }
+        let fs = self.virtio_fs();
+        let open_in = FuseOpenIn {
+            flags: AccessMode::O_RDONLY as u32,
+            open_flags: 0,
+        };
+        let open_out =
+            fs.request_val::<FuseOpenOut>(FuseOpcode::Opendir, self.nodeid, &[open_in.as_bytes()])?;
+
+        let mut iterate_offset = offset;
+        let res = self.try_readdir(open_out.fh, &mut iterate_offset, visitor);
+
+        let release_in = FuseReleaseIn {
+            fh: open_out.fh,
+            flags: open_in.flags,
+            release_flags: 0,
+            lock_owner: 0,
+        };
+        let _ = fs.request(
+            FuseOpcode::Releasedir,
+            self.nodeid,
+            &[release_in.as_bytes()],
+            0,
+        );
+
+        match res {
+            Err(e) if iterate_offset == offset => Err(e),
+            _ => Ok(iterate_offset - offset),
+        }
+    }
+
+    fn link(&self, old: &Arc<dyn Inode>, name: &str) -> Result<()> {
+        let old = old
+            .downcast_ref::<VirtioFsInode>()
+            .ok_or_else(|| Error::with_message(Errno::EXDEV, "not same fs"))?;
+        if !Weak::ptr_eq(&old.fs, &self.fs) {
+            return_errno_with_message!(Errno::EXDEV, "not same fs");
+        }
+        let link_in = FuseLinkIn {
+            oldnodeid: old.nodeid,
+        };
+        self.make_node(FuseOpcode::Link, link_in.as_bytes(), name)?;
+        Ok(())
+    }
+
+    fn unlink(&self, name: &str) -> Result<()> {
+        self.remove_node(FuseOpcode::Unlink, name)
+    }
+
+    fn rmdir(&self, name: &str) -> Result<()> {
+        self.remove_node(FuseOpcode::Rmdir, name)
+    }
+
+    fn lookup(&self, name: &str) -> Result<Arc<dyn Inode>> {
+        Ok(self.lookup_child(name)?)
+    }
+
+    fn rename(&self, old_name: &str, target: &Arc<dyn Inode>, new_name: &str) -> Result<()> {
+        let target = target
+            .downcast_ref::<VirtioFsInode>()
+            .ok_or_else(|| Error::with_message(Errno::EXDEV, "not same fs"))?;
+        if !Weak::ptr_eq(&target.fs, &self.fs) {
+            return_errno_with_message!(Errno::EXDEV, "not same fs");
+        }
+        if self.type_ != InodeType::Dir || target.type_ != InodeType::Dir {
+            return_errno!(Errno::ENOTDIR);
+        }
+        let rename_in = FuseRenameIn {
+            newdir: target.nodeid,
+        };
+        self.virtio_fs().request(
+            FuseOpcode::Rename,
+            self.nodeid,
+            &[
+                rename_in.as_bytes(),
+                old_name.as_bytes(),
+                &[0],
+                new_name.as_bytes(),
+                &[0],
+            ],
+            0,
+        )?;
+        Ok(())
+    }
+
+    fn read_link(&self) -> Result<String> {
+        if self.type_ != InodeType::SymLink {
+            return_errno!(Errno::EINVAL);
+        }
+        let target = self
+            .fs()
+            .request(FuseOpcode::Readlink, self.nodeid, &[], MAX_LINK_LEN)?;
+        String::from_utf8(target).map_err(|_| Error::with_message(Errno::EIO, "invalid link"))
+    }
+
+    fn write_link(&self, _target: &str) -> Result<()> {
+        return_errno_with_message!(Errno::EPERM, "the target of a link cannot be changed");
+    }
+
+    fn sync(&self) -> Result<()> {
+        let Some(handle) = *self.handle.lock() else {
+            return Ok(());
+        };
+        let fsync_in = FuseFsyncIn {
+            fh: handle.fh,
+            fsync_flags: 0,
+            padding: 0,
+        };
+        self.virtio_fs()
+            .request(FuseOpcode::Fsync, self.nodeid, &[fsync_in.as_bytes()], 0)?;
+        Ok(())
+    }
+
+    fn poll(&self, mask: IoEvents, _poller: Option<&Poller>) -> IoEvents {
+        let events = IoEvents::IN | IoEvents::OUT;
+        events & mask
+    }
+
+    fn fs(&self) -> Arc<dyn FileSystem> {
+        self.virtio_fs()
+    }
+}
+
+impl Drop for VirtioFsInode {
+    fn drop(&mut self) {
+        let Some(fs) = self.fs.upgrade() else {
+            return;
+        };
+
+        if let Some(handle) = *self.handle.get_mut() {
+            let release_in = FuseReleaseIn {
+                fh: handle.fh,
+                flags: if handle.is_writable {
+                    AccessMode::O_RDWR as u32
+                } else {
+                    AccessMode::O_RDONLY as u32
+                },
+                release_flags: 0,
+                lock_owner: 0,
+            };
+            let _ = fs.request(
+                FuseOpcode::Release,
+                self.nodeid,
+                &[release_in.as_bytes()],
+                0,
+            );
+        }
+
+        fs.remove_inode(self.nodeid);
+        let nlookup = *self.nlookup.get_mut();
+        if nlookup > 0 {
+            fs.forget(self.nodeid, nlookup);
+        }
+    }
+}
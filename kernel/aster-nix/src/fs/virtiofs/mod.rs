@@ -0,0 +1,14 @@
+// SPDX-License-Identifier: MPL-2.0
+
+//! The client of virtio-fs, which shares a directory of the host with the guest.
+//!
+//! The file system talks FUSE to the daemon on the host (e.g., virtiofsd) through a
+//! virtio-fs device, and is mounted with the tag of the device as the device name, e.g.,
+//! `mount -t virtiofs myfs /mnt`.
+
+mod fs;
+mod fuse;
+mod inode;
+
+pub use fs::VirtioFS;
+pub use inode::VirtioFsInode;
@@ -0,0 +1,91 @@
+// SPDX-License-Identifier: MPL-2.0
+
+//! System V inter-process communication (IPC).
+//!
+//! The IPC objects are identified by user-chosen keys, and each object has an owner
+//! and permission bits that control the access to it, see [`IpcPerm`].
+
+pub mod shm;
+
+use crate::{
+    prelude::*,
+    process::{credentials, credentials::capabilities::CapSet, Gid, Uid},
+};
+
+/// The key that always creates a new IPC object.
+pub const IPC_PRIVATE: i32 = 0;
+
+bitflags! {
+    /// The flags to get an IPC object.
+    pub struct IpcFlags: u32 {
+        /// Creates the object if it does not exist.
+        const IPC_CREAT = 0o1000;
+        /// Fails if the object exists.
+        const IPC_EXCL = 0o2000;
+    }
+}
+
+/// The ownership and permissions of an IPC object.
+#[derive(Debug, Clone, Copy)]
+pub struct IpcPerm {
+    pub key: i32,
+    pub uid: Uid,
+    pub gid: Gid,
+    pub cuid: Uid,
+    pub cgid: Gid,
+    /// The lowest 9 bits are the permissions, which are the same as those of files.
+    pub mode: u16,
+}
+
+impl IpcPerm {
+    /// Creates the ownership of an object created by the current process.
+    pub fn new_current(key: i32, mode: u16) -> Self {
+        let credentials = credentials();
+        let uid = credentials.euid();
+        let gid = credentials.egid();
+        Self {
+            key,
+            uid,
+            gid,
+            cuid: uid,
+            cgid: gid,
+            mode: mode & 0o777,
+        }
+    }
+
+    /// Checks whether the current process can access the object with the permissions,
+    /// which are given in the form of the `rwx` bits of others.
+    pub fn check_access(&self, perms: u16) -> Result<()> {
+        let credentials = credentials();
+        if credentials.effective_capset().contains(CapSet::IPC_OWNER) {
+            return Ok(());
+        }
+
+        let euid = credentials.euid();
+        let egid = credentials.egid();
+        let granted = if euid == self.uid || euid == self.cuid {
+            self.mode >> 6
+        } else if egid == self.gid || egid == self.cgid {
+            self.mode >> 3
+        } else {
+            self.mode
+        };
+        if granted & perms & 0o7 != perms {
+            return_errno_with_message!(Errno::EACCES, "the IPC object cannot be accessed");
+        }
+        Ok(())
+    }
+
+    /// Checks whether the current process can change or remove the object.
+    pub fn check_owner(&self) -> Result<()> {
+        let credentials = credentials();
+        let euid = credentials.euid();
+        if euid != self.uid
+            && euid != self.cuid
+            && !credentials.effective_capset().contains(CapSet::SYS_ADMIN)
+        {
+            return_errno_with_message!(Errno::EPERM, "the IPC object is not owned");
+        }
+        Ok(())
+    }
+}
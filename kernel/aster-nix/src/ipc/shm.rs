@@ -0,0 +1,225 @@
+// SPDX-License-Identifier: MPL-2.0
+
+//! System V shared memory segments.
+//!
+//! A segment is a [`SharedMem`] that is identified by an ID in a system-wide table,
+//! so unrelated processes can attach it to their address spaces with `shmat`.
+//!
+//! A segment removed with `IPC_RMID` can no longer be found by its key, but it stays
+//! in the table until it is no longer attached anywhere, so the processes that still
+//! attach it can detach it or query its status. Since a process may exit without
+//! detaching the segments, such segments are purged lazily, i.e., when the table is
+//! accessed later.
+
+use core::{
+    sync::atomic::{AtomicI32, Ordering},
+    time::Duration,
+};
+
+use align_ext::AlignExt;
+
+use super::{IpcFlags, IpcPerm, IPC_PRIVATE};
+use crate::{
+    prelude::*,
+    process::{Gid, Pid, Uid},
+    time::clocks::RealTimeClock,
+    vm::vmar::SharedMem,
+};
+
+/// The minimum size of a segment in bytes.
+const SHMMIN: usize = 1;
+/// The maximum size of a segment in bytes.
+const SHMMAX: usize = isize::MAX as usize - (1 << 24);
+
+static SEGMENTS: Mutex<BTreeMap<i32, Arc<ShmSegment>>> = Mutex::new(BTreeMap::new());
+static NEXT_ID: AtomicI32 = AtomicI32::new(0);
+
+pub struct ShmSegment {
+    id: i32,
+    shared_mem: Arc<SharedMem>,
+    /// The size requested on creation, which may not be page-aligned.
+    size: usize,
+    cpid: Pid,
+    inner: Mutex<ShmSegmentInner>,
+}
+
+struct ShmSegmentInner {
+    perm: IpcPerm,
+    is_removed: bool,
+    /// The last time that the segment is attached.
+    atime: Duration,
+    /// The last time that the segment is detached.
+    dtime: Duration,
+    /// The last time that the segment is created or changed with `IPC_SET`.
+    ctime: Duration,
+    /// The process that attaches or detaches the segment last.
+    lpid: Pid,
+}
+
+impl ShmSegment {
+    pub fn id(&self) -> i32 {
+        self.id
+    }
+
+    pub fn size(&self) -> usize {
+        self.size
+    }
+
+    pub fn shared_mem(&self) -> &Arc<SharedMem> {
+        &self.shared_mem
+    }
+
+    pub fn cpid(&self) -> Pid {
+        self.cpid
+    }
+
+    pub fn lpid(&self) -> Pid {
+        self.inner.lock().lpid
+    }
+
+    pub fn perm(&self) -> IpcPerm {
+        self.inner.lock().perm
+    }
+
+    pub fn is_removed(&self) -> bool {
+        self.inner.lock().is_removed
+    }
+
+    /// Returns the attach, detach and change times of the segment.
+    pub fn times(&self) -> (Duration, Duration, Duration) {
+        let inner = self.inner.lock();
+        (inner.atime, inner.dtime, inner.ctime)
+    }
+
+    /// Returns the number of times that the segment is attached.
+    ///
+    /// Like Linux, a mapping of the segment split by `mprotect` is counted for each part.
+    pub fn nattch(&self) -> usize {
+        self.shared_mem.nr_mappings()
+    }
+
+    /// Changes the owner and the permissions of the segment.
+    ///
+    /// Only the owner, the creator or a privileged process can change them.
+    pub fn set_perm(&self, uid: Uid, gid: Gid, mode: u16) -> Result<()> {
+        let mut inner = self.inner.lock();
+        inner.perm.check_owner()?;
+        inner.perm.uid = uid;
+        inner.perm.gid = gid;
+        inner.perm.mode = mode & 0o777;
+        inner.ctime = now();
+        Ok(())
+    }
+
+    /// Records that the segment is attached by the process.
+    pub fn on_attach(&self, pid: Pid) {
+        let mut inner = self.inner.lock();
+        inner.atime = now();
+        inner.lpid = pid;
+    }
+
+    /// Records that the segment is detached by the process.
+    pub fn on_detach(&self, pid: Pid) {
+        let mut inner = self.inner.lock();
+        inner.dtime = now();
+        inner.lpid = pid;
+    }
+}
+
+/// Gets the ID of the segment with the key, or creates a new segment with the key if
+/// `IPC_CREAT` is given.
+///
+/// The lowest 9 bits of `mode` are the permissions of the new segment. They are also
+/// the permissions requested to access an existing segment.
+pub fn get_or_create(key: i32, size: usize, flags: IpcFlags, mode: u16) -> Result<i32> {
+    let mut segments = SEGMENTS.lock();
+    purge_removed(&mut segments);
+
+    if key != IPC_PRIVATE
+        && let Some(segment) = segments.values().find(|segment| segment.perm().key == key)
+    {
+        if flags.contains(IpcFlags::IPC_CREAT | IpcFlags::IPC_EXCL) {
+            return_errno_with_message!(Errno::EEXIST, "the segment already exists");
+        }
+        segment
+            .perm()
+            .check_access((mode >> 6 | mode >> 3 | mode) & 0o7)?;
+        if size > segment.size {
+            return_errno_with_message!(Errno::EINVAL, "the segment is smaller than the size");
+        }
+        return Ok(segment.id);
+    }
+
+    if key != IPC_PRIVATE && !flags.contains(IpcFlags::IPC_CREAT) {
+        return_errno_with_message!(Errno::ENOENT, "the segment does not exist");
+    }
+    if !(SHMMIN..=SHMMAX).contains(&size) {
+        return_errno_with_message!(Errno::EINVAL, "the size of the segment is invalid");
+    }
+
+    let shared_mem = SharedMem::new(size.align_up(PAGE_SIZE))?;
+    let id = NEXT_ID.fetch_add(1, Ordering::Relaxed);
+    let pid = current!().pid();
+    let segment = Arc::new(ShmSegment {
+        id,
+        shared_mem,
+        size,
+        cpid: pid,
+        inner: Mutex::new(ShmSegmentInner {
+            perm: IpcPerm::new_current(key, mode),
+            is_removed: false,
+            atime: Duration::ZERO,
+            dtime: Duration::ZERO,
+            ctime: now(),
+            lpid: 0,
+        }),
+    });
+    segments.insert(id, segment);
+    Ok(id)
+}
+
+/// Gets the segment with the ID, including the removed but still attached ones.
+pub fn get(id: i32) -> Result<Arc<ShmSegment>> {
+    let mut segments = SEGMENTS.lock();
+    purge_removed(&mut segments);
+    segments
+        .get(&id)
+        .cloned()
+        .ok_or_else(|| Error::with_message(Errno::EINVAL, "the segment does not exist"))
+}
+
+/// Gets the segment whose pages are provided by the shared memory object.
+pub fn get_by_shared_mem(shared_mem: &Arc<SharedMem>) -> Option<Arc<ShmSegment>> {
+    SEGMENTS
+        .lock()
+        .values()
+        .find(|segment| Arc::ptr_eq(&segment.shared_mem, shared_mem))
+        .cloned()
+}
+
+/// Marks the segment as removed, so it is destroyed once it is no longer attached.
+///
+/// Only the owner, the creator or a privileged process can remove the segment.
+pub fn remove(id: i32) -> Result<()> {
+    let mut segments = SEGMENTS.lock();
+    let segment = segments
+        .get(&id)
+        .ok_or_else(|| Error::with_message(Errno::EINVAL, "the segment does not exist"))?;
+    {
+        let mut inner = segment.inner.lock();
+        inner.perm.check_owner()?;
+        inner.is_removed = true;
+        inner.perm.key = IPC_PRIVATE;
+    }
+    purge_removed(&mut segments);
+    Ok(())
+}
+
+/// Destroys the removed segments that are no longer attached.
+fn purge_removed(segments: &mut BTreeMap<i32, Arc<ShmSegment>>) {
+    segments.retain(|_, segment| !segment.is_removed() || segment.nattch() > 0);
+}
+
+fn now() -> Duration {
+    RealTimeClock::get().read_time()
+}
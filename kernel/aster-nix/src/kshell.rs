@@ -0,0 +1,188 @@
+// SPDX-License-Identifier: MPL-2.0
+
+//! A minimal shell built into the kernel.
+//!
+//! The shell is the last resort when no init process can be started, e.g., when the
+//! kernel boots without an initramfs. It runs in the init thread, reads commands from
+//! the console and provides just enough builtins to inspect the system and to start
+//! the user programs that are made available later.
+//!
+//! Since the init thread is a kernel thread, which cannot be paused like a POSIX
+//! thread, the console input is polled with a timeout instead of blocking reads.
+
+use core::time::Duration;
+
+use aster_frame::sync::WaitQueue;
+
+use crate::{
+    device::tty::get_n_tty,
+    fs::{
+        fs_resolver::{FsPath, FsResolver},
+        path::Dentry,
+        procfs::ProcFS,
+        ramfs::RamFS,
+        tracefs,
+        utils::{InodeMode, InodeType},
+    },
+    prelude::*,
+    process::{process_table, Process},
+    thread::Thread,
+    time::wait::WaitTimeout,
+};
+
+/// The interval to poll the console for input.
+const POLL_INTERVAL: Duration = Duration::from_millis(20);
+
+const HELP: &str = "\
+builtin commands:
+  help                      show this message
+  echo [ARG]...             print the arguments
+  ls [DIR]                  list the directory
+  cat FILE...               print the files
+  mkdir DIR...              create the directories
+  mount proc|ramfs|tracefs DIR
+                            mount a file system at the directory
+  ps                        list the processes
+  run PATH [ARG]...         run the program and wait for it to exit
+  exit                      leave the shell";
+
+/// Runs the shell until the `exit` command is given.
+pub fn run() {
+    println!("[kernel] built-in shell, type `help` for the commands");
+    loop {
+        print!("# ");
+        let line = match read_line() {
+            Ok(line) => line,
+            Err(e) => {
+                println!("failed to read the console: {:?}", e);
+                return;
+            }
+        };
+        let args: Vec<&str> = line.split_whitespace().collect();
+        let Some((&cmd, args)) = args.split_first() else {
+            continue;
+        };
+        if cmd == "exit" {
+            return;
+        }
+        if let Err(e) = run_cmd(cmd, args) {
+            println!("{}: {:?}", cmd, e);
+        }
+    }
+}
+
+fn run_cmd(cmd: &str, args: &[&str]) -> Result<()> {
+    match cmd {
+        "help" => println!("{}", HELP),
+        "echo" => println!("{}", args.join(" ")),
+        "ls" => {
+            let dir = lookup(args.first().copied().unwrap_or("/"))?;
+            let mut names: Vec<String> = Vec::new();
+            dir.inode().readdir_at(0, &mut names)?;
+            for name in names.iter().filter(|name| !name.starts_with('.')) {
+                println!("{}", name);
+            }
+        }
+        "cat" => {
+            for path in args {
+                cat(&lookup(path)?)?;
+            }
+        }
+        "mkdir" => {
+            let fs = FsResolver::new();
+            for path in args {
+                let (dir, name) = fs.lookup_dir_and_base_name(&FsPath::try_from(*path)?)?;
+                dir.new_fs_child(&name, InodeType::Dir, InodeMode::from_bits_truncate(0o755))?;
+            }
+        }
+        "mount" => {
+            let [fs_type, path] = args else {
+                return_errno_with_message!(Errno::EINVAL, "usage: mount proc|ramfs|tracefs DIR");
+            };
+            let dentry = lookup(path)?;
+            match *fs_type {
+                "proc" => dentry.mount(ProcFS::new())?,
+                "ramfs" => dentry.mount(RamFS::new())?,
+                "tracefs" => dentry.mount(tracefs::new())?,
+                _ => return_errno_with_message!(Errno::ENODEV, "the file system is unknown"),
+            };
+        }
+        "ps" => {
+            println!("  PID  PPID  CMD");
+            for process in process_table::process_table().iter() {
+                let ppid = process.parent().map_or(0, |parent| parent.pid());
+                println!(
+                    "{:>5} {:>5}  {}",
+                    process.pid(),
+                    ppid,
+                    process.executable_path()
+                );
+            }
+        }
+        "run" => {
+            let Some((path, args)) = args.split_first() else {
+                return_errno_with_message!(Errno::EINVAL, "usage: run PATH [ARG]...");
+            };
+            let argv = args
+                .iter()
+                .map(|arg| CString::new(*arg))
+                .collect::<core::result::Result<Vec<_>, _>>()
+                .map_err(|_| Error::with_message(Errno::EINVAL, "invalid argument"))?;
+            let process = Process::spawn_user_process(path, argv, Vec::new())?;
+            while !process.is_zombie() {
+                Thread::yield_now();
+            }
+            println!(
+                "[{}] exited with {}",
+                process.pid(),
+                process.exit_code().unwrap()
+            );
+        }
+        _ => return_errno_with_message!(Errno::ENOENT, "the command is not found"),
+    }
+    Ok(())
+}
+
+/// Reads a line from the console, excluding the trailing newline.
+fn read_line() -> Result<String> {
+    let tty = get_n_tty();
+    let wait_queue = WaitQueue::new();
+    let mut line = Vec::new();
+    let mut buf = [0u8; 128];
+    loop {
+        match tty.try_read(&mut buf) {
+            Ok(len) => line.extend_from_slice(&buf[..len]),
+            Err(e) if e.error() == Errno::EAGAIN => {
+                wait_queue.wait_until_or_timeout(|| None::<()>, &POLL_INTERVAL);
+                continue;
+            }
+            Err(e) => return Err(e),
+        }
+        if line.last() == Some(&b'\n') {
+            line.pop();
+            return Ok(String::from_utf8_lossy(&line).into_owned());
+        }
+    }
+}
+
+fn lookup(path: &str) -> Result<Arc<Dentry>> {
+    FsResolver::new().lookup(&FsPath::try_from(path)?)
+}
+
+fn cat(dentry: &Dentry) -> Result<()> {
+    let inode = dentry.inode();
+    if inode.type_() == InodeType::Dir {
+        return_errno_with_message!(Errno::EISDIR, "the file is a directory");
+    }
+    let mut buf = [0u8; 512];
+    let mut offset = 0;
+    loop {
+        let len = inode.read_at(offset, &mut buf)?;
+        if len == 0 {
+            break;
+        }
+        print!("{}", String::from_utf8_lossy(&buf[..len]));
+        offset += len;
+    }
+    Ok(())
+}
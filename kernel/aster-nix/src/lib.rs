@@ -23,10 +23,7 @@
 #![feature(trait_alias)]
 #![register_tool(component_access_control)]
 
-use aster_frame::{
-    arch::qemu::{exit_qemu, QemuExitCode},
-    boot,
-};
+use aster_frame::{arch::qemu::QemuExitCode, boot};
 use process::Process;
 
 use crate::{
@@ -48,6 +45,7 @@ extern crate ktest;
 extern crate getset;
 
 pub mod arch;
+pub mod config;
 pub mod console;
 pub mod cpu;
 pub mod device;
@@ -55,7 +53,11 @@ pub mod driver;
 pub mod error;
 pub mod events;
 pub mod fs;
+mod ipc;
+mod kshell;
+#[cfg(feature = "net")]
 pub mod net;
+pub mod power;
 pub mod prelude;
 mod process;
 mod sched;
@@ -72,6 +74,8 @@ pub fn init() {
     util::random::init();
     driver::init();
     time::init();
+    thread::oops::init();
+    #[cfg(feature = "net")]
     net::init();
     sched::init();
     fs::rootfs::init(boot::initramfs()).unwrap();
@@ -92,8 +96,12 @@ fn init_thread() {
     // FIXME: Remove this if we move the step of mounting
     // the filesystems to be done within the init process.
     aster_frame::trap::enable_local();
+    #[cfg(feature = "net")]
+    #[cfg(feature = "net")]
     net::lazy_init();
     fs::lazy_init();
+    vm::lazy_init();
+    util::random::lazy_init();
     // driver::pci::virtio::block::block_device_test();
     let thread = Thread::spawn_kernel_thread(ThreadOptions::new(|| {
         println!("[kernel] Hello world from kernel!");
@@ -109,14 +117,11 @@ fn init_thread() {
 
     print_banner();
 
-    let karg = boot::kernel_cmdline();
-
-    let initproc = Process::spawn_user_process(
-        karg.get_initproc_path().unwrap(),
-        karg.get_initproc_argv().to_vec(),
-        karg.get_initproc_envp().to_vec(),
-    )
-    .expect("Run init process failed.");
+    let Some(initproc) = spawn_init_process() else {
+        println!("[kernel] no init process can be started, falling back to the built-in shell");
+        kshell::run();
+        power::power_off(QemuExitCode::Success);
+    };
     // Wait till initproc become zombie.
     while !initproc.is_zombie() {
         // We don't have preemptive scheduler now.
@@ -124,13 +129,38 @@ fn init_thread() {
         Thread::yield_now();
     }
 
-    // TODO: exit via qemu isa debug device should not be the only way.
     let exit_code = if initproc.exit_code().unwrap() == 0 {
         QemuExitCode::Success
     } else {
         QemuExitCode::Failed
     };
-    exit_qemu(exit_code);
+    power::power_off(exit_code);
+}
+
+/// The paths to try in order when the init process is not given in the kernel
+/// command line or fails to start, which are the same as Linux.
+const DEFAULT_INITPROC_PATHS: [&str; 4] = ["/sbin/init", "/etc/init", "/bin/init", "/bin/sh"];
+
+/// Spawns the init process, or returns `None` if no candidate can be started.
+fn spawn_init_process() -> Option<Arc<Process>> {
+    let karg = boot::kernel_cmdline();
+    let argv = karg.get_initproc_argv();
+    let envp = karg.get_initproc_envp();
+
+    let paths = karg
+        .get_initproc_path()
+        .into_iter()
+        .chain(DEFAULT_INITPROC_PATHS);
+    for path in paths {
+        match Process::spawn_user_process(path, argv.to_vec(), envp.to_vec()) {
+            Ok(process) => return Some(process),
+            Err(e) => println!(
+                "[kernel] failed to run {} as the init process: {:?}",
+                path, e
+            ),
+        }
+    }
+    None
 }
 
 /// first process never return
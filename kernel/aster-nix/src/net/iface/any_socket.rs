@@ -2,7 +2,15 @@
 
 #![allow(dead_code)]
 
-use super::{Iface, IpAddress, IpEndpoint};
+use smoltcp::{
+    socket::tcp::State as TcpState,
+    wire::{IpListenEndpoint, IpProtocol},
+};
+
+use super::{
+    common::SocketHandleSlot, rps::cpu_of_flow, tcp_track::TrackedTcpFlow, Iface, IpAddress,
+    IpEndpoint,
+};
 use crate::{events::Observer, prelude::*};
 
 pub type RawTcpSocket = smoltcp::socket::tcp::Socket<'static>;
@@ -64,17 +72,19 @@ impl AnyUnboundSocket {
 
 pub struct AnyBoundSocket {
     iface: Arc<dyn Iface>,
-    handle: smoltcp::iface::SocketHandle,
+    handle: SocketHandleSlot,
     port: u16,
     socket_family: SocketFamily,
     observer: RwLock<Weak<dyn Observer<()>>>,
+    /// The error caused by the iface, e.g., when the local address is removed.
+    iface_error: SpinLock<Option<Error>>,
     weak_self: Weak<Self>,
 }
 
 impl AnyBoundSocket {
     pub(super) fn new(
         iface: Arc<dyn Iface>,
-        handle: smoltcp::iface::SocketHandle,
+        handle: SocketHandleSlot,
         port: u16,
         socket_family: SocketFamily,
         observer: Weak<dyn Observer<()>>,
@@ -85,6 +95,7 @@ impl AnyBoundSocket {
             port,
             socket_family,
             observer: RwLock::new(observer),
+            iface_error: SpinLock::new(None),
             weak_self: weak_self.clone(),
         })
     }
@@ -107,7 +118,70 @@ impl AnyBoundSocket {
         self.on_iface_events();
     }
 
+    /// Handles the change of the address of the iface from `old_addr` to `new_addr`, which
+    /// is `None` if the address is removed.
+    ///
+    /// A listening TCP socket on the old address listens on the new address instead, so
+    /// that a server keeps accepting connections. The other sockets on the old address can
+    /// no longer send or receive packets, so the TCP connections are aborted with
+    /// `ECONNABORTED`, and the UDP sockets fail with `EADDRNOTAVAIL`.
+    pub(super) fn on_addr_changed(&self, old_addr: IpAddress, new_addr: Option<IpAddress>) {
+        let addr_removed = || Error::with_message(Errno::EADDRNOTAVAIL, "the address is removed");
+        let error = match self.socket_family {
+            SocketFamily::Tcp => self.raw_with(|socket: &mut RawTcpSocket| {
+                if socket.state() == TcpState::Listen {
+                    let endpoint = socket.listen_endpoint();
+                    if endpoint.addr != Some(old_addr) {
+                        return None;
+                    }
+                    socket.abort();
+                    let new_endpoint = IpListenEndpoint {
+                        addr: new_addr,
+                        port: endpoint.port,
+                    };
+                    if new_addr.is_some() && socket.listen(new_endpoint).is_ok() {
+                        return None;
+                    }
+                    return Some(addr_removed());
+                }
+                if socket
+                    .local_endpoint()
+                    .is_some_and(|endpoint| endpoint.addr == old_addr)
+                {
+                    socket.abort();
+                    return Some(Error::with_message(
+                        Errno::ECONNABORTED,
+                        "the local address of the connection is removed",
+                    ));
+                }
+                None
+            }),
+            SocketFamily::Udp => self.raw_with(|socket: &mut RawUdpSocket| {
+                (socket.endpoint().addr == Some(old_addr)).then(addr_removed)
+            }),
+        };
+        let Some(error) = error else {
+            return;
+        };
+        *self.iface_error.lock_irq_disabled() = Some(error);
+        self.on_iface_events();
+    }
+
+    /// Returns the error caused by the iface, after which the socket is unusable.
+    pub fn iface_error(&self) -> Option<Error> {
+        *self.iface_error.lock_irq_disabled()
+    }
+
     pub fn local_endpoint(&self) -> Option<IpEndpoint> {
+        // A TCP connection keeps its local address even if the address of the iface has
+        // changed since the connection was established.
+        if let SocketFamily::Tcp = self.socket_family {
+            let endpoint = self.raw_with(|socket: &mut RawTcpSocket| socket.local_endpoint());
+            if endpoint.is_some() {
+                return endpoint;
+            }
+        }
+
         let ip_addr = {
             let ipv4_addr = self.iface.ipv4_addr()?;
             IpAddress::Ipv4(ipv4_addr)
@@ -115,24 +189,41 @@ impl AnyBoundSocket {
         Some(IpEndpoint::new(ip_addr, self.port))
     }
 
+    /// Returns the CPU that processes the packets received from the remote endpoint, if the
+    /// iface steers the received packets.
+    pub fn incoming_cpu(&self, remote_endpoint: IpEndpoint) -> Option<u32> {
+        self.iface.rps()?;
+        let protocol = match self.socket_family {
+            SocketFamily::Tcp => IpProtocol::Tcp,
+            SocketFamily::Udp => IpProtocol::Udp,
+        };
+        let local_endpoint = self.local_endpoint()?;
+        Some(cpu_of_flow(protocol, remote_endpoint, local_endpoint))
+    }
+
+    /// Starts to track the segments of the TCP connection to the remote endpoint that pass
+    /// the iface. Tcp socket only.
+    ///
+    /// The connection is tracked until the returned [`TrackedTcpFlow`] is dropped.
+    pub fn track_tcp_flow(&self, remote_endpoint: IpEndpoint) -> TrackedTcpFlow {
+        let local_endpoint = self.local_endpoint().unwrap();
+        TrackedTcpFlow::new(self.iface.clone(), local_endpoint, remote_endpoint)
+    }
+
     pub fn raw_with<T: smoltcp::socket::AnySocket<'static>, R, F: FnMut(&mut T) -> R>(
         &self,
-        mut f: F,
+        f: F,
     ) -> R {
-        let mut sockets = self.iface.sockets();
-        let socket = sockets.get_mut::<T>(self.handle);
-        f(socket)
+        self.iface.common().with_raw_socket(&self.handle, f)
     }
 
     /// Try to connect to a remote endpoint. Tcp socket only.
     pub fn do_connect(&self, remote_endpoint: IpEndpoint) -> Result<()> {
-        let mut sockets = self.iface.sockets();
-        let socket = sockets.get_mut::<RawTcpSocket>(self.handle);
         let port = self.port;
+        // Lock the interface before the socket, in the same order as polling.
         let mut iface_inner = self.iface.iface_inner();
         let cx = iface_inner.context();
-        socket
-            .connect(cx, remote_endpoint, port)
+        self.raw_with(|socket: &mut RawTcpSocket| socket.connect(cx, remote_endpoint, port))
             .map_err(|_| Error::with_message(Errno::ENOBUFS, "send connection request failed"))?;
         Ok(())
     }
@@ -156,9 +247,10 @@ impl AnyBoundSocket {
 impl Drop for AnyBoundSocket {
     fn drop(&mut self) {
         self.close();
+        self.iface
+            .common()
+            .remove_socket(&self.handle, self.port, &self.socket_family);
         self.iface.poll();
-        self.iface.common().remove_socket(self.handle);
-        self.iface.common().release_port(self.port);
         self.iface.common().remove_bound_socket(self.weak_ref());
     }
 }
@@ -8,26 +8,41 @@ use keyable_arc::KeyableWeak;
 use smoltcp::{
     iface::{SocketHandle, SocketSet},
     phy::Device,
-    wire::IpCidr,
+    socket::{tcp::State as TcpState, AnySocket, Socket},
+    time::{Duration, Instant},
+    wire::{IpCidr, Ipv4Cidr},
 };
+use spin::Once;
 
 use super::{
-    any_socket::{AnyBoundSocket, AnyRawSocket, AnyUnboundSocket, SocketFamily},
+    any_socket::{AnyBoundSocket, AnyRawSocket, AnyUnboundSocket, RawTcpSocket, SocketFamily},
+    tcp_track::TcpTracker,
     time::get_network_timestamp,
     util::BindPortConfig,
-    Iface, Ipv4Address,
+    Iface, IpAddress, Ipv4Address, PktGen,
 };
 use crate::prelude::*;
 
 pub struct IfaceCommon {
     interface: SpinLock<smoltcp::iface::Interface>,
     sockets: SpinLock<SocketSet<'static>>,
+    /// The sockets to be added to or removed from `sockets`.
+    ///
+    /// Servers that accept many connections bind and close sockets frequently. If they
+    /// locked `sockets` to do so, they would wait for the ongoing poll, and the poll
+    /// would wait for them in turn. Instead, the changes are queued here, and are applied
+    /// by the next poll.
+    pending_sockets: SpinLock<PendingSockets>,
+    /// The ports in use. The ports of the closed TCP sockets are released by polls, which
+    /// may happen in the IRQ handlers, so it is locked with IRQs disabled.
     used_ports: RwLock<BTreeMap<u16, usize>>,
     /// The time should do next poll. We stores the total milliseconds since system boots up.
     next_poll_at_ms: AtomicU64,
     bound_sockets: RwLock<BTreeSet<KeyableWeak<AnyBoundSocket>>>,
     /// The wait queue that background polling thread will sleep on
     polling_wait_queue: WaitQueue,
+    pktgen: PktGen,
+    tcp_tracker: TcpTracker,
 }
 
 impl IfaceCommon {
@@ -37,10 +52,13 @@ impl IfaceCommon {
         Self {
             interface: SpinLock::new(interface),
             sockets: SpinLock::new(socket_set),
+            pending_sockets: SpinLock::new(PendingSockets::default()),
             used_ports: RwLock::new(used_ports),
             next_poll_at_ms: AtomicU64::new(0),
             bound_sockets: RwLock::new(BTreeSet::new()),
             polling_wait_queue: WaitQueue::new(),
+            pktgen: PktGen::new(),
+            tcp_tracker: TcpTracker::new(),
         }
     }
 
@@ -56,6 +74,13 @@ impl IfaceCommon {
         self.interface.lock_irq_disabled().ipv4_addr()
     }
 
+    pub(super) fn ipv4_cidr(&self) -> Option<Ipv4Cidr> {
+        let interface = self.interface.lock_irq_disabled();
+        interface.ip_addrs().first().map(|cidr| match cidr {
+            IpCidr::Ipv4(ipv4_cidr) => *ipv4_cidr,
+        })
+    }
+
     pub(super) fn netmask(&self) -> Option<Ipv4Address> {
         let interface = self.interface.lock_irq_disabled();
         let ip_addrs = interface.ip_addrs();
@@ -64,13 +89,62 @@ impl IfaceCommon {
         })
     }
 
+    /// Sets the IPv4 address and the netmask of the iface.
+    ///
+    /// Changing only the netmask, e.g., when a DHCP lease is renewed, keeps the sockets
+    /// intact. If the address changes, the bound sockets are told that the old address is
+    /// removed, since their packets can no longer be delivered on it. See
+    /// [`AnyBoundSocket::on_addr_changed`]. The iface should be polled afterwards to send
+    /// the resets of the aborted connections.
+    ///
+    /// The address can be cleared by setting it to the unspecified address.
+    pub(super) fn set_ipv4_cidr(&self, new_cidr: Ipv4Cidr) {
+        let old_addr = {
+            let mut interface = self.interface.lock_irq_disabled();
+            let old_addr = interface.ipv4_addr();
+            interface.update_ip_addrs(|ip_addrs| {
+                if let Some(addr) = ip_addrs.iter_mut().next() {
+                    *addr = IpCidr::Ipv4(new_cidr);
+                } else {
+                    ip_addrs.push(IpCidr::Ipv4(new_cidr)).unwrap();
+                }
+            });
+            old_addr
+        };
+
+        let new_addr = new_cidr.address();
+        let Some(old_addr) =
+            old_addr.filter(|old_addr| !old_addr.is_unspecified() && *old_addr != new_addr)
+        else {
+            return;
+        };
+        let new_addr = (!new_addr.is_unspecified()).then_some(IpAddress::Ipv4(new_addr));
+        let bound_sockets: Vec<_> = self
+            .bound_sockets
+            .read()
+            .iter()
+            .filter_map(|bound_socket| bound_socket.upgrade())
+            .collect();
+        for bound_socket in bound_sockets {
+            bound_socket.on_addr_changed(IpAddress::Ipv4(old_addr), new_addr);
+        }
+    }
+
     pub(super) fn polling_wait_queue(&self) -> &WaitQueue {
         &self.polling_wait_queue
     }
 
+    pub(super) fn pktgen(&self) -> &PktGen {
+        &self.pktgen
+    }
+
+    pub(super) fn tcp_tracker(&self) -> &TcpTracker {
+        &self.tcp_tracker
+    }
+
     /// Alloc an unused port range from 49152 ~ 65535 (According to smoltcp docs)
     fn alloc_ephemeral_port(&self) -> Result<u16> {
-        let mut used_ports = self.used_ports.write();
+        let mut used_ports = self.used_ports.write_irq_disabled();
         for port in IP_LOCAL_PORT_START..=IP_LOCAL_PORT_END {
             if let Entry::Vacant(e) = used_ports.entry(port) {
                 e.insert(0);
@@ -81,7 +155,7 @@ impl IfaceCommon {
     }
 
     fn bind_port(&self, port: u16, can_reuse: bool) -> Result<()> {
-        let mut used_ports = self.used_ports.write();
+        let mut used_ports = self.used_ports.write_irq_disabled();
         if let Some(used_times) = used_ports.get_mut(&port) {
             if *used_times == 0 || can_reuse {
                 *used_times += 1;
@@ -95,8 +169,8 @@ impl IfaceCommon {
     }
 
     /// Release port number so the port can be used again. For reused port, the port may still be in use.
-    pub(super) fn release_port(&self, port: u16) {
-        let mut used_ports = self.used_ports.write();
+    fn release_port(&self, port: u16) {
+        let mut used_ports = self.used_ports.write_irq_disabled();
         if let Some(used_times) = used_ports.remove(&port) {
             if used_times != 1 {
                 used_ports.insert(port, used_times - 1);
@@ -122,37 +196,99 @@ impl IfaceCommon {
             return Err((err, socket));
         }
 
-        let (handle, socket_family, observer) = match socket.into_raw() {
-            (AnyRawSocket::Tcp(tcp_socket), observer) => (
-                self.sockets.lock_irq_disabled().add(tcp_socket),
-                SocketFamily::Tcp,
-                observer,
-            ),
-            (AnyRawSocket::Udp(udp_socket), observer) => (
-                self.sockets.lock_irq_disabled().add(udp_socket),
-                SocketFamily::Udp,
-                observer,
-            ),
+        let (raw_socket, socket_family, observer) = match socket.into_raw() {
+            (AnyRawSocket::Tcp(tcp_socket), observer) => {
+                (tcp_socket.upcast(), SocketFamily::Tcp, observer)
+            }
+            (AnyRawSocket::Udp(udp_socket), observer) => {
+                (udp_socket.upcast(), SocketFamily::Udp, observer)
+            }
         };
+        let handle: SocketHandleSlot = Arc::new(Once::new());
+        self.pending_sockets
+            .lock_irq_disabled()
+            .adds
+            .push((handle.clone(), raw_socket));
         let bound_socket = AnyBoundSocket::new(iface, handle, port, socket_family, observer);
         self.insert_bound_socket(&bound_socket).unwrap();
 
         Ok(bound_socket)
     }
 
-    /// Remove a socket from the interface
-    pub(super) fn remove_socket(&self, handle: SocketHandle) {
-        self.sockets.lock_irq_disabled().remove(handle);
+    /// Remove a closed socket from the interface, and release its port.
+    ///
+    /// The socket is removed by the next poll, so that the poll can still send the packets
+    /// of the socket. A TCP socket is kept until its connection is closed, i.e., until the
+    /// FIN packets are exchanged and the TIME-WAIT state ends, or until the connection is
+    /// aborted after [`TCP_CLOSE_TIMEOUT`]. Its port is not released until then, lest a
+    /// new connection reuses the port and is mistaken for the old one.
+    pub(super) fn remove_socket(
+        &self,
+        handle: &SocketHandleSlot,
+        port: u16,
+        family: &SocketFamily,
+    ) {
+        let mut pending_sockets = self.pending_sockets.lock_irq_disabled();
+        let Some(handle) = handle.get() else {
+            pending_sockets
+                .adds
+                .retain(|(pending_handle, _)| !Arc::ptr_eq(pending_handle, handle));
+            drop(pending_sockets);
+            self.release_port(port);
+            return;
+        };
+        match family {
+            SocketFamily::Tcp => pending_sockets.closing.push(ClosingSocket {
+                handle: *handle,
+                port,
+                deadline: get_network_timestamp() + TCP_CLOSE_TIMEOUT,
+            }),
+            SocketFamily::Udp => pending_sockets.removes.push((*handle, port)),
+        }
+    }
+
+    /// Calls `f` with the raw socket, which may not have been added to the socket set yet.
+    pub(super) fn with_raw_socket<T: AnySocket<'static>, R>(
+        &self,
+        handle: &SocketHandleSlot,
+        f: impl FnOnce(&mut T) -> R,
+    ) -> R {
+        if handle.get().is_none() {
+            let mut pending_sockets = self.pending_sockets.lock_irq_disabled();
+            if let Some((_, raw_socket)) = pending_sockets
+                .adds
+                .iter_mut()
+                .find(|(pending_handle, _)| Arc::ptr_eq(pending_handle, handle))
+            {
+                return f(T::downcast_mut(raw_socket).unwrap());
+            }
+            // The socket has been added to the socket set just now.
+        }
+
+        let mut sockets = self.sockets.lock_irq_disabled();
+        f(sockets.get_mut::<T>(*handle.get().unwrap()))
     }
 
     pub(super) fn poll<D: Device + ?Sized>(&self, device: &mut D) {
         let mut interface = self.interface.lock_irq_disabled();
         let timestamp = get_network_timestamp();
-        let has_events = {
+        let (has_events, released_ports) = {
             let mut sockets = self.sockets.lock_irq_disabled();
-            interface.poll(timestamp, device, &mut sockets)
+            self.pending_sockets
+                .lock_irq_disabled()
+                .add_to(&mut sockets);
+            let mut device = self.tcp_tracker.device(device);
+            let has_events = interface.poll(timestamp, &mut device, &mut sockets);
+            let released_ports = self
+                .pending_sockets
+                .lock_irq_disabled()
+                .remove_from(&mut sockets, timestamp);
             // drop sockets here to avoid deadlock
+            (has_events, released_ports)
         };
+        for port in released_ports {
+            self.release_port(port);
+        }
         if has_events {
             self.bound_sockets.read().iter().for_each(|bound_socket| {
                 if let Some(bound_socket) = bound_socket.upgrade() {
@@ -162,12 +298,17 @@ impl IfaceCommon {
         }
 
         let sockets = self.sockets.lock_irq_disabled();
-        if let Some(instant) = interface.poll_at(timestamp, &sockets) {
+        let poll_at = interface.poll_at(timestamp, &sockets);
+        // The closing sockets are aborted at their deadlines, even if they are idle.
+        let abort_at = self.pending_sockets.lock_irq_disabled().next_deadline();
+        if let Some(instant) = poll_at.into_iter().chain(abort_at).min() {
             let old_instant = self.next_poll_at_ms.load(Ordering::Acquire);
             let new_instant = instant.total_millis() as u64;
             self.next_poll_at_ms.store(new_instant, Ordering::Relaxed);
 
-            if new_instant < old_instant {
+            // The background polling thread may be waiting for the first poll time, or a
+            // later one. Either way, it should be woken up to wait for the new time.
+            if old_instant == 0 || new_instant < old_instant {
                 self.polling_wait_queue.wake_all();
             }
         } else {
@@ -200,5 +341,74 @@ impl IfaceCommon {
     }
 }
 
+/// The handle of a bound socket, which is set once the socket is added to the socket set.
+pub(super) type SocketHandleSlot = Arc<Once<SocketHandle>>;
+
+#[derive(Default)]
+struct PendingSockets {
+    adds: Vec<(SocketHandleSlot, Socket<'static>)>,
+    /// The sockets to be removed, with their ports.
+    removes: Vec<(SocketHandle, u16)>,
+    /// The TCP sockets that are closed, but whose connections are still closing.
+    closing: Vec<ClosingSocket>,
+}
+
+struct ClosingSocket {
+    handle: SocketHandle,
+    port: u16,
+    /// The time to abort the connection if it is not closed.
+    deadline: Instant,
+}
+
+impl PendingSockets {
+    fn add_to(&mut self, sockets: &mut SocketSet<'static>) {
+        for (handle, raw_socket) in self.adds.drain(..) {
+            let new_handle = match raw_socket {
+                Socket::Tcp(tcp_socket) => sockets.add(tcp_socket),
+                Socket::Udp(udp_socket) => sockets.add(udp_socket),
+                _ => unreachable!("only TCP and UDP sockets can be bound"),
+            };
+            handle.call_once(|| new_handle);
+        }
+    }
+
+    /// Removes the sockets, including the TCP sockets whose connections are closed.
+    ///
+    /// Returns the ports of the removed sockets, which should be released.
+    fn remove_from(&mut self, sockets: &mut SocketSet<'static>, now: Instant) -> Vec<u16> {
+        let mut released_ports = Vec::new();
+        for (handle, port) in self.removes.drain(..) {
+            sockets.remove(handle);
+            released_ports.push(port);
+        }
+        self.closing.retain(|closing_socket| {
+            let socket = sockets.get_mut::<RawTcpSocket>(closing_socket.handle);
+            if socket.state() == TcpState::Closed {
+                sockets.remove(closing_socket.handle);
+                released_ports.push(closing_socket.port);
+                return false;
+            }
+            if now >= closing_socket.deadline {
+                // The RST packet is sent by the next poll, which happens at once since the
+                // deadline has passed. Then the socket is closed and removed.
+                socket.abort();
+            }
+            true
+        });
+        released_ports
+    }
+
+    fn next_deadline(&self) -> Option<Instant> {
+        self.closing
+            .iter()
+            .map(|closing_socket| closing_socket.deadline)
+            .min()
+    }
+}
+
+/// The time that a closed TCP socket is given to close its connection, after which the
+/// connection is aborted. It is the same as the default `tcp_fin_timeout` of Linux.
+const TCP_CLOSE_TIMEOUT: Duration = Duration::from_secs(60);
+
 const IP_LOCAL_PORT_START: u16 = 49152;
 const IP_LOCAL_PORT_END: u16 = 65535;
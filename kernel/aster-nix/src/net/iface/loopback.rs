@@ -4,11 +4,14 @@
 
 use smoltcp::{
     iface::{Config, Routes},
-    phy::{Loopback, Medium},
+    phy::{Device, Loopback, Medium, TxToken},
     wire::IpCidr,
 };
 
-use super::{common::IfaceCommon, internal::IfaceInternal, Iface, IpAddress, Ipv4Address};
+use super::{
+    common::IfaceCommon, internal::IfaceInternal, time::get_network_timestamp, Iface, IpAddress,
+    Ipv4Address,
+};
 use crate::prelude::*;
 
 pub const LOOPBACK_ADDRESS: IpAddress = {
@@ -70,4 +73,15 @@ impl Iface for IfaceLoopback {
         let mut device = self.driver.lock();
         self.common.poll(&mut *device);
     }
+
+    fn transmit_raw(&self, packet: &[u8]) -> bool {
+        let mut device = self.driver.lock();
+        let Some(tx_token) = device.transmit(get_network_timestamp()) else {
+            return false;
+        };
+        tx_token.consume(packet.len(), |buf| buf.copy_from_slice(packet));
+        // The packets are queued in the device until they are received by a poll.
+        self.common.poll(&mut *device);
+        true
+    }
 }
@@ -1,14 +1,17 @@
 // SPDX-License-Identifier: MPL-2.0
 
 use aster_frame::sync::WaitQueue;
-use smoltcp::iface::SocketSet;
+use smoltcp::{iface::SocketSet, wire::Ipv4Cidr};
 
 use self::common::IfaceCommon;
-use crate::prelude::*;
+use crate::{events::NotifierChain, prelude::*};
 
 mod any_socket;
 mod common;
 mod loopback;
+mod pktgen;
+mod rps;
+mod tcp_track;
 mod time;
 mod util;
 mod virtio;
@@ -17,6 +20,9 @@ pub use any_socket::{
     AnyBoundSocket, AnyUnboundSocket, RawTcpSocket, RawUdpSocket, RECV_BUF_LEN, SEND_BUF_LEN,
 };
 pub use loopback::IfaceLoopback;
+pub use pktgen::{start as start_pktgen, PktGen, PktGenConfig, PktGenStats};
+pub use rps::{cpu_of_flow, spawn_rps_workers, Rps};
+pub use tcp_track::{TcpFlowStats, TrackedTcpFlow};
 pub use smoltcp::wire::{EthernetAddress, IpAddress, IpEndpoint, Ipv4Address};
 pub use util::{spawn_background_poll_thread, BindPortConfig};
 pub use virtio::IfaceVirtio;
@@ -37,6 +43,12 @@ pub trait Iface: internal::IfaceInternal + Send + Sync {
     /// It any event happens, this function will also update socket status.
     fn poll(&self);
 
+    /// Transmits a raw packet, bypassing the sockets and the routing of the iface.
+    ///
+    /// The packet starts with the link-layer header if the iface has a mac address, or the
+    /// IP header otherwise. Returns whether the device accepts the packet.
+    fn transmit_raw(&self, packet: &[u8]) -> bool;
+
     /// Bind a socket to the iface. So the packet for this socket will be dealt with by the interface.
     /// If port is None, the iface will pick up an empheral port for the socket.
     /// FIXME: The reason for binding socket and interface together is because there are limitations inside smoltcp.
@@ -62,12 +74,59 @@ pub trait Iface: internal::IfaceInternal + Send + Sync {
         self.common().netmask()
     }
 
+    /// Sets the ipv4 address, and keeps the netmask.
+    ///
+    /// The established connections are kept if the address does not change. Otherwise, the
+    /// sockets on the old address are notified that it is removed.
+    fn set_ipv4_addr(&self, addr: Ipv4Address) {
+        let prefix_len = self
+            .common()
+            .ipv4_cidr()
+            .map_or(0, |cidr| cidr.prefix_len());
+        self.common().set_ipv4_cidr(Ipv4Cidr::new(addr, prefix_len));
+        self.poll();
+        NETDEV_NOTIFIER_CHAIN.notify(&NetDeviceEvent::ChangeAddr(self.arc_self()));
+    }
+
+    /// Sets the netmask, and keeps the ipv4 address and the sockets on it.
+    fn set_netmask(&self, netmask: Ipv4Address) -> Result<()> {
+        let addr = self.ipv4_addr().unwrap_or(Ipv4Address::UNSPECIFIED);
+        let cidr = Ipv4Cidr::from_netmask(addr, netmask)
+            .map_err(|_| Error::with_message(Errno::EINVAL, "the netmask is invalid"))?;
+        self.common().set_ipv4_cidr(cidr);
+        NETDEV_NOTIFIER_CHAIN.notify(&NetDeviceEvent::ChangeAddr(self.arc_self()));
+        Ok(())
+    }
+
     /// The waitqueue used to background polling thread
     fn polling_wait_queue(&self) -> &WaitQueue {
         self.common().polling_wait_queue()
     }
+
+    /// The packet generator of the iface.
+    fn pktgen(&self) -> &PktGen {
+        self.common().pktgen()
+    }
+
+    /// The receive packet steering of the iface, if the iface steers the received packets
+    /// to the backlogs of the CPUs.
+    fn rps(&self) -> Option<&Rps> {
+        None
+    }
 }
 
+/// The events of the network interfaces.
+#[derive(Clone)]
+pub enum NetDeviceEvent {
+    /// The iface is registered.
+    Register(Arc<dyn Iface>),
+    /// The ipv4 address or the netmask of the iface is changed.
+    ChangeAddr(Arc<dyn Iface>),
+}
+
+/// The notifier chain that is notified of the events of the network interfaces.
+pub static NETDEV_NOTIFIER_CHAIN: NotifierChain<NetDeviceEvent> = NotifierChain::new();
+
 mod internal {
     use super::*;
 
@@ -0,0 +1,343 @@
+// SPDX-License-Identifier: MPL-2.0
+
+//! The packet generator, which is a self-test mode of the network stack.
+//!
+//! Like `pktgen` of Linux, the generator synthesizes a stream of UDP packets and
+//! transmits them at the iface layer, bypassing the sockets, so that the throughput of
+//! the drivers and the stack can be measured without a load generator in the userspace.
+//! Each iface has its own generator, which is configured and started through the files
+//! in `/proc/net/pktgen`.
+
+use core::{
+    sync::atomic::{AtomicBool, AtomicU64, Ordering},
+    time::Duration,
+};
+
+use aster_frame::sync::WaitQueue;
+use smoltcp::{
+    phy::ChecksumCapabilities,
+    wire::{
+        EthernetAddress, EthernetFrame, EthernetProtocol, EthernetRepr, IpProtocol, Ipv4Packet,
+        Ipv4Repr, UdpPacket, UdpRepr,
+    },
+};
+
+use super::{Iface, IpAddress, Ipv4Address};
+use crate::{
+    prelude::*,
+    thread::{
+        kernel_thread::{KernelThreadExt, ThreadOptions},
+        Thread,
+    },
+    time::{clocks::JiffiesClock, wait::WaitTimeout},
+};
+
+/// The magic number at the beginning of the UDP payload, which is the same as Linux.
+const PKTGEN_MAGIC: u32 = 0xbe9b_e955;
+const ETHERNET_HEADER_LEN: usize = 14;
+const IPV4_HEADER_LEN: usize = 20;
+const UDP_HEADER_LEN: usize = 8;
+/// The payload contains the magic number and the sequence number.
+const PAYLOAD_HEADER_LEN: usize = 8;
+const MAX_PKT_SIZE: usize = 1514;
+/// The number of packets sent before yielding the CPU if there is no rate limit.
+const YIELD_INTERVAL: u64 = 64;
+
+/// The configuration of a packet generator.
+#[derive(Debug, Clone)]
+pub struct PktGenConfig {
+    /// The number of packets to send, or zero to send until the generator is stopped.
+    pub count: u64,
+    /// The rate in packets per second, or zero to send as fast as possible.
+    pub rate: u64,
+    /// The size of the packets, including the headers of all the layers.
+    pub pkt_size: usize,
+    /// The source IP address, or `None` to use the address of the iface.
+    pub src_ip: Option<Ipv4Address>,
+    pub dst_ip: Ipv4Address,
+    /// The destination MAC address, which is ignored if the iface has no MAC address.
+    pub dst_mac: EthernetAddress,
+    pub udp_src_port: u16,
+    pub udp_dst_port: u16,
+}
+
+impl Default for PktGenConfig {
+    fn default() -> Self {
+        Self {
+            count: 1000,
+            rate: 0,
+            pkt_size: 64,
+            src_ip: None,
+            dst_ip: Ipv4Address::BROADCAST,
+            dst_mac: EthernetAddress::BROADCAST,
+            udp_src_port: 9,
+            udp_dst_port: 9,
+        }
+    }
+}
+
+impl PktGenConfig {
+    /// Applies a command in the form of `<name> <value>`, e.g., `count 10000`.
+    pub fn apply(&mut self, command: &str) -> Result<()> {
+        let mut words = command.split_whitespace();
+        let (Some(name), Some(value), None) = (words.next(), words.next(), words.next()) else {
+            return_errno_with_message!(Errno::EINVAL, "the command is not `<name> <value>`");
+        };
+        let invalid_value = || Error::with_message(Errno::EINVAL, "invalid value");
+        match name {
+            "count" => self.count = value.parse().map_err(|_| invalid_value())?,
+            "rate" => self.rate = value.parse().map_err(|_| invalid_value())?,
+            "pkt_size" => {
+                let pkt_size = value.parse().map_err(|_| invalid_value())?;
+                if !(min_pkt_size(false)..=MAX_PKT_SIZE).contains(&pkt_size) {
+                    return_errno_with_message!(Errno::EINVAL, "the packet size is out of range");
+                }
+                self.pkt_size = pkt_size;
+            }
+            "src" => {
+                let bytes = parse_bytes(value, '.', 10).ok_or_else(invalid_value)?;
+                self.src_ip = Some(Ipv4Address(bytes));
+            }
+            "dst" => {
+                let bytes = parse_bytes(value, '.', 10).ok_or_else(invalid_value)?;
+                self.dst_ip = Ipv4Address(bytes);
+            }
+            "dst_mac" => {
+                let bytes = parse_bytes(value, ':', 16).ok_or_else(invalid_value)?;
+                self.dst_mac = EthernetAddress(bytes);
+            }
+            "udp_src_port" => self.udp_src_port = value.parse().map_err(|_| invalid_value())?,
+            "udp_dst_port" => self.udp_dst_port = value.parse().map_err(|_| invalid_value())?,
+            _ => return_errno_with_message!(Errno::EINVAL, "unknown command"),
+        }
+        Ok(())
+    }
+}
+
+/// The counters of a packet generator, which are reset when it is started.
+#[derive(Debug, Clone, Copy)]
+pub struct PktGenStats {
+    /// The number of packets accepted by the device.
+    pub nr_generated: u64,
+    /// The number of packets dropped since the device has no room for them.
+    pub nr_dropped: u64,
+    /// The time since the generator was started, or the duration of the last run.
+    pub elapsed: Duration,
+}
+
+/// The packet generator of an iface.
+pub struct PktGen {
+    config: Mutex<PktGenConfig>,
+    /// Whether the thread of the generator is running.
+    is_running: AtomicBool,
+    is_stop_requested: AtomicBool,
+    nr_generated: AtomicU64,
+    nr_dropped: AtomicU64,
+    /// The time when the generator was started.
+    start_ns: AtomicU64,
+    /// The time when the generator was stopped, or zero if it is running.
+    stop_ns: AtomicU64,
+    /// The wait queue to pace the packets, which is woken up when the generator is stopped.
+    wait_queue: WaitQueue,
+}
+
+impl PktGen {
+    pub(super) fn new() -> Self {
+        Self {
+            config: Mutex::new(PktGenConfig::default()),
+            is_running: AtomicBool::new(false),
+            is_stop_requested: AtomicBool::new(false),
+            nr_generated: AtomicU64::new(0),
+            nr_dropped: AtomicU64::new(0),
+            start_ns: AtomicU64::new(0),
+            stop_ns: AtomicU64::new(0),
+            wait_queue: WaitQueue::new(),
+        }
+    }
+
+    pub fn config(&self) -> PktGenConfig {
+        self.config.lock().clone()
+    }
+
+    /// Applies a configuration command, which is refused while the generator is running.
+    pub fn apply(&self, command: &str) -> Result<()> {
+        if self.is_running() {
+            return_errno_with_message!(Errno::EBUSY, "the generator is running");
+        }
+        self.config.lock().apply(command)
+    }
+
+    pub fn is_running(&self) -> bool {
+        self.is_running.load(Ordering::Acquire)
+    }
+
+    pub fn stats(&self) -> PktGenStats {
+        let start_ns = self.start_ns.load(Ordering::Relaxed);
+        let elapsed_ns = match self.stop_ns.load(Ordering::Relaxed) {
+            // The generator has never been started.
+            _ if start_ns == 0 => 0,
+            0 => now_ns().saturating_sub(start_ns),
+            stop_ns => stop_ns.saturating_sub(start_ns),
+        };
+        PktGenStats {
+            nr_generated: self.nr_generated.load(Ordering::Relaxed),
+            nr_dropped: self.nr_dropped.load(Ordering::Relaxed),
+            elapsed: Duration::from_nanos(elapsed_ns),
+        }
+    }
+
+    /// Stops the generator, which takes effect before the next packet is sent.
+    pub fn stop(&self) {
+        self.is_stop_requested.store(true, Ordering::Release);
+        self.wait_queue.wake_all();
+    }
+
+    fn is_stop_requested(&self) -> bool {
+        self.is_stop_requested.load(Ordering::Acquire)
+    }
+}
+
+/// Starts the packet generator of the iface in a kernel thread.
+pub fn start(iface: Arc<dyn Iface>) -> Result<()> {
+    let pktgen = iface.pktgen();
+    if pktgen
+        .is_running
+        .compare_exchange(false, true, Ordering::AcqRel, Ordering::Acquire)
+        .is_err()
+    {
+        return_errno_with_message!(Errno::EBUSY, "the generator is running");
+    }
+    let config = pktgen.config();
+    let packet = match build_packet(iface.as_ref(), &config) {
+        Ok(packet) => packet,
+        Err(err) => {
+            pktgen.is_running.store(false, Ordering::Release);
+            return Err(err);
+        }
+    };
+
+    pktgen.is_stop_requested.store(false, Ordering::Release);
+    pktgen.nr_generated.store(0, Ordering::Relaxed);
+    pktgen.nr_dropped.store(0, Ordering::Relaxed);
+    pktgen.start_ns.store(now_ns(), Ordering::Relaxed);
+    pktgen.stop_ns.store(0, Ordering::Relaxed);
+    let task_fn = move || run(iface, config, packet);
+    Thread::spawn_kernel_thread(ThreadOptions::new(task_fn));
+    Ok(())
+}
+
+fn run(iface: Arc<dyn Iface>, config: PktGenConfig, mut packet: Vec<u8>) {
+    let pktgen = iface.pktgen();
+    let seq_offset = header_len(iface.as_ref()) + 4;
+    let start_ns = pktgen.start_ns.load(Ordering::Relaxed);
+
+    let mut seq = 0u64;
+    while !pktgen.is_stop_requested() && (config.count == 0 || seq < config.count) {
+        if config.rate != 0 {
+            // Sends the packets that are due, and sleeps until the next tick otherwise.
+            let elapsed_ns = now_ns().saturating_sub(start_ns) as u128;
+            let nr_due = elapsed_ns * config.rate as u128 / 1_000_000_000 + 1;
+            if seq as u128 >= nr_due {
+                pktgen.wait_queue.wait_until_or_timeout(
+                    || pktgen.is_stop_requested().then_some(()),
+                    &Duration::from_millis(1),
+                );
+                continue;
+            }
+        } else if seq % YIELD_INTERVAL == YIELD_INTERVAL - 1 {
+            Thread::yield_now();
+        }
+
+        packet[seq_offset..seq_offset + 4].copy_from_slice(&(seq as u32).to_be_bytes());
+        if iface.transmit_raw(&packet) {
+            pktgen.nr_generated.fetch_add(1, Ordering::Relaxed);
+        } else {
+            pktgen.nr_dropped.fetch_add(1, Ordering::Relaxed);
+        }
+        seq += 1;
+    }
+
+    pktgen.stop_ns.store(now_ns(), Ordering::Relaxed);
+    pktgen.is_running.store(false, Ordering::Release);
+}
+
+/// Builds the UDP packet to send, whose sequence number is filled in later.
+///
+/// The UDP checksum is left zero, which means no checksum in IPv4, since the payload
+/// changes with the sequence number.
+fn build_packet(iface: &dyn Iface, config: &PktGenConfig) -> Result<Vec<u8>> {
+    let has_ethernet = iface.mac_addr().is_some();
+    if config.pkt_size < min_pkt_size(has_ethernet) {
+        return_errno_with_message!(Errno::EINVAL, "the packet size is too small");
+    }
+    let src_ip = config
+        .src_ip
+        .or_else(|| iface.ipv4_addr())
+        .unwrap_or(Ipv4Address::UNSPECIFIED);
+
+    let mut packet = vec![0u8; config.pkt_size];
+    let ip_buf = if let Some(src_mac) = iface.mac_addr() {
+        let ethernet_repr = EthernetRepr {
+            src_addr: src_mac,
+            dst_addr: config.dst_mac,
+            ethertype: EthernetProtocol::Ipv4,
+        };
+        let mut frame = EthernetFrame::new_unchecked(&mut packet[..]);
+        ethernet_repr.emit(&mut frame);
+        &mut packet[ETHERNET_HEADER_LEN..]
+    } else {
+        &mut packet[..]
+    };
+
+    let udp_len = ip_buf.len() - IPV4_HEADER_LEN;
+    let ip_repr = Ipv4Repr {
+        src_addr: src_ip,
+        dst_addr: config.dst_ip,
+        next_header: IpProtocol::Udp,
+        payload_len: udp_len,
+        hop_limit: 64,
+    };
+    let checksum_caps = ChecksumCapabilities::default();
+    let mut ip_packet = Ipv4Packet::new_unchecked(&mut ip_buf[..]);
+    ip_repr.emit(&mut ip_packet, &checksum_caps);
+
+    let udp_repr = UdpRepr {
+        src_port: config.udp_src_port,
+        dst_port: config.udp_dst_port,
+    };
+    let mut udp_packet = UdpPacket::new_unchecked(ip_packet.payload_mut());
+    udp_repr.emit(
+        &mut udp_packet,
+        &IpAddress::Ipv4(src_ip),
+        &IpAddress::Ipv4(config.dst_ip),
+        udp_len - UDP_HEADER_LEN,
+        |payload| payload[..4].copy_from_slice(&PKTGEN_MAGIC.to_be_bytes()),
+        &checksum_caps,
+    );
+    udp_packet.set_checksum(0);
+    Ok(packet)
+}
+
+/// Parses an address whose bytes are separated by `separator`, e.g., `10.0.2.2` and
+/// `52:54:00:12:34:56`.
+fn parse_bytes<const N: usize>(str: &str, separator: char, radix: u32) -> Option<[u8; N]> {
+    let mut bytes = [0u8; N];
+    let mut parts = str.split(separator);
+    for byte in bytes.iter_mut() {
+        *byte = u8::from_str_radix(parts.next()?, radix).ok()?;
+    }
+    parts.next().is_none().then_some(bytes)
+}
+
+fn header_len(iface: &dyn Iface) -> usize {
+    min_pkt_size(iface.mac_addr().is_some()) - PAYLOAD_HEADER_LEN
+}
+
+fn min_pkt_size(has_ethernet: bool) -> usize {
+    let ethernet_header_len = if has_ethernet { ETHERNET_HEADER_LEN } else { 0 };
+    ethernet_header_len + IPV4_HEADER_LEN + UDP_HEADER_LEN + PAYLOAD_HEADER_LEN
+}
+
+fn now_ns() -> u64 {
+    JiffiesClock::elapsed().as_nanos() as u64
+}
@@ -0,0 +1,290 @@
+// SPDX-License-Identifier: MPL-2.0
+
+//! Receive packet steering (RPS).
+//!
+//! The packets received by an iface are steered to the backlogs of the CPUs by the hashes
+//! of their flows, i.e., their protocols, addresses and ports. Each CPU has a net worker,
+//! which processes the packets in the backlog of the CPU. So the packets of a flow are
+//! always processed on the same CPU, and a server can run the thread that serves a
+//! connection on the CPU, which is told by the `SO_INCOMING_CPU` socket option.
+//!
+//! The packets that belong to no flow, e.g., the ARP packets, are processed on the CPU
+//! that receives them.
+
+use alloc::collections::VecDeque;
+
+use aster_frame::{
+    cpu::{num_cpus, this_cpu, CpuSet},
+    sync::WaitQueue,
+    task::Priority,
+};
+use aster_network::{AnyNetworkDevice, NetBuf};
+use smoltcp::{
+    phy::{self, DeviceCapabilities},
+    time::Instant,
+    wire::{IpAddress, IpEndpoint, IpProtocol},
+};
+
+use super::Iface;
+use crate::{
+    prelude::*,
+    thread::{
+        kernel_thread::{KernelThreadExt, ThreadOptions},
+        Thread,
+    },
+};
+
+/// The receive packet steering of an iface.
+pub struct Rps {
+    driver: Arc<SpinLock<dyn AnyNetworkDevice>>,
+    /// The backlogs indexed by the CPU IDs.
+    backlogs: Vec<Backlog>,
+}
+
+struct Backlog {
+    packets: SpinLock<VecDeque<NetBuf>>,
+    /// The wait queue that the net worker of the CPU sleeps on.
+    wait_queue: WaitQueue,
+}
+
+impl Rps {
+    pub fn new(driver: Arc<SpinLock<dyn AnyNetworkDevice>>) -> Self {
+        let backlogs = (0..num_cpus())
+            .map(|_| Backlog {
+                packets: SpinLock::new(VecDeque::new()),
+                wait_queue: WaitQueue::new(),
+            })
+            .collect();
+        Self { driver, backlogs }
+    }
+
+    /// Receives the packets from the driver and steers them to the backlogs.
+    ///
+    /// This method can be called in the IRQ handlers.
+    pub fn steer(&self) {
+        let mut driver = self.driver.lock_irq_disabled();
+        self.steer_from(&mut *driver);
+    }
+
+    /// Receives the packets from the locked driver and steers them to the backlogs.
+    ///
+    /// The packets are received from all the receive queues of the driver. Even if the
+    /// device spreads the flows among the receive queues itself, they are still steered
+    /// by the software hashes, so that `SO_INCOMING_CPU` is consistent with the CPU that
+    /// processes them.
+    pub(super) fn steer_from(&self, driver: &mut dyn AnyNetworkDevice) {
+        let mut steered_cpus = CpuSet::new_empty();
+        for queue in 0..driver.num_queue_pairs() {
+            self.steer_queue(driver, queue, &mut steered_cpus);
+        }
+
+        for cpu in steered_cpus.iter() {
+            self.backlogs[cpu].wait_queue.wake_all();
+        }
+    }
+
+    fn steer_queue(
+        &self,
+        driver: &mut dyn AnyNetworkDevice,
+        queue: usize,
+        steered_cpus: &mut CpuSet,
+    ) {
+        while driver.can_receive_from(queue) {
+            let Ok(rx_buffer) = driver.receive_from(queue) else {
+                break;
+            };
+            // The packet stays in the DMA segment until it is processed.
+            let net_buf = NetBuf::from(rx_buffer);
+            let mut headers = [0u8; MAX_FLOW_HEADERS_LEN];
+            let headers_len = net_buf.copy_prefix(&mut headers);
+            let cpu =
+                flow_of_packet(&headers[..headers_len]).map_or_else(this_cpu, cpu_of_flow_hash);
+
+            let mut packets = self.backlogs[cpu as usize].packets.lock_irq_disabled();
+            // The packets are dropped if the net worker cannot keep up with them.
+            if packets.len() < MAX_BACKLOG_LEN {
+                packets.push_back(net_buf);
+                steered_cpus.add(cpu);
+            }
+        }
+    }
+
+    /// Returns a device that receives the packets from the backlog of the current CPU, and
+    /// transmits the packets through the locked driver.
+    pub(super) fn backlog_device<'a>(
+        &'a self,
+        driver: &'a mut dyn AnyNetworkDevice,
+    ) -> BacklogDevice<'a> {
+        BacklogDevice {
+            driver,
+            backlog: &self.backlogs[this_cpu() as usize],
+        }
+    }
+}
+
+/// The maximum number of packets in a backlog, which is the same as the default
+/// `netdev_max_backlog` of Linux.
+const MAX_BACKLOG_LEN: usize = 1000;
+
+/// The maximum length of the headers that [`flow_of_packet`] parses, i.e., the Ethernet
+/// header, the IPv4 header with options, and the ports.
+const MAX_FLOW_HEADERS_LEN: usize = 14 + 60 + 4;
+
+/// Returns the CPU that processes the packets of the flow, which are sent from `src` to
+/// `dst`. See the [module-level documentation](self).
+pub fn cpu_of_flow(protocol: IpProtocol, src: IpEndpoint, dst: IpEndpoint) -> u32 {
+    let (IpAddress::Ipv4(src_addr), IpAddress::Ipv4(dst_addr)) = (src.addr, dst.addr);
+    let hash = flow_hash(protocol.into(), src_addr.0, src.port, dst_addr.0, dst.port);
+    cpu_of_flow_hash(hash)
+}
+
+fn cpu_of_flow_hash(hash: u32) -> u32 {
+    hash % num_cpus()
+}
+
+/// Returns the flow hash of an Ethernet frame, if it carries an unfragmented TCP or UDP
+/// packet over IPv4.
+fn flow_of_packet(frame: &[u8]) -> Option<u32> {
+    const ETHERNET_HEADER_LEN: usize = 14;
+    const ETHERTYPE_IPV4: u16 = 0x0800;
+
+    let ethertype = u16::from_be_bytes(frame.get(12..14)?.try_into().unwrap());
+    if ethertype != ETHERTYPE_IPV4 {
+        return None;
+    }
+    let packet = &frame[ETHERNET_HEADER_LEN..];
+    let header_len = (*packet.first()? & 0xf) as usize * 4;
+    let protocol = *packet.get(9)?;
+    if protocol != u8::from(IpProtocol::Tcp) && protocol != u8::from(IpProtocol::Udp) {
+        return None;
+    }
+    // Only the first fragment has the ports.
+    let frag = u16::from_be_bytes(packet.get(6..8)?.try_into().unwrap());
+    let is_fragment = frag & 0x3fff != 0;
+    if is_fragment {
+        return None;
+    }
+
+    let src_addr = packet.get(12..16)?.try_into().unwrap();
+    let dst_addr = packet.get(16..20)?.try_into().unwrap();
+    let ports = packet.get(header_len..header_len + 4)?;
+    let src_port = u16::from_be_bytes([ports[0], ports[1]]);
+    let dst_port = u16::from_be_bytes([ports[2], ports[3]]);
+    Some(flow_hash(protocol, src_addr, src_port, dst_addr, dst_port))
+}
+
+/// Hashes the flow with Jenkins's one-at-a-time hash.
+fn flow_hash(
+    protocol: u8,
+    src_addr: [u8; 4],
+    src_port: u16,
+    dst_addr: [u8; 4],
+    dst_port: u16,
+) -> u32 {
+    let bytes = src_addr
+        .into_iter()
+        .chain(dst_addr)
+        .chain(src_port.to_be_bytes())
+        .chain(dst_port.to_be_bytes())
+        .chain([protocol]);
+
+    let mut hash: u32 = 0;
+    for byte in bytes {
+        hash = hash.wrapping_add(byte as u32);
+        hash = hash.wrapping_add(hash << 10);
+        hash ^= hash >> 6;
+    }
+    hash = hash.wrapping_add(hash << 3);
+    hash ^= hash >> 11;
+    hash.wrapping_add(hash << 15)
+}
+
+/// Spawns a net worker for each CPU if the iface steers the received packets.
+pub fn spawn_rps_workers(iface: Arc<dyn Iface>) {
+    let Some(rps) = iface.rps() else {
+        return;
+    };
+
+    for cpu in 0..rps.backlogs.len() {
+        let iface = iface.clone();
+        let task_fn = move || {
+            trace!("spawn net worker for {} on CPU {}", iface.name(), cpu);
+            let backlog = &iface.rps().unwrap().backlogs[cpu];
+            let has_packets = || !backlog.packets.lock_irq_disabled().is_empty();
+            loop {
+                backlog
+                    .wait_queue
+                    .wait_until(|| has_packets().then_some(()));
+                // The worker runs on the CPU, so the poll processes the backlog of the CPU.
+                iface.poll();
+            }
+        };
+
+        let mut cpu_affinity = CpuSet::new_empty();
+        cpu_affinity.add(cpu as u32);
+        let options = ThreadOptions::new(task_fn)
+            .cpu_affinity(cpu_affinity)
+            .priority(Priority::high());
+        Thread::spawn_kernel_thread(options);
+    }
+}
+
+/// A device that receives the packets from a backlog.
+pub(super) struct BacklogDevice<'a> {
+    driver: &'a mut dyn AnyNetworkDevice,
+    backlog: &'a Backlog,
+}
+
+impl phy::Device for BacklogDevice<'_> {
+    type RxToken<'a> = RxToken where Self: 'a;
+    type TxToken<'a> = TxToken<'a> where Self: 'a;
+
+    fn receive(&mut self, _timestamp: Instant) -> Option<(Self::RxToken<'_>, Self::TxToken<'_>)> {
+        let net_buf = self.backlog.packets.lock_irq_disabled().pop_front()?;
+        Some((RxToken(net_buf), TxToken(&mut *self.driver)))
+    }
+
+    fn transmit(&mut self, _timestamp: Instant) -> Option<Self::TxToken<'_>> {
+        if self.driver.can_send_to(tx_queue_of_this_cpu(&*self.driver)) {
+            Some(TxToken(&mut *self.driver))
+        } else {
+            None
+        }
+    }
+
+    fn capabilities(&self) -> DeviceCapabilities {
+        AnyNetworkDevice::capabilities(&*self.driver)
+    }
+}
+
+pub(super) struct RxToken(NetBuf);
+
+impl phy::RxToken for RxToken {
+    fn consume<R, F>(self, f: F) -> R
+    where
+        F: FnOnce(&mut [u8]) -> R,
+    {
+        self.0.consume(f)
+    }
+}
+
+pub(super) struct TxToken<'a>(&'a mut dyn AnyNetworkDevice);
+
+impl phy::TxToken for TxToken<'_> {
+    fn consume<R, F>(self, len: usize, f: F) -> R
+    where
+        F: FnOnce(&mut [u8]) -> R,
+    {
+        let mut buffer = vec![0u8; len];
+        let res = f(&mut buffer);
+        let queue = tx_queue_of_this_cpu(&*self.0);
+        self.0.send_to(queue, &buffer).expect("Send packet failed");
+        res
+    }
+}
+
+/// Returns the transmit queue of the current CPU, so that the CPUs send the packets
+/// without contending for the same queue on the device.
+fn tx_queue_of_this_cpu(driver: &dyn AnyNetworkDevice) -> usize {
+    this_cpu() as usize % driver.num_queue_pairs()
+}
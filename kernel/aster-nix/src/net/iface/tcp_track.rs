@@ -0,0 +1,339 @@
+// SPDX-License-Identifier: MPL-2.0
+
+//! Tracking of the TCP connections by the segments that pass an iface.
+//!
+//! smoltcp keeps its RTT estimator and its retransmissions to itself. So they are measured
+//! here, from the segments that the iface transmits and receives:
+//!  - A segment is a retransmission if it sends nothing beyond the highest sequence number
+//!    that the connection has ever sent.
+//!  - An RTT sample is the time from sending a segment with new sequence numbers until an
+//!    ACK covering it arrives. As Karn's algorithm requires, no sample is taken if some data
+//!    are retransmitted in between. The samples are then smoothed as described in RFC 6298.
+//!
+//! A connection is tracked while its [`TrackedTcpFlow`] is alive.
+
+use core::{
+    sync::atomic::{AtomicU64, Ordering},
+    time::Duration,
+};
+
+use smoltcp::{
+    phy::{self, Device, DeviceCapabilities, Medium},
+    time::Instant,
+    wire::{
+        EthernetFrame, EthernetProtocol, IpAddress, IpEndpoint, IpProtocol, Ipv4Packet, TcpPacket,
+        TcpSeqNumber,
+    },
+};
+
+use super::Iface;
+use crate::prelude::*;
+
+/// The statistics of a TCP connection that are collected by an iface.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct TcpFlowStats {
+    /// The smoothed RTT and the RTT variance.
+    pub rtt: Option<(Duration, Duration)>,
+    pub min_rtt: Option<Duration>,
+    /// The number of retransmissions since new data were last acknowledged.
+    pub retransmits: u32,
+    pub total_retrans: u32,
+    pub bytes_retrans: u64,
+    pub segs_out: u32,
+    pub data_segs_out: u32,
+    pub segs_in: u32,
+    pub data_segs_in: u32,
+}
+
+impl TcpFlowStats {
+    fn sample_rtt(&mut self, rtt: Duration) {
+        self.rtt = Some(match self.rtt {
+            None => (rtt, rtt / 2),
+            Some((srtt, rttvar)) => {
+                let delta = if srtt > rtt { srtt - rtt } else { rtt - srtt };
+                (srtt * 7 / 8 + rtt / 8, rttvar * 3 / 4 + delta / 4)
+            }
+        });
+        self.min_rtt = Some(self.min_rtt.map_or(rtt, |min_rtt| min_rtt.min(rtt)));
+    }
+}
+
+/// The TCP connections tracked by an iface, keyed by their local and remote endpoints.
+pub(super) struct TcpTracker {
+    flows: SpinLock<BTreeMap<(IpEndpoint, IpEndpoint), TcpFlow>>,
+    next_id: AtomicU64,
+}
+
+struct TcpFlow {
+    /// The ID of the [`TrackedTcpFlow`], which tells a new connection from a closed one
+    /// with the same endpoints.
+    id: u64,
+    stats: TcpFlowStats,
+    /// The end of the sequence numbers that have been sent.
+    snd_max: Option<TcpSeqNumber>,
+    /// The highest acknowledgment number that has been received.
+    snd_una: Option<TcpSeqNumber>,
+    /// The end of the segment that is being timed and the time that it was sent.
+    rtt_probe: Option<(TcpSeqNumber, Instant)>,
+}
+
+impl TcpFlow {
+    fn new(id: u64) -> Self {
+        Self {
+            id,
+            stats: TcpFlowStats::default(),
+            snd_max: None,
+            snd_una: None,
+            rtt_probe: None,
+        }
+    }
+
+    fn on_transmitted(&mut self, segment: &TcpPacket<&[u8]>, now: Instant) {
+        let data_len = segment.payload().len();
+        self.stats.segs_out += 1;
+        if data_len > 0 {
+            self.stats.data_segs_out += 1;
+        }
+
+        // The segments that occupy no sequence numbers, e.g., the pure ACKs, are never
+        // retransmitted.
+        let seq_len = segment.segment_len();
+        if seq_len == 0 {
+            return;
+        }
+        let seq_end = segment.seq_number() + seq_len;
+        match self.snd_max {
+            Some(snd_max) if seq_end <= snd_max => {
+                self.stats.retransmits += 1;
+                self.stats.total_retrans += 1;
+                self.stats.bytes_retrans += data_len as u64;
+                self.rtt_probe = None;
+            }
+            _ => {
+                self.snd_max = Some(seq_end);
+                if self.rtt_probe.is_none() {
+                    self.rtt_probe = Some((seq_end, now));
+                }
+            }
+        }
+    }
+
+    fn on_received(&mut self, segment: &TcpPacket<&[u8]>, now: Instant) {
+        self.stats.segs_in += 1;
+        if !segment.payload().is_empty() {
+            self.stats.data_segs_in += 1;
+        }
+
+        if !segment.ack() {
+            return;
+        }
+        let ack = segment.ack_number();
+        if self.snd_una.map_or(true, |snd_una| ack > snd_una) {
+            self.snd_una = Some(ack);
+            self.stats.retransmits = 0;
+        }
+        if let Some((probe_end, sent_at)) = self.rtt_probe {
+            if ack >= probe_end {
+                self.rtt_probe = None;
+                let rtt = Duration::from_micros((now - sent_at).total_micros());
+                self.stats.sample_rtt(rtt);
+            }
+        }
+    }
+}
+
+impl TcpTracker {
+    pub(super) fn new() -> Self {
+        Self {
+            flows: SpinLock::new(BTreeMap::new()),
+            next_id: AtomicU64::new(0),
+        }
+    }
+
+    /// Returns a device that tracks the segments transmitted and received through `device`.
+    pub(super) fn device<'a, D: Device + ?Sized>(
+        &'a self,
+        device: &'a mut D,
+    ) -> TrackingDevice<'a, D> {
+        let medium = device.capabilities().medium;
+        TrackingDevice {
+            device,
+            tracker: self,
+            medium,
+        }
+    }
+
+    fn on_transmitted(&self, medium: Medium, frame: &[u8], now: Instant) {
+        let Some((src, dst, segment)) = parse_tcp_segment(medium, frame) else {
+            return;
+        };
+        if let Some(flow) = self.flows.lock_irq_disabled().get_mut(&(src, dst)) {
+            flow.on_transmitted(&segment, now);
+        }
+    }
+
+    fn on_received(&self, medium: Medium, frame: &[u8], now: Instant) {
+        let Some((src, dst, segment)) = parse_tcp_segment(medium, frame) else {
+            return;
+        };
+        if let Some(flow) = self.flows.lock_irq_disabled().get_mut(&(dst, src)) {
+            flow.on_received(&segment, now);
+        }
+    }
+}
+
+/// Parses the TCP segment carried by an unfragmented IPv4 packet in the frame.
+///
+/// Returns the source and destination endpoints and the segment.
+fn parse_tcp_segment(
+    medium: Medium,
+    frame: &[u8],
+) -> Option<(IpEndpoint, IpEndpoint, TcpPacket<&[u8]>)> {
+    let packet = match medium {
+        Medium::Ethernet => {
+            let frame = EthernetFrame::new_checked(frame).ok()?;
+            if frame.ethertype() != EthernetProtocol::Ipv4 {
+                return None;
+            }
+            frame.payload()
+        }
+        Medium::Ip => frame,
+    };
+
+    let packet = Ipv4Packet::new_checked(packet).ok()?;
+    if packet.next_header() != IpProtocol::Tcp || packet.more_frags() || packet.frag_offset() != 0 {
+        return None;
+    }
+    let segment = TcpPacket::new_checked(packet.payload()).ok()?;
+    let src = IpEndpoint::new(IpAddress::Ipv4(packet.src_addr()), segment.src_port());
+    let dst = IpEndpoint::new(IpAddress::Ipv4(packet.dst_addr()), segment.dst_port());
+    Some((src, dst, segment))
+}
+
+/// A TCP connection that is tracked by an iface until this is dropped.
+pub struct TrackedTcpFlow {
+    iface: Arc<dyn Iface>,
+    key: (IpEndpoint, IpEndpoint),
+    id: u64,
+}
+
+impl TrackedTcpFlow {
+    pub(super) fn new(
+        iface: Arc<dyn Iface>,
+        local_endpoint: IpEndpoint,
+        remote_endpoint: IpEndpoint,
+    ) -> Self {
+        let key = (local_endpoint, remote_endpoint);
+        let tracker = iface.common().tcp_tracker();
+        let id = tracker.next_id.fetch_add(1, Ordering::Relaxed);
+        tracker
+            .flows
+            .lock_irq_disabled()
+            .insert(key, TcpFlow::new(id));
+        Self { iface, key, id }
+    }
+
+    /// Returns the statistics collected so far.
+    pub fn stats(&self) -> TcpFlowStats {
+        let flows = self.iface.common().tcp_tracker().flows.lock_irq_disabled();
+        flows
+            .get(&self.key)
+            .filter(|flow| flow.id == self.id)
+            .map(|flow| flow.stats)
+            .unwrap_or_default()
+    }
+}
+
+impl Drop for TrackedTcpFlow {
+    fn drop(&mut self) {
+        let mut flows = self.iface.common().tcp_tracker().flows.lock_irq_disabled();
+        // The endpoints may have been taken by a new connection.
+        if flows.get(&self.key).is_some_and(|flow| flow.id == self.id) {
+            flows.remove(&self.key);
+        }
+    }
+}
+
+/// A device that tracks the TCP segments that it transmits and receives.
+pub(super) struct TrackingDevice<'a, D: Device + ?Sized> {
+    device: &'a mut D,
+    tracker: &'a TcpTracker,
+    medium: Medium,
+}
+
+impl<D: Device + ?Sized> Device for TrackingDevice<'_, D> {
+    type RxToken<'a> = TrackingRxToken<'a, D::RxToken<'a>> where Self: 'a;
+    type TxToken<'a> = TrackingTxToken<'a, D::TxToken<'a>> where Self: 'a;
+
+    fn receive(&mut self, timestamp: Instant) -> Option<(Self::RxToken<'_>, Self::TxToken<'_>)> {
+        let (rx_token, tx_token) = self.device.receive(timestamp)?;
+        let rx_token = TrackingRxToken {
+            token: rx_token,
+            tracker: self.tracker,
+            medium: self.medium,
+            timestamp,
+        };
+        let tx_token = TrackingTxToken {
+            token: tx_token,
+            tracker: self.tracker,
+            medium: self.medium,
+            timestamp,
+        };
+        Some((rx_token, tx_token))
+    }
+
+    fn transmit(&mut self, timestamp: Instant) -> Option<Self::TxToken<'_>> {
+        let tx_token = self.device.transmit(timestamp)?;
+        Some(TrackingTxToken {
+            token: tx_token,
+            tracker: self.tracker,
+            medium: self.medium,
+            timestamp,
+        })
+    }
+
+    fn capabilities(&self) -> DeviceCapabilities {
+        self.device.capabilities()
+    }
+}
+
+pub(super) struct TrackingRxToken<'a, T> {
+    token: T,
+    tracker: &'a TcpTracker,
+    medium: Medium,
+    timestamp: Instant,
+}
+
+impl<T: phy::RxToken> phy::RxToken for TrackingRxToken<'_, T> {
+    fn consume<R, F>(self, f: F) -> R
+    where
+        F: FnOnce(&mut [u8]) -> R,
+    {
+        self.token.consume(|buffer| {
+            self.tracker
+                .on_received(self.medium, buffer, self.timestamp);
+            f(buffer)
+        })
+    }
+}
+
+pub(super) struct TrackingTxToken<'a, T> {
+    token: T,
+    tracker: &'a TcpTracker,
+    medium: Medium,
+    timestamp: Instant,
+}
+
+impl<T: phy::TxToken> phy::TxToken for TrackingTxToken<'_, T> {
+    fn consume<R, F>(self, len: usize, f: F) -> R
+    where
+        F: FnOnce(&mut [u8]) -> R,
+    {
+        self.token.consume(len, |buffer| {
+            let res = f(buffer);
+            self.tracker
+                .on_transmitted(self.medium, buffer, self.timestamp);
+            res
+        })
+    }
+}
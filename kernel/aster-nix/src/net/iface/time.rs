@@ -1,8 +1,8 @@
 // SPDX-License-Identifier: MPL-2.0
 
-use aster_frame::arch::timer::Jiffies;
+use crate::time::clocks::JiffiesClock;
 
 pub(super) fn get_network_timestamp() -> smoltcp::time::Instant {
-    let millis = Jiffies::elapsed().as_duration().as_millis();
+    let millis = JiffiesClock::elapsed().as_millis();
     smoltcp::time::Instant::from_millis(millis as i64)
 }
@@ -3,26 +3,28 @@
 #![allow(unused_variables)]
 
 use aster_network::AnyNetworkDevice;
-use aster_virtio::device::network::DEVICE_NAME;
 use smoltcp::{
     iface::{Config, Routes, SocketHandle, SocketSet},
     socket::dhcpv4,
-    wire::{self, IpCidr},
+    wire::{self, IpCidr, Ipv4Cidr},
 };
 
-use super::{common::IfaceCommon, internal::IfaceInternal, Iface};
+use super::{common::IfaceCommon, internal::IfaceInternal, rps::Rps, Iface};
 use crate::prelude::*;
 
 pub struct IfaceVirtio {
     driver: Arc<SpinLock<dyn AnyNetworkDevice>>,
     common: IfaceCommon,
+    rps: Rps,
     dhcp_handle: SocketHandle,
     weak_self: Weak<Self>,
 }
 
 impl IfaceVirtio {
-    pub fn new() -> Arc<Self> {
-        let virtio_net = aster_network::get_device(DEVICE_NAME).unwrap();
+    /// Creates the interface of the network device named `device_name`, which is not
+    /// necessarily a virtio device, e.g., an e1000 device.
+    pub fn new(device_name: &str) -> Arc<Self> {
+        let virtio_net = aster_network::get_device(device_name).unwrap();
         let interface = {
             let mac_addr = virtio_net.lock().mac_addr();
             let ip_addr = IpCidr::new(wire::IpAddress::Ipv4(wire::Ipv4Address::UNSPECIFIED), 0);
@@ -46,6 +48,7 @@ impl IfaceVirtio {
         let dhcp_handle = init_dhcp_client(&mut socket_set);
         drop(socket_set);
         Arc::new_cyclic(|weak| Self {
+            rps: Rps::new(virtio_net.clone()),
             driver: virtio_net,
             common,
             dhcp_handle,
@@ -54,40 +57,49 @@ impl IfaceVirtio {
     }
 
     /// FIXME: Once we have user program dhcp client, we may remove dhcp logic from kernel.
+    ///
+    /// A renewed lease with the same address keeps the established connections. If the
+    /// address changes or the lease is lost, the sockets on the old address are notified.
     pub fn process_dhcp(&self) {
-        let mut socket_set = self.common.sockets();
-        let dhcp_socket: &mut dhcpv4::Socket = socket_set.get_mut(self.dhcp_handle);
-        let config = if let Some(event) = dhcp_socket.poll() {
-            debug!("event = {:?}", event);
-            if let dhcpv4::Event::Configured(config) = event {
-                config
-            } else {
+        let event = {
+            let mut socket_set = self.common.sockets();
+            let dhcp_socket: &mut dhcpv4::Socket = socket_set.get_mut(self.dhcp_handle);
+            dhcp_socket.poll()
+        };
+        let Some(event) = event else {
+            return;
+        };
+        debug!("event = {:?}", event);
+
+        let config = match event {
+            dhcpv4::Event::Configured(config) => config,
+            dhcpv4::Event::Deconfigured => {
+                println!("DHCP lease is lost");
+                self.common
+                    .set_ipv4_cidr(Ipv4Cidr::new(wire::Ipv4Address::UNSPECIFIED, 0));
+                self.common
+                    .interface()
+                    .routes_mut()
+                    .remove_default_ipv4_route();
                 return;
             }
-        } else {
-            return;
         };
-        let ip_addr = IpCidr::Ipv4(config.address);
+        if self.common.ipv4_cidr() != Some(config.address) {
+            println!("DHCP update IP address: {:?}", config.address.address());
+            self.common.set_ipv4_cidr(config.address);
+        }
         let mut interface = self.common.interface();
-        interface.update_ip_addrs(|ipaddrs| {
-            if let Some(addr) = ipaddrs.iter_mut().next() {
-                // already has ipaddrs
-                *addr = ip_addr
-            } else {
-                // does not has ip addr
-                ipaddrs.push(ip_addr).unwrap();
+        match config.router {
+            Some(router) => {
+                println!("Default router address: {:?}", router);
+                interface
+                    .routes_mut()
+                    .add_default_ipv4_route(router)
+                    .unwrap();
+            }
+            None => {
+                interface.routes_mut().remove_default_ipv4_route();
             }
-        });
-        println!(
-            "DHCP update IP address: {:?}",
-            interface.ipv4_addr().unwrap()
-        );
-        if let Some(router) = config.router {
-            println!("Default router address: {:?}", router);
-            interface
-                .routes_mut()
-                .add_default_ipv4_route(router)
-                .unwrap();
         }
     }
 }
@@ -115,11 +127,23 @@ impl Iface for IfaceVirtio {
         }
     }
 
+    /// Steers the received packets to the backlogs, and processes the backlog of the
+    /// current CPU.
     fn poll(&self) {
         let mut driver = self.driver.lock_irq_disabled();
-        self.common.poll(&mut *driver);
+        self.rps.steer_from(&mut *driver);
+        self.common.poll(&mut self.rps.backlog_device(&mut *driver));
         self.process_dhcp();
     }
+
+    fn transmit_raw(&self, packet: &[u8]) -> bool {
+        let mut driver = self.driver.lock_irq_disabled();
+        driver.can_send() && driver.send(packet).is_ok()
+    }
+
+    fn rps(&self) -> Option<&Rps> {
+        Some(&self.rps)
+    }
 }
 
 /// Register a dhcp socket.
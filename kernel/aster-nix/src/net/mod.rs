@@ -2,9 +2,12 @@
 
 use spin::Once;
 
-use self::{iface::spawn_background_poll_thread, socket::vsock};
+use self::{
+    iface::{spawn_background_poll_thread, spawn_rps_workers},
+    socket::vsock,
+};
 use crate::{
-    net::iface::{Iface, IfaceLoopback, IfaceVirtio},
+    net::iface::{Iface, IfaceLoopback, IfaceVirtio, NetDeviceEvent, NETDEV_NOTIFIER_CHAIN},
     prelude::*,
 };
 
@@ -15,26 +18,49 @@ pub mod socket;
 
 pub fn init() {
     IFACES.call_once(|| {
-        let iface_virtio = IfaceVirtio::new();
+        let iface_virtio = IfaceVirtio::new(&primary_device_name());
         let iface_loopback = IfaceLoopback::new();
         vec![iface_virtio, iface_loopback]
     });
+    for iface in IFACES.get().unwrap() {
+        NETDEV_NOTIFIER_CHAIN.notify(&NetDeviceEvent::Register(iface.clone()));
+    }
 
     for (name, _) in aster_network::all_devices() {
         aster_network::register_recv_callback(&name, || {
             // TODO: further check that the irq num is the same as iface's irq num
             let iface_virtio = &IFACES.get().unwrap()[0];
-            iface_virtio.poll();
+            // The received packets are processed by the net workers of the CPUs that
+            // they are steered to, rather than in the IRQ handler.
+            match iface_virtio.rps() {
+                Some(rps) => rps.steer(),
+                None => iface_virtio.poll(),
+            }
         })
     }
     poll_ifaces();
     vsock::init();
 }
 
+/// Returns the name of the network device of the primary interface, which prefers the
+/// virtio-net device to the other devices.
+fn primary_device_name() -> String {
+    let device_name = aster_virtio::device::network::DEVICE_NAME;
+    if aster_network::get_device(device_name).is_some() {
+        return String::from(device_name);
+    }
+    aster_network::all_devices()
+        .into_iter()
+        .map(|(name, _)| name)
+        .next()
+        .unwrap_or_else(|| String::from(device_name))
+}
+
 /// Lazy init should be called after spawning init thread.
 pub fn lazy_init() {
     for iface in IFACES.get().unwrap() {
         spawn_background_poll_thread(iface.clone());
+        spawn_rps_workers(iface.clone());
     }
 }
 
@@ -1,11 +1,17 @@
 // SPDX-License-Identifier: MPL-2.0
 
 use crate::{
+    fs::utils::IoctlCmd,
     net::{
-        iface::{AnyBoundSocket, AnyUnboundSocket, BindPortConfig, Iface, IpAddress, IpEndpoint},
+        iface::{
+            AnyBoundSocket, AnyUnboundSocket, BindPortConfig, Iface, IpAddress, IpEndpoint,
+            Ipv4Address,
+        },
         IFACES,
     },
     prelude::*,
+    process::{credentials, credentials::capabilities::CapSet},
+    util::{read_val_from_user, write_val_to_user},
 };
 
 pub fn get_iface_to_bind(ip_addr: &IpAddress) -> Option<Arc<dyn Iface>> {
@@ -66,3 +72,83 @@ pub fn get_ephemeral_endpoint(remote_endpoint: &IpEndpoint) -> IpEndpoint {
     let ip_addr = iface.ipv4_addr().unwrap();
     IpEndpoint::new(IpAddress::Ipv4(ip_addr), 0)
 }
+
+const IFNAMSIZ: usize = 16;
+const AF_INET: u16 = 2;
+
+/// The `struct ifreq` of the ioctls on the IPv4 address of an iface, whose union holds a
+/// `struct sockaddr_in`.
+#[repr(C)]
+#[derive(Debug, Clone, Copy, Pod)]
+struct IfReq {
+    name: [u8; IFNAMSIZ],
+    family: u16,
+    port: u16,
+    addr: [u8; 4],
+    zero: [u8; 8],
+    /// The rest of the union, which is as large as `struct ifmap`.
+    padding: [u8; 8],
+}
+
+/// Gets or sets the address or the netmask of the iface whose name is in the `struct ifreq`
+/// at `arg`.
+///
+/// Setting the address of an iface keeps the established connections if the address does
+/// not change, and aborts those on the old address otherwise.
+pub(super) fn ioctl_iface(cmd: IoctlCmd, arg: Vaddr) -> Result<i32> {
+    if !matches!(
+        cmd,
+        IoctlCmd::SIOCGIFADDR
+            | IoctlCmd::SIOCSIFADDR
+            | IoctlCmd::SIOCGIFNETMASK
+            | IoctlCmd::SIOCSIFNETMASK
+    ) {
+        return_errno_with_message!(Errno::EINVAL, "the ioctl is not supported");
+    }
+
+    let mut ifreq: IfReq = read_val_from_user(arg)?;
+    let name = CStr::from_bytes_until_nul(&ifreq.name)
+        .ok()
+        .and_then(|name| name.to_str().ok())
+        .ok_or_else(|| Error::with_message(Errno::EINVAL, "invalid iface name"))?;
+    let iface = IFACES
+        .get()
+        .unwrap()
+        .iter()
+        .find(|iface| iface.name() == name)
+        .ok_or_else(|| Error::with_message(Errno::ENODEV, "the iface does not exist"))?;
+
+    match cmd {
+        IoctlCmd::SIOCGIFADDR | IoctlCmd::SIOCGIFNETMASK => {
+            let Some(ipv4_addr) = iface.ipv4_addr().filter(|addr| !addr.is_unspecified()) else {
+                return_errno_with_message!(Errno::EADDRNOTAVAIL, "the iface has no address");
+            };
+            let addr = if matches!(cmd, IoctlCmd::SIOCGIFADDR) {
+                ipv4_addr
+            } else {
+                iface.netmask().unwrap()
+            };
+            ifreq.family = AF_INET;
+            ifreq.port = 0;
+            ifreq.addr = addr.0;
+            ifreq.zero = [0; 8];
+            write_val_to_user(arg, &ifreq)?;
+        }
+        IoctlCmd::SIOCSIFADDR | IoctlCmd::SIOCSIFNETMASK => {
+            if !credentials().effective_capset().contains(CapSet::NET_ADMIN) {
+                return_errno_with_message!(Errno::EPERM, "CAP_NET_ADMIN is required");
+            }
+            if ifreq.family != AF_INET {
+                return_errno_with_message!(Errno::EINVAL, "only IPv4 addresses are supported");
+            }
+            let addr = Ipv4Address(ifreq.addr);
+            if matches!(cmd, IoctlCmd::SIOCSIFADDR) {
+                iface.set_ipv4_addr(addr);
+            } else {
+                iface.set_netmask(addr)?;
+            }
+        }
+        _ => unreachable!(),
+    }
+    Ok(0)
+}
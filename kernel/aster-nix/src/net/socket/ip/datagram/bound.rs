@@ -8,10 +8,9 @@ use crate::{
     events::IoEvents,
     net::{
         iface::{AnyBoundSocket, IpEndpoint, RawUdpSocket},
-        socket::util::send_recv_flags::SendRecvFlags,
+        socket::util::{pollee::SocketPollee, send_recv_flags::SendRecvFlags},
     },
     prelude::*,
-    process::signal::Pollee,
 };
 
 pub struct BoundDatagram {
@@ -35,6 +34,12 @@ impl BoundDatagram {
         self.remote_endpoint
     }
 
+    /// Returns the CPU that processes the packets from the remote endpoint, if the socket
+    /// is connected and the iface steers the received packets.
+    pub fn incoming_cpu(&self) -> Option<u32> {
+        self.bound_socket.incoming_cpu(self.remote_endpoint?)
+    }
+
     pub fn set_remote_endpoint(&mut self, endpoint: &IpEndpoint) {
         self.remote_endpoint = Some(*endpoint)
     }
@@ -60,12 +65,24 @@ impl BoundDatagram {
         buf: &[u8],
         remote: &IpEndpoint,
         flags: SendRecvFlags,
+        pollee: &SocketPollee,
     ) -> Result<usize> {
+        if let Some(err) = self.bound_socket.iface_error() {
+            return Err(err);
+        }
+
         let result = self.bound_socket.raw_with(|socket: &mut RawUdpSocket| {
             if socket.payload_send_capacity() < buf.len() {
                 return None;
             }
-            Some(socket.send_slice(buf, *remote))
+            let result = socket.send_slice(buf, *remote);
+            // The socket may still accept smaller datagrams, but the sender should wait for
+            // the queued ones to be sent. This is done with the socket locked, so the event
+            // is added back after the interface dequeues them.
+            if let Err(SendError::BufferFull) = result {
+                pollee.del_events(IoEvents::OUT);
+            }
+            Some(result)
         });
         match result {
             Some(Ok(())) => Ok(buf.len()),
@@ -79,19 +96,28 @@ impl BoundDatagram {
         }
     }
 
-    pub(super) fn init_pollee(&self, pollee: &Pollee) {
+    pub(super) fn init_pollee(&self, pollee: &SocketPollee) {
         pollee.reset_events();
         self.update_io_events(pollee)
     }
 
-    pub(super) fn update_io_events(&self, pollee: &Pollee) {
+    pub(super) fn update_io_events(&self, pollee: &SocketPollee) {
+        self.update_read_events(pollee);
+        self.update_write_events(pollee);
+    }
+
+    pub(super) fn update_read_events(&self, pollee: &SocketPollee) {
         self.bound_socket.raw_with(|socket: &mut RawUdpSocket| {
             if socket.can_recv() {
                 pollee.add_events(IoEvents::IN);
             } else {
                 pollee.del_events(IoEvents::IN);
             }
+        });
+    }
 
+    pub(super) fn update_write_events(&self, pollee: &SocketPollee) {
+        self.bound_socket.raw_with(|socket: &mut RawUdpSocket| {
             if socket.can_send() {
                 pollee.add_events(IoEvents::OUT);
             } else {
@@ -2,34 +2,54 @@
 
 #![allow(unused_variables)]
 
-use core::sync::atomic::{AtomicBool, Ordering};
+use core::{
+    sync::atomic::{AtomicBool, Ordering},
+    time::Duration,
+};
 
 use takeable::Takeable;
 
-use self::{bound::BoundDatagram, unbound::UnboundDatagram};
-use super::{common::get_ephemeral_endpoint, UNSPECIFIED_LOCAL_ENDPOINT};
+use self::{
+    bound::BoundDatagram,
+    options::{UdpGro, UdpSegment},
+    unbound::UnboundDatagram,
+    util::{UdpOptionSet, MAX_UDP_PAYLOAD, UDP_MAX_SEGMENTS},
+};
+use super::{
+    common::{get_ephemeral_endpoint, ioctl_iface},
+    UNSPECIFIED_LOCAL_ENDPOINT,
+};
 use crate::{
     events::{IoEvents, Observer},
-    fs::{file_handle::FileLike, utils::StatusFlags},
+    fs::{
+        file_handle::FileLike,
+        utils::{IoctlCmd, StatusFlags},
+    },
+    match_sock_option_mut, match_sock_option_ref,
     net::{
         iface::IpEndpoint,
         poll_ifaces,
         socket::{
-            util::{send_recv_flags::SendRecvFlags, socket_addr::SocketAddr},
+            is_deadline_reached,
+            options::{IncomingCpu, SocketOption},
+            util::{pollee::SocketPollee, send_recv_flags::SendRecvFlags, socket_addr::SocketAddr},
             Socket,
         },
     },
     prelude::*,
-    process::signal::{Pollee, Poller},
+    process::signal::Poller,
 };
 
 mod bound;
+pub mod options;
 mod unbound;
+mod util;
 
 pub struct DatagramSocket {
+    options: RwLock<UdpOptionSet>,
     inner: RwLock<Takeable<Inner>>,
     nonblocking: AtomicBool,
-    pollee: Pollee,
+    pollee: SocketPollee,
 }
 
 enum Inner {
@@ -73,9 +93,10 @@ impl DatagramSocket {
     pub fn new(nonblocking: bool) -> Arc<Self> {
         Arc::new_cyclic(|me| {
             let unbound_datagram = UnboundDatagram::new(me.clone() as _);
-            let pollee = Pollee::new(IoEvents::empty());
+            let pollee = SocketPollee::new(IoEvents::empty());
             unbound_datagram.init_pollee(&pollee);
             Self {
+                options: RwLock::new(UdpOptionSet::new()),
                 inner: RwLock::new(Takeable::new(Inner::Unbound(unbound_datagram))),
                 nonblocking: AtomicBool::new(nonblocking),
                 pollee,
@@ -121,44 +142,127 @@ impl DatagramSocket {
     }
 
     fn try_recvfrom(&self, buf: &mut [u8], flags: SendRecvFlags) -> Result<(usize, SocketAddr)> {
+        let received = self.try_recvfrom_without_polling(buf, flags);
+        poll_ifaces();
+        received
+    }
+
+    fn try_recvfrom_without_polling(
+        &self,
+        buf: &mut [u8],
+        flags: SendRecvFlags,
+    ) -> Result<(usize, SocketAddr)> {
         let inner = self.inner.read();
 
         let Inner::Bound(bound_datagram) = inner.as_ref() else {
             return_errno_with_message!(Errno::EAGAIN, "the socket is not bound");
         };
 
-        let received =
-            bound_datagram
-                .try_recvfrom(buf, flags)
-                .map(|(recv_bytes, remote_endpoint)| {
-                    bound_datagram.update_io_events(&self.pollee);
-                    (recv_bytes, remote_endpoint.into())
-                });
+        bound_datagram
+            .try_recvfrom(buf, flags)
+            .map(|(recv_bytes, remote_endpoint)| {
+                bound_datagram.update_read_events(&self.pollee);
+                (recv_bytes, remote_endpoint.into())
+            })
+    }
 
-        drop(inner);
+    fn try_sendto(&self, buf: &[u8], remote: &IpEndpoint, flags: SendRecvFlags) -> Result<usize> {
+        let sent_bytes = self.try_sendto_without_polling(buf, remote, flags);
         poll_ifaces();
+        sent_bytes
+    }
 
-        received
+    fn try_sendto_without_polling(
+        &self,
+        buf: &[u8],
+        remote: &IpEndpoint,
+        flags: SendRecvFlags,
+    ) -> Result<usize> {
+        let segment_size = self.options.read().segment_size() as usize;
+        if segment_size == 0 || buf.len() <= segment_size {
+            return self.try_send_datagram(buf, remote, flags);
+        }
+
+        // UDP segmentation offload: split the super-datagram into datagrams of
+        // `segment_size` bytes (the last one may be shorter). As with Linux, the
+        // super-datagram is not sent partially because the send buffer is full, so
+        // the sender waits for room for the rest of the segments.
+        //
+        // TODO: Pass the super-datagram through to the network device if it
+        // supports UDP segmentation offload (e.g., virtio-net USO).
+        if buf.len() > MAX_UDP_PAYLOAD as usize {
+            return_errno_with_message!(Errno::EINVAL, "the super-datagram is too large");
+        }
+        if buf.len() > segment_size * UDP_MAX_SEGMENTS {
+            return_errno_with_message!(Errno::EINVAL, "too many segments in the datagram");
+        }
+
+        // The first segment is the largest one and goes to the same remote endpoint as
+        // the others. So if it is queued, the others are valid as well, and it is the
+        // only segment whose failure leaves nothing sent.
+        let mut segments = buf.chunks(segment_size);
+        self.try_send_datagram(segments.next().unwrap(), remote, flags)?;
+
+        let is_nonblocking = self.is_nonblocking() || flags.contains(SendRecvFlags::MSG_DONTWAIT);
+        for segment in segments {
+            let sent = match self.try_send_datagram(segment, remote, flags) {
+                // The send buffer is full. Flush the queued segments to the interface to
+                // make room, and wait for the interface if it is busy as well, rather than
+                // sending a part of the super-datagram.
+                Err(err) if err.error() == Errno::EAGAIN => {
+                    poll_ifaces();
+                    if is_nonblocking {
+                        self.try_send_datagram(segment, remote, flags)
+                    } else {
+                        self.wait_events(IoEvents::OUT, || {
+                            self.try_send_datagram(segment, remote, flags)
+                        })
+                    }
+                }
+                sent => sent,
+            };
+            // The queued segments cannot be taken back, so they are lost like the
+            // datagrams dropped by the interface, and the error is reported.
+            sent?;
+        }
+
+        Ok(buf.len())
     }
 
-    fn try_sendto(&self, buf: &[u8], remote: &IpEndpoint, flags: SendRecvFlags) -> Result<usize> {
+    fn try_send_datagram(
+        &self,
+        buf: &[u8],
+        remote: &IpEndpoint,
+        flags: SendRecvFlags,
+    ) -> Result<usize> {
         let inner = self.inner.read();
 
         let Inner::Bound(bound_datagram) = inner.as_ref() else {
             return_errno_with_message!(Errno::EAGAIN, "the socket is not bound")
         };
 
-        let sent_bytes = bound_datagram
-            .try_sendto(buf, remote, flags)
+        bound_datagram
+            .try_sendto(buf, remote, flags, &self.pollee)
             .map(|sent_bytes| {
-                bound_datagram.update_io_events(&self.pollee);
+                bound_datagram.update_write_events(&self.pollee);
                 sent_bytes
-            });
-
-        drop(inner);
-        poll_ifaces();
+            })
+    }
 
-        sent_bytes
+    fn resolve_remote_endpoint(&self, remote: Option<SocketAddr>) -> Result<IpEndpoint> {
+        match remote {
+            Some(remote_addr) => {
+                let endpoint = remote_addr.try_into()?;
+                self.try_bind_empheral(&endpoint)?;
+                Ok(endpoint)
+            }
+            None => self.remote_endpoint().ok_or_else(|| {
+                Error::with_message(
+                    Errno::EDESTADDRREQ,
+                    "the destination address is not specified",
+                )
+            }),
+        }
     }
 
     // TODO: Support timeout
@@ -206,6 +310,10 @@ impl FileLike for DatagramSocket {
         self.sendto(buf, None, flags)
     }
 
+    fn ioctl(&self, cmd: IoctlCmd, arg: usize) -> Result<i32> {
+        ioctl_iface(cmd, arg)
+    }
+
     fn poll(&self, mask: IoEvents, poller: Option<&Poller>) -> IoEvents {
         self.pollee.poll(mask, poller)
     }
@@ -312,23 +420,147 @@ impl Socket for DatagramSocket {
     ) -> Result<usize> {
         debug_assert!(flags.is_all_supported());
 
-        let remote_endpoint = match remote {
-            Some(remote_addr) => {
-                let endpoint = remote_addr.try_into()?;
-                self.try_bind_empheral(&endpoint)?;
-                endpoint
-            }
-            None => self.remote_endpoint().ok_or_else(|| {
-                Error::with_message(
-                    Errno::EDESTADDRREQ,
-                    "the destination address is not specified",
-                )
-            })?,
-        };
+        let remote_endpoint = self.resolve_remote_endpoint(remote)?;
 
         // TODO: Block if the send buffer is full
         self.try_sendto(buf, &remote_endpoint, flags)
     }
+
+    fn get_option(&self, option: &mut dyn SocketOption) -> Result<()> {
+        let options = self.options.read();
+
+        match_sock_option_mut!(option, {
+            // Socket options:
+            socket_incoming_cpu: IncomingCpu => {
+                // Like Linux, -1 means that no packet has been received yet.
+                let incoming_cpu = match self.inner.read().as_ref() {
+                    Inner::Bound(bound_datagram) => bound_datagram.incoming_cpu(),
+                    Inner::Unbound(_) => None,
+                };
+                socket_incoming_cpu.set(incoming_cpu.map_or(-1, |cpu| cpu as i32));
+            },
+            // UDP options:
+            udp_segment: UdpSegment => {
+                let segment_size = options.segment_size();
+                udp_segment.set(segment_size);
+            },
+            udp_gro: UdpGro => {
+                let gro = options.gro();
+                udp_gro.set(gro);
+            },
+            _ => return_errno_with_message!(Errno::ENOPROTOOPT, "the socket option to get is unknown")
+        });
+
+        Ok(())
+    }
+
+    fn set_option(&self, option: &dyn SocketOption) -> Result<()> {
+        let mut options = self.options.write();
+
+        match_sock_option_ref!(option, {
+            // UDP options:
+            udp_segment: UdpSegment => {
+                let segment_size = udp_segment.get().unwrap();
+                if *segment_size > MAX_UDP_PAYLOAD {
+                    return_errno_with_message!(Errno::EINVAL, "the segment size is too large");
+                }
+                options.set_segment_size(*segment_size);
+            },
+            udp_gro: UdpGro => {
+                let gro = udp_gro.get().unwrap();
+                options.set_gro(*gro);
+            },
+            _ => return_errno_with_message!(Errno::ENOPROTOOPT, "the socket option to be set is unknown")
+        });
+
+        Ok(())
+    }
+
+    fn sendmmsg(
+        &self,
+        msgs: &mut dyn Iterator<Item = Result<(Vec<u8>, Option<SocketAddr>)>>,
+        flags: SendRecvFlags,
+    ) -> Result<Vec<usize>> {
+        debug_assert!(flags.is_all_supported());
+
+        let mut sent_lens = Vec::new();
+        let mut result = Ok(());
+        for msg in msgs {
+            let sent = msg.and_then(|(buf, remote)| {
+                let remote_endpoint = self.resolve_remote_endpoint(remote)?;
+                match self.try_sendto_without_polling(&buf, &remote_endpoint, flags) {
+                    // The send buffer is full. Flush the queued datagrams to the
+                    // interface and try again.
+                    Err(err) if err.error() == Errno::EAGAIN && !sent_lens.is_empty() => {
+                        poll_ifaces();
+                        self.try_sendto_without_polling(&buf, &remote_endpoint, flags)
+                    }
+                    sent => sent,
+                }
+            });
+            match sent {
+                Ok(sent_len) => sent_lens.push(sent_len),
+                Err(err) => {
+                    result = Err(err);
+                    break;
+                }
+            }
+        }
+
+        // Poll the interfaces only once for the whole batch
+        poll_ifaces();
+
+        match result {
+            Err(err) if sent_lens.is_empty() => Err(err),
+            _ => Ok(sent_lens),
+        }
+    }
+
+    fn recvmmsg(
+        &self,
+        bufs: &mut [Vec<u8>],
+        flags: SendRecvFlags,
+        deadline: Option<Duration>,
+    ) -> Result<Vec<(usize, SocketAddr)>> {
+        let wait_for_one = flags.contains(SendRecvFlags::MSG_WAITFORONE);
+        let flags = flags - SendRecvFlags::MSG_WAITFORONE;
+        debug_assert!(flags.is_all_supported());
+
+        let mut received = Vec::with_capacity(bufs.len());
+        let mut result = Ok(());
+        for buf in bufs.iter_mut() {
+            // The messages that have already arrived are received without polling the
+            // interfaces. Only if there are none is the socket polled, or waited on.
+            let recv = match self.try_recvfrom_without_polling(buf, flags) {
+                Err(err) if err.error() == Errno::EAGAIN => {
+                    if received.is_empty() || !(wait_for_one || self.is_nonblocking()) {
+                        self.recvfrom(buf, flags)
+                    } else {
+                        Err(err)
+                    }
+                }
+                recv => recv,
+            };
+            match recv {
+                Ok(recv) => received.push(recv),
+                Err(err) => {
+                    result = Err(err);
+                    break;
+                }
+            }
+            if is_deadline_reached(deadline) {
+                break;
+            }
+        }
+
+        // Poll the interfaces only once for the whole batch
+        poll_ifaces();
+
+        match result {
+            Err(err) if received.is_empty() => Err(err),
+            _ => Ok(received),
+        }
+    }
 }
 
 impl Observer<()> for DatagramSocket {
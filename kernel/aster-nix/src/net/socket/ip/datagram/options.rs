@@ -0,0 +1,8 @@
+// SPDX-License-Identifier: MPL-2.0
+
+use crate::impl_socket_options;
+
+impl_socket_options!(
+    pub struct UdpSegment(u32);
+    pub struct UdpGro(bool);
+);
@@ -7,10 +7,9 @@ use crate::{
     events::{IoEvents, Observer},
     net::{
         iface::{AnyUnboundSocket, IpEndpoint, RawUdpSocket},
-        socket::ip::common::bind_socket,
+        socket::{ip::common::bind_socket, util::pollee::SocketPollee},
     },
     prelude::*,
-    process::signal::Pollee,
 };
 
 pub struct UnboundDatagram {
@@ -38,7 +37,7 @@ impl UnboundDatagram {
         Ok(BoundDatagram::new(bound_socket))
     }
 
-    pub(super) fn init_pollee(&self, pollee: &Pollee) {
+    pub(super) fn init_pollee(&self, pollee: &SocketPollee) {
         pollee.reset_events();
         pollee.add_events(IoEvents::OUT);
     }
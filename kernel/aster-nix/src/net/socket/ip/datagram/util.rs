@@ -0,0 +1,39 @@
+// SPDX-License-Identifier: MPL-2.0
+
+use crate::prelude::*;
+
+#[derive(Debug, Clone, Copy, CopyGetters, Setters)]
+#[get_copy = "pub"]
+#[set = "pub"]
+pub struct UdpOptionSet {
+    /// The segment size for UDP segmentation offload. Zero means disabled.
+    segment_size: u32,
+    /// Whether the socket accepts coalesced datagrams.
+    ///
+    /// Datagrams are never coalesced on receipt for now, which is always allowed
+    /// since applications must handle datagrams that are not coalesced anyway.
+    gro: bool,
+}
+
+/// The maximum number of segments a single UDP super-datagram can be split into.
+///
+/// The value is the same as `UDP_MAX_SEGMENTS` in Linux.
+pub const UDP_MAX_SEGMENTS: usize = 1 << 6;
+
+/// The maximum payload length of a single UDP datagram over IPv4.
+pub const MAX_UDP_PAYLOAD: u32 = 65507;
+
+impl UdpOptionSet {
+    pub fn new() -> Self {
+        Self {
+            segment_size: 0,
+            gro: false,
+        }
+    }
+}
+
+impl Default for UdpOptionSet {
+    fn default() -> Self {
+        Self::new()
+    }
+}
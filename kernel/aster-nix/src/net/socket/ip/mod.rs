@@ -3,7 +3,7 @@
 use crate::net::iface::{IpAddress, IpEndpoint, Ipv4Address};
 
 mod common;
-mod datagram;
+pub mod datagram;
 pub mod stream;
 
 pub use datagram::DatagramSocket;
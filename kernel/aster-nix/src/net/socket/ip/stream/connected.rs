@@ -6,14 +6,16 @@ use alloc::sync::Weak;
 
 use smoltcp::socket::tcp::{RecvError, SendError};
 
+use super::info::{TcpInfo, TcpState, TcpStats};
 use crate::{
     events::{IoEvents, Observer},
     net::{
-        iface::{AnyBoundSocket, IpEndpoint, RawTcpSocket},
-        socket::util::{send_recv_flags::SendRecvFlags, shutdown_cmd::SockShutdownCmd},
+        iface::{AnyBoundSocket, IpEndpoint, RawTcpSocket, TrackedTcpFlow},
+        socket::util::{
+            pollee::SocketPollee, send_recv_flags::SendRecvFlags, shutdown_cmd::SockShutdownCmd,
+        },
     },
     prelude::*,
-    process::signal::Pollee,
 };
 
 pub struct ConnectedStream {
@@ -30,6 +32,8 @@ pub struct ConnectedStream {
     /// connection is established asynchronously will succeed and any subsequent `connect()` will
     /// fail.
     is_new_connection: bool,
+    stats: SpinLock<TcpStats>,
+    tcp_flow: TrackedTcpFlow,
 }
 
 impl ConnectedStream {
@@ -37,11 +41,14 @@ impl ConnectedStream {
         bound_socket: Arc<AnyBoundSocket>,
         remote_endpoint: IpEndpoint,
         is_new_connection: bool,
+        tcp_flow: TrackedTcpFlow,
     ) -> Self {
         Self {
             bound_socket,
             remote_endpoint,
             is_new_connection,
+            stats: SpinLock::new(TcpStats::new()),
+            tcp_flow,
         }
     }
 
@@ -59,11 +66,12 @@ impl ConnectedStream {
             .raw_with(|socket: &mut RawTcpSocket| socket.recv_slice(buf));
         match result {
             Ok(0) => return_errno_with_message!(Errno::EAGAIN, "the receive buffer is empty"),
-            Ok(recv_bytes) => Ok(recv_bytes),
-            Err(RecvError::Finished) => Ok(0),
-            Err(RecvError::InvalidState) => {
-                return_errno_with_message!(Errno::ECONNRESET, "the connection is reset")
+            Ok(recv_bytes) => {
+                self.stats.lock_irq_disabled().on_data_received(recv_bytes);
+                Ok(recv_bytes)
             }
+            Err(RecvError::Finished) => Ok(0),
+            Err(RecvError::InvalidState) => Err(self.reset_error()),
         }
     }
 
@@ -73,16 +81,30 @@ impl ConnectedStream {
             .raw_with(|socket: &mut RawTcpSocket| socket.send_slice(buf));
         match result {
             Ok(0) => return_errno_with_message!(Errno::EAGAIN, "the send buffer is full"),
-            Ok(sent_bytes) => Ok(sent_bytes),
+            Ok(sent_bytes) => {
+                self.stats.lock_irq_disabled().on_data_sent(sent_bytes);
+                Ok(sent_bytes)
+            }
             Err(SendError::InvalidState) => {
                 // FIXME: `EPIPE` is another possibility, which means that the socket is shut down
                 // for writing. In that case, we should also trigger a `SIGPIPE` if `MSG_NOSIGNAL`
                 // is not specified.
-                return_errno_with_message!(Errno::ECONNRESET, "the connection is reset");
+                Err(self.reset_error())
             }
         }
     }
 
+    /// Returns the error of a connection that is closed unexpectedly, which is aborted by
+    /// the iface if its local address is removed, or reset by the peer otherwise.
+    fn reset_error(&self) -> Error {
+        self.bound_socket
+            .iface_error()
+            .unwrap_or(Error::with_message(
+                Errno::ECONNRESET,
+                "the connection is reset",
+            ))
+    }
+
     pub fn local_endpoint(&self) -> IpEndpoint {
         self.bound_socket.local_endpoint().unwrap()
     }
@@ -91,6 +113,34 @@ impl ConnectedStream {
         self.remote_endpoint
     }
 
+    /// Returns the CPU that processes the packets of the connection, if the iface steers
+    /// the received packets.
+    pub fn incoming_cpu(&self) -> Option<u32> {
+        self.bound_socket.incoming_cpu(self.remote_endpoint)
+    }
+
+    pub fn tcp_info(&self, maxseg: u32) -> TcpInfo {
+        let mut info = self.bound_socket.raw_with(|socket: &mut RawTcpSocket| {
+            let mut info = TcpInfo::new(TcpState::from(socket.state()));
+
+            info.snd_mss = maxseg;
+            info.rcv_mss = maxseg;
+            info.advmss = maxseg;
+            info.ato = socket
+                .ack_delay()
+                .map_or(0, |ack_delay| ack_delay.total_micros() as u32);
+            info.rcv_space = socket.recv_capacity() as u32;
+            // FIXME: smoltcp does not implement congestion control. It sends as much as the
+            // window of the peer allows, so there is no `snd_cwnd` to report.
+            info
+        });
+
+        self.stats
+            .lock_irq_disabled()
+            .fill_info(&self.tcp_flow.stats(), &mut info);
+        info
+    }
+
     pub fn check_new(&mut self) -> Result<()> {
         if !self.is_new_connection {
             return_errno_with_message!(Errno::EISCONN, "the socket is already connected");
@@ -100,18 +150,43 @@ impl ConnectedStream {
         Ok(())
     }
 
-    pub(super) fn init_pollee(&self, pollee: &Pollee) {
+    pub(super) fn init_pollee(&self, pollee: &SocketPollee) {
         pollee.reset_events();
         self.update_io_events(pollee);
     }
 
-    pub(super) fn update_io_events(&self, pollee: &Pollee) {
+    pub(super) fn update_io_events(&self, pollee: &SocketPollee) {
+        self.update_read_events(pollee);
+        self.update_write_events(pollee);
+    }
+
+    pub(super) fn update_read_events(&self, pollee: &SocketPollee) {
+        if self.bound_socket.iface_error().is_some() {
+            // Wake up the readers, which then get the error.
+            pollee.add_events(IoEvents::IN | IoEvents::HUP);
+            return;
+        }
+
         self.bound_socket.raw_with(|socket: &mut RawTcpSocket| {
             if socket.can_recv() {
                 pollee.add_events(IoEvents::IN);
             } else {
                 pollee.del_events(IoEvents::IN);
             }
+        });
+    }
+
+    pub(super) fn update_write_events(&self, pollee: &SocketPollee) {
+        if self.bound_socket.iface_error().is_some() {
+            // Wake up the writers, which then get the error.
+            pollee.add_events(IoEvents::OUT);
+            return;
+        }
+
+        self.bound_socket.raw_with(|socket: &mut RawTcpSocket| {
+            self.stats
+                .lock_irq_disabled()
+                .on_send_queue_updated(socket.send_queue());
 
             if socket.can_send() {
                 pollee.add_events(IoEvents::OUT);
@@ -1,22 +1,36 @@
 // SPDX-License-Identifier: MPL-2.0
 
+use core::time::Duration;
+
+use smoltcp::time::Duration as SmolDuration;
+
 use super::{connected::ConnectedStream, init::InitStream};
 use crate::{
-    net::iface::{AnyBoundSocket, IpEndpoint, RawTcpSocket},
+    net::{
+        iface::{AnyBoundSocket, IpEndpoint, RawTcpSocket, TrackedTcpFlow},
+        socket::util::pollee::SocketPollee,
+    },
     prelude::*,
-    process::signal::Pollee,
+    time::clocks::JiffiesClock,
 };
 
 pub struct ConnectingStream {
     bound_socket: Arc<AnyBoundSocket>,
     remote_endpoint: IpEndpoint,
     conn_result: RwLock<Option<ConnResult>>,
+    /// The time when the connection attempt started.
+    started_at: Duration,
+    /// How long the connection attempt lasts before it times out.
+    timeout: Duration,
+    /// The connection, which is tracked from the SYN to time the handshake.
+    tcp_flow: TrackedTcpFlow,
 }
 
 #[derive(Clone, Copy)]
 enum ConnResult {
     Connected,
     Refused,
+    TimedOut,
 }
 
 pub enum NonConnectedStream {
@@ -28,27 +42,54 @@ impl ConnectingStream {
     pub fn new(
         bound_socket: Arc<AnyBoundSocket>,
         remote_endpoint: IpEndpoint,
+        timeout: Duration,
     ) -> core::result::Result<Self, (Error, Arc<AnyBoundSocket>)> {
         if let Err(err) = bound_socket.do_connect(remote_endpoint) {
             return Err((err, bound_socket));
         }
+        // smoltcp keeps retransmitting the SYN until the timeout expires, and then aborts the
+        // connection attempt. The interface will be polled at that time, so we will be notified.
+        bound_socket.raw_with(|socket: &mut RawTcpSocket| {
+            socket.set_timeout(Some(SmolDuration::from_millis(timeout.as_millis() as u64)));
+        });
+        let tcp_flow = bound_socket.track_tcp_flow(remote_endpoint);
         Ok(Self {
             bound_socket,
             remote_endpoint,
             conn_result: RwLock::new(None),
+            started_at: JiffiesClock::elapsed(),
+            timeout,
+            tcp_flow,
         })
     }
 
     pub fn into_result(self) -> core::result::Result<ConnectedStream, (Error, NonConnectedStream)> {
         let conn_result = *self.conn_result.read();
         match conn_result {
-            Some(ConnResult::Connected) => Ok(ConnectedStream::new(
-                self.bound_socket,
-                self.remote_endpoint,
-                true,
-            )),
+            Some(ConnResult::Connected) => {
+                // The timeout only applies to the connection attempt. Otherwise, an idle
+                // connection will be aborted.
+                self.bound_socket
+                    .raw_with(|socket: &mut RawTcpSocket| socket.set_timeout(None));
+                Ok(ConnectedStream::new(
+                    self.bound_socket,
+                    self.remote_endpoint,
+                    true,
+                    self.tcp_flow,
+                ))
+            }
             Some(ConnResult::Refused) => Err((
-                Error::with_message(Errno::ECONNREFUSED, "the connection is refused"),
+                // The connection attempt is aborted if the local address is removed.
+                self.bound_socket
+                    .iface_error()
+                    .unwrap_or(Error::with_message(
+                        Errno::ECONNREFUSED,
+                        "the connection is refused",
+                    )),
+                NonConnectedStream::Init(InitStream::new_bound(self.bound_socket)),
+            )),
+            Some(ConnResult::TimedOut) => Err((
+                Error::with_message(Errno::ETIMEDOUT, "the connection attempt timed out"),
                 NonConnectedStream::Init(InitStream::new_bound(self.bound_socket)),
             )),
             None => Err((
@@ -66,7 +107,7 @@ impl ConnectingStream {
         self.remote_endpoint
     }
 
-    pub(super) fn init_pollee(&self, pollee: &Pollee) {
+    pub(super) fn init_pollee(&self, pollee: &SocketPollee) {
         pollee.reset_events();
     }
 
@@ -96,6 +137,11 @@ impl ConnectingStream {
             if socket.is_open() {
                 return false;
             }
+            // Timed out
+            if JiffiesClock::elapsed() - self.started_at >= self.timeout {
+                *result = Some(ConnResult::TimedOut);
+                return true;
+            }
             // Refused
             *result = Some(ConnResult::Refused);
             true
@@ -0,0 +1,227 @@
+// SPDX-License-Identifier: MPL-2.0
+
+use core::time::Duration;
+
+use smoltcp::socket::tcp::State as RawTcpState;
+
+use crate::{net::iface::TcpFlowStats, prelude::*, time::clocks::JiffiesClock};
+
+/// `struct tcp_info` in Linux.
+///
+/// The raw definition is from https://elixir.bootlin.com/linux/v6.0.9/source/include/uapi/linux/tcp.h#L214
+#[repr(C)]
+#[derive(Debug, Default, Clone, Copy, Pod)]
+pub struct TcpInfo {
+    pub state: u8,
+    pub ca_state: u8,
+    pub retransmits: u8,
+    pub probes: u8,
+    pub backoff: u8,
+    pub options: u8,
+    /// `tcpi_snd_wscale:4` and `tcpi_rcv_wscale:4`
+    pub wscale: u8,
+    /// `tcpi_delivery_rate_app_limited:1` and `tcpi_fastopen_client_fail:2`
+    pub app_limited: u8,
+
+    pub rto: u32,
+    pub ato: u32,
+    pub snd_mss: u32,
+    pub rcv_mss: u32,
+
+    pub unacked: u32,
+    pub sacked: u32,
+    pub lost: u32,
+    pub retrans: u32,
+    pub fackets: u32,
+
+    // Times in milliseconds
+    pub last_data_sent: u32,
+    pub last_ack_sent: u32,
+    pub last_data_recv: u32,
+    pub last_ack_recv: u32,
+
+    // Metrics
+    pub pmtu: u32,
+    pub rcv_ssthresh: u32,
+    pub rtt: u32,
+    pub rttvar: u32,
+    pub snd_ssthresh: u32,
+    pub snd_cwnd: u32,
+    pub advmss: u32,
+    pub reordering: u32,
+
+    pub rcv_rtt: u32,
+    pub rcv_space: u32,
+
+    pub total_retrans: u32,
+
+    pub pacing_rate: u64,
+    pub max_pacing_rate: u64,
+    pub bytes_acked: u64,
+    pub bytes_received: u64,
+    pub segs_out: u32,
+    pub segs_in: u32,
+
+    pub notsent_bytes: u32,
+    pub min_rtt: u32,
+    pub data_segs_in: u32,
+    pub data_segs_out: u32,
+
+    pub delivery_rate: u64,
+
+    pub busy_time: u64,
+    pub rwnd_limited: u64,
+    pub sndbuf_limited: u64,
+
+    pub delivered: u32,
+    pub delivered_ce: u32,
+
+    pub bytes_sent: u64,
+    pub bytes_retrans: u64,
+    pub dsack_dups: u32,
+    pub reord_seen: u32,
+
+    pub rcv_ooopack: u32,
+
+    pub snd_wnd: u32,
+}
+
+/// TCP states as reported in `tcpi_state`.
+///
+/// The raw definition is from https://elixir.bootlin.com/linux/v6.0.9/source/include/net/tcp_states.h#L12
+#[repr(u8)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TcpState {
+    Established = 1,
+    SynSent = 2,
+    SynRecv = 3,
+    FinWait1 = 4,
+    FinWait2 = 5,
+    TimeWait = 6,
+    Close = 7,
+    CloseWait = 8,
+    LastAck = 9,
+    Listen = 10,
+    Closing = 11,
+}
+
+impl From<RawTcpState> for TcpState {
+    fn from(state: RawTcpState) -> Self {
+        match state {
+            RawTcpState::Closed => Self::Close,
+            RawTcpState::Listen => Self::Listen,
+            RawTcpState::SynSent => Self::SynSent,
+            RawTcpState::SynReceived => Self::SynRecv,
+            RawTcpState::Established => Self::Established,
+            RawTcpState::FinWait1 => Self::FinWait1,
+            RawTcpState::FinWait2 => Self::FinWait2,
+            RawTcpState::CloseWait => Self::CloseWait,
+            RawTcpState::Closing => Self::Closing,
+            RawTcpState::LastAck => Self::LastAck,
+            RawTcpState::TimeWait => Self::TimeWait,
+        }
+    }
+}
+
+/// The initial retransmission timeout before any RTT is measured (RFC 6298).
+const INITIAL_RTO: Duration = Duration::from_secs(1);
+/// The minimum retransmission timeout (`TCP_RTO_MIN` in Linux).
+const MIN_RTO: Duration = Duration::from_millis(200);
+/// The slow start threshold reported when there is none (`TCP_INFINITE_SSTHRESH` in Linux).
+const INFINITE_SSTHRESH: u32 = 0x7fff_ffff;
+
+impl TcpInfo {
+    /// Creates the information of a socket that has no connection (yet).
+    pub fn new(state: TcpState) -> Self {
+        Self {
+            state: state as u8,
+            rto: INITIAL_RTO.as_micros() as u32,
+            snd_ssthresh: INFINITE_SSTHRESH,
+            ..Default::default()
+        }
+    }
+}
+
+/// Per-connection statistics that are collected by the socket.
+///
+/// The statistics of the segments, including the RTT and the retransmissions, are collected
+/// by the iface instead. See [`TcpFlowStats`].
+#[derive(Debug, Default)]
+pub(super) struct TcpStats {
+    /// The number of bytes handed to smoltcp.
+    bytes_sent: u64,
+    /// The number of bytes acknowledged by the peer.
+    bytes_acked: u64,
+    /// The number of bytes received by the user.
+    bytes_received: u64,
+    last_data_sent: Option<Duration>,
+    last_data_recv: Option<Duration>,
+    last_ack_recv: Option<Duration>,
+}
+
+impl TcpStats {
+    pub(super) fn new() -> Self {
+        Self::default()
+    }
+
+    pub(super) fn on_data_sent(&mut self, len: usize) {
+        self.bytes_sent += len as u64;
+        self.last_data_sent = Some(now());
+    }
+
+    pub(super) fn on_data_received(&mut self, len: usize) {
+        self.bytes_received += len as u64;
+        self.last_data_recv = Some(now());
+    }
+
+    /// Updates the statistics with the number of bytes remaining in the send queue.
+    pub(super) fn on_send_queue_updated(&mut self, send_queue: usize) {
+        let bytes_acked = self.bytes_sent.saturating_sub(send_queue as u64);
+        if bytes_acked <= self.bytes_acked {
+            return;
+        }
+
+        self.bytes_acked = bytes_acked;
+        self.last_ack_recv = Some(now());
+    }
+
+    /// Fills the fields of `info` that are tracked by the statistics, and by the
+    /// statistics of the segments collected by the iface.
+    pub(super) fn fill_info(&self, flow_stats: &TcpFlowStats, info: &mut TcpInfo) {
+        let now = now();
+        let millis_since = |time: Option<Duration>| {
+            time.map_or(0, |time| {
+                (now - time).as_millis().min(u32::MAX as u128) as u32
+            })
+        };
+
+        if let Some((srtt, rttvar)) = flow_stats.rtt {
+            info.rtt = srtt.as_micros() as u32;
+            info.rttvar = rttvar.as_micros() as u32;
+            info.rto = (srtt + rttvar * 4).max(MIN_RTO).as_micros() as u32;
+        }
+        if let Some(min_rtt) = flow_stats.min_rtt {
+            info.min_rtt = min_rtt.as_micros() as u32;
+        }
+        info.retransmits = flow_stats.retransmits.min(u8::MAX as u32) as u8;
+        info.retrans = flow_stats.retransmits;
+        info.total_retrans = flow_stats.total_retrans;
+        info.bytes_retrans = flow_stats.bytes_retrans;
+        info.segs_out = flow_stats.segs_out;
+        info.data_segs_out = flow_stats.data_segs_out;
+        info.segs_in = flow_stats.segs_in;
+        info.data_segs_in = flow_stats.data_segs_in;
+
+        info.last_data_sent = millis_since(self.last_data_sent);
+        info.last_data_recv = millis_since(self.last_data_recv);
+        info.last_ack_recv = millis_since(self.last_ack_recv);
+
+        info.bytes_sent = self.bytes_sent;
+        info.bytes_acked = self.bytes_acked;
+        info.bytes_received = self.bytes_received;
+    }
+}
+
+fn now() -> Duration {
+    JiffiesClock::elapsed()
+}
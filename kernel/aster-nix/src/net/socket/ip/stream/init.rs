@@ -1,16 +1,19 @@
 // SPDX-License-Identifier: MPL-2.0
 
 use alloc::sync::Weak;
+use core::time::Duration;
 
 use super::{connecting::ConnectingStream, listen::ListenStream};
 use crate::{
     events::{IoEvents, Observer},
     net::{
         iface::{AnyBoundSocket, AnyUnboundSocket, IpEndpoint},
-        socket::ip::common::{bind_socket, get_ephemeral_endpoint},
+        socket::{
+            ip::common::{bind_socket, get_ephemeral_endpoint},
+            util::pollee::SocketPollee,
+        },
     },
     prelude::*,
-    process::signal::Pollee,
 };
 
 pub enum InitStream {
@@ -58,13 +61,14 @@ impl InitStream {
     pub fn connect(
         self,
         remote_endpoint: &IpEndpoint,
+        timeout: Duration,
     ) -> core::result::Result<ConnectingStream, (Error, Self)> {
         let bound_socket = match self {
             InitStream::Bound(bound_socket) => bound_socket,
             InitStream::Unbound(_) => self.bind_to_ephemeral_endpoint(remote_endpoint)?,
         };
 
-        ConnectingStream::new(bound_socket, *remote_endpoint)
+        ConnectingStream::new(bound_socket, *remote_endpoint, timeout)
             .map_err(|(err, bound_socket)| (err, InitStream::Bound(bound_socket)))
     }
 
@@ -90,7 +94,7 @@ impl InitStream {
         }
     }
 
-    pub(super) fn init_pollee(&self, pollee: &Pollee) {
+    pub(super) fn init_pollee(&self, pollee: &SocketPollee) {
         pollee.reset_events();
         pollee.add_events(IoEvents::OUT);
     }
@@ -7,9 +7,11 @@ use smoltcp::socket::tcp::ListenError;
 use super::connected::ConnectedStream;
 use crate::{
     events::IoEvents,
-    net::iface::{AnyBoundSocket, AnyUnboundSocket, BindPortConfig, IpEndpoint, RawTcpSocket},
+    net::{
+        iface::{AnyBoundSocket, AnyUnboundSocket, BindPortConfig, IpEndpoint, RawTcpSocket},
+        socket::util::pollee::SocketPollee,
+    },
     prelude::*,
-    process::signal::Pollee,
 };
 
 pub struct ListenStream {
@@ -72,10 +74,13 @@ impl ListenStream {
         }
 
         let remote_endpoint = active_backlog_socket.remote_endpoint().unwrap();
+        let bound_socket = active_backlog_socket.into_bound_socket();
+        let tcp_flow = bound_socket.track_tcp_flow(remote_endpoint);
         Ok(ConnectedStream::new(
-            active_backlog_socket.into_bound_socket(),
+            bound_socket,
             remote_endpoint,
             false,
+            tcp_flow,
         ))
     }
 
@@ -83,12 +88,12 @@ impl ListenStream {
         self.bound_socket.local_endpoint().unwrap()
     }
 
-    pub(super) fn init_pollee(&self, pollee: &Pollee) {
+    pub(super) fn init_pollee(&self, pollee: &SocketPollee) {
         pollee.reset_events();
         self.update_io_events(pollee);
     }
 
-    pub(super) fn update_io_events(&self, pollee: &Pollee) {
+    pub(super) fn update_io_events(&self, pollee: &SocketPollee) {
         // The lock should be held to avoid data races
         let backlog_sockets = self.backlog_sockets.read();
 
@@ -8,24 +8,29 @@ use connected::ConnectedStream;
 use connecting::ConnectingStream;
 use init::InitStream;
 use listen::ListenStream;
-use options::{Congestion, MaxSegment, NoDelay, WindowClamp};
+use options::{Congestion, Info, MaxSegment, NoDelay, SynCnt, WindowClamp};
 use smoltcp::wire::IpEndpoint;
 use takeable::Takeable;
-use util::{TcpOptionSet, DEFAULT_MAXSEG};
+use util::{TcpOptionSet, DEFAULT_MAXSEG, MAX_SYN_CNT};
 
-use super::UNSPECIFIED_LOCAL_ENDPOINT;
+use super::{common::ioctl_iface, UNSPECIFIED_LOCAL_ENDPOINT};
 use crate::{
     events::{IoEvents, Observer},
-    fs::{file_handle::FileLike, utils::StatusFlags},
+    fs::{
+        file_handle::FileLike,
+        utils::{IoctlCmd, StatusFlags},
+    },
     match_sock_option_mut, match_sock_option_ref,
     net::{
         poll_ifaces,
         socket::{
             options::{
-                Error as SocketError, Linger, RecvBuf, ReuseAddr, ReusePort, SendBuf, SocketOption,
+                Error as SocketError, IncomingCpu, Linger, RecvBuf, ReuseAddr, ReusePort, SendBuf,
+                SocketOption,
             },
             util::{
                 options::{SocketOptionSet, MIN_RECVBUF, MIN_SENDBUF},
+                pollee::SocketPollee,
                 send_recv_flags::SendRecvFlags,
                 shutdown_cmd::SockShutdownCmd,
                 socket_addr::SocketAddr,
@@ -34,24 +39,28 @@ use crate::{
         },
     },
     prelude::*,
-    process::signal::{Pollee, Poller},
+    process::signal::Poller,
 };
 
 mod connected;
 mod connecting;
+mod info;
 mod init;
 mod listen;
 pub mod options;
 mod util;
 
 use self::connecting::NonConnectedStream;
-pub use self::util::CongestionControl;
+pub use self::{
+    info::{TcpInfo, TcpState},
+    util::CongestionControl,
+};
 
 pub struct StreamSocket {
     options: RwLock<OptionSet>,
     state: RwLock<Takeable<State>>,
     is_nonblocking: AtomicBool,
-    pollee: Pollee,
+    pollee: SocketPollee,
 }
 
 enum State {
@@ -83,7 +92,7 @@ impl StreamSocket {
     pub fn new(nonblocking: bool) -> Arc<Self> {
         Arc::new_cyclic(|me| {
             let init_stream = InitStream::new(me.clone() as _);
-            let pollee = Pollee::new(IoEvents::empty());
+            let pollee = SocketPollee::new(IoEvents::empty());
             init_stream.init_pollee(&pollee);
             Self {
                 options: RwLock::new(OptionSet::new()),
@@ -96,7 +105,7 @@ impl StreamSocket {
 
     fn new_connected(connected_stream: ConnectedStream) -> Arc<Self> {
         Arc::new_cyclic(move |me| {
-            let pollee = Pollee::new(IoEvents::empty());
+            let pollee = SocketPollee::new(IoEvents::empty());
             connected_stream.set_observer(me.clone() as _);
             connected_stream.init_pollee(&pollee);
             Self {
@@ -120,6 +129,7 @@ impl StreamSocket {
     // `Some(_)` if blocking is not necessary or not allowed.
     fn start_connect(&self, remote_endpoint: &IpEndpoint) -> Option<Result<()>> {
         let is_nonblocking = self.is_nonblocking();
+        let timeout = self.options.read().tcp.connect_timeout();
         let mut state = self.state.write();
 
         let result_or_block = state.borrow_result(|mut owned_state| {
@@ -152,7 +162,7 @@ impl StreamSocket {
                 }
             };
 
-            let connecting_stream = match init_stream.connect(remote_endpoint) {
+            let connecting_stream = match init_stream.connect(remote_endpoint, timeout) {
                 Ok(connecting_stream) => connecting_stream,
                 Err((err, init_stream)) => {
                     return (State::Init(init_stream), Some(Err(err)));
@@ -223,6 +233,7 @@ impl StreamSocket {
             State::Init(_) | State::Listen(_) => {
                 let sock_errors = options.socket.sock_errors();
                 options.socket.set_sock_errors(None);
+                self.pollee.del_events(IoEvents::ERR);
                 sock_errors.map(Err).unwrap_or(Ok(()))
             }
         }
@@ -263,7 +274,7 @@ impl StreamSocket {
         };
 
         let received = connected_stream.try_recvfrom(buf, flags).map(|recv_bytes| {
-            connected_stream.update_io_events(&self.pollee);
+            connected_stream.update_read_events(&self.pollee);
 
             let remote_endpoint = connected_stream.remote_endpoint();
             (recv_bytes, remote_endpoint.into())
@@ -292,7 +303,7 @@ impl StreamSocket {
         };
 
         let sent_bytes = connected_stream.try_sendto(buf, flags).map(|sent_bytes| {
-            connected_stream.update_io_events(&self.pollee);
+            connected_stream.update_write_events(&self.pollee);
             sent_bytes
         });
 
@@ -356,6 +367,10 @@ impl FileLike for StreamSocket {
         self.sendto(buf, None, flags)
     }
 
+    fn ioctl(&self, cmd: IoctlCmd, arg: usize) -> Result<i32> {
+        ioctl_iface(cmd, arg)
+    }
+
     fn poll(&self, mask: IoEvents, poller: Option<&Poller>) -> IoEvents {
         self.pollee.poll(mask, poller)
     }
@@ -548,6 +563,7 @@ impl Socket for StreamSocket {
                 let sock_errors = options.socket.sock_errors();
                 socket_errors.set(sock_errors);
                 options.socket.set_sock_errors(None);
+                self.pollee.del_events(IoEvents::ERR);
 
                 return Ok(());
             },
@@ -574,6 +590,14 @@ impl Socket for StreamSocket {
                 let reuse_port = options.socket.reuse_port();
                 socket_reuse_port.set(reuse_port);
             },
+            socket_incoming_cpu: IncomingCpu => {
+                // Like Linux, -1 means that no packet has been received yet.
+                let incoming_cpu = match self.state.read().as_ref() {
+                    State::Connected(connected_stream) => connected_stream.incoming_cpu(),
+                    _ => None,
+                };
+                socket_incoming_cpu.set(incoming_cpu.map_or(-1, |cpu| cpu as i32));
+            },
             // TCP options:
             tcp_no_delay: NoDelay => {
                 let no_delay = options.tcp.no_delay();
@@ -598,6 +622,21 @@ impl Socket for StreamSocket {
                 let window_clamp = options.tcp.window_clamp();
                 tcp_window_clamp.set(window_clamp);
             },
+            tcp_info: Info => {
+                let info = match self.state.read().as_ref() {
+                    State::Init(_) => TcpInfo::new(TcpState::Close),
+                    State::Connecting(_) => TcpInfo::new(TcpState::SynSent),
+                    State::Listen(_) => TcpInfo::new(TcpState::Listen),
+                    State::Connected(connected_stream) => {
+                        connected_stream.tcp_info(options.tcp.maxseg())
+                    }
+                };
+                tcp_info.set(info);
+            },
+            tcp_syn_cnt: SynCnt => {
+                let syn_cnt = options.tcp.syn_cnt();
+                tcp_syn_cnt.set(syn_cnt as u32);
+            },
             _ => return_errno_with_message!(Errno::ENOPROTOOPT, "the socket option to get is unknown")
         });
 
@@ -667,6 +706,13 @@ impl Socket for StreamSocket {
                     options.tcp.set_window_clamp(*window_clamp);
                 }
             },
+            tcp_syn_cnt: SynCnt => {
+                let syn_cnt = tcp_syn_cnt.get().unwrap();
+                if *syn_cnt < 1 || *syn_cnt > MAX_SYN_CNT as u32 {
+                    return_errno_with_message!(Errno::EINVAL, "the number of SYN retransmits is out of bounds");
+                }
+                options.tcp.set_syn_cnt(*syn_cnt as u8);
+            },
             _ => return_errno_with_message!(Errno::ENOPROTOOPT, "the socket option to be set is unknown")
         });
 
@@ -683,6 +729,10 @@ impl Observer<()> for StreamSocket {
             let mut options = self.options.write();
 
             let result = self.finish_connect();
+            if result.is_err() {
+                // The pending error is reported by `EPOLLERR` until it is read via `SO_ERROR`.
+                self.pollee.add_events(IoEvents::ERR);
+            }
             options.socket.set_sock_errors(result.err());
         }
     }
@@ -1,6 +1,6 @@
 // SPDX-License-Identifier: MPL-2.0
 
-use super::CongestionControl;
+use super::{CongestionControl, TcpInfo};
 use crate::impl_socket_options;
 
 impl_socket_options!(
@@ -8,4 +8,6 @@ impl_socket_options!(
     pub struct Congestion(CongestionControl);
     pub struct MaxSegment(u32);
     pub struct WindowClamp(u32);
+    pub struct Info(TcpInfo);
+    pub struct SynCnt(u32);
 );
@@ -1,5 +1,7 @@
 // SPDX-License-Identifier: MPL-2.0
 
+use core::time::Duration;
+
 use crate::prelude::*;
 
 #[derive(Debug, Clone, Copy, CopyGetters, Setters)]
@@ -10,10 +12,17 @@ pub struct TcpOptionSet {
     congestion: CongestionControl,
     maxseg: u32,
     window_clamp: u32,
+    syn_cnt: u8,
 }
 
 pub const DEFAULT_MAXSEG: u32 = 536;
 pub const DEFAULT_WINDOW_CLAMP: u32 = 0x8000_0000;
+/// The default number of SYN retransmissions, which is the same as `tcp_syn_retries` in Linux.
+pub const DEFAULT_SYN_CNT: u8 = 6;
+/// The maximum number of SYN retransmissions that can be set by `TCP_SYNCNT`.
+pub const MAX_SYN_CNT: u8 = 127;
+/// The initial retransmission timeout of a SYN.
+const INIT_SYN_RTO: Duration = Duration::from_secs(1);
 
 impl TcpOptionSet {
     pub fn new() -> Self {
@@ -22,8 +31,21 @@ impl TcpOptionSet {
             congestion: CongestionControl::Reno,
             maxseg: DEFAULT_MAXSEG,
             window_clamp: DEFAULT_WINDOW_CLAMP,
+            syn_cnt: DEFAULT_SYN_CNT,
         }
     }
+
+    /// Returns how long a connection attempt lasts before it times out.
+    ///
+    /// The SYN is retransmitted `syn_cnt` times with the retransmission timeout doubled each
+    /// time, so the attempt fails after `(2^(syn_cnt + 1) - 1)` times the initial timeout. For
+    /// the default setting, this is 127 seconds, which matches Linux.
+    pub fn connect_timeout(&self) -> Duration {
+        let nr_rtos = 1u32
+            .checked_shl(self.syn_cnt as u32 + 1)
+            .map_or(u32::MAX, |nr_rtos| nr_rtos - 1);
+        INIT_SYN_RTO.saturating_mul(nr_rtos)
+    }
 }
 
 impl Default for TcpOptionSet {
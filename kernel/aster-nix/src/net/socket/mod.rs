@@ -2,12 +2,18 @@
 
 #![allow(unused_variables)]
 
+use core::time::Duration;
+
 use self::options::SocketOption;
 pub use self::util::{
     options::LingerOption, send_recv_flags::SendRecvFlags, shutdown_cmd::SockShutdownCmd,
     socket_addr::SocketAddr,
 };
-use crate::{fs::file_handle::FileLike, prelude::*};
+use crate::{
+    fs::file_handle::FileLike,
+    prelude::*,
+    time::{clocks::MonotonicClock, Clock},
+};
 
 pub mod ip;
 pub mod options;
@@ -77,4 +83,68 @@ pub trait Socket: FileLike + Send + Sync {
     ) -> Result<usize> {
         return_errno_with_message!(Errno::EOPNOTSUPP, "sendto() is not supported");
     }
+
+    /// Send multiple messages on a socket.
+    ///
+    /// The messages are taken from `msgs` one at a time, so that a message is not
+    /// gathered until the previous ones have been sent. The batch ends at the first
+    /// message that cannot be taken or sent.
+    ///
+    /// On success, the number of bytes sent for each of the leading messages that
+    /// have been sent is returned. An error is only reported if no message can be sent.
+    fn sendmmsg(
+        &self,
+        msgs: &mut dyn Iterator<Item = Result<(Vec<u8>, Option<SocketAddr>)>>,
+        flags: SendRecvFlags,
+    ) -> Result<Vec<usize>> {
+        let mut sent_lens = Vec::new();
+        for msg in msgs {
+            match msg.and_then(|(buf, remote)| self.sendto(&buf, remote, flags)) {
+                Ok(sent_len) => sent_lens.push(sent_len),
+                Err(err) if sent_lens.is_empty() => return Err(err),
+                Err(_) => break,
+            }
+        }
+        Ok(sent_lens)
+    }
+
+    /// Receive multiple messages from a socket.
+    ///
+    /// Each buffer in `bufs` receives at most one message. The method blocks for each
+    /// message as [`Socket::recvfrom`] does. With `MSG_WAITFORONE`, it only blocks for
+    /// the first message, and then receives the messages that are immediately available.
+    ///
+    /// Like Linux, the `deadline` (in terms of the monotonic clock) is only checked after
+    /// each message is received, so it does not cut short the wait for a message.
+    ///
+    /// An error is only reported if no message can be received.
+    fn recvmmsg(
+        &self,
+        bufs: &mut [Vec<u8>],
+        flags: SendRecvFlags,
+        deadline: Option<Duration>,
+    ) -> Result<Vec<(usize, SocketAddr)>> {
+        // The default implementation cannot tell whether another message is available
+        // without blocking, so it only receives the first one with `MSG_WAITFORONE`.
+        let wait_for_one = flags.contains(SendRecvFlags::MSG_WAITFORONE);
+        let flags = flags - SendRecvFlags::MSG_WAITFORONE;
+
+        let mut received = Vec::with_capacity(bufs.len());
+        for buf in bufs.iter_mut() {
+            match self.recvfrom(buf, flags) {
+                Ok(result) => received.push(result),
+                Err(err) if received.is_empty() => return Err(err),
+                Err(_) => break,
+            }
+            if wait_for_one || is_deadline_reached(deadline) {
+                break;
+            }
+        }
+        Ok(received)
+    }
+}
+
+/// Returns whether the deadline, in terms of the monotonic clock, has been reached.
+fn is_deadline_reached(deadline: Option<Duration>) -> bool {
+    deadline.is_some_and(|deadline| MonotonicClock::get().read_time() >= deadline)
 }
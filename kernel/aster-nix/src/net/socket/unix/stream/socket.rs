@@ -15,7 +15,7 @@ use crate::{
         file_handle::FileLike,
         fs_resolver::FsPath,
         path::Dentry,
-        utils::{InodeType, StatusFlags},
+        utils::{InodeType, Permission, StatusFlags},
     },
     net::socket::{
         unix::{addr::UnixSocketAddrBound, UnixSocketAddr},
@@ -303,8 +303,8 @@ fn lookup_socket_file(path: &str) -> Result<Arc<Dentry>> {
         return_errno_with_message!(Errno::ENOTSOCK, "not a socket file")
     }
 
-    if !dentry.mode()?.is_readable() || !dentry.mode()?.is_writable() {
-        return_errno_with_message!(Errno::EACCES, "the socket cannot be read or written")
-    }
+    dentry
+        .inode()
+        .check_permission(Permission::MAY_READ | Permission::MAY_WRITE)?;
     Ok(dentry)
 }
@@ -1,6 +1,7 @@
 // SPDX-License-Identifier: MPL-2.0
 
 pub mod options;
+pub mod pollee;
 pub mod send_recv_flags;
 pub mod shutdown_cmd;
 pub mod socket_addr;
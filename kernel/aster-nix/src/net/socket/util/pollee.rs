@@ -0,0 +1,163 @@
+// SPDX-License-Identifier: MPL-2.0
+
+use crate::{
+    events::{IoEvents, Observer},
+    prelude::*,
+    process::signal::{Pollee, Poller},
+};
+
+/// A pollee of a socket whose readiness is tracked by two independent sides.
+///
+/// The write side holds `IoEvents::OUT` and the read side holds all the other events. Since
+/// the two sides have their own events and observers, the receiving path and the sending path
+/// can update their readiness without waking up or contending with each other.
+pub struct SocketPollee {
+    read: Pollee,
+    write: Pollee,
+}
+
+impl SocketPollee {
+    /// Creates a new instance with the given initial events.
+    pub fn new(init_events: IoEvents) -> Self {
+        Self {
+            read: Pollee::new(init_events - IoEvents::OUT),
+            write: Pollee::new(init_events & IoEvents::OUT),
+        }
+    }
+
+    /// Returns the current events of both sides given an event mask.
+    ///
+    /// See [`Pollee::poll`] for the semantics. The poller is registered on the write side only
+    /// if the mask contains `IoEvents::OUT`.
+    pub fn poll(&self, mask: IoEvents, poller: Option<&Poller>) -> IoEvents {
+        let revents = self.poll_sides(mask, None);
+        if !revents.is_empty() || poller.is_none() {
+            return revents;
+        }
+
+        self.poll_sides(mask, poller)
+    }
+
+    fn poll_sides(&self, mask: IoEvents, poller: Option<&Poller>) -> IoEvents {
+        let mut revents = self.read.poll(mask - IoEvents::OUT, poller);
+        if mask.contains(IoEvents::OUT) {
+            revents |= self.write.poll(IoEvents::OUT, poller);
+        }
+        revents
+    }
+
+    /// Registers an `IoEvents` observer.
+    ///
+    /// See [`Pollee::register_observer`] for the semantics.
+    pub fn register_observer(&self, observer: Weak<dyn Observer<IoEvents>>, mask: IoEvents) {
+        if mask.contains(IoEvents::OUT) {
+            self.write
+                .register_observer(observer.clone(), IoEvents::OUT);
+        } else {
+            self.write.unregister_observer(&observer);
+        }
+        self.read.register_observer(observer, mask - IoEvents::OUT);
+    }
+
+    /// Unregisters an `IoEvents` observer.
+    ///
+    /// See [`Pollee::unregister_observer`] for the semantics.
+    pub fn unregister_observer(
+        &self,
+        observer: &Weak<dyn Observer<IoEvents>>,
+    ) -> Option<Weak<dyn Observer<IoEvents>>> {
+        let write_observer = self.write.unregister_observer(observer);
+        self.read.unregister_observer(observer).or(write_observer)
+    }
+
+    /// Adds some events, which are dispatched to the sides they belong to.
+    pub fn add_events(&self, events: IoEvents) {
+        let read_events = events - IoEvents::OUT;
+        if !read_events.is_empty() {
+            self.read.add_events(read_events);
+        }
+        if events.contains(IoEvents::OUT) {
+            self.write.add_events(IoEvents::OUT);
+        }
+    }
+
+    /// Removes some events, which are dispatched to the sides they belong to.
+    pub fn del_events(&self, events: IoEvents) {
+        let read_events = events - IoEvents::OUT;
+        if !read_events.is_empty() {
+            self.read.del_events(read_events);
+        }
+        if events.contains(IoEvents::OUT) {
+            self.write.del_events(IoEvents::OUT);
+        }
+    }
+
+    /// Removes all events of both sides.
+    pub fn reset_events(&self) {
+        self.read.reset_events();
+        self.write.reset_events();
+    }
+}
+
+#[cfg(ktest)]
+mod test {
+    use super::*;
+
+    /// An observer that records the events that it is notified of.
+    struct Recorder(Mutex<Vec<IoEvents>>);
+
+    impl Observer<IoEvents> for Recorder {
+        fn on_events(&self, events: &IoEvents) {
+            self.0.lock().push(*events);
+        }
+    }
+
+    #[ktest]
+    fn sides_hold_their_own_events() {
+        let pollee = SocketPollee::new(IoEvents::OUT);
+        let all = IoEvents::IN | IoEvents::OUT;
+        assert_eq!(pollee.poll(all, None), IoEvents::OUT);
+        assert_eq!(pollee.poll(IoEvents::IN, None), IoEvents::empty());
+
+        pollee.add_events(IoEvents::IN | IoEvents::HUP);
+        assert_eq!(pollee.poll(all, None), all | IoEvents::HUP);
+
+        // Removing the events of one side keeps those of the other.
+        pollee.del_events(IoEvents::OUT);
+        assert_eq!(pollee.poll(all, None), IoEvents::IN | IoEvents::HUP);
+        pollee.del_events(IoEvents::IN | IoEvents::HUP);
+        pollee.add_events(IoEvents::OUT);
+        assert_eq!(pollee.poll(all, None), IoEvents::OUT);
+
+        pollee.reset_events();
+        assert_eq!(pollee.poll(all, None), IoEvents::empty());
+    }
+
+    #[ktest]
+    fn observers_are_notified_by_their_sides() {
+        let pollee = SocketPollee::new(IoEvents::empty());
+        let reader = Arc::new(Recorder(Mutex::new(Vec::new())));
+        let writer = Arc::new(Recorder(Mutex::new(Vec::new())));
+        let reader_observer = Arc::downgrade(&reader) as Weak<dyn Observer<IoEvents>>;
+        let writer_observer = Arc::downgrade(&writer) as Weak<dyn Observer<IoEvents>>;
+        pollee.register_observer(reader_observer.clone(), IoEvents::IN);
+        pollee.register_observer(writer_observer.clone(), IoEvents::OUT);
+
+        pollee.add_events(IoEvents::OUT);
+        pollee.add_events(IoEvents::IN);
+        assert_eq!(*reader.0.lock(), vec![IoEvents::IN]);
+        assert_eq!(*writer.0.lock(), vec![IoEvents::OUT]);
+
+        // Registering again without `IoEvents::OUT` leaves the write side.
+        pollee.register_observer(writer_observer.clone(), IoEvents::IN);
+        pollee.add_events(IoEvents::OUT);
+        assert_eq!(*writer.0.lock(), vec![IoEvents::OUT]);
+
+        assert!(pollee.unregister_observer(&reader_observer).is_some());
+        assert!(pollee.unregister_observer(&writer_observer).is_some());
+        assert!(pollee.unregister_observer(&writer_observer).is_none());
+        pollee.add_events(IoEvents::IN | IoEvents::OUT);
+        assert_eq!(reader.0.lock().len(), 1);
+        assert_eq!(writer.0.lock().len(), 1);
+    }
+}
@@ -0,0 +1,28 @@
+// SPDX-License-Identifier: MPL-2.0
+
+//! The power management of the machine.
+
+use aster_frame::arch::qemu::{exit_qemu, QemuExitCode};
+
+use crate::events::NotifierChain;
+
+/// The events before the machine restarts, halts or powers off.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RebootEvent {
+    Restart,
+    Halt,
+    PowerOff,
+}
+
+/// The notifier chain that is notified before the machine restarts, halts or powers off.
+///
+/// The subsystems register to the chain to put the devices and the data in a consistent
+/// state, e.g., to flush the dirty data of the FSes.
+pub static REBOOT_NOTIFIER_CHAIN: NotifierChain<RebootEvent> = NotifierChain::new();
+
+/// Notifies the reboot notifiers and powers off the machine.
+pub fn power_off(exit_code: QemuExitCode) -> ! {
+    REBOOT_NOTIFIER_CHAIN.notify(&RebootEvent::PowerOff);
+    // TODO: exit via qemu isa debug device should not be the only way.
+    exit_qemu(exit_code);
+}
@@ -179,6 +179,11 @@ fn clone_child_thread(parent_context: &UserContext, clone_args: CloneArgs) -> Re
         *sigmask
     };
 
+    // Inherit CPU affinity and scheduling policy from current thread
+    let cpu_affinity = current_thread!().cpu_affinity();
+    let sched_policy = current_thread!().sched_policy();
+    let priority = current_thread!().priority();
+
     let child_tid = allocate_tid();
     let child_thread = {
         let credentials = {
@@ -188,7 +193,9 @@ fn clone_child_thread(parent_context: &UserContext, clone_args: CloneArgs) -> Re
 
         let thread_builder = PosixThreadBuilder::new(child_tid, child_user_space, credentials)
             .process(Arc::downgrade(&current))
-            .sig_mask(sig_mask);
+            .sig_mask(sig_mask)
+            .cpu_affinity(cpu_affinity)
+            .sched_policy(sched_policy, priority);
         thread_builder.build()
     };
 
@@ -262,9 +269,19 @@ fn clone_child_process(
         *sigmask
     };
 
+    // inherit parent's CPU affinity
+    let child_cpu_affinity = current_thread!().cpu_affinity();
+
+    // inherit parent's scheduling policy
+    let child_sched_policy = current_thread!().sched_policy();
+    let child_priority = current_thread!().priority();
+
     // inherit parent's nice value
     let child_nice = current.nice().load(Ordering::Relaxed);
 
+    // inherit parent's oom_score_adj
+    let child_oom_score_adj = current.oom_score_adj().load(Ordering::Relaxed);
+
     let child_tid = allocate_tid();
 
     let child = {
@@ -280,6 +297,8 @@ fn clone_child_process(
             PosixThreadBuilder::new(child_tid, child_user_space, credentials)
                 .thread_name(Some(child_thread_name))
                 .sig_mask(child_sig_mask)
+                .cpu_affinity(child_cpu_affinity)
+                .sched_policy(child_sched_policy, child_priority)
         };
 
         let mut process_builder =
@@ -292,7 +311,8 @@ fn clone_child_process(
             .fs(child_fs)
             .umask(child_umask)
             .sig_dispositions(child_sig_dispositions)
-            .nice(child_nice);
+            .nice(child_nice)
+            .oom_score_adj(child_oom_score_adj);
 
         process_builder.build()?
     };
@@ -410,12 +430,13 @@ fn clone_files(
     clone_flags: CloneFlags,
 ) -> Arc<Mutex<FileTable>> {
     // if CLONE_FILES is set, the child and parent shares the same file table
-    // Otherwise, the child will deep copy a new file table.
+    // Otherwise, the child will deep copy a new file table, where the file
+    // descriptors marked as close-on-fork are dropped.
     // FIXME: the clone may not be deep copy.
     if clone_flags.contains(CloneFlags::CLONE_FILES) {
         parent_file_table.clone()
     } else {
-        Arc::new(Mutex::new(parent_file_table.lock().clone()))
+        Arc::new(Mutex::new(parent_file_table.lock().clone_for_fork()))
     }
 }
 
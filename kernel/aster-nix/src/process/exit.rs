@@ -4,7 +4,7 @@ use super::{process_table, Pid, Process, TermStatus};
 use crate::{
     prelude::*,
     process::{
-        posix_thread::do_exit,
+        posix_thread::{detach_tracees, do_exit},
         signal::{constants::SIGCHLD, signals::kernel::KernelSignal},
     },
 };
@@ -25,6 +25,8 @@ pub fn do_exit_group(term_status: TermStatus) {
         }
     }
 
+    detach_tracees(&current);
+
     // Sends parent-death signal
     // FIXME: according to linux spec, the signal should be sent when a posix thread which
     // creates child process exits, not when the whole process exits group.
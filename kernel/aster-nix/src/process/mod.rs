@@ -28,10 +28,10 @@ pub use process::{
 };
 pub use process_filter::ProcessFilter;
 pub use process_vm::{MAX_ARGV_NUMBER, MAX_ARG_LEN, MAX_ENVP_NUMBER, MAX_ENV_LEN};
-pub use program_loader::{check_executable_file, load_program_to_vm};
+pub use program_loader::{binfmt_misc, check_executable_file, load_program_to_vm};
 pub use rlimit::ResourceType;
 pub use term_status::TermStatus;
-pub use wait::{wait_child_exit, WaitOptions};
+pub use wait::{wait_child_exit, WaitOptions, WaitStatus};
 
 pub(super) fn init() {
     process::init();
@@ -2,9 +2,15 @@
 
 #![allow(dead_code)]
 
-use aster_frame::user::UserSpace;
+use core::sync::atomic::Ordering;
 
-use super::PosixThread;
+use aster_frame::{
+    cpu::CpuSet,
+    task::{Priority, SchedPolicy},
+    user::UserSpace,
+};
+
+use super::{PosixThread, PtraceState};
 use crate::{
     prelude::*,
     process::{
@@ -30,6 +36,10 @@ pub struct PosixThreadBuilder {
     clear_child_tid: Vaddr,
     sig_mask: SigMask,
     sig_queues: SigQueues,
+    cpu_affinity: CpuSet,
+    /// The real-time scheduling policy and priority, or `None` to be scheduled with the
+    /// nice value of the process.
+    real_time_policy: Option<(SchedPolicy, Priority)>,
 }
 
 impl PosixThreadBuilder {
@@ -44,6 +54,8 @@ impl PosixThreadBuilder {
             clear_child_tid: 0,
             sig_mask: SigMask::new_empty(),
             sig_queues: SigQueues::new(),
+            cpu_affinity: CpuSet::new_full(),
+            real_time_policy: None,
         }
     }
 
@@ -72,6 +84,18 @@ impl PosixThreadBuilder {
         self
     }
 
+    pub fn cpu_affinity(mut self, cpu_affinity: CpuSet) -> Self {
+        self.cpu_affinity = cpu_affinity;
+        self
+    }
+
+    /// Sets the scheduling policy and priority, which are ignored unless they are
+    /// real-time.
+    pub fn sched_policy(mut self, policy: SchedPolicy, priority: Priority) -> Self {
+        self.real_time_policy = policy.is_real_time().then_some((policy, priority));
+        self
+    }
+
     pub fn build(self) -> Arc<Thread> {
         let Self {
             tid,
@@ -83,10 +107,20 @@ impl PosixThreadBuilder {
             clear_child_tid,
             sig_mask,
             sig_queues,
+            cpu_affinity,
+            real_time_policy,
         } = self;
 
         let thread = Arc::new_cyclic(|thread_ref| {
             let task = task::create_new_user_task(user_space, thread_ref.clone());
+            // The priorities are inherited from the parent, so they match the policies.
+            if let Some((policy, priority)) = real_time_policy {
+                task.set_sched_policy(policy, priority).unwrap();
+            } else if let Some(process) = process.upgrade() {
+                task.set_priority(process.nice().load(Ordering::Relaxed).into())
+                    .unwrap();
+            }
+            task.set_cpu_affinity(cpu_affinity);
             let status = ThreadStatus::Init;
 
             let prof_clock = ProfClock::new();
@@ -99,11 +133,13 @@ impl PosixThreadBuilder {
                 set_child_tid: Mutex::new(set_child_tid),
                 clear_child_tid: Mutex::new(clear_child_tid),
                 credentials,
+                ptrace: PtraceState::new(),
                 sig_mask: Mutex::new(sig_mask),
                 sig_queues,
                 sig_context: Mutex::new(None),
                 sig_stack: Mutex::new(None),
                 robust_list: Mutex::new(None),
+                pi_futex_priorities: Mutex::new(BTreeMap::new()),
                 prof_clock,
                 virtual_timer_manager,
                 prof_timer_manager,
@@ -1,11 +1,15 @@
 // SPDX-License-Identifier: MPL-2.0
 
-use super::{futex::futex_wake, robust_list::wake_robust_futex, PosixThread, PosixThreadExt};
+use super::{
+    futex::futex_wake,
+    robust_list::{wake_robust_futex, RobustListHead},
+    PosixThread, PosixThreadExt,
+};
 use crate::{
     prelude::*,
     process::{do_exit_group, TermStatus},
     thread::{thread_table, Thread, Tid},
-    util::write_val_to_user,
+    util::{read_val_from_user, write_val_to_user},
 };
 
 /// Exits the thread if the thread is a POSIX thread.
@@ -34,6 +38,8 @@ pub fn do_exit(thread: Arc<Thread>, term_status: TermStatus) -> Result<()> {
     // exit the robust list: walk the robust list; mark futex words as dead and do futex wake
     wake_robust_list(posix_thread, tid);
 
+    posix_thread.ptrace().detach_on_exit(tid);
+
     if tid != posix_thread.process().pid() {
         // We don't remove main thread.
         // The main thread is removed when the process is reaped.
@@ -52,14 +58,19 @@ pub fn do_exit(thread: Arc<Thread>, term_status: TermStatus) -> Result<()> {
 /// Walks the robust futex list, marking futex dead and wake waiters.
 /// It corresponds to Linux's exit_robust_list(), errors are silently ignored.
 fn wake_robust_list(thread: &PosixThread, tid: Tid) {
-    let mut robust_list = thread.robust_list.lock();
-    let list_head = match *robust_list {
-        Some(robust_list_head) => robust_list_head,
-        None => return,
+    let Some(head_addr) = thread.robust_list.lock().take() else {
+        return;
+    };
+    let Ok(list_head) = read_val_from_user::<RobustListHead>(head_addr) else {
+        return;
     };
     trace!("wake the rubust_list: {:?}", list_head);
-    for futex_addr in list_head.futexes() {
-        wake_robust_futex(futex_addr, tid).unwrap();
+    for (futex_addr, is_pi) in list_head.futexes(head_addr) {
+        if let Err(err) = wake_robust_futex(futex_addr, is_pi, tid) {
+            debug!(
+                "failed to wake the robust futex {:#x}: {:?}",
+                futex_addr, err
+            );
+        }
     }
-    *robust_list = None;
 }
@@ -2,14 +2,20 @@
 
 #![allow(dead_code)]
 
-use core::sync::atomic::{AtomicBool, Ordering};
+use core::{
+    fmt::Debug,
+    sync::atomic::{AtomicBool, AtomicUsize, Ordering},
+    time::Duration,
+};
 
-use aster_frame::cpu::num_cpus;
+use aster_frame::{cpu::num_cpus, task::Priority};
 
+use super::PosixThreadExt;
 use crate::{
     prelude::*,
-    thread::{Thread, Tid},
-    util::read_val_from_user,
+    process::signal::Pauser,
+    thread::{thread_table, Thread, Tid},
+    util::{compare_exchange_user_u32, read_val_from_user},
 };
 
 type FutexBitSet = u32;
@@ -20,7 +26,7 @@ const FUTEX_FLAGS_MASK: u32 = 0xFFFF_FFF0;
 const FUTEX_BITSET_MATCH_ANY: FutexBitSet = 0xFFFF_FFFF;
 
 /// do futex wait
-pub fn futex_wait(futex_addr: u64, futex_val: i32, timeout: &Option<FutexTimeout>) -> Result<()> {
+pub fn futex_wait(futex_addr: u64, futex_val: i32, timeout: Option<&Duration>) -> Result<()> {
     futex_wait_bitset(futex_addr as _, futex_val, timeout, FUTEX_BITSET_MATCH_ANY)
 }
 
@@ -28,31 +34,137 @@ pub fn futex_wait(futex_addr: u64, futex_val: i32, timeout: &Option<FutexTimeout
 pub fn futex_wait_bitset(
     futex_addr: Vaddr,
     futex_val: i32,
-    timeout: &Option<FutexTimeout>,
+    timeout: Option<&Duration>,
     bitset: FutexBitSet,
 ) -> Result<()> {
     debug!(
         "futex_wait_bitset addr: {:#x}, val: {}, timeout: {:?}, bitset: {:#x}",
         futex_addr, futex_val, timeout, bitset
     );
+    if bitset == 0 {
+        return_errno_with_message!(Errno::EINVAL, "the futex bitset is zero");
+    }
+
     let futex_key = FutexKey::new(futex_addr);
     let (_, futex_bucket_ref) = FUTEX_BUCKETS.get_bucket(futex_key);
 
     // lock futex bucket ref here to avoid data race
     let mut futex_bucket = futex_bucket_ref.lock();
 
-    if futex_key.load_val() != futex_val {
-        return_errno_with_message!(Errno::EINVAL, "futex value does not match");
+    if futex_key.load_val()? != futex_val {
+        return_errno_with_message!(Errno::EAGAIN, "futex value does not match");
     }
     let futex_item = FutexItem::new(futex_key, bitset);
     futex_bucket.enqueue_item(futex_item.clone());
 
     // drop lock
     drop(futex_bucket);
-    // Wait on the futex item
-    futex_item.wait();
 
-    Ok(())
+    let res = futex_item.waiter().pause_until_woken(timeout);
+
+    // The futex may be woken right after the timeout or the signal, which is not missed.
+    if !dequeue_futex_item(&futex_item) {
+        return Ok(());
+    }
+    res.map_err(|err| pause_error(err, "futex_wait"))
+}
+
+/// The maximum number of futexes waited by `futex_waitv`.
+pub const FUTEX_WAITV_MAX: usize = 128;
+
+/// A futex waited by `futex_waitv`.
+#[derive(Debug, Clone, Copy)]
+pub struct FutexWaitv {
+    pub addr: Vaddr,
+    pub val: u32,
+}
+
+/// Waits on multiple futexes until any of them is woken, the `timeout` expires, or a signal
+/// is received.
+///
+/// Returns the index of the woken futex. The futexes are enqueued only if all their values
+/// match, so the wait can be restarted after a signal as if it had never been started.
+pub fn futex_waitv(waiters: &[FutexWaitv], timeout: Option<&Duration>) -> Result<usize> {
+    debug!(
+        "futex_waitv waiters: {:x?}, timeout: {:?}",
+        waiters, timeout
+    );
+
+    let waiter = Arc::new(FutexWaiter::new());
+    let mut futex_items = Vec::with_capacity(waiters.len());
+    for futex_waitv in waiters {
+        let futex_key = FutexKey::new(futex_waitv.addr);
+        let (_, futex_bucket_ref) = FUTEX_BUCKETS.get_bucket(futex_key);
+        let mut futex_bucket = futex_bucket_ref.lock();
+
+        let futex_val = match futex_key.load_val() {
+            Ok(futex_val) => futex_val as u32,
+            Err(err) => {
+                drop(futex_bucket);
+                dequeue_futex_items(&futex_items);
+                return Err(err);
+            }
+        };
+        if futex_val != futex_waitv.val {
+            drop(futex_bucket);
+            dequeue_futex_items(&futex_items);
+            return_errno_with_message!(Errno::EAGAIN, "futex value does not match");
+        }
+        let futex_item = FutexItem::with_waiter(futex_key, FUTEX_BITSET_MATCH_ANY, waiter.clone());
+        futex_bucket.enqueue_item(futex_item.clone());
+        futex_items.push(futex_item);
+    }
+
+    let res = waiter.pause_until_woken(timeout);
+
+    // A futex may be woken right after the timeout or the signal, which is not missed.
+    if let Some(woken_index) = dequeue_futex_items(&futex_items) {
+        return Ok(woken_index);
+    }
+    match res {
+        Err(err) => Err(pause_error(err, "futex_waitv")),
+        Ok(()) => unreachable!("a woken futex is always dequeued by the waker"),
+    }
+}
+
+/// Converts the error of pausing on a futex to the error returned to the user space.
+fn pause_error(err: Error, op: &str) -> Error {
+    match err.error() {
+        Errno::ETIME => Error::with_message(Errno::ETIMEDOUT, "the futex wait is timeout"),
+        Errno::EINTR => {
+            debug!("{} is interrupted", op);
+            Error::with_message(Errno::ERESTARTSYS, "the futex wait is interrupted")
+        }
+        _ => err,
+    }
+}
+
+/// Dequeues the futex items from their buckets, and returns the index of the first item that
+/// has been dequeued by a waker.
+fn dequeue_futex_items(futex_items: &[FutexItem]) -> Option<usize> {
+    let mut woken_index = None;
+    for (index, futex_item) in futex_items.iter().enumerate() {
+        let is_enqueued = dequeue_futex_item(futex_item);
+        if !is_enqueued && woken_index.is_none() {
+            woken_index = Some(index);
+        }
+    }
+    woken_index
+}
+
+/// Dequeues the futex item from its bucket, and returns whether it is in the bucket, i.e.,
+/// it has not been dequeued by a waker.
+fn dequeue_futex_item(futex_item: &FutexItem) -> bool {
+    loop {
+        let futex_key = futex_item.key();
+        let (_, futex_bucket_ref) = FUTEX_BUCKETS.get_bucket(futex_key);
+        let mut futex_bucket = futex_bucket_ref.lock();
+        // The item may be requeued to another futex before the bucket is locked.
+        if futex_item.key() != futex_key {
+            continue;
+        }
+        return futex_bucket.dequeue_item(futex_item);
+    }
 }
 
 /// do futex wake
@@ -70,71 +182,242 @@ pub fn futex_wake_bitset(
         "futex_wake_bitset addr: {:#x}, max_count: {}, bitset: {:#x}",
         futex_addr, max_count, bitset
     );
+    if bitset == 0 {
+        return_errno_with_message!(Errno::EINVAL, "the futex bitset is zero");
+    }
 
     let futex_key = FutexKey::new(futex_addr);
     let (_, futex_bucket_ref) = FUTEX_BUCKETS.get_bucket(futex_key);
     let mut futex_bucket = futex_bucket_ref.lock();
     let res = futex_bucket.dequeue_and_wake_items(futex_key, max_count, bitset);
-    // debug!("futex wake bitset succeeds, res = {}", res);
     drop(futex_bucket);
-    // for _ in 0..res {
-    //     Thread::yield_now();
-    // }
     Ok(res)
 }
 
 /// Do futex requeue
+///
+/// At most `max_nwakes` waiters are woken, and at most `max_nrequeues` of the rest are moved
+/// to wait on the futex at `futex_new_addr`. If `expected_val` is given, nothing is done
+/// unless it matches the value of the futex at `futex_addr`, which is what
+/// `FUTEX_CMP_REQUEUE` requires.
+///
+/// Returns the number of the woken and the requeued waiters.
 pub fn futex_requeue(
     futex_addr: Vaddr,
     max_nwakes: usize,
     max_nrequeues: usize,
     futex_new_addr: Vaddr,
+    expected_val: Option<i32>,
 ) -> Result<usize> {
-    if futex_new_addr == futex_addr {
-        return futex_wake(futex_addr, max_nwakes);
-    }
-
     let futex_key = FutexKey::new(futex_addr);
     let futex_new_key = FutexKey::new(futex_new_addr);
     let (bucket_idx, futex_bucket_ref) = FUTEX_BUCKETS.get_bucket(futex_key);
     let (new_bucket_idx, futex_new_bucket_ref) = FUTEX_BUCKETS.get_bucket(futex_new_key);
 
-    let nwakes = {
-        if bucket_idx == new_bucket_idx {
-            let mut futex_bucket = futex_bucket_ref.lock();
-            let nwakes =
-                futex_bucket.dequeue_and_wake_items(futex_key, max_nwakes, FUTEX_BITSET_MATCH_ANY);
-            futex_bucket.update_item_keys(futex_key, futex_new_key, max_nrequeues);
-            drop(futex_bucket);
-            nwakes
+    let check_val = || -> Result<()> {
+        match expected_val {
+            Some(expected_val) if futex_key.load_val()? != expected_val => {
+                return_errno_with_message!(Errno::EAGAIN, "futex value does not match")
+            }
+            _ => Ok(()),
+        }
+    };
+
+    if bucket_idx == new_bucket_idx {
+        let mut futex_bucket = futex_bucket_ref.lock();
+        check_val()?;
+        let nwakes =
+            futex_bucket.dequeue_and_wake_items(futex_key, max_nwakes, FUTEX_BITSET_MATCH_ANY);
+        let nrequeues = if futex_new_key == futex_key {
+            0
+        } else {
+            futex_bucket.update_item_keys(futex_key, futex_new_key, max_nrequeues)
+        };
+        return Ok(nwakes + nrequeues);
+    }
+
+    let (mut futex_bucket, mut futex_new_bucket) = {
+        if bucket_idx < new_bucket_idx {
+            let futex_bucket = futex_bucket_ref.lock();
+            let futext_new_bucket = futex_new_bucket_ref.lock();
+            (futex_bucket, futext_new_bucket)
+        } else {
+            // bucket_idx > new_bucket_idx
+            let futex_new_bucket = futex_new_bucket_ref.lock();
+            let futex_bucket = futex_bucket_ref.lock();
+            (futex_bucket, futex_new_bucket)
+        }
+    };
+    check_val()?;
+
+    let nwakes = futex_bucket.dequeue_and_wake_items(futex_key, max_nwakes, FUTEX_BITSET_MATCH_ANY);
+    let nrequeues = futex_bucket.requeue_items_to_another_bucket(
+        futex_key,
+        &mut futex_new_bucket,
+        futex_new_key,
+        max_nrequeues,
+    );
+    Ok(nwakes + nrequeues)
+}
+
+/// Locks the priority-inheritance futex at `futex_addr` for the current thread.
+///
+/// The futex word holds the TID of the owner, or zero if the futex is not locked. If it is
+/// locked by another thread, the current thread sets `FUTEX_WAITERS` and waits until the
+/// owner hands the futex over, which lends the priority of the current thread to the owner
+/// meanwhile. A `timeout` is relative to now.
+///
+/// If `is_try` is true, the current thread does not wait, but fails with `EAGAIN` instead.
+pub fn futex_lock_pi(futex_addr: Vaddr, timeout: Option<&Duration>, is_try: bool) -> Result<()> {
+    debug!(
+        "futex_lock_pi addr: {:#x}, timeout: {:?}, is_try: {}",
+        futex_addr, timeout, is_try
+    );
+
+    let current_thread = current_thread!();
+    let tid = current_thread.tid();
+    let futex_key = FutexKey::new(futex_addr);
+    let (_, futex_bucket_ref) = FUTEX_BUCKETS.get_bucket(futex_key);
+    let mut futex_bucket = futex_bucket_ref.lock();
+
+    // The futex word is updated atomically, since the user space locks and unlocks the
+    // futex without the kernel if there are no waiters.
+    let has_waiters = futex_bucket.has_pi_items(futex_key);
+    let futex_val = match futex_key.update_val(|val| {
+        let owner_tid = val & FUTEX_TID_MASK;
+        if owner_tid == 0 {
+            // The futex is unlocked, or its owner died, which the user space can learn from
+            // `FUTEX_OWNER_DIED` after the futex is locked.
+            let waiters = if has_waiters { FUTEX_WAITERS } else { 0 };
+            Some(tid | waiters | (val & FUTEX_OWNER_DIED))
+        } else if owner_tid == tid || is_try || val & FUTEX_WAITERS != 0 {
+            None
         } else {
-            let (mut futex_bucket, mut futex_new_bucket) = {
-                if bucket_idx < new_bucket_idx {
-                    let futex_bucket = futex_bucket_ref.lock();
-                    let futext_new_bucket = futex_new_bucket_ref.lock();
-                    (futex_bucket, futext_new_bucket)
-                } else {
-                    // bucket_idx > new_bucket_idx
-                    let futex_new_bucket = futex_new_bucket_ref.lock();
-                    let futex_bucket = futex_bucket_ref.lock();
-                    (futex_bucket, futex_new_bucket)
-                }
-            };
-
-            let nwakes =
-                futex_bucket.dequeue_and_wake_items(futex_key, max_nwakes, FUTEX_BITSET_MATCH_ANY);
-            futex_bucket.requeue_items_to_another_bucket(
-                futex_key,
-                &mut futex_new_bucket,
-                futex_new_key,
-                max_nrequeues,
-            );
-            nwakes
+            // Let the owner unlock the futex in the kernel, which hands it over.
+            Some(val | FUTEX_WAITERS)
         }
+    })? {
+        Ok(futex_val) if futex_val & FUTEX_TID_MASK == 0 => return Ok(()),
+        Ok(futex_val) | Err(futex_val) => futex_val,
+    };
+    let owner_tid = futex_val & FUTEX_TID_MASK;
+    if owner_tid == tid {
+        return_errno_with_message!(Errno::EDEADLK, "the futex is locked by the current thread");
+    }
+    if is_try {
+        return_errno_with_message!(Errno::EAGAIN, "the futex is locked by another thread");
+    }
+    let Some(owner) = thread_table::get_thread(owner_tid) else {
+        return_errno_with_message!(Errno::ESRCH, "the owner of the futex does not exist");
+    };
+
+    let futex_item = FutexItem::new_pi(futex_key);
+    futex_bucket.enqueue_item(futex_item.clone());
+    set_pi_futex_priority(&owner, futex_key, futex_bucket.top_pi_priority(futex_key));
+    drop(futex_bucket);
+
+    let res = futex_item.waiter().pause_until_woken(timeout);
+
+    // The futex may be handed over right after the timeout or the signal, which is not
+    // missed.
+    let mut futex_bucket = futex_bucket_ref.lock();
+    if !futex_bucket.dequeue_item(&futex_item) {
+        return Ok(());
+    }
+    // Stop lending the priority of the current thread to the owner.
+    set_pi_futex_priority(&owner, futex_key, futex_bucket.top_pi_priority(futex_key));
+    if !futex_bucket.has_pi_items(futex_key) {
+        let _ = futex_key.update_val(|val| Some(val & !FUTEX_WAITERS));
+    }
+    drop(futex_bucket);
+    res.map_err(|err| pause_error(err, "futex_lock_pi"))
+}
+
+/// Unlocks the priority-inheritance futex at `futex_addr`, which must be locked by the
+/// current thread.
+///
+/// The futex is handed over to the waiter with the highest priority, if any.
+pub fn futex_unlock_pi(futex_addr: Vaddr) -> Result<()> {
+    debug!("futex_unlock_pi addr: {:#x}", futex_addr);
+
+    let current_thread = current_thread!();
+    let futex_key = FutexKey::new(futex_addr);
+    let (_, futex_bucket_ref) = FUTEX_BUCKETS.get_bucket(futex_key);
+    let mut futex_bucket = futex_bucket_ref.lock();
+
+    let futex_val = futex_key.load_val()? as u32;
+    if futex_val & FUTEX_TID_MASK != current_thread.tid() {
+        return_errno_with_message!(
+            Errno::EPERM,
+            "the futex is not locked by the current thread"
+        );
+    }
+    futex_bucket.hand_over_pi_futex(futex_key, current_thread.tid(), 0)?;
+    drop(futex_bucket);
+
+    // The priorities inherited from the waiters of the other priority-inheritance futexes
+    // held by the current thread are kept.
+    set_pi_futex_priority(&current_thread, futex_key, None);
+    Ok(())
+}
+
+/// Marks the futex at `futex_addr` as `FUTEX_OWNER_DIED` if it is owned by the exiting
+/// thread `tid`, and lets a waiter acquire it.
+///
+/// A waiter of a priority-inheritance futex is handed over the futex directly. Otherwise, a
+/// waiter is woken to try to lock the futex again, which is up to the user space.
+pub fn futex_wake_owner_died(futex_addr: Vaddr, tid: Tid, is_pi: bool) -> Result<()> {
+    let futex_key = FutexKey::new(futex_addr);
+    let (_, futex_bucket_ref) = FUTEX_BUCKETS.get_bucket(futex_key);
+    let mut futex_bucket = futex_bucket_ref.lock();
+
+    let futex_val = futex_key.load_val()? as u32;
+    // The futex may be held by another thread, do nothing.
+    if futex_val & FUTEX_TID_MASK != tid {
+        return Ok(());
+    }
+
+    if is_pi {
+        return futex_bucket.hand_over_pi_futex(futex_key, tid, FUTEX_OWNER_DIED);
+    }
+    let Ok(futex_val) = futex_key.update_val(|val| {
+        (val & FUTEX_TID_MASK == tid).then_some((val & FUTEX_WAITERS) | FUTEX_OWNER_DIED)
+    })?
+    else {
+        return Ok(());
+    };
+    if futex_val & FUTEX_WAITERS != 0 {
+        debug!("wake robust futex addr: {:#x}", futex_addr);
+        futex_bucket.dequeue_and_wake_items(futex_key, 1, FUTEX_BITSET_MATCH_ANY);
+    }
+    Ok(())
+}
+
+/// Sets the highest priority of the waiters of the priority-inheritance futex `key`, which
+/// is held by `owner`, or `None` if `owner` no longer inherits priorities from the futex.
+///
+/// The owner inherits the highest priority of the waiters of all the priority-inheritance
+/// futexes that it holds.
+fn set_pi_futex_priority(owner: &Thread, key: FutexKey, priority: Option<Priority>) {
+    let Some(posix_thread) = owner.as_posix_thread() else {
+        owner.inherit_priority(priority);
+        return;
+    };
+    let mut priorities = posix_thread.pi_futex_priorities().lock();
+    match priority {
+        Some(priority) => priorities.insert(key.addr(), priority),
+        None => priorities.remove(&key.addr()),
     };
-    Ok(nwakes)
+    owner.inherit_priority(priorities.values().min().copied());
 }
 
+/// The bit of a futex word that is set if some threads are waiting on the futex.
+pub const FUTEX_WAITERS: u32 = 0x8000_0000;
+/// The bit of a futex word that is set if the owner of the futex exited without unlocking it.
+pub const FUTEX_OWNER_DIED: u32 = 0x4000_0000;
+/// The bits of a futex word that hold the TID of the owner.
+pub const FUTEX_TID_MASK: u32 = 0x3FFF_FFFF;
+
 lazy_static! {
     // Use the same count as linux kernel to keep the same performance
     static ref BUCKET_COUNT: usize = ((1<<8)* num_cpus()).next_power_of_two() as _;
@@ -142,15 +425,6 @@ lazy_static! {
     static ref FUTEX_BUCKETS: FutexBucketVec = FutexBucketVec::new(*BUCKET_COUNT);
 }
 
-#[derive(Debug, Clone)]
-pub struct FutexTimeout {}
-
-impl FutexTimeout {
-    pub fn new() -> Self {
-        todo!()
-    }
-}
-
 struct FutexBucketVec {
     vec: Vec<FutexBucketRef>,
 }
@@ -197,13 +471,17 @@ impl FutexBucket {
         self.queue.push_back(item);
     }
 
-    pub fn dequeue_item(&mut self, item: &FutexItem) {
+    /// Dequeues the item, and returns whether it is in the queue.
+    pub fn dequeue_item(&mut self, item: &FutexItem) -> bool {
         let item_i = self
             .queue
             .iter()
             .position(|futex_item| *futex_item == *item);
         if let Some(item_i) = item_i {
             self.queue.remove(item_i).unwrap();
+            true
+        } else {
+            false
         }
     }
 
@@ -216,8 +494,11 @@ impl FutexBucket {
         let mut count = 0;
         let mut items_to_wake = Vec::new();
 
+        // The waiters of a priority-inheritance futex are only woken by handing over the
+        // futex.
         self.queue.retain(|item| {
-            if count >= max_count || key != item.key || (bitset & item.bitset) == 0 {
+            if count >= max_count || key != item.key() || item.is_pi || (bitset & item.bitset) == 0
+            {
                 true
             } else {
                 items_to_wake.push(item.clone());
@@ -226,23 +507,27 @@ impl FutexBucket {
             }
         });
 
-        // debug!("items to wake len: {}", items_to_wake.len());
-
         FutexItem::batch_wake(&items_to_wake);
         count
     }
 
-    pub fn update_item_keys(&mut self, key: FutexKey, new_key: FutexKey, max_count: usize) {
+    pub fn update_item_keys(
+        &mut self,
+        key: FutexKey,
+        new_key: FutexKey,
+        max_count: usize,
+    ) -> usize {
         let mut count = 0;
         for item in self.queue.iter_mut() {
             if count == max_count {
                 break;
             }
-            if item.key == key {
-                item.key = new_key;
+            if item.key() == key && !item.is_pi {
+                item.set_key(new_key);
                 count += 1;
             }
         }
+        count
     }
 
     pub fn requeue_items_to_another_bucket(
@@ -251,48 +536,139 @@ impl FutexBucket {
         another: &mut Self,
         new_key: FutexKey,
         max_nrequeues: usize,
-    ) {
+    ) -> usize {
         let mut count = 0;
 
         self.queue.retain(|item| {
-            if count >= max_nrequeues || key != item.key {
+            if count >= max_nrequeues || key != item.key() || item.is_pi {
                 true
             } else {
-                let mut new_item = item.clone();
-                new_item.key = new_key;
-                another.enqueue_item(new_item);
+                item.set_key(new_key);
+                another.enqueue_item(item.clone());
                 count += 1;
                 false
             }
         });
+        count
+    }
+
+    /// Returns whether there are threads waiting on the priority-inheritance futex.
+    pub fn has_pi_items(&self, key: FutexKey) -> bool {
+        self.queue
+            .iter()
+            .any(|item| item.is_pi && item.key() == key)
+    }
+
+    /// Returns the highest priority of the threads waiting on the priority-inheritance futex.
+    pub fn top_pi_priority(&self, key: FutexKey) -> Option<Priority> {
+        self.queue
+            .iter()
+            .filter(|item| item.is_pi && item.key() == key)
+            .map(|item| item.waiter().thread().effective_priority())
+            .min()
+    }
+
+    /// Hands over the priority-inheritance futex held by `owner_tid` to the waiter with the
+    /// highest priority, or unlocks it if there are no waiters. The `flags` are kept in the
+    /// futex word.
+    ///
+    /// It fails with `EPERM` if the futex is no longer held by `owner_tid`.
+    pub fn hand_over_pi_futex(&mut self, key: FutexKey, owner_tid: Tid, flags: u32) -> Result<()> {
+        let set_owner = |new_val: u32| {
+            let is_held = |val| val & FUTEX_TID_MASK == owner_tid;
+            if key
+                .update_val(|val| is_held(val).then_some(new_val))?
+                .is_err()
+            {
+                return_errno_with_message!(Errno::EPERM, "the futex is not held by the owner");
+            }
+            Ok(())
+        };
+
+        let Some(top_priority) = self.top_pi_priority(key) else {
+            return set_owner(flags);
+        };
+        // The waiters with the same priority are handed over the futex in FIFO order.
+        let item_i = self
+            .queue
+            .iter()
+            .position(|item| {
+                item.is_pi
+                    && item.key() == key
+                    && item.waiter().thread().effective_priority() == top_priority
+            })
+            .unwrap();
+        let item = self.queue.remove(item_i).unwrap();
+
+        let new_owner = item.waiter().thread();
+        let waiters = if self.has_pi_items(key) {
+            FUTEX_WAITERS
+        } else {
+            0
+        };
+        if let Err(err) = set_owner(new_owner.tid() | waiters | flags) {
+            self.queue.insert(item_i, item);
+            return Err(err);
+        }
+        set_pi_futex_priority(new_owner, key, self.top_pi_priority(key));
+        item.wake();
+        Ok(())
     }
 }
 
-#[derive(Debug, PartialEq, Clone)]
+#[derive(Debug, Clone)]
 struct FutexItem {
-    key: FutexKey,
+    /// The address of the futex, which is shared by the clones of the item, so it can be
+    /// found again after it is requeued to another futex.
+    key: Arc<AtomicUsize>,
     bitset: FutexBitSet,
+    /// Whether the item waits on a priority-inheritance futex.
+    is_pi: bool,
     waiter: FutexWaiterRef,
 }
 
+impl PartialEq for FutexItem {
+    fn eq(&self, other: &Self) -> bool {
+        Arc::ptr_eq(&self.key, &other.key)
+    }
+}
+
 impl FutexItem {
     pub fn new(key: FutexKey, bitset: FutexBitSet) -> Self {
+        Self::with_waiter(key, bitset, Arc::new(FutexWaiter::new()))
+    }
+
+    /// Creates an item that waits on a priority-inheritance futex.
+    pub fn new_pi(key: FutexKey) -> Self {
+        FutexItem {
+            is_pi: true,
+            ..Self::new(key, FUTEX_BITSET_MATCH_ANY)
+        }
+    }
+
+    /// Creates an item that shares the waiter with the other items, so that the waiter is
+    /// woken if any of them is woken.
+    pub fn with_waiter(key: FutexKey, bitset: FutexBitSet, waiter: FutexWaiterRef) -> Self {
         FutexItem {
-            key,
+            key: Arc::new(AtomicUsize::new(key.addr())),
             bitset,
-            waiter: Arc::new(FutexWaiter::new()),
+            is_pi: false,
+            waiter,
         }
     }
 
-    pub fn wake(&self) {
-        // debug!("wake futex item, key = {:?}", self.key);
-        self.waiter.wake();
+    pub fn key(&self) -> FutexKey {
+        FutexKey::new(self.key.load(Ordering::Relaxed))
     }
 
-    pub fn wait(&self) {
-        // debug!("wait on futex item, key = {:?}", self.key);
-        self.waiter.wait();
-        // debug!("wait finished, key = {:?}", self.key);
+    /// Sets the key of the item, which must be done with the locks of both the old and the
+    /// new buckets held.
+    fn set_key(&self, key: FutexKey) {
+        self.key.store(key.addr(), Ordering::Relaxed);
+    }
+
+    pub fn wake(&self) {
+        self.waiter.wake();
     }
 
     pub fn waiter(&self) -> &FutexWaiterRef {
@@ -314,10 +690,29 @@ impl FutexKey {
         FutexKey(futex_addr as _)
     }
 
-    pub fn load_val(&self) -> i32 {
-        // FIXME: how to implement a atomic load?
-        warn!("implement an atomic load");
-        read_val_from_user(self.0).unwrap()
+    pub fn load_val(&self) -> Result<i32> {
+        if self.0 % core::mem::size_of::<i32>() != 0 {
+            return_errno_with_message!(Errno::EINVAL, "the futex address is not aligned");
+        }
+        read_val_from_user(self.0)
+    }
+
+    /// Atomically updates the futex word with the new value returned by `f`, until the word
+    /// is not changed by the user space meanwhile, like `AtomicU32::fetch_update`.
+    ///
+    /// It returns the previous value, which is `Err` if `f` returns `None`.
+    pub fn update_val(
+        &self,
+        mut f: impl FnMut(u32) -> Option<u32>,
+    ) -> Result<core::result::Result<u32, u32>> {
+        let mut val = self.load_val()? as u32;
+        while let Some(new_val) = f(val) {
+            match compare_exchange_user_u32(self.0, val, new_val)? {
+                Ok(val) => return Ok(Ok(val)),
+                Err(changed_val) => val = changed_val,
+            }
+        }
+        Ok(Err(val))
     }
 
     pub fn addr(&self) -> Vaddr {
@@ -390,15 +785,25 @@ pub fn futex_op_and_flags_from_u32(bits: u32) -> Result<(FutexOp, FutexFlags)> {
 
 type FutexWaiterRef = Arc<FutexWaiter>;
 
-#[derive(Debug)]
 struct FutexWaiter {
     is_woken: AtomicBool,
-    tid: Tid,
+    thread: Arc<Thread>,
+    /// The pauser that the waiter sleeps on if it can be interrupted by signals.
+    pauser: Arc<Pauser>,
+}
+
+impl Debug for FutexWaiter {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        f.debug_struct("FutexWaiter")
+            .field("is_woken", &self.is_woken)
+            .field("tid", &self.thread.tid())
+            .finish()
+    }
 }
 
 impl PartialEq for FutexWaiter {
     fn eq(&self, other: &Self) -> bool {
-        self.tid == other.tid
+        self.thread.tid() == other.thread.tid()
     }
 }
 
@@ -406,27 +811,31 @@ impl FutexWaiter {
     pub fn new() -> Self {
         Self {
             is_woken: AtomicBool::new(false),
-            tid: current_thread!().tid(),
+            thread: current_thread!(),
+            pauser: Pauser::new(),
         }
     }
 
-    pub fn wait(&self) {
-        let current_thread = current_thread!();
-        if current_thread.tid() != self.tid {
-            return;
-        }
-        self.is_woken.store(false, Ordering::SeqCst);
-        while !self.is_woken() {
-            // debug!("futex is wait for waken, tid = {}", self.tid);
-            Thread::yield_now();
+    /// Returns the thread that waits.
+    pub fn thread(&self) -> &Arc<Thread> {
+        &self.thread
+    }
+
+    /// Pauses until the waiter is woken, the timeout expires, or a signal is received.
+    ///
+    /// The waiter is never reset, so it can only be used once.
+    pub fn pause_until_woken(&self, timeout: Option<&Duration>) -> Result<()> {
+        let cond = || self.is_woken().then_some(());
+        match timeout {
+            Some(timeout) => self.pauser.pause_until_or_timeout(cond, timeout),
+            None => self.pauser.pause_until(cond),
         }
-        // debug!("futex is waken, tid = {}", self.tid);
     }
 
     pub fn wake(&self) {
         if !self.is_woken() {
-            // debug!("wake up futex, tid = {}", self.tid);
             self.is_woken.store(true, Ordering::SeqCst);
+            self.pauser.resume_all();
         }
     }
 
@@ -2,6 +2,7 @@
 
 #![allow(dead_code)]
 
+use aster_frame::task::Priority;
 use aster_rights::{ReadOp, WriteOp};
 
 use super::{
@@ -28,12 +29,15 @@ mod exit;
 pub mod futex;
 mod name;
 mod posix_thread_ext;
+mod ptrace;
 mod robust_list;
 
 pub use builder::PosixThreadBuilder;
 pub use exit::do_exit;
 pub use name::{ThreadName, MAX_THREAD_NAME_LEN};
 pub use posix_thread_ext::PosixThreadExt;
+pub(in crate::process) use ptrace::detach_tracees;
+pub use ptrace::{PtraceOptions, PtraceState, PtraceStop, PtraceStopKind};
 pub use robust_list::RobustListHead;
 
 pub struct PosixThread {
@@ -48,11 +52,20 @@ pub struct PosixThread {
     set_child_tid: Mutex<Vaddr>,
     clear_child_tid: Mutex<Vaddr>,
 
-    robust_list: Mutex<Option<RobustListHead>>,
+    /// The user space address of the head of the robust futex list, which is walked when
+    /// the thread exits.
+    robust_list: Mutex<Option<Vaddr>>,
+
+    /// The highest priorities of the waiters of the priority-inheritance futexes held by
+    /// the thread, keyed by the addresses of the futexes.
+    pi_futex_priorities: Mutex<BTreeMap<Vaddr, Priority>>,
 
     /// Process credentials. At the kernel level, credentials are a per-thread attribute.
     credentials: Credentials,
 
+    /// The tracing state. At the kernel level, a thread rather than a process is traced.
+    ptrace: PtraceState,
+
     // Signal
     /// Blocked signals
     sig_mask: Mutex<SigMask>,
@@ -217,10 +230,18 @@ impl PosixThread {
         &self.sig_stack
     }
 
-    pub fn robust_list(&self) -> &Mutex<Option<RobustListHead>> {
+    pub fn ptrace(&self) -> &PtraceState {
+        &self.ptrace
+    }
+
+    pub fn robust_list(&self) -> &Mutex<Option<Vaddr>> {
         &self.robust_list
     }
 
+    pub fn pi_futex_priorities(&self) -> &Mutex<BTreeMap<Vaddr, Priority>> {
+        &self.pi_futex_priorities
+    }
+
     fn is_main_thread(&self, tid: Tid) -> bool {
         let process = self.process();
         let pid = process.pid();
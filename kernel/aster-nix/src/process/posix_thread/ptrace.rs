@@ -0,0 +1,411 @@
+// SPDX-License-Identifier: MPL-2.0
+
+//! Process tracing.
+//!
+//! A traced thread (a tracee) stops before a signal is delivered to it, after it executes
+//! a new program, and at the entries and the exits of the syscalls if its tracer asks for
+//! them. The tracer is notified of the stops via `wait4()`, and inspects or modifies the
+//! registers and the memory of the tracee with `ptrace()` before resuming it.
+
+use aster_frame::cpu::UserContext;
+
+use crate::{
+    cpu::LinuxAbi,
+    prelude::*,
+    process::{
+        signal::{
+            c_types::siginfo_t,
+            constants::{SIGCHLD, SIGKILL, SIGTRAP},
+            sig_mask::SigMask,
+            sig_num::SigNum,
+            signals::kernel::KernelSignal,
+            Pauser,
+        },
+        Process,
+    },
+    thread::Tid,
+};
+
+bitflags! {
+    pub struct PtraceOptions: u32 {
+        const PTRACE_O_TRACESYSGOOD = 1 << 0;
+        const PTRACE_O_TRACEFORK = 1 << 1;
+        const PTRACE_O_TRACEVFORK = 1 << 2;
+        const PTRACE_O_TRACECLONE = 1 << 3;
+        const PTRACE_O_TRACEEXEC = 1 << 4;
+        const PTRACE_O_TRACEVFORKDONE = 1 << 5;
+        const PTRACE_O_TRACEEXIT = 1 << 6;
+        const PTRACE_O_TRACESECCOMP = 1 << 7;
+        const PTRACE_O_EXITKILL = 1 << 20;
+        const PTRACE_O_SUSPEND_SECCOMP = 1 << 21;
+    }
+}
+
+impl PtraceOptions {
+    pub fn supported(&self) -> bool {
+        let supported_options = PtraceOptions::PTRACE_O_TRACESYSGOOD
+            | PtraceOptions::PTRACE_O_TRACEEXEC
+            | PtraceOptions::PTRACE_O_EXITKILL;
+        supported_options.contains(*self)
+    }
+}
+
+impl Default for PtraceOptions {
+    fn default() -> Self {
+        Self::empty()
+    }
+}
+
+const PTRACE_EVENT_EXEC: u32 = 4;
+
+/// The reason why a tracee stops.
+#[derive(Clone, Copy)]
+pub enum PtraceStopKind {
+    /// The tracee is going to handle a signal.
+    Signal(siginfo_t),
+    /// The tracee is going to execute a syscall.
+    SyscallEntry,
+    /// The tracee has executed a syscall.
+    SyscallExit,
+    /// The tracee has executed a new program, with the thread ID before `execve()`.
+    Exec(Tid),
+}
+
+/// A stop of a tracee, which is inspected and modified by its tracer.
+pub struct PtraceStop {
+    kind: PtraceStopKind,
+    /// The user context of the tracee, which is restored when the tracee is resumed.
+    context: UserContext,
+    /// The number of the syscall that the tracee is executing, or -1 if it is not in a
+    /// syscall. The number is visible to the tracer as `orig_rax`.
+    orig_rax: usize,
+    is_reported: bool,
+    /// The signal to deliver after the tracee is resumed, if the tracee is resumed.
+    resume_signal: Option<Option<SigNum>>,
+}
+
+impl PtraceStop {
+    pub fn context(&self) -> &UserContext {
+        &self.context
+    }
+
+    pub fn context_mut(&mut self) -> &mut UserContext {
+        &mut self.context
+    }
+
+    pub fn orig_rax(&self) -> usize {
+        self.orig_rax
+    }
+
+    pub fn set_orig_rax(&mut self, orig_rax: usize) {
+        self.orig_rax = orig_rax;
+    }
+
+    /// Returns the signal information of the stop.
+    ///
+    /// For the stops other than the signal-delivery stops, the information of a `SIGTRAP`
+    /// is returned, with the code that tells the kind of the stop.
+    pub fn siginfo(&self, options: PtraceOptions) -> siginfo_t {
+        match self.kind {
+            PtraceStopKind::Signal(siginfo) => siginfo,
+            _ => siginfo_t::new(SIGTRAP, self.stop_signal(options) as i32),
+        }
+    }
+
+    /// Returns the message of the event that the tracee stops at.
+    pub fn event_msg(&self) -> u64 {
+        match self.kind {
+            PtraceStopKind::Exec(tid) => tid as u64,
+            _ => 0,
+        }
+    }
+
+    /// Returns the status reported by `wait4()` for the stop.
+    fn wait_status(&self, options: PtraceOptions) -> u32 {
+        (self.stop_signal(options) << 8) | 0x7f
+    }
+
+    /// Returns the signal number of the stop, along with the bits that tell the kind of
+    /// the stop.
+    fn stop_signal(&self, options: PtraceOptions) -> u32 {
+        let sigtrap = SIGTRAP.as_u8() as u32;
+        match self.kind {
+            PtraceStopKind::Signal(siginfo) => siginfo.si_signo as u32,
+            PtraceStopKind::SyscallEntry | PtraceStopKind::SyscallExit => {
+                if options.contains(PtraceOptions::PTRACE_O_TRACESYSGOOD) {
+                    sigtrap | 0x80
+                } else {
+                    sigtrap
+                }
+            }
+            PtraceStopKind::Exec(_) => sigtrap | (PTRACE_EVENT_EXEC << 8),
+        }
+    }
+}
+
+/// The tracing state of a thread.
+pub struct PtraceState {
+    inner: Mutex<PtraceInner>,
+    /// The tracee is paused on it in the stops, and only `SIGKILL` interrupts it.
+    pauser: Arc<Pauser>,
+}
+
+#[derive(Default)]
+struct PtraceInner {
+    tracer: Weak<Process>,
+    options: PtraceOptions,
+    /// Whether the tracee stops at the entries and the exits of the syscalls.
+    traces_syscalls: bool,
+    stop: Option<PtraceStop>,
+}
+
+impl PtraceInner {
+    fn is_traced_by(&self, tracer: &Arc<Process>) -> bool {
+        self.tracer.as_ptr() == Arc::as_ptr(tracer)
+    }
+
+    /// Returns the stop that the tracee is in and has not been resumed from.
+    fn active_stop(&mut self) -> Option<&mut PtraceStop> {
+        self.stop
+            .as_mut()
+            .filter(|stop| stop.resume_signal.is_none())
+    }
+
+    /// Resumes the tracee from the stop if it is in one.
+    fn resume(&mut self, signal: Option<SigNum>) -> bool {
+        let Some(stop) = self.active_stop() else {
+            return false;
+        };
+        stop.resume_signal = Some(signal);
+        true
+    }
+}
+
+impl PtraceState {
+    pub(super) fn new() -> Self {
+        let pauser = {
+            let mut sig_mask = SigMask::new_full();
+            sig_mask.remove_signal(SIGKILL);
+            Pauser::new_with_mask(sig_mask)
+        };
+        Self {
+            inner: Mutex::new(PtraceInner::default()),
+            pauser,
+        }
+    }
+
+    /// Returns the tracer if the thread is traced.
+    pub fn tracer(&self) -> Option<Arc<Process>> {
+        self.inner.lock().tracer.upgrade()
+    }
+
+    pub fn is_traced(&self) -> bool {
+        self.tracer().is_some()
+    }
+
+    pub fn is_traced_by(&self, tracer: &Arc<Process>) -> bool {
+        self.inner.lock().is_traced_by(tracer)
+    }
+
+    pub fn options(&self) -> PtraceOptions {
+        self.inner.lock().options
+    }
+
+    /// Returns whether the thread stops at the entries and the exits of the syscalls.
+    pub fn traces_syscalls(&self) -> bool {
+        let inner = self.inner.lock();
+        inner.traces_syscalls && inner.tracer.strong_count() != 0
+    }
+
+    /// Stops the current thread until its tracer resumes it.
+    ///
+    /// The tracer may modify `context` in the stop. The syscall number is visible to the
+    /// tracer if the thread is executing the syscall of `syscall_number`.
+    ///
+    /// This method returns the signal to deliver, which is given by the tracer for a
+    /// signal-delivery stop. If the thread is not traced, the signal of the stop is
+    /// returned. If the thread is interrupted by `SIGKILL`, `None` is returned.
+    pub fn stop(
+        &self,
+        kind: PtraceStopKind,
+        context: &mut UserContext,
+        syscall_number: Option<usize>,
+    ) -> Option<SigNum> {
+        let original_signal = match kind {
+            PtraceStopKind::Signal(siginfo) => SigNum::try_from(siginfo.si_signo as u8).ok(),
+            _ => None,
+        };
+
+        let tracer = {
+            let mut inner = self.inner.lock();
+            let Some(tracer) = inner.tracer.upgrade() else {
+                return original_signal;
+            };
+
+            let mut stop_context = *context;
+            // Like Linux, the return value of a syscall is `-ENOSYS` before the syscall is
+            // executed, while the syscall number is kept in `orig_rax`.
+            if let PtraceStopKind::SyscallEntry = kind {
+                stop_context.set_syscall_ret(-(Errno::ENOSYS as isize) as usize);
+            }
+            inner.stop = Some(PtraceStop {
+                kind,
+                context: stop_context,
+                orig_rax: syscall_number.unwrap_or(usize::MAX),
+                is_reported: false,
+                resume_signal: None,
+            });
+            tracer
+        };
+
+        tracer.enqueue_signal(KernelSignal::new(SIGCHLD));
+        tracer.children_pauser().resume_all();
+
+        let res = self.pauser.pause_until(|| {
+            let inner = self.inner.lock();
+            let is_stopped = inner
+                .stop
+                .as_ref()
+                .is_some_and(|stop| stop.resume_signal.is_none());
+            (!is_stopped).then_some(())
+        });
+
+        let stop = self.inner.lock().stop.take()?;
+        *context = stop.context;
+        // The tracer may change the syscall to execute by changing `orig_rax`.
+        if let PtraceStopKind::SyscallEntry = kind {
+            context.set_rax(stop.orig_rax);
+        }
+
+        match (res, kind) {
+            (Err(_), _) => None,
+            (Ok(()), PtraceStopKind::Signal(_)) => stop.resume_signal.flatten(),
+            (Ok(()), _) => None,
+        }
+    }
+
+    /// Attaches the thread to `tracer`.
+    pub fn attach(&self, tracer: &Arc<Process>, options: PtraceOptions) -> Result<()> {
+        let mut inner = self.inner.lock();
+        if inner.tracer.strong_count() != 0 {
+            return_errno_with_message!(Errno::EPERM, "the thread is already traced");
+        }
+
+        *inner = PtraceInner {
+            tracer: Arc::downgrade(tracer),
+            options,
+            ..Default::default()
+        };
+        Ok(())
+    }
+
+    /// Detaches the thread from `tracer`, and resumes it with `signal` if it is stopped.
+    pub fn detach(&self, tracer: &Arc<Process>, signal: Option<SigNum>) -> Result<()> {
+        let mut inner = self.inner.lock();
+        if !inner.is_traced_by(tracer) {
+            return_errno_with_message!(Errno::ESRCH, "the thread is not traced by the caller");
+        }
+
+        inner.tracer = Weak::new();
+        inner.traces_syscalls = false;
+        inner.resume(signal);
+        drop(inner);
+
+        self.pauser.resume_all();
+        Ok(())
+    }
+
+    /// Detaches the thread from its tracer when the thread exits.
+    pub(super) fn detach_on_exit(&self, tid: Tid) {
+        let tracer = {
+            let mut inner = self.inner.lock();
+            let tracer = inner.tracer.upgrade();
+            *inner = PtraceInner::default();
+            tracer
+        };
+
+        if let Some(tracer) = tracer {
+            tracer.tracees().lock().remove(&tid);
+            tracer.children_pauser().resume_all();
+        }
+    }
+
+    /// Sets the options of tracing, which requires the thread to be stopped.
+    pub fn set_options(&self, tracer: &Arc<Process>, options: PtraceOptions) -> Result<()> {
+        self.with_stop(tracer, |_| ())?;
+        self.inner.lock().options = options;
+        Ok(())
+    }
+
+    /// Resumes the thread from the stop, and delivers `signal` to it if the stop is a
+    /// signal-delivery stop.
+    pub fn resume(
+        &self,
+        tracer: &Arc<Process>,
+        signal: Option<SigNum>,
+        traces_syscalls: bool,
+    ) -> Result<()> {
+        let mut inner = self.inner.lock();
+        if !inner.is_traced_by(tracer) || !inner.resume(signal) {
+            return_errno_with_message!(Errno::ESRCH, "the thread is not stopped by the caller");
+        }
+        inner.traces_syscalls = traces_syscalls;
+        drop(inner);
+
+        self.pauser.resume_all();
+        Ok(())
+    }
+
+    /// Calls `op` with the stop of the thread, which should be traced by `tracer`.
+    ///
+    /// # Errors
+    ///
+    /// If the thread is not traced by `tracer`, or is not stopped, this method returns
+    /// `ESRCH`.
+    pub fn with_stop<R>(
+        &self,
+        tracer: &Arc<Process>,
+        op: impl FnOnce(&mut PtraceStop) -> R,
+    ) -> Result<R> {
+        let mut inner = self.inner.lock();
+        if !inner.is_traced_by(tracer) {
+            return_errno_with_message!(Errno::ESRCH, "the thread is not traced by the caller");
+        }
+        let Some(stop) = inner.active_stop() else {
+            return_errno_with_message!(Errno::ESRCH, "the thread is not stopped");
+        };
+        Ok(op(stop))
+    }
+
+    /// Returns the status of the stop for `wait4()` if the stop has not been reported to
+    /// `tracer`, and marks it as reported if `consumes` is true.
+    pub fn take_unreported_status(&self, tracer: &Arc<Process>, consumes: bool) -> Option<u32> {
+        let mut inner = self.inner.lock();
+        if !inner.is_traced_by(tracer) {
+            return None;
+        }
+        let options = inner.options;
+        let stop = inner.active_stop().filter(|stop| !stop.is_reported)?;
+        if consumes {
+            stop.is_reported = true;
+        }
+        Some(stop.wait_status(options))
+    }
+}
+
+/// Detaches the tracees of `tracer` when it exits.
+///
+/// The tracees are killed if `PTRACE_O_EXITKILL` is set, and are resumed otherwise.
+pub(in crate::process) fn detach_tracees(tracer: &Arc<Process>) {
+    use super::PosixThreadExt;
+
+    let tracees = core::mem::take(&mut *tracer.tracees().lock());
+    for tracee in tracees.values() {
+        let posix_thread = tracee.as_posix_thread().unwrap();
+        let ptrace = posix_thread.ptrace();
+        if ptrace.options().contains(PtraceOptions::PTRACE_O_EXITKILL) {
+            posix_thread.enqueue_signal(Box::new(KernelSignal::new(SIGKILL)));
+        }
+        let _ = ptrace.detach(tracer, None);
+    }
+}
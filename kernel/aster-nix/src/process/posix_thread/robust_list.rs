@@ -4,8 +4,8 @@
 
 use crate::{
     prelude::*,
-    process::{posix_thread::futex::futex_wake, Pid},
-    util::{read_val_from_user, write_val_to_user},
+    process::{posix_thread::futex::futex_wake_owner_died, Pid},
+    util::read_val_from_user,
 };
 
 #[repr(C)]
@@ -32,20 +32,23 @@ pub struct RobustListHead {
 }
 
 impl RobustListHead {
-    /// Return an iterator for all futexes in the robust list.
+    /// Return an iterator for all futexes in the robust list, where `head_addr` is the
+    /// user space address of the head.
     ///
-    /// The futex refered to by `list_op_pending`, if any, will be returned as
-    /// the last item.
-    pub fn futexes(&self) -> FutexIter<'_> {
-        FutexIter::new(self)
+    /// Each item is the address of the futex and whether it is a priority-inheritance
+    /// futex. The futex refered to by `list_op_pending`, if any, will be returned as the
+    /// last item.
+    pub fn futexes(&self, head_addr: Vaddr) -> FutexIter<'_> {
+        FutexIter::new(self, head_addr)
     }
 
     /// Return the pending futex address if exist
-    fn pending_futex_addr(&self) -> Option<Vaddr> {
+    fn pending_futex(&self) -> Option<(Vaddr, bool)> {
         if self.list_op_pending == 0 {
             None
         } else {
-            Some(self.futex_addr(self.list_op_pending))
+            let (entry_ptr, is_pi) = split_entry_ptr(self.list_op_pending);
+            Some((self.futex_addr(entry_ptr), is_pi))
         }
     }
 
@@ -55,16 +58,24 @@ impl RobustListHead {
     }
 }
 
+/// Splits the pointer to a lock entry, whose lowest bit is set if the futex of the entry is
+/// a priority-inheritance futex.
+fn split_entry_ptr(entry_ptr: Vaddr) -> (Vaddr, bool) {
+    (entry_ptr & !1, entry_ptr & 1 != 0)
+}
+
 pub struct FutexIter<'a> {
     robust_list: &'a RobustListHead,
+    head_addr: Vaddr,
     entry_ptr: Vaddr,
     count: isize,
 }
 
 impl<'a> FutexIter<'a> {
-    pub fn new(robust_list: &'a RobustListHead) -> Self {
+    pub fn new(robust_list: &'a RobustListHead, head_addr: Vaddr) -> Self {
         Self {
             robust_list,
+            head_addr,
             entry_ptr: robust_list.list.next,
             count: 0,
         }
@@ -85,72 +96,45 @@ impl<'a> FutexIter<'a> {
 const ROBUST_LIST_LIMIT: isize = 2048;
 
 impl<'a> Iterator for FutexIter<'a> {
-    type Item = Vaddr;
+    type Item = (Vaddr, bool);
 
     fn next(&mut self) -> Option<Self::Item> {
         if self.is_end() {
             return None;
         }
 
-        while self.entry_ptr != &self.robust_list.list as *const _ as usize {
+        while self.entry_ptr != self.head_addr {
             if self.count == ROBUST_LIST_LIMIT {
                 break;
             }
-            if self.entry_ptr == 0 {
-                return None;
+            let (entry_ptr, is_pi) = split_entry_ptr(self.entry_ptr);
+            if entry_ptr == 0 {
+                break;
             }
-            let futex_addr = if self.entry_ptr != self.robust_list.list_op_pending {
-                Some(self.robust_list.futex_addr(self.entry_ptr))
+            // The pending futex is returned at the end.
+            let futex = if self.entry_ptr != self.robust_list.list_op_pending {
+                Some((self.robust_list.futex_addr(entry_ptr), is_pi))
             } else {
                 None
             };
-            let Ok(robust_list) = read_val_from_user::<RobustList>(self.entry_ptr) else {
-                return None;
+            let Ok(robust_list) = read_val_from_user::<RobustList>(entry_ptr) else {
+                break;
             };
             self.entry_ptr = robust_list.next;
             self.count += 1;
-            if futex_addr.is_some() {
-                return futex_addr;
+            if futex.is_some() {
+                return futex;
             }
         }
         self.set_end();
-        self.robust_list.pending_futex_addr()
+        self.robust_list.pending_futex()
     }
 }
 
-const FUTEX_WAITERS: u32 = 0x8000_0000;
-const FUTEX_OWNER_DIED: u32 = 0x4000_0000;
-const FUTEX_TID_MASK: u32 = 0x3FFF_FFFF;
-
 /// Wakeup one robust futex owned by the thread
-/// FIXME: requires atomic operations here
-pub fn wake_robust_futex(futex_addr: Vaddr, tid: Pid) -> Result<()> {
-    let futex_val = {
-        if futex_addr == 0 {
-            return_errno_with_message!(Errno::EINVAL, "invalid futext addr");
-        }
-        read_val_from_user::<u32>(futex_addr)?
-    };
-    let mut old_val = futex_val;
-    loop {
-        // This futex may held by another thread, do nothing
-        if old_val & FUTEX_TID_MASK != tid {
-            break;
-        }
-        let new_val = (old_val & FUTEX_WAITERS) | FUTEX_OWNER_DIED;
-        let cur_val = read_val_from_user(futex_addr)?;
-        if cur_val != new_val {
-            // The futex value has changed, let's retry with current value
-            old_val = cur_val;
-            write_val_to_user(futex_addr, &new_val)?;
-            continue;
-        }
-        // Wakeup one waiter
-        if cur_val & FUTEX_WAITERS != 0 {
-            debug!("wake robust futex addr: {:?}", futex_addr);
-            futex_wake(futex_addr, 1)?;
-        }
-        break;
+pub fn wake_robust_futex(futex_addr: Vaddr, is_pi: bool, tid: Pid) -> Result<()> {
+    if futex_addr == 0 {
+        return_errno_with_message!(Errno::EINVAL, "invalid futext addr");
     }
-    Ok(())
+    futex_wake_owner_died(futex_addr, tid, is_pi)
 }
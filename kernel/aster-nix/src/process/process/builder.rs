@@ -35,6 +35,7 @@ pub struct ProcessBuilder<'a> {
     sig_dispositions: Option<Arc<Mutex<SigDispositions>>>,
     credentials: Option<Credentials>,
     nice: Option<Nice>,
+    oom_score_adj: Option<i16>,
 }
 
 impl<'a> ProcessBuilder<'a> {
@@ -54,6 +55,7 @@ impl<'a> ProcessBuilder<'a> {
             sig_dispositions: None,
             credentials: None,
             nice: None,
+            oom_score_adj: None,
         }
     }
 
@@ -112,6 +114,11 @@ impl<'a> ProcessBuilder<'a> {
         self
     }
 
+    pub fn oom_score_adj(&mut self, oom_score_adj: i16) -> &mut Self {
+        self.oom_score_adj = Some(oom_score_adj);
+        self
+    }
+
     fn check_build(&self) -> Result<()> {
         if self.main_thread_builder.is_some() {
             debug_assert!(self.parent.upgrade().is_some());
@@ -147,6 +154,7 @@ impl<'a> ProcessBuilder<'a> {
             sig_dispositions,
             credentials,
             nice,
+            oom_score_adj,
         } = self;
 
         let process_vm = process_vm.or_else(|| Some(ProcessVm::alloc())).unwrap();
@@ -173,6 +181,8 @@ impl<'a> ProcessBuilder<'a> {
 
         let nice = nice.or_else(|| Some(Nice::default())).unwrap();
 
+        let oom_score_adj = oom_score_adj.unwrap_or(0);
+
         let process = {
             let threads = Vec::new();
             Process::new(
@@ -186,6 +196,7 @@ impl<'a> ProcessBuilder<'a> {
                 umask,
                 resource_limits,
                 nice,
+                oom_score_adj,
                 sig_dispositions,
             )
         };
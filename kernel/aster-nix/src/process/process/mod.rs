@@ -4,8 +4,8 @@ use self::timer_manager::PosixTimerManager;
 use super::{
     posix_thread::PosixThreadExt,
     process_table,
-    process_vm::{Heap, InitStackReader, ProcessVm},
-    rlimit::ResourceLimits,
+    process_vm::{Heap, InitStackReader, ProcessVm, MAX_STACK_SIZE},
+    rlimit::{ResourceLimits, ResourceType},
     signal::{
         constants::SIGCHLD,
         sig_disposition::SigDispositions,
@@ -22,7 +22,7 @@ use crate::{
     fs::{file_table::FileTable, fs_resolver::FsResolver, utils::FileCreationMask},
     prelude::*,
     sched::nice::Nice,
-    thread::{allocate_tid, Thread},
+    thread::{allocate_tid, Thread, Tid},
     time::clocks::ProfClock,
     vm::vmar::Vmar,
 };
@@ -34,6 +34,8 @@ mod session;
 mod terminal;
 mod timer_manager;
 
+use core::sync::atomic::AtomicI16;
+
 use aster_rights::Full;
 use atomic::Atomic;
 pub use builder::ProcessBuilder;
@@ -75,6 +77,8 @@ pub struct Process {
     pub(super) parent: Mutex<Weak<Process>>,
     /// Children processes
     children: Mutex<BTreeMap<Pid, Arc<Process>>>,
+    /// The threads traced by the process
+    tracees: Mutex<BTreeMap<Tid, Arc<Thread>>>,
     /// Process group
     pub(super) process_group: Mutex<Weak<ProcessGroup>>,
     /// File table
@@ -89,6 +93,8 @@ pub struct Process {
     /// According to POSIX.1, the nice value is a per-process attribute,
     /// the threads in a process should share a nice value.
     nice: Atomic<Nice>,
+    /// The adjustment of the badness score for the OOM killer.
+    oom_score_adj: AtomicI16,
 
     // Signal
     /// Sig dispositions
@@ -118,6 +124,7 @@ impl Process {
         umask: Arc<RwLock<FileCreationMask>>,
         resource_limits: ResourceLimits,
         nice: Nice,
+        oom_score_adj: i16,
         sig_dispositions: Arc<Mutex<SigDispositions>>,
     ) -> Arc<Self> {
         let children_pauser = {
@@ -138,6 +145,7 @@ impl Process {
             status: Mutex::new(ProcessStatus::Uninit),
             parent: Mutex::new(parent),
             children: Mutex::new(BTreeMap::new()),
+            tracees: Mutex::new(BTreeMap::new()),
             process_group: Mutex::new(Weak::new()),
             file_table,
             fs,
@@ -146,6 +154,7 @@ impl Process {
             parent_death_signal: AtomicSigNum::new_empty(),
             resource_limits: Mutex::new(resource_limits),
             nice: Atomic::new(nice),
+            oom_score_adj: AtomicI16::new(oom_score_adj),
             timer_manager: PosixTimerManager::new(&prof_clock, process_ref),
             prof_clock,
         })
@@ -254,6 +263,11 @@ impl Process {
         &self.nice
     }
 
+    /// Returns the adjustment of the badness score for the OOM killer.
+    pub fn oom_score_adj(&self) -> &AtomicI16 {
+        &self.oom_score_adj
+    }
+
     pub fn main_thread(&self) -> Option<Arc<Thread>> {
         self.threads
             .lock()
@@ -283,6 +297,11 @@ impl Process {
         &self.children_pauser
     }
 
+    /// Returns the threads traced by the process.
+    pub fn tracees(&self) -> &Mutex<BTreeMap<Tid, Arc<Thread>>> {
+        &self.tracees
+    }
+
     // *********** Process group & Session***********
 
     /// Returns the process group ID of the process.
@@ -545,6 +564,20 @@ impl Process {
         self.process_vm.init_stack_reader()
     }
 
+    /// Grows the user stack down to cover `addr`, within the stack size limit.
+    ///
+    /// This method does nothing if `addr` is already mapped. If the stack cannot grow,
+    /// the accesses to `addr` should fail with `SIGSEGV`.
+    pub fn grow_stack(&self, addr: Vaddr) -> Result<()> {
+        let stack_limit = self
+            .resource_limits
+            .lock()
+            .get_rlimit(ResourceType::RLIMIT_STACK)
+            .get_cur();
+        let max_size = stack_limit.min(MAX_STACK_SIZE as u64) as usize;
+        self.root_vmar().grow_stack(addr, max_size)
+    }
+
     // ************** File system ****************
 
     pub fn file_table(&self) -> &Arc<Mutex<FileTable>> {
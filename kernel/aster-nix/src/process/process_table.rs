@@ -37,6 +37,11 @@ pub fn process_table() -> ProcessTable<'static> {
     }
 }
 
+/// Like [`process_table`], but returns `None` instead of blocking if the table is locked.
+pub fn try_process_table() -> Option<ProcessTable<'static>> {
+    PROCESS_TABLE.try_lock().map(|inner| ProcessTable { inner })
+}
+
 /// A wrapper for the mutex-protected process table.
 ///
 /// It provides the `iter` method to iterator over the processes in the table.
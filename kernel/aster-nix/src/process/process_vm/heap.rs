@@ -51,6 +51,7 @@ impl Heap {
                 .unwrap()
                 .offset(self.base)
                 .size(self.limit)
+                .name("[heap]")
         };
         vmar_map_options.build()?;
 
@@ -26,13 +26,23 @@ use self::aux_vec::{AuxKey, AuxVec};
 use crate::{
     prelude::*,
     util::{random::getrandom, read_cstring_from_vmar},
-    vm::{perms::VmPerms, vmar::Vmar, vmo::VmoOptions},
+    vm::{
+        perms::VmPerms,
+        vmar::{Vmar, STACK_GUARD_GAP},
+        vmo::VmoOptions,
+    },
 };
 
 pub mod aux_vec;
 
 /// Set the initial stack size to 8 megabytes, following the default Linux stack size limit.
 pub const INIT_STACK_SIZE: usize = 8 * 1024 * 1024; // 8 MB
+/// The maximum size of the user stack, regardless of the stack size limit.
+/// The address range of this size below the initial top is reserved for the stack.
+pub const MAX_STACK_SIZE: usize = 128 * 1024 * 1024; // 128 MB
+/// The size of the initially mapped part of the stack, which can hold the maximum
+/// `argv` and `envp`. The stack grows down from it on page faults.
+pub const INIT_STACK_MAPPED_SIZE: usize = 512 * 1024; // 512 KB
 
 /// The max number of arguments that can be used to creating a new process.
 pub const MAX_ARGV_NUMBER: usize = 128;
@@ -86,7 +96,13 @@ pub const MAX_ENV_LEN: usize = 128;
  *  |                     |
  *  +---------------------+
  *  |                     |
- *  +---------------------+ <------+ User stack default rlimit
+ *  +---------------------+ <------+ The initially mapped part of the stack
+ *  |                     |
+ *  +---------------------+ <------+ User stack rlimit, which the stack grows within
+ *  |                     |
+ *  +---------------------+ <------+ Max stack size
+ *  |                     |          Guard gap
+ *  +---------------------+
  *  (low address)
  */
 
@@ -113,7 +129,7 @@ impl InitStack {
             random_nr_pages_padding as usize
         };
         let initial_top = MAX_USERSPACE_VADDR - PAGE_SIZE * nr_pages_padding;
-        let max_size = INIT_STACK_SIZE;
+        let max_size = MAX_STACK_SIZE;
         Self {
             initial_top,
             max_size,
@@ -122,21 +138,33 @@ impl InitStack {
     }
 
     /// Init and map the vmo for init stack
+    ///
+    /// Only the top of the vmo is mapped at first. The rest of the vmo backs the stack
+    /// when it grows down, and its address range is reserved together with a guard gap.
     pub(super) fn alloc_and_map_vmo(&self, root_vmar: &Vmar<Full>) -> Result<()> {
         let vmo = {
             let vmo_options = VmoOptions::<Rights>::new(self.max_size);
             vmo_options.alloc()?
         };
 
+        let map_addr = self.initial_top - INIT_STACK_MAPPED_SIZE;
+        debug_assert!(map_addr % PAGE_SIZE == 0);
         let vmar_map_options = {
             let perms = VmPerms::READ | VmPerms::WRITE;
-            let map_addr = self.initial_top - self.max_size;
-            debug_assert!(map_addr % PAGE_SIZE == 0);
-            root_vmar.new_map(vmo, perms)?.offset(map_addr)
+            root_vmar
+                .new_map(vmo, perms)?
+                .vmo_offset(self.max_size - INIT_STACK_MAPPED_SIZE)
+                .size(INIT_STACK_MAPPED_SIZE)
+                .offset(map_addr)
+                .grows_down(true)
+                .name("[stack]")
         };
 
         vmar_map_options.build()?;
 
+        let stack_bottom = self.initial_top - self.max_size;
+        root_vmar.reserve(stack_bottom - STACK_GUARD_GAP..map_addr)?;
+
         self.set_uninitialized();
         Ok(())
     }
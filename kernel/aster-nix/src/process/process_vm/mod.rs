@@ -20,10 +20,13 @@ pub use self::{
     init_stack::{
         aux_vec::{AuxKey, AuxVec},
         InitStack, InitStackReader, InitStackWriter, INIT_STACK_SIZE, MAX_ARGV_NUMBER, MAX_ARG_LEN,
-        MAX_ENVP_NUMBER, MAX_ENV_LEN,
+        MAX_ENVP_NUMBER, MAX_ENV_LEN, MAX_STACK_SIZE,
     },
 };
-use crate::{prelude::*, vm::vmar::Vmar};
+use crate::{
+    prelude::*,
+    vm::{pkey::PkeyAllocator, vmar::Vmar},
+};
 
 /*
  * The user's virtual memory space layout looks like below.
@@ -66,6 +69,7 @@ pub struct ProcessVm {
     root_vmar: Vmar<Full>,
     init_stack: InitStack,
     heap: Heap,
+    pkeys: Arc<PkeyAllocator>,
 }
 
 impl Clone for ProcessVm {
@@ -74,6 +78,7 @@ impl Clone for ProcessVm {
             root_vmar: self.root_vmar.dup().unwrap(),
             init_stack: self.init_stack.clone(),
             heap: self.heap.clone(),
+            pkeys: self.pkeys.clone(),
         }
     }
 }
@@ -90,6 +95,7 @@ impl ProcessVm {
             root_vmar,
             heap,
             init_stack,
+            pkeys: Arc::new(PkeyAllocator::new()),
         }
     }
 
@@ -102,6 +108,7 @@ impl ProcessVm {
             root_vmar,
             heap: other.heap.clone(),
             init_stack: other.init_stack.clone(),
+            pkeys: Arc::new(other.pkeys.as_ref().clone()),
         })
     }
 
@@ -109,6 +116,11 @@ impl ProcessVm {
         &self.root_vmar
     }
 
+    /// Returns the allocated protection keys, which are shared by the threads.
+    pub fn pkeys(&self) -> &PkeyAllocator {
+        &self.pkeys
+    }
+
     /// Returns a reader for reading contents from
     /// the `InitStack`.
     pub fn init_stack_reader(&self) -> InitStackReader {
@@ -131,6 +143,7 @@ impl ProcessVm {
     /// Clears existing mappings and then maps stack and heap vmo.
     pub(super) fn clear_and_map(&self) {
         self.root_vmar.clear().unwrap();
+        self.pkeys.reset();
         self.init_stack.alloc_and_map_vmo(&self.root_vmar).unwrap();
         self.heap.alloc_and_map_vmo(&self.root_vmar).unwrap();
     }
@@ -0,0 +1,340 @@
+// SPDX-License-Identifier: MPL-2.0
+
+//! The miscellaneous binary formats, which are run by the interpreters registered for them.
+//!
+//! A format is matched either by the magic bytes at an offset of the file, or by the
+//! extension of the file name. The file is then run by the interpreter with the path of
+//! the file as the first argument, e.g., a wasm runtime for `.wasm` files, or an emulator
+//! for the ELF binaries of a foreign architecture. The formats are registered, enabled and
+//! removed via the binfmt_misc file system.
+
+use core::sync::atomic::{AtomicBool, Ordering};
+
+use crate::{
+    fs::{
+        fs_resolver::{FsPath, FsResolver, AT_FDCWD},
+        path::Dentry,
+    },
+    prelude::*,
+};
+
+/// The maximum length of a registration string, which is the same as that in Linux.
+const MAX_REGISTER_LEN: usize = 1920;
+/// The size of the file header that the magic bytes must be in.
+pub(super) const BINPRM_BUF_SIZE: usize = 256;
+
+/// Whether the formats are used by `execve`.
+static IS_ENABLED: AtomicBool = AtomicBool::new(true);
+/// The registered formats, which are matched in the order of registration.
+static FORMATS: Mutex<Vec<Arc<BinaryFormat>>> = Mutex::new(Vec::new());
+
+pub fn is_enabled() -> bool {
+    IS_ENABLED.load(Ordering::Relaxed)
+}
+
+pub fn set_enabled(is_enabled: bool) {
+    IS_ENABLED.store(is_enabled, Ordering::Relaxed);
+}
+
+/// Registers a format described by a string of the form
+/// `:name:type:offset:magic:mask:interpreter:flags`.
+///
+/// The first character is the delimiter of the fields, which is usually `:`. The type
+/// is `M` for magic bytes or `E` for an extension. The offset and the mask are optional
+/// for `M`, and must be empty for `E`. The magic bytes and the mask may contain escapes
+/// like `\x7f`. The flags are `P` to preserve `argv[0]`, and `F` to resolve the
+/// interpreter at registration, so that it can be used in other mount namespaces or
+/// chroots.
+pub fn register(rule: &str, fs_resolver: &FsResolver) -> Result<Arc<BinaryFormat>> {
+    let format = Arc::new(BinaryFormat::parse(rule, fs_resolver)?);
+    let mut formats = FORMATS.lock();
+    if formats.iter().any(|other| other.name == format.name) {
+        return_errno_with_message!(Errno::EEXIST, "the format exists");
+    }
+    formats.push(format.clone());
+    Ok(format)
+}
+
+/// Removes the format with the name.
+pub fn unregister(name: &str) -> Result<()> {
+    let mut formats = FORMATS.lock();
+    let Some(pos) = formats.iter().position(|format| format.name == name) else {
+        return_errno_with_message!(Errno::ENOENT, "the format does not exist");
+    };
+    formats.remove(pos);
+    Ok(())
+}
+
+/// Removes all the formats.
+pub fn unregister_all() {
+    FORMATS.lock().clear();
+}
+
+pub fn get_format(name: &str) -> Option<Arc<BinaryFormat>> {
+    FORMATS
+        .lock()
+        .iter()
+        .find(|format| format.name == name)
+        .cloned()
+}
+
+pub fn formats() -> Vec<Arc<BinaryFormat>> {
+    FORMATS.lock().clone()
+}
+
+/// Finds the enabled format that matches the file at the path with the header.
+pub(super) fn match_format(path: &str, file_header: &[u8]) -> Option<Arc<BinaryFormat>> {
+    if !is_enabled() {
+        return None;
+    }
+    FORMATS
+        .lock()
+        .iter()
+        .find(|format| format.is_enabled() && format.matches(path, file_header))
+        .cloned()
+}
+
+/// A registered binary format.
+pub struct BinaryFormat {
+    name: String,
+    matcher: Matcher,
+    interpreter: String,
+    /// The interpreter resolved at registration, if the `F` flag is given.
+    fixed_interpreter: Option<Arc<Dentry>>,
+    preserves_argv0: bool,
+    is_enabled: AtomicBool,
+}
+
+enum Matcher {
+    Magic {
+        offset: usize,
+        magic: Vec<u8>,
+        mask: Option<Vec<u8>>,
+    },
+    Extension(String),
+}
+
+impl BinaryFormat {
+    fn parse(rule: &str, fs_resolver: &FsResolver) -> Result<Self> {
+        let rule = rule.strip_suffix('\n').unwrap_or(rule);
+        if rule.len() > MAX_REGISTER_LEN {
+            return_errno_with_message!(Errno::EINVAL, "the registration string is too long");
+        }
+        let mut chars = rule.chars();
+        let Some(delimiter) = chars.next() else {
+            return_errno_with_message!(Errno::EINVAL, "the registration string is empty");
+        };
+        let fields: Vec<&str> = chars.as_str().split(delimiter).collect();
+        let [name, type_, offset, magic, mask, interpreter, flags] = fields[..] else {
+            return_errno_with_message!(Errno::EINVAL, "the registration string is malformed");
+        };
+
+        if name.is_empty()
+            || name.contains('/')
+            || matches!(name, "." | ".." | "register" | "status")
+        {
+            return_errno_with_message!(Errno::EINVAL, "invalid format name");
+        }
+        let matcher = match type_ {
+            "M" => {
+                let offset = if offset.is_empty() {
+                    0
+                } else {
+                    offset
+                        .parse::<usize>()
+                        .map_err(|_| Error::with_message(Errno::EINVAL, "invalid offset"))?
+                };
+                let magic = unescape(magic)?;
+                let mask = if mask.is_empty() {
+                    None
+                } else {
+                    Some(unescape(mask)?)
+                };
+                if magic.is_empty() || offset + magic.len() > BINPRM_BUF_SIZE {
+                    return_errno_with_message!(Errno::EINVAL, "invalid magic bytes");
+                }
+                if mask.as_ref().is_some_and(|mask| mask.len() != magic.len()) {
+                    return_errno_with_message!(Errno::EINVAL, "the mask does not fit the magic");
+                }
+                Matcher::Magic {
+                    offset,
+                    magic,
+                    mask,
+                }
+            }
+            "E" => {
+                if !offset.is_empty() || !mask.is_empty() {
+                    return_errno_with_message!(Errno::EINVAL, "an extension has no offset or mask");
+                }
+                if magic.is_empty() || magic.contains('/') {
+                    return_errno_with_message!(Errno::EINVAL, "invalid extension");
+                }
+                Matcher::Extension(magic.to_string())
+            }
+            _ => return_errno_with_message!(Errno::EINVAL, "unknown format type"),
+        };
+        if interpreter.is_empty() {
+            return_errno_with_message!(Errno::EINVAL, "the interpreter is empty");
+        }
+
+        let mut preserves_argv0 = false;
+        let mut is_fixed = false;
+        for flag in flags.chars() {
+            match flag {
+                'P' => preserves_argv0 = true,
+                'F' => is_fixed = true,
+                // TODO: Support the `O` and `C` flags, which pass an open file to the
+                // interpreter and take the credentials from the file.
+                _ => return_errno_with_message!(Errno::EINVAL, "unsupported flag"),
+            }
+        }
+        let fixed_interpreter = if is_fixed {
+            Some(fs_resolver.lookup(&FsPath::new(AT_FDCWD, interpreter)?)?)
+        } else {
+            None
+        };
+
+        Ok(Self {
+            name: name.to_string(),
+            matcher,
+            interpreter: interpreter.to_string(),
+            fixed_interpreter,
+            preserves_argv0,
+            is_enabled: AtomicBool::new(true),
+        })
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        self.is_enabled.load(Ordering::Relaxed)
+    }
+
+    pub fn set_enabled(&self, is_enabled: bool) {
+        self.is_enabled.store(is_enabled, Ordering::Relaxed);
+    }
+
+    fn matches(&self, path: &str, file_header: &[u8]) -> bool {
+        match &self.matcher {
+            Matcher::Magic {
+                offset,
+                magic,
+                mask,
+            } => {
+                let Some(bytes) = file_header.get(*offset..*offset + magic.len()) else {
+                    return false;
+                };
+                match mask {
+                    Some(mask) => bytes
+                        .iter()
+                        .zip(mask.iter())
+                        .map(|(byte, mask)| byte & mask)
+                        .eq(magic.iter().copied()),
+                    None => bytes == magic.as_slice(),
+                }
+            }
+            Matcher::Extension(extension) => {
+                let file_name = path.rsplit('/').next().unwrap();
+                file_name
+                    .rsplit_once('.')
+                    .is_some_and(|(_, file_extension)| file_extension == extension)
+            }
+        }
+    }
+
+    /// Returns the interpreter and its arguments to run the file at the path.
+    ///
+    /// The original `argv[0]` is replaced by the path, unless the `P` flag is given.
+    pub(super) fn interpreter_argv(
+        &self,
+        path: &str,
+        argv: &[CString],
+        fs_resolver: &FsResolver,
+    ) -> Result<(Arc<Dentry>, Vec<CString>)> {
+        let interpreter = match &self.fixed_interpreter {
+            Some(interpreter) => interpreter.clone(),
+            None => fs_resolver.lookup(&FsPath::new(AT_FDCWD, &self.interpreter)?)?,
+        };
+
+        let mut new_argv = vec![
+            CString::new(self.interpreter.as_str())?,
+            CString::new(path)?,
+        ];
+        if self.preserves_argv0 {
+            new_argv.extend_from_slice(argv);
+        } else {
+            new_argv.extend_from_slice(argv.get(1..).unwrap_or(&[]));
+        }
+        Ok((interpreter, new_argv))
+    }
+
+    /// Describes the format in the same way as Linux.
+    pub fn describe(&self) -> String {
+        let mut description = String::new();
+        let status = if self.is_enabled() {
+            "enabled"
+        } else {
+            "disabled"
+        };
+        description.push_str(status);
+        description.push_str(&format!("\ninterpreter {}\nflags: ", self.interpreter));
+        if self.preserves_argv0 {
+            description.push('P');
+        }
+        if self.fixed_interpreter.is_some() {
+            description.push('F');
+        }
+        description.push('\n');
+        match &self.matcher {
+            Matcher::Magic {
+                offset,
+                magic,
+                mask,
+            } => {
+                description.push_str(&format!("offset {}\nmagic {}\n", offset, to_hex(magic)));
+                if let Some(mask) = mask {
+                    description.push_str(&format!("mask {}\n", to_hex(mask)));
+                }
+            }
+            Matcher::Extension(extension) => {
+                description.push_str(&format!("extension .{}\n", extension));
+            }
+        }
+        description
+    }
+}
+
+/// Decodes the escapes like `\x7f` and `\\` in the magic bytes or the mask.
+fn unescape(field: &str) -> Result<Vec<u8>> {
+    let mut bytes = Vec::with_capacity(field.len());
+    let mut rest = field.as_bytes();
+    while let Some((&byte, tail)) = rest.split_first() {
+        rest = tail;
+        if byte != b'\\' {
+            bytes.push(byte);
+            continue;
+        }
+        match rest {
+            [b'\\', tail @ ..] => {
+                bytes.push(b'\\');
+                rest = tail;
+            }
+            [b'x', high, low, tail @ ..] => {
+                let digits = core::str::from_utf8(&[*high, *low])
+                    .ok()
+                    .and_then(|digits| u8::from_str_radix(digits, 16).ok())
+                    .ok_or_else(|| Error::with_message(Errno::EINVAL, "invalid escape"))?;
+                bytes.push(digits);
+                rest = tail;
+            }
+            _ => return_errno_with_message!(Errno::EINVAL, "invalid escape"),
+        }
+    }
+    Ok(bytes)
+}
+
+fn to_hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|byte| format!("{:02x}", byte)).collect()
+}
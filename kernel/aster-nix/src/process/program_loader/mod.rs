@@ -1,9 +1,11 @@
 // SPDX-License-Identifier: MPL-2.0
 
+pub mod binfmt_misc;
 pub mod elf;
 mod shebang;
 
 use self::{
+    binfmt_misc::{match_format, BINPRM_BUF_SIZE},
     elf::{load_elf_to_vm, ElfLoadInfo},
     shebang::parse_shebang_line,
 };
@@ -12,6 +14,7 @@ use crate::{
     fs::{
         fs_resolver::{FsPath, FsResolver, AT_FDCWD},
         path::Dentry,
+        utils::Permission,
     },
     prelude::*,
 };
@@ -23,6 +26,7 @@ use crate::{
 /// then it will trigger recursion. We will try to setup root vmar for the interpreter.
 /// I guess for most cases, setting the recursion_limit as 1 should be enough.
 /// because the interpreter is usually an elf binary(e.g., /bin/bash)
+/// The same limit applies to the interpreters of the formats registered in binfmt_misc.
 pub fn load_program_to_vm(
     process_vm: &ProcessVm,
     elf_file: Arc<Dentry>,
@@ -33,11 +37,11 @@ pub fn load_program_to_vm(
 ) -> Result<(String, ElfLoadInfo)> {
     let abs_path = elf_file.abs_path();
     let inode = elf_file.inode();
-    let file_header = {
+    let (file_header, file_header_len) = {
         // read the first page of file header
         let mut file_header_buffer = Box::new([0u8; PAGE_SIZE]);
-        inode.read_at(0, &mut *file_header_buffer)?;
-        file_header_buffer
+        let file_header_len = inode.read_at(0, &mut *file_header_buffer)?;
+        (file_header_buffer, file_header_len)
     };
     if let Some(mut new_argv) = parse_shebang_line(&*file_header)? {
         if recursion_limit == 0 {
@@ -59,6 +63,22 @@ pub fn load_program_to_vm(
             recursion_limit - 1,
         );
     }
+    let binprm_header = &file_header[..file_header_len.min(BINPRM_BUF_SIZE)];
+    if let Some(format) = match_format(&abs_path, binprm_header) {
+        if recursion_limit == 0 {
+            return_errno_with_message!(Errno::ELOOP, "the recursieve limit is reached");
+        }
+        let (interpreter, new_argv) = format.interpreter_argv(&abs_path, &argv, fs_resolver)?;
+        check_executable_file(&interpreter)?;
+        return load_program_to_vm(
+            process_vm,
+            interpreter,
+            new_argv,
+            envp,
+            fs_resolver,
+            recursion_limit - 1,
+        );
+    }
 
     process_vm.clear_and_map();
 
@@ -77,9 +97,7 @@ pub fn check_executable_file(dentry: &Arc<Dentry>) -> Result<()> {
         return_errno_with_message!(Errno::EACCES, "the dentry is not a regular file");
     }
 
-    if !dentry.mode()?.is_executable() {
-        return_errno_with_message!(Errno::EACCES, "the dentry is not executable");
-    }
+    dentry.inode().check_permission(Permission::MAY_EXEC)?;
 
     Ok(())
 }
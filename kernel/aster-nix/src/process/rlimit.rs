@@ -5,6 +5,9 @@
 use super::process_vm::{INIT_STACK_SIZE, USER_HEAP_SIZE_LIMIT};
 use crate::prelude::*;
 
+/// The default limit of the memory that can be locked, which is the same as Linux.
+const MLOCK_LIMIT: u64 = 8 * 1024 * 1024;
+
 pub struct ResourceLimits {
     rlimits: [RLimit64; RLIMIT_COUNT],
 }
@@ -24,6 +27,9 @@ impl Default for ResourceLimits {
         let stack_size = RLimit64::new(INIT_STACK_SIZE as u64);
         let heap_size = RLimit64::new(USER_HEAP_SIZE_LIMIT as u64);
         let open_files = RLimit64::new(1024);
+        let locked_memory = RLimit64::new(MLOCK_LIMIT);
+        // Like Linux, the real-time priorities are only for the privileged by default.
+        let real_time_priority = RLimit64::new(0);
 
         let mut rlimits = Self {
             rlimits: [RLimit64::default(); RLIMIT_COUNT],
@@ -31,6 +37,8 @@ impl Default for ResourceLimits {
         *rlimits.get_rlimit_mut(ResourceType::RLIMIT_STACK) = stack_size;
         *rlimits.get_rlimit_mut(ResourceType::RLIMIT_DATA) = heap_size;
         *rlimits.get_rlimit_mut(ResourceType::RLIMIT_NOFILE) = open_files;
+        *rlimits.get_rlimit_mut(ResourceType::RLIMIT_MEMLOCK) = locked_memory;
+        *rlimits.get_rlimit_mut(ResourceType::RLIMIT_RTPRIO) = real_time_priority;
         rlimits
     }
 }
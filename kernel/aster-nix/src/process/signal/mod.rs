@@ -18,6 +18,7 @@ use core::{mem, sync::atomic::Ordering};
 use align_ext::AlignExt;
 use aster_frame::{cpu::UserContext, user::UserContextApi};
 use c_types::{siginfo_t, ucontext_t};
+use constants::SIGKILL;
 pub use events::{SigEvents, SigEventsFilter};
 pub use pauser::Pauser;
 pub use poll::{Pollee, Poller};
@@ -25,9 +26,11 @@ use sig_action::{SigAction, SigActionFlags, SigDefaultAction};
 use sig_mask::SigMask;
 use sig_num::SigNum;
 pub use sig_stack::{SigStack, SigStackFlags};
+use signals::kernel::KernelSignal;
 
-use super::posix_thread::{PosixThread, PosixThreadExt};
+use super::posix_thread::{PosixThread, PosixThreadExt, PtraceStopKind};
 use crate::{
+    cpu::LinuxAbi,
     prelude::*,
     process::{do_exit_group, TermStatus},
     thread::{status::ThreadStatus, Thread},
@@ -42,21 +45,48 @@ pub trait SignalContext {
 // TODO: This interface of this method is error prone.
 // The method takes an argument for the current thread to optimize its efficiency.
 /// Handle pending signal for current process.
+///
+/// If the current thread has just returned from the syscall of `syscall_number`, the syscall
+/// interrupted with `ERESTARTSYS` is restarted or fails with `EINTR` according to the signal.
 pub fn handle_pending_signal(
     context: &mut UserContext,
     current_thread: &Arc<Thread>,
+    syscall_number: Option<usize>,
 ) -> Result<()> {
     // We first deal with signal in current thread, then signal in current process.
     let posix_thread = current_thread.as_posix_thread().unwrap();
-    let signal = {
-        let sig_mask = *posix_thread.sig_mask().lock();
+    let sig_mask = *posix_thread.sig_mask().lock();
+    let mut signal = {
         if let Some(signal) = posix_thread.dequeue_signal(&sig_mask) {
             signal
         } else {
+            // The signal that interrupts the syscall may have been handled by another thread.
+            handle_syscall_restart(context, syscall_number, true);
             return Ok(());
         }
     };
 
+    // A traced thread stops before a signal other than `SIGKILL` is delivered, and then
+    // the tracer decides the signal to deliver.
+    let ptrace = posix_thread.ptrace();
+    if signal.num() != SIGKILL && ptrace.is_traced() {
+        let kind = PtraceStopKind::Signal(signal.to_info());
+        match ptrace.stop(kind, context, syscall_number) {
+            Some(sig_num) if sig_num == signal.num() => (),
+            Some(sig_num) if sig_mask.contains(sig_num) => {
+                // The signal is blocked, so it is delivered after it is unblocked.
+                posix_thread.enqueue_signal(Box::new(KernelSignal::new(sig_num)));
+                handle_syscall_restart(context, syscall_number, true);
+                return Ok(());
+            }
+            Some(sig_num) => signal = Box::new(KernelSignal::new(sig_num)),
+            None => {
+                handle_syscall_restart(context, syscall_number, true);
+                return Ok(());
+            }
+        }
+    }
+
     let sig_num = signal.num();
     trace!("sig_num = {:?}, sig_name = {}", sig_num, sig_num.sig_name());
     let current = posix_thread.process();
@@ -65,24 +95,32 @@ pub fn handle_pending_signal(
     match sig_action {
         SigAction::Ign => {
             trace!("Ignore signal {:?}", sig_num);
+            handle_syscall_restart(context, syscall_number, true);
         }
         SigAction::User {
             handler_addr,
             flags,
             restorer_addr,
             mask,
-        } => handle_user_signal(
-            sig_num,
-            handler_addr,
-            flags,
-            restorer_addr,
-            mask,
-            context,
-            signal.to_info(),
-        )?,
+        } => {
+            // The context is adjusted before it is saved for the handler, so that the
+            // syscall is restarted after `rt_sigreturn`.
+            let should_restart = flags.contains(SigActionFlags::SA_RESTART);
+            handle_syscall_restart(context, syscall_number, should_restart);
+            handle_user_signal(
+                sig_num,
+                handler_addr,
+                flags,
+                restorer_addr,
+                mask,
+                context,
+                signal.to_info(),
+            )?
+        }
         SigAction::Dfl => {
             let sig_default_action = SigDefaultAction::from_signum(sig_num);
             trace!("sig_default_action: {:?}", sig_default_action);
+            handle_syscall_restart(context, syscall_number, true);
             match sig_default_action {
                 SigDefaultAction::Core | SigDefaultAction::Term => {
                     warn!(
@@ -116,6 +154,28 @@ pub fn handle_pending_signal(
     Ok(())
 }
 
+/// Restarts the syscall of `syscall_number` if it is interrupted with `ERESTARTSYS` and
+/// `should_restart` is true, or makes it fail with `EINTR` otherwise.
+fn handle_syscall_restart(
+    context: &mut UserContext,
+    syscall_number: Option<usize>,
+    should_restart: bool,
+) {
+    let Some(syscall_number) = syscall_number else {
+        return;
+    };
+    if context.syscall_ret() as isize != -(Errno::ERESTARTSYS as isize) {
+        return;
+    }
+
+    if should_restart {
+        debug!("restart syscall {}", syscall_number);
+        context.restart_syscall(syscall_number);
+    } else {
+        context.set_syscall_ret(-(Errno::EINTR as isize) as usize);
+    }
+}
+
 pub fn handle_user_signal(
     sig_num: SigNum,
     handler_addr: Vaddr,
@@ -120,7 +120,16 @@ impl Pauser {
         F: FnMut() -> Option<R>,
     {
         let current_thread = current_thread!();
-        let posix_thread = current_thread.as_posix_thread().unwrap();
+        let Some(posix_thread) = current_thread.as_posix_thread() else {
+            // A kernel thread never receives signals, so it waits only for `cond`.
+            return if let Some(timeout) = timeout {
+                self.wait_queue
+                    .wait_until_or_timeout(cond, timeout)
+                    .ok_or_else(|| Error::with_message(Errno::ETIME, "timeout is reached"))
+            } else {
+                Ok(self.wait_queue.wait_until(cond))
+            };
+        };
 
         // Block `self.sig_mask`
         let (old_mask, filter) = {
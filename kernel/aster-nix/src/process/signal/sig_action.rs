@@ -88,12 +88,10 @@ impl TryFrom<u32> for SigActionFlags {
     type Error = Error;
 
     fn try_from(bits: u32) -> Result<Self> {
-        let flags = SigActionFlags::from_bits(bits)
-            .ok_or_else(|| Error::with_message(Errno::EINVAL, "invalid sig action flag"))?;
-        if flags.contains(SigActionFlags::SA_RESTART) {
-            warn!("SA_RESTART is not supported");
-        }
-        Ok(flags)
+        // `SA_RESTART` only takes effect on the syscalls that fail with `ERESTARTSYS` when
+        // interrupted. The others always fail with `EINTR`.
+        SigActionFlags::from_bits(bits)
+            .ok_or_else(|| Error::with_message(Errno::EINVAL, "invalid sig action flag"))
     }
 }
 
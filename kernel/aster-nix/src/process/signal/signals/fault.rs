@@ -1,9 +1,9 @@
 // SPDX-License-Identifier: MPL-2.0
 
 use aster_frame::cpu::{
-    CpuException, CpuExceptionInfo, ALIGNMENT_CHECK, BOUND_RANGE_EXCEEDED, DIVIDE_BY_ZERO,
-    GENERAL_PROTECTION_FAULT, INVALID_OPCODE, PAGE_FAULT, SIMD_FLOATING_POINT_EXCEPTION,
-    X87_FLOATING_POINT_EXCEPTION,
+    CpuException, CpuExceptionInfo, ALIGNMENT_CHECK, BOUND_RANGE_EXCEEDED, BREAKPOINT, DEBUG,
+    DIVIDE_BY_ZERO, GENERAL_PROTECTION_FAULT, INVALID_OPCODE, PAGE_FAULT,
+    SIMD_FLOATING_POINT_EXCEPTION, X87_FLOATING_POINT_EXCEPTION,
 };
 
 use super::Signal;
@@ -30,10 +30,15 @@ impl FaultSignal {
             BOUND_RANGE_EXCEEDED => (SIGSEGV, SEGV_BNDERR, None),
             ALIGNMENT_CHECK => (SIGBUS, BUS_ADRALN, None),
             INVALID_OPCODE => (SIGILL, ILL_ILLOPC, None),
+            BREAKPOINT => (SIGTRAP, TRAP_BRKPT, None),
+            DEBUG => (SIGTRAP, TRAP_TRACE, None),
             GENERAL_PROTECTION_FAULT => (SIGBUS, BUS_ADRERR, None),
             PAGE_FAULT => {
                 const PF_ERR_FLAG_PRESENT: usize = 1usize << 0;
-                let code = if trap_info.error_code & PF_ERR_FLAG_PRESENT != 0 {
+                const PF_ERR_FLAG_PKEY: usize = 1usize << 5;
+                let code = if trap_info.error_code & PF_ERR_FLAG_PKEY != 0 {
+                    SEGV_PKUERR
+                } else if trap_info.error_code & PF_ERR_FLAG_PRESENT != 0 {
                     SEGV_ACCERR
                 } else {
                     SEGV_MAPERR
@@ -2,8 +2,12 @@
 
 #![allow(dead_code)]
 
-use super::{process_filter::ProcessFilter, ExitCode, Pid, Process};
-use crate::{prelude::*, process::process_table, thread::thread_table};
+use super::{posix_thread::PosixThreadExt, process_filter::ProcessFilter, ExitCode, Pid, Process};
+use crate::{
+    prelude::*,
+    process::process_table,
+    thread::{thread_table, Thread},
+};
 
 // The definition of WaitOptions is from Occlum
 bitflags! {
@@ -14,6 +18,9 @@ bitflags! {
         const WEXITED = 0x4;
         const WCONTINUED = 0x8;
         const WNOWAIT = 0x01000000;
+        /// Waits for all children regardless of their exit signals, which is used by
+        /// debuggers. Every child is waited for now, so the flag has no effect.
+        const __WALL = 0x40000000;
     }
 }
 
@@ -24,12 +31,72 @@ impl WaitOptions {
     }
 }
 
+/// A status change of a child or a tracee that is reported by [`wait_child_exit`].
+pub enum WaitStatus {
+    /// A child process has exited.
+    Zombie(Arc<Process>),
+    /// A tracee has stopped, with the status that tells the reason.
+    PtraceStop { tracee: Arc<Thread>, status: u32 },
+}
+
+impl WaitStatus {
+    /// Returns the ID of the process that has exited, or the ID of the thread that has
+    /// stopped.
+    pub fn pid(&self) -> Pid {
+        match self {
+            WaitStatus::Zombie(process) => process.pid(),
+            WaitStatus::PtraceStop { tracee, .. } => tracee.tid(),
+        }
+    }
+
+    /// Returns the status in the format of `wait4()`.
+    pub fn status(&self) -> u32 {
+        match self {
+            WaitStatus::Zombie(process) => process.exit_code().unwrap(),
+            WaitStatus::PtraceStop { status, .. } => *status,
+        }
+    }
+
+    /// Returns the process that has exited, or the process of the thread that has stopped.
+    pub fn process(&self) -> Arc<Process> {
+        match self {
+            WaitStatus::Zombie(process) => process.clone(),
+            WaitStatus::PtraceStop { tracee, .. } => tracee.as_posix_thread().unwrap().process(),
+        }
+    }
+}
+
 pub fn wait_child_exit(
     child_filter: ProcessFilter,
     wait_options: WaitOptions,
-) -> Result<Option<Arc<Process>>> {
+) -> Result<Option<WaitStatus>> {
     let current = current!();
-    let zombie_child = current.children_pauser().pause_until(|| {
+    let wait_status = current.children_pauser().pause_until(|| {
+        // The stops of the tracees are reported even if `WSTOPPED` is not given.
+        let tracees = current
+            .tracees()
+            .lock()
+            .values()
+            .filter(|tracee| match child_filter {
+                ProcessFilter::Any => true,
+                ProcessFilter::WithPid(pid) => tracee.tid() == pid,
+                ProcessFilter::WithPgid(pgid) => {
+                    tracee.as_posix_thread().unwrap().process().pgid() == pgid
+                }
+            })
+            .cloned()
+            .collect::<Vec<_>>();
+        let consumes = !wait_options.contains(WaitOptions::WNOWAIT);
+        for tracee in tracees.iter() {
+            let ptrace = tracee.as_posix_thread().unwrap().ptrace();
+            if let Some(status) = ptrace.take_unreported_status(&current, consumes) {
+                return Some(Ok(Some(WaitStatus::PtraceStop {
+                    tracee: tracee.clone(),
+                    status,
+                })));
+            }
+        }
+
         let unwaited_children = current
             .children()
             .lock()
@@ -42,7 +109,7 @@ pub fn wait_child_exit(
             .cloned()
             .collect::<Vec<_>>();
 
-        if unwaited_children.is_empty() {
+        if unwaited_children.is_empty() && tracees.is_empty() {
             return Some(Err(Error::with_message(
                 Errno::ECHILD,
                 "the process has no child to wait",
@@ -56,10 +123,10 @@ pub fn wait_child_exit(
             let zombie_pid = zombie_child.pid();
             if wait_options.contains(WaitOptions::WNOWAIT) {
                 // does not reap child, directly return
-                return Some(Ok(Some(zombie_child.clone())));
+                return Some(Ok(Some(WaitStatus::Zombie(zombie_child.clone()))));
             } else {
                 reap_zombie_child(&current, zombie_pid);
-                return Some(Ok(Some(zombie_child.clone())));
+                return Some(Ok(Some(WaitStatus::Zombie(zombie_child.clone()))));
             }
         }
 
@@ -71,7 +138,7 @@ pub fn wait_child_exit(
         None
     })??;
 
-    Ok(zombie_child)
+    Ok(wait_status)
 }
 
 /// Free zombie child with pid, returns the exit code of child process.
@@ -0,0 +1,920 @@
+// SPDX-License-Identifier: MPL-2.0
+
+use aster_frame::{
+    arch::{read_tsc, tsc_freq},
+    cpu::{llc_id, num_cpus, numa_node, this_cpu},
+    task::{current_task, resched_cpu, set_scheduler, SchedPolicy, Scheduler, Task, TaskStatus},
+};
+
+use super::real_time::RR_TIME_SLICE;
+use crate::prelude::*;
+
+pub fn init() {
+    let fair_scheduler = Box::new(FairScheduler::new());
+    let scheduler = Box::<FairScheduler>::leak(fair_scheduler);
+    set_scheduler(scheduler);
+}
+
+/// The weight of a task with the nice value 0.
+const NICE_0_WEIGHT: u64 = 1024;
+
+/// The weights of the tasks with the nice values from -20 to 19, which are the same as
+/// those of Linux. A task gets about 10% more CPU time than a task whose nice value is
+/// larger by one.
+const NICE_TO_WEIGHT: [u64; 40] = [
+    88761, 71755, 56483, 46273, 36291, 29154, 23254, 18705, 14949, 11916, 9548, 7620, 6100, 4904,
+    3906, 3121, 2501, 1991, 1586, 1277, 1024, 820, 655, 526, 423, 335, 272, 215, 172, 137, 110, 87,
+    70, 56, 45, 36, 29, 23, 18, 15,
+];
+
+/// The period in nanoseconds, in which each normal task runs once if there are not too
+/// many of them.
+const SCHED_LATENCY: u64 = 6_000_000;
+/// The minimum time slice in nanoseconds.
+const MIN_GRANULARITY: u64 = 750_000;
+/// The lead in the virtual runtime that a task needs to preempt the running task.
+const WAKEUP_GRANULARITY: u64 = 1_000_000;
+/// The number of the real-time priorities, which range from 0 to 99.
+const NR_REAL_TIME_PRIORITIES: usize = 100;
+/// The number of the ticks, after which the load of a runqueue decays to about 1/e in
+/// the tracked load.
+const LOAD_AVG_PERIOD: u64 = 32;
+/// The number of the ticks between two periodic load balancing of a CPU.
+const BALANCE_INTERVAL: u64 = 4;
+/// The percentage, by which the load of the busiest runqueue must exceed that of this
+/// CPU, for the tasks to be pulled.
+const IMBALANCE_PCT: u64 = 125;
+/// The maximum number of the tasks that are pulled in a load balancing.
+const MAX_MIGRATIONS: usize = 8;
+
+/// The fair scheduler, which has a runqueue for each CPU.
+///
+/// The real-time tasks are always scheduled before the normal tasks, in the order of
+/// their priorities. A real-time task runs until it sleeps, yields, or is preempted by a
+/// task of a higher priority, and a task scheduled with [`SchedPolicy::RoundRobin`] also
+/// gives the CPU to the tasks of the same priority after its time slice.
+///
+/// The normal tasks share the CPU time in proportion to their weights, which are given by
+/// their nice values. The runtime of a normal task is scaled inversely by its weight as
+/// the virtual runtime, and the task with the smallest virtual runtime runs next, for a
+/// time slice that is also in proportion to its weight. So a normal task waits for at
+/// most a period, regardless of whether the others keep running or waking up.
+///
+/// The tasks are balanced between the runqueues, in the CPUs that share the last-level
+/// cache first, then in the NUMA node, and then in the system. A woken task prefers an
+/// idle CPU near its last one, a CPU that becomes idle pulls the tasks at once, and the
+/// others pull the tasks periodically. The tasks are only moved to the CPUs in their
+/// affinity.
+struct FairScheduler {
+    run_queues: Vec<SpinLock<RunQueue>>,
+}
+
+struct RunQueue {
+    real_time_tasks: RealTimeQueue,
+    /// The normal tasks, which are ordered by their virtual runtimes, and then by their
+    /// addresses.
+    fair_tasks: BTreeMap<(u64, usize), Arc<Task>>,
+    /// The lower bound of the virtual runtimes of the normal tasks, which never decreases.
+    min_vruntime: u64,
+    /// The total weight of the normal tasks in the runqueue.
+    load: u64,
+    /// The load of the runqueue, including the running task, which is tracked in the timer
+    /// ticks with an exponential decay. The value is scaled by [`LOAD_AVG_PERIOD`].
+    load_sum: u64,
+    /// The task that is picked to run on the CPU the last time.
+    curr: Option<Arc<Task>>,
+    /// The number of the ticks since the last periodic load balancing.
+    balance_ticks: u64,
+}
+
+/// The CPUs that the tasks are balanced between, from the nearest to the farthest.
+#[derive(Clone, Copy)]
+enum Domain {
+    /// The CPUs that share the last-level cache.
+    Llc,
+    /// The CPUs in the same NUMA node.
+    Node,
+    /// All the CPUs.
+    System,
+}
+
+impl Domain {
+    const ALL: [Self; 3] = [Self::Llc, Self::Node, Self::System];
+
+    fn contains(self, this_cpu: usize, cpu: usize) -> bool {
+        match self {
+            Self::Llc => llc_id(this_cpu as u32) == llc_id(cpu as u32),
+            Self::Node => numa_node(this_cpu as u32) == numa_node(cpu as u32),
+            Self::System => true,
+        }
+    }
+}
+
+impl FairScheduler {
+    fn new() -> Self {
+        Self {
+            run_queues: (0..num_cpus())
+                .map(|_| SpinLock::new(RunQueue::new()))
+                .collect(),
+        }
+    }
+
+    /// Selects the runqueue of a task to be enqueued, which is of a CPU in its affinity.
+    ///
+    /// A task that has run stays on its CPU, whose caches may be still hot, unless the CPU
+    /// is busy but a CPU near it is idle, or can be preempted by a real-time task. A new
+    /// task, or a task that cannot run on its CPU any more, goes to the runqueue with the
+    /// least load, preferring the NUMA node of the current CPU.
+    fn select_cpu(&self, task: &Arc<Task>) -> usize {
+        let entity = task.sched_entity();
+        let prev_cpu = entity.cpu() as usize;
+        if entity.sum_exec_runtime() > 0 && task.can_run_on(prev_cpu as u32) {
+            if self.run_queues[prev_cpu].lock_irq_disabled().is_idle() {
+                return prev_cpu;
+            }
+            let nearest_domain = if task.is_real_time() {
+                Domain::System
+            } else {
+                Domain::Llc
+            };
+            return self
+                .allowed_cpus(task)
+                .filter(|&cpu| nearest_domain.contains(prev_cpu, cpu))
+                .filter(|&cpu| {
+                    let run_queue = self.run_queues[cpu].lock_irq_disabled();
+                    run_queue.is_idle() || task.is_real_time() && run_queue.should_preempt(task)
+                })
+                .min_by_key(|&cpu| Domain::ALL.map(|domain| !domain.contains(prev_cpu, cpu)))
+                .unwrap_or(prev_cpu);
+        }
+
+        let this_cpu = this_cpu() as usize;
+        self.allowed_cpus(task)
+            .min_by_key(|&cpu| {
+                let load_sum = self.run_queues[cpu].lock_irq_disabled().load_sum;
+                (
+                    load_sum,
+                    !Domain::Node.contains(this_cpu, cpu),
+                    cpu != this_cpu,
+                )
+            })
+            // The affinity is never empty, except for the CPUs that do not exist.
+            .unwrap_or(this_cpu)
+    }
+
+    fn allowed_cpus<'a>(&'a self, task: &'a Arc<Task>) -> impl Iterator<Item = usize> + 'a {
+        (0..self.run_queues.len()).filter(|&cpu| task.can_run_on(cpu as u32))
+    }
+
+    /// Pulls the tasks from the busiest runqueue to that of this CPU, if they are
+    /// imbalanced.
+    ///
+    /// The busiest runqueue is searched from the nearest domain to the farthest one, so
+    /// that the tasks are moved as near as possible.
+    fn balance(&self, this_cpu: usize, is_idle: bool) {
+        let this_load = self.run_queues[this_cpu]
+            .lock_irq_disabled()
+            .runnable_load();
+        for domain in Domain::ALL {
+            let busiest = (0..self.run_queues.len())
+                .filter(|&cpu| cpu != this_cpu && domain.contains(this_cpu, cpu))
+                .map(|cpu| {
+                    let run_queue = self.run_queues[cpu].lock_irq_disabled();
+                    (cpu, run_queue.runnable_load(), run_queue.nr_queued())
+                })
+                .max_by_key(|&(_, load, _)| load);
+            let Some((busiest_cpu, busiest_load, nr_queued)) = busiest else {
+                continue;
+            };
+            // A runqueue that has no queued tasks has nothing to be pulled, and a
+            // runqueue is not balanced if the pulled tasks will make it busier than
+            // the busiest one.
+            if nr_queued == 0 || busiest_load * 100 <= this_load * IMBALANCE_PCT {
+                continue;
+            }
+            let max_load = (busiest_load - this_load) / 2;
+            if self.pull_tasks(busiest_cpu, this_cpu, max_load, is_idle) > 0 {
+                return;
+            }
+        }
+    }
+
+    /// Moves the queued tasks from the runqueue of `src_cpu` to that of `dst_cpu`, whose
+    /// total weight is at most `max_load`, and returns the number of the moved tasks.
+    ///
+    /// A real-time task is only moved to an idle CPU, where it runs next.
+    fn pull_tasks(&self, src_cpu: usize, dst_cpu: usize, max_load: u64, is_idle: bool) -> usize {
+        // The runqueues are locked in the order of the CPUs to avoid deadlocks.
+        let (mut src, mut dst) = if src_cpu < dst_cpu {
+            let src = self.run_queues[src_cpu].lock_irq_disabled();
+            (src, self.run_queues[dst_cpu].lock_irq_disabled())
+        } else {
+            let dst = self.run_queues[dst_cpu].lock_irq_disabled();
+            (self.run_queues[src_cpu].lock_irq_disabled(), dst)
+        };
+
+        if is_idle {
+            let task = src
+                .real_time_tasks
+                .remove_highest_if(|task| task.can_run_on(dst_cpu as u32));
+            if let Some(task) = task {
+                task.sched_entity().set_cpu(dst_cpu as u32);
+                dst.real_time_tasks.push_back(task);
+                return 1;
+            }
+        }
+
+        // The tasks with the largest virtual runtimes are moved, which run the last in
+        // the source runqueue, and whose caches are the coldest.
+        let mut moved_load = 0;
+        let tasks: Vec<Arc<Task>> = src
+            .fair_tasks
+            .values()
+            .rev()
+            .filter(|task| task.can_run_on(dst_cpu as u32))
+            .filter(|task| {
+                let weight = weight(task);
+                if moved_load + weight > max_load {
+                    return false;
+                }
+                moved_load += weight;
+                true
+            })
+            .take(MAX_MIGRATIONS)
+            .cloned()
+            .collect();
+        for task in tasks.iter() {
+            src.dequeue_fair(task);
+            // The virtual runtime is relative to the minimum one of the runqueue.
+            let entity = task.sched_entity();
+            let vruntime = entity.vruntime().saturating_sub(src.min_vruntime);
+            entity.set_vruntime(vruntime + dst.min_vruntime);
+            entity.set_cpu(dst_cpu as u32);
+            dst.enqueue_fair(task.clone());
+        }
+        tasks.len()
+    }
+
+    fn this_run_queue(&self) -> &SpinLock<RunQueue> {
+        &self.run_queues[this_cpu() as usize]
+    }
+}
+
+impl Scheduler for FairScheduler {
+    fn enqueue(&self, task: Arc<Task>) {
+        let cpu = self.select_cpu(&task);
+        task.sched_entity().set_cpu(cpu as u32);
+        let mut run_queue = self.run_queues[cpu].lock_irq_disabled();
+        // The preemption of the current CPU is decided by the framework.
+        let should_resched = cpu != this_cpu() as usize && run_queue.should_preempt(&task);
+        if task.is_real_time() {
+            run_queue.real_time_tasks.push_back(task);
+        } else {
+            run_queue.enqueue_fair(task);
+        }
+        drop(run_queue);
+
+        if should_resched {
+            resched_cpu(cpu as u32);
+        }
+    }
+
+    fn pick_next(&self) -> Option<Arc<Task>> {
+        let mut run_queue = self.this_run_queue().lock_irq_disabled();
+        // The current task is enqueued again after the next task is picked, if it is still
+        // runnable, so its runtime is accounted here.
+        if let Some(current) = current_task() {
+            run_queue.update_curr(&current, now());
+        }
+
+        if run_queue.nr_queued() == 0 {
+            drop(run_queue);
+            self.balance(this_cpu() as usize, true);
+            run_queue = self.this_run_queue().lock_irq_disabled();
+        }
+        run_queue.pick_next(now())
+    }
+
+    fn dequeue(&self, task: &Arc<Task>) -> bool {
+        // The task may be moved to another runqueue by a load balancing before the
+        // runqueue is locked, which changes its CPU with both runqueues locked.
+        let mut run_queue = loop {
+            let cpu = task.sched_entity().cpu() as usize;
+            let run_queue = self.run_queues[cpu].lock_irq_disabled();
+            if task.sched_entity().cpu() as usize == cpu {
+                break run_queue;
+            }
+        };
+        if task.is_real_time() {
+            run_queue.real_time_tasks.remove(task)
+        } else {
+            run_queue.dequeue_fair(task)
+        }
+    }
+
+    fn tick(&self, current: &Arc<Task>) -> bool {
+        let mut run_queue = self.this_run_queue().lock_irq_disabled();
+        run_queue.update_curr(current, now());
+        run_queue.update_load(current);
+
+        run_queue.balance_ticks += 1;
+        if run_queue.balance_ticks >= BALANCE_INTERVAL {
+            run_queue.balance_ticks = 0;
+            drop(run_queue);
+            self.balance(this_cpu() as usize, false);
+            run_queue = self.this_run_queue().lock_irq_disabled();
+        }
+        run_queue.is_slice_expired(current)
+    }
+
+    fn should_preempt(&self, task: &Arc<Task>) -> bool {
+        self.this_run_queue()
+            .lock_irq_disabled()
+            .is_preempted_by_queued(task)
+    }
+}
+
+impl RunQueue {
+    fn new() -> Self {
+        Self {
+            real_time_tasks: RealTimeQueue::new(),
+            fair_tasks: BTreeMap::new(),
+            min_vruntime: 0,
+            load: 0,
+            load_sum: 0,
+            curr: None,
+            balance_ticks: 0,
+        }
+    }
+
+    /// Returns the number of the queued tasks, excluding the running one.
+    fn nr_queued(&self) -> usize {
+        self.real_time_tasks.len() + self.fair_tasks.len()
+    }
+
+    /// Returns whether the CPU runs no task and has no queued tasks.
+    fn is_idle(&self) -> bool {
+        self.nr_queued() == 0 && !self.is_curr_running()
+    }
+
+    fn is_curr_running(&self) -> bool {
+        self.curr
+            .as_ref()
+            .is_some_and(|curr| curr.status() == TaskStatus::Runnable)
+    }
+
+    /// Returns the total weight of the normal tasks, including the running one.
+    fn runnable_load(&self) -> u64 {
+        let curr_load = match &self.curr {
+            Some(curr) if !curr.is_real_time() && self.is_curr_running() => weight(curr),
+            _ => 0,
+        };
+        self.load + curr_load
+    }
+
+    /// Tells whether the task should preempt the task that runs on the CPU, which is
+    /// used to decide the preemption of another CPU.
+    fn should_preempt(&self, task: &Arc<Task>) -> bool {
+        let Some(curr) = self.curr.as_ref().filter(|_| self.is_curr_running()) else {
+            return true;
+        };
+        match (task.is_real_time(), curr.is_real_time()) {
+            (true, true) => task.priority() < curr.priority(),
+            (true, false) => true,
+            (false, true) => false,
+            (false, false) => {
+                task.sched_entity().vruntime() + WAKEUP_GRANULARITY < curr.sched_entity().vruntime()
+            }
+        }
+    }
+
+    fn enqueue_fair(&mut self, task: Arc<Task>) {
+        let entity = task.sched_entity();
+        // A new task starts with the smallest virtual runtime, and a task that has slept
+        // is credited half a period, so that they neither starve the others nor are
+        // starved by them.
+        let vruntime = if entity.sum_exec_runtime() == 0 {
+            entity.vruntime().max(self.min_vruntime)
+        } else {
+            entity
+                .vruntime()
+                .max(self.min_vruntime.saturating_sub(SCHED_LATENCY / 2))
+        };
+        entity.set_vruntime(vruntime);
+
+        self.load += weight(&task);
+        self.fair_tasks.insert(fair_key(&task), task);
+    }
+
+    fn dequeue_fair(&mut self, task: &Arc<Task>) -> bool {
+        if self.fair_tasks.remove(&fair_key(task)).is_none() {
+            return false;
+        }
+        self.load -= weight(task);
+        true
+    }
+
+    /// Picks the next task to run, which starts its time slice at `now`.
+    fn pick_next(&mut self, now: u64) -> Option<Arc<Task>> {
+        let next = match self.real_time_tasks.pop_highest() {
+            Some(task) => task,
+            None => self.pick_fair()?,
+        };
+        next.sched_entity().start_slice(now);
+        self.curr = Some(next.clone());
+        Some(next)
+    }
+
+    /// Tells whether the running task should give the CPU to the queued tasks, after its
+    /// runtime is accounted in a timer tick.
+    fn is_slice_expired(&self, current: &Arc<Task>) -> bool {
+        if current.is_real_time() {
+            let priority = current.priority().get();
+            let Some(highest_priority) = self.real_time_tasks.highest_priority() else {
+                return false;
+            };
+            if highest_priority < priority {
+                return true;
+            }
+            // A task scheduled with the FIFO policy runs until it sleeps or yields.
+            return current.sched_policy() == SchedPolicy::RoundRobin
+                && highest_priority == priority
+                && current.sched_entity().slice_runtime() >= RR_TIME_SLICE.as_nanos() as u64;
+        }
+        if !self.real_time_tasks.is_empty() {
+            return true;
+        }
+        if self.fair_tasks.is_empty() {
+            return false;
+        }
+        current.sched_entity().slice_runtime() >= self.time_slice(weight(current))
+    }
+
+    /// Tells whether the running task should be preempted by the queued tasks, e.g., after
+    /// a task is woken up.
+    fn is_preempted_by_queued(&self, current: &Arc<Task>) -> bool {
+        if current.is_real_time() {
+            // Only a task of a strictly higher priority preempts a real-time task.
+            return self
+                .real_time_tasks
+                .highest_priority()
+                .is_some_and(|priority| priority < current.priority().get());
+        }
+        if !self.real_time_tasks.is_empty() {
+            return true;
+        }
+        // A woken task preempts the running task only if it is behind by enough, so that
+        // the tasks do not switch too often.
+        self.fair_tasks
+            .first_key_value()
+            .is_some_and(|(&(vruntime, _), _)| {
+                vruntime + WAKEUP_GRANULARITY < current.sched_entity().vruntime()
+            })
+    }
+
+    fn pick_fair(&mut self) -> Option<Arc<Task>> {
+        let (_, task) = self.fair_tasks.pop_first()?;
+        self.load -= weight(&task);
+        self.update_min_vruntime(Some(task.sched_entity().vruntime()));
+        Some(task)
+    }
+
+    /// Accounts the runtime of the running task until `now` since it is accounted the last
+    /// time.
+    fn update_curr(&mut self, current: &Arc<Task>, now: u64) {
+        let entity = current.sched_entity();
+        let delta = now.saturating_sub(entity.exec_start());
+        entity.set_exec_start(now);
+        entity.add_exec_runtime(delta);
+        if current.is_real_time() {
+            return;
+        }
+
+        let vruntime = entity.vruntime() + delta * NICE_0_WEIGHT / weight(current);
+        entity.set_vruntime(vruntime);
+        self.update_min_vruntime(Some(vruntime));
+    }
+
+    /// Advances the minimum virtual runtime to the smallest one of the normal tasks,
+    /// including the running one, whose virtual runtime is `curr_vruntime`.
+    fn update_min_vruntime(&mut self, curr_vruntime: Option<u64>) {
+        let leftmost = self
+            .fair_tasks
+            .first_key_value()
+            .map(|(&(vruntime, _), _)| vruntime);
+        let min_vruntime = match (leftmost, curr_vruntime) {
+            (Some(leftmost), Some(curr)) => leftmost.min(curr),
+            (Some(vruntime), None) | (None, Some(vruntime)) => vruntime,
+            (None, None) => return,
+        };
+        self.min_vruntime = self.min_vruntime.max(min_vruntime);
+    }
+
+    fn update_load(&mut self, current: &Arc<Task>) {
+        let curr_load = if current.is_real_time() {
+            0
+        } else {
+            weight(current)
+        };
+        self.load_sum = self.load_sum - self.load_sum / LOAD_AVG_PERIOD + self.load + curr_load;
+    }
+
+    /// Returns the time slice of the running normal task with the `weight`, which is its
+    /// share of the period.
+    fn time_slice(&self, weight: u64) -> u64 {
+        let nr_tasks = self.fair_tasks.len() as u64 + 1;
+        let period = SCHED_LATENCY.max(nr_tasks * MIN_GRANULARITY);
+        (period * weight / (self.load + weight)).max(MIN_GRANULARITY)
+    }
+}
+
+/// The real-time tasks of a runqueue, which has a FIFO queue for each real-time priority.
+struct RealTimeQueue {
+    queues: [VecDeque<Arc<Task>>; NR_REAL_TIME_PRIORITIES],
+    /// The bitmap of the priorities whose queues are not empty.
+    bitmap: u128,
+}
+
+impl RealTimeQueue {
+    fn new() -> Self {
+        Self {
+            queues: core::array::from_fn(|_| VecDeque::new()),
+            bitmap: 0,
+        }
+    }
+
+    fn is_empty(&self) -> bool {
+        self.bitmap == 0
+    }
+
+    fn len(&self) -> usize {
+        self.queues.iter().map(VecDeque::len).sum()
+    }
+
+    /// Returns the value of the highest priority of the tasks, if any.
+    fn highest_priority(&self) -> Option<u16> {
+        if self.is_empty() {
+            return None;
+        }
+        Some(self.bitmap.trailing_zeros() as u16)
+    }
+
+    fn push_back(&mut self, task: Arc<Task>) {
+        let priority = task.priority().get() as usize;
+        self.queues[priority].push_back(task);
+        self.bitmap |= 1 << priority;
+    }
+
+    /// Pops the first task of the highest priority.
+    fn pop_highest(&mut self) -> Option<Arc<Task>> {
+        let priority = self.highest_priority()? as usize;
+        let task = self.queues[priority].pop_front();
+        self.update_bitmap(priority);
+        task
+    }
+
+    /// Removes the first task of the highest priority that satisfies the predicate.
+    fn remove_highest_if(&mut self, predicate: impl Fn(&Arc<Task>) -> bool) -> Option<Arc<Task>> {
+        let mut bitmap = self.bitmap;
+        while bitmap != 0 {
+            let priority = bitmap.trailing_zeros() as usize;
+            bitmap &= !(1 << priority);
+            let queue = &mut self.queues[priority];
+            if let Some(index) = queue.iter().position(&predicate) {
+                let task = queue.remove(index);
+                self.update_bitmap(priority);
+                return task;
+            }
+        }
+        None
+    }
+
+    fn remove(&mut self, task: &Arc<Task>) -> bool {
+        let priority = task.priority().get() as usize;
+        let queue = &mut self.queues[priority];
+        let Some(index) = queue.iter().position(|queued| Arc::ptr_eq(queued, task)) else {
+            return false;
+        };
+        queue.remove(index);
+        self.update_bitmap(priority);
+        true
+    }
+
+    fn update_bitmap(&mut self, priority: usize) {
+        if self.queues[priority].is_empty() {
+            self.bitmap &= !(1 << priority);
+        }
+    }
+}
+
+/// Returns the key of a normal task in the runqueue.
+///
+/// The virtual runtime of a task is not changed while it is in the runqueue, so the key
+/// stays the same.
+fn fair_key(task: &Arc<Task>) -> (u64, usize) {
+    (task.sched_entity().vruntime(), Arc::as_ptr(task) as usize)
+}
+
+/// Returns the weight of a normal task, whose priority is 120 plus its nice value.
+fn weight(task: &Task) -> u64 {
+    let priority = task.priority().get().clamp(100, 139);
+    NICE_TO_WEIGHT[(priority - 100) as usize]
+}
+
+/// Returns the time since boot in nanoseconds.
+fn now() -> u64 {
+    (read_tsc() as u128 * 1_000_000_000 / tsc_freq().max(1) as u128) as u64
+}
+
+#[cfg(ktest)]
+mod test {
+    use aster_frame::{
+        cpu::CpuSet,
+        task::{Priority, TaskOptions},
+    };
+
+    use super::*;
+
+    /// The interval of the timer ticks in the simulations.
+    const TICK: u64 = 1_000_000;
+    /// The interval of the steps in the simulations, in which the tasks are woken up and
+    /// finish their bursts.
+    const STEP: u64 = 100_000;
+
+    /// A normal task in the simulation of a runqueue.
+    struct SimTask {
+        task: Arc<Task>,
+        /// The time that the task runs before it sleeps, or `None` if it never sleeps.
+        burst: Option<u64>,
+        /// The time that the task sleeps after a burst.
+        sleep: u64,
+        /// The time that the task has run in the current burst.
+        burst_runtime: u64,
+        /// The time when the task becomes runnable and starts to wait, if it is queued.
+        ready_at: Option<u64>,
+        /// The time when the task wakes up, if it is sleeping.
+        wake_at: Option<u64>,
+        /// The longest time that the task waits in the runqueue.
+        max_wait: u64,
+    }
+
+    impl SimTask {
+        fn new(priority: Priority, burst: Option<u64>, sleep: u64) -> Self {
+            let task = TaskOptions::new(|| {})
+                .data(())
+                .priority(priority)
+                .build()
+                .unwrap();
+            Self {
+                task,
+                burst,
+                sleep,
+                burst_runtime: 0,
+                ready_at: Some(0),
+                wake_at: None,
+                max_wait: 0,
+            }
+        }
+
+        fn hog(priority: Priority) -> Self {
+            Self::new(priority, None, 0)
+        }
+
+        fn interactive(burst: u64, sleep: u64) -> Self {
+            Self::new(Priority::normal(), Some(burst), sleep)
+        }
+
+        fn runtime(&self) -> u64 {
+            self.task.sched_entity().sum_exec_runtime()
+        }
+    }
+
+    /// Simulates a CPU that runs the tasks for `duration` nanoseconds, as the framework
+    /// does with the timer ticks, the wakeups and the context switches.
+    fn simulate(tasks: &mut [SimTask], duration: u64) {
+        let mut run_queue = RunQueue::new();
+        for sim_task in tasks.iter() {
+            run_queue.enqueue_fair(sim_task.task.clone());
+        }
+
+        let mut curr: Option<usize> = None;
+        let mut now = 0;
+        while now < duration {
+            let mut need_resched = curr.is_none();
+
+            for sim_task in tasks.iter_mut() {
+                if sim_task.wake_at.is_some_and(|wake_at| wake_at <= now) {
+                    sim_task.wake_at = None;
+                    sim_task.ready_at = Some(now);
+                    run_queue.enqueue_fair(sim_task.task.clone());
+                    need_resched = true;
+                }
+            }
+            if let Some(i) = curr {
+                let current = &tasks[i].task;
+                run_queue.update_curr(current, now);
+                if need_resched && !run_queue.is_preempted_by_queued(current) {
+                    need_resched = false;
+                }
+                if now % TICK == 0 && run_queue.is_slice_expired(current) {
+                    need_resched = true;
+                }
+                if tasks[i]
+                    .burst
+                    .is_some_and(|burst| tasks[i].burst_runtime >= burst)
+                {
+                    tasks[i].burst_runtime = 0;
+                    tasks[i].wake_at = Some(now + tasks[i].sleep);
+                    curr = None;
+                    need_resched = true;
+                }
+            }
+
+            if need_resched {
+                if let Some(next) = run_queue.pick_next(now) {
+                    let next = tasks
+                        .iter()
+                        .position(|sim_task| Arc::ptr_eq(&sim_task.task, &next))
+                        .unwrap();
+                    if let Some(prev) = curr {
+                        tasks[prev].ready_at = Some(now);
+                        run_queue.enqueue_fair(tasks[prev].task.clone());
+                    }
+                    let ready_at = tasks[next].ready_at.take().unwrap();
+                    tasks[next].max_wait = tasks[next].max_wait.max(now - ready_at);
+                    curr = Some(next);
+                }
+            }
+
+            now += STEP;
+            if let Some(i) = curr {
+                tasks[i].burst_runtime += STEP;
+            }
+        }
+        if let Some(i) = curr {
+            run_queue.update_curr(&tasks[i].task, now);
+        }
+    }
+
+    #[ktest]
+    fn share_by_weights() {
+        let mut tasks = [
+            SimTask::hog(Priority::normal()),
+            SimTask::hog(Priority::normal()),
+            // The nice value 5.
+            SimTask::hog(Priority::new(125)),
+        ];
+        simulate(&mut tasks, 3_000_000_000);
+
+        let total_weight: u64 = tasks.iter().map(|sim_task| weight(&sim_task.task)).sum();
+        let total_runtime: u64 = tasks.iter().map(SimTask::runtime).sum();
+        for sim_task in tasks.iter() {
+            let expected = total_runtime * weight(&sim_task.task) / total_weight;
+            let runtime = sim_task.runtime();
+            assert!(
+                runtime.abs_diff(expected) <= expected / 10,
+                "runtime {} is far from the share {}",
+                runtime,
+                expected
+            );
+        }
+    }
+
+    #[ktest]
+    fn hogs_wait_for_at_most_a_period() {
+        const NR_HOGS: usize = 8;
+
+        let mut tasks: Vec<SimTask> = (0..NR_HOGS)
+            .map(|_| SimTask::hog(Priority::normal()))
+            .collect();
+        simulate(&mut tasks, 1_000_000_000);
+
+        // Each hog runs for at least a tick, since the time slices end at the ticks.
+        let period = SCHED_LATENCY.max(NR_HOGS as u64 * MIN_GRANULARITY.max(TICK));
+        for sim_task in tasks.iter() {
+            assert!(
+                sim_task.max_wait <= period,
+                "a hog waits for {} ns",
+                sim_task.max_wait
+            );
+        }
+    }
+
+    #[ktest]
+    fn interactive_tasks_are_not_starved_by_hog() {
+        const BURST: u64 = 200_000;
+        const SLEEP: u64 = 3_000_000;
+        const DURATION: u64 = 2_000_000_000;
+
+        let mut tasks = vec![SimTask::hog(Priority::normal())];
+        tasks.extend((0..4).map(|_| SimTask::interactive(BURST, SLEEP)));
+        simulate(&mut tasks, DURATION);
+
+        // A woken task preempts the hog at once or in its next time slice.
+        for sim_task in tasks[1..].iter() {
+            assert!(
+                sim_task.max_wait <= SCHED_LATENCY,
+                "an interactive task waits for {} ns",
+                sim_task.max_wait
+            );
+            assert!(sim_task.runtime() >= DURATION / (BURST + SLEEP) / 2 * BURST);
+        }
+        // Neither is the hog starved by the interactive tasks.
+        assert!(tasks[0].runtime() >= DURATION / 2);
+    }
+
+    #[ktest]
+    fn tick_and_wakeup_preempt_current() {
+        let scheduler = FairScheduler::new();
+        let cpu = this_cpu() as usize;
+        let current = SimTask::hog(Priority::normal()).task;
+        let woken = SimTask::hog(Priority::normal()).task;
+
+        // The tasks are put in the runqueue of this CPU directly, since the scheduler may
+        // select another CPU for them.
+        let picked = {
+            let mut run_queue = scheduler.run_queues[cpu].lock_irq_disabled();
+            current.sched_entity().set_cpu(cpu as u32);
+            run_queue.enqueue_fair(current.clone());
+            run_queue.pick_next(now()).unwrap()
+        };
+        assert!(Arc::ptr_eq(&picked, &current));
+
+        // A task running alone is never preempted.
+        assert!(!scheduler.tick(&current));
+        assert!(!scheduler.should_preempt(&current));
+
+        // A woken task that is not behind by enough does not preempt the current task at once.
+        woken.sched_entity().set_cpu(cpu as u32);
+        scheduler.run_queues[cpu]
+            .lock_irq_disabled()
+            .enqueue_fair(woken.clone());
+        assert!(!scheduler.should_preempt(&current));
+
+        // After the current task has run for longer than its time slice, the tick asks it
+        // to be switched out, and the woken task is behind by enough to preempt it.
+        let entity = current.sched_entity();
+        entity.set_exec_start(entity.exec_start().saturating_sub(2 * SCHED_LATENCY));
+        assert!(scheduler.tick(&current));
+        assert!(scheduler.should_preempt(&current));
+
+        // A real-time task preempts a normal task at once.
+        let real_time = TaskOptions::new(|| {})
+            .data(())
+            .priority(Priority::new(50))
+            .build()
+            .unwrap();
+        let mut run_queue = scheduler.run_queues[cpu].lock_irq_disabled();
+        run_queue.dequeue_fair(&woken);
+        run_queue.real_time_tasks.push_back(real_time.clone());
+        drop(run_queue);
+        assert!(scheduler.should_preempt(&current));
+        assert!(!scheduler.should_preempt(&real_time));
+    }
+
+    #[ktest]
+    fn idle_cpu_pulls_tasks_in_affinity() {
+        // The tasks are moved between the runqueues of two CPUs.
+        if num_cpus() < 2 {
+            return;
+        }
+
+        let scheduler = FairScheduler::new();
+        let mut cpu_0_only = CpuSet::new_empty();
+        cpu_0_only.add(0);
+        let pinned = TaskOptions::new(|| {})
+            .data(())
+            .cpu_affinity(cpu_0_only)
+            .build()
+            .unwrap();
+        let mut tasks: Vec<Arc<Task>> = (0..4)
+            .map(|_| SimTask::hog(Priority::normal()).task)
+            .collect();
+        tasks.push(pinned.clone());
+
+        let mut run_queue = scheduler.run_queues[0].lock_irq_disabled();
+        for task in tasks.iter() {
+            task.sched_entity().set_cpu(0);
+            run_queue.enqueue_fair(task.clone());
+        }
+        drop(run_queue);
+
+        // The CPU 1 is idle, so it pulls half of the load from the busiest CPU 0.
+        scheduler.balance(1, true);
+
+        let nr_moved = tasks
+            .iter()
+            .filter(|task| task.sched_entity().cpu() == 1)
+            .count();
+        assert!(nr_moved > 0);
+        assert_eq!(
+            scheduler.run_queues[1].lock_irq_disabled().nr_queued(),
+            nr_moved
+        );
+        assert_eq!(
+            scheduler.run_queues[0].lock_irq_disabled().nr_queued(),
+            tasks.len() - nr_moved
+        );
+        assert_eq!(pinned.sched_entity().cpu(), 0);
+    }
+}
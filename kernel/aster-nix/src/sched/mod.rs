@@ -1,8 +1,9 @@
 // SPDX-License-Identifier: MPL-2.0
 
+mod fair_scheduler;
 pub mod nice;
-mod priority_scheduler;
+pub mod real_time;
 
 // There may be multiple scheduling policies in the system,
 // and subsequent schedulers can be placed under this module.
-pub use self::priority_scheduler::init;
+pub use self::fair_scheduler::init;
@@ -1,5 +1,6 @@
 // SPDX-License-Identifier: MPL-2.0
 
+use aster_frame::task::Priority as TaskPriority;
 use bytemuck_derive::NoUninit;
 
 use crate::prelude::*;
@@ -55,6 +56,13 @@ impl Default for Nice {
     }
 }
 
+impl From<Nice> for TaskPriority {
+    /// Converts to the priority of the tasks, which is 120 plus the nice value.
+    fn from(nice: Nice) -> Self {
+        TaskPriority::new((TaskPriority::normal().get() as i16 + nice.to_raw() as i16) as u16)
+    }
+}
+
 impl From<Priority> for Nice {
     fn from(priority: Priority) -> Self {
         Self {
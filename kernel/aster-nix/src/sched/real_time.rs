@@ -0,0 +1,65 @@
+// SPDX-License-Identifier: MPL-2.0
+
+use core::time::Duration;
+
+use aster_frame::task::Priority as TaskPriority;
+
+/// The time slice of the tasks scheduled with `SCHED_RR`, which is the same as that of
+/// Linux.
+pub const RR_TIME_SLICE: Duration = Duration::from_millis(100);
+
+/// The real-time scheduling priority value.
+///
+/// It is a value in the range 1 to 99, with 1 being the lowest priority and 99 being the
+/// highest priority. A real-time task always has a higher priority than the normal tasks,
+/// whose priority value is 0.
+#[repr(transparent)]
+#[derive(Copy, Clone, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub struct RealTimePriority {
+    value: u8,
+}
+
+impl RealTimePriority {
+    /// The minimum priority, whose value is 1.
+    pub const MIN: Self = Self { value: 1 };
+
+    /// The maximum priority, whose value is 99.
+    pub const MAX: Self = Self { value: 99 };
+
+    /// Creates a new `RealTimePriority` from the raw value.
+    ///
+    /// Returns `None` if the value is beyond the permissible range.
+    pub fn new(raw: u32) -> Option<Self> {
+        if raw < Self::MIN.to_raw() as u32 || raw > Self::MAX.to_raw() as u32 {
+            return None;
+        }
+        Some(Self { value: raw as u8 })
+    }
+
+    /// Converts to the raw value.
+    pub fn to_raw(self) -> u8 {
+        self.value
+    }
+}
+
+impl From<RealTimePriority> for TaskPriority {
+    /// Converts to the priority of the tasks, which is 99 minus the real-time priority
+    /// value.
+    fn from(priority: RealTimePriority) -> Self {
+        TaskPriority::new((RealTimePriority::MAX.to_raw() - priority.to_raw()) as u16)
+    }
+}
+
+impl TryFrom<TaskPriority> for RealTimePriority {
+    type Error = ();
+
+    /// Converts from the priority of the tasks, which must be real-time.
+    fn try_from(priority: TaskPriority) -> Result<Self, Self::Error> {
+        if !priority.is_real_time() {
+            return Err(());
+        }
+        Ok(Self {
+            value: RealTimePriority::MAX.to_raw() - priority.get() as u8,
+        })
+    }
+}
@@ -53,11 +53,7 @@ fn do_accept(
         connected_socket.set_status_flags(StatusFlags::O_NONBLOCK)?;
     }
 
-    let fd_flags = if flags.contains(Flags::SOCK_CLOEXEC) {
-        FdFlags::CLOEXEC
-    } else {
-        FdFlags::empty()
-    };
+    let fd_flags = FdFlags::from(CreationFlags::from_bits_truncate(flags.bits()));
 
     if sockaddr_ptr != 0 {
         write_socket_addr_to_user(&socket_addr, sockaddr_ptr, addrlen_ptr)?;
@@ -76,8 +72,10 @@ bitflags! {
     struct Flags: u32 {
         const SOCK_NONBLOCK = NONBLOCK;
         const SOCK_CLOEXEC = CLOEXEC;
+        const SOCK_CLOFORK = CLOFORK;
     }
 }
 
 const NONBLOCK: u32 = StatusFlags::O_NONBLOCK.bits();
 const CLOEXEC: u32 = CreationFlags::O_CLOEXEC.bits();
+const CLOFORK: u32 = CreationFlags::O_CLOFORK.bits();
@@ -1,11 +1,27 @@
 // SPDX-License-Identifier: MPL-2.0
 
+#[cfg(feature = "net")]
 use crate::syscall::{
     accept::{sys_accept, sys_accept4},
+    bind::sys_bind,
+    connect::sys_connect,
+    getpeername::sys_getpeername,
+    getsockname::sys_getsockname,
+    getsockopt::sys_getsockopt,
+    listen::sys_listen,
+    recvfrom::sys_recvfrom,
+    recvmmsg::sys_recvmmsg,
+    sendmmsg::sys_sendmmsg,
+    sendto::sys_sendto,
+    setsockopt::sys_setsockopt,
+    shutdown::sys_shutdown,
+    socket::sys_socket,
+    socketpair::sys_socketpair,
+};
+use crate::syscall::{
     access::sys_access,
     alarm::sys_alarm,
     arch_prctl::sys_arch_prctl,
-    bind::sys_bind,
     brk::sys_brk,
     capget::sys_capget,
     capset::sys_capset,
@@ -16,24 +32,26 @@ use crate::syscall::{
     clock_gettime::sys_clock_gettime,
     clone::{sys_clone, sys_clone3},
     close::sys_close,
-    connect::sys_connect,
+    copy_file_range::sys_copy_file_range,
     dup::{sys_dup, sys_dup2, sys_dup3},
     epoll::{sys_epoll_create, sys_epoll_create1, sys_epoll_ctl, sys_epoll_pwait, sys_epoll_wait},
     eventfd::{sys_eventfd, sys_eventfd2},
     execve::{sys_execve, sys_execveat},
     exit::sys_exit,
     exit_group::sys_exit_group,
+    fadvise64::sys_fadvise64,
+    fallocate::sys_fallocate,
     fcntl::sys_fcntl,
+    flock::sys_flock,
     fork::sys_fork,
     fsync::sys_fsync,
-    futex::sys_futex,
+    futex::{sys_futex, sys_futex_waitv},
     getcwd::sys_getcwd,
     getdents64::sys_getdents64,
     getegid::sys_getegid,
     geteuid::sys_geteuid,
     getgid::sys_getgid,
     getgroups::sys_getgroups,
-    getpeername::sys_getpeername,
     getpgrp::sys_getpgrp,
     getpid::sys_getpid,
     getppid::sys_getppid,
@@ -42,34 +60,37 @@ use crate::syscall::{
     getresuid::sys_getresuid,
     getrusage::sys_getrusage,
     getsid::sys_getsid,
-    getsockname::sys_getsockname,
-    getsockopt::sys_getsockopt,
     gettid::sys_gettid,
     gettimeofday::sys_gettimeofday,
     getuid::sys_getuid,
     impl_syscall_nums_and_dispatch_fn,
+    inotify::{sys_inotify_add_watch, sys_inotify_init, sys_inotify_init1, sys_inotify_rm_watch},
+    io_uring::{sys_io_uring_enter, sys_io_uring_register, sys_io_uring_setup},
     ioctl::sys_ioctl,
     kill::sys_kill,
     link::{sys_link, sys_linkat},
-    listen::sys_listen,
     lseek::sys_lseek,
     madvise::sys_madvise,
+    memfd_create::sys_memfd_create,
     mkdir::{sys_mkdir, sys_mkdirat},
+    mlock::{sys_mlock, sys_mlock2, sys_mlockall, sys_munlock, sys_munlockall},
     mmap::sys_mmap,
     mount::sys_mount,
-    mprotect::sys_mprotect,
+    mprotect::{sys_mprotect, sys_pkey_mprotect},
+    mremap::sys_mremap,
     munmap::sys_munmap,
     nanosleep::{sys_clock_nanosleep, sys_nanosleep},
     open::{sys_creat, sys_open, sys_openat},
     pause::sys_pause,
     pipe::{sys_pipe, sys_pipe2},
+    pkey::{sys_pkey_alloc, sys_pkey_free},
     poll::sys_poll,
     prctl::sys_prctl,
     pread64::sys_pread64,
     prlimit64::sys_prlimit64,
+    ptrace::sys_ptrace,
     read::sys_read,
     readlink::{sys_readlink, sys_readlinkat},
-    recvfrom::sys_recvfrom,
     rename::{sys_rename, sys_renameat},
     rmdir::sys_rmdir,
     rt_sigaction::sys_rt_sigaction,
@@ -77,11 +98,15 @@ use crate::syscall::{
     rt_sigprocmask::sys_rt_sigprocmask,
     rt_sigreturn::sys_rt_sigreturn,
     rt_sigsuspend::sys_rt_sigsuspend,
-    sched_getaffinity::sys_sched_getaffinity,
+    sched_affinity::{sys_sched_getaffinity, sys_sched_setaffinity},
+    sched_policy::{
+        sys_sched_get_priority_max, sys_sched_get_priority_min, sys_sched_getparam,
+        sys_sched_getscheduler, sys_sched_rr_get_interval, sys_sched_setparam,
+        sys_sched_setscheduler,
+    },
     sched_yield::sys_sched_yield,
     select::sys_select,
     sendfile::sys_sendfile,
-    sendto::sys_sendto,
     set_get_priority::{sys_get_priority, sys_set_priority},
     set_robust_list::sys_set_robust_list,
     set_tid_address::sys_set_tid_address,
@@ -96,30 +121,37 @@ use crate::syscall::{
     setresuid::sys_setresuid,
     setreuid::sys_setreuid,
     setsid::sys_setsid,
-    setsockopt::sys_setsockopt,
     setuid::sys_setuid,
-    shutdown::sys_shutdown,
+    shm::{sys_shmat, sys_shmctl, sys_shmdt, sys_shmget},
     sigaltstack::sys_sigaltstack,
-    socket::sys_socket,
-    socketpair::sys_socketpair,
+    signalfd::{sys_signalfd, sys_signalfd4},
+    splice::{sys_splice, sys_tee, sys_vmsplice},
     stat::{sys_fstat, sys_fstatat, sys_lstat, sys_stat},
     statfs::{sys_fstatfs, sys_statfs},
+    swapon::{sys_swapoff, sys_swapon},
     symlink::{sys_symlink, sys_symlinkat},
     sync::sys_sync,
     tgkill::sys_tgkill,
     time::sys_time,
     timer_create::{sys_timer_create, sys_timer_delete},
     timer_settime::{sys_timer_gettime, sys_timer_settime},
+    timerfd::{sys_timerfd_create, sys_timerfd_gettime, sys_timerfd_settime},
     truncate::{sys_ftruncate, sys_truncate},
     umask::sys_umask,
     umount::sys_umount,
     uname::sys_uname,
     unlink::{sys_unlink, sys_unlinkat},
+    userfaultfd::sys_userfaultfd,
     utimens::sys_utimensat,
     wait4::sys_wait4,
     waitid::sys_waitid,
     write::sys_write,
     writev::sys_writev,
+    xattr::{
+        sys_fgetxattr, sys_flistxattr, sys_fremovexattr, sys_fsetxattr, sys_getxattr,
+        sys_lgetxattr, sys_listxattr, sys_llistxattr, sys_lremovexattr, sys_lsetxattr,
+        sys_removexattr, sys_setxattr,
+    },
 };
 
 impl_syscall_nums_and_dispatch_fn! {
@@ -146,7 +178,11 @@ impl_syscall_nums_and_dispatch_fn! {
     SYS_PIPE = 22              => sys_pipe(args[..1]);
     SYS_SELECT = 23            => sys_select(args[..5]);
     SYS_SCHED_YIELD = 24       => sys_sched_yield(args[..0]);
+    SYS_MREMAP = 25            => sys_mremap(args[..5]);
     SYS_MADVISE = 28           => sys_madvise(args[..3]);
+    SYS_SHMGET = 29            => sys_shmget(args[..3]);
+    SYS_SHMAT = 30             => sys_shmat(args[..3]);
+    SYS_SHMCTL = 31            => sys_shmctl(args[..3]);
     SYS_DUP = 32               => sys_dup(args[..1]);
     SYS_DUP2 = 33              => sys_dup2(args[..2]);
     SYS_PAUSE = 34             => sys_pause(args[..0]);
@@ -156,18 +192,31 @@ impl_syscall_nums_and_dispatch_fn! {
     SYS_SETITIMER = 38         => sys_setitimer(args[..3]);
     SYS_GETPID = 39            => sys_getpid(args[..0]);
     SYS_SENDFILE = 40          => sys_sendfile(args[..4]);
+    #[cfg(feature = "net")]
     SYS_SOCKET = 41            => sys_socket(args[..3]);
+    #[cfg(feature = "net")]
     SYS_CONNECT = 42           => sys_connect(args[..3]);
+    #[cfg(feature = "net")]
     SYS_ACCEPT = 43            => sys_accept(args[..3]);
+    #[cfg(feature = "net")]
     SYS_SENDTO = 44            => sys_sendto(args[..6]);
+    #[cfg(feature = "net")]
     SYS_RECVFROM = 45          => sys_recvfrom(args[..6]);
+    #[cfg(feature = "net")]
     SYS_SHUTDOWN = 48          => sys_shutdown(args[..2]);
+    #[cfg(feature = "net")]
     SYS_BIND = 49              => sys_bind(args[..3]);
+    #[cfg(feature = "net")]
     SYS_LISTEN = 50            => sys_listen(args[..2]);
+    #[cfg(feature = "net")]
     SYS_GETSOCKNAME = 51       => sys_getsockname(args[..3]);
+    #[cfg(feature = "net")]
     SYS_GETPEERNAME = 52       => sys_getpeername(args[..3]);
+    #[cfg(feature = "net")]
     SYS_SOCKETPAIR = 53        => sys_socketpair(args[..4]);
+    #[cfg(feature = "net")]
     SYS_SETSOCKOPT = 54        => sys_setsockopt(args[..5]);
+    #[cfg(feature = "net")]
     SYS_GETSOCKOPT = 55        => sys_getsockopt(args[..5]);
     SYS_CLONE = 56             => sys_clone(args[..5], &context);
     SYS_FORK = 57              => sys_fork(args[..0], &context);
@@ -176,7 +225,9 @@ impl_syscall_nums_and_dispatch_fn! {
     SYS_WAIT4 = 61             => sys_wait4(args[..4]);
     SYS_KILL = 62              => sys_kill(args[..2]);
     SYS_UNAME = 63             => sys_uname(args[..1]);
+    SYS_SHMDT = 67             => sys_shmdt(args[..1]);
     SYS_FCNTL = 72             => sys_fcntl(args[..3]);
+    SYS_FLOCK = 73             => sys_flock(args[..2]);
     SYS_FSYNC = 74             => sys_fsync(args[..1]);
     SYS_TRUNCATE = 76          => sys_truncate(args[..2]);
     SYS_FTRUNCATE = 77         => sys_ftruncate(args[..2]);
@@ -199,6 +250,7 @@ impl_syscall_nums_and_dispatch_fn! {
     SYS_UMASK = 95             => sys_umask(args[..1]);
     SYS_GETTIMEOFDAY = 96      => sys_gettimeofday(args[..1]);
     SYS_GETRUSAGE = 98         => sys_getrusage(args[..2]);
+    SYS_PTRACE = 101           => sys_ptrace(args[..4]);
     SYS_GETUID = 102           => sys_getuid(args[..0]);
     SYS_GETGID = 104           => sys_getgid(args[..0]);
     SYS_SETUID = 105           => sys_setuid(args[..1]);
@@ -229,19 +281,46 @@ impl_syscall_nums_and_dispatch_fn! {
     SYS_FSTATFS = 138          => sys_fstatfs(args[..2]);
     SYS_GET_PRIORITY = 140     => sys_get_priority(args[..2]);
     SYS_SET_PRIORITY = 141     => sys_set_priority(args[..3]);
+    SYS_SCHED_SETPARAM = 142   => sys_sched_setparam(args[..2]);
+    SYS_SCHED_GETPARAM = 143   => sys_sched_getparam(args[..2]);
+    SYS_SCHED_SETSCHEDULER = 144 => sys_sched_setscheduler(args[..3]);
+    SYS_SCHED_GETSCHEDULER = 145 => sys_sched_getscheduler(args[..1]);
+    SYS_SCHED_GET_PRIORITY_MAX = 146 => sys_sched_get_priority_max(args[..1]);
+    SYS_SCHED_GET_PRIORITY_MIN = 147 => sys_sched_get_priority_min(args[..1]);
+    SYS_SCHED_RR_GET_INTERVAL = 148 => sys_sched_rr_get_interval(args[..2]);
+    SYS_MLOCK = 149            => sys_mlock(args[..2]);
+    SYS_MUNLOCK = 150          => sys_munlock(args[..2]);
+    SYS_MLOCKALL = 151         => sys_mlockall(args[..1]);
+    SYS_MUNLOCKALL = 152       => sys_munlockall(args[..0]);
     SYS_PRCTL = 157            => sys_prctl(args[..5]);
     SYS_ARCH_PRCTL = 158       => sys_arch_prctl(args[..2], &mut context);
     SYS_CHROOT = 161           => sys_chroot(args[..1]);
     SYS_SYNC = 162             => sys_sync(args[..0]);
     SYS_MOUNT = 165            => sys_mount(args[..5]);
     SYS_UMOUNT2 = 166           => sys_umount(args[..2]);
+    SYS_SWAPON = 167           => sys_swapon(args[..2]);
+    SYS_SWAPOFF = 168          => sys_swapoff(args[..1]);
     SYS_GETTID = 186           => sys_gettid(args[..0]);
+    SYS_SETXATTR = 188         => sys_setxattr(args[..5]);
+    SYS_LSETXATTR = 189        => sys_lsetxattr(args[..5]);
+    SYS_FSETXATTR = 190        => sys_fsetxattr(args[..5]);
+    SYS_GETXATTR = 191         => sys_getxattr(args[..4]);
+    SYS_LGETXATTR = 192        => sys_lgetxattr(args[..4]);
+    SYS_FGETXATTR = 193        => sys_fgetxattr(args[..4]);
+    SYS_LISTXATTR = 194        => sys_listxattr(args[..3]);
+    SYS_LLISTXATTR = 195       => sys_llistxattr(args[..3]);
+    SYS_FLISTXATTR = 196       => sys_flistxattr(args[..3]);
+    SYS_REMOVEXATTR = 197      => sys_removexattr(args[..2]);
+    SYS_LREMOVEXATTR = 198     => sys_lremovexattr(args[..2]);
+    SYS_FREMOVEXATTR = 199     => sys_fremovexattr(args[..2]);
     SYS_TIME = 201             => sys_time(args[..1]);
     SYS_FUTEX = 202            => sys_futex(args[..6]);
+    SYS_SCHED_SETAFFINITY = 203 => sys_sched_setaffinity(args[..3]);
     SYS_SCHED_GETAFFINITY = 204 => sys_sched_getaffinity(args[..3]);
     SYS_EPOLL_CREATE = 213     => sys_epoll_create(args[..1]);
     SYS_GETDENTS64 = 217       => sys_getdents64(args[..3]);
     SYS_SET_TID_ADDRESS = 218  => sys_set_tid_address(args[..1]);
+    SYS_FADVISE64 = 221        => sys_fadvise64(args[..4]);
     SYS_TIMER_CREATE = 222     => sys_timer_create(args[..3]);
     SYS_TIMER_SETTIME = 223    => sys_timer_settime(args[..4]);
     SYS_TIMER_GETTIME = 224    => sys_timer_gettime(args[..2]);
@@ -253,6 +332,9 @@ impl_syscall_nums_and_dispatch_fn! {
     SYS_EPOLL_CTL = 233        => sys_epoll_ctl(args[..4]);
     SYS_TGKILL = 234           => sys_tgkill(args[..3]);
     SYS_WAITID = 247           => sys_waitid(args[..5]);
+    SYS_INOTIFY_INIT = 253     => sys_inotify_init(args[..0]);
+    SYS_INOTIFY_ADD_WATCH = 254 => sys_inotify_add_watch(args[..3]);
+    SYS_INOTIFY_RM_WATCH = 255 => sys_inotify_rm_watch(args[..2]);
     SYS_OPENAT = 257           => sys_openat(args[..4]);
     SYS_MKDIRAT = 258          => sys_mkdirat(args[..3]);
     SYS_FCHOWNAT = 260         => sys_fchownat(args[..5]);
@@ -264,16 +346,42 @@ impl_syscall_nums_and_dispatch_fn! {
     SYS_READLINKAT = 267       => sys_readlinkat(args[..4]);
     SYS_FCHMODAT = 268         => sys_fchmodat(args[..3]);
     SYS_SET_ROBUST_LIST = 273  => sys_set_robust_list(args[..2]);
+    SYS_SPLICE = 275           => sys_splice(args[..6]);
+    SYS_TEE = 276              => sys_tee(args[..4]);
+    SYS_VMSPLICE = 278         => sys_vmsplice(args[..4]);
     SYS_UTIMENSAT = 280        => sys_utimensat(args[..4]);
     SYS_EPOLL_PWAIT = 281      => sys_epoll_pwait(args[..6]);
+    SYS_SIGNALFD = 282         => sys_signalfd(args[..3]);
+    SYS_TIMERFD_CREATE = 283   => sys_timerfd_create(args[..2]);
     SYS_EVENTFD = 284          => sys_eventfd(args[..1]);
+    SYS_FALLOCATE = 285        => sys_fallocate(args[..4]);
+    SYS_TIMERFD_SETTIME = 286  => sys_timerfd_settime(args[..4]);
+    SYS_TIMERFD_GETTIME = 287  => sys_timerfd_gettime(args[..2]);
+    #[cfg(feature = "net")]
     SYS_ACCEPT4 = 288          => sys_accept4(args[..4]);
+    SYS_SIGNALFD4 = 289        => sys_signalfd4(args[..4]);
     SYS_EVENTFD2 = 290         => sys_eventfd2(args[..2]);
     SYS_EPOLL_CREATE1 = 291    => sys_epoll_create1(args[..1]);
     SYS_DUP3 = 292             => sys_dup3(args[..3]);
     SYS_PIPE2 = 293            => sys_pipe2(args[..2]);
+    SYS_INOTIFY_INIT1 = 294    => sys_inotify_init1(args[..1]);
+    #[cfg(feature = "net")]
+    SYS_RECVMMSG = 299         => sys_recvmmsg(args[..5]);
     SYS_PRLIMIT64 = 302        => sys_prlimit64(args[..4]);
+    #[cfg(feature = "net")]
+    SYS_SENDMMSG = 307         => sys_sendmmsg(args[..4]);
     SYS_GETRANDOM = 318        => sys_getrandom(args[..3]);
+    SYS_MEMFD_CREATE = 319     => sys_memfd_create(args[..2]);
     SYS_EXECVEAT = 322         => sys_execveat(args[..5], &mut context);
+    SYS_USERFAULTFD = 323      => sys_userfaultfd(args[..1]);
+    SYS_MLOCK2 = 325           => sys_mlock2(args[..3]);
+    SYS_COPY_FILE_RANGE = 326  => sys_copy_file_range(args[..6]);
+    SYS_PKEY_MPROTECT = 329    => sys_pkey_mprotect(args[..4]);
+    SYS_PKEY_ALLOC = 330       => sys_pkey_alloc(args[..2], &mut context);
+    SYS_PKEY_FREE = 331        => sys_pkey_free(args[..1]);
+    SYS_IO_URING_SETUP = 425   => sys_io_uring_setup(args[..2]);
+    SYS_IO_URING_ENTER = 426   => sys_io_uring_enter(args[..6]);
+    SYS_IO_URING_REGISTER = 427 => sys_io_uring_register(args[..4]);
     SYS_CLONE3 = 435           => sys_clone3(args[..2], &context);
+    SYS_FUTEX_WAITV = 449      => sys_futex_waitv(args[..5]);
 }
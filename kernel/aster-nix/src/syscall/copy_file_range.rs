@@ -0,0 +1,112 @@
+// SPDX-License-Identifier: MPL-2.0
+
+use super::{
+    sendfile::{copy_between_files, MAX_RW_COUNT},
+    SyscallReturn,
+};
+use crate::{
+    fs::{
+        file_handle::FileLike,
+        file_table::FileDesc,
+        inode_handle::InodeHandle,
+        utils::{InodeType, SeekFrom, StatusFlags},
+    },
+    prelude::*,
+    util::{read_val_from_user, write_val_to_user},
+};
+
+pub fn sys_copy_file_range(
+    in_fd: FileDesc,
+    in_offset_ptr: Vaddr,
+    out_fd: FileDesc,
+    out_offset_ptr: Vaddr,
+    len: usize,
+    flags: u32,
+) -> Result<SyscallReturn> {
+    debug!(
+        "in_fd = {}, in_offset_ptr = {:#x}, out_fd = {}, out_offset_ptr = {:#x}",
+        in_fd, in_offset_ptr, out_fd, out_offset_ptr
+    );
+    debug!("len = {:#x}, flags = {:#x}", len, flags);
+
+    if flags != 0 {
+        return_errno_with_message!(Errno::EINVAL, "the flags must be zero");
+    }
+    let mut in_offset = read_offset_from_user(in_offset_ptr)?;
+    let mut out_offset = read_offset_from_user(out_offset_ptr)?;
+
+    let (in_file, out_file) = {
+        let current = current!();
+        let file_table = current.file_table().lock();
+        let in_file = file_table.get_file(in_fd)?.clone();
+        let out_file = file_table.get_file(out_fd)?.clone();
+        (in_file, out_file)
+    };
+    let in_handle = regular_file_handle(in_file.downcast_ref::<InodeHandle>())?;
+    let out_handle = regular_file_handle(out_file.downcast_ref::<InodeHandle>())?;
+    if !in_handle.access_mode().is_readable() {
+        return_errno_with_message!(Errno::EBADF, "the input file is not readable");
+    }
+    if !out_handle.access_mode().is_writable() {
+        return_errno_with_message!(Errno::EBADF, "the output file is not writable");
+    }
+    if out_handle.status_flags().contains(StatusFlags::O_APPEND) {
+        return_errno_with_message!(Errno::EBADF, "the output file is append-only");
+    }
+    if len == 0 {
+        return Ok(SyscallReturn::Return(0));
+    }
+
+    let len = len.min(MAX_RW_COUNT);
+    if Arc::ptr_eq(in_handle.dentry().inode(), out_handle.dentry().inode()) {
+        let in_start = match in_offset {
+            Some(offset) => offset,
+            None => in_handle.seek(SeekFrom::Current(0))?,
+        };
+        let out_start = match out_offset {
+            Some(offset) => offset,
+            None => out_handle.seek(SeekFrom::Current(0))?,
+        };
+        if in_start < out_start + len && out_start < in_start + len {
+            return_errno_with_message!(Errno::EINVAL, "the ranges overlap in the same file");
+        }
+    }
+
+    let copied_len = copy_between_files(
+        in_handle,
+        in_offset.as_mut(),
+        out_handle,
+        out_offset.as_mut(),
+        len,
+    )?;
+    if let Some(in_offset) = in_offset {
+        write_val_to_user(in_offset_ptr, &(in_offset as i64))?;
+    }
+    if let Some(out_offset) = out_offset {
+        write_val_to_user(out_offset_ptr, &(out_offset as i64))?;
+    }
+
+    Ok(SyscallReturn::Return(copied_len as _))
+}
+
+fn read_offset_from_user(offset_ptr: Vaddr) -> Result<Option<usize>> {
+    if offset_ptr == 0 {
+        return Ok(None);
+    }
+    let offset: i64 = read_val_from_user(offset_ptr)?;
+    if offset < 0 {
+        return_errno_with_message!(Errno::EINVAL, "the offset is negative");
+    }
+    Ok(Some(offset as usize))
+}
+
+fn regular_file_handle(handle: Option<&InodeHandle>) -> Result<&InodeHandle> {
+    let Some(handle) = handle else {
+        return_errno_with_message!(Errno::EINVAL, "the file is not an inode");
+    };
+    match handle.dentry().type_() {
+        InodeType::File => Ok(handle),
+        InodeType::Dir => return_errno_with_message!(Errno::EISDIR, "the file is a directory"),
+        _ => return_errno_with_message!(Errno::EINVAL, "the file is not a regular file"),
+    }
+}
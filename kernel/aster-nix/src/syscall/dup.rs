@@ -2,7 +2,10 @@
 
 use super::SyscallReturn;
 use crate::{
-    fs::file_table::{FdFlags, FileDesc},
+    fs::{
+        file_table::{FdFlags, FileDesc},
+        utils::CreationFlags,
+    },
     prelude::*,
     process::ResourceType,
 };
@@ -33,10 +36,15 @@ pub fn sys_dup2(old_fd: FileDesc, new_fd: FileDesc) -> Result<SyscallReturn> {
 pub fn sys_dup3(old_fd: FileDesc, new_fd: FileDesc, flags: u32) -> Result<SyscallReturn> {
     debug!("old_fd = {}, new_fd = {}", old_fd, new_fd);
 
-    let fdflag = match flags {
-        0x0 => FdFlags::empty(),
-        0x80000 => FdFlags::CLOEXEC,
-        _ => return_errno_with_message!(Errno::EINVAL, "flags must be O_CLOEXEC or 0"),
+    let fdflag = {
+        let creation_flags = CreationFlags::O_CLOEXEC | CreationFlags::O_CLOFORK;
+        if flags & !creation_flags.bits() != 0 {
+            return_errno_with_message!(
+                Errno::EINVAL,
+                "flags must be a combination of O_CLOEXEC and O_CLOFORK"
+            );
+        }
+        FdFlags::from(CreationFlags::from_bits_truncate(flags))
     };
 
     do_dup3(old_fd, new_fd, fdflag)
@@ -59,7 +67,10 @@ fn do_dup3(old_fd: FileDesc, new_fd: FileDesc, flags: FdFlags) -> Result<Syscall
     }
 
     let mut file_table = current.file_table().lock();
-    let _ = file_table.close_file(new_fd);
+    if let Some(closed_file) = file_table.close_file(new_fd) {
+        // Like `close`, this releases the POSIX locks, but the errors are ignored.
+        let _ = closed_file.clean_for_close();
+    }
     let new_fd = file_table.dup(old_fd, new_fd, flags)?;
 
     Ok(SyscallReturn::Return(new_fd as _))
@@ -64,6 +64,11 @@ fn do_sys_eventfd2(init_val: u64, flags: Flags) -> FileDesc {
     fd
 }
 
+/// Returns whether the file is an eventfd.
+pub(super) fn is_eventfd(file: &dyn FileLike) -> bool {
+    file.downcast_ref::<EventFile>().is_some()
+}
+
 bitflags! {
     struct Flags: u32 {
         const EFD_SEMAPHORE = 1;
@@ -199,6 +204,9 @@ impl FileLike for EventFile {
         }
 
         let supplied_value = u64::from_bytes(buf);
+        if supplied_value == u64::MAX {
+            return_errno_with_message!(Errno::EINVAL, "the value cannot be written");
+        }
 
         // Try to add counter val at first
         if self.add_counter_val(supplied_value).is_ok() {
@@ -15,7 +15,8 @@ use crate::{
     prelude::*,
     process::{
         check_executable_file, credentials_mut, load_program_to_vm,
-        posix_thread::{PosixThreadExt, ThreadName},
+        posix_thread::{PosixThreadExt, PtraceOptions, PtraceStopKind, ThreadName},
+        signal::{constants::SIGTRAP, signals::kernel::KernelSignal},
         Credentials, Process, MAX_ARGV_NUMBER, MAX_ARG_LEN, MAX_ENVP_NUMBER, MAX_ENV_LEN,
     },
     util::{read_cstring_from_user, read_val_from_user},
@@ -130,12 +131,25 @@ fn do_execve(
     *context.general_regs_mut() = *default_content.general_regs();
     context.set_tls_pointer(default_content.tls_pointer());
     *context.fp_regs_mut() = *default_content.fp_regs();
+    context.set_pkru(default_content.pkru());
     // set new entry point
     context.set_instruction_pointer(elf_load_info.entry_point() as _);
     debug!("entry_point: 0x{:x}", elf_load_info.entry_point());
     // set new user stack top
     context.set_stack_pointer(elf_load_info.user_stack_top() as _);
     debug!("user stack top: 0x{:x}", elf_load_info.user_stack_top());
+
+    // A traced thread stops before the new program runs, so that the tracer can inspect
+    // the program, e.g., to insert breakpoints.
+    let ptrace = posix_thread.ptrace();
+    if ptrace.is_traced() {
+        if ptrace.options().contains(PtraceOptions::PTRACE_O_TRACEEXEC) {
+            let kind = PtraceStopKind::Exec(current_thread.tid());
+            ptrace.stop(kind, context, None);
+        } else {
+            posix_thread.enqueue_signal(Box::new(KernelSignal::new(SIGTRAP)));
+        }
+    }
     Ok(())
 }
 
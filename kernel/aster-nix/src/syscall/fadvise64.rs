@@ -0,0 +1,38 @@
+// SPDX-License-Identifier: MPL-2.0
+
+use super::SyscallReturn;
+use crate::{
+    fs::{file_table::FileDesc, inode_handle::InodeHandle, utils::FileAdvice},
+    prelude::*,
+};
+
+pub fn sys_fadvise64(fd: FileDesc, offset: i64, len: i64, advice: i32) -> Result<SyscallReturn> {
+    let advice = FileAdvice::try_from(advice)?;
+    debug!(
+        "fd = {}, offset = {}, len = {}, advice = {:?}",
+        fd, offset, len, advice
+    );
+
+    if offset < 0 || len < 0 {
+        return_errno_with_message!(Errno::EINVAL, "offset or len is negative");
+    }
+    let start = offset as usize;
+    // A zero length means until the end of the file.
+    let end = if len == 0 {
+        usize::MAX
+    } else {
+        start.saturating_add(len as usize)
+    };
+
+    let file = {
+        let current = current!();
+        let file_table = current.file_table().lock();
+        file_table.get_file(fd)?.clone()
+    };
+    let inode_handle = file
+        .downcast_ref::<InodeHandle>()
+        .ok_or_else(|| Error::with_message(Errno::ESPIPE, "the file is not an inode"))?;
+    inode_handle.fadvise(start..end, advice)?;
+
+    Ok(SyscallReturn::Return(0))
+}
@@ -0,0 +1,60 @@
+// SPDX-License-Identifier: MPL-2.0
+
+use super::SyscallReturn;
+use crate::{
+    fs::{file_table::FileDesc, inode_handle::InodeHandle, utils::FallocMode},
+    prelude::*,
+};
+
+pub fn sys_fallocate(fd: FileDesc, mode: i32, offset: i64, len: i64) -> Result<SyscallReturn> {
+    debug!(
+        "fd = {}, mode = {:#x}, offset = {}, len = {}",
+        fd, mode, offset, len
+    );
+
+    if offset < 0 || len <= 0 {
+        return_errno_with_message!(Errno::EINVAL, "offset is negative or len is not positive");
+    }
+    let mode = FallocMode::from_bits(mode as u32)
+        .ok_or_else(|| Error::with_message(Errno::EOPNOTSUPP, "unknown mode"))?;
+    check_mode(mode)?;
+    let offset = offset as usize;
+    let len = len as usize;
+    if offset
+        .checked_add(len)
+        .map_or(true, |end| end > isize::MAX as usize)
+    {
+        return_errno_with_message!(Errno::EFBIG, "the range is too large");
+    }
+
+    let file = {
+        let current = current!();
+        let file_table = current.file_table().lock();
+        file_table.get_file(fd)?.clone()
+    };
+    let inode_handle = file
+        .downcast_ref::<InodeHandle>()
+        .ok_or_else(|| Error::with_message(Errno::ESPIPE, "the file is not an inode"))?;
+    inode_handle.fallocate(mode, offset, len)?;
+
+    Ok(SyscallReturn::Return(0))
+}
+
+/// Checks the combination of the modes, which is the same as that in Linux.
+fn check_mode(mode: FallocMode) -> Result<()> {
+    if mode.contains(FallocMode::PUNCH_HOLE | FallocMode::ZERO_RANGE) {
+        return_errno_with_message!(Errno::EOPNOTSUPP, "punching and zeroing are exclusive");
+    }
+    if mode.contains(FallocMode::PUNCH_HOLE) && !mode.contains(FallocMode::KEEP_SIZE) {
+        return_errno_with_message!(Errno::EOPNOTSUPP, "punching must keep the size");
+    }
+    if mode.intersects(
+        FallocMode::NO_HIDE_STALE
+            | FallocMode::COLLAPSE_RANGE
+            | FallocMode::INSERT_RANGE
+            | FallocMode::UNSHARE_RANGE,
+    ) {
+        return_errno_with_message!(Errno::EOPNOTSUPP, "the mode is not supported");
+    }
+    Ok(())
+}
@@ -3,10 +3,14 @@
 use super::SyscallReturn;
 use crate::{
     fs::{
+        file_handle::FileLike,
         file_table::{FdFlags, FileDesc},
-        utils::StatusFlags,
+        inode_handle::InodeHandle,
+        memfd::{FileSeals, MemfdFile},
+        utils::{file_lock, FileLockType, PosixLock, SeekFrom, StatusFlags},
     },
     prelude::*,
+    util::{read_val_from_user, write_val_to_user},
 };
 
 pub fn sys_fcntl(fd: FileDesc, cmd: i32, arg: u64) -> Result<SyscallReturn> {
@@ -80,6 +84,162 @@ pub fn sys_fcntl(fd: FileDesc, cmd: i32, arg: u64) -> Result<SyscallReturn> {
             file.set_status_flags(new_status_flags)?;
             Ok(SyscallReturn::Return(0))
         }
+        FcntlCmd::F_ADD_SEALS => {
+            let new_seals = FileSeals::from_bits(arg as u32)
+                .ok_or_else(|| Error::with_message(Errno::EINVAL, "invalid seals"))?;
+            let file = {
+                let current = current!();
+                let file_table = current.file_table().lock();
+                file_table.get_file(fd)?.clone()
+            };
+            let memfd_file = file
+                .downcast_ref::<MemfdFile>()
+                .ok_or_else(|| Error::with_message(Errno::EINVAL, "the file cannot be sealed"))?;
+            memfd_file.add_seals(new_seals)?;
+            Ok(SyscallReturn::Return(0))
+        }
+        FcntlCmd::F_GET_SEALS => {
+            let file = {
+                let current = current!();
+                let file_table = current.file_table().lock();
+                file_table.get_file(fd)?.clone()
+            };
+            let memfd_file = file
+                .downcast_ref::<MemfdFile>()
+                .ok_or_else(|| Error::with_message(Errno::EINVAL, "the file cannot be sealed"))?;
+            Ok(SyscallReturn::Return(memfd_file.seals().bits() as _))
+        }
+        FcntlCmd::F_GETLK => {
+            let file = {
+                let current = current!();
+                let file_table = current.file_table().lock();
+                file_table.get_file(fd)?.clone()
+            };
+            let mut c_flock: c_flock = read_val_from_user(arg as Vaddr)?;
+            let lock = to_posix_lock(&file, &c_flock)?;
+            if lock.type_ == FileLockType::Unlock {
+                return_errno_with_message!(Errno::EINVAL, "F_UNLCK cannot be tested");
+            }
+            let inode = inode_handle_of(&file)?.dentry().inode();
+            match file_lock::get_posix_lock(inode, &lock) {
+                Some(conflicting_lock) => c_flock.set_lock(&conflicting_lock),
+                None => c_flock.l_type = F_UNLCK,
+            }
+            write_val_to_user(arg as Vaddr, &c_flock)?;
+            Ok(SyscallReturn::Return(0))
+        }
+        FcntlCmd::F_SETLK | FcntlCmd::F_SETLKW => {
+            let file = {
+                let current = current!();
+                let file_table = current.file_table().lock();
+                file_table.get_file(fd)?.clone()
+            };
+            let c_flock: c_flock = read_val_from_user(arg as Vaddr)?;
+            let lock = to_posix_lock(&file, &c_flock)?;
+            let inode = inode_handle_of(&file)?.dentry().inode();
+            let is_blocking = matches!(fcntl_cmd, FcntlCmd::F_SETLKW);
+            file_lock::set_posix_lock(inode, lock, is_blocking)?;
+            Ok(SyscallReturn::Return(0))
+        }
+    }
+}
+
+fn inode_handle_of(file: &Arc<dyn FileLike>) -> Result<&InodeHandle> {
+    file.downcast_ref::<InodeHandle>()
+        .ok_or_else(|| Error::with_message(Errno::EINVAL, "the file cannot be locked"))
+}
+
+/// Converts the `struct flock` to the lock of the current process, whose range is
+/// relative to the file.
+fn to_posix_lock(file: &Arc<dyn FileLike>, c_flock: &c_flock) -> Result<PosixLock> {
+    let type_ = match c_flock.l_type {
+        F_RDLCK => {
+            if !file.access_mode().is_readable() {
+                return_errno_with_message!(Errno::EBADF, "the file is not readable");
+            }
+            FileLockType::ReadLock
+        }
+        F_WRLCK => {
+            if !file.access_mode().is_writable() {
+                return_errno_with_message!(Errno::EBADF, "the file is not writable");
+            }
+            FileLockType::WriteLock
+        }
+        F_UNLCK => FileLockType::Unlock,
+        _ => return_errno_with_message!(Errno::EINVAL, "invalid lock type"),
+    };
+
+    let base = match c_flock.l_whence {
+        SEEK_SET => 0,
+        SEEK_CUR => file.seek(SeekFrom::Current(0))?,
+        SEEK_END => inode_handle_of(file)?.dentry().size(),
+        _ => return_errno_with_message!(Errno::EINVAL, "invalid whence"),
+    };
+    let start = (base as i64)
+        .checked_add(c_flock.l_start)
+        .ok_or_else(|| Error::with_message(Errno::EOVERFLOW, "the start overflows"))?;
+    if start < 0 {
+        return_errno_with_message!(Errno::EINVAL, "the start is negative");
+    }
+    // A negative length means the bytes before the start, and zero means to the end.
+    let range = if c_flock.l_len > 0 {
+        let end = start
+            .checked_add(c_flock.l_len)
+            .ok_or_else(|| Error::with_message(Errno::EOVERFLOW, "the end overflows"))?;
+        start as usize..end as usize
+    } else if c_flock.l_len == 0 {
+        start as usize..usize::MAX
+    } else {
+        let new_start = start + c_flock.l_len;
+        if new_start < 0 {
+            return_errno_with_message!(Errno::EINVAL, "the start is negative");
+        }
+        new_start as usize..start as usize
+    };
+
+    Ok(PosixLock {
+        type_,
+        range,
+        owner: current!().pid(),
+    })
+}
+
+const F_RDLCK: i16 = 0;
+const F_WRLCK: i16 = 1;
+const F_UNLCK: i16 = 2;
+
+const SEEK_SET: i16 = 0;
+const SEEK_CUR: i16 = 1;
+const SEEK_END: i16 = 2;
+
+#[derive(Debug, Clone, Copy, Pod)]
+#[repr(C)]
+#[allow(non_camel_case_types)]
+struct c_flock {
+    l_type: i16,
+    l_whence: i16,
+    _padding0: u32,
+    l_start: i64,
+    l_len: i64,
+    l_pid: i32,
+    _padding1: u32,
+}
+
+impl c_flock {
+    fn set_lock(&mut self, lock: &PosixLock) {
+        self.l_type = match lock.type_ {
+            FileLockType::ReadLock => F_RDLCK,
+            FileLockType::WriteLock => F_WRLCK,
+            FileLockType::Unlock => F_UNLCK,
+        };
+        self.l_whence = SEEK_SET;
+        self.l_start = lock.range.start as i64;
+        self.l_len = if lock.range.end == usize::MAX {
+            0
+        } else {
+            (lock.range.end - lock.range.start) as i64
+        };
+        self.l_pid = lock.owner as i32;
     }
 }
 
@@ -92,5 +252,10 @@ enum FcntlCmd {
     F_SETFD = 2,
     F_GETFL = 3,
     F_SETFL = 4,
+    F_GETLK = 5,
+    F_SETLK = 6,
+    F_SETLKW = 7,
     F_DUPFD_CLOEXEC = 1030,
+    F_ADD_SEALS = 1033,
+    F_GET_SEALS = 1034,
 }
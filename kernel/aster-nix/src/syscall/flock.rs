@@ -0,0 +1,43 @@
+// SPDX-License-Identifier: MPL-2.0
+
+use super::SyscallReturn;
+use crate::{
+    fs::{file_table::FileDesc, inode_handle::InodeHandle, utils::FileLockType},
+    prelude::*,
+};
+
+pub fn sys_flock(fd: FileDesc, operation: i32) -> Result<SyscallReturn> {
+    let operation = FlockOperation::from_bits(operation)
+        .ok_or_else(|| Error::with_message(Errno::EINVAL, "unknown operation"))?;
+    debug!("fd = {}, operation = {:?}", fd, operation);
+
+    let type_ = match operation - FlockOperation::LOCK_NB {
+        FlockOperation::LOCK_SH => FileLockType::ReadLock,
+        FlockOperation::LOCK_EX => FileLockType::WriteLock,
+        FlockOperation::LOCK_UN => FileLockType::Unlock,
+        _ => return_errno_with_message!(Errno::EINVAL, "invalid operation"),
+    };
+    let file = {
+        let current = current!();
+        let file_table = current.file_table().lock();
+        file_table.get_file(fd)?.clone()
+    };
+    let inode_handle = file
+        .downcast_ref::<InodeHandle>()
+        .ok_or_else(|| Error::with_message(Errno::EINVAL, "the file cannot be locked"))?;
+    inode_handle.set_flock(type_, !operation.contains(FlockOperation::LOCK_NB))?;
+    Ok(SyscallReturn::Return(0))
+}
+
+bitflags! {
+    struct FlockOperation: i32 {
+        /// Places a shared lock.
+        const LOCK_SH = 1;
+        /// Places an exclusive lock.
+        const LOCK_EX = 2;
+        /// Does not block if the lock cannot be placed.
+        const LOCK_NB = 4;
+        /// Removes the lock.
+        const LOCK_UN = 8;
+    }
+}
@@ -1,12 +1,18 @@
 // SPDX-License-Identifier: MPL-2.0
 
+use core::time::Duration;
+
+use super::{clock_gettime::read_clock, ClockId};
 use crate::{
     prelude::*,
     process::posix_thread::futex::{
-        futex_op_and_flags_from_u32, futex_requeue, futex_wait, futex_wait_bitset, futex_wake,
-        futex_wake_bitset, FutexOp, FutexTimeout,
+        futex_lock_pi, futex_op_and_flags_from_u32, futex_requeue, futex_unlock_pi, futex_wait,
+        futex_wait_bitset, futex_waitv, futex_wake, futex_wake_bitset, FutexFlags, FutexOp,
+        FutexWaitv, FUTEX_WAITV_MAX,
     },
     syscall::SyscallReturn,
+    time::{clockid_t, timespec_t},
+    util::read_val_from_user,
 };
 
 pub fn sys_futex(
@@ -17,13 +23,23 @@ pub fn sys_futex(
     futex_new_addr: u64,
     bitset: u64,
 ) -> Result<SyscallReturn> {
-    // FIXME: we current ignore futex flags
-    let (futex_op, futex_flags) = futex_op_and_flags_from_u32(futex_op as _).unwrap();
+    // FIXME: we currently treat all futexes as shared ones, ignoring `FUTEX_PRIVATE`
+    let (futex_op, futex_flags) = futex_op_and_flags_from_u32(futex_op as _)?;
     debug!(
         "futex_op = {:?}, futex_flags = {:?}, futex_addr = 0x{:x}",
         futex_op, futex_flags, futex_addr
     );
 
+    if futex_flags.contains(FutexFlags::FUTEX_CLOCK_REALTIME)
+        && futex_op != FutexOp::FUTEX_WAIT
+        && futex_op != FutexOp::FUTEX_WAIT_BITSET
+    {
+        return_errno_with_message!(
+            Errno::ENOSYS,
+            "FUTEX_CLOCK_REALTIME is only supported by the wait operations"
+        );
+    }
+
     let get_futex_val = |val: i32| -> Result<usize> {
         if val < 0 {
             return_errno_with_message!(Errno::EINVAL, "the futex val must not be negative");
@@ -31,46 +47,162 @@ pub fn sys_futex(
         Ok(val as usize)
     };
 
-    let get_futex_timeout = |timeout_addr| -> Result<Option<FutexTimeout>> {
-        if timeout_addr == 0 {
+    // Reads the timeout, and converts it to be relative to now if it is an absolute time
+    // of the clock `clockid`.
+    let get_futex_timeout = |clockid: Option<ClockId>| -> Result<Option<Duration>> {
+        if utime_addr == 0 {
             return Ok(None);
         }
-        // TODO: parse a timeout
-        todo!()
+        let timespec = read_val_from_user::<timespec_t>(utime_addr as _)?;
+        if timespec.sec < 0 || !(0..1_000_000_000).contains(&timespec.nsec) {
+            return_errno_with_message!(Errno::EINVAL, "the futex timeout is invalid");
+        }
+        let timeout = Duration::from(timespec);
+        match clockid {
+            Some(clockid) => Ok(Some(
+                timeout.saturating_sub(read_clock(clockid as clockid_t)?),
+            )),
+            None => Ok(Some(timeout)),
+        }
     };
 
     let res = match futex_op {
         FutexOp::FUTEX_WAIT => {
-            let timeout = get_futex_timeout(utime_addr).expect("Invalid time addr");
-            futex_wait(futex_addr as _, futex_val as _, &timeout).map(|_| 0)
+            let timeout = get_futex_timeout(None)?;
+            futex_wait(futex_addr as _, futex_val as _, timeout.as_ref()).map(|_| 0)
         }
         FutexOp::FUTEX_WAIT_BITSET => {
-            let timeout = get_futex_timeout(utime_addr).expect("Invalid time addr");
-            futex_wait_bitset(futex_addr as _, futex_val as _, &timeout, bitset as _).map(|_| 0)
+            let clockid = if futex_flags.contains(FutexFlags::FUTEX_CLOCK_REALTIME) {
+                ClockId::CLOCK_REALTIME
+            } else {
+                ClockId::CLOCK_MONOTONIC
+            };
+            let timeout = get_futex_timeout(Some(clockid))?;
+            futex_wait_bitset(
+                futex_addr as _,
+                futex_val as _,
+                timeout.as_ref(),
+                bitset as _,
+            )
+            .map(|_| 0)
         }
         FutexOp::FUTEX_WAKE => {
-            let max_count = get_futex_val(futex_val as i32).expect("Invalid futex val");
+            let max_count = get_futex_val(futex_val as i32)?;
             futex_wake(futex_addr as _, max_count).map(|count| count as isize)
         }
         FutexOp::FUTEX_WAKE_BITSET => {
-            let max_count = get_futex_val(futex_val as i32).expect("Invalid futex val");
+            let max_count = get_futex_val(futex_val as i32)?;
             futex_wake_bitset(futex_addr as _, max_count, bitset as _).map(|count| count as isize)
         }
-        FutexOp::FUTEX_REQUEUE => {
-            let max_nwakes = get_futex_val(futex_val as i32).expect("Invalid futex val");
-            let max_nrequeues = get_futex_val(utime_addr as i32).expect("Invalid utime addr");
+        FutexOp::FUTEX_REQUEUE | FutexOp::FUTEX_CMP_REQUEUE => {
+            let max_nwakes = get_futex_val(futex_val as i32)?;
+            let max_nrequeues = get_futex_val(utime_addr as i32)?;
+            let expected_val = if futex_op == FutexOp::FUTEX_CMP_REQUEUE {
+                Some(bitset as i32)
+            } else {
+                None
+            };
             futex_requeue(
                 futex_addr as _,
                 max_nwakes,
                 max_nrequeues,
                 futex_new_addr as _,
+                expected_val,
             )
-            .map(|nwakes| nwakes as _)
+            .map(|count| count as isize)
         }
-        _ => panic!("Unsupported futex operations"),
-    }
-    .unwrap();
+        FutexOp::FUTEX_LOCK_PI => {
+            // The timeout of `FUTEX_LOCK_PI` is always an absolute time of `CLOCK_REALTIME`.
+            let timeout = get_futex_timeout(Some(ClockId::CLOCK_REALTIME))?;
+            futex_lock_pi(futex_addr as _, timeout.as_ref(), false).map(|_| 0)
+        }
+        FutexOp::FUTEX_TRYLOCK_PI => futex_lock_pi(futex_addr as _, None, true).map(|_| 0),
+        FutexOp::FUTEX_UNLOCK_PI => futex_unlock_pi(futex_addr as _).map(|_| 0),
+        FutexOp::FUTEX_FD | FutexOp::FUTEX_WAKE_OP => {
+            return_errno_with_message!(Errno::ENOSYS, "the futex operation is not supported")
+        }
+    }?;
 
     debug!("futex returns, tid= {} ", current_thread!().tid());
     Ok(SyscallReturn::Return(res as _))
 }
+
+pub fn sys_futex_waitv(
+    waiters_addr: Vaddr,
+    nr_futexes: u32,
+    flags: u32,
+    timeout_addr: Vaddr,
+    clockid: clockid_t,
+) -> Result<SyscallReturn> {
+    debug!(
+        "waiters = 0x{:x}, nr_futexes = {}, flags = {:#x}, timeout = 0x{:x}, clockid = {}",
+        waiters_addr, nr_futexes, flags, timeout_addr, clockid
+    );
+
+    if flags != 0 {
+        return_errno_with_message!(Errno::EINVAL, "the flags must be zero");
+    }
+    if nr_futexes == 0 || nr_futexes as usize > FUTEX_WAITV_MAX {
+        return_errno_with_message!(Errno::EINVAL, "the number of futexes is invalid");
+    }
+
+    // The timeout is absolute, so a restarted syscall waits until the same deadline.
+    let timeout = if timeout_addr == 0 {
+        None
+    } else {
+        if clockid != ClockId::CLOCK_MONOTONIC as clockid_t
+            && clockid != ClockId::CLOCK_REALTIME as clockid_t
+        {
+            return_errno_with_message!(Errno::EINVAL, "the clock is not supported");
+        }
+        let deadline = Duration::from(read_val_from_user::<timespec_t>(timeout_addr)?);
+        Some(deadline.saturating_sub(read_clock(clockid)?))
+    };
+
+    let waiters = (0..nr_futexes as usize)
+        .map(|i| {
+            let waiter_addr = waiters_addr + i * core::mem::size_of::<futex_waitv>();
+            read_val_from_user::<futex_waitv>(waiter_addr)?.try_into()
+        })
+        .collect::<Result<Vec<FutexWaitv>>>()?;
+
+    let woken_index = futex_waitv(&waiters, timeout.as_ref())?;
+    Ok(SyscallReturn::Return(woken_index as _))
+}
+
+/// The futex is 32 bits, which is the only supported size.
+const FUTEX2_SIZE_U32: u32 = 0x02;
+const FUTEX2_PRIVATE: u32 = 128;
+
+#[derive(Debug, Clone, Copy, Pod)]
+#[repr(C)]
+#[allow(non_camel_case_types)]
+struct futex_waitv {
+    val: u64,
+    uaddr: u64,
+    flags: u32,
+    __reserved: u32,
+}
+
+impl TryFrom<futex_waitv> for FutexWaitv {
+    type Error = Error;
+
+    fn try_from(futex_waitv: futex_waitv) -> Result<Self> {
+        if futex_waitv.flags & !(FUTEX2_SIZE_U32 | FUTEX2_PRIVATE) != 0
+            || futex_waitv.flags & FUTEX2_SIZE_U32 == 0
+            || futex_waitv.__reserved != 0
+        {
+            return_errno_with_message!(Errno::EINVAL, "the futex flags are invalid");
+        }
+        if futex_waitv.uaddr % core::mem::size_of::<u32>() as u64 != 0 {
+            return_errno_with_message!(Errno::EINVAL, "the futex address is not aligned");
+        }
+        let val = u32::try_from(futex_waitv.val)
+            .map_err(|_| Error::with_message(Errno::EINVAL, "the futex value is too large"))?;
+
+        Ok(FutexWaitv {
+            addr: futex_waitv.uaddr as Vaddr,
+            val,
+        })
+    }
+}
@@ -1,24 +1,36 @@
 // SPDX-License-Identifier: MPL-2.0
 
 use super::SyscallReturn;
-use crate::{device, prelude::*, util::write_bytes_to_user};
+use crate::{
+    prelude::*,
+    util::{random, write_bytes_to_user},
+};
 
 pub fn sys_getrandom(buf: Vaddr, count: usize, flags: u32) -> Result<SyscallReturn> {
-    let flags = GetRandomFlags::from_bits_truncate(flags);
+    let flags = GetRandomFlags::from_bits(flags)
+        .ok_or_else(|| Error::with_message(Errno::EINVAL, "unknown flags"))?;
     debug!(
         "buf = 0x{:x}, count = 0x{:x}, flags = {:?}",
         buf, count, flags
     );
-    // TODO: support nonblock flag.
-    // Currently our getrandom implementation relies on x86-specific `rdrand` instruction, so it will never block.
+    if flags.contains(GetRandomFlags::GRND_INSECURE | GetRandomFlags::GRND_RANDOM) {
+        return_errno_with_message!(
+            Errno::EINVAL,
+            "requesting insecure and blocking randomness makes no sense"
+        );
+    }
+    // Like Linux, `GRND_RANDOM` draws from the same RNG, which blocks only until it is
+    // seeded with enough entropy.
+    if !flags.contains(GetRandomFlags::GRND_INSECURE) && !random::is_ready() {
+        if flags.contains(GetRandomFlags::GRND_NONBLOCK) {
+            return_errno_with_message!(Errno::EAGAIN, "the RNG is not ready");
+        }
+        random::wait_until_ready()?;
+    }
     let mut buffer = vec![0u8; count];
-    let read_len = if flags.contains(GetRandomFlags::GRND_RANDOM) {
-        device::Random::getrandom(&mut buffer)?
-    } else {
-        device::Urandom::getrandom(&mut buffer)?
-    };
+    random::getrandom(&mut buffer)?;
     write_bytes_to_user(buf, &buffer)?;
-    Ok(SyscallReturn::Return(read_len as isize))
+    Ok(SyscallReturn::Return(count as isize))
 }
 
 bitflags::bitflags! {
@@ -0,0 +1,78 @@
+// SPDX-License-Identifier: MPL-2.0
+
+use super::SyscallReturn;
+use crate::{
+    fs::{
+        file_table::{FdFlags, FileDesc},
+        fs_resolver::{FsPath, AT_FDCWD},
+        inotify::{InotifyEvents, InotifyFile, InotifyFlags},
+        utils::{Permission, PATH_MAX},
+    },
+    prelude::*,
+    util::read_cstring_from_user,
+};
+
+pub fn sys_inotify_init() -> Result<SyscallReturn> {
+    do_inotify_init(InotifyFlags::empty())
+}
+
+pub fn sys_inotify_init1(flags: u32) -> Result<SyscallReturn> {
+    let flags = InotifyFlags::from_bits(flags)
+        .ok_or_else(|| Error::with_message(Errno::EINVAL, "unknown flags"))?;
+    debug!("flags = {:?}", flags);
+
+    do_inotify_init(flags)
+}
+
+fn do_inotify_init(flags: InotifyFlags) -> Result<SyscallReturn> {
+    let inotify_file = InotifyFile::new(flags.contains(InotifyFlags::IN_NONBLOCK));
+    let fd_flags = if flags.contains(InotifyFlags::IN_CLOEXEC) {
+        FdFlags::CLOEXEC
+    } else {
+        FdFlags::empty()
+    };
+    let current = current!();
+    let fd = current.file_table().lock().insert(inotify_file, fd_flags);
+    Ok(SyscallReturn::Return(fd as _))
+}
+
+pub fn sys_inotify_add_watch(fd: FileDesc, path_addr: Vaddr, mask: u32) -> Result<SyscallReturn> {
+    let path = read_cstring_from_user(path_addr, PATH_MAX)?;
+    let mask = InotifyEvents::from_bits_truncate(mask);
+    debug!("fd = {}, path = {:?}, mask = {:?}", fd, path, mask);
+
+    let current = current!();
+    let dentry = {
+        let path = path.to_string_lossy();
+        if path.is_empty() {
+            return_errno_with_message!(Errno::ENOENT, "path is empty");
+        }
+        let fs_path = FsPath::new(AT_FDCWD, path.as_ref())?;
+        let fs = current.fs().read();
+        if mask.contains(InotifyEvents::IN_DONT_FOLLOW) {
+            fs.lookup_no_follow(&fs_path)?
+        } else {
+            fs.lookup(&fs_path)?
+        }
+    };
+    dentry.inode().check_permission(Permission::MAY_READ)?;
+
+    let file = current.file_table().lock().get_file(fd)?.clone();
+    let inotify_file = file
+        .downcast_ref::<InotifyFile>()
+        .ok_or_else(|| Error::with_message(Errno::EINVAL, "not an inotify file"))?;
+    let wd = inotify_file.add_watch(dentry.inode().clone(), mask)?;
+    Ok(SyscallReturn::Return(wd as _))
+}
+
+pub fn sys_inotify_rm_watch(fd: FileDesc, wd: i32) -> Result<SyscallReturn> {
+    debug!("fd = {}, wd = {}", fd, wd);
+
+    let current = current!();
+    let file = current.file_table().lock().get_file(fd)?.clone();
+    let inotify_file = file
+        .downcast_ref::<InotifyFile>()
+        .ok_or_else(|| Error::with_message(Errno::EINVAL, "not an inotify file"))?;
+    inotify_file.remove_watch(wd)?;
+    Ok(SyscallReturn::Return(0))
+}
@@ -0,0 +1,131 @@
+// SPDX-License-Identifier: MPL-2.0
+
+use super::{eventfd::is_eventfd, SyscallReturn};
+use crate::{
+    fs::{
+        file_table::{FdFlags, FileDesc},
+        io_uring::{BufRingReg, EnterFlags, IoUringFile, IoUringParams},
+    },
+    prelude::*,
+    util::{read_val_from_user, write_val_to_user},
+};
+
+pub fn sys_io_uring_setup(entries: u32, params_ptr: Vaddr) -> Result<SyscallReturn> {
+    let mut params: IoUringParams = read_val_from_user(params_ptr)?;
+    debug!("entries = {}, params = {:?}", entries, params);
+
+    let io_uring = IoUringFile::new(entries, &mut params)?;
+    write_val_to_user(params_ptr, &params)?;
+
+    let fd = {
+        let current = current!();
+        let mut file_table = current.file_table().lock();
+        // Like Linux, the file is always closed on exec.
+        file_table.insert(Arc::new(io_uring), FdFlags::CLOEXEC)
+    };
+    Ok(SyscallReturn::Return(fd as _))
+}
+
+pub fn sys_io_uring_enter(
+    fd: FileDesc,
+    to_submit: u32,
+    min_complete: u32,
+    flags: u32,
+    sig_ptr: Vaddr,
+    sig_size: usize,
+) -> Result<SyscallReturn> {
+    debug!(
+        "fd = {}, to_submit = {}, min_complete = {}, flags = {:#x}, sig_ptr = {:#x}, sig_size = {}",
+        fd, to_submit, min_complete, flags, sig_ptr, sig_size
+    );
+
+    let flags = EnterFlags::from_bits(flags)
+        .ok_or_else(|| Error::with_message(Errno::EINVAL, "invalid enter flags"))?;
+    if flags.intersects(!EnterFlags::IORING_ENTER_GETEVENTS) {
+        return_errno_with_message!(Errno::EINVAL, "the enter flags are not supported");
+    }
+    if sig_ptr != 0 {
+        return_errno_with_message!(Errno::EINVAL, "the signal mask is not supported");
+    }
+
+    let file = {
+        let current = current!();
+        let file_table = current.file_table().lock();
+        file_table.get_file(fd)?.clone()
+    };
+    let Some(io_uring) = file.downcast_ref::<IoUringFile>() else {
+        return_errno_with_message!(Errno::EOPNOTSUPP, "the file is not an io_uring");
+    };
+
+    let nr_submitted = io_uring.enter(to_submit, min_complete, flags)?;
+    Ok(SyscallReturn::Return(nr_submitted as _))
+}
+
+pub fn sys_io_uring_register(
+    fd: FileDesc,
+    opcode: u32,
+    arg: Vaddr,
+    nr_args: u32,
+) -> Result<SyscallReturn> {
+    debug!(
+        "fd = {}, opcode = {}, arg = {:#x}, nr_args = {}",
+        fd, opcode, arg, nr_args
+    );
+
+    let eventfd_fd: Option<FileDesc> = if opcode == IORING_REGISTER_EVENTFD {
+        if nr_args != 1 {
+            return_errno_with_message!(Errno::EINVAL, "exactly one eventfd is expected");
+        }
+        Some(read_val_from_user(arg)?)
+    } else {
+        None
+    };
+
+    let (file, eventfd) = {
+        let current = current!();
+        let file_table = current.file_table().lock();
+        let file = file_table.get_file(fd)?.clone();
+        let eventfd = match eventfd_fd {
+            Some(eventfd_fd) => Some(file_table.get_file(eventfd_fd)?.clone()),
+            None => None,
+        };
+        (file, eventfd)
+    };
+    let Some(io_uring) = file.downcast_ref::<IoUringFile>() else {
+        return_errno_with_message!(Errno::EOPNOTSUPP, "the file is not an io_uring");
+    };
+
+    match opcode {
+        IORING_REGISTER_EVENTFD => {
+            let eventfd = eventfd.unwrap();
+            if !is_eventfd(eventfd.as_ref()) {
+                return_errno_with_message!(Errno::EINVAL, "the file is not an eventfd");
+            }
+            io_uring.register_eventfd(eventfd)?;
+        }
+        IORING_UNREGISTER_EVENTFD => {
+            if arg != 0 || nr_args != 0 {
+                return_errno_with_message!(Errno::EINVAL, "no arguments are expected");
+            }
+            io_uring.unregister_eventfd()?;
+        }
+        IORING_REGISTER_PBUF_RING | IORING_UNREGISTER_PBUF_RING => {
+            if nr_args != 1 {
+                return_errno_with_message!(Errno::EINVAL, "exactly one buffer ring is expected");
+            }
+            let reg: BufRingReg = read_val_from_user(arg)?;
+            if opcode == IORING_REGISTER_PBUF_RING {
+                io_uring.register_buf_ring(&reg)?;
+            } else {
+                io_uring.unregister_buf_ring(reg.bgid)?;
+            }
+        }
+        _ => return_errno_with_message!(Errno::EINVAL, "the register opcode is not supported"),
+    }
+    Ok(SyscallReturn::Return(0))
+}
+
+const IORING_REGISTER_EVENTFD: u32 = 4;
+const IORING_UNREGISTER_EVENTFD: u32 = 5;
+const IORING_REGISTER_PBUF_RING: u32 = 22;
+const IORING_UNREGISTER_PBUF_RING: u32 = 23;
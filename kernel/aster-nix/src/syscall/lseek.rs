@@ -17,6 +17,16 @@ pub fn sys_lseek(fd: FileDesc, offset: isize, whence: u32) -> Result<SyscallRetu
         }
         1 => SeekFrom::Current(offset),
         2 => SeekFrom::End(offset),
+        3 | 4 => {
+            if offset < 0 {
+                return_errno_with_message!(Errno::ENXIO, "the offset is negative");
+            }
+            if whence == 3 {
+                SeekFrom::Data(offset as usize)
+            } else {
+                SeekFrom::Hole(offset as usize)
+            }
+        }
         _ => return_errno!(Errno::EINVAL),
     };
     let current = current!();
@@ -1,7 +1,11 @@
 // SPDX-License-Identifier: MPL-2.0
 
+use core::ops::Range;
+
+use align_ext::AlignExt;
+
 use super::SyscallReturn;
-use crate::{prelude::*, util::read_bytes_from_user};
+use crate::prelude::*;
 
 pub fn sys_madvise(start: Vaddr, len: usize, behavior: i32) -> Result<SyscallReturn> {
     let behavior = MadviseBehavior::try_from(behavior)?;
@@ -9,29 +13,37 @@ pub fn sys_madvise(start: Vaddr, len: usize, behavior: i32) -> Result<SyscallRet
         "start = 0x{:x}, len = 0x{:x}, behavior = {:?}",
         start, len, behavior
     );
+    let range = advised_range(start, len)?;
+    if range.is_empty() {
+        return Ok(SyscallReturn::Return(0));
+    }
+
+    let current = current!();
+    let root_vmar = current.root_vmar();
     match behavior {
         MadviseBehavior::MADV_NORMAL
-        | MadviseBehavior::MADV_SEQUENTIAL
-        | MadviseBehavior::MADV_WILLNEED => {
-            // perform a read at first
-            let mut buffer = vec![0u8; len];
-            read_bytes_from_user(start, &mut buffer)?;
+        | MadviseBehavior::MADV_RANDOM
+        | MadviseBehavior::MADV_SEQUENTIAL => {
+            // These are only hints about the access pattern, so it is fine to ignore them.
         }
-        MadviseBehavior::MADV_DONTNEED => madv_dontneed(start, len)?,
+        MadviseBehavior::MADV_WILLNEED => root_vmar.will_need(range)?,
+        MadviseBehavior::MADV_DONTNEED => root_vmar.discard(range)?,
+        MadviseBehavior::MADV_FREE => root_vmar.lazy_free(range)?,
         _ => todo!(),
     }
     Ok(SyscallReturn::Return(0))
 }
 
-fn madv_dontneed(start: Vaddr, len: usize) -> Result<()> {
-    debug_assert!(start % PAGE_SIZE == 0);
-    debug_assert!(len % PAGE_SIZE == 0);
-    let current = current!();
-    let root_vmar = current.root_vmar();
-    let advised_range = start..start + len;
-    // `destroy()` interface may require adjustment and replacement afterwards.
-    let _ = root_vmar.destroy(advised_range);
-    Ok(())
+/// Returns the page-aligned range to be advised. The start address must be page-aligned.
+fn advised_range(start: Vaddr, len: usize) -> Result<Range<Vaddr>> {
+    if start % PAGE_SIZE != 0 {
+        return_errno_with_message!(Errno::EINVAL, "the start address is not page-aligned");
+    }
+    let end = start
+        .checked_add(len)
+        .filter(|end| *end <= usize::MAX - PAGE_SIZE + 1)
+        .ok_or_else(|| Error::with_message(Errno::EINVAL, "the range overflows"))?;
+    Ok(start..end.align_up(PAGE_SIZE))
 }
 
 #[repr(i32)]
@@ -0,0 +1,46 @@
+// SPDX-License-Identifier: MPL-2.0
+
+use super::SyscallReturn;
+use crate::{
+    fs::{
+        file_table::FdFlags,
+        memfd::{MemfdFile, MAX_MEMFD_NAME_LEN},
+    },
+    prelude::*,
+    util::read_cstring_from_user,
+};
+
+pub fn sys_memfd_create(name_addr: Vaddr, flags: u32) -> Result<SyscallReturn> {
+    let name = read_cstring_from_user(name_addr, MAX_MEMFD_NAME_LEN + 1)?;
+    let flags = MemfdFlags::from_bits(flags)
+        .ok_or_else(|| Error::with_message(Errno::EINVAL, "unknown flags"))?;
+    debug!("name = {:?}, flags = {:?}", name, flags);
+
+    if flags.contains(MemfdFlags::MFD_HUGETLB) {
+        return_errno_with_message!(Errno::EINVAL, "huge pages are not supported");
+    }
+
+    let memfd_file = MemfdFile::new(
+        name.to_string_lossy().into_owned(),
+        flags.contains(MemfdFlags::MFD_ALLOW_SEALING),
+    )?;
+    let fd_flags = if flags.contains(MemfdFlags::MFD_CLOEXEC) {
+        FdFlags::CLOEXEC
+    } else {
+        FdFlags::empty()
+    };
+    let current = current!();
+    let fd = current
+        .file_table()
+        .lock()
+        .insert(Arc::new(memfd_file), fd_flags);
+    Ok(SyscallReturn::Return(fd as _))
+}
+
+bitflags! {
+    struct MemfdFlags: u32 {
+        const MFD_CLOEXEC = 0x0001;
+        const MFD_ALLOW_SEALING = 0x0002;
+        const MFD_HUGETLB = 0x0004;
+    }
+}
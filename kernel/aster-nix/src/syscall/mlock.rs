@@ -0,0 +1,142 @@
+// SPDX-License-Identifier: MPL-2.0
+
+use core::ops::Range;
+
+use align_ext::AlignExt;
+
+use super::SyscallReturn;
+use crate::{
+    prelude::*,
+    process::{credentials, credentials::capabilities::CapSet, ResourceType},
+};
+
+pub fn sys_mlock(addr: Vaddr, len: usize) -> Result<SyscallReturn> {
+    debug!("addr = 0x{:x}, len = 0x{:x}", addr, len);
+    do_mlock(addr, len, true)
+}
+
+pub fn sys_mlock2(addr: Vaddr, len: usize, flags: u32) -> Result<SyscallReturn> {
+    let flags = Mlock2Flags::from_bits(flags)
+        .ok_or_else(|| Error::with_message(Errno::EINVAL, "invalid mlock2 flags"))?;
+    debug!(
+        "addr = 0x{:x}, len = 0x{:x}, flags = {:?}",
+        addr, len, flags
+    );
+    do_mlock(addr, len, !flags.contains(Mlock2Flags::MLOCK_ONFAULT))
+}
+
+pub fn sys_munlock(addr: Vaddr, len: usize) -> Result<SyscallReturn> {
+    debug!("addr = 0x{:x}, len = 0x{:x}", addr, len);
+    let range = page_range(addr, len)?;
+    if !range.is_empty() {
+        current!().root_vmar().munlock(range)?;
+    }
+    Ok(SyscallReturn::Return(0))
+}
+
+pub fn sys_mlockall(flags: u32) -> Result<SyscallReturn> {
+    let flags = MlockallFlags::from_bits(flags)
+        .ok_or_else(|| Error::with_message(Errno::EINVAL, "invalid mlockall flags"))?;
+    debug!("flags = {:?}", flags);
+    if !flags.intersects(MlockallFlags::MCL_CURRENT | MlockallFlags::MCL_FUTURE) {
+        return_errno_with_message!(Errno::EINVAL, "neither MCL_CURRENT nor MCL_FUTURE is set");
+    }
+    check_can_lock()?;
+
+    let current = current!();
+    let root_vmar = current.root_vmar();
+    let populate = !flags.contains(MlockallFlags::MCL_ONFAULT);
+    if flags.contains(MlockallFlags::MCL_CURRENT) {
+        let vmar_range = root_vmar.base()..root_vmar.base() + root_vmar.size();
+        let nr_bytes_to_lock =
+            root_vmar.mapped_size(&vmar_range) - root_vmar.locked_size(&vmar_range);
+        check_lock_limit(nr_bytes_to_lock)?;
+        root_vmar.mlock_all(populate)?;
+    }
+    if flags.contains(MlockallFlags::MCL_FUTURE) {
+        root_vmar.set_lock_future(Some(populate));
+    } else {
+        root_vmar.set_lock_future(None);
+    }
+    Ok(SyscallReturn::Return(0))
+}
+
+pub fn sys_munlockall() -> Result<SyscallReturn> {
+    current!().root_vmar().munlock_all()?;
+    Ok(SyscallReturn::Return(0))
+}
+
+fn do_mlock(addr: Vaddr, len: usize, populate: bool) -> Result<SyscallReturn> {
+    check_can_lock()?;
+    let range = page_range(addr, len)?;
+    if range.is_empty() {
+        return Ok(SyscallReturn::Return(0));
+    }
+
+    let current = current!();
+    let root_vmar = current.root_vmar();
+    check_lock_limit(range.len() - root_vmar.locked_size(&range))?;
+    root_vmar.mlock(range, populate)?;
+    Ok(SyscallReturn::Return(0))
+}
+
+/// Returns the page-aligned range that covers `len` bytes from `addr`.
+fn page_range(addr: Vaddr, len: usize) -> Result<Range<Vaddr>> {
+    let end = addr
+        .checked_add(len)
+        .filter(|end| *end <= usize::MAX - PAGE_SIZE + 1)
+        .ok_or_else(|| Error::with_message(Errno::EINVAL, "the range overflows"))?;
+    Ok(addr.align_down(PAGE_SIZE)..end.align_up(PAGE_SIZE))
+}
+
+fn has_ipc_lock_capability() -> bool {
+    credentials().effective_capset().contains(CapSet::IPC_LOCK)
+}
+
+/// Checks whether the current process is allowed to lock any memory.
+fn check_can_lock() -> Result<()> {
+    if memlock_limit() == 0 && !has_ipc_lock_capability() {
+        return_errno_with_message!(Errno::EPERM, "locking memory is not allowed");
+    }
+    Ok(())
+}
+
+/// Checks whether `nr_bytes_to_lock` more bytes can be locked in memory by the current
+/// process without exceeding `RLIMIT_MEMLOCK`.
+pub(super) fn check_lock_limit(nr_bytes_to_lock: usize) -> Result<()> {
+    if has_ipc_lock_capability() {
+        return Ok(());
+    }
+
+    let current = current!();
+    let root_vmar = current.root_vmar();
+    let vmar_range = root_vmar.base()..root_vmar.base() + root_vmar.size();
+    let nr_locked_bytes = root_vmar.locked_size(&vmar_range);
+    if nr_locked_bytes.saturating_add(nr_bytes_to_lock) > memlock_limit() {
+        return_errno_with_message!(Errno::ENOMEM, "the locked memory exceeds RLIMIT_MEMLOCK");
+    }
+    Ok(())
+}
+
+fn memlock_limit() -> usize {
+    let current = current!();
+    let resource_limits = current.resource_limits().lock();
+    let limit = resource_limits
+        .get_rlimit(ResourceType::RLIMIT_MEMLOCK)
+        .get_cur();
+    limit.try_into().unwrap_or(usize::MAX)
+}
+
+bitflags! {
+    struct Mlock2Flags: u32 {
+        const MLOCK_ONFAULT = 0x01;
+    }
+}
+
+bitflags! {
+    struct MlockallFlags: u32 {
+        const MCL_CURRENT = 1;
+        const MCL_FUTURE  = 2;
+        const MCL_ONFAULT = 4;
+    }
+}
@@ -7,13 +7,18 @@
 use align_ext::AlignExt;
 use aster_rights::Rights;
 
-use super::SyscallReturn;
+use super::{mlock::check_lock_limit, SyscallReturn};
 use crate::{
-    fs::file_table::FileDesc,
+    fs::{
+        file_handle::{MmapRegion, MmapTarget},
+        file_table::FileDesc,
+        path::Dentry,
+    },
     prelude::*,
     vm::{
         perms::VmPerms,
-        vmo::{Vmo, VmoChildOptions, VmoOptions, VmoRightsOp},
+        vmar::SharedMem,
+        vmo::{Vmo, VmoChildOptions, VmoFlags, VmoOptions, VmoRightsOp},
     },
 };
 
@@ -59,19 +64,59 @@ fn do_sys_mmap(
         return_errno_with_message!(Errno::EINVAL, "mmap only support page-aligned offset");
     }
 
-    let vmo = if option.flags.contains(MMapFlags::MAP_ANONYMOUS) {
+    let current = current!();
+    let mut file = None;
+    let region = if option.flags.contains(MMapFlags::MAP_ANONYMOUS) {
         if offset != 0 {
             return_errno_with_message!(Errno::EINVAL, "offset must be zero for anonymous mapping");
         }
-        alloc_anonyous_vmo(len)?
+        let vmo = alloc_anonyous_vmo(len, &option)?;
+        if option.typ() == MMapType::Shared {
+            // Shared anonymous memory is tracked by a shared memory object, so that all
+            // the mappings of it, including those inherited by the children, are coherent.
+            MmapRegion::from_shared_mem(SharedMem::from_vmo(vmo), 0)
+        } else {
+            MmapRegion::from_vmo(vmo)
+        }
     } else {
-        alloc_filebacked_vmo(fd, len, offset, &option)?
+        let is_shared = option.typ() == MMapType::Shared;
+        let file_like = current.file_table().lock().get_file(fd)?.clone();
+        if let Some(region) = file_like.mmap(offset, len, is_shared, vm_perms)? {
+            region
+        } else {
+            let dentry = current.fs().read().lookup_from_fd(fd)?;
+            let shared_mem = dentry.inode().shared_mem();
+            if is_shared && let Some(shared_mem) = shared_mem {
+                // The VMO of the shared memory object starts at the start of the file.
+                file = Some((dentry, 0));
+                MmapRegion::from_shared_mem(shared_mem, offset)
+            } else {
+                let vmo = alloc_filebacked_vmo(&dentry, len, offset, &option)?;
+                file = Some((dentry, offset));
+                MmapRegion::from_vmo(vmo)
+            }
+        }
     };
 
-    let current = current!();
     let root_vmar = current.root_vmar();
+    let is_locked =
+        option.flags.contains(MMapFlags::MAP_LOCKED) || root_vmar.lock_future().is_some();
+    if is_locked {
+        check_lock_limit(len).map_err(|_| {
+            Error::with_message(Errno::EAGAIN, "the locked memory exceeds RLIMIT_MEMLOCK")
+        })?;
+    }
+
     let vm_map_options = {
-        let mut options = root_vmar.new_map(vmo.to_dyn(), vm_perms)?;
+        let (target, cache_policy) = region.into_parts();
+        let mut options = match target {
+            MmapTarget::Vmo(vmo) => root_vmar.new_map(vmo.to_dyn(), vm_perms)?,
+            MmapTarget::SharedMem(shared_mem, vmo_offset) => root_vmar
+                .new_map_shared(&shared_mem, vm_perms)?
+                .vmo_offset(vmo_offset)
+                .size(len),
+        };
+        options = options.cache_policy(cache_policy);
         let flags = option.flags;
         if flags.contains(MMapFlags::MAP_FIXED) {
             options = options.offset(addr).can_overwrite(true);
@@ -82,6 +127,12 @@ fn do_sys_mmap(
 
         if option.typ() == MMapType::Shared {
             options = options.is_shared(true);
+        } else if option.typ() == MMapType::Droppable {
+            options = options.droppable(true);
+        }
+
+        if let Some((dentry, file_offset)) = file {
+            options = options.file(dentry, file_offset);
         }
 
         options
@@ -89,33 +140,41 @@ fn do_sys_mmap(
     let map_addr = vm_map_options.build()?;
     trace!("map range = 0x{:x} - 0x{:x}", map_addr, map_addr + len);
 
+    if option.flags.contains(MMapFlags::MAP_LOCKED) {
+        // Like Linux, failing to lock the pages does not fail the mapping.
+        let _ = root_vmar.mlock(map_addr..map_addr + len, true);
+    } else if option.flags.contains(MMapFlags::MAP_POPULATE) {
+        // Neither does failing to populate the pages.
+        let _ = root_vmar.populate(map_addr..map_addr + len);
+    }
+
     Ok(map_addr)
 }
 
-fn alloc_anonyous_vmo(len: usize) -> Result<Vmo> {
-    let vmo_options: VmoOptions<Rights> = VmoOptions::new(len);
+fn alloc_anonyous_vmo(len: usize, option: &MMapOptions) -> Result<Vmo> {
+    let mut vmo_options: VmoOptions<Rights> = VmoOptions::new(len);
+    // Private anonymous mappings can be enlarged by mremap. Shared ones cannot, because a
+    // resizable VMO cannot have the slice children that share the mappings on fork.
+    if matches!(option.typ(), MMapType::Private | MMapType::Droppable) {
+        vmo_options = vmo_options.flags(VmoFlags::RESIZABLE);
+    }
     vmo_options.alloc()
 }
 
 fn alloc_filebacked_vmo(
-    fd: FileDesc,
+    dentry: &Dentry,
     len: usize,
     offset: usize,
     option: &MMapOptions,
 ) -> Result<Vmo> {
-    let current = current!();
-    let page_cache_vmo = {
-        let fs_resolver = current.fs().read();
-        let dentry = fs_resolver.lookup_from_fd(fd)?;
-        let inode = dentry.inode();
-        inode
-            .page_cache()
-            .ok_or(Error::with_message(
-                Errno::EBADF,
-                "File does not have page cache",
-            ))?
-            .to_dyn()
-    };
+    let page_cache_vmo = dentry
+        .inode()
+        .page_cache()
+        .ok_or(Error::with_message(
+            Errno::EBADF,
+            "File does not have page cache",
+        ))?
+        .to_dyn();
 
     if option.typ() == MMapType::Private {
         // map private
@@ -132,6 +191,23 @@ fn check_option(option: &MMapOptions) -> Result<()> {
         return_errno_with_message!(Errno::EINVAL, "Invalid mmap type");
     }
 
+    if option.typ() == MMapType::Droppable {
+        // A droppable mapping can only be anonymous, and makes no sense to be locked or
+        // to grow down as a stack.
+        if !option.flags.contains(MMapFlags::MAP_ANONYMOUS) {
+            return_errno_with_message!(Errno::EINVAL, "droppable mappings must be anonymous");
+        }
+        if option
+            .flags
+            .intersects(MMapFlags::MAP_LOCKED | MMapFlags::MAP_GROWSDOWN)
+        {
+            return_errno_with_message!(
+                Errno::EINVAL,
+                "droppable mappings cannot be locked or grow down"
+            );
+        }
+    }
+
     Ok(())
 }
 
@@ -151,6 +227,7 @@ pub enum MMapType {
     Shared = 0x1,
     Private = 0x2,
     SharedValidate = 0x3,
+    Droppable = 0x8,
 }
 
 bitflags! {
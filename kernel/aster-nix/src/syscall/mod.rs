@@ -4,14 +4,17 @@
 //! The each sub module contains functions that handle real syscall logic.
 use aster_frame::cpu::UserContext;
 pub use clock_gettime::ClockId;
+pub use unsupported::{clear_unsupported_syscalls, unsupported_syscalls_report};
 
 use crate::{cpu::LinuxAbi, prelude::*};
 
+#[cfg(feature = "net")]
 mod accept;
 mod access;
 mod alarm;
 mod arch;
 mod arch_prctl;
+#[cfg(feature = "net")]
 mod bind;
 mod brk;
 mod capget;
@@ -23,15 +26,20 @@ mod chroot;
 mod clock_gettime;
 mod clone;
 mod close;
+#[cfg(feature = "net")]
 mod connect;
 mod constants;
+mod copy_file_range;
 mod dup;
 mod epoll;
 mod eventfd;
 mod execve;
 mod exit;
 mod exit_group;
+mod fadvise64;
+mod fallocate;
 mod fcntl;
+mod flock;
 mod fork;
 mod fsync;
 mod futex;
@@ -41,6 +49,7 @@ mod getegid;
 mod geteuid;
 mod getgid;
 mod getgroups;
+#[cfg(feature = "net")]
 mod getpeername;
 mod getpgrp;
 mod getpid;
@@ -50,33 +59,46 @@ mod getresgid;
 mod getresuid;
 mod getrusage;
 mod getsid;
+#[cfg(feature = "net")]
 mod getsockname;
+#[cfg(feature = "net")]
 mod getsockopt;
 mod gettid;
 mod gettimeofday;
 mod getuid;
+mod inotify;
+mod io_uring;
 mod ioctl;
 mod kill;
 mod link;
+#[cfg(feature = "net")]
 mod listen;
 mod lseek;
 mod madvise;
+mod memfd_create;
 mod mkdir;
+mod mlock;
 mod mmap;
 mod mount;
 mod mprotect;
+mod mremap;
 mod munmap;
 mod nanosleep;
 mod open;
 mod pause;
 mod pipe;
+mod pkey;
 mod poll;
 mod prctl;
 mod pread64;
 mod prlimit64;
+mod ptrace;
 mod read;
 mod readlink;
+#[cfg(feature = "net")]
 mod recvfrom;
+#[cfg(feature = "net")]
+mod recvmmsg;
 mod rename;
 mod rmdir;
 mod rt_sigaction;
@@ -84,10 +106,14 @@ mod rt_sigpending;
 mod rt_sigprocmask;
 mod rt_sigreturn;
 mod rt_sigsuspend;
-mod sched_getaffinity;
+mod sched_affinity;
+mod sched_policy;
 mod sched_yield;
 mod select;
 mod sendfile;
+#[cfg(feature = "net")]
+mod sendmmsg;
+#[cfg(feature = "net")]
 mod sendto;
 mod set_get_priority;
 mod set_robust_list;
@@ -103,30 +129,42 @@ mod setresgid;
 mod setresuid;
 mod setreuid;
 mod setsid;
+#[cfg(feature = "net")]
 mod setsockopt;
 mod setuid;
+mod shm;
+#[cfg(feature = "net")]
 mod shutdown;
 mod sigaltstack;
+mod signalfd;
+#[cfg(feature = "net")]
 mod socket;
+#[cfg(feature = "net")]
 mod socketpair;
+mod splice;
 mod stat;
 mod statfs;
+mod swapon;
 mod symlink;
 mod sync;
 mod tgkill;
 mod time;
 mod timer_create;
 mod timer_settime;
+mod timerfd;
 mod truncate;
 mod umask;
 mod umount;
 mod uname;
 mod unlink;
+mod unsupported;
+mod userfaultfd;
 mod utimens;
 mod wait4;
 mod waitid;
 mod write;
 mod writev;
+mod xattr;
 
 /// This macro is used to define syscall handler.
 /// The first param is ths number of parameters,
@@ -166,9 +204,16 @@ macro_rules! dispatch_fn_inner {
 
 macro_rules! impl_syscall_nums_and_dispatch_fn {
     // $args, $context, and $dispatcher_name are needed since Rust macro is hygienic
-    ( $( $name: ident = $num: literal => $handler: ident $args: tt );* $(;)? ) => {
+    //
+    // The attributes of an entry, e.g., `#[cfg(feature = "net")]`, apply to the syscall
+    // number and the dispatching. A syscall that is compiled out is unimplemented.
+    (
+        $( $(#[$attr: meta])* $name: ident = $num: literal => $handler: ident $args: tt );*
+        $(;)?
+    ) => {
         // First, define the syscall numbers
         $(
+            $(#[$attr])*
             pub const $name: u64 = $num;
         )*
 
@@ -180,6 +225,7 @@ macro_rules! impl_syscall_nums_and_dispatch_fn {
         ) -> $crate::prelude::Result<$crate::syscall::SyscallReturn> {
             match syscall_number {
                 $(
+                    $(#[$attr])*
                     $num => {
                         $crate::log_syscall_entry!($name);
                         $crate::syscall::dispatch_fn_inner!(args, context, $handler $args)
@@ -191,6 +237,17 @@ macro_rules! impl_syscall_nums_and_dispatch_fn {
                 }
             }
         }
+
+        // Finally, define the function that gets the names of the syscalls
+        pub fn syscall_name(syscall_number: u64) -> Option<&'static str> {
+            match syscall_number {
+                $(
+                    $(#[$attr])*
+                    $num => Some(stringify!($name)),
+                )*
+                _ => None,
+            }
+        }
     }
 }
 
@@ -237,6 +294,7 @@ pub fn handle_syscall(context: &mut UserContext) {
         }
         Err(err) => {
             debug!("syscall return error: {:?}", err);
+            unsupported::record(syscall_frame.syscall_number, err.error());
             let errno = err.error() as i32;
             context.set_syscall_ret((-errno) as usize)
         }
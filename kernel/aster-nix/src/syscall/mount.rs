@@ -1,12 +1,25 @@
 // SPDX-License-Identifier: MPL-2.0
 
 use super::SyscallReturn;
+#[cfg(feature = "exfat")]
+use crate::fs::exfat::{ExfatFS, ExfatMountOptions};
+#[cfg(feature = "ext2")]
+use crate::fs::ext2::Ext2;
+#[cfg(feature = "overlayfs")]
+use crate::fs::overlayfs::{OverlayFS, OverlayMountOptions};
+#[cfg(feature = "v9fs")]
+use crate::fs::v9fs::V9FS;
+#[cfg(feature = "vfat")]
+use crate::fs::vfat::VfatFS;
+#[cfg(feature = "virtiofs")]
+use crate::fs::virtiofs::VirtioFS;
 use crate::{
     fs::{
-        exfat::{ExfatFS, ExfatMountOptions},
-        ext2::Ext2,
+        binfmt_misc,
         fs_resolver::{FsPath, AT_FDCWD},
-        path::Dentry,
+        path::{Dentry, PerMountFlags},
+        ramfs::{RamFS, TmpfsMountOptions},
+        tracefs,
         utils::{FileSystem, InodeType},
     },
     prelude::*,
@@ -16,8 +29,8 @@ use crate::{
 
 /// The `data` argument is interpreted by the different filesystems.
 /// Typically it is a string of comma-separated options understood by
-/// this filesystem. The current implementation only considers the case
-/// where it is `NULL`. Because it should be interpreted by the specific filesystems.
+/// this filesystem. The current implementation only interprets it for tmpfs
+/// and overlay, and ignores it for the other filesystems.
 pub fn sys_mount(
     devname_addr: Vaddr,
     dirname_addr: Vaddr,
@@ -62,7 +75,7 @@ pub fn sys_mount(
     } else if mount_flags.contains(MountFlags::MS_MOVE) {
         do_move_mount_old(devname, dst_dentry)?;
     } else {
-        do_new_mount(devname, fstype_addr, dst_dentry)?;
+        do_new_mount(devname, fstype_addr, dst_dentry, mount_flags, data)?;
     }
 
     Ok(SyscallReturn::Return(0))
@@ -128,7 +141,13 @@ fn do_move_mount_old(src_name: CString, dst_dentry: Arc<Dentry>) -> Result<()> {
 }
 
 /// Mount a new filesystem.
-fn do_new_mount(devname: CString, fs_type: Vaddr, target_dentry: Arc<Dentry>) -> Result<()> {
+fn do_new_mount(
+    devname: CString,
+    fs_type: Vaddr,
+    target_dentry: Arc<Dentry>,
+    mount_flags: MountFlags,
+    data: Vaddr,
+) -> Result<()> {
     if target_dentry.type_() != InodeType::Dir {
         return_errno_with_message!(Errno::ENOTDIR, "mountpoint must be directory");
     };
@@ -137,29 +156,85 @@ fn do_new_mount(devname: CString, fs_type: Vaddr, target_dentry: Arc<Dentry>) ->
     if fs_type.is_empty() {
         return_errno_with_message!(Errno::EINVAL, "fs_type is empty");
     }
-    let fs = get_fs(fs_type, devname)?;
-    target_dentry.mount(fs)?;
+    let data = if data == 0 {
+        CString::default()
+    } else {
+        read_cstring_from_user(data, PAGE_SIZE)?
+    };
+    let fs = get_fs(fs_type, devname, data)?;
+    let mount_node = target_dentry.mount(fs)?;
+    mount_node.set_flags(PerMountFlags::from(mount_flags));
     Ok(())
 }
 
-/// Get the filesystem by fs_type and devname.
-fn get_fs(fs_type: CString, devname: CString) -> Result<Arc<dyn FileSystem>> {
+/// Get the filesystem by fs_type, devname and the mount options in data.
+fn get_fs(fs_type: CString, devname: CString, data: CString) -> Result<Arc<dyn FileSystem>> {
+    // The pseudo file systems are not backed by devices.
+    match fs_type.as_bytes() {
+        b"tracefs" => return Ok(tracefs::new()),
+        b"binfmt_misc" => return Ok(binfmt_misc::new()),
+        b"tmpfs" => {
+            let options = data
+                .to_str()
+                .map_err(|_| Error::with_message(Errno::EINVAL, "invalid tmpfs options"))?;
+            return Ok(RamFS::new_tmpfs(&TmpfsMountOptions::parse(options)?));
+        }
+        // The device name is the tag of the virtio-fs device.
+        #[cfg(feature = "virtiofs")]
+        b"virtiofs" => {
+            let tag = devname
+                .to_str()
+                .map_err(|_| Error::with_message(Errno::EINVAL, "invalid virtio-fs tag"))?;
+            return Ok(VirtioFS::open(tag)?);
+        }
+        // The device name is the mount tag of the virtio-9p device. The options, e.g.,
+        // `trans=virtio,version=9p2000.L`, are the only ones supported, so they are ignored.
+        #[cfg(feature = "v9fs")]
+        b"9p" => {
+            let tag = devname
+                .to_str()
+                .map_err(|_| Error::with_message(Errno::EINVAL, "invalid 9p mount tag"))?;
+            return Ok(V9FS::open(tag)?);
+        }
+        // The device name is ignored, and the layers are in the options.
+        #[cfg(feature = "overlayfs")]
+        b"overlay" => {
+            let options = data
+                .to_str()
+                .map_err(|_| Error::with_message(Errno::EINVAL, "invalid overlay options"))?;
+            return Ok(OverlayFS::open(&OverlayMountOptions::parse(options)?)?);
+        }
+        _ => {}
+    }
+
     let devname = devname.to_str().unwrap();
+    #[cfg_attr(
+        not(any(feature = "ext2", feature = "exfat", feature = "vfat")),
+        allow(unused_variables)
+    )]
     let device = match aster_block::get_device(devname) {
         Some(device) => device,
         None => return_errno_with_message!(Errno::ENOENT, "Device does not exist"),
     };
     let fs_type = fs_type.to_str().unwrap();
     match fs_type {
+        #[cfg(feature = "ext2")]
         "ext2" => {
             let ext2_fs = Ext2::open(device)?;
             Ok(ext2_fs)
         }
+        #[cfg(feature = "exfat")]
         "exfat" => {
             let exfat_fs = ExfatFS::open(device, ExfatMountOptions::default())?;
             Ok(exfat_fs)
         }
-        _ => return_errno_with_message!(Errno::EINVAL, "Invalid fs type"),
+        #[cfg(feature = "vfat")]
+        "vfat" => {
+            let vfat_fs = VfatFS::open(device)?;
+            Ok(vfat_fs)
+        }
+        // Like Linux, the file systems that are compiled out are unknown.
+        _ => return_errno_with_message!(Errno::ENODEV, "Invalid fs type"),
     }
 }
 
@@ -189,3 +264,10 @@ bitflags! {
         const MS_KERNMOUNT     =   1 << 22;      // This is a kern_mount call.
     }
 }
+
+impl From<MountFlags> for PerMountFlags {
+    fn from(flags: MountFlags) -> Self {
+        // The per-mount flags share the values of the corresponding `MS_*` flags.
+        Self::from_bits_truncate(flags.bits())
+    }
+}
@@ -5,17 +5,66 @@ use align_ext::AlignExt;
 use super::SyscallReturn;
 use crate::{prelude::*, vm::perms::VmPerms};
 
-pub fn sys_mprotect(addr: Vaddr, len: usize, perms: u64) -> Result<SyscallReturn> {
-    let vm_perms = VmPerms::from_bits_truncate(perms as u32);
+/// Extends the range down to the start of the mapping that grows down.
+const PROT_GROWSDOWN: u64 = 0x0100_0000;
+/// Extends the range up to the end of the mapping that grows up.
+const PROT_GROWSUP: u64 = 0x0200_0000;
+
+pub fn sys_mprotect(addr: Vaddr, len: usize, prot: u64) -> Result<SyscallReturn> {
+    do_mprotect(addr, len, prot, None)
+}
+
+pub fn sys_pkey_mprotect(addr: Vaddr, len: usize, prot: u64, pkey: i32) -> Result<SyscallReturn> {
+    // A key of -1 keeps the protection keys of the pages, like `mprotect`.
+    let pkey = if pkey == -1 {
+        None
+    } else {
+        let pkey = u8::try_from(pkey)
+            .ok()
+            .filter(|pkey| current!().vm().pkeys().is_allocated(*pkey))
+            .ok_or_else(|| Error::with_message(Errno::EINVAL, "invalid protection key"))?;
+        Some(pkey)
+    };
+    do_mprotect(addr, len, prot, pkey)
+}
+
+fn do_mprotect(addr: Vaddr, len: usize, prot: u64, pkey: Option<u8>) -> Result<SyscallReturn> {
+    let vm_perms = VmPerms::from_bits_truncate(prot as u32);
     debug!(
-        "addr = 0x{:x}, len = 0x{:x}, perms = {:?}",
-        addr, len, vm_perms
+        "addr = 0x{:x}, len = 0x{:x}, perms = {:?}, prot = 0x{:x}, pkey = {:?}",
+        addr, len, vm_perms, prot, pkey
     );
+    if prot & PROT_GROWSUP != 0 {
+        return_errno_with_message!(Errno::EINVAL, "no mappings grow up");
+    }
+    if addr % PAGE_SIZE != 0 {
+        return_errno_with_message!(Errno::EINVAL, "the address is not page-aligned");
+    }
+    let Some(end) = len
+        .checked_add(PAGE_SIZE - 1)
+        .and_then(|len| addr.checked_add(len.align_down(PAGE_SIZE)))
+    else {
+        return_errno_with_message!(Errno::ENOMEM, "the range overflows");
+    };
+    if end == addr {
+        return Ok(SyscallReturn::Return(0));
+    }
+
     let current = current!();
     let root_vmar = current.root_vmar();
-    debug_assert!(addr % PAGE_SIZE == 0);
-    let len = len.align_up(PAGE_SIZE);
-    let range = addr..(addr + len);
-    root_vmar.protect(vm_perms, range)?;
+    let mut start = addr;
+    if prot & PROT_GROWSDOWN != 0 {
+        let vm_mapping = root_vmar.get_vm_mapping(addr)?;
+        if !vm_mapping.grows_down() {
+            return_errno_with_message!(Errno::EINVAL, "the mapping does not grow down");
+        }
+        start = vm_mapping.map_to_addr();
+    }
+
+    let range = start..end;
+    match pkey {
+        Some(pkey) => root_vmar.protect_with_pkey(vm_perms, pkey, range)?,
+        None => root_vmar.protect(vm_perms, range)?,
+    }
     Ok(SyscallReturn::Return(0))
 }
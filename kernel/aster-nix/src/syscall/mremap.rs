@@ -0,0 +1,73 @@
+// SPDX-License-Identifier: MPL-2.0
+
+use align_ext::AlignExt;
+
+use super::SyscallReturn;
+use crate::prelude::*;
+
+pub fn sys_mremap(
+    old_addr: Vaddr,
+    old_size: usize,
+    new_size: usize,
+    flags: i32,
+    new_addr: Vaddr,
+) -> Result<SyscallReturn> {
+    let flags = MremapFlags::from_bits(flags)
+        .ok_or_else(|| Error::with_message(Errno::EINVAL, "unknown mremap flags"))?;
+    debug!(
+        "old_addr = 0x{:x}, old_size = 0x{:x}, new_size = 0x{:x}, flags = {:?}, new_addr = 0x{:x}",
+        old_addr, old_size, new_size, flags, new_addr
+    );
+
+    if flags.contains(MremapFlags::MREMAP_FIXED) && !flags.contains(MremapFlags::MREMAP_MAYMOVE) {
+        return_errno_with_message!(Errno::EINVAL, "MREMAP_FIXED requires MREMAP_MAYMOVE");
+    }
+    if flags.contains(MremapFlags::MREMAP_DONTUNMAP) {
+        return_errno_with_message!(Errno::EINVAL, "MREMAP_DONTUNMAP is not supported");
+    }
+    if old_addr % PAGE_SIZE != 0 {
+        return_errno_with_message!(Errno::EINVAL, "old_addr is not page-aligned");
+    }
+    let new_addr = if flags.contains(MremapFlags::MREMAP_FIXED) {
+        if new_addr % PAGE_SIZE != 0 {
+            return_errno_with_message!(Errno::EINVAL, "new_addr is not page-aligned");
+        }
+        Some(new_addr)
+    } else {
+        None
+    };
+
+    let old_size = old_size.align_up(PAGE_SIZE);
+    let new_size = new_size.align_up(PAGE_SIZE);
+    if new_size == 0 {
+        return_errno_with_message!(Errno::EINVAL, "new_size is zero");
+    }
+    // TODO: A zero `old_size` creates a new mapping of the same pages of a shared mapping,
+    // which is not supported yet.
+    if old_size == 0 {
+        return_errno_with_message!(Errno::EINVAL, "old_size is zero");
+    }
+    let old_end = old_addr
+        .checked_add(old_size)
+        .ok_or_else(|| Error::with_message(Errno::EINVAL, "old_addr overflows"))?;
+
+    let current = current!();
+    let root_vmar = current.root_vmar();
+    let addr = root_vmar.remap(
+        old_addr..old_end,
+        new_size,
+        flags.contains(MremapFlags::MREMAP_MAYMOVE),
+        new_addr,
+    )?;
+    trace!("remap range = 0x{:x} - 0x{:x}", addr, addr + new_size);
+
+    Ok(SyscallReturn::Return(addr as _))
+}
+
+bitflags! {
+    struct MremapFlags: i32 {
+        const MREMAP_MAYMOVE   = 1 << 0;
+        const MREMAP_FIXED     = 1 << 1;
+        const MREMAP_DONTUNMAP = 1 << 2;
+    }
+}
@@ -35,12 +35,7 @@ pub fn sys_openat(
     };
     let mut file_table = current.file_table().lock();
     let fd = {
-        let fd_flags =
-            if CreationFlags::from_bits_truncate(flags).contains(CreationFlags::O_CLOEXEC) {
-                FdFlags::CLOEXEC
-            } else {
-                FdFlags::empty()
-            };
+        let fd_flags = FdFlags::from(CreationFlags::from_bits_truncate(flags));
         file_table.insert(file_handle, fd_flags)
     };
     Ok(SyscallReturn::Return(fd as _))
@@ -25,11 +25,7 @@ pub fn sys_pipe2(fds: Vaddr, flags: u32) -> Result<SyscallReturn> {
     };
     let pipe_reader = Arc::new(reader);
     let pipe_writer = Arc::new(writer);
-    let fd_flags = if CreationFlags::from_bits_truncate(flags).contains(CreationFlags::O_CLOEXEC) {
-        FdFlags::CLOEXEC
-    } else {
-        FdFlags::empty()
-    };
+    let fd_flags = FdFlags::from(CreationFlags::from_bits_truncate(flags));
 
     let current = current!();
     let mut file_table = current.file_table().lock();
@@ -0,0 +1,38 @@
+// SPDX-License-Identifier: MPL-2.0
+
+use aster_frame::cpu::UserContext;
+
+use super::SyscallReturn;
+use crate::{
+    prelude::*,
+    vm::pkey::{set_pkey_access, PkeyAccess},
+};
+
+pub fn sys_pkey_alloc(
+    flags: u32,
+    access_rights: u32,
+    context: &mut UserContext,
+) -> Result<SyscallReturn> {
+    debug!(
+        "flags = 0x{:x}, access_rights = 0x{:x}",
+        flags, access_rights
+    );
+    if flags != 0 {
+        return_errno_with_message!(Errno::EINVAL, "invalid pkey_alloc flags");
+    }
+    let access = PkeyAccess::from_bits(access_rights)
+        .ok_or_else(|| Error::with_message(Errno::EINVAL, "invalid access rights"))?;
+
+    let pkey = current!().vm().pkeys().alloc()?;
+    // Only the PKRU register of the calling thread is initialized, like Linux.
+    context.set_pkru(set_pkey_access(context.pkru(), pkey, access));
+    Ok(SyscallReturn::Return(pkey as _))
+}
+
+pub fn sys_pkey_free(pkey: i32) -> Result<SyscallReturn> {
+    debug!("pkey = {}", pkey);
+    let pkey = u8::try_from(pkey)
+        .map_err(|_| Error::with_message(Errno::EINVAL, "invalid protection key"))?;
+    current!().vm().pkeys().free(pkey)?;
+    Ok(SyscallReturn::Return(0))
+}
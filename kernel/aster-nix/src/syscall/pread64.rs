@@ -4,7 +4,7 @@ use super::SyscallReturn;
 use crate::{
     fs::{file_table::FileDesc, utils::SeekFrom},
     prelude::*,
-    util::write_bytes_to_user,
+    util::write_bytes_to_user_partial,
 };
 
 pub fn sys_pread64(fd: FileDesc, buf_ptr: Vaddr, count: usize, pos: i64) -> Result<SyscallReturn> {
@@ -23,8 +23,11 @@ pub fn sys_pread64(fd: FileDesc, buf_ptr: Vaddr, count: usize, pos: i64) -> Resu
     let read_len = {
         let mut buffer = vec![0u8; count];
         let read_len = file.read(&mut buffer)?;
-        write_bytes_to_user(buf_ptr, &buffer)?;
-        read_len
+        if read_len == 0 {
+            0
+        } else {
+            write_bytes_to_user_partial(buf_ptr, &buffer[..read_len])?
+        }
     };
 
     Ok(SyscallReturn::Return(read_len as _))
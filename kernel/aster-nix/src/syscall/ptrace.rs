@@ -0,0 +1,405 @@
+// SPDX-License-Identifier: MPL-2.0
+
+use aster_frame::mm::{VmIo, MAX_USERSPACE_VADDR};
+
+use super::SyscallReturn;
+use crate::{
+    cpu::LinuxAbi,
+    prelude::*,
+    process::{
+        credentials,
+        posix_thread::{PosixThread, PosixThreadExt, PtraceOptions, PtraceStop},
+        signal::{
+            constants::{SIGKILL, SIGSTOP},
+            sig_num::SigNum,
+            signals::kernel::KernelSignal,
+        },
+        Process,
+    },
+    thread::{thread_table, Tid},
+    util::{read_val_from_user, write_val_to_user},
+};
+
+pub fn sys_ptrace(request: u32, tid: Tid, addr: Vaddr, data: u64) -> Result<SyscallReturn> {
+    let request = PtraceRequest::try_from(request)
+        .map_err(|_| Error::with_message(Errno::EIO, "the request is not supported"))?;
+    debug!(
+        "request = {:?}, tid = {}, addr = 0x{:x}, data = 0x{:x}",
+        request, tid, addr, data
+    );
+
+    let current = current!();
+    match request {
+        PtraceRequest::PTRACE_TRACEME => {
+            trace_me(&current)?;
+            return Ok(SyscallReturn::Return(0));
+        }
+        PtraceRequest::PTRACE_ATTACH => {
+            attach(&current, tid, PtraceOptions::empty(), true)?;
+            return Ok(SyscallReturn::Return(0));
+        }
+        PtraceRequest::PTRACE_SEIZE => {
+            attach(&current, tid, options_from_data(data)?, false)?;
+            return Ok(SyscallReturn::Return(0));
+        }
+        _ => (),
+    }
+
+    let tracee = thread_table::get_thread(tid)
+        .ok_or_else(|| Error::with_message(Errno::ESRCH, "the thread does not exist"))?;
+    let posix_thread = tracee
+        .as_posix_thread()
+        .ok_or_else(|| Error::with_message(Errno::ESRCH, "the thread is not traced"))?;
+    let ptrace = posix_thread.ptrace();
+
+    match request {
+        PtraceRequest::PTRACE_PEEKTEXT | PtraceRequest::PTRACE_PEEKDATA => {
+            ptrace.with_stop(&current, |_| ())?;
+            let val = peek_memory(&posix_thread.process(), addr)?;
+            write_val_to_user(data as Vaddr, &val)?;
+        }
+        PtraceRequest::PTRACE_POKETEXT | PtraceRequest::PTRACE_POKEDATA => {
+            ptrace.with_stop(&current, |_| ())?;
+            poke_memory(&posix_thread.process(), addr, data)?;
+        }
+        PtraceRequest::PTRACE_PEEKUSER => {
+            let val = ptrace.with_stop(&current, |stop| peek_user(stop, addr))??;
+            write_val_to_user(data as Vaddr, &val)?;
+        }
+        PtraceRequest::PTRACE_POKEUSER => {
+            ptrace.with_stop(&current, |stop| poke_user(stop, addr, data))??;
+        }
+        PtraceRequest::PTRACE_GETREGS => {
+            let regs = ptrace.with_stop(&current, |stop| user_regs_struct::from_stop(stop))?;
+            write_val_to_user(data as Vaddr, &regs)?;
+        }
+        PtraceRequest::PTRACE_SETREGS => {
+            let regs = read_val_from_user::<user_regs_struct>(data as Vaddr)?;
+            ptrace.with_stop(&current, |stop| regs.write_to_stop(stop))??;
+        }
+        PtraceRequest::PTRACE_GETSIGINFO => {
+            let options = ptrace.options();
+            let siginfo = ptrace.with_stop(&current, |stop| stop.siginfo(options))?;
+            write_val_to_user(data as Vaddr, &siginfo)?;
+        }
+        PtraceRequest::PTRACE_GETEVENTMSG => {
+            let event_msg = ptrace.with_stop(&current, |stop| stop.event_msg())?;
+            write_val_to_user(data as Vaddr, &event_msg)?;
+        }
+        PtraceRequest::PTRACE_SETOPTIONS => {
+            ptrace.set_options(&current, options_from_data(data)?)?;
+        }
+        PtraceRequest::PTRACE_CONT => {
+            ptrace.resume(&current, signal_from_data(data)?, false)?;
+        }
+        PtraceRequest::PTRACE_SYSCALL => {
+            ptrace.resume(&current, signal_from_data(data)?, true)?;
+        }
+        PtraceRequest::PTRACE_DETACH => {
+            ptrace.detach(&current, signal_from_data(data)?)?;
+            current.tracees().lock().remove(&tid);
+        }
+        PtraceRequest::PTRACE_KILL => {
+            if !ptrace.is_traced_by(&current) {
+                return_errno_with_message!(Errno::ESRCH, "the thread is not traced by the caller");
+            }
+            posix_thread.enqueue_signal(Box::new(KernelSignal::new(SIGKILL)));
+            // The thread may not be stopped, in which case it is killed soon anyway.
+            let _ = ptrace.resume(&current, None, false);
+        }
+        PtraceRequest::PTRACE_TRACEME
+        | PtraceRequest::PTRACE_ATTACH
+        | PtraceRequest::PTRACE_SEIZE => unreachable!(),
+    }
+
+    Ok(SyscallReturn::Return(0))
+}
+
+#[allow(non_camel_case_types)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, TryFromInt)]
+#[repr(u32)]
+enum PtraceRequest {
+    PTRACE_TRACEME = 0,
+    PTRACE_PEEKTEXT = 1,
+    PTRACE_PEEKDATA = 2,
+    PTRACE_PEEKUSER = 3,
+    PTRACE_POKETEXT = 4,
+    PTRACE_POKEDATA = 5,
+    PTRACE_POKEUSER = 6,
+    PTRACE_CONT = 7,
+    PTRACE_KILL = 8,
+    PTRACE_GETREGS = 12,
+    PTRACE_SETREGS = 13,
+    PTRACE_ATTACH = 16,
+    PTRACE_DETACH = 17,
+    PTRACE_SYSCALL = 24,
+    PTRACE_SETOPTIONS = 0x4200,
+    PTRACE_GETEVENTMSG = 0x4201,
+    PTRACE_GETSIGINFO = 0x4202,
+    PTRACE_SEIZE = 0x4206,
+}
+
+/// Makes the current thread traced by the parent process.
+fn trace_me(current: &Arc<Process>) -> Result<()> {
+    let parent = current
+        .parent()
+        .ok_or_else(|| Error::with_message(Errno::EPERM, "the process has no parent"))?;
+    let current_thread = current_thread!();
+    let posix_thread = current_thread.as_posix_thread().unwrap();
+
+    posix_thread
+        .ptrace()
+        .attach(&parent, PtraceOptions::empty())?;
+    parent
+        .tracees()
+        .lock()
+        .insert(current_thread.tid(), current_thread.clone());
+    Ok(())
+}
+
+/// Attaches the thread of `tid` to `tracer`, and stops the thread with `SIGSTOP` if
+/// `stops` is true.
+fn attach(tracer: &Arc<Process>, tid: Tid, options: PtraceOptions, stops: bool) -> Result<()> {
+    let tracee = thread_table::get_thread(tid)
+        .ok_or_else(|| Error::with_message(Errno::ESRCH, "the thread does not exist"))?;
+    let posix_thread = tracee
+        .as_posix_thread()
+        .ok_or_else(|| Error::with_message(Errno::EPERM, "kernel threads cannot be traced"))?;
+    if Arc::ptr_eq(&posix_thread.process(), tracer) {
+        return_errno_with_message!(Errno::EPERM, "the threads of the caller cannot be traced");
+    }
+    check_attach_perm(posix_thread)?;
+
+    let ptrace = posix_thread.ptrace();
+    ptrace.attach(tracer, options)?;
+    tracer.tracees().lock().insert(tid, tracee.clone());
+    // The thread may have exited before it is inserted.
+    if !ptrace.is_traced_by(tracer) {
+        tracer.tracees().lock().remove(&tid);
+        return_errno_with_message!(Errno::ESRCH, "the thread has exited");
+    }
+
+    if stops {
+        posix_thread.enqueue_signal(Box::new(KernelSignal::new(SIGSTOP)));
+    }
+    Ok(())
+}
+
+/// Checks whether the current thread can trace `tracee`.
+///
+/// The current thread must either be privileged, or its real user ID must equal the
+/// real, effective and saved set-user-ID of `tracee`.
+fn check_attach_perm(tracee: &PosixThread) -> Result<()> {
+    let credentials = credentials();
+    if credentials.euid().is_root() {
+        return Ok(());
+    }
+
+    let ruid = credentials.ruid();
+    let tracee_credentials = tracee.credentials();
+    if tracee_credentials.ruid() == ruid
+        && tracee_credentials.euid() == ruid
+        && tracee_credentials.suid() == ruid
+    {
+        return Ok(());
+    }
+
+    return_errno_with_message!(Errno::EPERM, "tracing the thread is not allowed");
+}
+
+fn options_from_data(data: u64) -> Result<PtraceOptions> {
+    let options = u32::try_from(data)
+        .ok()
+        .and_then(PtraceOptions::from_bits)
+        .ok_or_else(|| Error::with_message(Errno::EINVAL, "unknown options"))?;
+    if !options.supported() {
+        return_errno_with_message!(Errno::EINVAL, "the options are not supported");
+    }
+    Ok(options)
+}
+
+/// Returns the signal to deliver when the tracee is resumed, where zero means none.
+fn signal_from_data(data: u64) -> Result<Option<SigNum>> {
+    if data == 0 {
+        return Ok(None);
+    }
+    u8::try_from(data)
+        .ok()
+        .and_then(|sig_num| SigNum::try_from(sig_num).ok())
+        .map(Some)
+        .ok_or_else(|| Error::with_message(Errno::EIO, "the signal is invalid"))
+}
+
+fn peek_memory(process: &Process, addr: Vaddr) -> Result<u64> {
+    process
+        .root_vmar()
+        .read_val::<u64>(addr)
+        .map_err(|_| Error::with_message(Errno::EIO, "the memory is not readable"))
+}
+
+/// Writes a word to the memory of the tracee, even if the memory is not writable.
+fn poke_memory(process: &Process, addr: Vaddr, data: u64) -> Result<()> {
+    let root_vmar = process.root_vmar();
+    let bytes = data.as_bytes();
+
+    // The word may span two mappings.
+    let mut written_len = 0;
+    while written_len < bytes.len() {
+        let write_addr = addr + written_len;
+        let vm_mapping = root_vmar
+            .get_vm_mapping(write_addr)
+            .map_err(|_| Error::with_message(Errno::EIO, "the memory is not mapped"))?;
+        let write_len = (vm_mapping.range().end - write_addr).min(bytes.len() - written_len);
+        vm_mapping
+            .force_write_bytes(
+                write_addr - vm_mapping.map_to_addr(),
+                &bytes[written_len..written_len + write_len],
+            )
+            .map_err(|_| Error::with_message(Errno::EIO, "the memory is not writable"))?;
+        written_len += write_len;
+    }
+    Ok(())
+}
+
+/// Returns the offset of a register in `user_regs_struct`, which is the only part of
+/// `struct user` that is accessible now.
+fn user_regs_offset(addr: Vaddr) -> Result<usize> {
+    const REG_SIZE: usize = core::mem::size_of::<u64>();
+    let is_valid = addr % REG_SIZE == 0
+        && addr
+            .checked_add(REG_SIZE)
+            .is_some_and(|end| end <= core::mem::size_of::<user_regs_struct>());
+    if !is_valid {
+        return_errno_with_message!(Errno::EIO, "the offset in the user area is invalid");
+    }
+    Ok(addr)
+}
+
+fn peek_user(stop: &PtraceStop, addr: Vaddr) -> Result<u64> {
+    let offset = user_regs_offset(addr)?;
+    let regs = user_regs_struct::from_stop(stop);
+    Ok(u64::from_bytes(
+        &regs.as_bytes()[offset..offset + core::mem::size_of::<u64>()],
+    ))
+}
+
+fn poke_user(stop: &mut PtraceStop, addr: Vaddr, data: u64) -> Result<()> {
+    let offset = user_regs_offset(addr)?;
+    let mut regs = user_regs_struct::from_stop(stop);
+    regs.as_bytes_mut()[offset..offset + core::mem::size_of::<u64>()]
+        .copy_from_slice(data.as_bytes());
+    regs.write_to_stop(stop)
+}
+
+/// The registers of a tracee, in the layout of Linux.
+#[derive(Debug, Clone, Copy, Pod)]
+#[repr(C)]
+#[allow(non_camel_case_types)]
+struct user_regs_struct {
+    r15: u64,
+    r14: u64,
+    r13: u64,
+    r12: u64,
+    rbp: u64,
+    rbx: u64,
+    r11: u64,
+    r10: u64,
+    r9: u64,
+    r8: u64,
+    rax: u64,
+    rcx: u64,
+    rdx: u64,
+    rsi: u64,
+    rdi: u64,
+    orig_rax: u64,
+    rip: u64,
+    cs: u64,
+    eflags: u64,
+    rsp: u64,
+    ss: u64,
+    fs_base: u64,
+    gs_base: u64,
+    ds: u64,
+    es: u64,
+    fs: u64,
+    gs: u64,
+}
+
+/// The code and the stack segment selectors of the user space, which are the same as Linux.
+const USER_CS: u64 = 0x33;
+const USER_SS: u64 = 0x2b;
+
+/// The flags that the tracer can change, i.e., CF, PF, AF, ZF, SF, DF, OF and AC. The trap
+/// flag is excluded since single-stepping is not supported.
+const USER_RFLAGS_MASK: usize = 0x40cd5;
+
+impl user_regs_struct {
+    fn from_stop(stop: &PtraceStop) -> Self {
+        let context = stop.context();
+        Self {
+            r15: context.r15() as u64,
+            r14: context.r14() as u64,
+            r13: context.r13() as u64,
+            r12: context.r12() as u64,
+            rbp: context.rbp() as u64,
+            rbx: context.rbx() as u64,
+            r11: context.r11() as u64,
+            r10: context.r10() as u64,
+            r9: context.r9() as u64,
+            r8: context.r8() as u64,
+            rax: context.rax() as u64,
+            rcx: context.rcx() as u64,
+            rdx: context.rdx() as u64,
+            rsi: context.rsi() as u64,
+            rdi: context.rdi() as u64,
+            orig_rax: stop.orig_rax() as u64,
+            rip: context.rip() as u64,
+            cs: USER_CS,
+            eflags: context.rflags() as u64,
+            rsp: context.rsp() as u64,
+            ss: USER_SS,
+            fs_base: context.tls_pointer() as u64,
+            gs_base: context.gsbase() as u64,
+            ds: 0,
+            es: 0,
+            fs: 0,
+            gs: 0,
+        }
+    }
+
+    /// Writes the registers to the stop, except the segment selectors and the GS base,
+    /// which cannot be changed from the user space.
+    ///
+    /// Like Linux, it fails with `EIO` if the instruction pointer or the FS base is not a
+    /// user space address.
+    fn write_to_stop(&self, stop: &mut PtraceStop) -> Result<()> {
+        if self.rip >= MAX_USERSPACE_VADDR as u64 || self.fs_base >= MAX_USERSPACE_VADDR as u64 {
+            return_errno_with_message!(Errno::EIO, "the address is not in the user space");
+        }
+
+        let context = stop.context_mut();
+        context.set_r15(self.r15 as usize);
+        context.set_r14(self.r14 as usize);
+        context.set_r13(self.r13 as usize);
+        context.set_r12(self.r12 as usize);
+        context.set_rbp(self.rbp as usize);
+        context.set_rbx(self.rbx as usize);
+        context.set_r11(self.r11 as usize);
+        context.set_r10(self.r10 as usize);
+        context.set_r9(self.r9 as usize);
+        context.set_r8(self.r8 as usize);
+        context.set_rax(self.rax as usize);
+        context.set_rcx(self.rcx as usize);
+        context.set_rdx(self.rdx as usize);
+        context.set_rsi(self.rsi as usize);
+        context.set_rdi(self.rdi as usize);
+        context.set_rip(self.rip as usize);
+        let rflags =
+            (context.rflags() & !USER_RFLAGS_MASK) | (self.eflags as usize & USER_RFLAGS_MASK);
+        context.set_rflags(rflags);
+        context.set_rsp(self.rsp as usize);
+        context.set_tls_pointer(self.fs_base as usize);
+        stop.set_orig_rax(self.orig_rax as usize);
+        Ok(())
+    }
+}
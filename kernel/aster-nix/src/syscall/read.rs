@@ -1,7 +1,11 @@
 // SPDX-License-Identifier: MPL-2.0
 
 use super::SyscallReturn;
-use crate::{fs::file_table::FileDesc, prelude::*, util::write_bytes_to_user};
+use crate::{
+    fs::{file_table::FileDesc, utils::SeekFrom},
+    prelude::*,
+    util::write_bytes_to_user_partial,
+};
 
 pub fn sys_read(fd: FileDesc, user_buf_addr: Vaddr, buf_len: usize) -> Result<SyscallReturn> {
     debug!(
@@ -17,6 +21,16 @@ pub fn sys_read(fd: FileDesc, user_buf_addr: Vaddr, buf_len: usize) -> Result<Sy
 
     let mut read_buf = vec![0u8; buf_len];
     let read_len = file.read(&mut read_buf)?;
-    write_bytes_to_user(user_buf_addr, &read_buf)?;
-    Ok(SyscallReturn::Return(read_len as _))
+    if read_len == 0 {
+        return Ok(SyscallReturn::Return(0));
+    }
+
+    let copied_len = write_bytes_to_user_partial(user_buf_addr, &read_buf[..read_len]);
+    let uncopied_len = read_len - *copied_len.as_ref().unwrap_or(&0);
+    if uncopied_len > 0 {
+        // Give back the bytes that cannot be copied to the user buffer, so that they can
+        // be read again. The bytes are lost if the file is not seekable.
+        let _ = file.seek(SeekFrom::Current(-(uncopied_len as isize)));
+    }
+    Ok(SyscallReturn::Return(copied_len? as _))
 }
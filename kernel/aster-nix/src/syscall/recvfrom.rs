@@ -7,7 +7,7 @@ use crate::{
     prelude::*,
     util::{
         net::{get_socket_from_fd, write_socket_addr_to_user},
-        write_bytes_to_user,
+        write_bytes_to_user_partial,
     },
 };
 
@@ -26,9 +26,9 @@ pub fn sys_recvfrom(
 
     let mut buffer = vec![0u8; len];
 
-    let (recv_size, socket_addr) = socket.recvfrom(&mut buffer, flags)?;
-    if buf != 0 {
-        write_bytes_to_user(buf, &buffer[..recv_size])?;
+    let (mut recv_size, socket_addr) = socket.recvfrom(&mut buffer, flags)?;
+    if buf != 0 && recv_size != 0 {
+        recv_size = write_bytes_to_user_partial(buf, &buffer[..recv_size])?;
     }
     if src_addr != 0 {
         write_socket_addr_to_user(&socket_addr, src_addr, addrlen_ptr)?;
@@ -0,0 +1,115 @@
+// SPDX-License-Identifier: MPL-2.0
+
+use core::time::Duration;
+
+use super::SyscallReturn;
+use crate::{
+    fs::file_table::FileDesc,
+    net::socket::{SendRecvFlags, SocketAddr},
+    prelude::*,
+    time::{clocks::MonotonicClock, timespec_t, Clock},
+    util::{
+        iovec::{scatter_to_user, total_len, IoVec, IOVEC_MAX},
+        net::{get_socket_from_fd, write_socket_addr_to_user, CUserMmsgHdr, CUserMsgHdr},
+        read_val_from_user, write_val_to_user,
+    },
+};
+
+pub fn sys_recvmmsg(
+    sockfd: FileDesc,
+    msgvec: Vaddr,
+    vlen: u32,
+    flags: i32,
+    timeout: Vaddr,
+) -> Result<SyscallReturn> {
+    let flags = SendRecvFlags::from_bits_truncate(flags);
+    // Linux silently truncates `vlen` to `UIO_MAXIOV`
+    let vlen = (vlen as usize).min(IOVEC_MAX);
+    debug!(
+        "sockfd = {sockfd}, msgvec = 0x{msgvec:x}, vlen = {vlen}, flags = {flags:?}, timeout = 0x{timeout:x}"
+    );
+
+    let deadline = if timeout == 0 {
+        None
+    } else {
+        let timespec = read_val_from_user::<timespec_t>(timeout)?;
+        if timespec.sec < 0 || !(0..1_000_000_000).contains(&timespec.nsec) {
+            return_errno_with_message!(Errno::EINVAL, "invalid timeout");
+        }
+        Some(MonotonicClock::get().read_time() + Duration::from(timespec))
+    };
+
+    let socket = get_socket_from_fd(sockfd)?;
+
+    let mut mmsg_hdrs = Vec::with_capacity(vlen);
+    let mut io_vecs = Vec::with_capacity(vlen);
+    let mut bufs = Vec::with_capacity(vlen);
+    for i in 0..vlen {
+        let mmsg_hdr_and_io_vecs =
+            read_val_from_user::<CUserMmsgHdr>(msgvec + i * core::mem::size_of::<CUserMmsgHdr>())
+                .and_then(|mmsg_hdr| {
+                    let msg_io_vecs = mmsg_hdr.msg_hdr.read_iovecs_from_user()?;
+                    Ok((mmsg_hdr, msg_io_vecs))
+                });
+        // As with Linux, a faulty message header ends the batch before it, unless it
+        // is the first one.
+        let (mmsg_hdr, msg_io_vecs) = match mmsg_hdr_and_io_vecs {
+            Ok(mmsg_hdr_and_io_vecs) => mmsg_hdr_and_io_vecs,
+            Err(err) if i == 0 => return Err(err),
+            Err(_) => break,
+        };
+        bufs.push(vec![0u8; total_len(&msg_io_vecs)]);
+        io_vecs.push(msg_io_vecs);
+        mmsg_hdrs.push(mmsg_hdr);
+    }
+
+    let received = socket.recvmmsg(&mut bufs, flags, deadline)?;
+
+    // The received messages have been dequeued, so a fault in copying one of them out
+    // only ends the batch, unless it is the first one.
+    for (i, (recv_len, socket_addr)) in received.iter().enumerate() {
+        if let Err(err) = copy_msg_to_user(
+            msgvec + i * core::mem::size_of::<CUserMmsgHdr>(),
+            &mmsg_hdrs[i].msg_hdr,
+            &io_vecs[i],
+            &bufs[i][..*recv_len],
+            socket_addr,
+        ) {
+            if i == 0 {
+                return Err(err);
+            }
+            return Ok(SyscallReturn::Return(i as _));
+        }
+    }
+
+    // Like Linux, the remaining time is written back to the timeout
+    if let Some(deadline) = deadline {
+        let remaining = deadline.saturating_sub(MonotonicClock::get().read_time());
+        write_val_to_user(timeout, &timespec_t::from(remaining))?;
+    }
+
+    Ok(SyscallReturn::Return(received.len() as _))
+}
+
+fn copy_msg_to_user(
+    mmsg_hdr_addr: Vaddr,
+    msg_hdr: &CUserMsgHdr,
+    io_vecs: &[IoVec],
+    buf: &[u8],
+    socket_addr: &SocketAddr,
+) -> Result<()> {
+    let copied_len = scatter_to_user(io_vecs, buf)?;
+
+    if msg_hdr.msg_name != 0 {
+        write_socket_addr_to_user(
+            socket_addr,
+            msg_hdr.msg_name,
+            mmsg_hdr_addr + CUserMsgHdr::NAMELEN_OFFSET,
+        )?;
+    }
+
+    write_val_to_user(
+        mmsg_hdr_addr + CUserMmsgHdr::MSG_LEN_OFFSET,
+        &(copied_len as u32),
+    )
+}
@@ -0,0 +1,136 @@
+// SPDX-License-Identifier: MPL-2.0
+
+use core::{cmp, mem};
+
+use aster_frame::cpu::{num_cpus, CpuSet};
+
+use super::SyscallReturn;
+use crate::{
+    prelude::*,
+    process::{credentials, credentials::capabilities::CapSet, posix_thread::PosixThreadExt},
+    thread::{thread_table, Thread, Tid},
+    util::{read_bytes_from_user, write_bytes_to_user},
+};
+
+pub fn sys_sched_getaffinity(
+    tid: Tid,
+    cpuset_size: usize,
+    cpu_set_ptr: Vaddr,
+) -> Result<SyscallReturn> {
+    debug!(
+        "tid = {}, cpuset_size = {}, cpu_set_ptr = 0x{:x}",
+        tid, cpuset_size, cpu_set_ptr
+    );
+
+    if cpuset_size * 8 < num_cpus() as usize {
+        return_errno_with_message!(Errno::EINVAL, "the cpuset size is too small");
+    }
+    if cpuset_size % mem::size_of::<usize>() != 0 {
+        return_errno_with_message!(Errno::EINVAL, "the cpuset size is not aligned");
+    }
+
+    let thread = get_thread(tid)?;
+    let cpu_set = cpu_set_t::from(&thread.cpu_affinity());
+    // Like Linux, only the bits of the CPUs that may exist are written, and the number of
+    // the written bytes is returned.
+    let len = cmp::min(cpuset_size, cpu_set_t::size_of_cpus());
+    write_bytes_to_user(cpu_set_ptr, &cpu_set.as_bytes()[..len])?;
+
+    Ok(SyscallReturn::Return(len as _))
+}
+
+pub fn sys_sched_setaffinity(
+    tid: Tid,
+    cpuset_size: usize,
+    cpu_set_ptr: Vaddr,
+) -> Result<SyscallReturn> {
+    debug!(
+        "tid = {}, cpuset_size = {}, cpu_set_ptr = 0x{:x}",
+        tid, cpuset_size, cpu_set_ptr
+    );
+
+    let mut cpu_set = cpu_set_t::new_zeroed();
+    let len = cmp::min(cpuset_size, mem::size_of::<cpu_set_t>());
+    read_bytes_from_user(cpu_set_ptr, &mut cpu_set.as_bytes_mut()[..len])?;
+    let cpu_affinity = CpuSet::from(&cpu_set);
+    if cpu_affinity.iter().next().is_none() {
+        return_errno_with_message!(Errno::EINVAL, "the cpuset has no CPU that exists");
+    }
+
+    let thread = get_thread(tid)?;
+    check_permission(&thread)?;
+    thread.set_cpu_affinity(cpu_affinity);
+
+    Ok(SyscallReturn::Return(0))
+}
+
+pub(super) fn get_thread(tid: Tid) -> Result<Arc<Thread>> {
+    if tid == 0 {
+        return Ok(current_thread!());
+    }
+    thread_table::get_thread(tid)
+        .ok_or_else(|| Error::with_message(Errno::ESRCH, "the thread does not exist"))
+}
+
+/// Checks whether the current thread can set the CPU affinity, or the other scheduling
+/// attributes, of the `thread`.
+///
+/// Like Linux, it requires the same owner, or `CAP_SYS_NICE`.
+pub(super) fn check_permission(thread: &Thread) -> Result<()> {
+    let Some(posix_thread) = thread.as_posix_thread() else {
+        return_errno_with_message!(Errno::EPERM, "the thread is not a POSIX thread");
+    };
+    let credentials = credentials();
+    let target_credentials = posix_thread.credentials();
+    if credentials.euid() == target_credentials.euid()
+        || credentials.euid() == target_credentials.ruid()
+        || credentials.effective_capset().contains(CapSet::SYS_NICE)
+    {
+        return Ok(());
+    }
+    return_errno_with_message!(Errno::EPERM, "the thread is owned by another user")
+}
+
+const CPU_SETSIZE: usize = 1024; // Max number of CPU bits.
+const __NCPUBITS: usize = 8 * mem::size_of::<usize>();
+
+#[derive(Debug, Clone, Copy, Pod)]
+#[repr(C, packed)]
+struct cpu_set_t {
+    __bits: [usize; CPU_SETSIZE / __NCPUBITS],
+}
+
+impl cpu_set_t {
+    /// Returns the size of the words of the CPUs that may exist.
+    fn size_of_cpus() -> usize {
+        (num_cpus() as usize).div_ceil(__NCPUBITS) * mem::size_of::<usize>()
+    }
+}
+
+impl From<&CpuSet> for cpu_set_t {
+    fn from(cpus: &CpuSet) -> Self {
+        let mut bits = [0usize; CPU_SETSIZE / __NCPUBITS];
+
+        for cpu in cpus.iter().filter(|cpu| *cpu < CPU_SETSIZE) {
+            bits[cpu / __NCPUBITS] |= 1 << (cpu % __NCPUBITS);
+        }
+
+        Self { __bits: bits }
+    }
+}
+
+impl From<&cpu_set_t> for CpuSet {
+    /// Converts the bits, where the CPUs that do not exist are ignored.
+    fn from(cpu_set: &cpu_set_t) -> Self {
+        let bits = cpu_set.__bits;
+        let mut cpus = CpuSet::new_empty();
+
+        for cpu in 0..cmp::min(num_cpus() as usize, CPU_SETSIZE) {
+            if bits[cpu / __NCPUBITS] & (1 << (cpu % __NCPUBITS)) != 0 {
+                cpus.add(cpu as u32);
+            }
+        }
+
+        cpus
+    }
+}
@@ -0,0 +1,210 @@
+// SPDX-License-Identifier: MPL-2.0
+
+use core::{sync::atomic::Ordering, time::Duration};
+
+use aster_frame::task::SchedPolicy;
+use int_to_c_enum::TryFromInt;
+
+use super::{
+    sched_affinity::{check_permission, get_thread},
+    SyscallReturn,
+};
+use crate::{
+    prelude::*,
+    process::{
+        credentials, credentials::capabilities::CapSet, posix_thread::PosixThreadExt, ResourceType,
+    },
+    sched::real_time::{RealTimePriority, RR_TIME_SLICE},
+    thread::{Thread, Tid},
+    time::timespec_t,
+    util::{read_val_from_user, write_val_to_user},
+};
+
+pub fn sys_sched_setscheduler(tid: i32, policy: i32, param_ptr: Vaddr) -> Result<SyscallReturn> {
+    debug!(
+        "tid = {}, policy = {}, param_ptr = 0x{:x}",
+        tid, policy, param_ptr
+    );
+
+    let policy = Policy::try_from(policy)
+        .map_err(|_| Error::with_message(Errno::EINVAL, "the policy is not supported"))?;
+    let param = read_param(tid, param_ptr)?;
+    let thread = get_thread(tid as Tid)?;
+    set_sched_policy(&thread, Some(policy), param)?;
+
+    Ok(SyscallReturn::Return(0))
+}
+
+pub fn sys_sched_getscheduler(tid: i32) -> Result<SyscallReturn> {
+    debug!("tid = {}", tid);
+
+    if tid < 0 {
+        return_errno_with_message!(Errno::EINVAL, "the tid is negative");
+    }
+    let thread = get_thread(tid as Tid)?;
+    let policy = Policy::from(thread.sched_policy());
+
+    Ok(SyscallReturn::Return(policy as _))
+}
+
+pub fn sys_sched_setparam(tid: i32, param_ptr: Vaddr) -> Result<SyscallReturn> {
+    debug!("tid = {}, param_ptr = 0x{:x}", tid, param_ptr);
+
+    let param = read_param(tid, param_ptr)?;
+    let thread = get_thread(tid as Tid)?;
+    set_sched_policy(&thread, None, param)?;
+
+    Ok(SyscallReturn::Return(0))
+}
+
+pub fn sys_sched_getparam(tid: i32, param_ptr: Vaddr) -> Result<SyscallReturn> {
+    debug!("tid = {}, param_ptr = 0x{:x}", tid, param_ptr);
+
+    if tid < 0 || param_ptr == 0 {
+        return_errno_with_message!(Errno::EINVAL, "the tid or the param is invalid");
+    }
+    let thread = get_thread(tid as Tid)?;
+    let sched_priority = RealTimePriority::try_from(thread.priority())
+        .map_or(0, |priority| priority.to_raw() as i32);
+    write_val_to_user(param_ptr, &sched_param { sched_priority })?;
+
+    Ok(SyscallReturn::Return(0))
+}
+
+pub fn sys_sched_get_priority_max(policy: i32) -> Result<SyscallReturn> {
+    debug!("policy = {}", policy);
+
+    let max = match Policy::try_from(policy) {
+        Ok(Policy::SCHED_FIFO | Policy::SCHED_RR) => RealTimePriority::MAX.to_raw(),
+        Ok(Policy::SCHED_OTHER) => 0,
+        Err(_) => return_errno_with_message!(Errno::EINVAL, "the policy is not supported"),
+    };
+
+    Ok(SyscallReturn::Return(max as _))
+}
+
+pub fn sys_sched_get_priority_min(policy: i32) -> Result<SyscallReturn> {
+    debug!("policy = {}", policy);
+
+    let min = match Policy::try_from(policy) {
+        Ok(Policy::SCHED_FIFO | Policy::SCHED_RR) => RealTimePriority::MIN.to_raw(),
+        Ok(Policy::SCHED_OTHER) => 0,
+        Err(_) => return_errno_with_message!(Errno::EINVAL, "the policy is not supported"),
+    };
+
+    Ok(SyscallReturn::Return(min as _))
+}
+
+pub fn sys_sched_rr_get_interval(tid: i32, interval_ptr: Vaddr) -> Result<SyscallReturn> {
+    debug!("tid = {}, interval_ptr = 0x{:x}", tid, interval_ptr);
+
+    if tid < 0 {
+        return_errno_with_message!(Errno::EINVAL, "the tid is negative");
+    }
+    let thread = get_thread(tid as Tid)?;
+    // The tasks scheduled with other policies have no fixed time slices.
+    let interval = if thread.sched_policy() == SchedPolicy::RoundRobin {
+        RR_TIME_SLICE
+    } else {
+        Duration::ZERO
+    };
+    write_val_to_user(interval_ptr, &timespec_t::from(interval))?;
+
+    Ok(SyscallReturn::Return(0))
+}
+
+fn read_param(tid: i32, param_ptr: Vaddr) -> Result<sched_param> {
+    if tid < 0 || param_ptr == 0 {
+        return_errno_with_message!(Errno::EINVAL, "the tid or the param is invalid");
+    }
+    read_val_from_user(param_ptr)
+}
+
+/// Sets the scheduling policy and priority of the `thread`, or only the priority if
+/// `policy` is `None`.
+///
+/// Like Linux, a thread without `CAP_SYS_NICE` can only set a real-time priority up to
+/// its `RLIMIT_RTPRIO`, unless the priority is not raised and the policy is not changed
+/// from a normal one.
+fn set_sched_policy(thread: &Thread, policy: Option<Policy>, param: sched_param) -> Result<()> {
+    // The priority is checked against the base policy, regardless of the priority that the
+    // thread inherits. If the policy is changed concurrently, setting the priority fails.
+    let checked_policy = policy.unwrap_or_else(|| Policy::from(thread.sched_policy()));
+    let priority = match checked_policy {
+        Policy::SCHED_OTHER if param.sched_priority == 0 => None,
+        Policy::SCHED_FIFO | Policy::SCHED_RR => {
+            let priority = RealTimePriority::new(param.sched_priority as u32).ok_or_else(|| {
+                Error::with_message(Errno::EINVAL, "the priority is out of range")
+            })?;
+            Some(priority)
+        }
+        Policy::SCHED_OTHER => {
+            return_errno_with_message!(Errno::EINVAL, "the priority of SCHED_OTHER must be 0")
+        }
+    };
+
+    check_permission(thread)?;
+    let posix_thread = thread.as_posix_thread().unwrap();
+    let process = posix_thread.process();
+    if let Some(priority) = priority
+        && !credentials().effective_capset().contains(CapSet::SYS_NICE)
+    {
+        let rlimit = process
+            .resource_limits()
+            .lock()
+            .get_rlimit(ResourceType::RLIMIT_RTPRIO)
+            .get_cur();
+        let old_priority = RealTimePriority::try_from(thread.priority()).ok();
+        if old_priority.is_none() && rlimit == 0 {
+            return_errno_with_message!(Errno::EPERM, "the RLIMIT_RTPRIO is 0");
+        }
+        if Some(priority) > old_priority && priority.to_raw() as u64 > rlimit {
+            return_errno_with_message!(Errno::EPERM, "the priority exceeds the RLIMIT_RTPRIO");
+        }
+    }
+
+    let priority = match priority {
+        Some(priority) => priority.into(),
+        None => process.nice().load(Ordering::Relaxed).into(),
+    };
+    match policy {
+        Some(policy) => thread.set_sched_policy(policy.into(), priority),
+        None => thread.set_priority(priority),
+    }
+}
+
+#[allow(non_camel_case_types)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, TryFromInt)]
+#[repr(i32)]
+enum Policy {
+    SCHED_OTHER = 0,
+    SCHED_FIFO = 1,
+    SCHED_RR = 2,
+}
+
+impl From<Policy> for SchedPolicy {
+    fn from(policy: Policy) -> Self {
+        match policy {
+            Policy::SCHED_OTHER => SchedPolicy::Normal,
+            Policy::SCHED_FIFO => SchedPolicy::Fifo,
+            Policy::SCHED_RR => SchedPolicy::RoundRobin,
+        }
+    }
+}
+
+impl From<SchedPolicy> for Policy {
+    fn from(policy: SchedPolicy) -> Self {
+        match policy {
+            SchedPolicy::Normal => Policy::SCHED_OTHER,
+            SchedPolicy::Fifo => Policy::SCHED_FIFO,
+            SchedPolicy::RoundRobin => Policy::SCHED_RR,
+        }
+    }
+}
+
+#[allow(non_camel_case_types)]
+#[derive(Debug, Clone, Copy, Pod)]
+#[repr(C)]
+struct sched_param {
+    sched_priority: i32,
+}
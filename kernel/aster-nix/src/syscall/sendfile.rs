@@ -2,7 +2,12 @@
 
 use super::SyscallReturn;
 use crate::{
-    fs::file_table::FileDesc,
+    fs::{
+        file_handle::FileLike,
+        file_table::FileDesc,
+        inode_handle::InodeHandle,
+        utils::{InodeType, SeekFrom, StatusFlags},
+    },
     prelude::*,
     util::{read_val_from_user, write_val_to_user},
 };
@@ -30,7 +35,7 @@ pub fn sys_sendfile(
         out_fd, in_fd, offset, count
     );
 
-    let mut count = if count < 0 {
+    let count = if count < 0 {
         return_errno_with_message!(Errno::EINVAL, "count cannot be negative");
     } else {
         count as usize
@@ -40,81 +45,118 @@ pub fn sys_sendfile(
         let current = current!();
         let file_table = current.file_table().lock();
         let out_file = file_table.get_file(out_fd)?.clone();
-        // FIXME: the in_file must support mmap-like operations (i.e., it cannot be a socket).
         let in_file = file_table.get_file(in_fd)?.clone();
         (out_file, in_file)
     };
-
-    // sendfile can send at most `MAX_COUNT` bytes
-    const MAX_COUNT: usize = 0x7fff_f000;
-    if count > MAX_COUNT {
-        count = MAX_COUNT;
+    // Like Linux, the data must be read from the page cache, so the input file cannot be
+    // a socket or a pipe.
+    if !in_file
+        .downcast_ref::<InodeHandle>()
+        .is_some_and(|handle| handle.dentry().type_() == InodeType::File)
+    {
+        return_errno_with_message!(Errno::EINVAL, "the input file is not a regular file");
+    }
+    if out_file.status_flags().contains(StatusFlags::O_APPEND) {
+        return_errno_with_message!(Errno::EINVAL, "the output file is append-only");
     }
 
-    const BUFFER_SIZE: usize = PAGE_SIZE;
-    let mut buffer = vec![0u8; BUFFER_SIZE].into_boxed_slice();
-    let mut total_len = 0;
     let mut offset = offset.map(|offset| offset as usize);
+    let total_len = copy_between_files(
+        in_file.as_ref(),
+        offset.as_mut(),
+        out_file.as_ref(),
+        None,
+        count.min(MAX_RW_COUNT),
+    )?;
+
+    if let Some(offset) = offset {
+        write_val_to_user(offset_ptr, &(offset as isize))?;
+    }
 
+    Ok(SyscallReturn::Return(total_len as _))
+}
+
+/// The maximum number of bytes transferred by a single call, which is the same as Linux.
+pub(super) const MAX_RW_COUNT: usize = 0x7fff_f000;
+
+/// Copies at most `count` bytes from `in_file` to `out_file` in the kernel.
+///
+/// The data are read at `*in_offset` if it is `Some`, or at the file offset of `in_file`
+/// otherwise, which must be seekable. The data are written in the same way. The offsets
+/// are advanced by the number of bytes copied, which is returned. For regular files, the
+/// data go from the page cache of one file to that of the other.
+///
+/// Short reads and short writes end the copy. If an error occurs after some data have
+/// been copied, the error is dropped and the number of bytes copied is returned.
+pub(super) fn copy_between_files(
+    in_file: &dyn FileLike,
+    in_offset: Option<&mut usize>,
+    out_file: &dyn FileLike,
+    out_offset: Option<&mut usize>,
+    count: usize,
+) -> Result<usize> {
+    /// The maximum size of the buffer that the data are copied through.
+    const MAX_BUFFER_SIZE: usize = 16 * PAGE_SIZE;
+
+    let mut in_pos = match in_offset {
+        Some(ref offset) => **offset,
+        None => in_file.seek(SeekFrom::Current(0))?,
+    };
+    let mut out_pos = out_offset.as_ref().map(|offset| **offset);
+
+    let mut buffer = vec![0u8; count.min(MAX_BUFFER_SIZE)];
+    let mut total_len = 0;
+    let mut result = Ok(());
     while total_len < count {
-        // The offset decides how to read from `in_file`.
-        // If offset is `Some(_)`, the data will be read from the given offset,
-        // and after reading, the file offset of `in_file` will remain unchanged.
-        // If offset is `None`, the data will be read from the file offset,
-        // and the file offset of `in_file` is adjusted
-        // to reflect the number of bytes read from `in_file`.
-        let max_readlen = buffer.len().min(count - total_len);
-
-        // Read from `in_file`
-        let read_res = if let Some(offset) = offset.as_mut() {
-            let res = in_file.read_at(*offset, &mut buffer[..max_readlen]);
-            if let Ok(len) = res.as_ref() {
-                *offset += *len;
+        let max_len = buffer.len().min(count - total_len);
+        let read_len = match in_file.read_at(in_pos, &mut buffer[..max_len]) {
+            Ok(0) => break,
+            Ok(len) => len,
+            Err(err) => {
+                result = Err(err);
+                break;
             }
-            res
-        } else {
-            in_file.read(&mut buffer[..max_readlen])
         };
 
-        let read_len = match read_res {
+        let write_res = match out_pos.as_mut() {
+            Some(out_pos) => out_file.write_at(*out_pos, &buffer[..read_len]),
+            None => out_file.write(&buffer[..read_len]),
+        };
+        let write_len = match write_res {
             Ok(len) => len,
-            Err(e) => {
-                if total_len > 0 {
-                    warn!("error occurs when trying to read file: {:?}", e);
-                    break;
-                }
-                return Err(e);
+            Err(err) => {
+                result = Err(err);
+                break;
             }
         };
 
-        if read_len == 0 {
+        // The data that are read but not written will be read again by the next call.
+        in_pos += write_len;
+        if let Some(out_pos) = out_pos.as_mut() {
+            *out_pos += write_len;
+        }
+        total_len += write_len;
+        if write_len < max_len {
             break;
         }
+    }
 
-        // Note: `sendfile` allows sending partial data,
-        // so short reads and short writes are all acceptable
-        let write_res = out_file.write(&buffer[..read_len]);
-
-        match write_res {
-            Ok(len) => {
-                total_len += len;
-                if len < BUFFER_SIZE {
-                    break;
-                }
-            }
-            Err(e) => {
-                if total_len > 0 {
-                    warn!("error occurs when trying to write file: {:?}", e);
-                    break;
-                }
-                return Err(e);
-            }
+    match in_offset {
+        Some(offset) => *offset = in_pos,
+        None => {
+            in_file.seek(SeekFrom::Start(in_pos))?;
         }
     }
-
-    if let Some(offset) = offset {
-        write_val_to_user(offset_ptr, &(offset as isize))?;
+    if let (Some(offset), Some(out_pos)) = (out_offset, out_pos) {
+        *offset = out_pos;
     }
 
-    Ok(SyscallReturn::Return(total_len as _))
+    match result {
+        Err(err) if total_len == 0 => Err(err),
+        Err(err) => {
+            warn!("error occurs when copying between files: {:?}", err);
+            Ok(total_len)
+        }
+        Ok(()) => Ok(total_len),
+    }
 }
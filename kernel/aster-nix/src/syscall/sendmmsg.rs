@@ -0,0 +1,65 @@
+// SPDX-License-Identifier: MPL-2.0
+
+use super::SyscallReturn;
+use crate::{
+    fs::file_table::FileDesc,
+    net::socket::SendRecvFlags,
+    prelude::*,
+    util::{
+        iovec::{gather_from_user, IOVEC_MAX},
+        net::{get_socket_from_fd, read_socket_addr_from_user, CUserMmsgHdr},
+        read_val_from_user, write_val_to_user,
+    },
+};
+
+pub fn sys_sendmmsg(
+    sockfd: FileDesc,
+    msgvec: Vaddr,
+    vlen: u32,
+    flags: i32,
+) -> Result<SyscallReturn> {
+    let flags = SendRecvFlags::from_bits_truncate(flags);
+    // Linux silently truncates `vlen` to `UIO_MAXIOV`
+    let vlen = (vlen as usize).min(IOVEC_MAX);
+    debug!("sockfd = {sockfd}, msgvec = 0x{msgvec:x}, vlen = {vlen}, flags = {flags:?}");
+
+    let socket = get_socket_from_fd(sockfd)?;
+
+    // Each message is gathered only when it is about to be sent, so that a fault in a
+    // later message does not prevent the earlier ones from being sent.
+    let mut msgs = (0..vlen).map(|i| {
+        let mmsg_hdr: CUserMmsgHdr =
+            read_val_from_user(msgvec + i * core::mem::size_of::<CUserMmsgHdr>())?;
+        let msg_hdr = &mmsg_hdr.msg_hdr;
+
+        let socket_addr = if msg_hdr.msg_name == 0 {
+            None
+        } else {
+            let socket_addr =
+                read_socket_addr_from_user(msg_hdr.msg_name, msg_hdr.msg_namelen as usize)?;
+            Some(socket_addr)
+        };
+        if msg_hdr.msg_control != 0 {
+            warn!("sending control messages is not supported");
+        }
+
+        let buffer = gather_from_user(&msg_hdr.read_iovecs_from_user()?)?;
+        Ok((buffer, socket_addr))
+    });
+
+    let sent_lens = socket.sendmmsg(&mut msgs, flags)?;
+
+    // Like Linux, a message whose length cannot be written back is not counted
+    for (i, sent_len) in sent_lens.iter().enumerate() {
+        let msg_len_addr =
+            msgvec + i * core::mem::size_of::<CUserMmsgHdr>() + CUserMmsgHdr::MSG_LEN_OFFSET;
+        if let Err(err) = write_val_to_user(msg_len_addr, &(*sent_len as u32)) {
+            if i == 0 {
+                return Err(err);
+            }
+            return Ok(SyscallReturn::Return(i as _));
+        }
+    }
+
+    Ok(SyscallReturn::Return(sent_lens.len() as _))
+}
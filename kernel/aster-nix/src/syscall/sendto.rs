@@ -7,7 +7,7 @@ use crate::{
     prelude::*,
     util::{
         net::{get_socket_from_fd, read_socket_addr_from_user},
-        read_bytes_from_user,
+        read_bytes_from_user_partial,
     },
 };
 
@@ -28,11 +28,11 @@ pub fn sys_sendto(
     };
     debug!("sockfd = {sockfd}, buf = 0x{buf:x}, len = 0x{len:x}, flags = {flags:?}, socket_addr = {socket_addr:?}");
     let mut buffer = vec![0u8; len];
-    read_bytes_from_user(buf, &mut buffer)?;
+    let copied_len = read_bytes_from_user_partial(buf, &mut buffer)?;
 
     let socket = get_socket_from_fd(sockfd)?;
 
-    let send_size = socket.sendto(&buffer, socket_addr, flags)?;
+    let send_size = socket.sendto(&buffer[..copied_len], socket_addr, flags)?;
 
     Ok(SyscallReturn::Return(send_size as _))
 }
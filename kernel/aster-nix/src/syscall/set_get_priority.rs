@@ -30,6 +30,12 @@ pub fn sys_set_priority(which: i32, who: u32, prio: i32) -> Result<SyscallReturn
     let processes = get_processes(prio_target)?;
     for process in processes.iter() {
         process.nice().store(new_nice, Ordering::Relaxed);
+        // Like Linux, the nice value takes effect on the real-time threads only after
+        // they are scheduled with a normal policy again, so setting the priority of a
+        // real-time thread fails and is ignored.
+        for thread in process.threads().lock().iter() {
+            let _ = thread.set_priority(new_nice.into());
+        }
     }
 
     Ok(SyscallReturn::Return(0))
@@ -0,0 +1,224 @@
+// SPDX-License-Identifier: MPL-2.0
+
+//! The System V shared memory syscalls: `shmget`, `shmat`, `shmdt` and `shmctl`.
+
+use align_ext::AlignExt;
+
+use super::SyscallReturn;
+use crate::{
+    ipc::{shm, IpcFlags},
+    prelude::*,
+    process::{Gid, Uid},
+    util::{read_val_from_user, write_val_to_user},
+    vm::perms::VmPerms,
+};
+
+pub fn sys_shmget(key: i32, size: usize, flags: u32) -> Result<SyscallReturn> {
+    debug!("key = {}, size = {}, flags = 0o{:o}", key, size, flags);
+
+    let ipc_flags = IpcFlags::from_bits_truncate(flags);
+    let mode = (flags & 0o777) as u16;
+    let id = shm::get_or_create(key, size, ipc_flags, mode)?;
+    Ok(SyscallReturn::Return(id as _))
+}
+
+pub fn sys_shmat(shmid: i32, addr: Vaddr, flags: u32) -> Result<SyscallReturn> {
+    debug!(
+        "shmid = {}, addr = 0x{:x}, flags = 0o{:o}",
+        shmid, addr, flags
+    );
+
+    let flags = ShmFlags::from_bits_truncate(flags);
+    let addr = if addr % PAGE_SIZE == 0 {
+        addr
+    } else if flags.contains(ShmFlags::SHM_RND) {
+        addr.align_down(PAGE_SIZE)
+    } else {
+        return_errno_with_message!(Errno::EINVAL, "the address is not page-aligned");
+    };
+    if flags.contains(ShmFlags::SHM_REMAP) && addr == 0 {
+        return_errno_with_message!(Errno::EINVAL, "SHM_REMAP requires an address");
+    }
+
+    let (perms, access) = {
+        let mut perms = VmPerms::READ;
+        let mut access = 0o4;
+        if !flags.contains(ShmFlags::SHM_RDONLY) {
+            perms |= VmPerms::WRITE;
+            access |= 0o2;
+        }
+        if flags.contains(ShmFlags::SHM_EXEC) {
+            perms |= VmPerms::EXEC;
+            access |= 0o1;
+        }
+        (perms, access)
+    };
+
+    let segment = shm::get(shmid)?;
+    segment.perm().check_access(access)?;
+
+    let current = current!();
+    let root_vmar = current.root_vmar();
+    let mut options = root_vmar
+        .new_map_shared(segment.shared_mem(), perms)?
+        .size(segment.size().align_up(PAGE_SIZE));
+    if addr != 0 {
+        options = options
+            .offset(addr)
+            .can_overwrite(flags.contains(ShmFlags::SHM_REMAP));
+    }
+    let map_addr = options.build()?;
+    segment.on_attach(current.pid());
+
+    Ok(SyscallReturn::Return(map_addr as _))
+}
+
+pub fn sys_shmdt(addr: Vaddr) -> Result<SyscallReturn> {
+    debug!("addr = 0x{:x}", addr);
+
+    if addr % PAGE_SIZE != 0 {
+        return_errno_with_message!(Errno::EINVAL, "the address is not page-aligned");
+    }
+
+    // The segment attached at `addr` may have been split into several mappings by
+    // `mprotect`, so the adjacent mappings that start from `addr` in the segment are
+    // all detached.
+    let current = current!();
+    let root_vmar = current.root_vmar();
+    let mut segment = None;
+    let mut end = addr;
+    while let Ok(mapping) = root_vmar.get_vm_mapping(end)
+        && mapping.map_to_addr() == end
+        && mapping.map_to_addr() - mapping.vmo_offset() == addr
+        && let Some(shared_mem) = mapping.shared_mem()
+    {
+        if segment.is_none() {
+            let Some(found) = shm::get_by_shared_mem(shared_mem) else {
+                break;
+            };
+            segment = Some(found);
+        }
+        if !Arc::ptr_eq(segment.as_ref().unwrap().shared_mem(), shared_mem) {
+            break;
+        }
+        end += mapping.map_size();
+    }
+
+    let Some(segment) = segment else {
+        return_errno_with_message!(Errno::EINVAL, "no segment is attached at the address");
+    };
+    root_vmar.destroy(addr..end)?;
+    segment.on_detach(current.pid());
+
+    Ok(SyscallReturn::Return(0))
+}
+
+pub fn sys_shmctl(shmid: i32, cmd: i32, buf: Vaddr) -> Result<SyscallReturn> {
+    debug!("shmid = {}, cmd = {}, buf = 0x{:x}", shmid, cmd, buf);
+
+    match cmd & !IPC_64 {
+        IPC_RMID => shm::remove(shmid)?,
+        IPC_SET => {
+            let ds = read_val_from_user::<ShmidDs>(buf)?;
+            let segment = shm::get(shmid)?;
+            segment.set_perm(
+                Uid::new(ds.shm_perm.uid),
+                Gid::new(ds.shm_perm.gid),
+                ds.shm_perm.mode as u16,
+            )?;
+        }
+        IPC_STAT => {
+            let segment = shm::get(shmid)?;
+            segment.perm().check_access(0o4)?;
+            write_val_to_user(buf, &ShmidDs::from_segment(&segment))?;
+        }
+        _ => return_errno_with_message!(Errno::EINVAL, "the command is not supported"),
+    }
+    Ok(SyscallReturn::Return(0))
+}
+
+bitflags! {
+    struct ShmFlags: u32 {
+        const SHM_RDONLY = 0o10000;
+        const SHM_RND = 0o20000;
+        const SHM_REMAP = 0o40000;
+        const SHM_EXEC = 0o100000;
+    }
+}
+
+const IPC_RMID: i32 = 0;
+const IPC_SET: i32 = 1;
+const IPC_STAT: i32 = 2;
+/// The flag that selects the 64-bit version of the structures, which is the only version.
+const IPC_64: i32 = 0x100;
+
+/// The mode bit indicating that the segment is removed.
+const SHM_DEST: u32 = 0o1000;
+
+/// The `ipc64_perm` in Linux.
+#[derive(Debug, Clone, Copy, Pod)]
+#[repr(C)]
+struct IpcPermDs {
+    key: i32,
+    uid: u32,
+    gid: u32,
+    cuid: u32,
+    cgid: u32,
+    mode: u32,
+    seq: u16,
+    pad1: u16,
+    pad2: u32,
+    unused1: u64,
+    unused2: u64,
+}
+
+/// The `shmid64_ds` in Linux.
+#[derive(Debug, Clone, Copy, Pod)]
+#[repr(C)]
+struct ShmidDs {
+    shm_perm: IpcPermDs,
+    shm_segsz: u64,
+    shm_atime: i64,
+    shm_dtime: i64,
+    shm_ctime: i64,
+    shm_cpid: i32,
+    shm_lpid: i32,
+    shm_nattch: u64,
+    unused4: u64,
+    unused5: u64,
+}
+
+impl ShmidDs {
+    fn from_segment(segment: &shm::ShmSegment) -> Self {
+        let perm = segment.perm();
+        let mut mode = perm.mode as u32;
+        if segment.is_removed() {
+            mode |= SHM_DEST;
+        }
+        let (atime, dtime, ctime) = segment.times();
+        Self {
+            shm_perm: IpcPermDs {
+                key: perm.key,
+                uid: perm.uid.as_u32(),
+                gid: perm.gid.as_u32(),
+                cuid: perm.cuid.as_u32(),
+                cgid: perm.cgid.as_u32(),
+                mode,
+                seq: 0,
+                pad1: 0,
+                pad2: 0,
+                unused1: 0,
+                unused2: 0,
+            },
+            shm_segsz: segment.size() as u64,
+            shm_atime: atime.as_secs() as i64,
+            shm_dtime: dtime.as_secs() as i64,
+            shm_ctime: ctime.as_secs() as i64,
+            shm_cpid: segment.cpid() as i32,
+            shm_lpid: segment.lpid() as i32,
+            shm_nattch: segment.nattch() as u64,
+            unused4: 0,
+            unused5: 0,
+        }
+    }
+}
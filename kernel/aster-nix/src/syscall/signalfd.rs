@@ -0,0 +1,319 @@
+// SPDX-License-Identifier: MPL-2.0
+
+//! `signalfd()` creates a "signalfd object" (we name it as `SignalFile`)
+//! which accepts the signals targeted at the caller via a file descriptor.
+//!
+//! `SignalFile` holds a mask of the signals to accept. Reading from `SignalFile`
+//! dequeues the pending signals in the mask and returns them as `signalfd_siginfo`
+//! structures, and blocks until any of them is pending, unless the file is
+//! nonblocking. The file is readable, e.g., to `poll()` and `epoll_wait()`, once
+//! any of them is pending. The signals in the mask should normally be blocked,
+//! so that they are not handled in the default way before being read.
+//!
+//! For more detailed information about this syscall,
+//! refer to the man 2 signalfd documentation.
+
+use core::sync::atomic::{AtomicU64, Ordering};
+
+use super::SyscallReturn;
+use crate::{
+    events::{IoEvents, Observer},
+    fs::{
+        file_handle::FileLike,
+        file_table::{FdFlags, FileDesc},
+        utils::{CreationFlags, InodeMode, InodeType, Metadata, StatusFlags},
+    },
+    prelude::*,
+    process::{
+        posix_thread::PosixThreadExt,
+        signal::{
+            constants::{SIGKILL, SIGSTOP},
+            sig_mask::SigMask,
+            signals::Signal,
+            Pollee, Poller, SigEvents, SigEventsFilter,
+        },
+        Gid, Uid,
+    },
+    time::clocks::RealTimeClock,
+    util::read_val_from_user,
+};
+
+pub fn sys_signalfd(fd: FileDesc, mask_addr: Vaddr, mask_size: usize) -> Result<SyscallReturn> {
+    debug!(
+        "fd = {}, mask_addr = 0x{:x}, mask_size = {}",
+        fd, mask_addr, mask_size
+    );
+
+    do_sys_signalfd4(fd, mask_addr, mask_size, Flags::empty())
+}
+
+pub fn sys_signalfd4(
+    fd: FileDesc,
+    mask_addr: Vaddr,
+    mask_size: usize,
+    flags: u32,
+) -> Result<SyscallReturn> {
+    trace!("raw flags = {}", flags);
+    let flags = Flags::from_bits(flags)
+        .ok_or_else(|| Error::with_message(Errno::EINVAL, "unknown flags"))?;
+    debug!(
+        "fd = {}, mask_addr = 0x{:x}, mask_size = {}, flags = {:?}",
+        fd, mask_addr, mask_size, flags
+    );
+
+    do_sys_signalfd4(fd, mask_addr, mask_size, flags)
+}
+
+fn do_sys_signalfd4(
+    fd: FileDesc,
+    mask_addr: Vaddr,
+    mask_size: usize,
+    flags: Flags,
+) -> Result<SyscallReturn> {
+    if mask_size != core::mem::size_of::<SigMask>() {
+        return_errno_with_message!(Errno::EINVAL, "the size of the mask is invalid");
+    }
+    let mut mask = read_val_from_user::<SigMask>(mask_addr)?;
+    // According to man pages, SIGKILL and SIGSTOP cannot be received via a signalfd,
+    // and they are silently ignored if specified in the mask.
+    mask.remove_signal(SIGKILL);
+    mask.remove_signal(SIGSTOP);
+
+    let current = current!();
+    let mut file_table = current.file_table().lock();
+
+    // The mask of an existing signalfd is replaced, and the flags are ignored.
+    if fd != -1 {
+        let file = file_table.get_file(fd)?;
+        let signal_file = file
+            .downcast_ref::<SignalFile>()
+            .ok_or_else(|| Error::with_message(Errno::EINVAL, "the file is not a signalfd"))?;
+        signal_file.set_mask(mask);
+        return Ok(SyscallReturn::Return(fd as _));
+    }
+
+    let signal_file = SignalFile::new(mask, flags);
+    let fd_flags = if flags.contains(Flags::SFD_CLOEXEC) {
+        FdFlags::CLOEXEC
+    } else {
+        FdFlags::empty()
+    };
+    let fd = file_table.insert(signal_file, fd_flags);
+
+    Ok(SyscallReturn::Return(fd as _))
+}
+
+bitflags! {
+    struct Flags: u32 {
+        const SFD_CLOEXEC = CreationFlags::O_CLOEXEC.bits();
+        const SFD_NONBLOCK = StatusFlags::O_NONBLOCK.bits();
+    }
+}
+
+/// The structure read from a signalfd for each signal.
+#[derive(Debug, Clone, Copy, Pod)]
+#[repr(C)]
+#[allow(non_camel_case_types)]
+struct signalfd_siginfo {
+    ssi_signo: u32,
+    ssi_errno: i32,
+    ssi_code: i32,
+    ssi_pid: u32,
+    ssi_uid: u32,
+    ssi_fd: i32,
+    ssi_tid: u32,
+    ssi_band: u32,
+    ssi_overrun: u32,
+    ssi_trapno: u32,
+    ssi_status: i32,
+    ssi_int: i32,
+    ssi_ptr: u64,
+    ssi_utime: u64,
+    ssi_stime: u64,
+    ssi_addr: u64,
+    ssi_addr_lsb: u16,
+    __pad2: u16,
+    ssi_syscall: i32,
+    ssi_call_addr: u64,
+    ssi_arch: u32,
+    __pad: [u8; 28],
+}
+
+impl From<&dyn Signal> for signalfd_siginfo {
+    fn from(signal: &dyn Signal) -> Self {
+        let info = signal.to_info();
+        let mut ssi = Self::new_zeroed();
+        ssi.ssi_signo = info.si_signo as u32;
+        ssi.ssi_errno = info.si_errno;
+        ssi.ssi_code = info.si_code;
+        ssi.ssi_addr = info.si_addr() as u64;
+        ssi
+    }
+}
+
+// FIXME: A signalfd only observes the threads that have polled or read it, and reads
+// the signals from the queue of the calling thread. So the process-directed signals
+// that are queued on other threads are not accepted.
+struct SignalFile {
+    /// The signals to accept, as the bits of a `SigMask`.
+    mask: AtomicU64,
+    pollee: Pollee,
+    flags: Mutex<Flags>,
+    weak_self: Weak<Self>,
+}
+
+impl SignalFile {
+    fn new(mask: SigMask, flags: Flags) -> Arc<Self> {
+        let signal_file = Arc::new_cyclic(|weak_self| Self {
+            mask: AtomicU64::new(mask.as_u64()),
+            pollee: Pollee::new(IoEvents::empty()),
+            flags: Mutex::new(flags),
+            weak_self: weak_self.clone(),
+        });
+        signal_file.update_io_state();
+        signal_file
+    }
+
+    fn mask(&self) -> SigMask {
+        SigMask::from(self.mask.load(Ordering::Relaxed))
+    }
+
+    fn set_mask(&self, mask: SigMask) {
+        self.mask.store(mask.as_u64(), Ordering::Relaxed);
+        self.update_io_state();
+    }
+
+    fn is_nonblocking(&self) -> bool {
+        self.flags.lock().contains(Flags::SFD_NONBLOCK)
+    }
+
+    /// Observes the signal queue of the current thread, and updates the events
+    /// according to its pending signals.
+    fn update_io_state(&self) {
+        let current_thread = current_thread!();
+        let posix_thread = current_thread.as_posix_thread().unwrap();
+
+        // Registering the same observer again only replaces the filter. The signals are
+        // filtered in `on_events` instead, since the mask may be changed later.
+        let observer = self.weak_self.clone() as Weak<dyn Observer<SigEvents>>;
+        posix_thread
+            .register_sigqueue_observer(observer, SigEventsFilter::new(SigMask::new_empty()));
+
+        // The events are cleared first, so the signals enqueued after the check are not
+        // missed.
+        self.pollee.del_events(IoEvents::IN);
+        if posix_thread.sig_pending().as_u64() & self.mask().as_u64() != 0 {
+            self.pollee.add_events(IoEvents::IN);
+        }
+    }
+}
+
+impl Observer<SigEvents> for SignalFile {
+    fn on_events(&self, events: &SigEvents) {
+        if self.mask().contains(events.sig_num()) {
+            self.pollee.add_events(IoEvents::IN);
+        }
+    }
+}
+
+impl FileLike for SignalFile {
+    fn read(&self, buf: &mut [u8]) -> Result<usize> {
+        let info_len = core::mem::size_of::<signalfd_siginfo>();
+        if buf.len() < info_len {
+            return_errno_with_message!(Errno::EINVAL, "buf len is less than signalfd_siginfo size");
+        }
+
+        let current_thread = current_thread!();
+        let posix_thread = current_thread.as_posix_thread().unwrap();
+
+        loop {
+            // The signals out of the mask are regarded as blocked, so they are left
+            // in the queue.
+            let blocked = SigMask::from(!self.mask().as_u64());
+            let mut read_len = 0;
+            while read_len + info_len <= buf.len() {
+                let Some(signal) = posix_thread.dequeue_signal(&blocked) else {
+                    break;
+                };
+                let info = signalfd_siginfo::from(signal.as_ref());
+                buf[read_len..read_len + info_len].copy_from_slice(info.as_bytes());
+                read_len += info_len;
+            }
+
+            self.update_io_state();
+            if read_len != 0 {
+                return Ok(read_len);
+            }
+
+            if self.is_nonblocking() {
+                return_errno_with_message!(Errno::EAGAIN, "no signal is pending");
+            }
+
+            let poller = Poller::new();
+            if self.pollee.poll(IoEvents::IN, Some(&poller)).is_empty() {
+                poller.wait()?;
+            }
+        }
+    }
+
+    fn poll(&self, mask: IoEvents, poller: Option<&Poller>) -> IoEvents {
+        self.update_io_state();
+        self.pollee.poll(mask, poller)
+    }
+
+    fn status_flags(&self) -> StatusFlags {
+        if self.is_nonblocking() {
+            StatusFlags::O_NONBLOCK
+        } else {
+            StatusFlags::empty()
+        }
+    }
+
+    fn set_status_flags(&self, new_flags: StatusFlags) -> Result<()> {
+        let mut flags = self.flags.lock();
+
+        if new_flags.contains(StatusFlags::O_NONBLOCK) {
+            *flags |= Flags::SFD_NONBLOCK;
+        } else {
+            *flags &= !Flags::SFD_NONBLOCK;
+        }
+
+        Ok(())
+    }
+
+    fn register_observer(
+        &self,
+        observer: Weak<dyn Observer<IoEvents>>,
+        mask: IoEvents,
+    ) -> Result<()> {
+        self.pollee.register_observer(observer, mask);
+        Ok(())
+    }
+
+    fn unregister_observer(
+        &self,
+        observer: &Weak<dyn Observer<IoEvents>>,
+    ) -> Option<Weak<dyn Observer<IoEvents>>> {
+        self.pollee.unregister_observer(observer)
+    }
+
+    fn metadata(&self) -> Metadata {
+        let now = RealTimeClock::get().read_time();
+        Metadata {
+            dev: 0,
+            ino: 0,
+            size: 0,
+            blk_size: 0,
+            blocks: 0,
+            atime: now,
+            mtime: now,
+            ctime: now,
+            type_: InodeType::NamedPipe,
+            mode: InodeMode::from_bits_truncate(0o600),
+            nlinks: 1,
+            uid: Uid::new_root(),
+            gid: Gid::new_root(),
+            rdev: 0,
+        }
+    }
+}
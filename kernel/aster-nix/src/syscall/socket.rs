@@ -2,7 +2,7 @@
 
 use super::SyscallReturn;
 use crate::{
-    fs::{file_handle::FileLike, file_table::FdFlags},
+    fs::file_handle::FileLike,
     net::socket::{
         ip::{DatagramSocket, StreamSocket},
         unix::UnixStreamSocket,
@@ -44,11 +44,7 @@ pub fn sys_socket(domain: i32, type_: i32, protocol: i32) -> Result<SyscallRetur
     let fd = {
         let current = current!();
         let mut file_table = current.file_table().lock();
-        let fd_flags = if sock_flags.contains(SockFlags::SOCK_CLOEXEC) {
-            FdFlags::CLOEXEC
-        } else {
-            FdFlags::empty()
-        };
+        let fd_flags = sock_flags.fd_flags();
         file_table.insert(file_like, fd_flags)
     };
     Ok(SyscallReturn::Return(fd as _))
@@ -2,7 +2,7 @@
 
 use super::SyscallReturn;
 use crate::{
-    fs::file_table::{FdFlags, FileDesc},
+    fs::file_table::FileDesc,
     net::socket::unix::UnixStreamSocket,
     prelude::*,
     util::{
@@ -36,11 +36,7 @@ pub fn sys_socketpair(domain: i32, type_: i32, protocol: i32, sv: Vaddr) -> Resu
     let socket_fds = {
         let current = current!();
         let mut file_table = current.file_table().lock();
-        let fd_flags = if sock_flags.contains(SockFlags::SOCK_CLOEXEC) {
-            FdFlags::CLOEXEC
-        } else {
-            FdFlags::empty()
-        };
+        let fd_flags = sock_flags.fd_flags();
         let fd_a = file_table.insert(socket_a, fd_flags);
         let fd_b = file_table.insert(socket_b, fd_flags);
         SocketFds(fd_a, fd_b)
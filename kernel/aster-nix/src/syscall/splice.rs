@@ -0,0 +1,211 @@
+// SPDX-License-Identifier: MPL-2.0
+
+//! The splice family of system calls, i.e., `splice`, `tee` and `vmsplice`.
+//!
+//! Unlike Linux, a pipe is a ring buffer of bytes rather than of page references, so the
+//! data are copied by the kernel instead of being moved by pages. The data still never
+//! go through the user space. For the same reason, `SPLICE_F_MOVE` and `SPLICE_F_GIFT`
+//! are accepted but have no effect, which Linux permits as they are only hints.
+
+use super::{sendfile::MAX_RW_COUNT, SyscallReturn};
+use crate::{
+    fs::{
+        file_handle::FileLike,
+        file_table::FileDesc,
+        inode_handle::InodeHandle,
+        pipe::{PipeReader, PipeWriter},
+        utils::StatusFlags,
+    },
+    prelude::*,
+    util::{
+        iovec::{gather_from_user, read_iovecs_from_user, scatter_to_user, total_len},
+        read_val_from_user, write_val_to_user,
+    },
+};
+
+bitflags! {
+    struct SpliceFlags: u32 {
+        /// Moves the pages instead of copying them.
+        const SPLICE_F_MOVE = 1;
+        /// Does not block on the pipes.
+        const SPLICE_F_NONBLOCK = 2;
+        /// More data will be spliced soon.
+        const SPLICE_F_MORE = 4;
+        /// Gifts the user pages to the pipe in `vmsplice`.
+        const SPLICE_F_GIFT = 8;
+    }
+}
+
+pub fn sys_splice(
+    fd_in: FileDesc,
+    offset_in_ptr: Vaddr,
+    fd_out: FileDesc,
+    offset_out_ptr: Vaddr,
+    len: usize,
+    flags: u32,
+) -> Result<SyscallReturn> {
+    debug!(
+        "fd_in = {}, offset_in_ptr = {:#x}, fd_out = {}, offset_out_ptr = {:#x}",
+        fd_in, offset_in_ptr, fd_out, offset_out_ptr
+    );
+    debug!("len = {:#x}, flags = {:#x}", len, flags);
+
+    let flags = SpliceFlags::from_bits(flags)
+        .ok_or_else(|| Error::with_message(Errno::EINVAL, "invalid splice flags"))?;
+    let is_nonblocking = flags.contains(SpliceFlags::SPLICE_F_NONBLOCK);
+
+    let (in_file, out_file) = get_files(fd_in, fd_out)?;
+    if !in_file.access_mode().is_readable() {
+        return_errno_with_message!(Errno::EBADF, "the input file is not readable");
+    }
+    if !out_file.access_mode().is_writable() {
+        return_errno_with_message!(Errno::EBADF, "the output file is not writable");
+    }
+    if out_file.status_flags().contains(StatusFlags::O_APPEND) {
+        return_errno_with_message!(Errno::EINVAL, "the output file is append-only");
+    }
+    let mut offset_in = read_offset_from_user(offset_in_ptr, in_file.as_ref())?;
+    let mut offset_out = read_offset_from_user(offset_out_ptr, out_file.as_ref())?;
+    if len == 0 {
+        return Ok(SyscallReturn::Return(0));
+    }
+    let len = len.min(MAX_RW_COUNT);
+
+    let spliced_len = match (
+        in_file.downcast_ref::<PipeReader>(),
+        out_file.downcast_ref::<PipeWriter>(),
+    ) {
+        (Some(pipe_reader), Some(pipe_writer)) => {
+            pipe_reader.splice_to(pipe_writer, len, is_nonblocking)?
+        }
+        (Some(pipe_reader), None) => pipe_reader.splice_with(len, is_nonblocking, |buf| {
+            let Some(offset) = offset_out.as_mut() else {
+                return out_file.write(buf);
+            };
+            let written_len = out_file.write_at(*offset, buf)?;
+            *offset += written_len;
+            Ok(written_len)
+        })?,
+        (None, Some(pipe_writer)) => pipe_writer.splice_with(len, is_nonblocking, |buf| {
+            let Some(offset) = offset_in.as_mut() else {
+                return in_file.read(buf);
+            };
+            let read_len = in_file.read_at(*offset, buf)?;
+            *offset += read_len;
+            Ok(read_len)
+        })?,
+        (None, None) => return_errno_with_message!(Errno::EINVAL, "neither file is a pipe"),
+    };
+
+    if let Some(offset) = offset_in {
+        write_val_to_user(offset_in_ptr, &(offset as i64))?;
+    }
+    if let Some(offset) = offset_out {
+        write_val_to_user(offset_out_ptr, &(offset as i64))?;
+    }
+    Ok(SyscallReturn::Return(spliced_len as _))
+}
+
+pub fn sys_tee(fd_in: FileDesc, fd_out: FileDesc, len: usize, flags: u32) -> Result<SyscallReturn> {
+    debug!(
+        "fd_in = {}, fd_out = {}, len = {:#x}, flags = {:#x}",
+        fd_in, fd_out, len, flags
+    );
+
+    let flags = SpliceFlags::from_bits(flags)
+        .ok_or_else(|| Error::with_message(Errno::EINVAL, "invalid splice flags"))?;
+
+    let (in_file, out_file) = get_files(fd_in, fd_out)?;
+    let (Some(pipe_reader), Some(pipe_writer)) = (
+        in_file.downcast_ref::<PipeReader>(),
+        out_file.downcast_ref::<PipeWriter>(),
+    ) else {
+        return_errno_with_message!(Errno::EINVAL, "the files are not the ends of pipes");
+    };
+    if len == 0 {
+        return Ok(SyscallReturn::Return(0));
+    }
+
+    let copied_len = pipe_reader.tee_to(
+        pipe_writer,
+        len.min(MAX_RW_COUNT),
+        flags.contains(SpliceFlags::SPLICE_F_NONBLOCK),
+    )?;
+    Ok(SyscallReturn::Return(copied_len as _))
+}
+
+pub fn sys_vmsplice(
+    fd: FileDesc,
+    io_vec_ptr: Vaddr,
+    io_vec_count: usize,
+    flags: u32,
+) -> Result<SyscallReturn> {
+    debug!(
+        "fd = {}, io_vec_ptr = {:#x}, io_vec_count = {}, flags = {:#x}",
+        fd, io_vec_ptr, io_vec_count, flags
+    );
+
+    let flags = SpliceFlags::from_bits(flags)
+        .ok_or_else(|| Error::with_message(Errno::EINVAL, "invalid splice flags"))?;
+    let is_nonblocking = flags.contains(SpliceFlags::SPLICE_F_NONBLOCK);
+
+    let file = {
+        let current = current!();
+        let file_table = current.file_table().lock();
+        file_table.get_file(fd)?.clone()
+    };
+    let io_vecs = read_iovecs_from_user(io_vec_ptr, io_vec_count)?;
+
+    if let Some(pipe_reader) = file.downcast_ref::<PipeReader>() {
+        let len = total_len(&io_vecs).min(MAX_RW_COUNT);
+        if len == 0 {
+            return Ok(SyscallReturn::Return(0));
+        }
+        let read_len =
+            pipe_reader.splice_with(len, is_nonblocking, |buf| scatter_to_user(&io_vecs, buf))?;
+        return Ok(SyscallReturn::Return(read_len as _));
+    }
+
+    let Some(pipe_writer) = file.downcast_ref::<PipeWriter>() else {
+        return_errno_with_message!(Errno::EBADF, "the file is not the end of a pipe");
+    };
+    let data = gather_from_user(&io_vecs)?;
+    let mut written_len = 0;
+    while written_len < data.len() {
+        let res = pipe_writer.splice_with(data.len() - written_len, is_nonblocking, |buf| {
+            buf.copy_from_slice(&data[written_len..written_len + buf.len()]);
+            Ok(buf.len())
+        });
+        match res {
+            Ok(len) => written_len += len,
+            Err(_) if written_len > 0 => break,
+            Err(err) => return Err(err),
+        }
+    }
+    Ok(SyscallReturn::Return(written_len as _))
+}
+
+fn get_files(fd_in: FileDesc, fd_out: FileDesc) -> Result<(Arc<dyn FileLike>, Arc<dyn FileLike>)> {
+    let current = current!();
+    let file_table = current.file_table().lock();
+    let in_file = file_table.get_file(fd_in)?.clone();
+    let out_file = file_table.get_file(fd_out)?.clone();
+    Ok((in_file, out_file))
+}
+
+/// Reads the offset of `file` from the user space, where a null pointer means that the
+/// file offset is used.
+fn read_offset_from_user(offset_ptr: Vaddr, file: &dyn FileLike) -> Result<Option<usize>> {
+    if offset_ptr == 0 {
+        return Ok(None);
+    }
+    // Only the files with the page cache have their own offsets.
+    if file.downcast_ref::<InodeHandle>().is_none() {
+        return_errno_with_message!(Errno::ESPIPE, "the file is not seekable");
+    }
+    let offset: i64 = read_val_from_user(offset_ptr)?;
+    if offset < 0 {
+        return_errno_with_message!(Errno::EINVAL, "the offset is negative");
+    }
+    Ok(Some(offset as usize))
+}
@@ -6,6 +6,7 @@ use crate::{
         file_table::FileDesc,
         fs_resolver::FsPath,
         inode_handle::InodeHandle,
+        path::{Dentry, PerMountFlags},
         utils::{SuperBlock, PATH_MAX},
     },
     prelude::*,
@@ -22,7 +23,7 @@ pub fn sys_statfs(path_ptr: Vaddr, statfs_buf_ptr: Vaddr) -> Result<SyscallRetur
         let fs_path = FsPath::try_from(path.as_ref())?;
         current.fs().read().lookup(&fs_path)?
     };
-    let statfs = Statfs::from(dentry.fs().sb());
+    let statfs = Statfs::from(dentry.as_ref());
     write_val_to_user(statfs_buf_ptr, &statfs)?;
     Ok(SyscallReturn::Return(0))
 }
@@ -36,8 +37,7 @@ pub fn sys_fstatfs(fd: FileDesc, statfs_buf_ptr: Vaddr) -> Result<SyscallReturn>
     let inode_handle = file
         .downcast_ref::<InodeHandle>()
         .ok_or(Error::with_message(Errno::EBADF, "not inode"))?;
-    let dentry = inode_handle.dentry();
-    let statfs = Statfs::from(dentry.fs().sb());
+    let statfs = Statfs::from(inode_handle.dentry().as_ref());
     write_val_to_user(statfs_buf_ptr, &statfs)?;
     Ok(SyscallReturn::Return(0))
 }
@@ -72,8 +72,17 @@ struct Statfs {
     f_spare: [u64; 4],
 }
 
-impl From<SuperBlock> for Statfs {
-    fn from(sb: SuperBlock) -> Self {
+impl From<&Dentry> for Statfs {
+    fn from(dentry: &Dentry) -> Self {
+        let sb = dentry.fs().sb();
+        let mount_flags = StatfsFlags::from(dentry.mount_node().flags());
+        Self::new(sb, mount_flags)
+    }
+}
+
+impl Statfs {
+    fn new(sb: SuperBlock, mount_flags: StatfsFlags) -> Self {
+        let flags = StatfsFlags::from_bits_truncate(sb.flags) | mount_flags | StatfsFlags::ST_VALID;
         Self {
             f_type: sb.magic,
             f_bsize: sb.bsize,
@@ -85,8 +94,44 @@ impl From<SuperBlock> for Statfs {
             f_fsid: sb.fsid,
             f_namelen: sb.namelen,
             f_frsize: sb.frsize,
-            f_flags: sb.flags,
+            f_flags: flags.bits(),
             f_spare: [0u64; 4],
         }
     }
 }
+
+bitflags! {
+    /// The flags reported in `f_flags`.
+    struct StatfsFlags: u64 {
+        const ST_RDONLY      = 1 << 0;
+        const ST_NOSUID      = 1 << 1;
+        const ST_NODEV       = 1 << 2;
+        const ST_NOEXEC      = 1 << 3;
+        const ST_SYNCHRONOUS = 1 << 4;
+        /// `f_flags` is supported.
+        const ST_VALID       = 1 << 5;
+        const ST_MANDLOCK    = 1 << 6;
+        const ST_NOATIME     = 1 << 10;
+        const ST_NODIRATIME  = 1 << 11;
+        const ST_RELATIME    = 1 << 12;
+        const ST_NOSYMFOLLOW = 1 << 13;
+    }
+}
+
+impl From<PerMountFlags> for StatfsFlags {
+    fn from(flags: PerMountFlags) -> Self {
+        [
+            (PerMountFlags::RDONLY, Self::ST_RDONLY),
+            (PerMountFlags::NOSUID, Self::ST_NOSUID),
+            (PerMountFlags::NODEV, Self::ST_NODEV),
+            (PerMountFlags::NOEXEC, Self::ST_NOEXEC),
+            (PerMountFlags::NOSYMFOLLOW, Self::ST_NOSYMFOLLOW),
+            (PerMountFlags::NOATIME, Self::ST_NOATIME),
+            (PerMountFlags::NODIRATIME, Self::ST_NODIRATIME),
+            (PerMountFlags::RELATIME, Self::ST_RELATIME),
+        ]
+        .into_iter()
+        .filter(|(mount_flag, _)| flags.contains(*mount_flag))
+        .fold(Self::empty(), |statfs_flags, (_, flag)| statfs_flags | flag)
+    }
+}
@@ -0,0 +1,81 @@
+// SPDX-License-Identifier: MPL-2.0
+
+use super::SyscallReturn;
+use crate::{
+    fs::{
+        fs_resolver::{FsPath, AT_FDCWD},
+        utils::InodeType,
+    },
+    prelude::*,
+    process::{credentials, credentials::capabilities::CapSet},
+    syscall::constants::MAX_FILENAME_LEN,
+    util::read_cstring_from_user,
+    vm::swap::{self, SwapBackend},
+};
+
+pub fn sys_swapon(path_addr: Vaddr, flags: i32) -> Result<SyscallReturn> {
+    let path = read_cstring_from_user(path_addr, MAX_FILENAME_LEN)?;
+    let flags = flags as u32;
+    let swap_flags = SwapFlags::from_bits(flags & !SWAP_FLAG_PRIO_MASK)
+        .ok_or_else(|| Error::with_message(Errno::EINVAL, "invalid flags"))?;
+    debug!("path = {:?}, flags = {:?}", path, swap_flags);
+
+    check_privilege()?;
+    // The discard flags are accepted but ignored.
+    let priority = swap_flags
+        .contains(SwapFlags::SWAP_FLAG_PREFER)
+        .then_some((flags & SWAP_FLAG_PRIO_MASK) as i16);
+    let (path, backend) = resolve_swap_area(&path.to_string_lossy())?;
+    swap::swap_on(path, backend, priority)?;
+    Ok(SyscallReturn::Return(0))
+}
+
+pub fn sys_swapoff(path_addr: Vaddr) -> Result<SyscallReturn> {
+    let path = read_cstring_from_user(path_addr, MAX_FILENAME_LEN)?;
+    debug!("path = {:?}", path);
+
+    check_privilege()?;
+    let (path, _) = resolve_swap_area(&path.to_string_lossy())?;
+    swap::swap_off(&path)?;
+    Ok(SyscallReturn::Return(0))
+}
+
+fn check_privilege() -> Result<()> {
+    if !credentials().effective_capset().contains(CapSet::SYS_ADMIN) {
+        return_errno_with_message!(Errno::EPERM, "swapping requires CAP_SYS_ADMIN");
+    }
+    Ok(())
+}
+
+/// Resolves the swap area, which is either the name of a block device like the source
+/// of `mount`, or the path of a regular file.
+fn resolve_swap_area(path: &str) -> Result<(String, SwapBackend)> {
+    if let Some(device) = aster_block::get_device(path) {
+        return Ok((path.to_string(), SwapBackend::Partition(device)));
+    }
+    if path.is_empty() {
+        return_errno_with_message!(Errno::ENOENT, "path is empty");
+    }
+
+    let fs_path = FsPath::new(AT_FDCWD, path)?;
+    let dentry = current!().fs().read().lookup(&fs_path)?;
+    if dentry.type_() != InodeType::File {
+        return_errno_with_message!(
+            Errno::EINVAL,
+            "the swap area is neither a block device nor a regular file"
+        );
+    }
+    Ok((dentry.abs_path(), SwapBackend::File(dentry.inode().clone())))
+}
+
+const SWAP_FLAG_PRIO_MASK: u32 = 0x7fff;
+
+bitflags! {
+    struct SwapFlags: u32 {
+        /// Use the priority in the lowest bits.
+        const SWAP_FLAG_PREFER        = 0x8000;
+        const SWAP_FLAG_DISCARD       = 0x10000;
+        const SWAP_FLAG_DISCARD_ONCE  = 0x20000;
+        const SWAP_FLAG_DISCARD_PAGES = 0x40000;
+    }
+}
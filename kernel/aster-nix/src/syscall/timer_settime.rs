@@ -48,7 +48,7 @@ pub fn sys_timer_settime(
         } else if flags == TIMER_ABSTIME {
             Timeout::When(expire_time)
         } else {
-            unreachable!()
+            return_errno_with_message!(Errno::EINVAL, "invalid flags");
         };
         timer.set_timeout(timeout);
     }
@@ -0,0 +1,307 @@
+// SPDX-License-Identifier: MPL-2.0
+
+//! `timerfd_create()` creates a "timerfd object" (we name it as `TimerFile`)
+//! which notifies the expirations of a timer via a file descriptor.
+//!
+//! `TimerFile` holds a u64 counter of the expirations of its timer, which is
+//! armed and disarmed by `timerfd_settime()`. Reading from `TimerFile` returns
+//! the counter and resets it, and blocks until the timer expires if the counter
+//! is zero, unless the file is nonblocking. The file is readable, e.g., to
+//! `poll()` and `epoll_wait()`, once the timer expires.
+//!
+//! For more detailed information about this syscall,
+//! refer to the man 2 timerfd_create documentation.
+
+use core::{
+    sync::atomic::{AtomicU64, Ordering},
+    time::Duration,
+};
+
+use super::{ClockId, SyscallReturn};
+use crate::{
+    events::{IoEvents, Observer},
+    fs::{
+        file_handle::FileLike,
+        file_table::{FdFlags, FileDesc},
+        utils::{CreationFlags, InodeMode, InodeType, Metadata, StatusFlags},
+    },
+    prelude::*,
+    process::{
+        signal::{Pollee, Poller},
+        Gid, Uid,
+    },
+    thread::work_queue::{submit_work_item, work_item::WorkItem, WorkPriority},
+    time::{
+        clockid_t,
+        clocks::{BootTimeClock, MonotonicClock, RealTimeClock},
+        itimerspec_t,
+        timer::Timeout,
+        timespec_t, Timer,
+    },
+    util::{read_val_from_user, write_val_to_user},
+};
+
+pub fn sys_timerfd_create(clockid: clockid_t, flags: u32) -> Result<SyscallReturn> {
+    let flags = Flags::from_bits(flags)
+        .ok_or_else(|| Error::with_message(Errno::EINVAL, "unknown flags"))?;
+    debug!("clockid = {}, flags = {:?}", clockid, flags);
+
+    let timer_file = TimerFile::new(ClockId::try_from(clockid)?, flags)?;
+    let fd = {
+        let current = current!();
+        let mut file_table = current.file_table().lock();
+        let fd_flags = if flags.contains(Flags::TFD_CLOEXEC) {
+            FdFlags::CLOEXEC
+        } else {
+            FdFlags::empty()
+        };
+        file_table.insert(timer_file, fd_flags)
+    };
+
+    Ok(SyscallReturn::Return(fd as _))
+}
+
+pub fn sys_timerfd_settime(
+    fd: FileDesc,
+    flags: u32,
+    new_itimerspec_addr: Vaddr,
+    old_itimerspec_addr: Vaddr,
+) -> Result<SyscallReturn> {
+    let flags = SetTimeFlags::from_bits(flags)
+        .ok_or_else(|| Error::with_message(Errno::EINVAL, "unknown flags"))?;
+    debug!(
+        "fd = {}, flags = {:?}, new_itimerspec_addr = 0x{:x}, old_itimerspec_addr = 0x{:x}",
+        fd, flags, new_itimerspec_addr, old_itimerspec_addr
+    );
+
+    let new_itimerspec = read_val_from_user::<itimerspec_t>(new_itimerspec_addr)?;
+    let interval = duration_from_timespec(new_itimerspec.it_interval)?;
+    let expire_time = duration_from_timespec(new_itimerspec.it_value)?;
+
+    let file = get_file(fd)?;
+    let timer_file = file
+        .downcast_ref::<TimerFile>()
+        .ok_or_else(|| Error::with_message(Errno::EINVAL, "the file is not a timerfd"))?;
+
+    if old_itimerspec_addr != 0 {
+        write_val_to_user(old_itimerspec_addr, &timer_file.itimerspec())?;
+    }
+
+    // TODO: support `TFD_TIMER_CANCEL_ON_SET`, which requires the notifications of the
+    // discontinuous changes of the real time.
+    let timeout = if flags.contains(SetTimeFlags::TFD_TIMER_ABSTIME) {
+        Timeout::When(expire_time)
+    } else {
+        Timeout::After(expire_time)
+    };
+    timer_file.set_timer(interval, (expire_time != Duration::ZERO).then_some(timeout));
+
+    Ok(SyscallReturn::Return(0))
+}
+
+pub fn sys_timerfd_gettime(fd: FileDesc, itimerspec_addr: Vaddr) -> Result<SyscallReturn> {
+    debug!("fd = {}, itimerspec_addr = 0x{:x}", fd, itimerspec_addr);
+
+    let file = get_file(fd)?;
+    let timer_file = file
+        .downcast_ref::<TimerFile>()
+        .ok_or_else(|| Error::with_message(Errno::EINVAL, "the file is not a timerfd"))?;
+    write_val_to_user(itimerspec_addr, &timer_file.itimerspec())?;
+
+    Ok(SyscallReturn::Return(0))
+}
+
+fn get_file(fd: FileDesc) -> Result<Arc<dyn FileLike>> {
+    let current = current!();
+    let file_table = current.file_table().lock();
+    Ok(file_table.get_file(fd)?.clone())
+}
+
+fn duration_from_timespec(timespec: timespec_t) -> Result<Duration> {
+    if timespec.sec < 0 || !(0..1_000_000_000).contains(&timespec.nsec) {
+        return_errno_with_message!(Errno::EINVAL, "the time is invalid");
+    }
+    Ok(Duration::from(timespec))
+}
+
+bitflags! {
+    struct Flags: u32 {
+        const TFD_CLOEXEC = CreationFlags::O_CLOEXEC.bits();
+        const TFD_NONBLOCK = StatusFlags::O_NONBLOCK.bits();
+    }
+}
+
+bitflags! {
+    struct SetTimeFlags: u32 {
+        const TFD_TIMER_ABSTIME = 1;
+        const TFD_TIMER_CANCEL_ON_SET = 2;
+    }
+}
+
+struct TimerFile {
+    timer: Arc<Timer>,
+    /// The number of the expirations that have not been read.
+    expirations: Arc<AtomicU64>,
+    pollee: Pollee,
+    flags: Mutex<Flags>,
+}
+
+impl TimerFile {
+    fn new(clock_id: ClockId, flags: Flags) -> Result<Arc<Self>> {
+        let expirations = Arc::new(AtomicU64::new(0));
+        let pollee = Pollee::new(IoEvents::empty());
+
+        // The timer expires in the timer softirq, where the observers of the pollee cannot
+        // be notified since they may sleep. So the pollee is updated in a work item.
+        let work_item = {
+            let expirations = expirations.clone();
+            let pollee = pollee.clone();
+            Arc::new(WorkItem::new(Box::new(move || {
+                if expirations.load(Ordering::Acquire) != 0 {
+                    pollee.add_events(IoEvents::IN);
+                }
+            })))
+        };
+        let func = {
+            let expirations = expirations.clone();
+            move || {
+                expirations.fetch_add(1, Ordering::AcqRel);
+                submit_work_item(work_item.clone(), WorkPriority::High);
+            }
+        };
+
+        let timer = match clock_id {
+            ClockId::CLOCK_REALTIME => RealTimeClock::timer_manager().create_timer(func),
+            ClockId::CLOCK_MONOTONIC => MonotonicClock::timer_manager().create_timer(func),
+            ClockId::CLOCK_BOOTTIME => BootTimeClock::timer_manager().create_timer(func),
+            _ => return_errno_with_message!(Errno::EINVAL, "the clock is not supported"),
+        };
+
+        Ok(Arc::new(Self {
+            timer,
+            expirations,
+            pollee,
+            flags: Mutex::new(flags),
+        }))
+    }
+
+    fn is_nonblocking(&self) -> bool {
+        self.flags.lock().contains(Flags::TFD_NONBLOCK)
+    }
+
+    /// Arms the timer with `timeout`, or disarms it if `timeout` is `None`.
+    ///
+    /// The expirations that have not been read are discarded.
+    fn set_timer(&self, interval: Duration, timeout: Option<Timeout>) {
+        self.timer.cancel();
+        self.pollee.del_events(IoEvents::IN);
+        self.expirations.store(0, Ordering::Release);
+
+        self.timer.set_interval(interval);
+        if let Some(timeout) = timeout {
+            self.timer.set_timeout(timeout);
+        }
+    }
+
+    fn itimerspec(&self) -> itimerspec_t {
+        itimerspec_t {
+            it_interval: timespec_t::from(self.timer.interval()),
+            it_value: timespec_t::from(self.timer.remain()),
+        }
+    }
+}
+
+impl FileLike for TimerFile {
+    fn read(&self, buf: &mut [u8]) -> Result<usize> {
+        let read_len = core::mem::size_of::<u64>();
+        if buf.len() < read_len {
+            return_errno_with_message!(Errno::EINVAL, "buf len is less len u64 size");
+        }
+
+        loop {
+            // The events are cleared first, so the expirations after the counter is taken
+            // are not missed.
+            self.pollee.del_events(IoEvents::IN);
+            let expirations = self.expirations.swap(0, Ordering::AcqRel);
+            if expirations != 0 {
+                buf[..read_len].copy_from_slice(expirations.as_bytes());
+                return Ok(read_len);
+            }
+
+            if self.is_nonblocking() {
+                return_errno_with_message!(Errno::EAGAIN, "the timer has not expired");
+            }
+
+            let poller = Poller::new();
+            if self.pollee.poll(IoEvents::IN, Some(&poller)).is_empty() {
+                poller.wait()?;
+            }
+        }
+    }
+
+    fn poll(&self, mask: IoEvents, poller: Option<&Poller>) -> IoEvents {
+        self.pollee.poll(mask, poller)
+    }
+
+    fn status_flags(&self) -> StatusFlags {
+        if self.is_nonblocking() {
+            StatusFlags::O_NONBLOCK
+        } else {
+            StatusFlags::empty()
+        }
+    }
+
+    fn set_status_flags(&self, new_flags: StatusFlags) -> Result<()> {
+        let mut flags = self.flags.lock();
+
+        if new_flags.contains(StatusFlags::O_NONBLOCK) {
+            *flags |= Flags::TFD_NONBLOCK;
+        } else {
+            *flags &= !Flags::TFD_NONBLOCK;
+        }
+
+        Ok(())
+    }
+
+    fn register_observer(
+        &self,
+        observer: Weak<dyn Observer<IoEvents>>,
+        mask: IoEvents,
+    ) -> Result<()> {
+        self.pollee.register_observer(observer, mask);
+        Ok(())
+    }
+
+    fn unregister_observer(
+        &self,
+        observer: &Weak<dyn Observer<IoEvents>>,
+    ) -> Option<Weak<dyn Observer<IoEvents>>> {
+        self.pollee.unregister_observer(observer)
+    }
+
+    fn metadata(&self) -> Metadata {
+        let now = RealTimeClock::get().read_time();
+        Metadata {
+            dev: 0,
+            ino: 0,
+            size: 0,
+            blk_size: 0,
+            blocks: 0,
+            atime: now,
+            mtime: now,
+            ctime: now,
+            type_: InodeType::NamedPipe,
+            mode: InodeMode::from_bits_truncate(0o600),
+            nlinks: 1,
+            uid: Uid::new_root(),
+            gid: Gid::new_root(),
+            rdev: 0,
+        }
+    }
+}
+
+impl Drop for TimerFile {
+    fn drop(&mut self) {
+        self.timer.cancel();
+    }
+}
@@ -5,6 +5,7 @@ use crate::{
     fs::{
         file_table::FileDesc,
         fs_resolver::{FsPath, AT_FDCWD},
+        inotify::InotifyEvents,
         utils::PATH_MAX,
     },
     prelude::*,
@@ -40,6 +41,7 @@ pub fn sys_truncate(path_ptr: Vaddr, len: isize) -> Result<SyscallReturn> {
         current.fs().read().lookup(&fs_path)?
     };
     dir_dentry.resize(len as usize)?;
+    dir_dentry.notify(InotifyEvents::IN_MODIFY);
     Ok(SyscallReturn::Return(0))
 }
 
@@ -28,7 +28,7 @@ pub fn sys_umount(path_addr: Vaddr, flags: u64) -> Result<SyscallReturn> {
         current.fs().read().lookup(&fs_path)?
     };
 
-    target_dentry.unmount()?;
+    target_dentry.unmount(umount_flags.contains(UmountFlags::MNT_DETACH))?;
 
     Ok(SyscallReturn::Return(0))
 }
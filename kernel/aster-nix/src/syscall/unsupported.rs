@@ -0,0 +1,87 @@
+// SPDX-License-Identifier: MPL-2.0
+
+//! The records of the syscalls that fail with `ENOSYS` or `EOPNOTSUPP`.
+//!
+//! Such failures mark the gaps between the kernel and Linux that real applications run
+//! into, so they are counted per syscall and per calling binary, and reported at
+//! `/proc/unsupported_syscalls` with the most frequent ones first.
+
+use super::arch::syscall_name;
+use crate::prelude::*;
+
+/// The maximum number of distinct binaries recorded for a syscall. The calls from the
+/// other binaries are still counted, but are not attributed.
+const MAX_BINARIES_PER_SYSCALL: usize = 16;
+
+static UNSUPPORTED_SYSCALLS: SpinLock<BTreeMap<(u64, Errno), UnsupportedSyscall>> =
+    SpinLock::new(BTreeMap::new());
+
+#[derive(Default)]
+struct UnsupportedSyscall {
+    count: u64,
+    /// The number of the calls per executable path.
+    binaries: BTreeMap<String, u64>,
+}
+
+/// Records that the syscall `syscall_number` called by the current process fails with
+/// `errno`, if the error indicates an unsupported feature.
+pub(super) fn record(syscall_number: u64, errno: Errno) {
+    if errno != Errno::ENOSYS && errno != Errno::EOPNOTSUPP {
+        return;
+    }
+
+    let binary = current!().executable_path();
+    let mut syscalls = UNSUPPORTED_SYSCALLS.lock();
+    let syscall = syscalls.entry((syscall_number, errno)).or_default();
+    syscall.count += 1;
+    if let Some(count) = syscall.binaries.get_mut(&binary) {
+        *count += 1;
+    } else if syscall.binaries.len() < MAX_BINARIES_PER_SYSCALL {
+        syscall.binaries.insert(binary, 1);
+    }
+}
+
+/// Returns the report of the unsupported syscalls.
+///
+/// Each line is of the syscall number, the syscall name (`?` if the syscall is not
+/// implemented at all), the error, the number of the calls, and the calling binaries
+/// with their numbers of the calls. The lines are sorted by the numbers of the calls in
+/// the descending order.
+pub fn unsupported_syscalls_report() -> String {
+    let mut lines: Vec<(u64, String)> = UNSUPPORTED_SYSCALLS
+        .lock()
+        .iter()
+        .map(|(&(syscall_number, errno), syscall)| {
+            let binaries = syscall
+                .binaries
+                .iter()
+                .map(|(binary, count)| format!("{}:{}", binary, count))
+                .collect::<Vec<_>>()
+                .join(",");
+            let line = format!(
+                "{}\t{}\t{:?}\t{}\t{}\n",
+                syscall_number,
+                syscall_name(syscall_number)
+                    .map(|name| name.trim_start_matches("SYS_").to_lowercase())
+                    .unwrap_or_else(|| String::from("?")),
+                errno,
+                syscall.count,
+                binaries
+            );
+            (syscall.count, line)
+        })
+        .collect();
+    // The sort is stable, so the syscalls with the same count are ordered by number.
+    lines.sort_by(|(count, _), (other_count, _)| other_count.cmp(count));
+
+    let mut report = String::from("nr\tname\terror\tcount\tbinaries\n");
+    for (_, line) in lines {
+        report.push_str(&line);
+    }
+    report
+}
+
+/// Clears the records of the unsupported syscalls.
+pub fn clear_unsupported_syscalls() {
+    UNSUPPORTED_SYSCALLS.lock().clear();
+}
@@ -0,0 +1,424 @@
+// SPDX-License-Identifier: MPL-2.0
+
+//! `userfaultfd()` creates a file (we name it as `UserfaultFile`) that delegates the
+//! page faults on the missing pages of the registered memory ranges to user space.
+//!
+//! After the API handshake with `UFFDIO_API`, a memory range can be registered with
+//! `UFFDIO_REGISTER`. Accessing a missing page in the range pauses the faulting thread
+//! and a fault message can be read from the file. The handler resolves the fault by
+//! filling the page with `UFFDIO_COPY` or `UFFDIO_ZEROPAGE`, which also wake up the
+//! faulting threads unless the `DONTWAKE` mode is given.
+//!
+//! Only the `MISSING` mode on anonymous mappings is supported, and no API features are
+//! supported. For more detailed information, refer to the man 2 userfaultfd documentation.
+
+use core::{
+    ops::Range,
+    sync::atomic::{AtomicBool, Ordering},
+};
+
+use aster_frame::mm::MAX_USERSPACE_VADDR;
+use aster_rights::Full;
+
+use super::SyscallReturn;
+use crate::{
+    events::{IoEvents, Observer},
+    fs::{
+        file_handle::FileLike,
+        file_table::FdFlags,
+        utils::{CreationFlags, InodeMode, InodeType, IoctlCmd, Metadata, StatusFlags},
+    },
+    prelude::*,
+    process::{signal::Poller, Gid, Uid},
+    time::clocks::RealTimeClock,
+    util::{read_bytes_from_user, read_val_from_user, write_val_to_user},
+    vm::{
+        userfault::{UserfaultCtx, UserfaultEvent},
+        vmar::{is_intersected, Vmar},
+    },
+};
+
+pub fn sys_userfaultfd(flags: u32) -> Result<SyscallReturn> {
+    let flags = Flags::from_bits(flags)
+        .ok_or_else(|| Error::with_message(Errno::EINVAL, "unknown flags"))?;
+    debug!("flags = {:?}", flags);
+
+    let current = current!();
+    let userfault_file = UserfaultFile::new(current.root_vmar().dup()?, flags);
+    let fd_flags = if flags.contains(Flags::O_CLOEXEC) {
+        FdFlags::CLOEXEC
+    } else {
+        FdFlags::empty()
+    };
+    let fd = current
+        .file_table()
+        .lock()
+        .insert(Arc::new(userfault_file), fd_flags);
+    Ok(SyscallReturn::Return(fd as _))
+}
+
+bitflags! {
+    struct Flags: u32 {
+        /// Only handle the page faults from user space. This is always the case for now.
+        const UFFD_USER_MODE_ONLY = 1;
+        const O_CLOEXEC = CreationFlags::O_CLOEXEC.bits();
+        const O_NONBLOCK = StatusFlags::O_NONBLOCK.bits();
+    }
+}
+
+const UFFD_API: u64 = 0xaa;
+
+/// The ioctls that are supported after the API handshake.
+const UFFD_API_IOCTLS: u64 = 1 << 0x00 /* UFFDIO_REGISTER */
+    | 1 << 0x01 /* UFFDIO_UNREGISTER */
+    | 1 << 0x3f /* UFFDIO_API */;
+/// The ioctls that are supported on the registered ranges.
+const UFFD_API_RANGE_IOCTLS: u64 = 1 << 0x02 /* UFFDIO_WAKE */
+    | 1 << 0x03 /* UFFDIO_COPY */
+    | 1 << 0x04 /* UFFDIO_ZEROPAGE */;
+
+const UFFD_EVENT_PAGEFAULT: u8 = 0x12;
+const UFFD_PAGEFAULT_FLAG_WRITE: u64 = 1 << 0;
+
+const UFFDIO_REGISTER_MODE_MISSING: u64 = 1 << 0;
+const UFFDIO_COPY_MODE_DONTWAKE: u64 = 1 << 0;
+const UFFDIO_ZEROPAGE_MODE_DONTWAKE: u64 = 1 << 0;
+
+#[repr(C)]
+#[derive(Debug, Clone, Copy, Pod)]
+struct UffdioApi {
+    api: u64,
+    features: u64,
+    ioctls: u64,
+}
+
+#[repr(C)]
+#[derive(Debug, Clone, Copy, Pod)]
+struct UffdioRange {
+    start: u64,
+    len: u64,
+}
+
+#[repr(C)]
+#[derive(Debug, Clone, Copy, Pod)]
+struct UffdioRegister {
+    range: UffdioRange,
+    mode: u64,
+    ioctls: u64,
+}
+
+#[repr(C)]
+#[derive(Debug, Clone, Copy, Pod)]
+struct UffdioCopy {
+    dst: u64,
+    src: u64,
+    len: u64,
+    mode: u64,
+    copy: i64,
+}
+
+#[repr(C)]
+#[derive(Debug, Clone, Copy, Pod)]
+struct UffdioZeropage {
+    range: UffdioRange,
+    mode: u64,
+    zeropage: i64,
+}
+
+/// The message of a page fault, which is `struct uffd_msg` in Linux.
+#[repr(C)]
+#[derive(Debug, Clone, Copy, Pod)]
+struct UffdMsg {
+    event: u8,
+    reserved1: u8,
+    reserved2: u16,
+    reserved3: u32,
+    flags: u64,
+    address: u64,
+    ptid: u32,
+    reserved4: u32,
+}
+
+impl From<UserfaultEvent> for UffdMsg {
+    fn from(event: UserfaultEvent) -> Self {
+        let flags = if event.is_write {
+            UFFD_PAGEFAULT_FLAG_WRITE
+        } else {
+            0
+        };
+        Self {
+            event: UFFD_EVENT_PAGEFAULT,
+            reserved1: 0,
+            reserved2: 0,
+            reserved3: 0,
+            flags,
+            address: event.address as u64,
+            ptid: 0,
+            reserved4: 0,
+        }
+    }
+}
+
+struct UserfaultFile {
+    ctx: Arc<UserfaultCtx>,
+    /// The root VMAR of the process that creates the file.
+    vmar: Vmar<Full>,
+    flags: Mutex<Flags>,
+    /// Whether the API handshake is done.
+    is_api_done: AtomicBool,
+}
+
+impl UserfaultFile {
+    fn new(vmar: Vmar<Full>, flags: Flags) -> Self {
+        Self {
+            ctx: UserfaultCtx::new(),
+            vmar,
+            flags: Mutex::new(flags),
+            is_api_done: AtomicBool::new(false),
+        }
+    }
+
+    fn is_nonblocking(&self) -> bool {
+        self.flags.lock().contains(Flags::O_NONBLOCK)
+    }
+
+    fn check_api_done(&self) -> Result<()> {
+        if !self.is_api_done.load(Ordering::Acquire) {
+            return_errno_with_message!(Errno::EINVAL, "the API handshake is not done");
+        }
+        Ok(())
+    }
+
+    fn handle_api(&self, arg: Vaddr) -> Result<()> {
+        let mut uffdio_api: UffdioApi = read_val_from_user(arg)?;
+        if uffdio_api.api != UFFD_API || uffdio_api.features != 0 {
+            uffdio_api.features = 0;
+            write_val_to_user(arg, &uffdio_api)?;
+            return_errno_with_message!(Errno::EINVAL, "the API or features are not supported");
+        }
+        if self.is_api_done.swap(true, Ordering::AcqRel) {
+            return_errno_with_message!(Errno::EINVAL, "the API handshake is done already");
+        }
+
+        uffdio_api.ioctls = UFFD_API_IOCTLS;
+        write_val_to_user(arg, &uffdio_api)
+    }
+
+    fn handle_register(&self, arg: Vaddr) -> Result<()> {
+        let mut uffdio_register: UffdioRegister = read_val_from_user(arg)?;
+        if uffdio_register.mode != UFFDIO_REGISTER_MODE_MISSING {
+            return_errno_with_message!(Errno::EINVAL, "only the MISSING mode is supported");
+        }
+        let range = to_vaddr_range(&uffdio_register.range)?;
+        self.vmar.register_userfault(&self.ctx, range)?;
+
+        uffdio_register.ioctls = UFFD_API_RANGE_IOCTLS;
+        write_val_to_user(arg, &uffdio_register)
+    }
+
+    fn handle_unregister(&self, arg: Vaddr) -> Result<()> {
+        let uffdio_range: UffdioRange = read_val_from_user(arg)?;
+        let range = to_vaddr_range(&uffdio_range)?;
+        self.vmar.unregister_userfault(&self.ctx, range)
+    }
+
+    fn handle_wake(&self, arg: Vaddr) -> Result<()> {
+        let uffdio_range: UffdioRange = read_val_from_user(arg)?;
+        let range = to_vaddr_range(&uffdio_range)?;
+        self.ctx.wake(&range);
+        Ok(())
+    }
+
+    fn handle_copy(&self, arg: Vaddr) -> Result<()> {
+        let mut uffdio_copy: UffdioCopy = read_val_from_user(arg)?;
+        if uffdio_copy.mode & !UFFDIO_COPY_MODE_DONTWAKE != 0 {
+            return_errno_with_message!(Errno::EINVAL, "invalid copy mode");
+        }
+        let range = to_vaddr_range(&UffdioRange {
+            start: uffdio_copy.dst,
+            len: uffdio_copy.len,
+        })?;
+        let src = uffdio_copy.src as Vaddr;
+        let src_end = src
+            .checked_add(range.len())
+            .ok_or_else(|| Error::with_message(Errno::EINVAL, "the source range overflows"))?;
+        if is_intersected(&(src..src_end), &range) {
+            return_errno_with_message!(Errno::EINVAL, "invalid source range");
+        }
+
+        let mut buf = vec![0u8; range.len()];
+        read_bytes_from_user(src, &mut buf)?;
+        let should_wake = uffdio_copy.mode & UFFDIO_COPY_MODE_DONTWAKE == 0;
+        let res = self.fill(range.clone(), Some(&buf), should_wake);
+        let res = report_fill_result(res, range.len(), &mut uffdio_copy.copy);
+        write_val_to_user(arg, &uffdio_copy)?;
+        res
+    }
+
+    fn handle_zeropage(&self, arg: Vaddr) -> Result<()> {
+        let mut uffdio_zeropage: UffdioZeropage = read_val_from_user(arg)?;
+        if uffdio_zeropage.mode & !UFFDIO_ZEROPAGE_MODE_DONTWAKE != 0 {
+            return_errno_with_message!(Errno::EINVAL, "invalid zeropage mode");
+        }
+        let range = to_vaddr_range(&uffdio_zeropage.range)?;
+
+        let should_wake = uffdio_zeropage.mode & UFFDIO_ZEROPAGE_MODE_DONTWAKE == 0;
+        let res = self.fill(range.clone(), None, should_wake);
+        let res = report_fill_result(res, range.len(), &mut uffdio_zeropage.zeropage);
+        write_val_to_user(arg, &uffdio_zeropage)?;
+        res
+    }
+
+    /// Fill the missing pages within the range and wake up the faulting threads on the
+    /// filled pages if `should_wake` is true. Returns the number of bytes filled.
+    fn fill(&self, range: Range<Vaddr>, src: Option<&[u8]>, should_wake: bool) -> Result<usize> {
+        let nr_filled = self.vmar.userfault_fill(&self.ctx, range.clone(), src)?;
+        if should_wake {
+            self.ctx.wake(&(range.start..range.start + nr_filled));
+        }
+        Ok(nr_filled)
+    }
+}
+
+/// Reports the result of filling `len` bytes to `report`.
+///
+/// Like Linux, the number of bytes filled is reported, or the negated error number if
+/// nothing is filled. `EAGAIN` is returned if the range is partially filled.
+fn report_fill_result(res: Result<usize>, len: usize, report: &mut i64) -> Result<()> {
+    match res {
+        Ok(nr_filled) => {
+            *report = nr_filled as i64;
+            if nr_filled < len {
+                return_errno_with_message!(Errno::EAGAIN, "the range is partially filled");
+            }
+            Ok(())
+        }
+        Err(err) => {
+            *report = -(err.error() as i64);
+            Err(err)
+        }
+    }
+}
+
+/// Converts the range given by user space to a page-aligned range of virtual addresses.
+fn to_vaddr_range(uffdio_range: &UffdioRange) -> Result<Range<Vaddr>> {
+    let start = uffdio_range.start as Vaddr;
+    let len = uffdio_range.len as usize;
+    if start % PAGE_SIZE != 0 || len % PAGE_SIZE != 0 || len == 0 {
+        return_errno_with_message!(Errno::EINVAL, "the range is not page-aligned or is empty");
+    }
+    let end = start
+        .checked_add(len)
+        .filter(|end| *end <= MAX_USERSPACE_VADDR)
+        .ok_or_else(|| Error::with_message(Errno::EINVAL, "the range is out of user space"))?;
+    Ok(start..end)
+}
+
+impl Drop for UserfaultFile {
+    fn drop(&mut self) {
+        self.ctx.release();
+    }
+}
+
+impl FileLike for UserfaultFile {
+    fn read(&self, buf: &mut [u8]) -> Result<usize> {
+        self.check_api_done()?;
+        let msg_len = core::mem::size_of::<UffdMsg>();
+        if buf.len() < msg_len {
+            return_errno_with_message!(Errno::EINVAL, "buf len is less than the message size");
+        }
+
+        loop {
+            let events = self.ctx.take_events(buf.len() / msg_len);
+            if !events.is_empty() {
+                let read_len = events.len() * msg_len;
+                for (event, msg_buf) in events.into_iter().zip(buf.chunks_exact_mut(msg_len)) {
+                    msg_buf.copy_from_slice(UffdMsg::from(event).as_bytes());
+                }
+                return Ok(read_len);
+            }
+
+            if self.is_nonblocking() {
+                return_errno_with_message!(Errno::EAGAIN, "try reading userfaultfd again");
+            }
+
+            let poller = Poller::new();
+            if self.ctx.poll(IoEvents::IN, Some(&poller)).is_empty() {
+                poller.wait()?;
+            }
+        }
+    }
+
+    fn ioctl(&self, cmd: IoctlCmd, arg: usize) -> Result<i32> {
+        if !matches!(cmd, IoctlCmd::UFFDIO_API) {
+            self.check_api_done()?;
+        }
+        match cmd {
+            IoctlCmd::UFFDIO_API => self.handle_api(arg)?,
+            IoctlCmd::UFFDIO_REGISTER => self.handle_register(arg)?,
+            IoctlCmd::UFFDIO_UNREGISTER => self.handle_unregister(arg)?,
+            IoctlCmd::UFFDIO_WAKE => self.handle_wake(arg)?,
+            IoctlCmd::UFFDIO_COPY => self.handle_copy(arg)?,
+            IoctlCmd::UFFDIO_ZEROPAGE => self.handle_zeropage(arg)?,
+            _ => return_errno_with_message!(Errno::EINVAL, "the ioctl is not supported"),
+        }
+        Ok(0)
+    }
+
+    fn poll(&self, mask: IoEvents, poller: Option<&Poller>) -> IoEvents {
+        self.ctx.poll(mask, poller)
+    }
+
+    fn status_flags(&self) -> StatusFlags {
+        if self.is_nonblocking() {
+            StatusFlags::O_NONBLOCK
+        } else {
+            StatusFlags::empty()
+        }
+    }
+
+    fn set_status_flags(&self, new_flags: StatusFlags) -> Result<()> {
+        let mut flags = self.flags.lock();
+        flags.set(
+            Flags::O_NONBLOCK,
+            new_flags.contains(StatusFlags::O_NONBLOCK),
+        );
+        Ok(())
+    }
+
+    fn register_observer(
+        &self,
+        observer: Weak<dyn Observer<IoEvents>>,
+        mask: IoEvents,
+    ) -> Result<()> {
+        self.ctx.register_observer(observer, mask);
+        Ok(())
+    }
+
+    fn unregister_observer(
+        &self,
+        observer: &Weak<dyn Observer<IoEvents>>,
+    ) -> Option<Weak<dyn Observer<IoEvents>>> {
+        self.ctx.unregister_observer(observer)
+    }
+
+    fn metadata(&self) -> Metadata {
+        let now = RealTimeClock::get().read_time();
+        Metadata {
+            dev: 0,
+            ino: 0,
+            size: 0,
+            blk_size: 0,
+            blocks: 0,
+            atime: now,
+            mtime: now,
+            ctime: now,
+            type_: InodeType::NamedPipe,
+            mode: InodeMode::from_bits_truncate(0o600),
+            nlinks: 1,
+            uid: Uid::new_root(),
+            gid: Gid::new_root(),
+            rdev: 0,
+        }
+    }
+}
@@ -22,17 +22,18 @@ pub fn sys_wait4(
     debug!("wait4 current pid = {}", current!().pid());
     let process_filter = ProcessFilter::from_id(wait_pid as _);
 
-    let waited_process = wait_child_exit(process_filter, wait_options)?;
-    let Some(process) = waited_process else {
+    let wait_status = wait_child_exit(process_filter, wait_options)?;
+    let Some(wait_status) = wait_status else {
         return Ok(SyscallReturn::Return(0 as _));
     };
 
-    let (return_pid, exit_code) = (process.pid(), process.exit_code().unwrap());
+    let (return_pid, exit_code) = (wait_status.pid(), wait_status.status());
     if exit_status_ptr != 0 {
         write_val_to_user(exit_status_ptr as _, &exit_code)?;
     }
 
     if rusage_addr != 0 {
+        let process = wait_status.process();
         let rusage = rusage_t {
             ru_utime: process.prof_clock().user_clock().read_time().into(),
             ru_stime: process.prof_clock().kernel_clock().read_time().into(),
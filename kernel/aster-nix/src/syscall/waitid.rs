@@ -18,7 +18,7 @@ pub fn sys_waitid(
     // FIXME: what does infoq and rusage use for?
     let process_filter = ProcessFilter::from_which_and_id(which, upid);
     let wait_options = WaitOptions::from_bits(options as u32).expect("Unknown wait options");
-    let waited_process = wait_child_exit(process_filter, wait_options)?;
-    let pid = waited_process.map_or(0, |process| process.pid());
+    let wait_status = wait_child_exit(process_filter, wait_options)?;
+    let pid = wait_status.map_or(0, |wait_status| wait_status.pid());
     Ok(SyscallReturn::Return(pid as _))
 }
@@ -3,7 +3,7 @@
 #![allow(dead_code)]
 
 use super::SyscallReturn;
-use crate::{fs::file_table::FileDesc, prelude::*, util::read_bytes_from_user};
+use crate::{fs::file_table::FileDesc, prelude::*, util::read_bytes_from_user_partial};
 
 const STDOUT: u64 = 1;
 const STDERR: u64 = 2;
@@ -25,8 +25,9 @@ pub fn sys_write(fd: FileDesc, user_buf_ptr: Vaddr, user_buf_len: usize) -> Resu
     }
 
     let mut buffer = vec![0u8; user_buf_len];
-    read_bytes_from_user(user_buf_ptr, &mut buffer)?;
-    debug!("write content = {:?}", buffer);
-    let write_len = file.write(&buffer)?;
+    // Only the bytes before the first inaccessible page are written, like in Linux.
+    let copied_len = read_bytes_from_user_partial(user_buf_ptr, &mut buffer)?;
+    debug!("write content = {:?}", &buffer[..copied_len]);
+    let write_len = file.write(&buffer[..copied_len])?;
     Ok(SyscallReturn::Return(write_len as _))
 }
@@ -1,23 +1,12 @@
 // SPDX-License-Identifier: MPL-2.0
 
-#![allow(dead_code)]
-
 use super::SyscallReturn;
 use crate::{
     fs::file_table::FileDesc,
     prelude::*,
-    util::{read_bytes_from_user, read_val_from_user},
+    util::{iovec::read_iovecs_from_user, read_bytes_from_user_partial},
 };
 
-const IOVEC_MAX: usize = 256;
-
-#[repr(C)]
-#[derive(Debug, Clone, Copy, Pod)]
-pub struct IoVec {
-    base: Vaddr,
-    len: usize,
-}
-
 pub fn sys_writev(fd: FileDesc, io_vec_ptr: Vaddr, io_vec_count: usize) -> Result<SyscallReturn> {
     let res = do_sys_writev(fd, io_vec_ptr, io_vec_count)?;
     Ok(SyscallReturn::Return(res as _))
@@ -34,20 +23,21 @@ fn do_sys_writev(fd: FileDesc, io_vec_ptr: Vaddr, io_vec_count: usize) -> Result
         filetable.get_file(fd)?.clone()
     };
     let mut total_len = 0;
-    for i in 0..io_vec_count {
-        let io_vec = read_val_from_user::<IoVec>(io_vec_ptr + i * core::mem::size_of::<IoVec>())?;
-        if io_vec.base == 0 || io_vec.len == 0 {
+    for io_vec in read_iovecs_from_user(io_vec_ptr, io_vec_count)? {
+        if io_vec.is_empty() {
             continue;
         }
-        let buffer = {
-            let base = io_vec.base;
-            let len = io_vec.len;
-            let mut buffer = vec![0u8; len];
-            read_bytes_from_user(base, &mut buffer)?;
-            buffer
+        let mut buffer = vec![0u8; io_vec.len()];
+        let copied_len = match read_bytes_from_user_partial(io_vec.base(), &mut buffer) {
+            Ok(copied_len) => copied_len,
+            Err(_) if total_len > 0 => break,
+            Err(err) => return Err(err),
         };
-        let write_len = file.write(&buffer)?;
+        let write_len = file.write(&buffer[..copied_len])?;
         total_len += write_len;
+        if write_len < io_vec.len() {
+            break;
+        }
     }
     Ok(total_len)
 }
@@ -0,0 +1,302 @@
+// SPDX-License-Identifier: MPL-2.0
+
+//! The syscalls of extended attributes.
+//!
+//! The permission to access an extended attribute depends on its namespace, which is
+//! checked in the same way as Linux without security modules.
+
+use super::SyscallReturn;
+use crate::{
+    fs::{
+        file_table::FileDesc,
+        fs_resolver::{FsPath, AT_FDCWD},
+        inode_handle::InodeHandle,
+        inotify::InotifyEvents,
+        path::Dentry,
+        utils::{
+            posix_acl, Inode, InodeType, Permission, XattrNamespace, XattrSetFlags, PATH_MAX,
+            XATTR_LIST_MAX_LEN, XATTR_NAME_MAX_LEN, XATTR_NAME_POSIX_ACL_ACCESS,
+            XATTR_NAME_POSIX_ACL_DEFAULT, XATTR_VALUE_MAX_LEN,
+        },
+    },
+    prelude::*,
+    process::{credentials, credentials::capabilities::CapSet},
+    util::{read_bytes_from_user, read_cstring_from_user, write_bytes_to_user},
+};
+
+pub fn sys_setxattr(
+    path_ptr: Vaddr,
+    name_ptr: Vaddr,
+    value_ptr: Vaddr,
+    size: usize,
+    flags: i32,
+) -> Result<SyscallReturn> {
+    let dentry = lookup_dentry(path_ptr, true)?;
+    setxattr(&dentry, name_ptr, value_ptr, size, flags)
+}
+
+pub fn sys_lsetxattr(
+    path_ptr: Vaddr,
+    name_ptr: Vaddr,
+    value_ptr: Vaddr,
+    size: usize,
+    flags: i32,
+) -> Result<SyscallReturn> {
+    let dentry = lookup_dentry(path_ptr, false)?;
+    setxattr(&dentry, name_ptr, value_ptr, size, flags)
+}
+
+pub fn sys_fsetxattr(
+    fd: FileDesc,
+    name_ptr: Vaddr,
+    value_ptr: Vaddr,
+    size: usize,
+    flags: i32,
+) -> Result<SyscallReturn> {
+    let dentry = dentry_of_fd(fd)?;
+    setxattr(&dentry, name_ptr, value_ptr, size, flags)
+}
+
+pub fn sys_getxattr(
+    path_ptr: Vaddr,
+    name_ptr: Vaddr,
+    value_ptr: Vaddr,
+    size: usize,
+) -> Result<SyscallReturn> {
+    let dentry = lookup_dentry(path_ptr, true)?;
+    getxattr(&dentry, name_ptr, value_ptr, size)
+}
+
+pub fn sys_lgetxattr(
+    path_ptr: Vaddr,
+    name_ptr: Vaddr,
+    value_ptr: Vaddr,
+    size: usize,
+) -> Result<SyscallReturn> {
+    let dentry = lookup_dentry(path_ptr, false)?;
+    getxattr(&dentry, name_ptr, value_ptr, size)
+}
+
+pub fn sys_fgetxattr(
+    fd: FileDesc,
+    name_ptr: Vaddr,
+    value_ptr: Vaddr,
+    size: usize,
+) -> Result<SyscallReturn> {
+    let dentry = dentry_of_fd(fd)?;
+    getxattr(&dentry, name_ptr, value_ptr, size)
+}
+
+pub fn sys_listxattr(path_ptr: Vaddr, list_ptr: Vaddr, size: usize) -> Result<SyscallReturn> {
+    let dentry = lookup_dentry(path_ptr, true)?;
+    listxattr(&dentry, list_ptr, size)
+}
+
+pub fn sys_llistxattr(path_ptr: Vaddr, list_ptr: Vaddr, size: usize) -> Result<SyscallReturn> {
+    let dentry = lookup_dentry(path_ptr, false)?;
+    listxattr(&dentry, list_ptr, size)
+}
+
+pub fn sys_flistxattr(fd: FileDesc, list_ptr: Vaddr, size: usize) -> Result<SyscallReturn> {
+    let dentry = dentry_of_fd(fd)?;
+    listxattr(&dentry, list_ptr, size)
+}
+
+pub fn sys_removexattr(path_ptr: Vaddr, name_ptr: Vaddr) -> Result<SyscallReturn> {
+    let dentry = lookup_dentry(path_ptr, true)?;
+    removexattr(&dentry, name_ptr)
+}
+
+pub fn sys_lremovexattr(path_ptr: Vaddr, name_ptr: Vaddr) -> Result<SyscallReturn> {
+    let dentry = lookup_dentry(path_ptr, false)?;
+    removexattr(&dentry, name_ptr)
+}
+
+pub fn sys_fremovexattr(fd: FileDesc, name_ptr: Vaddr) -> Result<SyscallReturn> {
+    let dentry = dentry_of_fd(fd)?;
+    removexattr(&dentry, name_ptr)
+}
+
+fn setxattr(
+    dentry: &Dentry,
+    name_ptr: Vaddr,
+    value_ptr: Vaddr,
+    size: usize,
+    flags: i32,
+) -> Result<SyscallReturn> {
+    let flags = XattrSetFlags::from_bits(flags as u32)
+        .ok_or_else(|| Error::with_message(Errno::EINVAL, "invalid flags"))?;
+    let name = read_xattr_name(name_ptr)?;
+    if size > XATTR_VALUE_MAX_LEN {
+        return_errno_with_message!(Errno::E2BIG, "the value is too large");
+    }
+    let mut value = vec![0; size];
+    read_bytes_from_user(value_ptr, &mut value)?;
+    debug!("name = {:?}, size = {}, flags = {:?}", name, size, flags);
+
+    let inode = dentry.inode().as_ref();
+    check_xattr_permission(inode, &name, Permission::MAY_WRITE)?;
+    if is_acl_name(&name) {
+        posix_acl::set_acl(inode, &name, &value, flags)?;
+    } else {
+        inode.set_xattr(&name, &value, flags)?;
+    }
+    dentry.notify(InotifyEvents::IN_ATTRIB);
+    Ok(SyscallReturn::Return(0))
+}
+
+fn getxattr(
+    dentry: &Dentry,
+    name_ptr: Vaddr,
+    value_ptr: Vaddr,
+    size: usize,
+) -> Result<SyscallReturn> {
+    let name = read_xattr_name(name_ptr)?;
+    debug!("name = {:?}, size = {}", name, size);
+
+    let inode = dentry.inode().as_ref();
+    check_xattr_permission(inode, &name, Permission::MAY_READ)?;
+    let value = inode.get_xattr(&name)?;
+    // The size of the value is returned if the size of the buffer is zero.
+    if size != 0 {
+        if value.len() > size {
+            return_errno_with_message!(Errno::ERANGE, "the buffer is too small");
+        }
+        write_bytes_to_user(value_ptr, &value)?;
+    }
+    Ok(SyscallReturn::Return(value.len() as _))
+}
+
+fn listxattr(dentry: &Dentry, list_ptr: Vaddr, size: usize) -> Result<SyscallReturn> {
+    debug!("size = {}", size);
+
+    let names = match dentry.inode().list_xattr() {
+        Ok(names) => names,
+        // Like Linux, the inodes without extended attributes have empty lists.
+        Err(err) if err.error() == Errno::EOPNOTSUPP => Vec::new(),
+        Err(err) => return Err(err),
+    };
+    let is_admin = credentials().effective_capset().contains(CapSet::SYS_ADMIN);
+    let mut list = Vec::new();
+    for name in names {
+        if !is_admin && name.starts_with(XattrNamespace::Trusted.prefix()) {
+            continue;
+        }
+        list.extend_from_slice(name.as_bytes());
+        list.push(0);
+    }
+
+    if size != 0 {
+        let size = size.min(XATTR_LIST_MAX_LEN);
+        if list.len() > size {
+            if size == XATTR_LIST_MAX_LEN {
+                return_errno_with_message!(Errno::E2BIG, "the list is too large");
+            }
+            return_errno_with_message!(Errno::ERANGE, "the buffer is too small");
+        }
+        write_bytes_to_user(list_ptr, &list)?;
+    }
+    Ok(SyscallReturn::Return(list.len() as _))
+}
+
+fn removexattr(dentry: &Dentry, name_ptr: Vaddr) -> Result<SyscallReturn> {
+    let name = read_xattr_name(name_ptr)?;
+    debug!("name = {:?}", name);
+
+    let inode = dentry.inode().as_ref();
+    check_xattr_permission(inode, &name, Permission::MAY_WRITE)?;
+    if is_acl_name(&name) {
+        posix_acl::remove_acl(inode, &name)?;
+    } else {
+        inode.remove_xattr(&name)?;
+    }
+    dentry.notify(InotifyEvents::IN_ATTRIB);
+    Ok(SyscallReturn::Return(0))
+}
+
+/// Checks whether the current thread can access the extended attribute.
+fn check_xattr_permission(inode: &dyn Inode, name: &str, perm: Permission) -> Result<()> {
+    let capset = credentials().effective_capset();
+    let is_write = perm.contains(Permission::MAY_WRITE);
+    match XattrNamespace::of(name)? {
+        XattrNamespace::Trusted => {
+            if !capset.contains(CapSet::SYS_ADMIN) {
+                if is_write {
+                    return_errno_with_message!(
+                        Errno::EPERM,
+                        "the trusted xattrs need CAP_SYS_ADMIN"
+                    );
+                }
+                return_errno_with_message!(Errno::ENODATA, "the trusted xattrs are invisible");
+            }
+        }
+        XattrNamespace::Security => {
+            let cap = if name == "security.capability" {
+                CapSet::SETFCAP
+            } else {
+                CapSet::SYS_ADMIN
+            };
+            if is_write && !capset.contains(cap) {
+                return_errno_with_message!(Errno::EPERM, "the security xattrs cannot be set");
+            }
+        }
+        // The ACLs check the permission by themselves.
+        XattrNamespace::System => {
+            if !is_acl_name(name) {
+                return_errno_with_message!(Errno::EOPNOTSUPP, "the system xattr is not supported");
+            }
+        }
+        XattrNamespace::User => {
+            // Like Linux, the user xattrs are only for regular files and directories, whose
+            // permissions are controlled by the owners.
+            if !matches!(inode.type_(), InodeType::File | InodeType::Dir) {
+                if is_write {
+                    return_errno_with_message!(Errno::EPERM, "the inode cannot have user xattrs");
+                }
+                return_errno_with_message!(Errno::ENODATA, "the inode has no user xattrs");
+            }
+            inode.check_permission(perm)?;
+        }
+    }
+    Ok(())
+}
+
+fn is_acl_name(name: &str) -> bool {
+    name == XATTR_NAME_POSIX_ACL_ACCESS || name == XATTR_NAME_POSIX_ACL_DEFAULT
+}
+
+fn read_xattr_name(name_ptr: Vaddr) -> Result<String> {
+    let name = read_cstring_from_user(name_ptr, XATTR_NAME_MAX_LEN + 1)?;
+    name.into_string()
+        .map_err(|_| Error::with_message(Errno::EINVAL, "the name is not valid UTF-8"))
+}
+
+fn lookup_dentry(path_ptr: Vaddr, follow_tail_link: bool) -> Result<Arc<Dentry>> {
+    let path = read_cstring_from_user(path_ptr, PATH_MAX)?;
+    debug!("path = {:?}", path);
+
+    let path = path.to_string_lossy();
+    if path.is_empty() {
+        return_errno_with_message!(Errno::ENOENT, "path is empty");
+    }
+    let fs_path = FsPath::new(AT_FDCWD, path.as_ref())?;
+    let current = current!();
+    let fs = current.fs().read();
+    if follow_tail_link {
+        fs.lookup(&fs_path)
+    } else {
+        fs.lookup_no_follow(&fs_path)
+    }
+}
+
+fn dentry_of_fd(fd: FileDesc) -> Result<Arc<Dentry>> {
+    debug!("fd = {}", fd);
+
+    let current = current!();
+    let file_table = current.file_table().lock();
+    let file = file_table.get_file(fd)?;
+    let inode_handle = file
+        .downcast_ref::<InodeHandle>()
+        .ok_or_else(|| Error::with_message(Errno::EOPNOTSUPP, "the file is not an inode"))?;
+    Ok(inode_handle.dentry().clone())
+}
@@ -29,6 +29,7 @@ pub fn handle_exception(context: &UserContext) {
 fn handle_page_fault(trap_info: &CpuExceptionInfo) {
     const PAGE_NOT_PRESENT_ERROR_MASK: usize = 0x1 << 0;
     const WRITE_ACCESS_MASK: usize = 0x1 << 1;
+    const PROTECTION_KEY_MASK: usize = 0x1 << 5;
     let page_fault_addr = trap_info.page_fault_addr as Vaddr;
     trace!(
         "page fault error code: 0x{:x}, Page fault address: 0x{:x}",
@@ -37,11 +38,24 @@ fn handle_page_fault(trap_info: &CpuExceptionInfo) {
     );
     let not_present = trap_info.error_code & PAGE_NOT_PRESENT_ERROR_MASK == 0;
     let write = trap_info.error_code & WRITE_ACCESS_MASK != 0;
-    if not_present || write {
+    if trap_info.error_code & PROTECTION_KEY_MASK != 0 {
+        // The access is denied by the protection key of the page, which is not a fault
+        // that the VMAR can resolve.
+        generate_fault_signal(trap_info);
+    } else if not_present || write {
         // If page is not present or due to write access, we should ask the vmar try to commit this page
         let current = current!();
         let root_vmar = current.root_vmar();
-        if let Err(e) = root_vmar.handle_page_fault(page_fault_addr, not_present, write) {
+        let result = root_vmar
+            .handle_page_fault(page_fault_addr, not_present, write)
+            .or_else(|err| {
+                // The missing pages below the user stack are resolved by growing the stack.
+                if !not_present || current.grow_stack(page_fault_addr).is_err() {
+                    return Err(err);
+                }
+                root_vmar.handle_page_fault(page_fault_addr, not_present, write)
+            });
+        if let Err(e) = result {
             error!(
                 "page fault handler failed: addr: 0x{:x}, err: {:?}",
                 page_fault_addr, e
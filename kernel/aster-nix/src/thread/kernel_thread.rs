@@ -2,7 +2,7 @@
 
 use aster_frame::{
     cpu::CpuSet,
-    task::{Priority, TaskOptions},
+    task::{Priority, TaskOptions, KERNEL_STACK_SIZE},
 };
 
 use super::{allocate_tid, status::ThreadStatus, thread_table, Thread};
@@ -46,6 +46,7 @@ impl KernelThreadExt for Thread {
                 .data(weal_thread)
                 .priority(thread_options.priority)
                 .cpu_affinity(thread_options.cpu_affinity)
+                .kernel_stack_size(thread_options.kernel_stack_size)
                 .build()
                 .unwrap();
             let status = ThreadStatus::Init;
@@ -72,6 +73,7 @@ pub struct ThreadOptions {
     func: Option<Box<dyn Fn() + Send + Sync>>,
     priority: Priority,
     cpu_affinity: CpuSet,
+    kernel_stack_size: usize,
 }
 
 impl ThreadOptions {
@@ -84,6 +86,7 @@ impl ThreadOptions {
             func: Some(Box::new(func)),
             priority: Priority::normal(),
             cpu_affinity,
+            kernel_stack_size: KERNEL_STACK_SIZE,
         }
     }
 
@@ -108,4 +111,11 @@ impl ThreadOptions {
         self.cpu_affinity = cpu_affinity;
         self
     }
+
+    /// Sets the size of the kernel stack, e.g., `LARGE_KERNEL_STACK_SIZE` for the threads
+    /// that run deep call chains in file systems or crypto.
+    pub fn kernel_stack_size(mut self, kernel_stack_size: usize) -> Self {
+        self.kernel_stack_size = kernel_stack_size;
+        self
+    }
 }
@@ -4,13 +4,17 @@
 
 use core::sync::atomic::{AtomicU32, Ordering};
 
-use aster_frame::task::Task;
+use aster_frame::{
+    cpu::CpuSet,
+    task::{Priority, SchedPolicy, Task},
+};
 
 use self::status::{AtomicThreadStatus, ThreadStatus};
 use crate::prelude::*;
 
 pub mod exception;
 pub mod kernel_thread;
+pub mod oops;
 pub mod status;
 pub mod task;
 pub mod thread_table;
@@ -71,6 +75,58 @@ impl Thread {
         self.task.run();
     }
 
+    /// Returns the scheduling priority, regardless of the inherited one.
+    pub fn priority(&self) -> Priority {
+        self.task.base_priority()
+    }
+
+    /// Sets the scheduling priority, which takes effect at once.
+    ///
+    /// It fails with `EINVAL` unless the priority is real-time if and only if the
+    /// scheduling policy is real-time, which is checked atomically with the update.
+    pub fn set_priority(&self, priority: Priority) -> Result<()> {
+        self.task.set_priority(priority).map_err(|_| {
+            Error::with_message(Errno::EINVAL, "the priority does not match the policy")
+        })
+    }
+
+    /// Returns the scheduling priority that the thread is scheduled with, which may be
+    /// inherited from a thread blocked on it.
+    pub fn effective_priority(&self) -> Priority {
+        self.task.priority()
+    }
+
+    /// Inherits the scheduling priority of a thread blocked on this thread, or stops
+    /// inheriting any priority if `priority` is `None`.
+    pub fn inherit_priority(&self, priority: Option<Priority>) {
+        self.task.inherit_priority(priority);
+    }
+
+    /// Returns the scheduling policy, regardless of the inherited priority.
+    pub fn sched_policy(&self) -> SchedPolicy {
+        self.task.base_sched_policy()
+    }
+
+    /// Sets the scheduling policy and the scheduling priority, which take effect at once.
+    ///
+    /// It fails with `EINVAL` unless the priority is real-time if and only if the policy is
+    /// real-time.
+    pub fn set_sched_policy(&self, policy: SchedPolicy, priority: Priority) -> Result<()> {
+        self.task.set_sched_policy(policy, priority).map_err(|_| {
+            Error::with_message(Errno::EINVAL, "the priority does not match the policy")
+        })
+    }
+
+    /// Returns the set of the CPUs that the thread can run on.
+    pub fn cpu_affinity(&self) -> CpuSet {
+        self.task.cpu_affinity()
+    }
+
+    /// Sets the set of the CPUs that the thread can run on.
+    pub fn set_cpu_affinity(&self, cpu_affinity: CpuSet) {
+        self.task.set_cpu_affinity(cpu_affinity);
+    }
+
     pub(super) fn exit(&self) {
         self.set_status(ThreadStatus::Exited);
     }
@@ -0,0 +1,150 @@
+// SPDX-License-Identifier: MPL-2.0
+
+//! The oops mode for the recoverable kernel faults in the process context.
+//!
+//! By default, a panic of the kernel halts the whole system. With `kernel.panic_on_oops=0`
+//! on the kernel command line, or after writing `0` to `/proc/sys/kernel/panic_on_oops`,
+//! a panic that occurs when the kernel handles a system call or a CPU exception of a user
+//! task becomes an oops instead. The stack of the task is unwound, the subsystem that
+//! panics is marked as degraded, the kernel is tainted, and the task is killed by
+//! `SIGKILL`. The other tasks keep running, which helps the long-running test campaigns.
+//!
+//! Note that the states protected by the locks that are released during the unwinding
+//! may be left inconsistent, so the degraded subsystems may fail again later.
+
+use core::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+
+use aster_frame::{
+    boot::{self, kcmdline::ModuleArg},
+    panicking::catch_panic,
+};
+
+use crate::{
+    prelude::*,
+    process::signal::{constants::SIGKILL, signals::kernel::KernelSignal},
+};
+
+/// The taint flag that indicates that the kernel has oopsed, which is the same as Linux.
+pub const TAINT_DIE: usize = 1 << 7;
+
+static PANIC_ON_OOPS: AtomicBool = AtomicBool::new(true);
+
+static TAINTED: AtomicUsize = AtomicUsize::new(0);
+
+/// The degraded subsystems and the number of oopses in each of them.
+static DEGRADED_SUBSYSTEMS: SpinLock<BTreeMap<String, usize>> = SpinLock::new(BTreeMap::new());
+
+pub(crate) fn init() {
+    let Some(args) = boot::kernel_cmdline().get_module_args("kernel") else {
+        return;
+    };
+    for arg in args {
+        let ModuleArg::KeyVal(key, value) = arg else {
+            continue;
+        };
+        if key.as_bytes() == b"panic_on_oops" {
+            set_panic_on_oops(value.as_bytes() != b"0");
+        }
+    }
+    if !panic_on_oops() {
+        info!("the kernel panics in the process context are handled as oopses");
+    }
+}
+
+/// Returns whether a kernel panic halts the system rather than being handled as an oops.
+pub fn panic_on_oops() -> bool {
+    PANIC_ON_OOPS.load(Ordering::Relaxed)
+}
+
+pub fn set_panic_on_oops(panic_on_oops: bool) {
+    PANIC_ON_OOPS.store(panic_on_oops, Ordering::Relaxed);
+}
+
+/// Returns the taint flags of the kernel.
+pub fn tainted() -> usize {
+    TAINTED.load(Ordering::Relaxed)
+}
+
+/// Adds the taint flags to the kernel. The flags can never be cleared.
+pub fn add_taint(flags: usize) {
+    TAINTED.fetch_or(flags, Ordering::Relaxed);
+}
+
+/// Returns the degraded subsystems and the number of oopses in each of them.
+pub fn degraded_subsystems() -> Vec<(String, usize)> {
+    DEGRADED_SUBSYSTEMS
+        .lock()
+        .iter()
+        .map(|(name, nr_oopses)| (name.clone(), *nr_oopses))
+        .collect()
+}
+
+/// Calls `f` on behalf of the current user task.
+///
+/// If `f` panics when the oops mode is on, the panic is handled as an oops, which kills
+/// the current process. The caller should check the pending signals afterwards.
+pub(super) fn call_or_oops(f: impl FnOnce()) {
+    if panic_on_oops() {
+        f();
+        return;
+    }
+
+    let Err(info) = catch_panic(f) else {
+        return;
+    };
+
+    let subsystem = subsystem_of(&info.file);
+    error!(
+        "oops in the subsystem `{}`: {} at {}:{}:{}",
+        subsystem, info.message, info.file, info.line, info.col
+    );
+    *DEGRADED_SUBSYSTEMS.lock().entry(subsystem).or_insert(0) += 1;
+    add_taint(TAINT_DIE);
+
+    let current = current!();
+    warn!("the process {} is killed by the oops", current.pid());
+    current.enqueue_signal(KernelSignal::new(SIGKILL));
+}
+
+/// Returns the name of the subsystem that the source file belongs to.
+///
+/// The subsystem is the component for the files in `kernel/comps/`, or the top-level
+/// module for the files in the kernel crate, e.g., `fs` for `kernel/aster-nix/src/fs/`.
+fn subsystem_of(file: &str) -> String {
+    const PREFIXES: [&str; 3] = ["comps/", "aster-nix/src/", "aster-frame/src/"];
+
+    for prefix in PREFIXES {
+        let Some(pos) = file.find(prefix) else {
+            continue;
+        };
+        let rest = &file[pos + prefix.len()..];
+        let name = rest.split('/').next().unwrap_or(rest);
+        let name = name.strip_suffix(".rs").unwrap_or(name);
+        return if prefix == "aster-frame/src/" {
+            format!("frame/{}", name)
+        } else {
+            name.to_string()
+        };
+    }
+    "unknown".to_string()
+}
+
+#[cfg(ktest)]
+mod test {
+    use super::*;
+
+    #[ktest]
+    fn subsystems_of_files() {
+        assert_eq!(
+            subsystem_of("kernel/comps/virtio/src/device/mod.rs"),
+            "virtio"
+        );
+        assert_eq!(subsystem_of("kernel/aster-nix/src/fs/ext2/inode.rs"), "fs");
+        assert_eq!(subsystem_of("kernel/aster-nix/src/taskless.rs"), "taskless");
+        assert_eq!(
+            subsystem_of("framework/aster-frame/src/mm/mod.rs"),
+            "frame/mm"
+        );
+        assert_eq!(subsystem_of("/rustc/library/core/src/option.rs"), "unknown");
+    }
+}
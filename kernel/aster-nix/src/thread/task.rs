@@ -9,9 +9,12 @@ use super::Thread;
 use crate::{
     cpu::LinuxAbi,
     prelude::*,
-    process::{posix_thread::PosixThreadExt, signal::handle_pending_signal},
+    process::{
+        posix_thread::{PosixThreadExt, PtraceStopKind},
+        signal::handle_pending_signal,
+    },
     syscall::handle_syscall,
-    thread::exception::handle_exception,
+    thread::{exception::handle_exception, oops::call_or_oops},
 };
 
 /// create new task with userspace and parent process
@@ -42,21 +45,38 @@ pub fn create_new_user_task(user_space: Arc<UserSpace>, thread_ref: Weak<Thread>
             let return_reason = user_mode.execute(has_kernel_event_fn);
             let context = user_mode.context_mut();
             // handle user event:
-            match return_reason {
-                ReturnReason::UserException => handle_exception(context),
-                ReturnReason::UserSyscall => handle_syscall(context),
-                ReturnReason::KernelEvent => {}
+            let syscall_number = match return_reason {
+                ReturnReason::UserException => {
+                    call_or_oops(|| handle_exception(context));
+                    None
+                }
+                ReturnReason::UserSyscall => {
+                    // A thread traced with `PTRACE_SYSCALL` stops at the entry and the exit
+                    // of the syscall. The tracer may change the syscall at the entry.
+                    let ptrace = posix_thread.ptrace();
+                    if ptrace.traces_syscalls() {
+                        let syscall_number = context.syscall_num();
+                        ptrace.stop(PtraceStopKind::SyscallEntry, context, Some(syscall_number));
+                    }
+                    let syscall_number = context.syscall_num();
+                    call_or_oops(|| handle_syscall(context));
+                    if ptrace.traces_syscalls() {
+                        ptrace.stop(PtraceStopKind::SyscallExit, context, Some(syscall_number));
+                    }
+                    Some(syscall_number)
+                }
+                ReturnReason::KernelEvent => None,
             };
 
             if current_thread.status().is_exited() {
                 break;
             }
-            handle_pending_signal(context, &current_thread).unwrap();
+            handle_pending_signal(context, &current_thread, syscall_number).unwrap();
             // If current is suspended, wait for a signal to wake up self
             while current_thread.status().is_stopped() {
                 Thread::yield_now();
                 debug!("{} is suspended.", current_thread.tid());
-                handle_pending_signal(context, &current_thread).unwrap();
+                handle_pending_signal(context, &current_thread, None).unwrap();
             }
             if current_thread.status().is_exited() {
                 debug!("exit due to signal");
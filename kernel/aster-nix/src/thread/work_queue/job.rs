@@ -0,0 +1,242 @@
+// SPDX-License-Identifier: MPL-2.0
+
+//! CPU-bound jobs offloaded to the global work queues.
+//!
+//! Transforming large buffers, e.g., encrypting, verifying or compressing the data of
+//! large I/O requests, can take a long time. Instead of doing the work inline, possibly
+//! while holding locks, the data can be split into chunks and a job can be submitted for
+//! each chunk. The jobs run concurrently in the workers, and the submitter gets the
+//! results either by waiting on a [`JobHandle`] or by a callback.
+//!
+//! # Examples
+//!
+//! ```rust
+//! use crate::thread::work_queue::{job::{self, ScopeExt}, WorkPriority};
+//!
+//! let handle = job::submit_job(|| checksum(&data), WorkPriority::Normal);
+//! // Do something else ...
+//! let sum = handle.wait();
+//!
+//! // The jobs in a scope can borrow the data on the stack.
+//! let mut chunks = [[0u8; 4096]; 4];
+//! job::scope(|s| {
+//!     for chunk in chunks.iter_mut() {
+//!         s.submit(move || encrypt(chunk), WorkPriority::Normal);
+//!     }
+//! });
+//! ```
+
+use core::marker::PhantomData;
+
+use aster_frame::sync::WaitQueue;
+pub use aster_frame::task::{scope, Scope};
+
+use super::{submit_work_item, work_item::WorkItem, WorkPriority};
+use crate::prelude::*;
+
+/// Submits a job to a global work queue.
+///
+/// The returned handle can be used to wait for the result of the job.
+pub fn submit_job<F, T>(job: F, work_priority: WorkPriority) -> JobHandle<T>
+where
+    F: FnOnce() -> T + Send + 'static,
+    T: Send + 'static,
+{
+    let completion = Arc::new(Completion::new());
+    let job_completion = completion.clone();
+    submit_once(move || job_completion.complete(job()), work_priority);
+    JobHandle { completion }
+}
+
+/// Submits a job to a global work queue, and `callback` is called with the result
+/// of the job in the worker once the job is done.
+pub fn submit_job_with_callback<F, C, T>(job: F, callback: C, work_priority: WorkPriority)
+where
+    F: FnOnce() -> T + Send + 'static,
+    C: FnOnce(T) + Send + 'static,
+{
+    submit_once(move || callback(job()), work_priority);
+}
+
+/// Submits the jobs in a [`Scope`], which is created by [`scope`].
+///
+/// All the jobs submitted in a scope are done before [`scope`] returns or unwinds, so
+/// they can borrow the non-`'static` data outside the scope.
+pub trait ScopeExt<'scope> {
+    /// Submits a job to a global work queue, which may borrow the data outliving the scope.
+    fn submit<F, T>(
+        &'scope self,
+        job: F,
+        work_priority: WorkPriority,
+    ) -> ScopedJobHandle<'scope, T>
+    where
+        F: FnOnce() -> T + Send + 'scope,
+        T: Send + 'scope;
+}
+
+impl<'scope, 'env> ScopeExt<'scope> for Scope<'scope, 'env> {
+    fn submit<F, T>(&'scope self, job: F, work_priority: WorkPriority) -> ScopedJobHandle<'scope, T>
+    where
+        F: FnOnce() -> T + Send + 'scope,
+        T: Send + 'scope,
+    {
+        let completion = Arc::new(Completion::new());
+        let job_completion = completion.clone();
+        self.spawn(
+            move || job_completion.complete(job()),
+            |func| submit_once(func, work_priority),
+        );
+
+        ScopedJobHandle {
+            completion,
+            scope: PhantomData,
+        }
+    }
+}
+
+/// A handle to wait for the result of a job.
+pub struct JobHandle<T> {
+    completion: Arc<Completion<T>>,
+}
+
+impl<T> JobHandle<T> {
+    /// Returns whether the job is done.
+    pub fn is_done(&self) -> bool {
+        self.completion.is_done()
+    }
+
+    /// Waits until the job is done and returns its result.
+    pub fn wait(self) -> T {
+        self.completion.wait()
+    }
+}
+
+/// A handle to wait for the result of a job submitted in a [`Scope`].
+pub struct ScopedJobHandle<'scope, T> {
+    completion: Arc<Completion<T>>,
+    scope: PhantomData<&'scope ()>,
+}
+
+impl<'scope, T> ScopedJobHandle<'scope, T> {
+    /// Returns whether the job is done.
+    pub fn is_done(&self) -> bool {
+        self.completion.is_done()
+    }
+
+    /// Waits until the job is done and returns its result.
+    pub fn wait(self) -> T {
+        self.completion.wait()
+    }
+}
+
+struct Completion<T> {
+    result: SpinLock<Option<T>>,
+    wait_queue: WaitQueue,
+}
+
+impl<T> Completion<T> {
+    fn new() -> Self {
+        Self {
+            result: SpinLock::new(None),
+            wait_queue: WaitQueue::new(),
+        }
+    }
+
+    fn complete(&self, result: T) {
+        *self.result.lock() = Some(result);
+        self.wait_queue.wake_all();
+    }
+
+    fn is_done(&self) -> bool {
+        self.result.lock().is_some()
+    }
+
+    fn wait(&self) -> T {
+        self.wait_queue.wait_until(|| self.result.lock().take())
+    }
+}
+
+/// Submits a function that is called only once to a global work queue.
+fn submit_once<F>(func: F, work_priority: WorkPriority)
+where
+    F: FnOnce() + Send + 'static,
+{
+    let func = SpinLock::new(Some(func));
+    let work_item = Arc::new(WorkItem::new(Box::new(move || {
+        let func = func.lock().take();
+        if let Some(func) = func {
+            func();
+        }
+    })));
+    // A newly created work item cannot be pending, so it is always enqueued.
+    submit_work_item(work_item, work_priority);
+}
+
+#[cfg(ktest)]
+mod test {
+    use core::sync::atomic::{AtomicBool, Ordering};
+
+    use aster_frame::{panicking::catch_panic, task::Task};
+
+    use super::*;
+
+    fn init() {
+        static DONE: AtomicBool = AtomicBool::new(false);
+        if !DONE.load(Ordering::SeqCst) {
+            super::super::init();
+            DONE.store(true, Ordering::SeqCst);
+        }
+    }
+
+    #[ktest]
+    fn scoped_jobs_borrow_stack() {
+        init();
+        let mut chunks = [[0u8; 256]; 4];
+        let sums = scope(|s| {
+            let handles: Vec<_> = chunks
+                .iter_mut()
+                .enumerate()
+                .map(|(i, chunk)| {
+                    s.submit(
+                        move || {
+                            chunk.fill(i as u8 + 1);
+                            chunk.iter().map(|byte| *byte as usize).sum::<usize>()
+                        },
+                        WorkPriority::Normal,
+                    )
+                })
+                .collect();
+            handles
+                .into_iter()
+                .map(ScopedJobHandle::wait)
+                .collect::<Vec<_>>()
+        });
+        for (i, (chunk, sum)) in chunks.iter().zip(sums).enumerate() {
+            assert!(chunk.iter().all(|byte| *byte == i as u8 + 1));
+            assert_eq!(sum, (i + 1) * 256);
+        }
+    }
+
+    #[ktest]
+    fn scope_joins_when_panicking() {
+        init();
+        let is_done = AtomicBool::new(false);
+        let result = catch_panic(|| {
+            scope(|s| {
+                s.submit(
+                    || {
+                        for _ in 0..10 {
+                            Task::yield_now();
+                        }
+                        is_done.store(true, Ordering::Relaxed);
+                    },
+                    WorkPriority::Normal,
+                );
+                panic!("the scope panics");
+            })
+        });
+        assert!(result.is_err());
+        // The job borrowing `is_done` has finished before the panic is caught.
+        assert!(is_done.load(Ordering::Relaxed));
+    }
+}
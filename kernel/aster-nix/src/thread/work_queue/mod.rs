@@ -9,6 +9,7 @@ use worker_pool::WorkerPool;
 
 use crate::prelude::*;
 
+pub mod job;
 mod simple_scheduler;
 pub mod work_item;
 pub mod worker;
@@ -2,7 +2,10 @@
 
 #![allow(dead_code)]
 
-use aster_frame::{cpu::CpuSet, task::Priority};
+use aster_frame::{
+    cpu::CpuSet,
+    task::{Priority, LARGE_KERNEL_STACK_SIZE},
+};
 
 use super::worker_pool::WorkerPool;
 use crate::{
@@ -54,7 +57,9 @@ impl Worker {
             let bound_thread = Thread::new_kernel_thread(
                 ThreadOptions::new(task_fn)
                     .cpu_affinity(cpu_affinity)
-                    .priority(priority),
+                    .priority(priority)
+                    // The work items may run deep call chains, e.g., in file systems.
+                    .kernel_stack_size(LARGE_KERNEL_STACK_SIZE),
             );
             Self {
                 worker_pool,
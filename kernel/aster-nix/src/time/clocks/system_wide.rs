@@ -3,13 +3,19 @@
 use alloc::sync::Arc;
 use core::time::Duration;
 
-use aster_frame::{arch::timer::Jiffies, cpu_local, sync::SpinLock, CpuLocal};
-use aster_time::read_monotonic_time;
+use aster_frame::{
+    arch::timer::{HrTimer, Jiffies},
+    sync::SpinLock,
+};
 use paste::paste;
 use spin::Once;
 
 use crate::time::{
-    self, system_time::START_TIME_AS_DURATION, timer::TimerManager, Clock, SystemTime,
+    self,
+    system_time::START_TIME_AS_DURATION,
+    timer::TimerManager,
+    virtual_time::{self, read_monotonic_time},
+    Clock, SystemTime,
 };
 
 /// The Clock that reads the jiffies, and turn the counter into `Duration`.
@@ -17,6 +23,20 @@ pub struct JiffiesClock {
     _private: (),
 }
 
+impl JiffiesClock {
+    /// Returns the time elapsed since the system boots up.
+    ///
+    /// Unlike [`Jiffies::elapsed`], this follows the virtual time in the virtual time
+    /// mode, so the kernel should measure the timeouts with this method.
+    pub fn elapsed() -> Duration {
+        if virtual_time::is_enabled() {
+            virtual_time::now()
+        } else {
+            Jiffies::elapsed().as_duration()
+        }
+    }
+}
+
 /// `RealTimeClock` represents a clock that provides the current real time.
 pub struct RealTimeClock {
     _private: (),
@@ -28,7 +48,7 @@ impl RealTimeClock {
         CLOCK_REALTIME_INSTANCE.get().unwrap()
     }
 
-    /// Get the cpu-local system-wide `TimerManager` singleton of this clock.
+    /// Get the system-wide `TimerManager` singleton of this clock.
     pub fn timer_manager() -> &'static Arc<TimerManager> {
         CLOCK_REALTIME_MANAGER.get().unwrap()
     }
@@ -46,7 +66,7 @@ impl MonotonicClock {
         CLOCK_MONOTONIC_INSTANCE.get().unwrap()
     }
 
-    /// Get the cpu-local system-wide `TimerManager` singleton of this clock.
+    /// Get the system-wide `TimerManager` singleton of this clock.
     pub fn timer_manager() -> &'static Arc<TimerManager> {
         CLOCK_MONOTONIC_MANAGER.get().unwrap()
     }
@@ -125,7 +145,7 @@ impl BootTimeClock {
         CLOCK_BOOTTIME_INSTANCE.get().unwrap()
     }
 
-    /// Get the cpu-local system-wide `TimerManager` singleton of this clock.
+    /// Get the system-wide `TimerManager` singleton of this clock.
     pub fn timer_manager() -> &'static Arc<TimerManager> {
         CLOCK_BOOTTIME_MANAGER.get().unwrap()
     }
@@ -133,7 +153,7 @@ impl BootTimeClock {
 
 impl Clock for JiffiesClock {
     fn read_time(&self) -> Duration {
-        Jiffies::elapsed().as_duration()
+        Self::elapsed()
     }
 }
 
@@ -204,9 +224,7 @@ macro_rules! define_timer_managers {
     ($($clock_id:ident,)*) => {
         $(
             paste! {
-                cpu_local! {
-                    pub static [<$clock_id _MANAGER>]: Once<Arc<TimerManager>> = Once::new();
-                }
+                pub static [<$clock_id _MANAGER>]: Once<Arc<TimerManager>> = Once::new();
             }
         )*
 
@@ -214,11 +232,15 @@ macro_rules! define_timer_managers {
             $(
                 let clock = paste! {[<$clock_id _INSTANCE>].get().unwrap().clone()};
                 let clock_manager = TimerManager::new(clock);
+                // The virtual time only advances on demand, so the timers should not expire
+                // with the hardware timer.
+                if !virtual_time::is_enabled() {
+                    clock_manager.set_hrtimer(HrTimer::new(time::softirq::raise));
+                }
                 paste! {
-                    CpuLocal::borrow_with(&[<$clock_id _MANAGER>], |manager| {
-                        manager.call_once(|| clock_manager.clone());
-                    });
+                    [<$clock_id _MANAGER>].call_once(|| clock_manager.clone());
                 }
+                virtual_time::register_timer_manager(clock_manager.clone());
                 let callback = move || {
                     clock_manager.process_expired_timers();
                 };
@@ -244,7 +266,7 @@ fn init_system_wide_clocks() {
     _init_system_wide_clocks();
 }
 
-/// Init the system-wide cpu-local [`TimerManager`]s.
+/// Init the system-wide [`TimerManager`]s.
 fn init_system_wide_timer_managers() {
     _init_system_wide_timer_managers();
 }
@@ -256,6 +278,7 @@ fn init_jiffies_clock_manager() {
     let jiffies_clock = JiffiesClock { _private: () };
     let jiffies_timer_manager = TimerManager::new(Arc::new(jiffies_clock));
     JIFFIES_TIMER_MANAGER.call_once(|| jiffies_timer_manager.clone());
+    virtual_time::register_timer_manager(jiffies_timer_manager.clone());
 
     let callback = move || {
         jiffies_timer_manager.process_expired_timers();
@@ -283,7 +306,7 @@ pub(super) fn init() {
 }
 
 #[cfg(ktest)]
-/// Init `CLOCK_REALTIME_MANAGER` for process-related ktests.
+/// Init `CLOCK_REALTIME_MANAGER` and `CLOCK_MONOTONIC_MANAGER` for process-related ktests.
 ///
 /// TODO: `ktest` may require a feature that allows the registration of initialization functions
 /// to avoid functions like this one.
@@ -293,6 +316,10 @@ pub fn init_for_ktest() {
         let clock = RealTimeClock { _private: () };
         TimerManager::new(Arc::new(clock))
     });
+    CLOCK_MONOTONIC_MANAGER.call_once(|| {
+        let clock = MonotonicClock { _private: () };
+        TimerManager::new(Arc::new(clock))
+    });
     CLOCK_REALTIME_COARSE_INSTANCE.call_once(|| Arc::new(RealTimeCoarseClock { _private: () }));
     RealTimeCoarseClock::current_ref().call_once(|| SpinLock::new(Duration::from_secs(0)));
     JIFFIES_TIMER_MANAGER.call_once(|| {
@@ -11,7 +11,8 @@ use core::{
     time::Duration,
 };
 
-use aster_frame::sync::SpinLock;
+use aster_frame::{arch::timer::HrTimer, sync::SpinLock};
+use spin::Once;
 
 use super::Clock;
 
@@ -62,10 +63,13 @@ impl Timer {
 
     /// Cancel the current timer's set timeout callback.
     pub fn cancel(&self) {
-        let timer_callback = self.timer_callback.lock_irq_disabled();
+        let mut timer_callback = self.timer_callback.lock_irq_disabled();
         if let Some(timer_callback) = timer_callback.upgrade() {
             timer_callback.cancel();
         }
+        // The cancelled callback may stay in the manager for a while, so it is forgotten to
+        // stop reporting its remaining time.
+        *timer_callback = Weak::default();
     }
 
     /// Set the timer with a timeout.
@@ -148,6 +152,8 @@ fn interval_timer_callback(timer: &Weak<Timer>) {
 pub struct TimerManager {
     clock: Arc<dyn Clock>,
     timer_callbacks: SpinLock<BinaryHeap<Arc<TimerCallback>>>,
+    /// The high-resolution timer that is set to expire with the earliest managed timer.
+    hrtimer: Once<Arc<HrTimer>>,
 }
 
 impl TimerManager {
@@ -156,13 +162,31 @@ impl TimerManager {
         Arc::new(Self {
             clock,
             timer_callbacks: SpinLock::new(BinaryHeap::new()),
+            hrtimer: Once::new(),
         })
     }
 
+    /// Makes the managed timers expire precisely with `hrtimer`, rather than at the ticks.
+    ///
+    /// The `hrtimer` is set to expire when the earliest managed timer expires, and its
+    /// callback should get [`Self::process_expired_timers`] called.
+    pub fn set_hrtimer(&self, hrtimer: Arc<HrTimer>) {
+        self.hrtimer.call_once(|| hrtimer);
+    }
+
     fn insert(&self, timer_callback: Arc<TimerCallback>) {
-        self.timer_callbacks
-            .lock_irq_disabled()
-            .push(timer_callback);
+        let mut timeout_list = self.timer_callbacks.lock_irq_disabled();
+        let expired_time = timer_callback.expired_time;
+        timeout_list.push(timer_callback);
+        if timeout_list.peek().unwrap().expired_time == expired_time {
+            self.set_hrtimer_timeout(expired_time);
+        }
+    }
+
+    fn set_hrtimer_timeout(&self, expired_time: Duration) {
+        if let Some(hrtimer) = self.hrtimer.get() {
+            hrtimer.set_timeout(expired_time.saturating_sub(self.clock.read_time()));
+        }
     }
 
     /// Check the managed timers, and if any have timed out,
@@ -183,6 +207,7 @@ impl TimerManager {
                 } else if t.expired_time <= current_time {
                     callbacks.push(timeout_list.pop().unwrap());
                 } else {
+                    self.set_hrtimer_timeout(t.expired_time);
                     break;
                 }
             }
@@ -194,6 +219,17 @@ impl TimerManager {
         }
     }
 
+    /// Returns the time until the earliest managed timer expires, or `None` if there
+    /// are no timers.
+    pub fn next_timeout(&self) -> Option<Duration> {
+        let mut timeout_list = self.timer_callbacks.lock_irq_disabled();
+        while timeout_list.peek()?.is_cancelled() {
+            timeout_list.pop();
+        }
+        let expired_time = timeout_list.peek()?.expired_time;
+        Some(expired_time.saturating_sub(self.clock.read_time()))
+    }
+
     /// Create an [`Timer`], which will be managed by this `TimerManager`.
     pub fn create_timer<F>(self: &Arc<Self>, function: F) -> Arc<Timer>
     where
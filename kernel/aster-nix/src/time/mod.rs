@@ -14,6 +14,7 @@ pub mod clocks;
 mod core;
 mod softirq;
 mod system_time;
+pub mod virtual_time;
 pub mod wait;
 
 pub type clockid_t = i32;
@@ -22,6 +23,7 @@ pub type suseconds_t = i64;
 pub type clock_t = i64;
 
 pub(super) fn init() {
+    virtual_time::init();
     system_time::init();
     clocks::init();
     softirq::init();
@@ -11,9 +11,12 @@ static TIMER_SOFTIRQ_CALLBACKS: RwLock<Vec<Box<dyn Fn() + Sync + Send>>> = RwLoc
 pub(super) fn init() {
     SoftIrqLine::get(TIMER_SOFTIRQ_ID).enable(timer_softirq_handler);
 
-    timer::register_callback(|| {
-        SoftIrqLine::get(TIMER_SOFTIRQ_ID).raise();
-    });
+    timer::register_callback(raise);
+}
+
+/// Raises the timer softirq, which executes the registered functions soon.
+pub(super) fn raise() {
+    SoftIrqLine::get(TIMER_SOFTIRQ_ID).raise();
 }
 
 /// Registers a function that will be executed during timer softirq.
@@ -26,7 +29,8 @@ where
         .push(Box::new(func));
 }
 
-fn timer_softirq_handler() {
+/// Executes the functions registered to the timer softirq.
+pub(super) fn timer_softirq_handler() {
     let callbacks = TIMER_SOFTIRQ_CALLBACKS.read_irq_disabled();
     for callback in callbacks.iter() {
         (callback)();
@@ -2,10 +2,11 @@
 
 use core::time::Duration;
 
-use aster_time::{read_monotonic_time, read_start_time};
+use aster_time::read_start_time;
 use spin::Once;
 use time::{Date, Month, PrimitiveDateTime, Time};
 
+use super::virtual_time::read_monotonic_time;
 use crate::prelude::*;
 
 /// This struct corresponds to `SystemTime` in Rust std.
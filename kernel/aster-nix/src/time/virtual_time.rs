@@ -0,0 +1,112 @@
+// SPDX-License-Identifier: MPL-2.0
+
+//! The virtual time mode for reproducible tests.
+//!
+//! With `time.virtual` on the kernel command line, the clocks do not follow the hardware.
+//! Instead, the monotonic time starts from zero and only advances when the test harness
+//! writes a number of nanoseconds to `/proc/sys/kernel/virtual_time_ns`. The real time
+//! is the time at boot plus the virtual monotonic time.
+//!
+//! The timers are driven by the virtual time as well. When the time is advanced, it stops
+//! at the expired time of each timer on the way and fires the timer there, in the order of
+//! the expired time. So a timeout, e.g., of a network connection or of a sleep, always
+//! expires at the same point of a test no matter how fast the host runs it.
+//!
+//! Note that the preemption of the tasks is still driven by the hardware timer, since the
+//! scheduler counts the timer ticks rather than reading the clocks.
+
+use core::{
+    sync::atomic::{AtomicBool, AtomicU64, Ordering},
+    time::Duration,
+};
+
+use aster_frame::boot::{self, kcmdline::ModuleArg};
+
+use super::{softirq, timer::TimerManager};
+use crate::prelude::*;
+
+static IS_ENABLED: AtomicBool = AtomicBool::new(false);
+
+/// The virtual monotonic time, in nanoseconds.
+static NOW_NANOS: AtomicU64 = AtomicU64::new(0);
+
+/// The timer managers of the system-wide clocks, whose timers fire on the virtual time.
+static TIMER_MANAGERS: SpinLock<Vec<Arc<TimerManager>>> = SpinLock::new(Vec::new());
+
+/// Serializes the advances of the virtual time.
+static ADVANCE_LOCK: Mutex<()> = Mutex::new(());
+
+pub(super) fn init() {
+    let Some(args) = boot::kernel_cmdline().get_module_args("time") else {
+        return;
+    };
+    let is_enabled = args
+        .iter()
+        .any(|arg| matches!(arg, ModuleArg::Arg(arg) if arg.as_bytes() == b"virtual"));
+    if is_enabled {
+        IS_ENABLED.store(true, Ordering::Relaxed);
+        info!("the virtual time mode is enabled");
+    }
+}
+
+/// Returns whether the virtual time mode is enabled.
+pub fn is_enabled() -> bool {
+    IS_ENABLED.load(Ordering::Relaxed)
+}
+
+/// Returns the virtual monotonic time.
+pub fn now() -> Duration {
+    Duration::from_nanos(NOW_NANOS.load(Ordering::Acquire))
+}
+
+/// Returns the monotonic time, which is the virtual time in the virtual time mode.
+pub(super) fn read_monotonic_time() -> Duration {
+    if is_enabled() {
+        now()
+    } else {
+        aster_time::read_monotonic_time()
+    }
+}
+
+pub(super) fn register_timer_manager(timer_manager: Arc<TimerManager>) {
+    TIMER_MANAGERS.lock_irq_disabled().push(timer_manager);
+}
+
+/// Advances the virtual time by `delta`, and fires the timers that expire meanwhile.
+///
+/// The timers fire in the order of their expired time, each at the virtual time when it
+/// expires, before this method returns.
+pub fn advance(delta: Duration) -> Result<()> {
+    if !is_enabled() {
+        return_errno_with_message!(Errno::EPERM, "the virtual time mode is not enabled");
+    }
+
+    let _guard = ADVANCE_LOCK.lock();
+    let target = now()
+        .checked_add(delta)
+        .filter(|target| target.as_nanos() <= u64::MAX as u128)
+        .ok_or_else(|| Error::with_message(Errno::EINVAL, "the virtual time overflows"))?;
+    loop {
+        // The same as what the timer interrupts do on the real time.
+        softirq::timer_softirq_handler();
+
+        let now = now();
+        if now >= target {
+            break;
+        }
+        let step = next_timeout().map_or(target - now, |timeout| timeout.min(target - now));
+        // Make progress even if some timers are set to expire immediately again and again.
+        let step = step.max(Duration::from_nanos(1));
+        NOW_NANOS.store((now + step).as_nanos() as u64, Ordering::Release);
+    }
+    Ok(())
+}
+
+/// Returns the time until the earliest timer expires.
+fn next_timeout() -> Option<Duration> {
+    let timer_managers = TIMER_MANAGERS.lock_irq_disabled().clone();
+    timer_managers
+        .iter()
+        .filter_map(|timer_manager| timer_manager.next_timeout())
+        .min()
+}
@@ -4,7 +4,7 @@ use core::time::Duration;
 
 use aster_frame::sync::{WaitQueue, Waiter};
 
-use super::{clocks::JIFFIES_TIMER_MANAGER, timer::Timeout};
+use super::{clocks::MonotonicClock, timer::Timeout};
 
 /// A trait that provide the timeout related function for [`WaitQueue`]`.
 pub trait WaitTimeout {
@@ -31,20 +31,22 @@ impl WaitTimeout for WaitQueue {
 
         let (waiter, waker) = Waiter::new_pair();
 
-        let jiffies_timer = JIFFIES_TIMER_MANAGER.get().unwrap().create_timer(move || {
+        // The monotonic clock is precise, and its timers expire with a high-resolution
+        // timer rather than at the ticks.
+        let timer = MonotonicClock::timer_manager().create_timer(move || {
             waker.wake_up();
         });
-        jiffies_timer.set_timeout(Timeout::After(*timeout));
+        timer.set_timeout(Timeout::After(*timeout));
 
         let cancel_cond = {
-            let jiffies_timer = jiffies_timer.clone();
-            move || jiffies_timer.remain() == Duration::ZERO
+            let timer = timer.clone();
+            move || timer.remain() == Duration::ZERO
         };
         let res = self.wait_until_or_cancelled(cond, waiter, cancel_cond);
 
         // If res is `Some`, then the timeout may not have been expired. We cancel it manually.
         if res.is_some() {
-            jiffies_timer.cancel();
+            timer.cancel();
         }
 
         res
@@ -0,0 +1,109 @@
+// SPDX-License-Identifier: MPL-2.0
+
+use crate::{
+    prelude::*,
+    util::{read_bytes_from_user_partial, read_val_from_user, write_bytes_to_user_partial},
+};
+
+/// The maximum number of `IoVec`s accepted by a single syscall (`UIO_MAXIOV` in Linux).
+pub const IOVEC_MAX: usize = 1024;
+
+/// `struct iovec` in Linux.
+#[repr(C)]
+#[derive(Debug, Clone, Copy, Pod)]
+pub struct IoVec {
+    base: Vaddr,
+    len: usize,
+}
+
+impl IoVec {
+    pub fn base(&self) -> Vaddr {
+        self.base
+    }
+
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.base == 0 || self.len == 0
+    }
+}
+
+/// Read an array of `IoVec`s from the user space of the current process.
+pub fn read_iovecs_from_user(io_vec_ptr: Vaddr, io_vec_count: usize) -> Result<Vec<IoVec>> {
+    if io_vec_count > IOVEC_MAX {
+        return_errno_with_message!(Errno::EINVAL, "too many iovecs");
+    }
+
+    let mut io_vecs = Vec::with_capacity(io_vec_count);
+    for i in 0..io_vec_count {
+        let io_vec = read_val_from_user::<IoVec>(io_vec_ptr + i * core::mem::size_of::<IoVec>())?;
+        io_vecs.push(io_vec);
+    }
+    Ok(io_vecs)
+}
+
+/// Gather the user buffers described by `io_vecs` into a single kernel buffer.
+///
+/// The gathering stops at the first page that cannot be read, so the kernel buffer
+/// may be shorter than the user buffers. If not even the first byte can be read,
+/// an error is returned instead.
+pub fn gather_from_user(io_vecs: &[IoVec]) -> Result<Vec<u8>> {
+    let mut buffer = vec![0u8; total_len(io_vecs)];
+
+    let mut offset = 0;
+    for io_vec in io_vecs.iter().filter(|io_vec| !io_vec.is_empty()) {
+        let copied_len = match read_bytes_from_user_partial(
+            io_vec.base,
+            &mut buffer[offset..offset + io_vec.len],
+        ) {
+            Ok(copied_len) => copied_len,
+            Err(_) if offset > 0 => break,
+            Err(err) => return Err(err),
+        };
+        offset += copied_len;
+        if copied_len < io_vec.len {
+            break;
+        }
+    }
+
+    buffer.truncate(offset);
+    Ok(buffer)
+}
+
+/// Scatter the kernel buffer `buf` to the user buffers described by `io_vecs`.
+///
+/// Returns the number of bytes written, which may be less than `buf.len()`
+/// if the user buffers are not large enough, or a page of the user buffers
+/// cannot be written. If not even the first byte can be written, an error is
+/// returned instead.
+pub fn scatter_to_user(io_vecs: &[IoVec], buf: &[u8]) -> Result<usize> {
+    let mut offset = 0;
+    for io_vec in io_vecs.iter().filter(|io_vec| !io_vec.is_empty()) {
+        if offset >= buf.len() {
+            break;
+        }
+        let copy_len = io_vec.len.min(buf.len() - offset);
+        let copied_len =
+            match write_bytes_to_user_partial(io_vec.base, &buf[offset..offset + copy_len]) {
+                Ok(copied_len) => copied_len,
+                Err(_) if offset > 0 => break,
+                Err(err) => return Err(err),
+            };
+        offset += copied_len;
+        if copied_len < copy_len {
+            break;
+        }
+    }
+    Ok(offset)
+}
+
+/// Returns the total length of the user buffers described by `io_vecs`.
+pub fn total_len(io_vecs: &[IoVec]) -> usize {
+    io_vecs
+        .iter()
+        .filter(|io_vec| !io_vec.is_empty())
+        .map(IoVec::len)
+        .sum()
+}
@@ -1,11 +1,14 @@
 // SPDX-License-Identifier: MPL-2.0
 
-use core::mem;
+use core::{mem, ops::Range};
 
-use aster_frame::mm::VmIo;
+use align_ext::AlignExt;
+use aster_frame::mm::{VmIo, PAGE_SIZE};
 use aster_rights::Full;
 
 use crate::{prelude::*, vm::vmar::Vmar};
+pub mod iovec;
+#[cfg(feature = "net")]
 pub mod net;
 pub mod random;
 
@@ -14,33 +17,114 @@ pub mod random;
 /// If successful,
 /// the `dest` buffer is filled with exact `dest.len` bytes.
 pub fn read_bytes_from_user(src: Vaddr, dest: &mut [u8]) -> Result<()> {
-    let current = current!();
-    let root_vmar = current.root_vmar();
-    Ok(root_vmar.read_bytes(src, dest)?)
+    access_user(src, |root_vmar| root_vmar.read_bytes(src, dest))
+}
+
+/// Read bytes into the `dest` buffer
+/// from the user space of the current process,
+/// stopping at the first page that cannot be read.
+///
+/// Returns the number of bytes read. If not even the first byte can be read,
+/// `EFAULT` is returned instead.
+pub fn read_bytes_from_user_partial(src: Vaddr, dest: &mut [u8]) -> Result<usize> {
+    copy_user_partial(src, dest.len(), |addr, range| {
+        read_bytes_from_user(addr, &mut dest[range])
+    })
 }
 
 /// Read a value of `Pod` type
 /// from the user space of the current process.
 pub fn read_val_from_user<T: Pod>(src: Vaddr) -> Result<T> {
-    let current = current!();
-    let root_vmar = current.root_vmar();
-    Ok(root_vmar.read_val(src)?)
+    access_user(src, |root_vmar| root_vmar.read_val(src))
 }
 
 /// Write bytes from the `src` buffer
 /// to the user space of the current process. If successful,
 /// the write length will be equal to `src.len`.
 pub fn write_bytes_to_user(dest: Vaddr, src: &[u8]) -> Result<()> {
-    let current = current!();
-    let root_vmar = current.root_vmar();
-    Ok(root_vmar.write_bytes(dest, src)?)
+    access_user(dest, |root_vmar| root_vmar.write_bytes(dest, src))
+}
+
+/// Write bytes from the `src` buffer
+/// to the user space of the current process,
+/// stopping at the first page that cannot be written.
+///
+/// Returns the number of bytes written. If not even the first byte can be written,
+/// `EFAULT` is returned instead.
+pub fn write_bytes_to_user_partial(dest: Vaddr, src: &[u8]) -> Result<usize> {
+    copy_user_partial(dest, src.len(), |addr, range| {
+        write_bytes_to_user(addr, &src[range])
+    })
 }
 
 /// Write `val` to the user space of the current process.
 pub fn write_val_to_user<T: Pod>(dest: Vaddr, val: &T) -> Result<()> {
+    access_user(dest, |root_vmar| root_vmar.write_val(dest, val))
+}
+
+/// Atomically replaces the `u32` at `addr` in the user space of the current process with
+/// `new` if it equals `current`.
+///
+/// It returns the previous value, which is `Ok` if the value is replaced. The update is
+/// atomic with the atomic operations of the user space on the same value.
+pub fn compare_exchange_user_u32(
+    addr: Vaddr,
+    current: u32,
+    new: u32,
+) -> Result<core::result::Result<u32, u32>> {
+    access_user(addr, |root_vmar| {
+        let vm_mapping = root_vmar.get_vm_mapping(addr)?;
+        Ok(vm_mapping.compare_exchange_u32(addr - vm_mapping.map_to_addr(), current, new)?)
+    })
+}
+
+/// Access the user space of the current process at `addr`.
+///
+/// If the access fails, it is retried after the user stack grows to cover `addr`, like
+/// the page faults below the user stack in user mode.
+fn access_user<T>(
+    addr: Vaddr,
+    mut access: impl FnMut(&Vmar<Full>) -> aster_frame::Result<T>,
+) -> Result<T> {
     let current = current!();
     let root_vmar = current.root_vmar();
-    Ok(root_vmar.write_val(dest, val)?)
+    match access(root_vmar) {
+        Ok(val) => Ok(val),
+        Err(_) if current.grow_stack(addr).is_ok() => Ok(access(root_vmar)?),
+        Err(err) => Err(err.into()),
+    }
+}
+
+/// Copy `len` bytes between the user space at `addr` and a kernel buffer by `copy`,
+/// which is given the user address and the range in the kernel buffer of a chunk.
+///
+/// The user space is accessible in pages, so the bytes are copied page by page once
+/// the whole copy fails, until a page cannot be accessed.
+fn copy_user_partial(
+    addr: Vaddr,
+    len: usize,
+    mut copy: impl FnMut(Vaddr, Range<usize>) -> Result<()>,
+) -> Result<usize> {
+    if len == 0 {
+        return Ok(0);
+    }
+    if copy(addr, 0..len).is_ok() {
+        return Ok(len);
+    }
+
+    let mut copied_len = 0;
+    while copied_len < len {
+        let page_end = (addr + copied_len + 1).align_up(PAGE_SIZE);
+        let chunk_end = (page_end - addr).min(len);
+        if copy(addr + copied_len, copied_len..chunk_end).is_err() {
+            break;
+        }
+        copied_len = chunk_end;
+    }
+    if copied_len == 0 {
+        return_errno_with_message!(Errno::EFAULT, "the user buffer is not accessible");
+    }
+    Ok(copied_len)
 }
 
 /// Read a C string from the user space of the current process.
@@ -9,7 +9,7 @@ use crate::{
         socket::{unix::UnixSocketAddr, vsock::VsockSocketAddr, SocketAddr},
     },
     prelude::*,
-    util::{read_bytes_from_user, read_val_from_user, write_val_to_user},
+    util::{read_bytes_from_user, read_val_from_user, write_bytes_to_user, write_val_to_user},
 };
 
 pub fn read_socket_addr_from_user(addr: Vaddr, addr_len: usize) -> Result<SocketAddr> {
@@ -80,34 +80,25 @@ pub fn write_socket_addr_to_user(
         return_errno_with_message!(Errno::EINVAL, "must provide the addrlen ptr");
     }
     let max_len = read_val_from_user::<i32>(addrlen_ptr)? as usize;
-    let write_size = match socket_addr {
-        SocketAddr::Unix(path) => {
-            let sock_addr_unix = CSocketAddrUnix::try_from(path)?;
-            let write_size = core::mem::size_of::<CSocketAddrUnix>();
-            debug_assert!(max_len >= write_size);
-            write_val_to_user(dest, &sock_addr_unix)?;
-            write_size as i32
-        }
+    let bytes = socket_addr_to_bytes(socket_addr)?;
+    debug_assert!(max_len >= bytes.len());
+    write_bytes_to_user(dest, &bytes)?;
+    write_val_to_user(addrlen_ptr, &(bytes.len() as i32))?;
+    Ok(())
+}
+
+/// Converts the socket address to the bytes of its C representation.
+pub fn socket_addr_to_bytes(socket_addr: &SocketAddr) -> Result<Vec<u8>> {
+    let bytes = match socket_addr {
+        SocketAddr::Unix(path) => CSocketAddrUnix::try_from(path)?.as_bytes().to_vec(),
         SocketAddr::IPv4(addr, port) => {
             let in_addr = CInetAddr::from(*addr);
-            let sock_addr_in = CSocketAddrInet::new(*port, in_addr);
-            let write_size = core::mem::size_of::<CSocketAddrInet>();
-            debug_assert!(max_len >= write_size);
-            write_val_to_user(dest, &sock_addr_in)?;
-            write_size as i32
+            CSocketAddrInet::new(*port, in_addr).as_bytes().to_vec()
         }
         SocketAddr::IPv6 => todo!(),
-        SocketAddr::Vsock(addr) => {
-            let vm_addr = CSocketAddrVm::new(addr.cid, addr.port);
-            let write_size = core::mem::size_of::<CSocketAddrVm>();
-            write_val_to_user(dest, &vm_addr)?;
-            write_size as i32
-        }
+        SocketAddr::Vsock(addr) => CSocketAddrVm::new(addr.cid, addr.port).as_bytes().to_vec(),
     };
-    if addrlen_ptr != 0 {
-        write_val_to_user(addrlen_ptr, &write_size)?;
-    }
-    Ok(())
+    Ok(bytes)
 }
 
 /// PlaceHolder
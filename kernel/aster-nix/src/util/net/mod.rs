@@ -1,10 +1,14 @@
 // SPDX-License-Identifier: MPL-2.0
 
 mod addr;
+mod msg;
 mod options;
 mod socket;
 
-pub use addr::{read_socket_addr_from_user, write_socket_addr_to_user, CSocketAddrFamily};
+pub use addr::{
+    read_socket_addr_from_user, socket_addr_to_bytes, write_socket_addr_to_user, CSocketAddrFamily,
+};
+pub use msg::{CUserMmsgHdr, CUserMsgHdr};
 pub use options::{new_raw_socket_option, CSocketOptionLevel};
 pub use socket::{Protocol, SockFlags, SockType, SOCK_TYPE_MASK};
 
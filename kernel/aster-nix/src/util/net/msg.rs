@@ -0,0 +1,54 @@
+// SPDX-License-Identifier: MPL-2.0
+
+use crate::{
+    prelude::*,
+    util::iovec::{read_iovecs_from_user, IoVec},
+};
+
+/// `struct msghdr` in Linux.
+#[repr(C)]
+#[derive(Debug, Clone, Copy, Pod)]
+pub struct CUserMsgHdr {
+    /// Pointer to the socket address
+    pub msg_name: Vaddr,
+    /// Size of the socket address
+    pub msg_namelen: i32,
+    _pad0: u32,
+    /// Pointer to the `IoVec` array
+    pub msg_iov: Vaddr,
+    /// Number of elements in the `IoVec` array
+    pub msg_iovlen: usize,
+    /// Pointer to the ancillary data
+    pub msg_control: Vaddr,
+    /// Size of the ancillary data
+    pub msg_controllen: usize,
+    /// Flags on the received message
+    pub msg_flags: i32,
+    _pad1: u32,
+}
+
+impl CUserMsgHdr {
+    /// The offset of the `msg_namelen` field.
+    pub const NAMELEN_OFFSET: usize = core::mem::size_of::<Vaddr>();
+
+    /// Read the `IoVec`s referred to by this header from the user space.
+    pub fn read_iovecs_from_user(&self) -> Result<Vec<IoVec>> {
+        read_iovecs_from_user(self.msg_iov, self.msg_iovlen)
+    }
+}
+
+/// `struct mmsghdr` in Linux.
+#[repr(C)]
+#[derive(Debug, Clone, Copy, Pod)]
+pub struct CUserMmsgHdr {
+    /// The message header
+    pub msg_hdr: CUserMsgHdr,
+    /// Number of bytes transmitted for this message
+    pub msg_len: u32,
+    _pad: u32,
+}
+
+impl CUserMmsgHdr {
+    /// The offset of the `msg_len` field.
+    pub const MSG_LEN_OFFSET: usize = core::mem::size_of::<CUserMsgHdr>();
+}
@@ -57,9 +57,10 @@ use crate::{net::socket::options::SocketOption, prelude::*, vm::vmar::Vmar};
 
 mod socket;
 mod tcp;
+mod udp;
 mod utils;
 
-use self::{socket::new_socket_option, tcp::new_tcp_option};
+use self::{socket::new_socket_option, tcp::new_tcp_option, udp::new_udp_option};
 
 pub trait RawSocketOption: SocketOption {
     fn read_from_user(&mut self, vmar: &Vmar<Full>, addr: Vaddr, max_len: u32) -> Result<()>;
@@ -146,6 +147,7 @@ pub fn new_raw_socket_option(
     match level {
         CSocketOptionLevel::SOL_SOCKET => new_socket_option(name),
         CSocketOptionLevel::SOL_TCP => new_tcp_option(name),
+        CSocketOptionLevel::SOL_UDP => new_udp_option(name),
         _ => todo!(),
     }
 }
@@ -4,8 +4,10 @@ use aster_rights::Full;
 
 use super::RawSocketOption;
 use crate::{
-    impl_raw_socket_option,
-    net::socket::ip::stream::options::{Congestion, MaxSegment, NoDelay, WindowClamp},
+    impl_raw_sock_option_get_only, impl_raw_socket_option,
+    net::socket::ip::stream::options::{
+        Congestion, Info, MaxSegment, NoDelay, SynCnt, WindowClamp,
+    },
     prelude::*,
     util::net::options::SocketOption,
     vm::vmar::Vmar,
@@ -24,7 +26,9 @@ pub enum CTcpOptionName {
     CORK = 3,          /* Never send partially complete segments */
     KEEPIDLE = 4,      /* Start keeplives after this period */
     KEEPALIVE = 5,     /* Interval between keepalives */
+    SYNCNT = 7,        /* Number of SYN retransmits */
     WINDOW_CLAMP = 10, /* Bound advertised window */
+    INFO = 11,         /* Information about this connection. */
     CONGESTION = 13,   /* Congestion control algorithm */
 }
 
@@ -35,6 +39,8 @@ pub fn new_tcp_option(name: i32) -> Result<Box<dyn RawSocketOption>> {
         CTcpOptionName::CONGESTION => Ok(Box::new(Congestion::new())),
         CTcpOptionName::MAXSEG => Ok(Box::new(MaxSegment::new())),
         CTcpOptionName::WINDOW_CLAMP => Ok(Box::new(WindowClamp::new())),
+        CTcpOptionName::INFO => Ok(Box::new(Info::new())),
+        CTcpOptionName::SYNCNT => Ok(Box::new(SynCnt::new())),
         _ => todo!(),
     }
 }
@@ -43,3 +49,5 @@ impl_raw_socket_option!(NoDelay);
 impl_raw_socket_option!(Congestion);
 impl_raw_socket_option!(MaxSegment);
 impl_raw_socket_option!(WindowClamp);
+impl_raw_sock_option_get_only!(Info);
+impl_raw_socket_option!(SynCnt);
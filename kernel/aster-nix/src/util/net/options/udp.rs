@@ -0,0 +1,40 @@
+// SPDX-License-Identifier: MPL-2.0
+
+use aster_rights::Full;
+
+use super::RawSocketOption;
+use crate::{
+    impl_raw_socket_option,
+    net::socket::ip::datagram::options::{UdpGro, UdpSegment},
+    prelude::*,
+    util::net::options::SocketOption,
+    vm::vmar::Vmar,
+};
+
+/// Sock options for udp socket.
+///
+/// The raw definition is from https://elixir.bootlin.com/linux/v6.0.9/source/include/uapi/linux/udp.h#L29
+#[repr(i32)]
+#[derive(Debug, Clone, Copy, TryFromInt)]
+#[allow(non_camel_case_types)]
+#[allow(clippy::upper_case_acronyms)]
+pub enum CUdpOptionName {
+    CORK = 1,           /* Never send partially complete segments */
+    ENCAP = 100,        /* Set the socket to accept encapsulated packets */
+    NO_CHECK6_TX = 101, /* Disable sending checksum for UDP6 */
+    NO_CHECK6_RX = 102, /* Disable accepting checksum for UDP6 */
+    SEGMENT = 103,      /* Set GSO segmentation size */
+    GRO = 104,          /* This socket can receive UDP GRO packets */
+}
+
+pub fn new_udp_option(name: i32) -> Result<Box<dyn RawSocketOption>> {
+    let name = CUdpOptionName::try_from(name)?;
+    match name {
+        CUdpOptionName::SEGMENT => Ok(Box::new(UdpSegment::new())),
+        CUdpOptionName::GRO => Ok(Box::new(UdpGro::new())),
+        _ => return_errno_with_message!(Errno::ENOPROTOOPT, "the udp option is not supported"),
+    }
+}
+
+impl_raw_socket_option!(UdpSegment);
+impl_raw_socket_option!(UdpGro);
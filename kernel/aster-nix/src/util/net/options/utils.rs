@@ -6,7 +6,10 @@ use aster_frame::mm::VmIo;
 use aster_rights::Full;
 
 use crate::{
-    net::socket::{ip::stream::CongestionControl, LingerOption},
+    net::socket::{
+        ip::stream::{CongestionControl, TcpInfo},
+        LingerOption,
+    },
     prelude::*,
     vm::vmar::Vmar,
 };
@@ -70,6 +73,7 @@ macro_rules! impl_read_write_for_pod_type {
 }
 
 impl_read_write_for_pod_type!(u32);
+impl_read_write_for_pod_type!(i32);
 
 impl ReadFromUser for bool {
     fn read_from_user(vmar: &Vmar<Full>, addr: Vaddr, max_len: u32) -> Result<Self> {
@@ -165,6 +169,18 @@ impl WriteToUser for CongestionControl {
     }
 }
 
+impl WriteToUser for TcpInfo {
+    fn write_to_user(&self, vmar: &Vmar<Full>, addr: Vaddr, max_len: u32) -> Result<usize> {
+        // As in Linux, the structure is truncated if the user buffer is too short, so that
+        // applications built against an older version of `struct tcp_info` keep working.
+        let bytes = self.as_bytes();
+        let write_len = bytes.len().min(max_len as usize);
+
+        vmar.write_bytes(addr, &bytes[..write_len])?;
+        Ok(write_len)
+    }
+}
+
 #[repr(C)]
 #[derive(Debug, Clone, Copy, Pod)]
 struct CLinger {
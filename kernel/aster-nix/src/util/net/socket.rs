@@ -1,6 +1,6 @@
 // SPDX-License-Identifier: MPL-2.0
 
-use crate::prelude::*;
+use crate::{fs::file_table::FdFlags, prelude::*};
 
 /// Standard well-defined IP protocols.
 /// From https://elixir.bootlin.com/linux/v6.0.9/source/include/uapi/linux/in.h.
@@ -66,5 +66,20 @@ bitflags! {
     pub struct SockFlags: i32 {
         const SOCK_NONBLOCK = 1 << 11;
         const SOCK_CLOEXEC = 1 << 19;
+        const SOCK_CLOFORK = 1 << 23;
+    }
+}
+
+impl SockFlags {
+    /// Returns the flags of the file descriptor that refers to the new socket.
+    pub fn fd_flags(&self) -> FdFlags {
+        let mut fd_flags = FdFlags::empty();
+        if self.contains(SockFlags::SOCK_CLOEXEC) {
+            fd_flags |= FdFlags::CLOEXEC;
+        }
+        if self.contains(SockFlags::SOCK_CLOFORK) {
+            fd_flags |= FdFlags::CLOFORK;
+        }
+        fd_flags
     }
 }
@@ -1,33 +1,248 @@
 // SPDX-License-Identifier: MPL-2.0
 
+//! The kernel random number generator.
+//!
+//! The random bytes are generated by a CSPRNG, which is seeded from an entropy pool. The
+//! entropy pool collects the randomness from the CPU (`RDSEED` and `RDRAND`), the virtio
+//! entropy devices, and the writes to `/dev/random` or `/dev/urandom`. The CSPRNG becomes
+//! _ready_ once the entropy pool has been credited with enough entropy, before which the
+//! blocking requests for the random bytes wait.
+
 #![allow(unused_variables)]
 
-use rand::{rngs::StdRng, Error as RandError, RngCore};
+use core::{
+    mem::size_of,
+    sync::atomic::{AtomicBool, Ordering},
+    time::Duration,
+};
+
+use aster_frame::{
+    arch::{read_random, read_random_seed, read_tsc, timer::Jiffies},
+    sync::WaitQueue,
+};
+#[cfg(feature = "virtio-rng")]
+use aster_virtio::device::entropy::{all_devices, device::EntropyDevice};
+use rand::{rngs::StdRng, Error as RandError, RngCore, SeedableRng};
 use spin::Once;
 
-use crate::prelude::*;
+#[cfg(feature = "virtio-rng")]
+use crate::thread::{
+    kernel_thread::{KernelThreadExt, ThreadOptions},
+    Thread,
+};
+use crate::{prelude::*, process::signal::Pauser, vdso::update_vdso_rng_generation};
+
+/// The interval to reseed the RNG, which is the same as that in Linux.
+const RESEED_INTERVAL: Duration = Duration::from_secs(60);
+
+/// The size of the entropy pool, and also the size of the seed of the RNG.
+const POOL_SIZE: usize = 32;
+/// The number of the bits of entropy that make the RNG ready, which is the same as that
+/// in Linux.
+const POOL_READY_BITS: usize = POOL_SIZE * 8;
+
+static RNG: Once<SpinLock<Crng>> = Once::new();
+
+static POOL: SpinLock<EntropyPool> = SpinLock::new(EntropyPool::new());
+
+static IS_READY: AtomicBool = AtomicBool::new(false);
 
-static RNG: Once<SpinLock<StdRng>> = Once::new();
+/// The pauser of the threads that wait for the RNG to become ready.
+static READY_PAUSER: Once<Arc<Pauser>> = Once::new();
+
+/// The wait queue of the hardware RNG threads, which is woken up when the entropy in
+/// the entropy pool is consumed.
+static HWRNG_WAIT_QUEUE: WaitQueue = WaitQueue::new();
+
+/// A cryptographically secure RNG that is reseeded periodically.
+struct Crng {
+    rng: StdRng,
+    /// The time when the RNG is seeded last time, since the system boots up.
+    seeded_at: Duration,
+    /// The number of times that the RNG is reseeded.
+    generation: u64,
+}
+
+/// An entropy pool that mixes the inputs into a fixed-size key.
+struct EntropyPool {
+    key: [u8; POOL_SIZE],
+    /// The number of the bits of entropy that are credited since the last extraction.
+    entropy_bits: usize,
+}
 
 /// Fill `dest` with random bytes.
 ///
-/// It's cryptographically secure, as documented in [`rand::rngs::StdRng`].
+/// It's cryptographically secure, as documented in [`rand::rngs::StdRng`], once the RNG
+/// is ready. This method never blocks, so the callers that require the RNG to be ready
+/// should call [`wait_until_ready`] first.
 pub fn getrandom(dst: &mut [u8]) -> Result<()> {
-    Ok(RNG.get().unwrap().lock().try_fill_bytes(dst)?)
+    let mut crng = RNG.get().unwrap().lock();
+    if Jiffies::elapsed().as_duration() - crng.seeded_at >= RESEED_INTERVAL {
+        crng.reseed();
+    }
+    Ok(crng.rng.try_fill_bytes(dst)?)
+}
+
+/// Returns whether the RNG has been seeded with enough entropy.
+pub fn is_ready() -> bool {
+    IS_READY.load(Ordering::Acquire)
+}
+
+/// Waits until the RNG has been seeded with enough entropy.
+///
+/// Returns `EINTR` if the current thread is interrupted by a signal.
+pub fn wait_until_ready() -> Result<()> {
+    READY_PAUSER
+        .get()
+        .unwrap()
+        .pause_until(|| is_ready().then_some(()))
+}
+
+/// Mixes `input` into the entropy pool, crediting the pool with `nr_bits` bits of entropy.
+///
+/// The input without entropy, e.g., the data written by the users, can still be mixed
+/// with `nr_bits` being zero, which never makes the pool less random.
+pub fn add_entropy(input: &[u8], nr_bits: usize) {
+    if mix_entropy(input, nr_bits) {
+        // Throw away the seed that is generated without enough entropy.
+        RNG.get().unwrap().lock().reseed();
+        set_ready();
+    }
+}
+
+/// Mixes `input` into the entropy pool without reseeding the RNG.
+///
+/// Returns whether the RNG should be reseeded because the pool becomes ready.
+fn mix_entropy(input: &[u8], nr_bits: usize) -> bool {
+    let mut pool = POOL.lock_irq_disabled();
+    pool.mix(&read_tsc().to_ne_bytes());
+    pool.mix(input);
+    pool.entropy_bits = (pool.entropy_bits + nr_bits).min(POOL_READY_BITS);
+    pool.entropy_bits >= POOL_READY_BITS && !is_ready()
+}
+
+fn set_ready() {
+    IS_READY.store(true, Ordering::Release);
+    READY_PAUSER.get().unwrap().resume_all();
+}
+
+/// Returns the generation of the RNG, which is increased every time the RNG is reseeded.
+///
+/// The `getrandom` VDSO routine refreshes its keys once the generation changes.
+pub fn generation() -> u64 {
+    RNG.get().unwrap().lock().generation
 }
 
 pub fn init() {
-    // The seed used to initialize the RNG is required to be secure and unpredictable.
+    READY_PAUSER.call_once(Pauser::new);
+    let becomes_ready = mix_arch_entropy();
+    RNG.call_once(|| {
+        SpinLock::new(Crng {
+            rng: StdRng::from_seed(POOL.lock_irq_disabled().extract()),
+            seeded_at: Jiffies::elapsed().as_duration(),
+            generation: 0,
+        })
+    });
+    if becomes_ready {
+        set_ready();
+    }
+}
+
+/// Spawns a kernel thread for each virtio entropy device, which keeps the entropy pool
+/// filled with the random bytes from the device.
+pub fn lazy_init() {
+    #[cfg(feature = "virtio-rng")]
+    for device in all_devices() {
+        Thread::spawn_kernel_thread(ThreadOptions::new(move || run_hwrng(&device)));
+    }
+}
+
+#[cfg(feature = "virtio-rng")]
+fn run_hwrng(device: &EntropyDevice) {
+    let mut buf = [0u8; POOL_SIZE];
+    loop {
+        HWRNG_WAIT_QUEUE.wait_until(|| POOL.lock_irq_disabled().needs_entropy().then_some(()));
+
+        let nr_filled = device.read(&mut buf);
+        if nr_filled == 0 {
+            warn!("hwrng: the entropy device provides no random bytes");
+            return;
+        }
+        add_entropy(&buf[..nr_filled], nr_filled * 8);
+    }
+}
+
+impl Crng {
+    fn reseed(&mut self) {
+        let becomes_ready = mix_arch_entropy();
+        self.rng = StdRng::from_seed(POOL.lock_irq_disabled().extract());
+        self.seeded_at = Jiffies::elapsed().as_duration();
+        self.generation += 1;
+        update_vdso_rng_generation(self.generation);
+        if becomes_ready {
+            set_ready();
+        }
+    }
+}
 
-    cfg_if::cfg_if! {
-        if #[cfg(target_arch = "x86_64")] {
-            use rand::SeedableRng;
+impl EntropyPool {
+    const fn new() -> Self {
+        Self {
+            key: [0; POOL_SIZE],
+            entropy_bits: 0,
+        }
+    }
 
-            RNG.call_once(|| SpinLock::new(StdRng::from_entropy()));
-        } else {
-            compile_error!("unsupported target");
+    /// Mixes `input` into the key.
+    ///
+    /// Each chunk of the input is added to the key, and the result is then replaced by
+    /// the output of the ChaCha stream cipher keyed with it, which cannot be inverted.
+    fn mix(&mut self, input: &[u8]) {
+        for chunk in input.chunks(POOL_SIZE) {
+            for (byte, input_byte) in self.key.iter_mut().zip(chunk) {
+                *byte ^= input_byte;
+            }
+            StdRng::from_seed(self.key).fill_bytes(&mut self.key);
         }
     }
+
+    /// Extracts a seed from the pool.
+    ///
+    /// The key is updated, so that the seed cannot be recovered from the pool later.
+    fn extract(&mut self) -> [u8; POOL_SIZE] {
+        let mut rng = StdRng::from_seed(self.key);
+        let mut seed = [0; POOL_SIZE];
+        rng.fill_bytes(&mut seed);
+        rng.fill_bytes(&mut self.key);
+
+        self.entropy_bits = 0;
+        HWRNG_WAIT_QUEUE.wake_all();
+        seed
+    }
+
+    #[cfg(feature = "virtio-rng")]
+    fn needs_entropy(&self) -> bool {
+        self.entropy_bits < POOL_READY_BITS
+    }
+}
+
+/// Mixes the randomness of the CPU into the entropy pool.
+///
+/// `RDSEED` is preferred, and `RDRAND` is used if `RDSEED` is exhausted. Like Linux with
+/// `CONFIG_RANDOM_TRUST_CPU`, the randomness of the CPU is trusted and credited.
+///
+/// Returns whether the pool becomes ready.
+fn mix_arch_entropy() -> bool {
+    let mut nr_bits = 0;
+    let mut input = [0u8; POOL_SIZE];
+    for chunk in input.chunks_mut(size_of::<u64>()) {
+        let Some(value) = read_random_seed().or_else(read_random) else {
+            break;
+        };
+        chunk.copy_from_slice(&value.to_ne_bytes());
+        nr_bits += u64::BITS as usize;
+    }
+    mix_entropy(&input, nr_bits)
 }
 
 impl From<RandError> for Error {
@@ -13,16 +13,20 @@
 //!
 //! The module is initialized with `init`, which sets up the `START_SECS_COUNT` and prepares the VDSO instance for
 //! use. It also hooks up the VDSO data update routine to the time management subsystem for periodic updates.
+//!
+//! Besides the timing information, the VDSO data contains the state of the kernel random number generator, which
+//! allows the `getrandom` VDSO routine to generate random bytes in user space with the per-thread opaque states.
 
-use alloc::{boxed::Box, sync::Arc};
+use alloc::{sync::Arc, vec};
 use core::{mem::ManuallyDrop, time::Duration};
 
+use align_ext::AlignExt;
 use aster_frame::{
     mm::{Frame, VmIo, PAGE_SIZE},
     sync::SpinLock,
 };
 use aster_rights::Rights;
-use aster_time::{read_monotonic_time, Instant};
+use aster_time::Instant;
 use aster_util::coeff::Coeff;
 use pod::Pod;
 use spin::Once;
@@ -30,7 +34,8 @@ use spin::Once;
 use crate::{
     fs::fs_resolver::{FsPath, FsResolver, AT_FDCWD},
     syscall::ClockId,
-    time::{clocks::MonotonicClock, timer::Timeout, SystemTime, START_TIME},
+    time::{clocks::MonotonicClock, timer::Timeout, virtual_time, Clock, SystemTime, START_TIME},
+    util::random,
     vm::vmo::{Vmo, VmoOptions},
 };
 
@@ -38,6 +43,11 @@ const CLOCK_TAI: usize = 11;
 const VDSO_BASES: usize = CLOCK_TAI + 1;
 const DEFAULT_CLOCK_MODE: VdsoClockMode = VdsoClockMode::Tsc;
 
+/// The offset of the VDSO library text in the VDSO vmo.
+pub(crate) const VDSO_TEXT_OFFSET: usize = 0x4000;
+/// The offset of `VdsoRngData` in the VDSO data frame.
+const VDSO_RNG_DATA_OFFSET: usize = 0x280;
+
 static START_SECS_COUNT: Once<u64> = Once::new();
 static VDSO: Once<Arc<Vdso>> = Once::new();
 
@@ -103,6 +113,20 @@ struct VdsoData {
     arch_data: ArchVdsoData,
 }
 
+/// A POD structure maintaining the state of the kernel random number generator that is required for the
+/// `getrandom` VDSO routine.
+///
+/// The routine generates random bytes with the keys in the opaque states of user space, and refreshes the keys with
+/// the `getrandom` system call once the `generation` changes, i.e., the kernel random number generator is reseeded.
+/// It aligns with the `vdso_rng_data` structure in Linux (Linux v6.11).
+#[repr(C)]
+#[derive(Debug, Default, Copy, Clone, Pod)]
+struct VdsoRngData {
+    generation: u64,
+    is_ready: u8,
+    __padding: [u8; 7],
+}
+
 const HIGH_RES_CLOCK_IDS: [ClockId; 4] = [
     ClockId::CLOCK_REALTIME,
     ClockId::CLOCK_MONOTONIC,
@@ -143,6 +167,13 @@ impl VdsoData {
         let (last_instant, last_cycles) = clocksource.last_record();
         self.update_high_res_instant(last_instant, last_cycles);
         self.update_coarse_res_instant(last_instant);
+
+        if virtual_time::is_enabled() {
+            // The clocks cannot be calculated from the TSC in the virtual time mode, so the
+            // VDSO routines fall back to the system calls for the high-resolution clocks.
+            self.set_clock_mode(VdsoClockMode::None);
+            self.update_coarse_res_instant(Instant::from(virtual_time::now()));
+        }
     }
 
     fn set_clock_mode(&mut self, mode: VdsoClockMode) {
@@ -212,23 +243,37 @@ impl Vdso {
     fn new() -> Self {
         let mut vdso_data = VdsoData::empty();
         vdso_data.init();
+        let rng_data = VdsoRngData {
+            generation: random::generation(),
+            is_ready: 1,
+            ..Default::default()
+        };
 
         let (vdso_vmo, data_frame) = {
-            let vmo_options = VmoOptions::<Rights>::new(5 * PAGE_SIZE);
-            let vdso_vmo = vmo_options.alloc().unwrap();
-            // Write VDSO data to VDSO vmo.
-            vdso_vmo.write_bytes(0x80, vdso_data.as_bytes()).unwrap();
-
-            let vdso_lib_vmo = {
+            let vdso_lib = {
                 let vdso_path = FsPath::new(AT_FDCWD, "/lib/x86_64-linux-gnu/vdso64.so").unwrap();
                 let fs_resolver = FsResolver::new();
-                let vdso_lib = fs_resolver.lookup(&vdso_path).unwrap();
-                vdso_lib.inode().page_cache().unwrap()
+                fs_resolver.lookup(&vdso_path).unwrap()
             };
-            let mut vdso_text = Box::new([0u8; PAGE_SIZE]);
-            vdso_lib_vmo.read_bytes(0, &mut *vdso_text).unwrap();
+            // The whole library is loaded, since the text of newer VDSO libraries (e.g., the ones with
+            // the `getrandom` routine) takes more than one page.
+            let mut vdso_text = vec![0u8; vdso_lib.size().align_up(PAGE_SIZE)];
+            vdso_lib
+                .inode()
+                .page_cache()
+                .unwrap()
+                .read_bytes(0, &mut vdso_text)
+                .unwrap();
+
+            let vmo_options = VmoOptions::<Rights>::new(VDSO_TEXT_OFFSET + vdso_text.len());
+            let vdso_vmo = vmo_options.alloc().unwrap();
+            // Write VDSO data to VDSO vmo.
+            vdso_vmo.write_bytes(0x80, vdso_data.as_bytes()).unwrap();
+            vdso_vmo
+                .write_bytes(VDSO_RNG_DATA_OFFSET, rng_data.as_bytes())
+                .unwrap();
             // Write VDSO library to VDSO vmo.
-            vdso_vmo.write_bytes(0x4000, &*vdso_text).unwrap();
+            vdso_vmo.write_bytes(VDSO_TEXT_OFFSET, &vdso_text).unwrap();
 
             let data_frame = vdso_vmo.get_committed_frame(0, true).unwrap();
             (vdso_vmo, data_frame)
@@ -271,6 +316,13 @@ impl Vdso {
         self.data_frame.write_val(0x80, &0).unwrap();
     }
 
+    /// Update the generation of the kernel random number generator in the `data_frame`.
+    fn update_rng_generation(&self, generation: u64) {
+        self.data_frame
+            .write_val(VDSO_RNG_DATA_OFFSET, &generation)
+            .unwrap();
+    }
+
     /// Update the requisite fields of the VDSO data in the `data_frame`.
     fn update_data_frame_instant(&self, clockid: ClockId) {
         let clock_index = clockid as usize;
@@ -295,10 +347,19 @@ fn update_vdso_high_res_instant(instant: Instant, instant_cycles: u64) {
 
 /// Update the `VdsoInstant` for clock IDs with coarse resolution in Vdso.
 fn update_vdso_coarse_res_instant() {
-    let instant = Instant::from(read_monotonic_time());
+    let instant = Instant::from(MonotonicClock::get().read_time());
     VDSO.get().unwrap().update_coarse_res_instant(instant);
 }
 
+/// Update the generation of the kernel random number generator in Vdso, which makes the `getrandom` VDSO
+/// routine refresh the keys in the opaque states.
+pub(crate) fn update_vdso_rng_generation(generation: u64) {
+    // The random number generator may be reseeded before VDSO is initialized.
+    if let Some(vdso) = VDSO.get() {
+        vdso.update_rng_generation(generation);
+    }
+}
+
 /// Init `START_SECS_COUNT`, which is used to record the seconds passed since 1970-01-01 00:00:00.
 fn init_start_secs_count() {
     let time_duration = START_TIME
@@ -318,7 +379,10 @@ fn init_vdso() {
 pub(super) fn init() {
     init_start_secs_count();
     init_vdso();
-    aster_time::VDSO_DATA_HIGH_RES_UPDATE_FN.call_once(|| Arc::new(update_vdso_high_res_instant));
+    if !virtual_time::is_enabled() {
+        aster_time::VDSO_DATA_HIGH_RES_UPDATE_FN
+            .call_once(|| Arc::new(update_vdso_high_res_instant));
+    }
 
     // Coarse resolution clock IDs directly read the instant stored in VDSO data without
     // using coefficients for calculation, thus the related instant requires more frequent updating.
@@ -0,0 +1,161 @@
+// SPDX-License-Identifier: MPL-2.0
+
+//! The memory balloon.
+//!
+//! A balloon thread is spawned for each virtio balloon device. It inflates or deflates the
+//! balloon to the size that the host wants, answers the requests for the memory statistics,
+//! and reports the free pages to the host periodically if the device supports it. The pages
+//! that are taken by or given back to the balloon are notified through the
+//! [`MEMORY_NOTIFIER_CHAIN`].
+
+use core::time::Duration;
+
+use aster_frame::mm::{nr_free_frames, nr_total_frames, reclaim, PAGE_SIZE};
+use aster_virtio::device::balloon::{
+    all_devices,
+    config::BalloonStatTag,
+    device::{BalloonDevice, BalloonRequests},
+};
+
+use super::{MemoryEvent, MEMORY_NOTIFIER_CHAIN};
+use crate::{
+    events::NotifyResult,
+    fs::utils::nr_cached_pages,
+    prelude::*,
+    thread::{
+        kernel_thread::{KernelThreadExt, ThreadOptions},
+        Thread,
+    },
+    time::wait::WaitTimeout,
+};
+
+/// The interval at which the free pages are checked for reporting.
+const REPORTING_INTERVAL: Duration = Duration::from_secs(2);
+/// The number of frames that must be freed since the last reporting before the free pages
+/// are reported again, i.e., 32 MiB.
+const REPORTING_THRESHOLD: usize = 8192;
+
+pub(super) fn spawn_balloon_threads() {
+    for device in all_devices() {
+        let shrinker = device.deflates_on_oom().then(|| {
+            let shrinker = Arc::new(BalloonShrinker {
+                device: device.clone(),
+            });
+            reclaim::register_shrinker(Arc::downgrade(&shrinker) as _);
+            shrinker
+        });
+
+        let task_fn = move || {
+            // The shrinker is alive as long as the thread.
+            let _shrinker = &shrinker;
+            run_balloon(&device);
+        };
+        Thread::spawn_kernel_thread(ThreadOptions::new(task_fn));
+    }
+}
+
+fn run_balloon(device: &BalloonDevice) {
+    // The lowest number of free frames since the last reporting.
+    let mut min_nr_free = nr_free_frames();
+    loop {
+        let requests = if device.can_report_free_pages() {
+            device
+                .wait_queue()
+                .wait_until_or_timeout(|| take_requests(device), &REPORTING_INTERVAL)
+                .unwrap_or(BalloonRequests::empty())
+        } else {
+            device.wait_queue().wait_until(|| take_requests(device))
+        };
+
+        if requests.contains(BalloonRequests::STATS) {
+            device.update_stats(&memory_stats());
+        }
+        if requests.contains(BalloonRequests::RESIZE) {
+            resize_balloon(device);
+        }
+
+        if device.can_report_free_pages() {
+            let nr_free = nr_free_frames();
+            min_nr_free = min_nr_free.min(nr_free);
+            // Most of the free pages are reported already unless enough pages are freed.
+            if nr_free >= min_nr_free + REPORTING_THRESHOLD {
+                let nr_reported = device.report_free_pages(reclaim::high_watermark());
+                debug!("balloon: {} free pages reported", nr_reported);
+                min_nr_free = nr_free_frames();
+            }
+        }
+    }
+}
+
+fn take_requests(device: &BalloonDevice) -> Option<BalloonRequests> {
+    let requests = device.take_requests();
+    (!requests.is_empty()).then_some(requests)
+}
+
+/// Inflates or deflates the balloon to the size that the host wants.
+fn resize_balloon(device: &BalloonDevice) {
+    let target = device.target_pages();
+    let current = device.nr_pages();
+
+    if target > current {
+        let nr_pages = target - current;
+        let result = MEMORY_NOTIFIER_CHAIN.notify(&MemoryEvent::GoingOffline { nr_pages });
+        if result == NotifyResult::Bad {
+            MEMORY_NOTIFIER_CHAIN.notify(&MemoryEvent::CancelOffline { nr_pages });
+            return;
+        }
+        let nr_inflated = device.inflate(nr_pages);
+        MEMORY_NOTIFIER_CHAIN.notify(&MemoryEvent::Offline {
+            nr_pages: nr_inflated,
+        });
+        debug!("balloon: {} of {} pages inflated", nr_inflated, nr_pages);
+    } else if target < current {
+        let nr_pages = current - target;
+        let result = MEMORY_NOTIFIER_CHAIN.notify(&MemoryEvent::GoingOnline { nr_pages });
+        if result == NotifyResult::Bad {
+            MEMORY_NOTIFIER_CHAIN.notify(&MemoryEvent::CancelOnline { nr_pages });
+            return;
+        }
+        let nr_deflated = device.deflate(nr_pages);
+        MEMORY_NOTIFIER_CHAIN.notify(&MemoryEvent::Online {
+            nr_pages: nr_deflated,
+        });
+        debug!("balloon: {} of {} pages deflated", nr_deflated, nr_pages);
+    }
+}
+
+fn memory_stats() -> [(BalloonStatTag, u64); 4] {
+    let total = nr_total_frames();
+    let free = nr_free_frames();
+    let cached = nr_cached_pages();
+    // Like `/proc/meminfo`, the page cache can be reclaimed, so it is available as well.
+    let available = (free + cached).min(total);
+
+    [
+        (BalloonStatTag::VIRTIO_BALLOON_S_MEMTOT, total),
+        (BalloonStatTag::VIRTIO_BALLOON_S_MEMFREE, free),
+        (BalloonStatTag::VIRTIO_BALLOON_S_AVAIL, available),
+        (BalloonStatTag::VIRTIO_BALLOON_S_CACHES, cached),
+    ]
+    .map(|(tag, nr_pages)| (tag, (nr_pages * PAGE_SIZE) as u64))
+}
+
+/// Deflates the balloon when the guest is out of memory, which is allowed by the device
+/// with `VIRTIO_BALLOON_F_DEFLATE_ON_OOM`.
+struct BalloonShrinker {
+    device: Arc<BalloonDevice>,
+}
+
+impl reclaim::Shrinker for BalloonShrinker {
+    fn nr_reclaimable(&self) -> usize {
+        self.device.nr_pages()
+    }
+
+    fn shrink(&self, nr_to_reclaim: usize) -> usize {
+        let nr_deflated = self.device.deflate(nr_to_reclaim);
+        MEMORY_NOTIFIER_CHAIN.notify(&MemoryEvent::Online {
+            nr_pages: nr_deflated,
+        });
+        nr_deflated
+    }
+}
@@ -0,0 +1,478 @@
+// SPDX-License-Identifier: MPL-2.0
+
+//! The data access monitor (DAMON).
+//!
+//! The monitor estimates how frequently the regions of the address spaces of the monitored
+//! processes are accessed, with an overhead that does not grow with the sizes of the address
+//! spaces. Like DAMON in Linux, each address space is split into regions, and the accessed
+//! bit of one random page of each region is checked every sampling interval, assuming that
+//! all the pages of a region are accessed similarly. The number of the accesses found in a
+//! region in an aggregation interval is the heat of the region. After each aggregation
+//! interval, the adjacent regions with similar heat are merged, and the regions are split
+//! randomly if there are few of them, so that the regions adapt to the access pattern.
+//!
+//! The heat of the regions is exported via `/proc/[pid]/damon_regions`, where writing `1`
+//! starts monitoring the process and writing `0` stops. The swap shrinker swaps out the
+//! pages of the cold regions, i.e., those that have not been accessed for a while, before
+//! it scans the other pages.
+//!
+//! FIXME: The accessed bits are taken from the page tables, so the sampled pages look like
+//! that they are not accessed recently to the reclaimers. Linux remembers the taken bits in
+//! the page metadata to avoid this.
+
+use core::{
+    sync::atomic::{AtomicUsize, Ordering},
+    time::Duration,
+};
+
+use align_ext::AlignExt;
+use aster_frame::sync::WaitQueue;
+use spin::Once;
+
+use crate::{
+    prelude::*,
+    process::Process,
+    thread::{
+        kernel_thread::{KernelThreadExt, ThreadOptions},
+        Thread,
+    },
+    time::{clocks::JiffiesClock, wait::WaitTimeout},
+    util::random::getrandom,
+};
+
+/// The interval between two checks of the accessed bits.
+const SAMPLE_INTERVAL: Duration = Duration::from_millis(5);
+/// The interval between two aggregations of the accesses of the regions.
+const AGGR_INTERVAL: Duration = Duration::from_millis(100);
+/// The interval between two updates of the regions from the mappings of the processes.
+const UPDATE_INTERVAL: Duration = Duration::from_secs(1);
+/// The maximum number of the accesses of a region in an aggregation interval.
+const MAX_NR_ACCESSES: u32 = (AGGR_INTERVAL.as_millis() / SAMPLE_INTERVAL.as_millis()) as u32;
+/// The minimum number of the regions of a process.
+const MIN_NR_REGIONS: usize = 10;
+/// The maximum number of the regions of a process.
+const MAX_NR_REGIONS: usize = 1000;
+/// The number of the aggregation intervals in which a region is not accessed before it is
+/// considered cold.
+const COLD_MIN_AGE: u32 = 20;
+
+/// A region of an address space, and its heat.
+#[derive(Debug, Clone)]
+pub struct Region {
+    range: Range<Vaddr>,
+    /// The address of the page whose accessed bit is checked in this sampling interval.
+    sampling_addr: Vaddr,
+    /// The number of the accesses found in this aggregation interval.
+    nr_accesses: u32,
+    /// The number of the accesses found in the last aggregation interval.
+    last_nr_accesses: u32,
+    /// The number of the aggregation intervals for which the heat has not changed much.
+    age: u32,
+}
+
+impl Region {
+    fn new(range: Range<Vaddr>) -> Self {
+        Self {
+            sampling_addr: range.start,
+            range,
+            nr_accesses: 0,
+            last_nr_accesses: 0,
+            age: 0,
+        }
+    }
+
+    pub fn range(&self) -> &Range<Vaddr> {
+        &self.range
+    }
+
+    /// Returns the number of the accesses found in the last aggregation interval, which is
+    /// at most the number of the sampling intervals in an aggregation interval.
+    pub fn nr_accesses(&self) -> u32 {
+        self.last_nr_accesses
+    }
+
+    /// Returns the number of the aggregation intervals for which the heat has not changed
+    /// much.
+    pub fn age(&self) -> u32 {
+        self.age
+    }
+
+    fn is_cold(&self) -> bool {
+        self.last_nr_accesses == 0 && self.age >= COLD_MIN_AGE
+    }
+
+    fn nr_pages(&self) -> usize {
+        self.range.len() / PAGE_SIZE
+    }
+}
+
+/// A monitored process.
+struct Target {
+    process: Weak<Process>,
+    regions: Vec<Region>,
+}
+
+/// The monitored processes.
+static TARGETS: Mutex<Vec<Target>> = Mutex::new(Vec::new());
+/// The number of the monitored processes, which can be read without locking `TARGETS`.
+static NR_TARGETS: AtomicUsize = AtomicUsize::new(0);
+static KDAMOND_WAIT_QUEUE: WaitQueue = WaitQueue::new();
+static KDAMOND: Once<()> = Once::new();
+
+/// Starts monitoring the accesses of the process.
+pub fn start_monitoring(process: &Arc<Process>) {
+    let mut targets = TARGETS.lock();
+    if targets
+        .iter()
+        .any(|target| Weak::as_ptr(&target.process) == Arc::as_ptr(process))
+    {
+        return;
+    }
+    let regions = initial_regions(process);
+    targets.push(Target {
+        process: Arc::downgrade(process),
+        regions,
+    });
+    NR_TARGETS.store(targets.len(), Ordering::Relaxed);
+    drop(targets);
+
+    KDAMOND.call_once(spawn_kdamond);
+    KDAMOND_WAIT_QUEUE.wake_all();
+}
+
+/// Stops monitoring the accesses of the process.
+pub fn stop_monitoring(process: &Arc<Process>) {
+    let mut targets = TARGETS.lock();
+    targets.retain(|target| Weak::as_ptr(&target.process) != Arc::as_ptr(process));
+    NR_TARGETS.store(targets.len(), Ordering::Relaxed);
+}
+
+/// Returns the regions of the process with their heat, or `None` if the process is not
+/// monitored.
+pub fn regions_of(process: &Arc<Process>) -> Option<Vec<Region>> {
+    TARGETS
+        .lock()
+        .iter()
+        .find(|target| Weak::as_ptr(&target.process) == Arc::as_ptr(process))
+        .map(|target| target.regions.clone())
+}
+
+/// Returns the cold regions of the monitored processes, without blocking.
+pub(super) fn cold_regions() -> Vec<(Arc<Process>, Range<Vaddr>)> {
+    let Some(targets) = TARGETS.try_lock() else {
+        return Vec::new();
+    };
+    let mut cold_regions = Vec::new();
+    for target in targets.iter() {
+        let Some(process) = target.process.upgrade() else {
+            continue;
+        };
+        for region in target.regions.iter().filter(|region| region.is_cold()) {
+            cold_regions.push((process.clone(), region.range.clone()));
+        }
+    }
+    cold_regions
+}
+
+fn spawn_kdamond() {
+    let task_fn = || {
+        let mut rng = Rng::new();
+        let mut next_aggr_at = now() + AGGR_INTERVAL;
+        let mut next_update_at = now() + UPDATE_INTERVAL;
+        loop {
+            KDAMOND_WAIT_QUEUE
+                .wait_until(|| (NR_TARGETS.load(Ordering::Relaxed) > 0).then_some(()));
+            KDAMOND_WAIT_QUEUE.wait_until_or_timeout(|| None::<()>, &SAMPLE_INTERVAL);
+
+            let now = now();
+            let mut targets = TARGETS.lock();
+            // The exited processes are no longer monitored.
+            targets.retain(|target| {
+                target
+                    .process
+                    .upgrade()
+                    .is_some_and(|process| !process.is_zombie())
+            });
+            NR_TARGETS.store(targets.len(), Ordering::Relaxed);
+            for target in targets.iter_mut() {
+                let process = target.process.upgrade().unwrap();
+                check_accesses(&process, &mut target.regions, &mut rng);
+                if now >= next_aggr_at {
+                    aggregate(&mut target.regions, &mut rng);
+                }
+                if now >= next_update_at {
+                    update_regions(&process, &mut target.regions);
+                }
+            }
+            if now >= next_aggr_at {
+                next_aggr_at = now + AGGR_INTERVAL;
+            }
+            if now >= next_update_at {
+                next_update_at = now + UPDATE_INTERVAL;
+            }
+        }
+    };
+
+    Thread::spawn_kernel_thread(ThreadOptions::new(task_fn));
+}
+
+/// Checks whether the sampled pages of the regions have been accessed, and samples
+/// another page of each region for the next check.
+fn check_accesses(process: &Process, regions: &mut [Region], rng: &mut Rng) {
+    let vm_space = process.root_vmar().vm_space();
+    for region in regions.iter_mut() {
+        let sampling_page = region.sampling_addr..region.sampling_addr + PAGE_SIZE;
+        if vm_space
+            .take_accessed(&sampling_page)
+            .is_ok_and(|taken| !taken.is_empty())
+        {
+            region.nr_accesses += 1;
+        }
+
+        region.sampling_addr = region.range.start + rng.below(region.nr_pages()) * PAGE_SIZE;
+        // The accesses before the sampling interval are not counted.
+        let sampling_page = region.sampling_addr..region.sampling_addr + PAGE_SIZE;
+        let _ = vm_space.take_accessed(&sampling_page);
+    }
+}
+
+/// Ends the aggregation interval, and adjusts the regions for the next one.
+fn aggregate(regions: &mut Vec<Region>, rng: &mut Rng) {
+    for region in regions.iter_mut() {
+        if region.nr_accesses.abs_diff(region.last_nr_accesses) <= merge_threshold() {
+            region.age += 1;
+        } else {
+            region.age = 0;
+        }
+        region.last_nr_accesses = region.nr_accesses;
+        region.nr_accesses = 0;
+    }
+
+    merge_regions(regions);
+    if regions.len() < MAX_NR_REGIONS / 2 {
+        split_regions(regions, rng);
+    }
+}
+
+/// Returns the maximum difference of the accesses of two regions that can be merged.
+fn merge_threshold() -> u32 {
+    MAX_NR_ACCESSES / 10
+}
+
+/// Merges the adjacent regions with similar heat.
+fn merge_regions(regions: &mut Vec<Region>) {
+    // The merged regions are not larger than this, so that there are at least
+    // `MIN_NR_REGIONS` regions.
+    let total_size: usize = regions.iter().map(|region| region.range.len()).sum();
+    let max_size = (total_size / MIN_NR_REGIONS).max(PAGE_SIZE);
+
+    let mut merged: Vec<Region> = Vec::with_capacity(regions.len());
+    for region in regions.drain(..) {
+        if let Some(last) = merged.last_mut()
+            && last.range.end == region.range.start
+            && last.last_nr_accesses.abs_diff(region.last_nr_accesses) <= merge_threshold()
+            && last.range.len() + region.range.len() <= max_size
+        {
+            // The heat and the age of the merged region are weighted by the sizes.
+            let (last_pages, pages) = (last.nr_pages() as u64, region.nr_pages() as u64);
+            let weighted = |a: u32, b: u32| {
+                ((a as u64 * last_pages + b as u64 * pages) / (last_pages + pages)) as u32
+            };
+            last.last_nr_accesses = weighted(last.last_nr_accesses, region.last_nr_accesses);
+            last.age = weighted(last.age, region.age);
+            last.range.end = region.range.end;
+            continue;
+        }
+        merged.push(region);
+    }
+    *regions = merged;
+}
+
+/// Splits each region into two at a random page.
+fn split_regions(regions: &mut Vec<Region>, rng: &mut Rng) {
+    let mut split = Vec::with_capacity(regions.len() * 2);
+    for mut region in regions.drain(..) {
+        if region.nr_pages() < 2 {
+            split.push(region);
+            continue;
+        }
+        let at = region.range.start + (1 + rng.below(region.nr_pages() - 1)) * PAGE_SIZE;
+        let mut right = region.clone();
+        right.range.start = at;
+        right.sampling_addr = at;
+        region.range.end = at;
+        region.sampling_addr = region.range.start;
+        split.push(region);
+        split.push(right);
+    }
+    *regions = split;
+}
+
+/// Updates the regions to cover the mappings of the process, which keeps the heat of the
+/// existing regions.
+fn update_regions(process: &Process, regions: &mut Vec<Region>) {
+    let mut updated = Vec::new();
+    for mapping in process.root_vmar().mappings() {
+        let mapping_range = mapping.range();
+        let mut next_addr = mapping_range.start;
+        for region in regions.iter() {
+            let start = region.range.start.max(next_addr);
+            let end = region.range.end.min(mapping_range.end);
+            if start >= end {
+                continue;
+            }
+            if next_addr < start {
+                updated.push(Region::new(next_addr..start));
+            }
+            let mut region = region.clone();
+            region.range = start..end;
+            if !region.range.contains(&region.sampling_addr) {
+                region.sampling_addr = start;
+            }
+            updated.push(region);
+            next_addr = end;
+        }
+        if next_addr < mapping_range.end {
+            updated.push(Region::new(next_addr..mapping_range.end));
+        }
+    }
+    *regions = updated;
+}
+
+/// Returns the initial regions of the process, which split each mapping evenly.
+fn initial_regions(process: &Process) -> Vec<Region> {
+    let mappings = process.root_vmar().mappings();
+    let total_size: usize = mappings.iter().map(|mapping| mapping.range().len()).sum();
+    let region_size = (total_size / MIN_NR_REGIONS)
+        .align_up(PAGE_SIZE)
+        .max(PAGE_SIZE);
+
+    let mut regions = Vec::new();
+    for mapping in mappings {
+        let range = mapping.range();
+        let mut start = range.start;
+        while start < range.end {
+            let end = (start + region_size).min(range.end);
+            regions.push(Region::new(start..end));
+            start = end;
+        }
+    }
+    regions
+}
+
+fn now() -> Duration {
+    JiffiesClock::elapsed()
+}
+
+/// A xorshift pseudorandom number generator, which picks the sampled pages much faster
+/// than the cryptographically secure one.
+struct Rng(u64);
+
+impl Rng {
+    fn new() -> Self {
+        let mut seed = [0u8; 8];
+        let _ = getrandom(&mut seed);
+        // The state must not be zero.
+        Self(u64::from_ne_bytes(seed) | 1)
+    }
+
+    /// Returns a random number below `bound`, which must not be zero.
+    fn below(&mut self, bound: usize) -> usize {
+        self.0 ^= self.0 << 13;
+        self.0 ^= self.0 >> 7;
+        self.0 ^= self.0 << 17;
+        (self.0 % bound as u64) as usize
+    }
+}
+
+#[cfg(ktest)]
+mod test {
+    use super::*;
+
+    const BASE: Vaddr = 0x1000_0000;
+
+    fn region(pages: Range<usize>, last_nr_accesses: u32, age: u32) -> Region {
+        let mut region = Region::new(BASE + pages.start * PAGE_SIZE..BASE + pages.end * PAGE_SIZE);
+        region.last_nr_accesses = last_nr_accesses;
+        region.age = age;
+        region
+    }
+
+    /// Asserts that the regions are sorted and cover `range` without holes.
+    fn assert_cover(regions: &[Region], range: Range<Vaddr>) {
+        let mut next_addr = range.start;
+        for region in regions {
+            assert_eq!(region.range.start, next_addr);
+            assert!(region.range.start < region.range.end);
+            assert!(region.range.contains(&region.sampling_addr));
+            next_addr = region.range.end;
+        }
+        assert_eq!(next_addr, range.end);
+    }
+
+    #[ktest]
+    fn merge_adjacent_regions_with_similar_heat() {
+        let mut regions = vec![
+            region(0..4, 0, 4),
+            region(4..8, 2, 0),
+            region(8..12, 10, 3),
+            // A distant region that makes the merged regions no larger than 13 pages.
+            region(1000..1118, 10, 3),
+        ];
+        merge_regions(&mut regions);
+
+        assert_eq!(regions.len(), 3);
+        assert_eq!(regions[0].range, region(0..8, 0, 0).range);
+        assert_eq!(regions[0].last_nr_accesses, 1);
+        assert_eq!(regions[0].age, 2);
+        // The heat differs too much.
+        assert_eq!(regions[1].range, region(8..12, 0, 0).range);
+        // The regions are not adjacent.
+        assert_eq!(regions[2].range, region(1000..1118, 0, 0).range);
+
+        // The merged regions are no larger than a tenth of the total size.
+        let mut regions: Vec<_> = (0..20).map(|page| region(page..page + 1, 0, 0)).collect();
+        merge_regions(&mut regions);
+        assert_eq!(regions.len(), 10);
+        assert!(regions.iter().all(|region| region.nr_pages() == 2));
+    }
+
+    #[ktest]
+    fn split_regions_keep_heat() {
+        let mut rng = Rng::new();
+        let mut regions = vec![region(0..1, 5, 1), region(1..9, 7, 2)];
+        split_regions(&mut regions, &mut rng);
+
+        assert_eq!(regions.len(), 3);
+        assert_cover(&regions, BASE..BASE + 9 * PAGE_SIZE);
+        assert_eq!(regions[0].last_nr_accesses, 5);
+        for region in &regions[1..] {
+            assert_eq!(region.last_nr_accesses, 7);
+            assert_eq!(region.age, 2);
+        }
+    }
+
+    #[ktest]
+    fn unaccessed_regions_become_cold() {
+        let mut rng = Rng::new();
+        let mut regions = vec![region(0..64, 0, 0)];
+        for _ in 0..COLD_MIN_AGE {
+            assert!(regions.iter().all(|region| !region.is_cold()));
+            aggregate(&mut regions, &mut rng);
+            assert_cover(&regions, BASE..BASE + 64 * PAGE_SIZE);
+        }
+        assert!(regions.iter().all(|region| region.is_cold()));
+
+        // The regions that are accessed again are no longer cold.
+        for region in regions.iter_mut() {
+            region.nr_accesses = MAX_NR_ACCESSES;
+        }
+        aggregate(&mut regions, &mut rng);
+        assert_cover(&regions, BASE..BASE + 64 * PAGE_SIZE);
+        for region in regions.iter() {
+            assert_eq!(region.nr_accesses(), MAX_NR_ACCESSES);
+            assert_eq!(region.age(), 0);
+            assert!(!region.is_cold());
+        }
+    }
+}
@@ -0,0 +1,50 @@
+// SPDX-License-Identifier: MPL-2.0
+
+//! Migration of anonymous pages for memory compaction.
+//!
+//! When the frame allocator compacts the memory to create a contiguous free block, the
+//! migrator of this module moves the anonymous pages of the processes out of the block.
+//! A page is unmapped from all the address spaces with the TLB entries shot down, copied
+//! to a new frame, and replaced by the new frame in its VMO, so the next page faults on
+//! the page map the new frame.
+//!
+//! The file-backed pages are not migrated, since they can be reclaimed by the shrinker of
+//! the page cache instead.
+
+use core::ops::Range;
+
+use aster_frame::mm::{
+    compaction::{register_migrator, Migrator},
+    Frame, Paddr,
+};
+use spin::Once;
+
+use crate::{prelude::*, process::process_table};
+
+struct AnonMigrator;
+
+impl Migrator for AnonMigrator {
+    fn migrate(&self, range: &Range<Paddr>, alloc: &mut dyn FnMut() -> Option<Frame>) -> usize {
+        // Do not wait for the process table, which may be locked by the allocating thread.
+        let Some(processes) = process_table::try_process_table()
+            .map(|table| table.iter().cloned().collect::<Vec<_>>())
+        else {
+            return 0;
+        };
+
+        processes
+            .iter()
+            .map(|process| process.root_vmar().migrate_pages(range, alloc))
+            .sum()
+    }
+}
+
+static ANON_MIGRATOR: Once<Arc<AnonMigrator>> = Once::new();
+
+pub(super) fn register_anon_migrator() {
+    ANON_MIGRATOR.call_once(|| {
+        let migrator = Arc::new(AnonMigrator);
+        register_migrator(Arc::downgrade(&migrator) as _);
+        migrator
+    });
+}
@@ -16,7 +16,47 @@
 //! In Asterinas, VMARs and VMOs, as well as other capabilities, are implemented
 //! as zero-cost capabilities.
 
+#[cfg(feature = "virtio-balloon")]
+mod balloon;
+pub mod damon;
+mod migration;
+pub mod oom;
 pub mod page_fault_handler;
 pub mod perms;
+pub mod pkey;
+mod reclaimer;
+mod scrubber;
+pub mod swap;
+pub mod userfault;
 pub mod vmar;
 pub mod vmo;
+
+use crate::events::NotifierChain;
+
+/// The events of the memory hotplug, e.g., when the pages are taken by or given back to a
+/// balloon device.
+///
+/// A notifier may veto a `GoingOnline` or `GoingOffline` event. The operation is then
+/// canceled, which is notified with a `CancelOnline` or `CancelOffline` event.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MemoryEvent {
+    GoingOnline { nr_pages: usize },
+    Online { nr_pages: usize },
+    CancelOnline { nr_pages: usize },
+    GoingOffline { nr_pages: usize },
+    Offline { nr_pages: usize },
+    CancelOffline { nr_pages: usize },
+}
+
+/// The notifier chain that is notified of the memory hotplug events.
+pub static MEMORY_NOTIFIER_CHAIN: NotifierChain<MemoryEvent> = NotifierChain::new();
+
+/// Lazy init should be called after spawning init thread.
+pub fn lazy_init() {
+    reclaimer::spawn_reclaimer_thread();
+    oom::register_oom_killer();
+    scrubber::spawn_scrubber_thread();
+    migration::register_anon_migrator();
+    #[cfg(feature = "virtio-balloon")]
+    balloon::spawn_balloon_threads();
+}
@@ -0,0 +1,177 @@
+// SPDX-License-Identifier: MPL-2.0
+
+//! The out-of-memory (OOM) killer.
+//!
+//! When a frame allocation still fails after the memory is reclaimed, the OOM killer
+//! selects the process with the highest badness score and kills it with `SIGKILL`, so
+//! that its memory is freed once it exits. The badness score of a process is its resident
+//! set size (RSS), adjusted by its `oom_score_adj` in the unit of 1/1000 of the memory,
+//! like Linux. A process with an `oom_score_adj` of [`OOM_SCORE_ADJ_MIN`] is never killed.
+//!
+//! The allocating thread waits for the victim to exit in bounded intervals, and retries the
+//! allocation after each of them. The wait is interrupted if the thread itself is killed.
+//! If the victim has not exited after [`VICTIM_TIMEOUT`], e.g., because it is waiting for
+//! memory as well, another victim is selected.
+
+use core::{sync::atomic::Ordering, time::Duration};
+
+use aster_frame::mm::{
+    nr_total_frames,
+    reclaim::{register_oom_handler, OomVerdict},
+};
+
+use super::swap::swap_areas_info;
+use crate::{
+    prelude::*,
+    process::{
+        posix_thread::PosixThreadExt,
+        process_table,
+        signal::{constants::SIGKILL, sig_mask::SigMask, signals::kernel::KernelSignal, Pauser},
+        Process,
+    },
+    time::{clocks::MonotonicClock, Clock},
+    vm::vmar::RssType,
+};
+
+/// The minimum of `oom_score_adj`, which disables the OOM killing of the process.
+pub const OOM_SCORE_ADJ_MIN: i16 = -1000;
+/// The maximum of `oom_score_adj`.
+pub const OOM_SCORE_ADJ_MAX: i16 = 1000;
+
+/// The interval after which the allocation is retried while the victim is exiting.
+const VICTIM_WAIT_INTERVAL: Duration = Duration::from_millis(100);
+/// The time after which a victim that has not exited is considered stuck.
+const VICTIM_TIMEOUT: Duration = Duration::from_secs(1);
+
+/// A killed process, which may still be exiting.
+struct Victim {
+    process: Weak<Process>,
+    killed_at: Duration,
+}
+
+/// The killed processes that may still be exiting.
+static VICTIMS: Mutex<Vec<Victim>> = Mutex::new(Vec::new());
+
+lazy_static! {
+    /// The pauser to wait for a victim to exit, which is interrupted only by `SIGKILL`.
+    static ref VICTIM_PAUSER: Arc<Pauser> = {
+        let mut sig_mask = SigMask::new_full();
+        sig_mask.remove_signal(SIGKILL);
+        Pauser::new_with_mask(sig_mask)
+    };
+}
+
+pub(super) fn register_oom_killer() {
+    register_oom_handler(&out_of_memory);
+}
+
+fn out_of_memory(nframes: usize) -> OomVerdict {
+    let Some(victim_process) = select_victim(nframes) else {
+        return OomVerdict::NoVictim;
+    };
+
+    let is_current = current_thread!()
+        .as_posix_thread()
+        .is_some_and(|posix_thread| Arc::ptr_eq(&posix_thread.process(), &victim_process));
+    if is_current {
+        return OomVerdict::CurrentKilled;
+    }
+
+    // The victim frees its memory once it is reaped.
+    let victim = Arc::downgrade(&victim_process);
+    drop(victim_process);
+    let res = VICTIM_PAUSER.pause_until_or_timeout(
+        || (victim.strong_count() == 0).then_some(()),
+        &VICTIM_WAIT_INTERVAL,
+    );
+    match res {
+        Err(err) if err.error() == Errno::EINTR => OomVerdict::CurrentKilled,
+        _ => OomVerdict::Retry,
+    }
+}
+
+/// Returns the victim to wait for, which is killed if it is newly selected.
+fn select_victim(nframes: usize) -> Option<Arc<Process>> {
+    let mut victims = VICTIMS.lock();
+    let now = MonotonicClock::get().read_time();
+    victims.retain(|victim| victim.process.strong_count() > 0);
+    // Wait for the last victim instead of killing another process, unless it is stuck.
+    if let Some(victim) = victims.last()
+        && now.saturating_sub(victim.killed_at) < VICTIM_TIMEOUT
+        && let Some(process) = victim.process.upgrade()
+    {
+        return Some(process);
+    }
+
+    let total_pages = total_pages();
+    // Do not wait for the process table, which may be locked by the allocating thread.
+    let candidates = process_table::try_process_table().map(|table| {
+        table
+            .iter()
+            .filter(|process| {
+                !victims
+                    .iter()
+                    .any(|victim| victim.process.as_ptr() == Arc::as_ptr(process))
+            })
+            .filter_map(|process| Some((badness(process, total_pages)?, process.clone())))
+            .collect::<Vec<_>>()
+    })?;
+    let Some((points, victim)) = candidates.into_iter().max_by_key(|(points, _)| *points) else {
+        error!(
+            "out of memory: {} frames cannot be allocated and no process can be killed",
+            nframes
+        );
+        return None;
+    };
+
+    let rss = victim.root_vmar().rss();
+    error!(
+        "out of memory: killed process {} ({}) with score {}, anon-rss: {} kB, file-rss: {} kB, \
+         shmem-rss: {} kB, oom_score_adj: {}",
+        victim.pid(),
+        victim.executable_path(),
+        points,
+        rss.get(RssType::Anon) * PAGE_SIZE / 1024,
+        rss.get(RssType::File) * PAGE_SIZE / 1024,
+        rss.get(RssType::Shmem) * PAGE_SIZE / 1024,
+        victim.oom_score_adj().load(Ordering::Relaxed)
+    );
+    victim.enqueue_signal(KernelSignal::new(SIGKILL));
+    victims.push(Victim {
+        process: Arc::downgrade(&victim),
+        killed_at: now,
+    });
+    Some(victim)
+}
+
+/// Returns the number of pages that processes can use, including the swap areas.
+fn total_pages() -> usize {
+    let nr_swap_pages: usize = swap_areas_info().iter().map(|info| info.nr_pages).sum();
+    nr_total_frames() + nr_swap_pages
+}
+
+/// Returns the badness score of the process, or `None` if it cannot be killed.
+///
+/// The score does not count the swapped-out pages, because they cannot be counted
+/// without locking the address space, which may be locked by the allocating thread.
+fn badness(process: &Process, total_pages: usize) -> Option<isize> {
+    let oom_score_adj = process.oom_score_adj().load(Ordering::Relaxed);
+    if process.is_init_process() || process.is_zombie() || oom_score_adj == OOM_SCORE_ADJ_MIN {
+        return None;
+    }
+
+    let rss = process.root_vmar().rss().total() as isize;
+    let adj = oom_score_adj as isize * (total_pages / 1000) as isize;
+    // A process that can be killed has a positive score.
+    Some((rss + adj).max(1))
+}
+
+/// Returns the OOM score of the process in `/proc/[pid]/oom_score`, which is the badness
+/// score scaled like Linux.
+pub fn oom_score(process: &Process) -> usize {
+    let total_pages = total_pages();
+    let Some(points) = badness(process, total_pages) else {
+        return 0;
+    };
+    ((1000 + points * 1000 / total_pages as isize) * 2 / 3).max(0) as usize
+}
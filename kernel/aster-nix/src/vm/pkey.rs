@@ -0,0 +1,131 @@
+// SPDX-License-Identifier: MPL-2.0
+
+//! Memory protection keys (pkeys).
+//!
+//! Each page of a mapping is tagged with a protection key. If the CPU supports protection
+//! keys, the accesses to the page from the user mode are further restricted by the access
+//! rights of the key in the PKRU register, which is per-thread and can be changed by the
+//! user mode without system calls. This allows a process to revoke its own accesses to
+//! some memory cheaply, e.g., for the isolation inside language runtimes.
+//!
+//! The keys are allocated per address space with `pkey_alloc`, and assigned to the pages
+//! with `pkey_mprotect`. Key 0 is the default key of all pages, which is always allocated.
+
+use aster_frame::cpu::{has_pkeys, NR_PKEYS};
+use bitflags::bitflags;
+
+use crate::prelude::*;
+
+bitflags! {
+    /// The access rights of a protection key in the PKRU register.
+    pub struct PkeyAccess: u32 {
+        /// Disables all the data accesses.
+        const DISABLE_ACCESS = 1 << 0;
+        /// Disables the writes.
+        const DISABLE_WRITE = 1 << 1;
+    }
+}
+
+/// Returns the value of the PKRU register with the access rights of `pkey` replaced by `access`.
+pub fn set_pkey_access(pkru: u32, pkey: u8, access: PkeyAccess) -> u32 {
+    let shift = 2 * pkey as u32;
+    (pkru & !(PkeyAccess::all().bits() << shift)) | (access.bits() << shift)
+}
+
+/// The allocated protection keys of an address space.
+pub struct PkeyAllocator {
+    /// The bitmap of the allocated keys.
+    allocated: SpinLock<u16>,
+}
+
+impl PkeyAllocator {
+    /// Creates an allocator with only the default key allocated.
+    pub fn new() -> Self {
+        Self {
+            allocated: SpinLock::new(1),
+        }
+    }
+
+    /// Allocates a free protection key.
+    ///
+    /// Fails with `ENOSPC` if all the keys are allocated or protection keys are not
+    /// supported.
+    pub fn alloc(&self) -> Result<u8> {
+        if !has_pkeys() {
+            return_errno_with_message!(Errno::ENOSPC, "protection keys are not supported");
+        }
+        let mut allocated = self.allocated.lock();
+        let pkey = allocated.trailing_ones() as usize;
+        if pkey >= NR_PKEYS {
+            return_errno_with_message!(Errno::ENOSPC, "all the protection keys are allocated");
+        }
+        *allocated |= 1 << pkey;
+        Ok(pkey as u8)
+    }
+
+    /// Frees an allocated protection key.
+    ///
+    /// The pages that are tagged with the key keep the key, so the key should not be
+    /// used by any pages when it is freed.
+    pub fn free(&self, pkey: u8) -> Result<()> {
+        if pkey == 0 || !self.is_allocated(pkey) {
+            return_errno_with_message!(Errno::EINVAL, "the protection key is not allocated");
+        }
+        *self.allocated.lock() &= !(1 << pkey);
+        Ok(())
+    }
+
+    /// Returns whether the protection key is allocated and can be assigned to pages.
+    pub fn is_allocated(&self, pkey: u8) -> bool {
+        has_pkeys() && (pkey as usize) < NR_PKEYS && *self.allocated.lock() & (1 << pkey) != 0
+    }
+
+    /// Frees all the keys except the default one.
+    pub fn reset(&self) {
+        *self.allocated.lock() = 1;
+    }
+}
+
+impl Default for PkeyAllocator {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Clone for PkeyAllocator {
+    fn clone(&self) -> Self {
+        Self {
+            allocated: SpinLock::new(*self.allocated.lock()),
+        }
+    }
+}
+
+#[cfg(ktest)]
+mod test {
+    use super::*;
+
+    #[ktest]
+    fn pkru_access_bits() {
+        let pkru = set_pkey_access(0, 3, PkeyAccess::DISABLE_WRITE);
+        assert_eq!(pkru, 0b10 << 6);
+        let pkru = set_pkey_access(pkru, 3, PkeyAccess::DISABLE_ACCESS);
+        assert_eq!(pkru, 0b01 << 6);
+        assert_eq!(set_pkey_access(pkru, 3, PkeyAccess::empty()), 0);
+    }
+
+    #[ktest]
+    fn alloc_and_free() {
+        let pkeys = PkeyAllocator::new();
+        assert!(pkeys.free(0).is_err());
+        if !has_pkeys() {
+            assert!(pkeys.alloc().is_err());
+            return;
+        }
+        let allocated: Vec<u8> = (1..NR_PKEYS).map(|_| pkeys.alloc().unwrap()).collect();
+        assert_eq!(allocated, (1..NR_PKEYS as u8).collect::<Vec<_>>());
+        assert!(pkeys.alloc().is_err());
+        pkeys.free(5).unwrap();
+        assert!(pkeys.free(5).is_err());
+        assert_eq!(pkeys.alloc().unwrap(), 5);
+    }
+}
@@ -0,0 +1,38 @@
+// SPDX-License-Identifier: MPL-2.0
+
+//! The background memory reclaimer.
+//!
+//! The reclaimer sleeps until the frame allocator reports that the number of
+//! free frames has fallen below the low watermark. Then it invokes the
+//! registered shrinkers to bring the number of free frames back to the high
+//! watermark, so that most allocations never need to reclaim memory themselves.
+
+use aster_frame::mm::{nr_free_frames, reclaim};
+
+use crate::{
+    prelude::*,
+    thread::{
+        kernel_thread::{KernelThreadExt, ThreadOptions},
+        Thread,
+    },
+};
+
+pub(super) fn spawn_reclaimer_thread() {
+    let task_fn = || loop {
+        reclaim::wait_for_low_memory();
+
+        let nr_free = nr_free_frames();
+        let high_watermark = reclaim::high_watermark();
+        if nr_free >= high_watermark {
+            continue;
+        }
+
+        let nr_reclaimed = reclaim::reclaim(high_watermark - nr_free);
+        debug!(
+            "reclaimer: {} frames free, {} frames reclaimed",
+            nr_free, nr_reclaimed
+        );
+    };
+
+    Thread::spawn_kernel_thread(ThreadOptions::new(task_fn));
+}
@@ -0,0 +1,38 @@
+// SPDX-License-Identifier: MPL-2.0
+
+//! The background zero-page scrubber.
+//!
+//! The scrubber sleeps until the pool of pre-zeroed frames is drained by the
+//! allocations of zeroed frames. Then it zeroes free frames to refill the pool,
+//! so that most page faults of anonymous memory do not need to zero the new
+//! frames themselves. It runs with the lowest priority and yields the CPU after
+//! every batch of frames, to do the work only when the CPU is otherwise idle.
+
+use aster_frame::{mm::zeroed, task::Priority};
+
+use crate::{
+    prelude::*,
+    thread::{
+        kernel_thread::{KernelThreadExt, ThreadOptions},
+        Thread,
+    },
+};
+
+pub(super) fn spawn_scrubber_thread() {
+    let task_fn = || loop {
+        let nr_scrubbed = zeroed::scrub(|| {
+            Thread::yield_now();
+            false
+        });
+        debug!(
+            "scrubber: {} frames zeroed, {} frames in the pool",
+            nr_scrubbed,
+            zeroed::nr_zeroed_frames()
+        );
+
+        zeroed::wait_for_scrub();
+    };
+
+    let options = ThreadOptions::new(task_fn).priority(Priority::lowest());
+    Thread::spawn_kernel_thread(options);
+}
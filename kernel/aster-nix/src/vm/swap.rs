@@ -0,0 +1,417 @@
+// SPDX-License-Identifier: MPL-2.0
+
+//! Swapping of anonymous pages.
+//!
+//! A swap area is a block device or a regular file formatted by `mkswap`, which is
+//! enabled by `swapon` and disabled by `swapoff`. The first page of the area is the
+//! header, and each of the other pages is a slot that can hold a swapped-out page.
+//!
+//! Under memory pressure, the background reclaimer invokes the shrinker of this module,
+//! which scans the private anonymous mappings of the processes and writes the pages that
+//! are not accessed recently to the swap areas. A swapped-out page is replaced by its
+//! [`SwapSlot`] in the VMO, so the page fault on it reads the content back to a new frame
+//! when the VMO commits the page again.
+//!
+//! Other kernel objects may hold swap slots as well, e.g., the files of tmpfs, whose
+//! pages are written to the swap areas when their page caches are shrunk. They are
+//! registered as [`SwapUser`]s, so that their pages are read back when an area is
+//! disabled.
+
+use core::sync::atomic::{AtomicUsize, Ordering};
+
+use aster_block::{bio::BioStatus, id::Bid, BlockDevice};
+use aster_frame::mm::{
+    reclaim::{register_shrinker, Shrinker},
+    Frame, FrameAllocOptions, VmIo,
+};
+use spin::Once;
+
+use crate::{fs::utils::Inode, prelude::*, process::process_table};
+
+/// The maximum number of swap areas.
+const MAX_SWAP_AREAS: usize = 32;
+
+/// The magic at the end of the header page.
+const SWAP_MAGIC: &[u8] = b"SWAPSPACE2";
+/// The offset of the header information, which follows the boot sector.
+const SWAP_INFO_OFFSET: usize = 1024;
+/// The offset of the indexes of the bad pages.
+const SWAP_BADPAGES_OFFSET: usize = SWAP_INFO_OFFSET + 512;
+const MAX_SWAP_BADPAGES: usize = (PAGE_SIZE - SWAP_BADPAGES_OFFSET) / 4;
+
+/// The users of the swap slots other than the VMOs of the processes.
+static SWAP_USERS: SpinLock<Vec<Weak<dyn SwapUser>>> = SpinLock::new(Vec::new());
+
+/// The enabled swap areas, in the descending order of the priorities.
+static SWAP_AREAS: SpinLock<Vec<Arc<SwapArea>>> = SpinLock::new(Vec::new());
+/// The priority of the next swap area that is enabled without one, which decreases
+/// from -1 like Linux.
+static NEXT_PRIORITY: SpinLock<i16> = SpinLock::new(-1);
+
+/// The device or file that the swapped-out pages are written to.
+pub enum SwapBackend {
+    Partition(Arc<dyn BlockDevice>),
+    File(Arc<dyn Inode>),
+}
+
+// The pages are read and written with their frames directly, so that swapping out the
+// pages does not allocate any buffers when the memory is short.
+impl SwapBackend {
+    fn read_page(&self, idx: usize, frame: &Frame) -> Result<()> {
+        match self {
+            Self::Partition(device) => match device.read_block_sync(Bid::new(idx as u64), frame)? {
+                BioStatus::Complete => Ok(()),
+                err_status => Err(Error::from(err_status)),
+            },
+            Self::File(inode) => inode.read_page_direct(idx, frame),
+        }
+    }
+
+    fn write_page(&self, idx: usize, frame: &Frame) -> Result<()> {
+        match self {
+            Self::Partition(device) => {
+                match device.write_block_sync(Bid::new(idx as u64), frame)? {
+                    BioStatus::Complete => Ok(()),
+                    err_status => Err(Error::from(err_status)),
+                }
+            }
+            Self::File(inode) => inode.write_page_direct(idx, frame),
+        }
+    }
+
+    fn type_name(&self) -> &'static str {
+        match self {
+            Self::Partition(_) => "partition",
+            Self::File(_) => "file",
+        }
+    }
+}
+
+struct SwapArea {
+    path: String,
+    backend: SwapBackend,
+    priority: i16,
+    /// The number of usable slots, excluding the header and the bad pages.
+    nr_slots: usize,
+    slots: SpinLock<SlotMap>,
+}
+
+struct SlotMap {
+    /// Whether each page of the area is in use. The header and the bad pages are
+    /// always in use.
+    is_used: Vec<bool>,
+    /// The index where the search of a free slot starts.
+    next: usize,
+    /// The number of slots in use, excluding the header and the bad pages.
+    nr_used: usize,
+}
+
+impl SwapArea {
+    fn alloc_slot(&self) -> Option<usize> {
+        let mut slots = self.slots.lock();
+        if slots.nr_used == self.nr_slots {
+            return None;
+        }
+        let nr_pages = slots.is_used.len();
+        let idx = (slots.next..nr_pages)
+            .chain(1..slots.next)
+            .find(|&idx| !slots.is_used[idx])?;
+        slots.is_used[idx] = true;
+        slots.next = if idx + 1 < nr_pages { idx + 1 } else { 1 };
+        slots.nr_used += 1;
+        Some(idx)
+    }
+
+    fn free_slot(&self, idx: usize) {
+        let mut slots = self.slots.lock();
+        debug_assert!(slots.is_used[idx]);
+        slots.is_used[idx] = false;
+        slots.nr_used -= 1;
+    }
+
+    fn nr_used(&self) -> usize {
+        self.slots.lock().nr_used
+    }
+}
+
+/// A slot in a swap area that holds the content of a swapped-out page.
+///
+/// The slot is freed when it is dropped. It may be shared by the VMOs that are forked
+/// from each other, which read the content to their own frames respectively.
+pub struct SwapSlot {
+    area: Arc<SwapArea>,
+    idx: usize,
+}
+
+impl SwapSlot {
+    /// Reads the content of the swapped-out page to the frame.
+    pub fn read(&self, frame: &Frame) -> Result<()> {
+        self.area.backend.read_page(self.idx, frame)
+    }
+}
+
+impl Drop for SwapSlot {
+    fn drop(&mut self) {
+        self.area.free_slot(self.idx);
+    }
+}
+
+/// Writes the content of the frame to a free slot in the swap areas.
+///
+/// The areas with higher priorities are used first.
+pub fn swap_out(frame: &Frame) -> Result<Arc<SwapSlot>> {
+    let areas = SWAP_AREAS.lock().clone();
+    for area in areas {
+        let Some(idx) = area.alloc_slot() else {
+            continue;
+        };
+        let slot = SwapSlot { area, idx };
+        slot.area.backend.write_page(idx, frame)?;
+        return Ok(Arc::new(slot));
+    }
+    return_errno_with_message!(Errno::ENOSPC, "no free slot in the swap areas")
+}
+
+/// A holder of swap slots other than the VMOs of the processes.
+pub trait SwapUser: Send + Sync {
+    /// Reads the swapped-out pages whose slots satisfy `filter` back to memory.
+    fn swap_in(&self, filter: &dyn Fn(&SwapSlot) -> bool) -> Result<()>;
+}
+
+/// Registers a holder of swap slots, whose pages are read back by [`swap_off`].
+///
+/// Registering the same user more than once has no effect.
+pub fn register_swap_user(user: Weak<dyn SwapUser>) {
+    let mut users = SWAP_USERS.lock();
+    users.retain(|user| user.strong_count() > 0);
+    if !users.iter().any(|registered| registered.ptr_eq(&user)) {
+        users.push(user);
+    }
+}
+
+/// Enables the swap area formatted by `mkswap`.
+///
+/// The area is identified by `path` in `/proc/swaps` and when it is disabled. If the
+/// priority is not given, it is lower than that of all the areas enabled before.
+pub fn swap_on(path: String, backend: SwapBackend, priority: Option<i16>) -> Result<()> {
+    if SWAP_AREAS.lock().iter().any(|area| area.path == path) {
+        return_errno_with_message!(Errno::EBUSY, "the swap area is already enabled");
+    }
+
+    let mut header = vec![0u8; PAGE_SIZE];
+    match &backend {
+        SwapBackend::Partition(device) => device.read_bytes(0, &mut header)?,
+        SwapBackend::File(inode) => {
+            if inode.size() < PAGE_SIZE {
+                return_errno_with_message!(Errno::EINVAL, "the swap file is too small");
+            }
+            // The pages bypass the page cache from now on, so the cached data are written
+            // back first. The header is read in the same way as the pages, which also
+            // checks that the file system supports it.
+            inode.drop_cache(0..inode.size())?;
+            let frame = FrameAllocOptions::new(1).uninit(true).alloc_single()?;
+            inode.read_page_direct(0, &frame)?;
+            frame.read_bytes(0, &mut header)?;
+        }
+    }
+    if &header[PAGE_SIZE - SWAP_MAGIC.len()..] != SWAP_MAGIC {
+        return_errno_with_message!(Errno::EINVAL, "the swap area is not formatted");
+    }
+    let read_u32 =
+        |offset: usize| u32::from_ne_bytes(header[offset..offset + 4].try_into().unwrap()) as usize;
+    let version = read_u32(SWAP_INFO_OFFSET);
+    let last_page = read_u32(SWAP_INFO_OFFSET + 4);
+    let nr_badpages = read_u32(SWAP_INFO_OFFSET + 8);
+    if version != 1 {
+        return_errno_with_message!(Errno::EINVAL, "the version of the swap area is unsupported");
+    }
+    if nr_badpages > MAX_SWAP_BADPAGES {
+        return_errno_with_message!(Errno::EINVAL, "the swap area has too many bad pages");
+    }
+
+    let mut nr_pages = last_page + 1;
+    if let SwapBackend::File(inode) = &backend {
+        // Like Linux, the area is truncated to the size of the file.
+        nr_pages = nr_pages.min(inode.size() / PAGE_SIZE);
+        // The pages are written without allocating the blocks of the file.
+        if inode.seek_hole(0)? < nr_pages * PAGE_SIZE {
+            return_errno_with_message!(Errno::EINVAL, "the swap file has holes");
+        }
+    }
+    let mut is_used = vec![false; nr_pages];
+    is_used[0] = true;
+    for i in 0..nr_badpages {
+        let badpage = read_u32(SWAP_BADPAGES_OFFSET + i * 4);
+        if badpage == 0 || badpage > last_page {
+            return_errno_with_message!(Errno::EINVAL, "the bad page is out of the swap area");
+        }
+        if let Some(is_bad) = is_used.get_mut(badpage) {
+            *is_bad = true;
+        }
+    }
+    let nr_slots = is_used.iter().filter(|is_used| !**is_used).count();
+    if nr_slots == 0 {
+        return_errno_with_message!(Errno::EINVAL, "the swap area is empty");
+    }
+
+    let priority = priority.unwrap_or_else(|| {
+        let mut next_priority = NEXT_PRIORITY.lock();
+        let priority = *next_priority;
+        *next_priority = priority.saturating_sub(1);
+        priority
+    });
+    let area = Arc::new(SwapArea {
+        path,
+        backend,
+        priority,
+        nr_slots,
+        slots: SpinLock::new(SlotMap {
+            is_used,
+            next: 1,
+            nr_used: 0,
+        }),
+    });
+
+    {
+        let mut areas = SWAP_AREAS.lock();
+        if areas.iter().any(|enabled| enabled.path == area.path) {
+            return_errno_with_message!(Errno::EBUSY, "the swap area is already enabled");
+        }
+        if areas.len() >= MAX_SWAP_AREAS {
+            return_errno_with_message!(Errno::EPERM, "too many swap areas");
+        }
+        let pos = areas.partition_point(|enabled| enabled.priority >= area.priority);
+        areas.insert(pos, area);
+    }
+    swap_shrinker();
+    Ok(())
+}
+
+/// Disables the swap area after reading all its swapped-out pages back to memory.
+pub fn swap_off(path: &str) -> Result<()> {
+    let area = {
+        let mut areas = SWAP_AREAS.lock();
+        let Some(pos) = areas.iter().position(|area| area.path == path) else {
+            return_errno_with_message!(Errno::EINVAL, "the swap area is not enabled");
+        };
+        areas.remove(pos)
+    };
+
+    let processes: Vec<_> = process_table::process_table().iter().cloned().collect();
+    let in_area = |slot: &SwapSlot| Arc::ptr_eq(&slot.area, &area);
+    let mut result = Ok(());
+    for process in processes {
+        result = result.and(process.root_vmar().swap_in(&in_area));
+    }
+    let users: Vec<_> = SWAP_USERS.lock().iter().filter_map(Weak::upgrade).collect();
+    for user in users {
+        result = result.and(user.swap_in(&in_area));
+    }
+    if result.is_ok() && area.nr_used() > 0 {
+        result = Err(Error::with_message(
+            Errno::ENOMEM,
+            "the pages in the swap area cannot be read back",
+        ));
+    }
+
+    if result.is_err() {
+        // Keep the area enabled, so the pages that are still in it can be read later.
+        let mut areas = SWAP_AREAS.lock();
+        let pos = areas.partition_point(|enabled| enabled.priority >= area.priority);
+        areas.insert(pos, area);
+    }
+    result
+}
+
+/// The information of an enabled swap area, as shown in `/proc/swaps`.
+pub struct SwapAreaInfo {
+    pub path: String,
+    pub type_name: &'static str,
+    /// The number of usable pages.
+    pub nr_pages: usize,
+    /// The number of pages in use.
+    pub nr_used: usize,
+    pub priority: i16,
+}
+
+/// Returns the information of the enabled swap areas.
+pub fn swap_areas_info() -> Vec<SwapAreaInfo> {
+    SWAP_AREAS
+        .lock()
+        .iter()
+        .map(|area| SwapAreaInfo {
+            path: area.path.clone(),
+            type_name: area.backend.type_name(),
+            nr_pages: area.nr_slots,
+            nr_used: area.nr_used(),
+            priority: area.priority,
+        })
+        .collect()
+}
+
+/// Returns the number of free slots in all the swap areas.
+pub fn nr_free_slots() -> usize {
+    SWAP_AREAS
+        .lock()
+        .iter()
+        .map(|area| area.nr_slots - area.nr_used())
+        .sum()
+}
+
+/// Swaps out the anonymous pages that are not accessed recently under memory pressure.
+struct SwapShrinker {
+    /// The number of processes skipped at the beginning of the next scan, so that the
+    /// processes are scanned in turn.
+    scan_offset: AtomicUsize,
+}
+
+static SWAP_SHRINKER: Once<Arc<SwapShrinker>> = Once::new();
+
+fn swap_shrinker() -> &'static Arc<SwapShrinker> {
+    SWAP_SHRINKER.call_once(|| {
+        let shrinker = Arc::new(SwapShrinker {
+            scan_offset: AtomicUsize::new(0),
+        });
+        register_shrinker(Arc::downgrade(&shrinker) as _);
+        shrinker
+    })
+}
+
+impl Shrinker for SwapShrinker {
+    fn nr_reclaimable(&self) -> usize {
+        nr_free_slots()
+    }
+
+    fn shrink(&self, nr_to_reclaim: usize) -> usize {
+        // The cold regions found by the access monitor are swapped out first.
+        let mut nr_reclaimed = 0;
+        for (process, range) in super::damon::cold_regions() {
+            if nr_reclaimed >= nr_to_reclaim || nr_free_slots() == 0 {
+                return nr_reclaimed;
+            }
+            nr_reclaimed += process
+                .root_vmar()
+                .swap_out_range(&range, nr_to_reclaim - nr_reclaimed);
+        }
+
+        // Do not wait for the process table, which may be locked by the allocating thread.
+        let Some(mut processes) = process_table::try_process_table()
+            .map(|table| table.iter().cloned().collect::<Vec<_>>())
+        else {
+            return nr_reclaimed;
+        };
+        if processes.is_empty() {
+            return nr_reclaimed;
+        }
+        let offset = self.scan_offset.fetch_add(1, Ordering::Relaxed) % processes.len();
+        processes.rotate_left(offset);
+
+        for process in processes {
+            if nr_reclaimed >= nr_to_reclaim || nr_free_slots() == 0 {
+                break;
+            }
+            nr_reclaimed += process.root_vmar().swap_out(nr_to_reclaim - nr_reclaimed);
+        }
+        nr_reclaimed
+    }
+}
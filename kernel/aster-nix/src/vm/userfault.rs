@@ -0,0 +1,130 @@
+// SPDX-License-Identifier: MPL-2.0
+
+//! User page fault delegation.
+//!
+//! The page faults on the missing pages of the memory mappings registered with a
+//! [`UserfaultCtx`] are not resolved by the kernel. Instead, the faulting threads are
+//! paused and the faults are reported to user space as events, e.g., by reading a
+//! userfaultfd. The handler resolves a fault by filling the missing page (see
+//! [`Vmar::userfault_fill`]) and then waking up the faulting threads.
+//!
+//! [`Vmar::userfault_fill`]: super::vmar::Vmar::userfault_fill
+
+use core::{
+    ops::Range,
+    sync::atomic::{AtomicBool, Ordering},
+};
+
+use crate::{
+    events::{IoEvents, Observer},
+    prelude::*,
+    process::signal::{Pauser, Pollee, Poller},
+};
+
+/// A page fault reported to user space.
+#[derive(Debug, Clone, Copy)]
+pub struct UserfaultEvent {
+    /// The page-aligned address of the fault.
+    pub address: Vaddr,
+    /// Whether the fault is caused by a write access.
+    pub is_write: bool,
+}
+
+/// The context of user page fault delegation.
+pub struct UserfaultCtx {
+    /// The page faults that are not read by the handler yet.
+    events: Mutex<VecDeque<UserfaultEvent>>,
+    /// The addresses of the pages whose faults are not resolved yet.
+    faulting_pages: Mutex<BTreeSet<Vaddr>>,
+    pollee: Pollee,
+    /// The pauser of the threads that wait for the page faults to be resolved.
+    fault_pauser: Arc<Pauser>,
+    /// Whether the context is released, after which the registered mappings
+    /// behave as if they were not registered.
+    is_released: AtomicBool,
+}
+
+impl UserfaultCtx {
+    pub fn new() -> Arc<Self> {
+        Arc::new(Self {
+            events: Mutex::new(VecDeque::new()),
+            faulting_pages: Mutex::new(BTreeSet::new()),
+            pollee: Pollee::new(IoEvents::empty()),
+            fault_pauser: Pauser::new(),
+            is_released: AtomicBool::new(false),
+        })
+    }
+
+    pub fn is_released(&self) -> bool {
+        self.is_released.load(Ordering::Acquire)
+    }
+
+    /// Release the context and wake up all the faulting threads, which will then
+    /// resolve the faults by themselves.
+    pub fn release(&self) {
+        self.is_released.store(true, Ordering::Release);
+        self.fault_pauser.resume_all();
+    }
+
+    /// Report a page fault and wait until the faulting threads are woken up.
+    ///
+    /// Being woken up does not mean that the missing page is filled (see [`Self::wake`]), and
+    /// the waiting can also be interrupted by a signal. So the caller should check the page
+    /// again and retry the faulting access if it is still missing.
+    pub(super) fn report_and_wait(&self, event: UserfaultEvent) {
+        self.faulting_pages.lock().insert(event.address);
+        {
+            let mut events = self.events.lock();
+            events.push_back(event);
+            self.pollee.add_events(IoEvents::IN);
+        }
+
+        let _ = self.fault_pauser.pause_until(|| {
+            (self.is_released() || !self.faulting_pages.lock().contains(&event.address))
+                .then_some(())
+        });
+    }
+
+    /// Take at most `max_events` page faults that are not read yet.
+    pub fn take_events(&self, max_events: usize) -> Vec<UserfaultEvent> {
+        let mut events = self.events.lock();
+        let nr_events = max_events.min(events.len());
+        let taken = events.drain(..nr_events).collect();
+        if events.is_empty() {
+            self.pollee.del_events(IoEvents::IN);
+        }
+        taken
+    }
+
+    /// Wake up the threads that wait for the page faults within the range.
+    ///
+    /// The faults that are not read yet are dropped as well.
+    pub fn wake(&self, range: &Range<Vaddr>) {
+        {
+            let mut events = self.events.lock();
+            events.retain(|event| !range.contains(&event.address));
+            if events.is_empty() {
+                self.pollee.del_events(IoEvents::IN);
+            }
+        }
+        self.faulting_pages
+            .lock()
+            .retain(|page_addr| !range.contains(page_addr));
+        self.fault_pauser.resume_all();
+    }
+
+    pub fn poll(&self, mask: IoEvents, poller: Option<&Poller>) -> IoEvents {
+        self.pollee.poll(mask, poller)
+    }
+
+    pub fn register_observer(&self, observer: Weak<dyn Observer<IoEvents>>, mask: IoEvents) {
+        self.pollee.register_observer(observer, mask);
+    }
+
+    pub fn unregister_observer(
+        &self,
+        observer: &Weak<dyn Observer<IoEvents>>,
+    ) -> Option<Weak<dyn Observer<IoEvents>>> {
+        self.pollee.unregister_observer(observer)
+    }
+}
@@ -6,7 +6,8 @@ use aster_frame::mm::VmIo;
 use aster_rights::Rights;
 
 use super::{
-    options::VmarChildOptions, vm_mapping::VmarMapOptions, VmPerms, Vmar, VmarRightsOp, Vmar_,
+    options::VmarChildOptions, vm_mapping::VmarMapOptions, SharedMem, VmPerms, Vmar, VmarRightsOp,
+    Vmar_,
 };
 use crate::{
     prelude::*,
@@ -65,6 +66,34 @@ impl Vmar<Rights> {
         Ok(VmarMapOptions::new(dup_self, vmo, perms))
     }
 
+    /// Maps a shared memory object into this VMAR through a set of VMAR mapping options.
+    ///
+    /// Unlike [`Self::new_map`], the mapping is tracked by the shared memory object, so
+    /// it is always mapped with `MAP_SHARED` semantics, and the permissions of the
+    /// mapping are bounded by those of the object.
+    ///
+    /// # Access rights
+    ///
+    /// This method requires the following access rights:
+    /// 1. The VMAR contains the rights corresponding to the memory permissions of
+    /// the mapping.
+    /// 2. The permissions of the shared memory object contain the memory permissions.
+    pub fn new_map_shared(
+        &self,
+        shared_mem: &Arc<SharedMem>,
+        perms: VmPerms,
+    ) -> Result<VmarMapOptions<Rights, Rights>> {
+        if !shared_mem.max_perms().contains(perms) {
+            return_errno_with_message!(
+                Errno::EACCES,
+                "the permissions exceed those of the shared memory"
+            );
+        }
+        let dup_self = self.dup()?;
+        let vmo = shared_mem.vmo().dup()?;
+        Ok(VmarMapOptions::new(dup_self, vmo, perms).shared_mem(shared_mem.clone()))
+    }
+
     /// Creates a new child VMAR through a set of VMAR child options.
     ///
     /// # Example
@@ -104,7 +133,7 @@ impl Vmar<Rights> {
     /// memory permissions.
     pub fn protect(&self, perms: VmPerms, range: Range<usize>) -> Result<()> {
         self.check_rights(perms.into())?;
-        self.0.protect(perms, range)
+        self.0.protect(perms, None, range)
     }
 
     /// clear all mappings and children vmars.
@@ -135,6 +164,26 @@ impl Vmar<Rights> {
         self.0.destroy(range)
     }
 
+    /// Resizes the mapped range `old_range` to `new_size` bytes, moving it if necessary.
+    ///
+    /// The range must be within a single mapping. If `new_addr` is specified, the
+    /// range will be moved there, and any mappings in the destination range will
+    /// be destroyed. Otherwise, the range is resized in place if possible, or moved
+    /// to a free region if `may_move` is true.
+    ///
+    /// All the sizes and addresses must be page-aligned.
+    ///
+    /// Returns the new start address of the range.
+    pub fn remap(
+        &self,
+        old_range: Range<usize>,
+        new_size: usize,
+        may_move: bool,
+        new_addr: Option<Vaddr>,
+    ) -> Result<Vaddr> {
+        self.0.remap(old_range, new_size, may_move, new_addr)
+    }
+
     /// Duplicates the capability.
     ///
     /// # Access rights
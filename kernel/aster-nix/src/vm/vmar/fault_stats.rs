@@ -0,0 +1,81 @@
+// SPDX-License-Identifier: MPL-2.0
+
+//! Page fault statistics of mappings, which tell the working set of a workload.
+
+use core::{
+    sync::atomic::{AtomicU64, Ordering},
+    time::Duration,
+};
+
+use crate::time::{clocks::MonotonicClock, Clock};
+
+/// The upper bounds of the buckets of the fault latency histogram, in microseconds.
+/// The last bucket is for the faults that take longer than the last bound.
+pub const FAULT_LATENCY_BOUNDS_US: [u64; 7] = [1, 4, 16, 64, 256, 1024, 4096];
+
+const NR_LATENCY_BUCKETS: usize = FAULT_LATENCY_BOUNDS_US.len() + 1;
+
+/// The counters of the page faults handled by a mapping.
+///
+/// The counters are not inherited by the mappings split from or forked from the mapping,
+/// so they start over after, e.g., `mprotect` on a part of the mapping.
+#[derive(Debug, Default)]
+pub struct FaultStats {
+    nr_faults: AtomicU64,
+    nr_write_faults: AtomicU64,
+    total_latency_ns: AtomicU64,
+    latency_buckets: [AtomicU64; NR_LATENCY_BUCKETS],
+}
+
+impl FaultStats {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Runs the handler of a page fault, and records the fault with its latency.
+    pub fn record<R>(&self, write: bool, handle: impl FnOnce() -> R) -> R {
+        let start = MonotonicClock::get().read_time();
+        let res = handle();
+        let latency = MonotonicClock::get().read_time().saturating_sub(start);
+
+        self.nr_faults.fetch_add(1, Ordering::Relaxed);
+        if write {
+            self.nr_write_faults.fetch_add(1, Ordering::Relaxed);
+        }
+        let latency_ns = latency.as_nanos() as u64;
+        self.total_latency_ns
+            .fetch_add(latency_ns, Ordering::Relaxed);
+        let bucket = FAULT_LATENCY_BOUNDS_US
+            .iter()
+            .position(|&bound| latency_ns < bound * 1000)
+            .unwrap_or(NR_LATENCY_BUCKETS - 1);
+        self.latency_buckets[bucket].fetch_add(1, Ordering::Relaxed);
+
+        res
+    }
+
+    /// Returns the number of the page faults.
+    pub fn nr_faults(&self) -> u64 {
+        self.nr_faults.load(Ordering::Relaxed)
+    }
+
+    /// Returns the number of the page faults caused by writes.
+    pub fn nr_write_faults(&self) -> u64 {
+        self.nr_write_faults.load(Ordering::Relaxed)
+    }
+
+    /// Returns the average latency of the page faults.
+    pub fn avg_latency(&self) -> Duration {
+        let nr_faults = self.nr_faults();
+        if nr_faults == 0 {
+            return Duration::ZERO;
+        }
+        Duration::from_nanos(self.total_latency_ns.load(Ordering::Relaxed) / nr_faults)
+    }
+
+    /// Returns the numbers of the page faults in the buckets of the latency histogram,
+    /// whose bounds are [`FAULT_LATENCY_BOUNDS_US`].
+    pub fn latency_histogram(&self) -> [u64; NR_LATENCY_BUCKETS] {
+        core::array::from_fn(|i| self.latency_buckets[i].load(Ordering::Relaxed))
+    }
+}
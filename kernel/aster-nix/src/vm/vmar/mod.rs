@@ -5,23 +5,38 @@
 //! Virtual Memory Address Regions (VMARs).
 
 mod dyn_cap;
+pub mod fault_stats;
 mod interval;
 mod options;
+mod rmap;
+mod rss;
+mod shared_mem;
 mod static_cap;
 pub mod vm_mapping;
 
 use core::{cmp::min, ops::Range};
 
 use align_ext::AlignExt;
-use aster_frame::mm::{VmSpace, MAX_USERSPACE_VADDR};
+use aster_frame::mm::{Frame, Paddr, VmSpace, MAX_USERSPACE_VADDR};
 use aster_rights::Rights;
 
+pub(super) use self::rmap::Rmap;
 use self::{
     interval::{Interval, IntervalSet},
     vm_mapping::VmMapping,
 };
-use super::page_fault_handler::PageFaultHandler;
-use crate::{prelude::*, vm::perms::VmPerms};
+pub use self::{
+    rss::{RssCounters, RssType},
+    shared_mem::SharedMem,
+};
+use super::{page_fault_handler::PageFaultHandler, swap::SwapSlot};
+use crate::{
+    prelude::*,
+    vm::{
+        perms::VmPerms,
+        userfault::{UserfaultCtx, UserfaultEvent},
+    },
+};
 
 /// Virtual Memory Address Regions (VMARs) are a type of capability that manages
 /// user address spaces.
@@ -86,6 +101,11 @@ impl<R> Vmar<R> {
     pub fn vm_space(&self) -> &Arc<VmSpace> {
         self.0.vm_space()
     }
+
+    /// Returns the RSS counters of the address space.
+    pub fn rss(&self) -> &RssCounters {
+        self.0.rss()
+    }
 }
 
 pub(super) struct Vmar_ {
@@ -97,6 +117,8 @@ pub(super) struct Vmar_ {
     size: usize,
     /// The attached vmspace
     vm_space: Arc<VmSpace>,
+    /// The RSS counters of the attached vmspace
+    rss: Arc<RssCounters>,
     /// The parent vmar. If points to none, this is a root vmar
     parent: Weak<Vmar_>,
 }
@@ -110,6 +132,9 @@ struct VmarInner {
     vm_mappings: BTreeMap<Vaddr, Arc<VmMapping>>,
     /// Free regions that can be used for creating child vmar or mapping vmos
     free_regions: BTreeMap<Vaddr, FreeRegion>,
+    /// Whether the mappings created in the future are locked in memory. If it is
+    /// `Some(populate)`, `populate` tells whether their pages are committed at once.
+    lock_future: Option<bool>,
 }
 
 impl VmarInner {
@@ -119,6 +144,7 @@ impl VmarInner {
             child_vmar_s: BTreeMap::new(),
             vm_mappings: BTreeMap::new(),
             free_regions: BTreeMap::new(),
+            lock_future: None,
         }
     }
 }
@@ -126,6 +152,11 @@ impl VmarInner {
 const ROOT_VMAR_LOWEST_ADDR: Vaddr = 0x001_0000; // 64 KiB is the Linux configurable default
 const ROOT_VMAR_CAP_ADDR: Vaddr = MAX_USERSPACE_VADDR;
 
+/// The minimum gap between a stack and the mapping below it, which is the default
+/// `stack_guard_gap` of Linux. The accesses in the gap are never resolved by growing the
+/// stack, so that a stack overflow cannot silently run into other mappings.
+pub const STACK_GUARD_GAP: usize = 256 * PAGE_SIZE;
+
 impl Interval<usize> for Arc<Vmar_> {
     fn range(&self) -> Range<usize> {
         self.base..(self.base + self.size)
@@ -136,6 +167,7 @@ impl Vmar_ {
     fn new(
         inner: VmarInner,
         vm_space: Arc<VmSpace>,
+        rss: Arc<RssCounters>,
         base: usize,
         size: usize,
         parent: Option<&Arc<Vmar_>>,
@@ -151,6 +183,7 @@ impl Vmar_ {
             base,
             size,
             vm_space,
+            rss,
             parent,
         })
     }
@@ -164,10 +197,12 @@ impl Vmar_ {
             child_vmar_s: BTreeMap::new(),
             vm_mappings: BTreeMap::new(),
             free_regions,
+            lock_future: None,
         };
         Vmar_::new(
             vmar_inner,
             Arc::new(VmSpace::new()),
+            Arc::new(RssCounters::default()),
             0,
             ROOT_VMAR_CAP_ADDR,
             None,
@@ -178,16 +213,21 @@ impl Vmar_ {
         self.parent.upgrade().is_none()
     }
 
-    pub fn protect(&self, perms: VmPerms, range: Range<usize>) -> Result<()> {
+    pub fn protect(&self, perms: VmPerms, pkey: Option<u8>, range: Range<usize>) -> Result<()> {
         assert!(range.start % PAGE_SIZE == 0);
         assert!(range.end % PAGE_SIZE == 0);
         self.check_protected_range(&range)?;
-        self.do_protect_inner(perms, range)?;
+        self.do_protect_inner(perms, pkey, range)?;
         Ok(())
     }
 
     // Do real protect. The protected range is ensured to be mapped.
-    fn do_protect_inner(&self, perms: VmPerms, range: Range<usize>) -> Result<()> {
+    fn do_protect_inner(
+        &self,
+        perms: VmPerms,
+        pkey: Option<u8>,
+        range: Range<usize>,
+    ) -> Result<()> {
         let protect_mappings: Vec<Arc<VmMapping>> = {
             let inner = self.inner.lock();
             inner
@@ -202,14 +242,14 @@ impl Vmar_ {
             let vm_mapping_range =
                 vm_mapping.map_to_addr()..(vm_mapping.map_to_addr() + vm_mapping.map_size());
             let intersected_range = get_intersected_range(&range, &vm_mapping_range);
-            vm_mapping.protect(perms, intersected_range)?;
+            vm_mapping.protect(perms, pkey, intersected_range)?;
         }
 
         for child_vmar_ in self.inner.lock().child_vmar_s.find(&range) {
             let child_vmar_range = child_vmar_.range();
             debug_assert!(is_intersected(&child_vmar_range, &range));
             let intersected_range = get_intersected_range(&range, &child_vmar_range);
-            child_vmar_.do_protect_inner(perms, intersected_range)?;
+            child_vmar_.do_protect_inner(perms, pkey, intersected_range)?;
         }
 
         Ok(())
@@ -246,6 +286,503 @@ impl Vmar_ {
         Ok(())
     }
 
+    /// Lock the pages within the range in memory.
+    ///
+    /// If `populate` is true, the pages are committed and mapped at once. Otherwise, they are
+    /// locked once they are accessed. Since the pages mapped to user space are never reclaimed,
+    /// the locked pages stay resident until they are unlocked or unmapped.
+    pub fn mlock(&self, range: Range<usize>, populate: bool) -> Result<()> {
+        assert!(range.start % PAGE_SIZE == 0);
+        assert!(range.end % PAGE_SIZE == 0);
+        self.check_locked_range(&range)?;
+        self.do_set_locked_inner(true, &range)?;
+        if populate {
+            self.populate_locked(&range)?;
+        }
+        Ok(())
+    }
+
+    /// Unlock the pages within the range.
+    pub fn munlock(&self, range: Range<usize>) -> Result<()> {
+        assert!(range.start % PAGE_SIZE == 0);
+        assert!(range.end % PAGE_SIZE == 0);
+        self.check_locked_range(&range)?;
+        self.do_set_locked_inner(false, &range)
+    }
+
+    /// Lock the pages of all current mappings in memory.
+    ///
+    /// See [`Self::mlock`] for the meaning of `populate`.
+    pub fn mlock_all(&self, populate: bool) -> Result<()> {
+        let range = self.range();
+        self.do_set_locked_inner(true, &range)?;
+        if populate {
+            self.populate_locked(&range)?;
+        }
+        Ok(())
+    }
+
+    /// Unlock the pages of all mappings, including the ones created in the future.
+    pub fn munlock_all(&self) -> Result<()> {
+        self.set_lock_future(None);
+        self.do_set_locked_inner(false, &self.range())
+    }
+
+    /// Set whether the mappings created in the future are locked in memory.
+    ///
+    /// See [`Self::mlock`] for the meaning of `populate`.
+    pub fn set_lock_future(&self, populate: Option<bool>) {
+        self.inner.lock().lock_future = populate;
+    }
+
+    /// Returns whether the mappings created in the future are locked in memory.
+    pub fn lock_future(&self) -> Option<bool> {
+        self.inner.lock().lock_future
+    }
+
+    /// Returns the number of bytes that are locked in memory within the range.
+    pub fn locked_size(&self, range: &Range<usize>) -> usize {
+        self.size_of_mappings(range, &|vm_mapping, range| {
+            if vm_mapping.is_locked() {
+                range.len()
+            } else {
+                0
+            }
+        })
+    }
+
+    /// Returns the number of bytes that are mapped within the range.
+    pub fn mapped_size(&self, range: &Range<usize>) -> usize {
+        self.size_of_mappings(range, &|_, range| range.len())
+    }
+
+    /// Returns the number of bytes that are swapped out within the range.
+    pub fn swapped_size(&self, range: &Range<usize>) -> usize {
+        self.size_of_mappings(range, &|vm_mapping, range| vm_mapping.swapped_size(range))
+    }
+
+    /// Sums up `size_of` the mappings within the range, which is given the part of each
+    /// mapping that intersects with the range.
+    fn size_of_mappings(
+        &self,
+        range: &Range<usize>,
+        size_of: &dyn Fn(&VmMapping, &Range<usize>) -> usize,
+    ) -> usize {
+        let inner = self.inner.lock();
+        let mapped_size: usize = inner
+            .vm_mappings
+            .find(range)
+            .into_iter()
+            .map(|vm_mapping| {
+                size_of(
+                    vm_mapping,
+                    &get_intersected_range(range, &vm_mapping.range()),
+                )
+            })
+            .sum();
+        let child_size: usize = inner
+            .child_vmar_s
+            .find(range)
+            .into_iter()
+            .map(|child_vmar_| {
+                let intersected_range = get_intersected_range(range, &child_vmar_.range());
+                child_vmar_.size_of_mappings(&intersected_range, size_of)
+            })
+            .sum();
+        mapped_size + child_size
+    }
+
+    /// Swap out at most `nr_to_swap_out` pages within the range of the private anonymous
+    /// mappings that are not accessed recently.
+    ///
+    /// This method never blocks on the locks, see [`VmMapping::swap_out`]. Returns the
+    /// number of pages that have been swapped out.
+    pub fn swap_out(&self, range: &Range<Vaddr>, nr_to_swap_out: usize) -> usize {
+        let mut mappings = Vec::new();
+        self.try_collect_mappings(&mut mappings);
+
+        let mut nr_swapped_out = 0;
+        for vm_mapping in mappings {
+            if nr_swapped_out >= nr_to_swap_out {
+                break;
+            }
+            let mapping_range = vm_mapping.range();
+            if mapping_range.end <= range.start || range.end <= mapping_range.start {
+                continue;
+            }
+            nr_swapped_out += vm_mapping.swap_out(range, nr_to_swap_out - nr_swapped_out);
+        }
+        nr_swapped_out
+    }
+
+    /// Migrate the pages of the anonymous mappings within the physical address range to
+    /// the frames allocated by `alloc`.
+    ///
+    /// This method never blocks on the locks, see [`VmMapping::migrate_pages`]. Returns
+    /// the number of pages that have been migrated.
+    pub fn migrate_pages(
+        &self,
+        range: &Range<Paddr>,
+        alloc: &mut dyn FnMut() -> Option<Frame>,
+    ) -> usize {
+        let mut mappings = Vec::new();
+        self.try_collect_mappings(&mut mappings);
+
+        mappings
+            .into_iter()
+            .map(|vm_mapping| vm_mapping.migrate_pages(range, alloc))
+            .sum()
+    }
+
+    /// Read the swapped-out pages whose slots satisfy `filter` back to memory.
+    pub fn swap_in(&self, filter: &dyn Fn(&SwapSlot) -> bool) -> Result<()> {
+        let (mappings, children): (Vec<_>, Vec<_>) = {
+            let inner = self.inner.lock();
+            (
+                inner.vm_mappings.values().cloned().collect(),
+                inner.child_vmar_s.values().cloned().collect(),
+            )
+        };
+        for vm_mapping in mappings {
+            vm_mapping.vmo().swap_in(filter)?;
+        }
+        for child_vmar_ in children {
+            child_vmar_.swap_in(filter)?;
+        }
+        Ok(())
+    }
+
+    /// Collect the mappings of the VMAR and its children, skipping the VMARs that are locked.
+    ///
+    /// Returns whether the mappings of all the VMARs have been collected.
+    fn try_collect_mappings(&self, mappings: &mut Vec<Arc<VmMapping>>) -> bool {
+        let Some(inner) = self.inner.try_lock() else {
+            return false;
+        };
+        mappings.extend(inner.vm_mappings.values().cloned());
+        let children: Vec<_> = inner.child_vmar_s.values().cloned().collect();
+        drop(inner);
+        let mut is_complete = true;
+        for child_vmar_ in children {
+            is_complete &= child_vmar_.try_collect_mappings(mappings);
+        }
+        is_complete
+    }
+
+    /// Ensure the whole locked range is mapped.
+    fn check_locked_range(&self, range: &Range<usize>) -> Result<()> {
+        if range.start < self.base || range.end > self.base + self.size {
+            return_errno_with_message!(Errno::ENOMEM, "locked range is not in current vmar");
+        }
+        self.check_protected_range(range)
+            .map_err(|_| Error::with_message(Errno::ENOMEM, "locked range is not fully mapped"))
+    }
+
+    // Do real lock or unlock. The range is ensured to be mapped.
+    fn do_set_locked_inner(&self, is_locked: bool, range: &Range<usize>) -> Result<()> {
+        let vm_mappings: Vec<Arc<VmMapping>> = {
+            let inner = self.inner.lock();
+            inner.vm_mappings.find(range).into_iter().cloned().collect()
+        };
+
+        for vm_mapping in vm_mappings {
+            let intersected_range = get_intersected_range(range, &vm_mapping.range());
+            vm_mapping.set_locked(is_locked, intersected_range)?;
+        }
+
+        let child_vmar_s: Vec<Arc<Vmar_>> = self
+            .inner
+            .lock()
+            .child_vmar_s
+            .find(range)
+            .into_iter()
+            .cloned()
+            .collect();
+        for child_vmar_ in child_vmar_s {
+            let intersected_range = get_intersected_range(range, &child_vmar_.range());
+            child_vmar_.do_set_locked_inner(is_locked, &intersected_range)?;
+        }
+
+        Ok(())
+    }
+
+    /// Commit the pages of the mappings within the range and map them.
+    pub fn populate(&self, range: &Range<usize>) -> Result<()> {
+        self.populate_mappings(range, &|_| true)
+    }
+
+    /// Commit the pages of the locked mappings within the range and map them.
+    pub(super) fn populate_locked(&self, range: &Range<usize>) -> Result<()> {
+        self.populate_mappings(range, &|vm_mapping| vm_mapping.is_locked())
+    }
+
+    /// Commit the pages of the mappings within the range that satisfy `filter`, and
+    /// map them. See [`VmMapping::populate`].
+    fn populate_mappings(
+        &self,
+        range: &Range<usize>,
+        filter: &dyn Fn(&VmMapping) -> bool,
+    ) -> Result<()> {
+        let vm_mappings: Vec<Arc<VmMapping>> = {
+            let inner = self.inner.lock();
+            inner
+                .vm_mappings
+                .find(range)
+                .into_iter()
+                .filter(|vm_mapping| filter(vm_mapping))
+                .cloned()
+                .collect()
+        };
+
+        for vm_mapping in vm_mappings {
+            let intersected_range = get_intersected_range(range, &vm_mapping.range());
+            vm_mapping.populate(intersected_range)?;
+        }
+
+        let child_vmar_s: Vec<Arc<Vmar_>> = self
+            .inner
+            .lock()
+            .child_vmar_s
+            .find(range)
+            .into_iter()
+            .cloned()
+            .collect();
+        for child_vmar_ in child_vmar_s {
+            let intersected_range = get_intersected_range(range, &child_vmar_.range());
+            child_vmar_.populate_mappings(&intersected_range, filter)?;
+        }
+
+        Ok(())
+    }
+
+    /// Discard the pages within the range.
+    ///
+    /// For private mappings, accessing the pages again will see zero pages or the file contents.
+    /// See [`VmMapping::discard`] for details.
+    pub fn discard(&self, range: Range<usize>) -> Result<()> {
+        self.advise(&range, &|vm_mapping, range| vm_mapping.discard(range))
+    }
+
+    /// Mark the pages within the range as lazily freeable under memory pressure.
+    ///
+    /// See [`VmMapping::lazy_free`] for details.
+    pub fn lazy_free(&self, range: Range<usize>) -> Result<()> {
+        self.advise(&range, &|vm_mapping, range| vm_mapping.lazy_free(range))
+    }
+
+    /// Read the file-backed pages within the range ahead of time.
+    pub fn will_need(&self, range: Range<usize>) -> Result<()> {
+        self.advise(&range, &|vm_mapping, range| vm_mapping.will_need(range))
+    }
+
+    /// Apply the advice to the mappings within the range.
+    ///
+    /// Like Linux, the advice is still applied to the mapped parts if the range is not fully
+    /// mapped, but an error will be returned in the end.
+    fn advise(
+        &self,
+        range: &Range<usize>,
+        advise: &dyn Fn(&Arc<VmMapping>, Range<usize>) -> Result<()>,
+    ) -> Result<()> {
+        assert!(range.start % PAGE_SIZE == 0);
+        assert!(range.end % PAGE_SIZE == 0);
+        if range.start < self.base || range.end > self.base + self.size {
+            return_errno_with_message!(Errno::ENOMEM, "advised range is not in current vmar");
+        }
+        let is_fully_mapped = self.check_protected_range(range).is_ok();
+        self.do_advise_inner(range, advise)?;
+        if !is_fully_mapped {
+            return_errno_with_message!(Errno::ENOMEM, "advised range is not fully mapped");
+        }
+        Ok(())
+    }
+
+    fn do_advise_inner(
+        &self,
+        range: &Range<usize>,
+        advise: &dyn Fn(&Arc<VmMapping>, Range<usize>) -> Result<()>,
+    ) -> Result<()> {
+        let vm_mappings: Vec<Arc<VmMapping>> = {
+            let inner = self.inner.lock();
+            inner.vm_mappings.find(range).into_iter().cloned().collect()
+        };
+
+        for vm_mapping in vm_mappings {
+            let intersected_range = get_intersected_range(range, &vm_mapping.range());
+            advise(&vm_mapping, intersected_range)?;
+        }
+
+        let child_vmar_s: Vec<Arc<Vmar_>> = self
+            .inner
+            .lock()
+            .child_vmar_s
+            .find(range)
+            .into_iter()
+            .cloned()
+            .collect();
+        for child_vmar_ in child_vmar_s {
+            let intersected_range = get_intersected_range(range, &child_vmar_.range());
+            child_vmar_.do_advise_inner(&intersected_range, advise)?;
+        }
+
+        Ok(())
+    }
+
+    /// Register the mappings within the range for user page fault delegation with `ctx`.
+    ///
+    /// Like Linux, the holes in the range are skipped, but the range must contain at least
+    /// one mapping. Only anonymous mappings can be registered, and a mapping cannot be
+    /// registered with more than one context at the same time.
+    pub fn register_userfault(&self, ctx: &Arc<UserfaultCtx>, range: Range<usize>) -> Result<()> {
+        let vm_mappings = self.userfault_mappings(&range)?;
+        if vm_mappings.is_empty() {
+            return_errno_with_message!(Errno::EINVAL, "no mapping is in the range");
+        }
+        for vm_mapping in vm_mappings.iter() {
+            if !vm_mapping.vmo().is_anonymous() {
+                return_errno_with_message!(
+                    Errno::EINVAL,
+                    "only anonymous mappings can be registered"
+                );
+            }
+            if vm_mapping
+                .userfault()
+                .is_some_and(|old_ctx| !Arc::ptr_eq(&old_ctx, ctx))
+            {
+                return_errno_with_message!(
+                    Errno::EBUSY,
+                    "the mapping is registered with another context"
+                );
+            }
+        }
+
+        for vm_mapping in vm_mappings {
+            let intersected_range = get_intersected_range(&range, &vm_mapping.range());
+            vm_mapping.set_userfault(Some(ctx.clone()), intersected_range)?;
+        }
+        Ok(())
+    }
+
+    /// Unregister the mappings within the range from user page fault delegation with `ctx`.
+    ///
+    /// The threads waiting for the faults within the range are woken up.
+    pub fn unregister_userfault(&self, ctx: &Arc<UserfaultCtx>, range: Range<usize>) -> Result<()> {
+        let vm_mappings = self.userfault_mappings(&range)?;
+        if vm_mappings.is_empty() {
+            return_errno_with_message!(Errno::EINVAL, "no mapping is in the range");
+        }
+        for vm_mapping in vm_mappings {
+            if !vm_mapping
+                .userfault()
+                .is_some_and(|old_ctx| Arc::ptr_eq(&old_ctx, ctx))
+            {
+                continue;
+            }
+            let intersected_range = get_intersected_range(&range, &vm_mapping.range());
+            vm_mapping.set_userfault(None, intersected_range)?;
+        }
+        ctx.wake(&range);
+        Ok(())
+    }
+
+    /// Fill the missing pages within the range registered with `ctx`, which are filled with
+    /// `src`, or with zeros if `src` is `None`.
+    ///
+    /// The filling stops at the first page that is not missing. Returns the number of
+    /// bytes that are filled. The faulting threads are not woken up by this method.
+    pub fn userfault_fill(
+        &self,
+        ctx: &Arc<UserfaultCtx>,
+        range: Range<usize>,
+        src: Option<&[u8]>,
+    ) -> Result<usize> {
+        let vm_mappings = self.userfault_mappings(&range)?;
+        let mut fill_addr = range.start;
+        for vm_mapping in vm_mappings {
+            let intersected_range = get_intersected_range(&range, &vm_mapping.range());
+            if intersected_range.start != fill_addr {
+                // There is a hole before the mapping.
+                break;
+            }
+            let src = src.map(|src| {
+                &src[intersected_range.start - range.start..intersected_range.end - range.start]
+            });
+            match vm_mapping.userfault_fill(ctx, intersected_range.clone(), src) {
+                Ok(nr_filled) => fill_addr += nr_filled,
+                Err(_) if fill_addr > range.start => break,
+                Err(err) => return Err(err),
+            }
+            if fill_addr < intersected_range.end {
+                break;
+            }
+        }
+        if fill_addr == range.start {
+            return_errno_with_message!(Errno::ENOENT, "the range is not mapped");
+        }
+        Ok(fill_addr - range.start)
+    }
+
+    /// Returns the mappings within the range, which are sorted by their addresses.
+    fn userfault_mappings(&self, range: &Range<usize>) -> Result<Vec<Arc<VmMapping>>> {
+        assert!(range.start % PAGE_SIZE == 0);
+        assert!(range.end % PAGE_SIZE == 0);
+        if range.start < self.base || range.end > self.base + self.size {
+            return_errno_with_message!(Errno::EINVAL, "the range is not in current vmar");
+        }
+
+        let mut vm_mappings = Vec::new();
+        self.collect_vm_mappings(range, &mut vm_mappings);
+        vm_mappings.sort_by_key(|vm_mapping| vm_mapping.map_to_addr());
+        Ok(vm_mappings)
+    }
+
+    /// Returns all the mappings of the VMAR and its children, which are sorted by their
+    /// addresses.
+    fn mappings(&self) -> Vec<Arc<VmMapping>> {
+        let mut vm_mappings = Vec::new();
+        self.collect_vm_mappings(&self.range(), &mut vm_mappings);
+        vm_mappings.sort_by_key(|vm_mapping| vm_mapping.map_to_addr());
+        vm_mappings
+    }
+
+    fn collect_vm_mappings(&self, range: &Range<usize>, vm_mappings: &mut Vec<Arc<VmMapping>>) {
+        let inner = self.inner.lock();
+        vm_mappings.extend(inner.vm_mappings.find(range).into_iter().cloned());
+        for child_vmar_ in inner.child_vmar_s.find(range) {
+            let intersected_range = get_intersected_range(range, &child_vmar_.range());
+            child_vmar_.collect_vm_mappings(&intersected_range, vm_mappings);
+        }
+    }
+
+    /// Returns the context that the fault at the address should be delegated to, if any.
+    fn missing_page_userfault(&self, page_fault_addr: Vaddr) -> Option<Arc<UserfaultCtx>> {
+        let inner = self.inner.lock();
+        if let Some(child_vmar) = inner.child_vmar_s.find_one(&page_fault_addr) {
+            return child_vmar.missing_page_userfault(page_fault_addr);
+        }
+        inner
+            .vm_mappings
+            .find_one(&page_fault_addr)?
+            .missing_page_userfault(page_fault_addr)
+    }
+
+    /// Delegate the fault on a missing page to user space if the page is registered for
+    /// user page fault delegation.
+    ///
+    /// Returns `true` if the page is still missing after the faulting thread is woken up,
+    /// in which case the faulting access should be retried.
+    fn delegate_page_fault(&self, page_fault_addr: Vaddr, write: bool) -> bool {
+        let Some(ctx) = self.missing_page_userfault(page_fault_addr) else {
+            return false;
+        };
+        let event = UserfaultEvent {
+            address: page_fault_addr.align_down(PAGE_SIZE),
+            is_write: write,
+        };
+        ctx.report_and_wait(event);
+        self.missing_page_userfault(page_fault_addr).is_some()
+    }
+
     /// Handle user space page fault, if the page fault is successfully handled ,return Ok(()).
     pub fn handle_page_fault(
         &self,
@@ -257,6 +794,12 @@ impl Vmar_ {
             return_errno_with_message!(Errno::EACCES, "page fault addr is not in current vmar");
         }
 
+        // The faults on the missing pages may be resolved by user space. If the page is
+        // still missing after that, the faulting access will trigger another page fault.
+        if not_present && self.is_root_vmar() && self.delegate_page_fault(page_fault_addr, write) {
+            return Ok(());
+        }
+
         let inner = self.inner.lock();
         if let Some(child_vmar) = inner.child_vmar_s.find_one(&page_fault_addr) {
             debug_assert!(is_intersected(
@@ -278,6 +821,82 @@ impl Vmar_ {
         return_errno_with_message!(Errno::EACCES, "page fault addr is not in current vmar");
     }
 
+    /// Grow the stack mapping above the unmapped `addr` down to cover `addr`.
+    fn grow_stack(&self, addr: Vaddr, max_size: usize) -> Result<()> {
+        debug_assert!(self.is_root_vmar());
+        let new_start = addr.align_down(PAGE_SIZE);
+        let mut inner = self.inner.lock();
+        if inner.vm_mappings.find_one(&addr).is_some() {
+            return Ok(());
+        }
+
+        let Some(stack) = inner
+            .vm_mappings
+            .range(addr..)
+            .next()
+            .map(|(_, vm_mapping)| vm_mapping.clone())
+            .filter(|vm_mapping| vm_mapping.grows_down())
+        else {
+            return_errno_with_message!(Errno::EFAULT, "the address is not below a stack");
+        };
+        let old_start = stack.map_to_addr();
+        if stack.range().end - new_start > max_size {
+            return_errno_with_message!(Errno::ENOMEM, "the stack exceeds the size limit");
+        }
+        if let Some((_, below)) = inner.vm_mappings.range(..addr).next_back()
+            && below.range().end + STACK_GUARD_GAP > new_start
+        {
+            return_errno_with_message!(Errno::ENOMEM, "the stack reaches the guard gap");
+        }
+        let grown_range = new_start..old_start;
+        let reaches_child_vmar = inner
+            .child_vmar_s
+            .find(&grown_range)
+            .into_iter()
+            .next()
+            .is_some();
+        if new_start < ROOT_VMAR_LOWEST_ADDR || reaches_child_vmar {
+            return_errno_with_message!(Errno::ENOMEM, "the stack reaches other regions");
+        }
+
+        stack.grow_down(new_start)?;
+
+        // The grown range is taken from the free regions, unless it is reserved for the stack.
+        let free_region_bases: Vec<Vaddr> = inner
+            .free_regions
+            .find(&grown_range)
+            .into_iter()
+            .map(|free_region| free_region.start())
+            .collect();
+        for free_region_base in free_region_bases {
+            let free_region = inner.free_regions.remove(&free_region_base).unwrap();
+            let intersected_range = get_intersected_range(&free_region.range(), &grown_range);
+            for region in free_region.allocate_range(intersected_range) {
+                inner.free_regions.insert(region.start(), region);
+            }
+        }
+        inner.vm_mappings.remove(&old_start);
+        inner.vm_mappings.insert(new_start, stack);
+        Ok(())
+    }
+
+    /// Reserve the free range, so that new mappings are not placed in the range unless
+    /// they are mapped to fixed addresses.
+    fn reserve(&self, range: Range<usize>) -> Result<()> {
+        debug_assert!(range.start % PAGE_SIZE == 0);
+        debug_assert!(range.end % PAGE_SIZE == 0);
+        let mut inner = self.inner.lock();
+        let free_region_base = match inner.free_regions.find_one(&range.start) {
+            Some(free_region) if free_region.end() >= range.end => free_region.start(),
+            _ => return_errno_with_message!(Errno::ENOMEM, "the reserved range is not free"),
+        };
+        let free_region = inner.free_regions.remove(&free_region_base).unwrap();
+        for region in free_region.allocate_range(range) {
+            inner.free_regions.insert(region.start(), region);
+        }
+        Ok(())
+    }
+
     /// Clear all content of the root vmar
     pub fn clear_root_vmar(&self) -> Result<()> {
         debug_assert!(self.is_root_vmar());
@@ -285,10 +904,12 @@ impl Vmar_ {
             return_errno_with_message!(Errno::EACCES, "The vmar is not root vmar");
         }
         self.vm_space.clear();
+        self.rss.clear();
         let mut inner = self.inner.lock();
         inner.child_vmar_s.clear();
         inner.vm_mappings.clear();
         inner.free_regions.clear();
+        inner.lock_future = None;
         let root_region = FreeRegion::new(ROOT_VMAR_LOWEST_ADDR..ROOT_VMAR_CAP_ADDR);
         inner.free_regions.insert(root_region.start(), root_region);
         Ok(())
@@ -317,6 +938,7 @@ impl Vmar_ {
         drop(inner);
         self.merge_continuous_regions();
         self.vm_space.clear();
+        self.rss.clear();
         Ok(())
     }
 
@@ -391,6 +1013,156 @@ impl Vmar_ {
         Ok(())
     }
 
+    /// Resize the mapped range `old_range` to `new_size`, and move it if necessary.
+    ///
+    /// If `new_addr` is specified, the range is always moved there, and the mappings that
+    /// exist in the destination range are destroyed. Otherwise, the range is resized in place
+    /// if possible, or moved to a free region if `may_move` is true.
+    ///
+    /// Moving the range does not copy the contents of the mapped pages.
+    ///
+    /// Returns the new start address of the range.
+    pub fn remap(
+        &self,
+        old_range: Range<usize>,
+        new_size: usize,
+        may_move: bool,
+        new_addr: Option<Vaddr>,
+    ) -> Result<Vaddr> {
+        debug_assert!(old_range.start % PAGE_SIZE == 0);
+        debug_assert!(old_range.end % PAGE_SIZE == 0);
+        debug_assert!(new_size % PAGE_SIZE == 0);
+
+        let vm_mapping = self.check_remapped_range(&old_range, new_size)?;
+
+        if let Some(new_addr) = new_addr {
+            let new_range = new_addr
+                ..new_addr
+                    .checked_add(new_size)
+                    .ok_or_else(|| Error::with_message(Errno::EINVAL, "new_addr overflows"))?;
+            if is_intersected(&old_range, &new_range) {
+                return_errno_with_message!(Errno::EINVAL, "the new range overlaps the old range");
+            }
+            self.destroy(new_range)?;
+            // Destroying the new range may split the mapping containing the old range.
+            let vm_mapping = self.get_vm_mapping(old_range.start)?;
+            return self.move_range(&vm_mapping, old_range, Some(new_addr), new_size);
+        }
+
+        if new_size <= old_range.len() {
+            if new_size < old_range.len() {
+                self.destroy(old_range.start + new_size..old_range.end)?;
+            }
+            return Ok(old_range.start);
+        }
+
+        if self.try_enlarge_in_place(&vm_mapping, &old_range, new_size)? {
+            return Ok(old_range.start);
+        }
+        if !may_move {
+            return_errno_with_message!(Errno::ENOMEM, "the range cannot be enlarged in place");
+        }
+        self.move_range(&vm_mapping, old_range, None, new_size)
+    }
+
+    /// Ensure that the range to be remapped is within a single mapping, and that the mapping
+    /// can be enlarged if `new_size` is larger.
+    ///
+    /// Returns the mapping that contains the range.
+    fn check_remapped_range(
+        &self,
+        old_range: &Range<usize>,
+        new_size: usize,
+    ) -> Result<Arc<VmMapping>> {
+        let inner = self.inner.lock();
+        let Some(vm_mapping) = inner.vm_mappings.find_one(&old_range.start) else {
+            return_errno_with_message!(Errno::EFAULT, "the range is not mapped");
+        };
+        let vm_mapping_range = vm_mapping.range();
+        if old_range.end > vm_mapping_range.end {
+            return_errno_with_message!(Errno::EFAULT, "the range spans multiple mappings");
+        }
+
+        if new_size > old_range.len() {
+            // The VMO beyond the range will be used after enlarging. So it must not be used by
+            // other mappings.
+            if old_range.end != vm_mapping_range.end {
+                return_errno_with_message!(Errno::ENOMEM, "the range is not at the mapping end");
+            }
+            let is_vmo_shared = inner.vm_mappings.values().any(|other_mapping| {
+                !Arc::ptr_eq(other_mapping, vm_mapping)
+                    && Arc::ptr_eq(&other_mapping.vmo().0, &vm_mapping.vmo().0)
+            });
+            if is_vmo_shared {
+                return_errno_with_message!(Errno::ENOMEM, "the vmo is shared by other mappings");
+            }
+        }
+
+        Ok(vm_mapping.clone())
+    }
+
+    /// Try to enlarge the range at the end of `vm_mapping` to `new_size` in place.
+    ///
+    /// Returns `false` if the address range after the mapping is not free.
+    fn try_enlarge_in_place(
+        &self,
+        vm_mapping: &Arc<VmMapping>,
+        old_range: &Range<usize>,
+        new_size: usize,
+    ) -> Result<bool> {
+        let enlarged_range = old_range.end..old_range.start + new_size;
+        let mut inner = self.inner.lock();
+        let free_region_base = match inner.free_regions.find_one(&enlarged_range.start) {
+            Some(free_region) if free_region.end() >= enlarged_range.end => free_region.start(),
+            _ => return Ok(false),
+        };
+
+        vm_mapping.enlarge(enlarged_range.end - vm_mapping.map_to_addr())?;
+
+        let free_region = inner.free_regions.remove(&free_region_base).unwrap();
+        let regions_after_allocation = free_region.allocate_range(enlarged_range);
+        regions_after_allocation.into_iter().for_each(|region| {
+            inner.free_regions.insert(region.start(), region);
+        });
+        Ok(true)
+    }
+
+    /// Move the range in `vm_mapping` to a new mapping with `new_size`.
+    ///
+    /// The new mapping is created at `new_addr` if specified, or in a free region otherwise.
+    /// The destination range must be free.
+    fn move_range(
+        &self,
+        vm_mapping: &Arc<VmMapping>,
+        old_range: Range<usize>,
+        new_addr: Option<Vaddr>,
+        new_size: usize,
+    ) -> Result<Vaddr> {
+        let new_addr =
+            self.allocate_free_region_for_vmo(0, new_size, new_addr, PAGE_SIZE, false)?;
+        let new_mapping = match vm_mapping.new_remapped(old_range.clone(), new_addr, new_size) {
+            Ok(new_mapping) => new_mapping,
+            Err(err) => {
+                let free_region = FreeRegion::new(new_addr..new_addr + new_size);
+                self.inner
+                    .lock()
+                    .free_regions
+                    .insert(free_region.start(), free_region);
+                self.merge_continuous_regions();
+                return Err(err);
+            }
+        };
+
+        // Move the mapped pages without copying them. The pages beyond `new_size` are left in
+        // the old range and will be unmapped when the old range is destroyed.
+        let moved_range = old_range.start..old_range.start + old_range.len().min(new_size);
+        self.vm_space.remap(&moved_range, new_addr)?;
+
+        self.destroy(old_range)?;
+        self.add_mapping(new_mapping);
+        Ok(new_addr)
+    }
+
     fn is_destroyed(&self) -> bool {
         self.inner.lock().is_destroyed
     }
@@ -440,7 +1212,7 @@ impl Vmar_ {
                 let vm_mapping_offset = current_start - vm_mapping_range.start;
                 vm_mapping.read_bytes(
                     vm_mapping_offset,
-                    buf.get_mut(read_offset..buf_len).unwrap(),
+                    buf.get_mut(read_offset..read_offset + buf_len).unwrap(),
                 )?;
                 read_offset += buf_len;
             } else {
@@ -488,8 +1260,10 @@ impl Vmar_ {
                     vm_mapping_range.end - current_start,
                 );
                 let vm_mapping_offset = current_start - vm_mapping_range.start;
-                vm_mapping
-                    .write_bytes(vm_mapping_offset, buf.get(write_offset..buf_len).unwrap())?;
+                vm_mapping.write_bytes(
+                    vm_mapping_offset,
+                    buf.get(write_offset..write_offset + buf_len).unwrap(),
+                )?;
                 write_offset += buf_len;
             } else {
                 return_errno_with_message!(Errno::EACCES, "write range is not fully mapped");
@@ -530,10 +1304,12 @@ impl Vmar_ {
             child_vmar_s: BTreeMap::new(),
             vm_mappings: BTreeMap::new(),
             free_regions: child_regions,
+            lock_future: None,
         };
         let child_vmar_ = Vmar_::new(
             child_vmar_inner,
             self.vm_space.clone(),
+            self.rss.clone(),
             child_vmar_offset,
             child_vmar_size,
             Some(self),
@@ -614,6 +1390,11 @@ impl Vmar_ {
         &self.vm_space
     }
 
+    /// Returns the RSS counters of the attached `VmSpace`.
+    pub(super) fn rss(&self) -> &RssCounters {
+        &self.rss
+    }
+
     /// Map a vmo to this vmar.
     pub fn add_mapping(&self, mapping: Arc<VmMapping>) {
         self.inner
@@ -715,12 +1496,15 @@ impl Vmar_ {
             //
             // If this is a root `Vmar`, we leverage Copy-On-Write (COW) mechanism to
             // clone the `VmSpace` to the child.
-            let vm_space = if let Some(parent) = parent {
-                parent.vm_space().clone()
+            let (vm_space, rss) = if let Some(parent) = parent {
+                (parent.vm_space().clone(), parent.rss.clone())
             } else {
-                Arc::new(self.vm_space().fork_copy_on_write())
+                (
+                    Arc::new(self.vm_space().fork_copy_on_write()),
+                    Arc::new(self.rss.new_fork()),
+                )
             };
-            Vmar_::new(vmar_inner, vm_space, self.base, self.size, parent)
+            Vmar_::new(vmar_inner, vm_space, rss, self.base, self.size, parent)
         };
 
         let inner = self.inner.lock();
@@ -789,6 +1573,183 @@ impl<R> Vmar<R> {
         self.check_rights(rights)?;
         self.0.get_vm_mapping(offset)
     }
+
+    /// Returns all the mappings, which are sorted by their addresses.
+    pub fn mappings(&self) -> Vec<Arc<VmMapping>> {
+        self.0.mappings()
+    }
+
+    /// Changes the permissions and the protection key of the memory mappings in the range.
+    ///
+    /// The range must be page-aligned and fully mapped. Like `protect`, the VMAR and the
+    /// VMOs of the mappings must have the rights corresponding to the permissions.
+    pub fn protect_with_pkey(&self, perms: VmPerms, pkey: u8, range: Range<usize>) -> Result<()> {
+        self.check_rights(perms.into())?;
+        self.0.protect(perms, Some(pkey), range)
+    }
+
+    /// Commits the pages within the range and maps them, so that accessing them does not
+    /// cause page faults.
+    ///
+    /// The range must be page-aligned. The pages are mapped in a batch for each mapping,
+    /// which is much faster than faulting them in one by one.
+    pub fn populate(&self, range: Range<usize>) -> Result<()> {
+        self.0.populate(&range)
+    }
+
+    /// Locks the pages within the range in memory.
+    ///
+    /// The range must be page-aligned and fully mapped. If `populate` is true, the pages
+    /// are committed at once. Otherwise, they are locked once they are accessed.
+    pub fn mlock(&self, range: Range<usize>, populate: bool) -> Result<()> {
+        self.0.mlock(range, populate)
+    }
+
+    /// Unlocks the pages within the range.
+    ///
+    /// The range must be page-aligned and fully mapped.
+    pub fn munlock(&self, range: Range<usize>) -> Result<()> {
+        self.0.munlock(range)
+    }
+
+    /// Locks the pages of all current mappings in memory.
+    pub fn mlock_all(&self, populate: bool) -> Result<()> {
+        self.0.mlock_all(populate)
+    }
+
+    /// Unlocks the pages of all mappings, including the ones created in the future.
+    pub fn munlock_all(&self) -> Result<()> {
+        self.0.munlock_all()
+    }
+
+    /// Sets whether the mappings created in the future are locked in memory.
+    ///
+    /// If it is `Some(populate)`, `populate` tells whether their pages are committed at once.
+    pub fn set_lock_future(&self, populate: Option<bool>) {
+        self.0.set_lock_future(populate)
+    }
+
+    /// Returns whether the mappings created in the future are locked in memory.
+    pub fn lock_future(&self) -> Option<bool> {
+        self.0.lock_future()
+    }
+
+    /// Grows the stack mapping above `addr` down to cover `addr`, if `addr` is not mapped.
+    ///
+    /// A stack is a mapping created with [`VmarMapOptions::grows_down`]. The stack grows
+    /// only if its size does not exceed `max_size` after growing, and it keeps a gap of
+    /// [`STACK_GUARD_GAP`] from the mapping below it.
+    ///
+    /// [`VmarMapOptions::grows_down`]: vm_mapping::VmarMapOptions::grows_down
+    pub fn grow_stack(&self, addr: Vaddr, max_size: usize) -> Result<()> {
+        self.0.grow_stack(addr, max_size)
+    }
+
+    /// Reserves the free range, so that new mappings are not placed in the range unless
+    /// they are mapped to fixed addresses.
+    ///
+    /// The range must be page-aligned. It keeps the room for a stack to grow down.
+    pub fn reserve(&self, range: Range<usize>) -> Result<()> {
+        self.0.reserve(range)
+    }
+
+    /// Returns the number of bytes that are locked in memory within the range.
+    pub fn locked_size(&self, range: &Range<usize>) -> usize {
+        self.0.locked_size(range)
+    }
+
+    /// Returns the number of bytes that are mapped within the range.
+    pub fn mapped_size(&self, range: &Range<usize>) -> usize {
+        self.0.mapped_size(range)
+    }
+
+    /// Returns the number of bytes that are swapped out within the range.
+    pub fn swapped_size(&self, range: &Range<usize>) -> usize {
+        self.0.swapped_size(range)
+    }
+
+    /// Swaps out at most `nr_to_swap_out` pages of the private anonymous mappings that
+    /// are not accessed recently, without blocking.
+    ///
+    /// Returns the number of pages that have been swapped out.
+    pub(crate) fn swap_out(&self, nr_to_swap_out: usize) -> usize {
+        self.swap_out_range(&(ROOT_VMAR_LOWEST_ADDR..ROOT_VMAR_CAP_ADDR), nr_to_swap_out)
+    }
+
+    /// Like [`Self::swap_out`], but only swaps out the pages within the range.
+    pub(crate) fn swap_out_range(&self, range: &Range<Vaddr>, nr_to_swap_out: usize) -> usize {
+        self.0.swap_out(range, nr_to_swap_out)
+    }
+
+    /// Reads the swapped-out pages whose slots satisfy `filter` back to memory.
+    pub(crate) fn swap_in(&self, filter: &dyn Fn(&SwapSlot) -> bool) -> Result<()> {
+        self.0.swap_in(filter)
+    }
+
+    /// Migrates the pages of the anonymous mappings within the physical address range to
+    /// the frames allocated by `alloc`, without blocking.
+    ///
+    /// Returns the number of pages that have been migrated.
+    pub(crate) fn migrate_pages(
+        &self,
+        range: &Range<Paddr>,
+        alloc: &mut dyn FnMut() -> Option<Frame>,
+    ) -> usize {
+        self.0.migrate_pages(range, alloc)
+    }
+
+    /// Discards the pages within the range, like `MADV_DONTNEED`.
+    ///
+    /// The range must be page-aligned. The pages of private mappings are dropped, so
+    /// accessing them again will see zero pages or the file contents, while the data of
+    /// shared mappings are kept.
+    pub fn discard(&self, range: Range<usize>) -> Result<()> {
+        self.0.discard(range)
+    }
+
+    /// Marks the pages within the range as lazily freeable, like `MADV_FREE`.
+    ///
+    /// The range must be page-aligned and only cover private anonymous mappings. The pages
+    /// are freed under memory pressure unless they are written again before that.
+    pub fn lazy_free(&self, range: Range<usize>) -> Result<()> {
+        self.0.lazy_free(range)
+    }
+
+    /// Reads the file-backed pages within the range ahead of time, like `MADV_WILLNEED`.
+    ///
+    /// The range must be page-aligned.
+    pub fn will_need(&self, range: Range<usize>) -> Result<()> {
+        self.0.will_need(range)
+    }
+
+    /// Registers the mappings within the range for user page fault delegation with `ctx`.
+    ///
+    /// The range must be page-aligned. The holes in the range are skipped, but all the
+    /// mappings in the range must be anonymous.
+    pub fn register_userfault(&self, ctx: &Arc<UserfaultCtx>, range: Range<usize>) -> Result<()> {
+        self.0.register_userfault(ctx, range)
+    }
+
+    /// Unregisters the mappings within the range from user page fault delegation with `ctx`.
+    ///
+    /// The range must be page-aligned. The holes in the range are skipped.
+    pub fn unregister_userfault(&self, ctx: &Arc<UserfaultCtx>, range: Range<usize>) -> Result<()> {
+        self.0.unregister_userfault(ctx, range)
+    }
+
+    /// Fills the missing pages within the range registered with `ctx` to resolve the faults
+    /// on them. The pages are filled with `src`, or with zeros if `src` is `None`.
+    ///
+    /// The range must be page-aligned. Returns the number of bytes that are filled, which
+    /// stops at the first page that is not missing.
+    pub fn userfault_fill(
+        &self,
+        ctx: &Arc<UserfaultCtx>,
+        range: Range<usize>,
+        src: Option<&[u8]>,
+    ) -> Result<usize> {
+        self.0.userfault_fill(ctx, range, src)
+    }
 }
 
 #[derive(Debug, Clone)]
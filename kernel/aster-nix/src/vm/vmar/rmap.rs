@@ -0,0 +1,203 @@
+// SPDX-License-Identifier: MPL-2.0
+
+//! Reverse mapping (rmap) from the pages of VMOs to the mappings that map them.
+//!
+//! Like Linux, the pages are reverse mapped by objects rather than one by one. A VMO
+//! shares its pages with its slice and COW children, so a VMO and all its descendants
+//! share an [`Rmap`], which records the VMARs that they are mapped into. The pages are
+//! identified by their indexes in the shared pages, which are the same for the whole
+//! family of VMOs.
+//!
+//! To find the page table entries that map a frame, the mappings of the family are
+//! collected from the recorded VMARs, and the entry at the address where each mapping
+//! maps the page index is checked to see whether it does map the frame. Checking the
+//! entries also covers those copied to the child process when forking, which are not
+//! mapped through any mapping. In this way, the page cache, the swapping and the other
+//! memory management can unmap a frame from all the address spaces.
+//!
+//! # Locking
+//!
+//! The rmap never holds its own lock while locking a VMAR, a mapping or a page table,
+//! so it imposes no order among the locks of the address spaces. Besides, the methods
+//! that walk the rmap only try to lock the VMARs and the mappings, and give up if any
+//! of them is busy. Since the frames are allocated with the mappings or the pages of
+//! VMOs locked, this keeps the walks deadlock-free even when they are done to reclaim
+//! memory for such allocations.
+
+use aster_frame::mm::{Frame, PageFlags};
+
+use super::{interval::Interval, vm_mapping::VmMapping, Vmar_};
+use crate::prelude::*;
+
+/// The reverse mapping of a family of VMOs that share their pages.
+pub(in crate::vm) struct Rmap {
+    /// The VMARs that the VMOs may be mapped into.
+    vmars: SpinLock<Vec<Weak<Vmar_>>>,
+}
+
+/// The operation on a page table entry found by walking an [`Rmap`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(super) enum PteOp {
+    /// Clears the accessed bit of the entry.
+    ClearAccessed,
+    /// Unmaps the entry.
+    Unmap,
+}
+
+impl Rmap {
+    pub(in crate::vm) fn new() -> Arc<Self> {
+        Arc::new(Self {
+            vmars: SpinLock::new(Vec::new()),
+        })
+    }
+
+    /// Records that the VMOs are mapped into the VMAR.
+    pub(super) fn add_vmar(&self, vmar: &Arc<Vmar_>) {
+        let vmar = Arc::downgrade(vmar);
+        let mut vmars = self.vmars.lock();
+        vmars.retain(|added| added.strong_count() > 0);
+        if !vmars.iter().any(|added| added.ptr_eq(&vmar)) {
+            vmars.push(vmar);
+        }
+    }
+
+    /// Returns the mappings of the VMOs in all the address spaces.
+    pub(super) fn mappings(self: &Arc<Self>) -> Vec<Arc<VmMapping>> {
+        let mut mappings = Vec::new();
+        for vmar in self.vmars() {
+            vmar.collect_vm_mappings(&vmar.range(), &mut mappings);
+        }
+        self.retain_mapped(&mut mappings);
+        mappings
+    }
+
+    /// Clears the accessed bits of the page table entries that map `frame` at the page
+    /// index `idx`, and returns whether any of them has been accessed.
+    ///
+    /// Like [`Self::try_unmap`], this method never blocks.
+    pub(in crate::vm) fn try_clear_accessed(
+        self: &Arc<Self>,
+        idx: usize,
+        frame: &Frame,
+    ) -> Result<bool> {
+        let flags = self.try_operate(idx, frame, PteOp::ClearAccessed)?;
+        Ok(flags.contains(PageFlags::ACCESSED))
+    }
+
+    /// Unmaps `frame` at the page index `idx` from all the address spaces, and returns
+    /// the union of the flags of the page table entries, e.g., whether the frame has
+    /// been written via any of them.
+    ///
+    /// This method never blocks. It fails with `EAGAIN` if any VMAR or mapping that may
+    /// map the frame is being operated on by others, and with `EBUSY` if the frame is
+    /// locked in memory by any mapping. On failures, the frame may still be mapped
+    /// somewhere, which the caller can tell by the reference count of the frame.
+    pub(in crate::vm) fn try_unmap(
+        self: &Arc<Self>,
+        idx: usize,
+        frame: &Frame,
+    ) -> Result<PageFlags> {
+        self.try_operate(idx, frame, PteOp::Unmap)
+    }
+
+    fn try_operate(self: &Arc<Self>, idx: usize, frame: &Frame, op: PteOp) -> Result<PageFlags> {
+        let mut mappings = Vec::new();
+        for vmar in self.try_vmars()? {
+            if !vmar.try_collect_mappings(&mut mappings) {
+                return_errno_with_message!(Errno::EAGAIN, "the vmar is being operated on");
+            }
+        }
+
+        self.retain_mapped(&mut mappings);
+
+        let mut flags = PageFlags::empty();
+        for mapping in mappings {
+            let Some(page_idx) = idx.checked_sub(mapping.vmo().page_idx_offset()) else {
+                continue;
+            };
+            if let Some(pte_flags) = mapping.try_operate_pte(page_idx, frame, op)? {
+                flags |= pte_flags;
+            }
+        }
+        Ok(flags)
+    }
+
+    /// Returns the VMARs that are alive, and forgets those that are dropped.
+    fn vmars(&self) -> Vec<Arc<Vmar_>> {
+        let mut vmars = self.vmars.lock();
+        vmars.retain(|vmar| vmar.strong_count() > 0);
+        vmars.iter().filter_map(Weak::upgrade).collect()
+    }
+
+    /// Like [`Self::vmars`], but fails with `EAGAIN` instead of spinning on the lock.
+    fn try_vmars(&self) -> Result<Vec<Arc<Vmar_>>> {
+        let Some(vmars) = self.vmars.try_lock() else {
+            return_errno_with_message!(Errno::EAGAIN, "the rmap is being operated on");
+        };
+        Ok(vmars.iter().filter_map(Weak::upgrade).collect())
+    }
+
+    /// Retains the mappings of the VMOs only, and removes the duplicated ones.
+    fn retain_mapped(self: &Arc<Self>, mappings: &mut Vec<Arc<VmMapping>>) {
+        mappings.retain(|mapping| Arc::ptr_eq(mapping.vmo().rmap(), self));
+        // A mapping is collected more than once if both its VMAR and an ancestor of the
+        // VMAR are recorded.
+        mappings.sort_by_key(Arc::as_ptr);
+        mappings.dedup_by(|a, b| Arc::ptr_eq(a, b));
+    }
+}
+
+#[cfg(ktest)]
+mod test {
+    use aster_rights::Full;
+
+    use super::*;
+    use crate::vm::{
+        perms::VmPerms,
+        vmar::Vmar,
+        vmo::{VmoChildOptions, VmoOptions, VmoRightsOp},
+    };
+
+    #[ktest]
+    fn unmap_from_all_mappings() {
+        let root_vmar = Vmar::<Full>::new_root();
+        let vmo = VmoOptions::<Full>::new(PAGE_SIZE).alloc().unwrap().to_dyn();
+        let perms = VmPerms::READ | VmPerms::WRITE;
+        let map_addrs = [0x1000_0000, 0x1100_0000];
+        for map_addr in map_addrs {
+            root_vmar
+                .new_map(vmo.dup().unwrap(), perms)
+                .unwrap()
+                .offset(map_addr)
+                .is_shared(true)
+                .build()
+                .unwrap();
+            root_vmar.handle_page_fault(map_addr, true, false).unwrap();
+        }
+        // The COW child shares the rmap, but maps its own frame once written.
+        let cow_addr = 0x1200_0000;
+        let cow_vmo = VmoChildOptions::new_cow(vmo.dup().unwrap(), 0..PAGE_SIZE)
+            .alloc()
+            .unwrap();
+        root_vmar
+            .new_map(cow_vmo, perms)
+            .unwrap()
+            .offset(cow_addr)
+            .build()
+            .unwrap();
+        root_vmar.handle_page_fault(cow_addr, true, true).unwrap();
+
+        let frame = vmo.get_committed_frame(0, false).unwrap();
+        vmo.rmap().try_unmap(0, &frame).unwrap();
+        for map_addr in map_addrs {
+            assert!(root_vmar.vm_space().query(map_addr).unwrap().is_none());
+        }
+        assert!(root_vmar.vm_space().query(cow_addr).unwrap().is_some());
+
+        // The page is mapped again on access.
+        root_vmar
+            .handle_page_fault(map_addrs[0], true, false)
+            .unwrap();
+        assert!(root_vmar.vm_space().query(map_addrs[0]).unwrap().is_some());
+    }
+}
@@ -0,0 +1,67 @@
+// SPDX-License-Identifier: MPL-2.0
+
+//! Resident set size (RSS) counters of address spaces.
+
+use core::sync::atomic::{AtomicUsize, Ordering};
+
+/// The types of the pages in the resident set.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RssType {
+    /// The pages of private anonymous mappings.
+    Anon = 0,
+    /// The pages of file-backed mappings.
+    ///
+    /// The pages copied on write in private file-backed mappings are also counted as
+    /// file pages, since the mappings do not tell them apart.
+    File = 1,
+    /// The pages of shared anonymous mappings, e.g., those created by `memfd_create`
+    /// and `shmat`.
+    Shmem = 2,
+}
+
+const NR_RSS_TYPES: usize = 3;
+
+/// The RSS counters of an address space, in pages.
+///
+/// The counters are updated when the pages are mapped to or unmapped from the
+/// address space, so reading them does not need to walk the page table.
+#[derive(Debug, Default)]
+pub struct RssCounters([AtomicUsize; NR_RSS_TYPES]);
+
+impl RssCounters {
+    /// Returns the number of resident pages of the type.
+    pub fn get(&self, rss_type: RssType) -> usize {
+        self.0[rss_type as usize].load(Ordering::Relaxed)
+    }
+
+    /// Returns the number of resident pages of all types.
+    pub fn total(&self) -> usize {
+        self.0
+            .iter()
+            .map(|counter| counter.load(Ordering::Relaxed))
+            .sum()
+    }
+
+    pub(super) fn add(&self, rss_type: RssType, nr_pages: usize) {
+        self.0[rss_type as usize].fetch_add(nr_pages, Ordering::Relaxed);
+    }
+
+    pub(super) fn sub(&self, rss_type: RssType, nr_pages: usize) {
+        let _ = self.0[rss_type as usize].fetch_update(Ordering::Relaxed, Ordering::Relaxed, |n| {
+            Some(n.saturating_sub(nr_pages))
+        });
+    }
+
+    /// Creates the counters of a forked address space, which has the same pages mapped.
+    pub(super) fn new_fork(&self) -> Self {
+        Self(core::array::from_fn(|i| {
+            AtomicUsize::new(self.0[i].load(Ordering::Relaxed))
+        }))
+    }
+
+    pub(super) fn clear(&self) {
+        for counter in self.0.iter() {
+            counter.store(0, Ordering::Relaxed);
+        }
+    }
+}
@@ -0,0 +1,180 @@
+// SPDX-License-Identifier: MPL-2.0
+
+//! Memory objects shared among address spaces.
+
+use core::ops::Range;
+
+use align_ext::AlignExt;
+use aster_rights::Rights;
+
+use super::{get_intersected_range, is_intersected, vm_mapping::VmMapping};
+use crate::{
+    prelude::*,
+    vm::{
+        perms::VmPerms,
+        vmo::{Vmo, VmoOptions},
+    },
+};
+
+/// A memory object that can be mapped into multiple address spaces with the `MAP_SHARED`
+/// semantics.
+///
+/// The pages of the object are provided by a VMO, which is either anonymous or backed by
+/// a pager. All the mappings of the object, including those inherited by child processes,
+/// see the same pages. The mappings are found by the reverse mapping of the VMO, so that the
+/// changes to the object, e.g., revoking the write permission or dropping the pages, are
+/// applied to all of its mappings coherently. It serves as the foundation of `shm_open`,
+/// `memfd_create` and the shared file mappings.
+///
+/// Each mapping of the object holds a reference to it, so the object lives as long as it
+/// is mapped or referenced by others, e.g., a file.
+pub struct SharedMem {
+    vmo: Vmo<Rights>,
+    /// The permissions that the mappings of the object can have at most.
+    max_perms: Mutex<VmPerms>,
+    /// The lock held when a mapping of the object is registered or the permissions are
+    /// restricted, so that a new mapping cannot escape the restriction.
+    mapping_lock: Mutex<()>,
+}
+
+impl SharedMem {
+    /// Creates an anonymous shared memory object with a size of `size` bytes, which is
+    /// filled with zeros.
+    pub fn new(size: usize) -> Result<Arc<Self>> {
+        let vmo = VmoOptions::<Rights>::new(size).alloc()?;
+        Ok(Self::from_vmo(vmo))
+    }
+
+    /// Creates a shared memory object whose pages are provided by `vmo`.
+    pub fn from_vmo(vmo: Vmo<Rights>) -> Arc<Self> {
+        Arc::new(Self {
+            vmo,
+            max_perms: Mutex::new(VmPerms::all()),
+            mapping_lock: Mutex::new(()),
+        })
+    }
+
+    pub fn vmo(&self) -> &Vmo<Rights> {
+        &self.vmo
+    }
+
+    pub fn size(&self) -> usize {
+        self.vmo.size()
+    }
+
+    /// Returns the permissions that the mappings of the object can have at most.
+    pub fn max_perms(&self) -> VmPerms {
+        *self.max_perms.lock()
+    }
+
+    /// Returns the number of the mappings of the object in all the address spaces.
+    pub fn nr_mappings(self: &Arc<Self>) -> usize {
+        self.mappings().len()
+    }
+
+    /// Returns whether the object is mapped with the write permission somewhere.
+    pub fn is_mapped_writable(self: &Arc<Self>) -> bool {
+        self.mappings()
+            .iter()
+            .any(|mapping| mapping.perms().contains(VmPerms::WRITE))
+    }
+
+    /// Revokes the permissions that are not in `perms` from all the mappings of the object.
+    ///
+    /// The object cannot be mapped with the revoked permissions any more, and the existing
+    /// mappings cannot regain them with `mprotect`.
+    pub fn restrict_perms(self: &Arc<Self>, perms: VmPerms) -> Result<()> {
+        let _mapping_guard = self.mapping_lock.lock();
+        self.do_restrict_perms(perms)
+    }
+
+    /// Revokes the permissions that are not in `perms`, like [`Self::restrict_perms`],
+    /// if no mapping of the object has them.
+    ///
+    /// Otherwise, it fails with `EBUSY` and nothing is revoked.
+    pub fn try_restrict_perms(self: &Arc<Self>, perms: VmPerms) -> Result<()> {
+        let _mapping_guard = self.mapping_lock.lock();
+        if self
+            .mappings()
+            .iter()
+            .any(|mapping| !perms.contains(mapping.perms()))
+        {
+            return_errno_with_message!(Errno::EBUSY, "the permissions are in use by a mapping");
+        }
+        self.do_restrict_perms(perms)
+    }
+
+    fn do_restrict_perms(self: &Arc<Self>, perms: VmPerms) -> Result<()> {
+        {
+            let mut max_perms = self.max_perms.lock();
+            *max_perms &= perms;
+        }
+        for mapping in self.mappings() {
+            let old_perms = mapping.perms();
+            if !perms.contains(old_perms) {
+                mapping.protect_allowed(old_perms & perms, None, mapping.range())?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Calls `f` to register a new mapping of the object with `perms` or to change an
+    /// existing one to `perms`, if the permissions are allowed.
+    ///
+    /// The permissions cannot be restricted in the meantime, so the mapping is either
+    /// rejected here or found by a later restriction.
+    pub(super) fn with_allowed_perms<R>(
+        &self,
+        perms: VmPerms,
+        f: impl FnOnce() -> Result<R>,
+    ) -> Result<R> {
+        let _mapping_guard = self.mapping_lock.lock();
+        if !self.max_perms().contains(perms) {
+            return_errno_with_message!(
+                Errno::EACCES,
+                "the permissions exceed those of the shared memory"
+            );
+        }
+        f()
+    }
+
+    /// Unmaps the pages within the VMO range from all the mappings of the object.
+    ///
+    /// This should be called after the pages are removed from the VMO, e.g., when a file is
+    /// truncated, so that the stale pages are not accessed via the page tables. The mappings
+    /// themselves remain, and the pages will be committed again when accessed.
+    pub fn unmap_pages(self: &Arc<Self>, vmo_range: Range<usize>) -> Result<()> {
+        let vmo_range = vmo_range.start.align_down(PAGE_SIZE)..vmo_range.end.align_up(PAGE_SIZE);
+        if vmo_range.is_empty() {
+            return Ok(());
+        }
+        for mapping in self.mappings() {
+            let vmo_offset = mapping.vmo_offset();
+            let mapped_vmo_range = vmo_offset..vmo_offset + mapping.map_size();
+            if !is_intersected(&vmo_range, &mapped_vmo_range) {
+                continue;
+            }
+            let range = get_intersected_range(&vmo_range, &mapped_vmo_range);
+            let map_to_addr = mapping.map_to_addr();
+            mapping.unmap(
+                &(range.start - vmo_offset + map_to_addr..range.end - vmo_offset + map_to_addr),
+                false,
+            )?;
+        }
+        Ok(())
+    }
+
+    /// Returns the mappings of the object.
+    ///
+    /// The VMO may also be mapped without the object, e.g., by the private mappings of a
+    /// file, which share the reverse mapping but are not the mappings of the object.
+    fn mappings(self: &Arc<Self>) -> Vec<Arc<VmMapping>> {
+        let mut mappings = self.vmo.rmap().mappings();
+        mappings.retain(|mapping| {
+            mapping
+                .shared_mem()
+                .is_some_and(|shared_mem| Arc::ptr_eq(shared_mem, self))
+        });
+        mappings
+    }
+}
@@ -111,7 +111,7 @@ impl<R: TRights> Vmar<TRightSet<R>> {
     /// memory permissions.
     pub fn protect(&self, perms: VmPerms, range: Range<usize>) -> Result<()> {
         self.check_rights(perms.into())?;
-        self.0.protect(perms, range)
+        self.0.protect(perms, None, range)
     }
 
     /// clear all mappings and children vmars.
@@ -142,6 +142,26 @@ impl<R: TRights> Vmar<TRightSet<R>> {
         self.0.destroy(range)
     }
 
+    /// Resizes the mapped range `old_range` to `new_size` bytes, moving it if necessary.
+    ///
+    /// The range must be within a single mapping. If `new_addr` is specified, the
+    /// range will be moved there, and any mappings in the destination range will
+    /// be destroyed. Otherwise, the range is resized in place if possible, or moved
+    /// to a free region if `may_move` is true.
+    ///
+    /// All the sizes and addresses must be page-aligned.
+    ///
+    /// Returns the new start address of the range.
+    pub fn remap(
+        &self,
+        old_range: Range<usize>,
+        new_size: usize,
+        may_move: bool,
+        new_addr: Option<Vaddr>,
+    ) -> Result<Vaddr> {
+        self.0.remap(old_range, new_size, may_move, new_addr)
+    }
+
     /// Duplicate the capability.
     ///
     /// # Access rights
@@ -5,15 +5,25 @@
 
 use core::ops::Range;
 
-use aster_frame::mm::{Frame, FrameVec, PageFlags, VmIo, VmMapOptions, VmSpace};
+use aster_frame::mm::{
+    reclaim::{register_shrinker, Shrinker},
+    CachePolicy, Frame, FrameVec, Paddr, PageFlags, VmIo, VmMapOptions, VmQueryResult, VmReader,
+    VmSpace,
+};
+use spin::Once;
 
-use super::{interval::Interval, is_intersected, Vmar, Vmar_};
+use super::{
+    fault_stats::FaultStats, interval::Interval, is_intersected, rmap::PteOp, rss::RssType,
+    shared_mem::SharedMem, Vmar, Vmar_,
+};
 use crate::{
+    fs::path::Dentry,
     prelude::*,
     vm::{
         perms::VmPerms,
+        userfault::UserfaultCtx,
         vmar::Rights,
-        vmo::{get_page_idx_range, Vmo, VmoChildOptions, VmoRightsOp},
+        vmo::{get_page_idx_range, Vmo, VmoChildOptions, VmoFlags, VmoOptions, VmoRightsOp},
     },
 };
 
@@ -31,6 +41,15 @@ pub struct VmMapping {
     /// TODO: support file-backed shared mappings.
     /// only anonyous memory can be mapped shared now.
     is_shared: bool,
+    /// The shared memory object that is mapped, if any.
+    shared_mem: Option<Arc<SharedMem>>,
+    /// The file that backs the mapping, if any, and the offset in the file where the
+    /// vmo starts.
+    file: Option<(Arc<Dentry>, usize)>,
+    /// The name of the mapping that is not backed by a file, e.g., `[heap]`.
+    name: Option<&'static str>,
+    /// The statistics of the page faults on the mapping.
+    fault_stats: FaultStats,
 }
 
 impl VmMapping {
@@ -42,6 +61,10 @@ impl VmMapping {
             parent: self.parent.clone(),
             vmo,
             is_shared: self.is_shared,
+            shared_mem: self.shared_mem.clone(),
+            file: self.file.clone(),
+            name: self.name,
+            fault_stats: FaultStats::new(),
         })
     }
 }
@@ -62,6 +85,28 @@ struct VmMappingInner {
     /// The permissions of pages in the mapping.
     /// All pages within the same VmMapping have the same permissions.
     perms: VmPerms,
+    /// Whether the pages in the mapping are locked in memory.
+    is_locked: bool,
+    /// The pages that can be freed lazily under memory pressure, unless they are written
+    /// again. The key is the page index in vmo.
+    ///
+    /// All the mapped pages of a droppable mapping are lazily freeable, even if they
+    /// are written.
+    lazy_free_pages: BTreeSet<usize>,
+    /// The context that the faults on the missing pages are delegated to, if any.
+    userfault: Option<Arc<UserfaultCtx>>,
+    /// The RSS counter that the mapped pages are accounted to.
+    rss_type: RssType,
+    /// Whether the mapping is a stack, which grows down on the page faults below it.
+    grows_down: bool,
+    /// The protection key of the pages in the mapping.
+    pkey: u8,
+    /// Whether the mapping is droppable, i.e., mapped with `MAP_DROPPABLE`. The pages
+    /// of a droppable mapping may be dropped under memory pressure, and are wiped in
+    /// the child process on fork.
+    is_droppable: bool,
+    /// The cache policy of the pages in the mapping.
+    cache_policy: CachePolicy,
 }
 
 impl Interval<usize> for Arc<VmMapping> {
@@ -82,8 +127,16 @@ impl VmMapping {
             align,
             can_overwrite,
             is_shared,
+            shared_mem,
+            grows_down,
+            is_droppable,
+            file,
+            name,
+            cache_policy,
         } = option;
         let Vmar(parent_vmar, _) = parent;
+        // Like Linux, the droppable mappings are never locked in memory.
+        let is_locked = parent_vmar.lock_future().is_some() && !is_droppable;
         let vmo_size = vmo.size();
         let map_to_addr = parent_vmar.allocate_free_region_for_vmo(
             vmo_size,
@@ -98,6 +151,14 @@ impl VmMapping {
             map_to_addr + size
         );
 
+        let rss_type = if !vmo.is_anonymous() {
+            RssType::File
+        } else if is_shared {
+            RssType::Shmem
+        } else {
+            RssType::Anon
+        };
+
         let vm_mapping_inner = VmMappingInner {
             vmo_offset,
             map_size: size,
@@ -105,6 +166,14 @@ impl VmMapping {
             is_destroyed: false,
             mapped_pages: BTreeSet::new(),
             perms,
+            is_locked,
+            lazy_free_pages: BTreeSet::new(),
+            userfault: None,
+            rss_type,
+            grows_down,
+            pkey: 0,
+            is_droppable,
+            cache_policy,
         };
 
         Ok(Self {
@@ -112,6 +181,10 @@ impl VmMapping {
             parent: Arc::downgrade(&parent_vmar),
             vmo: vmo.to_dyn(),
             is_shared,
+            shared_mem,
+            file,
+            name,
+            fault_stats: FaultStats::new(),
         })
     }
 
@@ -120,27 +193,112 @@ impl VmMapping {
     ///
     /// Note: Since such new mappings will intersect with the current mapping,
     /// making sure that when adding the new mapping into a Vmar, the current mapping in the Vmar will be removed.
-    fn clone_partial(
+    fn clone_partial(&self, range: Range<usize>) -> Result<Arc<VmMapping>> {
+        let partial_mapping = Arc::new(self.try_clone()?);
+        // Adjust the mapping range.
+        partial_mapping.inner.lock().shrink_to(range);
+        partial_mapping.track_if_droppable();
+        Ok(partial_mapping)
+    }
+
+    /// Build a new VmMapping that maps the part of the VMO mapped at `range` of the current
+    /// `VmMapping` to `new_addr`, with a size of `new_size`.
+    ///
+    /// If the new mapping is larger, the VMO will be enlarged (see [`Self::enlarge`]). The pages
+    /// already mapped in the `VmSpace` are not moved by this method.
+    ///
+    /// Note: Like [`Self::clone_partial`], the new mapping shares the VMO with the current mapping,
+    /// so the current mapping should be trimmed by the caller.
+    pub(super) fn new_remapped(
         &self,
         range: Range<usize>,
-        new_perms: Option<VmPerms>,
+        new_addr: Vaddr,
+        new_size: usize,
     ) -> Result<Arc<VmMapping>> {
-        let partial_mapping = Arc::new(self.try_clone()?);
-        // Adjust the mapping range and the permission.
+        let remapped_mapping = self.clone_partial(range)?;
         {
-            let mut inner = partial_mapping.inner.lock();
-            inner.shrink_to(range);
-            if let Some(perms) = new_perms {
-                inner.perms = perms;
+            let mut inner = remapped_mapping.inner.lock();
+            if new_size > inner.map_size {
+                remapped_mapping.enlarge_vmo(&inner, new_size)?;
             }
+            inner.map_to_addr = new_addr;
+            inner.map_size = new_size;
         }
-        Ok(partial_mapping)
+        Ok(remapped_mapping)
+    }
+
+    /// Enlarge the mapping in place to `new_size`.
+    ///
+    /// The part of the VMO beyond the current mapping must not be used by other mappings,
+    /// since it will be resized and filled with zeros.
+    pub(super) fn enlarge(&self, new_size: usize) -> Result<()> {
+        let mut inner = self.inner.lock();
+        debug_assert!(new_size > inner.map_size);
+        self.enlarge_vmo(&inner, new_size)?;
+        inner.map_size = new_size;
+        Ok(())
+    }
+
+    /// Returns whether the mapping is a stack that grows down.
+    pub fn grows_down(&self) -> bool {
+        self.inner.lock().grows_down
+    }
+
+    /// Grow the mapping down in place to start at `new_start`.
+    ///
+    /// The mapping is backed by the part of the VMO before the current mapping, so it
+    /// cannot grow beyond the start of the VMO.
+    pub(super) fn grow_down(&self, new_start: Vaddr) -> Result<()> {
+        let mut inner = self.inner.lock();
+        debug_assert!(inner.grows_down);
+        debug_assert!(new_start % PAGE_SIZE == 0);
+        debug_assert!(new_start < inner.map_to_addr);
+        let grown_size = inner.map_to_addr - new_start;
+        if grown_size > inner.vmo_offset {
+            return_errno_with_message!(Errno::ENOMEM, "the stack exceeds its vmo");
+        }
+        inner.vmo_offset -= grown_size;
+        inner.map_to_addr = new_start;
+        inner.map_size += grown_size;
+        Ok(())
+    }
+
+    fn enlarge_vmo(&self, inner: &VmMappingInner, new_size: usize) -> Result<()> {
+        if !self.vmo.flags().contains(VmoFlags::RESIZABLE) {
+            return_errno_with_message!(Errno::ENOMEM, "the vmo of the mapping is not resizable");
+        }
+        // Shrinking the VMO first drops the pages left by previous trimming, so that the
+        // enlarged part of the mapping is always filled with zeros.
+        self.vmo.resize(inner.vmo_offset + inner.map_size)?;
+        self.vmo.resize(inner.vmo_offset + new_size)
     }
 
     pub fn vmo(&self) -> &Vmo<Rights> {
         &self.vmo
     }
 
+    /// Returns the shared memory object that is mapped, if any.
+    pub fn shared_mem(&self) -> Option<&Arc<SharedMem>> {
+        self.shared_mem.as_ref()
+    }
+
+    /// Returns whether the mapping is shared among processes.
+    pub fn is_shared(&self) -> bool {
+        self.is_shared
+    }
+
+    /// Returns the file that backs the mapping, if any, and the offset in the file where
+    /// the mapping starts.
+    pub fn file(&self) -> Option<(&Arc<Dentry>, usize)> {
+        let (dentry, file_offset) = self.file.as_ref()?;
+        Some((dentry, file_offset + self.vmo_offset()))
+    }
+
+    /// Returns the name of the mapping that is not backed by a file, if any.
+    pub fn name(&self) -> Option<&'static str> {
+        self.name
+    }
+
     /// Add a new committed page and map it to vmspace. If copy on write is set, it's allowed to unmap the page at the same address.
     /// FIXME: This implementation based on the truth that we map one page at a time. If multiple pages are mapped together, this implementation may have problems
     pub(super) fn map_one_page(
@@ -150,17 +308,15 @@ impl VmMapping {
         is_readonly: bool,
     ) -> Result<()> {
         let parent = self.parent.upgrade().unwrap();
-        let vm_space = parent.vm_space();
         self.inner
             .lock()
-            .map_one_page(&self.vmo, vm_space, page_idx, frame, is_readonly)
+            .map_one_page(&self.vmo, &parent, page_idx, frame, is_readonly)
     }
 
     /// unmap a page
     pub(super) fn unmap_one_page(&self, page_idx: usize) -> Result<()> {
         let parent = self.parent.upgrade().unwrap();
-        let vm_space = parent.vm_space();
-        self.inner.lock().unmap_one_page(vm_space, page_idx)
+        self.inner.lock().unmap_one_page(&parent, page_idx)
     }
 
     /// the mapping's start address
@@ -178,6 +334,16 @@ impl VmMapping {
         self.inner.lock().vmo_offset
     }
 
+    /// the permissions of the mapping
+    pub fn perms(&self) -> VmPerms {
+        self.inner.lock().perms
+    }
+
+    /// Returns the protection key of the pages in the mapping.
+    pub fn pkey(&self) -> u8 {
+        self.inner.lock().pkey
+    }
+
     pub fn read_bytes(&self, offset: usize, buf: &mut [u8]) -> Result<()> {
         let vmo_read_offset = self.vmo_offset() + offset;
 
@@ -201,7 +367,7 @@ impl VmMapping {
 
         let mut page_addr =
             self.map_to_addr() - self.vmo_offset() + page_idx_range.start * PAGE_SIZE;
-        for page_idx in page_idx_range {
+        for page_idx in page_idx_range.clone() {
             let parent = self.parent.upgrade().unwrap();
             let vm_space = parent.vm_space();
 
@@ -216,27 +382,141 @@ impl VmMapping {
             }
             page_addr += PAGE_SIZE;
         }
+        // The writes do not go through the page table, so the pages must not be freed lazily.
+        let mut inner = self.inner.lock();
+        if !inner.is_droppable {
+            inner
+                .lazy_free_pages
+                .retain(|page_idx| !page_idx_range.contains(page_idx));
+        }
+        drop(inner);
 
         self.vmo.write_bytes(vmo_write_offset, buf)?;
         Ok(())
     }
 
+    /// Atomically replaces the `u32` at `offset` with `new` if it equals `current`, and
+    /// returns the previous value, which is `Ok` if the value is replaced.
+    ///
+    /// The mapping must be writable. Unlike [`Self::write_bytes`], the value is updated in
+    /// the frame that is mapped to the user space, so the update is atomic with the atomic
+    /// operations of the user space on the same value.
+    pub fn compare_exchange_u32(
+        &self,
+        offset: usize,
+        current: u32,
+        new: u32,
+    ) -> Result<core::result::Result<u32, u32>> {
+        if offset % core::mem::size_of::<u32>() != 0 {
+            return_errno_with_message!(Errno::EINVAL, "the address is not aligned");
+        }
+        let vmo_offset = self.vmo_offset() + offset;
+        let page_idx = vmo_offset / PAGE_SIZE;
+        self.check_page_idx_range(&(page_idx..page_idx + 1))?;
+        self.check_perms(&VmPerms::WRITE)?;
+
+        // The page may be read-only due to COW, see `write_bytes`.
+        let page_addr = self.map_to_addr() - self.vmo_offset() + page_idx * PAGE_SIZE;
+        let parent = self.parent.upgrade().unwrap();
+        let need_page_fault = parent
+            .vm_space()
+            .query(page_addr)?
+            .is_some_and(|prop| !prop.flags.contains(PageFlags::W));
+        if need_page_fault {
+            self.handle_page_fault(page_addr, false, true)?;
+        }
+        let mut inner = self.inner.lock();
+        if !inner.is_droppable {
+            inner.lazy_free_pages.retain(|idx| *idx != page_idx);
+        }
+        drop(inner);
+
+        let frame = self.vmo.get_committed_frame(page_idx, true)?;
+        Ok(frame.compare_exchange_u32(vmo_offset % PAGE_SIZE, current, new)?)
+    }
+
+    /// Writes `buf` at `offset` even if the mapping is not writable, like a debugger that
+    /// inserts breakpoints into the code of its tracee.
+    ///
+    /// The pages of a private mapping are copied before they are written, so the backing
+    /// file is not modified. The written pages are mapped with the original permissions.
+    pub fn force_write_bytes(&self, offset: usize, buf: &[u8]) -> Result<()> {
+        if self.check_perms(&VmPerms::WRITE).is_ok() {
+            return self.write_bytes(offset, buf);
+        }
+        if self.is_shared || !self.vmo.is_cow_vmo() {
+            return_errno_with_message!(Errno::EACCES, "the shared mapping is not writable");
+        }
+
+        let vmo_write_offset = self.vmo_offset() + offset;
+        let page_idx_range = get_page_idx_range(&(vmo_write_offset..vmo_write_offset + buf.len()));
+        self.check_page_idx_range(&page_idx_range)?;
+
+        let mut buf_offset = 0;
+        let mut page_offset = vmo_write_offset % PAGE_SIZE;
+        for page_idx in page_idx_range {
+            // The frame is written directly, since the VMO may not have the write right.
+            let frame = self.vmo.get_committed_frame(page_idx, true)?;
+            let write_len = (PAGE_SIZE - page_offset).min(buf.len() - buf_offset);
+            let mut reader: VmReader = buf[buf_offset..buf_offset + write_len].into();
+            frame.writer().skip(page_offset).write(&mut reader);
+            self.map_one_page(page_idx, frame, false)?;
+
+            buf_offset += write_len;
+            page_offset = 0;
+        }
+        Ok(())
+    }
+
     /// Unmap pages in the range
     pub fn unmap(&self, range: &Range<usize>, may_destroy: bool) -> Result<()> {
         let parent = self.parent.upgrade().unwrap();
-        let vm_space = parent.vm_space();
-        self.inner.lock().unmap(vm_space, range, may_destroy)
+        self.inner.lock().unmap(&parent, range, may_destroy)
     }
 
     pub fn is_destroyed(&self) -> bool {
         self.inner.lock().is_destroyed
     }
 
+    /// Returns whether the pages in the mapping are locked in memory.
+    pub fn is_locked(&self) -> bool {
+        self.inner.lock().is_locked
+    }
+
+    /// Returns whether the mapping is droppable.
+    pub fn is_droppable(&self) -> bool {
+        self.inner.lock().is_droppable
+    }
+
+    /// Lets the shrinker drop the pages of the mapping under memory pressure, if the
+    /// mapping is droppable.
+    pub(super) fn track_if_droppable(self: &Arc<Self>) {
+        if self.is_droppable() {
+            lazy_free_shrinker().add(Arc::downgrade(self));
+        }
+    }
+
+    /// Returns the statistics of the page faults on the mapping.
+    pub fn fault_stats(&self) -> &FaultStats {
+        &self.fault_stats
+    }
+
     pub fn handle_page_fault(
         &self,
         page_fault_addr: Vaddr,
         not_present: bool,
         write: bool,
+    ) -> Result<()> {
+        self.fault_stats.record(write, || {
+            self.do_handle_page_fault(page_fault_addr, not_present, write)
+        })
+    }
+
+    fn do_handle_page_fault(
+        &self,
+        page_fault_addr: Vaddr,
+        not_present: bool,
+        write: bool,
     ) -> Result<()> {
         let vmo_offset = self.vmo_offset() + page_fault_addr - self.map_to_addr();
         if vmo_offset >= self.vmo.size() {
@@ -263,34 +543,522 @@ impl VmMapping {
     /// Protect a specified range of pages in the mapping to the target perms.
     /// The VmMapping will split to maintain its property.
     ///
+    /// If `new_pkey` is `Some(_)`, the protection key of the pages is changed as well.
+    ///
     /// Since this method will modify the `vm_mappings` in the vmar,
     /// it should not be called during the direct iteration of the `vm_mappings`.
-    pub(super) fn protect(&self, new_perms: VmPerms, range: Range<usize>) -> Result<()> {
-        // If `new_perms` is equal to `old_perms`, `protect()` will not modify any permission in the VmMapping.
-        let old_perms = self.inner.lock().perms;
-        if old_perms == new_perms {
+    pub(super) fn protect(
+        &self,
+        new_perms: VmPerms,
+        new_pkey: Option<u8>,
+        range: Range<usize>,
+    ) -> Result<()> {
+        let rights = Rights::from(new_perms);
+        self.vmo().check_rights(rights)?;
+        match &self.shared_mem {
+            // The permissions of the shared memory cannot be restricted while the mapping
+            // is updated, so the new permissions are either rejected here or revoked by a
+            // later restriction.
+            Some(shared_mem) => shared_mem.with_allowed_perms(new_perms, || {
+                self.protect_allowed(new_perms, new_pkey, range)
+            }),
+            None => self.protect_allowed(new_perms, new_pkey, range),
+        }
+    }
+
+    /// Protects the range like [`Self::protect`], with the permissions that are known to
+    /// be allowed by the shared memory, if the mapping is of one.
+    pub(super) fn protect_allowed(
+        &self,
+        new_perms: VmPerms,
+        new_pkey: Option<u8>,
+        range: Range<usize>,
+    ) -> Result<()> {
+        // If nothing is changed, `protect()` will not modify any permission in the VmMapping.
+        let (old_perms, old_pkey) = {
+            let inner = self.inner.lock();
+            (inner.perms, inner.pkey)
+        };
+        let new_pkey = new_pkey.unwrap_or(old_pkey);
+        if old_perms == new_perms && old_pkey == new_pkey {
             return Ok(());
         }
 
-        let rights = Rights::from(new_perms);
-        self.vmo().check_rights(rights)?;
         // Protect permission for the perm in the VmMapping.
-        self.protect_with_subdivision(&range, new_perms)?;
+        self.update_with_subdivision(&range, |inner| {
+            inner.perms = new_perms;
+            inner.pkey = new_pkey;
+        })?;
         // Protect permission in the VmSpace.
         let vmar = self.parent.upgrade().unwrap();
         let vm_space = vmar.vm_space();
-        self.inner.lock().protect(vm_space, new_perms, range)?;
+        self.inner
+            .lock()
+            .protect(vm_space, new_perms, new_pkey, range)?;
 
         Ok(())
     }
 
+    /// Lock or unlock the pages within a specified range of the mapping in memory.
+    /// The VmMapping will split to maintain its property.
+    ///
+    /// The pages are not committed by this method. See [`Self::populate`].
+    ///
+    /// Since this method will modify the `vm_mappings` in the vmar,
+    /// it should not be called during the direct iteration of the `vm_mappings`.
+    pub(super) fn set_locked(&self, is_locked: bool, range: Range<usize>) -> Result<()> {
+        let inner = self.inner.lock();
+        if inner.is_locked == is_locked || inner.is_droppable {
+            return Ok(());
+        }
+        drop(inner);
+
+        self.update_with_subdivision(&range, |inner| inner.is_locked = is_locked)
+    }
+
+    /// Returns the context that the faults on the pages of the mapping are delegated to.
+    pub fn userfault(&self) -> Option<Arc<UserfaultCtx>> {
+        self.inner
+            .lock()
+            .userfault
+            .clone()
+            .filter(|ctx| !ctx.is_released())
+    }
+
+    /// Returns the context that the fault at the address should be delegated to, if the
+    /// page is missing and the mapping is registered for user page fault delegation.
+    pub(super) fn missing_page_userfault(
+        &self,
+        page_fault_addr: Vaddr,
+    ) -> Option<Arc<UserfaultCtx>> {
+        let ctx = self.userfault()?;
+        let vmo_offset = self.vmo_offset() + page_fault_addr - self.map_to_addr();
+        if vmo_offset >= self.vmo.size() || self.vmo.is_page_committed(vmo_offset / PAGE_SIZE) {
+            return None;
+        }
+        Some(ctx)
+    }
+
+    /// Register or unregister the pages within a specified range of the mapping for user
+    /// page fault delegation. The VmMapping will split to maintain its property.
+    ///
+    /// Since this method will modify the `vm_mappings` in the vmar,
+    /// it should not be called during the direct iteration of the `vm_mappings`.
+    pub(super) fn set_userfault(
+        &self,
+        ctx: Option<Arc<UserfaultCtx>>,
+        range: Range<usize>,
+    ) -> Result<()> {
+        let is_same = match (&self.inner.lock().userfault, &ctx) {
+            (Some(old_ctx), Some(new_ctx)) => Arc::ptr_eq(old_ctx, new_ctx),
+            (None, None) => true,
+            _ => false,
+        };
+        if is_same {
+            return Ok(());
+        }
+
+        self.update_with_subdivision(&range, |inner| inner.userfault = ctx)
+    }
+
+    /// Fill the missing pages within a specified range of the mapping to resolve the faults
+    /// on them. The pages are filled with `src`, or with zeros if `src` is `None`.
+    ///
+    /// The mapping must be registered with `ctx`. The filling stops at the first page that
+    /// is not missing. Returns the number of bytes that are filled.
+    pub(super) fn userfault_fill(
+        &self,
+        ctx: &Arc<UserfaultCtx>,
+        range: Range<usize>,
+        src: Option<&[u8]>,
+    ) -> Result<usize> {
+        let vmo_range = {
+            let inner = self.inner.lock();
+            if !inner
+                .userfault
+                .as_ref()
+                .is_some_and(|inner_ctx| Arc::ptr_eq(inner_ctx, ctx))
+            {
+                return_errno_with_message!(
+                    Errno::ENOENT,
+                    "the mapping is not registered for user page fault delegation"
+                );
+            }
+            inner.vmo_range(&range)
+        };
+        if vmo_range.end > self.vmo.size() {
+            return_errno_with_message!(Errno::EFAULT, "the range is not backed up by the vmo");
+        }
+
+        let mut nr_filled = 0;
+        for vmo_offset in vmo_range.step_by(PAGE_SIZE) {
+            let page_src = src.map(|src| &src[nr_filled..nr_filled + PAGE_SIZE]);
+            if !self.vmo.fill_page(vmo_offset / PAGE_SIZE, page_src)? {
+                break;
+            }
+            nr_filled += PAGE_SIZE;
+        }
+        if nr_filled == 0 {
+            return_errno_with_message!(Errno::EEXIST, "the page is not missing");
+        }
+        Ok(nr_filled)
+    }
+
+    /// Commit the pages within a specified range of the mapping and map them to the vmspace,
+    /// so that accessing them will not cause page faults.
+    ///
+    /// Writable pages are committed by write accesses, so that the copy-on-write pages are
+    /// copied as well. The pages beyond the vmo and the pages that are not accessible are skipped.
+    ///
+    /// The pages are committed first, and then mapped in a batch, i.e., with one pass of the
+    /// page table cursor and one TLB flush, rather than by one page fault for each page.
+    pub(super) fn populate(&self, range: Range<usize>) -> Result<()> {
+        let (perms, vmo_range) = {
+            let inner = self.inner.lock();
+            (inner.perms, inner.vmo_range(&range))
+        };
+        if !perms.intersects(VmPerms::READ | VmPerms::WRITE) {
+            return Ok(());
+        }
+        let write = perms.contains(VmPerms::WRITE);
+        self.vmo
+            .check_rights(if write { Rights::WRITE } else { Rights::READ })?;
+
+        let vmo_end = vmo_range.end.min(self.vmo.size());
+        if vmo_range.start >= vmo_end {
+            return Ok(());
+        }
+        let page_idx_range = get_page_idx_range(&(vmo_range.start..vmo_end));
+
+        // The pages committed before an error are still mapped.
+        let mut frames = FrameVec::new_with_capacity(page_idx_range.len());
+        let mut commit_result = Ok(());
+        for page_idx in page_idx_range.clone() {
+            match self.vmo.get_committed_frame(page_idx, write) {
+                Ok(frame) => frames.push(frame),
+                Err(err) => {
+                    commit_result = Err(err);
+                    break;
+                }
+            }
+        }
+        if !frames.is_empty() {
+            let parent = self.parent.upgrade().unwrap();
+            self.inner
+                .lock()
+                .map_pages(&parent, page_idx_range.start, frames)?;
+        }
+        commit_result
+    }
+
+    /// Discard the pages within a specified range of the mapping.
+    ///
+    /// The pages are unmapped from the vmspace. For private mappings, they are dropped from
+    /// the vmo as well, so accessing them again will see zero pages for anonymous mappings
+    /// and the file contents for file-backed mappings. For shared mappings, the data are kept.
+    pub(super) fn discard(&self, range: Range<usize>) -> Result<()> {
+        let parent = self.parent.upgrade().unwrap();
+        let vm_space = parent.vm_space();
+        let mut inner = self.inner.lock();
+        if inner.is_locked {
+            return_errno_with_message!(Errno::EINVAL, "the pages are locked in memory");
+        }
+
+        let nr_unmapped = vm_space.unmap(&range)?;
+        parent.rss().sub(inner.rss_type, nr_unmapped);
+        let vmo_range = inner.vmo_range(&range);
+        let page_idx_range = get_page_idx_range(&vmo_range);
+        inner
+            .mapped_pages
+            .retain(|page_idx| !page_idx_range.contains(page_idx));
+        inner
+            .lazy_free_pages
+            .retain(|page_idx| !page_idx_range.contains(page_idx));
+
+        if !self.is_shared {
+            let vmo_range = vmo_range.start..vmo_range.end.min(self.vmo.size());
+            if !vmo_range.is_empty() {
+                self.vmo.discard(vmo_range)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Mark the pages within a specified range of the mapping as lazily freeable.
+    ///
+    /// The pages are freed only under memory pressure, and the ones that are written again
+    /// before that are kept. Only private anonymous mappings are supported.
+    pub(super) fn lazy_free(self: &Arc<Self>, range: Range<usize>) -> Result<()> {
+        if self.is_shared || !self.vmo.is_anonymous() {
+            return_errno_with_message!(
+                Errno::EINVAL,
+                "only private anonymous mappings can be freed lazily"
+            );
+        }
+
+        let parent = self.parent.upgrade().unwrap();
+        let vm_space = parent.vm_space();
+        let mut inner = self.inner.lock();
+        if inner.is_locked {
+            return_errno_with_message!(Errno::EINVAL, "the pages are locked in memory");
+        }
+
+        // The dirty bits tell whether the pages are written again.
+        vm_space.protect(&range, |prop| prop.flags -= PageFlags::DIRTY)?;
+        let page_idx_range = get_page_idx_range(&inner.vmo_range(&range));
+        let pages: Vec<usize> = inner.mapped_pages.range(page_idx_range).copied().collect();
+        if pages.is_empty() {
+            return Ok(());
+        }
+        inner.lazy_free_pages.extend(pages);
+        drop(inner);
+
+        lazy_free_shrinker().add(Arc::downgrade(self));
+        Ok(())
+    }
+
+    /// Ask the pager to read the pages within a specified range of the mapping ahead of time.
+    ///
+    /// This does nothing for anonymous mappings.
+    pub(super) fn will_need(&self, range: Range<usize>) -> Result<()> {
+        let vmo_range = self.inner.lock().vmo_range(&range);
+        let vmo_range = vmo_range.start..vmo_range.end.min(self.vmo.size());
+        if vmo_range.is_empty() {
+            return Ok(());
+        }
+        self.vmo.prefetch(vmo_range)
+    }
+
+    fn nr_lazy_free_pages(&self) -> usize {
+        self.inner
+            .try_lock()
+            .map_or(0, |inner| inner.lazy_free_pages.len())
+    }
+
+    /// Free at most `nr_to_reclaim` lazily freeable pages that are not written again.
+    ///
+    /// This method never blocks, so it can be called during memory reclamation.
+    /// Returns the number of pages that have been freed.
+    fn reclaim_lazy_free_pages(&self, nr_to_reclaim: usize) -> usize {
+        let Some(parent) = self.parent.upgrade() else {
+            return 0;
+        };
+        let vm_space = parent.vm_space();
+        let Some(mut inner) = self.inner.try_lock() else {
+            return 0;
+        };
+
+        let mut nr_reclaimed = 0;
+        while nr_reclaimed < nr_to_reclaim {
+            let Some(page_idx) = inner.lazy_free_pages.pop_first() else {
+                break;
+            };
+            let page_addr = inner.page_map_addr(page_idx);
+            let page_range = page_addr..(page_addr + PAGE_SIZE);
+
+            // Write-protect the page first, so that it cannot be written again after it is
+            // checked to be clean.
+            let mut is_writable = false;
+            let protected = vm_space.protect(&page_range, |prop| {
+                is_writable = prop.flags.contains(PageFlags::W);
+                prop.flags -= PageFlags::W;
+            });
+            let Ok(Some(prop)) = protected.and_then(|_| vm_space.query(page_addr)) else {
+                continue;
+            };
+            if prop.flags.contains(PageFlags::DIRTY) && !inner.is_droppable {
+                if is_writable {
+                    let _ = vm_space.protect(&page_range, |prop| prop.flags |= PageFlags::W);
+                }
+                continue;
+            }
+
+            let Ok(nr_unmapped) = vm_space.unmap(&page_range) else {
+                continue;
+            };
+            parent.rss().sub(inner.rss_type, nr_unmapped);
+            inner.mapped_pages.remove(&page_idx);
+            if self.vmo.try_evict_page(page_idx, |_| true) {
+                nr_reclaimed += 1;
+            }
+        }
+        nr_reclaimed
+    }
+
+    /// Returns the number of bytes that are swapped out within the range of the mapping.
+    pub fn swapped_size(&self, range: &Range<usize>) -> usize {
+        let vmo_range = self.inner.lock().vmo_range(range);
+        self.vmo.nr_swapped_pages(get_page_idx_range(&vmo_range)) * PAGE_SIZE
+    }
+
+    /// Swap out at most `nr_to_swap_out` pages within the range that are not accessed
+    /// since the last scan.
+    ///
+    /// The accessed pages are given a second chance, i.e., their accessed bits are cleared
+    /// so that they are swapped out in the next scan unless accessed again. Only the pages
+    /// of private anonymous mappings that are not locked in memory are swapped out.
+    ///
+    /// Like [`Self::reclaim_lazy_free_pages`], this method never blocks, so it can be
+    /// called during memory reclamation. Returns the number of pages that have been
+    /// swapped out.
+    pub(super) fn swap_out(&self, range: &Range<Vaddr>, nr_to_swap_out: usize) -> usize {
+        if self.is_shared || !self.vmo.is_anonymous() {
+            return 0;
+        }
+        let Some(parent) = self.parent.upgrade() else {
+            return 0;
+        };
+        let vm_space = parent.vm_space();
+        let Some(mut inner) = self.inner.try_lock() else {
+            return 0;
+        };
+        if inner.is_locked {
+            return 0;
+        }
+
+        let mut nr_swapped_out = 0;
+        let mapped_pages: Vec<usize> = inner.mapped_pages.iter().copied().collect();
+        for page_idx in mapped_pages {
+            if nr_swapped_out >= nr_to_swap_out {
+                break;
+            }
+            // The lazily freeable pages are reclaimed by dropping them instead.
+            if inner.lazy_free_pages.contains(&page_idx) {
+                continue;
+            }
+            let page_addr = inner.page_map_addr(page_idx);
+            if !range.contains(&page_addr) {
+                continue;
+            }
+            let page_range = page_addr..(page_addr + PAGE_SIZE);
+
+            let Ok(Some(prop)) = vm_space.query(page_addr) else {
+                continue;
+            };
+            if prop.flags.contains(PageFlags::ACCESSED) {
+                let _ = vm_space.protect(&page_range, |prop| prop.flags -= PageFlags::ACCESSED);
+                continue;
+            }
+
+            // Unmap the page first, so that it cannot be written while it is swapped out.
+            let Ok(nr_unmapped) = vm_space.unmap(&page_range) else {
+                continue;
+            };
+            parent.rss().sub(inner.rss_type, nr_unmapped);
+            inner.mapped_pages.remove(&page_idx);
+            if self.vmo.try_swap_out_page(page_idx) {
+                nr_swapped_out += 1;
+            }
+        }
+        nr_swapped_out
+    }
+
+    /// Migrates the mapped pages of an anonymous mapping within the physical address range
+    /// to the frames allocated by `alloc`.
+    ///
+    /// Like [`Self::swap_out`], this method never blocks on the locks. The pages that are
+    /// locked in memory are not migrated. Returns the number of pages that have been
+    /// migrated.
+    pub(super) fn migrate_pages(
+        &self,
+        range: &Range<Paddr>,
+        alloc: &mut dyn FnMut() -> Option<Frame>,
+    ) -> usize {
+        if !self.vmo.is_anonymous() {
+            return 0;
+        }
+        let mapped_pages: Vec<usize> = {
+            let Some(inner) = self.inner.try_lock() else {
+                return 0;
+            };
+            if inner.is_locked {
+                return 0;
+            }
+            inner.mapped_pages.iter().copied().collect()
+        };
+
+        // The lock of the mapping is released, since the VMO unmaps the pages from all the
+        // mappings, including this one.
+        mapped_pages
+            .into_iter()
+            .filter(|page_idx| self.vmo.try_migrate_page(*page_idx, range, alloc))
+            .count()
+    }
+
+    /// Operates on the page table entry where the page at `page_idx` of the VMO is mapped,
+    /// if the entry maps `frame`. Returns the flags of the entry before the operation, or
+    /// `None` if the entry does not map the frame.
+    ///
+    /// This method never blocks. See [`super::Rmap::try_unmap`] for the errors.
+    pub(super) fn try_operate_pte(
+        &self,
+        page_idx: usize,
+        frame: &Frame,
+        op: PteOp,
+    ) -> Result<Option<PageFlags>> {
+        let Some(parent) = self.parent.upgrade() else {
+            return Ok(None);
+        };
+        let Some(mut inner) = self.inner.try_lock() else {
+            return_errno_with_message!(Errno::EAGAIN, "the mapping is being operated on");
+        };
+        let vmo_range = inner.vmo_offset..(inner.vmo_offset + inner.map_size);
+        if !vmo_range.contains(&(page_idx * PAGE_SIZE)) {
+            return Ok(None);
+        }
+        let page_addr = inner.page_map_addr(page_idx);
+        let page_range = page_addr..(page_addr + PAGE_SIZE);
+
+        let vm_space = parent.vm_space();
+        let query_result = vm_space.query_range(&page_range)?.next();
+        let Some(VmQueryResult::Mapped {
+            frame: mapped_frame,
+            prop,
+            ..
+        }) = query_result
+        else {
+            return Ok(None);
+        };
+        if mapped_frame.start_paddr() != frame.start_paddr() {
+            return Ok(None);
+        }
+        drop(mapped_frame);
+
+        match op {
+            PteOp::ClearAccessed => {
+                if prop.flags.contains(PageFlags::ACCESSED) {
+                    vm_space.protect(&page_range, |prop| prop.flags -= PageFlags::ACCESSED)?;
+                }
+            }
+            PteOp::Unmap => {
+                if inner.is_locked {
+                    return_errno_with_message!(Errno::EBUSY, "the page is locked in memory");
+                }
+                let nr_unmapped = vm_space.unmap(&page_range)?;
+                parent.rss().sub(inner.rss_type, nr_unmapped);
+                inner.mapped_pages.remove(&page_idx);
+                inner.lazy_free_pages.remove(&page_idx);
+            }
+        }
+        Ok(Some(prop.flags))
+    }
+
     pub(super) fn new_fork(&self, new_parent: &Arc<Vmar_>) -> Result<VmMapping> {
         let VmMapping { inner, vmo, .. } = self;
 
         let child_vmo = {
             let parent_vmo = vmo.dup().unwrap();
             let vmo_size = parent_vmo.size();
-            if self.is_shared {
+            if self.shared_mem.is_some() {
+                // The VMO of a shared memory object may be resizable, which cannot have
+                // slice children. Sharing the VMO itself has the same effect.
+                parent_vmo
+            } else if self.is_droppable() {
+                // The pages of a droppable mapping are wiped in the child process.
+                VmoOptions::<Rights>::new(vmo_size)
+                    .flags(parent_vmo.flags())
+                    .alloc()?
+            } else if self.is_shared {
                 VmoChildOptions::new_slice_rights(parent_vmo, 0..vmo_size).alloc()?
             } else {
                 VmoChildOptions::new_cow(parent_vmo, 0..vmo_size).alloc()?
@@ -306,14 +1074,37 @@ impl VmMapping {
                 is_destroyed: inner.is_destroyed,
                 mapped_pages: BTreeSet::new(),
                 perms: inner.perms,
+                // Memory locks are not inherited by the child process.
+                is_locked: false,
+                lazy_free_pages: BTreeSet::new(),
+                // Neither is the delegation of user page faults.
+                userfault: None,
+                rss_type: inner.rss_type,
+                grows_down: inner.grows_down,
+                pkey: inner.pkey,
+                is_droppable: inner.is_droppable,
+                cache_policy: inner.cache_policy,
             }
         };
 
+        if new_inner.is_droppable {
+            // The pages inherited by the copy-on-write page table are wiped as well.
+            let nr_unmapped = new_parent.vm_space().unmap(&new_inner.range())?;
+            new_parent.rss().sub(new_inner.rss_type, nr_unmapped);
+        }
+
+        // The child VMO shares the reverse mapping with the VMO of the mapping.
+        child_vmo.rmap().add_vmar(new_parent);
+
         Ok(VmMapping {
             inner: Mutex::new(new_inner),
             parent: Arc::downgrade(new_parent),
             vmo: child_vmo,
             is_shared: self.is_shared,
+            shared_mem: self.shared_mem.clone(),
+            file: self.file.clone(),
+            name: self.name,
+            fault_stats: FaultStats::new(),
         })
     }
 
@@ -321,11 +1112,11 @@ impl VmMapping {
         self.map_to_addr()..self.map_to_addr() + self.map_size()
     }
 
-    /// Protect the current `VmMapping` to enforce new permissions within a specified range.
+    /// Update the properties (e.g., the permissions) of the current `VmMapping` within a specified range.
     ///
     /// Due to the property of `VmMapping`, this operation may require subdividing the current
-    /// `VmMapping`. In this condition, it will generate a new `VmMapping` with the specified `perm` to protect the
-    /// target range, as well as additional `VmMappings` to preserve the mappings in the remaining ranges.
+    /// `VmMapping`. In this condition, it will generate a new `VmMapping` with the updated properties for
+    /// the target range, as well as additional `VmMappings` to preserve the mappings in the remaining ranges.
     ///
     /// There are four conditions:
     /// 1. |--------old perm--------| -> |-old-| + |------new------|
@@ -333,45 +1124,59 @@ impl VmMapping {
     /// 3. |--------old perm--------| -> |-old-| + |-new-| + |-old-|
     /// 4. |--------old perm--------| -> |---------new perm--------|
     ///
-    /// Generally, this function is only used in `protect()` method.
+    /// Generally, this function is only used in `protect()`, `set_locked()` and `set_userfault()`
+    /// methods.
     /// This method modifies the parent `Vmar` in the end if subdividing is required.
     /// It removes current mapping and add splitted mapping to the Vmar.
-    fn protect_with_subdivision(
-        &self,
-        intersect_range: &Range<usize>,
-        perms: VmPerms,
-    ) -> Result<()> {
+    fn update_with_subdivision<F>(&self, intersect_range: &Range<usize>, update: F) -> Result<()>
+    where
+        F: FnOnce(&mut VmMappingInner),
+    {
         let mut additional_mappings = Vec::new();
         let range = self.range();
         // Condition 4, the `additional_mappings` will be empty.
         if range.start == intersect_range.start && range.end == intersect_range.end {
-            self.inner.lock().perms = perms;
+            update(&mut self.inner.lock());
             return Ok(());
         }
         // Condition 1 or 3, which needs an additional new VmMapping with range (range.start..intersect_range.start)
         if range.start < intersect_range.start {
-            let additional_left_mapping =
-                self.clone_partial(range.start..intersect_range.start, None)?;
+            let additional_left_mapping = self.clone_partial(range.start..intersect_range.start)?;
             additional_mappings.push(additional_left_mapping);
         }
         // Condition 2 or 3, which needs an additional new VmMapping with range (intersect_range.end..range.end).
         if range.end > intersect_range.end {
-            let additional_right_mapping =
-                self.clone_partial(intersect_range.end..range.end, None)?;
+            let additional_right_mapping = self.clone_partial(intersect_range.end..range.end)?;
             additional_mappings.push(additional_right_mapping);
         }
-        // The protected VmMapping must exist and its range is `intersect_range`.
-        let protected_mapping = self.clone_partial(intersect_range.clone(), Some(perms))?;
+        // The updated VmMapping must exist and its range is `intersect_range`.
+        let updated_mapping = self.clone_partial(intersect_range.clone())?;
+        update(&mut updated_mapping.inner.lock());
+
+        // The lazily freeable pages are now tracked by the new mappings.
+        let has_lazy_free_pages = {
+            let mut inner = self.inner.lock();
+            let has_lazy_free_pages = !inner.lazy_free_pages.is_empty();
+            inner.lazy_free_pages.clear();
+            has_lazy_free_pages
+        };
+        if has_lazy_free_pages {
+            let shrinker = lazy_free_shrinker();
+            shrinker.add(Arc::downgrade(&updated_mapping));
+            for mapping in additional_mappings.iter() {
+                shrinker.add(Arc::downgrade(mapping));
+            }
+        }
 
         // Begin to modify the `Vmar`.
         let vmar = self.parent.upgrade().unwrap();
         let mut vmar_inner = vmar.inner.lock();
         // Remove the original mapping.
         vmar_inner.vm_mappings.remove(&self.map_to_addr());
-        // Add protected mappings to the vmar.
+        // Add the updated mapping to the vmar.
         vmar_inner
             .vm_mappings
-            .insert(protected_mapping.map_to_addr(), protected_mapping);
+            .insert(updated_mapping.map_to_addr(), updated_mapping);
         // Add additional mappings to the vmar.
         for mapping in additional_mappings {
             vmar_inner
@@ -422,6 +1227,7 @@ impl VmMapping {
                 // The trim range was totally inside the old mapping.
                 let another_mapping = Arc::new(self.try_clone()?);
                 let another_map_to_addr = another_mapping.trim_left(trim_range.end)?;
+                another_mapping.track_if_droppable();
                 mappings_to_append.insert(another_map_to_addr, another_mapping);
             } else {
                 // Overlap vm_mapping from right.
@@ -435,15 +1241,13 @@ impl VmMapping {
     /// Trim the mapping from left to a new address.
     fn trim_left(&self, vaddr: Vaddr) -> Result<Vaddr> {
         let vmar = self.parent.upgrade().unwrap();
-        let vm_space = vmar.vm_space();
-        self.inner.lock().trim_left(vm_space, vaddr)
+        self.inner.lock().trim_left(&vmar, vaddr)
     }
 
     /// Trim the mapping from right to a new address.
     fn trim_right(&self, vaddr: Vaddr) -> Result<Vaddr> {
         let vmar = self.parent.upgrade().unwrap();
-        let vm_space = vmar.vm_space();
-        self.inner.lock().trim_right(vm_space, vaddr)
+        self.inner.lock().trim_right(&vmar, vaddr)
     }
 
     fn check_perms(&self, perms: &VmPerms) -> Result<()> {
@@ -459,11 +1263,12 @@ impl VmMappingInner {
     fn map_one_page(
         &mut self,
         vmo: &Vmo<Rights>,
-        vm_space: &VmSpace,
+        vmar: &Vmar_,
         page_idx: usize,
         frame: Frame,
         is_readonly: bool,
     ) -> Result<()> {
+        let vm_space = vmar.vm_space();
         let map_addr = self.page_map_addr(page_idx);
 
         let vm_perms = {
@@ -479,54 +1284,110 @@ impl VmMappingInner {
             let mut options = VmMapOptions::new();
             options.addr(Some(map_addr));
             options.flags(vm_perms.into());
+            options.pkey(self.pkey);
+            options.cache(self.cache_policy);
             options
         };
 
         // Cow child allows unmapping the mapped page.
         if vmo.is_cow_vmo() && vm_space.query(map_addr)?.is_some() {
-            vm_space.unmap(&(map_addr..(map_addr + PAGE_SIZE))).unwrap();
+            let nr_unmapped = vm_space.unmap(&(map_addr..(map_addr + PAGE_SIZE))).unwrap();
+            vmar.rss().sub(self.rss_type, nr_unmapped);
         }
 
         vm_space.map(FrameVec::from_one_frame(frame), &vm_map_options)?;
+        vmar.rss().add(self.rss_type, 1);
         self.mapped_pages.insert(page_idx);
+        if self.is_droppable {
+            self.lazy_free_pages.insert(page_idx);
+        } else {
+            self.lazy_free_pages.remove(&page_idx);
+        }
+        Ok(())
+    }
+
+    /// Map the committed frames to the consecutive pages starting from `start_page_idx`.
+    ///
+    /// The pages that are already mapped are replaced.
+    fn map_pages(&mut self, vmar: &Vmar_, start_page_idx: usize, frames: FrameVec) -> Result<()> {
+        let vm_space = vmar.vm_space();
+        let page_idx_range = start_page_idx..(start_page_idx + frames.len());
+        let vm_map_options = {
+            let mut options = VmMapOptions::new();
+            options.addr(Some(self.page_map_addr(start_page_idx)));
+            options.flags(self.perms.into());
+            options.pkey(self.pkey);
+            options.cache(self.cache_policy);
+            options.can_overwrite(true);
+            options
+        };
+        vm_space.map(frames, &vm_map_options)?;
+
+        let nr_new_pages = page_idx_range
+            .clone()
+            .filter(|page_idx| !self.mapped_pages.contains(page_idx))
+            .count();
+        vmar.rss().add(self.rss_type, nr_new_pages);
+        self.mapped_pages.extend(page_idx_range.clone());
+        if self.is_droppable {
+            self.lazy_free_pages.extend(page_idx_range);
+        } else {
+            self.lazy_free_pages
+                .retain(|page_idx| !page_idx_range.contains(page_idx));
+        }
         Ok(())
     }
 
-    fn unmap_one_page(&mut self, vm_space: &VmSpace, page_idx: usize) -> Result<()> {
+    fn unmap_one_page(&mut self, vmar: &Vmar_, page_idx: usize) -> Result<()> {
+        let vm_space = vmar.vm_space();
         let map_addr = self.page_map_addr(page_idx);
         let range = map_addr..(map_addr + PAGE_SIZE);
         if vm_space.query(map_addr)?.is_some() {
-            vm_space.unmap(&range)?;
+            let nr_unmapped = vm_space.unmap(&range)?;
+            vmar.rss().sub(self.rss_type, nr_unmapped);
         }
         self.mapped_pages.remove(&page_idx);
+        self.lazy_free_pages.remove(&page_idx);
         Ok(())
     }
 
     /// Unmap pages in the range.
-    fn unmap(&mut self, vm_space: &VmSpace, range: &Range<usize>, may_destroy: bool) -> Result<()> {
-        let map_to_addr = self.map_to_addr;
-        let vmo_map_range = (range.start - map_to_addr + self.vmo_offset)
-            ..(range.end - map_to_addr + self.vmo_offset);
-        let page_idx_range = get_page_idx_range(&vmo_map_range);
-        let original_mapped_pages = self.mapped_pages.clone();
-        let mapped_pages_in_range = original_mapped_pages.range(page_idx_range);
-        for page_idx in mapped_pages_in_range {
-            self.unmap_one_page(vm_space, *page_idx)?;
-        }
+    fn unmap(&mut self, vmar: &Vmar_, range: &Range<usize>, may_destroy: bool) -> Result<()> {
+        self.unmap_range(vmar, range)?;
         if may_destroy && *range == self.range() {
             self.is_destroyed = true;
         }
         Ok(())
     }
 
+    /// Unmap all the pages in the range, including those that are not in `mapped_pages`,
+    /// e.g., the pages inherited from the parent process when forking.
+    fn unmap_range(&mut self, vmar: &Vmar_, range: &Range<usize>) -> Result<()> {
+        let nr_unmapped = vmar.vm_space().unmap(range)?;
+        vmar.rss().sub(self.rss_type, nr_unmapped);
+        let page_idx_range = get_page_idx_range(&self.vmo_range(range));
+        self.mapped_pages
+            .retain(|page_idx| !page_idx_range.contains(page_idx));
+        self.lazy_free_pages
+            .retain(|page_idx| !page_idx_range.contains(page_idx));
+        Ok(())
+    }
+
     fn page_map_addr(&self, page_idx: usize) -> usize {
         page_idx * PAGE_SIZE + self.map_to_addr - self.vmo_offset
     }
 
+    /// Returns the vmo offset range mapped at the specified range.
+    fn vmo_range(&self, range: &Range<usize>) -> Range<usize> {
+        (range.start - self.map_to_addr + self.vmo_offset)
+            ..(range.end - self.map_to_addr + self.vmo_offset)
+    }
+
     pub(super) fn protect(
         &mut self,
         vm_space: &VmSpace,
         perms: VmPerms,
+        pkey: u8,
         range: Range<usize>,
     ) -> Result<()> {
         debug_assert!(range.start % PAGE_SIZE == 0);
@@ -539,14 +1400,17 @@ impl VmMappingInner {
             if vm_space.query(page_addr)?.is_some() {
                 // If the page is already mapped, we will modify page table
                 let page_range = page_addr..(page_addr + PAGE_SIZE);
-                vm_space.protect(&page_range, |p| p.flags = flags)?;
+                vm_space.protect(&page_range, |p| {
+                    p.flags = flags;
+                    p.pkey = pkey;
+                })?;
             }
         }
         Ok(())
     }
 
     /// Trim the mapping from left to a new address.
-    fn trim_left(&mut self, vm_space: &VmSpace, vaddr: Vaddr) -> Result<Vaddr> {
+    fn trim_left(&mut self, vmar: &Vmar_, vaddr: Vaddr) -> Result<Vaddr> {
         trace!(
             "trim left: range: {:x?}, vaddr = 0x{:x}",
             self.range(),
@@ -555,21 +1419,16 @@ impl VmMappingInner {
         debug_assert!(vaddr >= self.map_to_addr && vaddr <= self.map_to_addr + self.map_size);
         debug_assert!(vaddr % PAGE_SIZE == 0);
         let trim_size = vaddr - self.map_to_addr;
+        self.unmap_range(vmar, &(self.map_to_addr..vaddr))?;
 
         self.map_to_addr = vaddr;
-        let old_vmo_offset = self.vmo_offset;
         self.vmo_offset += trim_size;
         self.map_size -= trim_size;
-        for page_idx in old_vmo_offset / PAGE_SIZE..self.vmo_offset / PAGE_SIZE {
-            if self.mapped_pages.remove(&page_idx) {
-                let _ = self.unmap_one_page(vm_space, page_idx);
-            }
-        }
         Ok(self.map_to_addr)
     }
 
     /// Trim the mapping from right to a new address.
-    fn trim_right(&mut self, vm_space: &VmSpace, vaddr: Vaddr) -> Result<Vaddr> {
+    fn trim_right(&mut self, vmar: &Vmar_, vaddr: Vaddr) -> Result<Vaddr> {
         trace!(
             "trim right: range: {:x?}, vaddr = 0x{:x}",
             self.range(),
@@ -577,11 +1436,7 @@ impl VmMappingInner {
         );
         debug_assert!(vaddr >= self.map_to_addr && vaddr <= self.map_to_addr + self.map_size);
         debug_assert!(vaddr % PAGE_SIZE == 0);
-        let page_idx_range = (vaddr - self.map_to_addr + self.vmo_offset) / PAGE_SIZE
-            ..(self.map_size + self.vmo_offset) / PAGE_SIZE;
-        for page_idx in page_idx_range {
-            let _ = self.unmap_one_page(vm_space, page_idx);
-        }
+        self.unmap_range(vmar, &(vaddr..self.map_to_addr + self.map_size))?;
         self.map_size = vaddr - self.map_to_addr;
         Ok(self.map_to_addr)
     }
@@ -594,6 +1449,9 @@ impl VmMappingInner {
         self.vmo_offset += new_range.start - self.map_to_addr;
         self.map_to_addr = new_range.start;
         self.map_size = new_range.end - new_range.start;
+        let page_idx_range = get_page_idx_range(&self.vmo_range(&new_range));
+        self.lazy_free_pages
+            .retain(|page_idx| page_idx_range.contains(page_idx));
     }
 
     fn range(&self) -> Range<usize> {
@@ -617,6 +1475,68 @@ impl VmMappingInner {
     }
 }
 
+/// Reclaims the lazily freeable pages of private anonymous mappings under memory pressure.
+struct LazyFreeShrinker {
+    mappings: Mutex<Vec<Weak<VmMapping>>>,
+}
+
+static LAZY_FREE_SHRINKER: Once<Arc<LazyFreeShrinker>> = Once::new();
+
+fn lazy_free_shrinker() -> &'static Arc<LazyFreeShrinker> {
+    LAZY_FREE_SHRINKER.call_once(|| {
+        let shrinker = Arc::new(LazyFreeShrinker {
+            mappings: Mutex::new(Vec::new()),
+        });
+        register_shrinker(Arc::downgrade(&shrinker) as _);
+        shrinker
+    })
+}
+
+impl LazyFreeShrinker {
+    fn add(&self, mapping: Weak<VmMapping>) {
+        let mut mappings = self.mappings.lock();
+        if !mappings.iter().any(|added| added.ptr_eq(&mapping)) {
+            mappings.push(mapping);
+        }
+    }
+
+    /// Returns the mappings that may have lazily freeable pages, and forgets the others.
+    ///
+    /// The droppable mappings are kept, since their pages become lazily freeable once
+    /// they are mapped.
+    fn mappings(&self) -> Vec<Arc<VmMapping>> {
+        let mut mappings = self.mappings.lock();
+        mappings.retain(|mapping| {
+            mapping.upgrade().is_some_and(|mapping| {
+                mapping.inner.try_lock().map_or(true, |inner| {
+                    inner.is_droppable || !inner.lazy_free_pages.is_empty()
+                })
+            })
+        });
+        mappings.iter().filter_map(Weak::upgrade).collect()
+    }
+}
+
+impl Shrinker for LazyFreeShrinker {
+    fn nr_reclaimable(&self) -> usize {
+        self.mappings()
+            .iter()
+            .map(|mapping| mapping.nr_lazy_free_pages())
+            .sum()
+    }
+
+    fn shrink(&self, nr_to_reclaim: usize) -> usize {
+        let mut nr_reclaimed = 0;
+        for mapping in self.mappings() {
+            if nr_reclaimed >= nr_to_reclaim {
+                break;
+            }
+            nr_reclaimed += mapping.reclaim_lazy_free_pages(nr_to_reclaim - nr_reclaimed);
+        }
+        nr_reclaimed
+    }
+}
+
 /// Options for creating a new mapping. The mapping is not allowed to overlap
 /// with any child VMARs. And unless specified otherwise, it is not allowed
 /// to overlap with any existing mapping, either.
@@ -631,6 +1551,18 @@ pub struct VmarMapOptions<R1, R2> {
     can_overwrite: bool,
     // Whether the mapping is mapped with `MAP_SHARED`
     is_shared: bool,
+    // The shared memory object to be mapped
+    shared_mem: Option<Arc<SharedMem>>,
+    // Whether the mapping is a stack that grows down
+    grows_down: bool,
+    // Whether the mapping is mapped with `MAP_DROPPABLE`
+    is_droppable: bool,
+    // The file that backs the mapping and the offset in the file where the VMO starts
+    file: Option<(Arc<Dentry>, usize)>,
+    // The name of the mapping that is not backed by a file
+    name: Option<&'static str>,
+    // The cache policy of the pages in the mapping
+    cache_policy: CachePolicy,
 }
 
 impl<R1, R2> VmarMapOptions<R1, R2> {
@@ -652,6 +1584,12 @@ impl<R1, R2> VmarMapOptions<R1, R2> {
             align: PAGE_SIZE,
             can_overwrite: false,
             is_shared: false,
+            shared_mem: None,
+            grows_down: false,
+            is_droppable: false,
+            file: None,
+            name: None,
+            cache_policy: CachePolicy::Writeback,
         }
     }
 
@@ -732,18 +1670,96 @@ impl<R1, R2> VmarMapOptions<R1, R2> {
         self
     }
 
+    /// Sets the shared memory object to be mapped, whose VMO should be the VMO of
+    /// the options.
+    ///
+    /// The mapping is recorded by the object, so the later changes to the object
+    /// are applied to the mapping as well. See [`SharedMem`] for more details.
+    pub(super) fn shared_mem(mut self, shared_mem: Arc<SharedMem>) -> Self {
+        self.is_shared = true;
+        self.shared_mem = Some(shared_mem);
+        self
+    }
+
+    /// Sets whether the mapping is a stack that grows down.
+    ///
+    /// The default value is false.
+    ///
+    /// If this value is set to true, the page faults below the mapping can grow the
+    /// mapping down in place, using the part of the VMO before the `vmo_offset`. See
+    /// [`Vmar::grow_stack`] for more details.
+    pub fn grows_down(mut self, grows_down: bool) -> Self {
+        self.grows_down = grows_down;
+        self
+    }
+
+    /// Sets whether the mapping is droppable.
+    ///
+    /// The default value is false.
+    ///
+    /// The pages of a droppable mapping may be dropped under memory pressure, after which
+    /// they read as zero pages. They are wiped in the child process on fork as well.
+    /// Only private anonymous mappings can be droppable.
+    pub fn droppable(mut self, is_droppable: bool) -> Self {
+        self.is_droppable = is_droppable;
+        self
+    }
+
+    /// Sets the file that backs the mapping, and the offset in the file where the VMO
+    /// starts.
+    ///
+    /// The file only describes the mapping, e.g., in `/proc/[pid]/maps`. The content of
+    /// the mapping always comes from the VMO.
+    pub fn file(mut self, dentry: Arc<Dentry>, file_offset: usize) -> Self {
+        self.file = Some((dentry, file_offset));
+        self
+    }
+
+    /// Sets the name of the mapping that is not backed by a file, e.g., `[heap]`.
+    ///
+    /// Like the file, the name only describes the mapping.
+    pub fn name(mut self, name: &'static str) -> Self {
+        self.name = Some(name);
+        self
+    }
+
+    /// Sets the cache policy of the pages in the mapping.
+    ///
+    /// The default value is `CachePolicy::Writeback`. A device may map the memory that
+    /// it accesses with another policy, e.g., a framebuffer with write-combining.
+    pub fn cache_policy(mut self, cache_policy: CachePolicy) -> Self {
+        self.cache_policy = cache_policy;
+        self
+    }
+
     /// Creates the mapping.
     ///
     /// All options will be checked at this point.
     ///
     /// On success, the virtual address of the new mapping is returned.
     pub fn build(self) -> Result<Vaddr> {
+        match self.shared_mem.clone() {
+            // The mapping must be visible to the shared memory object once it is
+            // checked against the permissions of the object.
+            Some(shared_mem) => shared_mem.with_allowed_perms(self.perms, || self.do_build()),
+            None => self.do_build(),
+        }
+    }
+
+    fn do_build(self) -> Result<Vaddr> {
         self.check_options()?;
         let parent_vmar = self.parent.0.clone();
         let vmo_ = self.vmo.0.clone();
         let vm_mapping = Arc::new(VmMapping::build_mapping(self)?);
+        vm_mapping.vmo().rmap().add_vmar(&parent_vmar);
+        vm_mapping.track_if_droppable();
         let map_to_addr = vm_mapping.map_to_addr();
+        let map_range = vm_mapping.range();
         parent_vmar.add_mapping(vm_mapping);
+        if parent_vmar.lock_future() == Some(true) {
+            // Like Linux, failing to populate the locked mapping does not fail the mapping.
+            let _ = parent_vmar.populate_locked(&map_range);
+        }
         Ok(map_to_addr)
     }
 
@@ -765,6 +1781,12 @@ impl<R1, R2> VmarMapOptions<R1, R2> {
                 return_errno_with_message!(Errno::EINVAL, "invalid offset");
             }
         }
+        if self.is_droppable && (self.is_shared || !self.vmo.is_anonymous()) {
+            return_errno_with_message!(
+                Errno::EINVAL,
+                "only private anonymous mappings can be droppable"
+            );
+        }
         self.check_perms()?;
         self.check_overwrite()?;
         Ok(())
@@ -5,15 +5,19 @@
 
 //! Virtual Memory Objects (VMOs).
 
-use core::ops::Range;
+use core::ops::{Deref, DerefMut, Range};
 
 use align_ext::AlignExt;
 use aster_frame::{
     collections::xarray::{CursorMut, XArray, XMark},
-    mm::{Frame, FrameAllocOptions, VmReader, VmWriter},
+    mm::{Frame, FrameAllocOptions, Paddr, PageFlags, VmReader, VmWriter},
 };
 use aster_rights::Rights;
 
+use super::{
+    swap::{self, SwapSlot},
+    vmar::Rmap,
+};
 use crate::prelude::*;
 
 mod dyn_cap;
@@ -157,19 +161,78 @@ impl From<VmoMark> for XMark {
     }
 }
 
+/// The committed pages of a VMO, including the ones that are swapped out.
+///
+/// It dereferences to the `XArray` of the `Frame`s that are in memory.
+#[derive(Clone)]
+pub(super) struct PageSet {
+    frames: XArray<Frame, VmoMark>,
+    /// The swap slots of the pages that are swapped out. The key is the index in `frames`.
+    ///
+    /// The slots are shared when the `PageSet` is cloned for a COW child, since they
+    /// are never written again.
+    swapped: BTreeMap<u64, Arc<SwapSlot>>,
+}
+
+impl PageSet {
+    pub(super) fn new(frames: XArray<Frame, VmoMark>) -> Self {
+        Self {
+            frames,
+            swapped: BTreeMap::new(),
+        }
+    }
+
+    /// Reads the page at the index back to memory if it is swapped out.
+    fn swap_in(&mut self, idx: u64) -> Result<()> {
+        let Some(slot) = self.swapped.get(&idx) else {
+            return Ok(());
+        };
+        let frame = FrameAllocOptions::new(1).uninit(true).alloc_single_wait()?;
+        slot.read(&frame)?;
+        self.swapped.remove(&idx);
+
+        // The new frame is not shared with other VMOs.
+        let is_cow_vmo = self.frames.is_marked(VmoMark::CowVmo);
+        let mut cursor = self.frames.cursor_mut(idx);
+        cursor.store(frame);
+        if is_cow_vmo {
+            cursor.set_mark(VmoMark::ExclusivePage).unwrap();
+        }
+        Ok(())
+    }
+
+    fn is_swapped(&self, idx: u64) -> bool {
+        self.swapped.contains_key(&idx)
+    }
+}
+
+impl Deref for PageSet {
+    type Target = XArray<Frame, VmoMark>;
+
+    fn deref(&self) -> &Self::Target {
+        &self.frames
+    }
+}
+
+impl DerefMut for PageSet {
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        &mut self.frames
+    }
+}
+
 /// `Pages` is the struct that manages the `Frame`s stored in `Vmo_`.
 pub(super) enum Pages {
     /// `Pages` that cannot be resized. This kind of `Pages` will have a constant size.
-    Nonresizable(Arc<Mutex<XArray<Frame, VmoMark>>>, usize),
+    Nonresizable(Arc<Mutex<PageSet>>, usize),
     /// `Pages` that can be resized and have a variable size, and such `Pages` cannot
     /// be shared between different VMOs.
-    Resizable(Mutex<(XArray<Frame, VmoMark>, usize)>),
+    Resizable(Mutex<(PageSet, usize)>),
 }
 
 impl Pages {
     fn with<R, F>(&self, func: F) -> R
     where
-        F: FnOnce(&mut XArray<Frame, VmoMark>, usize) -> R,
+        F: FnOnce(&mut PageSet, usize) -> R,
     {
         match self {
             Self::Nonresizable(pages, size) => func(&mut pages.lock(), *size),
@@ -180,6 +243,21 @@ impl Pages {
             }
         }
     }
+
+    /// Like `with`, but returns `None` instead of blocking if the pages are locked.
+    fn try_with<R, F>(&self, func: F) -> Option<R>
+    where
+        F: FnOnce(&mut PageSet, usize) -> R,
+    {
+        match self {
+            Self::Nonresizable(pages, size) => Some(func(&mut pages.try_lock()?, *size)),
+            Self::Resizable(pages) => {
+                let mut lock = pages.try_lock()?;
+                let size = lock.1;
+                Some(func(&mut lock.0, size))
+            }
+        }
+    }
 }
 
 /// `Vmo_` is the structure that actually manages the content of VMO.
@@ -195,10 +273,12 @@ pub(super) struct Vmo_ {
     page_idx_offset: usize,
     /// The virtual pages where the VMO resides.
     pages: Pages,
+    /// The reverse mapping of the pages, which is shared with the parent and the children.
+    rmap: Arc<Rmap>,
 }
 
 fn clone_page(page: &Frame) -> Result<Frame> {
-    let new_page = FrameAllocOptions::new(1).alloc_single()?;
+    let new_page = FrameAllocOptions::new(1).alloc_single_wait()?;
     new_page.copy_from(page);
     Ok(new_page)
 }
@@ -245,7 +325,7 @@ impl Vmo_ {
             None => {
                 // Condition 1. The new anonymous page only need to be marked as `ExclusivePage`
                 // when current VMO is a cow VMO, otherwise this mark is meaningless.
-                (FrameAllocOptions::new(1).alloc_single()?, is_cow_vmo)
+                (FrameAllocOptions::new(1).alloc_single_wait()?, is_cow_vmo)
             }
             Some(pager) => {
                 let page = pager.commit_page(page_idx)?;
@@ -275,7 +355,7 @@ impl Vmo_ {
         {
             pager.commit_overwrite(page_idx)?
         } else {
-            FrameAllocOptions::new(1).alloc_single()?
+            FrameAllocOptions::new(1).alloc_single_wait()?
         };
         Ok(page)
     }
@@ -299,7 +379,7 @@ impl Vmo_ {
                 }
 
                 if commit_flags.will_overwrite() {
-                    (FrameAllocOptions::new(1).alloc_single()?, true)
+                    (FrameAllocOptions::new(1).alloc_single_wait()?, true)
                 } else {
                     (clone_page(&committed_page)?, true)
                 }
@@ -328,6 +408,7 @@ impl Vmo_ {
     pub fn commit_page(&self, offset: usize, will_write: bool) -> Result<Frame> {
         let page_idx = offset / PAGE_SIZE + self.page_idx_offset;
         self.pages.with(|pages, size| {
+            pages.swap_in(page_idx as u64)?;
             let is_cow_vmo = pages.is_marked(VmoMark::CowVmo);
             let mut cursor = pages.cursor_mut(page_idx as u64);
             let commit_flags = if will_write {
@@ -343,6 +424,7 @@ impl Vmo_ {
     fn decommit_page(&mut self, offset: usize) -> Result<()> {
         let page_idx = offset / PAGE_SIZE + self.page_idx_offset;
         self.pages.with(|pages, size| {
+            pages.swapped.remove(&(page_idx as u64));
             let is_cow_vmo = pages.is_marked(VmoMark::CowVmo);
             let mut cursor = pages.cursor_mut(page_idx as u64);
             if cursor.remove().is_some()
@@ -375,6 +457,9 @@ impl Vmo_ {
             let page_idx_range = (raw_page_idx_range.start + self.page_idx_offset)
                 ..(raw_page_idx_range.end + self.page_idx_offset);
 
+            for page_idx in page_idx_range.clone() {
+                pages.swap_in(page_idx as u64)?;
+            }
             let is_cow_vmo = pages.is_marked(VmoMark::CowVmo);
             let mut cursor = pages.cursor_mut(page_idx_range.start as u64);
             for page_idx in page_idx_range {
@@ -395,6 +480,46 @@ impl Vmo_ {
         })
     }
 
+    /// Ask the pager to prepare the pages within a range of the VMO ahead of time.
+    pub fn prefetch(&self, range: Range<usize>) -> Result<()> {
+        let Some(pager) = &self.pager else {
+            return Ok(());
+        };
+        let raw_page_idx_range = get_page_idx_range(&range);
+        let page_idx_range = (raw_page_idx_range.start + self.page_idx_offset)
+            ..(raw_page_idx_range.end + self.page_idx_offset);
+        pager.prefetch(page_idx_range)
+    }
+
+    /// Commit a new page at the target index, which is filled with `src`, or with zeros
+    /// if `src` is `None`.
+    ///
+    /// Returns `false` without doing anything if the page has been committed already.
+    pub fn fill_page(&self, page_idx: usize, src: Option<&[u8]>) -> Result<bool> {
+        let page_idx = page_idx + self.page_idx_offset;
+        self.pages.with(|pages, size| {
+            if pages.is_swapped(page_idx as u64) {
+                return Ok(false);
+            }
+            let is_cow_vmo = pages.is_marked(VmoMark::CowVmo);
+            let mut cursor = pages.cursor_mut(page_idx as u64);
+            if cursor.load().is_some() {
+                return Ok(false);
+            }
+
+            let new_page = FrameAllocOptions::new(1).alloc_single_wait()?;
+            if let Some(src) = src {
+                let mut reader: VmReader = src.into();
+                new_page.writer().write(&mut reader);
+            }
+            cursor.store(new_page);
+            if is_cow_vmo {
+                cursor.set_mark(VmoMark::ExclusivePage).unwrap();
+            }
+            Ok(true)
+        })
+    }
+
     /// Read the specified amount of buffer content starting from the target offset in the VMO.
     pub fn read_bytes(&self, offset: usize, buf: &mut [u8]) -> Result<()> {
         let read_len = buf.len();
@@ -473,6 +598,38 @@ impl Vmo_ {
         self.page_idx_offset
     }
 
+    /// Return the committed page at `page_idx`, or `None` if it is not in memory or the
+    /// pages of the VMO are being operated on by others.
+    fn try_load_page(&self, page_idx: usize) -> Option<Frame> {
+        let idx = (page_idx + self.page_idx_offset) as u64;
+        self.pages
+            .try_with(|pages, size| pages.load(idx).map(|page| Frame::clone(&page)))
+            .flatten()
+    }
+
+    /// Try to unmap the committed page at `page_idx` from all the address spaces, and
+    /// return the union of the flags of its page table entries.
+    ///
+    /// This method never blocks. See [`Rmap::try_unmap`] for the errors.
+    pub fn try_unmap_page(&self, page_idx: usize) -> Result<PageFlags> {
+        let Some(frame) = self.try_load_page(page_idx) else {
+            return_errno_with_message!(Errno::EAGAIN, "the page is not committed or busy");
+        };
+        self.rmap.try_unmap(page_idx + self.page_idx_offset, &frame)
+    }
+
+    /// Try to clear the accessed bits of the page table entries that map the committed
+    /// page at `page_idx`, and return whether the page has been accessed via any of them.
+    ///
+    /// This method never blocks. See [`Rmap::try_unmap`] for the errors.
+    pub fn try_clear_accessed(&self, page_idx: usize) -> Result<bool> {
+        let Some(frame) = self.try_load_page(page_idx) else {
+            return_errno_with_message!(Errno::EAGAIN, "the page is not committed or busy");
+        };
+        self.rmap
+            .try_clear_accessed(page_idx + self.page_idx_offset, &frame)
+    }
+
     /// Clone the current `pages` to the child VMO.
     ///
     /// Depending on the type of the VMO and the child, there are 4 conditions:
@@ -588,14 +745,12 @@ impl Vmo_ {
         Ok(())
     }
 
-    fn decommit_pages(
-        &self,
-        pages: &mut XArray<Frame, VmoMark>,
-        range: Range<usize>,
-    ) -> Result<()> {
+    fn decommit_pages(&self, pages: &mut PageSet, range: Range<usize>) -> Result<()> {
         let raw_page_idx_range = get_page_idx_range(&range);
         let page_idx_range = (raw_page_idx_range.start + self.page_idx_offset)
             ..(raw_page_idx_range.end + self.page_idx_offset);
+        let swapped_range = page_idx_range.start as u64..page_idx_range.end as u64;
+        pages.swapped.retain(|idx, _| !swapped_range.contains(idx));
         let is_cow_vmo = pages.is_marked(VmoMark::CowVmo);
         let mut cursor = pages.cursor_mut(page_idx_range.start as u64);
         for page_idx in page_idx_range {
@@ -610,15 +765,148 @@ impl Vmo_ {
         Ok(())
     }
 
-    /// Determine whether a page is committed.
+    /// Try to evict the committed page at `page_idx` if `can_evict` returns true for it.
+    ///
+    /// This method never blocks. It gives up if the pages of the VMO are being
+    /// operated on by others, which also avoids deadlocks when it is called
+    /// during memory reclamation. Returns whether the page has been evicted.
+    pub fn try_evict_page<F>(&self, page_idx: usize, can_evict: F) -> bool
+    where
+        F: FnOnce(&Frame) -> bool,
+    {
+        self.pages
+            .try_with(|pages, size| {
+                let is_cow_vmo = pages.is_marked(VmoMark::CowVmo);
+                let mut cursor = pages.cursor_mut((page_idx + self.page_idx_offset) as u64);
+                if !cursor.load().is_some_and(|page| can_evict(&page)) {
+                    return false;
+                }
+                cursor.remove();
+                if let Some(pager) = &self.pager
+                    && !is_cow_vmo
+                {
+                    // Only decommit the page from the pager after it has been removed from
+                    // the VMO, so that it is not referenced any more.
+                    let _ = pager.decommit_page(page_idx + self.page_idx_offset);
+                }
+                true
+            })
+            .unwrap_or(false)
+    }
+
+    /// Determine whether a page is committed, including the case that it is swapped out.
     pub fn is_page_committed(&self, page_idx: usize) -> bool {
+        let idx = (page_idx + self.page_idx_offset) as u64;
+        self.pages
+            .with(|pages, size| pages.load(idx).is_some() || pages.is_swapped(idx))
+    }
+
+    /// Try to swap out the committed page at `page_idx` to the swap areas.
+    ///
+    /// Only the pages of anonymous VMOs that are not shared with other VMOs or mapped
+    /// anywhere can be swapped out. Like [`Self::try_evict_page`], this method never
+    /// blocks on the pages of the VMO. Returns whether the page has been swapped out.
+    pub fn try_swap_out_page(&self, page_idx: usize) -> bool {
+        if self.pager.is_some() {
+            return false;
+        }
+        let idx = (page_idx + self.page_idx_offset) as u64;
+        self.pages
+            .try_with(|pages, size| {
+                let mut cursor = pages.cursor_mut(idx);
+                let Some(frame) = cursor.load().map(|page| Frame::clone(&page)) else {
+                    return false;
+                };
+                // The frame is referenced by the VMO and `frame` only.
+                if frame.reference_count() > 2 {
+                    return false;
+                }
+                let Ok(slot) = swap::swap_out(&frame) else {
+                    return false;
+                };
+                cursor.remove();
+                drop(cursor);
+                pages.swapped.insert(idx, slot);
+                true
+            })
+            .unwrap_or(false)
+    }
+
+    /// Try to migrate the committed page at `page_idx` to a frame allocated by `alloc`,
+    /// if the page is within the physical address range.
+    ///
+    /// Only the pages of anonymous VMOs that are not shared with other VMOs can be migrated.
+    /// The page is unmapped from all the address spaces before it is copied, and mapped
+    /// again on the next page faults. Like [`Self::try_evict_page`], this method never
+    /// blocks on the pages of the VMO. Returns whether the page has been migrated.
+    pub fn try_migrate_page(
+        &self,
+        page_idx: usize,
+        range: &Range<Paddr>,
+        alloc: &mut dyn FnMut() -> Option<Frame>,
+    ) -> bool {
+        if self.pager.is_some() {
+            return false;
+        }
+        let idx = page_idx + self.page_idx_offset;
+        self.pages
+            .try_with(|pages, size| {
+                let mut cursor = pages.cursor_mut(idx as u64);
+                let Some(frame) = cursor.load().map(|page| Frame::clone(&page)) else {
+                    return false;
+                };
+                if !range.contains(&frame.start_paddr()) {
+                    return false;
+                }
+                // The page cannot be written via the mappings during the copy after the
+                // page table entries are shot down, and cannot be committed again while
+                // the pages are locked.
+                if self.rmap.try_unmap(idx, &frame).is_err() {
+                    return false;
+                }
+                // The frame is referenced by the VMO and `frame` only, i.e., it is neither
+                // mapped nor used by others, e.g., for I/O.
+                if frame.reference_count() > 2 {
+                    return false;
+                }
+                let Some(new_frame) = alloc() else {
+                    return false;
+                };
+                new_frame.copy_from(&frame);
+                let is_exclusive = cursor.is_marked(VmoMark::ExclusivePage);
+                cursor.store(new_frame);
+                if is_exclusive {
+                    cursor.set_mark(VmoMark::ExclusivePage).unwrap();
+                }
+                true
+            })
+            .unwrap_or(false)
+    }
+
+    /// Read the swapped-out pages whose slots satisfy `filter` back to memory.
+    pub fn swap_in(&self, filter: &dyn Fn(&SwapSlot) -> bool) -> Result<()> {
         self.pages.with(|pages, size| {
-            pages
-                .load((page_idx + self.page_idx_offset) as u64)
-                .is_some()
+            let idxs: Vec<u64> = pages
+                .swapped
+                .iter()
+                .filter(|(_, slot)| filter(slot))
+                .map(|(idx, _)| *idx)
+                .collect();
+            for idx in idxs {
+                pages.swap_in(idx)?;
+            }
+            Ok(())
         })
     }
 
+    /// Return the number of swapped-out pages within the range of page indexes.
+    pub fn nr_swapped_pages(&self, page_idx_range: Range<usize>) -> usize {
+        let start = (page_idx_range.start + self.page_idx_offset) as u64;
+        let end = (page_idx_range.end + self.page_idx_offset) as u64;
+        self.pages
+            .with(|pages, size| pages.swapped.range(start..end).count())
+    }
+
     /// Return the flags of current VMO.
     pub fn flags(&self) -> VmoFlags {
         self.flags
@@ -651,9 +939,94 @@ impl<R> Vmo<R> {
         self.0.commit_page(page_idx * PAGE_SIZE, write_page)
     }
 
+    /// Try to evict a committed page without blocking. See [`Vmo_::try_evict_page`].
+    pub(crate) fn try_evict_page<F>(&self, page_idx: usize, can_evict: F) -> bool
+    where
+        F: FnOnce(&Frame) -> bool,
+    {
+        self.0.try_evict_page(page_idx, can_evict)
+    }
+
     pub fn is_cow_vmo(&self) -> bool {
         self.0.is_cow_vmo()
     }
+
+    /// Try to swap out a committed page without blocking. See [`Vmo_::try_swap_out_page`].
+    pub(crate) fn try_swap_out_page(&self, page_idx: usize) -> bool {
+        self.0.try_swap_out_page(page_idx)
+    }
+
+    /// Try to migrate the committed page at `page_idx` if it is within the physical
+    /// address range. See [`Vmo_::try_migrate_page`].
+    pub(crate) fn try_migrate_page(
+        &self,
+        page_idx: usize,
+        range: &Range<Paddr>,
+        alloc: &mut dyn FnMut() -> Option<Frame>,
+    ) -> bool {
+        self.0.try_migrate_page(page_idx, range, alloc)
+    }
+
+    /// Try to unmap a committed page from all the address spaces without blocking.
+    /// See [`Vmo_::try_unmap_page`].
+    pub(crate) fn try_unmap_page(&self, page_idx: usize) -> Result<PageFlags> {
+        self.0.try_unmap_page(page_idx)
+    }
+
+    /// Try to clear the accessed bits of the mapped page without blocking.
+    /// See [`Vmo_::try_clear_accessed`].
+    pub(crate) fn try_clear_accessed(&self, page_idx: usize) -> Result<bool> {
+        self.0.try_clear_accessed(page_idx)
+    }
+
+    /// Returns the reverse mapping of the pages of the VMO.
+    pub(in crate::vm) fn rmap(&self) -> &Arc<Rmap> {
+        &self.0.rmap
+    }
+
+    /// Returns the offset of the first page of the VMO in the pages shared with its
+    /// parent, which is the index of the page in its [`Rmap`].
+    pub(in crate::vm) fn page_idx_offset(&self) -> usize {
+        self.0.page_idx_offset()
+    }
+
+    /// Read the swapped-out pages whose slots satisfy `filter` back to memory.
+    pub(crate) fn swap_in(&self, filter: &dyn Fn(&SwapSlot) -> bool) -> Result<()> {
+        self.0.swap_in(filter)
+    }
+
+    /// Returns the number of swapped-out pages within the range of page indexes.
+    pub fn nr_swapped_pages(&self, page_idx_range: Range<usize>) -> usize {
+        self.0.nr_swapped_pages(page_idx_range)
+    }
+
+    /// Returns whether the VMO is anonymous, i.e., it is not backed by a pager.
+    pub fn is_anonymous(&self) -> bool {
+        self.0.pager.is_none()
+    }
+
+    /// Decommit the pages within the range without checking the access rights.
+    ///
+    /// This is used to discard the private pages of memory mappings, which may be
+    /// read-only. See [`Vmo::decommit`] for the version that checks the rights.
+    pub(crate) fn discard(&self, range: Range<usize>) -> Result<()> {
+        self.0.decommit(range)
+    }
+
+    /// Commit a new page at the target index without checking the access rights.
+    ///
+    /// This is used to resolve the user page faults of memory mappings, which may be
+    /// read-only. The new page is filled with `src`, or with zeros if `src` is `None`.
+    /// Returns `false` if the page has been committed already.
+    pub(crate) fn fill_page(&self, page_idx: usize, src: Option<&[u8]>) -> Result<bool> {
+        self.0.fill_page(page_idx, src)
+    }
+
+    /// Ask the pager to prepare the pages within the range ahead of time, so that
+    /// committing them later will not wait for I/O. It does nothing for anonymous VMOs.
+    pub fn prefetch(&self, range: Range<usize>) -> Result<()> {
+        self.0.prefetch(range)
+    }
 }
 
 /// get the page index range that contains the offset range of vmo
@@ -15,8 +15,11 @@ use aster_rights::{Dup, Rights, TRightSet, TRights, Write};
 use aster_rights_proc::require;
 use typeflags_util::{SetExtend, SetExtendOp};
 
-use super::{Pager, Pages, Vmo, VmoFlags, VmoMark, VmoRightsOp};
-use crate::{prelude::*, vm::vmo::Vmo_};
+use super::{PageSet, Pager, Pages, Vmo, VmoFlags, VmoMark, VmoRightsOp};
+use crate::{
+    prelude::*,
+    vm::{vmar::Rmap, vmo::Vmo_},
+};
 
 /// Options for allocating a root VMO.
 ///
@@ -127,7 +130,7 @@ impl<R: TRights> VmoOptions<TRightSet<R>> {
 fn alloc_vmo_(size: usize, flags: VmoFlags, pager: Option<Arc<dyn Pager>>) -> Result<Vmo_> {
     let size = size.align_up(PAGE_SIZE);
     let pages = {
-        let pages = committed_pages_if_continuous(flags, size)?;
+        let pages = PageSet::new(committed_pages_if_continuous(flags, size)?);
         if flags.contains(VmoFlags::RESIZABLE) {
             Pages::Resizable(Mutex::new((pages, size)))
         } else {
@@ -139,6 +142,7 @@ fn alloc_vmo_(size: usize, flags: VmoFlags, pager: Option<Arc<dyn Pager>>) -> Re
         flags,
         page_idx_offset: 0,
         pages,
+        rmap: Rmap::new(),
     })
 }
 
@@ -457,6 +461,7 @@ fn alloc_child_vmo_(
         flags: child_flags,
         pages: child_pages,
         page_idx_offset: parent_page_idx_offset + parent_vmo_.page_idx_offset(),
+        rmap: parent_vmo_.rmap.clone(),
     };
     Ok(new_vmo)
 }
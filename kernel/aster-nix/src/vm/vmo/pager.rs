@@ -1,5 +1,7 @@
 // SPDX-License-Identifier: MPL-2.0
 
+use core::ops::Range;
+
 use aster_frame::mm::Frame;
 
 use crate::prelude::*;
@@ -55,4 +57,13 @@ pub trait Pager: Send + Sync {
     /// Notify the pager that the frame will be fully overwritten soon, so pager can
     /// choose not to initialize it.
     fn commit_overwrite(&self, idx: usize) -> Result<Frame>;
+
+    /// Hint the pager that the frames within the specified index range will be
+    /// committed soon.
+    ///
+    /// The pager (e.g., an inode) can take this chance to read the data in a batch
+    /// ahead of time. It is only a hint, so the default implementation does nothing.
+    fn prefetch(&self, _idx_range: Range<usize>) -> Result<()> {
+        Ok(())
+    }
 }
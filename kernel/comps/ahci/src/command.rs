@@ -0,0 +1,246 @@
+// SPDX-License-Identifier: MPL-2.0
+
+//! The command lists, the command tables, and the FISes of the AHCI ports.
+
+use core::mem::size_of;
+
+use pod::Pod;
+use static_assertions::const_assert_eq;
+
+/// The size of the command list, which has 32 command headers.
+pub(crate) const COMMAND_LIST_SIZE: usize = 32 * size_of::<CommandHeader>();
+/// The size of the received FIS area.
+pub(crate) const RECEIVED_FIS_SIZE: usize = 256;
+
+/// The offset of the PRDT in a command table.
+pub(crate) const PRDT_OFFSET: usize = 0x80;
+/// The maximum number of the bytes of a PRD entry.
+pub(crate) const MAX_PRD_BYTES: usize = 4 << 20;
+
+/// A command header in the command list, which describes the command in a slot.
+#[repr(C)]
+#[derive(Debug, Default, Clone, Copy, Pod)]
+pub(crate) struct CommandHeader {
+    /// The length of the command FIS in dwords in bits 0-4, and the flags.
+    pub flags: u16,
+    /// The number of the PRD entries.
+    pub prdtl: u16,
+    /// The number of the bytes transferred, which is updated by the controller.
+    pub prdbc: u32,
+    /// The address of the command table, which is aligned to 128 bytes.
+    pub ctba: u64,
+    pub reserved: [u32; 4],
+}
+
+const_assert_eq!(size_of::<CommandHeader>(), 32);
+
+impl CommandHeader {
+    /// The direction of the data is from the host to the device.
+    const WRITE: u16 = 1 << 6;
+
+    pub(crate) fn new(ctba: u64, nr_prds: usize, is_write: bool) -> Self {
+        let cfl = (size_of::<RegH2dFis>() / 4) as u16;
+        Self {
+            flags: cfl | if is_write { Self::WRITE } else { 0 },
+            prdtl: nr_prds as u16,
+            prdbc: 0,
+            ctba,
+            reserved: [0; 4],
+        }
+    }
+}
+
+/// An entry of the physical region descriptor table (PRDT) in a command table.
+#[repr(C)]
+#[derive(Debug, Default, Clone, Copy, Pod)]
+pub(crate) struct PrdEntry {
+    /// The address of the data, which is aligned to 2 bytes.
+    pub dba: u64,
+    pub reserved: u32,
+    /// The number of the bytes minus one in bits 0-21, which must be odd.
+    pub dbc: u32,
+}
+
+const_assert_eq!(size_of::<PrdEntry>(), 16);
+
+impl PrdEntry {
+    pub(crate) fn new(daddr: u64, len: usize) -> Self {
+        debug_assert!(len > 0 && len <= MAX_PRD_BYTES && len % 2 == 0);
+        Self {
+            dba: daddr,
+            reserved: 0,
+            dbc: (len - 1) as u32,
+        }
+    }
+}
+
+/// The ATA commands used by the driver.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[repr(u8)]
+pub(crate) enum AtaCommand {
+    ReadDmaExt = 0x25,
+    WriteDmaExt = 0x35,
+    ReadFpdmaQueued = 0x60,
+    WriteFpdmaQueued = 0x61,
+    IdentifyDevice = 0xEC,
+    FlushCacheExt = 0xEA,
+}
+
+/// A Register Host to Device FIS, which sends an ATA command to the device.
+#[repr(C)]
+#[derive(Debug, Default, Clone, Copy, Pod)]
+pub(crate) struct RegH2dFis {
+    pub fis_type: u8,
+    /// The Port Multiplier port in bits 0-3, and the command bit in bit 7.
+    pub flags: u8,
+    pub command: u8,
+    pub features_low: u8,
+    pub lba_low: [u8; 3],
+    pub device: u8,
+    pub lba_high: [u8; 3],
+    pub features_high: u8,
+    pub count: u16,
+    pub icc: u8,
+    pub control: u8,
+    pub reserved: u32,
+}
+
+const_assert_eq!(size_of::<RegH2dFis>(), 20);
+
+impl RegH2dFis {
+    const FIS_TYPE: u8 = 0x27;
+    /// The FIS updates the command register, rather than the device control register.
+    const COMMAND_BIT: u8 = 1 << 7;
+    /// The LBA is used, rather than the CHS.
+    const DEVICE_LBA: u8 = 1 << 6;
+
+    fn new(command: AtaCommand) -> Self {
+        Self {
+            fis_type: Self::FIS_TYPE,
+            flags: Self::COMMAND_BIT,
+            command: command as u8,
+            ..Default::default()
+        }
+    }
+
+    pub(crate) fn identify_device() -> Self {
+        Self::new(AtaCommand::IdentifyDevice)
+    }
+
+    pub(crate) fn flush_cache() -> Self {
+        Self::new(AtaCommand::FlushCacheExt)
+    }
+
+    /// Reads or writes `nr_sectors` sectors starting from `lba` with a non-queued command.
+    pub(crate) fn read_write_dma(is_write: bool, lba: u64, nr_sectors: u16) -> Self {
+        let command = if is_write {
+            AtaCommand::WriteDmaExt
+        } else {
+            AtaCommand::ReadDmaExt
+        };
+        let mut fis = Self::new(command);
+        fis.set_lba(lba);
+        fis.count = nr_sectors;
+        fis
+    }
+
+    /// Reads or writes `nr_sectors` sectors starting from `lba` with an NCQ command.
+    ///
+    /// The tag of the command must be set with [`Self::set_tag`] before it is issued.
+    pub(crate) fn read_write_fpdma(is_write: bool, lba: u64, nr_sectors: u16) -> Self {
+        let command = if is_write {
+            AtaCommand::WriteFpdmaQueued
+        } else {
+            AtaCommand::ReadFpdmaQueued
+        };
+        let mut fis = Self::new(command);
+        fis.set_lba(lba);
+        // The sector count of an NCQ command is in the features fields.
+        fis.features_low = nr_sectors as u8;
+        fis.features_high = (nr_sectors >> 8) as u8;
+        fis
+    }
+
+    /// Returns whether the command is an NCQ command.
+    pub(crate) fn is_queued(&self) -> bool {
+        self.command == AtaCommand::ReadFpdmaQueued as u8
+            || self.command == AtaCommand::WriteFpdmaQueued as u8
+    }
+
+    /// Sets the tag of an NCQ command, which is the same as its slot.
+    pub(crate) fn set_tag(&mut self, tag: usize) {
+        self.count = (tag as u16) << 3;
+    }
+
+    fn set_lba(&mut self, lba: u64) {
+        let bytes = lba.to_le_bytes();
+        self.lba_low.copy_from_slice(&bytes[0..3]);
+        self.lba_high.copy_from_slice(&bytes[3..6]);
+        self.device = Self::DEVICE_LBA;
+    }
+}
+
+/// The data returned by the IDENTIFY DEVICE command.
+pub(crate) struct IdentifyData(pub [u16; 256]);
+
+impl IdentifyData {
+    /// Returns the number of the logical sectors that are addressable with the 48-bit
+    /// LBA, or `None` if the 48-bit LBA is not supported.
+    pub(crate) fn nr_sectors(&self) -> Option<u64> {
+        if self.0[83] & (1 << 10) == 0 {
+            return None;
+        }
+        let words = &self.0[100..104];
+        Some(
+            words
+                .iter()
+                .rev()
+                .fold(0u64, |acc, word| (acc << 16) | *word as u64),
+        )
+    }
+
+    /// Returns the size of a logical sector in bytes.
+    pub(crate) fn sector_size(&self) -> usize {
+        let word = self.0[106];
+        // Bit 14 is set and bit 15 is clear if the word is valid, and bit 12 is set if
+        // the logical sector is longer than 256 words.
+        if word & 0xC000 == 0x4000 && word & (1 << 12) != 0 {
+            let nr_words = self.0[117] as usize | (self.0[118] as usize) << 16;
+            nr_words * 2
+        } else {
+            512
+        }
+    }
+
+    /// Returns the queue depth of the NCQ commands, or `None` if NCQ is not supported.
+    pub(crate) fn ncq_depth(&self) -> Option<usize> {
+        if self.0[76] & (1 << 8) == 0 {
+            return None;
+        }
+        Some((self.0[75] & 0x1F) as usize + 1)
+    }
+
+    /// Returns the serial number, whose spaces at the ends are trimmed.
+    pub(crate) fn serial_number(&self) -> alloc::string::String {
+        self.ascii_string(10..20)
+    }
+
+    /// Returns the model number, whose spaces at the ends are trimmed.
+    pub(crate) fn model_number(&self) -> alloc::string::String {
+        self.ascii_string(27..47)
+    }
+
+    /// The ATA strings have two characters in each word, where the first character is in
+    /// the higher byte.
+    fn ascii_string(&self, words: core::ops::Range<usize>) -> alloc::string::String {
+        let bytes: alloc::vec::Vec<u8> = self.0[words]
+            .iter()
+            .flat_map(|word| word.to_be_bytes())
+            .filter(|byte| byte.is_ascii_graphic() || *byte == b' ')
+            .collect();
+        alloc::string::String::from_utf8(bytes)
+            .unwrap()
+            .trim()
+            .into()
+    }
+}
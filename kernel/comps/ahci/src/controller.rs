@@ -0,0 +1,228 @@
+// SPDX-License-Identifier: MPL-2.0
+
+#![allow(dead_code)]
+
+use alloc::{format, string::String, sync::Arc, vec::Vec};
+use core::{
+    sync::atomic::{AtomicUsize, Ordering},
+    time::Duration,
+};
+
+use aster_frame::{
+    arch::timer::Jiffies,
+    bus::pci::{
+        capability::{msi::CapabilityMsiData, CapabilityData},
+        cfg_space::Bar,
+        common_device::PciCommonDevice,
+    },
+    io_mem::IoMem,
+    mm::{DmaDevice, PAGE_SIZE},
+    sync::SpinLock,
+    trap::IrqLine,
+};
+use log::{info, warn};
+
+use crate::{
+    disk::AhciDisk,
+    port::AhciPort,
+    regs::{ghc, Capability, HbaReg},
+    AhciError,
+};
+
+/// An AHCI controller.
+#[derive(Debug)]
+pub(crate) struct AhciController {
+    id: usize,
+    registers: IoMem,
+    disks: Vec<Arc<AhciDisk>>,
+    /// The MSI capability, which owns the IRQ line of the controller.
+    msi: SpinLock<CapabilityMsiData>,
+    pci_device: PciCommonDevice,
+}
+
+impl AhciController {
+    /// The index of the BAR of the registers, i.e., ABAR.
+    const ABAR_INDEX: u8 = 5;
+    /// The time to wait for the controller to reset.
+    const RESET_TIMEOUT: Duration = Duration::from_secs(1);
+
+    /// Resets and initializes the controller, and discovers the disks on its ports.
+    pub(crate) fn init(id: usize, pci_device: PciCommonDevice) -> Result<Arc<Self>, AhciError> {
+        let Some(Bar::Memory(bar)) = pci_device.bar_manager().bar(Self::ABAR_INDEX) else {
+            return Err(AhciError::NoResource);
+        };
+        // There is no INTx support, so the controllers without MSI are not supported.
+        let mut msi = pci_device
+            .capabilities()
+            .iter()
+            .find_map(|cap| match cap.capability_data() {
+                CapabilityData::Msi(data) => Some(data.clone()),
+                _ => None,
+            })
+            .ok_or(AhciError::NoResource)?;
+        let registers = bar.io_mem().clone();
+        let version: u32 = registers.read_val(HbaReg::Vs as usize).unwrap();
+        info!(
+            "[AHCI]: Found controller {} of version {}.{}",
+            id,
+            version >> 16,
+            (version >> 8) & 0xFF
+        );
+
+        Self::reset(&registers)?;
+        let cap = Capability(registers.read_val(HbaReg::Cap as usize).unwrap());
+        let addr_width = if cap.supports_64bit() { 64 } else { 32 };
+        // The buffers of the bios are mapped for the controller only.
+        let dma_device = DmaDevice::new(*pci_device.location(), addr_width);
+
+        let ports_implemented: u32 = registers.read_val(HbaReg::Pi as usize).unwrap();
+        let mut disks = Vec::new();
+        for index in (0..32).filter(|index| ports_implemented & (1 << index) != 0) {
+            match Self::probe_port(&registers, index, cap, dma_device) {
+                Ok(Some(disk)) => disks.push(Arc::new(disk)),
+                Ok(None) => (),
+                Err(err) => warn!("[AHCI]: Failed to probe port {}: {:?}", index, err),
+            }
+        }
+
+        let irq = IrqLine::alloc().map_err(|_| AhciError::NoResource)?;
+        msi.set_interrupt_vector(irq);
+        let cloned_registers = registers.clone();
+        let cloned_disks = disks.clone();
+        msi.irq_mut().unwrap().on_active(move |_| {
+            let status: u32 = cloned_registers.read_val(HbaReg::Is as usize).unwrap();
+            for disk in cloned_disks.iter() {
+                if status & (1 << disk.port_index()) != 0 {
+                    disk.handle_irq();
+                }
+            }
+            // The interrupt status of the controller is cleared after those of the ports.
+            cloned_registers
+                .write_val(HbaReg::Is as usize, &status)
+                .unwrap();
+        });
+        let control: u32 = registers.read_val(HbaReg::Ghc as usize).unwrap();
+        registers
+            .write_val(HbaReg::Ghc as usize, &(control | ghc::INTERRUPT_ENABLE))
+            .unwrap();
+
+        Ok(Arc::new(Self {
+            id,
+            registers,
+            disks,
+            msi: SpinLock::new(msi),
+            pci_device,
+        }))
+    }
+
+    pub(crate) fn disks(&self) -> &[Arc<AhciDisk>] {
+        &self.disks
+    }
+
+    /// Resets the controller, and enables the AHCI mode.
+    fn reset(registers: &IoMem) -> Result<(), AhciError> {
+        // The AHCI mode must be enabled before the other bits are written.
+        registers
+            .write_val(HbaReg::Ghc as usize, &ghc::AHCI_ENABLE)
+            .unwrap();
+        registers
+            .write_val(HbaReg::Ghc as usize, &(ghc::AHCI_ENABLE | ghc::HBA_RESET))
+            .unwrap();
+        let deadline = Jiffies::elapsed().as_duration() + Self::RESET_TIMEOUT;
+        loop {
+            let control: u32 = registers.read_val(HbaReg::Ghc as usize).unwrap();
+            if control & ghc::HBA_RESET == 0 {
+                break;
+            }
+            if Jiffies::elapsed().as_duration() >= deadline {
+                return Err(AhciError::Timeout);
+            }
+            core::hint::spin_loop();
+        }
+        // The reset clears the AHCI mode.
+        registers
+            .write_val(HbaReg::Ghc as usize, &ghc::AHCI_ENABLE)
+            .unwrap();
+        Ok(())
+    }
+
+    /// Sets up the port and identifies the attached SATA disk.
+    ///
+    /// Returns `None` if there is no supported disk attached to the port.
+    fn probe_port(
+        registers: &IoMem,
+        index: usize,
+        cap: Capability,
+        dma_device: DmaDevice,
+    ) -> Result<Option<AhciDisk>, AhciError> {
+        let Some(port) = AhciPort::new(
+            index,
+            registers,
+            cap.nr_command_slots(),
+            cap.supports_64bit(),
+        )?
+        else {
+            return Ok(None);
+        };
+        port.start();
+        let data = port.identify()?;
+
+        let Some(nr_sectors) = data.nr_sectors() else {
+            warn!(
+                "[AHCI]: Unsupported disk without 48-bit LBA on port {}",
+                index
+            );
+            return Ok(None);
+        };
+        let sector_size = data.sector_size();
+        if nr_sectors == 0 || !(512..=PAGE_SIZE).contains(&sector_size) {
+            warn!(
+                "[AHCI]: Unsupported disk with {} sectors of {} bytes on port {}",
+                nr_sectors, sector_size, index
+            );
+            return Ok(None);
+        }
+        let ncq_depth = if cap.supports_ncq() {
+            data.ncq_depth()
+        } else {
+            None
+        };
+
+        let serial = data.serial_number();
+        let name = if serial.is_empty() {
+            default_disk_name()
+        } else {
+            serial
+        };
+        info!(
+            "[AHCI]: Found disk {} ({}) of {} sectors on port {}, NCQ depth: {:?}",
+            name,
+            data.model_number(),
+            nr_sectors,
+            index,
+            ncq_depth
+        );
+        Ok(Some(AhciDisk::new(
+            name,
+            port,
+            nr_sectors,
+            sector_size,
+            ncq_depth,
+            dma_device,
+        )))
+    }
+}
+
+/// Returns the name of a disk without a serial number, i.e., `sda`, `sdb`, and so on.
+fn default_disk_name() -> String {
+    static NR_UNNAMED_DISKS: AtomicUsize = AtomicUsize::new(0);
+    let index = NR_UNNAMED_DISKS.fetch_add(1, Ordering::Relaxed);
+    let mut suffix = String::new();
+    let mut n = index + 1;
+    while n > 0 {
+        n -= 1;
+        suffix.insert(0, (b'a' + (n % 26) as u8) as char);
+        n /= 26;
+    }
+    format!("sd{}", suffix)
+}
@@ -0,0 +1,385 @@
+// SPDX-License-Identifier: MPL-2.0
+
+use alloc::{collections::VecDeque, string::String, sync::Arc, vec, vec::Vec};
+use core::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+
+use aster_block::{
+    bio::{BioEnqueueError, BioStatus, BioType, SubmittedBio},
+    BlockDevice,
+};
+use aster_frame::{
+    mm::{dma_map, Daddr, DmaDevice, DmaDirection, DmaMapping, HasDaddr},
+    sync::SpinLock,
+};
+use log::warn;
+
+use crate::{
+    command::{RegH2dFis, MAX_PRD_BYTES},
+    port::AhciPort,
+    regs::port_is,
+};
+
+/// A SATA disk attached to a port of an AHCI controller, which is a block device.
+///
+/// With NCQ, the read and write commands are queued in the device, and up to the queue
+/// depth of the device are in flight. Otherwise, the commands are issued to the slots of
+/// the port, and processed by the controller one by one.
+#[derive(Debug)]
+pub struct AhciDisk {
+    name: String,
+    nr_sectors: u64,
+    /// The size of a logical sector in bytes.
+    sector_size: usize,
+    /// The controller, for which the buffers of the bios are mapped.
+    dma_device: DmaDevice,
+    port_index: usize,
+    is_ncq: bool,
+    inner: SpinLock<DiskInner>,
+}
+
+#[derive(Debug)]
+struct DiskInner {
+    port: AhciPort,
+    /// The issued commands indexed by their slots, which are also the NCQ tags.
+    slots: Vec<Option<InflightCommand>>,
+    /// The commands that wait for free slots.
+    pending: VecDeque<InflightCommand>,
+}
+
+impl AhciDisk {
+    /// The maximum number of the segments of a bio.
+    const MAX_NR_SEGMENTS_PER_BIO: usize = 128;
+    /// The maximum number of the bytes of a command, which keeps the sector count of a
+    /// command in 16 bits.
+    const MAX_COMMAND_BYTES: usize = 16 << 20;
+
+    /// Creates the disk with a started port, and enables the interrupts of the port.
+    ///
+    /// If `ncq_depth` is `Some`, the read and write commands are NCQ commands.
+    pub(crate) fn new(
+        name: String,
+        port: AhciPort,
+        nr_sectors: u64,
+        sector_size: usize,
+        ncq_depth: Option<usize>,
+        dma_device: DmaDevice,
+    ) -> Self {
+        let nr_slots = match ncq_depth {
+            Some(depth) => depth.min(port.nr_slots()),
+            None => port.nr_slots(),
+        };
+        port.enable_interrupts();
+        Self {
+            name,
+            nr_sectors,
+            sector_size,
+            dma_device,
+            port_index: port.index(),
+            is_ncq: ncq_depth.is_some(),
+            inner: SpinLock::new(DiskInner {
+                port,
+                slots: (0..nr_slots).map(|_| None).collect(),
+                pending: VecDeque::new(),
+            }),
+        }
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// Returns the capacity of the disk in bytes.
+    pub fn capacity(&self) -> u64 {
+        self.nr_sectors * self.sector_size as u64
+    }
+
+    /// Returns the index of the port, to which the disk is attached.
+    pub(crate) fn port_index(&self) -> usize {
+        self.port_index
+    }
+
+    /// Builds the read or write commands of the bio.
+    fn build_read_write(
+        &self,
+        bio: SubmittedBio,
+        is_write: bool,
+    ) -> Result<Vec<InflightCommand>, BioEnqueueError> {
+        let byte_range = bio.sid_range().start.to_offset()..bio.sid_range().end.to_offset();
+        if byte_range.end as u64 > self.capacity() {
+            return Err(BioEnqueueError::Refused);
+        }
+
+        let direction = if is_write {
+            DmaDirection::ToDevice
+        } else {
+            DmaDirection::FromDevice
+        };
+        let dma_bufs = bio
+            .segments()
+            .iter()
+            .map(|segment| {
+                let (offset, len) = (segment.offset(), segment.nbytes());
+                let mapping = dma_map(&self.dma_device, segment.pages().clone(), direction)
+                    .map_err(|_| BioEnqueueError::Refused)?;
+                mapping.sync_for_device(offset..offset + len).unwrap();
+                Ok((mapping, offset, len))
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+        let transfers = split_into_transfers(
+            dma_bufs
+                .iter()
+                .map(|(mapping, offset, len)| (mapping.daddr() + offset, *len)),
+        );
+
+        let mut commands = Vec::with_capacity(transfers.len());
+        for transfer in transfers {
+            let offset = byte_range.start + transfer.offset;
+            // A transfer that does not cover whole sectors cannot be expressed.
+            if offset % self.sector_size != 0
+                || transfer.len % self.sector_size != 0
+                || transfer
+                    .prds
+                    .iter()
+                    .any(|(daddr, len)| (daddr | len) % 2 != 0)
+            {
+                warn!(
+                    "[AHCI]: The transfer at {:#x} of {} bytes is not aligned to sectors",
+                    offset, transfer.len
+                );
+                return Err(BioEnqueueError::Refused);
+            }
+            let lba = (offset / self.sector_size) as u64;
+            let nr_sectors = (transfer.len / self.sector_size) as u16;
+            let fis = if self.is_ncq {
+                RegH2dFis::read_write_fpdma(is_write, lba, nr_sectors)
+            } else {
+                RegH2dFis::read_write_dma(is_write, lba, nr_sectors)
+            };
+            commands.push((fis, transfer.prds));
+        }
+
+        aster_block::trace::trace_issue(&bio);
+        let inflight_bio = Arc::new(InflightBio::new(bio, dma_bufs, commands.len()));
+        Ok(commands
+            .into_iter()
+            .map(|(fis, prds)| InflightCommand {
+                fis,
+                prds,
+                is_write,
+                bio: inflight_bio.clone(),
+            })
+            .collect())
+    }
+
+    /// Handles the completions and the errors of the commands.
+    pub(crate) fn handle_irq(&self) {
+        let mut completed_bios = Vec::new();
+        {
+            // The IRQs have already been disabled in the IRQ handler.
+            let mut inner = self.inner.lock();
+            let status = inner.port.take_interrupt_status();
+            if status & port_is::ERRORS != 0 {
+                warn!(
+                    "[AHCI]: Disk {} failed with the interrupt status {:#x}",
+                    self.name, status
+                );
+                // The port stops processing the commands on errors, so all the issued
+                // commands are aborted.
+                if let Err(err) = inner.port.recover() {
+                    warn!("[AHCI]: Failed to recover disk {}: {:?}", self.name, err);
+                }
+                for command in inner.slots.iter_mut().filter_map(Option::take) {
+                    command.bio.has_failed.store(true, Ordering::Relaxed);
+                    completed_bios.extend(command.complete());
+                }
+            } else {
+                let busy_slots = inner.port.busy_slots();
+                for (slot, entry) in inner.slots.iter_mut().enumerate() {
+                    if busy_slots & (1 << slot) != 0 {
+                        continue;
+                    }
+                    if let Some(command) = entry.take() {
+                        completed_bios.extend(command.complete());
+                    }
+                }
+            }
+            inner.submit_pending();
+        }
+
+        for bio in completed_bios {
+            bio.complete();
+        }
+    }
+
+    fn submit(&self, commands: Vec<InflightCommand>) {
+        let mut inner = self.inner.lock_irq_disabled();
+        inner.pending.extend(commands);
+        inner.submit_pending();
+    }
+}
+
+impl BlockDevice for AhciDisk {
+    fn enqueue(&self, bio: SubmittedBio) -> Result<(), BioEnqueueError> {
+        let commands = match bio.type_() {
+            BioType::Read => self.build_read_write(bio, false)?,
+            BioType::Write => self.build_read_write(bio, true)?,
+            BioType::Flush => {
+                aster_block::trace::trace_issue(&bio);
+                let inflight_bio = Arc::new(InflightBio::new(bio, Vec::new(), 1));
+                vec![InflightCommand {
+                    fis: RegH2dFis::flush_cache(),
+                    prds: Vec::new(),
+                    is_write: false,
+                    bio: inflight_bio,
+                }]
+            }
+            // TODO: Support the DATA SET MANAGEMENT command to trim the sectors.
+            BioType::Discard => {
+                bio.complete(BioStatus::NotSupported);
+                return Ok(());
+            }
+        };
+        self.submit(commands);
+        Ok(())
+    }
+
+    fn max_nr_segments_per_bio(&self) -> usize {
+        Self::MAX_NR_SEGMENTS_PER_BIO
+    }
+}
+
+impl DiskInner {
+    /// Issues the pending commands in order, as long as there are free slots.
+    ///
+    /// The NCQ commands and the non-queued commands cannot be in flight at the same time,
+    /// so a command waits until the issued commands of the other kind complete.
+    fn submit_pending(&mut self) {
+        while let Some(command) = self.pending.front() {
+            let is_queued = command.fis.is_queued();
+            let mut free_slot = None;
+            for (slot, entry) in self.slots.iter().enumerate() {
+                match entry {
+                    Some(issued) if issued.fis.is_queued() != is_queued => return,
+                    Some(_) => (),
+                    None => free_slot = free_slot.or(Some(slot)),
+                }
+            }
+            let Some(slot) = free_slot else {
+                return;
+            };
+            let command = self.pending.pop_front().unwrap();
+            self.port
+                .prepare(slot, &command.fis, &command.prds, command.is_write);
+            self.port.issue(slot, is_queued);
+            self.slots[slot] = Some(command);
+        }
+    }
+}
+
+/// A command of a bio.
+#[derive(Debug)]
+struct InflightCommand {
+    fis: RegH2dFis,
+    /// The PRD entries, which are the DMA addresses and the lengths of the data.
+    prds: Vec<(Daddr, usize)>,
+    is_write: bool,
+    bio: Arc<InflightBio>,
+}
+
+impl InflightCommand {
+    /// Completes the command, and returns its bio if all the commands of the bio complete.
+    fn complete(self) -> Option<Arc<InflightBio>> {
+        if self.bio.nr_pending_commands.fetch_sub(1, Ordering::AcqRel) == 1 {
+            Some(self.bio)
+        } else {
+            None
+        }
+    }
+}
+
+/// A bio that is split into commands.
+#[derive(Debug)]
+struct InflightBio {
+    bio: SubmittedBio,
+    dma_bufs: Vec<(DmaMapping, usize, usize)>,
+    nr_pending_commands: AtomicUsize,
+    has_failed: AtomicBool,
+}
+
+impl InflightBio {
+    fn new(
+        bio: SubmittedBio,
+        dma_bufs: Vec<(DmaMapping, usize, usize)>,
+        nr_commands: usize,
+    ) -> Self {
+        Self {
+            bio,
+            dma_bufs,
+            nr_pending_commands: AtomicUsize::new(nr_commands),
+            has_failed: AtomicBool::new(false),
+        }
+    }
+
+    /// Completes the bio after all its commands complete.
+    fn complete(&self) {
+        if self.has_failed.load(Ordering::Relaxed) {
+            self.bio.complete(BioStatus::IoError);
+            return;
+        }
+        if self.bio.type_() == BioType::Read {
+            for (mapping, offset, len) in self.dma_bufs.iter() {
+                mapping.sync_for_cpu(*offset..*offset + *len).unwrap();
+            }
+        }
+        self.bio.complete(BioStatus::Complete);
+    }
+}
+
+/// The data of a command, which is a part of a bio.
+#[derive(Debug)]
+struct Transfer {
+    /// The offset of the data in the bio.
+    offset: usize,
+    len: usize,
+    prds: Vec<(Daddr, usize)>,
+}
+
+/// Splits the buffers, which are the DMA addresses and the lengths, into the transfers
+/// that fit in the PRD tables of the commands.
+fn split_into_transfers(bufs: impl Iterator<Item = (Daddr, usize)>) -> Vec<Transfer> {
+    let mut transfers = Vec::new();
+    let mut current = Transfer {
+        offset: 0,
+        len: 0,
+        prds: Vec::new(),
+    };
+    for (mut daddr, mut len) in bufs {
+        while len > 0 {
+            let chunk_len = len
+                .min(MAX_PRD_BYTES)
+                .min(AhciDisk::MAX_COMMAND_BYTES - current.len);
+            current.prds.push((daddr, chunk_len));
+            current.len += chunk_len;
+            daddr += chunk_len;
+            len -= chunk_len;
+
+            if current.prds.len() == AhciPort::MAX_NR_PRDS
+                || current.len == AhciDisk::MAX_COMMAND_BYTES
+            {
+                let offset = current.offset + current.len;
+                transfers.push(core::mem::replace(
+                    &mut current,
+                    Transfer {
+                        offset,
+                        len: 0,
+                        prds: Vec::new(),
+                    },
+                ));
+            }
+        }
+    }
+    if current.len > 0 {
+        transfers.push(current);
+    }
+    transfers
+}
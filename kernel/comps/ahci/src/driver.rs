@@ -0,0 +1,79 @@
+// SPDX-License-Identifier: MPL-2.0
+
+use alloc::{sync::Arc, vec::Vec};
+
+use aster_frame::{
+    bus::{
+        pci::{
+            bus::{PciDevice, PciDriver},
+            common_device::PciCommonDevice,
+            PciDeviceId, PCI_BUS,
+        },
+        BusProbeError,
+    },
+    sync::SpinLock,
+};
+use spin::Once;
+
+pub(crate) static AHCI_PCI_DRIVER: Once<Arc<AhciPciDriver>> = Once::new();
+
+pub(crate) fn ahci_pci_init() {
+    AHCI_PCI_DRIVER.call_once(|| Arc::new(AhciPciDriver::new()));
+    PCI_BUS
+        .lock()
+        .register_driver(AHCI_PCI_DRIVER.get().unwrap().clone());
+}
+
+/// The PCI driver that claims the AHCI controllers.
+#[derive(Debug)]
+pub(crate) struct AhciPciDriver {
+    devices: SpinLock<Vec<PciCommonDevice>>,
+}
+
+#[derive(Debug)]
+struct AhciPciDevice {
+    device_id: PciDeviceId,
+}
+
+impl PciDevice for AhciPciDevice {
+    fn device_id(&self) -> PciDeviceId {
+        self.device_id
+    }
+}
+
+impl AhciPciDriver {
+    /// The class code of the mass storage controllers.
+    const CLASS_MASS_STORAGE: u8 = 0x01;
+    /// The subclass code of the SATA controllers.
+    const SUBCLASS_SATA: u8 = 0x06;
+    /// The programming interface of the AHCI controllers.
+    const PROG_IF_AHCI: u8 = 0x01;
+
+    fn new() -> Self {
+        Self {
+            devices: SpinLock::new(Vec::new()),
+        }
+    }
+
+    /// Pops a probed controller that is not initialized yet.
+    pub(crate) fn pop_device(&self) -> Option<PciCommonDevice> {
+        self.devices.lock().pop()
+    }
+}
+
+impl PciDriver for AhciPciDriver {
+    fn probe(
+        &self,
+        device: PciCommonDevice,
+    ) -> Result<Arc<dyn PciDevice>, (BusProbeError, PciCommonDevice)> {
+        let device_id = *device.device_id();
+        if device_id.class != Self::CLASS_MASS_STORAGE
+            || device_id.subclass != Self::SUBCLASS_SATA
+            || device_id.prog_if != Self::PROG_IF_AHCI
+        {
+            return Err((BusProbeError::DeviceNotMatch, device));
+        }
+        self.devices.lock().push(device);
+        Ok(Arc::new(AhciPciDevice { device_id }))
+    }
+}
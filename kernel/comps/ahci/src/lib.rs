@@ -0,0 +1,75 @@
+// SPDX-License-Identifier: MPL-2.0
+
+//! The AHCI driver of Asterinas.
+//!
+//! The driver resets each AHCI controller found on the PCI bus, sets up the command list
+//! and the received FIS area of each port with a SATA disk attached, and registers each
+//! disk as a block device. Like the virtio block devices, a disk is named by its serial
+//! number, or `sd{a,b,...}` if it has none.
+//!
+//! The read and write commands are NCQ commands if both the controller and the disk
+//! support NCQ. The commands complete in the interrupt handler of the controller, which
+//! raises the interrupts of all its ports with the only MSI vector.
+#![no_std]
+#![deny(unsafe_code)]
+
+extern crate alloc;
+
+use alloc::{string::String, sync::Arc, vec::Vec};
+
+use aster_frame::sync::SpinLock;
+use component::{init_component, ComponentInitError};
+use log::error;
+use spin::Once;
+
+use self::{controller::AhciController, driver::AHCI_PCI_DRIVER};
+
+mod command;
+mod controller;
+mod disk;
+mod driver;
+mod port;
+mod regs;
+
+/// The errors of the AHCI driver.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AhciError {
+    /// The controller does not provide the required resources, e.g., the ABAR or MSI.
+    NoResource,
+    /// The controller or the disk does not respond in time.
+    Timeout,
+    /// The disk reports an error.
+    DeviceError,
+    /// The memory for the command lists or the data structures cannot be allocated.
+    NoMemory,
+}
+
+static CONTROLLERS: SpinLock<Vec<Arc<AhciController>>> = SpinLock::new(Vec::new());
+static DISK_NAMES: Once<Vec<String>> = Once::new();
+
+#[init_component]
+fn ahci_component_init() -> Result<(), ComponentInitError> {
+    driver::ahci_pci_init();
+
+    let mut names = Vec::new();
+    while let Some(device) = AHCI_PCI_DRIVER.get().unwrap().pop_device() {
+        let id = CONTROLLERS.lock().len();
+        match AhciController::init(id, device) {
+            Ok(controller) => {
+                for disk in controller.disks() {
+                    names.push(String::from(disk.name()));
+                    aster_block::register_device(String::from(disk.name()), disk.clone());
+                }
+                CONTROLLERS.lock().push(controller);
+            }
+            Err(err) => error!("[AHCI]: Controller initialization error: {:?}", err),
+        }
+    }
+    DISK_NAMES.call_once(|| names);
+    Ok(())
+}
+
+/// Returns the names of all the SATA disks, which are registered as block devices.
+pub fn all_disks() -> Vec<String> {
+    DISK_NAMES.get().cloned().unwrap_or_default()
+}
@@ -0,0 +1,285 @@
+// SPDX-License-Identifier: MPL-2.0
+
+use core::{hint::spin_loop, mem::size_of, time::Duration};
+
+use aster_frame::{
+    arch::timer::Jiffies,
+    io_mem::IoMem,
+    mm::{
+        Daddr, DmaCoherent, DmaDirection, DmaStream, FrameAllocOptions, HasDaddr, VmIo, PAGE_SIZE,
+    },
+};
+use static_assertions::const_assert;
+
+use crate::{
+    command::{
+        CommandHeader, IdentifyData, PrdEntry, RegH2dFis, COMMAND_LIST_SIZE, PRDT_OFFSET,
+        RECEIVED_FIS_SIZE,
+    },
+    regs::{is_link_up, port_base, port_cmd, port_is, tfd, PortReg, SIG_ATA},
+    AhciError,
+};
+
+/// A port of an AHCI controller, which has its own command list.
+///
+/// Each command slot has a command table of a page, so a command has at most
+/// [`Self::MAX_NR_PRDS`] PRD entries.
+#[derive(Debug)]
+pub(crate) struct AhciPort {
+    index: usize,
+    registers: IoMem,
+    /// The command list at the start, and the received FIS area after it.
+    command_list: DmaCoherent,
+    /// The command tables of the slots, one page for each slot.
+    command_tables: DmaCoherent,
+    nr_slots: usize,
+}
+
+impl AhciPort {
+    /// The maximum number of the PRD entries of a command.
+    pub(crate) const MAX_NR_PRDS: usize = (PAGE_SIZE - PRDT_OFFSET) / size_of::<PrdEntry>();
+    /// The time to wait for the port to start or stop.
+    const TIMEOUT: Duration = Duration::from_millis(500);
+    /// The time to wait for the link of the device to come up.
+    const LINK_TIMEOUT: Duration = Duration::from_millis(50);
+    /// The time to wait for a command that is polled.
+    const COMMAND_TIMEOUT: Duration = Duration::from_secs(5);
+
+    /// Stops the port, and sets up its command list and received FIS area.
+    ///
+    /// Returns `None` if there is no SATA disk attached to the port.
+    pub(crate) fn new(
+        index: usize,
+        registers: &IoMem,
+        nr_slots: usize,
+        supports_64bit: bool,
+    ) -> Result<Option<Self>, AhciError> {
+        let alloc_coherent = |nframes| {
+            let segment = FrameAllocOptions::new(nframes)
+                .alloc_contiguous()
+                .map_err(|_| AhciError::NoMemory)?;
+            DmaCoherent::map(segment, true).map_err(|_| AhciError::NoMemory)
+        };
+        let port = Self {
+            index,
+            registers: registers.clone(),
+            command_list: alloc_coherent(1)?,
+            command_tables: alloc_coherent(nr_slots)?,
+            nr_slots,
+        };
+        let end_daddr =
+            port.command_list.daddr().max(port.command_tables.daddr()) + nr_slots * PAGE_SIZE;
+        if !supports_64bit && end_daddr > u32::MAX as usize {
+            return Err(AhciError::NoResource);
+        }
+
+        port.stop()?;
+        let clb = port.command_list.daddr() as u64;
+        let fb = clb + COMMAND_LIST_SIZE as u64;
+        port.write_reg64(PortReg::Clb, clb);
+        port.write_reg64(PortReg::Fb, fb);
+        let cmd = port.read_reg(PortReg::Cmd);
+        port.write_reg(
+            PortReg::Cmd,
+            cmd | port_cmd::FRE | port_cmd::SUD | port_cmd::POD,
+        );
+        port.clear_errors();
+
+        let deadline = Jiffies::elapsed().as_duration() + Self::LINK_TIMEOUT;
+        while !is_link_up(port.read_reg(PortReg::Ssts)) {
+            if Jiffies::elapsed().as_duration() >= deadline {
+                return Ok(None);
+            }
+            spin_loop();
+        }
+        port.wait_idle()?;
+        if port.read_reg(PortReg::Sig) != SIG_ATA {
+            return Ok(None);
+        }
+        Ok(Some(port))
+    }
+
+    pub(crate) fn index(&self) -> usize {
+        self.index
+    }
+
+    pub(crate) fn nr_slots(&self) -> usize {
+        self.nr_slots
+    }
+
+    /// Allows the port to process the command list.
+    pub(crate) fn start(&self) {
+        let cmd = self.read_reg(PortReg::Cmd);
+        self.write_reg(PortReg::Cmd, cmd | port_cmd::ST);
+    }
+
+    pub(crate) fn enable_interrupts(&self) {
+        self.write_reg(PortReg::Ie, port_is::ENABLED);
+    }
+
+    /// Stops the port from processing the command list and receiving the FISes.
+    fn stop(&self) -> Result<(), AhciError> {
+        let cmd = self.read_reg(PortReg::Cmd);
+        self.write_reg(PortReg::Cmd, cmd & !port_cmd::ST);
+        self.wait_cmd_clear(port_cmd::CR)?;
+        let cmd = self.read_reg(PortReg::Cmd);
+        self.write_reg(PortReg::Cmd, cmd & !port_cmd::FRE);
+        self.wait_cmd_clear(port_cmd::FR)
+    }
+
+    /// Recovers the port from an error, which aborts all the issued commands.
+    pub(crate) fn recover(&self) -> Result<(), AhciError> {
+        let cmd = self.read_reg(PortReg::Cmd);
+        self.write_reg(PortReg::Cmd, cmd & !port_cmd::ST);
+        self.wait_cmd_clear(port_cmd::CR)?;
+        self.clear_errors();
+        self.wait_idle()?;
+        let cmd = self.read_reg(PortReg::Cmd);
+        self.write_reg(PortReg::Cmd, cmd | port_cmd::ST);
+        Ok(())
+    }
+
+    /// Reads and clears the interrupt status of the port.
+    pub(crate) fn take_interrupt_status(&self) -> u32 {
+        let status = self.read_reg(PortReg::Is);
+        self.write_reg(PortReg::Is, status);
+        status
+    }
+
+    /// Returns the mask of the slots whose commands are still being processed.
+    pub(crate) fn busy_slots(&self) -> u32 {
+        self.read_reg(PortReg::Sact) | self.read_reg(PortReg::Ci)
+    }
+
+    /// Writes the command into the command header and the command table of the slot.
+    pub(crate) fn prepare(
+        &self,
+        slot: usize,
+        fis: &RegH2dFis,
+        prds: &[(Daddr, usize)],
+        is_write: bool,
+    ) {
+        debug_assert!(slot < self.nr_slots && prds.len() <= Self::MAX_NR_PRDS);
+        let table_offset = slot * PAGE_SIZE;
+        let mut fis = *fis;
+        if fis.is_queued() {
+            fis.set_tag(slot);
+        }
+        self.command_tables.write_val(table_offset, &fis).unwrap();
+        for (i, (daddr, len)) in prds.iter().enumerate() {
+            let offset = table_offset + PRDT_OFFSET + i * size_of::<PrdEntry>();
+            self.command_tables
+                .write_val(offset, &PrdEntry::new(*daddr as u64, *len))
+                .unwrap();
+        }
+        let ctba = (self.command_tables.daddr() + table_offset) as u64;
+        let header = CommandHeader::new(ctba, prds.len(), is_write);
+        self.command_list
+            .write_val(slot * size_of::<CommandHeader>(), &header)
+            .unwrap();
+    }
+
+    /// Issues the prepared command in the slot.
+    pub(crate) fn issue(&self, slot: usize, is_queued: bool) {
+        // The SActive bit of an NCQ command must be set before its Command Issue bit.
+        if is_queued {
+            self.write_reg(PortReg::Sact, 1 << slot);
+        }
+        self.write_reg(PortReg::Ci, 1 << slot);
+    }
+
+    /// Issues an IDENTIFY DEVICE command in the slot 0, and polls for its completion.
+    ///
+    /// The port must be started and have no interrupts enabled.
+    pub(crate) fn identify(&self) -> Result<IdentifyData, AhciError> {
+        let segment = FrameAllocOptions::new(1)
+            .alloc_contiguous()
+            .map_err(|_| AhciError::NoMemory)?;
+        let buffer = DmaStream::map(segment, DmaDirection::FromDevice, false)
+            .map_err(|_| AhciError::NoMemory)?;
+        let len = size_of::<IdentifyData>();
+        self.prepare(
+            0,
+            &RegH2dFis::identify_device(),
+            &[(buffer.daddr(), len)],
+            false,
+        );
+        self.issue(0, false);
+
+        let deadline = Jiffies::elapsed().as_duration() + Self::COMMAND_TIMEOUT;
+        while self.read_reg(PortReg::Ci) & 1 != 0 {
+            if self.read_reg(PortReg::Is) & port_is::ERRORS != 0 {
+                self.take_interrupt_status();
+                self.recover()?;
+                return Err(AhciError::DeviceError);
+            }
+            if Jiffies::elapsed().as_duration() >= deadline {
+                return Err(AhciError::Timeout);
+            }
+            spin_loop();
+        }
+        self.take_interrupt_status();
+
+        buffer.sync(0..len).unwrap();
+        let mut bytes = [0u8; size_of::<IdentifyData>()];
+        buffer.read_bytes(0, &mut bytes).unwrap();
+        let mut words = [0u16; 256];
+        for (word, chunk) in words.iter_mut().zip(bytes.chunks_exact(2)) {
+            *word = u16::from_le_bytes([chunk[0], chunk[1]]);
+        }
+        Ok(IdentifyData(words))
+    }
+
+    /// Clears the SATA errors and the interrupt status of the port.
+    fn clear_errors(&self) {
+        self.write_reg(PortReg::Serr, u32::MAX);
+        self.write_reg(PortReg::Is, u32::MAX);
+    }
+
+    /// Waits for the device to become neither busy nor requesting a data transfer.
+    fn wait_idle(&self) -> Result<(), AhciError> {
+        let deadline = Jiffies::elapsed().as_duration() + Self::TIMEOUT;
+        while self.read_reg(PortReg::Tfd) & (tfd::BSY | tfd::DRQ) != 0 {
+            if Jiffies::elapsed().as_duration() >= deadline {
+                return Err(AhciError::Timeout);
+            }
+            spin_loop();
+        }
+        Ok(())
+    }
+
+    fn wait_cmd_clear(&self, bits: u32) -> Result<(), AhciError> {
+        let deadline = Jiffies::elapsed().as_duration() + Self::TIMEOUT;
+        while self.read_reg(PortReg::Cmd) & bits != 0 {
+            if Jiffies::elapsed().as_duration() >= deadline {
+                return Err(AhciError::Timeout);
+            }
+            spin_loop();
+        }
+        Ok(())
+    }
+
+    fn read_reg(&self, reg: PortReg) -> u32 {
+        self.registers
+            .read_val(port_base(self.index) + reg as usize)
+            .unwrap()
+    }
+
+    fn write_reg(&self, reg: PortReg, val: u32) {
+        self.registers
+            .write_val(port_base(self.index) + reg as usize, &val)
+            .unwrap();
+    }
+
+    /// Writes a 64-bit address register as two 32-bit registers, since the controllers
+    /// may not support the 64-bit accesses.
+    fn write_reg64(&self, reg: PortReg, val: u64) {
+        let offset = port_base(self.index) + reg as usize;
+        self.registers.write_val(offset, &(val as u32)).unwrap();
+        self.registers
+            .write_val(offset + 4, &((val >> 32) as u32))
+            .unwrap();
+    }
+}
+
+const_assert!(RECEIVED_FIS_SIZE + COMMAND_LIST_SIZE <= PAGE_SIZE);
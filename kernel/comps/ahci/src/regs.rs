@@ -0,0 +1,153 @@
+// SPDX-License-Identifier: MPL-2.0
+
+//! The registers of the AHCI controllers, which are in BAR 5, i.e., ABAR.
+
+/// The offsets of the generic host control registers.
+#[derive(Debug, Clone, Copy)]
+#[repr(usize)]
+pub(crate) enum HbaReg {
+    /// Host Capabilities.
+    Cap = 0x00,
+    /// Global Host Control.
+    Ghc = 0x04,
+    /// Interrupt Status, whose bit `n` is set if the port `n` has a pending interrupt.
+    Is = 0x08,
+    /// Ports Implemented.
+    Pi = 0x0C,
+    /// Version.
+    Vs = 0x10,
+}
+
+/// The offsets of the registers of a port, relative to the registers of the port.
+#[derive(Debug, Clone, Copy)]
+#[repr(usize)]
+pub(crate) enum PortReg {
+    /// Command List Base Address, 64 bits.
+    Clb = 0x00,
+    /// FIS Base Address, 64 bits.
+    Fb = 0x08,
+    /// Interrupt Status.
+    Is = 0x10,
+    /// Interrupt Enable.
+    Ie = 0x14,
+    /// Command and Status.
+    Cmd = 0x18,
+    /// Task File Data.
+    Tfd = 0x20,
+    /// Signature.
+    Sig = 0x24,
+    /// Serial ATA Status.
+    Ssts = 0x28,
+    /// Serial ATA Error.
+    Serr = 0x30,
+    /// Serial ATA Active, whose bit `n` is set if the NCQ command in slot `n` is outstanding.
+    Sact = 0x34,
+    /// Command Issue, whose bit `n` is set if the command in slot `n` is issued.
+    Ci = 0x38,
+}
+
+/// Returns the offset of the registers of the port.
+pub(crate) fn port_base(port: usize) -> usize {
+    0x100 + port * 0x80
+}
+
+/// The Host Capabilities register.
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct Capability(pub u32);
+
+impl Capability {
+    /// Returns the number of the command slots of each port.
+    pub(crate) fn nr_command_slots(&self) -> usize {
+        ((self.0 >> 8) & 0x1F) as usize + 1
+    }
+
+    /// Returns whether the controller supports the native command queuing (NCQ).
+    pub(crate) fn supports_ncq(&self) -> bool {
+        (self.0 >> 30) & 1 == 1
+    }
+
+    /// Returns whether the controller can access the 64-bit addresses.
+    pub(crate) fn supports_64bit(&self) -> bool {
+        (self.0 >> 31) & 1 == 1
+    }
+}
+
+/// The bits of the Global Host Control register.
+pub(crate) mod ghc {
+    /// Resets the controller, which is cleared by the controller when the reset completes.
+    pub(crate) const HBA_RESET: u32 = 1 << 0;
+    /// Enables the interrupts of the controller.
+    pub(crate) const INTERRUPT_ENABLE: u32 = 1 << 1;
+    /// Enables the AHCI mode, rather than the legacy IDE mode.
+    pub(crate) const AHCI_ENABLE: u32 = 1 << 31;
+}
+
+/// The bits of the Interrupt Status and the Interrupt Enable registers of a port.
+pub(crate) mod port_is {
+    /// A Device to Host Register FIS is received, i.e., a non-queued command completes.
+    pub(crate) const DHRS: u32 = 1 << 0;
+    /// A PIO Setup FIS is received.
+    pub(crate) const PSS: u32 = 1 << 1;
+    /// A DMA Setup FIS is received.
+    pub(crate) const DSS: u32 = 1 << 2;
+    /// A Set Device Bits FIS is received, i.e., NCQ commands complete.
+    pub(crate) const SDBS: u32 = 1 << 3;
+    /// A PRD with the interrupt bit is processed.
+    pub(crate) const DPS: u32 = 1 << 5;
+    /// Interface Non-fatal Error.
+    pub(crate) const INFS: u32 = 1 << 26;
+    /// Overflow.
+    pub(crate) const OFS: u32 = 1 << 24;
+    /// Interface Fatal Error.
+    pub(crate) const IFS: u32 = 1 << 27;
+    /// Host Bus Data Error.
+    pub(crate) const HBDS: u32 = 1 << 28;
+    /// Host Bus Fatal Error.
+    pub(crate) const HBFS: u32 = 1 << 29;
+    /// Task File Error, i.e., the device reports an error.
+    pub(crate) const TFES: u32 = 1 << 30;
+
+    /// The interrupts that are enabled.
+    pub(crate) const ENABLED: u32 = DHRS | PSS | DSS | SDBS | DPS | ERRORS;
+    /// The errors that stop the port from processing the commands.
+    pub(crate) const ERRORS: u32 = INFS | OFS | IFS | HBDS | HBFS | TFES;
+}
+
+/// The bits of the Command and Status register of a port.
+pub(crate) mod port_cmd {
+    /// Start, which allows the port to process the command list.
+    pub(crate) const ST: u32 = 1 << 0;
+    /// Spin-Up Device.
+    pub(crate) const SUD: u32 = 1 << 1;
+    /// Power On Device.
+    pub(crate) const POD: u32 = 1 << 2;
+    /// FIS Receive Enable.
+    pub(crate) const FRE: u32 = 1 << 4;
+    /// FIS Receive Running.
+    pub(crate) const FR: u32 = 1 << 14;
+    /// Command List Running.
+    pub(crate) const CR: u32 = 1 << 15;
+}
+
+/// The bits of the Task File Data register of a port.
+pub(crate) mod tfd {
+    /// The device has an error.
+    pub(crate) const ERR: u32 = 1 << 0;
+    /// The device requests a data transfer.
+    pub(crate) const DRQ: u32 = 1 << 3;
+    /// The device is busy.
+    pub(crate) const BSY: u32 = 1 << 7;
+}
+
+/// The signature of the SATA disks, in the Signature register of a port.
+pub(crate) const SIG_ATA: u32 = 0x0000_0101;
+
+/// Returns whether a device is present and the communication is established, according
+/// to the Serial ATA Status register.
+pub(crate) fn is_link_up(ssts: u32) -> bool {
+    /// The device is present and the physical communication is established.
+    const DET_PRESENT: u32 = 3;
+    /// The interface is in the active state.
+    const IPM_ACTIVE: u32 = 1;
+    ssts & 0xF == DET_PRESENT && (ssts >> 8) & 0xF == IPM_ACTIVE
+}
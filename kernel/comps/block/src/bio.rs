@@ -7,7 +7,11 @@ use aster_frame::{
 };
 use int_to_c_enum::TryFromInt;
 
-use super::{id::Sid, BlockDevice};
+use super::{
+    id::Sid,
+    trace::{self, BioTraceInfo},
+    BlockDevice,
+};
 use crate::prelude::*;
 
 /// The unit for block I/O.
@@ -45,6 +49,7 @@ impl Bio {
             complete_fn,
             status: AtomicU32::new(BioStatus::Init as u32),
             wait_queue: WaitQueue::new(),
+            trace_info: BioTraceInfo::default(),
         });
         Self(inner)
     }
@@ -86,7 +91,19 @@ impl Bio {
         );
         assert!(result.is_ok());
 
-        if let Err(e) = block_device.enqueue(SubmittedBio(self.0.clone())) {
+        let submitted_bio = SubmittedBio(self.0.clone());
+        if trace::is_enabled() {
+            let trace_info = &self.0.trace_info;
+            trace_info
+                .device
+                .store(trace::device_number(block_device), Ordering::Relaxed);
+            trace_info
+                .queued_at_ns
+                .store(trace::now_ns().max(1), Ordering::Relaxed);
+            trace::trace_queue(&submitted_bio);
+        }
+
+        if let Err(e) = block_device.enqueue(submitted_bio) {
             // Fail to submit, revert the status.
             let result = self.0.status.compare_exchange(
                 BioStatus::Submit as u32,
@@ -270,11 +287,21 @@ impl SubmittedBio {
         );
         assert!(result.is_ok());
 
+        let queued_at_ns = self.0.trace_info.queued_at_ns.load(Ordering::Relaxed);
+        if queued_at_ns != 0 {
+            trace::trace_complete(self, status, queued_at_ns);
+        }
+
         self.0.wait_queue.wake_all();
         if let Some(complete_fn) = self.0.complete_fn {
             complete_fn(self);
         }
     }
+
+    /// Returns the device number of the block device for tracing.
+    pub(crate) fn trace_device(&self) -> u32 {
+        self.0.trace_info.device.load(Ordering::Relaxed)
+    }
 }
 
 /// The common inner part of `Bio`.
@@ -291,6 +318,8 @@ struct BioInner {
     status: AtomicU32,
     /// The wait queue for I/O completion
     wait_queue: WaitQueue,
+    /// The information for block I/O tracing
+    trace_info: BioTraceInfo,
 }
 
 impl BioInner {
@@ -71,6 +71,21 @@ impl dyn BlockDevice {
         let bio = create_bio_from_frame(BioType::Write, bid, frame);
         bio.submit(self)
     }
+
+    /// Synchronously flushes the volatile write cache of the device.
+    ///
+    /// Only the writes that have completed before the flush is submitted are guaranteed
+    /// to be persistent after the flush completes.
+    pub fn flush_sync(&self) -> Result<BioStatus, BioEnqueueError> {
+        let bio = Bio::new(
+            BioType::Flush,
+            Sid::new(0),
+            Vec::new(),
+            Some(general_complete_fn),
+        );
+        let status = bio.submit_sync(self)?;
+        Ok(status)
+    }
 }
 
 impl VmIo for dyn BlockDevice {
@@ -39,6 +39,8 @@ pub mod id;
 mod impl_block_device;
 mod prelude;
 pub mod request_queue;
+pub mod scheduler;
+pub mod trace;
 
 use aster_frame::sync::SpinLock;
 use component::{init_component, ComponentInitError};
@@ -47,6 +49,7 @@ use spin::Once;
 use self::{
     bio::{BioEnqueueError, SubmittedBio},
     prelude::*,
+    request_queue::BioRequestSingleQueue,
 };
 
 pub const BLOCK_SIZE: usize = aster_frame::mm::PAGE_SIZE;
@@ -57,6 +60,12 @@ pub trait BlockDevice: Send + Sync + Any + Debug {
     fn enqueue(&self, bio: SubmittedBio) -> Result<(), BioEnqueueError>;
     /// Returns the upper limit for the number of segments per bio.
     fn max_nr_segments_per_bio(&self) -> usize;
+    /// Returns the software request queue of the block device, if any.
+    ///
+    /// The I/O scheduler of the block device can be changed through the queue.
+    fn request_queue(&self) -> Option<&BioRequestSingleQueue> {
+        None
+    }
 }
 
 impl dyn BlockDevice {
@@ -66,6 +75,7 @@ impl dyn BlockDevice {
 }
 
 pub fn register_device(name: String, device: Arc<dyn BlockDevice>) {
+    trace::register_device(&device);
     COMPONENT
         .get()
         .unwrap()
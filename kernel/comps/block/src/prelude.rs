@@ -1,6 +1,7 @@
 // SPDX-License-Identifier: MPL-2.0
 
 pub(crate) use alloc::{
+    boxed::Box,
     collections::{BTreeMap, VecDeque},
     string::String,
     sync::Arc,
@@ -5,19 +5,22 @@ use aster_frame::sync::{Mutex, WaitQueue};
 use super::{
     bio::{BioEnqueueError, BioType, SubmittedBio},
     id::Sid,
+    scheduler::{IoScheduler, IoSchedulerKind},
+    trace,
 };
 use crate::prelude::*;
 
-/// A simple block I/O request queue backed by one internal FIFO queue.
+/// A block I/O request queue backed by a pluggable I/O scheduler.
 ///
-/// It is a FIFO producer-consumer queue, where the producer (e.g., filesystem)
+/// It is a producer-consumer queue, where the producer (e.g., filesystem)
 /// submits requests to the queue, and the consumer (e.g., block device driver)
 /// continuously consumes and processes these requests from the queue.
 ///
-/// It supports merging the new request with the front request if if the type
-/// is same and the sector range is contiguous.
+/// The scheduler merges the new bio into a queued request if the type is same and
+/// the sector range is contiguous, and decides the order in which the requests are
+/// dispatched. See [`IoScheduler`] for details.
 pub struct BioRequestSingleQueue {
-    queue: Mutex<VecDeque<BioRequest>>,
+    scheduler: Mutex<Box<dyn IoScheduler>>,
     num_requests: AtomicUsize,
     wait_queue: WaitQueue,
     max_nr_segments_per_bio: usize,
@@ -30,9 +33,17 @@ impl BioRequestSingleQueue {
     }
 
     /// Creates an empty queue with the upper bound for the number of segments in a bio.
+    ///
+    /// The queue uses the deadline scheduler.
     pub fn with_max_nr_segments_per_bio(max_nr_segments_per_bio: usize) -> Self {
+        Self::with_scheduler(IoSchedulerKind::Deadline, max_nr_segments_per_bio)
+    }
+
+    /// Creates an empty queue with the kind of the scheduler and the upper bound for
+    /// the number of segments in a bio.
+    pub fn with_scheduler(kind: IoSchedulerKind, max_nr_segments_per_bio: usize) -> Self {
         Self {
-            queue: Mutex::new(VecDeque::new()),
+            scheduler: Mutex::new(kind.new_scheduler()),
             num_requests: AtomicUsize::new(0),
             wait_queue: WaitQueue::new(),
             max_nr_segments_per_bio,
@@ -49,11 +60,28 @@ impl BioRequestSingleQueue {
         self.num_requests.load(Ordering::Relaxed)
     }
 
+    /// Returns the name of the scheduler.
+    pub fn scheduler_name(&self) -> &'static str {
+        self.scheduler.lock().name()
+    }
+
+    /// Replaces the scheduler with a new one of the kind.
+    ///
+    /// The queued requests are moved to the new scheduler in the order in which the
+    /// old scheduler dispatches them.
+    pub fn set_scheduler(&self, kind: IoSchedulerKind) {
+        let mut scheduler = self.scheduler.lock();
+        let mut new_scheduler = kind.new_scheduler();
+        while let Some(request) = scheduler.dispatch() {
+            new_scheduler.insert_request(request);
+        }
+        *scheduler = new_scheduler;
+    }
+
     /// Enqueues a `SubmittedBio` to this queue.
     ///
-    /// When enqueueing the `SubmittedBio`, try to insert it into the last request if the
-    /// type is same and the sector range is contiguous.
-    /// Otherwise, creates and inserts a new request for the `SubmittedBio`.
+    /// The scheduler tries to merge the `SubmittedBio` into a queued request.
+    /// Otherwise, it creates and inserts a new request for the `SubmittedBio`.
     ///
     /// This method will wake up the waiter if a new `BioRequest` is enqueued.
     pub fn enqueue(&self, bio: SubmittedBio) -> Result<(), BioEnqueueError> {
@@ -61,22 +89,16 @@ impl BioRequestSingleQueue {
             return Err(BioEnqueueError::TooBig);
         }
 
-        let mut queue = self.queue.lock();
-        if let Some(request) = queue.front_mut() {
-            if request.can_merge(&bio)
-                && request.num_segments() + bio.segments().len() <= self.max_nr_segments_per_bio
-            {
-                request.merge_bio(bio);
-                return Ok(());
-            }
-        }
-
-        let new_request = BioRequest::from(bio);
-        queue.push_front(new_request);
-        self.inc_num_requests();
-        drop(queue);
+        let mut scheduler = self.scheduler.lock();
+        let old_num_requests = scheduler.num_requests();
+        scheduler.insert(bio, self.max_nr_segments_per_bio);
+        let num_requests = scheduler.num_requests();
+        self.num_requests.store(num_requests, Ordering::Relaxed);
+        drop(scheduler);
 
-        self.wait_queue.wake_all();
+        if num_requests > old_num_requests {
+            self.wait_queue.wake_all();
+        }
         Ok(())
     }
 
@@ -88,9 +110,7 @@ impl BioRequestSingleQueue {
 
         loop {
             if num_requests > 0 {
-                let mut queue = self.queue.lock();
-                if let Some(request) = queue.pop_back() {
-                    self.dec_num_requests();
+                if let Some(request) = self.try_dequeue() {
                     return request;
                 }
             }
@@ -106,12 +126,18 @@ impl BioRequestSingleQueue {
         }
     }
 
-    fn dec_num_requests(&self) {
-        self.num_requests.fetch_sub(1, Ordering::Relaxed);
-    }
+    /// Dequeues a `BioRequest` from this queue if there is any.
+    ///
+    /// This method never waits.
+    pub fn try_dequeue(&self) -> Option<BioRequest> {
+        let mut scheduler = self.scheduler.lock();
+        let request = scheduler.dispatch()?;
+        self.num_requests
+            .store(scheduler.num_requests(), Ordering::Relaxed);
+        drop(scheduler);
 
-    fn inc_num_requests(&self) {
-        self.num_requests.fetch_add(1, Ordering::Relaxed);
+        trace::trace_request_issue(&request);
+        Some(request)
     }
 }
 
@@ -125,7 +151,7 @@ impl Debug for BioRequestSingleQueue {
     fn fmt(&self, f: &mut core::fmt::Formatter) -> core::fmt::Result {
         f.debug_struct("BioRequestSingleQueue")
             .field("num_requests", &self.num_requests())
-            .field("queue", &self.queue.lock())
+            .field("scheduler", &self.scheduler.lock())
             .finish()
     }
 }
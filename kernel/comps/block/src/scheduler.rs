@@ -0,0 +1,487 @@
+// SPDX-License-Identifier: MPL-2.0
+
+//! The I/O schedulers of the block request queues.
+//!
+//! An I/O scheduler decides how the submitted bios are merged into requests and in which
+//! order the requests are dispatched to the driver. The schedulers are pluggable, i.e.,
+//! each request queue has its own scheduler, which can be replaced at runtime.
+//!
+//! The flush requests bypass the sorting of all the schedulers, since a flush only covers
+//! the writes that have been completed before it is submitted.
+
+use core::time::Duration;
+
+use aster_frame::arch::timer::Jiffies;
+
+use super::{
+    bio::{BioType, SubmittedBio},
+    id::Sid,
+    request_queue::BioRequest,
+};
+use crate::prelude::*;
+
+/// An I/O scheduler, which holds the requests that are not dispatched yet.
+pub trait IoScheduler: Send + Sync + Debug {
+    /// Returns the name of the scheduler.
+    fn name(&self) -> &'static str;
+
+    /// Inserts a `SubmittedBio` into the scheduler.
+    ///
+    /// The bio is merged into a queued request if possible, as long as the request has
+    /// no more than `max_nr_segments` segments after merging.
+    fn insert(&mut self, bio: SubmittedBio, max_nr_segments: usize);
+
+    /// Inserts a `BioRequest` into the scheduler without merging it.
+    fn insert_request(&mut self, request: BioRequest);
+
+    /// Dispatches the next request to the driver.
+    fn dispatch(&mut self) -> Option<BioRequest>;
+
+    /// Returns the number of the queued requests.
+    fn num_requests(&self) -> usize;
+}
+
+/// The kinds of the I/O schedulers.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum IoSchedulerKind {
+    Noop,
+    Deadline,
+}
+
+impl IoSchedulerKind {
+    /// Returns the kind of the scheduler with the name.
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "noop" | "none" => Some(Self::Noop),
+            "deadline" | "mq-deadline" => Some(Self::Deadline),
+            _ => None,
+        }
+    }
+
+    /// Creates an empty scheduler of this kind.
+    pub fn new_scheduler(self) -> Box<dyn IoScheduler> {
+        match self {
+            Self::Noop => Box::new(NoopScheduler::new()),
+            Self::Deadline => Box::new(DeadlineScheduler::new()),
+        }
+    }
+}
+
+/// The no-op scheduler.
+///
+/// It dispatches the requests in FIFO order. The bios are merged into any queued request
+/// whose sector range is contiguous with them.
+#[derive(Debug, Default)]
+pub struct NoopScheduler {
+    requests: VecDeque<BioRequest>,
+}
+
+impl NoopScheduler {
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+impl IoScheduler for NoopScheduler {
+    fn name(&self) -> &'static str {
+        "noop"
+    }
+
+    fn insert(&mut self, bio: SubmittedBio, max_nr_segments: usize) {
+        // The recent requests are more likely to be merged with.
+        if let Some(request) = self
+            .requests
+            .iter_mut()
+            .rev()
+            .find(|request| can_merge(request, &bio, max_nr_segments))
+        {
+            request.merge_bio(bio);
+            return;
+        }
+        self.requests.push_back(BioRequest::from(bio));
+    }
+
+    fn insert_request(&mut self, request: BioRequest) {
+        self.requests.push_back(request);
+    }
+
+    fn dispatch(&mut self) -> Option<BioRequest> {
+        self.requests.pop_front()
+    }
+
+    fn num_requests(&self) -> usize {
+        self.requests.len()
+    }
+}
+
+/// The deadline scheduler.
+///
+/// It dispatches the requests in the ascending order of their sectors to reduce the
+/// seeks, in batches of the same direction. Each request has a deadline, and the next
+/// batch starts with the oldest request if it has expired, so that no request starves.
+/// The reads are preferred over the writes, since the reads are usually synchronous, but
+/// the writes are not starved by the reads for more than `WRITES_STARVED` batches.
+#[derive(Debug)]
+pub struct DeadlineScheduler {
+    /// The queued requests of each direction indexed by their IDs.
+    requests: [BTreeMap<u64, QueuedRequest>; 2],
+    /// The IDs of the queued requests of each direction sorted by the start sectors.
+    sorted: [BTreeMap<(Sid, u64), ()>; 2],
+    /// The IDs of the requests of each direction in the order of their deadlines.
+    ///
+    /// The requests that have been dispatched are removed lazily.
+    fifo: [VecDeque<u64>; 2],
+    /// The flush requests, which are dispatched before the others.
+    flushes: VecDeque<BioRequest>,
+    next_id: u64,
+    /// The direction and the next sector of the current batch.
+    batch_pos: Option<(Direction, Sid)>,
+    /// The number of requests dispatched in the current batch.
+    batch_len: usize,
+    /// The number of the read batches started while there are queued writes.
+    nr_starved_writes: usize,
+}
+
+#[derive(Debug)]
+struct QueuedRequest {
+    request: BioRequest,
+    deadline: Duration,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+enum Direction {
+    Read = 0,
+    Write = 1,
+}
+
+impl Direction {
+    fn of(type_: BioType) -> Self {
+        match type_ {
+            BioType::Read => Self::Read,
+            _ => Self::Write,
+        }
+    }
+
+    fn expire_time(self) -> Duration {
+        match self {
+            Self::Read => DeadlineScheduler::READ_EXPIRE,
+            Self::Write => DeadlineScheduler::WRITE_EXPIRE,
+        }
+    }
+}
+
+impl DeadlineScheduler {
+    /// The time before a read expires.
+    const READ_EXPIRE: Duration = Duration::from_millis(500);
+    /// The time before a write expires.
+    const WRITE_EXPIRE: Duration = Duration::from_secs(5);
+    /// The maximum number of requests dispatched in a batch.
+    const FIFO_BATCH: usize = 16;
+    /// The maximum number of read batches that may starve the writes.
+    const WRITES_STARVED: usize = 2;
+
+    pub fn new() -> Self {
+        Self {
+            requests: [BTreeMap::new(), BTreeMap::new()],
+            sorted: [BTreeMap::new(), BTreeMap::new()],
+            fifo: [VecDeque::new(), VecDeque::new()],
+            flushes: VecDeque::new(),
+            next_id: 0,
+            batch_pos: None,
+            batch_len: 0,
+            nr_starved_writes: 0,
+        }
+    }
+
+    fn add_request(&mut self, request: BioRequest, deadline: Duration) {
+        let dir = Direction::of(request.type_());
+        let id = self.next_id;
+        self.next_id += 1;
+
+        self.sorted[dir as usize].insert((request.sid_range().start, id), ());
+        self.fifo[dir as usize].push_back(id);
+        self.requests[dir as usize].insert(id, QueuedRequest { request, deadline });
+    }
+
+    fn remove_request(&mut self, dir: Direction, id: u64) -> BioRequest {
+        let queued = self.requests[dir as usize].remove(&id).unwrap();
+        self.sorted[dir as usize].remove(&(queued.request.sid_range().start, id));
+        queued.request
+    }
+
+    /// Returns the ID of the oldest request of the direction.
+    fn oldest(&mut self, dir: Direction) -> Option<u64> {
+        let fifo = &mut self.fifo[dir as usize];
+        while let Some(id) = fifo.front() {
+            if self.requests[dir as usize].contains_key(id) {
+                return Some(*id);
+            }
+            fifo.pop_front();
+        }
+        None
+    }
+
+    fn is_expired(&mut self, dir: Direction) -> bool {
+        let Some(id) = self.oldest(dir) else {
+            return false;
+        };
+        self.requests[dir as usize][&id].deadline <= now()
+    }
+
+    /// Returns the ID of the first request of the direction at or after the sector.
+    fn next_sorted(&self, dir: Direction, sid: Sid) -> Option<u64> {
+        self.sorted[dir as usize]
+            .range((sid, 0)..)
+            .next()
+            .map(|((_, id), _)| *id)
+    }
+
+    /// Starts a new batch and returns the ID of its first request.
+    fn start_batch(&mut self) -> Option<(Direction, u64)> {
+        let has_reads = !self.requests[Direction::Read as usize].is_empty();
+        let has_writes = !self.requests[Direction::Write as usize].is_empty();
+
+        let dir = if has_reads && (!has_writes || self.nr_starved_writes < Self::WRITES_STARVED) {
+            if has_writes {
+                self.nr_starved_writes += 1;
+            }
+            Direction::Read
+        } else if has_writes {
+            self.nr_starved_writes = 0;
+            Direction::Write
+        } else {
+            return None;
+        };
+
+        // The batch starts with the oldest request if it has expired, or continues from
+        // the position of the last batch of the same direction otherwise.
+        let id = if self.is_expired(dir) {
+            self.oldest(dir).unwrap()
+        } else {
+            let sid = match self.batch_pos {
+                Some((batch_dir, sid)) if batch_dir == dir => sid,
+                _ => Sid::new(0),
+            };
+            self.next_sorted(dir, sid)
+                .or_else(|| self.next_sorted(dir, Sid::new(0)))
+                .unwrap()
+        };
+        self.batch_len = 0;
+        Some((dir, id))
+    }
+}
+
+impl Default for DeadlineScheduler {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl IoScheduler for DeadlineScheduler {
+    fn name(&self) -> &'static str {
+        "deadline"
+    }
+
+    fn insert(&mut self, bio: SubmittedBio, max_nr_segments: usize) {
+        if bio.type_() == BioType::Flush {
+            self.flushes.push_back(BioRequest::from(bio));
+            return;
+        }
+
+        let dir = Direction::of(bio.type_());
+        let merged_id = self.requests[dir as usize]
+            .iter()
+            .find(|(_, queued)| can_merge(&queued.request, &bio, max_nr_segments))
+            .map(|(id, _)| *id);
+        let Some(id) = merged_id else {
+            self.add_request(BioRequest::from(bio), now() + dir.expire_time());
+            return;
+        };
+
+        // The request is sorted by its start sector, which changes for a front merge.
+        let queued = self.requests[dir as usize].get_mut(&id).unwrap();
+        let old_start = queued.request.sid_range().start;
+        queued.request.merge_bio(bio);
+        let new_start = queued.request.sid_range().start;
+        if new_start != old_start {
+            self.sorted[dir as usize].remove(&(old_start, id));
+            self.sorted[dir as usize].insert((new_start, id), ());
+        }
+    }
+
+    fn insert_request(&mut self, request: BioRequest) {
+        if request.type_() == BioType::Flush {
+            self.flushes.push_back(request);
+            return;
+        }
+        let dir = Direction::of(request.type_());
+        self.add_request(request, now() + dir.expire_time());
+    }
+
+    fn dispatch(&mut self) -> Option<BioRequest> {
+        if let Some(request) = self.flushes.pop_front() {
+            return Some(request);
+        }
+
+        // Continues the current batch if it is not full and not interrupted by an
+        // expired request of the same direction.
+        let next = match self.batch_pos {
+            Some((dir, sid)) if self.batch_len < Self::FIFO_BATCH && !self.is_expired(dir) => {
+                self.next_sorted(dir, sid).map(|id| (dir, id))
+            }
+            _ => None,
+        };
+        let (dir, id) = match next {
+            Some(next) => next,
+            None => self.start_batch()?,
+        };
+
+        let request = self.remove_request(dir, id);
+        self.batch_pos = Some((dir, request.sid_range().end));
+        self.batch_len += 1;
+        Some(request)
+    }
+
+    fn num_requests(&self) -> usize {
+        self.flushes.len()
+            + self
+                .requests
+                .iter()
+                .map(|requests| requests.len())
+                .sum::<usize>()
+    }
+}
+
+fn can_merge(request: &BioRequest, bio: &SubmittedBio, max_nr_segments: usize) -> bool {
+    request.can_merge(bio) && request.num_segments() + bio.segments().len() <= max_nr_segments
+}
+
+fn now() -> Duration {
+    Jiffies::elapsed().as_duration()
+}
+
+#[cfg(ktest)]
+mod test {
+    use aster_frame::mm::FrameAllocOptions;
+    use ktest::ktest;
+
+    use super::*;
+    use crate::{
+        bio::{Bio, BioEnqueueError, BioSegment},
+        request_queue::BioRequestSingleQueue,
+        BlockDevice, SECTOR_SIZE,
+    };
+
+    /// A device that only queues the bios.
+    #[derive(Debug)]
+    struct QueueDevice(BioRequestSingleQueue);
+
+    impl BlockDevice for QueueDevice {
+        fn enqueue(&self, bio: SubmittedBio) -> Result<(), BioEnqueueError> {
+            self.0.enqueue(bio)
+        }
+
+        fn max_nr_segments_per_bio(&self) -> usize {
+            self.0.max_nr_segments_per_bio()
+        }
+    }
+
+    /// Submits a bio of one sector, or an empty one if it is a flush.
+    fn submit(device: &QueueDevice, type_: BioType, sid: u64) {
+        let segments = if type_ == BioType::Flush {
+            Vec::new()
+        } else {
+            let frame = FrameAllocOptions::new(1).alloc_single().unwrap();
+            vec![BioSegment::from_frame(frame, 0, SECTOR_SIZE)]
+        };
+        let bio = Bio::new(type_, Sid::new(sid), segments, None);
+        bio.submit(device).unwrap();
+    }
+
+    fn dispatch_all(device: &QueueDevice) -> Vec<(BioType, u64, u64)> {
+        let mut dispatched = Vec::new();
+        while let Some(request) = device.0.try_dequeue() {
+            let range = request.sid_range();
+            dispatched.push((request.type_(), range.start.to_raw(), range.end.to_raw()));
+        }
+        dispatched
+    }
+
+    #[ktest]
+    fn noop_merges_and_dispatches_in_order() {
+        let device = QueueDevice(BioRequestSingleQueue::with_scheduler(
+            IoSchedulerKind::Noop,
+            16,
+        ));
+        assert_eq!(device.0.scheduler_name(), "noop");
+
+        submit(&device, BioType::Write, 8);
+        submit(&device, BioType::Write, 0);
+        submit(&device, BioType::Write, 1);
+        // A read is never merged with a write.
+        submit(&device, BioType::Read, 2);
+        assert_eq!(device.0.num_requests(), 3);
+
+        assert_eq!(
+            dispatch_all(&device),
+            vec![
+                (BioType::Write, 8, 9),
+                (BioType::Write, 0, 2),
+                (BioType::Read, 2, 3),
+            ]
+        );
+        assert_eq!(device.0.num_requests(), 0);
+    }
+
+    #[ktest]
+    fn deadline_sorts_and_prefers_reads() {
+        let device = QueueDevice(BioRequestSingleQueue::with_scheduler(
+            IoSchedulerKind::Deadline,
+            16,
+        ));
+        assert_eq!(device.0.scheduler_name(), "deadline");
+
+        submit(&device, BioType::Write, 30);
+        submit(&device, BioType::Write, 10);
+        submit(&device, BioType::Read, 20);
+        submit(&device, BioType::Read, 5);
+        submit(&device, BioType::Write, 11);
+        submit(&device, BioType::Flush, 0);
+        assert_eq!(device.0.num_requests(), 5);
+
+        assert_eq!(
+            dispatch_all(&device),
+            vec![
+                (BioType::Flush, 0, 0),
+                (BioType::Read, 5, 6),
+                (BioType::Read, 20, 21),
+                (BioType::Write, 10, 12),
+                (BioType::Write, 30, 31),
+            ]
+        );
+    }
+
+    #[ktest]
+    fn switch_scheduler_keeps_requests() {
+        let device = QueueDevice(BioRequestSingleQueue::with_scheduler(
+            IoSchedulerKind::Noop,
+            16,
+        ));
+        submit(&device, BioType::Write, 30);
+        submit(&device, BioType::Read, 20);
+        submit(&device, BioType::Write, 10);
+
+        device.0.set_scheduler(IoSchedulerKind::Deadline);
+        assert_eq!(device.0.scheduler_name(), "deadline");
+        assert_eq!(device.0.num_requests(), 3);
+
+        assert_eq!(
+            dispatch_all(&device),
+            vec![
+                (BioType::Read, 20, 21),
+                (BioType::Write, 10, 11),
+                (BioType::Write, 30, 31),
+            ]
+        );
+    }
+}
@@ -0,0 +1,403 @@
+// SPDX-License-Identifier: MPL-2.0
+
+//! Block I/O tracing.
+//!
+//! When tracing is enabled, the block layer records an event when a bio is queued to a
+//! block device, when a request is issued to the driver, and when a bio is completed. The
+//! events are recorded in a ring buffer in the binary format of Linux's `blktrace`, so
+//! the dumped events can be analyzed by `blkparse`, `btt` or `iowatcher`. The oldest
+//! events are overwritten if the ring buffer is full.
+//!
+//! The completion events carry the latency from the queueing of the bio in nanoseconds
+//! as the payload.
+
+use core::{
+    mem::size_of,
+    sync::atomic::{AtomicBool, AtomicU64},
+};
+
+use aster_frame::{
+    arch::{read_tsc, tsc_freq},
+    cpu::this_cpu,
+    sync::SpinLock,
+};
+use pod::Pod;
+use spin::Once;
+
+use crate::{
+    bio::{BioStatus, BioType, SubmittedBio},
+    prelude::*,
+    request_queue::BioRequest,
+    BlockDevice, SECTOR_SIZE,
+};
+
+/// The magic number of the events, with the version in the lowest byte.
+const BLK_IO_TRACE_MAGIC: u32 = 0x6561_7407;
+
+/// The maximum number of the events in the ring buffer.
+const MAX_NR_EVENTS: usize = 16384;
+
+/// The major device number of the traced block devices.
+///
+/// Asterinas does not have the device numbers of the block devices, so the devices are
+/// numbered in the order of their registration with the major number of `blkext`.
+const BLOCK_EXT_MAJOR: u32 = 259;
+
+/// An event in the binary format of `blktrace`, which is `struct blk_io_trace` in Linux.
+#[repr(C)]
+#[derive(Debug, Default, Clone, Copy, Pod)]
+pub struct BlkIoTrace {
+    pub magic: u32,
+    pub sequence: u32,
+    /// The time since the boot in nanoseconds.
+    pub time: u64,
+    pub sector: u64,
+    pub bytes: u32,
+    pub action: u32,
+    pub pid: u32,
+    pub device: u32,
+    pub cpu: u32,
+    pub error: u16,
+    pub pdu_len: u16,
+}
+
+/// The actions of the events.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[repr(u32)]
+enum Action {
+    Queue = 1,
+    Issue = 7,
+    Complete = 8,
+}
+
+impl Action {
+    /// Returns the category bit of the action.
+    fn category(self) -> u32 {
+        match self {
+            Self::Queue => 1 << 4,
+            Self::Issue => 1 << 6,
+            Self::Complete => 1 << 7,
+        }
+    }
+}
+
+/// Returns the category bits of the type of the I/O.
+fn type_category(type_: BioType) -> u32 {
+    match type_ {
+        BioType::Read => 1 << 0,
+        BioType::Write => 1 << 1,
+        BioType::Flush => (1 << 1) | (1 << 2),
+        BioType::Discard => (1 << 1) | (1 << 13),
+    }
+}
+
+/// The shift of the category bits in the action field.
+const CATEGORY_SHIFT: u32 = 16;
+
+struct TraceBuffer {
+    events: VecDeque<(BlkIoTrace, Option<u64>)>,
+    next_sequence: u32,
+    nr_dropped: usize,
+}
+
+static IS_ENABLED: AtomicBool = AtomicBool::new(false);
+static BUFFER: SpinLock<TraceBuffer> = SpinLock::new(TraceBuffer {
+    events: VecDeque::new(),
+    next_sequence: 0,
+    nr_dropped: 0,
+});
+/// The addresses of the registered block devices, whose indexes are their minor numbers.
+static DEVICES: SpinLock<Vec<usize>> = SpinLock::new(Vec::new());
+static PID_FN: Once<fn() -> u32> = Once::new();
+
+/// Sets the function that returns the ID of the current process, which is recorded in
+/// the events.
+pub fn set_pid_fn(pid_fn: fn() -> u32) {
+    PID_FN.call_once(|| pid_fn);
+}
+
+/// Enables tracing, which discards the recorded events.
+pub fn enable() {
+    clear();
+    IS_ENABLED.store(true, Ordering::Relaxed);
+}
+
+/// Disables tracing, which keeps the recorded events.
+pub fn disable() {
+    IS_ENABLED.store(false, Ordering::Relaxed);
+}
+
+pub fn is_enabled() -> bool {
+    IS_ENABLED.load(Ordering::Relaxed)
+}
+
+/// Discards the recorded events.
+pub fn clear() {
+    let mut buffer = BUFFER.lock_irq_disabled();
+    buffer.events.clear();
+    buffer.nr_dropped = 0;
+}
+
+/// Returns the number of the events that are overwritten since tracing is enabled.
+pub fn nr_dropped() -> usize {
+    BUFFER.lock_irq_disabled().nr_dropped
+}
+
+/// Returns the recorded events in the binary format of `blktrace`.
+pub fn events_as_bytes() -> Vec<u8> {
+    let buffer = BUFFER.lock_irq_disabled();
+    let mut bytes = Vec::with_capacity(buffer.events.len() * (size_of::<BlkIoTrace>() + 8));
+    for (event, pdu) in buffer.events.iter() {
+        bytes.extend_from_slice(event.as_bytes());
+        if let Some(pdu) = pdu {
+            bytes.extend_from_slice(&pdu.to_ne_bytes());
+        }
+    }
+    bytes
+}
+
+pub(crate) fn register_device(device: &Arc<dyn BlockDevice>) {
+    DEVICES
+        .lock_irq_disabled()
+        .push(device_addr(device.as_ref()));
+}
+
+/// Returns the device number of the block device, or zero if it is not registered.
+pub(crate) fn device_number(device: &dyn BlockDevice) -> u32 {
+    let addr = device_addr(device);
+    DEVICES
+        .lock_irq_disabled()
+        .iter()
+        .position(|registered| *registered == addr)
+        .map_or(0, |minor| (BLOCK_EXT_MAJOR << 20) | minor as u32)
+}
+
+fn device_addr(device: &dyn BlockDevice) -> usize {
+    device as *const dyn BlockDevice as *const () as usize
+}
+
+/// Returns the time since the boot in nanoseconds.
+pub(crate) fn now_ns() -> u64 {
+    let freq = tsc_freq();
+    if freq == 0 {
+        return 0;
+    }
+    (read_tsc() as u128 * 1_000_000_000 / freq as u128) as u64
+}
+
+/// Records that the bio is queued to its block device.
+pub(crate) fn trace_queue(bio: &SubmittedBio) {
+    record(
+        Action::Queue,
+        bio,
+        bio.sid_range().start.to_raw(),
+        bio_bytes(bio),
+        0,
+        None,
+    );
+}
+
+/// Records that the bio is issued to the driver.
+///
+/// The drivers that do not use a [`BioRequestSingleQueue`] should call this when they
+/// submit the bio to the device.
+///
+/// [`BioRequestSingleQueue`]: crate::request_queue::BioRequestSingleQueue
+pub fn trace_issue(bio: &SubmittedBio) {
+    if !is_enabled() {
+        return;
+    }
+    record(
+        Action::Issue,
+        bio,
+        bio.sid_range().start.to_raw(),
+        bio_bytes(bio),
+        0,
+        None,
+    );
+}
+
+/// Records that the request is issued to the driver.
+pub(crate) fn trace_request_issue(request: &BioRequest) {
+    if !is_enabled() {
+        return;
+    }
+    let Some(first_bio) = request.bios().next() else {
+        return;
+    };
+    let sid_range = request.sid_range();
+    let bytes = (sid_range.end.to_raw() - sid_range.start.to_raw()) as usize * SECTOR_SIZE;
+    record(
+        Action::Issue,
+        first_bio,
+        sid_range.start.to_raw(),
+        bytes as u32,
+        0,
+        None,
+    );
+}
+
+/// Records that the bio is completed, with its latency as the payload.
+pub(crate) fn trace_complete(bio: &SubmittedBio, status: BioStatus, queued_at_ns: u64) {
+    let error = match status {
+        BioStatus::Complete => 0,
+        BioStatus::NotSupported => 95, // EOPNOTSUPP
+        BioStatus::NoSpace => 28,      // ENOSPC
+        _ => 5,                        // EIO
+    };
+    let latency_ns = now_ns().saturating_sub(queued_at_ns);
+    record(
+        Action::Complete,
+        bio,
+        bio.sid_range().start.to_raw(),
+        bio_bytes(bio),
+        error,
+        Some(latency_ns),
+    );
+}
+
+fn bio_bytes(bio: &SubmittedBio) -> u32 {
+    let sid_range = bio.sid_range();
+    ((sid_range.end.to_raw() - sid_range.start.to_raw()) as usize * SECTOR_SIZE) as u32
+}
+
+fn record(
+    action: Action,
+    bio: &SubmittedBio,
+    sector: u64,
+    bytes: u32,
+    error: u16,
+    pdu: Option<u64>,
+) {
+    let mut event = BlkIoTrace {
+        magic: BLK_IO_TRACE_MAGIC,
+        sequence: 0,
+        time: now_ns(),
+        sector,
+        bytes,
+        action: action as u32
+            | ((action.category() | type_category(bio.type_())) << CATEGORY_SHIFT),
+        pid: PID_FN.get().map_or(0, |pid_fn| pid_fn()),
+        device: bio.trace_device(),
+        cpu: this_cpu(),
+        error,
+        pdu_len: if pdu.is_some() { 8 } else { 0 },
+    };
+
+    let mut buffer = BUFFER.lock_irq_disabled();
+    event.sequence = buffer.next_sequence;
+    buffer.next_sequence = buffer.next_sequence.wrapping_add(1);
+    if buffer.events.len() >= MAX_NR_EVENTS {
+        buffer.events.pop_front();
+        buffer.nr_dropped += 1;
+    }
+    buffer.events.push_back((event, pdu));
+}
+
+/// The tracing information of a bio.
+#[derive(Debug, Default)]
+pub(crate) struct BioTraceInfo {
+    /// The device number of the block device to which the bio is submitted.
+    pub device: AtomicU32,
+    /// The time when the bio is queued in nanoseconds, or zero if it is not traced.
+    pub queued_at_ns: AtomicU64,
+}
+
+#[cfg(ktest)]
+mod test {
+    use aster_frame::mm::FrameAllocOptions;
+    use ktest::ktest;
+
+    use super::*;
+    use crate::{
+        bio::{Bio, BioEnqueueError, BioSegment},
+        id::Sid,
+        request_queue::BioRequestSingleQueue,
+    };
+
+    #[derive(Debug)]
+    struct QueueDevice(BioRequestSingleQueue);
+
+    impl BlockDevice for QueueDevice {
+        fn enqueue(&self, bio: SubmittedBio) -> Result<(), BioEnqueueError> {
+            self.0.enqueue(bio)
+        }
+
+        fn max_nr_segments_per_bio(&self) -> usize {
+            self.0.max_nr_segments_per_bio()
+        }
+
+        fn request_queue(&self) -> Option<&BioRequestSingleQueue> {
+            Some(&self.0)
+        }
+    }
+
+    /// Parses the events and their payloads.
+    fn parse_events(mut bytes: &[u8]) -> Vec<(BlkIoTrace, Option<u64>)> {
+        let mut events = Vec::new();
+        while !bytes.is_empty() {
+            let event = BlkIoTrace::from_bytes(&bytes[..size_of::<BlkIoTrace>()]);
+            bytes = &bytes[size_of::<BlkIoTrace>()..];
+            let pdu = (event.pdu_len == 8).then(|| {
+                let pdu = u64::from_ne_bytes(bytes[..8].try_into().unwrap());
+                bytes = &bytes[8..];
+                pdu
+            });
+            events.push((event, pdu));
+        }
+        events
+    }
+
+    #[ktest]
+    fn trace_queue_issue_and_complete() {
+        let device: Arc<dyn BlockDevice> = Arc::new(QueueDevice(
+            BioRequestSingleQueue::with_max_nr_segments_per_bio(16),
+        ));
+        // The device is not registered, so its number is zero.
+        assert_eq!(device_number(device.as_ref()), 0);
+
+        enable();
+        let frame = FrameAllocOptions::new(1).alloc_single().unwrap();
+        let segment = BioSegment::from_frame(frame, 0, 2 * SECTOR_SIZE);
+        let bio = Bio::new(BioType::Write, Sid::new(100), vec![segment], None);
+        let waiter = bio.submit(device.as_ref()).unwrap();
+        let request = device.request_queue().unwrap().try_dequeue().unwrap();
+        for bio in request.bios() {
+            bio.complete(BioStatus::NoSpace);
+        }
+        assert_eq!(waiter.wait(), None);
+        assert_eq!(bio.status(), BioStatus::NoSpace);
+        disable();
+
+        let events = parse_events(&events_as_bytes());
+        assert_eq!(nr_dropped(), 0);
+        let actions = [Action::Queue, Action::Issue, Action::Complete];
+        assert_eq!(events.len(), actions.len());
+        for (i, ((event, pdu), action)) in events.iter().zip(actions).enumerate() {
+            assert_eq!(event.magic, BLK_IO_TRACE_MAGIC);
+            assert_eq!(event.sequence, events[0].0.sequence + i as u32);
+            assert!(event.time >= events[0].0.time);
+            assert_eq!(event.sector, 100);
+            assert_eq!(event.bytes, 2 * SECTOR_SIZE as u32);
+            assert_eq!(event.device, 0);
+            assert_eq!(
+                event.action,
+                action as u32
+                    | ((action.category() | type_category(BioType::Write)) << CATEGORY_SHIFT)
+            );
+            if action == Action::Complete {
+                assert_eq!(event.error, 28);
+                assert!(pdu.is_some());
+            } else {
+                assert_eq!(event.error, 0);
+                assert_eq!(*pdu, None);
+            }
+        }
+
+        // No more events are recorded after tracing is disabled.
+        let bio = Bio::new(BioType::Flush, Sid::new(0), Vec::new(), None);
+        bio.submit(device.as_ref()).unwrap();
+        assert!(device.request_queue().unwrap().try_dequeue().is_some());
+        assert_eq!(parse_events(&events_as_bytes()).len(), actions.len());
+    }
+}
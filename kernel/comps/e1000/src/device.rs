@@ -0,0 +1,271 @@
+// SPDX-License-Identifier: MPL-2.0
+
+#![allow(dead_code)]
+
+use alloc::{format, string::String, sync::Arc};
+use core::{fmt::Debug, time::Duration};
+
+use aster_frame::{
+    arch::timer::{self, Jiffies},
+    bus::pci::{
+        capability::{msi::CapabilityMsiData, CapabilityData},
+        cfg_space::{Bar, Command},
+        common_device::PciCommonDevice,
+    },
+    io_mem::IoMem,
+    sync::SpinLock,
+    trap::IrqLine,
+};
+use aster_network::{AnyNetworkDevice, EthernetAddr, RxBuffer, VirtioNetError};
+use log::{info, warn};
+use smoltcp::phy::{DeviceCapabilities, Medium};
+
+use crate::{
+    regs::{ctrl, int, rctl, status, tctl, E1000Reg, NR_MTA_REGS, RAH_AV, TIPG_COPPER},
+    ring::{RxRing, TxRing, RING_SIZE},
+    E1000Error,
+};
+
+/// An e1000-family network device.
+pub struct E1000Device {
+    name: String,
+    registers: IoMem,
+    mac_addr: EthernetAddr,
+    rx_ring: RxRing,
+    tx_ring: TxRing,
+    /// The MSI capability, which owns the IRQ line of the device, or `None` if the device
+    /// is polled in the timer interrupts.
+    msi: Option<CapabilityMsiData>,
+    pci_device: PciCommonDevice,
+}
+
+impl E1000Device {
+    /// The time to wait for the device to reset.
+    const RESET_TIMEOUT: Duration = Duration::from_millis(10);
+    /// The minimum interval between the interrupts in the units of 256 ns, which limits
+    /// the interrupts to about 8000 per second.
+    const INTERRUPT_INTERVAL: u32 = 488;
+
+    /// Resets and initializes the device, and registers it as a network device named
+    /// `e1000-{id}`.
+    pub(crate) fn init(id: usize, pci_device: PciCommonDevice) -> Result<String, E1000Error> {
+        let Some(Bar::Memory(bar)) = pci_device.bar_manager().bar(0) else {
+            return Err(E1000Error::NoResource);
+        };
+        let registers = bar.io_mem().clone();
+        let msi = pci_device
+            .capabilities()
+            .iter()
+            .find_map(|cap| match cap.capability_data() {
+                CapabilityData::Msi(data) => Some(data.clone()),
+                _ => None,
+            });
+        // The DMA needs the bus mastering, which is only enabled along with MSI otherwise.
+        pci_device.set_command(pci_device.command() | Command::BUS_MASTER);
+
+        Self::reset(&registers)?;
+        let mac_addr = Self::read_mac_addr(&registers)?;
+        let name = format!("e1000-{}", id);
+        let mut device = Self {
+            name: name.clone(),
+            registers,
+            mac_addr,
+            rx_ring: RxRing::new()?,
+            tx_ring: TxRing::new()?,
+            msi,
+            pci_device,
+        };
+        device.init_rx();
+        device.init_tx();
+        info!(
+            "[e1000]: Found device {} with MAC address {:x?}",
+            name, mac_addr.0
+        );
+
+        device.write_reg(E1000Reg::Itr, Self::INTERRUPT_INTERVAL);
+        // The receive delay timers are not used, since the interrupts are throttled.
+        device.write_reg(E1000Reg::Rdtr, 0);
+        device.write_reg(E1000Reg::Radv, 0);
+        let registers = device.registers.clone();
+        let cloned_name = name.clone();
+        let handle_irq = move || {
+            // Reading the interrupt causes clears them, which acknowledges the interrupt.
+            let cause = registers.read_val::<u32>(E1000Reg::Icr as usize).unwrap();
+            if cause & int::LSC != 0 {
+                let device_status: u32 = registers.read_val(E1000Reg::Status as usize).unwrap();
+                let is_up = device_status & status::LU != 0;
+                info!(
+                    "[e1000]: The link of {} is {}",
+                    cloned_name,
+                    if is_up { "up" } else { "down" }
+                );
+            }
+            if cause & int::RX != 0 {
+                aster_network::handle_recv_irq(&cloned_name);
+            }
+        };
+        match device.msi.as_mut() {
+            Some(msi) => {
+                let irq = IrqLine::alloc().map_err(|_| E1000Error::NoResource)?;
+                msi.set_interrupt_vector(irq);
+                msi.irq_mut().unwrap().on_active(move |_| handle_irq());
+            }
+            None => {
+                // There is no INTx support, so the devices without MSI, e.g., the 82540EM
+                // of QEMU, are polled in the timer interrupts of the current CPU.
+                warn!("[e1000]: Device {} without MSI is polled", name);
+                timer::register_callback(handle_irq);
+            }
+        }
+        device.write_reg(E1000Reg::Ims, int::ENABLED);
+
+        aster_network::register_device(name.clone(), Arc::new(SpinLock::new(device)));
+        Ok(name)
+    }
+
+    /// Resets the device, and sets the link up.
+    fn reset(registers: &IoMem) -> Result<(), E1000Error> {
+        registers
+            .write_val(E1000Reg::Imc as usize, &u32::MAX)
+            .unwrap();
+        let control: u32 = registers.read_val(E1000Reg::Ctrl as usize).unwrap();
+        registers
+            .write_val(E1000Reg::Ctrl as usize, &(control | ctrl::RST))
+            .unwrap();
+        let deadline = Jiffies::elapsed().as_duration() + Self::RESET_TIMEOUT;
+        loop {
+            let control: u32 = registers.read_val(E1000Reg::Ctrl as usize).unwrap();
+            if control & ctrl::RST == 0 {
+                break;
+            }
+            if Jiffies::elapsed().as_duration() >= deadline {
+                return Err(E1000Error::Timeout);
+            }
+            core::hint::spin_loop();
+        }
+        // The interrupts are enabled again by the reset.
+        registers
+            .write_val(E1000Reg::Imc as usize, &u32::MAX)
+            .unwrap();
+        registers.read_val::<u32>(E1000Reg::Icr as usize).unwrap();
+
+        let control: u32 = registers.read_val(E1000Reg::Ctrl as usize).unwrap();
+        let control = (control | ctrl::SLU | ctrl::ASDE) & !ctrl::PHY_RST;
+        registers
+            .write_val(E1000Reg::Ctrl as usize, &control)
+            .unwrap();
+        Ok(())
+    }
+
+    /// Reads the MAC address, which is loaded from the EEPROM into the first receive
+    /// address by the reset.
+    fn read_mac_addr(registers: &IoMem) -> Result<EthernetAddr, E1000Error> {
+        let low: u32 = registers.read_val(E1000Reg::Ral0 as usize).unwrap();
+        let high: u32 = registers.read_val(E1000Reg::Rah0 as usize).unwrap();
+        if high & RAH_AV == 0 {
+            return Err(E1000Error::NoMacAddr);
+        }
+        let mut mac_addr = [0u8; 6];
+        mac_addr[..4].copy_from_slice(&low.to_le_bytes());
+        mac_addr[4..].copy_from_slice(&high.to_le_bytes()[..2]);
+        Ok(EthernetAddr(mac_addr))
+    }
+
+    fn init_rx(&self) {
+        for i in 0..NR_MTA_REGS {
+            self.write_reg_at(E1000Reg::Mta, i * 4, 0);
+        }
+        self.write_reg64(E1000Reg::Rdba, self.rx_ring.daddr());
+        self.write_reg(E1000Reg::Rdlen, (RING_SIZE * 16) as u32);
+        self.write_reg(E1000Reg::Rdh, 0);
+        self.write_reg(E1000Reg::Rdt, self.rx_ring.initial_tail() as u32);
+        self.write_reg(
+            E1000Reg::Rctl,
+            rctl::EN | rctl::BAM | rctl::BSIZE_2048 | rctl::SECRC,
+        );
+    }
+
+    fn init_tx(&self) {
+        self.write_reg64(E1000Reg::Tdba, self.tx_ring.daddr());
+        self.write_reg(E1000Reg::Tdlen, (RING_SIZE * 16) as u32);
+        self.write_reg(E1000Reg::Tdh, 0);
+        self.write_reg(E1000Reg::Tdt, 0);
+        self.write_reg(E1000Reg::Tipg, TIPG_COPPER);
+        self.write_reg(E1000Reg::Tctl, tctl::EN | tctl::PSP | tctl::CT | tctl::COLD);
+    }
+
+    fn write_reg(&self, reg: E1000Reg, val: u32) {
+        self.write_reg_at(reg, 0, val);
+    }
+
+    fn write_reg_at(&self, reg: E1000Reg, offset: usize, val: u32) {
+        self.registers
+            .write_val(reg as usize + offset, &val)
+            .unwrap();
+    }
+
+    /// Writes a 64-bit address register as two 32-bit registers.
+    fn write_reg64(&self, reg: E1000Reg, val: u64) {
+        self.write_reg_at(reg, 0, val as u32);
+        self.write_reg_at(reg, 4, (val >> 32) as u32);
+    }
+}
+
+impl AnyNetworkDevice for E1000Device {
+    fn mac_addr(&self) -> EthernetAddr {
+        self.mac_addr
+    }
+
+    fn capabilities(&self) -> DeviceCapabilities {
+        let mut caps = DeviceCapabilities::default();
+        caps.max_transmission_unit = 1514;
+        caps.max_burst_size = Some(1);
+        caps.medium = Medium::Ethernet;
+        caps
+    }
+
+    fn can_receive(&self) -> bool {
+        self.rx_ring.has_packet()
+    }
+
+    fn can_send(&self) -> bool {
+        self.tx_ring.can_push()
+    }
+
+    fn receive(&mut self) -> Result<RxBuffer, VirtioNetError> {
+        loop {
+            let (buffer, is_good, tail) = self.rx_ring.pop().ok_or(VirtioNetError::NotReady)?;
+            self.write_reg(E1000Reg::Rdt, tail as u32);
+            if is_good {
+                return Ok(buffer);
+            }
+            warn!("[e1000]: Drop a bad packet received by {}", self.name);
+        }
+    }
+
+    /// Queues the packet to be sent, which is copied into a buffer of the device.
+    ///
+    /// The buffer is freed after the packet is sent, so this method does not wait.
+    fn send(&mut self, packet: &[u8]) -> Result<(), VirtioNetError> {
+        let tail = self
+            .tx_ring
+            .push(packet)
+            .map_err(|_| VirtioNetError::Unknown)?
+            .ok_or(VirtioNetError::NotReady)?;
+        self.write_reg(E1000Reg::Tdt, tail as u32);
+        Ok(())
+    }
+}
+
+impl Debug for E1000Device {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        f.debug_struct("E1000Device")
+            .field("name", &self.name)
+            .field("registers", &self.registers)
+            .field("mac_addr", &self.mac_addr)
+            .field("rx_ring", &self.rx_ring)
+            .field("tx_ring", &self.tx_ring)
+            .field("msi", &self.msi)
+            .finish()
+    }
+}
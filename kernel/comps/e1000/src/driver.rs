@@ -0,0 +1,83 @@
+// SPDX-License-Identifier: MPL-2.0
+
+use alloc::{sync::Arc, vec::Vec};
+
+use aster_frame::{
+    bus::{
+        pci::{
+            bus::{PciDevice, PciDriver},
+            common_device::PciCommonDevice,
+            PciDeviceId, PCI_BUS,
+        },
+        BusProbeError,
+    },
+    sync::SpinLock,
+};
+use spin::Once;
+
+pub(crate) static E1000_PCI_DRIVER: Once<Arc<E1000PciDriver>> = Once::new();
+
+pub(crate) fn e1000_pci_init() {
+    E1000_PCI_DRIVER.call_once(|| Arc::new(E1000PciDriver::new()));
+    PCI_BUS
+        .lock()
+        .register_driver(E1000_PCI_DRIVER.get().unwrap().clone());
+}
+
+/// The PCI driver that claims the e1000-family controllers.
+#[derive(Debug)]
+pub(crate) struct E1000PciDriver {
+    devices: SpinLock<Vec<PciCommonDevice>>,
+}
+
+#[derive(Debug)]
+struct E1000PciDevice {
+    device_id: PciDeviceId,
+}
+
+impl PciDevice for E1000PciDevice {
+    fn device_id(&self) -> PciDeviceId {
+        self.device_id
+    }
+}
+
+impl E1000PciDriver {
+    const VENDOR_INTEL: u16 = 0x8086;
+    /// The device IDs of the supported controllers, which use the legacy descriptors.
+    const DEVICE_IDS: &'static [u16] = &[
+        0x100E, // 82540EM, which is the default NIC of QEMU.
+        0x100F, // 82545EM.
+        0x1010, // 82546EB.
+        0x107C, // 82541PI.
+        0x105E, // 82571EB.
+        0x107D, // 82572EI.
+        0x10D3, // 82574L, which is the e1000e NIC of QEMU.
+    ];
+
+    fn new() -> Self {
+        Self {
+            devices: SpinLock::new(Vec::new()),
+        }
+    }
+
+    /// Pops a probed controller that is not initialized yet.
+    pub(crate) fn pop_device(&self) -> Option<PciCommonDevice> {
+        self.devices.lock().pop()
+    }
+}
+
+impl PciDriver for E1000PciDriver {
+    fn probe(
+        &self,
+        device: PciCommonDevice,
+    ) -> Result<Arc<dyn PciDevice>, (BusProbeError, PciCommonDevice)> {
+        let device_id = *device.device_id();
+        if device_id.vendor_id != Self::VENDOR_INTEL
+            || !Self::DEVICE_IDS.contains(&device_id.device_id)
+        {
+            return Err((BusProbeError::DeviceNotMatch, device));
+        }
+        self.devices.lock().push(device);
+        Ok(Arc::new(E1000PciDevice { device_id }))
+    }
+}
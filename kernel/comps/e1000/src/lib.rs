@@ -0,0 +1,59 @@
+// SPDX-License-Identifier: MPL-2.0
+
+//! The e1000 driver of Asterinas, which supports the Intel 8254x and 8257x controllers.
+//!
+//! Each controller has a receive ring and a transmit ring of the legacy descriptors, and
+//! is registered as a network device named `e1000-{id}`. The interrupts are throttled by
+//! the controller, so a burst of received packets raises few interrupts.
+#![no_std]
+#![deny(unsafe_code)]
+
+extern crate alloc;
+
+use alloc::{string::String, vec::Vec};
+
+use component::{init_component, ComponentInitError};
+use log::error;
+use spin::Once;
+
+use self::{device::E1000Device, driver::E1000_PCI_DRIVER};
+
+mod device;
+mod driver;
+mod regs;
+mod ring;
+
+/// The errors of the e1000 driver.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum E1000Error {
+    /// The device does not provide the required resources, e.g., the BAR or the IRQ.
+    NoResource,
+    /// The device does not respond in time.
+    Timeout,
+    /// The device has no valid MAC address.
+    NoMacAddr,
+    /// The memory for the rings or the buffers cannot be allocated.
+    NoMemory,
+}
+
+static DEVICE_NAMES: Once<Vec<String>> = Once::new();
+
+#[init_component]
+fn e1000_component_init() -> Result<(), ComponentInitError> {
+    driver::e1000_pci_init();
+
+    let mut names = Vec::new();
+    while let Some(device) = E1000_PCI_DRIVER.get().unwrap().pop_device() {
+        match E1000Device::init(names.len(), device) {
+            Ok(name) => names.push(name),
+            Err(err) => error!("[e1000]: Device initialization error: {:?}", err),
+        }
+    }
+    DEVICE_NAMES.call_once(|| names);
+    Ok(())
+}
+
+/// Returns the names of all the e1000 devices, which are registered as network devices.
+pub fn all_devices() -> Vec<String> {
+    DEVICE_NAMES.get().cloned().unwrap_or_default()
+}
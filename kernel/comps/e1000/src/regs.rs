@@ -0,0 +1,124 @@
+// SPDX-License-Identifier: MPL-2.0
+
+//! The registers of the e1000-family controllers, which are in BAR 0.
+
+/// The offsets of the registers.
+#[derive(Debug, Clone, Copy)]
+#[repr(usize)]
+pub(crate) enum E1000Reg {
+    /// Device Control.
+    Ctrl = 0x0000,
+    /// Device Status.
+    Status = 0x0008,
+    /// Interrupt Cause Read, which is cleared when it is read.
+    Icr = 0x00C0,
+    /// Interrupt Throttling Rate.
+    Itr = 0x00C4,
+    /// Interrupt Mask Set.
+    Ims = 0x00D0,
+    /// Interrupt Mask Clear.
+    Imc = 0x00D8,
+    /// Receive Control.
+    Rctl = 0x0100,
+    /// Transmit Control.
+    Tctl = 0x0400,
+    /// Transmit Inter-Packet Gap.
+    Tipg = 0x0410,
+    /// Receive Descriptor Base Address, 64 bits.
+    Rdba = 0x2800,
+    /// Receive Descriptor Length.
+    Rdlen = 0x2808,
+    /// Receive Descriptor Head.
+    Rdh = 0x2810,
+    /// Receive Descriptor Tail.
+    Rdt = 0x2818,
+    /// Receive Delay Timer.
+    Rdtr = 0x2820,
+    /// Receive Interrupt Absolute Delay Timer.
+    Radv = 0x282C,
+    /// Transmit Descriptor Base Address, 64 bits.
+    Tdba = 0x3800,
+    /// Transmit Descriptor Length.
+    Tdlen = 0x3808,
+    /// Transmit Descriptor Head.
+    Tdh = 0x3810,
+    /// Transmit Descriptor Tail.
+    Tdt = 0x3818,
+    /// Multicast Table Array, 128 registers.
+    Mta = 0x5200,
+    /// Receive Address Low of the first receive address.
+    Ral0 = 0x5400,
+    /// Receive Address High of the first receive address.
+    Rah0 = 0x5404,
+}
+
+/// The number of the Multicast Table Array registers.
+pub(crate) const NR_MTA_REGS: usize = 128;
+
+/// The bits of the Device Control register.
+pub(crate) mod ctrl {
+    /// Auto-Speed Detection Enable.
+    pub(crate) const ASDE: u32 = 1 << 5;
+    /// Set Link Up.
+    pub(crate) const SLU: u32 = 1 << 6;
+    /// Device Reset, which is cleared by the controller when the reset completes.
+    pub(crate) const RST: u32 = 1 << 26;
+    /// PHY Reset.
+    pub(crate) const PHY_RST: u32 = 1 << 31;
+}
+
+/// The bits of the Device Status register.
+pub(crate) mod status {
+    /// Link Up.
+    pub(crate) const LU: u32 = 1 << 1;
+}
+
+/// The bits of the interrupt registers, i.e., ICR, IMS, and IMC.
+pub(crate) mod int {
+    /// Transmit Descriptor Written Back.
+    pub(crate) const TXDW: u32 = 1 << 0;
+    /// Link Status Change.
+    pub(crate) const LSC: u32 = 1 << 2;
+    /// Receive Descriptor Minimum Threshold Reached.
+    pub(crate) const RXDMT0: u32 = 1 << 4;
+    /// Receiver Overrun.
+    pub(crate) const RXO: u32 = 1 << 6;
+    /// Receiver Timer Interrupt, i.e., packets are received.
+    pub(crate) const RXT0: u32 = 1 << 7;
+
+    /// The interrupts that indicate the received packets.
+    pub(crate) const RX: u32 = RXDMT0 | RXO | RXT0;
+    /// The interrupts that are enabled.
+    pub(crate) const ENABLED: u32 = RX | LSC;
+}
+
+/// The bits of the Receive Control register.
+pub(crate) mod rctl {
+    /// Receiver Enable.
+    pub(crate) const EN: u32 = 1 << 1;
+    /// Broadcast Accept Mode.
+    pub(crate) const BAM: u32 = 1 << 15;
+    /// The receive buffers are 2048 bytes, if the bits 16-17 and BSEX are clear.
+    pub(crate) const BSIZE_2048: u32 = 0;
+    /// Strip Ethernet CRC.
+    pub(crate) const SECRC: u32 = 1 << 26;
+}
+
+/// The bits of the Transmit Control register.
+pub(crate) mod tctl {
+    /// Transmit Enable.
+    pub(crate) const EN: u32 = 1 << 1;
+    /// Pad Short Packets.
+    pub(crate) const PSP: u32 = 1 << 3;
+    /// The recommended Collision Threshold.
+    pub(crate) const CT: u32 = 0x0F << 4;
+    /// The recommended Collision Distance of the full-duplex mode.
+    pub(crate) const COLD: u32 = 0x40 << 12;
+}
+
+/// The recommended Transmit Inter-Packet Gap of the copper media, i.e., IPGT = 10,
+/// IPGR1 = 8, and IPGR2 = 6.
+pub(crate) const TIPG_COPPER: u32 = 10 | (8 << 10) | (6 << 20);
+
+/// The bit of the Receive Address High register that marks the address as valid.
+pub(crate) const RAH_AV: u32 = 1 << 31;
@@ -0,0 +1,249 @@
+// SPDX-License-Identifier: MPL-2.0
+
+use alloc::vec::Vec;
+use core::{fmt::Debug, marker::PhantomData, mem::size_of};
+
+use aster_frame::mm::{DmaCoherent, FrameAllocOptions, HasDaddr, VmIo, PAGE_SIZE};
+use aster_network::{RxBuffer, TxBuffer, RX_BUFFER_POOL, TX_BUFFER_POOL};
+use pod::Pod;
+use static_assertions::const_assert_eq;
+
+use crate::E1000Error;
+
+/// The number of the descriptors of a ring, which fill a page.
+pub(crate) const RING_SIZE: usize = PAGE_SIZE / 16;
+
+/// A legacy receive descriptor.
+#[repr(C)]
+#[derive(Debug, Default, Clone, Copy, Pod)]
+pub(crate) struct RxDesc {
+    pub addr: u64,
+    pub length: u16,
+    pub checksum: u16,
+    pub status: u8,
+    pub errors: u8,
+    pub special: u16,
+}
+
+const_assert_eq!(size_of::<RxDesc>(), 16);
+
+impl RxDesc {
+    /// The descriptor is written back by the controller.
+    const STATUS_DD: u8 = 1 << 0;
+    /// The descriptor holds the last part of a packet.
+    const STATUS_EOP: u8 = 1 << 1;
+}
+
+/// A legacy transmit descriptor.
+#[repr(C)]
+#[derive(Debug, Default, Clone, Copy, Pod)]
+pub(crate) struct TxDesc {
+    pub addr: u64,
+    pub length: u16,
+    pub cso: u8,
+    pub cmd: u8,
+    pub status: u8,
+    pub css: u8,
+    pub special: u16,
+}
+
+const_assert_eq!(size_of::<TxDesc>(), 16);
+
+impl TxDesc {
+    /// The descriptor holds the last part of a packet.
+    const CMD_EOP: u8 = 1 << 0;
+    /// The controller inserts the Ethernet CRC.
+    const CMD_IFCS: u8 = 1 << 1;
+    /// The controller reports the status by setting the DD bit.
+    const CMD_RS: u8 = 1 << 3;
+    /// The descriptor is processed by the controller.
+    const STATUS_DD: u8 = 1 << 0;
+}
+
+/// The descriptors of a ring, which are in the memory shared with the controller.
+#[derive(Debug)]
+struct DescRing<D> {
+    descs: DmaCoherent,
+    _marker: PhantomData<D>,
+}
+
+impl<D: Pod> DescRing<D> {
+    fn new() -> Result<Self, E1000Error> {
+        let segment = FrameAllocOptions::new(1)
+            .alloc_contiguous()
+            .map_err(|_| E1000Error::NoMemory)?;
+        let descs = DmaCoherent::map(segment, true).map_err(|_| E1000Error::NoMemory)?;
+        Ok(Self {
+            descs,
+            _marker: PhantomData,
+        })
+    }
+
+    fn read(&self, index: usize) -> D {
+        self.descs.read_val(index * size_of::<D>()).unwrap()
+    }
+
+    fn write(&self, index: usize, desc: &D) {
+        self.descs.write_val(index * size_of::<D>(), desc).unwrap();
+    }
+}
+
+/// The receive ring, whose descriptors all hold the buffers for the controller.
+pub(crate) struct RxRing {
+    descs: DescRing<RxDesc>,
+    buffers: Vec<Option<RxBuffer>>,
+    /// The next descriptor to be written back by the controller.
+    next_to_clean: usize,
+}
+
+impl RxRing {
+    pub(crate) fn new() -> Result<Self, E1000Error> {
+        let descs = DescRing::new()?;
+        let mut buffers = Vec::with_capacity(RING_SIZE);
+        for index in 0..RING_SIZE {
+            let buffer = RxBuffer::new(0, RX_BUFFER_POOL.get().unwrap());
+            descs.write(index, &Self::empty_desc(&buffer));
+            buffers.push(Some(buffer));
+        }
+        Ok(Self {
+            descs,
+            buffers,
+            next_to_clean: 0,
+        })
+    }
+
+    pub(crate) fn daddr(&self) -> u64 {
+        self.descs.descs.daddr() as u64
+    }
+
+    /// Returns the tail after the initialization, where the controller stops.
+    ///
+    /// A descriptor is always left unused, so that the controller can tell a full ring
+    /// from an empty one.
+    pub(crate) fn initial_tail(&self) -> usize {
+        RING_SIZE - 1
+    }
+
+    pub(crate) fn has_packet(&self) -> bool {
+        self.descs.read(self.next_to_clean).status & RxDesc::STATUS_DD != 0
+    }
+
+    /// Pops a received packet, and refills its descriptor with a new buffer.
+    ///
+    /// Returns the packet, whether the packet is good, and the new tail. The packets that
+    /// span multiple buffers or have errors are bad, since the buffers fit the maximum
+    /// size of an Ethernet frame.
+    pub(crate) fn pop(&mut self) -> Option<(RxBuffer, bool, usize)> {
+        let index = self.next_to_clean;
+        let desc = self.descs.read(index);
+        if desc.status & RxDesc::STATUS_DD == 0 {
+            return None;
+        }
+
+        let new_buffer = RxBuffer::new(0, RX_BUFFER_POOL.get().unwrap());
+        self.descs.write(index, &Self::empty_desc(&new_buffer));
+        let mut buffer = self.buffers[index].replace(new_buffer).unwrap();
+        let is_good = desc.status & RxDesc::STATUS_EOP != 0 && desc.errors == 0;
+        if is_good {
+            buffer.set_packet_len(desc.length as usize);
+        }
+        self.next_to_clean = (index + 1) % RING_SIZE;
+        // The refilled descriptor becomes the unused one.
+        Some((buffer, is_good, index))
+    }
+
+    fn empty_desc(buffer: &RxBuffer) -> RxDesc {
+        RxDesc {
+            addr: buffer.daddr() as u64,
+            ..Default::default()
+        }
+    }
+}
+
+impl Debug for RxRing {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        f.debug_struct("RxRing")
+            .field("descs", &self.descs)
+            .field("next_to_clean", &self.next_to_clean)
+            .finish()
+    }
+}
+
+/// The transmit ring, whose descriptors hold the buffers until they are sent.
+pub(crate) struct TxRing {
+    descs: DescRing<TxDesc>,
+    buffers: Vec<Option<TxBuffer>>,
+    /// The next descriptor to be filled by the driver, i.e., the tail.
+    next_to_use: usize,
+    /// The next descriptor to be processed by the controller.
+    next_to_clean: usize,
+}
+
+impl TxRing {
+    pub(crate) fn new() -> Result<Self, E1000Error> {
+        Ok(Self {
+            descs: DescRing::new()?,
+            buffers: (0..RING_SIZE).map(|_| None).collect(),
+            next_to_use: 0,
+            next_to_clean: 0,
+        })
+    }
+
+    pub(crate) fn daddr(&self) -> u64 {
+        self.descs.descs.daddr() as u64
+    }
+
+    /// Returns whether a packet can be pushed, possibly after reclaiming the sent ones.
+    pub(crate) fn can_push(&self) -> bool {
+        !self.is_full() || self.descs.read(self.next_to_clean).status & TxDesc::STATUS_DD != 0
+    }
+
+    /// Pushes a packet, and returns the new tail.
+    ///
+    /// Returns `None` if the ring is full.
+    pub(crate) fn push(&mut self, packet: &[u8]) -> Result<Option<usize>, E1000Error> {
+        self.reclaim();
+        if self.is_full() {
+            return Ok(None);
+        }
+        let buffer = TxBuffer::new_from_pool(&[0u8; 0], packet, TX_BUFFER_POOL.get().unwrap())
+            .map_err(|_| E1000Error::NoMemory)?;
+        let index = self.next_to_use;
+        self.descs.write(
+            index,
+            &TxDesc {
+                addr: buffer.daddr() as u64,
+                length: packet.len() as u16,
+                cmd: TxDesc::CMD_EOP | TxDesc::CMD_IFCS | TxDesc::CMD_RS,
+                ..Default::default()
+            },
+        );
+        self.buffers[index] = Some(buffer);
+        self.next_to_use = (index + 1) % RING_SIZE;
+        Ok(Some(self.next_to_use))
+    }
+
+    /// Frees the buffers of the packets that have been sent.
+    fn reclaim(&mut self) {
+        while self.next_to_clean != self.next_to_use
+            && self.descs.read(self.next_to_clean).status & TxDesc::STATUS_DD != 0
+        {
+            self.buffers[self.next_to_clean] = None;
+            self.next_to_clean = (self.next_to_clean + 1) % RING_SIZE;
+        }
+    }
+
+    fn is_full(&self) -> bool {
+        (self.next_to_use + 1) % RING_SIZE == self.next_to_clean
+    }
+}
+
+impl Debug for TxRing {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        f.debug_struct("TxRing")
+            .field("descs", &self.descs)
+            .field("next_to_use", &self.next_to_use)
+            .field("next_to_clean", &self.next_to_clean)
+            .finish()
+    }
+}
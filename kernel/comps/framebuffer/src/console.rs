@@ -0,0 +1,230 @@
+// SPDX-License-Identifier: MPL-2.0
+
+//! The text console on a framebuffer, i.e., fbcon.
+
+use alloc::{fmt::Debug, sync::Arc, vec, vec::Vec};
+use core::ops::Range;
+
+use aster_console::{AnyConsoleDevice, ConsoleCallback};
+use aster_frame::{mm::VmIo, sync::SpinLock};
+use font8x8::UnicodeFonts;
+
+use crate::{AnyFramebufferDevice, FramebufferInfo, Rect};
+
+/// The width of a character cell, in pixels.
+const CELL_WIDTH: usize = 8;
+/// The height of a character cell, in pixels. Each line of the 8x8 glyphs is drawn twice,
+/// which makes the characters as tall as those of the VGA text mode.
+const CELL_HEIGHT: usize = 16;
+
+const FOREGROUND_COLOR: u32 = 0xaaaaaa;
+const BACKGROUND_COLOR: u32 = 0x000000;
+
+/// A console that renders the text on a framebuffer.
+///
+/// It only supports the output. The escape sequences, e.g., those for the colors, are
+/// ignored.
+pub struct FramebufferConsole {
+    device: Arc<dyn AnyFramebufferDevice>,
+    state: SpinLock<ConsoleState>,
+}
+
+struct ConsoleState {
+    info: FramebufferInfo,
+    cols: usize,
+    rows: usize,
+    /// The position of the cursor.
+    col: usize,
+    row: usize,
+    escape: EscapeState,
+    /// The rows of characters that are changed but not flushed.
+    dirty_rows: Option<Range<usize>>,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum EscapeState {
+    Normal,
+    /// An `ESC` is received.
+    Escape,
+    /// A control sequence, i.e., `ESC [`, is received.
+    Csi,
+}
+
+impl Debug for FramebufferConsole {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        f.debug_struct("FramebufferConsole")
+            .field("device", &self.device)
+            .finish()
+    }
+}
+
+impl AnyConsoleDevice for FramebufferConsole {
+    fn send(&self, buf: &[u8]) {
+        let mut state = self.state.lock_irq_disabled();
+        for &byte in buf {
+            state.put_byte(byte, &*self.device);
+        }
+        state.flush(&*self.device);
+    }
+
+    fn register_callback(&self, _callback: &'static ConsoleCallback) {
+        // The console has no input. The keyboards are handled by the input devices.
+    }
+}
+
+impl FramebufferConsole {
+    /// Creates a console on `device`, which clears the framebuffer.
+    pub fn new(device: Arc<dyn AnyFramebufferDevice>) -> Self {
+        let info = device.info();
+        let mut state = ConsoleState {
+            info,
+            cols: info.width / CELL_WIDTH,
+            rows: info.height / CELL_HEIGHT,
+            col: 0,
+            row: 0,
+            escape: EscapeState::Normal,
+            dirty_rows: None,
+        };
+        for row in 0..state.rows {
+            state.clear_row(row, &*device);
+        }
+        state.flush(&*device);
+
+        Self {
+            device,
+            state: SpinLock::new(state),
+        }
+    }
+}
+
+impl ConsoleState {
+    fn put_byte(&mut self, byte: u8, device: &dyn AnyFramebufferDevice) {
+        match self.escape {
+            EscapeState::Escape => {
+                self.escape = if byte == b'[' {
+                    EscapeState::Csi
+                } else {
+                    EscapeState::Normal
+                };
+                return;
+            }
+            EscapeState::Csi => {
+                // The final byte of a control sequence is in the range of `@` to `~`.
+                if (0x40..=0x7e).contains(&byte) {
+                    self.escape = EscapeState::Normal;
+                }
+                return;
+            }
+            EscapeState::Normal => (),
+        }
+
+        match byte {
+            0x1b => self.escape = EscapeState::Escape,
+            // The kernel messages end with `\n` only, so it also returns the carriage.
+            b'\n' => self.newline(device),
+            b'\r' => self.col = 0,
+            0x08 => self.col = self.col.saturating_sub(1),
+            b'\t' => {
+                for _ in 0..(8 - self.col % 8) {
+                    self.put_char(' ', device);
+                }
+            }
+            0x20..=0x7e => self.put_char(byte as char, device),
+            // The first byte of a non-ASCII UTF-8 character, which has no glyph.
+            0xc0..=0xff => self.put_char('?', device),
+            _ => (),
+        }
+    }
+
+    fn put_char(&mut self, ch: char, device: &dyn AnyFramebufferDevice) {
+        if self.col >= self.cols {
+            self.newline(device);
+        }
+        let glyph = font8x8::BASIC_FONTS
+            .get(ch)
+            .unwrap_or_else(|| font8x8::BASIC_FONTS.get('?').unwrap());
+
+        let format = self.info.format;
+        let bytes_per_pixel = format.bytes_per_pixel();
+        let mut line = [0u8; CELL_WIDTH * 4];
+        let x = self.col * CELL_WIDTH;
+        for (i, bits) in glyph.iter().enumerate() {
+            for (j, pixel) in line.chunks_mut(bytes_per_pixel).enumerate() {
+                let color = if *bits & (1 << j) != 0 {
+                    FOREGROUND_COLOR
+                } else {
+                    BACKGROUND_COLOR
+                };
+                pixel.copy_from_slice(&format.pixel(color)[..bytes_per_pixel]);
+            }
+            let line = &line[..CELL_WIDTH * bytes_per_pixel];
+            for k in 0..2 {
+                let y = self.row * CELL_HEIGHT + i * 2 + k;
+                let offset = y * self.info.line_length + x * bytes_per_pixel;
+                device.frames().write_bytes(offset, line).unwrap();
+            }
+        }
+
+        self.mark_dirty(self.row..self.row + 1);
+        self.col += 1;
+    }
+
+    fn newline(&mut self, device: &dyn AnyFramebufferDevice) {
+        self.col = 0;
+        if self.row + 1 < self.rows {
+            self.row += 1;
+        } else {
+            self.scroll_up(device);
+        }
+    }
+
+    /// Moves the rows up by one row, and clears the last row.
+    fn scroll_up(&mut self, device: &dyn AnyFramebufferDevice) {
+        let row_len = CELL_HEIGHT * self.info.line_length;
+        let mut buf = vec![0u8; row_len];
+        let frames = device.frames();
+        for row in 1..self.rows {
+            frames.read_bytes(row * row_len, &mut buf).unwrap();
+            frames.write_bytes((row - 1) * row_len, &buf).unwrap();
+        }
+        self.clear_row(self.rows - 1, device);
+        self.mark_dirty(0..self.rows);
+    }
+
+    fn clear_row(&mut self, row: usize, device: &dyn AnyFramebufferDevice) {
+        let format = self.info.format;
+        let bytes_per_pixel = format.bytes_per_pixel();
+        let line: Vec<u8> = (0..self.info.width)
+            .flat_map(|_| {
+                format
+                    .pixel(BACKGROUND_COLOR)
+                    .into_iter()
+                    .take(bytes_per_pixel)
+            })
+            .collect();
+        for y in row * CELL_HEIGHT..(row + 1) * CELL_HEIGHT {
+            let offset = y * self.info.line_length;
+            device.frames().write_bytes(offset, &line).unwrap();
+        }
+        self.mark_dirty(row..row + 1);
+    }
+
+    fn mark_dirty(&mut self, rows: Range<usize>) {
+        self.dirty_rows = Some(match self.dirty_rows.take() {
+            Some(dirty) => dirty.start.min(rows.start)..dirty.end.max(rows.end),
+            None => rows,
+        });
+    }
+
+    fn flush(&mut self, device: &dyn AnyFramebufferDevice) {
+        let Some(rows) = self.dirty_rows.take() else {
+            return;
+        };
+        device.flush(Rect {
+            x: 0,
+            y: rows.start * CELL_HEIGHT,
+            width: self.info.width,
+            height: rows.len() * CELL_HEIGHT,
+        });
+    }
+}
@@ -1,210 +1,149 @@
 // SPDX-License-Identifier: MPL-2.0
 
-//! The framebuffer of Asterinas.
+//! The framebuffer devices of Asterinas.
+//!
+//! A framebuffer device shows the pixels in a buffer in the memory on a display. The
+//! pixels are written to the buffer first, and become visible once they are flushed.
+//!
+//! The first registered device also backs a text console, which is registered as a
+//! console device so that the output of the kernel and the TTYs is shown on the display.
 #![no_std]
 #![deny(unsafe_code)]
-#![feature(strict_provenance)]
 
 extern crate alloc;
 
-use alloc::{vec, vec::Vec};
-use core::{
-    fmt,
-    ops::{Index, IndexMut},
-};
-
-use aster_frame::{
-    boot,
-    io_mem::IoMem,
-    mm::{VmIo, PAGE_SIZE},
-    sync::SpinLock,
-};
-use component::{init_component, ComponentInitError};
-use font8x8::UnicodeFonts;
-use spin::Once;
-
-#[init_component]
-fn framebuffer_init() -> Result<(), ComponentInitError> {
-    init();
-    Ok(())
-}
+mod console;
 
-pub(crate) static WRITER: Once<SpinLock<Writer>> = Once::new();
-
-// ignore the warnings since we use the `todo!` macro.
-#[allow(unused_variables)]
-#[allow(unreachable_code)]
-#[allow(clippy::diverging_sub_expression)]
-pub(crate) fn init() {
-    let mut writer = {
-        let framebuffer = boot::framebuffer_arg();
-        let mut size = 0;
-        for i in aster_frame::mm::FRAMEBUFFER_REGIONS.get().unwrap().iter() {
-            size = i.len();
-        }
+use alloc::{collections::BTreeMap, fmt::Debug, string::String, sync::Arc, vec::Vec};
+use core::any::Any;
 
-        let page_size = size / PAGE_SIZE;
+use aster_frame::{mm::FrameVec, sync::SpinLock};
+use component::{init_component, ComponentInitError};
+use spin::Once;
 
-        let start_paddr = framebuffer.address;
-        let io_mem = todo!("IoMem is private for components now, should fix it.");
+pub use self::console::FramebufferConsole;
 
-        let mut buffer: Vec<u8> = vec![0; size];
-        log::debug!("Found framebuffer:{:?}", framebuffer);
+pub trait AnyFramebufferDevice: Send + Sync + Any + Debug {
+    /// Returns the resolution and the pixel layout of the framebuffer.
+    fn info(&self) -> FramebufferInfo;
 
-        Writer {
-            io_mem,
-            x_pos: 0,
-            y_pos: 0,
-            bytes_per_pixel: (framebuffer.bpp / 8) as usize,
-            width: framebuffer.width as usize,
-            height: framebuffer.height as usize,
-            buffer: buffer.leak(),
-        }
-    };
-    writer.clear();
+    /// Returns the frames that hold the pixels, which are contiguous in the framebuffer
+    /// and can be mapped to the user space.
+    fn frames(&self) -> &FrameVec;
 
-    WRITER.call_once(|| SpinLock::new(writer));
+    /// Shows the pixels in `rect` on the display.
+    fn flush(&self, rect: Rect);
 }
 
-pub(crate) struct Writer {
-    io_mem: IoMem,
-    /// FIXME: remove buffer. The meaning of buffer is to facilitate the various operations of framebuffer
-    buffer: &'static mut [u8],
-
-    bytes_per_pixel: usize,
-    width: usize,
-    height: usize,
-
-    x_pos: usize,
-    y_pos: usize,
+/// The resolution and the pixel layout of a framebuffer.
+#[derive(Debug, Clone, Copy)]
+pub struct FramebufferInfo {
+    /// The width of the visible area, in pixels.
+    pub width: usize,
+    /// The height of the visible area, in pixels.
+    pub height: usize,
+    /// The number of bytes between the starts of two adjacent lines.
+    pub line_length: usize,
+    pub format: PixelFormat,
 }
 
-impl Writer {
-    fn newline(&mut self) {
-        self.y_pos += 8;
-        self.carriage_return();
-    }
-
-    fn carriage_return(&mut self) {
-        self.x_pos = 0;
-    }
-
-    /// Erases all text on the screen
-    pub fn clear(&mut self) {
-        self.x_pos = 0;
-        self.y_pos = 0;
-        self.buffer.fill(0);
-        self.io_mem.write_bytes(0, self.buffer).unwrap();
-    }
-
-    /// Everything moves up one letter in size
-    fn shift_lines_up(&mut self) {
-        let offset = self.bytes_per_pixel * 8;
-        self.buffer.copy_within(offset.., 0);
-        self.io_mem.write_bytes(0, self.buffer).unwrap();
-        self.y_pos -= 8;
+impl FramebufferInfo {
+    /// Returns the number of bytes of the pixels.
+    pub fn size(&self) -> usize {
+        self.line_length * self.height
     }
 
-    fn width(&self) -> usize {
-        self.width
+    /// Returns the rectangle that covers the whole visible area.
+    pub fn rect(&self) -> Rect {
+        Rect {
+            x: 0,
+            y: 0,
+            width: self.width,
+            height: self.height,
+        }
     }
+}
 
-    fn height(&self) -> usize {
-        self.height
-    }
+/// The layout of a pixel.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PixelFormat {
+    /// 32 bits per pixel, whose bytes are blue, green, red and unused in order.
+    Xrgb8888,
+}
 
-    fn write_char(&mut self, c: char) {
-        match c {
-            '\n' => self.newline(),
-            '\r' => self.carriage_return(),
-            c => {
-                if self.x_pos >= self.width() {
-                    self.newline();
-                }
-                while self.y_pos >= (self.height() - 8) {
-                    self.shift_lines_up();
-                }
-                let rendered = font8x8::BASIC_FONTS
-                    .get(c)
-                    .expect("character not found in basic font");
-                self.write_rendered_char(rendered);
-            }
+impl PixelFormat {
+    pub fn bytes_per_pixel(&self) -> usize {
+        match self {
+            PixelFormat::Xrgb8888 => 4,
         }
     }
 
-    fn write_rendered_char(&mut self, rendered_char: [u8; 8]) {
-        for (y, byte) in rendered_char.iter().enumerate() {
-            for (x, bit) in (0..8).enumerate() {
-                let on = *byte & (1 << bit) != 0;
-                self.write_pixel(self.x_pos + x, self.y_pos + y, on);
-            }
+    /// Returns the bytes of a pixel with the color `rgb`, e.g., `0xff0000` for red.
+    pub fn pixel(&self, rgb: u32) -> [u8; 4] {
+        match self {
+            PixelFormat::Xrgb8888 => (rgb & 0x00ff_ffff).to_le_bytes(),
         }
-        self.x_pos += 8;
-    }
-
-    fn write_pixel(&mut self, x: usize, y: usize, on: bool) {
-        let pixel_offset = y * self.width + x;
-        let color = if on {
-            [0x33, 0xff, 0x66, 0]
-        } else {
-            [0, 0, 0, 0]
-        };
-        let bytes_per_pixel = self.bytes_per_pixel;
-        let byte_offset = pixel_offset * bytes_per_pixel;
-        self.buffer
-            .index_mut(byte_offset..(byte_offset + bytes_per_pixel))
-            .copy_from_slice(&color[..bytes_per_pixel]);
-        self.io_mem
-            .write_bytes(
-                byte_offset,
-                self.buffer
-                    .index(byte_offset..(byte_offset + bytes_per_pixel)),
-            )
-            .unwrap();
     }
+}
 
-    /// Writes the given ASCII string to the buffer.
-    ///
-    /// Wraps lines at `BUFFER_WIDTH`. Supports the `\n` newline character. Does **not**
-    /// support strings with non-ASCII characters, since they can't be printed in the VGA text
-    /// mode.
-    fn write_string(&mut self, s: &str) {
-        for char in s.chars() {
-            self.write_char(char);
-        }
-    }
+/// A rectangle area of a framebuffer, in pixels.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Rect {
+    pub x: usize,
+    pub y: usize,
+    pub width: usize,
+    pub height: usize,
 }
 
-impl fmt::Write for Writer {
-    fn write_str(&mut self, s: &str) -> fmt::Result {
-        self.write_string(s);
-        Ok(())
+/// The name of the console device that is backed by the first framebuffer device.
+pub const CONSOLE_NAME: &str = "Framebuffer-Console";
+
+pub fn register_device(name: String, device: Arc<dyn AnyFramebufferDevice>) {
+    let mut devices = COMPONENT
+        .get()
+        .unwrap()
+        .framebuffer_device_table
+        .lock_irq_disabled();
+    let is_first = devices.is_empty();
+    devices.insert(name, device.clone());
+    drop(devices);
+
+    if is_first {
+        let console = FramebufferConsole::new(device);
+        aster_console::register_device(CONSOLE_NAME.into(), Arc::new(console));
     }
 }
 
-/// Like the `print!` macro in the standard library, but prints to the VGA text buffer.
-#[macro_export]
-macro_rules! print {
-    ($($arg:tt)*) => ($crate::_print(format_args!($($arg)*)));
+pub fn all_devices() -> Vec<(String, Arc<dyn AnyFramebufferDevice>)> {
+    let devices = COMPONENT
+        .get()
+        .unwrap()
+        .framebuffer_device_table
+        .lock_irq_disabled();
+    devices
+        .iter()
+        .map(|(name, device)| (name.clone(), device.clone()))
+        .collect()
 }
 
-/// Like the `println!` macro in the standard library, but prints to the VGA text buffer.
-#[macro_export]
-macro_rules! println {
-    () => ($crate::print!("\n"));
-    ($($arg:tt)*) => ($crate::print!("{}\n", format_args!($($arg)*)));
+static COMPONENT: Once<Component> = Once::new();
+
+#[init_component]
+fn component_init() -> Result<(), ComponentInitError> {
+    let a = Component::init()?;
+    COMPONENT.call_once(|| a);
+    Ok(())
 }
 
-/// Prints the given formatted string to the VGA text buffer
-/// through the global `WRITER` instance.
-#[doc(hidden)]
-pub fn _print(args: fmt::Arguments) {
-    use core::fmt::Write;
+#[derive(Debug)]
+struct Component {
+    framebuffer_device_table: SpinLock<BTreeMap<String, Arc<dyn AnyFramebufferDevice>>>,
+}
 
-    WRITER
-        .get()
-        .unwrap()
-        .lock_irq_disabled()
-        .write_fmt(args)
-        .unwrap();
+impl Component {
+    pub fn init() -> Result<Self, ComponentInitError> {
+        Ok(Self {
+            framebuffer_device_table: SpinLock::new(BTreeMap::new()),
+        })
+    }
 }
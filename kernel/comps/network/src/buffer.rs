@@ -1,11 +1,13 @@
 // SPDX-License-Identifier: MPL-2.0
 
 use alloc::{collections::LinkedList, sync::Arc};
+use core::mem::size_of;
 
 use align_ext::AlignExt;
 use aster_frame::{
     mm::{
-        Daddr, DmaDirection, DmaStream, FrameAllocOptions, HasDaddr, VmReader, VmWriter, PAGE_SIZE,
+        Daddr, DmaDirection, DmaStream, FrameAllocOptions, HasDaddr, VmReader, VmWriter,
+        HUGE_PAGE_SIZE, PAGE_SIZE,
     },
     sync::SpinLock,
 };
@@ -15,9 +17,18 @@ use spin::Once;
 use crate::dma_pool::{DmaPool, DmaSegment};
 
 pub struct TxBuffer {
-    dma_stream: DmaStream,
+    storage: TxStorage,
     nbytes: usize,
-    pool: &'static SpinLock<LinkedList<DmaStream>>,
+}
+
+enum TxStorage {
+    /// A DMA stream that is put back to a list of streams when the buffer is dropped.
+    Stream {
+        dma_stream: DmaStream,
+        pool: &'static SpinLock<LinkedList<DmaStream>>,
+    },
+    /// A segment of a [`DmaPool`], which is returned to the pool when it is dropped.
+    Segment(DmaSegment),
 }
 
 impl TxBuffer {
@@ -26,8 +37,7 @@ impl TxBuffer {
         packet: &[u8],
         pool: &'static SpinLock<LinkedList<DmaStream>>,
     ) -> Self {
-        let header = header.as_bytes();
-        let nbytes = header.len() + packet.len();
+        let nbytes = size_of::<H>() + packet.len();
 
         let dma_stream = if let Some(stream) = get_tx_stream_from_pool(nbytes, pool) {
             stream
@@ -39,25 +49,64 @@ impl TxBuffer {
             DmaStream::map(segment, DmaDirection::ToDevice, false).unwrap()
         };
 
-        let mut writer = dma_stream.writer().unwrap();
-        writer.write(&mut VmReader::from(header));
-        writer.write(&mut VmReader::from(packet));
+        let storage = TxStorage::Stream { dma_stream, pool };
+        Self::with_storage(storage, header, packet)
+    }
 
+    /// Creates a buffer from a segment of `pool`, e.g., [`TX_BUFFER_POOL`].
+    ///
+    /// # Panics
+    ///
+    /// This method panics if the header and the packet do not fit in a segment of the pool.
+    pub fn new_from_pool<H: Pod>(
+        header: &H,
+        packet: &[u8],
+        pool: &Arc<DmaPool>,
+    ) -> Result<Self, aster_frame::Error> {
+        assert!(size_of::<H>() + packet.len() <= pool.segment_size());
+        let segment = pool.alloc_segment()?;
+        Ok(Self::with_storage(
+            TxStorage::Segment(segment),
+            header,
+            packet,
+        ))
+    }
+
+    fn with_storage<H: Pod>(storage: TxStorage, header: &H, packet: &[u8]) -> Self {
+        let header = header.as_bytes();
         let tx_buffer = Self {
-            dma_stream,
-            nbytes,
-            pool,
+            storage,
+            nbytes: header.len() + packet.len(),
         };
+
+        let mut writer = tx_buffer.writer();
+        writer.write(&mut VmReader::from(header));
+        writer.write(&mut VmReader::from(packet));
         tx_buffer.sync();
         tx_buffer
     }
 
+    /// Overwrites the bytes of the buffer at `offset`, which counts from the header.
+    pub fn write_bytes_at(&self, offset: usize, bytes: &[u8]) {
+        assert!(offset + bytes.len() <= self.nbytes);
+        let mut writer = self.writer().skip(offset);
+        writer.write(&mut VmReader::from(bytes));
+        self.sync();
+    }
+
     pub fn writer(&self) -> VmWriter<'_> {
-        self.dma_stream.writer().unwrap().limit(self.nbytes)
+        let writer = match &self.storage {
+            TxStorage::Stream { dma_stream, .. } => dma_stream.writer(),
+            TxStorage::Segment(segment) => segment.writer(),
+        };
+        writer.unwrap().limit(self.nbytes)
     }
 
     fn sync(&self) {
-        self.dma_stream.sync(0..self.nbytes).unwrap();
+        match &self.storage {
+            TxStorage::Stream { dma_stream, .. } => dma_stream.sync(0..self.nbytes).unwrap(),
+            TxStorage::Segment(segment) => segment.sync(0..self.nbytes).unwrap(),
+        }
     }
 
     pub fn nbytes(&self) -> usize {
@@ -67,15 +116,18 @@ impl TxBuffer {
 
 impl HasDaddr for TxBuffer {
     fn daddr(&self) -> Daddr {
-        self.dma_stream.daddr()
+        match &self.storage {
+            TxStorage::Stream { dma_stream, .. } => dma_stream.daddr(),
+            TxStorage::Segment(segment) => segment.daddr(),
+        }
     }
 }
 
 impl Drop for TxBuffer {
     fn drop(&mut self) {
-        self.pool
-            .lock_irq_disabled()
-            .push_back(self.dma_stream.clone());
+        if let TxStorage::Stream { dma_stream, pool } = &self.storage {
+            pool.lock_irq_disabled().push_back(dma_stream.clone());
+        }
     }
 }
 
@@ -101,7 +153,7 @@ impl RxBuffer {
     }
 
     pub fn set_packet_len(&mut self, packet_len: usize) {
-        assert!(self.header_len + packet_len <= RX_BUFFER_LEN);
+        assert!(self.header_len + packet_len <= self.segment.size());
         self.packet_len = packet_len;
     }
 
@@ -129,6 +181,11 @@ impl RxBuffer {
     pub const fn buf_len(&self) -> usize {
         self.segment.size()
     }
+
+    /// Returns the segment, the length of the header and the length of the packet.
+    pub(crate) fn into_parts(self) -> (DmaSegment, usize, usize) {
+        (self.segment, self.header_len, self.packet_len)
+    }
 }
 
 impl HasDaddr for RxBuffer {
@@ -137,9 +194,18 @@ impl HasDaddr for RxBuffer {
     }
 }
 
-const RX_BUFFER_LEN: usize = 4096;
+/// The size of the packet buffers, which holds an Ethernet frame of the maximum size along
+/// with the header of the device.
+const PACKET_BUFFER_LEN: usize = 2048;
+/// The number of the pinned RX buffers, which fill a huge page.
+const NR_PINNED_RX_BUFFERS: usize = HUGE_PAGE_SIZE / PACKET_BUFFER_LEN;
+const NR_PINNED_TX_BUFFERS: usize = 256;
+
+/// The pool of the RX buffers of the network devices, whose buffers are pinned and
+/// backed by a huge page.
 pub static RX_BUFFER_POOL: Once<Arc<DmaPool>> = Once::new();
-pub static TX_BUFFER_POOL: Once<SpinLock<LinkedList<DmaStream>>> = Once::new();
+/// The pool of the TX buffers of the network devices, whose buffers are pinned.
+pub static TX_BUFFER_POOL: Once<Arc<DmaPool>> = Once::new();
 
 fn get_tx_stream_from_pool(
     nbytes: usize,
@@ -158,16 +224,34 @@ fn get_tx_stream_from_pool(
 }
 
 pub fn init() {
-    const POOL_INIT_SIZE: usize = 32;
-    const POOL_HIGH_WATERMARK: usize = 64;
     RX_BUFFER_POOL.call_once(|| {
-        DmaPool::new(
-            RX_BUFFER_LEN,
-            POOL_INIT_SIZE,
-            POOL_HIGH_WATERMARK,
+        DmaPool::new_pinned(
+            PACKET_BUFFER_LEN,
+            NR_PINNED_RX_BUFFERS,
             DmaDirection::FromDevice,
             false,
+            true,
+        )
+        .or_else(|_| {
+            // Falls back to the base pages if there is no contiguous memory for a huge page.
+            DmaPool::new_pinned(
+                PACKET_BUFFER_LEN,
+                NR_PINNED_RX_BUFFERS,
+                DmaDirection::FromDevice,
+                false,
+                false,
+            )
+        })
+        .unwrap()
+    });
+    TX_BUFFER_POOL.call_once(|| {
+        DmaPool::new_pinned(
+            PACKET_BUFFER_LEN,
+            NR_PINNED_TX_BUFFERS,
+            DmaDirection::ToDevice,
+            false,
+            false,
         )
+        .unwrap()
     });
-    TX_BUFFER_POOL.call_once(|| SpinLock::new(LinkedList::new()));
 }
@@ -10,12 +10,14 @@ use core::ops::Range;
 
 use aster_frame::{
     mm::{
-        Daddr, DmaDirection, DmaStream, FrameAllocOptions, HasDaddr, VmReader, VmWriter, PAGE_SIZE,
+        Daddr, DmaDirection, DmaStream, FrameAllocOptions, HasDaddr, VmReader, VmWriter,
+        HUGE_PAGE_SIZE, PAGE_SIZE,
     },
     sync::{RwLock, SpinLock},
 };
 use bitvec::{array::BitArray, prelude::Lsb0};
 use ktest::ktest;
+use log::warn;
 
 /// `DmaPool` is responsible for allocating small streaming DMA segments
 /// (equal to or smaller than PAGE_SIZE),
@@ -28,6 +30,10 @@ use ktest::ktest;
 ///
 /// Therefore, as a best practice,
 /// it is recommended for the `DmaPool` to have a static lifetime.
+///
+/// A pool can also be created with pinned pages (see [`DmaPool::new_pinned`]),
+/// which are carved from a single physically contiguous region that is mapped for DMA once
+/// and never freed. So the segments are recycled without allocating or mapping any frames.
 #[derive(Debug)]
 pub struct DmaPool {
     segment_size: usize,
@@ -71,7 +77,7 @@ impl DmaPool {
 
             for _ in 0..init_size {
                 let page = Arc::new(
-                    DmaPage::new(
+                    DmaPage::alloc(
                         segment_size,
                         direction,
                         is_cache_coherent,
@@ -94,6 +100,69 @@ impl DmaPool {
         })
     }
 
+    /// Constructs a new `DmaPool` whose first `nr_segments` segments are pinned.
+    ///
+    /// The pinned segments are backed by a physically contiguous region, which is allocated
+    /// and mapped for DMA at once, and is kept until the pool is dropped. If `use_huge_page`
+    /// is true, the region is rounded up to huge pages, so that it is covered by huge pages in
+    /// the linear mapping of the kernel, which reduces the TLB misses when copying packets.
+    ///
+    /// If the pinned segments are used up, the pool allocates more pages like the other pools,
+    /// which are freed once they are unused.
+    pub fn new_pinned(
+        segment_size: usize,
+        nr_segments: usize,
+        direction: DmaDirection,
+        is_cache_coherent: bool,
+        use_huge_page: bool,
+    ) -> Result<Arc<Self>, aster_frame::Error> {
+        assert!(segment_size.is_power_of_two());
+        assert!(segment_size >= 64);
+        assert!(segment_size <= PAGE_SIZE);
+
+        let region_size = {
+            let size = nr_segments * segment_size;
+            let align = if use_huge_page {
+                HUGE_PAGE_SIZE
+            } else {
+                PAGE_SIZE
+            };
+            size.div_ceil(align) * align
+        };
+        let storage = {
+            let vm_segment = FrameAllocOptions::new(region_size / PAGE_SIZE).alloc_contiguous()?;
+            if use_huge_page && vm_segment.start_paddr() % HUGE_PAGE_SIZE != 0 {
+                warn!("the pinned DMA region is not aligned to huge pages");
+            }
+            DmaStream::map(vm_segment, direction, is_cache_coherent)
+                .map_err(|_| aster_frame::Error::AccessDenied)?
+        };
+        let nr_pages = region_size / PAGE_SIZE;
+
+        Ok(Arc::new_cyclic(|pool| {
+            let pages: VecDeque<_> = (0..nr_pages)
+                .map(|index| {
+                    Arc::new(DmaPage::new_pinned(
+                        storage.clone(),
+                        index * PAGE_SIZE,
+                        segment_size,
+                        Weak::clone(pool),
+                    ))
+                })
+                .collect();
+
+            Self {
+                segment_size,
+                direction,
+                is_cache_coherent,
+                // The pages allocated beyond the pinned ones are freed once they are unused.
+                high_watermark: nr_pages,
+                avail_pages: SpinLock::new(pages.clone()),
+                all_pages: SpinLock::new(pages),
+            }
+        }))
+    }
+
     /// Allocates a `DmaSegment` from the pool
     pub fn alloc_segment(self: &Arc<Self>) -> Result<DmaSegment, aster_frame::Error> {
         // Lock order: pool.avail_pages -> pool.all_pages
@@ -103,7 +172,7 @@ impl DmaPool {
             /// Allocate a new page
             let new_page = {
                 let pool = Arc::downgrade(self);
-                Arc::new(DmaPage::new(
+                Arc::new(DmaPage::alloc(
                     self.segment_size,
                     self.direction,
                     self.is_cache_coherent,
@@ -137,6 +206,10 @@ impl DmaPool {
 #[derive(Debug)]
 struct DmaPage {
     storage: DmaStream,
+    /// The offset of the page in `storage`, which is non-zero only for the pinned pages.
+    offset: usize,
+    /// Whether the page is pinned, i.e., it is never freed until the pool is dropped.
+    is_pinned: bool,
     segment_size: usize,
     // `BitArray` is 64 bits, since each `DmaSegment` is bigger than 64 bytes,
     // there's no more than `PAGE_SIZE` / 64 = 64 `DmaSegment`s in a `DmaPage`.
@@ -145,7 +218,7 @@ struct DmaPage {
 }
 
 impl DmaPage {
-    fn new(
+    fn alloc(
         segment_size: usize,
         direction: DmaDirection,
         is_cache_coherent: bool,
@@ -160,12 +233,30 @@ impl DmaPage {
 
         Ok(Self {
             storage: dma_stream,
+            offset: 0,
+            is_pinned: false,
             segment_size,
             allocated_segments: SpinLock::new(BitArray::ZERO),
             pool,
         })
     }
 
+    fn new_pinned(
+        storage: DmaStream,
+        offset: usize,
+        segment_size: usize,
+        pool: Weak<DmaPool>,
+    ) -> Self {
+        Self {
+            storage,
+            offset,
+            is_pinned: true,
+            segment_size,
+            allocated_segments: SpinLock::new(BitArray::ZERO),
+            pool,
+        }
+    }
+
     fn alloc_segment(self: &Arc<Self>) -> Option<DmaSegment> {
         let mut segments = self.allocated_segments.lock_irq_disabled();
         let free_segment_index = get_next_free_index(&segments, self.nr_blocks_per_page())?;
@@ -174,7 +265,7 @@ impl DmaPage {
         let segment = DmaSegment {
             size: self.segment_size,
             dma_stream: self.storage.clone(),
-            start_addr: self.storage.daddr() + free_segment_index * self.segment_size,
+            start_addr: self.daddr() + free_segment_index * self.segment_size,
             page: Arc::downgrade(self),
         };
 
@@ -207,7 +298,7 @@ fn get_next_free_index(segments: &BitArray, nr_blocks_per_page: usize) -> Option
 
 impl HasDaddr for DmaPage {
     fn daddr(&self) -> Daddr {
-        self.storage.daddr()
+        self.storage.daddr() + self.offset
     }
 }
 
@@ -272,7 +363,7 @@ impl Drop for DmaSegment {
 
         let became_free = allocated_segments.not_any();
 
-        if became_free && all_pages.len() > pool.high_watermark {
+        if became_free && !page.is_pinned && all_pages.len() > pool.high_watermark {
             avail_pages.retain(|page_| !Arc::ptr_eq(page_, &page));
             all_pages.retain(|page_| !Arc::ptr_eq(page_, &page));
             return;
@@ -371,4 +462,34 @@ mod test {
         reader.read(&mut VmWriter::from(&mut read_buf as &mut [u8]));
         assert_eq!(&read_buf, data);
     }
+
+    #[ktest]
+    fn pinned_pool_segments() {
+        const SEGMENT_SIZE: usize = PAGE_SIZE / 2;
+        let pool: Arc<DmaPool> =
+            DmaPool::new_pinned(SEGMENT_SIZE, 8, DmaDirection::Bidirectional, false, false)
+                .unwrap();
+        assert_eq!(pool.num_pages(), 4);
+
+        let segments: Vec<_> = (0..8).map(|_| pool.alloc_segment().unwrap()).collect();
+        // The pinned segments are physically contiguous.
+        for (index, segment) in segments.iter().enumerate() {
+            assert_eq!(segment.daddr(), segments[0].daddr() + index * SEGMENT_SIZE);
+        }
+        assert_eq!(pool.num_pages(), 4);
+
+        // The pool grows beyond the pinned pages, and shrinks back when they are unused.
+        let extra_segments: Vec<_> = (0..4).map(|_| pool.alloc_segment().unwrap()).collect();
+        assert_eq!(pool.num_pages(), 6);
+        drop(extra_segments);
+        drop(segments);
+        assert_eq!(pool.num_pages(), 4);
+    }
+
+    #[ktest]
+    fn pinned_pool_huge_page() {
+        let pool: Arc<DmaPool> =
+            DmaPool::new_pinned(PAGE_SIZE, 1, DmaDirection::ToDevice, false, true).unwrap();
+        assert_eq!(pool.num_pages(), HUGE_PAGE_SIZE / PAGE_SIZE);
+    }
 }
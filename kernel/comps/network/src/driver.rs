@@ -2,10 +2,9 @@
 
 use alloc::vec;
 
-use aster_frame::mm::VmWriter;
 use smoltcp::{phy, time::Instant};
 
-use crate::{buffer::RxBuffer, AnyNetworkDevice};
+use crate::{net_buf::NetBuf, AnyNetworkDevice};
 
 impl phy::Device for dyn AnyNetworkDevice {
     type RxToken<'a> = RxToken;
@@ -14,7 +13,7 @@ impl phy::Device for dyn AnyNetworkDevice {
     fn receive(&mut self, _timestamp: Instant) -> Option<(Self::RxToken<'_>, Self::TxToken<'_>)> {
         if self.can_receive() {
             let rx_buffer = self.receive().unwrap();
-            Some((RxToken(rx_buffer), TxToken(self)))
+            Some((RxToken(NetBuf::from(rx_buffer)), TxToken(self)))
         } else {
             None
         }
@@ -32,17 +31,14 @@ impl phy::Device for dyn AnyNetworkDevice {
         self.capabilities()
     }
 }
-pub struct RxToken(RxBuffer);
+pub struct RxToken(NetBuf);
 
 impl phy::RxToken for RxToken {
     fn consume<R, F>(self, f: F) -> R
     where
         F: FnOnce(&mut [u8]) -> R,
     {
-        let mut packet = self.0.packet();
-        let mut buffer = vec![0u8; packet.remain()];
-        packet.read(&mut VmWriter::from(&mut buffer as &mut [u8]));
-        f(&mut buffer)
+        self.0.consume(f)
     }
 }
 
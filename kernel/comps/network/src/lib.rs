@@ -9,6 +9,7 @@
 mod buffer;
 pub mod dma_pool;
 mod driver;
+mod net_buf;
 
 extern crate alloc;
 
@@ -20,6 +21,7 @@ use aster_util::safe_ptr::Pod;
 pub use buffer::{RxBuffer, TxBuffer, RX_BUFFER_POOL, TX_BUFFER_POOL};
 use component::{init_component, ComponentInitError};
 pub use dma_pool::DmaSegment;
+pub use net_buf::NetBuf;
 use smoltcp::phy;
 use spin::Once;
 
@@ -49,6 +51,32 @@ pub trait AnyNetworkDevice: Send + Sync + Any + Debug {
     fn receive(&mut self) -> Result<RxBuffer, VirtioNetError>;
     /// Send a packet to network. Return until the request completes.
     fn send(&mut self, packet: &[u8]) -> Result<(), VirtioNetError>;
+
+    // ================Multi-queue Operation==============
+
+    /// The number of the queue pairs, each of which has a receive queue and a transmit queue.
+    ///
+    /// The methods without a queue index use any of the receive queues, or the transmit
+    /// queue chosen by the device.
+    fn num_queue_pairs(&self) -> usize {
+        1
+    }
+    /// Whether the receive queue of the pair has a packet.
+    fn can_receive_from(&self, _queue: usize) -> bool {
+        self.can_receive()
+    }
+    /// Receive a packet from the receive queue of the pair.
+    fn receive_from(&mut self, _queue: usize) -> Result<RxBuffer, VirtioNetError> {
+        self.receive()
+    }
+    /// Whether the transmit queue of the pair can send a packet.
+    fn can_send_to(&self, _queue: usize) -> bool {
+        self.can_send()
+    }
+    /// Send a packet through the transmit queue of the pair.
+    fn send_to(&mut self, _queue: usize, packet: &[u8]) -> Result<(), VirtioNetError> {
+        self.send(packet)
+    }
 }
 
 pub trait NetDeviceIrqHandler = Fn() + Send + Sync + 'static;
@@ -0,0 +1,195 @@
+// SPDX-License-Identifier: MPL-2.0
+
+//! Reference-counted packet buffers passed between the network layers.
+//!
+//! A [`NetBuf`] holds a received packet in the DMA segment that the device has written
+//! it to, so the packet is passed from the driver to the iface, e.g., through the
+//! backlogs of the receive packet steering, without being copied. The segment also has
+//! some space before the data (the headroom), e.g., for the header of the device, and
+//! after the data (the tailroom):
+//!
+//! ```plain
+//!  |<- headroom ->|<------- data ------->|<- tailroom ->|
+//!  +--------------+----------------------+--------------+
+//!  |              | header |   payload   |              |
+//!  +--------------+----------------------+--------------+
+//!                 ^                      ^
+//!               start                   end
+//! ```
+//!
+//! The upper layers strip the headers by moving the start, and cloning a buffer only
+//! increments the reference count of the segment, which is returned to its pool once
+//! all the clones are dropped.
+//!
+//! The DMA memory cannot be borrowed as a slice, so the packet is copied once when it is
+//! handed to smoltcp (see [`NetBuf::consume`]).
+
+use alloc::{sync::Arc, vec};
+use core::ops::Range;
+
+use aster_frame::mm::{VmReader, VmWriter};
+
+use crate::{buffer::RxBuffer, dma_pool::DmaSegment};
+
+/// A reference-counted packet buffer in a DMA segment.
+#[derive(Debug, Clone)]
+pub struct NetBuf {
+    segment: Arc<DmaSegment>,
+    start: usize,
+    end: usize,
+}
+
+impl NetBuf {
+    /// Creates a buffer whose data are the bytes of `segment` in `data_range`.
+    ///
+    /// The data are synchronized from the device first.
+    ///
+    /// # Panics
+    ///
+    /// This method panics if the range is out of the segment.
+    pub fn new(segment: DmaSegment, data_range: Range<usize>) -> Self {
+        assert!(data_range.start <= data_range.end && data_range.end <= segment.size());
+        segment.sync(data_range.clone()).unwrap();
+        Self {
+            segment: Arc::new(segment),
+            start: data_range.start,
+            end: data_range.end,
+        }
+    }
+
+    /// Returns the length of the data.
+    pub fn len(&self) -> usize {
+        self.end - self.start
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.start == self.end
+    }
+
+    /// Returns the number of bytes before the data.
+    pub fn headroom(&self) -> usize {
+        self.start
+    }
+
+    /// Returns the number of bytes after the data.
+    pub fn tailroom(&self) -> usize {
+        self.segment.size() - self.end
+    }
+
+    /// Returns whether the segment is shared with other buffers.
+    pub fn is_shared(&self) -> bool {
+        Arc::strong_count(&self.segment) > 1
+    }
+
+    /// Returns a reader of the data.
+    pub fn reader(&self) -> VmReader<'_> {
+        self.segment
+            .reader()
+            .unwrap()
+            .skip(self.start)
+            .limit(self.len())
+    }
+
+    /// Copies the data from the beginning to `buf`, e.g., to parse the headers.
+    ///
+    /// Returns the number of the copied bytes.
+    pub fn copy_prefix(&self, buf: &mut [u8]) -> usize {
+        self.reader().read(&mut VmWriter::from(buf))
+    }
+
+    /// Removes `len` bytes from the front of the data, e.g., to strip a header.
+    ///
+    /// # Panics
+    ///
+    /// This method panics if the data is shorter than `len` bytes.
+    pub fn pull(&mut self, len: usize) {
+        assert!(len <= self.len(), "not enough data");
+        self.start += len;
+    }
+
+    /// Shortens the data to `len` bytes, e.g., to remove the padding.
+    ///
+    /// This method has no effect if the data is not longer than `len` bytes.
+    pub fn trim(&mut self, len: usize) {
+        self.end = self.end.min(self.start + len);
+    }
+
+    /// Copies the data to a contiguous buffer and calls `f` with it.
+    pub fn consume<R>(&self, f: impl FnOnce(&mut [u8]) -> R) -> R {
+        let mut buffer = vec![0u8; self.len()];
+        self.reader()
+            .read(&mut VmWriter::from(&mut buffer as &mut [u8]));
+        f(&mut buffer)
+    }
+}
+
+impl From<RxBuffer> for NetBuf {
+    /// Takes over the segment of the buffer, whose header is in the headroom.
+    fn from(rx_buffer: RxBuffer) -> Self {
+        let (segment, header_len, packet_len) = rx_buffer.into_parts();
+        Self::new(segment, header_len..header_len + packet_len)
+    }
+}
+
+#[cfg(ktest)]
+mod test {
+    use aster_frame::mm::DmaDirection;
+    use ktest::ktest;
+
+    use super::*;
+    use crate::dma_pool::DmaPool;
+
+    fn net_buf_of(pool: &Arc<DmaPool>, headroom: usize, packet: &[u8]) -> NetBuf {
+        let segment = pool.alloc_segment().unwrap();
+        let mut writer = segment.writer().unwrap().skip(headroom);
+        writer.write(&mut VmReader::from(packet));
+        NetBuf::new(segment, headroom..headroom + packet.len())
+    }
+
+    fn data_of(buf: &NetBuf) -> alloc::vec::Vec<u8> {
+        buf.consume(|data| data.to_vec())
+    }
+
+    #[ktest]
+    fn pull_headers() {
+        let pool = DmaPool::new(64, 1, 1, DmaDirection::Bidirectional, false);
+        let mut buf = net_buf_of(&pool, 8, b"hdr0hdr1payload");
+        assert_eq!(buf.headroom(), 8);
+        assert_eq!(buf.tailroom(), 64 - 8 - 15);
+
+        let mut header = [0u8; 4];
+        assert_eq!(buf.copy_prefix(&mut header), 4);
+        assert_eq!(&header, b"hdr0");
+        buf.pull(4);
+        buf.pull(4);
+        assert_eq!(buf.headroom(), 16);
+        assert_eq!(data_of(&buf), b"payload");
+
+        buf.trim(3);
+        assert_eq!(data_of(&buf), b"pay");
+        buf.trim(8);
+        assert_eq!(buf.len(), 3);
+    }
+
+    #[ktest]
+    fn clones_share_segment() {
+        let pool = DmaPool::new(64, 1, 1, DmaDirection::Bidirectional, false);
+        let mut buf = net_buf_of(&pool, 0, b"data");
+        let cloned = buf.clone();
+        assert!(buf.is_shared() && cloned.is_shared());
+
+        buf.pull(1);
+        assert_eq!(data_of(&buf), b"ata");
+        assert_eq!(data_of(&cloned), b"data");
+        drop(cloned);
+        assert!(!buf.is_shared());
+    }
+
+    #[ktest]
+    #[should_panic]
+    fn pull_beyond_data() {
+        let pool = DmaPool::new(64, 1, 1, DmaDirection::Bidirectional, false);
+        let mut buf = net_buf_of(&pool, 2, b"ab");
+        buf.pull(3);
+    }
+}
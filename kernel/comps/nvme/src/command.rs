@@ -0,0 +1,176 @@
+// SPDX-License-Identifier: MPL-2.0
+
+//! The commands and the completions of the NVMe queues.
+
+use core::mem::size_of;
+
+use pod::Pod;
+use static_assertions::const_assert_eq;
+
+use crate::NvmeError;
+
+/// An entry of a submission queue.
+#[repr(C)]
+#[derive(Debug, Default, Clone, Copy, Pod)]
+pub(crate) struct NvmeCommand {
+    pub opcode: u8,
+    pub flags: u8,
+    pub cid: u16,
+    pub nsid: u32,
+    pub cdw2: u32,
+    pub cdw3: u32,
+    pub mptr: u64,
+    pub prp1: u64,
+    pub prp2: u64,
+    pub cdw10: u32,
+    pub cdw11: u32,
+    pub cdw12: u32,
+    pub cdw13: u32,
+    pub cdw14: u32,
+    pub cdw15: u32,
+}
+
+const_assert_eq!(size_of::<NvmeCommand>(), 64);
+
+/// An entry of a completion queue.
+#[repr(C)]
+#[derive(Debug, Default, Clone, Copy, Pod)]
+pub(crate) struct NvmeCompletion {
+    /// The command-specific result.
+    pub result: u32,
+    pub reserved: u32,
+    pub sq_head: u16,
+    pub sq_id: u16,
+    pub cid: u16,
+    /// The phase tag in bit 0, and the status field in bits 1-15.
+    pub status: u16,
+}
+
+const_assert_eq!(size_of::<NvmeCompletion>(), 16);
+
+impl NvmeCompletion {
+    pub(crate) fn phase(&self) -> bool {
+        self.status & 1 == 1
+    }
+
+    /// Returns the error if the command fails.
+    pub(crate) fn check(&self) -> Result<(), NvmeError> {
+        let sc = ((self.status >> 1) & 0xFF) as u8;
+        let sct = ((self.status >> 9) & 0x7) as u8;
+        if sc == 0 && sct == 0 {
+            Ok(())
+        } else {
+            Err(NvmeError::CommandFailed { sct, sc })
+        }
+    }
+}
+
+/// The opcodes of the admin commands.
+#[derive(Debug, Clone, Copy)]
+#[repr(u8)]
+enum AdminOpcode {
+    CreateIoSq = 0x01,
+    CreateIoCq = 0x05,
+    Identify = 0x06,
+    SetFeatures = 0x09,
+}
+
+/// The opcodes of the NVM I/O commands.
+#[derive(Debug, Clone, Copy)]
+#[repr(u8)]
+pub(crate) enum IoOpcode {
+    Flush = 0x00,
+    Write = 0x01,
+    Read = 0x02,
+}
+
+/// The data structures returned by the Identify command.
+#[derive(Debug, Clone, Copy)]
+#[repr(u32)]
+pub(crate) enum IdentifyCns {
+    Namespace = 0x00,
+    Controller = 0x01,
+    ActiveNamespaceList = 0x02,
+}
+
+/// The feature identifier of the number of queues.
+const FEATURE_NUMBER_OF_QUEUES: u32 = 0x07;
+
+impl NvmeCommand {
+    pub(crate) fn identify(cns: IdentifyCns, nsid: u32, buffer_daddr: u64) -> Self {
+        Self {
+            opcode: AdminOpcode::Identify as u8,
+            nsid,
+            prp1: buffer_daddr,
+            cdw10: cns as u32,
+            ..Default::default()
+        }
+    }
+
+    /// Requests the numbers of the I/O submission and completion queues.
+    pub(crate) fn set_number_of_queues(nr_queues: u16) -> Self {
+        let nr = (nr_queues - 1) as u32;
+        Self {
+            opcode: AdminOpcode::SetFeatures as u8,
+            cdw10: FEATURE_NUMBER_OF_QUEUES,
+            cdw11: (nr << 16) | nr,
+            ..Default::default()
+        }
+    }
+
+    /// Creates a physically contiguous completion queue, which raises the interrupts with
+    /// the MSI-X vector.
+    pub(crate) fn create_io_cq(qid: u16, depth: u16, daddr: u64, vector: u16) -> Self {
+        const PHYSICALLY_CONTIGUOUS: u32 = 1 << 0;
+        const INTERRUPTS_ENABLED: u32 = 1 << 1;
+        Self {
+            opcode: AdminOpcode::CreateIoCq as u8,
+            prp1: daddr,
+            cdw10: ((depth as u32 - 1) << 16) | qid as u32,
+            cdw11: ((vector as u32) << 16) | INTERRUPTS_ENABLED | PHYSICALLY_CONTIGUOUS,
+            ..Default::default()
+        }
+    }
+
+    /// Creates a physically contiguous submission queue, whose completions are posted to
+    /// the completion queue with the same ID.
+    pub(crate) fn create_io_sq(qid: u16, depth: u16, daddr: u64) -> Self {
+        const PHYSICALLY_CONTIGUOUS: u32 = 1 << 0;
+        Self {
+            opcode: AdminOpcode::CreateIoSq as u8,
+            prp1: daddr,
+            cdw10: ((depth as u32 - 1) << 16) | qid as u32,
+            cdw11: ((qid as u32) << 16) | PHYSICALLY_CONTIGUOUS,
+            ..Default::default()
+        }
+    }
+
+    /// Reads or writes `nr_blocks` logical blocks starting from `lba`.
+    pub(crate) fn read_write(
+        opcode: IoOpcode,
+        nsid: u32,
+        lba: u64,
+        nr_blocks: u16,
+        prp1: u64,
+        prp2: u64,
+    ) -> Self {
+        Self {
+            opcode: opcode as u8,
+            nsid,
+            prp1,
+            prp2,
+            cdw10: lba as u32,
+            cdw11: (lba >> 32) as u32,
+            cdw12: (nr_blocks - 1) as u32,
+            ..Default::default()
+        }
+    }
+
+    pub(crate) fn flush(nsid: u32) -> Self {
+        Self {
+            opcode: IoOpcode::Flush as u8,
+            nsid,
+            ..Default::default()
+        }
+    }
+}
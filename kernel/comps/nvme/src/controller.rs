@@ -0,0 +1,348 @@
+// SPDX-License-Identifier: MPL-2.0
+
+#![allow(dead_code)]
+
+use alloc::{format, sync::Arc, vec::Vec};
+use core::time::Duration;
+
+use aster_frame::{
+    arch::timer::Jiffies,
+    bus::pci::{
+        capability::{msi::CapabilityMsiData, msix::CapabilityMsixData, CapabilityData},
+        cfg_space::Bar,
+        common_device::PciCommonDevice,
+    },
+    cpu::{num_cpus, CpuSet},
+    io_mem::IoMem,
+    mm::{DmaDevice, DmaDirection, DmaStream, FrameAllocOptions, HasDaddr, VmIo, PAGE_SIZE},
+    sync::SpinLock,
+    trap::IrqLine,
+};
+use log::{info, warn};
+
+use crate::{
+    command::{IdentifyCns, NvmeCommand},
+    namespace::{IoQueue, NvmeNamespace},
+    prp::MAX_TRANSFER_PAGES,
+    queue::NvmeQueue,
+    regs::{cc, csts, Capability, NvmeReg},
+    NvmeError,
+};
+
+/// An NVMe controller.
+#[derive(Debug)]
+pub(crate) struct NvmeController {
+    id: usize,
+    registers: IoMem,
+    admin_queue: SpinLock<NvmeQueue>,
+    io_queues: Vec<Arc<IoQueue>>,
+    namespaces: Vec<Arc<NvmeNamespace>>,
+    /// The interrupt capability, which owns the IRQ lines of the I/O queues.
+    interrupt: SpinLock<InterruptCapability>,
+    pci_device: PciCommonDevice,
+}
+
+impl NvmeController {
+    /// The depth of the admin queue.
+    const ADMIN_QUEUE_DEPTH: u16 = 32;
+    /// The time to wait for an admin command.
+    const ADMIN_TIMEOUT: Duration = Duration::from_secs(5);
+
+    /// Resets and initializes the controller, and discovers its namespaces.
+    pub(crate) fn init(id: usize, pci_device: PciCommonDevice) -> Result<Arc<Self>, NvmeError> {
+        let Some(Bar::Memory(bar)) = pci_device.bar_manager().bar_shared_with_msix(0) else {
+            return Err(NvmeError::NoResource);
+        };
+        let registers = bar.io_mem().clone();
+        let cap = Capability(registers.read_val(NvmeReg::Cap as usize).unwrap());
+        if !cap.supports_nvm_command_set() || cap.min_page_size() > PAGE_SIZE {
+            return Err(NvmeError::NoResource);
+        }
+        let version: u32 = registers.read_val(NvmeReg::Vs as usize).unwrap();
+        info!(
+            "[NVMe]: Found controller {} of version {}.{}",
+            id,
+            version >> 16,
+            (version >> 8) & 0xFF
+        );
+
+        let mut admin_queue = Self::enable(&registers, cap)?;
+        let max_transfer_pages = Self::identify_controller(&mut admin_queue, cap)?;
+
+        let mut interrupt = InterruptCapability::find(&pci_device)?;
+        let io_queues = Self::create_io_queues(&mut admin_queue, &registers, cap, &mut interrupt)?;
+        // The buffers of the bios are mapped for the controller only.
+        let dma_device = DmaDevice::new(*pci_device.location(), 64);
+        let namespaces = Self::discover_namespaces(
+            &mut admin_queue,
+            id,
+            &io_queues,
+            max_transfer_pages,
+            dma_device,
+        )?;
+
+        Ok(Arc::new(Self {
+            id,
+            registers,
+            admin_queue: SpinLock::new(admin_queue),
+            io_queues,
+            namespaces,
+            interrupt: SpinLock::new(interrupt),
+            pci_device,
+        }))
+    }
+
+    pub(crate) fn namespaces(&self) -> &[Arc<NvmeNamespace>] {
+        &self.namespaces
+    }
+
+    /// Resets the controller, sets up the admin queue, and enables the controller.
+    fn enable(registers: &IoMem, cap: Capability) -> Result<NvmeQueue, NvmeError> {
+        let timeout = Duration::from_millis(cap.timeout_ms());
+
+        let config: u32 = registers.read_val(NvmeReg::Cc as usize).unwrap();
+        if config & cc::ENABLE != 0 {
+            registers
+                .write_val(NvmeReg::Cc as usize, &(config & !cc::ENABLE))
+                .unwrap();
+        }
+        wait_ready(registers, false, timeout)?;
+
+        let depth = Self::ADMIN_QUEUE_DEPTH;
+        let admin_queue = NvmeQueue::new(0, depth, registers, cap.doorbell_stride())?;
+        let attributes = ((depth as u32 - 1) << 16) | (depth as u32 - 1);
+        registers
+            .write_val(NvmeReg::Aqa as usize, &attributes)
+            .unwrap();
+        registers
+            .write_val(NvmeReg::Asq as usize, &admin_queue.sq_daddr())
+            .unwrap();
+        registers
+            .write_val(NvmeReg::Acq as usize, &admin_queue.cq_daddr())
+            .unwrap();
+
+        let config = cc::ENABLE | cc::MPS_4K | cc::IOSQES_64 | cc::IOCQES_16;
+        registers.write_val(NvmeReg::Cc as usize, &config).unwrap();
+        wait_ready(registers, true, timeout)?;
+        Ok(admin_queue)
+    }
+
+    /// Identifies the controller, and returns the maximum number of the pages of a
+    /// transfer.
+    fn identify_controller(
+        admin_queue: &mut NvmeQueue,
+        cap: Capability,
+    ) -> Result<usize, NvmeError> {
+        /// The offset of the Maximum Data Transfer Size in the Identify Controller data.
+        const MDTS_OFFSET: usize = 77;
+
+        let buffer = identify(admin_queue, IdentifyCns::Controller, 0)?;
+        let mdts: u8 = buffer.read_val(MDTS_OFFSET).unwrap();
+        // The size is in the units of the minimum memory page size, and zero means no limit.
+        let max_transfer_pages = if mdts == 0 {
+            MAX_TRANSFER_PAGES
+        } else {
+            ((1usize << mdts) * cap.min_page_size() / PAGE_SIZE).min(MAX_TRANSFER_PAGES)
+        };
+        Ok(max_transfer_pages)
+    }
+
+    /// Creates an I/O queue pair for each CPU, as long as the controller and its MSI-X
+    /// vectors allow.
+    ///
+    /// With MSI-X, the I/O queue with ID `n` raises the interrupts with the MSI-X vector
+    /// `n`, which is handled by the CPU `n - 1`. With MSI, all the I/O queues share the
+    /// only vector.
+    fn create_io_queues(
+        admin_queue: &mut NvmeQueue,
+        registers: &IoMem,
+        cap: Capability,
+        interrupt: &mut InterruptCapability,
+    ) -> Result<Vec<Arc<IoQueue>>, NvmeError> {
+        let nr_wanted = match interrupt {
+            InterruptCapability::Msix(msix) => {
+                // The MSI-X vector 0 is reserved for the admin queue, which is polled.
+                let nr_vectors = msix.table_size();
+                if nr_vectors < 2 {
+                    return Err(NvmeError::NoResource);
+                }
+                (num_cpus() as u16).min(nr_vectors - 1)
+            }
+            InterruptCapability::Msi(_) => num_cpus() as u16,
+        };
+        let completion = admin_queue.submit_and_wait(
+            NvmeCommand::set_number_of_queues(nr_wanted),
+            Self::ADMIN_TIMEOUT,
+        )?;
+        // The numbers of the allocated submission and completion queues are zero-based.
+        let nr_allocated = (completion.result & 0xFFFF).min(completion.result >> 16) as u16 + 1;
+        let nr_queues = nr_wanted.min(nr_allocated);
+
+        let depth = cap.max_queue_entries().min(NvmeQueue::MAX_DEPTH as usize) as u16;
+        let mut io_queues = Vec::with_capacity(nr_queues as usize);
+        for qid in 1..=nr_queues {
+            let queue = NvmeQueue::new(qid, depth, registers, cap.doorbell_stride())?;
+            admin_queue.submit_and_wait(
+                NvmeCommand::create_io_cq(qid, depth, queue.cq_daddr(), interrupt.vector(qid)),
+                Self::ADMIN_TIMEOUT,
+            )?;
+            admin_queue.submit_and_wait(
+                NvmeCommand::create_io_sq(qid, depth, queue.sq_daddr()),
+                Self::ADMIN_TIMEOUT,
+            )?;
+            let io_queue = Arc::new(IoQueue::new(queue));
+            io_queues.push(io_queue.clone());
+
+            let InterruptCapability::Msix(msix) = &mut *interrupt else {
+                continue;
+            };
+            let irq = IrqLine::alloc().map_err(|_| NvmeError::NoResource)?;
+            msix.set_interrupt_vector(irq, qid);
+            let irq = msix.irq_mut(qid as usize).unwrap();
+            let cloned_io_queue = io_queue.clone();
+            irq.on_active(move |_| cloned_io_queue.handle_irq());
+            let mut cpus = CpuSet::new_empty();
+            cpus.add(qid as u32 - 1);
+            if irq.set_affinity(&cpus).is_err() {
+                warn!(
+                    "[NVMe]: Failed to set the affinity of the I/O queue {}",
+                    qid
+                );
+            }
+        }
+        if let InterruptCapability::Msi(msi) = interrupt {
+            let irq = IrqLine::alloc().map_err(|_| NvmeError::NoResource)?;
+            msi.set_interrupt_vector(irq);
+            let cloned_io_queues = io_queues.clone();
+            msi.irq_mut().unwrap().on_active(move |_| {
+                for io_queue in cloned_io_queues.iter() {
+                    io_queue.handle_irq();
+                }
+            });
+        }
+        info!(
+            "[NVMe]: Created {} I/O queues of depth {}",
+            nr_queues, depth
+        );
+        Ok(io_queues)
+    }
+
+    /// Discovers the active namespaces of the controller.
+    fn discover_namespaces(
+        admin_queue: &mut NvmeQueue,
+        controller_id: usize,
+        io_queues: &[Arc<IoQueue>],
+        max_transfer_pages: usize,
+        dma_device: DmaDevice,
+    ) -> Result<Vec<Arc<NvmeNamespace>>, NvmeError> {
+        /// The offset of the Namespace Size in the Identify Namespace data.
+        const NSZE_OFFSET: usize = 0;
+        /// The offset of the Formatted LBA Size in the Identify Namespace data.
+        const FLBAS_OFFSET: usize = 26;
+        /// The offset of the LBA Format descriptors in the Identify Namespace data.
+        const LBAF_OFFSET: usize = 128;
+
+        let id_list = identify(admin_queue, IdentifyCns::ActiveNamespaceList, 0)?;
+        let mut namespaces = Vec::new();
+        for i in 0..PAGE_SIZE / 4 {
+            let nsid: u32 = id_list.read_val(i * 4).unwrap();
+            if nsid == 0 {
+                break;
+            }
+
+            let data = identify(admin_queue, IdentifyCns::Namespace, nsid)?;
+            let nr_blocks: u64 = data.read_val(NSZE_OFFSET).unwrap();
+            let flbas: u8 = data.read_val(FLBAS_OFFSET).unwrap();
+            let lba_format: u32 = data
+                .read_val(LBAF_OFFSET + 4 * (flbas & 0xF) as usize)
+                .unwrap();
+            let block_size = 1usize << ((lba_format >> 16) & 0xFF);
+            if nr_blocks == 0 || !(512..=PAGE_SIZE).contains(&block_size) {
+                warn!(
+                    "[NVMe]: Unsupported namespace {} with {} blocks of {} bytes",
+                    nsid, nr_blocks, block_size
+                );
+                continue;
+            }
+
+            namespaces.push(Arc::new(NvmeNamespace::new(
+                format!("nvme{}n{}", controller_id, nsid),
+                nsid,
+                nr_blocks,
+                block_size,
+                io_queues.to_vec(),
+                max_transfer_pages,
+                dma_device,
+            )));
+        }
+        Ok(namespaces)
+    }
+}
+
+/// The capability with which the controller raises the interrupts of the I/O queues.
+#[derive(Debug)]
+enum InterruptCapability {
+    Msix(CapabilityMsixData),
+    /// The fallback for the controllers without MSI-X.
+    Msi(CapabilityMsiData),
+}
+
+impl InterruptCapability {
+    /// Finds the interrupt capability of the controller, which prefers MSI-X to MSI.
+    fn find(pci_device: &PciCommonDevice) -> Result<Self, NvmeError> {
+        let mut msi = None;
+        for cap in pci_device.capabilities().iter() {
+            match cap.capability_data() {
+                CapabilityData::Msix(data) => return Ok(Self::Msix(data.clone())),
+                CapabilityData::Msi(data) => msi = Some(Self::Msi(data.clone())),
+                _ => (),
+            }
+        }
+        msi.ok_or(NvmeError::NoResource)
+    }
+
+    /// Returns the vector of the interrupts of the I/O queue `qid`.
+    fn vector(&self, qid: u16) -> u16 {
+        match self {
+            Self::Msix(_) => qid,
+            Self::Msi(_) => 0,
+        }
+    }
+}
+
+/// Waits for the controller to become ready or not ready.
+fn wait_ready(registers: &IoMem, ready: bool, timeout: Duration) -> Result<(), NvmeError> {
+    let deadline = Jiffies::elapsed().as_duration() + timeout;
+    loop {
+        let status: u32 = registers.read_val(NvmeReg::Csts as usize).unwrap();
+        if status & csts::FATAL != 0 {
+            return Err(NvmeError::ControllerFatal);
+        }
+        if (status & csts::READY != 0) == ready {
+            return Ok(());
+        }
+        if Jiffies::elapsed().as_duration() >= deadline {
+            return Err(NvmeError::Timeout);
+        }
+        core::hint::spin_loop();
+    }
+}
+
+/// Issues an Identify command, and returns the data structure returned by the controller.
+fn identify(
+    admin_queue: &mut NvmeQueue,
+    cns: IdentifyCns,
+    nsid: u32,
+) -> Result<DmaStream, NvmeError> {
+    let segment = FrameAllocOptions::new(1)
+        .alloc_contiguous()
+        .map_err(|_| NvmeError::NoMemory)?;
+    let buffer = DmaStream::map(segment, DmaDirection::FromDevice, false)
+        .map_err(|_| NvmeError::NoMemory)?;
+    admin_queue.submit_and_wait(
+        NvmeCommand::identify(cns, nsid, buffer.daddr() as u64),
+        NvmeController::ADMIN_TIMEOUT,
+    )?;
+    buffer.sync(0..PAGE_SIZE).unwrap();
+    Ok(buffer)
+}
@@ -0,0 +1,79 @@
+// SPDX-License-Identifier: MPL-2.0
+
+use alloc::{sync::Arc, vec::Vec};
+
+use aster_frame::{
+    bus::{
+        pci::{
+            bus::{PciDevice, PciDriver},
+            common_device::PciCommonDevice,
+            PciDeviceId, PCI_BUS,
+        },
+        BusProbeError,
+    },
+    sync::SpinLock,
+};
+use spin::Once;
+
+pub(crate) static NVME_PCI_DRIVER: Once<Arc<NvmePciDriver>> = Once::new();
+
+pub(crate) fn nvme_pci_init() {
+    NVME_PCI_DRIVER.call_once(|| Arc::new(NvmePciDriver::new()));
+    PCI_BUS
+        .lock()
+        .register_driver(NVME_PCI_DRIVER.get().unwrap().clone());
+}
+
+/// The PCI driver that claims the NVMe controllers.
+#[derive(Debug)]
+pub(crate) struct NvmePciDriver {
+    devices: SpinLock<Vec<PciCommonDevice>>,
+}
+
+#[derive(Debug)]
+struct NvmePciDevice {
+    device_id: PciDeviceId,
+}
+
+impl PciDevice for NvmePciDevice {
+    fn device_id(&self) -> PciDeviceId {
+        self.device_id
+    }
+}
+
+impl NvmePciDriver {
+    /// The class code of the mass storage controllers.
+    const CLASS_MASS_STORAGE: u8 = 0x01;
+    /// The subclass code of the non-volatile memory controllers.
+    const SUBCLASS_NVM: u8 = 0x08;
+    /// The programming interface of the NVMe I/O controllers.
+    const PROG_IF_NVME: u8 = 0x02;
+
+    fn new() -> Self {
+        Self {
+            devices: SpinLock::new(Vec::new()),
+        }
+    }
+
+    /// Pops a probed controller that is not initialized yet.
+    pub(crate) fn pop_device(&self) -> Option<PciCommonDevice> {
+        self.devices.lock().pop()
+    }
+}
+
+impl PciDriver for NvmePciDriver {
+    fn probe(
+        &self,
+        device: PciCommonDevice,
+    ) -> Result<Arc<dyn PciDevice>, (BusProbeError, PciCommonDevice)> {
+        let device_id = *device.device_id();
+        if device_id.class != Self::CLASS_MASS_STORAGE
+            || device_id.subclass != Self::SUBCLASS_NVM
+            || device_id.prog_if != Self::PROG_IF_NVME
+        {
+            return Err((BusProbeError::DeviceNotMatch, device));
+        }
+        self.devices.lock().push(device);
+        Ok(Arc::new(NvmePciDevice { device_id }))
+    }
+}
@@ -0,0 +1,81 @@
+// SPDX-License-Identifier: MPL-2.0
+
+//! The NVMe driver of Asterinas.
+//!
+//! The driver sets up the admin queue pair of each NVMe controller found on the PCI bus,
+//! discovers the namespaces of the controller, and registers each namespace as a block
+//! device named `nvme{controller}n{namespace}`.
+//!
+//! Each controller has an I/O queue pair for each CPU, as long as the controller and its
+//! MSI-X vectors allow. A bio is submitted to the queue pair of the CPU that submits it,
+//! and completed in the interrupt handler of the queue pair, which is handled by the same
+//! CPU. So the bios are not staged in a software queue unless the submission queue is full.
+//! The controllers without MSI-X fall back to MSI, whose only vector is shared by all the
+//! queue pairs.
+#![no_std]
+#![deny(unsafe_code)]
+
+extern crate alloc;
+
+use alloc::{string::String, sync::Arc, vec::Vec};
+
+use aster_frame::sync::SpinLock;
+use component::{init_component, ComponentInitError};
+use log::{error, info};
+use spin::Once;
+
+use self::{controller::NvmeController, driver::NVME_PCI_DRIVER};
+
+mod command;
+mod controller;
+mod driver;
+mod namespace;
+mod prp;
+mod queue;
+mod regs;
+
+/// The errors of the NVMe driver.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum NvmeError {
+    /// The controller does not provide the required resources, e.g., the BAR or MSI-X.
+    NoResource,
+    /// The controller does not respond in time.
+    Timeout,
+    /// The controller reports a fatal status.
+    ControllerFatal,
+    /// A command fails with the status code type and the status code.
+    CommandFailed { sct: u8, sc: u8 },
+    /// The memory for the queues or the data structures cannot be allocated.
+    NoMemory,
+}
+
+static CONTROLLERS: SpinLock<Vec<Arc<NvmeController>>> = SpinLock::new(Vec::new());
+static NAMESPACE_NAMES: Once<Vec<String>> = Once::new();
+
+#[init_component]
+fn nvme_component_init() -> Result<(), ComponentInitError> {
+    driver::nvme_pci_init();
+
+    let mut names = Vec::new();
+    while let Some(device) = NVME_PCI_DRIVER.get().unwrap().pop_device() {
+        let id = CONTROLLERS.lock().len();
+        match NvmeController::init(id, device) {
+            Ok(controller) => {
+                for namespace in controller.namespaces() {
+                    info!("[NVMe]: Found namespace {}", namespace.name());
+                    names.push(String::from(namespace.name()));
+                    aster_block::register_device(String::from(namespace.name()), namespace.clone());
+                }
+                CONTROLLERS.lock().push(controller);
+            }
+            Err(err) => error!("[NVMe]: Controller initialization error: {:?}", err),
+        }
+    }
+    NAMESPACE_NAMES.call_once(|| names);
+    Ok(())
+}
+
+/// Returns the names of all the NVMe namespaces, which are registered as block devices.
+pub fn all_namespaces() -> Vec<String> {
+    NAMESPACE_NAMES.get().cloned().unwrap_or_default()
+}
@@ -0,0 +1,311 @@
+// SPDX-License-Identifier: MPL-2.0
+
+use alloc::{
+    collections::{BTreeMap, VecDeque},
+    string::String,
+    sync::Arc,
+    vec,
+    vec::Vec,
+};
+use core::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+
+use aster_block::{
+    bio::{BioEnqueueError, BioStatus, BioType, SubmittedBio},
+    BlockDevice,
+};
+use aster_frame::{
+    cpu::this_cpu,
+    mm::{dma_map, DmaDevice, DmaDirection, DmaMapping, DmaStream, HasDaddr},
+    sync::SpinLock,
+};
+use log::warn;
+
+use crate::{
+    command::{IoOpcode, NvmeCommand},
+    prp::split_into_transfers,
+    queue::NvmeQueue,
+};
+
+/// An NVMe namespace, which is a block device.
+#[derive(Debug)]
+pub struct NvmeNamespace {
+    name: String,
+    nsid: u32,
+    nr_blocks: u64,
+    /// The size of a logical block in bytes.
+    block_size: usize,
+    /// The I/O queues of the controller, which are shared by its namespaces.
+    io_queues: Vec<Arc<IoQueue>>,
+    max_transfer_pages: usize,
+    /// The controller, for which the buffers of the bios are mapped.
+    dma_device: DmaDevice,
+}
+
+impl NvmeNamespace {
+    /// The maximum number of the segments of a bio.
+    const MAX_NR_SEGMENTS_PER_BIO: usize = 128;
+
+    pub(crate) fn new(
+        name: String,
+        nsid: u32,
+        nr_blocks: u64,
+        block_size: usize,
+        io_queues: Vec<Arc<IoQueue>>,
+        max_transfer_pages: usize,
+        dma_device: DmaDevice,
+    ) -> Self {
+        Self {
+            name,
+            nsid,
+            nr_blocks,
+            block_size,
+            io_queues,
+            max_transfer_pages,
+            dma_device,
+        }
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// Returns the capacity of the namespace in bytes.
+    pub fn capacity(&self) -> u64 {
+        self.nr_blocks * self.block_size as u64
+    }
+
+    /// Returns the I/O queue of the current CPU.
+    fn io_queue(&self) -> &Arc<IoQueue> {
+        &self.io_queues[this_cpu() as usize % self.io_queues.len()]
+    }
+
+    /// Builds the read or write commands of the bio.
+    fn build_read_write(
+        &self,
+        bio: SubmittedBio,
+        opcode: IoOpcode,
+    ) -> Result<Vec<InflightCommand>, BioEnqueueError> {
+        let byte_range = bio.sid_range().start.to_offset()..bio.sid_range().end.to_offset();
+        if byte_range.end as u64 > self.capacity() {
+            return Err(BioEnqueueError::Refused);
+        }
+
+        let direction = match opcode {
+            IoOpcode::Read => DmaDirection::FromDevice,
+            _ => DmaDirection::ToDevice,
+        };
+        let dma_bufs = bio
+            .segments()
+            .iter()
+            .map(|segment| {
+                let (offset, len) = (segment.offset(), segment.nbytes());
+                let mapping = dma_map(&self.dma_device, segment.pages().clone(), direction)
+                    .map_err(|_| BioEnqueueError::Refused)?;
+                mapping.sync_for_device(offset..offset + len).unwrap();
+                Ok((mapping, offset, len))
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+        let transfers = split_into_transfers(
+            dma_bufs
+                .iter()
+                .map(|(mapping, offset, len)| (mapping.daddr() + offset, *len)),
+            self.max_transfer_pages,
+        );
+
+        let mut commands = Vec::with_capacity(transfers.len());
+        for transfer in transfers {
+            let offset = byte_range.start + transfer.offset;
+            // A transfer that does not cover whole logical blocks cannot be expressed.
+            if offset % self.block_size != 0 || transfer.len % self.block_size != 0 {
+                warn!(
+                    "[NVMe]: The transfer at {:#x} of {} bytes is not aligned to blocks",
+                    offset, transfer.len
+                );
+                return Err(BioEnqueueError::Refused);
+            }
+            let (prp1, prp2, prp_list) = transfer
+                .build_prps()
+                .map_err(|_| BioEnqueueError::Refused)?;
+            let command = NvmeCommand::read_write(
+                opcode,
+                self.nsid,
+                (offset / self.block_size) as u64,
+                (transfer.len / self.block_size) as u16,
+                prp1,
+                prp2,
+            );
+            commands.push((command, prp_list));
+        }
+
+        aster_block::trace::trace_issue(&bio);
+        let inflight_bio = Arc::new(InflightBio::new(bio, dma_bufs, commands.len()));
+        Ok(commands
+            .into_iter()
+            .map(|(command, prp_list)| InflightCommand {
+                command,
+                bio: inflight_bio.clone(),
+                _prp_list: prp_list,
+            })
+            .collect())
+    }
+}
+
+impl BlockDevice for NvmeNamespace {
+    fn enqueue(&self, bio: SubmittedBio) -> Result<(), BioEnqueueError> {
+        let commands = match bio.type_() {
+            BioType::Read => self.build_read_write(bio, IoOpcode::Read)?,
+            BioType::Write => self.build_read_write(bio, IoOpcode::Write)?,
+            BioType::Flush => {
+                aster_block::trace::trace_issue(&bio);
+                let command = NvmeCommand::flush(self.nsid);
+                let inflight_bio = Arc::new(InflightBio::new(bio, Vec::new(), 1));
+                vec![InflightCommand {
+                    command,
+                    bio: inflight_bio,
+                    _prp_list: None,
+                }]
+            }
+            // TODO: Support the Dataset Management command to discard the blocks.
+            BioType::Discard => {
+                bio.complete(BioStatus::NotSupported);
+                return Ok(());
+            }
+        };
+        self.io_queue().submit(commands);
+        Ok(())
+    }
+
+    fn max_nr_segments_per_bio(&self) -> usize {
+        Self::MAX_NR_SEGMENTS_PER_BIO
+    }
+}
+
+/// An I/O queue pair, which holds the in-flight commands.
+#[derive(Debug)]
+pub(crate) struct IoQueue {
+    inner: SpinLock<IoQueueInner>,
+}
+
+#[derive(Debug)]
+struct IoQueueInner {
+    queue: NvmeQueue,
+    /// The submitted commands indexed by their command IDs.
+    inflight: BTreeMap<u16, InflightCommand>,
+    /// The commands that wait for free entries of the submission queue.
+    pending: VecDeque<InflightCommand>,
+}
+
+/// A command of a bio.
+#[derive(Debug)]
+struct InflightCommand {
+    command: NvmeCommand,
+    bio: Arc<InflightBio>,
+    /// The PRP list of the command, which must be kept until the command completes.
+    _prp_list: Option<DmaStream>,
+}
+
+/// A bio that is split into commands.
+#[derive(Debug)]
+struct InflightBio {
+    bio: SubmittedBio,
+    dma_bufs: Vec<(DmaMapping, usize, usize)>,
+    nr_pending_commands: AtomicUsize,
+    has_failed: AtomicBool,
+}
+
+impl InflightBio {
+    fn new(
+        bio: SubmittedBio,
+        dma_bufs: Vec<(DmaMapping, usize, usize)>,
+        nr_commands: usize,
+    ) -> Self {
+        Self {
+            bio,
+            dma_bufs,
+            nr_pending_commands: AtomicUsize::new(nr_commands),
+            has_failed: AtomicBool::new(false),
+        }
+    }
+
+    /// Completes the bio after all its commands complete.
+    fn complete(&self) {
+        if self.has_failed.load(Ordering::Relaxed) {
+            self.bio.complete(BioStatus::IoError);
+            return;
+        }
+        if self.bio.type_() == BioType::Read {
+            for (mapping, offset, len) in self.dma_bufs.iter() {
+                mapping.sync_for_cpu(*offset..*offset + *len).unwrap();
+            }
+        }
+        self.bio.complete(BioStatus::Complete);
+    }
+}
+
+impl IoQueue {
+    pub(crate) fn new(queue: NvmeQueue) -> Self {
+        Self {
+            inner: SpinLock::new(IoQueueInner {
+                queue,
+                inflight: BTreeMap::new(),
+                pending: VecDeque::new(),
+            }),
+        }
+    }
+
+    /// Submits the commands, or keeps them pending if the submission queue is full.
+    fn submit(&self, commands: Vec<InflightCommand>) {
+        let mut inner = self.inner.lock_irq_disabled();
+        for command in commands {
+            inner.pending.push_back(command);
+        }
+        inner.submit_pending();
+    }
+
+    /// Handles the completions of the commands.
+    pub(crate) fn handle_irq(&self) {
+        let mut completed_bios = Vec::new();
+        {
+            // The IRQs have already been disabled in the IRQ handler.
+            let mut inner = self.inner.lock();
+            while let Some(completion) = inner.queue.pop_completion() {
+                let Some(command) = inner.inflight.remove(&completion.cid) else {
+                    warn!(
+                        "[NVMe]: Unexpected completion of command {}",
+                        completion.cid
+                    );
+                    continue;
+                };
+                if let Err(err) = completion.check() {
+                    warn!("[NVMe]: I/O command failed: {:?}", err);
+                    command.bio.has_failed.store(true, Ordering::Relaxed);
+                }
+                if command
+                    .bio
+                    .nr_pending_commands
+                    .fetch_sub(1, Ordering::AcqRel)
+                    == 1
+                {
+                    completed_bios.push(command.bio);
+                }
+            }
+            inner.submit_pending();
+        }
+
+        for bio in completed_bios {
+            bio.complete();
+        }
+    }
+}
+
+impl IoQueueInner {
+    fn submit_pending(&mut self) {
+        while let Some(command) = self.pending.front() {
+            let Some(cid) = self.queue.submit(command.command) else {
+                return;
+            };
+            let command = self.pending.pop_front().unwrap();
+            self.inflight.insert(cid, command);
+        }
+    }
+}
@@ -0,0 +1,107 @@
+// SPDX-License-Identifier: MPL-2.0
+
+//! The construction of the Physical Region Page (PRP) entries.
+//!
+//! An NVMe command describes its data buffer with the PRP entries, each of which points to
+//! a memory page. The first entry may start at any offset of a page, while the others must
+//! start at the beginning of a page, and all the entries but the last one must end at the
+//! end of a page. The first entry is put in PRP1. The second one is put in PRP2 if there
+//! are no more than two entries, or PRP2 points to a PRP list that holds the others.
+//!
+//! The segments of a bio may not satisfy these rules together, so a bio is split into
+//! transfers, each of which is described by a PRP and is submitted as one command.
+
+use alloc::vec::Vec;
+
+use aster_frame::mm::{
+    Daddr, DmaDirection, DmaStream, FrameAllocOptions, HasDaddr, VmIo, PAGE_SIZE,
+};
+
+use crate::NvmeError;
+
+/// The maximum number of the entries in a PRP list, which fits in a page.
+const MAX_PRP_LIST_ENTRIES: usize = PAGE_SIZE / 8;
+
+/// The maximum number of the pages of a transfer, which is bounded by a single PRP list.
+pub(crate) const MAX_TRANSFER_PAGES: usize = MAX_PRP_LIST_ENTRIES + 1;
+
+/// A transfer of a bio, which is described by one PRP.
+#[derive(Debug, Default)]
+pub(crate) struct Transfer {
+    /// The offset of the transfer in the bio in bytes.
+    pub offset: usize,
+    /// The length of the transfer in bytes.
+    pub len: usize,
+    /// The DMA address of each page.
+    entries: Vec<Daddr>,
+    /// The DMA address where the last page piece ends.
+    end: Daddr,
+}
+
+impl Transfer {
+    /// Returns whether the page piece starting at `daddr` can be appended to this transfer.
+    fn can_append(&self, daddr: Daddr, max_pages: usize) -> bool {
+        self.entries.is_empty()
+            || (self.end % PAGE_SIZE == 0
+                && daddr % PAGE_SIZE == 0
+                && self.entries.len() < max_pages)
+    }
+
+    /// Builds PRP1 and PRP2 of the transfer.
+    ///
+    /// The returned PRP list, if any, must be kept until the command completes.
+    pub(crate) fn build_prps(&self) -> Result<(u64, u64, Option<DmaStream>), NvmeError> {
+        match self.entries.as_slice() {
+            [prp1] => Ok((*prp1 as u64, 0, None)),
+            [prp1, prp2] => Ok((*prp1 as u64, *prp2 as u64, None)),
+            [prp1, others @ ..] => {
+                debug_assert!(others.len() <= MAX_PRP_LIST_ENTRIES);
+                let segment = FrameAllocOptions::new(1)
+                    .alloc_contiguous()
+                    .map_err(|_| NvmeError::NoMemory)?;
+                let list = DmaStream::map(segment, DmaDirection::ToDevice, false)
+                    .map_err(|_| NvmeError::NoMemory)?;
+                for (i, entry) in others.iter().enumerate() {
+                    list.write_val(i * 8, &(*entry as u64)).unwrap();
+                }
+                list.sync(0..others.len() * 8).unwrap();
+                Ok((*prp1 as u64, list.daddr() as u64, Some(list)))
+            }
+            [] => unreachable!("a transfer is never empty"),
+        }
+    }
+}
+
+/// Splits the physically contiguous buffers, given as the DMA addresses and the lengths,
+/// into transfers with at most `max_pages` pages.
+pub(crate) fn split_into_transfers(
+    buffers: impl Iterator<Item = (Daddr, usize)>,
+    max_pages: usize,
+) -> Vec<Transfer> {
+    let mut transfers = Vec::new();
+    let mut current = Transfer::default();
+    let mut offset = 0;
+    for (daddr, len) in buffers {
+        // Each piece is the part of the buffer in a page.
+        let end = daddr + len;
+        let mut piece_start = daddr;
+        while piece_start < end {
+            let piece_end = (piece_start - piece_start % PAGE_SIZE + PAGE_SIZE).min(end);
+            if !current.can_append(piece_start, max_pages) {
+                transfers.push(core::mem::take(&mut current));
+            }
+            if current.entries.is_empty() {
+                current.offset = offset;
+            }
+            current.entries.push(piece_start);
+            current.end = piece_end;
+            current.len += piece_end - piece_start;
+            offset += piece_end - piece_start;
+            piece_start = piece_end;
+        }
+    }
+    if !current.entries.is_empty() {
+        transfers.push(current);
+    }
+    transfers
+}
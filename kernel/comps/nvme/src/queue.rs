@@ -0,0 +1,137 @@
+// SPDX-License-Identifier: MPL-2.0
+
+use core::{hint::spin_loop, mem::size_of, time::Duration};
+
+use aster_frame::{
+    arch::timer::Jiffies,
+    io_mem::IoMem,
+    mm::{DmaCoherent, FrameAllocOptions, HasDaddr, VmIo, PAGE_SIZE},
+};
+use id_alloc::IdAlloc;
+
+use crate::{
+    command::{NvmeCommand, NvmeCompletion},
+    regs::{cq_head_doorbell, sq_tail_doorbell},
+    NvmeError,
+};
+
+/// A pair of a submission queue and a completion queue.
+///
+/// The number of the in-flight commands is limited by the command IDs, which are freed
+/// when the commands complete. So the submission queue never overflows.
+#[derive(Debug)]
+pub(crate) struct NvmeQueue {
+    depth: u16,
+    sq: DmaCoherent,
+    cq: DmaCoherent,
+    sq_tail: u16,
+    cq_head: u16,
+    /// The expected phase tag of the next completion.
+    cq_phase: bool,
+    registers: IoMem,
+    sq_doorbell: usize,
+    cq_doorbell: usize,
+    cids: IdAlloc,
+}
+
+impl NvmeQueue {
+    /// The maximum depth of a queue, whose submission queue fits in a page.
+    pub(crate) const MAX_DEPTH: u16 = (PAGE_SIZE / size_of::<NvmeCommand>()) as u16;
+
+    pub(crate) fn new(
+        id: u16,
+        depth: u16,
+        registers: &IoMem,
+        doorbell_stride: usize,
+    ) -> Result<Self, NvmeError> {
+        debug_assert!(depth >= 2 && depth <= Self::MAX_DEPTH);
+        let alloc_queue = || {
+            let segment = FrameAllocOptions::new(1)
+                .alloc_contiguous()
+                .map_err(|_| NvmeError::NoMemory)?;
+            DmaCoherent::map(segment, true).map_err(|_| NvmeError::NoMemory)
+        };
+        Ok(Self {
+            depth,
+            sq: alloc_queue()?,
+            cq: alloc_queue()?,
+            sq_tail: 0,
+            cq_head: 0,
+            cq_phase: true,
+            registers: registers.clone(),
+            sq_doorbell: sq_tail_doorbell(id, doorbell_stride),
+            cq_doorbell: cq_head_doorbell(id, doorbell_stride),
+            // One entry is kept empty to tell a full queue from an empty one.
+            cids: IdAlloc::with_capacity(depth as usize - 1),
+        })
+    }
+
+    pub(crate) fn sq_daddr(&self) -> u64 {
+        self.sq.daddr() as u64
+    }
+
+    pub(crate) fn cq_daddr(&self) -> u64 {
+        self.cq.daddr() as u64
+    }
+
+    /// Submits the command, and returns its command ID.
+    ///
+    /// Returns `None` if the queue is full.
+    pub(crate) fn submit(&mut self, mut command: NvmeCommand) -> Option<u16> {
+        let cid = self.cids.alloc()? as u16;
+        command.cid = cid;
+        self.sq
+            .write_val(self.sq_tail as usize * size_of::<NvmeCommand>(), &command)
+            .unwrap();
+        self.sq_tail = (self.sq_tail + 1) % self.depth;
+        self.registers
+            .write_val(self.sq_doorbell, &(self.sq_tail as u32))
+            .unwrap();
+        Some(cid)
+    }
+
+    /// Pops a completion, and frees the command ID of the completed command.
+    pub(crate) fn pop_completion(&mut self) -> Option<NvmeCompletion> {
+        let completion: NvmeCompletion = self
+            .cq
+            .read_val(self.cq_head as usize * size_of::<NvmeCompletion>())
+            .unwrap();
+        if completion.phase() != self.cq_phase {
+            return None;
+        }
+
+        self.cq_head += 1;
+        if self.cq_head == self.depth {
+            self.cq_head = 0;
+            self.cq_phase = !self.cq_phase;
+        }
+        self.registers
+            .write_val(self.cq_doorbell, &(self.cq_head as u32))
+            .unwrap();
+        self.cids.free(completion.cid as usize);
+        Some(completion)
+    }
+
+    /// Submits the command and polls for its completion.
+    ///
+    /// This is only used for the admin commands, which are submitted one by one.
+    pub(crate) fn submit_and_wait(
+        &mut self,
+        command: NvmeCommand,
+        timeout: Duration,
+    ) -> Result<NvmeCompletion, NvmeError> {
+        let cid = self.submit(command).ok_or(NvmeError::NoResource)?;
+        let deadline = Jiffies::elapsed().as_duration() + timeout;
+        while Jiffies::elapsed().as_duration() < deadline {
+            match self.pop_completion() {
+                Some(completion) if completion.cid == cid => {
+                    completion.check()?;
+                    return Ok(completion);
+                }
+                Some(_) => {}
+                None => spin_loop(),
+            }
+        }
+        Err(NvmeError::Timeout)
+    }
+}
@@ -0,0 +1,88 @@
+// SPDX-License-Identifier: MPL-2.0
+
+//! The registers of the NVMe controllers, which are in BAR 0.
+
+/// The offsets of the controller registers.
+#[derive(Debug, Clone, Copy)]
+#[repr(usize)]
+pub(crate) enum NvmeReg {
+    /// Controller Capabilities, 64 bits.
+    Cap = 0x00,
+    /// Version, 32 bits.
+    Vs = 0x08,
+    /// Controller Configuration, 32 bits.
+    Cc = 0x14,
+    /// Controller Status, 32 bits.
+    Csts = 0x1C,
+    /// Admin Queue Attributes, 32 bits.
+    Aqa = 0x24,
+    /// Admin Submission Queue Base Address, 64 bits.
+    Asq = 0x28,
+    /// Admin Completion Queue Base Address, 64 bits.
+    Acq = 0x30,
+}
+
+/// The offset of the first doorbell register.
+const DOORBELL_BASE: usize = 0x1000;
+
+/// Returns the offset of the submission queue tail doorbell of the queue.
+pub(crate) fn sq_tail_doorbell(qid: u16, doorbell_stride: usize) -> usize {
+    DOORBELL_BASE + (2 * qid as usize) * doorbell_stride
+}
+
+/// Returns the offset of the completion queue head doorbell of the queue.
+pub(crate) fn cq_head_doorbell(qid: u16, doorbell_stride: usize) -> usize {
+    DOORBELL_BASE + (2 * qid as usize + 1) * doorbell_stride
+}
+
+/// The Controller Capabilities register.
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct Capability(pub u64);
+
+impl Capability {
+    /// Returns the maximum number of the entries of an I/O queue.
+    pub(crate) fn max_queue_entries(&self) -> usize {
+        (self.0 & 0xFFFF) as usize + 1
+    }
+
+    /// Returns the maximum time to wait for the controller to become ready or not ready,
+    /// in milliseconds.
+    pub(crate) fn timeout_ms(&self) -> u64 {
+        ((self.0 >> 24) & 0xFF).max(1) * 500
+    }
+
+    /// Returns the distance between two doorbell registers in bytes.
+    pub(crate) fn doorbell_stride(&self) -> usize {
+        4 << ((self.0 >> 32) & 0xF)
+    }
+
+    /// Returns whether the controller supports the NVM command set.
+    pub(crate) fn supports_nvm_command_set(&self) -> bool {
+        (self.0 >> 37) & 1 == 1
+    }
+
+    /// Returns the minimum memory page size of the controller in bytes.
+    pub(crate) fn min_page_size(&self) -> usize {
+        1 << (12 + ((self.0 >> 48) & 0xF))
+    }
+}
+
+/// The bits of the Controller Configuration register.
+pub(crate) mod cc {
+    /// Enables the controller.
+    pub(crate) const ENABLE: u32 = 1 << 0;
+    /// The memory page size is 4 KiB.
+    pub(crate) const MPS_4K: u32 = 0 << 7;
+    /// The entries of the I/O submission queues are 64 bytes.
+    pub(crate) const IOSQES_64: u32 = 6 << 16;
+    /// The entries of the I/O completion queues are 16 bytes.
+    pub(crate) const IOCQES_16: u32 = 4 << 20;
+}
+
+/// The bits of the Controller Status register.
+pub(crate) mod csts {
+    /// The controller is ready to process the commands.
+    pub(crate) const READY: u32 = 1 << 0;
+    /// The controller has a fatal error.
+    pub(crate) const FATAL: u32 = 1 << 1;
+}
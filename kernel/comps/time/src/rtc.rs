@@ -2,11 +2,28 @@
 
 use core::sync::atomic::{AtomicU8, Ordering::Relaxed};
 
-use aster_frame::arch::x86::device::cmos::{get_century_register, CMOS_ADDRESS, CMOS_DATA};
+use aster_frame::arch::x86::device::{
+    cmos::{get_century_register, CMOS_ADDRESS_PORT, CMOS_DATA_PORT},
+    io_port::{IoPort, ReadOnlyAccess, WriteOnlyAccess},
+};
+use spin::Once;
 
 pub(crate) static CENTURY_REGISTER: AtomicU8 = AtomicU8::new(0);
 
+/// The I/O ports of the CMOS, which are acquired by the RTC driver.
+struct CmosPorts {
+    address: IoPort<u8, WriteOnlyAccess>,
+    data: IoPort<u8, ReadOnlyAccess>,
+}
+
+static CMOS: Once<CmosPorts> = Once::new();
+
 pub fn init() {
+    CMOS.call_once(|| CmosPorts {
+        address: IoPort::acquire(CMOS_ADDRESS_PORT, "rtc").unwrap(),
+        data: IoPort::acquire(CMOS_DATA_PORT, "rtc").unwrap(),
+    });
+
     let Some(century_register) = get_century_register() else {
         return;
     };
@@ -14,11 +31,11 @@ pub fn init() {
 }
 
 pub fn get_cmos(reg: u8) -> u8 {
-    CMOS_ADDRESS.write(reg);
-    CMOS_DATA.read()
+    let cmos = CMOS.get().unwrap();
+    cmos.address.write(reg);
+    cmos.data.read()
 }
 
 pub fn is_updating() -> bool {
-    CMOS_ADDRESS.write(0x0A);
-    CMOS_DATA.read() & 0x80 != 0
+    get_cmos(0x0A) & 0x80 != 0
 }
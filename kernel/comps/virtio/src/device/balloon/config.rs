@@ -0,0 +1,70 @@
+// SPDX-License-Identifier: MPL-2.0
+
+use aster_frame::io_mem::IoMem;
+use aster_util::safe_ptr::SafePtr;
+use int_to_c_enum::TryFromInt;
+use pod::Pod;
+
+use crate::transport::VirtioTransport;
+
+bitflags::bitflags! {
+    pub struct BalloonFeatures: u64 {
+        /// The host must be told before the pages are used again after deflation.
+        const VIRTIO_BALLOON_F_MUST_TELL_HOST = 1 << 0;
+        /// The statistics queue is present.
+        const VIRTIO_BALLOON_F_STATS_VQ = 1 << 1;
+        /// The balloon is deflated when the guest is out of memory.
+        const VIRTIO_BALLOON_F_DEFLATE_ON_OOM = 1 << 2;
+        /// The free page hinting queue is present, which is used by the live migration.
+        const VIRTIO_BALLOON_F_FREE_PAGE_HINT = 1 << 3;
+        /// The free pages are filled with `poison_val`.
+        const VIRTIO_BALLOON_F_PAGE_POISON = 1 << 4;
+        /// The free page reporting queue is present.
+        const VIRTIO_BALLOON_F_PAGE_REPORTING = 1 << 5;
+    }
+}
+
+#[derive(Debug, Pod, Clone, Copy)]
+#[repr(C)]
+pub struct VirtioBalloonConfig {
+    /// The number of pages that the host wants in the balloon.
+    pub num_pages: u32,
+    /// The number of pages that the driver has given to the host.
+    pub actual: u32,
+    pub free_page_hint_cmd_id: u32,
+    pub poison_val: u32,
+}
+
+impl VirtioBalloonConfig {
+    pub(super) fn new(transport: &dyn VirtioTransport) -> SafePtr<Self, IoMem> {
+        let memory = transport.device_config_memory();
+        SafePtr::new(memory, 0)
+    }
+}
+
+/// The tags of the memory statistics, whose values are in bytes unless noted otherwise.
+#[repr(u16)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, TryFromInt)]
+#[allow(non_camel_case_types)]
+pub enum BalloonStatTag {
+    /// The amount of memory swapped in.
+    VIRTIO_BALLOON_S_SWAP_IN = 0,
+    /// The amount of memory swapped out.
+    VIRTIO_BALLOON_S_SWAP_OUT = 1,
+    /// The number of major page faults.
+    VIRTIO_BALLOON_S_MAJFLT = 2,
+    /// The number of minor page faults.
+    VIRTIO_BALLOON_S_MINFLT = 3,
+    /// The amount of free memory.
+    VIRTIO_BALLOON_S_MEMFREE = 4,
+    /// The total amount of memory.
+    VIRTIO_BALLOON_S_MEMTOT = 5,
+    /// The amount of memory that is available without swapping.
+    VIRTIO_BALLOON_S_AVAIL = 6,
+    /// The amount of memory that is used as the disk caches.
+    VIRTIO_BALLOON_S_CACHES = 7,
+    /// The number of successful huge page allocations.
+    VIRTIO_BALLOON_S_HTLB_PGALLOC = 8,
+    /// The number of failed huge page allocations.
+    VIRTIO_BALLOON_S_HTLB_PGFAIL = 9,
+}
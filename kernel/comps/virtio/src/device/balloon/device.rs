@@ -0,0 +1,436 @@
+// SPDX-License-Identifier: MPL-2.0
+
+use alloc::{boxed::Box, sync::Arc, vec::Vec};
+use core::{
+    fmt::Debug,
+    hint::spin_loop,
+    mem::size_of,
+    sync::atomic::{AtomicU8, Ordering},
+};
+
+use aster_frame::{
+    io_mem::IoMem,
+    mm::{
+        nr_free_frames, nr_total_frames, reclaim, DmaDirection, DmaStream, DmaStreamSlice, Frame,
+        FrameAllocOptions, VmIo, PAGE_SIZE,
+    },
+    offset_of,
+    sync::{Mutex, SpinLock, WaitQueue},
+    trap::TrapFrame,
+};
+use aster_util::{field_ptr, safe_ptr::SafePtr};
+use bitflags::bitflags;
+use log::{debug, info};
+
+use super::{
+    config::{BalloonFeatures, BalloonStatTag, VirtioBalloonConfig},
+    register_device,
+};
+use crate::{device::VirtioDeviceError, queue::VirtQueue, transport::VirtioTransport};
+
+const INFLATE_QUEUE_INDEX: u16 = 0;
+const DEFLATE_QUEUE_INDEX: u16 = 1;
+
+/// The page size of the balloon, which is always 4 KiB regardless of the page size of
+/// the guest.
+const BALLOON_PFN_SHIFT: usize = 12;
+/// The maximum number of the page frame numbers in an inflation or deflation request.
+const MAX_PFNS_PER_REQUEST: usize = 256;
+
+/// The number of frames in a block of free pages that is reported at a time, i.e., 2 MiB.
+const REPORTING_BLOCK_FRAMES: usize = 512;
+/// The maximum number of blocks in a reporting request, which is also the queue size.
+const REPORTING_CAPACITY: usize = 32;
+
+/// The size of an entry in the statistics buffer, which is a packed `u16` tag followed by
+/// a `u64` value.
+const STAT_ENTRY_LEN: usize = size_of::<u16>() + size_of::<u64>();
+
+bitflags! {
+    /// The requests from the device that the kernel should handle.
+    pub struct BalloonRequests: u8 {
+        /// The number of pages that the host wants in the balloon is changed.
+        const RESIZE = 1 << 0;
+        /// The host wants the up-to-date memory statistics.
+        const STATS = 1 << 1;
+    }
+}
+
+/// A virtio memory balloon device.
+///
+/// The pages in the balloon are allocated from the frame allocator and given to the host,
+/// so the guest must not access them until they are deflated.
+pub struct BalloonDevice {
+    config: SafePtr<VirtioBalloonConfig, IoMem>,
+    features: BalloonFeatures,
+    inflate_queue: SpinLock<VirtQueue>,
+    deflate_queue: SpinLock<VirtQueue>,
+    /// The buffer of the page frame numbers in the inflation or deflation requests.
+    pfn_buffer: DmaStream,
+    /// The frames in the balloon. The lock also serializes the inflations and deflations.
+    frames: Mutex<Vec<Frame>>,
+    stats: Option<SpinLock<StatsQueue>>,
+    reporting_queue: Option<SpinLock<VirtQueue>>,
+    transport: SpinLock<Box<dyn VirtioTransport>>,
+    /// The pending requests, which are the bits of `BalloonRequests`.
+    requests: AtomicU8,
+    wait_queue: WaitQueue,
+}
+
+struct StatsQueue {
+    queue: VirtQueue,
+    buffer: DmaStream,
+    /// Whether the buffer is held by the device, which returns it to request the
+    /// statistics.
+    is_buffer_queued: bool,
+}
+
+impl Debug for BalloonDevice {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        f.debug_struct("BalloonDevice")
+            .field("config", &self.config)
+            .field("features", &self.features)
+            .field("transport", &self.transport)
+            .finish()
+    }
+}
+
+impl BalloonDevice {
+    pub fn negotiate_features(features: u64) -> u64 {
+        let mut features = BalloonFeatures::from_bits_truncate(features);
+        // The free page hinting for the live migration is not supported, and neither is
+        // the page poisoning, which requires the free pages to be filled.
+        features.remove(
+            BalloonFeatures::VIRTIO_BALLOON_F_FREE_PAGE_HINT
+                | BalloonFeatures::VIRTIO_BALLOON_F_PAGE_POISON,
+        );
+        features.bits()
+    }
+
+    pub fn init(mut transport: Box<dyn VirtioTransport>) -> Result<(), VirtioDeviceError> {
+        let config = VirtioBalloonConfig::new(transport.as_ref());
+        let features = BalloonFeatures::from_bits_truncate(Self::negotiate_features(
+            transport.device_features(),
+        ));
+        debug!("[Virtio-Balloon]: features = {:?}", features);
+
+        // The queues that are absent do not take up the indexes.
+        let mut nr_queues = DEFLATE_QUEUE_INDEX + 1;
+        let stats_queue_index = features
+            .contains(BalloonFeatures::VIRTIO_BALLOON_F_STATS_VQ)
+            .then(|| {
+                nr_queues += 1;
+                nr_queues - 1
+            });
+        let reporting_queue_index = features
+            .contains(BalloonFeatures::VIRTIO_BALLOON_F_PAGE_REPORTING)
+            .then(|| {
+                nr_queues += 1;
+                nr_queues - 1
+            });
+        if transport.num_queues() < nr_queues {
+            return Err(VirtioDeviceError::QueuesAmountDoNotMatch(
+                transport.num_queues(),
+                nr_queues,
+            ));
+        }
+
+        let inflate_queue = VirtQueue::new(INFLATE_QUEUE_INDEX, 2, transport.as_mut())?;
+        let deflate_queue = VirtQueue::new(DEFLATE_QUEUE_INDEX, 2, transport.as_mut())?;
+        let pfn_buffer = {
+            let vm_segment = FrameAllocOptions::new(1).alloc_contiguous().unwrap();
+            DmaStream::map(vm_segment, DmaDirection::ToDevice, false).unwrap()
+        };
+        let stats = match stats_queue_index {
+            Some(index) => {
+                let queue = VirtQueue::new(index, 2, transport.as_mut())?;
+                let buffer = {
+                    let vm_segment = FrameAllocOptions::new(1).alloc_contiguous().unwrap();
+                    DmaStream::map(vm_segment, DmaDirection::ToDevice, false).unwrap()
+                };
+                Some(SpinLock::new(StatsQueue {
+                    queue,
+                    buffer,
+                    is_buffer_queued: false,
+                }))
+            }
+            None => None,
+        };
+        let reporting_queue = match reporting_queue_index {
+            Some(index) => Some(SpinLock::new(VirtQueue::new(
+                index,
+                REPORTING_CAPACITY as u16,
+                transport.as_mut(),
+            )?)),
+            None => None,
+        };
+
+        let device = Arc::new(Self {
+            config,
+            features,
+            inflate_queue: SpinLock::new(inflate_queue),
+            deflate_queue: SpinLock::new(deflate_queue),
+            pfn_buffer,
+            frames: Mutex::new(Vec::new()),
+            stats,
+            reporting_queue,
+            transport: SpinLock::new(transport),
+            // The host may want some pages at the beginning.
+            requests: AtomicU8::new(BalloonRequests::RESIZE.bits()),
+            wait_queue: WaitQueue::new(),
+        });
+
+        let mut transport = device.transport.lock_irq_disabled();
+        if let Some(index) = stats_queue_index {
+            let handle_stats_request = {
+                let device = device.clone();
+                move |_: &TrapFrame| device.handle_stats_irq()
+            };
+            transport
+                .register_queue_callback(index, Box::new(handle_stats_request), false)
+                .unwrap();
+        }
+        let handle_config_change = {
+            let device = device.clone();
+            move |_: &TrapFrame| device.add_requests(BalloonRequests::RESIZE)
+        };
+        transport
+            .register_cfg_callback(Box::new(handle_config_change))
+            .unwrap();
+        transport.finish_init();
+        drop(transport);
+
+        // The device requests the statistics by returning the buffer, so a buffer must be
+        // queued first. The kernel refreshes the statistics later.
+        let total = (nr_total_frames() * PAGE_SIZE) as u64;
+        let free = (nr_free_frames() * PAGE_SIZE) as u64;
+        device.update_stats(&[
+            (BalloonStatTag::VIRTIO_BALLOON_S_MEMTOT, total),
+            (BalloonStatTag::VIRTIO_BALLOON_S_MEMFREE, free),
+        ]);
+
+        info!("[Virtio-Balloon]: target pages = {}", device.target_pages());
+        register_device(device);
+        Ok(())
+    }
+
+    /// Returns the number of pages that the host wants in the balloon.
+    pub fn target_pages(&self) -> usize {
+        field_ptr!(&self.config, VirtioBalloonConfig, num_pages)
+            .read()
+            .unwrap() as usize
+    }
+
+    /// Returns the number of pages in the balloon.
+    pub fn nr_pages(&self) -> usize {
+        self.frames.lock().len()
+    }
+
+    /// Returns whether the balloon should be deflated when the guest is out of memory.
+    pub fn deflates_on_oom(&self) -> bool {
+        self.features
+            .contains(BalloonFeatures::VIRTIO_BALLOON_F_DEFLATE_ON_OOM)
+    }
+
+    /// Returns whether the free pages can be reported to the host.
+    pub fn can_report_free_pages(&self) -> bool {
+        self.reporting_queue.is_some()
+    }
+
+    /// Returns the wait queue that is woken up when there are new requests.
+    pub fn wait_queue(&self) -> &WaitQueue {
+        &self.wait_queue
+    }
+
+    /// Takes the pending requests.
+    pub fn take_requests(&self) -> BalloonRequests {
+        BalloonRequests::from_bits_truncate(self.requests.swap(0, Ordering::Relaxed))
+    }
+
+    /// Inflates the balloon by at most `nr_pages` pages, which are allocated from the frame
+    /// allocator and given to the host.
+    ///
+    /// The inflation stops early if the guest is low on memory. Returns the number of the
+    /// pages that are added to the balloon.
+    pub fn inflate(&self, nr_pages: usize) -> usize {
+        let mut frames = self.frames.lock();
+        let mut nr_inflated = 0;
+        while nr_inflated < nr_pages {
+            let batch_len = (nr_pages - nr_inflated).min(MAX_PFNS_PER_REQUEST);
+            let mut batch = Vec::with_capacity(batch_len);
+            while batch.len() < batch_len && !reclaim::is_low_on_memory() {
+                // The content of the pages is discarded by the host.
+                let Ok(frame) = FrameAllocOptions::new(1).uninit(true).alloc_single() else {
+                    break;
+                };
+                batch.push(frame);
+            }
+            if batch.is_empty() {
+                break;
+            }
+
+            self.tell_host(&self.inflate_queue, &batch);
+            nr_inflated += batch.len();
+            let is_exhausted = batch.len() < batch_len;
+            frames.extend(batch);
+            if is_exhausted {
+                break;
+            }
+        }
+        self.set_actual_pages(frames.len());
+        nr_inflated
+    }
+
+    /// Deflates the balloon by at most `nr_pages` pages, which are given back to the frame
+    /// allocator.
+    ///
+    /// Returns the number of the pages that are removed from the balloon.
+    pub fn deflate(&self, nr_pages: usize) -> usize {
+        let mut frames = self.frames.lock();
+        let mut nr_deflated = 0;
+        while nr_deflated < nr_pages && !frames.is_empty() {
+            let batch_len = (nr_pages - nr_deflated)
+                .min(MAX_PFNS_PER_REQUEST)
+                .min(frames.len());
+            let batch = frames.split_off(frames.len() - batch_len);
+            // The host is always told before the pages are used again, as required by
+            // `VIRTIO_BALLOON_F_MUST_TELL_HOST`.
+            self.tell_host(&self.deflate_queue, &batch);
+            nr_deflated += batch_len;
+        }
+        self.set_actual_pages(frames.len());
+        nr_deflated
+    }
+
+    /// Updates the memory statistics for the host.
+    ///
+    /// The statistics are sent only if the device has requested them, or at the
+    /// initialization. Otherwise, this method does nothing.
+    pub fn update_stats(&self, stats: &[(BalloonStatTag, u64)]) {
+        let Some(stats_queue) = &self.stats else {
+            return;
+        };
+        let mut stats_queue = stats_queue.lock_irq_disabled();
+        if stats_queue.is_buffer_queued {
+            return;
+        }
+
+        let bytes: Vec<u8> = stats
+            .iter()
+            .take(PAGE_SIZE / STAT_ENTRY_LEN)
+            .flat_map(|(tag, value)| {
+                let tag = (*tag as u16).to_le_bytes();
+                tag.into_iter().chain(value.to_le_bytes())
+            })
+            .collect();
+        stats_queue.buffer.write_bytes(0, &bytes).unwrap();
+        stats_queue.buffer.sync(0..bytes.len()).unwrap();
+
+        let StatsQueue { queue, buffer, .. } = &mut *stats_queue;
+        let slice = DmaStreamSlice::new(buffer, 0, bytes.len());
+        queue.add_dma_buf(&[&slice], &[]).unwrap();
+        if queue.should_notify() {
+            queue.notify();
+        }
+        stats_queue.is_buffer_queued = true;
+    }
+
+    /// Reports the free pages to the host, so that the host can reclaim the memory behind
+    /// them, until no more than `nr_to_keep` frames are left free.
+    ///
+    /// The free pages are reported in 2 MiB blocks, which are allocated from the frame
+    /// allocator during the reporting and freed afterwards. Returns the number of the
+    /// reported pages.
+    pub fn report_free_pages(&self, nr_to_keep: usize) -> usize {
+        let Some(reporting_queue) = &self.reporting_queue else {
+            return 0;
+        };
+
+        // The reported blocks are kept until all the requests are completed, so that no
+        // block is reported twice.
+        let mut reported_blocks = Vec::new();
+        loop {
+            let mut blocks = Vec::with_capacity(REPORTING_CAPACITY);
+            while blocks.len() < REPORTING_CAPACITY
+                && nr_free_frames() >= nr_to_keep + REPORTING_BLOCK_FRAMES
+            {
+                let Ok(segment) = FrameAllocOptions::new(REPORTING_BLOCK_FRAMES)
+                    .uninit(true)
+                    .alloc_contiguous()
+                else {
+                    break;
+                };
+                blocks.push(DmaStream::map(segment, DmaDirection::ToDevice, false).unwrap());
+            }
+            if blocks.is_empty() {
+                break;
+            }
+
+            let mut queue = reporting_queue.lock_irq_disabled();
+            let inputs: Vec<&DmaStream> = blocks.iter().collect();
+            queue.add_dma_buf(inputs.as_slice(), &[]).unwrap();
+            if queue.should_notify() {
+                queue.notify();
+            }
+            while !queue.can_pop() {
+                spin_loop();
+            }
+            queue.pop_used().unwrap();
+            drop(queue);
+
+            let is_exhausted = blocks.len() < REPORTING_CAPACITY;
+            reported_blocks.extend(blocks);
+            if is_exhausted {
+                break;
+            }
+        }
+        reported_blocks.len() * REPORTING_BLOCK_FRAMES
+    }
+
+    /// Sends the page frame numbers of the frames to the inflate or deflate queue, and
+    /// returns when the host has processed them.
+    fn tell_host(&self, queue: &SpinLock<VirtQueue>, frames: &[Frame]) {
+        for (i, frame) in frames.iter().enumerate() {
+            let pfn = (frame.start_paddr() >> BALLOON_PFN_SHIFT) as u32;
+            self.pfn_buffer
+                .write_val(i * size_of::<u32>(), &pfn)
+                .unwrap();
+        }
+        let len = frames.len() * size_of::<u32>();
+        self.pfn_buffer.sync(0..len).unwrap();
+
+        let mut queue = queue.lock_irq_disabled();
+        let slice = DmaStreamSlice::new(&self.pfn_buffer, 0, len);
+        queue.add_dma_buf(&[&slice], &[]).unwrap();
+        if queue.should_notify() {
+            queue.notify();
+        }
+        while !queue.can_pop() {
+            spin_loop();
+        }
+        queue.pop_used().unwrap();
+    }
+
+    fn set_actual_pages(&self, nr_pages: usize) {
+        field_ptr!(&self.config, VirtioBalloonConfig, actual)
+            .write(&(nr_pages as u32))
+            .unwrap();
+    }
+
+    fn handle_stats_irq(&self) {
+        let mut stats_queue = self.stats.as_ref().unwrap().lock_irq_disabled();
+        if !stats_queue.queue.can_pop() {
+            return;
+        }
+        stats_queue.queue.pop_used().unwrap();
+        stats_queue.is_buffer_queued = false;
+        drop(stats_queue);
+
+        self.add_requests(BalloonRequests::STATS);
+    }
+
+    fn add_requests(&self, requests: BalloonRequests) {
+        self.requests.fetch_or(requests.bits(), Ordering::Relaxed);
+        self.wait_queue.wake_all();
+    }
+}
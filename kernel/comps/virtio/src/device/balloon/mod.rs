@@ -0,0 +1,29 @@
+// SPDX-License-Identifier: MPL-2.0
+
+//! The virtio memory balloon device, through which the host takes memory from the guest
+//! and gives it back later, so that the memory of the guests can be overcommitted.
+//!
+//! The driver only talks to the device. When to inflate or deflate the balloon, and
+//! which statistics are reported, is decided by the kernel.
+
+use alloc::{sync::Arc, vec::Vec};
+
+use aster_frame::sync::SpinLock;
+
+use self::device::BalloonDevice;
+
+pub mod config;
+pub mod device;
+
+pub static DEVICE_NAME: &str = "Virtio-Balloon";
+
+/// The balloon devices, in the order of their registrations.
+static DEVICE_TABLE: SpinLock<Vec<Arc<BalloonDevice>>> = SpinLock::new(Vec::new());
+
+pub fn register_device(device: Arc<BalloonDevice>) {
+    DEVICE_TABLE.lock_irq_disabled().push(device);
+}
+
+pub fn all_devices() -> Vec<Arc<BalloonDevice>> {
+    DEVICE_TABLE.lock_irq_disabled().clone()
+}
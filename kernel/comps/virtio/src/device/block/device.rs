@@ -8,12 +8,13 @@ use aster_block::{
     request_queue::{BioRequest, BioRequestSingleQueue},
 };
 use aster_frame::{
+    cpu::{num_cpus, this_cpu},
     io_mem::IoMem,
-    mm::{DmaDirection, DmaStream, DmaStreamSlice, FrameAllocOptions, VmIo},
+    mm::{DmaDirection, DmaStream, DmaStreamSlice, FrameAllocOptions, VmIo, PAGE_SIZE},
     sync::SpinLock,
     trap::TrapFrame,
 };
-use aster_util::safe_ptr::SafePtr;
+use aster_util::{field_ptr, safe_ptr::SafePtr};
 use id_alloc::IdAlloc;
 use log::info;
 use pod::Pod;
@@ -41,35 +42,45 @@ impl BlockDevice {
         let device = DeviceInner::init(transport)?;
         let device_id = device.request_device_id();
 
+        // Each bio request includes an additional 1 request and 1 response descriptor,
+        // therefore this upper bound is set to (max_nr_descs - 2).
+        let max_nr_segments_per_bio = device.max_nr_descs() - 2;
         let block_device = Arc::new(Self {
             device,
-            // Each bio request includes an additional 1 request and 1 response descriptor,
-            // therefore this upper bound is set to (QUEUE_SIZE - 2).
-            queue: BioRequestSingleQueue::with_max_nr_segments_per_bio(
-                (DeviceInner::QUEUE_SIZE - 2) as usize,
-            ),
+            queue: BioRequestSingleQueue::with_max_nr_segments_per_bio(max_nr_segments_per_bio),
         });
 
         aster_block::register_device(device_id, block_device);
         Ok(())
     }
 
+    /// Returns the number of the virtqueues used to submit the requests.
+    ///
+    /// Each virtqueue has its own lock, so up to this number of threads can call
+    /// [`Self::handle_requests`] without contending with each other.
+    pub fn num_queues(&self) -> usize {
+        self.device.queues.len()
+    }
+
     /// Dequeues a `BioRequest` from the software staging queue and
     /// processes the request.
+    ///
+    /// The request is submitted to the virtqueue of the current CPU.
     pub fn handle_requests(&self) {
         let request = self.queue.dequeue();
         info!("Handle Request: {:?}", request);
         match request.type_() {
             BioType::Read => self.device.read(request),
             BioType::Write => self.device.write(request),
-            BioType::Flush | BioType::Discard => todo!(),
+            BioType::Flush => self.device.flush(request),
+            BioType::Discard => todo!(),
         }
     }
 
     /// Negotiate features for the device specified bits 0~23
     pub(crate) fn negotiate_features(features: u64) -> u64 {
-        let feature = BlockFeatures::from_bits(features).unwrap();
-        let support_features = BlockFeatures::from_bits(features).unwrap();
+        let feature = BlockFeatures::from_bits_truncate(features);
+        let support_features = BlockFeatures::all();
         (feature & support_features).bits
     }
 }
@@ -82,17 +93,33 @@ impl aster_block::BlockDevice for BlockDevice {
     fn max_nr_segments_per_bio(&self) -> usize {
         self.queue.max_nr_segments_per_bio()
     }
+
+    fn request_queue(&self) -> Option<&BioRequestSingleQueue> {
+        Some(&self.queue)
+    }
 }
 
 #[derive(Debug)]
 struct DeviceInner {
     config: SafePtr<VirtioBlockConfig, IoMem>,
-    queue: SpinLock<VirtQueue>,
+    /// The request virtqueues. A CPU uses the one whose index is its ID modulo the
+    /// number of the virtqueues.
+    queues: Vec<SpinLock<IoQueue>>,
     transport: SpinLock<Box<dyn VirtioTransport>>,
     block_requests: DmaStream,
     block_responses: DmaStream,
     id_allocator: SpinLock<IdAlloc>,
-    submitted_requests: SpinLock<BTreeMap<u16, SubmittedRequest>>,
+    /// Whether the device has a volatile write cache that needs to be flushed.
+    supports_flush: bool,
+    /// Whether the requests are added to the virtqueues through indirect descriptor tables.
+    supports_indirect: bool,
+}
+
+/// A request virtqueue and the requests submitted to it.
+#[derive(Debug)]
+struct IoQueue {
+    queue: VirtQueue,
+    submitted_requests: BTreeMap<u16, SubmittedRequest>,
 }
 
 impl DeviceInner {
@@ -101,38 +128,64 @@ impl DeviceInner {
     /// Creates and inits the device.
     pub fn init(mut transport: Box<dyn VirtioTransport>) -> Result<Arc<Self>, VirtioDeviceError> {
         let config = VirtioBlockConfig::new(transport.as_mut());
-        let num_queues = transport.num_queues();
-        if num_queues != 1 {
-            return Err(VirtioDeviceError::QueuesAmountDoNotMatch(num_queues, 1));
+        let features = BlockFeatures::from_bits_truncate(transport.driver_features());
+        let supports_flush = features.contains(BlockFeatures::FLUSH);
+        let num_queues = if features.contains(BlockFeatures::MQ) {
+            field_ptr!(&config, VirtioBlockConfig, num_queues)
+                .read()
+                .unwrap()
+        } else {
+            1
+        };
+        if num_queues == 0 || num_queues > transport.num_queues() {
+            return Err(VirtioDeviceError::QueuesAmountDoNotMatch(
+                transport.num_queues(),
+                num_queues,
+            ));
         }
-        let queue = VirtQueue::new(0, Self::QUEUE_SIZE, transport.as_mut())
-            .expect("create virtqueue failed");
+        // More virtqueues than CPUs do not make the submission more concurrent.
+        let num_queues = num_queues.min(num_cpus() as u16);
+        let queues: Vec<VirtQueue> = (0..num_queues)
+            .map(|idx| {
+                VirtQueue::new(idx, Self::QUEUE_SIZE, transport.as_mut())
+                    .expect("create virtqueue failed")
+            })
+            .collect();
+        let supports_indirect = queues[0].supports_indirect();
+
+        // Besides the in-flight requests, each thread that handles the requests may hold
+        // one request waiting for free descriptors.
+        let max_nr_requests = (Self::QUEUE_SIZE as usize + 1) * num_queues as usize;
         let block_requests = {
-            let vm_segment = FrameAllocOptions::new(1).alloc_contiguous().unwrap();
+            let nframes = (max_nr_requests * REQ_SIZE).div_ceil(PAGE_SIZE);
+            let vm_segment = FrameAllocOptions::new(nframes).alloc_contiguous().unwrap();
             DmaStream::map(vm_segment, DmaDirection::Bidirectional, false).unwrap()
         };
-        assert!(Self::QUEUE_SIZE as usize * REQ_SIZE <= block_requests.nbytes());
         let block_responses = {
-            let vm_segment = FrameAllocOptions::new(1).alloc_contiguous().unwrap();
+            let nframes = (max_nr_requests * RESP_SIZE).div_ceil(PAGE_SIZE);
+            let vm_segment = FrameAllocOptions::new(nframes).alloc_contiguous().unwrap();
             DmaStream::map(vm_segment, DmaDirection::Bidirectional, false).unwrap()
         };
-        assert!(Self::QUEUE_SIZE as usize * RESP_SIZE <= block_responses.nbytes());
 
         let device = Arc::new(Self {
             config,
-            queue: SpinLock::new(queue),
+            queues: queues
+                .into_iter()
+                .map(|queue| {
+                    SpinLock::new(IoQueue {
+                        queue,
+                        submitted_requests: BTreeMap::new(),
+                    })
+                })
+                .collect(),
             transport: SpinLock::new(transport),
             block_requests,
             block_responses,
-            id_allocator: SpinLock::new(IdAlloc::with_capacity(Self::QUEUE_SIZE as usize)),
-            submitted_requests: SpinLock::new(BTreeMap::new()),
+            id_allocator: SpinLock::new(IdAlloc::with_capacity(max_nr_requests)),
+            supports_flush,
+            supports_indirect,
         });
 
-        let cloned_device = device.clone();
-        let handle_irq = move |_: &TrapFrame| {
-            cloned_device.handle_irq();
-        };
-
         let cloned_device = device.clone();
         let handle_config_change = move |_: &TrapFrame| {
             cloned_device.handle_config_change();
@@ -143,17 +196,44 @@ impl DeviceInner {
             transport
                 .register_cfg_callback(Box::new(handle_config_change))
                 .unwrap();
-            transport
-                .register_queue_callback(0, Box::new(handle_irq), false)
-                .unwrap();
+            for idx in 0..num_queues {
+                let cloned_device = device.clone();
+                let handle_irq = move |_: &TrapFrame| {
+                    cloned_device.handle_irq(idx as usize);
+                };
+                // Each virtqueue has its own interrupt if there are enough MSI-X vectors,
+                // or shares one with the others otherwise.
+                if transport
+                    .register_queue_callback(idx, Box::new(handle_irq.clone()), true)
+                    .is_err()
+                {
+                    transport
+                        .register_queue_callback(idx, Box::new(handle_irq), false)
+                        .unwrap();
+                }
+            }
             transport.finish_init();
         }
 
         Ok(device)
     }
 
-    /// Handles the irq issued from the device
-    fn handle_irq(&self) {
+    /// Returns the maximum number of descriptors of a request.
+    fn max_nr_descs(&self) -> usize {
+        if self.supports_indirect {
+            VirtQueue::MAX_INDIRECT_DESCS
+        } else {
+            Self::QUEUE_SIZE as usize
+        }
+    }
+
+    /// Returns the virtqueue of the current CPU.
+    fn io_queue(&self) -> &SpinLock<IoQueue> {
+        &self.queues[this_cpu() as usize % self.queues.len()]
+    }
+
+    /// Handles the irq of the virtqueue issued from the device
+    fn handle_irq(&self, idx: usize) {
         info!("Virtio block device handle irq");
         // When we enter the IRQs handling function,
         // IRQs have already been disabled,
@@ -161,11 +241,11 @@ impl DeviceInner {
         loop {
             // Pops the complete request
             let complete_request = {
-                let mut queue = self.queue.lock();
-                let Ok((token, _)) = queue.pop_used() else {
+                let mut io_queue = self.queues[idx].lock();
+                let Ok((token, _)) = io_queue.queue.pop_used() else {
                     return;
                 };
-                self.submitted_requests.lock().remove(&token).unwrap()
+                io_queue.submitted_requests.remove(&token).unwrap()
             };
 
             // Handles the response
@@ -233,7 +313,8 @@ impl DeviceInner {
         let device_id_slice = DmaStreamSlice::new(&device_id_stream, 0, MAX_ID_LENGTH);
         let outputs = vec![&device_id_slice, &resp_slice];
 
-        let mut queue = self.queue.lock_irq_disabled();
+        let mut io_queue = self.queues[0].lock_irq_disabled();
+        let queue = &mut io_queue.queue;
         let token = queue
             .add_dma_buf(&[&req_slice], outputs.as_slice())
             .expect("add queue failed");
@@ -301,31 +382,8 @@ impl DeviceInner {
             outputs
         };
 
-        let num_used_descs = outputs.len() + 1;
-        // FIXME: Split the request if it is too big
-        if num_used_descs > Self::QUEUE_SIZE as usize {
-            panic!("The request size surpasses the queue size");
-        }
-
-        loop {
-            let mut queue = self.queue.lock_irq_disabled();
-            if num_used_descs > queue.available_desc() {
-                continue;
-            }
-            let token = queue
-                .add_dma_buf(&[&req_slice], outputs.as_slice())
-                .expect("add queue failed");
-            if queue.should_notify() {
-                queue.notify();
-            }
-
-            // Records the submitted request
-            let submitted_request = SubmittedRequest::new(id as u16, bio_request, dma_streams);
-            self.submitted_requests
-                .lock_irq_disabled()
-                .insert(token, submitted_request);
-            return;
-        }
+        let submitted_request = SubmittedRequest::new(id as u16, bio_request, dma_streams.clone());
+        self.submit(&[&req_slice], outputs.as_slice(), submitted_request);
     }
 
     /// Writes data to the device, this function is non-blocking.
@@ -362,28 +420,81 @@ impl DeviceInner {
             inputs
         };
 
-        let num_used_descs = inputs.len() + 1;
+        let submitted_request = SubmittedRequest::new(id as u16, bio_request, dma_streams.clone());
+        self.submit(inputs.as_slice(), &[&resp_slice], submitted_request);
+    }
+
+    /// Flushes the volatile write cache of the device, this function is non-blocking.
+    fn flush(&self, bio_request: BioRequest) {
+        if !self.supports_flush {
+            // The device writes through, so the completed writes are persistent already.
+            bio_request.bios().for_each(|bio| {
+                bio.complete(BioStatus::Complete);
+            });
+            return;
+        }
+
+        let id = self.id_allocator.lock_irq_disabled().alloc().unwrap();
+        let req_slice = {
+            let req_slice = DmaStreamSlice::new(&self.block_requests, id * REQ_SIZE, REQ_SIZE);
+            let req = BlockReq {
+                type_: ReqType::Flush as _,
+                reserved: 0,
+                sector: 0,
+            };
+            req_slice.write_val(0, &req).unwrap();
+            req_slice.sync().unwrap();
+            req_slice
+        };
+
+        let resp_slice = {
+            let resp_slice = DmaStreamSlice::new(&self.block_responses, id * RESP_SIZE, RESP_SIZE);
+            resp_slice.write_val(0, &BlockResp::default()).unwrap();
+            resp_slice
+        };
+
+        let submitted_request = SubmittedRequest::new(id as u16, bio_request, Vec::new());
+        self.submit(&[&req_slice], &[&resp_slice], submitted_request);
+    }
+
+    /// Submits the buffers of a request to the virtqueue of the current CPU, this function
+    /// is non-blocking.
+    ///
+    /// The buffers are added through an indirect descriptor table if possible, so that the
+    /// request takes only one descriptor no matter how many segments it has.
+    fn submit(
+        &self,
+        inputs: &[&DmaStreamSlice],
+        outputs: &[&DmaStreamSlice],
+        submitted_request: SubmittedRequest,
+    ) {
+        let num_bufs = inputs.len() + outputs.len();
         // FIXME: Split the request if it is too big
-        if num_used_descs > Self::QUEUE_SIZE as usize {
+        if num_bufs > self.max_nr_descs() {
             panic!("The request size surpasses the queue size");
         }
+        let is_indirect = self.supports_indirect && num_bufs > 1;
+        let num_used_descs = if is_indirect { 1 } else { num_bufs };
+
+        let io_queue = self.io_queue();
         loop {
-            let mut queue = self.queue.lock_irq_disabled();
+            let mut io_queue = io_queue.lock_irq_disabled();
+            let queue = &mut io_queue.queue;
             if num_used_descs > queue.available_desc() {
                 continue;
             }
-            let token = queue
-                .add_dma_buf(inputs.as_slice(), &[&resp_slice])
-                .expect("add queue failed");
+            let token = if is_indirect {
+                queue.add_dma_buf_indirect(inputs, outputs)
+            } else {
+                queue.add_dma_buf(inputs, outputs)
+            }
+            .expect("add queue failed");
             if queue.should_notify() {
                 queue.notify();
             }
 
             // Records the submitted request
-            let submitted_request = SubmittedRequest::new(id as u16, bio_request, dma_streams);
-            self.submitted_requests
-                .lock_irq_disabled()
-                .insert(token, submitted_request);
+            io_queue.submitted_requests.insert(token, submitted_request);
             return;
         }
     }
@@ -0,0 +1,47 @@
+// SPDX-License-Identifier: MPL-2.0
+
+//! The control messages of the multi-port console device.
+
+use int_to_c_enum::TryFromInt;
+use pod::Pod;
+
+/// A control message, which may be followed by the event-specific data.
+#[derive(Debug, Clone, Copy, Pod)]
+#[repr(C)]
+pub(super) struct ControlMessage {
+    pub id: u32,
+    pub event: u16,
+    pub value: u16,
+}
+
+pub(super) const CONTROL_MESSAGE_LEN: usize = core::mem::size_of::<ControlMessage>();
+
+#[repr(u16)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, TryFromInt)]
+#[allow(non_camel_case_types)]
+pub(super) enum ControlEvent {
+    /// The driver is ready to receive the control messages.
+    VIRTIO_CONSOLE_DEVICE_READY = 0,
+    /// A port is added.
+    VIRTIO_CONSOLE_DEVICE_ADD = 1,
+    /// A port is removed.
+    VIRTIO_CONSOLE_DEVICE_REMOVE = 2,
+    /// The driver is ready to use the port.
+    VIRTIO_CONSOLE_PORT_READY = 3,
+    /// The port is a console port.
+    VIRTIO_CONSOLE_CONSOLE_PORT = 4,
+    /// The size of the console port is changed, which is followed by the new size.
+    VIRTIO_CONSOLE_RESIZE = 5,
+    /// The port is opened or closed by the other side.
+    VIRTIO_CONSOLE_PORT_OPEN = 6,
+    /// The name of the port, which follows the message.
+    VIRTIO_CONSOLE_PORT_NAME = 7,
+}
+
+/// The size that follows a `VIRTIO_CONSOLE_RESIZE` message.
+#[derive(Debug, Clone, Copy, Pod)]
+#[repr(C)]
+pub(super) struct ConsoleSize {
+    pub rows: u16,
+    pub cols: u16,
+}
@@ -1,19 +1,32 @@
 // SPDX-License-Identifier: MPL-2.0
 
-use alloc::{boxed::Box, fmt::Debug, string::ToString, sync::Arc, vec::Vec};
-use core::hint::spin_loop;
+use alloc::{
+    boxed::Box,
+    fmt::Debug,
+    string::{String, ToString},
+    sync::Arc,
+    vec,
+    vec::Vec,
+};
+use core::{hint::spin_loop, mem::size_of};
 
-use aster_console::{AnyConsoleDevice, ConsoleCallback};
 use aster_frame::{
     io_mem::IoMem,
-    mm::{DmaDirection, DmaStream, DmaStreamSlice, FrameAllocOptions, VmReader},
-    sync::{RwLock, SpinLock},
+    mm::{DmaDirection, DmaStream, DmaStreamSlice, FrameAllocOptions, VmIo, PAGE_SIZE},
+    offset_of,
+    sync::SpinLock,
     trap::TrapFrame,
 };
-use aster_util::safe_ptr::SafePtr;
-use log::debug;
+use aster_util::{field_ptr, safe_ptr::SafePtr};
+use log::{debug, warn};
+use pod::Pod;
 
-use super::{config::VirtioConsoleConfig, DEVICE_NAME};
+use super::{
+    config::VirtioConsoleConfig,
+    control::{ConsoleSize, ControlEvent, ControlMessage, CONTROL_MESSAGE_LEN},
+    port::ConsolePort,
+    register_port, DEVICE_NAME,
+};
 use crate::{
     device::{console::config::ConsoleFeatures, VirtioDeviceError},
     queue::VirtQueue,
@@ -23,39 +36,24 @@ use crate::{
 pub struct ConsoleDevice {
     config: SafePtr<VirtioConsoleConfig, IoMem>,
     transport: SpinLock<Box<dyn VirtioTransport>>,
-    receive_queue: SpinLock<VirtQueue>,
+    /// The ports indexed by their IDs.
+    ports: Vec<Arc<ConsolePort>>,
+    /// The control queues, which exist only if the multi-port feature is negotiated.
+    control: Option<ControlQueues>,
+}
+
+struct ControlQueues {
+    receiver: SpinLock<ControlReceiver>,
     transmit_queue: SpinLock<VirtQueue>,
     send_buffer: DmaStream,
-    receive_buffer: DmaStream,
-    callbacks: RwLock<Vec<&'static ConsoleCallback>>,
 }
 
-impl AnyConsoleDevice for ConsoleDevice {
-    fn send(&self, value: &[u8]) {
-        let mut transmit_queue = self.transmit_queue.lock_irq_disabled();
-        let mut reader = VmReader::from(value);
-
-        while reader.remain() > 0 {
-            let mut writer = self.send_buffer.writer().unwrap();
-            let len = writer.write(&mut reader);
-            self.send_buffer.sync(0..len).unwrap();
-
-            let slice = DmaStreamSlice::new(&self.send_buffer, 0, len);
-            transmit_queue.add_dma_buf(&[&slice], &[]).unwrap();
-
-            if transmit_queue.should_notify() {
-                transmit_queue.notify();
-            }
-            while !transmit_queue.can_pop() {
-                spin_loop();
-            }
-            transmit_queue.pop_used().unwrap();
-        }
-    }
-
-    fn register_callback(&self, callback: &'static ConsoleCallback) {
-        self.callbacks.write_irq_disabled().push(callback);
-    }
+struct ControlReceiver {
+    queue: VirtQueue,
+    /// The buffers of the control messages, which are divided into slots.
+    buffers: DmaStream,
+    /// The slots of the buffers indexed by the tokens of the queue.
+    slots: Vec<Option<usize>>,
 }
 
 impl Debug for ConsoleDevice {
@@ -63,98 +61,279 @@ impl Debug for ConsoleDevice {
         f.debug_struct("ConsoleDevice")
             .field("config", &self.config)
             .field("transport", &self.transport)
-            .field("receive_queue", &self.receive_queue)
-            .field("transmit_queue", &self.transmit_queue)
+            .field("ports", &self.ports)
             .finish()
     }
 }
 
 impl ConsoleDevice {
     pub fn negotiate_features(features: u64) -> u64 {
-        let mut features = ConsoleFeatures::from_bits_truncate(features);
-        // A virtio console device may have multiple ports, but we only use one port to communicate now.
-        features.remove(ConsoleFeatures::VIRTIO_CONSOLE_F_MULTIPORT);
+        let features = ConsoleFeatures::from_bits_truncate(features);
         features.bits()
     }
 
     pub fn init(mut transport: Box<dyn VirtioTransport>) -> Result<(), VirtioDeviceError> {
         let config = VirtioConsoleConfig::new(transport.as_ref());
-        const RECV0_QUEUE_INDEX: u16 = 0;
-        const TRANSMIT0_QUEUE_INDEX: u16 = 1;
-        let receive_queue =
-            SpinLock::new(VirtQueue::new(RECV0_QUEUE_INDEX, 2, transport.as_mut()).unwrap());
-        let transmit_queue =
-            SpinLock::new(VirtQueue::new(TRANSMIT0_QUEUE_INDEX, 2, transport.as_mut()).unwrap());
-
-        let send_buffer = {
-            let vm_segment = FrameAllocOptions::new(1).alloc_contiguous().unwrap();
-            DmaStream::map(vm_segment, DmaDirection::ToDevice, false).unwrap()
+        let features = ConsoleFeatures::from_bits_truncate(Self::negotiate_features(
+            transport.device_features(),
+        ));
+        let supports_multiport = features.contains(ConsoleFeatures::VIRTIO_CONSOLE_F_MULTIPORT);
+        let nr_ports = if supports_multiport {
+            let max_nr_ports = field_ptr!(&config, VirtioConsoleConfig, max_nr_ports)
+                .read()
+                .unwrap();
+            max_nr_ports.clamp(1, MAX_NR_PORTS)
+        } else {
+            1
         };
+        debug!("features = {:?}, nr_ports = {}", features, nr_ports);
 
-        let receive_buffer = {
-            let vm_segment = FrameAllocOptions::new(1).alloc_contiguous().unwrap();
-            DmaStream::map(vm_segment, DmaDirection::FromDevice, false).unwrap()
-        };
+        // The queues of port 0 are followed by the control queues, and then the queues of
+        // the other ports.
+        let ports: Vec<_> = (0..nr_ports)
+            .map(|id| {
+                let receive_queue_index = receive_queue_index(id);
+                let receive_queue =
+                    VirtQueue::new(receive_queue_index, 2, transport.as_mut()).unwrap();
+                let transmit_queue =
+                    VirtQueue::new(receive_queue_index + 1, 2, transport.as_mut()).unwrap();
+                Arc::new(ConsolePort::new(id, receive_queue, transmit_queue))
+            })
+            .collect();
+        let control = supports_multiport.then(|| ControlQueues::new(transport.as_mut()));
 
         let device = Arc::new(Self {
             config,
             transport: SpinLock::new(transport),
-            receive_queue,
-            transmit_queue,
-            send_buffer,
-            receive_buffer,
-            callbacks: RwLock::new(Vec::new()),
+            ports,
+            control,
         });
 
-        let mut receive_queue = device.receive_queue.lock_irq_disabled();
-        receive_queue
-            .add_dma_buf(&[], &[&device.receive_buffer])
-            .unwrap();
-        if receive_queue.should_notify() {
-            receive_queue.notify();
-        }
-        drop(receive_queue);
-
         // Register irq callbacks
         let mut transport = device.transport.lock_irq_disabled();
-        let handle_console_input = {
-            let device = device.clone();
-            move |_: &TrapFrame| device.handle_recv_irq()
-        };
-        transport
-            .register_queue_callback(RECV0_QUEUE_INDEX, Box::new(handle_console_input), false)
-            .unwrap();
+        for port in device.ports.iter() {
+            let handle_console_input = {
+                let port = port.clone();
+                move |_: &TrapFrame| port.handle_recv_irq()
+            };
+            transport
+                .register_queue_callback(
+                    receive_queue_index(port.id()),
+                    Box::new(handle_console_input),
+                    false,
+                )
+                .unwrap();
+        }
+        if device.control.is_some() {
+            let handle_control_message = {
+                let device = device.clone();
+                move |_: &TrapFrame| device.handle_control_irq()
+            };
+            transport
+                .register_queue_callback(
+                    CONTROL_RECEIVE_QUEUE_INDEX,
+                    Box::new(handle_control_message),
+                    false,
+                )
+                .unwrap();
+        }
         transport
             .register_cfg_callback(Box::new(config_space_change))
             .unwrap();
         transport.finish_init();
         drop(transport);
 
-        aster_console::register_device(DEVICE_NAME.to_string(), device);
+        if device.control.is_some() {
+            // The device adds the ports after the driver is ready.
+            device.send_control_message(0, ControlEvent::VIRTIO_CONSOLE_DEVICE_READY, 1);
+        } else {
+            let port = &device.ports[0];
+            port.set_added(true);
+            port.set_console();
+            port.set_host_connected(true);
+        }
+
+        // Port 0 is used to print the kernel messages, as before the multi-port support.
+        aster_console::register_device(DEVICE_NAME.to_string(), device.ports[0].clone());
+        for port in device.ports.iter() {
+            register_port(port.clone());
+        }
 
         Ok(())
     }
 
-    fn handle_recv_irq(&self) {
-        let mut receive_queue = self.receive_queue.lock_irq_disabled();
-        if !receive_queue.can_pop() {
+    fn handle_control_irq(&self) {
+        let control = self.control.as_ref().unwrap();
+        let mut receiver = control.receiver.lock_irq_disabled();
+        let receiver = &mut *receiver;
+
+        while receiver.queue.can_pop() {
+            let (token, len) = receiver.queue.pop_used().unwrap();
+            let slot = receiver.slots[token as usize].take().unwrap();
+            let slot_slice = DmaStreamSlice::new(
+                &receiver.buffers,
+                slot * CONTROL_BUFFER_LEN,
+                CONTROL_BUFFER_LEN,
+            );
+            slot_slice.sync().unwrap();
+
+            let len = (len as usize).min(CONTROL_BUFFER_LEN);
+            if len >= CONTROL_MESSAGE_LEN {
+                let message: ControlMessage = slot_slice.read_val(0).unwrap();
+                let mut data = vec![0u8; len - CONTROL_MESSAGE_LEN];
+                slot_slice
+                    .read_bytes(CONTROL_MESSAGE_LEN, &mut data)
+                    .unwrap();
+                self.handle_control_message(message, &data);
+            }
+
+            let token = receiver.queue.add_dma_buf(&[], &[&slot_slice]).unwrap();
+            receiver.slots[token as usize] = Some(slot);
+        }
+        if receiver.queue.should_notify() {
+            receiver.queue.notify();
+        }
+    }
+
+    fn handle_control_message(&self, message: ControlMessage, data: &[u8]) {
+        debug!("console control message: {:?}", message);
+        let Ok(event) = ControlEvent::try_from(message.event) else {
+            warn!("unknown console control event: {}", message.event);
             return;
+        };
+        let port = self.ports.get(message.id as usize);
+
+        match event {
+            ControlEvent::VIRTIO_CONSOLE_DEVICE_ADD => {
+                let Some(port) = port else {
+                    // The driver cannot use the ports beyond `MAX_NR_PORTS`.
+                    self.send_control_message(
+                        message.id,
+                        ControlEvent::VIRTIO_CONSOLE_PORT_READY,
+                        0,
+                    );
+                    return;
+                };
+                port.set_added(true);
+                self.send_control_message(message.id, ControlEvent::VIRTIO_CONSOLE_PORT_READY, 1);
+                // The ports are always opened by the driver, since they are exposed as TTYs.
+                self.send_control_message(message.id, ControlEvent::VIRTIO_CONSOLE_PORT_OPEN, 1);
+            }
+            ControlEvent::VIRTIO_CONSOLE_DEVICE_REMOVE => {
+                if let Some(port) = port {
+                    port.set_added(false);
+                }
+            }
+            ControlEvent::VIRTIO_CONSOLE_CONSOLE_PORT => {
+                if let Some(port) = port {
+                    port.set_console();
+                }
+            }
+            ControlEvent::VIRTIO_CONSOLE_PORT_OPEN => {
+                if let Some(port) = port {
+                    port.set_host_connected(message.value != 0);
+                }
+            }
+            ControlEvent::VIRTIO_CONSOLE_PORT_NAME => {
+                if let Some(port) = port {
+                    let name = data.split(|byte| *byte == 0).next().unwrap_or_default();
+                    port.set_name(String::from_utf8_lossy(name).into_owned());
+                }
+            }
+            ControlEvent::VIRTIO_CONSOLE_RESIZE => {
+                if let Some(bytes) = data.get(..size_of::<ConsoleSize>()) {
+                    let size = ConsoleSize::from_bytes(bytes);
+                    debug!("console port {} is resized: {:?}", message.id, size);
+                }
+            }
+            ControlEvent::VIRTIO_CONSOLE_DEVICE_READY | ControlEvent::VIRTIO_CONSOLE_PORT_READY => {
+                warn!("unexpected console control event: {:?}", event);
+            }
         }
-        let (_, len) = receive_queue.pop_used().unwrap();
-        self.receive_buffer.sync(0..len as usize).unwrap();
+    }
 
-        let callbacks = self.callbacks.read_irq_disabled();
+    /// Sends a control message. Return until the device receives it.
+    fn send_control_message(&self, id: u32, event: ControlEvent, value: u16) {
+        let control = self.control.as_ref().unwrap();
+        let mut transmit_queue = control.transmit_queue.lock_irq_disabled();
+
+        let message = ControlMessage {
+            id,
+            event: event as u16,
+            value,
+        };
+        let slice = DmaStreamSlice::new(&control.send_buffer, 0, CONTROL_MESSAGE_LEN);
+        slice.write_val(0, &message).unwrap();
+        slice.sync().unwrap();
 
-        for callback in callbacks.iter() {
-            let reader = self.receive_buffer.reader().unwrap().limit(len as usize);
-            callback(reader);
+        transmit_queue.add_dma_buf(&[&slice], &[]).unwrap();
+        if transmit_queue.should_notify() {
+            transmit_queue.notify();
         }
-        receive_queue
-            .add_dma_buf(&[], &[&self.receive_buffer])
-            .unwrap();
-        if receive_queue.should_notify() {
-            receive_queue.notify();
+        while !transmit_queue.can_pop() {
+            spin_loop();
         }
+        transmit_queue.pop_used().unwrap();
+    }
+}
+
+impl ControlQueues {
+    fn new(transport: &mut dyn VirtioTransport) -> Self {
+        let mut queue = VirtQueue::new(
+            CONTROL_RECEIVE_QUEUE_INDEX,
+            CONTROL_QUEUE_SIZE as u16,
+            transport,
+        )
+        .unwrap();
+        let transmit_queue = VirtQueue::new(CONTROL_RECEIVE_QUEUE_INDEX + 1, 2, transport).unwrap();
+
+        let buffers = {
+            let nframes = (CONTROL_QUEUE_SIZE * CONTROL_BUFFER_LEN).div_ceil(PAGE_SIZE);
+            let vm_segment = FrameAllocOptions::new(nframes).alloc_contiguous().unwrap();
+            DmaStream::map(vm_segment, DmaDirection::FromDevice, false).unwrap()
+        };
+        let mut slots = vec![None; CONTROL_QUEUE_SIZE];
+        for slot in 0..CONTROL_QUEUE_SIZE {
+            let slot_slice =
+                DmaStreamSlice::new(&buffers, slot * CONTROL_BUFFER_LEN, CONTROL_BUFFER_LEN);
+            let token = queue.add_dma_buf(&[], &[&slot_slice]).unwrap();
+            slots[token as usize] = Some(slot);
+        }
+        if queue.should_notify() {
+            queue.notify();
+        }
+
+        let send_buffer = {
+            let vm_segment = FrameAllocOptions::new(1).alloc_contiguous().unwrap();
+            DmaStream::map(vm_segment, DmaDirection::ToDevice, false).unwrap()
+        };
+
+        Self {
+            receiver: SpinLock::new(ControlReceiver {
+                queue,
+                buffers,
+                slots,
+            }),
+            transmit_queue: SpinLock::new(transmit_queue),
+            send_buffer,
+        }
+    }
+}
+
+/// The maximum number of ports used by the driver.
+const MAX_NR_PORTS: u32 = 8;
+
+const CONTROL_RECEIVE_QUEUE_INDEX: u16 = 2;
+const CONTROL_QUEUE_SIZE: usize = 8;
+const CONTROL_BUFFER_LEN: usize = 512;
+
+/// Returns the index of the receive queue of the port, which is followed by the transmit
+/// queue.
+fn receive_queue_index(port_id: u32) -> u16 {
+    if port_id == 0 {
+        0
+    } else {
+        (port_id as u16 + 1) * 2
     }
 }
 
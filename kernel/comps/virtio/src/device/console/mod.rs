@@ -1,6 +1,25 @@
 // SPDX-License-Identifier: MPL-2.0
 
+use alloc::{sync::Arc, vec::Vec};
+
+use aster_frame::sync::SpinLock;
+
+use self::port::ConsolePort;
+
 pub mod config;
+mod control;
 pub mod device;
+pub mod port;
 
 pub static DEVICE_NAME: &str = "Virtio-Console";
+
+/// The ports of all console devices, in the order of their registrations.
+static PORT_TABLE: SpinLock<Vec<Arc<ConsolePort>>> = SpinLock::new(Vec::new());
+
+pub fn register_port(port: Arc<ConsolePort>) {
+    PORT_TABLE.lock_irq_disabled().push(port);
+}
+
+pub fn all_ports() -> Vec<Arc<ConsolePort>> {
+    PORT_TABLE.lock_irq_disabled().clone()
+}
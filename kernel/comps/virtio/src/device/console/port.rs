@@ -0,0 +1,183 @@
+// SPDX-License-Identifier: MPL-2.0
+
+use alloc::{fmt::Debug, string::String, vec::Vec};
+use core::{
+    hint::spin_loop,
+    sync::atomic::{AtomicBool, Ordering},
+};
+
+use aster_console::{AnyConsoleDevice, ConsoleCallback};
+use aster_frame::{
+    mm::{DmaDirection, DmaStream, DmaStreamSlice, FrameAllocOptions, VmReader},
+    sync::{RwLock, SpinLock},
+};
+
+use crate::queue::VirtQueue;
+
+/// A port of the console device, which has its own receive queue and transmit queue.
+///
+/// A device without the multi-port feature has only one port, i.e., port 0.
+pub struct ConsolePort {
+    id: u32,
+    receive_queue: SpinLock<VirtQueue>,
+    transmit_queue: SpinLock<VirtQueue>,
+    send_buffer: DmaStream,
+    receive_buffer: DmaStream,
+    callbacks: RwLock<Vec<&'static ConsoleCallback>>,
+    /// Whether the port is added by the device.
+    is_added: AtomicBool,
+    /// Whether the port is a console port.
+    is_console: AtomicBool,
+    /// Whether the other side opens the port.
+    is_host_connected: AtomicBool,
+    name: SpinLock<Option<String>>,
+}
+
+impl ConsolePort {
+    pub(super) fn new(id: u32, receive_queue: VirtQueue, transmit_queue: VirtQueue) -> Self {
+        let send_buffer = {
+            let vm_segment = FrameAllocOptions::new(1).alloc_contiguous().unwrap();
+            DmaStream::map(vm_segment, DmaDirection::ToDevice, false).unwrap()
+        };
+
+        let receive_buffer = {
+            let vm_segment = FrameAllocOptions::new(1).alloc_contiguous().unwrap();
+            DmaStream::map(vm_segment, DmaDirection::FromDevice, false).unwrap()
+        };
+
+        let port = Self {
+            id,
+            receive_queue: SpinLock::new(receive_queue),
+            transmit_queue: SpinLock::new(transmit_queue),
+            send_buffer,
+            receive_buffer,
+            callbacks: RwLock::new(Vec::new()),
+            is_added: AtomicBool::new(false),
+            is_console: AtomicBool::new(false),
+            is_host_connected: AtomicBool::new(false),
+            name: SpinLock::new(None),
+        };
+
+        let mut receive_queue = port.receive_queue.lock_irq_disabled();
+        receive_queue
+            .add_dma_buf(&[], &[&port.receive_buffer])
+            .unwrap();
+        if receive_queue.should_notify() {
+            receive_queue.notify();
+        }
+        drop(receive_queue);
+
+        port
+    }
+
+    /// Returns the ID of the port.
+    pub fn id(&self) -> u32 {
+        self.id
+    }
+
+    /// Returns whether the port is added by the device.
+    pub fn is_added(&self) -> bool {
+        self.is_added.load(Ordering::Relaxed)
+    }
+
+    /// Returns whether the port is a console port, e.g., `hvc0` of the host.
+    pub fn is_console(&self) -> bool {
+        self.is_console.load(Ordering::Relaxed)
+    }
+
+    /// Returns whether the other side opens the port.
+    pub fn is_host_connected(&self) -> bool {
+        self.is_host_connected.load(Ordering::Relaxed)
+    }
+
+    /// Returns the name of the port given by the device.
+    pub fn name(&self) -> Option<String> {
+        self.name.lock_irq_disabled().clone()
+    }
+
+    pub(super) fn set_added(&self, is_added: bool) {
+        self.is_added.store(is_added, Ordering::Relaxed);
+        if !is_added {
+            self.is_console.store(false, Ordering::Relaxed);
+            self.is_host_connected.store(false, Ordering::Relaxed);
+            *self.name.lock_irq_disabled() = None;
+        }
+    }
+
+    pub(super) fn set_console(&self) {
+        self.is_console.store(true, Ordering::Relaxed);
+    }
+
+    pub(super) fn set_host_connected(&self, is_host_connected: bool) {
+        self.is_host_connected
+            .store(is_host_connected, Ordering::Relaxed);
+    }
+
+    pub(super) fn set_name(&self, name: String) {
+        *self.name.lock_irq_disabled() = Some(name);
+    }
+
+    pub(super) fn handle_recv_irq(&self) {
+        let mut receive_queue = self.receive_queue.lock_irq_disabled();
+        if !receive_queue.can_pop() {
+            return;
+        }
+        let (_, len) = receive_queue.pop_used().unwrap();
+        self.receive_buffer.sync(0..len as usize).unwrap();
+
+        let callbacks = self.callbacks.read_irq_disabled();
+
+        for callback in callbacks.iter() {
+            let reader = self.receive_buffer.reader().unwrap().limit(len as usize);
+            callback(reader);
+        }
+        receive_queue
+            .add_dma_buf(&[], &[&self.receive_buffer])
+            .unwrap();
+        if receive_queue.should_notify() {
+            receive_queue.notify();
+        }
+    }
+}
+
+impl AnyConsoleDevice for ConsolePort {
+    fn send(&self, value: &[u8]) {
+        let mut transmit_queue = self.transmit_queue.lock_irq_disabled();
+        let mut reader = VmReader::from(value);
+
+        while reader.remain() > 0 {
+            let mut writer = self.send_buffer.writer().unwrap();
+            let len = writer.write(&mut reader);
+            self.send_buffer.sync(0..len).unwrap();
+
+            let slice = DmaStreamSlice::new(&self.send_buffer, 0, len);
+            transmit_queue.add_dma_buf(&[&slice], &[]).unwrap();
+
+            if transmit_queue.should_notify() {
+                transmit_queue.notify();
+            }
+            while !transmit_queue.can_pop() {
+                spin_loop();
+            }
+            transmit_queue.pop_used().unwrap();
+        }
+    }
+
+    fn register_callback(&self, callback: &'static ConsoleCallback) {
+        self.callbacks.write_irq_disabled().push(callback);
+    }
+}
+
+impl Debug for ConsolePort {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        f.debug_struct("ConsolePort")
+            .field("id", &self.id)
+            .field("receive_queue", &self.receive_queue)
+            .field("transmit_queue", &self.transmit_queue)
+            .field("is_added", &self.is_added)
+            .field("is_console", &self.is_console)
+            .field("is_host_connected", &self.is_host_connected)
+            .field("name", &self.name)
+            .finish()
+    }
+}
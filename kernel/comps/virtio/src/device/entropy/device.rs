@@ -0,0 +1,87 @@
+// SPDX-License-Identifier: MPL-2.0
+
+use alloc::{boxed::Box, sync::Arc};
+use core::{fmt::Debug, hint::spin_loop};
+
+use aster_frame::{
+    mm::{DmaDirection, DmaStream, DmaStreamSlice, FrameAllocOptions, VmIo, PAGE_SIZE},
+    sync::{Mutex, SpinLock},
+};
+use log::info;
+
+use super::register_device;
+use crate::{device::VirtioDeviceError, queue::VirtQueue, transport::VirtioTransport};
+
+const REQUEST_QUEUE_INDEX: u16 = 0;
+
+/// A virtio entropy device.
+pub struct EntropyDevice {
+    request_queue: SpinLock<VirtQueue>,
+    /// The buffer that the device fills with the random bytes. The lock also serializes
+    /// the requests.
+    buffer: Mutex<DmaStream>,
+    transport: SpinLock<Box<dyn VirtioTransport>>,
+}
+
+impl Debug for EntropyDevice {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        f.debug_struct("EntropyDevice")
+            .field("transport", &self.transport)
+            .finish()
+    }
+}
+
+impl EntropyDevice {
+    pub fn negotiate_features(features: u64) -> u64 {
+        // The device has no feature bits.
+        features
+    }
+
+    pub fn init(mut transport: Box<dyn VirtioTransport>) -> Result<(), VirtioDeviceError> {
+        let request_queue = VirtQueue::new(REQUEST_QUEUE_INDEX, 1, transport.as_mut())?;
+        let buffer = {
+            let vm_segment = FrameAllocOptions::new(1).alloc_contiguous().unwrap();
+            DmaStream::map(vm_segment, DmaDirection::FromDevice, false).unwrap()
+        };
+        transport.finish_init();
+
+        let device = Arc::new(Self {
+            request_queue: SpinLock::new(request_queue),
+            buffer: Mutex::new(buffer),
+            transport: SpinLock::new(transport),
+        });
+        info!("[Virtio-Entropy]: device initialized");
+        register_device(device);
+        Ok(())
+    }
+
+    /// Fills `buf` with the random bytes from the device, and returns when the device
+    /// has processed the request.
+    ///
+    /// The device may provide fewer bytes than requested. Returns the number of the bytes
+    /// that are filled.
+    pub fn read(&self, buf: &mut [u8]) -> usize {
+        let len = buf.len().min(PAGE_SIZE);
+        if len == 0 {
+            return 0;
+        }
+
+        let buffer = self.buffer.lock();
+        let mut queue = self.request_queue.lock_irq_disabled();
+        let slice = DmaStreamSlice::new(&*buffer, 0, len);
+        queue.add_dma_buf(&[], &[&slice]).unwrap();
+        if queue.should_notify() {
+            queue.notify();
+        }
+        while !queue.can_pop() {
+            spin_loop();
+        }
+        let (_, nr_filled) = queue.pop_used().unwrap();
+        drop(queue);
+
+        let nr_filled = (nr_filled as usize).min(len);
+        buffer.sync(0..nr_filled).unwrap();
+        buffer.read_bytes(0, &mut buf[..nr_filled]).unwrap();
+        nr_filled
+    }
+}
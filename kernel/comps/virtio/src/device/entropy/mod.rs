@@ -0,0 +1,28 @@
+// SPDX-License-Identifier: MPL-2.0
+
+//! The virtio entropy device, which provides the random bytes from the entropy source of
+//! the host to the guest.
+//!
+//! The driver only fetches the random bytes. How they are mixed into the entropy pool is
+//! decided by the kernel.
+
+use alloc::{sync::Arc, vec::Vec};
+
+use aster_frame::sync::SpinLock;
+
+use self::device::EntropyDevice;
+
+pub mod device;
+
+pub static DEVICE_NAME: &str = "Virtio-Entropy";
+
+/// The entropy devices, in the order of their registrations.
+static DEVICE_TABLE: SpinLock<Vec<Arc<EntropyDevice>>> = SpinLock::new(Vec::new());
+
+pub fn register_device(device: Arc<EntropyDevice>) {
+    DEVICE_TABLE.lock_irq_disabled().push(device);
+}
+
+pub fn all_devices() -> Vec<Arc<EntropyDevice>> {
+    DEVICE_TABLE.lock_irq_disabled().clone()
+}
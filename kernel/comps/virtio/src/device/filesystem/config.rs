@@ -0,0 +1,35 @@
+// SPDX-License-Identifier: MPL-2.0
+
+use aster_frame::io_mem::IoMem;
+use aster_util::safe_ptr::SafePtr;
+use pod::Pod;
+
+use crate::transport::VirtioTransport;
+
+/// The length of the tag of a virtio-fs device.
+pub const TAG_LEN: usize = 36;
+
+bitflags::bitflags! {
+    pub struct FileSystemFeatures: u64 {
+        /// The device supports the notification queue.
+        const VIRTIO_FS_F_NOTIFICATION = 1 << 0;
+    }
+}
+
+#[derive(Debug, Pod, Clone, Copy)]
+#[repr(C)]
+pub struct VirtioFileSystemConfig {
+    /// The name of the file system, which is encoded in UTF-8 and padded with NUL bytes.
+    pub tag: [u8; TAG_LEN],
+    /// The number of the request queues.
+    pub num_request_queues: u32,
+    /// The size of the notification buffers.
+    pub notify_buf_size: u32,
+}
+
+impl VirtioFileSystemConfig {
+    pub(super) fn new(transport: &dyn VirtioTransport) -> SafePtr<Self, IoMem> {
+        let memory = transport.device_config_memory();
+        SafePtr::new(memory, 0)
+    }
+}
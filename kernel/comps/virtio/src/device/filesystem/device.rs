@@ -0,0 +1,241 @@
+// SPDX-License-Identifier: MPL-2.0
+
+use alloc::{boxed::Box, collections::BTreeMap, string::String, sync::Arc};
+use core::{
+    fmt::Debug,
+    sync::atomic::{AtomicU64, Ordering},
+};
+
+use aster_frame::{
+    mm::{DmaDirection, DmaStream, DmaStreamSlice, FrameAllocOptions, VmIo, PAGE_SIZE},
+    sync::{SpinLock, WaitQueue},
+    trap::TrapFrame,
+};
+use log::{debug, info};
+
+use super::{
+    config::{VirtioFileSystemConfig, TAG_LEN},
+    register_device,
+};
+use crate::{device::VirtioDeviceError, queue::VirtQueue, transport::VirtioTransport};
+
+const QUEUE_SIZE: u16 = 64;
+/// The queue of the high-priority requests, e.g., `FUSE_FORGET`.
+const QUEUE_HIPRIO: u16 = 0;
+/// The first request queue. Only this one is used.
+const QUEUE_REQUEST: u16 = 1;
+
+/// A virtio-fs device.
+pub struct FileSystemDevice {
+    tag: String,
+    hiprio_queue: SpinLock<VirtQueue>,
+    request_queue: SpinLock<VirtQueue>,
+    transport: SpinLock<Box<dyn VirtioTransport>>,
+    /// The IDs of the requests that are being processed by the device, indexed by the queues
+    /// and the tokens of the requests.
+    ///
+    /// A token can be reused as soon as its request is completed, so the requests are
+    /// identified by the IDs instead.
+    submitted_requests: SpinLock<BTreeMap<(u16, u16), u64>>,
+    /// The lengths of the replies of the completed requests, indexed by the request IDs.
+    completed_requests: SpinLock<BTreeMap<u64, usize>>,
+    next_request_id: AtomicU64,
+    wait_queue: WaitQueue,
+}
+
+impl FileSystemDevice {
+    /// Negotiates features for the device specified bits 0~23.
+    pub(crate) fn negotiate_features(_features: u64) -> u64 {
+        // The notification queue is not supported.
+        0
+    }
+
+    /// Creates a new virtio-fs driver and registers it.
+    pub(crate) fn init(mut transport: Box<dyn VirtioTransport>) -> Result<(), VirtioDeviceError> {
+        let config = VirtioFileSystemConfig::new(transport.as_mut())
+            .read()
+            .unwrap();
+        let tag = {
+            let len = config.tag.iter().position(|&b| b == 0).unwrap_or(TAG_LEN);
+            String::from_utf8_lossy(&config.tag[..len]).into_owned()
+        };
+        info!(
+            "[Virtio-FS]: tag = {}, num_request_queues = {}",
+            tag, config.num_request_queues
+        );
+
+        let num_queues = transport.num_queues();
+        if num_queues < QUEUE_REQUEST + 1 {
+            return Err(VirtioDeviceError::QueuesAmountDoNotMatch(
+                num_queues,
+                QUEUE_REQUEST + 1,
+            ));
+        }
+        let hiprio_queue = VirtQueue::new(QUEUE_HIPRIO, QUEUE_SIZE, transport.as_mut())
+            .expect("creating hiprio queue fails");
+        let request_queue = VirtQueue::new(QUEUE_REQUEST, QUEUE_SIZE, transport.as_mut())
+            .expect("creating request queue fails");
+
+        let device = Arc::new(Self {
+            tag: tag.clone(),
+            hiprio_queue: SpinLock::new(hiprio_queue),
+            request_queue: SpinLock::new(request_queue),
+            transport: SpinLock::new(transport),
+            submitted_requests: SpinLock::new(BTreeMap::new()),
+            completed_requests: SpinLock::new(BTreeMap::new()),
+            next_request_id: AtomicU64::new(0),
+            wait_queue: WaitQueue::new(),
+        });
+
+        let cloned_device = device.clone();
+        let handle_hiprio_irq = move |_: &TrapFrame| {
+            cloned_device.handle_irq(QUEUE_HIPRIO);
+        };
+        let cloned_device = device.clone();
+        let handle_request_irq = move |_: &TrapFrame| {
+            cloned_device.handle_irq(QUEUE_REQUEST);
+        };
+        let handle_config_change = |_: &TrapFrame| {
+            debug!("virtio-fs device config space change");
+        };
+
+        {
+            let mut transport = device.transport.lock();
+            transport
+                .register_cfg_callback(Box::new(handle_config_change))
+                .unwrap();
+            transport
+                .register_queue_callback(QUEUE_HIPRIO, Box::new(handle_hiprio_irq), false)
+                .unwrap();
+            transport
+                .register_queue_callback(QUEUE_REQUEST, Box::new(handle_request_irq), false)
+                .unwrap();
+            transport.finish_init();
+        }
+
+        register_device(tag, device);
+        Ok(())
+    }
+
+    /// Returns the tag of the device.
+    pub fn tag(&self) -> &str {
+        &self.tag
+    }
+
+    /// Sends a request, which is the concatenation of the parts in `request`, and waits for
+    /// the reply to be written to `reply`.
+    ///
+    /// Returns the length of the reply.
+    pub fn request(&self, request: &[&[u8]], reply: &mut [u8]) -> usize {
+        let (request_stream, request_len) = Self::map_request(request);
+        let request_slice = DmaStreamSlice::new(&request_stream, 0, request_len);
+        let reply_stream = Self::alloc_stream(reply.len(), DmaDirection::FromDevice);
+        let reply_slice = DmaStreamSlice::new(&reply_stream, 0, reply.len());
+
+        let reply_len = self
+            .submit_and_wait(QUEUE_REQUEST, &[&request_slice], &[&reply_slice])
+            .min(reply.len());
+        reply_slice.sync().unwrap();
+        reply_stream.read_bytes(0, &mut reply[..reply_len]).unwrap();
+        reply_len
+    }
+
+    /// Sends a high-priority request that has no reply, e.g., `FUSE_FORGET`.
+    pub fn request_without_reply(&self, request: &[&[u8]]) {
+        let (request_stream, request_len) = Self::map_request(request);
+        let request_slice = DmaStreamSlice::new(&request_stream, 0, request_len);
+        // The buffer is kept alive until the device has read it.
+        self.submit_and_wait(QUEUE_HIPRIO, &[&request_slice], &[]);
+    }
+
+    fn submit_and_wait(
+        &self,
+        queue_idx: u16,
+        inputs: &[&DmaStreamSlice],
+        outputs: &[&DmaStreamSlice],
+    ) -> usize {
+        let queue = match queue_idx {
+            QUEUE_HIPRIO => &self.hiprio_queue,
+            _ => &self.request_queue,
+        };
+        let num_used_descs = inputs.len() + outputs.len();
+        let request_id = self.next_request_id.fetch_add(1, Ordering::Relaxed);
+        loop {
+            let mut queue = queue.lock_irq_disabled();
+            if num_used_descs > queue.available_desc() {
+                continue;
+            }
+            let token = queue
+                .add_dma_buf(inputs, outputs)
+                .expect("add queue failed");
+            // Record the request before the device can complete it.
+            self.submitted_requests
+                .lock_irq_disabled()
+                .insert((queue_idx, token), request_id);
+            if queue.should_notify() {
+                queue.notify();
+            }
+            break;
+        }
+
+        self.wait_queue.wait_until(|| {
+            self.completed_requests
+                .lock_irq_disabled()
+                .remove(&request_id)
+        })
+    }
+
+    /// Handles the completed requests in a queue.
+    fn handle_irq(&self, queue_idx: u16) {
+        let queue = match queue_idx {
+            QUEUE_HIPRIO => &self.hiprio_queue,
+            _ => &self.request_queue,
+        };
+        // When we enter the IRQs handling function,
+        // IRQs have already been disabled,
+        // so there is no need to call `lock_irq_disabled`.
+        {
+            let mut queue = queue.lock();
+            while let Ok((token, len)) = queue.pop_used() {
+                let Some(request_id) = self.submitted_requests.lock().remove(&(queue_idx, token))
+                else {
+                    continue;
+                };
+                self.completed_requests
+                    .lock()
+                    .insert(request_id, len as usize);
+            }
+        }
+        self.wait_queue.wake_all();
+    }
+
+    /// Copies the parts of a request into a DMA stream, and returns the stream with the
+    /// length of the request.
+    fn map_request(request: &[&[u8]]) -> (DmaStream, usize) {
+        let len = request.iter().map(|part| part.len()).sum();
+        let stream = Self::alloc_stream(len, DmaDirection::ToDevice);
+        let mut offset = 0;
+        for part in request {
+            stream.write_bytes(offset, part).unwrap();
+            offset += part.len();
+        }
+        stream.sync(0..len).unwrap();
+        (stream, len)
+    }
+
+    fn alloc_stream(len: usize, direction: DmaDirection) -> DmaStream {
+        let segment = FrameAllocOptions::new(len.max(1).div_ceil(PAGE_SIZE))
+            .uninit(true)
+            .alloc_contiguous()
+            .unwrap();
+        DmaStream::map(segment, direction, false).unwrap()
+    }
+}
+
+impl Debug for FileSystemDevice {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        f.debug_struct("FileSystemDevice")
+            .field("tag", &self.tag)
+            .finish()
+    }
+}
@@ -0,0 +1,54 @@
+// SPDX-License-Identifier: MPL-2.0
+
+//! The virtio-fs device, which exports a file system of the host through the FUSE protocol.
+//!
+//! The device only transports the FUSE requests and replies. The FUSE protocol itself is
+//! spoken by the file system in the kernel.
+
+use alloc::{collections::BTreeMap, string::String, sync::Arc, vec::Vec};
+
+use aster_frame::sync::SpinLock;
+use spin::Once;
+
+use self::device::FileSystemDevice;
+
+pub mod config;
+pub mod device;
+
+pub static DEVICE_NAME: &str = "Virtio-FS";
+
+/// Registers a virtio-fs device by its tag.
+pub fn register_device(tag: String, device: Arc<FileSystemDevice>) {
+    FILE_SYSTEM_DEVICE_TABLE
+        .get()
+        .unwrap()
+        .lock_irq_disabled()
+        .insert(tag, device);
+}
+
+/// Gets the virtio-fs device with the tag, which is the "device name" when mounting it.
+pub fn get_device(tag: &str) -> Option<Arc<FileSystemDevice>> {
+    FILE_SYSTEM_DEVICE_TABLE
+        .get()
+        .unwrap()
+        .lock_irq_disabled()
+        .get(tag)
+        .cloned()
+}
+
+pub fn all_devices() -> Vec<(String, Arc<FileSystemDevice>)> {
+    FILE_SYSTEM_DEVICE_TABLE
+        .get()
+        .unwrap()
+        .lock_irq_disabled()
+        .iter()
+        .map(|(tag, device)| (tag.clone(), device.clone()))
+        .collect()
+}
+
+pub fn init() {
+    FILE_SYSTEM_DEVICE_TABLE.call_once(|| SpinLock::new(BTreeMap::new()));
+}
+
+static FILE_SYSTEM_DEVICE_TABLE: Once<SpinLock<BTreeMap<String, Arc<FileSystemDevice>>>> =
+    Once::new();
@@ -0,0 +1,49 @@
+// SPDX-License-Identifier: MPL-2.0
+
+use aster_frame::io_mem::IoMem;
+use aster_util::safe_ptr::SafePtr;
+use pod::Pod;
+
+use crate::transport::VirtioTransport;
+
+bitflags::bitflags! {
+    pub struct GpuFeatures: u64 {
+        /// The 3D mode, i.e., virgl, is supported.
+        const VIRTIO_GPU_F_VIRGL = 1 << 0;
+        /// The EDID of the displays is supported.
+        const VIRTIO_GPU_F_EDID = 1 << 1;
+        /// The UUIDs of the resources are supported.
+        const VIRTIO_GPU_F_RESOURCE_UUID = 1 << 2;
+        /// The blob resources are supported.
+        const VIRTIO_GPU_F_RESOURCE_BLOB = 1 << 3;
+        /// The contexts with the capability sets are supported.
+        const VIRTIO_GPU_F_CONTEXT_INIT = 1 << 4;
+    }
+}
+
+bitflags::bitflags! {
+    /// The pending events in the configuration space.
+    pub struct GpuEvents: u32 {
+        /// The configuration of the displays is changed.
+        const VIRTIO_GPU_EVENT_DISPLAY = 1 << 0;
+    }
+}
+
+#[derive(Debug, Pod, Clone, Copy)]
+#[repr(C)]
+pub struct VirtioGpuConfig {
+    /// The pending events, which are the bits of `GpuEvents`.
+    pub events_read: u32,
+    /// The events that are cleared by the driver.
+    pub events_clear: u32,
+    /// The maximum number of the scanouts, i.e., the displays.
+    pub num_scanouts: u32,
+    pub num_capsets: u32,
+}
+
+impl VirtioGpuConfig {
+    pub(super) fn new(transport: &dyn VirtioTransport) -> SafePtr<Self, IoMem> {
+        let memory = transport.device_config_memory();
+        SafePtr::new(memory, 0)
+    }
+}
@@ -0,0 +1,152 @@
+// SPDX-License-Identifier: MPL-2.0
+
+//! The requests and the responses in the control queue of the GPU device.
+
+use int_to_c_enum::TryFromInt;
+use pod::Pod;
+
+/// The maximum number of the scanouts that a device can have.
+pub(super) const VIRTIO_GPU_MAX_SCANOUTS: usize = 16;
+
+#[repr(u32)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, TryFromInt)]
+#[allow(non_camel_case_types)]
+pub(super) enum CtrlType {
+    // The 2D commands.
+    VIRTIO_GPU_CMD_GET_DISPLAY_INFO = 0x0100,
+    VIRTIO_GPU_CMD_RESOURCE_CREATE_2D = 0x0101,
+    VIRTIO_GPU_CMD_RESOURCE_UNREF = 0x0102,
+    VIRTIO_GPU_CMD_SET_SCANOUT = 0x0103,
+    VIRTIO_GPU_CMD_RESOURCE_FLUSH = 0x0104,
+    VIRTIO_GPU_CMD_TRANSFER_TO_HOST_2D = 0x0105,
+    VIRTIO_GPU_CMD_RESOURCE_ATTACH_BACKING = 0x0106,
+    VIRTIO_GPU_CMD_RESOURCE_DETACH_BACKING = 0x0107,
+
+    // The success responses.
+    VIRTIO_GPU_RESP_OK_NODATA = 0x1100,
+    VIRTIO_GPU_RESP_OK_DISPLAY_INFO = 0x1101,
+
+    // The error responses.
+    VIRTIO_GPU_RESP_ERR_UNSPEC = 0x1200,
+    VIRTIO_GPU_RESP_ERR_OUT_OF_MEMORY = 0x1201,
+    VIRTIO_GPU_RESP_ERR_INVALID_SCANOUT_ID = 0x1202,
+    VIRTIO_GPU_RESP_ERR_INVALID_RESOURCE_ID = 0x1203,
+    VIRTIO_GPU_RESP_ERR_INVALID_CONTEXT_ID = 0x1204,
+    VIRTIO_GPU_RESP_ERR_INVALID_PARAMETER = 0x1205,
+}
+
+/// The formats of the 2D resources, which are named by the order of the bytes of a pixel.
+#[repr(u32)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, TryFromInt)]
+#[allow(non_camel_case_types)]
+pub(super) enum ResourceFormat {
+    VIRTIO_GPU_FORMAT_B8G8R8A8_UNORM = 1,
+    VIRTIO_GPU_FORMAT_B8G8R8X8_UNORM = 2,
+    VIRTIO_GPU_FORMAT_A8R8G8B8_UNORM = 3,
+    VIRTIO_GPU_FORMAT_X8R8G8B8_UNORM = 4,
+    VIRTIO_GPU_FORMAT_R8G8B8A8_UNORM = 67,
+    VIRTIO_GPU_FORMAT_X8B8G8R8_UNORM = 68,
+    VIRTIO_GPU_FORMAT_A8B8G8R8_UNORM = 121,
+    VIRTIO_GPU_FORMAT_R8G8B8X8_UNORM = 134,
+}
+
+/// The header of the requests and the responses.
+#[derive(Debug, Clone, Copy, Default, Pod)]
+#[repr(C)]
+pub(super) struct CtrlHeader {
+    /// The type of the request or the response, i.e., `CtrlType`.
+    pub type_: u32,
+    pub flags: u32,
+    pub fence_id: u64,
+    pub ctx_id: u32,
+    pub ring_idx: u8,
+    pub padding: [u8; 3],
+}
+
+impl CtrlHeader {
+    pub fn new(type_: CtrlType) -> Self {
+        Self {
+            type_: type_ as u32,
+            ..Default::default()
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, Pod)]
+#[repr(C)]
+pub(super) struct GpuRect {
+    pub x: u32,
+    pub y: u32,
+    pub width: u32,
+    pub height: u32,
+}
+
+/// A display in the response of `VIRTIO_GPU_CMD_GET_DISPLAY_INFO`, which follows the
+/// header. There are `VIRTIO_GPU_MAX_SCANOUTS` displays in the response.
+#[derive(Debug, Clone, Copy, Pod)]
+#[repr(C)]
+pub(super) struct DisplayOne {
+    /// The preferred position and size of the display.
+    pub rect: GpuRect,
+    pub enabled: u32,
+    pub flags: u32,
+}
+
+#[derive(Debug, Clone, Copy, Pod)]
+#[repr(C)]
+pub(super) struct ResourceCreate2d {
+    pub header: CtrlHeader,
+    pub resource_id: u32,
+    /// The format of the pixels, i.e., `ResourceFormat`.
+    pub format: u32,
+    pub width: u32,
+    pub height: u32,
+}
+
+/// The request of `VIRTIO_GPU_CMD_RESOURCE_ATTACH_BACKING` with one memory entry, which
+/// is enough since the backing memory is contiguous.
+#[derive(Debug, Clone, Copy, Pod)]
+#[repr(C)]
+pub(super) struct ResourceAttachBacking {
+    pub header: CtrlHeader,
+    pub resource_id: u32,
+    pub nr_entries: u32,
+    pub entry: MemEntry,
+}
+
+#[derive(Debug, Clone, Copy, Pod)]
+#[repr(C)]
+pub(super) struct MemEntry {
+    pub addr: u64,
+    pub length: u32,
+    pub padding: u32,
+}
+
+#[derive(Debug, Clone, Copy, Pod)]
+#[repr(C)]
+pub(super) struct SetScanout {
+    pub header: CtrlHeader,
+    pub rect: GpuRect,
+    pub scanout_id: u32,
+    pub resource_id: u32,
+}
+
+#[derive(Debug, Clone, Copy, Pod)]
+#[repr(C)]
+pub(super) struct TransferToHost2d {
+    pub header: CtrlHeader,
+    pub rect: GpuRect,
+    /// The offset of the rectangle in the backing memory.
+    pub offset: u64,
+    pub resource_id: u32,
+    pub padding: u32,
+}
+
+#[derive(Debug, Clone, Copy, Pod)]
+#[repr(C)]
+pub(super) struct ResourceFlush {
+    pub header: CtrlHeader,
+    pub rect: GpuRect,
+    pub resource_id: u32,
+    pub padding: u32,
+}
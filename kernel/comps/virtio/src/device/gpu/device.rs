@@ -0,0 +1,288 @@
+// SPDX-License-Identifier: MPL-2.0
+
+use alloc::{boxed::Box, string::ToString, sync::Arc, vec::Vec};
+use core::{fmt::Debug, hint::spin_loop, mem::size_of};
+
+use aster_frame::{
+    io_mem::IoMem,
+    mm::{
+        DmaDirection, DmaStream, DmaStreamSlice, FrameAllocOptions, FrameVec, HasDaddr, Segment,
+        VmIo, PAGE_SIZE,
+    },
+    sync::SpinLock,
+};
+use aster_framebuffer::{AnyFramebufferDevice, FramebufferInfo, PixelFormat, Rect};
+use aster_util::safe_ptr::SafePtr;
+use log::{info, warn};
+use pod::Pod;
+
+use super::{
+    config::{GpuFeatures, VirtioGpuConfig},
+    control::{
+        CtrlHeader, CtrlType, DisplayOne, GpuRect, MemEntry, ResourceAttachBacking,
+        ResourceCreate2d, ResourceFlush, ResourceFormat, SetScanout, TransferToHost2d,
+        VIRTIO_GPU_MAX_SCANOUTS,
+    },
+    DEVICE_NAME,
+};
+use crate::{device::VirtioDeviceError, queue::VirtQueue, transport::VirtioTransport};
+
+const CONTROL_QUEUE_INDEX: u16 = 0;
+const CURSOR_QUEUE_INDEX: u16 = 1;
+
+/// The ID of the resource of the framebuffer. The IDs are chosen by the driver, except
+/// that zero is invalid.
+const FRAMEBUFFER_RESOURCE_ID: u32 = 1;
+/// The scanout that shows the framebuffer.
+const SCANOUT_ID: u32 = 0;
+
+/// The resolution of the framebuffer if the display is not enabled, which is
+/// the same as that of Linux.
+const DEFAULT_WIDTH: u32 = 1024;
+const DEFAULT_HEIGHT: u32 = 768;
+
+/// A virtio GPU device, whose first display shows a framebuffer.
+pub struct GpuDevice {
+    config: SafePtr<VirtioGpuConfig, IoMem>,
+    control: SpinLock<ControlQueue>,
+    /// The cursor queue, which is set up as required by the device but not used.
+    cursor_queue: SpinLock<VirtQueue>,
+    info: FramebufferInfo,
+    /// The backing memory of the framebuffer resource, which is physically contiguous.
+    frames: FrameVec,
+    /// The DMA mappings of `frames`, which make them accessible to the device.
+    frame_mappings: Vec<DmaStream>,
+    transport: SpinLock<Box<dyn VirtioTransport>>,
+}
+
+/// The control queue, and the buffers of a request and its response.
+///
+/// The requests are sent one at a time, which is fast enough for a framebuffer.
+struct ControlQueue {
+    queue: VirtQueue,
+    request: DmaStream,
+    response: DmaStream,
+}
+
+impl Debug for GpuDevice {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        f.debug_struct("GpuDevice")
+            .field("info", &self.info)
+            .field("transport", &self.transport)
+            .finish()
+    }
+}
+
+impl GpuDevice {
+    pub fn negotiate_features(features: u64) -> u64 {
+        // Only the 2D mode is supported, which requires none of the features.
+        features & !GpuFeatures::all().bits()
+    }
+
+    pub fn init(mut transport: Box<dyn VirtioTransport>) -> Result<(), VirtioDeviceError> {
+        let config = VirtioGpuConfig::new(transport.as_ref());
+        let mut control = {
+            let queue = VirtQueue::new(CONTROL_QUEUE_INDEX, 2, transport.as_mut())?;
+            let request = {
+                let vm_segment = FrameAllocOptions::new(1).alloc_contiguous().unwrap();
+                DmaStream::map(vm_segment, DmaDirection::ToDevice, false).unwrap()
+            };
+            let response = {
+                let vm_segment = FrameAllocOptions::new(1).alloc_contiguous().unwrap();
+                DmaStream::map(vm_segment, DmaDirection::FromDevice, false).unwrap()
+            };
+            ControlQueue {
+                queue,
+                request,
+                response,
+            }
+        };
+        let cursor_queue = VirtQueue::new(CURSOR_QUEUE_INDEX, 2, transport.as_mut())?;
+        transport.finish_init();
+
+        let (width, height) = control
+            .display_size()?
+            .unwrap_or((DEFAULT_WIDTH, DEFAULT_HEIGHT));
+        let info = FramebufferInfo {
+            width: width as usize,
+            height: height as usize,
+            line_length: width as usize * PixelFormat::Xrgb8888.bytes_per_pixel(),
+            format: PixelFormat::Xrgb8888,
+        };
+
+        let frames = FrameAllocOptions::new(info.size().div_ceil(PAGE_SIZE))
+            .is_contiguous(true)
+            .alloc()
+            .map_err(|_| VirtioDeviceError::ResourceAllocError)?;
+        let frame_mappings = frames
+            .iter()
+            .map(|frame| {
+                DmaStream::map(Segment::from(frame.clone()), DmaDirection::ToDevice, false).unwrap()
+            })
+            .collect::<Vec<_>>();
+        control.set_up_framebuffer(&info, frame_mappings[0].daddr() as u64)?;
+
+        let device = Arc::new(Self {
+            config,
+            control: SpinLock::new(control),
+            cursor_queue: SpinLock::new(cursor_queue),
+            info,
+            frames,
+            frame_mappings,
+            transport: SpinLock::new(transport),
+        });
+        device.flush(info.rect());
+        info!(
+            "[Virtio-GPU]: device initialized, resolution = {}x{}",
+            width, height
+        );
+        aster_framebuffer::register_device(DEVICE_NAME.to_string(), device);
+        Ok(())
+    }
+}
+
+impl AnyFramebufferDevice for GpuDevice {
+    fn info(&self) -> FramebufferInfo {
+        self.info
+    }
+
+    fn frames(&self) -> &FrameVec {
+        &self.frames
+    }
+
+    fn flush(&self, rect: Rect) {
+        let x = rect.x.min(self.info.width);
+        let y = rect.y.min(self.info.height);
+        let rect = GpuRect {
+            x: x as u32,
+            y: y as u32,
+            width: rect.width.min(self.info.width - x) as u32,
+            height: rect.height.min(self.info.height - y) as u32,
+        };
+        if rect.width == 0 || rect.height == 0 {
+            return;
+        }
+        let offset = y * self.info.line_length + x * self.info.format.bytes_per_pixel();
+
+        let mut control = self.control.lock_irq_disabled();
+        let transfer = TransferToHost2d {
+            header: CtrlHeader::new(CtrlType::VIRTIO_GPU_CMD_TRANSFER_TO_HOST_2D),
+            rect,
+            offset: offset as u64,
+            resource_id: FRAMEBUFFER_RESOURCE_ID,
+            padding: 0,
+        };
+        let flush = ResourceFlush {
+            header: CtrlHeader::new(CtrlType::VIRTIO_GPU_CMD_RESOURCE_FLUSH),
+            rect,
+            resource_id: FRAMEBUFFER_RESOURCE_ID,
+            padding: 0,
+        };
+        // The failures are ignored, since nothing can be done except that the display
+        // shows the stale pixels.
+        let _ = control
+            .request(&transfer)
+            .and_then(|_| control.request(&flush));
+    }
+}
+
+impl ControlQueue {
+    /// Returns the preferred size of the display of `SCANOUT_ID`, or `None` if the display
+    /// is not enabled.
+    fn display_size(&mut self) -> Result<Option<(u32, u32)>, VirtioDeviceError> {
+        let header = CtrlHeader::new(CtrlType::VIRTIO_GPU_CMD_GET_DISPLAY_INFO);
+        self.request_with_response(
+            &header,
+            CtrlType::VIRTIO_GPU_RESP_OK_DISPLAY_INFO,
+            size_of::<CtrlHeader>() + size_of::<DisplayOne>() * VIRTIO_GPU_MAX_SCANOUTS,
+        )?;
+
+        let offset = size_of::<CtrlHeader>() + size_of::<DisplayOne>() * SCANOUT_ID as usize;
+        let display: DisplayOne = self.response.read_val(offset).unwrap();
+        if display.enabled == 0 || display.rect.width == 0 || display.rect.height == 0 {
+            return Ok(None);
+        }
+        Ok(Some((display.rect.width, display.rect.height)))
+    }
+
+    /// Creates the framebuffer resource whose backing memory starts at `daddr`, and shows
+    /// it on the first display.
+    fn set_up_framebuffer(
+        &mut self,
+        info: &FramebufferInfo,
+        daddr: u64,
+    ) -> Result<(), VirtioDeviceError> {
+        let rect = GpuRect {
+            x: 0,
+            y: 0,
+            width: info.width as u32,
+            height: info.height as u32,
+        };
+        self.request(&ResourceCreate2d {
+            header: CtrlHeader::new(CtrlType::VIRTIO_GPU_CMD_RESOURCE_CREATE_2D),
+            resource_id: FRAMEBUFFER_RESOURCE_ID,
+            format: ResourceFormat::VIRTIO_GPU_FORMAT_B8G8R8X8_UNORM as u32,
+            width: rect.width,
+            height: rect.height,
+        })?;
+        self.request(&ResourceAttachBacking {
+            header: CtrlHeader::new(CtrlType::VIRTIO_GPU_CMD_RESOURCE_ATTACH_BACKING),
+            resource_id: FRAMEBUFFER_RESOURCE_ID,
+            nr_entries: 1,
+            entry: MemEntry {
+                addr: daddr,
+                length: info.size() as u32,
+                padding: 0,
+            },
+        })?;
+        self.request(&SetScanout {
+            header: CtrlHeader::new(CtrlType::VIRTIO_GPU_CMD_SET_SCANOUT),
+            rect,
+            scanout_id: SCANOUT_ID,
+            resource_id: FRAMEBUFFER_RESOURCE_ID,
+        })
+    }
+
+    /// Sends a request whose response has no data.
+    fn request<T: Pod>(&mut self, request: &T) -> Result<(), VirtioDeviceError> {
+        self.request_with_response(
+            request,
+            CtrlType::VIRTIO_GPU_RESP_OK_NODATA,
+            size_of::<CtrlHeader>(),
+        )
+    }
+
+    /// Sends a request and waits for its response, which is left in the response buffer.
+    ///
+    /// Fails if the type of the response is not `resp_type`.
+    fn request_with_response<T: Pod>(
+        &mut self,
+        request: &T,
+        resp_type: CtrlType,
+        resp_len: usize,
+    ) -> Result<(), VirtioDeviceError> {
+        let req_len = size_of::<T>();
+        self.request.write_val(0, request).unwrap();
+        self.request.sync(0..req_len).unwrap();
+
+        let req_slice = DmaStreamSlice::new(&self.request, 0, req_len);
+        let resp_slice = DmaStreamSlice::new(&self.response, 0, resp_len);
+        self.queue.add_dma_buf(&[&req_slice], &[&resp_slice])?;
+        if self.queue.should_notify() {
+            self.queue.notify();
+        }
+        while !self.queue.can_pop() {
+            spin_loop();
+        }
+        self.queue.pop_used()?;
+
+        self.response.sync(0..resp_len).unwrap();
+        let header: CtrlHeader = self.response.read_val(0).unwrap();
+        if header.type_ != resp_type as u32 {
+            let error = CtrlType::try_from(header.type_);
+            warn!("[Virtio-GPU]: request failed with {:?}", error);
+            return Err(VirtioDeviceError::RequestFailed);
+        }
+        Ok(())
+    }
+}
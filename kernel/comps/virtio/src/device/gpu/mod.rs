@@ -0,0 +1,13 @@
+// SPDX-License-Identifier: MPL-2.0
+
+//! The virtio GPU device, which is used as a framebuffer in the 2D mode.
+//!
+//! The driver creates a 2D resource as large as the first display, backs it with the
+//! guest memory, and sets it as the scanout of the display. The pixels are written to
+//! the guest memory, and are transferred to the host when the framebuffer is flushed.
+
+pub mod config;
+mod control;
+pub mod device;
+
+pub static DEVICE_NAME: &str = "Virtio-GPU";
@@ -4,11 +4,23 @@ use int_to_c_enum::TryFromInt;
 
 use crate::queue::QueueError;
 
+#[cfg(feature = "balloon")]
+pub mod balloon;
 pub mod block;
 pub mod console;
+#[cfg(feature = "entropy")]
+pub mod entropy;
+#[cfg(feature = "filesystem")]
+pub mod filesystem;
+#[cfg(feature = "gpu")]
+pub mod gpu;
 pub mod input;
+#[cfg(feature = "network")]
 pub mod network;
+#[cfg(feature = "network")]
 pub mod socket;
+#[cfg(feature = "transport-9p")]
+pub mod transport_9p;
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, TryFromInt)]
 #[repr(u8)]
@@ -36,6 +48,7 @@ pub enum VirtioDeviceType {
     Pstore = 22,
     IOMMU = 23,
     Memory = 24,
+    FileSystem = 26,
 }
 
 #[derive(Debug)]
@@ -47,6 +60,10 @@ pub enum VirtioDeviceError {
     QueueUnknownError,
     /// The input virtio capability list contains invalid element
     CapabilityListError,
+    /// The resources needed by the device cannot be allocated
+    ResourceAllocError,
+    /// The device fails to handle a request
+    RequestFailed,
 }
 
 impl From<QueueError> for VirtioDeviceError {
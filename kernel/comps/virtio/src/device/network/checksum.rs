@@ -0,0 +1,133 @@
+// SPDX-License-Identifier: MPL-2.0
+
+//! The checksums of the TCP and UDP packets over IPv4, which are offloaded to the device.
+//!
+//! To offload the checksum of an outgoing packet, the driver stores the checksum of the
+//! pseudo header in the checksum field, and the device adds the checksum of the TCP or UDP
+//! segment to it. An incoming packet whose checksum is not validated by the device is
+//! validated by the driver.
+
+const ETHERNET_HEADER_LEN: usize = 14;
+const ETHERTYPE_IPV4: u16 = 0x0800;
+const PROTOCOL_TCP: u8 = 6;
+const PROTOCOL_UDP: u8 = 17;
+
+/// The offsets of the checksum field in the TCP and UDP headers.
+const TCP_CHECKSUM_OFFSET: usize = 16;
+const UDP_CHECKSUM_OFFSET: usize = 6;
+
+/// A TCP or UDP segment in an Ethernet frame.
+struct Segment {
+    /// The offset of the segment in the frame.
+    start: usize,
+    /// The length of the segment.
+    len: usize,
+    /// The offset of the checksum field in the segment.
+    checksum_offset: usize,
+    protocol: u8,
+    src_addr: [u8; 4],
+    dst_addr: [u8; 4],
+}
+
+impl Segment {
+    /// Parses the frame, if it carries an unfragmented TCP or UDP packet over IPv4.
+    fn parse(frame: &[u8]) -> Option<Self> {
+        let ethertype = u16::from_be_bytes(frame.get(12..14)?.try_into().unwrap());
+        if ethertype != ETHERTYPE_IPV4 {
+            return None;
+        }
+        let packet = &frame[ETHERNET_HEADER_LEN..];
+        let header_len = (*packet.first()? & 0xf) as usize * 4;
+        let total_len = u16::from_be_bytes(packet.get(2..4)?.try_into().unwrap()) as usize;
+        let frag = u16::from_be_bytes(packet.get(6..8)?.try_into().unwrap());
+        if frag & 0x3fff != 0 || header_len < 20 || total_len < header_len {
+            return None;
+        }
+        if total_len > packet.len() {
+            return None;
+        }
+
+        let protocol = packet[9];
+        let checksum_offset = match protocol {
+            PROTOCOL_TCP => TCP_CHECKSUM_OFFSET,
+            PROTOCOL_UDP => UDP_CHECKSUM_OFFSET,
+            _ => return None,
+        };
+        let len = total_len - header_len;
+        if len < checksum_offset + 2 {
+            return None;
+        }
+        Some(Self {
+            start: ETHERNET_HEADER_LEN + header_len,
+            len,
+            checksum_offset,
+            protocol,
+            src_addr: packet[12..16].try_into().unwrap(),
+            dst_addr: packet[16..20].try_into().unwrap(),
+        })
+    }
+
+    /// Returns the unfolded sum of the pseudo header.
+    fn pseudo_header_sum(&self) -> u32 {
+        let mut sum = sum_words(&self.src_addr) + sum_words(&self.dst_addr);
+        sum += self.protocol as u32;
+        sum + self.len as u32
+    }
+}
+
+/// Prepares the frame for the checksum offload.
+///
+/// Returns the offset of the TCP or UDP segment in the frame, and the offset of the
+/// checksum field in the segment, which are told to the device. The checksum field is
+/// overwritten with the checksum of the pseudo header, which is returned along with them.
+pub(super) fn prepare_partial_checksum(frame: &[u8]) -> Option<(u16, u16, [u8; 2])> {
+    let segment = Segment::parse(frame)?;
+    let sum = fold(segment.pseudo_header_sum());
+    Some((
+        segment.start as u16,
+        segment.checksum_offset as u16,
+        sum.to_be_bytes(),
+    ))
+}
+
+/// Validates the checksum of the frame.
+///
+/// The frames that carry no TCP or UDP packet over IPv4 are considered valid, since their
+/// checksums are validated by the network stack.
+pub(super) fn is_checksum_valid(frame: &[u8]) -> bool {
+    let Some(segment) = Segment::parse(frame) else {
+        return true;
+    };
+    let data = &frame[segment.start..segment.start + segment.len];
+    let checksum = &data[segment.checksum_offset..segment.checksum_offset + 2];
+    // A zero UDP checksum means that the checksum is not computed.
+    if segment.protocol == PROTOCOL_UDP && checksum == [0, 0] {
+        return true;
+    }
+    fold(segment.pseudo_header_sum() + sum_words(data)) == 0xffff
+}
+
+/// Returns the unfolded sum of the big-endian 16-bit words.
+fn sum_words(data: &[u8]) -> u32 {
+    let mut chunks = data.chunks_exact(2);
+    let mut sum: u32 = 0;
+    for chunk in chunks.by_ref() {
+        sum = sum.wrapping_add(u16::from_be_bytes([chunk[0], chunk[1]]) as u32);
+        // Folds early, so that a large segment does not overflow the sum.
+        if sum & 0x8000_0000 != 0 {
+            sum = (sum & 0xffff) + (sum >> 16);
+        }
+    }
+    if let [last] = chunks.remainder() {
+        sum += (*last as u32) << 8;
+    }
+    sum
+}
+
+/// Folds the sum into 16 bits with the end-around carries.
+fn fold(mut sum: u32) -> u16 {
+    while sum >> 16 != 0 {
+        sum = (sum & 0xffff) + (sum >> 16);
+    }
+    sum as u16
+}
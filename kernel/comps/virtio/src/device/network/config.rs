@@ -36,7 +36,7 @@ bitflags! {
         // const VIRTIO_NET_F_HOST_USO = 1 << 56;          // Device can receive USO packets.
         // const VIRTIO_NET_F_HASH_REPORT = 1 << 57;       // Device can report per-packet hash value and a type of calculated hash.
         // const VIRTIO_NET_F_GUEST_HDRLEN = 1 << 59;      // Driver can provide the exact hdr_len value. Device benefits from knowing the exact header length.
-        // const VIRTIO_NET_F_RSS = 1 << 60;               // Device supports RSS (receive-side scaling) with Toeplitz hash calculation and configurable hash parameters for receive steering.
+        const VIRTIO_NET_F_RSS = 1 << 60;               // Device supports RSS (receive-side scaling) with Toeplitz hash calculation and configurable hash parameters for receive steering.
         // const VIRTIO_NET_F_RSC_EXT = 1 << 61;           // DevicecanprocessduplicatedACKsandreportnumberofcoalescedseg- ments and duplicated ACKs.
         // const VIRTIO_NET_F_STANDBY = 1 << 62;           // Device may act as a standby for a primary device with the same MAC address.
         // const VIRTIO_NET_F_SPEED_DUPLEX = 1 << 63;      // Device reports speed and duplex.
@@ -45,7 +45,28 @@ bitflags! {
 
 impl NetworkFeatures {
     pub fn support_features() -> Self {
-        NetworkFeatures::VIRTIO_NET_F_MAC | NetworkFeatures::VIRTIO_NET_F_STATUS
+        NetworkFeatures::VIRTIO_NET_F_MAC
+            | NetworkFeatures::VIRTIO_NET_F_STATUS
+            | NetworkFeatures::VIRTIO_NET_F_CSUM
+            | NetworkFeatures::VIRTIO_NET_F_GUEST_CSUM
+            | NetworkFeatures::VIRTIO_NET_F_HOST_TSO4
+            | NetworkFeatures::VIRTIO_NET_F_HOST_TSO6
+            | NetworkFeatures::VIRTIO_NET_F_CTRL_VQ
+            | NetworkFeatures::VIRTIO_NET_F_MQ
+            | NetworkFeatures::VIRTIO_NET_F_RSS
+    }
+
+    /// Removes the features whose dependencies are not negotiated.
+    pub fn with_dependencies(mut self) -> Self {
+        if !self.contains(NetworkFeatures::VIRTIO_NET_F_CSUM) {
+            self.remove(
+                NetworkFeatures::VIRTIO_NET_F_HOST_TSO4 | NetworkFeatures::VIRTIO_NET_F_HOST_TSO6,
+            );
+        }
+        if !self.contains(NetworkFeatures::VIRTIO_NET_F_CTRL_VQ) {
+            self.remove(NetworkFeatures::VIRTIO_NET_F_MQ | NetworkFeatures::VIRTIO_NET_F_RSS);
+        }
+        self
     }
 }
 
@@ -63,13 +84,13 @@ bitflags! {
 pub struct VirtioNetConfig {
     pub mac: EthernetAddr,
     pub status: Status,
-    max_virtqueue_pairs: u16,
+    pub max_virtqueue_pairs: u16,
     mtu: u16,
     speed: u32,
     duplex: u8,
-    rss_max_key_size: u8,
-    rss_max_indirection_table_length: u16,
-    supported_hash_types: u32,
+    pub rss_max_key_size: u8,
+    pub rss_max_indirection_table_length: u16,
+    pub supported_hash_types: u32,
 }
 
 impl VirtioNetConfig {
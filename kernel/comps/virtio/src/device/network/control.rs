@@ -0,0 +1,82 @@
+// SPDX-License-Identifier: MPL-2.0
+
+//! The commands sent through the control virtqueue.
+
+use alloc::vec::Vec;
+
+use pod::Pod;
+
+/// The header of a control command, which is followed by the command-specific data and an
+/// ack byte written by the device.
+#[derive(Debug, Clone, Copy, Pod)]
+#[repr(C)]
+pub(super) struct CtrlHdr {
+    pub class: u8,
+    pub command: u8,
+}
+
+pub(super) const VIRTIO_NET_OK: u8 = 0;
+
+pub(super) const VIRTIO_NET_CTRL_MQ: u8 = 4;
+pub(super) const VIRTIO_NET_CTRL_MQ_VQ_PAIRS_SET: u8 = 0;
+pub(super) const VIRTIO_NET_CTRL_MQ_RSS_CONFIG: u8 = 1;
+
+const VIRTIO_NET_HASH_TYPE_IPV4: u32 = 1 << 0;
+const VIRTIO_NET_HASH_TYPE_TCPV4: u32 = 1 << 1;
+const VIRTIO_NET_HASH_TYPE_UDPV4: u32 = 1 << 2;
+
+/// The maximum length of the RSS indirection table used by the driver.
+const MAX_INDIRECTION_TABLE_LEN: u16 = 128;
+
+/// The default Toeplitz key, which is the one suggested by Microsoft and used by most of the
+/// NIC drivers.
+const DEFAULT_RSS_KEY: [u8; 40] = [
+    0x6d, 0x5a, 0x56, 0xda, 0x25, 0x5b, 0x0e, 0xc2, 0x41, 0x67, 0x25, 0x3d, 0x43, 0xa3, 0x8f, 0xb0,
+    0xd0, 0xca, 0x2b, 0xcb, 0xae, 0x7b, 0x30, 0xb4, 0x77, 0xcb, 0x2d, 0xa3, 0x80, 0x30, 0xf2, 0x0c,
+    0x6a, 0x42, 0xb7, 0x3b, 0xbe, 0xac, 0x01, 0xfa,
+];
+
+/// Builds the data of the `VIRTIO_NET_CTRL_MQ_RSS_CONFIG` command, which spreads the TCP and
+/// UDP flows over IPv4 among the receive queues of `num_queue_pairs` pairs.
+///
+/// The layout of the data is variable-length:
+///
+/// ```c
+/// struct virtio_net_rss_config {
+///     le32 hash_types;
+///     le16 indirection_table_mask;
+///     le16 unclassified_queue;
+///     le16 indirection_table[indirection_table_length];
+///     le16 max_tx_vq;
+///     u8 hash_key_length;
+///     u8 hash_key_data[hash_key_length];
+/// };
+/// ```
+pub(super) fn rss_config(
+    num_queue_pairs: u16,
+    supported_hash_types: u32,
+    max_indirection_table_len: u16,
+    max_key_size: u8,
+) -> Vec<u8> {
+    let hash_types = supported_hash_types
+        & (VIRTIO_NET_HASH_TYPE_IPV4 | VIRTIO_NET_HASH_TYPE_TCPV4 | VIRTIO_NET_HASH_TYPE_UDPV4);
+    // The length of the indirection table must be a power of two.
+    let table_len = {
+        let max_len = max_indirection_table_len.clamp(1, MAX_INDIRECTION_TABLE_LEN);
+        1u16 << max_len.ilog2()
+    };
+    let key_len = (max_key_size as usize).min(DEFAULT_RSS_KEY.len());
+
+    let mut data = Vec::new();
+    data.extend_from_slice(&hash_types.to_le_bytes());
+    data.extend_from_slice(&(table_len - 1).to_le_bytes());
+    // The unclassified packets go to the first receive queue.
+    data.extend_from_slice(&0u16.to_le_bytes());
+    for i in 0..table_len {
+        data.extend_from_slice(&(i % num_queue_pairs).to_le_bytes());
+    }
+    data.extend_from_slice(&num_queue_pairs.to_le_bytes());
+    data.push(key_len as u8);
+    data.extend_from_slice(&DEFAULT_RSS_KEY[..key_len]);
+    data
+}
@@ -1,18 +1,29 @@
 // SPDX-License-Identifier: MPL-2.0
 
-use alloc::{boxed::Box, string::ToString, sync::Arc};
+use alloc::{boxed::Box, string::ToString, sync::Arc, vec, vec::Vec};
 use core::{fmt::Debug, hint::spin_loop, mem::size_of};
 
-use aster_frame::{offset_of, sync::SpinLock, trap::TrapFrame};
+use aster_frame::{
+    cpu::{num_cpus, this_cpu},
+    mm::{DmaDirection, DmaStream, DmaStreamSlice, FrameAllocOptions, VmIo, VmWriter},
+    offset_of,
+    sync::SpinLock,
+    trap::TrapFrame,
+};
 use aster_network::{
     AnyNetworkDevice, EthernetAddr, RxBuffer, TxBuffer, VirtioNetError, RX_BUFFER_POOL,
     TX_BUFFER_POOL,
 };
 use aster_util::{field_ptr, slot_vec::SlotVec};
-use log::debug;
-use smoltcp::phy::{DeviceCapabilities, Medium};
+use log::{debug, warn};
+use smoltcp::phy::{Checksum, DeviceCapabilities, Medium};
 
-use super::{config::VirtioNetConfig, header::VirtioNetHdr};
+use super::{
+    checksum,
+    config::VirtioNetConfig,
+    control::{self, CtrlHdr},
+    header::{Flags, VirtioNetHdr, VIRTIO_NET_HDR_LEN},
+};
 use crate::{
     device::{network::config::NetworkFeatures, VirtioDeviceError},
     queue::{QueueError, VirtQueue},
@@ -21,18 +32,27 @@ use crate::{
 
 pub struct NetworkDevice {
     config: VirtioNetConfig,
+    features: NetworkFeatures,
     mac_addr: EthernetAddr,
-    send_queue: VirtQueue,
+    queue_pairs: Vec<QueuePair>,
+    /// The control virtqueue, which is only used during the initialization but must live as
+    /// long as the device.
+    ctrl_queue: Option<VirtQueue>,
+    transport: Box<dyn VirtioTransport>,
+}
+
+/// A receive queue and a transmit queue.
+struct QueuePair {
     recv_queue: VirtQueue,
+    send_queue: VirtQueue,
     rx_buffers: SlotVec<RxBuffer>,
-    transport: Box<dyn VirtioTransport>,
 }
 
 impl NetworkDevice {
     pub(crate) fn negotiate_features(device_features: u64) -> u64 {
         let device_features = NetworkFeatures::from_bits_truncate(device_features);
         let supported_features = NetworkFeatures::support_features();
-        let network_features = device_features & supported_features;
+        let network_features = (device_features & supported_features).with_dependencies();
         debug!("{:?}", network_features);
         network_features.bits()
     }
@@ -44,6 +64,7 @@ impl NetworkDevice {
         ));
         debug!("virtio_net_config = {:?}", virtio_net_config);
         debug!("features = {:?}", features);
+        let config = virtio_net_config.read().unwrap();
         let mac_addr = field_ptr!(&virtio_net_config, VirtioNetConfig, mac)
             .read()
             .unwrap();
@@ -51,31 +72,62 @@ impl NetworkDevice {
             .read()
             .unwrap();
         debug!("mac addr = {:x?}, status = {:?}", mac_addr, status);
-        let mut recv_queue = VirtQueue::new(QUEUE_RECV, QUEUE_SIZE, transport.as_mut())
-            .expect("creating recv queue fails");
-        let send_queue = VirtQueue::new(QUEUE_SEND, QUEUE_SIZE, transport.as_mut())
-            .expect("create send queue fails");
 
-        let mut rx_buffers = SlotVec::new();
-        for i in 0..QUEUE_SIZE {
-            let rx_pool = RX_BUFFER_POOL.get().unwrap();
-            let rx_buffer = RxBuffer::new(size_of::<VirtioNetHdr>(), rx_pool);
-            // FIEME: Replace rx_buffer with VM segment-based data structure to use dma mapping.
-            let token = recv_queue.add_dma_buf(&[], &[&rx_buffer])?;
-            assert_eq!(i, token);
-            assert_eq!(rx_buffers.put(rx_buffer) as u16, i);
-        }
+        // The queues are laid out as receiveq1, transmitq1, ..., receiveqN, transmitqN,
+        // controlq, where N is `max_virtqueue_pairs` if the multi-queue is supported.
+        let supports_mq = features
+            .intersects(NetworkFeatures::VIRTIO_NET_F_MQ | NetworkFeatures::VIRTIO_NET_F_RSS);
+        let max_queue_pairs = if supports_mq {
+            config.max_virtqueue_pairs.max(1)
+        } else {
+            1
+        };
+        // More queue pairs than CPUs are of no use.
+        let num_queue_pairs = max_queue_pairs.min(num_cpus() as u16);
+
+        let mut queue_pairs = Vec::with_capacity(num_queue_pairs as usize);
+        for pair in 0..num_queue_pairs {
+            let mut recv_queue = VirtQueue::new(2 * pair, QUEUE_SIZE, transport.as_mut())
+                .expect("creating recv queue fails");
+            let send_queue = VirtQueue::new(2 * pair + 1, QUEUE_SIZE, transport.as_mut())
+                .expect("create send queue fails");
+
+            let mut rx_buffers = SlotVec::new();
+            for i in 0..QUEUE_SIZE {
+                let rx_pool = RX_BUFFER_POOL.get().unwrap();
+                let rx_buffer = RxBuffer::new(size_of::<VirtioNetHdr>(), rx_pool);
+                // FIEME: Replace rx_buffer with VM segment-based data structure to use dma mapping.
+                let token = recv_queue.add_dma_buf(&[], &[&rx_buffer])?;
+                assert_eq!(i, token);
+                assert_eq!(rx_buffers.put(rx_buffer) as u16, i);
+            }
 
-        if recv_queue.should_notify() {
-            debug!("notify receive queue");
-            recv_queue.notify();
+            if recv_queue.should_notify() {
+                debug!("notify receive queue");
+                recv_queue.notify();
+            }
+            queue_pairs.push(QueuePair {
+                recv_queue,
+                send_queue,
+                rx_buffers,
+            });
         }
+
+        let ctrl_queue = if features.contains(NetworkFeatures::VIRTIO_NET_F_CTRL_VQ) {
+            let ctrl_queue =
+                VirtQueue::new(2 * max_queue_pairs, CTRL_QUEUE_SIZE, transport.as_mut())
+                    .expect("create control queue fails");
+            Some(ctrl_queue)
+        } else {
+            None
+        };
+
         let mut device = Self {
-            config: virtio_net_config.read().unwrap(),
+            config,
+            features,
             mac_addr,
-            send_queue,
-            recv_queue,
-            rx_buffers,
+            queue_pairs,
+            ctrl_queue,
             transport,
         };
 
@@ -93,12 +145,43 @@ impl NetworkDevice {
             .transport
             .register_cfg_callback(Box::new(config_space_change))
             .unwrap();
-        device
-            .transport
-            .register_queue_callback(QUEUE_RECV, Box::new(handle_network_event), false)
-            .unwrap();
+        for pair in 0..num_queue_pairs {
+            // Each receive queue has its own interrupt if there are enough MSI-X vectors,
+            // or shares one with the others otherwise.
+            if device
+                .transport
+                .register_queue_callback(2 * pair, Box::new(handle_network_event), true)
+                .is_err()
+            {
+                device
+                    .transport
+                    .register_queue_callback(2 * pair, Box::new(handle_network_event), false)
+                    .unwrap();
+            }
+        }
         device.transport.finish_init();
 
+        // The control commands can only be sent after the device is alive.
+        if features.contains(NetworkFeatures::VIRTIO_NET_F_RSS) {
+            let rss_config = control::rss_config(
+                num_queue_pairs,
+                config.supported_hash_types,
+                config.rss_max_indirection_table_length,
+                config.rss_max_key_size,
+            );
+            device.send_ctrl_command(
+                control::VIRTIO_NET_CTRL_MQ,
+                control::VIRTIO_NET_CTRL_MQ_RSS_CONFIG,
+                &rss_config,
+            );
+        } else if features.contains(NetworkFeatures::VIRTIO_NET_F_MQ) {
+            device.send_ctrl_command(
+                control::VIRTIO_NET_CTRL_MQ,
+                control::VIRTIO_NET_CTRL_MQ_VQ_PAIRS_SET,
+                &num_queue_pairs.to_le_bytes(),
+            );
+        }
+
         aster_network::register_device(
             super::DEVICE_NAME.to_string(),
             Arc::new(SpinLock::new(device)),
@@ -106,59 +189,161 @@ impl NetworkDevice {
         Ok(())
     }
 
+    /// Sends a command through the control queue. Return until the device acknowledges it.
+    ///
+    /// If the device rejects the command, it keeps working as before, so the failure is
+    /// only reported.
+    fn send_ctrl_command(&mut self, class: u8, command: u8, data: &[u8]) {
+        let ctrl_queue = self.ctrl_queue.as_mut().unwrap();
+
+        let hdr_len = size_of::<CtrlHdr>();
+        let stream = {
+            let segment = FrameAllocOptions::new(1).alloc_contiguous().unwrap();
+            DmaStream::map(segment, DmaDirection::Bidirectional, false).unwrap()
+        };
+        let hdr_slice = DmaStreamSlice::new(&stream, 0, hdr_len);
+        hdr_slice.write_val(0, &CtrlHdr { class, command }).unwrap();
+        let data_slice = DmaStreamSlice::new(&stream, hdr_len, data.len());
+        data_slice.write_bytes(0, data).unwrap();
+        let ack_slice = DmaStreamSlice::new(&stream, hdr_len + data.len(), size_of::<u8>());
+        ack_slice.write_val(0, &u8::MAX).unwrap();
+        stream
+            .sync(0..hdr_len + data.len() + size_of::<u8>())
+            .unwrap();
+
+        let token = ctrl_queue
+            .add_dma_buf(&[&hdr_slice, &data_slice], &[&ack_slice])
+            .expect("add control queue failed");
+        if ctrl_queue.should_notify() {
+            ctrl_queue.notify();
+        }
+        while !ctrl_queue.can_pop() {
+            spin_loop();
+        }
+        ctrl_queue
+            .pop_used_with_token(token)
+            .expect("pop control queue failed");
+
+        ack_slice.sync().unwrap();
+        let ack: u8 = ack_slice.read_val(0).unwrap();
+        if ack != control::VIRTIO_NET_OK {
+            warn!(
+                "control command (class = {}, command = {}) fails: ack = {}",
+                class, command, ack
+            );
+        }
+    }
+
     /// Add a rx buffer to recv queue
     /// FIEME: Replace rx_buffer with VM segment-based data structure to use dma mapping.
-    fn add_rx_buffer(&mut self, rx_buffer: RxBuffer) -> Result<(), VirtioNetError> {
-        let token = self
+    fn add_rx_buffer(&mut self, queue: usize, rx_buffer: RxBuffer) -> Result<(), VirtioNetError> {
+        let queue_pair = &mut self.queue_pairs[queue];
+        let token = queue_pair
             .recv_queue
             .add_dma_buf(&[], &[&rx_buffer])
             .map_err(queue_to_network_error)?;
-        assert!(self.rx_buffers.put_at(token as usize, rx_buffer).is_none());
-        if self.recv_queue.should_notify() {
-            self.recv_queue.notify();
+        assert!(queue_pair
+            .rx_buffers
+            .put_at(token as usize, rx_buffer)
+            .is_none());
+        if queue_pair.recv_queue.should_notify() {
+            queue_pair.recv_queue.notify();
         }
         Ok(())
     }
 
-    /// Receive a packet from network. If packet is ready, returns a RxBuffer containing the packet.
-    /// Otherwise, return NotReady error.
-    fn receive(&mut self) -> Result<RxBuffer, VirtioNetError> {
-        let (token, len) = self.recv_queue.pop_used().map_err(queue_to_network_error)?;
-        debug!("receive packet: token = {}, len = {}", token, len);
-        let mut rx_buffer = self
-            .rx_buffers
-            .remove(token as usize)
-            .ok_or(VirtioNetError::WrongToken)?;
-        rx_buffer.set_packet_len(len as usize);
-        // FIXME: Ideally, we can reuse the returned buffer without creating new buffer.
-        // But this requires locking device to be compatible with smoltcp interface.
-        let rx_pool = RX_BUFFER_POOL.get().unwrap();
-        let new_rx_buffer = RxBuffer::new(size_of::<VirtioNetHdr>(), rx_pool);
-        self.add_rx_buffer(new_rx_buffer)?;
-        Ok(rx_buffer)
-    }
-
-    /// Send a packet to network. Return until the request completes.
+    /// Receive a packet from the receive queue. If packet is ready, returns a RxBuffer
+    /// containing the packet. Otherwise, return NotReady error.
+    fn receive(&mut self, queue: usize) -> Result<RxBuffer, VirtioNetError> {
+        loop {
+            let queue_pair = &mut self.queue_pairs[queue];
+            let (token, len) = queue_pair
+                .recv_queue
+                .pop_used()
+                .map_err(queue_to_network_error)?;
+            debug!("receive packet: token = {}, len = {}", token, len);
+            let mut rx_buffer = queue_pair
+                .rx_buffers
+                .remove(token as usize)
+                .ok_or(VirtioNetError::WrongToken)?;
+            rx_buffer.set_packet_len(len as usize);
+            // FIXME: Ideally, we can reuse the returned buffer without creating new buffer.
+            // But this requires locking device to be compatible with smoltcp interface.
+            let rx_pool = RX_BUFFER_POOL.get().unwrap();
+            let new_rx_buffer = RxBuffer::new(size_of::<VirtioNetHdr>(), rx_pool);
+            self.add_rx_buffer(queue, new_rx_buffer)?;
+
+            if self.is_checksum_valid(&rx_buffer) {
+                return Ok(rx_buffer);
+            }
+            debug!("drop packet with bad checksum");
+        }
+    }
+
+    /// Validates the checksum of the received packet, if the network stack leaves it to
+    /// the driver and the device has not validated it.
+    fn is_checksum_valid(&self, rx_buffer: &RxBuffer) -> bool {
+        if !self
+            .features
+            .contains(NetworkFeatures::VIRTIO_NET_F_GUEST_CSUM)
+        {
+            return true;
+        }
+
+        let header: VirtioNetHdr = rx_buffer.buf().read_val();
+        // A packet with a partial checksum comes from the same host and is not corrupted.
+        if header
+            .flags()
+            .intersects(Flags::VIRTIO_NET_HDR_F_DATA_VALID | Flags::VIRTIO_NET_HDR_F_NEEDS_CSUM)
+        {
+            return true;
+        }
+
+        let mut packet = vec![0u8; rx_buffer.packet_len()];
+        rx_buffer
+            .packet()
+            .read(&mut VmWriter::from(&mut packet as &mut [u8]));
+        checksum::is_checksum_valid(&packet)
+    }
+
+    /// Send a packet to network through the transmit queue. Return until the request completes.
     /// FIEME: Replace tx_buffer with VM segment-based data structure to use dma mapping.
-    fn send(&mut self, packet: &[u8]) -> Result<(), VirtioNetError> {
-        let header = VirtioNetHdr::default();
+    fn send(&mut self, queue: usize, packet: &[u8]) -> Result<(), VirtioNetError> {
+        // The segmentation is not offloaded, since the network stack never sends a packet
+        // larger than the MTU. So all packets are sent with `VIRTIO_NET_HDR_GSO_NONE`.
+        let partial_checksum = if self.features.contains(NetworkFeatures::VIRTIO_NET_F_CSUM) {
+            checksum::prepare_partial_checksum(packet)
+        } else {
+            None
+        };
+        let header = match partial_checksum {
+            Some((csum_start, csum_offset, _)) => {
+                VirtioNetHdr::with_partial_checksum(csum_start, csum_offset)
+            }
+            None => VirtioNetHdr::default(),
+        };
         let tx_pool = TX_BUFFER_POOL.get().unwrap();
-        let tx_buffer = TxBuffer::new(&header, packet, tx_pool);
+        let tx_buffer = TxBuffer::new_from_pool(&header, packet, tx_pool)
+            .map_err(|_| VirtioNetError::Unknown)?;
+        if let Some((csum_start, csum_offset, pseudo_header_sum)) = partial_checksum {
+            let offset = VIRTIO_NET_HDR_LEN + (csum_start + csum_offset) as usize;
+            tx_buffer.write_bytes_at(offset, &pseudo_header_sum);
+        }
 
-        let token = self
-            .send_queue
+        let send_queue = &mut self.queue_pairs[queue].send_queue;
+        let token = send_queue
             .add_dma_buf(&[&tx_buffer], &[])
             .map_err(queue_to_network_error)?;
 
-        if self.send_queue.should_notify() {
-            self.send_queue.notify();
+        if send_queue.should_notify() {
+            send_queue.notify();
         }
         // Wait until the buffer is used
-        while !self.send_queue.can_pop() {
+        while !send_queue.can_pop() {
             spin_loop();
         }
         // Pop out the buffer, so we can reuse the send queue further
-        let (pop_token, _) = self.send_queue.pop_used().map_err(queue_to_network_error)?;
+        let (pop_token, _) = send_queue.pop_used().map_err(queue_to_network_error)?;
         debug_assert!(pop_token == token);
         if pop_token != token {
             return Err(VirtioNetError::WrongToken);
@@ -166,6 +351,11 @@ impl NetworkDevice {
         debug!("send packet succeeds");
         Ok(())
     }
+
+    /// Returns the transmit queue of the current CPU.
+    fn this_cpu_queue(&self) -> usize {
+        this_cpu() as usize % self.queue_pairs.len()
+    }
 }
 
 fn queue_to_network_error(err: QueueError) -> VirtioNetError {
@@ -186,23 +376,62 @@ impl AnyNetworkDevice for NetworkDevice {
         caps.max_transmission_unit = 1536;
         caps.max_burst_size = Some(1);
         caps.medium = Medium::Ethernet;
+
+        // The checksums of the TCP and UDP packets are skipped by the network stack if the
+        // device computes them for the sent packets, or the device or the driver validates
+        // them for the received packets.
+        let offloads_tx = self.features.contains(NetworkFeatures::VIRTIO_NET_F_CSUM);
+        let offloads_rx = self
+            .features
+            .contains(NetworkFeatures::VIRTIO_NET_F_GUEST_CSUM);
+        let tcp_udp_checksum = match (offloads_tx, offloads_rx) {
+            (true, true) => Checksum::None,
+            (true, false) => Checksum::Rx,
+            (false, true) => Checksum::Tx,
+            (false, false) => Checksum::Both,
+        };
+        caps.checksum.tcp = tcp_udp_checksum;
+        caps.checksum.udp = tcp_udp_checksum;
         caps
     }
 
     fn can_receive(&self) -> bool {
-        self.recv_queue.can_pop()
+        (0..self.queue_pairs.len()).any(|queue| self.can_receive_from(queue))
     }
 
     fn can_send(&self) -> bool {
-        self.send_queue.available_desc() >= 2
+        self.can_send_to(self.this_cpu_queue())
     }
 
     fn receive(&mut self) -> Result<RxBuffer, VirtioNetError> {
-        self.receive()
+        let queue = (0..self.queue_pairs.len())
+            .find(|queue| self.can_receive_from(*queue))
+            .ok_or(VirtioNetError::NotReady)?;
+        self.receive(queue)
     }
 
     fn send(&mut self, packet: &[u8]) -> Result<(), VirtioNetError> {
-        self.send(packet)
+        self.send(self.this_cpu_queue(), packet)
+    }
+
+    fn num_queue_pairs(&self) -> usize {
+        self.queue_pairs.len()
+    }
+
+    fn can_receive_from(&self, queue: usize) -> bool {
+        self.queue_pairs[queue].recv_queue.can_pop()
+    }
+
+    fn receive_from(&mut self, queue: usize) -> Result<RxBuffer, VirtioNetError> {
+        self.receive(queue)
+    }
+
+    fn can_send_to(&self, queue: usize) -> bool {
+        self.queue_pairs[queue].send_queue.available_desc() >= 2
+    }
+
+    fn send_to(&mut self, queue: usize, packet: &[u8]) -> Result<(), VirtioNetError> {
+        self.send(queue, packet)
     }
 }
 
@@ -210,15 +439,24 @@ impl Debug for NetworkDevice {
     fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
         f.debug_struct("NetworkDevice")
             .field("config", &self.config)
+            .field("features", &self.features)
             .field("mac_addr", &self.mac_addr)
-            .field("send_queue", &self.send_queue)
-            .field("recv_queue", &self.recv_queue)
+            .field("queue_pairs", &self.queue_pairs)
+            .field("ctrl_queue", &self.ctrl_queue)
             .field("transport", &self.transport)
             .finish()
     }
 }
 
-const QUEUE_RECV: u16 = 0;
-const QUEUE_SEND: u16 = 1;
+impl Debug for QueuePair {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        f.debug_struct("QueuePair")
+            .field("send_queue", &self.send_queue)
+            .field("recv_queue", &self.recv_queue)
+            .finish()
+    }
+}
 
 const QUEUE_SIZE: u16 = 64;
+
+const CTRL_QUEUE_SIZE: u16 = 16;
@@ -22,6 +22,25 @@ pub struct VirtioNetHdr {
                       // padding_reserved: u16,  // Only if VIRTIO_NET_F_HASH_REPORT negotiated
 }
 
+impl VirtioNetHdr {
+    /// Creates a header of a packet whose checksum is to be completed by the device.
+    ///
+    /// The device computes the checksum from `csum_start` to the end of the packet, and
+    /// stores it at `csum_offset` after `csum_start`.
+    pub fn with_partial_checksum(csum_start: u16, csum_offset: u16) -> Self {
+        Self {
+            flags: Flags::VIRTIO_NET_HDR_F_NEEDS_CSUM,
+            csum_start,
+            csum_offset,
+            ..Self::default()
+        }
+    }
+
+    pub fn flags(&self) -> Flags {
+        self.flags
+    }
+}
+
 bitflags! {
     #[repr(C)]
     #[derive(Default, Pod)]
@@ -1,6 +1,8 @@
 // SPDX-License-Identifier: MPL-2.0
 
+mod checksum;
 pub mod config;
+mod control;
 pub mod device;
 pub mod header;
 
@@ -0,0 +1,42 @@
+// SPDX-License-Identifier: MPL-2.0
+
+use alloc::string::String;
+
+use aster_frame::{io_mem::IoMem, mm::VmIo};
+use aster_util::safe_ptr::SafePtr;
+use pod::Pod;
+
+use crate::transport::VirtioTransport;
+
+bitflags::bitflags! {
+    pub struct Transport9PFeatures: u64 {
+        /// The mount tag is in the configuration space.
+        const VIRTIO_9P_MOUNT_TAG = 1 << 0;
+    }
+}
+
+/// The fixed part of the configuration space, which is followed by `tag_len` bytes of the
+/// mount tag.
+#[derive(Debug, Pod, Clone, Copy)]
+#[repr(C)]
+pub struct Virtio9PConfig {
+    pub tag_len: u16,
+}
+
+impl Virtio9PConfig {
+    pub(super) fn new(transport: &dyn VirtioTransport) -> SafePtr<Self, IoMem> {
+        let memory = transport.device_config_memory();
+        SafePtr::new(memory, 0)
+    }
+
+    /// Reads the mount tag, which is not terminated by a NUL byte.
+    pub(super) fn read_tag(transport: &dyn VirtioTransport) -> String {
+        let memory = transport.device_config_memory();
+        let tag_len = Self::new(transport).read().unwrap().tag_len as usize;
+        let mut tag = alloc::vec![0u8; tag_len];
+        memory
+            .read_bytes(core::mem::size_of::<Self>(), &mut tag)
+            .unwrap();
+        String::from_utf8_lossy(&tag).into_owned()
+    }
+}
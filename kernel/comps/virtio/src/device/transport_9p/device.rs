@@ -0,0 +1,176 @@
+// SPDX-License-Identifier: MPL-2.0
+
+use alloc::{boxed::Box, collections::BTreeMap, string::String, sync::Arc};
+use core::{
+    fmt::Debug,
+    sync::atomic::{AtomicU64, Ordering},
+};
+
+use aster_frame::{
+    mm::{DmaDirection, DmaStream, DmaStreamSlice, FrameAllocOptions, VmIo, PAGE_SIZE},
+    sync::{SpinLock, WaitQueue},
+    trap::TrapFrame,
+};
+use log::{debug, info};
+
+use super::{
+    config::{Transport9PFeatures, Virtio9PConfig},
+    register_device,
+};
+use crate::{device::VirtioDeviceError, queue::VirtQueue, transport::VirtioTransport};
+
+const QUEUE_SIZE: u16 = 64;
+const QUEUE_REQUEST: u16 = 0;
+
+/// A virtio-9p device.
+pub struct Transport9PDevice {
+    tag: String,
+    request_queue: SpinLock<VirtQueue>,
+    transport: SpinLock<Box<dyn VirtioTransport>>,
+    /// The IDs of the requests that are being processed by the device, indexed by the tokens
+    /// of the requests.
+    ///
+    /// A token can be reused as soon as its request is completed, so the requests are
+    /// identified by the IDs instead.
+    submitted_requests: SpinLock<BTreeMap<u16, u64>>,
+    /// The lengths of the replies of the completed requests, indexed by the request IDs.
+    completed_requests: SpinLock<BTreeMap<u64, usize>>,
+    next_request_id: AtomicU64,
+    wait_queue: WaitQueue,
+}
+
+impl Transport9PDevice {
+    /// Negotiates features for the device specified bits 0~23.
+    pub(crate) fn negotiate_features(features: u64) -> u64 {
+        let features = Transport9PFeatures::from_bits_truncate(features);
+        (features & Transport9PFeatures::VIRTIO_9P_MOUNT_TAG).bits()
+    }
+
+    /// Creates a new virtio-9p driver and registers it.
+    pub(crate) fn init(mut transport: Box<dyn VirtioTransport>) -> Result<(), VirtioDeviceError> {
+        let tag = Virtio9PConfig::read_tag(transport.as_ref());
+        info!("[Virtio-9P]: tag = {}", tag);
+
+        let request_queue = VirtQueue::new(QUEUE_REQUEST, QUEUE_SIZE, transport.as_mut())
+            .expect("creating request queue fails");
+
+        let device = Arc::new(Self {
+            tag: tag.clone(),
+            request_queue: SpinLock::new(request_queue),
+            transport: SpinLock::new(transport),
+            submitted_requests: SpinLock::new(BTreeMap::new()),
+            completed_requests: SpinLock::new(BTreeMap::new()),
+            next_request_id: AtomicU64::new(0),
+            wait_queue: WaitQueue::new(),
+        });
+
+        let cloned_device = device.clone();
+        let handle_request_irq = move |_: &TrapFrame| {
+            cloned_device.handle_irq();
+        };
+        let handle_config_change = |_: &TrapFrame| {
+            debug!("virtio-9p device config space change");
+        };
+
+        {
+            let mut transport = device.transport.lock();
+            transport
+                .register_cfg_callback(Box::new(handle_config_change))
+                .unwrap();
+            transport
+                .register_queue_callback(QUEUE_REQUEST, Box::new(handle_request_irq), false)
+                .unwrap();
+            transport.finish_init();
+        }
+
+        register_device(tag, device);
+        Ok(())
+    }
+
+    /// Returns the mount tag of the device.
+    pub fn tag(&self) -> &str {
+        &self.tag
+    }
+
+    /// Sends a T-message and waits for the R-message to be written to `reply`.
+    ///
+    /// Returns the length of the R-message.
+    pub fn request(&self, request: &[u8], reply: &mut [u8]) -> usize {
+        let request_stream = Self::alloc_stream(request.len(), DmaDirection::ToDevice);
+        request_stream.write_bytes(0, request).unwrap();
+        request_stream.sync(0..request.len()).unwrap();
+        let request_slice = DmaStreamSlice::new(&request_stream, 0, request.len());
+        let reply_stream = Self::alloc_stream(reply.len(), DmaDirection::FromDevice);
+        let reply_slice = DmaStreamSlice::new(&reply_stream, 0, reply.len());
+
+        let reply_len = self
+            .submit_and_wait(&[&request_slice], &[&reply_slice])
+            .min(reply.len());
+        reply_slice.sync().unwrap();
+        reply_stream.read_bytes(0, &mut reply[..reply_len]).unwrap();
+        reply_len
+    }
+
+    fn submit_and_wait(&self, inputs: &[&DmaStreamSlice], outputs: &[&DmaStreamSlice]) -> usize {
+        let num_used_descs = inputs.len() + outputs.len();
+        let request_id = self.next_request_id.fetch_add(1, Ordering::Relaxed);
+        loop {
+            let mut queue = self.request_queue.lock_irq_disabled();
+            if num_used_descs > queue.available_desc() {
+                continue;
+            }
+            let token = queue
+                .add_dma_buf(inputs, outputs)
+                .expect("add queue failed");
+            // Record the request before the device can complete it.
+            self.submitted_requests
+                .lock_irq_disabled()
+                .insert(token, request_id);
+            if queue.should_notify() {
+                queue.notify();
+            }
+            break;
+        }
+
+        self.wait_queue.wait_until(|| {
+            self.completed_requests
+                .lock_irq_disabled()
+                .remove(&request_id)
+        })
+    }
+
+    /// Handles the completed requests.
+    fn handle_irq(&self) {
+        // When we enter the IRQs handling function,
+        // IRQs have already been disabled,
+        // so there is no need to call `lock_irq_disabled`.
+        {
+            let mut queue = self.request_queue.lock();
+            while let Ok((token, len)) = queue.pop_used() {
+                let Some(request_id) = self.submitted_requests.lock().remove(&token) else {
+                    continue;
+                };
+                self.completed_requests
+                    .lock()
+                    .insert(request_id, len as usize);
+            }
+        }
+        self.wait_queue.wake_all();
+    }
+
+    fn alloc_stream(len: usize, direction: DmaDirection) -> DmaStream {
+        let segment = FrameAllocOptions::new(len.max(1).div_ceil(PAGE_SIZE))
+            .uninit(true)
+            .alloc_contiguous()
+            .unwrap();
+        DmaStream::map(segment, direction, false).unwrap()
+    }
+}
+
+impl Debug for Transport9PDevice {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        f.debug_struct("Transport9PDevice")
+            .field("tag", &self.tag)
+            .finish()
+    }
+}
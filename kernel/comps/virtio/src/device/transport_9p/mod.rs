@@ -0,0 +1,54 @@
+// SPDX-License-Identifier: MPL-2.0
+
+//! The virtio-9p device, which exports a directory of the host through the 9P protocol.
+//!
+//! The device only transports the 9P messages. The 9P protocol itself is spoken by the
+//! file system in the kernel.
+
+use alloc::{collections::BTreeMap, string::String, sync::Arc, vec::Vec};
+
+use aster_frame::sync::SpinLock;
+use spin::Once;
+
+use self::device::Transport9PDevice;
+
+pub mod config;
+pub mod device;
+
+pub static DEVICE_NAME: &str = "Virtio-9P";
+
+/// Registers a virtio-9p device by its mount tag.
+pub fn register_device(tag: String, device: Arc<Transport9PDevice>) {
+    TRANSPORT_9P_DEVICE_TABLE
+        .get()
+        .unwrap()
+        .lock_irq_disabled()
+        .insert(tag, device);
+}
+
+/// Gets the virtio-9p device with the mount tag, which is the "device name" when mounting it.
+pub fn get_device(tag: &str) -> Option<Arc<Transport9PDevice>> {
+    TRANSPORT_9P_DEVICE_TABLE
+        .get()
+        .unwrap()
+        .lock_irq_disabled()
+        .get(tag)
+        .cloned()
+}
+
+pub fn all_devices() -> Vec<(String, Arc<Transport9PDevice>)> {
+    TRANSPORT_9P_DEVICE_TABLE
+        .get()
+        .unwrap()
+        .lock_irq_disabled()
+        .iter()
+        .map(|(tag, device)| (tag.clone(), device.clone()))
+        .collect()
+}
+
+pub fn init() {
+    TRANSPORT_9P_DEVICE_TABLE.call_once(|| SpinLock::new(BTreeMap::new()));
+}
+
+static TRANSPORT_9P_DEVICE_TABLE: Once<SpinLock<BTreeMap<String, Arc<Transport9PDevice>>>> =
+    Once::new();
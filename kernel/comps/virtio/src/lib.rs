@@ -14,12 +14,22 @@ use alloc::boxed::Box;
 
 use bitflags::bitflags;
 use component::{init_component, ComponentInitError};
+#[cfg(feature = "balloon")]
+use device::balloon::device::BalloonDevice;
+#[cfg(feature = "entropy")]
+use device::entropy::device::EntropyDevice;
+#[cfg(feature = "filesystem")]
+use device::filesystem::{self, device::FileSystemDevice};
+#[cfg(feature = "gpu")]
+use device::gpu::device::GpuDevice;
+#[cfg(feature = "network")]
+use device::network::device::NetworkDevice;
+#[cfg(feature = "network")]
+use device::socket::{self, device::SocketDevice};
+#[cfg(feature = "transport-9p")]
+use device::transport_9p::{self, device::Transport9PDevice};
 use device::{
-    block::device::BlockDevice,
-    console::device::ConsoleDevice,
-    input::device::InputDevice,
-    network::device::NetworkDevice,
-    socket::{self, device::SocketDevice},
+    block::device::BlockDevice, console::device::ConsoleDevice, input::device::InputDevice,
     VirtioDeviceType,
 };
 use log::{error, warn};
@@ -37,7 +47,12 @@ fn virtio_component_init() -> Result<(), ComponentInitError> {
     // Find all devices and register them to the corresponding crate
     transport::init();
     // For vsock table static init
+    #[cfg(feature = "network")]
     socket::init();
+    #[cfg(feature = "filesystem")]
+    filesystem::init();
+    #[cfg(feature = "transport-9p")]
+    transport_9p::init();
     while let Some(mut transport) = pop_device_transport() {
         // Reset device
         transport.set_device_status(DeviceStatus::empty()).unwrap();
@@ -58,9 +73,21 @@ fn virtio_component_init() -> Result<(), ComponentInitError> {
         let res = match transport.device_type() {
             VirtioDeviceType::Block => BlockDevice::init(transport),
             VirtioDeviceType::Input => InputDevice::init(transport),
+            #[cfg(feature = "network")]
             VirtioDeviceType::Network => NetworkDevice::init(transport),
             VirtioDeviceType::Console => ConsoleDevice::init(transport),
+            #[cfg(feature = "entropy")]
+            VirtioDeviceType::Entropy => EntropyDevice::init(transport),
+            #[cfg(feature = "network")]
             VirtioDeviceType::Socket => SocketDevice::init(transport),
+            #[cfg(feature = "filesystem")]
+            VirtioDeviceType::FileSystem => FileSystemDevice::init(transport),
+            #[cfg(feature = "transport-9p")]
+            VirtioDeviceType::Transport9P => Transport9PDevice::init(transport),
+            #[cfg(feature = "balloon")]
+            VirtioDeviceType::TraditionalMemoryBalloon => BalloonDevice::init(transport),
+            #[cfg(feature = "gpu")]
+            VirtioDeviceType::GPU => GpuDevice::init(transport),
             _ => {
                 warn!("[Virtio]: Found unimplemented device:{:?}", device_type);
                 Ok(())
@@ -91,15 +118,37 @@ fn negotiate_features(transport: &mut Box<dyn VirtioTransport>) {
     let mask = ((1u64 << 24) - 1) | (((1u64 << 24) - 1) << 50);
     let device_specified_features = features & mask;
     let device_support_features = match transport.device_type() {
+        #[cfg(feature = "network")]
         VirtioDeviceType::Network => NetworkDevice::negotiate_features(device_specified_features),
         VirtioDeviceType::Block => BlockDevice::negotiate_features(device_specified_features),
         VirtioDeviceType::Input => InputDevice::negotiate_features(device_specified_features),
         VirtioDeviceType::Console => ConsoleDevice::negotiate_features(device_specified_features),
+        #[cfg(feature = "entropy")]
+        VirtioDeviceType::Entropy => EntropyDevice::negotiate_features(device_specified_features),
+        #[cfg(feature = "network")]
         VirtioDeviceType::Socket => SocketDevice::negotiate_features(device_specified_features),
+        #[cfg(feature = "filesystem")]
+        VirtioDeviceType::FileSystem => {
+            FileSystemDevice::negotiate_features(device_specified_features)
+        }
+        #[cfg(feature = "transport-9p")]
+        VirtioDeviceType::Transport9P => {
+            Transport9PDevice::negotiate_features(device_specified_features)
+        }
+        #[cfg(feature = "balloon")]
+        VirtioDeviceType::TraditionalMemoryBalloon => {
+            BalloonDevice::negotiate_features(device_specified_features)
+        }
+        #[cfg(feature = "gpu")]
+        VirtioDeviceType::GPU => GpuDevice::negotiate_features(device_specified_features),
         _ => device_specified_features,
     };
     let mut support_feature = Feature::from_bits_truncate(features);
     support_feature.remove(Feature::RING_EVENT_IDX);
+    // The legacy interfaces only support split virtqueues.
+    if transport.is_legacy_version() {
+        support_feature.remove(Feature::RING_PACKED);
+    }
     transport
         .set_driver_features(features & (support_feature.bits | device_support_features))
         .unwrap();
@@ -0,0 +1,256 @@
+// SPDX-License-Identifier: MPL-2.0
+
+//! Virtqueue
+//!
+//! A virtqueue is either a split virtqueue or a packed virtqueue, which is selected by
+//! whether the `VIRTIO_F_RING_PACKED` feature is negotiated. The two formats have the same
+//! interface, so the device drivers work with both of them.
+
+mod packed;
+mod split;
+
+use alloc::{vec, vec::Vec};
+
+use aster_frame::{
+    io_mem::IoMem,
+    mm::{DmaCoherent, FrameAllocOptions, Paddr, PAGE_SIZE},
+};
+use aster_util::safe_ptr::SafePtr;
+use log::debug;
+
+pub use self::split::{AvailRing, Descriptor, UsedElem, UsedRing};
+use self::{packed::PackedRing, split::SplitRing};
+use crate::{dma_buf::DmaBuf, transport::VirtioTransport, Feature};
+
+#[derive(Debug)]
+pub enum QueueError {
+    InvalidArgs,
+    BufferTooSmall,
+    NotReady,
+    AlreadyUsed,
+    WrongToken,
+}
+
+/// The mechanism for bulk data transport on virtio devices.
+///
+/// Each device can have zero or more virtqueues.
+#[derive(Debug)]
+pub struct VirtQueue {
+    /// The descriptors and the rings
+    ring: Ring,
+    /// point to notify address
+    notify: SafePtr<u32, IoMem>,
+    /// The index of queue
+    queue_idx: u32,
+    /// The size of the queue.
+    ///
+    /// This is the number of descriptors, as well as the number of slots in the rings.
+    queue_size: u16,
+    /// Whether the `VIRTIO_F_INDIRECT_DESC` feature is negotiated.
+    supports_indirect: bool,
+    /// The indirect descriptor tables indexed by the tokens, which are allocated on demand
+    /// and reused since then.
+    indirect_tables: Vec<Option<DmaCoherent>>,
+}
+
+#[derive(Debug)]
+enum Ring {
+    Split(SplitRing),
+    Packed(PackedRing),
+}
+
+/// The physical addresses of the areas of a virtqueue.
+struct RingAreas {
+    descriptor: Paddr,
+    driver: Paddr,
+    device: Paddr,
+}
+
+impl VirtQueue {
+    /// The maximum number of buffers in an indirect descriptor table.
+    ///
+    /// Each table takes one page, and the descriptors of both formats are 16 bytes.
+    pub const MAX_INDIRECT_DESCS: usize = PAGE_SIZE / 16;
+
+    /// Create a new VirtQueue.
+    pub(crate) fn new(
+        idx: u16,
+        size: u16,
+        transport: &mut dyn VirtioTransport,
+    ) -> Result<Self, QueueError> {
+        if !size.is_power_of_two() {
+            return Err(QueueError::InvalidArgs);
+        }
+
+        let driver_features = Feature::from_bits_truncate(transport.driver_features());
+        let is_packed = driver_features.contains(Feature::RING_PACKED);
+        let supports_indirect = driver_features.contains(Feature::RING_INDIRECT_DESC);
+        let (ring, areas) = if is_packed {
+            let (ring, areas) = PackedRing::new(size)?;
+            (Ring::Packed(ring), areas)
+        } else {
+            let (ring, areas) = SplitRing::new(size, transport.is_legacy_version())?;
+            (Ring::Split(ring), areas)
+        };
+        debug!("queue_desc start paddr:{:x?}", areas.descriptor);
+        debug!("queue_driver start paddr:{:x?}", areas.driver);
+        debug!("queue_device start paddr:{:x?}", areas.device);
+
+        transport
+            .set_queue(idx, size, areas.descriptor, areas.driver, areas.device)
+            .unwrap();
+        let notify = transport.get_notify_ptr(idx).unwrap();
+        Ok(VirtQueue {
+            ring,
+            notify,
+            queue_idx: idx as u32,
+            queue_size: size,
+            supports_indirect,
+            indirect_tables: Vec::new(),
+        })
+    }
+
+    /// Add dma buffers to the virtqueue, return a token.
+    ///
+    /// Ref: linux virtio_ring.c virtqueue_add
+    pub fn add_dma_buf<T: DmaBuf>(
+        &mut self,
+        inputs: &[&T],
+        outputs: &[&T],
+    ) -> Result<u16, QueueError> {
+        if inputs.is_empty() && outputs.is_empty() {
+            return Err(QueueError::InvalidArgs);
+        }
+        if inputs.len() + outputs.len() > self.available_desc() {
+            return Err(QueueError::BufferTooSmall);
+        }
+
+        match &mut self.ring {
+            Ring::Split(ring) => Ok(ring.add_dma_buf(inputs, outputs)),
+            Ring::Packed(ring) => Ok(ring.add_dma_buf(inputs, outputs)),
+        }
+    }
+
+    /// Add dma buffers to the virtqueue through an indirect descriptor table, return a token.
+    ///
+    /// The buffers take only one descriptor of the queue, so a request with many buffers
+    /// neither waits for lots of free descriptors nor exceeds the queue size. The number
+    /// of buffers must not exceed [`Self::MAX_INDIRECT_DESCS`].
+    ///
+    /// Ref: linux virtio_ring.c virtqueue_add_split and virtqueue_add_indirect_packed
+    pub fn add_dma_buf_indirect<T: DmaBuf>(
+        &mut self,
+        inputs: &[&T],
+        outputs: &[&T],
+    ) -> Result<u16, QueueError> {
+        let num = inputs.len() + outputs.len();
+        if !self.supports_indirect || num == 0 || num > Self::MAX_INDIRECT_DESCS {
+            return Err(QueueError::InvalidArgs);
+        }
+        if self.available_desc() == 0 {
+            return Err(QueueError::BufferTooSmall);
+        }
+
+        let token = match &self.ring {
+            Ring::Split(ring) => ring.next_token(),
+            Ring::Packed(ring) => ring.next_token(),
+        };
+        if self.indirect_tables.is_empty() {
+            self.indirect_tables = vec![None; self.queue_size as usize];
+        }
+        if self.indirect_tables[token as usize].is_none() {
+            let segment = FrameAllocOptions::new(1)
+                .alloc_contiguous()
+                .map_err(|_| QueueError::BufferTooSmall)?;
+            let table = DmaCoherent::map(segment, true).map_err(|_| QueueError::BufferTooSmall)?;
+            self.indirect_tables[token as usize] = Some(table);
+        }
+        let table = self.indirect_tables[token as usize].as_ref().unwrap();
+
+        // The table of a token is not used by the device until the token is added again.
+        match &mut self.ring {
+            Ring::Split(ring) => {
+                split::write_indirect_table(table, inputs, outputs);
+                Ok(ring.add_indirect_table(table, num))
+            }
+            Ring::Packed(ring) => {
+                packed::write_indirect_table(table, inputs, outputs);
+                Ok(ring.add_indirect_table(table, num))
+            }
+        }
+    }
+
+    /// Whether the buffers can be added through indirect descriptor tables.
+    pub fn supports_indirect(&self) -> bool {
+        self.supports_indirect
+    }
+
+    /// Whether there is a used element that can pop.
+    pub fn can_pop(&self) -> bool {
+        match &self.ring {
+            Ring::Split(ring) => ring.can_pop(),
+            Ring::Packed(ring) => ring.can_pop(),
+        }
+    }
+
+    /// The number of free descriptors.
+    pub fn available_desc(&self) -> usize {
+        match &self.ring {
+            Ring::Split(ring) => ring.available_desc(),
+            Ring::Packed(ring) => ring.available_desc(),
+        }
+    }
+
+    /// Get a token from device used buffers, return (token, len).
+    ///
+    /// Ref: linux virtio_ring.c virtqueue_get_buf_ctx
+    pub fn pop_used(&mut self) -> Result<(u16, u32), QueueError> {
+        if !self.can_pop() {
+            return Err(QueueError::NotReady);
+        }
+
+        match &mut self.ring {
+            Ring::Split(ring) => ring.pop_used(None),
+            Ring::Packed(ring) => ring.pop_used(None),
+        }
+    }
+
+    /// If the given token is next on the device used queue, pops it and returns the total buffer
+    /// length which was used (written) by the device.
+    ///
+    /// Ref: linux virtio_ring.c virtqueue_get_buf_ctx
+    pub fn pop_used_with_token(&mut self, token: u16) -> Result<u32, QueueError> {
+        if !self.can_pop() {
+            return Err(QueueError::NotReady);
+        }
+
+        let (_, len) = match &mut self.ring {
+            Ring::Split(ring) => ring.pop_used(Some(token))?,
+            Ring::Packed(ring) => ring.pop_used(Some(token))?,
+        };
+        Ok(len)
+    }
+
+    /// Return size of the queue.
+    pub fn size(&self) -> u16 {
+        self.queue_size
+    }
+
+    /// Whether the queue is a packed virtqueue.
+    pub fn is_packed(&self) -> bool {
+        matches!(self.ring, Ring::Packed(_))
+    }
+
+    /// whether the driver should notify the device
+    pub fn should_notify(&self) -> bool {
+        match &self.ring {
+            Ring::Split(ring) => ring.should_notify(),
+            Ring::Packed(ring) => ring.should_notify(),
+        }
+    }
+
+    /// notify that there are available rings
+    pub fn notify(&mut self) {
+        self.notify.write(&self.queue_idx).unwrap();
+    }
+}
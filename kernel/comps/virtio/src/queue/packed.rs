@@ -0,0 +1,348 @@
+// SPDX-License-Identifier: MPL-2.0
+
+//! Packed virtqueues
+//!
+//! A packed virtqueue has a single descriptor ring, which is shared by the driver and the
+//! device. The driver makes descriptors available in the ring order, and the device writes
+//! the used descriptors back to the ring. Whether a descriptor is available or used is told
+//! by its `AVAIL` and `USED` flags, compared with the wrap counters that flip each time the
+//! ring wraps around.
+//!
+//! Ref: virtio v1.1 spec, section 2.7 Packed Virtqueues
+
+use alloc::{vec, vec::Vec};
+use core::{
+    mem::size_of,
+    sync::atomic::{fence, Ordering},
+};
+
+use aster_frame::mm::{DmaCoherent, FrameAllocOptions, HasDaddr, VmIo};
+use aster_util::{field_ptr, safe_ptr::SafePtr};
+use bitflags::bitflags;
+use pod::Pod;
+
+use super::{QueueError, RingAreas};
+use crate::dma_buf::DmaBuf;
+
+/// The descriptor ring and the event suppression structures of a packed virtqueue.
+#[derive(Debug)]
+pub(super) struct PackedRing {
+    /// Descriptor ring
+    descs: Vec<SafePtr<PackedDescriptor, DmaCoherent>>,
+    /// The event suppression structure written by the device
+    device_event: SafePtr<EventSuppression, DmaCoherent>,
+    /// The size of the queue.
+    queue_size: u16,
+    /// The number of free descriptors.
+    num_free: u16,
+    /// The ring index of the next available descriptor.
+    next_avail_idx: u16,
+    /// The wrap counter of the available descriptors.
+    avail_wrap_counter: bool,
+    /// The ring index of the next used descriptor.
+    last_used_idx: u16,
+    /// The wrap counter of the used descriptors.
+    used_wrap_counter: bool,
+    /// The head of the free list of buffer IDs.
+    free_head: u16,
+    /// The next free buffer ID of each free buffer ID.
+    free_next: Vec<u16>,
+    /// The number of descriptors of the buffer with each ID.
+    chain_len: Vec<u16>,
+}
+
+impl PackedRing {
+    /// Allocate the descriptor ring and the event suppression structures of a packed virtqueue.
+    pub(super) fn new(size: u16) -> Result<(Self, RingAreas), QueueError> {
+        if size as usize * size_of::<PackedDescriptor>() > aster_frame::mm::PAGE_SIZE {
+            return Err(QueueError::InvalidArgs);
+        }
+
+        let desc_ptr: SafePtr<PackedDescriptor, DmaCoherent> = SafePtr::new(
+            DmaCoherent::map(FrameAllocOptions::new(1).alloc_contiguous().unwrap(), true).unwrap(),
+            0,
+        );
+        let driver_event: SafePtr<EventSuppression, DmaCoherent> = SafePtr::new(
+            DmaCoherent::map(FrameAllocOptions::new(1).alloc_contiguous().unwrap(), true).unwrap(),
+            0,
+        );
+        let mut device_event = driver_event.clone();
+        device_event.add(1);
+        let areas = RingAreas {
+            descriptor: desc_ptr.paddr(),
+            driver: driver_event.paddr(),
+            device: device_event.paddr(),
+        };
+
+        let mut descs = Vec::with_capacity(size as usize);
+        for i in 0..size as usize {
+            let mut desc = desc_ptr.clone();
+            desc.add(i);
+            descs.push(desc);
+        }
+        // Enable the notifications from the device.
+        field_ptr!(&driver_event, EventSuppression, flags)
+            .write(&EventFlags::ENABLE)
+            .unwrap();
+
+        let ring = PackedRing {
+            descs,
+            device_event,
+            queue_size: size,
+            num_free: size,
+            next_avail_idx: 0,
+            avail_wrap_counter: true,
+            last_used_idx: 0,
+            used_wrap_counter: true,
+            free_head: 0,
+            free_next: (1..=size).collect(),
+            chain_len: vec![0; size as usize],
+        };
+        Ok((ring, areas))
+    }
+
+    /// Add dma buffers to the virtqueue, return a token, which is the buffer ID.
+    ///
+    /// The number of buffers must not exceed the number of free descriptors.
+    pub(super) fn add_dma_buf<T: DmaBuf>(&mut self, inputs: &[&T], outputs: &[&T]) -> u16 {
+        let id = self.free_head;
+        self.free_head = self.free_next[id as usize];
+
+        let num = inputs.len() + outputs.len();
+        let head = self.next_avail_idx;
+        let head_wrap_counter = self.avail_wrap_counter;
+        let mut head_flags = DescFlags::empty();
+        let buffers = inputs
+            .iter()
+            .map(|buf| (*buf, DescFlags::empty()))
+            .chain(outputs.iter().map(|buf| (*buf, DescFlags::WRITE)));
+        for (i, (buf, mut flags)) in buffers.enumerate() {
+            if i + 1 != num {
+                flags |= DescFlags::NEXT;
+            }
+            let desc = &self.descs[self.next_avail_idx as usize];
+            // TODO: skip the empty dma buffer or just return error?
+            debug_assert_ne!(buf.len(), 0);
+            field_ptr!(desc, PackedDescriptor, addr)
+                .write(&(buf.daddr() as u64))
+                .unwrap();
+            field_ptr!(desc, PackedDescriptor, len)
+                .write(&(buf.len() as u32))
+                .unwrap();
+            field_ptr!(desc, PackedDescriptor, id).write(&id).unwrap();
+            // The head descriptor is made available after all the others, so that the
+            // device never sees a partial chain.
+            if i == 0 {
+                head_flags = flags;
+            } else {
+                field_ptr!(desc, PackedDescriptor, flags)
+                    .write(&(flags | DescFlags::avail_used(self.avail_wrap_counter)))
+                    .unwrap();
+            }
+
+            self.next_avail_idx += 1;
+            if self.next_avail_idx == self.queue_size {
+                self.next_avail_idx = 0;
+                self.avail_wrap_counter = !self.avail_wrap_counter;
+            }
+        }
+        self.chain_len[id as usize] = num as u16;
+        self.num_free -= num as u16;
+
+        // write barrier
+        fence(Ordering::SeqCst);
+        field_ptr!(&self.descs[head as usize], PackedDescriptor, flags)
+            .write(&(head_flags | DescFlags::avail_used(head_wrap_counter)))
+            .unwrap();
+        fence(Ordering::SeqCst);
+
+        id
+    }
+
+    /// The token of the next buffer to add.
+    pub(super) fn next_token(&self) -> u16 {
+        self.free_head
+    }
+
+    /// Add an indirect descriptor table with `num` descriptors to the virtqueue, return a
+    /// token.
+    ///
+    /// The table takes only one descriptor of the queue, which must be free.
+    pub(super) fn add_indirect_table(&mut self, table: &DmaCoherent, num: usize) -> u16 {
+        let id = self.free_head;
+        self.free_head = self.free_next[id as usize];
+
+        let desc = &self.descs[self.next_avail_idx as usize];
+        field_ptr!(desc, PackedDescriptor, addr)
+            .write(&(table.daddr() as u64))
+            .unwrap();
+        field_ptr!(desc, PackedDescriptor, len)
+            .write(&((num * size_of::<PackedDescriptor>()) as u32))
+            .unwrap();
+        field_ptr!(desc, PackedDescriptor, id).write(&id).unwrap();
+        let flags = DescFlags::INDIRECT | DescFlags::avail_used(self.avail_wrap_counter);
+
+        self.next_avail_idx += 1;
+        if self.next_avail_idx == self.queue_size {
+            self.next_avail_idx = 0;
+            self.avail_wrap_counter = !self.avail_wrap_counter;
+        }
+        self.chain_len[id as usize] = 1;
+        self.num_free -= 1;
+
+        // write barrier
+        fence(Ordering::SeqCst);
+        field_ptr!(desc, PackedDescriptor, flags)
+            .write(&flags)
+            .unwrap();
+        fence(Ordering::SeqCst);
+
+        id
+    }
+
+    /// Whether there is a used element that can pop.
+    pub(super) fn can_pop(&self) -> bool {
+        // read barrier
+        fence(Ordering::SeqCst);
+
+        let flags: DescFlags = field_ptr!(
+            &self.descs[self.last_used_idx as usize],
+            PackedDescriptor,
+            flags
+        )
+        .read()
+        .unwrap();
+        flags.is_used(self.used_wrap_counter)
+    }
+
+    /// The number of free descriptors.
+    pub(super) fn available_desc(&self) -> usize {
+        self.num_free as usize
+    }
+
+    /// Get a token from device used buffers, return (token, len).
+    ///
+    /// If `token` is specified, the used element is popped only if it has the token.
+    pub(super) fn pop_used(&mut self, token: Option<u16>) -> Result<(u16, u32), QueueError> {
+        // read barrier
+        fence(Ordering::SeqCst);
+
+        let desc = &self.descs[self.last_used_idx as usize];
+        let id = field_ptr!(desc, PackedDescriptor, id).read().unwrap();
+        let len = field_ptr!(desc, PackedDescriptor, len).read().unwrap();
+        if id >= self.queue_size || self.chain_len[id as usize] == 0 {
+            return Err(QueueError::AlreadyUsed);
+        }
+        if token.is_some_and(|token| id != token) {
+            return Err(QueueError::WrongToken);
+        }
+
+        // The device writes back one used descriptor for the whole chain, and skips the
+        // other descriptors of the chain.
+        let num = core::mem::take(&mut self.chain_len[id as usize]);
+        self.num_free += num;
+        self.last_used_idx += num;
+        if self.last_used_idx >= self.queue_size {
+            self.last_used_idx -= self.queue_size;
+            self.used_wrap_counter = !self.used_wrap_counter;
+        }
+        self.free_next[id as usize] = self.free_head;
+        self.free_head = id;
+
+        Ok((id, len))
+    }
+
+    /// Whether the driver should notify the device.
+    pub(super) fn should_notify(&self) -> bool {
+        // read barrier
+        fence(Ordering::SeqCst);
+        let flags: EventFlags = field_ptr!(&self.device_event, EventSuppression, flags)
+            .read()
+            .unwrap();
+        // The descriptor-specific notifications require `VIRTIO_F_RING_EVENT_IDX`, which is
+        // not negotiated. So the notifications are either enabled or disabled.
+        flags != EventFlags::DISABLE
+    }
+}
+
+#[repr(C, align(16))]
+#[derive(Debug, Default, Copy, Clone, Pod)]
+struct PackedDescriptor {
+    addr: u64,
+    len: u32,
+    id: u16,
+    flags: DescFlags,
+}
+
+/// Write the descriptors of the buffers to an indirect descriptor table.
+///
+/// The descriptors in an indirect table are used in order, so neither their buffer IDs nor
+/// their `NEXT` flags are used.
+pub(super) fn write_indirect_table<T: DmaBuf>(table: &DmaCoherent, inputs: &[&T], outputs: &[&T]) {
+    let buffers = inputs
+        .iter()
+        .map(|buf| (*buf, DescFlags::empty()))
+        .chain(outputs.iter().map(|buf| (*buf, DescFlags::WRITE)));
+    for (i, (buf, flags)) in buffers.enumerate() {
+        debug_assert_ne!(buf.len(), 0);
+        let desc = PackedDescriptor {
+            addr: buf.daddr() as u64,
+            len: buf.len() as u32,
+            id: 0,
+            flags,
+        };
+        table
+            .write_val(i * size_of::<PackedDescriptor>(), &desc)
+            .unwrap();
+    }
+}
+
+bitflags! {
+    /// Descriptor flags
+    #[derive(Pod, Default)]
+    #[repr(C)]
+    struct DescFlags: u16 {
+        const NEXT = 1;
+        const WRITE = 2;
+        const INDIRECT = 4;
+        const AVAIL = 1 << 7;
+        const USED = 1 << 15;
+    }
+}
+
+impl DescFlags {
+    /// Returns the `AVAIL` and `USED` flags of an available descriptor for the wrap counter.
+    fn avail_used(wrap_counter: bool) -> Self {
+        if wrap_counter {
+            DescFlags::AVAIL
+        } else {
+            DescFlags::USED
+        }
+    }
+
+    /// Whether the descriptor is used for the wrap counter.
+    fn is_used(&self, wrap_counter: bool) -> bool {
+        self.contains(DescFlags::AVAIL) == wrap_counter
+            && self.contains(DescFlags::USED) == wrap_counter
+    }
+}
+
+/// The structure to suppress the notifications from the other side.
+#[repr(C, align(4))]
+#[derive(Debug, Default, Copy, Clone, Pod)]
+struct EventSuppression {
+    /// The descriptor ring offset and the wrap counter, used with `EventFlags::DESC`.
+    desc: u16,
+    flags: EventFlags,
+}
+
+bitflags! {
+    /// Event suppression flags
+    #[derive(Pod, Default)]
+    #[repr(C)]
+    struct EventFlags: u16 {
+        const ENABLE = 0;
+        const DISABLE = 1;
+        const DESC = 2;
+    }
+}
@@ -1,6 +1,6 @@
 // SPDX-License-Identifier: MPL-2.0
 
-//! Virtqueue
+//! Split virtqueues
 
 use alloc::vec::Vec;
 use core::{
@@ -9,47 +9,27 @@ use core::{
 };
 
 use aster_frame::{
-    io_mem::IoMem,
-    mm::{DmaCoherent, FrameAllocOptions},
+    mm::{DmaCoherent, FrameAllocOptions, HasDaddr, VmIo},
     offset_of,
 };
 use aster_rights::{Dup, TRightSet, TRights, Write};
 use aster_util::{field_ptr, safe_ptr::SafePtr};
 use bitflags::bitflags;
-use log::debug;
 use pod::Pod;
 
-use crate::{dma_buf::DmaBuf, transport::VirtioTransport};
+use super::{QueueError, RingAreas};
+use crate::dma_buf::DmaBuf;
 
+/// The descriptor table, the available ring and the used ring of a split virtqueue.
 #[derive(Debug)]
-pub enum QueueError {
-    InvalidArgs,
-    BufferTooSmall,
-    NotReady,
-    AlreadyUsed,
-    WrongToken,
-}
-
-/// The mechanism for bulk data transport on virtio devices.
-///
-/// Each device can have zero or more virtqueues.
-#[derive(Debug)]
-pub struct VirtQueue {
+pub(super) struct SplitRing {
     /// Descriptor table
     descs: Vec<SafePtr<Descriptor, DmaCoherent>>,
     /// Available ring
     avail: SafePtr<AvailRing, DmaCoherent>,
     /// Used ring
     used: SafePtr<UsedRing, DmaCoherent>,
-    /// point to notify address
-    notify: SafePtr<u32, IoMem>,
-
-    /// The index of queue
-    queue_idx: u32,
     /// The size of the queue.
-    ///
-    /// This is both the number of descriptors, and the number of slots in the available and used
-    /// rings.
     queue_size: u16,
     /// The number of used queues.
     num_used: u16,
@@ -61,18 +41,10 @@ pub struct VirtQueue {
     last_used_idx: u16,
 }
 
-impl VirtQueue {
-    /// Create a new VirtQueue.
-    pub(crate) fn new(
-        idx: u16,
-        size: u16,
-        transport: &mut dyn VirtioTransport,
-    ) -> Result<Self, QueueError> {
-        if !size.is_power_of_two() {
-            return Err(QueueError::InvalidArgs);
-        }
-
-        let (descriptor_ptr, avail_ring_ptr, used_ring_ptr) = if transport.is_legacy_version() {
+impl SplitRing {
+    /// Allocate the descriptor table and the rings of a split virtqueue.
+    pub(super) fn new(size: u16, is_legacy: bool) -> Result<(Self, RingAreas), QueueError> {
+        let (descriptor_ptr, avail_ring_ptr, used_ring_ptr) = if is_legacy {
             // FIXME: How about pci legacy?
             // Currently, we use one Frame to place the descriptors and avaliable rings, one Frame to place used rings
             // because the virtio-mmio legacy required the address to be continuous. The max queue size is 128.
@@ -117,13 +89,12 @@ impl VirtQueue {
                 ),
             )
         };
-        debug!("queue_desc start paddr:{:x?}", descriptor_ptr.paddr());
-        debug!("queue_driver start paddr:{:x?}", avail_ring_ptr.paddr());
-        debug!("queue_device start paddr:{:x?}", used_ring_ptr.paddr());
+        let areas = RingAreas {
+            descriptor: descriptor_ptr.paddr(),
+            driver: avail_ring_ptr.paddr(),
+            device: used_ring_ptr.paddr(),
+        };
 
-        transport
-            .set_queue(idx, size, &descriptor_ptr, &avail_ring_ptr, &used_ring_ptr)
-            .unwrap();
         let mut descs = Vec::with_capacity(size as usize);
         descs.push(descriptor_ptr);
         for i in 0..size {
@@ -138,39 +109,26 @@ impl VirtQueue {
             }
         }
 
-        let notify = transport.get_notify_ptr(idx).unwrap();
         field_ptr!(&avail_ring_ptr, AvailRing, flags)
             .write(&(0u16))
             .unwrap();
-        Ok(VirtQueue {
+        let ring = SplitRing {
             descs,
             avail: avail_ring_ptr,
             used: used_ring_ptr,
-            notify,
             queue_size: size,
-            queue_idx: idx as u32,
             num_used: 0,
             free_head: 0,
             avail_idx: 0,
             last_used_idx: 0,
-        })
+        };
+        Ok((ring, areas))
     }
 
     /// Add dma buffers to the virtqueue, return a token.
     ///
-    /// Ref: linux virtio_ring.c virtqueue_add
-    pub fn add_dma_buf<T: DmaBuf>(
-        &mut self,
-        inputs: &[&T],
-        outputs: &[&T],
-    ) -> Result<u16, QueueError> {
-        if inputs.is_empty() && outputs.is_empty() {
-            return Err(QueueError::InvalidArgs);
-        }
-        if inputs.len() + outputs.len() + self.num_used as usize > self.queue_size as usize {
-            return Err(QueueError::BufferTooSmall);
-        }
-
+    /// The number of buffers must not exceed the number of free descriptors.
+    pub(super) fn add_dma_buf<T: DmaBuf>(&mut self, inputs: &[&T], outputs: &[&T]) -> u16 {
         // allocate descriptors from free list
         let head = self.free_head;
         let mut last = self.free_head;
@@ -204,6 +162,40 @@ impl VirtQueue {
         }
         self.num_used += (inputs.len() + outputs.len()) as u16;
 
+        self.make_available(head);
+        head
+    }
+
+    /// The token of the next buffer to add.
+    pub(super) fn next_token(&self) -> u16 {
+        self.free_head
+    }
+
+    /// Add an indirect descriptor table with `num` descriptors to the virtqueue, return a
+    /// token.
+    ///
+    /// The table takes only one descriptor of the queue, which must be free.
+    pub(super) fn add_indirect_table(&mut self, table: &DmaCoherent, num: usize) -> u16 {
+        let head = self.free_head;
+        let desc = &self.descs[head as usize];
+        field_ptr!(desc, Descriptor, addr)
+            .write(&(table.daddr() as u64))
+            .unwrap();
+        field_ptr!(desc, Descriptor, len)
+            .write(&((num * size_of::<Descriptor>()) as u32))
+            .unwrap();
+        field_ptr!(desc, Descriptor, flags)
+            .write(&DescFlags::INDIRECT)
+            .unwrap();
+        self.free_head = field_ptr!(desc, Descriptor, next).read().unwrap();
+        self.num_used += 1;
+
+        self.make_available(head);
+        head
+    }
+
+    /// Put the head of a descriptor chain into the available ring.
+    fn make_available(&mut self, head: u16) {
         let avail_slot = self.avail_idx & (self.queue_size - 1);
 
         {
@@ -223,11 +215,10 @@ impl VirtQueue {
             .unwrap();
 
         fence(Ordering::SeqCst);
-        Ok(head)
     }
 
     /// Whether there is a used element that can pop.
-    pub fn can_pop(&self) -> bool {
+    pub(super) fn can_pop(&self) -> bool {
         // read barrier
         fence(Ordering::SeqCst);
 
@@ -235,7 +226,7 @@ impl VirtQueue {
     }
 
     /// The number of free descriptors.
-    pub fn available_desc(&self) -> usize {
+    pub(super) fn available_desc(&self) -> usize {
         (self.queue_size - self.num_used) as usize
     }
 
@@ -269,36 +260,8 @@ impl VirtQueue {
 
     /// Get a token from device used buffers, return (token, len).
     ///
-    /// Ref: linux virtio_ring.c virtqueue_get_buf_ctx
-    pub fn pop_used(&mut self) -> Result<(u16, u32), QueueError> {
-        if !self.can_pop() {
-            return Err(QueueError::NotReady);
-        }
-
-        let last_used_slot = self.last_used_idx & (self.queue_size - 1);
-        let element_ptr = {
-            let mut ptr = self.used.borrow_vm();
-            ptr.byte_add(offset_of!(UsedRing, ring) as usize + last_used_slot as usize * 8);
-            ptr.cast::<UsedElem>()
-        };
-        let index = field_ptr!(&element_ptr, UsedElem, id).read().unwrap();
-        let len = field_ptr!(&element_ptr, UsedElem, len).read().unwrap();
-
-        self.recycle_descriptors(index as u16);
-        self.last_used_idx = self.last_used_idx.wrapping_add(1);
-
-        Ok((index as u16, len))
-    }
-
-    /// If the given token is next on the device used queue, pops it and returns the total buffer
-    /// length which was used (written) by the device.
-    ///
-    /// Ref: linux virtio_ring.c virtqueue_get_buf_ctx
-    pub fn pop_used_with_token(&mut self, token: u16) -> Result<u32, QueueError> {
-        if !self.can_pop() {
-            return Err(QueueError::NotReady);
-        }
-
+    /// If `token` is specified, the used element is popped only if it has the token.
+    pub(super) fn pop_used(&mut self, token: Option<u16>) -> Result<(u16, u32), QueueError> {
         let last_used_slot = self.last_used_idx & (self.queue_size - 1);
         let element_ptr = {
             let mut ptr = self.used.borrow_vm();
@@ -308,33 +271,23 @@ impl VirtQueue {
         let index = field_ptr!(&element_ptr, UsedElem, id).read().unwrap();
         let len = field_ptr!(&element_ptr, UsedElem, len).read().unwrap();
 
-        if index as u16 != token {
+        if token.is_some_and(|token| index as u16 != token) {
             return Err(QueueError::WrongToken);
         }
 
         self.recycle_descriptors(index as u16);
         self.last_used_idx = self.last_used_idx.wrapping_add(1);
 
-        Ok(len)
-    }
-
-    /// Return size of the queue.
-    pub fn size(&self) -> u16 {
-        self.queue_size
+        Ok((index as u16, len))
     }
 
     /// whether the driver should notify the device
-    pub fn should_notify(&self) -> bool {
+    pub(super) fn should_notify(&self) -> bool {
         // read barrier
         fence(Ordering::SeqCst);
         let flags = field_ptr!(&self.used, UsedRing, flags).read().unwrap();
         flags & 0x0001u16 == 0u16
     }
-
-    /// notify that there are available rings
-    pub fn notify(&mut self) {
-        self.notify.write(&self.queue_idx).unwrap();
-    }
 }
 
 #[repr(C, align(16))]
@@ -361,6 +314,32 @@ fn set_dma_buf<T: DmaBuf>(desc_ptr: &DescriptorPtr, buf: &T) {
         .unwrap();
 }
 
+/// Write the descriptors of the buffers to an indirect descriptor table.
+pub(super) fn write_indirect_table<T: DmaBuf>(table: &DmaCoherent, inputs: &[&T], outputs: &[&T]) {
+    let num = inputs.len() + outputs.len();
+    let buffers = inputs
+        .iter()
+        .map(|buf| (*buf, DescFlags::empty()))
+        .chain(outputs.iter().map(|buf| (*buf, DescFlags::WRITE)));
+    for (i, (buf, mut flags)) in buffers.enumerate() {
+        debug_assert_ne!(buf.len(), 0);
+        // The descriptors in an indirect table are chained in order.
+        let next = if i + 1 != num {
+            flags |= DescFlags::NEXT;
+            (i + 1) as u16
+        } else {
+            0
+        };
+        let desc = Descriptor {
+            addr: buf.daddr() as u64,
+            len: buf.len() as u32,
+            flags,
+            next,
+        };
+        table.write_val(i * size_of::<Descriptor>(), &desc).unwrap();
+    }
+}
+
 bitflags! {
     /// Descriptor flags
     #[derive(Pod, Default)]
@@ -9,7 +9,7 @@ use aster_frame::{
         device::{MmioCommonDevice, VirtioMmioVersion},
     },
     io_mem::IoMem,
-    mm::{DmaCoherent, PAGE_SIZE},
+    mm::{Paddr, PAGE_SIZE},
     offset_of,
     sync::RwLock,
     trap::IrqCallbackFunction,
@@ -20,7 +20,7 @@ use log::warn;
 
 use super::{layout::VirtioMmioLayout, multiplex::MultiplexIrq};
 use crate::{
-    queue::{AvailRing, Descriptor, UsedRing},
+    queue::Descriptor,
     transport::{DeviceStatus, VirtioTransport, VirtioTransportError},
     VirtioDeviceType,
 };
@@ -36,6 +36,7 @@ pub struct VirtioMmioTransport {
     device: Arc<VirtioMmioDevice>,
     common_device: aster_frame::bus::mmio::device::MmioCommonDevice,
     multiplex: Arc<RwLock<MultiplexIrq>>,
+    driver_features: u64,
 }
 
 impl MmioDevice for VirtioMmioDevice {
@@ -76,6 +77,7 @@ impl VirtioMmioTransport {
             common_device: device,
             multiplex: MultiplexIrq::new(irq, interrupt_ack, interrupt_status),
             device: Arc::new(VirtioMmioDevice { device_id }),
+            driver_features: 0,
         };
         if device.common_device.version() == VirtioMmioVersion::Legacy {
             field_ptr!(&device.layout, VirtioMmioLayout, legacy_guest_page_size)
@@ -95,9 +97,9 @@ impl VirtioTransport for VirtioMmioTransport {
         &mut self,
         idx: u16,
         queue_size: u16,
-        descriptor_ptr: &SafePtr<Descriptor, DmaCoherent>,
-        driver_ptr: &SafePtr<AvailRing, DmaCoherent>,
-        device_ptr: &SafePtr<UsedRing, DmaCoherent>,
+        descriptor_paddr: Paddr,
+        driver_paddr: Paddr,
+        device_paddr: Paddr,
     ) -> Result<(), VirtioTransportError> {
         field_ptr!(&self.layout, VirtioMmioLayout, queue_sel)
             .write(&(idx as u32))
@@ -112,10 +114,6 @@ impl VirtioTransport for VirtioMmioTransport {
             return Err(VirtioTransportError::InvalidArgs);
         }
 
-        let descriptor_paddr = descriptor_ptr.paddr();
-        let driver_paddr = driver_ptr.paddr();
-        let device_paddr = device_ptr.paddr();
-
         field_ptr!(&self.layout, VirtioMmioLayout, queue_num)
             .write(&(queue_size as u32))
             .unwrap();
@@ -237,9 +235,14 @@ impl VirtioTransport for VirtioMmioTransport {
         field_ptr!(&self.layout, VirtioMmioLayout, driver_features)
             .write(&high)
             .unwrap();
+        self.driver_features = features;
         Ok(())
     }
 
+    fn driver_features(&self) -> u64 {
+        self.driver_features
+    }
+
     fn device_status(&self) -> DeviceStatus {
         DeviceStatus::from_bits(
             field_ptr!(&self.layout, VirtioMmioLayout, status)
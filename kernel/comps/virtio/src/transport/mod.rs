@@ -3,14 +3,11 @@
 use alloc::boxed::Box;
 use core::fmt::Debug;
 
-use aster_frame::{io_mem::IoMem, mm::DmaCoherent, trap::IrqCallbackFunction};
+use aster_frame::{io_mem::IoMem, mm::Paddr, trap::IrqCallbackFunction};
 use aster_util::safe_ptr::SafePtr;
 
 use self::{mmio::virtio_mmio_init, pci::virtio_pci_init};
-use crate::{
-    queue::{AvailRing, Descriptor, UsedRing},
-    VirtioDeviceType,
-};
+use crate::VirtioDeviceType;
 
 pub mod mmio;
 pub mod pci;
@@ -32,6 +29,9 @@ pub trait VirtioTransport: Sync + Send + Debug {
     /// Set driver features.
     fn set_driver_features(&mut self, features: u64) -> Result<(), VirtioTransportError>;
 
+    /// Get the driver features set by the last `set_driver_features`.
+    fn driver_features(&self) -> u64;
+
     /// Get device status.
     fn device_status(&self) -> DeviceStatus;
 
@@ -58,13 +58,17 @@ pub trait VirtioTransport: Sync + Send + Debug {
     fn num_queues(&self) -> u16;
 
     /// Set virtqueue information. Some transport may set other necessary information such as MSI-X vector in PCI transport.
+    ///
+    /// For split virtqueues, the driver area and the device area are the available ring and
+    /// the used ring. For packed virtqueues, they are the driver and the device event
+    /// suppression structures.
     fn set_queue(
         &mut self,
         idx: u16,
         queue_size: u16,
-        descriptor_ptr: &SafePtr<Descriptor, DmaCoherent>,
-        avail_ring_ptr: &SafePtr<AvailRing, DmaCoherent>,
-        used_ring_ptr: &SafePtr<UsedRing, DmaCoherent>,
+        descriptor_paddr: Paddr,
+        driver_paddr: Paddr,
+        device_paddr: Paddr,
     ) -> Result<(), VirtioTransportError>;
 
     /// The max queue size of one virtqueue.
@@ -11,7 +11,7 @@ use aster_frame::{
         BusProbeError,
     },
     io_mem::IoMem,
-    mm::DmaCoherent,
+    mm::Paddr,
     offset_of,
     trap::IrqCallbackFunction,
 };
@@ -20,7 +20,6 @@ use log::{info, warn};
 
 use super::{common_cfg::VirtioPciCommonCfg, msix::VirtioMsixManager};
 use crate::{
-    queue::{AvailRing, Descriptor, UsedRing},
     transport::{
         pci::capability::{VirtioPciCapabilityData, VirtioPciCpabilityType},
         DeviceStatus, VirtioTransport, VirtioTransportError,
@@ -47,6 +46,7 @@ pub struct VirtioPciTransport {
     notify: VirtioPciNotify,
     msix_manager: VirtioMsixManager,
     device: Arc<VirtioPciDevice>,
+    driver_features: u64,
 }
 
 impl PciDevice for VirtioPciDevice {
@@ -72,9 +72,9 @@ impl VirtioTransport for VirtioPciTransport {
         &mut self,
         idx: u16,
         queue_size: u16,
-        descriptor_ptr: &SafePtr<Descriptor, DmaCoherent>,
-        avail_ring_ptr: &SafePtr<AvailRing, DmaCoherent>,
-        used_ring_ptr: &SafePtr<UsedRing, DmaCoherent>,
+        descriptor_paddr: Paddr,
+        driver_paddr: Paddr,
+        device_paddr: Paddr,
     ) -> Result<(), VirtioTransportError> {
         if idx >= self.num_queues() {
             return Err(VirtioTransportError::InvalidArgs);
@@ -93,13 +93,13 @@ impl VirtioTransport for VirtioPciTransport {
             .write(&queue_size)
             .unwrap();
         field_ptr!(&self.common_cfg, VirtioPciCommonCfg, queue_desc)
-            .write(&(descriptor_ptr.paddr() as u64))
+            .write(&(descriptor_paddr as u64))
             .unwrap();
         field_ptr!(&self.common_cfg, VirtioPciCommonCfg, queue_driver)
-            .write(&(avail_ring_ptr.paddr() as u64))
+            .write(&(driver_paddr as u64))
             .unwrap();
         field_ptr!(&self.common_cfg, VirtioPciCommonCfg, queue_device)
-            .write(&(used_ring_ptr.paddr() as u64))
+            .write(&(device_paddr as u64))
             .unwrap();
         // Enable queue
         field_ptr!(&self.common_cfg, VirtioPciCommonCfg, queue_enable)
@@ -172,9 +172,14 @@ impl VirtioTransport for VirtioPciTransport {
         field_ptr!(&self.common_cfg, VirtioPciCommonCfg, driver_features)
             .write(&high)
             .unwrap();
+        self.driver_features = features;
         Ok(())
     }
 
+    fn driver_features(&self) -> u64 {
+        self.driver_features
+    }
+
     fn device_status(&self) -> DeviceStatus {
         let status = field_ptr!(&self.common_cfg, VirtioPciCommonCfg, device_status)
             .read()
@@ -322,6 +327,9 @@ impl VirtioPciTransport {
                 CapabilityData::Msix(data) => {
                     msix = Some(data.clone());
                 }
+                CapabilityData::Msi(_) => {
+                    // The virtio devices raise the interrupts of the queues with MSI-X only.
+                }
                 CapabilityData::Unknown(id) => {
                     panic!("unknown capability: {}", id)
                 }
@@ -345,6 +353,7 @@ impl VirtioPciTransport {
             msix_manager,
             device_type,
             device: Arc::new(VirtioPciDevice { device_id }),
+            driver_features: 0,
         })
     }
 }
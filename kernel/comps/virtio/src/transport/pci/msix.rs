@@ -8,8 +8,11 @@ pub struct VirtioMsixManager {
     config_msix_vector: u16,
     /// Shared interrupt vector used by queue.
     shared_interrupt_vector: u16,
-    /// The MSI-X vectors allocated to queue interrupt except `shared_interrupt_vector`. All the
-    /// vector are considered to be occupied by only one queue.
+    /// The MSI-X vectors that can be allocated to queue interrupt except
+    /// `shared_interrupt_vector`. All the vector are considered to be occupied by only one queue.
+    ///
+    /// The IRQs of these vectors are allocated only when the vectors are popped, so that a device
+    /// does not consume the IRQs for the queues that never use them.
     unused_msix_vectors: Vec<u16>,
     /// Used MSI-X vectors.
     used_msix_vectors: Vec<u16>,
@@ -19,12 +22,12 @@ pub struct VirtioMsixManager {
 impl VirtioMsixManager {
     pub fn new(mut msix: CapabilityMsixData) -> Self {
         let mut msix_vector_list: Vec<u16> = (0..msix.table_size()).collect();
-        for i in msix_vector_list.iter() {
-            let irq = aster_frame::trap::IrqLine::alloc().unwrap();
-            msix.set_interrupt_vector(irq, *i);
-        }
         let config_msix_vector = msix_vector_list.pop().unwrap();
         let shared_interrupt_vector = msix_vector_list.pop().unwrap();
+        for vector in [config_msix_vector, shared_interrupt_vector] {
+            let irq = IrqLine::alloc().unwrap();
+            msix.set_interrupt_vector(irq, vector);
+        }
         Self {
             config_msix_vector,
             unused_msix_vectors: msix_vector_list,
@@ -57,9 +60,16 @@ impl VirtioMsixManager {
     /// Pop unused vector. If a virtqueue will send interrupt frequently.
     /// Then this virtqueue should use the single IRQ that this function provides.
     /// this function will return the MSI-X vector and corresponding IRQ.
+    ///
+    /// The IRQ is balanced among CPUs, so that the queues of a device are handled by different CPUs.
     pub fn pop_unused_irq(&mut self) -> Option<(u16, &mut IrqLine)> {
-        let vector = self.unused_msix_vectors.pop()?;
+        let vector = *self.unused_msix_vectors.last()?;
+        let irq = IrqLine::alloc().ok()?;
+        self.unused_msix_vectors.pop();
+        self.msix.set_interrupt_vector(irq, vector);
         self.used_msix_vectors.push(vector);
-        Some((vector, self.msix.irq_mut(vector as usize).unwrap()))
+        let irq = self.msix.irq_mut(vector as usize).unwrap();
+        irq.balance();
+        Some((vector, irq))
     }
 }
@@ -0,0 +1,183 @@
+// SPDX-License-Identifier: MPL-2.0
+
+#define _GNU_SOURCE
+
+#include <dirent.h>
+#include <sched.h>
+#include <errno.h>
+#include <fcntl.h>
+#include <stdio.h>
+#include <stdlib.h>
+#include <string.h>
+#include <unistd.h>
+
+#define MAX_IRQS 256
+
+#define CHECK(cond)                                                       \
+	do {                                                              \
+		if (!(cond)) {                                            \
+			fprintf(stderr, "%s:%d: check failed: %s (%s)\n", \
+				__FILE__, __LINE__, #cond,                \
+				strerror(errno));                         \
+			exit(1);                                          \
+		}                                                         \
+	} while (0)
+
+static void read_affinity(const char *path, char *buf, size_t len)
+{
+	int fd;
+	ssize_t n;
+
+	fd = open(path, O_RDONLY);
+	CHECK(fd >= 0);
+	n = read(fd, buf, len - 1);
+	CHECK(n > 0);
+	buf[n] = '\0';
+	CHECK(close(fd) == 0);
+}
+
+static int write_affinity(const char *path, const char *mask)
+{
+	int fd, ret;
+
+	fd = open(path, O_WRONLY);
+	CHECK(fd >= 0);
+	ret = write(fd, mask, strlen(mask));
+	CHECK(close(fd) == 0);
+	return ret;
+}
+
+static void test_irq(const char *irq)
+{
+	char path[64], buf[64];
+
+	snprintf(path, sizeof(path), "/proc/irq/%s/smp_affinity", irq);
+
+	// The mask is in hexadecimal, and the IRQ is handled by at least one CPU.
+	read_affinity(path, buf, sizeof(buf));
+	CHECK(buf[strlen(buf) - 1] == '\n');
+	CHECK(strspn(buf, "0123456789abcdef,") == strlen(buf) - 1);
+	CHECK(strtoul(buf, NULL, 16) != 0);
+
+	// CPU 0 always exists.
+	CHECK(write_affinity(path, "1\n") == 2);
+	read_affinity(path, buf, sizeof(buf));
+	CHECK(strtoul(buf, NULL, 16) == 1);
+
+	// No CPU or an invalid mask.
+	CHECK(write_affinity(path, "0\n") < 0 && errno == EINVAL);
+	CHECK(write_affinity(path, "xyz\n") < 0 && errno == EINVAL);
+}
+
+// Returns the number of the interrupts of the IRQ handled by the CPU in
+// `/proc/interrupts`.
+static unsigned long irq_count(const char *irq, int cpu)
+{
+	static char buf[16384];
+	char prefix[16];
+	char *line, *p;
+	unsigned long count = 0;
+	ssize_t n;
+	int fd, i;
+
+	fd = open("/proc/interrupts", O_RDONLY);
+	CHECK(fd >= 0);
+	n = read(fd, buf, sizeof(buf) - 1);
+	CHECK(n > 0);
+	buf[n] = '\0';
+	CHECK(close(fd) == 0);
+
+	snprintf(prefix, sizeof(prefix), "%s:", irq);
+	for (line = strtok(buf, "\n"); line != NULL; line = strtok(NULL, "\n")) {
+		p = line + strspn(line, " ");
+		if (strncmp(p, prefix, strlen(prefix)) != 0)
+			continue;
+		p += strlen(prefix);
+		for (i = 0; i <= cpu; i++)
+			count = strtoul(p, &p, 10);
+		return count;
+	}
+	return 0;
+}
+
+// Generates some interrupts of the block device.
+static void do_block_io(void)
+{
+	static char data[4096];
+	int fd, i;
+
+	fd = open("/ext2/irq_affinity", O_CREAT | O_WRONLY | O_TRUNC, 0644);
+	CHECK(fd >= 0);
+	for (i = 0; i < 16; i++) {
+		memset(data, i, sizeof(data));
+		CHECK(write(fd, data, sizeof(data)) == sizeof(data));
+		CHECK(fsync(fd) == 0);
+	}
+	CHECK(close(fd) == 0);
+	CHECK(unlink("/ext2/irq_affinity") == 0);
+}
+
+// Routes all IRQs to the last CPU and checks that some of them are handled by it.
+//
+// Returns the number of the IRQs whose interrupts are handled by the CPU after that.
+static int test_retarget(char irqs[][16], int nr_irqs, int nr_cpus)
+{
+	static unsigned long counts[MAX_IRQS];
+	char path[64], mask[16];
+	int cpu = nr_cpus - 1 < 31 ? nr_cpus - 1 : 31;
+	int i, nr_retargeted = 0;
+
+	snprintf(mask, sizeof(mask), "%x\n", 1U << cpu);
+	for (i = 0; i < nr_irqs; i++) {
+		snprintf(path, sizeof(path), "/proc/irq/%s/smp_affinity", irqs[i]);
+		CHECK(write_affinity(path, mask) == (int)strlen(mask));
+		counts[i] = irq_count(irqs[i], cpu);
+	}
+
+	do_block_io();
+
+	for (i = 0; i < nr_irqs; i++) {
+		if (irq_count(irqs[i], cpu) > counts[i])
+			nr_retargeted++;
+	}
+	CHECK(nr_retargeted > 0);
+
+	snprintf(mask, sizeof(mask), "%x\n",
+		 nr_cpus >= 32 ? 0xffffffffU : (1U << nr_cpus) - 1);
+	for (i = 0; i < nr_irqs; i++) {
+		snprintf(path, sizeof(path), "/proc/irq/%s/smp_affinity", irqs[i]);
+		CHECK(write_affinity(path, mask) == (int)strlen(mask));
+	}
+	return nr_retargeted;
+}
+
+int main(void)
+{
+	static char irqs[MAX_IRQS][16];
+	DIR *dir;
+	struct dirent *entry;
+	cpu_set_t cpus;
+	int nr_irqs = 0, nr_cpus, nr_retargeted = 0;
+
+	dir = opendir("/proc/irq");
+	CHECK(dir != NULL);
+	while ((entry = readdir(dir)) != NULL) {
+		if (entry->d_name[0] < '0' || entry->d_name[0] > '9')
+			continue;
+		CHECK(nr_irqs < MAX_IRQS);
+		test_irq(entry->d_name);
+		snprintf(irqs[nr_irqs], sizeof(irqs[nr_irqs]), "%s",
+			 entry->d_name);
+		nr_irqs++;
+	}
+	CHECK(closedir(dir) == 0);
+
+	CHECK(sched_getaffinity(0, sizeof(cpus), &cpus) == 0);
+	nr_cpus = CPU_COUNT(&cpus);
+	if (nr_cpus > 1)
+		nr_retargeted = test_retarget(irqs, nr_irqs, nr_cpus);
+
+	printf("All IRQ affinity tests passed (%d IRQs, %d retargeted).\n",
+	       nr_irqs, nr_retargeted);
+	return 0;
+}
@@ -0,0 +1,95 @@
+// SPDX-License-Identifier: MPL-2.0
+
+#define _GNU_SOURCE
+
+#include <errno.h>
+#include <fcntl.h>
+#include <sched.h>
+#include <stdio.h>
+#include <stdlib.h>
+#include <string.h>
+#include <sys/syscall.h>
+#include <sys/wait.h>
+#include <unistd.h>
+
+#define CHECK(cond)                                                       \
+	do {                                                              \
+		if (!(cond)) {                                            \
+			fprintf(stderr, "%s:%d: check failed: %s (%s)\n", \
+				__FILE__, __LINE__, #cond,                \
+				strerror(errno));                         \
+			exit(1);                                          \
+		}                                                         \
+	} while (0)
+
+static void check_status_list(const char *expected)
+{
+	char buf[4096], line[64];
+	int fd;
+	ssize_t n;
+
+	fd = open("/proc/self/status", O_RDONLY);
+	CHECK(fd >= 0);
+	n = read(fd, buf, sizeof(buf) - 1);
+	CHECK(n > 0);
+	buf[n] = '\0';
+	CHECK(close(fd) == 0);
+
+	snprintf(line, sizeof(line), "\nCpus_allowed_list:\t%s\n", expected);
+	CHECK(strstr(buf, line) != NULL);
+	CHECK(strstr(buf, "\nCpus_allowed:\t") != NULL);
+}
+
+int main(void)
+{
+	cpu_set_t set, old_set;
+	unsigned long raw_mask;
+	int status;
+	pid_t pid;
+
+	// CPU 0 always exists, and all the CPUs are allowed by default.
+	CPU_ZERO(&old_set);
+	CHECK(sched_getaffinity(0, sizeof(old_set), &old_set) == 0);
+	CHECK(CPU_ISSET(0, &old_set));
+	CHECK(sched_getaffinity(getpid(), sizeof(old_set), &old_set) == 0);
+	CHECK(CPU_ISSET(0, &old_set));
+
+	// The raw system call returns the number of the written bytes.
+	CHECK(syscall(SYS_sched_getaffinity, 0, sizeof(raw_mask), &raw_mask) ==
+	      sizeof(raw_mask));
+	CHECK(raw_mask & 1);
+	CHECK(syscall(SYS_sched_getaffinity, 0, 1, &raw_mask) < 0 &&
+	      errno == EINVAL);
+
+	// Pin to CPU 0, which is inherited by the children.
+	CPU_ZERO(&set);
+	CPU_SET(0, &set);
+	CHECK(sched_setaffinity(0, sizeof(set), &set) == 0);
+	CHECK(sched_getaffinity(0, sizeof(set), &set) == 0);
+	CHECK(CPU_COUNT(&set) == 1 && CPU_ISSET(0, &set));
+	check_status_list("0");
+
+	pid = fork();
+	CHECK(pid >= 0);
+	if (pid == 0) {
+		CPU_ZERO(&set);
+		CHECK(sched_getaffinity(0, sizeof(set), &set) == 0);
+		CHECK(CPU_COUNT(&set) == 1 && CPU_ISSET(0, &set));
+		exit(0);
+	}
+	CHECK(waitpid(pid, &status, 0) == pid);
+	CHECK(WIFEXITED(status) && WEXITSTATUS(status) == 0);
+
+	// No CPU that exists, or no such thread.
+	CPU_ZERO(&set);
+	CHECK(sched_setaffinity(0, sizeof(set), &set) < 0 && errno == EINVAL);
+	CPU_SET(CPU_SETSIZE - 1, &set);
+	CHECK(sched_setaffinity(0, sizeof(set), &set) < 0 && errno == EINVAL);
+	CHECK(sched_getaffinity(0x7fffffff, sizeof(set), &set) < 0 &&
+	      errno == ESRCH);
+
+	CHECK(sched_setaffinity(0, sizeof(old_set), &old_set) == 0);
+
+	printf("All sched affinity tests passed.\n");
+	return 0;
+}
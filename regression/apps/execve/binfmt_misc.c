@@ -0,0 +1,127 @@
+// SPDX-License-Identifier: MPL-2.0
+
+#define _GNU_SOURCE
+
+#include <errno.h>
+#include <fcntl.h>
+#include <stdio.h>
+#include <stdlib.h>
+#include <string.h>
+#include <unistd.h>
+#include <sys/mount.h>
+#include <sys/stat.h>
+#include <sys/wait.h>
+
+#define BINFMT_DIR "/proc/sys/fs/binfmt_misc"
+#define FORMAT_FILE BINFMT_DIR "/bfmt"
+#define INTERPRETER "/regression/execve/binfmt_misc"
+#define PROGRAM "/tmp/program.bfmt"
+#define INTERPRETER_EXIT_CODE 42
+
+#define CHECK(cond)                                                       \
+	do {                                                              \
+		if (!(cond)) {                                            \
+			fprintf(stderr, "%s:%d: check failed: %s (%s)\n", \
+				__FILE__, __LINE__, #cond,                \
+				strerror(errno));                         \
+			exit(1);                                          \
+		}                                                         \
+	} while (0)
+
+static void write_file(const char *path, const char *content)
+{
+	int fd;
+
+	fd = open(path, O_WRONLY);
+	CHECK(fd >= 0);
+	CHECK(write(fd, content, strlen(content)) == strlen(content));
+	CHECK(close(fd) == 0);
+}
+
+static void read_file(const char *path, char *buf, size_t size)
+{
+	int fd;
+	ssize_t len;
+
+	fd = open(path, O_RDONLY);
+	CHECK(fd >= 0);
+	len = read(fd, buf, size - 1);
+	CHECK(len >= 0);
+	buf[len] = '\0';
+	CHECK(close(fd) == 0);
+}
+
+// Runs the program, and returns the exit code, or -1 if it cannot be executed.
+static int run_program(void)
+{
+	char *argv[] = { "program", "arg", NULL };
+	char *envp[] = { NULL };
+	int status;
+	pid_t pid;
+
+	pid = fork();
+	CHECK(pid >= 0);
+	if (pid == 0) {
+		execve(PROGRAM, argv, envp);
+		_exit(255);
+	}
+	CHECK(waitpid(pid, &status, 0) == pid);
+	CHECK(WIFEXITED(status));
+	return WEXITSTATUS(status) == 255 ? -1 : WEXITSTATUS(status);
+}
+
+int main(int argc, char *argv[])
+{
+	char buf[256];
+	int fd;
+
+	// Run as the interpreter, with the original argv[0] preserved.
+	if (argc == 4 && strcmp(argv[1], PROGRAM) == 0) {
+		CHECK(strcmp(argv[2], "program") == 0);
+		CHECK(strcmp(argv[3], "arg") == 0);
+		return INTERPRETER_EXIT_CODE;
+	}
+
+	CHECK(mount("none", BINFMT_DIR, "binfmt_misc", 0, NULL) == 0);
+	read_file(BINFMT_DIR "/status", buf, sizeof(buf));
+	CHECK(strcmp(buf, "enabled\n") == 0);
+
+	fd = open(PROGRAM, O_WRONLY | O_CREAT | O_TRUNC, 0755);
+	CHECK(fd >= 0);
+	CHECK(write(fd, "\x7f" "BFMT", 5) == 5);
+	CHECK(close(fd) == 0);
+	CHECK(run_program() == -1);
+
+	write_file(BINFMT_DIR "/register",
+		   ":bfmt:M::\\x7fBFMT::" INTERPRETER ":P\n");
+	read_file(FORMAT_FILE, buf, sizeof(buf));
+	CHECK(strcmp(buf, "enabled\n"
+			  "interpreter " INTERPRETER "\n"
+			  "flags: P\n"
+			  "offset 0\n"
+			  "magic 7f42464d54\n") == 0);
+	CHECK(run_program() == INTERPRETER_EXIT_CODE);
+
+	// A format with the same name cannot be registered twice.
+	fd = open(BINFMT_DIR "/register", O_WRONLY);
+	CHECK(fd >= 0);
+	CHECK(write(fd, ":bfmt:E::bfmt::/bin/true:", 25) < 0 && errno == EEXIST);
+	CHECK(close(fd) == 0);
+
+	write_file(FORMAT_FILE, "0");
+	CHECK(run_program() == -1);
+	write_file(FORMAT_FILE, "1");
+	CHECK(run_program() == INTERPRETER_EXIT_CODE);
+	write_file(BINFMT_DIR "/status", "0");
+	CHECK(run_program() == -1);
+	write_file(BINFMT_DIR "/status", "1");
+
+	write_file(FORMAT_FILE, "-1");
+	CHECK(access(FORMAT_FILE, F_OK) < 0 && errno == ENOENT);
+	CHECK(run_program() == -1);
+
+	CHECK(unlink(PROGRAM) == 0);
+	CHECK(umount(BINFMT_DIR) == 0);
+	printf("binfmt_misc test passed\n");
+	return 0;
+}
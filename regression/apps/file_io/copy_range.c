@@ -0,0 +1,146 @@
+// SPDX-License-Identifier: MPL-2.0
+
+#define _GNU_SOURCE
+
+#include <errno.h>
+#include <fcntl.h>
+#include <stdio.h>
+#include <stdlib.h>
+#include <string.h>
+#include <unistd.h>
+#include <sys/sendfile.h>
+#include <sys/socket.h>
+#include <sys/stat.h>
+
+#define SRC_NAME "/ext2/copy_range_src"
+#define DST_NAME "/tmp/copy_range_dst"
+#define FILE_SIZE (3 * 4096 + 100)
+
+#define CHECK(cond)                                                       \
+	do {                                                              \
+		if (!(cond)) {                                            \
+			fprintf(stderr, "%s:%d: check failed: %s (%s)\n", \
+				__FILE__, __LINE__, #cond,                \
+				strerror(errno));                         \
+			exit(1);                                          \
+		}                                                         \
+	} while (0)
+
+static char data[FILE_SIZE];
+static char buffer[FILE_SIZE];
+
+static void test_copy_file_range(int src_fd)
+{
+	int dst_fd, dir_fd, ro_fd;
+	off_t in_off, out_off;
+
+	dst_fd = open(DST_NAME, O_CREAT | O_RDWR | O_TRUNC, 0644);
+	CHECK(dst_fd >= 0);
+
+	// Copies with the file offsets.
+	CHECK(lseek(src_fd, 0, SEEK_SET) == 0);
+	CHECK(copy_file_range(src_fd, NULL, dst_fd, NULL, FILE_SIZE, 0) ==
+	      FILE_SIZE);
+	CHECK(lseek(src_fd, 0, SEEK_CUR) == FILE_SIZE);
+	CHECK(lseek(dst_fd, 0, SEEK_CUR) == FILE_SIZE);
+	CHECK(pread(dst_fd, buffer, FILE_SIZE, 0) == FILE_SIZE);
+	CHECK(memcmp(buffer, data, FILE_SIZE) == 0);
+
+	// Copies with explicit offsets, which keep the file offsets.
+	in_off = 100;
+	out_off = FILE_SIZE;
+	CHECK(copy_file_range(src_fd, &in_off, dst_fd, &out_off, 5000, 0) ==
+	      5000);
+	CHECK(in_off == 5100 && out_off == FILE_SIZE + 5000);
+	CHECK(lseek(src_fd, 0, SEEK_CUR) == FILE_SIZE);
+	CHECK(pread(dst_fd, buffer, 5000, FILE_SIZE) == 5000);
+	CHECK(memcmp(buffer, data + 100, 5000) == 0);
+
+	// Copying at the end of the file copies nothing.
+	in_off = FILE_SIZE;
+	CHECK(copy_file_range(src_fd, &in_off, dst_fd, NULL, 10, 0) == 0);
+
+	// A copy in the same file must not overlap.
+	in_off = 0;
+	out_off = 10;
+	CHECK(copy_file_range(dst_fd, &in_off, dst_fd, &out_off, 20, 0) < 0 &&
+	      errno == EINVAL);
+	out_off = 20;
+	CHECK(copy_file_range(dst_fd, &in_off, dst_fd, &out_off, 20, 0) == 20);
+
+	CHECK(copy_file_range(src_fd, NULL, dst_fd, NULL, 10, 1) < 0 &&
+	      errno == EINVAL);
+	dir_fd = open("/tmp", O_RDONLY | O_DIRECTORY);
+	CHECK(dir_fd >= 0);
+	CHECK(copy_file_range(dir_fd, NULL, dst_fd, NULL, 10, 0) < 0 &&
+	      errno == EISDIR);
+	CHECK(close(dir_fd) == 0);
+	ro_fd = open(SRC_NAME, O_RDONLY);
+	CHECK(ro_fd >= 0);
+	CHECK(copy_file_range(dst_fd, NULL, ro_fd, NULL, 10, 0) < 0 &&
+	      errno == EBADF);
+	CHECK(close(ro_fd) == 0);
+
+	CHECK(close(dst_fd) == 0);
+	dst_fd = open(DST_NAME, O_WRONLY | O_APPEND);
+	CHECK(dst_fd >= 0);
+	CHECK(copy_file_range(src_fd, NULL, dst_fd, NULL, 10, 0) < 0 &&
+	      errno == EBADF);
+	CHECK(close(dst_fd) == 0);
+	CHECK(unlink(DST_NAME) == 0);
+}
+
+static void test_sendfile(int src_fd)
+{
+	int socks[2];
+	off_t off;
+	size_t len = 0;
+
+	CHECK(socketpair(AF_UNIX, SOCK_STREAM, 0, socks) == 0);
+
+	// Sends from the file offset.
+	CHECK(lseek(src_fd, 1000, SEEK_SET) == 1000);
+	CHECK(sendfile(socks[0], src_fd, NULL, 2000) == 2000);
+	CHECK(lseek(src_fd, 0, SEEK_CUR) == 3000);
+	CHECK(read(socks[1], buffer, 2000) == 2000);
+	CHECK(memcmp(buffer, data + 1000, 2000) == 0);
+
+	// Sends from an explicit offset until the end of the file.
+	off = FILE_SIZE - 3000;
+	CHECK(sendfile(socks[0], src_fd, &off, FILE_SIZE) == 3000);
+	CHECK(off == FILE_SIZE && lseek(src_fd, 0, SEEK_CUR) == 3000);
+	while (len < 3000) {
+		ssize_t ret = read(socks[1], buffer + len, 3000 - len);
+
+		CHECK(ret > 0);
+		len += ret;
+	}
+	CHECK(memcmp(buffer, data + FILE_SIZE - 3000, 3000) == 0);
+
+	// The input file cannot be a socket.
+	CHECK(sendfile(src_fd, socks[1], NULL, 10) < 0 && errno == EINVAL);
+
+	CHECK(close(socks[0]) == 0);
+	CHECK(close(socks[1]) == 0);
+}
+
+int main(void)
+{
+	int src_fd;
+
+	for (int i = 0; i < FILE_SIZE; i++) {
+		data[i] = i * 7 + i / 256;
+	}
+	src_fd = open(SRC_NAME, O_CREAT | O_RDWR | O_TRUNC, 0644);
+	CHECK(src_fd >= 0);
+	CHECK(write(src_fd, data, FILE_SIZE) == FILE_SIZE);
+
+	test_copy_file_range(src_fd);
+	test_sendfile(src_fd);
+
+	CHECK(close(src_fd) == 0);
+	CHECK(unlink(SRC_NAME) == 0);
+
+	printf("Test passed.\n");
+	return 0;
+}
@@ -0,0 +1,158 @@
+// SPDX-License-Identifier: MPL-2.0
+
+#define _GNU_SOURCE
+
+#include <errno.h>
+#include <fcntl.h>
+#include <stdio.h>
+#include <stdlib.h>
+#include <string.h>
+#include <unistd.h>
+
+#define FILE_NAME "/ext2/fadvise_test.txt"
+#define PAGE_SIZE 4096
+#define NR_PAGES 256
+
+static char buffer[PAGE_SIZE];
+
+static void fill_page(int idx)
+{
+	memset(buffer, 'a' + idx % 26, PAGE_SIZE);
+}
+
+static int check_page(int idx)
+{
+	for (int i = 0; i < PAGE_SIZE; i++) {
+		if (buffer[i] != 'a' + idx % 26) {
+			return -1;
+		}
+	}
+	return 0;
+}
+
+static int read_and_check(int fd, int idx)
+{
+	if (pread(fd, buffer, PAGE_SIZE, (off_t)idx * PAGE_SIZE) != PAGE_SIZE) {
+		perror("pread failed");
+		return -1;
+	}
+	if (check_page(idx) != 0) {
+		fprintf(stderr, "page %d contains invalid data\n", idx);
+		return -1;
+	}
+	return 0;
+}
+
+static int read_all(int fd)
+{
+	for (int i = 0; i < NR_PAGES; i++) {
+		if (read_and_check(fd, i) != 0) {
+			return -1;
+		}
+	}
+	return 0;
+}
+
+int main()
+{
+	int fd, pipe_fds[2], ret;
+
+	fd = open(FILE_NAME, O_RDWR | O_CREAT | O_TRUNC, 0644);
+	if (fd < 0) {
+		perror("open failed");
+		exit(1);
+	}
+	for (int i = 0; i < NR_PAGES; i++) {
+		fill_page(i);
+		if (write(fd, buffer, PAGE_SIZE) != PAGE_SIZE) {
+			perror("write failed");
+			exit(1);
+		}
+	}
+
+	// Dirty pages must be written back before they are dropped
+	if ((ret = posix_fadvise(fd, 0, 0, POSIX_FADV_DONTNEED)) != 0) {
+		fprintf(stderr, "POSIX_FADV_DONTNEED failed: %s\n",
+			strerror(ret));
+		exit(1);
+	}
+	if (read_all(fd) != 0) {
+		exit(1);
+	}
+
+	if ((ret = posix_fadvise(fd, 0, 0, POSIX_FADV_SEQUENTIAL)) != 0) {
+		fprintf(stderr, "POSIX_FADV_SEQUENTIAL failed: %s\n",
+			strerror(ret));
+		exit(1);
+	}
+	posix_fadvise(fd, 0, 0, POSIX_FADV_DONTNEED);
+	lseek(fd, 0, SEEK_SET);
+	for (int i = 0; i < NR_PAGES; i++) {
+		if (read(fd, buffer, PAGE_SIZE) != PAGE_SIZE) {
+			perror("read failed");
+			exit(1);
+		}
+		if (check_page(i) != 0) {
+			fprintf(stderr, "page %d contains invalid data\n", i);
+			exit(1);
+		}
+	}
+
+	if ((ret = posix_fadvise(fd, 0, 0, POSIX_FADV_RANDOM)) != 0) {
+		fprintf(stderr, "POSIX_FADV_RANDOM failed: %s\n",
+			strerror(ret));
+		exit(1);
+	}
+	posix_fadvise(fd, 0, 0, POSIX_FADV_DONTNEED);
+	for (int i = NR_PAGES - 1; i >= 0; i -= 7) {
+		if (read_and_check(fd, i) != 0) {
+			exit(1);
+		}
+	}
+
+	if ((ret = posix_fadvise(fd, PAGE_SIZE, 16 * PAGE_SIZE,
+				 POSIX_FADV_WILLNEED)) != 0) {
+		fprintf(stderr, "POSIX_FADV_WILLNEED failed: %s\n",
+			strerror(ret));
+		exit(1);
+	}
+	if ((ret = posix_fadvise(fd, 0, 0, POSIX_FADV_NOREUSE)) != 0) {
+		fprintf(stderr, "POSIX_FADV_NOREUSE failed: %s\n",
+			strerror(ret));
+		exit(1);
+	}
+	if ((ret = posix_fadvise(fd, 0, 0, POSIX_FADV_NORMAL)) != 0) {
+		fprintf(stderr, "POSIX_FADV_NORMAL failed: %s\n",
+			strerror(ret));
+		exit(1);
+	}
+	if (read_all(fd) != 0) {
+		exit(1);
+	}
+
+	if (posix_fadvise(fd, 0, 0, 100) != EINVAL) {
+		fprintf(stderr, "invalid advice should fail with EINVAL\n");
+		exit(1);
+	}
+	if (posix_fadvise(fd, 0, -1, POSIX_FADV_NORMAL) != EINVAL) {
+		fprintf(stderr, "negative length should fail with EINVAL\n");
+		exit(1);
+	}
+
+	if (pipe(pipe_fds) != 0) {
+		perror("pipe failed");
+		exit(1);
+	}
+	if (posix_fadvise(pipe_fds[0], 0, 0, POSIX_FADV_NORMAL) != ESPIPE) {
+		fprintf(stderr, "advising a pipe should fail with ESPIPE\n");
+		exit(1);
+	}
+	close(pipe_fds[0]);
+	close(pipe_fds[1]);
+
+	close(fd);
+	unlink(FILE_NAME);
+
+	printf("fadvise test passed\n");
+	return 0;
+}
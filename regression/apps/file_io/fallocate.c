@@ -0,0 +1,144 @@
+// SPDX-License-Identifier: MPL-2.0
+
+#define _GNU_SOURCE
+
+#include <errno.h>
+#include <fcntl.h>
+#include <stdio.h>
+#include <stdlib.h>
+#include <string.h>
+#include <unistd.h>
+#include <sys/stat.h>
+
+#define FILE_NAME "/ext2/fallocate_test"
+#define BLOCK_SIZE 4096
+#define NR_BLOCKS 16
+#define FILE_SIZE (NR_BLOCKS * BLOCK_SIZE)
+
+#define CHECK(cond)                                                       \
+	do {                                                              \
+		if (!(cond)) {                                            \
+			fprintf(stderr, "%s:%d: check failed: %s (%s)\n", \
+				__FILE__, __LINE__, #cond,                \
+				strerror(errno));                         \
+			exit(1);                                          \
+		}                                                         \
+	} while (0)
+
+static char buffer[BLOCK_SIZE];
+
+static int check_bytes(int fd, off_t offset, size_t len, char val)
+{
+	CHECK(pread(fd, buffer, len, offset) == len);
+	for (size_t i = 0; i < len; i++) {
+		if (buffer[i] != val) {
+			return -1;
+		}
+	}
+	return 0;
+}
+
+static blkcnt_t nr_blocks(int fd)
+{
+	struct stat st;
+
+	CHECK(fstat(fd, &st) == 0);
+	return st.st_blocks;
+}
+
+static void test_invalid_args(int fd)
+{
+	int ro_fd;
+
+	CHECK(fallocate(fd, 0, -1, BLOCK_SIZE) < 0 && errno == EINVAL);
+	CHECK(fallocate(fd, 0, 0, 0) < 0 && errno == EINVAL);
+	CHECK(fallocate(fd, FALLOC_FL_PUNCH_HOLE, 0, BLOCK_SIZE) < 0 &&
+	      errno == EOPNOTSUPP);
+	CHECK(fallocate(fd, FALLOC_FL_PUNCH_HOLE | FALLOC_FL_ZERO_RANGE, 0,
+			BLOCK_SIZE) < 0 &&
+	      errno == EOPNOTSUPP);
+	CHECK(fallocate(fd, FALLOC_FL_COLLAPSE_RANGE, 0, BLOCK_SIZE) < 0 &&
+	      errno == EOPNOTSUPP);
+
+	ro_fd = open(FILE_NAME, O_RDONLY);
+	CHECK(ro_fd >= 0);
+	CHECK(fallocate(ro_fd, 0, 0, BLOCK_SIZE) < 0 && errno == EBADF);
+	CHECK(close(ro_fd) == 0);
+}
+
+static void test_preallocate(int fd)
+{
+	struct stat st;
+
+	CHECK(fallocate(fd, 0, 0, FILE_SIZE) == 0);
+	CHECK(fstat(fd, &st) == 0);
+	CHECK(st.st_size == FILE_SIZE);
+	CHECK(st.st_blocks >= FILE_SIZE / 512);
+	CHECK(check_bytes(fd, 0, BLOCK_SIZE, 0) == 0);
+
+	// The size is kept.
+	CHECK(fallocate(fd, FALLOC_FL_KEEP_SIZE, FILE_SIZE, BLOCK_SIZE) == 0);
+	CHECK(fstat(fd, &st) == 0 && st.st_size == FILE_SIZE);
+
+	memset(buffer, 'a', BLOCK_SIZE);
+	for (int i = 0; i < NR_BLOCKS; i++) {
+		CHECK(pwrite(fd, buffer, BLOCK_SIZE, i * BLOCK_SIZE) ==
+		      BLOCK_SIZE);
+	}
+	CHECK(fsync(fd) == 0);
+}
+
+static void test_punch_hole(int fd)
+{
+	blkcnt_t blocks = nr_blocks(fd);
+
+	// Punches the blocks from 4 to 7, and a part of the block 3.
+	CHECK(fallocate(fd, FALLOC_FL_PUNCH_HOLE | FALLOC_FL_KEEP_SIZE,
+			3 * BLOCK_SIZE + 100, 5 * BLOCK_SIZE - 100) == 0);
+	CHECK(nr_blocks(fd) == blocks - 4 * (BLOCK_SIZE / 512));
+	CHECK(check_bytes(fd, 3 * BLOCK_SIZE, 100, 'a') == 0);
+	CHECK(check_bytes(fd, 3 * BLOCK_SIZE + 100, BLOCK_SIZE - 100, 0) == 0);
+	CHECK(check_bytes(fd, 4 * BLOCK_SIZE, BLOCK_SIZE, 0) == 0);
+	CHECK(check_bytes(fd, 8 * BLOCK_SIZE, BLOCK_SIZE, 'a') == 0);
+
+	CHECK(lseek(fd, 0, SEEK_HOLE) == 4 * BLOCK_SIZE);
+	CHECK(lseek(fd, 4 * BLOCK_SIZE + 1, SEEK_DATA) == 8 * BLOCK_SIZE);
+	CHECK(lseek(fd, 8 * BLOCK_SIZE, SEEK_HOLE) == FILE_SIZE);
+	CHECK(lseek(fd, FILE_SIZE, SEEK_DATA) < 0 && errno == ENXIO);
+
+	// Writing to the hole allocates the block again.
+	CHECK(pwrite(fd, buffer, BLOCK_SIZE, 5 * BLOCK_SIZE) == BLOCK_SIZE);
+	CHECK(fsync(fd) == 0);
+	CHECK(nr_blocks(fd) == blocks - 3 * (BLOCK_SIZE / 512));
+	CHECK(lseek(fd, 4 * BLOCK_SIZE, SEEK_DATA) == 5 * BLOCK_SIZE);
+	CHECK(check_bytes(fd, 5 * BLOCK_SIZE, BLOCK_SIZE, 'a') == 0);
+}
+
+static void test_zero_range(int fd)
+{
+	CHECK(fallocate(fd, FALLOC_FL_ZERO_RANGE, 9 * BLOCK_SIZE,
+			2 * BLOCK_SIZE) == 0);
+	CHECK(check_bytes(fd, 9 * BLOCK_SIZE, BLOCK_SIZE, 0) == 0);
+	CHECK(check_bytes(fd, 10 * BLOCK_SIZE, BLOCK_SIZE, 0) == 0);
+	CHECK(check_bytes(fd, 11 * BLOCK_SIZE, BLOCK_SIZE, 'a') == 0);
+	// The zeroed range is still allocated.
+	CHECK(lseek(fd, 9 * BLOCK_SIZE, SEEK_DATA) == 9 * BLOCK_SIZE);
+}
+
+int main(void)
+{
+	int fd;
+
+	fd = open(FILE_NAME, O_RDWR | O_CREAT | O_TRUNC, 0644);
+	CHECK(fd >= 0);
+
+	test_invalid_args(fd);
+	test_preallocate(fd);
+	test_punch_hole(fd);
+	test_zero_range(fd);
+
+	CHECK(close(fd) == 0);
+	CHECK(unlink(FILE_NAME) == 0);
+	printf("fallocate test passed\n");
+	return 0;
+}
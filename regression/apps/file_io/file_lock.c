@@ -0,0 +1,198 @@
+// SPDX-License-Identifier: MPL-2.0
+
+#define _GNU_SOURCE
+
+#include <errno.h>
+#include <fcntl.h>
+#include <stdio.h>
+#include <stdlib.h>
+#include <string.h>
+#include <unistd.h>
+#include <sys/file.h>
+#include <sys/wait.h>
+
+#define FILE_NAME "/tmp/file_lock_test"
+#define FILE_SIZE 100
+
+#define CHECK(cond)                                                       \
+	do {                                                              \
+		if (!(cond)) {                                            \
+			fprintf(stderr, "%s:%d: check failed: %s (%s)\n", \
+				__FILE__, __LINE__, #cond,                \
+				strerror(errno));                         \
+			exit(1);                                          \
+		}                                                         \
+	} while (0)
+
+static int set_lock(int fd, int cmd, short type, off_t start, off_t len)
+{
+	struct flock lock = {
+		.l_type = type,
+		.l_whence = SEEK_SET,
+		.l_start = start,
+		.l_len = len,
+	};
+
+	return fcntl(fd, cmd, &lock);
+}
+
+static struct flock get_lock(int fd, short type, off_t start, off_t len)
+{
+	struct flock lock = {
+		.l_type = type,
+		.l_whence = SEEK_SET,
+		.l_start = start,
+		.l_len = len,
+	};
+
+	CHECK(fcntl(fd, F_GETLK, &lock) == 0);
+	return lock;
+}
+
+static void wait_child(pid_t pid)
+{
+	int status;
+
+	CHECK(waitpid(pid, &status, 0) == pid);
+	CHECK(WIFEXITED(status) && WEXITSTATUS(status) == 0);
+}
+
+static void test_posix_locks(void)
+{
+	struct flock lock;
+	pid_t parent = getpid(), pid;
+	int fd, other_fd;
+
+	fd = open(FILE_NAME, O_RDWR);
+	CHECK(fd >= 0);
+	CHECK(set_lock(fd, F_SETLK, F_WRLCK, 0, 10) == 0);
+	// The own locks are replaced, and split by unlocking.
+	CHECK(set_lock(fd, F_SETLK, F_RDLCK, 0, 10) == 0);
+	CHECK(set_lock(fd, F_SETLK, F_WRLCK, 0, 10) == 0);
+	CHECK(set_lock(fd, F_SETLK, F_UNLCK, 2, 2) == 0);
+
+	pid = fork();
+	CHECK(pid >= 0);
+	if (pid == 0) {
+		fd = open(FILE_NAME, O_RDWR);
+		CHECK(fd >= 0);
+		lock = get_lock(fd, F_RDLCK, 0, FILE_SIZE);
+		CHECK(lock.l_type == F_WRLCK && lock.l_pid == parent);
+		CHECK(lock.l_start == 0 && lock.l_len == 2);
+		lock = get_lock(fd, F_WRLCK, 2, 2);
+		CHECK(lock.l_type == F_UNLCK);
+		CHECK(set_lock(fd, F_SETLK, F_RDLCK, 5, 1) < 0 &&
+		      errno == EAGAIN);
+		CHECK(set_lock(fd, F_SETLK, F_WRLCK, 2, 2) == 0);
+		CHECK(set_lock(fd, F_SETLK, F_WRLCK, 10, 0) == 0);
+		exit(0);
+	}
+	wait_child(pid);
+
+	// The locks of the parent are released once any of its file descriptors is closed.
+	other_fd = open(FILE_NAME, O_RDONLY);
+	CHECK(other_fd >= 0);
+	CHECK(set_lock(other_fd, F_SETLK, F_WRLCK, 0, 1) < 0 && errno == EBADF);
+	CHECK(close(other_fd) == 0);
+	pid = fork();
+	CHECK(pid >= 0);
+	if (pid == 0) {
+		lock = get_lock(fd, F_WRLCK, 0, 0);
+		CHECK(lock.l_type == F_UNLCK);
+		exit(0);
+	}
+	wait_child(pid);
+	CHECK(close(fd) == 0);
+}
+
+static void test_deadlock(void)
+{
+	int fd, pipe_fds[2];
+	char byte;
+	pid_t pid;
+
+	fd = open(FILE_NAME, O_RDWR);
+	CHECK(fd >= 0);
+	CHECK(pipe(pipe_fds) == 0);
+	CHECK(set_lock(fd, F_SETLK, F_WRLCK, 0, 10) == 0);
+
+	pid = fork();
+	CHECK(pid >= 0);
+	if (pid == 0) {
+		CHECK(set_lock(fd, F_SETLK, F_WRLCK, 20, 10) == 0);
+		CHECK(write(pipe_fds[1], "a", 1) == 1);
+		// Waits until the parent unlocks the range.
+		CHECK(set_lock(fd, F_SETLKW, F_WRLCK, 0, 10) == 0);
+		exit(0);
+	}
+
+	CHECK(read(pipe_fds[0], &byte, 1) == 1);
+	// Wait for the child to be blocked.
+	usleep(100 * 1000);
+	CHECK(set_lock(fd, F_SETLKW, F_WRLCK, 20, 10) < 0 && errno == EDEADLK);
+	CHECK(set_lock(fd, F_SETLK, F_UNLCK, 0, 10) == 0);
+	wait_child(pid);
+
+	CHECK(close(pipe_fds[0]) == 0);
+	CHECK(close(pipe_fds[1]) == 0);
+	CHECK(close(fd) == 0);
+}
+
+static void test_flock(void)
+{
+	int fd, other_fd, dup_fd;
+	pid_t pid;
+
+	fd = open(FILE_NAME, O_RDONLY);
+	CHECK(fd >= 0);
+	other_fd = open(FILE_NAME, O_RDONLY);
+	CHECK(other_fd >= 0);
+
+	CHECK(flock(fd, LOCK_SH | LOCK_EX) < 0 && errno == EINVAL);
+	CHECK(flock(fd, LOCK_SH) == 0);
+	CHECK(flock(other_fd, LOCK_SH | LOCK_NB) == 0);
+	CHECK(flock(fd, LOCK_EX | LOCK_NB) < 0 && errno == EWOULDBLOCK);
+	CHECK(flock(other_fd, LOCK_UN) == 0);
+	CHECK(flock(fd, LOCK_EX | LOCK_NB) == 0);
+	// The POSIX locks do not interact with the flock locks.
+	CHECK(set_lock(other_fd, F_SETLK, F_RDLCK, 0, 0) == 0);
+
+	// The lock is released once all the file descriptors of the open file are closed.
+	dup_fd = dup(fd);
+	CHECK(dup_fd >= 0);
+	CHECK(close(fd) == 0);
+	CHECK(flock(other_fd, LOCK_SH | LOCK_NB) < 0 && errno == EWOULDBLOCK);
+
+	pid = fork();
+	CHECK(pid >= 0);
+	if (pid == 0) {
+		// Waits until the parent closes the file.
+		CHECK(close(dup_fd) == 0);
+		CHECK(flock(other_fd, LOCK_EX) == 0);
+		exit(0);
+	}
+	usleep(100 * 1000);
+	CHECK(close(dup_fd) == 0);
+	wait_child(pid);
+
+	CHECK(close(other_fd) == 0);
+}
+
+int main(void)
+{
+	char buffer[FILE_SIZE] = { 0 };
+	int fd;
+
+	fd = open(FILE_NAME, O_RDWR | O_CREAT | O_TRUNC, 0644);
+	CHECK(fd >= 0);
+	CHECK(write(fd, buffer, FILE_SIZE) == FILE_SIZE);
+	CHECK(close(fd) == 0);
+
+	test_posix_locks();
+	test_deadlock();
+	test_flock();
+
+	CHECK(unlink(FILE_NAME) == 0);
+	printf("file lock test passed\n");
+	return 0;
+}
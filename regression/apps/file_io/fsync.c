@@ -0,0 +1,123 @@
+// SPDX-License-Identifier: MPL-2.0
+
+#define _GNU_SOURCE
+
+#include <errno.h>
+#include <fcntl.h>
+#include <stdio.h>
+#include <stdlib.h>
+#include <string.h>
+#include <unistd.h>
+#include <sys/stat.h>
+#include <sys/statfs.h>
+
+#define DIR_NAME "/ext2/fsync_test"
+#define FILE_NAME DIR_NAME "/file"
+#define BLOCK_SIZE 4096
+// More than the 12 direct blocks, so the indirect blocks are allocated.
+#define NR_BLOCKS 32
+#define NR_ENTRIES 64
+
+#define CHECK(cond)                                                       \
+	do {                                                              \
+		if (!(cond)) {                                            \
+			fprintf(stderr, "%s:%d: check failed: %s (%s)\n", \
+				__FILE__, __LINE__, #cond,                \
+				strerror(errno));                         \
+			exit(1);                                          \
+		}                                                         \
+	} while (0)
+
+static char buffer[BLOCK_SIZE];
+
+static long free_blocks(void)
+{
+	struct statfs stat;
+
+	CHECK(statfs("/ext2", &stat) == 0);
+	return stat.f_bfree;
+}
+
+static void test_write_and_truncate(void)
+{
+	long nr_free;
+	int fd, i;
+
+	nr_free = free_blocks();
+	fd = open(FILE_NAME, O_RDWR | O_CREAT | O_TRUNC, 0644);
+	CHECK(fd >= 0);
+	for (i = 0; i < NR_BLOCKS; i++) {
+		memset(buffer, 'a' + i % 26, BLOCK_SIZE);
+		CHECK(write(fd, buffer, BLOCK_SIZE) == BLOCK_SIZE);
+	}
+	CHECK(fsync(fd) == 0);
+	CHECK(free_blocks() <= nr_free - NR_BLOCKS);
+
+	for (i = 0; i < NR_BLOCKS; i += 7) {
+		CHECK(pread(fd, buffer, BLOCK_SIZE, (off_t)i * BLOCK_SIZE) ==
+		      BLOCK_SIZE);
+		CHECK(buffer[0] == 'a' + i % 26 &&
+		      buffer[BLOCK_SIZE - 1] == 'a' + i % 26);
+	}
+
+	// The truncated part of the last block reads as zeros after the file grows.
+	CHECK(ftruncate(fd, 100) == 0);
+	CHECK(ftruncate(fd, 2 * BLOCK_SIZE) == 0);
+	CHECK(fsync(fd) == 0);
+	CHECK(pread(fd, buffer, BLOCK_SIZE, 0) == BLOCK_SIZE);
+	CHECK(buffer[99] == 'a' && buffer[100] == 0 &&
+	      buffer[BLOCK_SIZE - 1] == 0);
+
+	CHECK(ftruncate(fd, 0) == 0);
+	CHECK(fsync(fd) == 0);
+	CHECK(free_blocks() == nr_free);
+
+	CHECK(close(fd) == 0);
+	CHECK(unlink(FILE_NAME) == 0);
+}
+
+static void test_dir_entries(void)
+{
+	char name[64];
+	struct stat stat;
+	int fd, dir_fd, i;
+
+	for (i = 0; i < NR_ENTRIES; i++) {
+		snprintf(name, sizeof(name), DIR_NAME "/entry_%d", i);
+		fd = open(name, O_WRONLY | O_CREAT, 0644);
+		CHECK(fd >= 0);
+		CHECK(close(fd) == 0);
+	}
+	for (i = 0; i < NR_ENTRIES; i += 2) {
+		snprintf(name, sizeof(name), DIR_NAME "/entry_%d", i);
+		CHECK(unlink(name) == 0);
+	}
+
+	dir_fd = open(DIR_NAME, O_RDONLY | O_DIRECTORY);
+	CHECK(dir_fd >= 0);
+	CHECK(fsync(dir_fd) == 0);
+	CHECK(close(dir_fd) == 0);
+
+	for (i = 0; i < NR_ENTRIES; i++) {
+		snprintf(name, sizeof(name), DIR_NAME "/entry_%d", i);
+		if (i % 2 == 0) {
+			errno = 0;
+			CHECK(lstat(name, &stat) == -1 && errno == ENOENT);
+		} else {
+			CHECK(lstat(name, &stat) == 0);
+			CHECK(unlink(name) == 0);
+		}
+	}
+}
+
+int main(void)
+{
+	CHECK(mkdir(DIR_NAME, 0755) == 0);
+
+	test_write_and_truncate();
+	test_dir_entries();
+
+	CHECK(rmdir(DIR_NAME) == 0);
+	printf("All fsync tests passed.\n");
+	return 0;
+}
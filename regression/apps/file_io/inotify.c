@@ -0,0 +1,191 @@
+// SPDX-License-Identifier: MPL-2.0
+
+#define _GNU_SOURCE
+
+#include <errno.h>
+#include <fcntl.h>
+#include <poll.h>
+#include <stdio.h>
+#include <stdlib.h>
+#include <string.h>
+#include <unistd.h>
+#include <sys/inotify.h>
+#include <sys/ioctl.h>
+#include <sys/stat.h>
+
+#define DIR_NAME "/tmp/inotify_test"
+#define FILE_NAME DIR_NAME "/file"
+#define NEW_FILE_NAME DIR_NAME "/new_file"
+
+#define CHECK(cond)                                                       \
+	do {                                                              \
+		if (!(cond)) {                                            \
+			fprintf(stderr, "%s:%d: check failed: %s (%s)\n", \
+				__FILE__, __LINE__, #cond,                \
+				strerror(errno));                         \
+			exit(1);                                          \
+		}                                                         \
+	} while (0)
+
+static char buffer[4096] __attribute__((aligned(8)));
+static size_t buffer_len;
+static size_t buffer_pos;
+
+// Returns the next event, reading more events from the inotify file if needed.
+static struct inotify_event *next_event(int fd)
+{
+	struct inotify_event *event;
+	ssize_t len;
+
+	if (buffer_pos == buffer_len) {
+		len = read(fd, buffer, sizeof(buffer));
+		CHECK(len > 0);
+		buffer_len = len;
+		buffer_pos = 0;
+	}
+	event = (struct inotify_event *)(buffer + buffer_pos);
+	buffer_pos += sizeof(*event) + event->len;
+	return event;
+}
+
+static void expect_event(int fd, int wd, uint32_t mask, const char *name)
+{
+	struct inotify_event *event = next_event(fd);
+
+	CHECK(event->wd == wd);
+	CHECK(event->mask == mask);
+	if (name == NULL) {
+		CHECK(event->len == 0);
+	} else {
+		CHECK(event->len > strlen(name) &&
+		      event->len % sizeof(struct inotify_event) == 0);
+		CHECK(strcmp(event->name, name) == 0);
+	}
+}
+
+static void expect_no_event(int fd)
+{
+	char buf[sizeof(struct inotify_event)];
+
+	CHECK(buffer_pos == buffer_len);
+	CHECK(read(fd, buf, sizeof(buf)) < 0 && errno == EAGAIN);
+}
+
+static void test_file_events(int fd, int dir_wd)
+{
+	struct pollfd pfd = { .fd = fd, .events = POLLIN };
+	int file_fd, pending;
+
+	file_fd = open(FILE_NAME, O_WRONLY | O_CREAT | O_TRUNC, 0644);
+	CHECK(file_fd >= 0);
+	CHECK(poll(&pfd, 1, 0) == 1 && (pfd.revents & POLLIN));
+	CHECK(ioctl(fd, FIONREAD, &pending) == 0 && pending > 0);
+	// The event is larger than the buffer.
+	CHECK(read(fd, buffer, sizeof(struct inotify_event)) < 0 &&
+	      errno == EINVAL);
+
+	// The same events in a row are merged.
+	CHECK(write(file_fd, "a", 1) == 1);
+	CHECK(write(file_fd, "b", 1) == 1);
+	CHECK(close(file_fd) == 0);
+	CHECK(truncate(FILE_NAME, 1) == 0);
+
+	expect_event(fd, dir_wd, IN_CREATE, "file");
+	expect_event(fd, dir_wd, IN_OPEN, "file");
+	expect_event(fd, dir_wd, IN_MODIFY, "file");
+	expect_event(fd, dir_wd, IN_CLOSE_WRITE, "file");
+	expect_event(fd, dir_wd, IN_MODIFY, "file");
+	expect_no_event(fd);
+	CHECK(poll(&pfd, 1, 0) == 0);
+}
+
+static void test_rename_and_unlink(int fd, int dir_wd)
+{
+	struct inotify_event *event;
+	uint32_t cookie;
+	int file_wd;
+
+	file_wd = inotify_add_watch(fd, FILE_NAME, IN_MOVE_SELF | IN_ATTRIB);
+	CHECK(file_wd > 0 && file_wd != dir_wd);
+	CHECK(inotify_add_watch(fd, FILE_NAME, IN_DELETE_SELF | IN_MASK_ADD) ==
+	      file_wd);
+	CHECK(inotify_add_watch(fd, FILE_NAME, IN_MODIFY | IN_MASK_CREATE) <
+		      0 &&
+	      errno == EEXIST);
+	CHECK(inotify_add_watch(fd, FILE_NAME, IN_MODIFY | IN_ONLYDIR) < 0 &&
+	      errno == ENOTDIR);
+
+	CHECK(rename(FILE_NAME, NEW_FILE_NAME) == 0);
+	event = next_event(fd);
+	CHECK(event->wd == dir_wd && event->mask == IN_MOVED_FROM);
+	CHECK(strcmp(event->name, "file") == 0);
+	cookie = event->cookie;
+	event = next_event(fd);
+	CHECK(event->wd == dir_wd && event->mask == IN_MOVED_TO);
+	CHECK(strcmp(event->name, "new_file") == 0);
+	CHECK(event->cookie == cookie && cookie != 0);
+	expect_event(fd, file_wd, IN_MOVE_SELF, NULL);
+
+	CHECK(unlink(NEW_FILE_NAME) == 0);
+	expect_event(fd, file_wd, IN_ATTRIB, NULL);
+	expect_event(fd, dir_wd, IN_DELETE, "new_file");
+	expect_event(fd, file_wd, IN_DELETE_SELF, NULL);
+	expect_event(fd, file_wd, IN_IGNORED, NULL);
+	expect_no_event(fd);
+	CHECK(inotify_rm_watch(fd, file_wd) < 0 && errno == EINVAL);
+}
+
+static void test_dir_events(int fd, int dir_wd)
+{
+	CHECK(mkdir(DIR_NAME "/subdir", 0755) == 0);
+	CHECK(rmdir(DIR_NAME "/subdir") == 0);
+	expect_event(fd, dir_wd, IN_CREATE | IN_ISDIR, "subdir");
+	expect_event(fd, dir_wd, IN_DELETE | IN_ISDIR, "subdir");
+	expect_no_event(fd);
+}
+
+static void test_oneshot(int fd)
+{
+	int wd;
+
+	wd = inotify_add_watch(fd, DIR_NAME, IN_CREATE | IN_ONESHOT);
+	CHECK(wd > 0);
+	CHECK(close(open(FILE_NAME, O_RDONLY | O_CREAT, 0644)) == 0);
+	CHECK(unlink(FILE_NAME) == 0);
+	expect_event(fd, wd, IN_CREATE, "file");
+	expect_event(fd, wd, IN_IGNORED, NULL);
+	expect_no_event(fd);
+}
+
+int main(void)
+{
+	int fd, dir_wd;
+
+	CHECK(inotify_init1(~(IN_NONBLOCK | IN_CLOEXEC)) < 0 &&
+	      errno == EINVAL);
+	fd = inotify_init1(IN_NONBLOCK | IN_CLOEXEC);
+	CHECK(fd >= 0);
+	CHECK(inotify_add_watch(fd, DIR_NAME, IN_ALL_EVENTS) < 0 &&
+	      errno == ENOENT);
+	CHECK(inotify_add_watch(fd, "/tmp", 0) < 0 && errno == EINVAL);
+	CHECK(inotify_add_watch(STDOUT_FILENO, "/tmp", IN_CREATE) < 0 &&
+	      errno == EINVAL);
+
+	CHECK(mkdir(DIR_NAME, 0755) == 0);
+	dir_wd = inotify_add_watch(fd, DIR_NAME, IN_ALL_EVENTS);
+	CHECK(dir_wd > 0);
+	expect_no_event(fd);
+
+	test_file_events(fd, dir_wd);
+	test_rename_and_unlink(fd, dir_wd);
+	test_dir_events(fd, dir_wd);
+
+	CHECK(inotify_rm_watch(fd, dir_wd) == 0);
+	expect_event(fd, dir_wd, IN_IGNORED, NULL);
+	test_oneshot(fd);
+
+	CHECK(rmdir(DIR_NAME) == 0);
+	CHECK(close(fd) == 0);
+	printf("inotify test passed\n");
+	return 0;
+}
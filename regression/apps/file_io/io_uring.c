@@ -0,0 +1,453 @@
+// SPDX-License-Identifier: MPL-2.0
+
+#define _GNU_SOURCE
+
+#include <errno.h>
+#include <fcntl.h>
+#include <stdint.h>
+#include <stdio.h>
+#include <stdlib.h>
+#include <string.h>
+#include <unistd.h>
+#include <arpa/inet.h>
+#include <linux/io_uring.h>
+#include <netinet/in.h>
+#include <sys/eventfd.h>
+#include <sys/mman.h>
+#include <sys/socket.h>
+#include <sys/syscall.h>
+
+#define FILE_NAME "/tmp/io_uring_test"
+#define NR_ENTRIES 4
+
+#define CHECK(cond)                                                       \
+	do {                                                              \
+		if (!(cond)) {                                            \
+			fprintf(stderr, "%s:%d: check failed: %s (%s)\n", \
+				__FILE__, __LINE__, #cond,                \
+				strerror(errno));                         \
+			exit(1);                                          \
+		}                                                         \
+	} while (0)
+
+static int ring_fd;
+static struct io_uring_params params;
+static char *sq_ring;
+static char *cq_ring;
+static struct io_uring_sqe *sqes;
+
+#define SQ_FIELD(name) ((unsigned *)(sq_ring + params.sq_off.name))
+#define CQ_FIELD(name) ((unsigned *)(cq_ring + params.cq_off.name))
+
+static void setup_ring(void)
+{
+	size_t sq_size, cq_size;
+
+	memset(&params, 0, sizeof(params));
+	ring_fd = syscall(SYS_io_uring_setup, 3, &params);
+	CHECK(ring_fd >= 0);
+	CHECK(params.sq_entries == NR_ENTRIES);
+	CHECK(params.cq_entries == 2 * NR_ENTRIES);
+	CHECK(params.features & IORING_FEAT_SINGLE_MMAP);
+	CHECK(params.features & IORING_FEAT_NODROP);
+	CHECK((fcntl(ring_fd, F_GETFD) & FD_CLOEXEC) != 0);
+
+	sq_size = params.sq_off.array + params.sq_entries * sizeof(unsigned);
+	cq_size = params.cq_off.cqes +
+		  params.cq_entries * sizeof(struct io_uring_cqe);
+	if (cq_size > sq_size)
+		sq_size = cq_size;
+	sq_ring = mmap(NULL, sq_size, PROT_READ | PROT_WRITE, MAP_SHARED,
+		       ring_fd, IORING_OFF_SQ_RING);
+	CHECK(sq_ring != MAP_FAILED);
+	cq_ring = sq_ring;
+	sqes = mmap(NULL, params.sq_entries * sizeof(struct io_uring_sqe),
+		    PROT_READ | PROT_WRITE, MAP_SHARED, ring_fd,
+		    IORING_OFF_SQES);
+	CHECK(sqes != MAP_FAILED);
+
+	CHECK(*SQ_FIELD(ring_entries) == NR_ENTRIES);
+	CHECK(*CQ_FIELD(ring_entries) == 2 * NR_ENTRIES);
+}
+
+static struct io_uring_sqe *get_sqe(void)
+{
+	unsigned tail = *SQ_FIELD(tail);
+	unsigned index = tail & *SQ_FIELD(ring_mask);
+	struct io_uring_sqe *sqe = &sqes[index];
+
+	memset(sqe, 0, sizeof(*sqe));
+	SQ_FIELD(array)[index] = index;
+	return sqe;
+}
+
+static void submit(int min_complete)
+{
+	unsigned tail = *SQ_FIELD(tail);
+
+	__atomic_store_n(SQ_FIELD(tail), tail + 1, __ATOMIC_RELEASE);
+	CHECK(syscall(SYS_io_uring_enter, ring_fd, 1, min_complete,
+		      IORING_ENTER_GETEVENTS, NULL, 0) == 1);
+}
+
+static int nr_ready_cqes(void)
+{
+	unsigned head = *CQ_FIELD(head);
+
+	return __atomic_load_n(CQ_FIELD(tail), __ATOMIC_ACQUIRE) - head;
+}
+
+static int reap_cqe_with_flags(uint64_t user_data, unsigned *flags)
+{
+	unsigned head = *CQ_FIELD(head);
+	struct io_uring_cqe *cqes = (void *)(cq_ring + params.cq_off.cqes);
+	struct io_uring_cqe *cqe;
+	int res;
+
+	CHECK(nr_ready_cqes() > 0);
+	cqe = &cqes[head & *CQ_FIELD(ring_mask)];
+	CHECK(cqe->user_data == user_data);
+	res = cqe->res;
+	if (flags)
+		*flags = cqe->flags;
+	__atomic_store_n(CQ_FIELD(head), head + 1, __ATOMIC_RELEASE);
+	return res;
+}
+
+static int reap_cqe(uint64_t user_data)
+{
+	return reap_cqe_with_flags(user_data, NULL);
+}
+
+static void wait_cqe(void)
+{
+	CHECK(syscall(SYS_io_uring_enter, ring_fd, 0, 1,
+		      IORING_ENTER_GETEVENTS, NULL, 0) == 0);
+}
+
+static int submit_and_wait(struct io_uring_sqe *sqe, uint64_t user_data)
+{
+	sqe->user_data = user_data;
+	submit(1);
+	return reap_cqe(user_data);
+}
+
+static void test_file_ops(void)
+{
+	struct io_uring_sqe *sqe;
+	char buffer[16];
+	int fd;
+
+	sqe = get_sqe();
+	sqe->opcode = IORING_OP_OPENAT;
+	sqe->fd = AT_FDCWD;
+	sqe->addr = (uintptr_t)FILE_NAME;
+	sqe->open_flags = O_RDWR | O_CREAT | O_TRUNC;
+	sqe->len = 0644;
+	fd = submit_and_wait(sqe, 1);
+	CHECK(fd >= 0);
+
+	sqe = get_sqe();
+	sqe->opcode = IORING_OP_WRITE;
+	sqe->fd = fd;
+	sqe->addr = (uintptr_t) "hello, io_uring";
+	sqe->len = 15;
+	sqe->off = 0;
+	CHECK(submit_and_wait(sqe, 2) == 15);
+
+	sqe = get_sqe();
+	sqe->opcode = IORING_OP_READ;
+	sqe->fd = fd;
+	sqe->addr = (uintptr_t)buffer;
+	sqe->len = sizeof(buffer);
+	sqe->off = 7;
+	CHECK(submit_and_wait(sqe, 3) == 8);
+	CHECK(memcmp(buffer, "io_uring", 8) == 0);
+
+	// The offset of -1 means the file offset, which has not been moved.
+	sqe = get_sqe();
+	sqe->opcode = IORING_OP_READ;
+	sqe->fd = fd;
+	sqe->addr = (uintptr_t)buffer;
+	sqe->len = 5;
+	sqe->off = (uint64_t)-1;
+	CHECK(submit_and_wait(sqe, 4) == 5);
+	CHECK(memcmp(buffer, "hello", 5) == 0);
+	CHECK(lseek(fd, 0, SEEK_CUR) == 5);
+
+	sqe = get_sqe();
+	sqe->opcode = IORING_OP_CLOSE;
+	sqe->fd = fd;
+	CHECK(submit_and_wait(sqe, 5) == 0);
+	CHECK(fcntl(fd, F_GETFD) < 0 && errno == EBADF);
+
+	sqe = get_sqe();
+	sqe->opcode = IORING_OP_CLOSE;
+	sqe->fd = fd;
+	CHECK(submit_and_wait(sqe, 6) == -EBADF);
+
+	CHECK(unlink(FILE_NAME) == 0);
+}
+
+static void test_pipe_and_eventfd(void)
+{
+	struct io_uring_sqe *sqe;
+	int pipe_fds[2], event_fd;
+	char buffer[16];
+	uint64_t count;
+
+	event_fd = eventfd(0, EFD_NONBLOCK);
+	CHECK(event_fd >= 0);
+	CHECK(syscall(SYS_io_uring_register, ring_fd,
+		      IORING_REGISTER_EVENTFD, &event_fd, 1) == 0);
+	errno = 0;
+	CHECK(syscall(SYS_io_uring_register, ring_fd,
+		      IORING_REGISTER_EVENTFD, &event_fd, 1) < 0 &&
+	      errno == EBUSY);
+
+	CHECK(pipe(pipe_fds) == 0);
+
+	// The read waits for the data without blocking the submission.
+	sqe = get_sqe();
+	sqe->opcode = IORING_OP_READ;
+	sqe->fd = pipe_fds[0];
+	sqe->addr = (uintptr_t)buffer;
+	sqe->len = sizeof(buffer);
+	sqe->user_data = 7;
+	submit(0);
+	usleep(100 * 1000);
+	CHECK(nr_ready_cqes() == 0);
+	CHECK(read(event_fd, &count, sizeof(count)) < 0 && errno == EAGAIN);
+
+	CHECK(write(pipe_fds[1], "pipe", 4) == 4);
+	CHECK(syscall(SYS_io_uring_enter, ring_fd, 0, 1,
+		      IORING_ENTER_GETEVENTS, NULL, 0) == 0);
+	CHECK(reap_cqe(7) == 4);
+	CHECK(memcmp(buffer, "pipe", 4) == 0);
+	CHECK(read(event_fd, &count, sizeof(count)) == sizeof(count));
+	CHECK(count == 1);
+
+	CHECK(syscall(SYS_io_uring_register, ring_fd,
+		      IORING_UNREGISTER_EVENTFD, NULL, 0) == 0);
+	errno = 0;
+	CHECK(syscall(SYS_io_uring_register, ring_fd,
+		      IORING_UNREGISTER_EVENTFD, NULL, 0) < 0 &&
+	      errno == ENXIO);
+
+	CHECK(close(pipe_fds[0]) == 0);
+	CHECK(close(pipe_fds[1]) == 0);
+	CHECK(close(event_fd) == 0);
+}
+
+static void test_invalid_sqes(void)
+{
+	struct io_uring_sqe *sqe;
+
+	sqe = get_sqe();
+	sqe->opcode = IORING_OP_NOP;
+	CHECK(submit_and_wait(sqe, 8) == 0);
+
+	sqe = get_sqe();
+	sqe->opcode = 0xff;
+	CHECK(submit_and_wait(sqe, 9) == -EINVAL);
+
+	sqe = get_sqe();
+	sqe->opcode = IORING_OP_READ;
+	sqe->fd = -1;
+	CHECK(submit_and_wait(sqe, 10) == -EBADF);
+
+	errno = 0;
+	CHECK(syscall(SYS_io_uring_enter, 0, 0, 0, 0, NULL, 0) < 0 &&
+	      errno == EOPNOTSUPP);
+}
+
+static void test_provide_buffers(void)
+{
+	struct io_uring_sqe *sqe;
+	char buffers[2][8];
+	int pipe_fds[2];
+	unsigned flags;
+
+	CHECK(pipe(pipe_fds) == 0);
+
+	// Two buffers with the IDs 5 and 6 are added to the group 2.
+	sqe = get_sqe();
+	sqe->opcode = IORING_OP_PROVIDE_BUFFERS;
+	sqe->fd = 2;
+	sqe->addr = (uintptr_t)buffers;
+	sqe->len = sizeof(buffers[0]);
+	sqe->off = 5;
+	sqe->buf_group = 2;
+	CHECK(submit_and_wait(sqe, 20) == 0);
+
+	// The read selects the first buffer.
+	CHECK(write(pipe_fds[1], "abc", 3) == 3);
+	sqe = get_sqe();
+	sqe->opcode = IORING_OP_READ;
+	sqe->flags = IOSQE_BUFFER_SELECT;
+	sqe->fd = pipe_fds[0];
+	sqe->buf_group = 2;
+	sqe->user_data = 21;
+	submit(1);
+	CHECK(reap_cqe_with_flags(21, &flags) == 3);
+	CHECK((flags & IORING_CQE_F_BUFFER) &&
+	      (flags >> IORING_CQE_BUFFER_SHIFT) == 5);
+	CHECK(memcmp(buffers[0], "abc", 3) == 0);
+
+	// The last buffer is removed, and the group is gone with it.
+	sqe = get_sqe();
+	sqe->opcode = IORING_OP_REMOVE_BUFFERS;
+	sqe->fd = 8;
+	sqe->buf_group = 2;
+	CHECK(submit_and_wait(sqe, 22) == 1);
+
+	CHECK(write(pipe_fds[1], "d", 1) == 1);
+	sqe = get_sqe();
+	sqe->opcode = IORING_OP_READ;
+	sqe->flags = IOSQE_BUFFER_SELECT;
+	sqe->fd = pipe_fds[0];
+	sqe->buf_group = 2;
+	CHECK(submit_and_wait(sqe, 23) == -ENOBUFS);
+
+	sqe = get_sqe();
+	sqe->opcode = IORING_OP_REMOVE_BUFFERS;
+	sqe->fd = 1;
+	sqe->buf_group = 2;
+	CHECK(submit_and_wait(sqe, 24) == -ENOENT);
+
+	// Only the operations that read into a buffer can select one.
+	sqe = get_sqe();
+	sqe->opcode = IORING_OP_NOP;
+	sqe->flags = IOSQE_BUFFER_SELECT;
+	CHECK(submit_and_wait(sqe, 25) == -EINVAL);
+
+	CHECK(close(pipe_fds[0]) == 0);
+	CHECK(close(pipe_fds[1]) == 0);
+}
+
+#define NR_RING_BUFS 2
+#define RING_BUF_LEN 16
+#define RING_BGID 1
+
+static void test_multishot_accept_and_recv(void)
+{
+	struct sockaddr_in addr = { .sin_family = AF_INET };
+	socklen_t addrlen = sizeof(addr);
+	struct io_uring_buf_reg reg;
+	struct io_uring_buf_ring *buf_ring;
+	static char bufs[NR_RING_BUFS][RING_BUF_LEN];
+	struct io_uring_sqe *sqe;
+	int listener, clients[2], accepted[2];
+	unsigned flags, bid;
+	int i;
+
+	listener = socket(AF_INET, SOCK_STREAM, 0);
+	CHECK(listener >= 0);
+	addr.sin_addr.s_addr = htonl(INADDR_LOOPBACK);
+	CHECK(bind(listener, (struct sockaddr *)&addr, sizeof(addr)) == 0);
+	CHECK(getsockname(listener, (struct sockaddr *)&addr, &addrlen) == 0);
+	CHECK(listen(listener, 2) == 0);
+
+	// One SQE accepts all the connections.
+	sqe = get_sqe();
+	sqe->opcode = IORING_OP_ACCEPT;
+	sqe->fd = listener;
+	sqe->ioprio = IORING_ACCEPT_MULTISHOT;
+	sqe->user_data = 30;
+	submit(0);
+	for (i = 0; i < 2; i++) {
+		clients[i] = socket(AF_INET, SOCK_STREAM, 0);
+		CHECK(clients[i] >= 0);
+		CHECK(connect(clients[i], (struct sockaddr *)&addr,
+			      sizeof(addr)) == 0);
+		wait_cqe();
+		accepted[i] = reap_cqe_with_flags(30, &flags);
+		CHECK(accepted[i] >= 0 && (flags & IORING_CQE_F_MORE));
+	}
+
+	buf_ring = mmap(NULL, 4096, PROT_READ | PROT_WRITE,
+			MAP_PRIVATE | MAP_ANONYMOUS, -1, 0);
+	CHECK(buf_ring != MAP_FAILED);
+	memset(&reg, 0, sizeof(reg));
+	reg.ring_addr = (uintptr_t)buf_ring;
+	reg.ring_entries = 4;
+	reg.bgid = RING_BGID;
+	CHECK(syscall(SYS_io_uring_register, ring_fd, IORING_REGISTER_PBUF_RING,
+		      &reg, 1) == 0);
+	errno = 0;
+	CHECK(syscall(SYS_io_uring_register, ring_fd, IORING_REGISTER_PBUF_RING,
+		      &reg, 1) < 0 &&
+	      errno == EEXIST);
+	for (i = 0; i < NR_RING_BUFS; i++) {
+		buf_ring->bufs[i].addr = (uintptr_t)bufs[i];
+		buf_ring->bufs[i].len = RING_BUF_LEN;
+		buf_ring->bufs[i].bid = i;
+	}
+	__atomic_store_n(&buf_ring->tail, NR_RING_BUFS, __ATOMIC_RELEASE);
+
+	// A multishot receiving must select the buffers.
+	sqe = get_sqe();
+	sqe->opcode = IORING_OP_RECV;
+	sqe->fd = accepted[0];
+	sqe->ioprio = IORING_RECV_MULTISHOT;
+	CHECK(submit_and_wait(sqe, 31) == -EINVAL);
+
+	// One SQE receives all the messages, each into a buffer of the ring.
+	sqe = get_sqe();
+	sqe->opcode = IORING_OP_RECV;
+	sqe->flags = IOSQE_BUFFER_SELECT;
+	sqe->fd = accepted[0];
+	sqe->buf_group = RING_BGID;
+	sqe->ioprio = IORING_RECV_MULTISHOT;
+	sqe->user_data = 32;
+	submit(0);
+
+	CHECK(write(clients[0], "first", 5) == 5);
+	wait_cqe();
+	CHECK(reap_cqe_with_flags(32, &flags) == 5);
+	CHECK((flags & IORING_CQE_F_MORE) && (flags & IORING_CQE_F_BUFFER));
+	bid = flags >> IORING_CQE_BUFFER_SHIFT;
+	CHECK(bid < NR_RING_BUFS && memcmp(bufs[bid], "first", 5) == 0);
+
+	CHECK(write(clients[0], "second", 6) == 6);
+	wait_cqe();
+	CHECK(reap_cqe_with_flags(32, &flags) == 6);
+	CHECK((flags & IORING_CQE_F_MORE) && (flags & IORING_CQE_F_BUFFER));
+	CHECK((flags >> IORING_CQE_BUFFER_SHIFT) != bid);
+	bid = flags >> IORING_CQE_BUFFER_SHIFT;
+	CHECK(bid < NR_RING_BUFS && memcmp(bufs[bid], "second", 6) == 0);
+
+	// The ring runs out of buffers, which terminates the receiving.
+	CHECK(write(clients[0], "third", 5) == 5);
+	wait_cqe();
+	CHECK(reap_cqe_with_flags(32, &flags) == -ENOBUFS);
+	CHECK(!(flags & IORING_CQE_F_MORE));
+
+	CHECK(syscall(SYS_io_uring_register, ring_fd,
+		      IORING_UNREGISTER_PBUF_RING, &reg, 1) == 0);
+	errno = 0;
+	CHECK(syscall(SYS_io_uring_register, ring_fd,
+		      IORING_UNREGISTER_PBUF_RING, &reg, 1) < 0 &&
+	      errno == ENOENT);
+
+	for (i = 0; i < 2; i++) {
+		CHECK(close(clients[i]) == 0);
+		CHECK(close(accepted[i]) == 0);
+	}
+	CHECK(close(listener) == 0);
+	CHECK(munmap(buf_ring, 4096) == 0);
+}
+
+int main(void)
+{
+	setup_ring();
+	test_file_ops();
+	test_pipe_and_eventfd();
+	test_invalid_sqes();
+	test_provide_buffers();
+	test_multishot_accept_and_recv();
+	CHECK(close(ring_fd) == 0);
+
+	printf("Test passed.\n");
+	return 0;
+}
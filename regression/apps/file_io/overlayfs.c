@@ -0,0 +1,139 @@
+// SPDX-License-Identifier: MPL-2.0
+
+#define _GNU_SOURCE
+
+#include <errno.h>
+#include <fcntl.h>
+#include <stdio.h>
+#include <stdlib.h>
+#include <string.h>
+#include <unistd.h>
+#include <sys/mman.h>
+#include <sys/mount.h>
+#include <sys/stat.h>
+
+#define BASE_DIR "/tmp/overlayfs_test"
+#define LOWER_DIR BASE_DIR "/lower"
+#define UPPER_DIR BASE_DIR "/upper"
+#define WORK_DIR BASE_DIR "/work"
+#define MERGED_DIR BASE_DIR "/merged"
+#define OPTIONS \
+	"lowerdir=" LOWER_DIR ",upperdir=" UPPER_DIR ",workdir=" WORK_DIR
+
+#define CHECK(cond)                                                       \
+	do {                                                              \
+		if (!(cond)) {                                            \
+			fprintf(stderr, "%s:%d: check failed: %s (%s)\n", \
+				__FILE__, __LINE__, #cond,                \
+				strerror(errno));                         \
+			exit(1);                                          \
+		}                                                         \
+	} while (0)
+
+static void write_file(const char *path, const char *data)
+{
+	int fd;
+
+	fd = open(path, O_CREAT | O_WRONLY | O_TRUNC, 0644);
+	CHECK(fd >= 0);
+	CHECK(write(fd, data, strlen(data)) == (ssize_t)strlen(data));
+	CHECK(close(fd) == 0);
+}
+
+static void check_file(const char *path, const char *data)
+{
+	char buf[64] = { 0 };
+	int fd;
+
+	fd = open(path, O_RDONLY);
+	CHECK(fd >= 0);
+	CHECK(read(fd, buf, sizeof(buf) - 1) == (ssize_t)strlen(data));
+	CHECK(strcmp(buf, data) == 0);
+	CHECK(close(fd) == 0);
+}
+
+static void test_open_for_read(void)
+{
+	int fd;
+
+	write_file(LOWER_DIR "/read", "lower");
+
+	// Opening a file for reading does not copy it up.
+	fd = open(MERGED_DIR "/read", O_RDONLY);
+	CHECK(fd >= 0);
+	CHECK(close(fd) == 0);
+	CHECK(access(UPPER_DIR "/read", F_OK) == -1 && errno == ENOENT);
+	check_file(MERGED_DIR "/read", "lower");
+}
+
+static void test_open_for_write(void)
+{
+	int fd;
+
+	write_file(LOWER_DIR "/write", "lower");
+
+	// Opening a file for writing copies it up, even if nothing is written.
+	fd = open(MERGED_DIR "/write", O_WRONLY);
+	CHECK(fd >= 0);
+	check_file(UPPER_DIR "/write", "lower");
+	CHECK(pwrite(fd, "UPPER", 5, 0) == 5);
+	CHECK(close(fd) == 0);
+
+	check_file(MERGED_DIR "/write", "UPPER");
+	check_file(UPPER_DIR "/write", "UPPER");
+	check_file(LOWER_DIR "/write", "lower");
+}
+
+static void test_shared_mmap(void)
+{
+	char *addr;
+	int fd;
+
+	write_file(LOWER_DIR "/mmap", "lower");
+
+	// A writable shared mapping writes to the upper file, not the lower one.
+	fd = open(MERGED_DIR "/mmap", O_RDWR);
+	CHECK(fd >= 0);
+	addr = mmap(NULL, 4096, PROT_READ | PROT_WRITE, MAP_SHARED, fd, 0);
+	CHECK(addr != MAP_FAILED);
+	CHECK(memcmp(addr, "lower", 5) == 0);
+	memcpy(addr, "UPPER", 5);
+	CHECK(msync(addr, 4096, MS_SYNC) == 0);
+	CHECK(munmap(addr, 4096) == 0);
+	CHECK(close(fd) == 0);
+
+	check_file(MERGED_DIR "/mmap", "UPPER");
+	check_file(UPPER_DIR "/mmap", "UPPER");
+	check_file(LOWER_DIR "/mmap", "lower");
+
+	// A read-only file cannot be mapped as shared and writable.
+	fd = open(MERGED_DIR "/read", O_RDONLY);
+	CHECK(fd >= 0);
+	CHECK(mmap(NULL, 4096, PROT_READ | PROT_WRITE, MAP_SHARED, fd, 0) ==
+		      MAP_FAILED &&
+	      errno == EACCES);
+	CHECK(close(fd) == 0);
+	CHECK(access(UPPER_DIR "/read", F_OK) == -1 && errno == ENOENT);
+}
+
+int main(void)
+{
+	CHECK(mkdir(BASE_DIR, 0755) == 0 || errno == EEXIST);
+	CHECK(mount("none", BASE_DIR, "tmpfs", 0, NULL) == 0);
+	CHECK(mkdir(LOWER_DIR, 0755) == 0);
+	CHECK(mkdir(UPPER_DIR, 0755) == 0);
+	CHECK(mkdir(WORK_DIR, 0755) == 0);
+	CHECK(mkdir(MERGED_DIR, 0755) == 0);
+	CHECK(mount("overlay", MERGED_DIR, "overlay", 0, OPTIONS) == 0);
+
+	test_open_for_read();
+	test_open_for_write();
+	test_shared_mmap();
+
+	CHECK(umount(MERGED_DIR) == 0);
+	CHECK(umount(BASE_DIR) == 0);
+	CHECK(rmdir(BASE_DIR) == 0);
+
+	printf("Test passed.\n");
+	return 0;
+}
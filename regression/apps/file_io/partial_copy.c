@@ -0,0 +1,103 @@
+// SPDX-License-Identifier: MPL-2.0
+
+#define _GNU_SOURCE
+
+#include <errno.h>
+#include <fcntl.h>
+#include <stdio.h>
+#include <stdlib.h>
+#include <string.h>
+#include <sys/mman.h>
+#include <sys/uio.h>
+#include <unistd.h>
+
+#define CHECK(cond)                                                       \
+	do {                                                              \
+		if (!(cond)) {                                            \
+			fprintf(stderr, "%s:%d: check failed: %s (%s)\n", \
+				__FILE__, __LINE__, #cond,                \
+				strerror(errno));                         \
+			exit(1);                                          \
+		}                                                         \
+	} while (0)
+
+#define FILE_NAME "/tmp/partial_copy_test.txt"
+#define PAGE_SIZE 4096
+#define NR_VALID 100
+
+// Returns a buffer whose last `NR_VALID` bytes are followed by an unmapped page.
+static char *alloc_straddling_buffer(void)
+{
+	char *pages;
+
+	pages = mmap(NULL, 2 * PAGE_SIZE, PROT_READ | PROT_WRITE,
+		     MAP_PRIVATE | MAP_ANONYMOUS, -1, 0);
+	CHECK(pages != MAP_FAILED);
+	CHECK(munmap(pages + PAGE_SIZE, PAGE_SIZE) == 0);
+	memset(pages, 'a', PAGE_SIZE);
+	return pages + PAGE_SIZE - NR_VALID;
+}
+
+static void test_read_write(char *buf)
+{
+	int fd;
+	char data[2 * NR_VALID];
+
+	fd = open(FILE_NAME, O_RDWR | O_CREAT | O_TRUNC, 0644);
+	CHECK(fd >= 0);
+
+	// Only the bytes before the unmapped page are written.
+	CHECK(write(fd, buf, 2 * NR_VALID) == NR_VALID);
+	CHECK(pread(fd, data, sizeof(data), 0) == NR_VALID);
+	CHECK(data[0] == 'a' && data[NR_VALID - 1] == 'a');
+
+	// Only the bytes before the unmapped page are read, and the file offset
+	// only advances over them.
+	memset(data, 'b', sizeof(data));
+	CHECK(pwrite(fd, data, sizeof(data), 0) == sizeof(data));
+	CHECK(lseek(fd, 0, SEEK_SET) == 0);
+	CHECK(read(fd, buf, sizeof(data)) == NR_VALID);
+	CHECK(buf[0] == 'b' && buf[NR_VALID - 1] == 'b');
+	CHECK(lseek(fd, 0, SEEK_CUR) == NR_VALID);
+
+	// Nothing can be copied from or to an unmapped page.
+	CHECK(write(fd, buf + NR_VALID, 1) == -1 && errno == EFAULT);
+	CHECK(read(fd, buf + NR_VALID, 1) == -1 && errno == EFAULT);
+	CHECK(lseek(fd, 0, SEEK_CUR) == NR_VALID);
+
+	CHECK(close(fd) == 0);
+	CHECK(unlink(FILE_NAME) == 0);
+}
+
+static void test_writev(char *buf)
+{
+	int fd;
+	char data[2 * NR_VALID];
+	struct iovec iov[2] = {
+		{ .iov_base = data, .iov_len = NR_VALID },
+		{ .iov_base = buf, .iov_len = 2 * NR_VALID },
+	};
+
+	fd = open(FILE_NAME, O_RDWR | O_CREAT | O_TRUNC, 0644);
+	CHECK(fd >= 0);
+
+	// The bytes before the unmapped page in all the buffers are written.
+	memset(data, 'c', NR_VALID);
+	CHECK(writev(fd, iov, 2) == 2 * NR_VALID);
+	CHECK(pread(fd, data, sizeof(data), 0) == 2 * NR_VALID);
+	CHECK(data[0] == 'c' && data[NR_VALID] == buf[0]);
+
+	CHECK(close(fd) == 0);
+	CHECK(unlink(FILE_NAME) == 0);
+}
+
+int main(void)
+{
+	char *buf = alloc_straddling_buffer();
+
+	test_read_write(buf);
+	test_writev(buf);
+
+	printf("Test passed.\n");
+	return 0;
+}
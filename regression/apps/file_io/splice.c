@@ -0,0 +1,148 @@
+// SPDX-License-Identifier: MPL-2.0
+
+#define _GNU_SOURCE
+
+#include <errno.h>
+#include <fcntl.h>
+#include <stdio.h>
+#include <stdlib.h>
+#include <string.h>
+#include <unistd.h>
+#include <sys/uio.h>
+
+#define FILE_NAME "/tmp/splice_test"
+#define DATA_LEN 3000
+
+#define CHECK(cond)                                                       \
+	do {                                                              \
+		if (!(cond)) {                                            \
+			fprintf(stderr, "%s:%d: check failed: %s (%s)\n", \
+				__FILE__, __LINE__, #cond,                \
+				strerror(errno));                         \
+			exit(1);                                          \
+		}                                                         \
+	} while (0)
+
+static char data[DATA_LEN];
+static char buffer[DATA_LEN];
+
+static void test_vmsplice(int pipe_fds[2])
+{
+	struct iovec iov[2] = {
+		{ .iov_base = data, .iov_len = 1000 },
+		{ .iov_base = data + 1000, .iov_len = DATA_LEN - 1000 },
+	};
+
+	CHECK(vmsplice(pipe_fds[1], iov, 2, SPLICE_F_GIFT) == DATA_LEN);
+
+	iov[0].iov_base = buffer;
+	iov[1].iov_base = buffer + 1000;
+	CHECK(vmsplice(pipe_fds[0], iov, 2, 0) == DATA_LEN);
+	CHECK(memcmp(buffer, data, DATA_LEN) == 0);
+
+	// The read end is empty now.
+	CHECK(vmsplice(pipe_fds[0], iov, 2, SPLICE_F_NONBLOCK) == -1 &&
+	      errno == EAGAIN);
+}
+
+static void test_tee(int pipe_fds[2], int other_fds[2])
+{
+	CHECK(write(pipe_fds[1], data, DATA_LEN) == DATA_LEN);
+
+	// The data are copied to the other pipe and remain in the pipe.
+	CHECK(tee(pipe_fds[0], other_fds[1], DATA_LEN, 0) == DATA_LEN);
+	CHECK(read(other_fds[0], buffer, DATA_LEN) == DATA_LEN);
+	CHECK(memcmp(buffer, data, DATA_LEN) == 0);
+
+	// The data are moved to the other pipe.
+	CHECK(splice(pipe_fds[0], NULL, other_fds[1], NULL, DATA_LEN, 0) ==
+	      DATA_LEN);
+	CHECK(read(other_fds[0], buffer, DATA_LEN) == DATA_LEN);
+	CHECK(memcmp(buffer, data, DATA_LEN) == 0);
+
+	CHECK(tee(pipe_fds[0], pipe_fds[1], DATA_LEN, 0) == -1 &&
+	      errno == EINVAL);
+	CHECK(tee(pipe_fds[0], other_fds[1], DATA_LEN, SPLICE_F_NONBLOCK) ==
+		      -1 &&
+	      errno == EAGAIN);
+}
+
+static void test_splice_file(int pipe_fds[2])
+{
+	int fd;
+	loff_t offset;
+
+	fd = open(FILE_NAME, O_CREAT | O_RDWR | O_TRUNC, 0644);
+	CHECK(fd >= 0);
+
+	// Pipe to file, at the given offset.
+	CHECK(write(pipe_fds[1], data, DATA_LEN) == DATA_LEN);
+	offset = 100;
+	CHECK(splice(pipe_fds[0], NULL, fd, &offset, DATA_LEN, SPLICE_F_MOVE) ==
+	      DATA_LEN);
+	CHECK(offset == 100 + DATA_LEN);
+	CHECK(lseek(fd, 0, SEEK_CUR) == 0);
+	CHECK(pread(fd, buffer, DATA_LEN, 100) == DATA_LEN);
+	CHECK(memcmp(buffer, data, DATA_LEN) == 0);
+
+	// File to pipe, at the file offset.
+	CHECK(lseek(fd, 100, SEEK_SET) == 100);
+	CHECK(splice(fd, NULL, pipe_fds[1], NULL, DATA_LEN, SPLICE_F_MORE) ==
+	      DATA_LEN);
+	CHECK(lseek(fd, 0, SEEK_CUR) == 100 + DATA_LEN);
+	CHECK(read(pipe_fds[0], buffer, DATA_LEN) == DATA_LEN);
+	CHECK(memcmp(buffer, data, DATA_LEN) == 0);
+
+	// Splicing at the end of the file splices nothing.
+	CHECK(splice(fd, NULL, pipe_fds[1], NULL, DATA_LEN, 0) == 0);
+
+	CHECK(splice(fd, NULL, fd, NULL, DATA_LEN, 0) == -1 &&
+	      errno == EINVAL);
+	offset = 0;
+	CHECK(splice(pipe_fds[0], &offset, fd, NULL, DATA_LEN, 0) == -1 &&
+	      errno == ESPIPE);
+	CHECK(splice(fd, NULL, pipe_fds[1], NULL, DATA_LEN, 0x100) == -1 &&
+	      errno == EINVAL);
+
+	CHECK(close(fd) == 0);
+	CHECK(unlink(FILE_NAME) == 0);
+}
+
+static void test_closed_write_end(void)
+{
+	int pipe_fds[2], other_fds[2];
+
+	CHECK(pipe(pipe_fds) == 0);
+	CHECK(pipe(other_fds) == 0);
+
+	CHECK(write(pipe_fds[1], data, 100) == 100);
+	CHECK(close(pipe_fds[1]) == 0);
+	CHECK(splice(pipe_fds[0], NULL, other_fds[1], NULL, DATA_LEN, 0) ==
+	      100);
+	// The end of the pipe is reached.
+	CHECK(splice(pipe_fds[0], NULL, other_fds[1], NULL, DATA_LEN, 0) == 0);
+
+	CHECK(close(pipe_fds[0]) == 0);
+	CHECK(close(other_fds[0]) == 0);
+	CHECK(close(other_fds[1]) == 0);
+}
+
+int main(void)
+{
+	int pipe_fds[2], other_fds[2];
+
+	for (int i = 0; i < DATA_LEN; i++) {
+		data[i] = i * 7 + 3;
+	}
+
+	CHECK(pipe(pipe_fds) == 0);
+	CHECK(pipe(other_fds) == 0);
+
+	test_vmsplice(pipe_fds);
+	test_tee(pipe_fds, other_fds);
+	test_splice_file(pipe_fds);
+	test_closed_write_end();
+
+	printf("Test passed.\n");
+	return 0;
+}
@@ -0,0 +1,103 @@
+// SPDX-License-Identifier: MPL-2.0
+
+#define _GNU_SOURCE
+
+#include <errno.h>
+#include <fcntl.h>
+#include <stdio.h>
+#include <stdlib.h>
+#include <string.h>
+#include <unistd.h>
+#include <sys/mount.h>
+#include <sys/stat.h>
+
+#define MOUNT_DIR "/tmp/umount_test"
+#define SUB_DIR MOUNT_DIR "/sub"
+#define FILE_NAME MOUNT_DIR "/file"
+
+#define CHECK(cond)                                                       \
+	do {                                                              \
+		if (!(cond)) {                                            \
+			fprintf(stderr, "%s:%d: check failed: %s (%s)\n", \
+				__FILE__, __LINE__, #cond,                \
+				strerror(errno));                         \
+			exit(1);                                          \
+		}                                                         \
+	} while (0)
+
+static void mount_tmpfs(const char *dir)
+{
+	CHECK(mount("none", dir, "tmpfs", 0, NULL) == 0);
+}
+
+static void test_open_file(void)
+{
+	int fd;
+
+	mount_tmpfs(MOUNT_DIR);
+	fd = open(FILE_NAME, O_CREAT | O_RDWR, 0644);
+	CHECK(fd >= 0);
+	CHECK(umount(MOUNT_DIR) == -1 && errno == EBUSY);
+	CHECK(close(fd) == 0);
+	CHECK(umount(MOUNT_DIR) == 0);
+	CHECK(access(FILE_NAME, F_OK) == -1 && errno == ENOENT);
+}
+
+static void test_cwd(void)
+{
+	mount_tmpfs(MOUNT_DIR);
+	CHECK(chdir(MOUNT_DIR) == 0);
+	CHECK(umount(MOUNT_DIR) == -1 && errno == EBUSY);
+	CHECK(chdir("/") == 0);
+	CHECK(umount(MOUNT_DIR) == 0);
+}
+
+static void test_submount(void)
+{
+	mount_tmpfs(MOUNT_DIR);
+	CHECK(mkdir(SUB_DIR, 0755) == 0);
+	mount_tmpfs(SUB_DIR);
+	CHECK(umount(MOUNT_DIR) == -1 && errno == EBUSY);
+	CHECK(umount(SUB_DIR) == 0);
+	CHECK(umount(MOUNT_DIR) == 0);
+}
+
+static void test_lazy_umount(void)
+{
+	char buf[6] = { 0 };
+	int fd;
+
+	mount_tmpfs(MOUNT_DIR);
+	CHECK(mkdir(SUB_DIR, 0755) == 0);
+	mount_tmpfs(SUB_DIR);
+	fd = open(FILE_NAME, O_CREAT | O_RDWR, 0644);
+	CHECK(fd >= 0);
+
+	// The busy mount is detached along with its submount.
+	CHECK(umount2(MOUNT_DIR, MNT_DETACH) == 0);
+	CHECK(access(FILE_NAME, F_OK) == -1 && errno == ENOENT);
+	CHECK(access(SUB_DIR, F_OK) == -1 && errno == ENOENT);
+
+	// The open file is still usable.
+	CHECK(write(fd, "hello", 5) == 5);
+	CHECK(pread(fd, buf, 5, 0) == 5);
+	CHECK(strcmp(buf, "hello") == 0);
+	CHECK(close(fd) == 0);
+}
+
+int main(void)
+{
+	CHECK(mkdir(MOUNT_DIR, 0755) == 0 || errno == EEXIST);
+
+	test_open_file();
+	test_cwd();
+	test_submount();
+	test_lazy_umount();
+
+	CHECK(umount("/") == -1);
+	CHECK(umount(MOUNT_DIR) == -1 && errno == EINVAL);
+	CHECK(rmdir(MOUNT_DIR) == 0);
+
+	printf("Test passed.\n");
+	return 0;
+}
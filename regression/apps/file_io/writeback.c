@@ -0,0 +1,133 @@
+// SPDX-License-Identifier: MPL-2.0
+
+#define _GNU_SOURCE
+
+#include <errno.h>
+#include <fcntl.h>
+#include <stdio.h>
+#include <stdlib.h>
+#include <string.h>
+#include <unistd.h>
+
+#define FILE_NAME "/ext2/writeback_test.txt"
+#define PAGE_SIZE 4096
+#define NR_PAGES 64
+
+#define CHECK(cond)                                                       \
+	do {                                                              \
+		if (!(cond)) {                                            \
+			fprintf(stderr, "%s:%d: check failed: %s (%s)\n", \
+				__FILE__, __LINE__, #cond,                \
+				strerror(errno));                         \
+			exit(1);                                          \
+		}                                                         \
+	} while (0)
+
+static char buffer[PAGE_SIZE];
+
+static long read_tunable(const char *name)
+{
+	char path[128], buf[32] = { 0 };
+	int fd;
+
+	snprintf(path, sizeof(path), "/proc/sys/vm/%s", name);
+	fd = open(path, O_RDONLY);
+	CHECK(fd >= 0);
+	CHECK(read(fd, buf, sizeof(buf) - 1) > 0);
+	CHECK(close(fd) == 0);
+	return strtol(buf, NULL, 10);
+}
+
+static int write_tunable(const char *name, long value)
+{
+	char path[128], buf[32];
+	int fd, ret;
+
+	snprintf(path, sizeof(path), "/proc/sys/vm/%s", name);
+	snprintf(buf, sizeof(buf), "%ld\n", value);
+	fd = open(path, O_WRONLY);
+	CHECK(fd >= 0);
+	ret = write(fd, buf, strlen(buf));
+	CHECK(close(fd) == 0);
+	return ret < 0 ? -1 : 0;
+}
+
+static long dirty_kb(void)
+{
+	char line[128];
+	long kb = -1;
+	FILE *file = fopen("/proc/meminfo", "r");
+
+	CHECK(file != NULL);
+	while (fgets(line, sizeof(line), file) != NULL) {
+		if (sscanf(line, "Dirty: %ld kB", &kb) == 1) {
+			break;
+		}
+	}
+	CHECK(fclose(file) == 0);
+	CHECK(kb >= 0);
+	return kb;
+}
+
+static void test_tunables(void)
+{
+	CHECK(read_tunable("dirty_writeback_centisecs") == 500);
+	CHECK(read_tunable("dirty_expire_centisecs") == 3000);
+	CHECK(read_tunable("dirty_background_ratio") == 10);
+	CHECK(read_tunable("read_ahead_kb") == 128);
+
+	CHECK(write_tunable("dirty_background_ratio", 20) == 0);
+	CHECK(read_tunable("dirty_background_ratio") == 20);
+	errno = 0;
+	CHECK(write_tunable("dirty_background_ratio", 101) == -1 &&
+	      errno == EINVAL);
+	CHECK(read_tunable("dirty_background_ratio") == 20);
+	CHECK(write_tunable("dirty_background_ratio", 10) == 0);
+}
+
+static void test_periodic_writeback(void)
+{
+	int fd, i;
+
+	// Write back the pages as soon as they are dirty.
+	CHECK(write_tunable("dirty_expire_centisecs", 0) == 0);
+	CHECK(write_tunable("dirty_writeback_centisecs", 10) == 0);
+
+	fd = open(FILE_NAME, O_RDWR | O_CREAT | O_TRUNC, 0644);
+	CHECK(fd >= 0);
+	for (i = 0; i < NR_PAGES; i++) {
+		memset(buffer, 'a' + i % 26, PAGE_SIZE);
+		CHECK(write(fd, buffer, PAGE_SIZE) == PAGE_SIZE);
+	}
+
+	// The pages are written back by the flusher without `fsync`.
+	for (i = 0; i < 50 && dirty_kb() >= NR_PAGES * PAGE_SIZE / 1024; i++) {
+		usleep(100 * 1000);
+	}
+	CHECK(dirty_kb() < NR_PAGES * PAGE_SIZE / 1024);
+
+	CHECK(write_tunable("dirty_writeback_centisecs", 500) == 0);
+	CHECK(write_tunable("dirty_expire_centisecs", 3000) == 0);
+
+	// The data can be read without read-ahead.
+	CHECK(write_tunable("read_ahead_kb", 0) == 0);
+	for (i = 0; i < NR_PAGES; i++) {
+		CHECK(pread(fd, buffer, PAGE_SIZE, (off_t)i * PAGE_SIZE) ==
+		      PAGE_SIZE);
+		CHECK(buffer[0] == 'a' + i % 26 &&
+		      buffer[PAGE_SIZE - 1] == 'a' + i % 26);
+	}
+	CHECK(write_tunable("read_ahead_kb", 128) == 0);
+
+	CHECK(close(fd) == 0);
+	CHECK(unlink(FILE_NAME) == 0);
+}
+
+int main(void)
+{
+	test_tunables();
+	test_periodic_writeback();
+
+	printf("All writeback tests passed.\n");
+	return 0;
+}
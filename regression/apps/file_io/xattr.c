@@ -0,0 +1,238 @@
+// SPDX-License-Identifier: MPL-2.0
+
+#define _GNU_SOURCE
+
+#include <errno.h>
+#include <fcntl.h>
+#include <stdint.h>
+#include <stdio.h>
+#include <stdlib.h>
+#include <string.h>
+#include <unistd.h>
+#include <sys/stat.h>
+#include <sys/xattr.h>
+
+#define CHECK(cond)                                                       \
+	do {                                                              \
+		if (!(cond)) {                                            \
+			fprintf(stderr, "%s:%d: check failed: %s (%s)\n", \
+				__FILE__, __LINE__, #cond,                \
+				strerror(errno));                         \
+			exit(1);                                          \
+		}                                                         \
+	} while (0)
+
+#define ACL_ACCESS "system.posix_acl_access"
+#define ACL_DEFAULT "system.posix_acl_default"
+#define ACL_VERSION 2
+
+#define ACL_USER_OBJ 0x01
+#define ACL_USER 0x02
+#define ACL_GROUP_OBJ 0x04
+#define ACL_MASK 0x10
+#define ACL_OTHER 0x20
+#define ACL_UNDEFINED_ID ((uint32_t)-1)
+
+struct acl_entry {
+	uint16_t tag;
+	uint16_t perm;
+	uint32_t id;
+};
+
+struct acl {
+	uint32_t version;
+	struct acl_entry entries[5];
+};
+
+static mode_t file_mode(const char *path)
+{
+	struct stat st;
+
+	CHECK(stat(path, &st) == 0);
+	return st.st_mode & 07777;
+}
+
+static void test_user_xattrs(const char *path)
+{
+	char value[16];
+	char list[64];
+	ssize_t len;
+
+	CHECK(setxattr(path, "user.mime_type", "text", 4, XATTR_CREATE) == 0);
+	CHECK(setxattr(path, "user.mime_type", "text", 4, XATTR_CREATE) < 0 &&
+	      errno == EEXIST);
+	CHECK(setxattr(path, "user.author", "me", 2, XATTR_REPLACE) < 0 &&
+	      errno == ENODATA);
+	CHECK(setxattr(path, "user.author", "me", 2, 0) == 0);
+	CHECK(setxattr(path, "user.empty", "", 0, 0) == 0);
+	CHECK(setxattr(path, "unknown.name", "", 0, 0) < 0 &&
+	      errno == EOPNOTSUPP);
+	CHECK(setxattr(path, "system.unknown", "", 0, 0) < 0 &&
+	      errno == EOPNOTSUPP);
+
+	CHECK(getxattr(path, "user.mime_type", NULL, 0) == 4);
+	CHECK(getxattr(path, "user.mime_type", value, 2) < 0 &&
+	      errno == ERANGE);
+	CHECK(getxattr(path, "user.mime_type", value, sizeof(value)) == 4);
+	CHECK(memcmp(value, "text", 4) == 0);
+	CHECK(getxattr(path, "user.empty", value, sizeof(value)) == 0);
+	CHECK(getxattr(path, "user.none", value, sizeof(value)) < 0 &&
+	      errno == ENODATA);
+
+	CHECK(setxattr(path, "user.mime_type", "text/plain", 10,
+		       XATTR_REPLACE) == 0);
+	CHECK(getxattr(path, "user.mime_type", value, sizeof(value)) == 10);
+	CHECK(memcmp(value, "text/plain", 10) == 0);
+
+	len = listxattr(path, NULL, 0);
+	CHECK(len == sizeof("user.author") + sizeof("user.empty") +
+			     sizeof("user.mime_type"));
+	CHECK(listxattr(path, list, 1) < 0 && errno == ERANGE);
+	CHECK(listxattr(path, list, sizeof(list)) == len);
+	CHECK(memmem(list, len, "user.author", sizeof("user.author")) != NULL);
+	CHECK(memmem(list, len, "user.mime_type", sizeof("user.mime_type")) !=
+	      NULL);
+
+	CHECK(removexattr(path, "user.mime_type") == 0);
+	CHECK(removexattr(path, "user.mime_type") < 0 && errno == ENODATA);
+	CHECK(getxattr(path, "user.mime_type", value, sizeof(value)) < 0 &&
+	      errno == ENODATA);
+	CHECK(removexattr(path, "user.author") == 0);
+	CHECK(removexattr(path, "user.empty") == 0);
+	CHECK(listxattr(path, list, sizeof(list)) == 0);
+}
+
+static void test_access_acl(const char *path)
+{
+	struct acl acl = {
+		.version = ACL_VERSION,
+		.entries = {
+			{ ACL_USER_OBJ, 6, ACL_UNDEFINED_ID },
+			{ ACL_USER, 4, 1000 },
+			{ ACL_GROUP_OBJ, 4, ACL_UNDEFINED_ID },
+			{ ACL_MASK, 6, ACL_UNDEFINED_ID },
+			{ ACL_OTHER, 0, ACL_UNDEFINED_ID },
+		},
+	};
+	struct acl read_acl;
+
+	CHECK(chmod(path, 0644) == 0);
+	// A named user entry requires a mask entry.
+	acl.entries[3].tag = ACL_OTHER;
+	CHECK(setxattr(path, ACL_ACCESS, &acl, sizeof(acl) - 8, 0) < 0 &&
+	      errno == EINVAL);
+	acl.entries[3].tag = ACL_MASK;
+
+	// The group class of the mode is the mask.
+	CHECK(setxattr(path, ACL_ACCESS, &acl, sizeof(acl), 0) == 0);
+	CHECK(file_mode(path) == 0660);
+	CHECK(getxattr(path, ACL_ACCESS, &read_acl, sizeof(read_acl)) ==
+	      sizeof(acl));
+	CHECK(memcmp(&read_acl, &acl, sizeof(acl)) == 0);
+
+	// Changing the mode changes the mask.
+	CHECK(chmod(path, 0640) == 0);
+	CHECK(getxattr(path, ACL_ACCESS, &read_acl, sizeof(read_acl)) ==
+	      sizeof(acl));
+	CHECK(read_acl.entries[3].tag == ACL_MASK &&
+	      read_acl.entries[3].perm == 4);
+	CHECK(read_acl.entries[2].tag == ACL_GROUP_OBJ &&
+	      read_acl.entries[2].perm == 4);
+
+	// An ACL equivalent to the mode is not stored.
+	acl.entries[1] = (struct acl_entry){ ACL_GROUP_OBJ, 0,
+					     ACL_UNDEFINED_ID };
+	acl.entries[2] = (struct acl_entry){ ACL_OTHER, 4, ACL_UNDEFINED_ID };
+	CHECK(setxattr(path, ACL_ACCESS, &acl, 4 + 3 * 8, 0) == 0);
+	CHECK(file_mode(path) == 0604);
+	CHECK(getxattr(path, ACL_ACCESS, NULL, 0) < 0 && errno == ENODATA);
+
+	// Only directories have default ACLs.
+	CHECK(setxattr(path, ACL_DEFAULT, &acl, 4 + 3 * 8, 0) < 0 &&
+	      errno == EACCES);
+}
+
+static void test_default_acl(const char *dir)
+{
+	struct acl acl = {
+		.version = ACL_VERSION,
+		.entries = {
+			{ ACL_USER_OBJ, 7, ACL_UNDEFINED_ID },
+			{ ACL_USER, 5, 1000 },
+			{ ACL_GROUP_OBJ, 5, ACL_UNDEFINED_ID },
+			{ ACL_MASK, 7, ACL_UNDEFINED_ID },
+			{ ACL_OTHER, 0, ACL_UNDEFINED_ID },
+		},
+	};
+	struct acl read_acl;
+	char path[64];
+	int fd;
+
+	CHECK(mkdir(dir, 0755) == 0);
+	CHECK(setxattr(dir, ACL_DEFAULT, &acl, sizeof(acl), 0) == 0);
+	CHECK(file_mode(dir) == 0755);
+
+	// The new file gets the default ACL restricted by its mode.
+	snprintf(path, sizeof(path), "%s/file", dir);
+	fd = open(path, O_CREAT | O_WRONLY, 0666);
+	CHECK(fd >= 0);
+	CHECK(close(fd) == 0);
+	CHECK(file_mode(path) == 0660);
+	CHECK(getxattr(path, ACL_ACCESS, &read_acl, sizeof(read_acl)) ==
+	      sizeof(acl));
+	CHECK(read_acl.entries[1].tag == ACL_USER &&
+	      read_acl.entries[1].perm == 5 && read_acl.entries[1].id == 1000);
+	CHECK(read_acl.entries[3].tag == ACL_MASK &&
+	      read_acl.entries[3].perm == 6);
+	CHECK(getxattr(path, ACL_DEFAULT, NULL, 0) < 0 && errno == ENODATA);
+	CHECK(unlink(path) == 0);
+
+	// The new directory also inherits the default ACL.
+	snprintf(path, sizeof(path), "%s/dir", dir);
+	CHECK(mkdir(path, 0777) == 0);
+	CHECK(file_mode(path) == 0770);
+	CHECK(getxattr(path, ACL_DEFAULT, &read_acl, sizeof(read_acl)) ==
+	      sizeof(acl));
+	CHECK(memcmp(&read_acl, &acl, sizeof(acl)) == 0);
+	CHECK(rmdir(path) == 0);
+
+	// Setting an empty default ACL removes it.
+	CHECK(setxattr(dir, ACL_DEFAULT, "", 0, 0) == 0);
+	CHECK(getxattr(dir, ACL_DEFAULT, NULL, 0) < 0 && errno == ENODATA);
+	CHECK(rmdir(dir) == 0);
+}
+
+static void test_fs(const char *base)
+{
+	char path[64];
+	int fd;
+
+	snprintf(path, sizeof(path), "%s/xattr_test", base);
+	fd = open(path, O_CREAT | O_RDWR | O_TRUNC, 0644);
+	CHECK(fd >= 0);
+
+	test_user_xattrs(path);
+	CHECK(fsetxattr(fd, "user.fd", "1", 1, 0) == 0);
+	CHECK(fgetxattr(fd, "user.fd", NULL, 0) == 1);
+	CHECK(flistxattr(fd, NULL, 0) == sizeof("user.fd"));
+	CHECK(fremovexattr(fd, "user.fd") == 0);
+
+	test_access_acl(path);
+	CHECK(close(fd) == 0);
+	CHECK(unlink(path) == 0);
+
+	snprintf(path, sizeof(path), "%s/xattr_dir", base);
+	test_default_acl(path);
+}
+
+int main(void)
+{
+	// The inherited ACLs are restricted by the mode without the umask.
+	umask(0);
+
+	test_fs("/ext2");
+	test_fs("/tmp");
+
+	printf("Test passed.\n");
+	return 0;
+}
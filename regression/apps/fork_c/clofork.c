@@ -0,0 +1,83 @@
+// SPDX-License-Identifier: MPL-2.0
+
+#define _GNU_SOURCE
+
+#include <errno.h>
+#include <fcntl.h>
+#include <stdio.h>
+#include <stdlib.h>
+#include <sys/socket.h>
+#include <sys/wait.h>
+#include <unistd.h>
+
+#ifndef O_CLOFORK
+#define O_CLOFORK 040000000
+#endif
+#ifndef SOCK_CLOFORK
+#define SOCK_CLOFORK O_CLOFORK
+#endif
+#ifndef FD_CLOFORK
+#define FD_CLOFORK 2
+#endif
+
+#define CHECK(cond)                                                       \
+	do {                                                              \
+		if (!(cond)) {                                            \
+			fprintf(stderr, "%s:%d: `%s` failed (errno = %d)\n", \
+				__FILE__, __LINE__, #cond, errno);        \
+			exit(EXIT_FAILURE);                               \
+		}                                                         \
+	} while (0)
+
+static int is_open(int fd)
+{
+	return fcntl(fd, F_GETFD) >= 0 || errno != EBADF;
+}
+
+int main()
+{
+	int pipe_fds[2], sock_fd, dup_fd, setfd_fd, inherited_fd, status;
+	pid_t pid;
+
+	CHECK(pipe2(pipe_fds, O_CLOFORK | O_CLOEXEC) == 0);
+	CHECK(fcntl(pipe_fds[0], F_GETFD) == (FD_CLOFORK | FD_CLOEXEC));
+
+	sock_fd = socket(AF_INET, SOCK_STREAM | SOCK_CLOFORK, 0);
+	CHECK(sock_fd >= 0);
+	CHECK(fcntl(sock_fd, F_GETFD) == FD_CLOFORK);
+
+	dup_fd = dup3(pipe_fds[1], 100, O_CLOFORK);
+	CHECK(dup_fd == 100);
+	CHECK(fcntl(dup_fd, F_GETFD) == FD_CLOFORK);
+
+	setfd_fd = dup(pipe_fds[1]);
+	CHECK(setfd_fd >= 0 && fcntl(setfd_fd, F_GETFD) == 0);
+	CHECK(fcntl(setfd_fd, F_SETFD, FD_CLOFORK) == 0);
+	CHECK(fcntl(setfd_fd, F_GETFD) == FD_CLOFORK);
+
+	inherited_fd = dup(pipe_fds[1]);
+	CHECK(inherited_fd >= 0);
+
+	pid = fork();
+	CHECK(pid >= 0);
+	if (pid == 0) {
+		// The file descriptors marked as close-on-fork must be closed
+		// in the child, while the others must be inherited.
+		CHECK(!is_open(pipe_fds[0]));
+		CHECK(!is_open(pipe_fds[1]));
+		CHECK(!is_open(sock_fd));
+		CHECK(!is_open(dup_fd));
+		CHECK(!is_open(setfd_fd));
+		CHECK(is_open(inherited_fd));
+		exit(EXIT_SUCCESS);
+	}
+
+	CHECK(waitpid(pid, &status, 0) == pid);
+	CHECK(WIFEXITED(status) && WEXITSTATUS(status) == 0);
+
+	// The file descriptors are still open in the parent.
+	CHECK(is_open(pipe_fds[0]) && is_open(sock_fd) && is_open(dup_fd));
+
+	printf("Test close-on-fork passed\n");
+	return 0;
+}
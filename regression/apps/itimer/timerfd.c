@@ -0,0 +1,128 @@
+// SPDX-License-Identifier: MPL-2.0
+
+#define _GNU_SOURCE
+#include <errno.h>
+#include <poll.h>
+#include <stdint.h>
+#include <stdio.h>
+#include <stdlib.h>
+#include <string.h>
+#include <sys/timerfd.h>
+#include <time.h>
+#include <unistd.h>
+
+#define CHECK(cond)                                                 \
+	do {                                                        \
+		if (!(cond)) {                                      \
+			fprintf(stderr, "%s:%d: `%s` fails: %s\n",  \
+				__FILE__, __LINE__, #cond,          \
+				strerror(errno));                   \
+			exit(EXIT_FAILURE);                         \
+		}                                                   \
+	} while (0)
+
+#define MS_TO_NS(ms) ((ms) * 1000000L)
+
+static void test_invalid_args(void)
+{
+	struct itimerspec its = { .it_value = { .tv_nsec = 1000000000 } };
+	int fd;
+
+	CHECK(timerfd_create(CLOCK_PROCESS_CPUTIME_ID, 0) == -1 &&
+	      errno == EINVAL);
+	CHECK(timerfd_create(CLOCK_MONOTONIC, 0x1234) == -1 && errno == EINVAL);
+
+	fd = timerfd_create(CLOCK_MONOTONIC, 0);
+	CHECK(fd >= 0);
+	CHECK(timerfd_settime(fd, 0, &its, NULL) == -1 && errno == EINVAL);
+	CHECK(close(fd) == 0);
+
+	CHECK(timerfd_settime(STDOUT_FILENO, 0, &its, NULL) == -1 &&
+	      errno == EINVAL);
+}
+
+static void test_periodic_timer(void)
+{
+	struct itimerspec its = {
+		.it_value = { .tv_nsec = MS_TO_NS(50) },
+		.it_interval = { .tv_nsec = MS_TO_NS(20) },
+	};
+	struct pollfd pfd;
+	uint64_t expirations;
+	int fd;
+
+	fd = timerfd_create(CLOCK_MONOTONIC, TFD_NONBLOCK | TFD_CLOEXEC);
+	CHECK(fd >= 0);
+
+	// The timer is not armed.
+	CHECK(read(fd, &expirations, sizeof(expirations)) == -1 &&
+	      errno == EAGAIN);
+
+	CHECK(timerfd_settime(fd, 0, &its, NULL) == 0);
+	memset(&its, 0, sizeof(its));
+	CHECK(timerfd_gettime(fd, &its) == 0);
+	CHECK(its.it_interval.tv_sec == 0 &&
+	      its.it_interval.tv_nsec == MS_TO_NS(20));
+	CHECK(its.it_value.tv_sec == 0 && its.it_value.tv_nsec > 0 &&
+	      its.it_value.tv_nsec <= MS_TO_NS(50));
+
+	// The file becomes readable once the timer expires.
+	pfd.fd = fd;
+	pfd.events = POLLIN;
+	CHECK(poll(&pfd, 1, 0) == 0);
+	CHECK(poll(&pfd, 1, 1000) == 1 && (pfd.revents & POLLIN));
+
+	// The expirations are counted until they are read.
+	usleep(100000);
+	CHECK(read(fd, &expirations, sizeof(expirations)) ==
+	      sizeof(expirations));
+	CHECK(expirations >= 2);
+	CHECK(read(fd, &expirations, sizeof(expirations) - 1) == -1 &&
+	      errno == EINVAL);
+
+	// The timer is disarmed.
+	memset(&its, 0, sizeof(its));
+	CHECK(timerfd_settime(fd, 0, &its, NULL) == 0);
+	CHECK(timerfd_gettime(fd, &its) == 0);
+	CHECK(its.it_value.tv_sec == 0 && its.it_value.tv_nsec == 0);
+	usleep(50000);
+	CHECK(read(fd, &expirations, sizeof(expirations)) == -1 &&
+	      errno == EAGAIN);
+
+	CHECK(close(fd) == 0);
+}
+
+static void test_absolute_timer(void)
+{
+	struct itimerspec its = { 0 };
+	uint64_t expirations;
+	int fd;
+
+	fd = timerfd_create(CLOCK_REALTIME, 0);
+	CHECK(fd >= 0);
+
+	CHECK(clock_gettime(CLOCK_REALTIME, &its.it_value) == 0);
+	its.it_value.tv_nsec += MS_TO_NS(50);
+	if (its.it_value.tv_nsec >= 1000000000) {
+		its.it_value.tv_sec += 1;
+		its.it_value.tv_nsec -= 1000000000;
+	}
+	CHECK(timerfd_settime(fd, TFD_TIMER_ABSTIME, &its, NULL) == 0);
+
+	// The read blocks until the timer expires.
+	CHECK(read(fd, &expirations, sizeof(expirations)) ==
+	      sizeof(expirations));
+	CHECK(expirations == 1);
+
+	CHECK(close(fd) == 0);
+}
+
+int main(void)
+{
+	test_invalid_args();
+	test_periodic_timer();
+	test_absolute_timer();
+
+	printf("timerfd test passed\n");
+	return 0;
+}
@@ -0,0 +1,121 @@
+// SPDX-License-Identifier: MPL-2.0
+
+#define _GNU_SOURCE
+
+#include <errno.h>
+#include <fcntl.h>
+#include <signal.h>
+#include <stdio.h>
+#include <stdlib.h>
+#include <string.h>
+#include <time.h>
+#include <unistd.h>
+#include <sys/time.h>
+
+#define VIRTUAL_TIME_FILE "/proc/sys/kernel/virtual_time_ns"
+#define NSEC_PER_SEC 1000000000L
+#define NSEC_PER_MSEC 1000000L
+
+#define CHECK(cond)                                                       \
+	do {                                                              \
+		if (!(cond)) {                                            \
+			fprintf(stderr, "%s:%d: check failed: %s (%s)\n", \
+				__FILE__, __LINE__, #cond,                \
+				strerror(errno));                         \
+			exit(1);                                          \
+		}                                                         \
+	} while (0)
+
+static volatile int nr_alarms;
+
+static void alarm_handler(int sig)
+{
+	nr_alarms++;
+}
+
+static long monotonic_nanos(void)
+{
+	struct timespec ts;
+
+	CHECK(clock_gettime(CLOCK_MONOTONIC, &ts) == 0);
+	return ts.tv_sec * NSEC_PER_SEC + ts.tv_nsec;
+}
+
+static int write_virtual_time(const char *buf)
+{
+	int fd, ret;
+
+	fd = open(VIRTUAL_TIME_FILE, O_WRONLY);
+	CHECK(fd >= 0);
+	ret = write(fd, buf, strlen(buf));
+	CHECK(close(fd) == 0);
+	return ret;
+}
+
+static void advance(long nanos)
+{
+	char buf[32];
+
+	snprintf(buf, sizeof(buf), "%ld\n", nanos);
+	CHECK(write_virtual_time(buf) == strlen(buf));
+}
+
+static void test_frozen_clock(void)
+{
+	long start;
+	volatile long i;
+
+	start = monotonic_nanos();
+	for (i = 0; i < 10000000; i++)
+		;
+	// The time does not advance by itself.
+	CHECK(monotonic_nanos() == start);
+
+	advance(NSEC_PER_SEC + 1);
+	CHECK(monotonic_nanos() == start + NSEC_PER_SEC + 1);
+
+	errno = 0;
+	CHECK(write_virtual_time("invalid") == -1 && errno == EINVAL);
+}
+
+static void test_timer_expiry(void)
+{
+	struct itimerval itv = { 0 };
+
+	CHECK(signal(SIGALRM, alarm_handler) != SIG_ERR);
+	nr_alarms = 0;
+
+	itv.it_value.tv_usec = 500000;
+	CHECK(setitimer(ITIMER_REAL, &itv, NULL) == 0);
+	advance(499 * NSEC_PER_MSEC);
+	CHECK(nr_alarms == 0);
+	advance(NSEC_PER_MSEC);
+	CHECK(nr_alarms == 1);
+
+	// The periodic timer fires at 300ms, 600ms and 900ms, each exactly when the time
+	// reaches its expiry, so the next expiry is at 1200ms.
+	itv.it_interval.tv_usec = 300000;
+	itv.it_value.tv_usec = 300000;
+	CHECK(setitimer(ITIMER_REAL, &itv, NULL) == 0);
+	advance(NSEC_PER_SEC);
+	CHECK(nr_alarms == 2);
+	CHECK(getitimer(ITIMER_REAL, &itv) == 0);
+	CHECK(itv.it_value.tv_sec == 0 && itv.it_value.tv_usec == 200000);
+
+	itv.it_value.tv_usec = 0;
+	CHECK(setitimer(ITIMER_REAL, &itv, NULL) == 0);
+}
+
+int main(void)
+{
+	if (access(VIRTUAL_TIME_FILE, F_OK) != 0) {
+		printf("The virtual time mode is not enabled, skipped.\n");
+		return 0;
+	}
+
+	test_frozen_clock();
+	test_timer_expiry();
+
+	printf("All virtual time tests passed.\n");
+	return 0;
+}
@@ -0,0 +1,94 @@
+// SPDX-License-Identifier: MPL-2.0
+
+#define _GNU_SOURCE
+
+#include <errno.h>
+#include <fcntl.h>
+#include <stdio.h>
+#include <stdlib.h>
+#include <string.h>
+#include <unistd.h>
+#include <sys/mman.h>
+#include <sys/wait.h>
+
+#define PAGE_SIZE 4096
+#define NR_PAGES 2048
+
+#define CHECK(cond)                                                       \
+	do {                                                              \
+		if (!(cond)) {                                            \
+			fprintf(stderr, "%s:%d: check failed: %s (%s)\n", \
+				__FILE__, __LINE__, #cond,                \
+				strerror(errno));                         \
+			exit(1);                                          \
+		}                                                         \
+	} while (0)
+
+static void compact_memory(void)
+{
+	int fd = open("/proc/sys/vm/compact_memory", O_WRONLY);
+
+	CHECK(fd >= 0);
+	CHECK(write(fd, "1\n", 2) == 2);
+	CHECK(close(fd) == 0);
+}
+
+static void check_pages(char *buf, int step)
+{
+	for (int i = 0; i < NR_PAGES; i += step) {
+		CHECK(buf[i * PAGE_SIZE] == (char)i &&
+		      buf[i * PAGE_SIZE + PAGE_SIZE - 1] == (char)~i);
+	}
+}
+
+int main(void)
+{
+	char *buf;
+	pid_t pid;
+	int status, fd;
+
+	buf = mmap(NULL, NR_PAGES * PAGE_SIZE, PROT_READ | PROT_WRITE,
+		   MAP_PRIVATE | MAP_ANONYMOUS, -1, 0);
+	CHECK(buf != MAP_FAILED);
+	for (int i = 0; i < NR_PAGES; i++) {
+		buf[i * PAGE_SIZE] = (char)i;
+		buf[i * PAGE_SIZE + PAGE_SIZE - 1] = (char)~i;
+	}
+
+	// Free every other page to fragment the memory.
+	for (int i = 1; i < NR_PAGES; i += 2) {
+		CHECK(madvise(buf + i * PAGE_SIZE, PAGE_SIZE, MADV_DONTNEED) ==
+		      0);
+	}
+
+	// The contents are kept after the pages are migrated.
+	compact_memory();
+	check_pages(buf, 2);
+
+	// The pages are still writable and private after being migrated.
+	buf[0] = 'x';
+	CHECK(buf[0] == 'x');
+	buf[0] = 0;
+
+	// The pages shared with the child are not migrated, but they are still intact.
+	pid = fork();
+	CHECK(pid >= 0);
+	if (pid == 0) {
+		compact_memory();
+		check_pages(buf, 2);
+		exit(0);
+	}
+	CHECK(waitpid(pid, &status, 0) == pid);
+	CHECK(WIFEXITED(status) && WEXITSTATUS(status) == 0);
+	check_pages(buf, 2);
+
+	errno = 0;
+	fd = open("/proc/sys/vm/compact_memory", O_WRONLY);
+	CHECK(fd >= 0);
+	CHECK(write(fd, "x", 1) == -1 && errno == EINVAL);
+	CHECK(close(fd) == 0);
+
+	CHECK(munmap(buf, NR_PAGES * PAGE_SIZE) == 0);
+	printf("All compaction tests passed.\n");
+	return 0;
+}
@@ -0,0 +1,80 @@
+// SPDX-License-Identifier: MPL-2.0
+
+#include <errno.h>
+#include <fcntl.h>
+#include <linux/fb.h>
+#include <stdint.h>
+#include <stdio.h>
+#include <stdlib.h>
+#include <string.h>
+#include <unistd.h>
+#include <sys/ioctl.h>
+#include <sys/mman.h>
+
+#define CHECK(cond)                                                 \
+	do {                                                        \
+		if (!(cond)) {                                      \
+			fprintf(stderr, "%s:%d: `%s` fails: %s\n",  \
+				__FILE__, __LINE__, #cond,          \
+				strerror(errno));                   \
+			exit(EXIT_FAILURE);                         \
+		}                                                   \
+	} while (0)
+
+int main(void)
+{
+	int fd;
+	struct fb_var_screeninfo var, new_var;
+	struct fb_fix_screeninfo fix;
+	uint32_t *pixels;
+	uint32_t pixel = 0x00ff8000, read_pixel;
+	size_t offset;
+
+	fd = open("/dev/fb0", O_RDWR);
+	if (fd < 0 && errno == ENOENT) {
+		printf("There is no framebuffer, the test is skipped\n");
+		return 0;
+	}
+	CHECK(fd >= 0);
+
+	CHECK(ioctl(fd, FBIOGET_VSCREENINFO, &var) == 0);
+	CHECK(ioctl(fd, FBIOGET_FSCREENINFO, &fix) == 0);
+	CHECK(var.xres > 0 && var.yres > 0);
+	CHECK(var.bits_per_pixel == 32);
+	CHECK(fix.visual == FB_VISUAL_TRUECOLOR);
+	CHECK(fix.line_length >= var.xres * 4);
+	CHECK(fix.smem_len >= fix.line_length * var.yres);
+
+	// The current mode can be set again, but a larger one cannot.
+	new_var = var;
+	CHECK(ioctl(fd, FBIOPUT_VSCREENINFO, &new_var) == 0);
+	CHECK(new_var.xres == var.xres && new_var.yres == var.yres);
+	new_var.xres = var.xres + 1;
+	CHECK(ioctl(fd, FBIOPUT_VSCREENINFO, &new_var) < 0 && errno == EINVAL);
+
+	// The pixels written with `write` are seen in the mapping, and vice versa.
+	pixels = mmap(NULL, fix.smem_len, PROT_READ | PROT_WRITE, MAP_SHARED,
+		      fd, 0);
+	CHECK(pixels != MAP_FAILED);
+
+	offset = fix.line_length * (var.yres / 2) + 4 * (var.xres / 2);
+	CHECK(lseek(fd, offset, SEEK_SET) == offset);
+	CHECK(write(fd, &pixel, sizeof(pixel)) == sizeof(pixel));
+	CHECK(pixels[offset / 4] == pixel);
+
+	pixels[offset / 4 + 1] = ~pixel;
+	CHECK(pread(fd, &read_pixel, sizeof(read_pixel), offset + 4) ==
+	      sizeof(read_pixel));
+	CHECK(read_pixel == ~pixel);
+
+	// Nothing can be written beyond the framebuffer.
+	CHECK(lseek(fd, fix.smem_len, SEEK_SET) == fix.smem_len);
+	CHECK(write(fd, &pixel, sizeof(pixel)) < 0 && errno == ENOSPC);
+	CHECK(pread(fd, &read_pixel, sizeof(read_pixel), fix.smem_len) == 0);
+
+	CHECK(munmap(pixels, fix.smem_len) == 0);
+	CHECK(close(fd) == 0);
+
+	printf("Test passed\n");
+	return 0;
+}
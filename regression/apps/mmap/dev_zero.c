@@ -0,0 +1,71 @@
+// SPDX-License-Identifier: MPL-2.0
+
+#include <errno.h>
+#include <fcntl.h>
+#include <stdio.h>
+#include <stdlib.h>
+#include <string.h>
+#include <unistd.h>
+#include <sys/mman.h>
+#include <sys/wait.h>
+
+#define PAGE_SIZE 4096
+
+#define CHECK(cond)                                                 \
+	do {                                                        \
+		if (!(cond)) {                                      \
+			fprintf(stderr, "%s:%d: `%s` fails: %s\n",  \
+				__FILE__, __LINE__, #cond,          \
+				strerror(errno));                   \
+			exit(EXIT_FAILURE);                         \
+		}                                                   \
+	} while (0)
+
+int main(void)
+{
+	int fd, status;
+	char *private_map, *shared_map;
+	pid_t pid;
+
+	fd = open("/dev/zero", O_RDWR);
+	CHECK(fd >= 0);
+
+	// A private mapping reads as zeros and is private to the process.
+	private_map = mmap(NULL, PAGE_SIZE, PROT_READ | PROT_WRITE, MAP_PRIVATE,
+			   fd, 0);
+	CHECK(private_map != MAP_FAILED);
+	for (int i = 0; i < PAGE_SIZE; i++)
+		CHECK(private_map[i] == 0);
+
+	// A shared mapping is shared with the child process.
+	shared_map = mmap(NULL, PAGE_SIZE, PROT_READ | PROT_WRITE, MAP_SHARED,
+			  fd, 0);
+	CHECK(shared_map != MAP_FAILED);
+
+	pid = fork();
+	CHECK(pid >= 0);
+	if (pid == 0) {
+		private_map[0] = 'p';
+		shared_map[0] = 's';
+		exit(EXIT_SUCCESS);
+	}
+	CHECK(waitpid(pid, &status, 0) == pid);
+	CHECK(WIFEXITED(status) && WEXITSTATUS(status) == 0);
+	CHECK(private_map[0] == 0);
+	CHECK(shared_map[0] == 's');
+
+	// Mapping a file that is not readable fails.
+	close(fd);
+	fd = open("/dev/zero", O_WRONLY);
+	CHECK(fd >= 0);
+	CHECK(mmap(NULL, PAGE_SIZE, PROT_WRITE, MAP_SHARED, fd, 0) ==
+		      MAP_FAILED &&
+	      errno == EACCES);
+	close(fd);
+
+	CHECK(munmap(private_map, PAGE_SIZE) == 0);
+	CHECK(munmap(shared_map, PAGE_SIZE) == 0);
+
+	printf("/dev/zero mmap test passed\n");
+	return 0;
+}
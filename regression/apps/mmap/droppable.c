@@ -0,0 +1,113 @@
+// SPDX-License-Identifier: MPL-2.0
+
+#define _GNU_SOURCE
+
+#include <errno.h>
+#include <fcntl.h>
+#include <stdio.h>
+#include <stdlib.h>
+#include <string.h>
+#include <unistd.h>
+#include <sys/mman.h>
+#include <sys/random.h>
+#include <sys/wait.h>
+
+#ifndef MAP_DROPPABLE
+#define MAP_DROPPABLE 0x08
+#endif
+#ifndef GRND_INSECURE
+#define GRND_INSECURE 0x04
+#endif
+
+#define PAGE_SIZE 4096
+#define BUF_SIZE (4 * PAGE_SIZE)
+
+#define CHECK(cond)                                                       \
+	do {                                                              \
+		if (!(cond)) {                                            \
+			fprintf(stderr, "%s:%d: check failed: %s (%s)\n", \
+				__FILE__, __LINE__, #cond,                \
+				strerror(errno));                         \
+			exit(1);                                          \
+		}                                                         \
+	} while (0)
+
+static int check_bytes(const char *addr, size_t len, char val)
+{
+	for (size_t i = 0; i < len; i++) {
+		if (addr[i] != val) {
+			return -1;
+		}
+	}
+	return 0;
+}
+
+static void test_invalid_mappings(void)
+{
+	int fd;
+
+	fd = open("/dev/zero", O_RDWR);
+	CHECK(fd >= 0);
+	CHECK(mmap(NULL, BUF_SIZE, PROT_READ | PROT_WRITE, MAP_DROPPABLE, fd,
+		   0) == MAP_FAILED &&
+	      errno == EINVAL);
+	CHECK(close(fd) == 0);
+
+	CHECK(mmap(NULL, BUF_SIZE, PROT_READ | PROT_WRITE,
+		   MAP_DROPPABLE | MAP_ANONYMOUS | MAP_LOCKED, -1,
+		   0) == MAP_FAILED &&
+	      errno == EINVAL);
+	CHECK(mmap(NULL, BUF_SIZE, PROT_READ | PROT_WRITE,
+		   MAP_DROPPABLE | MAP_ANONYMOUS | MAP_GROWSDOWN, -1,
+		   0) == MAP_FAILED &&
+	      errno == EINVAL);
+}
+
+static void test_wipe_on_fork(void)
+{
+	char *addr;
+	int status;
+	pid_t pid;
+
+	addr = mmap(NULL, BUF_SIZE, PROT_READ | PROT_WRITE,
+		    MAP_DROPPABLE | MAP_ANONYMOUS, -1, 0);
+	CHECK(addr != MAP_FAILED);
+	memset(addr, 'a', BUF_SIZE);
+
+	pid = fork();
+	CHECK(pid >= 0);
+	if (pid == 0) {
+		// The child sees zero pages, which can be written as usual.
+		CHECK(check_bytes(addr, BUF_SIZE, 0) == 0);
+		memset(addr, 'b', BUF_SIZE);
+		CHECK(check_bytes(addr, BUF_SIZE, 'b') == 0);
+		exit(0);
+	}
+	CHECK(waitpid(pid, &status, 0) == pid);
+	CHECK(WIFEXITED(status) && WEXITSTATUS(status) == 0);
+	CHECK(check_bytes(addr, BUF_SIZE, 'a') == 0);
+
+	// The droppable mappings are never locked in memory.
+	CHECK(mlock(addr, BUF_SIZE) == 0);
+	CHECK(munmap(addr, BUF_SIZE) == 0);
+}
+
+static void test_getrandom_flags(void)
+{
+	char buf[16];
+
+	CHECK(getrandom(buf, sizeof(buf), 0x80) < 0 && errno == EINVAL);
+	CHECK(getrandom(buf, sizeof(buf), GRND_INSECURE | GRND_RANDOM) < 0 &&
+	      errno == EINVAL);
+	CHECK(getrandom(buf, sizeof(buf), GRND_INSECURE) == sizeof(buf));
+}
+
+int main(void)
+{
+	test_invalid_mappings();
+	test_wipe_on_fork();
+	test_getrandom_flags();
+
+	printf("droppable mapping test passed\n");
+	return 0;
+}
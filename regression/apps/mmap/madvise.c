@@ -0,0 +1,194 @@
+// SPDX-License-Identifier: MPL-2.0
+
+#define _GNU_SOURCE
+
+#include <errno.h>
+#include <fcntl.h>
+#include <stdio.h>
+#include <stdlib.h>
+#include <string.h>
+#include <unistd.h>
+#include <sys/mman.h>
+
+#define PAGE_SIZE 4096
+#define BUF_SIZE (8 * PAGE_SIZE)
+#define FILE_NAME "/ext2/madvise_test.txt"
+
+static int check_bytes(const char *addr, size_t len, char val)
+{
+	for (size_t i = 0; i < len; i++) {
+		if (addr[i] != val) {
+			return -1;
+		}
+	}
+	return 0;
+}
+
+static char *map_anon(int flags)
+{
+	char *addr = mmap(NULL, BUF_SIZE, PROT_READ | PROT_WRITE,
+			  flags | MAP_ANONYMOUS, -1, 0);
+	if (addr == MAP_FAILED) {
+		perror("mmap failed");
+		exit(1);
+	}
+	return addr;
+}
+
+static void test_anon(void)
+{
+	char *private = map_anon(MAP_PRIVATE);
+	char *shared = map_anon(MAP_SHARED);
+
+	// Private pages are dropped and become zero pages
+	memset(private, 'a', BUF_SIZE);
+	if (madvise(private + PAGE_SIZE, 2 * PAGE_SIZE, MADV_DONTNEED) != 0) {
+		perror("madvise MADV_DONTNEED failed");
+		exit(1);
+	}
+	if (check_bytes(private, PAGE_SIZE, 'a') != 0 ||
+	    check_bytes(private + PAGE_SIZE, 2 * PAGE_SIZE, 0) != 0 ||
+	    check_bytes(private + 3 * PAGE_SIZE, BUF_SIZE - 3 * PAGE_SIZE,
+			'a') != 0) {
+		fprintf(stderr, "private pages are not dropped correctly\n");
+		exit(1);
+	}
+
+	// Shared pages keep their data
+	memset(shared, 'b', BUF_SIZE);
+	if (madvise(shared, BUF_SIZE, MADV_DONTNEED) != 0) {
+		perror("madvise MADV_DONTNEED failed");
+		exit(1);
+	}
+	if (check_bytes(shared, BUF_SIZE, 'b') != 0) {
+		fprintf(stderr, "shared pages lose their data\n");
+		exit(1);
+	}
+
+	// Lazily freed pages that are written again must be kept
+	memset(private, 'c', BUF_SIZE);
+	if (madvise(private, BUF_SIZE, MADV_FREE) != 0) {
+		perror("madvise MADV_FREE failed");
+		exit(1);
+	}
+	memset(private, 'd', BUF_SIZE);
+	if (check_bytes(private, BUF_SIZE, 'd') != 0) {
+		fprintf(stderr, "lazily freed pages lose the new data\n");
+		exit(1);
+	}
+	if (madvise(shared, BUF_SIZE, MADV_FREE) != -1 || errno != EINVAL) {
+		fprintf(stderr, "MADV_FREE should fail on shared mappings\n");
+		exit(1);
+	}
+
+	// Locked pages cannot be dropped
+	if (mlock(private, PAGE_SIZE) != 0) {
+		perror("mlock failed");
+		exit(1);
+	}
+	if (madvise(private, PAGE_SIZE, MADV_DONTNEED) != -1 ||
+	    errno != EINVAL) {
+		fprintf(stderr, "MADV_DONTNEED should fail on locked pages\n");
+		exit(1);
+	}
+	if (munlock(private, PAGE_SIZE) != 0) {
+		perror("munlock failed");
+		exit(1);
+	}
+
+	munmap(private, BUF_SIZE);
+	munmap(shared, BUF_SIZE);
+}
+
+static void test_file(void)
+{
+	char buf[PAGE_SIZE];
+	char *addr;
+	int fd;
+
+	fd = open(FILE_NAME, O_RDWR | O_CREAT | O_TRUNC, 0644);
+	if (fd < 0) {
+		perror("open failed");
+		exit(1);
+	}
+	memset(buf, 'f', PAGE_SIZE);
+	for (int i = 0; i < BUF_SIZE / PAGE_SIZE; i++) {
+		if (write(fd, buf, PAGE_SIZE) != PAGE_SIZE) {
+			perror("write failed");
+			exit(1);
+		}
+	}
+
+	addr = mmap(NULL, BUF_SIZE, PROT_READ | PROT_WRITE, MAP_PRIVATE, fd,
+		    0);
+	if (addr == MAP_FAILED) {
+		perror("mmap failed");
+		exit(1);
+	}
+	if (madvise(addr, BUF_SIZE, MADV_WILLNEED) != 0) {
+		perror("madvise MADV_WILLNEED failed");
+		exit(1);
+	}
+	if (check_bytes(addr, BUF_SIZE, 'f') != 0) {
+		fprintf(stderr, "the file contents are not mapped\n");
+		exit(1);
+	}
+
+	// Private copies are dropped and the file contents are seen again
+	memset(addr, 'g', BUF_SIZE);
+	if (madvise(addr, BUF_SIZE, MADV_DONTNEED) != 0) {
+		perror("madvise MADV_DONTNEED failed");
+		exit(1);
+	}
+	if (check_bytes(addr, BUF_SIZE, 'f') != 0) {
+		fprintf(stderr, "private copies are not dropped\n");
+		exit(1);
+	}
+	if (madvise(addr, BUF_SIZE, MADV_FREE) != -1 || errno != EINVAL) {
+		fprintf(stderr, "MADV_FREE should fail on file mappings\n");
+		exit(1);
+	}
+
+	munmap(addr, BUF_SIZE);
+	close(fd);
+	unlink(FILE_NAME);
+}
+
+static void test_invalid(void)
+{
+	char *addr = map_anon(MAP_PRIVATE);
+
+	if (madvise(addr + 1, PAGE_SIZE, MADV_DONTNEED) != -1 ||
+	    errno != EINVAL) {
+		fprintf(stderr, "unaligned addresses should fail\n");
+		exit(1);
+	}
+	if (madvise(addr, 0, MADV_DONTNEED) != 0) {
+		perror("madvise with zero length failed");
+		exit(1);
+	}
+
+	// The range with holes is still advised, but it fails with ENOMEM
+	addr[0] = 'h';
+	munmap(addr + PAGE_SIZE, PAGE_SIZE);
+	if (madvise(addr, BUF_SIZE, MADV_DONTNEED) != -1 || errno != ENOMEM) {
+		fprintf(stderr, "ranges with holes should fail\n");
+		exit(1);
+	}
+	if (addr[0] != 0) {
+		fprintf(stderr, "the mapped part is not advised\n");
+		exit(1);
+	}
+
+	munmap(addr, BUF_SIZE);
+}
+
+int main()
+{
+	test_anon();
+	test_file();
+	test_invalid();
+
+	printf("madvise test passed\n");
+	return 0;
+}
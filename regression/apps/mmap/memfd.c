@@ -0,0 +1,202 @@
+// SPDX-License-Identifier: MPL-2.0
+
+#define _GNU_SOURCE
+
+#include <errno.h>
+#include <fcntl.h>
+#include <pthread.h>
+#include <sched.h>
+#include <stdatomic.h>
+#include <stdio.h>
+#include <stdlib.h>
+#include <string.h>
+#include <unistd.h>
+#include <sys/mman.h>
+#include <sys/stat.h>
+#include <sys/wait.h>
+
+#define PAGE_SIZE 4096
+
+#define CHECK(cond)                                                       \
+	do {                                                              \
+		if (!(cond)) {                                            \
+			fprintf(stderr, "%s:%d: check failed: %s (%s)\n", \
+				__FILE__, __LINE__, #cond,                \
+				strerror(errno));                         \
+			exit(1);                                          \
+		}                                                         \
+	} while (0)
+
+static ssize_t write_at(int fd, const void *buf, size_t count, off_t offset)
+{
+	if (lseek(fd, offset, SEEK_SET) != offset)
+		return -1;
+	return write(fd, buf, count);
+}
+
+static void test_shared_mapping(void)
+{
+	int fd = memfd_create("test_shared", MFD_CLOEXEC);
+	char buf[6];
+	char *addr;
+	pid_t pid;
+
+	CHECK(fd >= 0);
+	CHECK(ftruncate(fd, PAGE_SIZE) == 0);
+	addr = mmap(NULL, PAGE_SIZE, PROT_READ | PROT_WRITE, MAP_SHARED, fd,
+		    0);
+	CHECK(addr != MAP_FAILED);
+
+	// The writes of the child are visible to the parent and the file.
+	pid = fork();
+	CHECK(pid >= 0);
+	if (pid == 0) {
+		strcpy(addr, "hello");
+		exit(0);
+	}
+	CHECK(waitpid(pid, NULL, 0) == pid);
+	CHECK(strcmp(addr, "hello") == 0);
+	CHECK(pread(fd, buf, sizeof(buf), 0) == sizeof(buf));
+	CHECK(strcmp(buf, "hello") == 0);
+
+	// The writes to the file are visible to the mapping.
+	CHECK(write_at(fd, "world", 6, 0) == 6);
+	CHECK(strcmp(addr, "world") == 0);
+
+	// Seals cannot be added without MFD_ALLOW_SEALING.
+	CHECK(fcntl(fd, F_GET_SEALS) == F_SEAL_SEAL);
+	CHECK(fcntl(fd, F_ADD_SEALS, F_SEAL_WRITE) < 0 && errno == EPERM);
+
+	CHECK(munmap(addr, PAGE_SIZE) == 0);
+	CHECK(close(fd) == 0);
+}
+
+static void test_seal_write(void)
+{
+	int fd = memfd_create("test_seal_write", MFD_ALLOW_SEALING);
+	char *addr;
+
+	CHECK(fd >= 0);
+	CHECK(fcntl(fd, F_GET_SEALS) == 0);
+	CHECK(write(fd, "data", 4) == 4);
+
+	// The write seal cannot be added while the file is mapped writable.
+	addr = mmap(NULL, PAGE_SIZE, PROT_READ | PROT_WRITE, MAP_SHARED, fd,
+		    0);
+	CHECK(addr != MAP_FAILED);
+	CHECK(fcntl(fd, F_ADD_SEALS, F_SEAL_WRITE) < 0 && errno == EBUSY);
+	CHECK(munmap(addr, PAGE_SIZE) == 0);
+
+	CHECK(fcntl(fd, F_ADD_SEALS, F_SEAL_WRITE) == 0);
+	CHECK(fcntl(fd, F_GET_SEALS) == F_SEAL_WRITE);
+	CHECK(write_at(fd, "x", 1, 0) < 0 && errno == EPERM);
+	CHECK(mmap(NULL, PAGE_SIZE, PROT_READ | PROT_WRITE, MAP_SHARED, fd,
+		   0) == MAP_FAILED &&
+	      errno == EPERM);
+
+	// Read-only shared mappings cannot be made writable.
+	addr = mmap(NULL, PAGE_SIZE, PROT_READ, MAP_SHARED, fd, 0);
+	CHECK(addr != MAP_FAILED);
+	CHECK(memcmp(addr, "data", 4) == 0);
+	CHECK(mprotect(addr, PAGE_SIZE, PROT_READ | PROT_WRITE) < 0);
+	CHECK(munmap(addr, PAGE_SIZE) == 0);
+
+	// Private mappings are still writable, and the writes are not visible to the file.
+	addr = mmap(NULL, PAGE_SIZE, PROT_READ | PROT_WRITE, MAP_PRIVATE, fd,
+		    0);
+	CHECK(addr != MAP_FAILED);
+	addr[0] = 'D';
+	CHECK(pread(fd, addr + 8, 1, 0) == 1 && addr[8] == 'd');
+	CHECK(munmap(addr, PAGE_SIZE) == 0);
+
+	CHECK(close(fd) == 0);
+}
+
+struct mprotect_race {
+	int fd;
+	char *addr;
+	atomic_int stop;
+	int nr_violations;
+};
+
+static void *toggle_write_perm(void *arg)
+{
+	struct mprotect_race *race = arg;
+
+	while (!atomic_load(&race->stop)) {
+		if (mprotect(race->addr, PAGE_SIZE, PROT_READ | PROT_WRITE) != 0)
+			continue;
+		// The write seal cannot be added while the mapping is writable.
+		if (fcntl(race->fd, F_GET_SEALS) & F_SEAL_WRITE)
+			race->nr_violations++;
+		CHECK(mprotect(race->addr, PAGE_SIZE, PROT_READ) == 0);
+	}
+	return NULL;
+}
+
+static void test_seal_write_races_mprotect(void)
+{
+	struct mprotect_race race = { 0 };
+	pthread_t thread;
+	int i;
+
+	race.fd = memfd_create("test_seal_race", MFD_ALLOW_SEALING);
+	CHECK(race.fd >= 0);
+	CHECK(ftruncate(race.fd, PAGE_SIZE) == 0);
+	race.addr =
+		mmap(NULL, PAGE_SIZE, PROT_READ, MAP_SHARED, race.fd, 0);
+	CHECK(race.addr != MAP_FAILED);
+
+	CHECK(pthread_create(&thread, NULL, toggle_write_perm, &race) == 0);
+	// The seal succeeds only while the other thread has the mapping read-only.
+	for (i = 0; fcntl(race.fd, F_ADD_SEALS, F_SEAL_WRITE) != 0; i++) {
+		CHECK(errno == EBUSY);
+		if (i % 16 == 0)
+			sched_yield();
+	}
+	// Give the other thread some chances to make the mapping writable again.
+	for (i = 0; i < 1000; i++)
+		sched_yield();
+	atomic_store(&race.stop, 1);
+	CHECK(pthread_join(thread, NULL) == 0);
+
+	CHECK(race.nr_violations == 0);
+	CHECK(mprotect(race.addr, PAGE_SIZE, PROT_READ | PROT_WRITE) < 0);
+	CHECK(munmap(race.addr, PAGE_SIZE) == 0);
+	CHECK(close(race.fd) == 0);
+}
+
+static void test_seal_grow_and_shrink(void)
+{
+	int fd = memfd_create("test_seal_size", MFD_ALLOW_SEALING);
+	struct stat stat;
+
+	CHECK(fd >= 0);
+	CHECK(ftruncate(fd, 100) == 0);
+	CHECK(fstat(fd, &stat) == 0 && stat.st_size == 100);
+
+	CHECK(fcntl(fd, F_ADD_SEALS, F_SEAL_GROW | F_SEAL_SHRINK) == 0);
+	CHECK(ftruncate(fd, 200) < 0 && errno == EPERM);
+	CHECK(ftruncate(fd, 50) < 0 && errno == EPERM);
+	CHECK(write_at(fd, "xx", 2, 99) < 0 && errno == EPERM);
+	CHECK(write_at(fd, "xx", 2, 98) == 2);
+	CHECK(ftruncate(fd, 100) == 0);
+
+	// No more seals can be added after F_SEAL_SEAL.
+	CHECK(fcntl(fd, F_ADD_SEALS, F_SEAL_SEAL) == 0);
+	CHECK(fcntl(fd, F_ADD_SEALS, F_SEAL_WRITE) < 0 && errno == EPERM);
+	CHECK(fcntl(fd, F_GET_SEALS) ==
+	      (F_SEAL_SEAL | F_SEAL_GROW | F_SEAL_SHRINK));
+
+	CHECK(close(fd) == 0);
+}
+
+int main(void)
+{
+	test_shared_mapping();
+	test_seal_write();
+	test_seal_write_races_mprotect();
+	test_seal_grow_and_shrink();
+	printf("memfd test passed\n");
+	return 0;
+}
@@ -0,0 +1,139 @@
+// SPDX-License-Identifier: MPL-2.0
+
+#define _GNU_SOURCE
+
+#include <errno.h>
+#include <stdio.h>
+#include <stdlib.h>
+#include <string.h>
+#include <unistd.h>
+#include <sys/mman.h>
+#include <sys/wait.h>
+
+#define PAGE_SIZE 4096
+#define BUF_SIZE (16 * PAGE_SIZE)
+
+// Returns the value of `VmLck` in `/proc/self/status` in kB, or -1 on errors.
+static long locked_kb(void)
+{
+	char line[256];
+	long kb = -1;
+	FILE *file = fopen("/proc/self/status", "r");
+
+	if (file == NULL) {
+		return -1;
+	}
+	while (fgets(line, sizeof(line), file) != NULL) {
+		if (sscanf(line, "VmLck: %ld kB", &kb) == 1) {
+			break;
+		}
+	}
+	fclose(file);
+	return kb;
+}
+
+int main()
+{
+	char *buf;
+	long base_kb;
+	pid_t pid;
+	int status;
+
+	buf = mmap(NULL, BUF_SIZE, PROT_READ | PROT_WRITE,
+		   MAP_PRIVATE | MAP_ANONYMOUS, -1, 0);
+	if (buf == MAP_FAILED) {
+		perror("mmap failed");
+		exit(1);
+	}
+	base_kb = locked_kb();
+	if (base_kb < 0) {
+		fprintf(stderr, "failed to read VmLck\n");
+		exit(1);
+	}
+
+	// Lock a part of the mapping, which splits the mapping
+	if (mlock(buf + PAGE_SIZE, 4 * PAGE_SIZE) != 0) {
+		perror("mlock failed");
+		exit(1);
+	}
+	if (locked_kb() != base_kb + 4 * PAGE_SIZE / 1024) {
+		fprintf(stderr, "VmLck is not updated after mlock\n");
+		exit(1);
+	}
+	memset(buf, 'a', BUF_SIZE);
+
+	// Memory locks are not inherited by the child process
+	pid = fork();
+	if (pid < 0) {
+		perror("fork failed");
+		exit(1);
+	} else if (pid == 0) {
+		exit(locked_kb() == 0 && buf[PAGE_SIZE] == 'a' ? 0 : 1);
+	}
+	if (waitpid(pid, &status, 0) != pid || !WIFEXITED(status) ||
+	    WEXITSTATUS(status) != 0) {
+		fprintf(stderr, "the child process inherits memory locks\n");
+		exit(1);
+	}
+
+	if (munlock(buf, BUF_SIZE) != 0) {
+		perror("munlock failed");
+		exit(1);
+	}
+	if (locked_kb() != base_kb) {
+		fprintf(stderr, "VmLck is not updated after munlock\n");
+		exit(1);
+	}
+
+	if (mlock2(buf, BUF_SIZE, MLOCK_ONFAULT) != 0) {
+		perror("mlock2 failed");
+		exit(1);
+	}
+	if (mlock2(buf, BUF_SIZE, 0x10) != -1 || errno != EINVAL) {
+		fprintf(stderr, "mlock2 should fail with invalid flags\n");
+		exit(1);
+	}
+	if (munlock(buf, BUF_SIZE) != 0) {
+		perror("munlock failed");
+		exit(1);
+	}
+
+	// Locking unmapped memory must fail
+	if (munmap(buf + BUF_SIZE - PAGE_SIZE, PAGE_SIZE) != 0) {
+		perror("munmap failed");
+		exit(1);
+	}
+	if (mlock(buf, BUF_SIZE) != -1 || errno != ENOMEM) {
+		fprintf(stderr, "mlock should fail on unmapped memory\n");
+		exit(1);
+	}
+
+	if (mlockall(MCL_CURRENT | MCL_FUTURE) != 0) {
+		perror("mlockall failed");
+		exit(1);
+	}
+	if (locked_kb() < (BUF_SIZE - PAGE_SIZE) / 1024) {
+		fprintf(stderr, "VmLck is not updated after mlockall\n");
+		exit(1);
+	}
+	if (mlockall(MCL_ONFAULT) != -1 || errno != EINVAL) {
+		fprintf(stderr, "mlockall should fail with only MCL_ONFAULT\n");
+		exit(1);
+	}
+	if (munlockall() != 0) {
+		perror("munlockall failed");
+		exit(1);
+	}
+	if (locked_kb() != 0) {
+		fprintf(stderr, "VmLck is not updated after munlockall\n");
+		exit(1);
+	}
+
+	if (munmap(buf, BUF_SIZE - PAGE_SIZE) != 0) {
+		perror("munmap failed");
+		exit(1);
+	}
+
+	printf("mlock test passed\n");
+	return 0;
+}
@@ -0,0 +1,133 @@
+// SPDX-License-Identifier: MPL-2.0
+
+#define _GNU_SOURCE
+
+#include <stdio.h>
+#include <stdlib.h>
+#include <string.h>
+#include <unistd.h>
+#include <sys/mman.h>
+
+#define PAGE_SIZE 4096
+
+static void fill_pattern(char *addr, size_t len)
+{
+	for (size_t i = 0; i < len; i++) {
+		addr[i] = (char)(i % 251);
+	}
+}
+
+static int check_pattern(const char *addr, size_t len)
+{
+	for (size_t i = 0; i < len; i++) {
+		if (addr[i] != (char)(i % 251)) {
+			return -1;
+		}
+	}
+	return 0;
+}
+
+static int check_zero(const char *addr, size_t len)
+{
+	for (size_t i = 0; i < len; i++) {
+		if (addr[i] != 0) {
+			return -1;
+		}
+	}
+	return 0;
+}
+
+int main()
+{
+	size_t old_len = 4 * PAGE_SIZE;
+	size_t new_len = 16 * PAGE_SIZE;
+	char *addr, *new_addr, *fixed_addr, *target;
+
+	// Reserve one more page as a guard that blocks the in-place growth,
+	// so that the mapping has to be moved
+	addr = mmap(NULL, old_len + PAGE_SIZE, PROT_READ,
+		    MAP_PRIVATE | MAP_ANONYMOUS, -1, 0);
+	if (addr == MAP_FAILED) {
+		perror("mmap failed");
+		exit(1);
+	}
+	if (munmap(addr, old_len) == -1) {
+		perror("munmap failed");
+		exit(1);
+	}
+	if (mmap(addr, old_len, PROT_READ | PROT_WRITE,
+		 MAP_PRIVATE | MAP_ANONYMOUS | MAP_FIXED, -1,
+		 0) == MAP_FAILED) {
+		perror("mmap fixed failed");
+		exit(1);
+	}
+	fill_pattern(addr, old_len);
+
+	// Growing without MREMAP_MAYMOVE must fail
+	if (mremap(addr, old_len, new_len, 0) != MAP_FAILED) {
+		fprintf(stderr, "mremap should fail without MREMAP_MAYMOVE\n");
+		exit(1);
+	}
+
+	new_addr = mremap(addr, old_len, new_len, MREMAP_MAYMOVE);
+	if (new_addr == MAP_FAILED) {
+		perror("mremap grow failed");
+		exit(1);
+	}
+	if (check_pattern(new_addr, old_len) != 0) {
+		fprintf(stderr, "data is not preserved after mremap\n");
+		exit(1);
+	}
+	if (check_zero(new_addr + old_len, new_len - old_len) != 0) {
+		fprintf(stderr, "the enlarged part is not zeroed\n");
+		exit(1);
+	}
+	fill_pattern(new_addr, new_len);
+
+	// Shrinking is done in place
+	if (mremap(new_addr, new_len, old_len, 0) != new_addr) {
+		perror("mremap shrink failed");
+		exit(1);
+	}
+	if (check_pattern(new_addr, old_len) != 0) {
+		fprintf(stderr, "data is not preserved after shrinking\n");
+		exit(1);
+	}
+
+	// Move the mapping to a fixed address
+	target = mmap(NULL, old_len, PROT_READ | PROT_WRITE,
+		      MAP_PRIVATE | MAP_ANONYMOUS, -1, 0);
+	if (target == MAP_FAILED) {
+		perror("mmap target failed");
+		exit(1);
+	}
+	fixed_addr = mremap(new_addr, old_len, old_len,
+			    MREMAP_MAYMOVE | MREMAP_FIXED, target);
+	if (fixed_addr != target) {
+		perror("mremap fixed failed");
+		exit(1);
+	}
+	if (check_pattern(fixed_addr, old_len) != 0) {
+		fprintf(stderr, "data is not preserved after moving\n");
+		exit(1);
+	}
+
+	// MREMAP_FIXED requires MREMAP_MAYMOVE
+	if (mremap(fixed_addr, old_len, old_len, MREMAP_FIXED, new_addr) !=
+	    MAP_FAILED) {
+		fprintf(stderr, "mremap should fail without MREMAP_MAYMOVE\n");
+		exit(1);
+	}
+
+	if (munmap(fixed_addr, old_len) == -1) {
+		perror("munmap failed");
+		exit(1);
+	}
+	if (munmap(addr + old_len, PAGE_SIZE) == -1) {
+		perror("munmap guard failed");
+		exit(1);
+	}
+
+	printf("mremap test passed\n");
+	return 0;
+}
@@ -0,0 +1,152 @@
+// SPDX-License-Identifier: MPL-2.0
+
+#define _GNU_SOURCE
+
+#include <errno.h>
+#include <fcntl.h>
+#include <signal.h>
+#include <stdio.h>
+#include <stdlib.h>
+#include <string.h>
+#include <unistd.h>
+#include <sys/mman.h>
+#include <sys/wait.h>
+
+#define CHECK(cond)                                                       \
+	do {                                                              \
+		if (!(cond)) {                                            \
+			fprintf(stderr, "%s:%d: check failed: %s (%s)\n", \
+				__FILE__, __LINE__, #cond,                \
+				strerror(errno));                         \
+			exit(1);                                          \
+		}                                                         \
+	} while (0)
+
+static long read_long(const char *path)
+{
+	char buf[32];
+	long val;
+	int fd = open(path, O_RDONLY);
+	ssize_t len;
+
+	CHECK(fd >= 0);
+	len = read(fd, buf, sizeof(buf) - 1);
+	CHECK(len > 0);
+	buf[len] = '\0';
+	CHECK(sscanf(buf, "%ld", &val) == 1);
+	CHECK(close(fd) == 0);
+	return val;
+}
+
+// Writes the string to `/proc/self/oom_score_adj` and returns the result of `write`.
+static ssize_t write_adj(const char *str)
+{
+	int fd = open("/proc/self/oom_score_adj", O_WRONLY);
+	ssize_t len;
+
+	CHECK(fd >= 0);
+	len = write(fd, str, strlen(str));
+	CHECK(close(fd) == 0);
+	return len;
+}
+
+static void test_read_write(void)
+{
+	long adj = read_long("/proc/self/oom_score_adj");
+	long score = read_long("/proc/self/oom_score");
+
+	CHECK(adj >= -1000 && adj <= 1000);
+	CHECK(score >= 0);
+
+	CHECK(write_adj("500\n") == 4);
+	CHECK(read_long("/proc/self/oom_score_adj") == 500);
+	CHECK(read_long("/proc/self/oom_score") > score);
+
+	errno = 0;
+	CHECK(write_adj("1001") == -1 && errno == EINVAL);
+	errno = 0;
+	CHECK(write_adj("-1001") == -1 && errno == EINVAL);
+	errno = 0;
+	CHECK(write_adj("abc") == -1 && errno == EINVAL);
+	CHECK(read_long("/proc/self/oom_score_adj") == 500);
+
+	// The process is never killed with the minimum value.
+	CHECK(write_adj("-1000") == 5);
+	CHECK(read_long("/proc/self/oom_score") == 0);
+
+	CHECK(write_adj("0") == 1);
+	CHECK(read_long("/proc/self/oom_score_adj") == 0);
+}
+
+static void test_inherit(void)
+{
+	pid_t pid;
+	int status;
+
+	CHECK(write_adj("300") == 3);
+	pid = fork();
+	CHECK(pid >= 0);
+	if (pid == 0) {
+		CHECK(read_long("/proc/self/oom_score_adj") == 300);
+		exit(0);
+	}
+	CHECK(waitpid(pid, &status, 0) == pid);
+	CHECK(WIFEXITED(status) && WEXITSTATUS(status) == 0);
+	CHECK(write_adj("0") == 1);
+}
+
+#define CHUNK_SIZE (64UL << 20)
+
+// Allocates memory until the process is killed by the OOM killer.
+static void exhaust_memory(void)
+{
+	char *chunk;
+	size_t i;
+
+	CHECK(write_adj("1000") == 4);
+	for (;;) {
+		chunk = mmap(NULL, CHUNK_SIZE, PROT_READ | PROT_WRITE,
+			     MAP_PRIVATE | MAP_ANONYMOUS, -1, 0);
+		if (chunk == MAP_FAILED)
+			continue;
+		for (i = 0; i < CHUNK_SIZE; i += 4096)
+			chunk[i] = 1;
+	}
+}
+
+static void test_oom_kill(void)
+{
+	pid_t pid;
+	int status;
+
+	// The victim must be the child, rather than this process or any other one.
+	CHECK(write_adj("-1000") == 5);
+	pid = fork();
+	CHECK(pid >= 0);
+	if (pid == 0)
+		exhaust_memory();
+
+	CHECK(waitpid(pid, &status, 0) == pid);
+	CHECK(WIFSIGNALED(status) && WTERMSIG(status) == SIGKILL);
+	CHECK(write_adj("0") == 1);
+
+	// The memory of the victim is freed, so it can be allocated again.
+	pid = fork();
+	CHECK(pid >= 0);
+	if (pid == 0)
+		exit(mmap(NULL, CHUNK_SIZE, PROT_READ | PROT_WRITE,
+			  MAP_PRIVATE | MAP_ANONYMOUS | MAP_POPULATE, -1,
+			  0) == MAP_FAILED);
+	CHECK(waitpid(pid, &status, 0) == pid);
+	CHECK(WIFEXITED(status) && WEXITSTATUS(status) == 0);
+}
+
+int main(void)
+{
+	test_read_write();
+	test_inherit();
+	test_oom_kill();
+
+	printf("All OOM tests passed.\n");
+	return 0;
+}
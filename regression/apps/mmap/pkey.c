@@ -0,0 +1,180 @@
+// SPDX-License-Identifier: MPL-2.0
+
+#define _GNU_SOURCE
+
+#include <errno.h>
+#include <setjmp.h>
+#include <signal.h>
+#include <stdio.h>
+#include <stdlib.h>
+#include <string.h>
+#include <unistd.h>
+#include <sys/mman.h>
+#include <sys/syscall.h>
+
+#define PAGE_SIZE 4096
+
+#ifndef SYS_pkey_mprotect
+#define SYS_pkey_mprotect 329
+#define SYS_pkey_alloc 330
+#define SYS_pkey_free 331
+#endif
+
+#ifndef SEGV_PKUERR
+#define SEGV_PKUERR 4
+#endif
+
+#define PKEY_DISABLE_ACCESS 0x1
+#define PKEY_DISABLE_WRITE 0x2
+
+#define CHECK(cond)                                                       \
+	do {                                                              \
+		if (!(cond)) {                                            \
+			fprintf(stderr, "%s:%d: check failed: %s (%s)\n", \
+				__FILE__, __LINE__, #cond,                \
+				strerror(errno));                         \
+			exit(1);                                          \
+		}                                                         \
+	} while (0)
+
+static int pkey_mprotect_raw(void *addr, size_t len, int prot, int pkey)
+{
+	return syscall(SYS_pkey_mprotect, addr, len, prot, pkey);
+}
+
+static int pkey_alloc_raw(unsigned int flags, unsigned int access_rights)
+{
+	return syscall(SYS_pkey_alloc, flags, access_rights);
+}
+
+static int pkey_free_raw(int pkey)
+{
+	return syscall(SYS_pkey_free, pkey);
+}
+
+static unsigned int read_pkru(void)
+{
+	unsigned int eax, edx;
+
+	asm volatile("rdpkru" : "=a"(eax), "=d"(edx) : "c"(0));
+	return eax;
+}
+
+static void write_pkru(unsigned int pkru)
+{
+	asm volatile("wrpkru" : : "a"(pkru), "c"(0), "d"(0) : "memory");
+}
+
+static sigjmp_buf fault_env;
+static volatile int fault_code;
+
+static void segv_handler(int sig, siginfo_t *info, void *ucontext)
+{
+	fault_code = info->si_code;
+	siglongjmp(fault_env, 1);
+}
+
+static void test_growsdown(void)
+{
+	char *buf;
+	char on_stack = 0;
+	void *stack_page =
+		(void *)((unsigned long)&on_stack & ~(unsigned long)(PAGE_SIZE - 1));
+
+	// The protection applies down to the start of the stack.
+	CHECK(mprotect(stack_page, PAGE_SIZE,
+		       PROT_READ | PROT_WRITE | PROT_GROWSDOWN) == 0);
+	on_stack = 1;
+	CHECK(on_stack == 1);
+
+	buf = mmap(NULL, PAGE_SIZE, PROT_READ | PROT_WRITE,
+		   MAP_PRIVATE | MAP_ANONYMOUS, -1, 0);
+	CHECK(buf != MAP_FAILED);
+	errno = 0;
+	CHECK(mprotect(buf, PAGE_SIZE, PROT_READ | PROT_GROWSDOWN) == -1 &&
+	      errno == EINVAL);
+	errno = 0;
+	CHECK(mprotect(buf, PAGE_SIZE, PROT_READ | PROT_GROWSUP) == -1 &&
+	      errno == EINVAL);
+	CHECK(munmap(buf, PAGE_SIZE) == 0);
+}
+
+static void test_pkey_mprotect_default(void)
+{
+	char *buf;
+
+	buf = mmap(NULL, PAGE_SIZE, PROT_READ | PROT_WRITE,
+		   MAP_PRIVATE | MAP_ANONYMOUS, -1, 0);
+	CHECK(buf != MAP_FAILED);
+	// A key of -1 behaves the same as `mprotect`.
+	CHECK(pkey_mprotect_raw(buf, PAGE_SIZE, PROT_READ, -1) == 0);
+	CHECK(buf[0] == 0);
+	errno = 0;
+	CHECK(pkey_mprotect_raw(buf, PAGE_SIZE, PROT_READ, 15) == -1 &&
+	      errno == EINVAL);
+	errno = 0;
+	CHECK(pkey_alloc_raw(1, 0) == -1 && errno == EINVAL);
+	errno = 0;
+	CHECK(pkey_alloc_raw(0, 0x4) == -1 && errno == EINVAL);
+	CHECK(munmap(buf, PAGE_SIZE) == 0);
+}
+
+static void test_pkey_access(void)
+{
+	struct sigaction action = { 0 };
+	char *buf;
+	int pkey;
+
+	pkey = pkey_alloc_raw(0, PKEY_DISABLE_WRITE);
+	if (pkey < 0) {
+		// The CPU does not support protection keys.
+		CHECK(errno == ENOSPC);
+		return;
+	}
+	CHECK(pkey > 0);
+	CHECK((read_pkru() >> (2 * pkey) & 0x3) == PKEY_DISABLE_WRITE);
+
+	buf = mmap(NULL, PAGE_SIZE, PROT_READ | PROT_WRITE,
+		   MAP_PRIVATE | MAP_ANONYMOUS, -1, 0);
+	CHECK(buf != MAP_FAILED);
+	buf[0] = 'a';
+	CHECK(pkey_mprotect_raw(buf, PAGE_SIZE, PROT_READ | PROT_WRITE, pkey) ==
+	      0);
+
+	action.sa_sigaction = segv_handler;
+	action.sa_flags = SA_SIGINFO | SA_NODEFER;
+	CHECK(sigaction(SIGSEGV, &action, NULL) == 0);
+
+	// The writes are denied by the key, while the reads are not.
+	CHECK(buf[0] == 'a');
+	fault_code = 0;
+	if (sigsetjmp(fault_env, 1) == 0) {
+		*(volatile char *)buf = 'b';
+		CHECK(0);
+	}
+	CHECK(fault_code == SEGV_PKUERR);
+
+	// The access rights are changed without system calls.
+	write_pkru(read_pkru() & ~(0x3u << (2 * pkey)));
+	buf[0] = 'b';
+	CHECK(buf[0] == 'b');
+
+	action.sa_handler = SIG_DFL;
+	action.sa_flags = 0;
+	CHECK(sigaction(SIGSEGV, &action, NULL) == 0);
+
+	CHECK(munmap(buf, PAGE_SIZE) == 0);
+	CHECK(pkey_free_raw(pkey) == 0);
+	errno = 0;
+	CHECK(pkey_free_raw(pkey) == -1 && errno == EINVAL);
+}
+
+int main(void)
+{
+	test_growsdown();
+	test_pkey_mprotect_default();
+	test_pkey_access();
+
+	printf("All pkey tests passed.\n");
+	return 0;
+}
@@ -0,0 +1,191 @@
+// SPDX-License-Identifier: MPL-2.0
+
+#define _GNU_SOURCE
+
+#include <errno.h>
+#include <fcntl.h>
+#include <stdio.h>
+#include <stdlib.h>
+#include <string.h>
+#include <unistd.h>
+#include <sys/mman.h>
+#include <sys/stat.h>
+
+#define PAGE_SIZE 4096
+#define NR_PAGES 4
+#define BUF_SIZE (NR_PAGES * PAGE_SIZE)
+#define FILE_NAME "/ext2/proc_maps_test.txt"
+
+#define CHECK(cond)                                                       \
+	do {                                                              \
+		if (!(cond)) {                                            \
+			fprintf(stderr, "%s:%d: check failed: %s (%s)\n", \
+				__FILE__, __LINE__, #cond,                \
+				strerror(errno));                         \
+			exit(1);                                          \
+		}                                                         \
+	} while (0)
+
+// Finds the line of the mapping that starts at `addr` in `/proc/self/maps`.
+static int find_maps_line(void *addr, char *line, size_t size)
+{
+	char start[32];
+	int found = 0;
+	FILE *file = fopen("/proc/self/maps", "r");
+
+	CHECK(file != NULL);
+	snprintf(start, sizeof(start), "%08lx-", (unsigned long)addr);
+	while (fgets(line, size, file) != NULL) {
+		if (strncmp(line, start, strlen(start)) == 0) {
+			found = 1;
+			break;
+		}
+	}
+	fclose(file);
+	return found;
+}
+
+// Returns the value of the field of the mapping that starts at `addr` in
+// `/proc/self/smaps` in kB, or -1 on errors.
+static long smaps_kb(void *addr, const char *field)
+{
+	char line[512];
+	char start[32];
+	size_t len = strlen(field);
+	int in_mapping = 0;
+	long kb = -1;
+	FILE *file = fopen("/proc/self/smaps", "r");
+
+	CHECK(file != NULL);
+	snprintf(start, sizeof(start), "%08lx-", (unsigned long)addr);
+	while (fgets(line, sizeof(line), file) != NULL) {
+		if (line[0] < 'A' || line[0] > 'Z') {
+			// The line that describes a mapping, instead of a field.
+			if (in_mapping)
+				break;
+			in_mapping = strncmp(line, start, strlen(start)) == 0;
+		} else if (in_mapping && strncmp(line, field, len) == 0 &&
+			   line[len] == ':') {
+			sscanf(line + len + 1, "%ld", &kb);
+			break;
+		}
+	}
+	fclose(file);
+	return kb;
+}
+
+static void test_anon(void)
+{
+	char line[512];
+	char *buf;
+
+	buf = mmap(NULL, BUF_SIZE, PROT_READ | PROT_WRITE,
+		   MAP_PRIVATE | MAP_ANONYMOUS, -1, 0);
+	CHECK(buf != MAP_FAILED);
+	CHECK(find_maps_line(buf, line, sizeof(line)));
+	CHECK(strstr(line, " rw-p 00000000 00:00 0 ") != NULL);
+
+	CHECK(smaps_kb(buf, "Size") == BUF_SIZE / 1024);
+	CHECK(smaps_kb(buf, "Rss") == 0);
+	buf[0] = 'a';
+	buf[PAGE_SIZE] = 'a';
+	CHECK(smaps_kb(buf, "Rss") == 2 * PAGE_SIZE / 1024);
+	CHECK(smaps_kb(buf, "Pss") == 2 * PAGE_SIZE / 1024);
+	CHECK(smaps_kb(buf, "Private_Dirty") == 2 * PAGE_SIZE / 1024);
+	CHECK(smaps_kb(buf, "Anonymous") == 2 * PAGE_SIZE / 1024);
+
+	// The mapping is split by the different permissions.
+	CHECK(mprotect(buf + PAGE_SIZE, PAGE_SIZE, PROT_READ) == 0);
+	CHECK(find_maps_line(buf + PAGE_SIZE, line, sizeof(line)));
+	CHECK(strstr(line, " r--p ") != NULL);
+	CHECK(smaps_kb(buf, "Size") == PAGE_SIZE / 1024);
+
+	CHECK(munmap(buf, BUF_SIZE) == 0);
+	CHECK(!find_maps_line(buf, line, sizeof(line)));
+}
+
+static void test_fault_stats(void)
+{
+	char *buf;
+
+	buf = mmap(NULL, 4 * PAGE_SIZE, PROT_READ | PROT_WRITE,
+		   MAP_PRIVATE | MAP_ANONYMOUS, -1, 0);
+	CHECK(buf != MAP_FAILED);
+	// The fields are counts instead of sizes, but are parsed in the same way.
+	CHECK(smaps_kb(buf, "Faults") == 0);
+
+	for (int i = 0; i < 3; i++)
+		buf[i * PAGE_SIZE] = 'a';
+	CHECK(buf[3 * PAGE_SIZE] == 0);
+	CHECK(smaps_kb(buf, "Faults") == 4);
+	CHECK(smaps_kb(buf, "WriteFaults") == 3);
+	CHECK(smaps_kb(buf, "FaultLatency") >= 0);
+
+	// The pages are mapped, so accessing them again does not fault.
+	buf[0] = 'b';
+	CHECK(smaps_kb(buf, "Faults") == 4);
+
+	CHECK(munmap(buf, 4 * PAGE_SIZE) == 0);
+}
+
+static void test_file(void)
+{
+	char line[512];
+	char expected[128];
+	char page[PAGE_SIZE];
+	struct stat stat_buf;
+	char *buf;
+	int fd;
+
+	fd = open(FILE_NAME, O_RDWR | O_CREAT | O_TRUNC, 0644);
+	CHECK(fd >= 0);
+	memset(page, 'f', PAGE_SIZE);
+	for (int i = 0; i < NR_PAGES; i++)
+		CHECK(write(fd, page, PAGE_SIZE) == PAGE_SIZE);
+	CHECK(fstat(fd, &stat_buf) == 0);
+
+	buf = mmap(NULL, 2 * PAGE_SIZE, PROT_READ, MAP_SHARED, fd,
+		   2 * PAGE_SIZE);
+	CHECK(buf != MAP_FAILED);
+	CHECK(find_maps_line(buf, line, sizeof(line)));
+	snprintf(expected, sizeof(expected), " r--s %08x ", 2 * PAGE_SIZE);
+	CHECK(strstr(line, expected) != NULL);
+	snprintf(expected, sizeof(expected), " %lu ",
+		 (unsigned long)stat_buf.st_ino);
+	CHECK(strstr(line, expected) != NULL);
+	CHECK(strstr(line, FILE_NAME "\n") != NULL);
+
+	CHECK(buf[PAGE_SIZE] == 'f');
+	CHECK(smaps_kb(buf, "Rss") >= PAGE_SIZE / 1024);
+	CHECK(smaps_kb(buf, "Anonymous") == 0);
+
+	CHECK(munmap(buf, 2 * PAGE_SIZE) == 0);
+	CHECK(close(fd) == 0);
+	CHECK(unlink(FILE_NAME) == 0);
+}
+
+static void test_special(void)
+{
+	char line[512];
+	int has_heap = 0, has_stack = 0;
+	FILE *file = fopen("/proc/self/maps", "r");
+
+	CHECK(file != NULL);
+	while (fgets(line, sizeof(line), file) != NULL) {
+		has_heap |= strstr(line, " [heap]\n") != NULL;
+		has_stack |= strstr(line, " [stack]\n") != NULL;
+	}
+	fclose(file);
+	CHECK(has_heap && has_stack);
+}
+
+int main(void)
+{
+	test_anon();
+	test_fault_stats();
+	test_file();
+	test_special();
+
+	printf("All /proc/[pid]/maps tests passed.\n");
+	return 0;
+}
@@ -0,0 +1,167 @@
+// SPDX-License-Identifier: MPL-2.0
+
+#define _GNU_SOURCE
+
+#include <errno.h>
+#include <stdio.h>
+#include <stdlib.h>
+#include <string.h>
+#include <unistd.h>
+#include <sys/mman.h>
+#include <sys/wait.h>
+
+#define PAGE_SIZE 4096
+#define NR_PAGES 1024
+#define BUF_SIZE (NR_PAGES * PAGE_SIZE)
+#define BUF_KB (BUF_SIZE / 1024)
+
+#define CHECK(cond)                                                       \
+	do {                                                              \
+		if (!(cond)) {                                            \
+			fprintf(stderr, "%s:%d: check failed: %s (%s)\n", \
+				__FILE__, __LINE__, #cond,                \
+				strerror(errno));                         \
+			exit(1);                                          \
+		}                                                         \
+	} while (0)
+
+// Returns the value of the field in `/proc/self/status` in kB, or -1 on errors.
+static long status_kb(const char *field)
+{
+	char line[256];
+	size_t len = strlen(field);
+	long kb = -1;
+	FILE *file = fopen("/proc/self/status", "r");
+
+	if (file == NULL)
+		return -1;
+	while (fgets(line, sizeof(line), file) != NULL) {
+		if (strncmp(line, field, len) == 0 && line[len] == ':') {
+			sscanf(line + len + 1, "%ld", &kb);
+			break;
+		}
+	}
+	fclose(file);
+	return kb;
+}
+
+// Reads the resident and shared pages in `/proc/self/statm`.
+static void read_statm(long *resident, long *shared)
+{
+	long size;
+	FILE *file = fopen("/proc/self/statm", "r");
+
+	CHECK(file != NULL);
+	CHECK(fscanf(file, "%ld %ld %ld", &size, resident, shared) == 3);
+	CHECK(size >= *resident);
+	fclose(file);
+}
+
+// Checks that the value grows by about `expected` with some slack.
+static int grows_by(long before, long after, long expected)
+{
+	return after - before >= expected / 2 && after - before <= expected * 2;
+}
+
+static void touch(char *buf)
+{
+	for (int i = 0; i < NR_PAGES; i++)
+		buf[i * PAGE_SIZE] = 'a';
+}
+
+static void test_anon(void)
+{
+	long anon = status_kb("RssAnon");
+	long rss = status_kb("VmRSS");
+	long resident, shared, resident2, shared2;
+	char *buf;
+
+	CHECK(anon >= 0 && rss >= anon);
+	read_statm(&resident, &shared);
+
+	buf = mmap(NULL, BUF_SIZE, PROT_READ | PROT_WRITE,
+		   MAP_PRIVATE | MAP_ANONYMOUS, -1, 0);
+	CHECK(buf != MAP_FAILED);
+	// The pages are not resident until they are accessed.
+	CHECK(status_kb("RssAnon") - anon < BUF_KB / 2);
+
+	touch(buf);
+	CHECK(grows_by(anon, status_kb("RssAnon"), BUF_KB));
+	CHECK(grows_by(rss, status_kb("VmRSS"), BUF_KB));
+	read_statm(&resident2, &shared2);
+	CHECK(grows_by(resident, resident2, NR_PAGES));
+	CHECK(shared2 - shared < NR_PAGES / 2);
+
+	CHECK(munmap(buf, BUF_SIZE) == 0);
+	CHECK(status_kb("RssAnon") - anon < BUF_KB / 2);
+}
+
+static void test_populate(void)
+{
+	long anon = status_kb("RssAnon");
+	char *buf;
+
+	buf = mmap(NULL, BUF_SIZE, PROT_READ | PROT_WRITE,
+		   MAP_PRIVATE | MAP_ANONYMOUS | MAP_POPULATE, -1, 0);
+	CHECK(buf != MAP_FAILED);
+	// The pages are resident before they are accessed.
+	CHECK(grows_by(anon, status_kb("RssAnon"), BUF_KB));
+	for (int i = 0; i < NR_PAGES; i++)
+		CHECK(buf[i * PAGE_SIZE] == 0);
+
+	touch(buf);
+	CHECK(grows_by(anon, status_kb("RssAnon"), BUF_KB));
+
+	CHECK(munmap(buf, BUF_SIZE) == 0);
+	CHECK(status_kb("RssAnon") - anon < BUF_KB / 2);
+}
+
+static void test_shmem(void)
+{
+	long shmem = status_kb("RssShmem");
+	long resident, shared, resident2, shared2;
+	char *buf;
+	pid_t pid;
+	int status;
+
+	CHECK(shmem >= 0);
+	read_statm(&resident, &shared);
+
+	buf = mmap(NULL, BUF_SIZE, PROT_READ | PROT_WRITE,
+		   MAP_SHARED | MAP_ANONYMOUS, -1, 0);
+	CHECK(buf != MAP_FAILED);
+	touch(buf);
+	CHECK(grows_by(shmem, status_kb("RssShmem"), BUF_KB));
+	read_statm(&resident2, &shared2);
+	CHECK(grows_by(resident, resident2, NR_PAGES));
+	CHECK(grows_by(shared, shared2, NR_PAGES));
+
+	pid = fork();
+	CHECK(pid >= 0);
+	if (pid == 0) {
+		// The counters are per process, so the child sees its own pages.
+		touch(buf);
+		CHECK(status_kb("RssShmem") >= BUF_KB / 2);
+		CHECK(munmap(buf, BUF_SIZE) == 0);
+		CHECK(status_kb("RssShmem") < BUF_KB / 2);
+		exit(0);
+	}
+	CHECK(waitpid(pid, &status, 0) == pid);
+	CHECK(WIFEXITED(status) && WEXITSTATUS(status) == 0);
+	CHECK(grows_by(shmem, status_kb("RssShmem"), BUF_KB));
+
+	CHECK(munmap(buf, BUF_SIZE) == 0);
+	CHECK(status_kb("RssShmem") - shmem < BUF_KB / 2);
+}
+
+int main(void)
+{
+	CHECK(status_kb("VmSwap") >= 0);
+
+	test_anon();
+	test_populate();
+	test_shmem();
+
+	printf("All RSS tests passed.\n");
+	return 0;
+}
@@ -0,0 +1,307 @@
+// SPDX-License-Identifier: MPL-2.0
+
+#define _GNU_SOURCE
+
+#include <errno.h>
+#include <fcntl.h>
+#include <stdio.h>
+#include <stdlib.h>
+#include <string.h>
+#include <unistd.h>
+#include <sys/ipc.h>
+#include <sys/mman.h>
+#include <sys/mount.h>
+#include <sys/shm.h>
+#include <sys/stat.h>
+#include <sys/vfs.h>
+#include <sys/wait.h>
+
+#define PAGE_SIZE 4096
+#define TMPFS_MAGIC 0x01021994
+#define TMPFS_DIR "/tmp/aster_tmpfs_test"
+
+#define CHECK(cond)                                                       \
+	do {                                                              \
+		if (!(cond)) {                                            \
+			fprintf(stderr, "%s:%d: check failed: %s (%s)\n", \
+				__FILE__, __LINE__, #cond,                \
+				strerror(errno));                         \
+			exit(1);                                          \
+		}                                                         \
+	} while (0)
+
+static void wait_child(pid_t pid)
+{
+	int status;
+
+	CHECK(waitpid(pid, &status, 0) == pid);
+	CHECK(WIFEXITED(status) && WEXITSTATUS(status) == 0);
+}
+
+static void test_shmget(void)
+{
+	key_t key = 0x5a5a;
+	int id, id2;
+
+	// Clean up a segment left by a previous run.
+	id = shmget(key, 0, 0);
+	if (id >= 0)
+		CHECK(shmctl(id, IPC_RMID, NULL) == 0);
+
+	CHECK(shmget(key, PAGE_SIZE, 0600) < 0 && errno == ENOENT);
+	CHECK(shmget(IPC_PRIVATE, 0, IPC_CREAT | 0600) < 0 && errno == EINVAL);
+
+	id = shmget(key, PAGE_SIZE, IPC_CREAT | 0600);
+	CHECK(id >= 0);
+	CHECK(shmget(key, PAGE_SIZE, IPC_CREAT | IPC_EXCL | 0600) < 0 &&
+	      errno == EEXIST);
+	CHECK(shmget(key, 2 * PAGE_SIZE, 0600) < 0 && errno == EINVAL);
+	id2 = shmget(key, PAGE_SIZE / 2, 0600);
+	CHECK(id2 == id);
+
+	// A private segment is always a new one.
+	id2 = shmget(IPC_PRIVATE, PAGE_SIZE, 0600);
+	CHECK(id2 >= 0 && id2 != id);
+
+	CHECK(shmctl(id, IPC_RMID, NULL) == 0);
+	CHECK(shmctl(id2, IPC_RMID, NULL) == 0);
+	CHECK(shmget(key, PAGE_SIZE, 0600) < 0 && errno == ENOENT);
+}
+
+static void test_shmat_fork(void)
+{
+	int id = shmget(IPC_PRIVATE, 100, IPC_CREAT | 0600);
+	struct shmid_ds ds;
+	char *addr;
+	pid_t pid;
+
+	CHECK(id >= 0);
+	addr = shmat(id, NULL, 0);
+	CHECK(addr != (void *)-1);
+	CHECK(((unsigned long)addr & (PAGE_SIZE - 1)) == 0);
+	strcpy(addr, "parent");
+
+	CHECK(shmctl(id, IPC_STAT, &ds) == 0);
+	CHECK(ds.shm_segsz == 100);
+	CHECK(ds.shm_nattch == 1);
+	CHECK(ds.shm_cpid == getpid());
+	CHECK(ds.shm_lpid == getpid());
+	CHECK((ds.shm_perm.mode & 0777) == 0600);
+
+	pid = fork();
+	CHECK(pid >= 0);
+	if (pid == 0) {
+		char *addr2;
+
+		// The attached segment is inherited.
+		CHECK(strcmp(addr, "parent") == 0);
+		strcpy(addr, "child");
+
+		// The segment can be attached more than once.
+		addr2 = shmat(id, NULL, SHM_RDONLY);
+		CHECK(addr2 != (void *)-1 && addr2 != addr);
+		CHECK(strcmp(addr2, "child") == 0);
+		CHECK(shmctl(id, IPC_STAT, &ds) == 0);
+		CHECK(ds.shm_nattch == 3);
+		CHECK(shmdt(addr2) == 0);
+		exit(0);
+	}
+	wait_child(pid);
+	CHECK(strcmp(addr, "child") == 0);
+	CHECK(shmctl(id, IPC_STAT, &ds) == 0);
+	CHECK(ds.shm_nattch == 1);
+
+	// A removed segment stays attached until it is detached.
+	CHECK(shmctl(id, IPC_RMID, NULL) == 0);
+	CHECK(strcmp(addr, "child") == 0);
+	CHECK(shmctl(id, IPC_STAT, &ds) == 0);
+	CHECK(ds.shm_perm.mode & SHM_DEST);
+
+	CHECK(shmdt(addr) == 0);
+	CHECK(shmdt(addr) < 0 && errno == EINVAL);
+	CHECK(shmctl(id, IPC_STAT, &ds) < 0 && errno == EINVAL);
+}
+
+static void test_shmat_addr(void)
+{
+	int id = shmget(IPC_PRIVATE, PAGE_SIZE, IPC_CREAT | 0600);
+	char *hint, *addr;
+
+	CHECK(id >= 0);
+	hint = mmap(NULL, 2 * PAGE_SIZE, PROT_NONE, MAP_PRIVATE | MAP_ANONYMOUS,
+		    -1, 0);
+	CHECK(hint != MAP_FAILED);
+
+	// The address must be page-aligned unless SHM_RND is given.
+	CHECK(shmat(id, hint + 1, 0) == (void *)-1 && errno == EINVAL);
+	addr = shmat(id, hint + 1, SHM_RND | SHM_REMAP);
+	CHECK(addr == hint);
+	addr[0] = 'a';
+
+	CHECK(shmdt(addr) == 0);
+	CHECK(munmap(hint, 2 * PAGE_SIZE) == 0);
+	CHECK(shmctl(id, IPC_RMID, NULL) == 0);
+}
+
+static void test_shm_open(void)
+{
+	const char *name = "/aster_shm_test";
+	char *addr, *addr2;
+	pid_t pid;
+	int fd;
+
+	shm_unlink(name);
+	fd = shm_open(name, O_RDWR | O_CREAT | O_EXCL, 0600);
+	CHECK(fd >= 0);
+	CHECK(ftruncate(fd, PAGE_SIZE) == 0);
+	addr = mmap(NULL, PAGE_SIZE, PROT_READ | PROT_WRITE, MAP_SHARED, fd, 0);
+	CHECK(addr != MAP_FAILED);
+	CHECK(close(fd) == 0);
+
+	pid = fork();
+	CHECK(pid >= 0);
+	if (pid == 0) {
+		// An unrelated mapping of the same object sees the same pages.
+		int fd2 = shm_open(name, O_RDWR, 0);
+
+		CHECK(fd2 >= 0);
+		addr2 = mmap(NULL, PAGE_SIZE, PROT_READ | PROT_WRITE,
+			     MAP_SHARED, fd2, 0);
+		CHECK(addr2 != MAP_FAILED);
+		strcpy(addr2, "hello");
+		exit(0);
+	}
+	wait_child(pid);
+	CHECK(strcmp(addr, "hello") == 0);
+
+	CHECK(shm_unlink(name) == 0);
+	CHECK(shm_open(name, O_RDWR, 0) < 0 && errno == ENOENT);
+	CHECK(munmap(addr, PAGE_SIZE) == 0);
+}
+
+static void test_shm_statfs(void)
+{
+	const char *name = "/aster_shm_statfs_test";
+	struct statfs before, after;
+	int fd;
+
+	shm_unlink(name);
+	CHECK(statfs("/dev/shm", &before) == 0);
+	CHECK(before.f_type == TMPFS_MAGIC);
+	CHECK(before.f_bsize == PAGE_SIZE);
+
+	// The size of the object is accounted once it is truncated.
+	fd = shm_open(name, O_RDWR | O_CREAT | O_EXCL, 0600);
+	CHECK(fd >= 0);
+	CHECK(ftruncate(fd, 4 * PAGE_SIZE) == 0);
+	CHECK(statfs("/dev/shm", &after) == 0);
+	CHECK(after.f_bfree + 4 == before.f_bfree);
+
+	CHECK(close(fd) == 0);
+	CHECK(shm_unlink(name) == 0);
+	CHECK(statfs("/dev/shm", &after) == 0);
+	CHECK(after.f_bfree == before.f_bfree);
+}
+
+static void test_tmpfs_size(void)
+{
+	char page[PAGE_SIZE];
+	struct statfs buf;
+	int fd;
+
+	memset(page, 'a', PAGE_SIZE);
+	mkdir(TMPFS_DIR, 0755);
+	CHECK(mount("tmpfs", TMPFS_DIR, "tmpfs", 0, "size=16k,mode=700") == 0);
+	CHECK(statfs(TMPFS_DIR, &buf) == 0);
+	CHECK(buf.f_type == TMPFS_MAGIC);
+	CHECK(buf.f_blocks == 4 && buf.f_bfree == 4);
+
+	fd = open(TMPFS_DIR "/file", O_RDWR | O_CREAT, 0600);
+	CHECK(fd >= 0);
+	for (int i = 0; i < 4; i++)
+		CHECK(write(fd, page, PAGE_SIZE) == PAGE_SIZE);
+	CHECK(write(fd, page, PAGE_SIZE) < 0 && errno == ENOSPC);
+	CHECK(ftruncate(fd, 5 * PAGE_SIZE) < 0 && errno == ENOSPC);
+	CHECK(ftruncate(fd, PAGE_SIZE) == 0);
+	CHECK(ftruncate(fd, 4 * PAGE_SIZE) == 0);
+	CHECK(close(fd) == 0);
+	CHECK(unlink(TMPFS_DIR "/file") == 0);
+
+	CHECK(mount("tmpfs", TMPFS_DIR, "tmpfs", 0, "nosuchoption") < 0 &&
+	      errno == EINVAL);
+	CHECK(umount(TMPFS_DIR) == 0);
+	CHECK(rmdir(TMPFS_DIR) == 0);
+}
+
+static void test_tmpfs_nr_inodes(void)
+{
+	struct statfs buf;
+	int fd;
+
+	mkdir(TMPFS_DIR, 0755);
+	CHECK(mount("tmpfs", TMPFS_DIR, "tmpfs", 0, "nr_inodes=3") == 0);
+	CHECK(statfs(TMPFS_DIR, &buf) == 0);
+	// The root directory takes an inode.
+	CHECK(buf.f_files == 3 && buf.f_ffree == 2);
+
+	CHECK(mkdir(TMPFS_DIR "/dir", 0755) == 0);
+	fd = open(TMPFS_DIR "/file", O_RDWR | O_CREAT, 0600);
+	CHECK(fd >= 0);
+	CHECK(close(fd) == 0);
+	CHECK(statfs(TMPFS_DIR, &buf) == 0);
+	CHECK(buf.f_ffree == 0);
+	CHECK(open(TMPFS_DIR "/full", O_RDWR | O_CREAT, 0600) < 0 &&
+	      errno == ENOSPC);
+	CHECK(open(TMPFS_DIR, O_TMPFILE | O_RDWR, 0600) < 0 &&
+	      errno == ENOSPC);
+
+	CHECK(unlink(TMPFS_DIR "/file") == 0);
+	CHECK(rmdir(TMPFS_DIR "/dir") == 0);
+	CHECK(statfs(TMPFS_DIR, &buf) == 0);
+	CHECK(buf.f_ffree == 2);
+	CHECK(umount(TMPFS_DIR) == 0);
+	CHECK(rmdir(TMPFS_DIR) == 0);
+}
+
+static void test_tmpfile(void)
+{
+	char buf[6];
+	struct stat stat_buf;
+	int fd;
+
+	CHECK(open("/tmp", O_TMPFILE | O_RDONLY, 0600) < 0 && errno == EINVAL);
+
+	fd = open("/tmp", O_TMPFILE | O_RDWR, 0600);
+	CHECK(fd >= 0);
+	CHECK(fstat(fd, &stat_buf) == 0);
+	CHECK(S_ISREG(stat_buf.st_mode) && stat_buf.st_nlink == 0);
+	CHECK(write(fd, "hello", 5) == 5);
+	CHECK(pread(fd, buf, 5, 0) == 5 && memcmp(buf, "hello", 5) == 0);
+
+	// The file is unnamed until it is linked.
+	CHECK(linkat(fd, "", AT_FDCWD, "/tmp/aster_tmpfile", AT_EMPTY_PATH) ==
+	      0);
+	CHECK(fstat(fd, &stat_buf) == 0 && stat_buf.st_nlink == 1);
+	CHECK(close(fd) == 0);
+
+	fd = open("/tmp/aster_tmpfile", O_RDONLY);
+	CHECK(fd >= 0);
+	CHECK(read(fd, buf, sizeof(buf)) == 5 && memcmp(buf, "hello", 5) == 0);
+	CHECK(close(fd) == 0);
+	CHECK(unlink("/tmp/aster_tmpfile") == 0);
+}
+
+int main(void)
+{
+	test_shmget();
+	test_shmat_fork();
+	test_shmat_addr();
+	test_shm_open();
+	test_shm_statfs();
+	test_tmpfs_size();
+	test_tmpfs_nr_inodes();
+	test_tmpfile();
+
+	printf("All shm tests passed.\n");
+	return 0;
+}
@@ -0,0 +1,88 @@
+// SPDX-License-Identifier: MPL-2.0
+
+#define _GNU_SOURCE
+
+#include <errno.h>
+#include <signal.h>
+#include <stdio.h>
+#include <stdlib.h>
+#include <string.h>
+#include <unistd.h>
+#include <sys/resource.h>
+#include <sys/wait.h>
+
+#define FRAME_SIZE (64 * 1024)
+#define BIG_BUF_SIZE (2 * 1024 * 1024)
+
+#define CHECK(cond)                                                       \
+	do {                                                              \
+		if (!(cond)) {                                            \
+			fprintf(stderr, "%s:%d: check failed: %s (%s)\n", \
+				__FILE__, __LINE__, #cond,                \
+				strerror(errno));                         \
+			exit(1);                                          \
+		}                                                         \
+	} while (0)
+
+// Uses `depth * FRAME_SIZE` bytes of the stack, touching every frame.
+static int recurse(int depth)
+{
+	volatile char frame[FRAME_SIZE];
+
+	frame[0] = (char)depth;
+	frame[FRAME_SIZE - 1] = (char)depth;
+	if (depth == 0)
+		return 0;
+	return recurse(depth - 1) + frame[0] - frame[FRAME_SIZE - 1];
+}
+
+static void test_grow_on_fault(void)
+{
+	// 4 MiB, which is below the default limit of 8 MiB.
+	CHECK(recurse(64) == 0);
+}
+
+// The kernel writes to the stack below the mapped part, which should grow the stack too.
+static void __attribute__((noinline)) test_grow_on_syscall(void)
+{
+	char buf[BIG_BUF_SIZE];
+	int fds[2];
+
+	CHECK(pipe(fds) == 0);
+	CHECK(write(fds[1], "stack", 5) == 5);
+	CHECK(read(fds[0], buf, 5) == 5);
+	CHECK(memcmp(buf, "stack", 5) == 0);
+	CHECK(close(fds[0]) == 0);
+	CHECK(close(fds[1]) == 0);
+}
+
+static void test_overflow_limit(void)
+{
+	struct rlimit limit;
+	pid_t pid;
+	int status;
+
+	pid = fork();
+	CHECK(pid >= 0);
+	if (pid == 0) {
+		CHECK(getrlimit(RLIMIT_STACK, &limit) == 0);
+		limit.rlim_cur = 1024 * 1024;
+		CHECK(setrlimit(RLIMIT_STACK, &limit) == 0);
+		// 8 MiB, which is beyond the limit and the part of the stack
+		// grown by the tests above.
+		recurse(128);
+		exit(0);
+	}
+	CHECK(waitpid(pid, &status, 0) == pid);
+	CHECK(WIFSIGNALED(status) && WTERMSIG(status) == SIGSEGV);
+}
+
+int main(void)
+{
+	test_grow_on_fault();
+	test_grow_on_syscall();
+	test_overflow_limit();
+
+	printf("All stack tests passed.\n");
+	return 0;
+}
@@ -0,0 +1,273 @@
+// SPDX-License-Identifier: MPL-2.0
+
+#define _GNU_SOURCE
+
+#include <errno.h>
+#include <fcntl.h>
+#include <stdint.h>
+#include <stdio.h>
+#include <stdlib.h>
+#include <string.h>
+#include <unistd.h>
+#include <sys/mman.h>
+#include <sys/swap.h>
+#include <sys/wait.h>
+
+#define PAGE_SIZE 4096
+#define NR_PAGES 1024
+#define SWAP_KB ((NR_PAGES - 1) * PAGE_SIZE / 1024)
+// The pages are written to the swap file without the page cache, which is not supported
+// by tmpfs.
+#define SWAP_FILE "/ext2/swap_test_file"
+#define TMPFS_SWAP_FILE "/tmp/swap_test_file"
+
+// The swap area for the test under memory pressure, which is larger than the victim.
+#define NR_PRESSURE_PAGES 8192
+#define VICTIM_SIZE (16 << 20)
+// The memory is exhausted with shared mappings, which are never swapped out, so that
+// only the victim can be swapped out in the process.
+#define CHUNK_SIZE (16 << 20)
+#define MAX_CHUNKS 4096
+
+#define CHECK(cond)                                                       \
+	do {                                                              \
+		if (!(cond)) {                                            \
+			fprintf(stderr, "%s:%d: check failed: %s (%s)\n", \
+				__FILE__, __LINE__, #cond,                \
+				strerror(errno));                         \
+			exit(1);                                          \
+		}                                                         \
+	} while (0)
+
+// The header of swap areas in the format of `mkswap`.
+struct swap_header {
+	char bootbits[1024];
+	uint32_t version;
+	uint32_t last_page;
+	uint32_t nr_badpages;
+};
+
+// Creates the swap file of `nr_pages` pages, with the signature only if `with_magic` is
+// set.
+static void make_swap_file(const char *path, int nr_pages, int with_magic)
+{
+	static char page[PAGE_SIZE];
+	struct swap_header *header = (struct swap_header *)page;
+	int fd = open(path, O_CREAT | O_TRUNC | O_WRONLY, 0600);
+
+	CHECK(fd >= 0);
+	memset(page, 0, sizeof(page));
+	header->version = 1;
+	header->last_page = nr_pages - 1;
+	if (with_magic)
+		memcpy(page + PAGE_SIZE - 10, "SWAPSPACE2", 10);
+	CHECK(write(fd, page, PAGE_SIZE) == PAGE_SIZE);
+	memset(page, 0, sizeof(page));
+	for (int i = 1; i < nr_pages; i++)
+		CHECK(write(fd, page, PAGE_SIZE) == PAGE_SIZE);
+	CHECK(close(fd) == 0);
+}
+
+// Returns the value of the field in `/proc/meminfo` in kB, or -1 on errors.
+static long meminfo_kb(const char *field)
+{
+	char line[256];
+	size_t len = strlen(field);
+	long kb = -1;
+	FILE *file = fopen("/proc/meminfo", "r");
+
+	if (file == NULL)
+		return -1;
+	while (fgets(line, sizeof(line), file) != NULL) {
+		if (strncmp(line, field, len) == 0 && line[len] == ':') {
+			sscanf(line + len + 1, "%ld", &kb);
+			break;
+		}
+	}
+	fclose(file);
+	return kb;
+}
+
+// Finds the swap file in `/proc/swaps` and returns its size and priority.
+static int find_swap_file(long *size, long *priority)
+{
+	char line[256], path[128], type[16];
+	long used;
+	int found = 0;
+	FILE *file = fopen("/proc/swaps", "r");
+
+	CHECK(file != NULL);
+	// Skip the header.
+	CHECK(fgets(line, sizeof(line), file) != NULL);
+	while (fgets(line, sizeof(line), file) != NULL) {
+		CHECK(sscanf(line, "%127s %15s %ld %ld %ld", path, type, size,
+			     &used, priority) == 5);
+		if (strcmp(path, SWAP_FILE) == 0) {
+			CHECK(strcmp(type, "file") == 0);
+			found = 1;
+			break;
+		}
+	}
+	fclose(file);
+	return found;
+}
+
+static void test_invalid(void)
+{
+	errno = 0;
+	CHECK(swapon("/tmp/no_such_swap_file", 0) == -1 && errno == ENOENT);
+	errno = 0;
+	CHECK(swapon("/tmp", 0) == -1 && errno == EINVAL);
+
+	make_swap_file(SWAP_FILE, NR_PAGES, 0);
+	errno = 0;
+	CHECK(swapon(SWAP_FILE, 0) == -1 && errno == EINVAL);
+	errno = 0;
+	CHECK(swapoff(SWAP_FILE) == -1 && errno == EINVAL);
+
+	make_swap_file(TMPFS_SWAP_FILE, NR_PAGES, 1);
+	errno = 0;
+	CHECK(swapon(TMPFS_SWAP_FILE, 0) == -1 && errno == EINVAL);
+	CHECK(unlink(TMPFS_SWAP_FILE) == 0);
+}
+
+static void test_swapon_swapoff(void)
+{
+	long total = meminfo_kb("SwapTotal");
+	long size, priority;
+
+	CHECK(total >= 0 && meminfo_kb("SwapFree") <= total);
+	make_swap_file(SWAP_FILE, NR_PAGES, 1);
+
+	CHECK(swapon(SWAP_FILE, SWAP_FLAG_PREFER | 5) == 0);
+	errno = 0;
+	CHECK(swapon(SWAP_FILE, 0) == -1 && errno == EBUSY);
+	CHECK(find_swap_file(&size, &priority));
+	CHECK(size == SWAP_KB);
+	CHECK(priority == 5);
+	CHECK(meminfo_kb("SwapTotal") - total == SWAP_KB);
+
+	CHECK(swapoff(SWAP_FILE) == 0);
+	CHECK(!find_swap_file(&size, &priority));
+	CHECK(meminfo_kb("SwapTotal") == total);
+	errno = 0;
+	CHECK(swapoff(SWAP_FILE) == -1 && errno == EINVAL);
+}
+
+// Returns the swapped size in kB of the mapping that contains `addr` in
+// `/proc/self/smaps`.
+static long swapped_kb(void *addr)
+{
+	char line[256];
+	unsigned long start, end;
+	int in_mapping = 0;
+	long kb = -1;
+	FILE *file = fopen("/proc/self/smaps", "r");
+
+	CHECK(file != NULL);
+	while (fgets(line, sizeof(line), file) != NULL) {
+		// The field names may also start with hexadecimal digits, e.g., `Anonymous`.
+		if (sscanf(line, "%lx-%lx", &start, &end) == 2) {
+			in_mapping = start <= (unsigned long)addr &&
+				     (unsigned long)addr < end;
+			continue;
+		}
+		if (in_mapping && sscanf(line, "Swap: %ld kB", &kb) == 1)
+			break;
+	}
+	fclose(file);
+	CHECK(kb >= 0);
+	return kb;
+}
+
+static void fill_page(char *page, long idx)
+{
+	memset(page, (int)(idx % 251) + 1, PAGE_SIZE);
+	memcpy(page, &idx, sizeof(idx));
+}
+
+static int check_page(const char *page, long idx)
+{
+	long stored;
+
+	memcpy(&stored, page, sizeof(stored));
+	if (stored != idx)
+		return 0;
+	for (int i = sizeof(stored); i < PAGE_SIZE; i++) {
+		if (page[i] != (char)(idx % 251 + 1))
+			return 0;
+	}
+	return 1;
+}
+
+// Fills the victim, exhausts the memory until some of the victim is swapped out, and
+// checks the victim after it is faulted in from the swap area.
+static void swap_out_victim(void)
+{
+	static char *chunks[MAX_CHUNKS];
+	long nr_victim_pages = VICTIM_SIZE / PAGE_SIZE;
+	int nr_chunks = 0;
+	long swapped;
+	char *victim = mmap(NULL, VICTIM_SIZE, PROT_READ | PROT_WRITE,
+			    MAP_PRIVATE | MAP_ANONYMOUS, -1, 0);
+
+	CHECK(victim != MAP_FAILED);
+	for (long i = 0; i < nr_victim_pages; i++)
+		fill_page(victim + i * PAGE_SIZE, i);
+	CHECK(swapped_kb(victim) == 0);
+
+	while ((swapped = swapped_kb(victim)) == 0) {
+		char *chunk;
+
+		CHECK(nr_chunks < MAX_CHUNKS);
+		chunk = mmap(NULL, CHUNK_SIZE, PROT_READ | PROT_WRITE,
+			     MAP_SHARED | MAP_ANONYMOUS, -1, 0);
+		CHECK(chunk != MAP_FAILED);
+		for (long off = 0; off < CHUNK_SIZE; off += PAGE_SIZE)
+			chunk[off] = 1;
+		chunks[nr_chunks++] = chunk;
+	}
+	CHECK(swapped > 0 && swapped <= VICTIM_SIZE / 1024);
+	for (int i = 0; i < nr_chunks; i++)
+		CHECK(munmap(chunks[i], CHUNK_SIZE) == 0);
+
+	for (long i = 0; i < nr_victim_pages; i++)
+		CHECK(check_page(victim + i * PAGE_SIZE, i));
+	CHECK(swapped_kb(victim) == 0);
+	CHECK(munmap(victim, VICTIM_SIZE) == 0);
+}
+
+static void test_swap_under_pressure(void)
+{
+	long total = meminfo_kb("SwapTotal");
+	int status;
+	pid_t pid;
+
+	make_swap_file(SWAP_FILE, NR_PRESSURE_PAGES, 1);
+	CHECK(swapon(SWAP_FILE, 0) == 0);
+
+	// The memory is exhausted in the child, so that the test can go on even if the
+	// child is killed by the OOM killer.
+	pid = fork();
+	CHECK(pid >= 0);
+	if (pid == 0) {
+		swap_out_victim();
+		exit(0);
+	}
+	CHECK(waitpid(pid, &status, 0) == pid);
+	CHECK(WIFEXITED(status) && WEXITSTATUS(status) == 0);
+
+	CHECK(swapoff(SWAP_FILE) == 0);
+	CHECK(meminfo_kb("SwapTotal") == total);
+}
+
+int main(void)
+{
+	test_invalid();
+	test_swapon_swapoff();
+	test_swap_under_pressure();
+	CHECK(unlink(SWAP_FILE) == 0);
+
+	printf("All swap tests passed.\n");
+	return 0;
+}
@@ -0,0 +1,281 @@
+// SPDX-License-Identifier: MPL-2.0
+
+#define _GNU_SOURCE
+
+#include <errno.h>
+#include <fcntl.h>
+#include <linux/userfaultfd.h>
+#include <poll.h>
+#include <pthread.h>
+#include <stdio.h>
+#include <stdlib.h>
+#include <string.h>
+#include <unistd.h>
+#include <sys/ioctl.h>
+#include <sys/mman.h>
+#include <sys/syscall.h>
+
+#define PAGE_SIZE 4096
+#define NR_PAGES 4
+#define BUF_SIZE (NR_PAGES * PAGE_SIZE)
+
+static char src_page[PAGE_SIZE];
+
+static int create_uffd(int flags)
+{
+	struct uffdio_api api = { .api = UFFD_API, .features = 0 };
+	int uffd = syscall(SYS_userfaultfd, flags);
+
+	if (uffd < 0) {
+		perror("userfaultfd failed");
+		exit(1);
+	}
+	if (ioctl(uffd, UFFDIO_API, &api) != 0) {
+		perror("UFFDIO_API failed");
+		exit(1);
+	}
+	if (!(api.ioctls & (1ULL << _UFFDIO_REGISTER))) {
+		fprintf(stderr, "UFFDIO_REGISTER is not supported\n");
+		exit(1);
+	}
+	return uffd;
+}
+
+static char *map_and_register(int uffd)
+{
+	struct uffdio_register reg;
+	char *addr = mmap(NULL, BUF_SIZE, PROT_READ | PROT_WRITE,
+			  MAP_PRIVATE | MAP_ANONYMOUS, -1, 0);
+
+	if (addr == MAP_FAILED) {
+		perror("mmap failed");
+		exit(1);
+	}
+	reg.range.start = (unsigned long)addr;
+	reg.range.len = BUF_SIZE;
+	reg.mode = UFFDIO_REGISTER_MODE_MISSING;
+	if (ioctl(uffd, UFFDIO_REGISTER, &reg) != 0) {
+		perror("UFFDIO_REGISTER failed");
+		exit(1);
+	}
+	if (!(reg.ioctls & (1ULL << _UFFDIO_COPY)) ||
+	    !(reg.ioctls & (1ULL << _UFFDIO_ZEROPAGE))) {
+		fprintf(stderr, "UFFDIO_COPY or UFFDIO_ZEROPAGE is missing\n");
+		exit(1);
+	}
+	return addr;
+}
+
+// Resolves `NR_PAGES` page faults, where the odd pages are zero pages
+static void *fault_handler(void *arg)
+{
+	int uffd = *(int *)arg;
+	struct uffd_msg msg;
+	struct pollfd pfd = { .fd = uffd, .events = POLLIN };
+
+	for (int i = 0; i < NR_PAGES; i++) {
+		if (poll(&pfd, 1, -1) != 1 || !(pfd.revents & POLLIN)) {
+			fprintf(stderr, "poll failed\n");
+			exit(1);
+		}
+		if (read(uffd, &msg, sizeof(msg)) != sizeof(msg)) {
+			perror("read failed");
+			exit(1);
+		}
+		if (msg.event != UFFD_EVENT_PAGEFAULT ||
+		    msg.arg.pagefault.address % PAGE_SIZE != 0) {
+			fprintf(stderr, "invalid fault message\n");
+			exit(1);
+		}
+
+		unsigned long addr = msg.arg.pagefault.address;
+		if ((addr / PAGE_SIZE) % 2 == 0) {
+			struct uffdio_copy copy = {
+				.dst = addr,
+				.src = (unsigned long)src_page,
+				.len = PAGE_SIZE,
+				.mode = 0,
+			};
+			if (ioctl(uffd, UFFDIO_COPY, &copy) != 0 ||
+			    copy.copy != PAGE_SIZE) {
+				perror("UFFDIO_COPY failed");
+				exit(1);
+			}
+		} else {
+			struct uffdio_zeropage zero = {
+				.range = { .start = addr, .len = PAGE_SIZE },
+				.mode = 0,
+			};
+			if (ioctl(uffd, UFFDIO_ZEROPAGE, &zero) != 0 ||
+			    zero.zeropage != PAGE_SIZE) {
+				perror("UFFDIO_ZEROPAGE failed");
+				exit(1);
+			}
+		}
+	}
+	return NULL;
+}
+
+static void test_fault_handling(void)
+{
+	int uffd = create_uffd(O_CLOEXEC | O_NONBLOCK);
+	char *addr = map_and_register(uffd);
+	pthread_t handler;
+
+	memset(src_page, 'a', PAGE_SIZE);
+	if (pthread_create(&handler, NULL, fault_handler, &uffd) != 0) {
+		fprintf(stderr, "pthread_create failed\n");
+		exit(1);
+	}
+
+	for (int i = 0; i < NR_PAGES; i++) {
+		char *page = addr + i * PAGE_SIZE;
+		char expected =
+			((unsigned long)page / PAGE_SIZE) % 2 == 0 ? 'a' : 0;
+		if (page[PAGE_SIZE - 1] != expected) {
+			fprintf(stderr, "page %d is not resolved correctly\n",
+				i);
+			exit(1);
+		}
+	}
+	pthread_join(handler, NULL);
+
+	munmap(addr, BUF_SIZE);
+	close(uffd);
+}
+
+static void test_fill_in_advance(void)
+{
+	int uffd = create_uffd(O_NONBLOCK);
+	char *addr = map_and_register(uffd);
+	struct uffd_msg msg;
+	struct uffdio_copy copy = {
+		.dst = (unsigned long)addr,
+		.src = (unsigned long)src_page,
+		.len = PAGE_SIZE,
+		.mode = UFFDIO_COPY_MODE_DONTWAKE,
+	};
+	struct uffdio_range range = {
+		.start = (unsigned long)addr,
+		.len = BUF_SIZE,
+	};
+
+	memset(src_page, 'b', PAGE_SIZE);
+	if (ioctl(uffd, UFFDIO_COPY, &copy) != 0 || copy.copy != PAGE_SIZE) {
+		perror("UFFDIO_COPY failed");
+		exit(1);
+	}
+	if (addr[0] != 'b') {
+		fprintf(stderr, "the copied page is not seen\n");
+		exit(1);
+	}
+
+	// The page is not missing any more
+	if (ioctl(uffd, UFFDIO_COPY, &copy) != -1 || errno != EEXIST ||
+	    copy.copy != -EEXIST) {
+		fprintf(stderr, "copying to a present page should fail\n");
+		exit(1);
+	}
+
+	// No fault is reported
+	if (read(uffd, &msg, sizeof(msg)) != -1 || errno != EAGAIN) {
+		fprintf(stderr, "no fault should be reported\n");
+		exit(1);
+	}
+	if (read(uffd, &msg, sizeof(msg) - 1) != -1 || errno != EINVAL) {
+		fprintf(stderr, "reading a short buffer should fail\n");
+		exit(1);
+	}
+
+	// Unregistered pages are resolved by the kernel
+	if (ioctl(uffd, UFFDIO_UNREGISTER, &range) != 0) {
+		perror("UFFDIO_UNREGISTER failed");
+		exit(1);
+	}
+	if (addr[PAGE_SIZE] != 0) {
+		fprintf(stderr, "the unregistered page is not a zero page\n");
+		exit(1);
+	}
+	copy.dst = (unsigned long)addr + 2 * PAGE_SIZE;
+	if (ioctl(uffd, UFFDIO_COPY, &copy) != -1 || errno != ENOENT) {
+		fprintf(stderr, "copying to unregistered pages should fail\n");
+		exit(1);
+	}
+
+	munmap(addr, BUF_SIZE);
+	close(uffd);
+}
+
+static void test_invalid(void)
+{
+	struct uffdio_api api = { .api = UFFD_API, .features = 0 };
+	struct uffdio_register reg;
+	int uffd = syscall(SYS_userfaultfd, O_CLOEXEC);
+	char *addr;
+
+	if (uffd < 0) {
+		perror("userfaultfd failed");
+		exit(1);
+	}
+	addr = mmap(NULL, BUF_SIZE, PROT_READ | PROT_WRITE,
+		    MAP_PRIVATE | MAP_ANONYMOUS, -1, 0);
+	if (addr == MAP_FAILED) {
+		perror("mmap failed");
+		exit(1);
+	}
+	reg.range.start = (unsigned long)addr;
+	reg.range.len = BUF_SIZE;
+	reg.mode = UFFDIO_REGISTER_MODE_MISSING;
+
+	// The API handshake must be done first
+	if (ioctl(uffd, UFFDIO_REGISTER, &reg) != -1 || errno != EINVAL) {
+		fprintf(stderr, "registering before UFFDIO_API should fail\n");
+		exit(1);
+	}
+	if (ioctl(uffd, UFFDIO_API, &api) != 0) {
+		perror("UFFDIO_API failed");
+		exit(1);
+	}
+	if (ioctl(uffd, UFFDIO_API, &api) != -1 || errno != EINVAL) {
+		fprintf(stderr, "the second UFFDIO_API should fail\n");
+		exit(1);
+	}
+
+	reg.range.start = (unsigned long)addr + 1;
+	if (ioctl(uffd, UFFDIO_REGISTER, &reg) != -1 || errno != EINVAL) {
+		fprintf(stderr, "registering unaligned ranges should fail\n");
+		exit(1);
+	}
+
+	// The holes are skipped, but there must be some mappings
+	reg.range.start = (unsigned long)addr;
+	munmap(addr + PAGE_SIZE, PAGE_SIZE);
+	if (ioctl(uffd, UFFDIO_REGISTER, &reg) != 0) {
+		perror("registering ranges with holes failed");
+		exit(1);
+	}
+	reg.range.start = (unsigned long)addr + PAGE_SIZE;
+	reg.range.len = PAGE_SIZE;
+	if (ioctl(uffd, UFFDIO_REGISTER, &reg) != -1 || errno != EINVAL) {
+		fprintf(stderr, "registering unmapped ranges should fail\n");
+		exit(1);
+	}
+
+	if (syscall(SYS_userfaultfd, 0x10) != -1 || errno != EINVAL) {
+		fprintf(stderr, "invalid flags should fail\n");
+		exit(1);
+	}
+
+	munmap(addr, BUF_SIZE);
+	close(uffd);
+}
+
+int main()
+{
+	test_fault_handling();
+	test_fill_in_advance();
+	test_invalid();
+
+	printf("userfaultfd test passed\n");
+	return 0;
+}
@@ -0,0 +1,126 @@
+// SPDX-License-Identifier: MPL-2.0
+
+#include <unistd.h>
+#include <net/if.h>
+#include <sys/ioctl.h>
+#include <sys/poll.h>
+#include <sys/socket.h>
+#include <netinet/in.h>
+#include <arpa/inet.h>
+
+#include "test.h"
+
+#define S_PORT htons(0x2345)
+
+static struct sockaddr_in sk_addr;
+static int sk_listen;
+static int sk_connected;
+static int sk_accepted;
+
+static int set_iface_addr(unsigned long cmd, const char *addr)
+{
+	struct ifreq ifr;
+	struct sockaddr_in *sin = (struct sockaddr_in *)&ifr.ifr_addr;
+
+	memset(&ifr, 0, sizeof(ifr));
+	strcpy(ifr.ifr_name, "lo");
+	sin->sin_family = AF_INET;
+	inet_aton(addr, &sin->sin_addr);
+	return ioctl(sk_listen, cmd, &ifr);
+}
+
+static int get_iface_addr(unsigned long cmd, const char *name,
+			  struct in_addr *addr)
+{
+	struct ifreq ifr;
+	int ret;
+
+	memset(&ifr, 0, sizeof(ifr));
+	strcpy(ifr.ifr_name, name);
+	ret = ioctl(sk_listen, cmd, &ifr);
+	*addr = ((struct sockaddr_in *)&ifr.ifr_addr)->sin_addr;
+	return ret;
+}
+
+static int accept_connection(void)
+{
+	struct pollfd pfd = { .fd = sk_listen, .events = POLLIN };
+
+	if (poll(&pfd, 1, 1000) != 1)
+		return -1;
+	return accept(sk_listen, NULL, NULL);
+}
+
+static int echo_once(void)
+{
+	struct pollfd pfd = { .fd = sk_accepted, .events = POLLIN };
+	char buf[1];
+
+	if (send(sk_connected, "a", 1, 0) != 1 || poll(&pfd, 1, 1000) != 1)
+		return -1;
+	return recv(sk_accepted, buf, 1, 0);
+}
+
+FN_SETUP(connection)
+{
+	sk_addr.sin_family = AF_INET;
+	sk_addr.sin_port = S_PORT;
+	CHECK(inet_aton("127.0.0.1", &sk_addr.sin_addr));
+
+	sk_listen = CHECK(socket(PF_INET, SOCK_STREAM | SOCK_NONBLOCK, 0));
+	CHECK(bind(sk_listen, (struct sockaddr *)&sk_addr, sizeof(sk_addr)));
+	CHECK(listen(sk_listen, 2));
+
+	sk_connected = CHECK(socket(PF_INET, SOCK_STREAM | SOCK_NONBLOCK, 0));
+	CHECK_WITH(connect(sk_connected, (struct sockaddr *)&sk_addr,
+			   sizeof(sk_addr)),
+		   _ret < 0 && errno == EINPROGRESS);
+	sk_accepted = CHECK(accept_connection());
+}
+END_SETUP()
+
+FN_TEST(get_addr)
+{
+	struct in_addr addr;
+
+	TEST_RES(get_iface_addr(SIOCGIFADDR, "lo", &addr),
+		 addr.s_addr == htonl(INADDR_LOOPBACK));
+	TEST_RES(get_iface_addr(SIOCGIFNETMASK, "lo", &addr),
+		 addr.s_addr == htonl(0xff000000));
+	TEST_ERRNO(get_iface_addr(SIOCGIFADDR, "nosuchif", &addr), ENODEV);
+}
+END_TEST()
+
+FN_TEST(keep_addr)
+{
+	// The connection survives if the address is unchanged.
+	TEST_SUCC(set_iface_addr(SIOCSIFNETMASK, "255.255.0.0"));
+	TEST_RES(echo_once(), _ret == 1);
+	TEST_SUCC(set_iface_addr(SIOCSIFADDR, "127.0.0.1"));
+	TEST_RES(echo_once(), _ret == 1);
+	TEST_ERRNO(set_iface_addr(SIOCSIFNETMASK, "255.0.255.0"), EINVAL);
+}
+END_TEST()
+
+FN_TEST(change_addr)
+{
+	char buf[1];
+	int sk;
+
+	// The connection on the old address is aborted.
+	TEST_SUCC(set_iface_addr(SIOCSIFADDR, "127.0.0.2"));
+	TEST_ERRNO(recv(sk_connected, buf, 1, 0), ECONNABORTED);
+	TEST_ERRNO(send(sk_connected, "a", 1, 0), ECONNABORTED);
+
+	// The listening socket accepts connections on the new address.
+	sk = TEST_SUCC(socket(PF_INET, SOCK_STREAM | SOCK_NONBLOCK, 0));
+	sk_addr.sin_addr.s_addr = htonl(INADDR_LOOPBACK + 1);
+	TEST_ERRNO(connect(sk, (struct sockaddr *)&sk_addr, sizeof(sk_addr)),
+		   EINPROGRESS);
+	TEST_SUCC(close(accept_connection()));
+	TEST_SUCC(close(sk));
+
+	TEST_SUCC(set_iface_addr(SIOCSIFADDR, "127.0.0.1"));
+	TEST_SUCC(set_iface_addr(SIOCSIFNETMASK, "255.0.0.0"));
+}
+END_TEST()
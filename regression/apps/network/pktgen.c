@@ -0,0 +1,81 @@
+// SPDX-License-Identifier: MPL-2.0
+
+#define _GNU_SOURCE
+
+#include <fcntl.h>
+#include <unistd.h>
+
+#include "test.h"
+
+#define PGCTRL "/proc/net/pktgen/pgctrl"
+#define PKTGEN_LO "/proc/net/pktgen/lo"
+
+static int write_str(const char *path, const char *str)
+{
+	int fd, ret;
+
+	fd = CHECK(open(path, O_WRONLY));
+	ret = write(fd, str, strlen(str));
+	CHECK(close(fd));
+	return ret;
+}
+
+static void read_str(const char *path, char *buf, size_t len)
+{
+	int fd;
+	ssize_t ret;
+
+	fd = CHECK(open(path, O_RDONLY));
+	ret = CHECK(read(fd, buf, len - 1));
+	buf[ret] = '\0';
+	CHECK(close(fd));
+}
+
+static int is_running(void)
+{
+	char buf[256];
+
+	read_str(PKTGEN_LO, buf, sizeof(buf));
+	return strstr(buf, "State: running") != NULL;
+}
+
+FN_TEST(config)
+{
+	TEST_RES(write_str(PKTGEN_LO, "count 100\npkt_size 128\n"), _ret == 23);
+	TEST_ERRNO(write_str(PKTGEN_LO, "unknown 1"), EINVAL);
+	TEST_ERRNO(write_str(PKTGEN_LO, "pkt_size 16"), EINVAL);
+	TEST_ERRNO(write_str(PKTGEN_LO, "dst 127.0.0"), EINVAL);
+	TEST_SUCC(write_str(PKTGEN_LO, "dst 127.0.0.1"));
+	TEST_ERRNO(write_str(PGCTRL, "start none"), ENOENT);
+	TEST_ERRNO(write_str(PGCTRL, "restart lo"), EINVAL);
+}
+END_TEST()
+
+FN_TEST(generate)
+{
+	char buf[256];
+
+	TEST_SUCC(write_str(PGCTRL, "start lo"));
+	while (is_running())
+		usleep(1000);
+
+	read_str(PKTGEN_LO, buf, sizeof(buf));
+	TEST_RES(strstr(buf, "generated 100  dropped 0"), _ret != NULL);
+}
+END_TEST()
+
+FN_TEST(stop)
+{
+	TEST_SUCC(write_str(PKTGEN_LO, "count 0"));
+	TEST_SUCC(write_str(PKTGEN_LO, "rate 100"));
+	TEST_SUCC(write_str(PGCTRL, "start lo"));
+	TEST_ERRNO(write_str(PGCTRL, "start lo"), EBUSY);
+	TEST_ERRNO(write_str(PKTGEN_LO, "count 1"), EBUSY);
+
+	TEST_SUCC(write_str(PGCTRL, "stop lo"));
+	while (is_running())
+		usleep(1000);
+	TEST_SUCC(write_str(PKTGEN_LO, "count 1000"));
+	TEST_SUCC(write_str(PKTGEN_LO, "rate 0"));
+}
+END_TEST()
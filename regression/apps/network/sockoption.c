@@ -72,6 +72,16 @@ int main()
 		exit(EXIT_FAILURE);
 	}
 
+	// No packet has been received by an unconnected socket
+	int incoming_cpu;
+	socklen_t incoming_cpu_len = sizeof(incoming_cpu);
+	if (getsockopt(sockfd, SOL_SOCKET, SO_INCOMING_CPU, &incoming_cpu,
+		       &incoming_cpu_len) < 0 ||
+	    incoming_cpu != -1) {
+		perror("Getting SO_INCOMING_CPU option failed.");
+		exit(EXIT_FAILURE);
+	}
+
 	// Close socket
 	close(sockfd);
 
@@ -5,6 +5,7 @@
 #include <sys/socket.h>
 #include <sys/poll.h>
 #include <netinet/in.h>
+#include <netinet/tcp.h>
 #include <arpa/inet.h>
 
 #include "test.h"
@@ -184,6 +185,59 @@ FN_TEST(send_and_recv)
 }
 END_TEST()
 
+FN_TEST(tcp_info)
+{
+	struct tcp_info info;
+	socklen_t infolen = sizeof(info);
+	// The fields that follow the glibc definition of `struct tcp_info`
+	struct {
+		struct tcp_info base;
+		uint64_t pacing_rate;
+		uint64_t max_pacing_rate;
+		uint64_t bytes_acked;
+		uint64_t bytes_received;
+		uint32_t segs_out;
+		uint32_t segs_in;
+	} ext_info;
+	socklen_t ext_infolen = sizeof(ext_info);
+
+	TEST_RES(getsockopt(sk_unbound, IPPROTO_TCP, TCP_INFO, &info, &infolen),
+		 infolen == sizeof(info) && info.tcpi_state == TCP_CLOSE);
+
+	TEST_RES(getsockopt(sk_listen, IPPROTO_TCP, TCP_INFO, &info, &infolen),
+		 infolen == sizeof(info) && info.tcpi_state == TCP_LISTEN);
+
+	TEST_RES(getsockopt(sk_connected, IPPROTO_TCP, TCP_INFO, &info,
+			    &infolen),
+		 infolen == sizeof(info) &&
+			 info.tcpi_state == TCP_ESTABLISHED &&
+			 info.tcpi_snd_mss > 0 && info.tcpi_rto > 0);
+
+	TEST_RES(getsockopt(sk_accepted, IPPROTO_TCP, TCP_INFO, &info,
+			    &infolen),
+		 infolen == sizeof(info) &&
+			 info.tcpi_state == TCP_ESTABLISHED &&
+			 info.tcpi_snd_mss > 0 && info.tcpi_rto > 0);
+
+	// The segments of the handshake are counted
+	TEST_RES(getsockopt(sk_connected, IPPROTO_TCP, TCP_INFO, &ext_info,
+			    &ext_infolen),
+		 ext_infolen == sizeof(ext_info) && ext_info.segs_out > 0 &&
+			 ext_info.segs_in > 0 &&
+			 ext_info.base.tcpi_total_retrans == 0);
+
+	// The structure is truncated if the buffer is too short
+	infolen = 1;
+	TEST_RES(getsockopt(sk_connected, IPPROTO_TCP, TCP_INFO, &info,
+			    &infolen),
+		 infolen == 1 && info.tcpi_state == TCP_ESTABLISHED);
+
+	TEST_ERRNO(setsockopt(sk_connected, IPPROTO_TCP, TCP_INFO, &info,
+			      sizeof(info)),
+		   ENOPROTOOPT);
+}
+END_TEST()
+
 FN_TEST(bind)
 {
 	struct sockaddr *psaddr = (struct sockaddr *)&sk_addr;
@@ -280,7 +334,8 @@ FN_TEST(async_connect)
 			   sizeof(sk_addr)),
 		   EINPROGRESS);
 
-	TEST_RES(poll(&pfd, 1, 60), pfd.revents & POLLOUT);
+	TEST_RES(poll(&pfd, 1, 60),
+		 (pfd.revents & (POLLOUT | POLLERR)) == (POLLOUT | POLLERR));
 
 	TEST_RES(getsockopt(sk_bound, SOL_SOCKET, SO_ERROR, &err, &errlen),
 		 errlen == sizeof(err) && err == ECONNREFUSED);
@@ -288,5 +343,34 @@ FN_TEST(async_connect)
 	// Reading the socket error will cause it to be cleared
 	TEST_RES(getsockopt(sk_bound, SOL_SOCKET, SO_ERROR, &err, &errlen),
 		 errlen == sizeof(err) && err == 0);
+
+	TEST_RES(poll(&pfd, 1, 0), (pfd.revents & POLLERR) == 0);
+}
+END_TEST()
+
+FN_TEST(syn_cnt)
+{
+	int cnt;
+	socklen_t cntlen = sizeof(cnt);
+
+	TEST_RES(getsockopt(sk_unbound, IPPROTO_TCP, TCP_SYNCNT, &cnt, &cntlen),
+		 cntlen == sizeof(cnt) && cnt == 6);
+
+	cnt = 0;
+	TEST_ERRNO(setsockopt(sk_unbound, IPPROTO_TCP, TCP_SYNCNT, &cnt,
+			      sizeof(cnt)),
+		   EINVAL);
+
+	cnt = 128;
+	TEST_ERRNO(setsockopt(sk_unbound, IPPROTO_TCP, TCP_SYNCNT, &cnt,
+			      sizeof(cnt)),
+		   EINVAL);
+
+	cnt = 2;
+	TEST_SUCC(setsockopt(sk_unbound, IPPROTO_TCP, TCP_SYNCNT, &cnt,
+			     sizeof(cnt)));
+
+	TEST_RES(getsockopt(sk_unbound, IPPROTO_TCP, TCP_SYNCNT, &cnt, &cntlen),
+		 cntlen == sizeof(cnt) && cnt == 2);
 }
 END_TEST()
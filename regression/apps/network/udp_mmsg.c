@@ -0,0 +1,198 @@
+// SPDX-License-Identifier: MPL-2.0
+
+#define _GNU_SOURCE
+
+#include <unistd.h>
+#include <sys/socket.h>
+#include <netinet/in.h>
+#include <arpa/inet.h>
+#include <netinet/udp.h>
+
+#include "test.h"
+
+#define NR_MSGS 4
+#define MSG_SIZE 16
+
+#ifndef UDP_SEGMENT
+#define UDP_SEGMENT 103
+#endif
+
+static struct sockaddr_in sk_addr;
+static int sk_send;
+static int sk_recv;
+
+FN_SETUP(sockets)
+{
+	sk_addr.sin_family = AF_INET;
+	sk_addr.sin_port = htons(0x4321);
+	CHECK(inet_aton("127.0.0.1", &sk_addr.sin_addr));
+
+	sk_recv = CHECK(socket(PF_INET, SOCK_DGRAM | SOCK_NONBLOCK, 0));
+	CHECK(bind(sk_recv, (struct sockaddr *)&sk_addr, sizeof(sk_addr)));
+
+	sk_send = CHECK(socket(PF_INET, SOCK_DGRAM | SOCK_NONBLOCK, 0));
+}
+END_SETUP()
+
+FN_TEST(sendmmsg)
+{
+	char bufs[NR_MSGS][MSG_SIZE];
+	struct iovec iovs[NR_MSGS];
+	struct mmsghdr msgs[NR_MSGS];
+	int i;
+
+	memset(msgs, 0, sizeof(msgs));
+	for (i = 0; i < NR_MSGS; i++) {
+		memset(bufs[i], 'a' + i, MSG_SIZE);
+		iovs[i].iov_base = bufs[i];
+		iovs[i].iov_len = MSG_SIZE - i;
+		msgs[i].msg_hdr.msg_name = &sk_addr;
+		msgs[i].msg_hdr.msg_namelen = sizeof(sk_addr);
+		msgs[i].msg_hdr.msg_iov = &iovs[i];
+		msgs[i].msg_hdr.msg_iovlen = 1;
+	}
+
+	TEST_RES(sendmmsg(sk_send, msgs, NR_MSGS, 0),
+		 _ret == NR_MSGS && msgs[0].msg_len == MSG_SIZE &&
+			 msgs[NR_MSGS - 1].msg_len == MSG_SIZE - NR_MSGS + 1);
+}
+END_TEST()
+
+FN_TEST(recvmmsg)
+{
+	char bufs[NR_MSGS + 1][MSG_SIZE];
+	struct iovec iovs[NR_MSGS + 1];
+	struct mmsghdr msgs[NR_MSGS + 1];
+	struct sockaddr_in saddr;
+	int i;
+
+	memset(msgs, 0, sizeof(msgs));
+	for (i = 0; i < NR_MSGS + 1; i++) {
+		iovs[i].iov_base = bufs[i];
+		iovs[i].iov_len = MSG_SIZE;
+		msgs[i].msg_hdr.msg_iov = &iovs[i];
+		msgs[i].msg_hdr.msg_iovlen = 1;
+	}
+	msgs[0].msg_hdr.msg_name = &saddr;
+	msgs[0].msg_hdr.msg_namelen = sizeof(saddr);
+
+	TEST_RES(recvmmsg(sk_recv, msgs, NR_MSGS + 1, MSG_WAITFORONE, NULL),
+		 _ret == NR_MSGS && msgs[0].msg_len == MSG_SIZE &&
+			 msgs[1].msg_len == MSG_SIZE - 1 &&
+			 bufs[1][0] == 'b' &&
+			 msgs[0].msg_hdr.msg_namelen == sizeof(saddr));
+
+	TEST_ERRNO(recvmmsg(sk_recv, msgs, NR_MSGS, 0, NULL), EAGAIN);
+}
+END_TEST()
+
+FN_TEST(sendmmsg_fault)
+{
+	char buf[MSG_SIZE];
+	struct iovec iovs[2];
+	struct mmsghdr msgs[2];
+	int i;
+
+	memset(buf, 'x', sizeof(buf));
+	memset(msgs, 0, sizeof(msgs));
+	for (i = 0; i < 2; i++) {
+		iovs[i].iov_base = buf;
+		iovs[i].iov_len = MSG_SIZE;
+		msgs[i].msg_hdr.msg_name = &sk_addr;
+		msgs[i].msg_hdr.msg_namelen = sizeof(sk_addr);
+		msgs[i].msg_hdr.msg_iov = &iovs[i];
+		msgs[i].msg_hdr.msg_iovlen = 1;
+	}
+	iovs[1].iov_base = (void *)1;
+
+	// The first message is sent before the second one faults
+	TEST_RES(sendmmsg(sk_send, msgs, 2, 0),
+		 _ret == 1 && msgs[0].msg_len == MSG_SIZE);
+	TEST_ERRNO(sendmmsg(sk_send, &msgs[1], 1, 0), EFAULT);
+
+	TEST_RES(recv(sk_recv, buf, sizeof(buf), 0), _ret == MSG_SIZE);
+	TEST_ERRNO(recv(sk_recv, buf, sizeof(buf), 0), EAGAIN);
+}
+END_TEST()
+
+FN_TEST(recvmmsg_timeout)
+{
+	char buf[MSG_SIZE];
+	struct iovec iov;
+	struct mmsghdr msgs[NR_MSGS];
+	struct timespec timeout;
+	int i;
+
+	memset(buf, 'x', sizeof(buf));
+	for (i = 0; i < 3; i++)
+		TEST_RES(sendto(sk_send, buf, sizeof(buf), 0,
+				(struct sockaddr *)&sk_addr, sizeof(sk_addr)),
+			 _ret == sizeof(buf));
+
+	iov.iov_base = buf;
+	iov.iov_len = sizeof(buf);
+	memset(msgs, 0, sizeof(msgs));
+	for (i = 0; i < NR_MSGS; i++) {
+		msgs[i].msg_hdr.msg_iov = &iov;
+		msgs[i].msg_hdr.msg_iovlen = 1;
+	}
+
+	// The expired timeout ends the batch after the first message
+	timeout.tv_sec = 0;
+	timeout.tv_nsec = 0;
+	TEST_RES(recvmmsg(sk_recv, msgs, NR_MSGS, 0, &timeout),
+		 _ret == 1 && msgs[0].msg_len == MSG_SIZE &&
+			 timeout.tv_sec == 0 && timeout.tv_nsec == 0);
+
+	// Without `MSG_WAITFORONE`, a nonblocking socket returns what has arrived
+	timeout.tv_sec = 1;
+	TEST_RES(recvmmsg(sk_recv, msgs, NR_MSGS, 0, &timeout),
+		 _ret == 2 && msgs[1].msg_len == MSG_SIZE);
+
+	timeout.tv_sec = 0;
+	timeout.tv_nsec = 1000000000;
+	TEST_ERRNO(recvmmsg(sk_recv, msgs, NR_MSGS, 0, &timeout), EINVAL);
+}
+END_TEST()
+
+FN_TEST(udp_segment)
+{
+	char buf[MSG_SIZE * 2 + 2];
+	int segment_size = MSG_SIZE;
+	socklen_t optlen = sizeof(segment_size);
+
+	memset(buf, 'x', sizeof(buf));
+
+	TEST_SUCC(setsockopt(sk_send, SOL_UDP, UDP_SEGMENT, &segment_size,
+			     sizeof(segment_size)));
+	segment_size = 0;
+	TEST_RES(getsockopt(sk_send, SOL_UDP, UDP_SEGMENT, &segment_size,
+			    &optlen),
+		 segment_size == MSG_SIZE);
+
+	TEST_RES(sendto(sk_send, buf, sizeof(buf), 0,
+			(struct sockaddr *)&sk_addr, sizeof(sk_addr)),
+		 _ret == sizeof(buf));
+
+	TEST_RES(recv(sk_recv, buf, sizeof(buf), 0), _ret == MSG_SIZE);
+	TEST_RES(recv(sk_recv, buf, sizeof(buf), 0), _ret == MSG_SIZE);
+	TEST_RES(recv(sk_recv, buf, sizeof(buf), 0), _ret == 2);
+	TEST_ERRNO(recv(sk_recv, buf, sizeof(buf), 0), EAGAIN);
+}
+END_TEST()
+
+FN_TEST(udp_segment_too_large)
+{
+	// The payload of a super-datagram must fit in a single IPv4 packet.
+	static char buf[65507 + 1];
+	int segment_size = 1400;
+
+	TEST_SUCC(setsockopt(sk_send, SOL_UDP, UDP_SEGMENT, &segment_size,
+			     sizeof(segment_size)));
+
+	TEST_ERRNO(sendto(sk_send, buf, sizeof(buf), 0,
+			  (struct sockaddr *)&sk_addr, sizeof(sk_addr)),
+		   EINVAL);
+	TEST_ERRNO(recv(sk_recv, buf, sizeof(buf), 0), EAGAIN);
+}
+END_TEST()
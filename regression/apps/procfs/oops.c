@@ -0,0 +1,78 @@
+// SPDX-License-Identifier: MPL-2.0
+
+#define _GNU_SOURCE
+
+#include <errno.h>
+#include <fcntl.h>
+#include <stdio.h>
+#include <stdlib.h>
+#include <string.h>
+#include <unistd.h>
+
+#define CHECK(cond)                                                       \
+	do {                                                              \
+		if (!(cond)) {                                            \
+			fprintf(stderr, "%s:%d: check failed: %s (%s)\n", \
+				__FILE__, __LINE__, #cond,                \
+				strerror(errno));                         \
+			exit(1);                                          \
+		}                                                         \
+	} while (0)
+
+#define TAINT_DIE 128
+
+static long read_knob(const char *name)
+{
+	char path[128], buf[32] = { 0 };
+	int fd;
+
+	snprintf(path, sizeof(path), "/proc/sys/kernel/%s", name);
+	fd = open(path, O_RDONLY);
+	CHECK(fd >= 0);
+	CHECK(read(fd, buf, sizeof(buf) - 1) > 0);
+	CHECK(close(fd) == 0);
+	return strtol(buf, NULL, 10);
+}
+
+static void write_knob(const char *name, long value)
+{
+	char path[128], buf[32];
+	int fd;
+
+	snprintf(path, sizeof(path), "/proc/sys/kernel/%s", name);
+	snprintf(buf, sizeof(buf), "%ld\n", value);
+	fd = open(path, O_WRONLY);
+	CHECK(fd >= 0);
+	CHECK(write(fd, buf, strlen(buf)) == strlen(buf));
+	CHECK(close(fd) == 0);
+}
+
+int main(void)
+{
+	long panic_on_oops = read_knob("panic_on_oops");
+	long tainted = read_knob("tainted");
+	char buf[256];
+	int fd;
+
+	CHECK(panic_on_oops == 0 || panic_on_oops == 1);
+
+	write_knob("panic_on_oops", !panic_on_oops);
+	CHECK(read_knob("panic_on_oops") == !panic_on_oops);
+	write_knob("panic_on_oops", panic_on_oops);
+	CHECK(read_knob("panic_on_oops") == panic_on_oops);
+
+	// The taint flags can be added but never cleared.
+	write_knob("tainted", 0);
+	CHECK(read_knob("tainted") == tainted);
+
+	// Without any oopses, no subsystem is degraded.
+	fd = open("/proc/sys/kernel/degraded_subsystems", O_RDONLY);
+	CHECK(fd >= 0);
+	if (!(tainted & TAINT_DIE)) {
+		CHECK(read(fd, buf, sizeof(buf)) == 0);
+	}
+	CHECK(close(fd) == 0);
+
+	printf("Test passed.\n");
+	return 0;
+}
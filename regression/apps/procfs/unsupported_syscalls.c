@@ -0,0 +1,89 @@
+// SPDX-License-Identifier: MPL-2.0
+
+#define _GNU_SOURCE
+
+#include <errno.h>
+#include <fcntl.h>
+#include <stdio.h>
+#include <stdlib.h>
+#include <string.h>
+#include <unistd.h>
+#include <sys/syscall.h>
+
+#define UNSUPPORTED_SYSCALLS_FILE "/proc/unsupported_syscalls"
+// A syscall number that is not used by Linux.
+#define INVALID_SYSCALL 1000
+
+#define CHECK(cond)                                                       \
+	do {                                                              \
+		if (!(cond)) {                                            \
+			fprintf(stderr, "%s:%d: check failed: %s (%s)\n", \
+				__FILE__, __LINE__, #cond,                \
+				strerror(errno));                         \
+			exit(1);                                          \
+		}                                                         \
+	} while (0)
+
+static char report[16384];
+
+static void read_report(void)
+{
+	int fd;
+	ssize_t len;
+
+	fd = open(UNSUPPORTED_SYSCALLS_FILE, O_RDONLY);
+	CHECK(fd >= 0);
+	len = read(fd, report, sizeof(report) - 1);
+	CHECK(len > 0);
+	report[len] = '\0';
+	CHECK(close(fd) == 0);
+}
+
+static void clear_report(void)
+{
+	int fd;
+
+	fd = open(UNSUPPORTED_SYSCALLS_FILE, O_WRONLY);
+	CHECK(fd >= 0);
+	CHECK(write(fd, "0\n", 2) == 2);
+	CHECK(close(fd) == 0);
+}
+
+int main(int argc, char *argv[])
+{
+	char line[64];
+	char *pos;
+	int i;
+
+	clear_report();
+	read_report();
+	CHECK(strstr(report, "nr\tname\terror\tcount\tbinaries\n") == report);
+	snprintf(line, sizeof(line), "\n%d\t", INVALID_SYSCALL);
+	CHECK(strstr(report, line) == NULL);
+
+	for (i = 0; i < 3; i++) {
+		errno = 0;
+		CHECK(syscall(INVALID_SYSCALL) == -1 && errno == ENOSYS);
+	}
+	// The successful or otherwise failing syscalls are not recorded.
+	errno = 0;
+	CHECK(close(-1) == -1 && errno == EBADF);
+
+	read_report();
+	snprintf(line, sizeof(line), "\n%d\t?\tENOSYS\t3\t", INVALID_SYSCALL);
+	pos = strstr(report, line);
+	CHECK(pos != NULL);
+	// The calls are attributed to this binary.
+	pos += strlen(line);
+	CHECK(strncmp(pos, argv[0], strlen(argv[0])) == 0);
+	CHECK(strncmp(pos + strlen(argv[0]), ":3\n", 3) == 0);
+	CHECK(strstr(report, "\tclose\t") == NULL);
+
+	clear_report();
+	read_report();
+	snprintf(line, sizeof(line), "\n%d\t", INVALID_SYSCALL);
+	CHECK(strstr(report, line) == NULL);
+
+	printf("Test unsupported syscalls passed.\n");
+	return 0;
+}
@@ -0,0 +1,178 @@
+// SPDX-License-Identifier: MPL-2.0
+
+#define _GNU_SOURCE
+#include <errno.h>
+#include <pthread.h>
+#include <stdint.h>
+#include <stdio.h>
+#include <stdlib.h>
+#include <string.h>
+#include <sys/syscall.h>
+#include <time.h>
+#include <unistd.h>
+#include <linux/futex.h>
+
+#define CHECK(cond)                                                 \
+	do {                                                        \
+		if (!(cond)) {                                      \
+			fprintf(stderr, "%s:%d: `%s` fails: %s\n",  \
+				__FILE__, __LINE__, #cond,          \
+				strerror(errno));                   \
+			exit(EXIT_FAILURE);                         \
+		}                                                   \
+	} while (0)
+
+static long futex(uint32_t *uaddr, int op, uint32_t val,
+		  const struct timespec *timeout, uint32_t *uaddr2,
+		  uint32_t val3)
+{
+	return syscall(SYS_futex, uaddr, op, val, timeout, uaddr2, val3);
+}
+
+static long cmp_requeue(uint32_t *uaddr, int nwakes, int nrequeues,
+			uint32_t *uaddr2, uint32_t val)
+{
+	return syscall(SYS_futex, uaddr, FUTEX_CMP_REQUEUE_PRIVATE, nwakes,
+		       (long)nrequeues, uaddr2, val);
+}
+
+static uint32_t futex1;
+static uint32_t futex2;
+
+// Waits on `futex1` until it is woken.
+static void *waiter(void *arg)
+{
+	CHECK(futex(&futex1, FUTEX_WAIT_PRIVATE, 0, NULL, NULL, 0) == 0);
+	return NULL;
+}
+
+static void test_wait_bitset(void)
+{
+	struct timespec timeout = { .tv_nsec = 100000000 };
+	struct timespec deadline;
+
+	// The values mismatch.
+	futex1 = 1;
+	CHECK(futex(&futex1, FUTEX_WAIT_PRIVATE, 0, NULL, NULL, 0) == -1 &&
+	      errno == EAGAIN);
+
+	// The relative timeout of FUTEX_WAIT expires.
+	futex1 = 0;
+	CHECK(futex(&futex1, FUTEX_WAIT_PRIVATE, 0, &timeout, NULL, 0) == -1 &&
+	      errno == ETIMEDOUT);
+
+	// The bitset must not be zero.
+	CHECK(futex(&futex1, FUTEX_WAIT_BITSET_PRIVATE, 0, NULL, NULL, 0) ==
+		      -1 &&
+	      errno == EINVAL);
+
+	// The absolute timeout of FUTEX_WAIT_BITSET expires.
+	clock_gettime(CLOCK_REALTIME, &deadline);
+	deadline.tv_sec += 1;
+	CHECK(futex(&futex1,
+		    FUTEX_WAIT_BITSET_PRIVATE | FUTEX_CLOCK_REALTIME, 0,
+		    &deadline, NULL, FUTEX_BITSET_MATCH_ANY) == -1 &&
+	      errno == ETIMEDOUT);
+
+	// FUTEX_CLOCK_REALTIME is not allowed for the other operations.
+	CHECK(futex(&futex1, FUTEX_WAKE_PRIVATE | FUTEX_CLOCK_REALTIME, 1,
+		    NULL, NULL, 0) == -1 &&
+	      errno == ENOSYS);
+}
+
+static void test_cmp_requeue(void)
+{
+	pthread_t threads[2];
+
+	futex1 = 0;
+	futex2 = 0;
+	for (int i = 0; i < 2; i++)
+		CHECK(pthread_create(&threads[i], NULL, waiter, NULL) == 0);
+	usleep(100000);
+
+	// The value mismatches.
+	CHECK(cmp_requeue(&futex1, 1, 1, &futex2, 1) == -1 && errno == EAGAIN);
+
+	// One waiter is woken and the other is requeued.
+	CHECK(cmp_requeue(&futex1, 1, 1, &futex2, 0) == 2);
+	CHECK(futex(&futex1, FUTEX_WAKE_PRIVATE, 1, NULL, NULL, 0) == 0);
+	CHECK(futex(&futex2, FUTEX_WAKE_PRIVATE, 1, NULL, NULL, 0) == 1);
+
+	for (int i = 0; i < 2; i++)
+		CHECK(pthread_join(threads[i], NULL) == 0);
+}
+
+static pthread_mutex_t mutex;
+
+// Locks the mutex and exits without unlocking it.
+static void *dying_owner(void *arg)
+{
+	CHECK(pthread_mutex_lock(&mutex) == 0);
+	return NULL;
+}
+
+// Locks the mutex and unlocks it after `arg` milliseconds.
+static void *sleeping_owner(void *arg)
+{
+	CHECK(pthread_mutex_lock(&mutex) == 0);
+	usleep((long)arg * 1000);
+	CHECK(pthread_mutex_unlock(&mutex) == 0);
+	return NULL;
+}
+
+static void init_mutex(int robust, int protocol)
+{
+	pthread_mutexattr_t attr;
+
+	CHECK(pthread_mutexattr_init(&attr) == 0);
+	CHECK(pthread_mutexattr_setrobust(&attr, robust) == 0);
+	CHECK(pthread_mutexattr_setprotocol(&attr, protocol) == 0);
+	CHECK(pthread_mutex_init(&mutex, &attr) == 0);
+	CHECK(pthread_mutexattr_destroy(&attr) == 0);
+}
+
+static void test_robust_mutex(int protocol)
+{
+	pthread_t thread;
+
+	init_mutex(PTHREAD_MUTEX_ROBUST, protocol);
+	CHECK(pthread_create(&thread, NULL, dying_owner, NULL) == 0);
+	CHECK(pthread_join(thread, NULL) == 0);
+
+	CHECK(pthread_mutex_lock(&mutex) == EOWNERDEAD);
+	CHECK(pthread_mutex_consistent(&mutex) == 0);
+	CHECK(pthread_mutex_unlock(&mutex) == 0);
+
+	CHECK(pthread_mutex_lock(&mutex) == 0);
+	CHECK(pthread_mutex_unlock(&mutex) == 0);
+	CHECK(pthread_mutex_destroy(&mutex) == 0);
+}
+
+static void test_pi_mutex(void)
+{
+	pthread_t thread;
+
+	init_mutex(PTHREAD_MUTEX_STALLED, PTHREAD_PRIO_INHERIT);
+	CHECK(pthread_create(&thread, NULL, sleeping_owner, (void *)200) == 0);
+	usleep(100000);
+
+	CHECK(pthread_mutex_trylock(&mutex) == EBUSY);
+	// The mutex is handed over once it is unlocked.
+	CHECK(pthread_mutex_lock(&mutex) == 0);
+	CHECK(pthread_mutex_unlock(&mutex) == 0);
+
+	CHECK(pthread_join(thread, NULL) == 0);
+	CHECK(pthread_mutex_destroy(&mutex) == 0);
+}
+
+int main(void)
+{
+	test_wait_bitset();
+	test_cmp_requeue();
+	test_robust_mutex(PTHREAD_PRIO_NONE);
+	test_robust_mutex(PTHREAD_PRIO_INHERIT);
+	test_pi_mutex();
+
+	printf("futex test passed\n");
+	return 0;
+}
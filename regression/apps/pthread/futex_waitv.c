@@ -0,0 +1,148 @@
+// SPDX-License-Identifier: MPL-2.0
+
+#define _GNU_SOURCE
+#include <errno.h>
+#include <pthread.h>
+#include <signal.h>
+#include <stdint.h>
+#include <stdio.h>
+#include <stdlib.h>
+#include <string.h>
+#include <sys/syscall.h>
+#include <sys/time.h>
+#include <time.h>
+#include <unistd.h>
+#include <linux/futex.h>
+
+#ifndef SYS_futex_waitv
+#define SYS_futex_waitv 449
+#endif
+
+#define FUTEX2_SIZE_U32 0x02
+#define FUTEX2_PRIVATE 128
+
+struct waitv {
+	uint64_t val;
+	uint64_t uaddr;
+	uint32_t flags;
+	uint32_t __reserved;
+};
+
+#define NR_FUTEXES 2
+
+static uint32_t futexes[NR_FUTEXES];
+static struct waitv waiters[NR_FUTEXES];
+
+static long futex_waitv(struct timespec *deadline)
+{
+	return syscall(SYS_futex_waitv, waiters, NR_FUTEXES, 0, deadline,
+		       CLOCK_MONOTONIC);
+}
+
+static void init_waiters(void)
+{
+	for (int i = 0; i < NR_FUTEXES; i++) {
+		futexes[i] = 0;
+		waiters[i].val = 0;
+		waiters[i].uaddr = (uintptr_t)&futexes[i];
+		waiters[i].flags = FUTEX2_SIZE_U32 | FUTEX2_PRIVATE;
+		waiters[i].__reserved = 0;
+	}
+}
+
+static void deadline_after(struct timespec *deadline, long ms)
+{
+	clock_gettime(CLOCK_MONOTONIC, deadline);
+	deadline->tv_sec += ms / 1000;
+	deadline->tv_nsec += (ms % 1000) * 1000000;
+	if (deadline->tv_nsec >= 1000000000) {
+		deadline->tv_sec += 1;
+		deadline->tv_nsec -= 1000000000;
+	}
+}
+
+// Wakes the second futex after `arg` milliseconds.
+static void *waker(void *arg)
+{
+	usleep((long)arg * 1000);
+	futexes[1] = 1;
+	syscall(SYS_futex, &futexes[1], FUTEX_WAKE_PRIVATE, 1, NULL, NULL, 0);
+	return NULL;
+}
+
+static void handler(int sig)
+{
+}
+
+// Interrupts the current thread with SIGALRM after 100 milliseconds.
+static void alarm_after_100ms(int flags)
+{
+	struct sigaction sa;
+	struct itimerval timer = { .it_value = { .tv_usec = 100000 } };
+
+	memset(&sa, 0, sizeof(sa));
+	sa.sa_handler = handler;
+	sa.sa_flags = flags;
+	sigemptyset(&sa.sa_mask);
+	if (sigaction(SIGALRM, &sa, NULL) < 0 ||
+	    setitimer(ITIMER_REAL, &timer, NULL) < 0) {
+		perror("alarm");
+		exit(EXIT_FAILURE);
+	}
+}
+
+#define CHECK(cond)                                                 \
+	do {                                                        \
+		if (!(cond)) {                                      \
+			fprintf(stderr, "%s:%d: `%s` fails: %s\n",  \
+				__FILE__, __LINE__, #cond,          \
+				strerror(errno));                   \
+			exit(EXIT_FAILURE);                         \
+		}                                                   \
+	} while (0)
+
+int main(void)
+{
+	struct timespec deadline;
+	pthread_t thread;
+
+	// The values mismatch.
+	init_waiters();
+	waiters[1].val = 1;
+	CHECK(futex_waitv(NULL) == -1 && errno == EAGAIN);
+
+	// The deadline expires.
+	init_waiters();
+	deadline_after(&deadline, 100);
+	CHECK(futex_waitv(&deadline) == -1 && errno == ETIMEDOUT);
+
+	// The second futex is woken.
+	init_waiters();
+	CHECK(pthread_create(&thread, NULL, waker, (void *)100) == 0);
+	CHECK(futex_waitv(NULL) == 1);
+	CHECK(pthread_join(thread, NULL) == 0);
+
+	// The wait is interrupted by a handler without SA_RESTART.
+	init_waiters();
+	alarm_after_100ms(0);
+	deadline_after(&deadline, 1000);
+	CHECK(futex_waitv(&deadline) == -1 && errno == EINTR);
+
+	// The wait is restarted after a handler with SA_RESTART, and still
+	// sees the wake-up that happens after the signal.
+	init_waiters();
+	alarm_after_100ms(SA_RESTART);
+	CHECK(pthread_create(&thread, NULL, waker, (void *)300) == 0);
+	deadline_after(&deadline, 1000);
+	CHECK(futex_waitv(&deadline) == 1);
+	CHECK(pthread_join(thread, NULL) == 0);
+
+	// The restarted wait still expires at the same deadline.
+	init_waiters();
+	alarm_after_100ms(SA_RESTART);
+	deadline_after(&deadline, 300);
+	CHECK(futex_waitv(&deadline) == -1 && errno == ETIMEDOUT);
+
+	printf("futex_waitv test passed\n");
+	return 0;
+}
@@ -0,0 +1,157 @@
+// SPDX-License-Identifier: MPL-2.0
+
+#define _GNU_SOURCE
+
+#include <errno.h>
+#include <linux/capability.h>
+#include <sched.h>
+#include <stdio.h>
+#include <stdlib.h>
+#include <string.h>
+#include <sys/resource.h>
+#include <sys/syscall.h>
+#include <sys/wait.h>
+#include <time.h>
+#include <unistd.h>
+
+#define CHECK(cond)                                                       \
+	do {                                                              \
+		if (!(cond)) {                                            \
+			fprintf(stderr, "%s:%d: check failed: %s (%s)\n", \
+				__FILE__, __LINE__, #cond,                \
+				strerror(errno));                         \
+			exit(1);                                          \
+		}                                                         \
+	} while (0)
+
+static int set_policy(int policy, int priority)
+{
+	struct sched_param param = { .sched_priority = priority };
+
+	return sched_setscheduler(0, policy, &param);
+}
+
+static int get_priority(void)
+{
+	struct sched_param param;
+
+	CHECK(sched_getparam(0, &param) == 0);
+	return param.sched_priority;
+}
+
+static void drop_capabilities(void)
+{
+	struct __user_cap_header_struct header = {
+		.version = _LINUX_CAPABILITY_VERSION_3,
+		.pid = 0,
+	};
+	struct __user_cap_data_struct data[2];
+
+	memset(data, 0, sizeof(data));
+	CHECK(syscall(SYS_capset, &header, data) == 0);
+}
+
+static void test_priority_range(void)
+{
+	CHECK(sched_get_priority_max(SCHED_FIFO) == 99);
+	CHECK(sched_get_priority_min(SCHED_FIFO) == 1);
+	CHECK(sched_get_priority_max(SCHED_RR) == 99);
+	CHECK(sched_get_priority_min(SCHED_RR) == 1);
+	CHECK(sched_get_priority_max(SCHED_OTHER) == 0);
+	CHECK(sched_get_priority_min(SCHED_OTHER) == 0);
+	CHECK(sched_get_priority_max(-1) == -1 && errno == EINVAL);
+}
+
+static void test_set_policy(void)
+{
+	struct sched_param param = { .sched_priority = 30 };
+	struct timespec interval;
+	pid_t pid;
+	int status;
+
+	CHECK(sched_getscheduler(0) == SCHED_OTHER);
+	CHECK(get_priority() == 0);
+
+	CHECK(set_policy(SCHED_FIFO, 0) == -1 && errno == EINVAL);
+	CHECK(set_policy(SCHED_FIFO, 100) == -1 && errno == EINVAL);
+	CHECK(set_policy(SCHED_OTHER, 1) == -1 && errno == EINVAL);
+	CHECK(set_policy(-1, 0) == -1 && errno == EINVAL);
+
+	CHECK(set_policy(SCHED_FIFO, 10) == 0);
+	CHECK(sched_getscheduler(0) == SCHED_FIFO);
+	CHECK(get_priority() == 10);
+	CHECK(sched_rr_get_interval(0, &interval) == 0);
+	CHECK(interval.tv_sec == 0 && interval.tv_nsec == 0);
+
+	CHECK(sched_setparam(0, &param) == 0);
+	CHECK(sched_getscheduler(0) == SCHED_FIFO);
+	CHECK(get_priority() == 30);
+
+	CHECK(set_policy(SCHED_RR, 20) == 0);
+	CHECK(sched_getscheduler(0) == SCHED_RR);
+	CHECK(get_priority() == 20);
+	CHECK(sched_rr_get_interval(0, &interval) == 0);
+	CHECK(interval.tv_sec > 0 || interval.tv_nsec > 0);
+
+	// The child inherits the policy and the priority.
+	pid = fork();
+	CHECK(pid >= 0);
+	if (pid == 0) {
+		CHECK(sched_getscheduler(0) == SCHED_RR);
+		CHECK(get_priority() == 20);
+		exit(0);
+	}
+	CHECK(waitpid(pid, &status, 0) == pid);
+	CHECK(WIFEXITED(status) && WEXITSTATUS(status) == 0);
+
+	CHECK(set_policy(SCHED_OTHER, 0) == 0);
+	CHECK(sched_getscheduler(0) == SCHED_OTHER);
+	CHECK(get_priority() == 0);
+}
+
+static void test_rlimit_rtprio(void)
+{
+	struct rlimit rlimit = { .rlim_cur = 5, .rlim_max = 5 };
+	pid_t pid;
+	int status;
+
+	pid = fork();
+	CHECK(pid >= 0);
+	if (pid == 0) {
+		drop_capabilities();
+
+		rlimit.rlim_cur = rlimit.rlim_max = 0;
+		CHECK(setrlimit(RLIMIT_RTPRIO, &rlimit) == 0);
+		CHECK(set_policy(SCHED_FIFO, 1) == -1 && errno == EPERM);
+		CHECK(set_policy(SCHED_OTHER, 0) == 0);
+		exit(0);
+	}
+	CHECK(waitpid(pid, &status, 0) == pid);
+	CHECK(WIFEXITED(status) && WEXITSTATUS(status) == 0);
+
+	pid = fork();
+	CHECK(pid >= 0);
+	if (pid == 0) {
+		rlimit.rlim_cur = rlimit.rlim_max = 5;
+		CHECK(setrlimit(RLIMIT_RTPRIO, &rlimit) == 0);
+		drop_capabilities();
+
+		CHECK(set_policy(SCHED_FIFO, 6) == -1 && errno == EPERM);
+		CHECK(set_policy(SCHED_FIFO, 5) == 0);
+		CHECK(set_policy(SCHED_RR, 3) == 0);
+		CHECK(set_policy(SCHED_OTHER, 0) == 0);
+		exit(0);
+	}
+	CHECK(waitpid(pid, &status, 0) == pid);
+	CHECK(WIFEXITED(status) && WEXITSTATUS(status) == 0);
+}
+
+int main(void)
+{
+	test_priority_range();
+	test_set_policy();
+	test_rlimit_rtprio();
+
+	printf("All sched_policy tests passed.\n");
+	return 0;
+}
@@ -23,5 +23,8 @@ echo "Start network test......"
 ./http_client
 ./tcp_err
 ./udp_err
+./udp_mmsg
+./iface_addr
+./pktgen
 
 echo "All network test passed"
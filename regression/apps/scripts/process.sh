@@ -11,20 +11,62 @@ echo "Start process test......"
 # These test programs are sorted by name.
 tests="
 clone3/clone_process
+cpu_affinity/irq_affinity
+cpu_affinity/sched_affinity
+execve/binfmt_misc
 execve/execve
 eventfd2/eventfd2
+file_io/copy_range
+file_io/fadvise
+file_io/fallocate
+file_io/file_lock
+file_io/fsync
+file_io/inotify
+file_io/io_uring
+file_io/overlayfs
+file_io/partial_copy
+file_io/splice
+file_io/umount
+file_io/writeback
+file_io/xattr
 fork/fork
+fork_c/clofork
 fork_c/fork
 getpid/getpid
 hello_pie/hello
 hello_world/hello_world
 itimer/setitimer
 itimer/timer_create
+itimer/timerfd
+itimer/virtual_time
+mmap/compaction
+mmap/dev_fb
+mmap/dev_zero
+mmap/droppable
+mmap/madvise
 mmap/map_shared_anon
+mmap/memfd
+mmap/mlock
+mmap/mremap
+mmap/oom
+mmap/pkey
+mmap/proc_maps
+mmap/rss
+mmap/shm
+mmap/stack
+mmap/swap
+mmap/userfaultfd
+procfs/oops
+procfs/unsupported_syscalls
+pthread/futex
+pthread/futex_waitv
 pthread/pthread_test
 pty/open_pty
+sched/sched_policy
 signal_c/parent_death_signal
+signal_c/ptrace
 signal_c/signal_test
+signal_c/signalfd
 "
 
 for testcase in ${tests}
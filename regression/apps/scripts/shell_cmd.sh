@@ -36,4 +36,10 @@ rmdir foo
 echo "Hello world from asterinas" > hello.txt
 rm hello.txt
 
+grep MemTotal /proc/meminfo
+grep VmRSS /proc/self/status
+
+df /
+stat -f /proc
+
 cd ..
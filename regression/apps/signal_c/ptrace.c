@@ -0,0 +1,148 @@
+// SPDX-License-Identifier: MPL-2.0
+
+#define _GNU_SOURCE
+#include <errno.h>
+#include <signal.h>
+#include <stddef.h>
+#include <stdio.h>
+#include <stdlib.h>
+#include <string.h>
+#include <sys/ptrace.h>
+#include <sys/syscall.h>
+#include <sys/user.h>
+#include <sys/wait.h>
+#include <unistd.h>
+
+#define CHECK(cond)                                                 \
+	do {                                                        \
+		if (!(cond)) {                                      \
+			fprintf(stderr, "%s:%d: `%s` fails: %s\n",  \
+				__FILE__, __LINE__, #cond,          \
+				strerror(errno));                   \
+			exit(EXIT_FAILURE);                         \
+		}                                                   \
+	} while (0)
+
+#define ORIGINAL_VALUE 0x1234567890abcdefL
+#define POKED_VALUE 0x0fedcba987654321L
+#define FAKE_PID 12345
+
+static volatile long value = ORIGINAL_VALUE;
+
+static void run_tracee(void)
+{
+	if (ptrace(PTRACE_TRACEME, 0, NULL, NULL) != 0)
+		exit(1);
+	// A thread cannot be traced twice.
+	if (ptrace(PTRACE_TRACEME, 0, NULL, NULL) != -1 || errno != EPERM)
+		exit(2);
+
+	raise(SIGSTOP);
+
+	// The tracer changes the return value of the syscall.
+	if (syscall(SYS_getpid) != FAKE_PID)
+		exit(3);
+	if (value != POKED_VALUE)
+		exit(4);
+	exit(0);
+}
+
+// Resumes the tracee until the entry or the exit of the next `getpid()`.
+static void wait_getpid_stop(pid_t pid, struct user_regs_struct *regs)
+{
+	int status;
+
+	for (;;) {
+		CHECK(ptrace(PTRACE_SYSCALL, pid, NULL, NULL) == 0);
+		CHECK(waitpid(pid, &status, 0) == pid);
+		CHECK(WIFSTOPPED(status) && WSTOPSIG(status) == (SIGTRAP | 0x80));
+		CHECK(ptrace(PTRACE_GETREGS, pid, NULL, regs) == 0);
+		if (regs->orig_rax == SYS_getpid)
+			return;
+	}
+}
+
+int main(void)
+{
+	struct user_regs_struct regs;
+	siginfo_t info;
+	pid_t pid;
+	long word;
+	int status;
+
+	// The caller does not trace any thread.
+	errno = 0;
+	CHECK(ptrace(PTRACE_PEEKDATA, getpid(), &value, NULL) == -1 &&
+	      errno == ESRCH);
+
+	pid = fork();
+	CHECK(pid >= 0);
+	if (pid == 0)
+		run_tracee();
+
+	CHECK(waitpid(pid, &status, 0) == pid);
+	CHECK(WIFSTOPPED(status) && WSTOPSIG(status) == SIGSTOP);
+	CHECK(ptrace(PTRACE_GETSIGINFO, pid, NULL, &info) == 0);
+	CHECK(info.si_signo == SIGSTOP);
+
+	// The memory of the tracee is accessible.
+	errno = 0;
+	word = ptrace(PTRACE_PEEKDATA, pid, &value, NULL);
+	CHECK(errno == 0 && word == ORIGINAL_VALUE);
+	CHECK(ptrace(PTRACE_POKEDATA, pid, &value, POKED_VALUE) == 0);
+	errno = 0;
+	word = ptrace(PTRACE_PEEKDATA, pid, &value, NULL);
+	CHECK(errno == 0 && word == POKED_VALUE);
+	CHECK(value == ORIGINAL_VALUE);
+
+	// The registers are accessible, both as a whole and one by one.
+	CHECK(ptrace(PTRACE_GETREGS, pid, NULL, &regs) == 0);
+	errno = 0;
+	word = ptrace(PTRACE_PEEKUSER, pid,
+		      (void *)offsetof(struct user_regs_struct, rip), NULL);
+	CHECK(errno == 0 && word == (long)regs.rip);
+	errno = 0;
+	CHECK(ptrace(PTRACE_PEEKUSER, pid, (void *)1, NULL) == -1 &&
+	      errno == EIO);
+	errno = 0;
+	CHECK(ptrace(PTRACE_PEEKUSER, pid, (void *)0xfffffffffffffff8UL,
+		     NULL) == -1 &&
+	      errno == EIO);
+	CHECK(ptrace(PTRACE_POKEUSER, pid, (void *)0xfffffffffffffff8UL,
+		     0) == -1 &&
+	      errno == EIO);
+
+	// The instruction pointer and the FS base must be user space addresses.
+	CHECK(ptrace(PTRACE_POKEUSER, pid,
+		     (void *)offsetof(struct user_regs_struct, rip),
+		     0x8000000000000000UL) == -1 &&
+	      errno == EIO);
+	CHECK(ptrace(PTRACE_POKEUSER, pid,
+		     (void *)offsetof(struct user_regs_struct, fs_base),
+		     0xffff800000000000UL) == -1 &&
+	      errno == EIO);
+	errno = 0;
+	word = ptrace(PTRACE_PEEKUSER, pid,
+		      (void *)offsetof(struct user_regs_struct, rip), NULL);
+	CHECK(errno == 0 && word == (long)regs.rip);
+
+	CHECK(ptrace(PTRACE_SETOPTIONS, pid, NULL, PTRACE_O_TRACESYSGOOD) ==
+	      0);
+
+	// The syscall-entry stop sees the syscall number and `-ENOSYS` in `rax`.
+	wait_getpid_stop(pid, &regs);
+	CHECK(regs.rax == (unsigned long)-ENOSYS);
+
+	// The syscall-exit stop sees the return value, which can be changed.
+	wait_getpid_stop(pid, &regs);
+	CHECK(regs.rax == (unsigned long)pid);
+	regs.rax = FAKE_PID;
+	CHECK(ptrace(PTRACE_SETREGS, pid, NULL, &regs) == 0);
+
+	CHECK(ptrace(PTRACE_CONT, pid, NULL, NULL) == 0);
+	CHECK(waitpid(pid, &status, 0) == pid);
+	CHECK(WIFEXITED(status) && WEXITSTATUS(status) == 0);
+
+	printf("ptrace test passed\n");
+	return 0;
+}
@@ -0,0 +1,109 @@
+// SPDX-License-Identifier: MPL-2.0
+
+#define _GNU_SOURCE
+#include <errno.h>
+#include <poll.h>
+#include <signal.h>
+#include <stdint.h>
+#include <stdio.h>
+#include <stdlib.h>
+#include <string.h>
+#include <sys/epoll.h>
+#include <sys/eventfd.h>
+#include <sys/signalfd.h>
+#include <unistd.h>
+
+#define CHECK(cond)                                                 \
+	do {                                                        \
+		if (!(cond)) {                                      \
+			fprintf(stderr, "%s:%d: `%s` fails: %s\n",  \
+				__FILE__, __LINE__, #cond,          \
+				strerror(errno));                   \
+			exit(EXIT_FAILURE);                         \
+		}                                                   \
+	} while (0)
+
+static void test_eventfd(void)
+{
+	uint64_t val;
+	int fd;
+
+	fd = eventfd(2, EFD_SEMAPHORE | EFD_NONBLOCK);
+	CHECK(fd >= 0);
+
+	// A semaphore is decremented by one for each read.
+	CHECK(read(fd, &val, sizeof(val)) == sizeof(val) && val == 1);
+	CHECK(read(fd, &val, sizeof(val)) == sizeof(val) && val == 1);
+	CHECK(read(fd, &val, sizeof(val)) == -1 && errno == EAGAIN);
+
+	// The maximum value cannot be written.
+	val = UINT64_MAX;
+	CHECK(write(fd, &val, sizeof(val)) == -1 && errno == EINVAL);
+	val = UINT64_MAX - 1;
+	CHECK(write(fd, &val, sizeof(val)) == sizeof(val));
+	val = 1;
+	CHECK(write(fd, &val, sizeof(val)) == -1 && errno == EAGAIN);
+
+	CHECK(close(fd) == 0);
+}
+
+static void test_signalfd(void)
+{
+	struct signalfd_siginfo info[2];
+	struct epoll_event event = { .events = EPOLLIN };
+	struct pollfd pfd;
+	sigset_t mask;
+	int fd, epfd;
+
+	sigemptyset(&mask);
+	sigaddset(&mask, SIGUSR1);
+	sigaddset(&mask, SIGUSR2);
+	CHECK(sigprocmask(SIG_BLOCK, &mask, NULL) == 0);
+
+	sigdelset(&mask, SIGUSR2);
+	fd = signalfd(-1, &mask, SFD_NONBLOCK | SFD_CLOEXEC);
+	CHECK(fd >= 0);
+	CHECK(read(fd, info, sizeof(info)) == -1 && errno == EAGAIN);
+	CHECK(read(fd, info, sizeof(info[0]) - 1) == -1 && errno == EINVAL);
+
+	epfd = epoll_create1(0);
+	CHECK(epfd >= 0);
+	CHECK(epoll_ctl(epfd, EPOLL_CTL_ADD, fd, &event) == 0);
+	CHECK(epoll_wait(epfd, &event, 1, 0) == 0);
+
+	// The signals out of the mask are not accepted.
+	CHECK(kill(getpid(), SIGUSR2) == 0);
+	CHECK(epoll_wait(epfd, &event, 1, 0) == 0);
+
+	// The file becomes readable once a signal in the mask is pending.
+	CHECK(kill(getpid(), SIGUSR1) == 0);
+	CHECK(epoll_wait(epfd, &event, 1, 1000) == 1 &&
+	      (event.events & EPOLLIN));
+	CHECK(read(fd, info, sizeof(info)) == sizeof(info[0]));
+	CHECK(info[0].ssi_signo == SIGUSR1);
+	CHECK(epoll_wait(epfd, &event, 1, 0) == 0);
+
+	// The pending signal is accepted after the mask is updated.
+	sigaddset(&mask, SIGUSR2);
+	CHECK(signalfd(fd, &mask, 0) == fd);
+	pfd.fd = fd;
+	pfd.events = POLLIN;
+	CHECK(poll(&pfd, 1, 0) == 1 && (pfd.revents & POLLIN));
+	CHECK(read(fd, info, sizeof(info)) == sizeof(info[0]));
+	CHECK(info[0].ssi_signo == SIGUSR2);
+
+	CHECK(signalfd(STDOUT_FILENO, &mask, 0) == -1 && errno == EINVAL);
+
+	CHECK(close(epfd) == 0);
+	CHECK(close(fd) == 0);
+	CHECK(sigprocmask(SIG_UNBLOCK, &mask, NULL) == 0);
+}
+
+int main(void)
+{
+	test_eventfd();
+	test_signalfd();
+
+	printf("signalfd test passed\n");
+	return 0;
+}
@@ -6,6 +6,11 @@
 # The positional argument $1 is the scheme.
 # A switch "-ovmf" can be passed as an argument to enable OVMF.
 # The enrivonmental variable VSOCK can be passed as 1 to trigger vsock module.
+# The enrivonmental variable GPU can be passed as 1 to add a virtio-gpu device.
+# The enrivonmental variable SATA can be passed as 1 to attach the disks to the AHCI
+# controller of q35, instead of using virtio-blk.
+# The enrivonmental variable NIC can be passed as e1000 or e1000e to use the NIC model,
+# instead of virtio-net.
 
 RAND_PORT_NUM1=$(shuf -i 1024-65535 -n 1)
 RAND_PORT_NUM2=$(shuf -i 1024-65535 -n 1)
@@ -37,13 +42,30 @@ if [ "$1" = "iommu" ]; then
     "
 fi
 
+if [ "$SATA" = "1" ]; then
+    BLOCK_DEVICE_ARGS="\
+        -device ide-hd,drive=x0,bus=ide.0,serial=vext2 \
+        -device ide-hd,drive=x1,bus=ide.1,serial=vexfat \
+    "
+else
+    BLOCK_DEVICE_ARGS="\
+        -device virtio-blk-pci,bus=pcie.0,addr=0x6,drive=x0,serial=vext2,disable-legacy=on,disable-modern=off$IOMMU_DEV_EXTRA \
+        -device virtio-blk-pci,bus=pcie.0,addr=0x7,drive=x1,serial=vexfat,disable-legacy=on,disable-modern=off$IOMMU_DEV_EXTRA \
+    "
+fi
+
+if [ -n "$NIC" ]; then
+    NET_DEVICE_ARGS="-device $NIC,netdev=net01"
+else
+    NET_DEVICE_ARGS="-device virtio-net-pci,netdev=net01,disable-legacy=on,disable-modern=off$IOMMU_DEV_EXTRA"
+fi
+
 QEMU_ARGS="\
     $COMMON_QEMU_ARGS \
     -machine q35,kernel-irqchip=split \
-    -device virtio-blk-pci,bus=pcie.0,addr=0x6,drive=x0,serial=vext2,disable-legacy=on,disable-modern=off$IOMMU_DEV_EXTRA \
-    -device virtio-blk-pci,bus=pcie.0,addr=0x7,drive=x1,serial=vexfat,disable-legacy=on,disable-modern=off$IOMMU_DEV_EXTRA \
+    $BLOCK_DEVICE_ARGS \
     -device virtio-keyboard-pci,disable-legacy=on,disable-modern=off$IOMMU_DEV_EXTRA \
-    -device virtio-net-pci,netdev=net01,disable-legacy=on,disable-modern=off$IOMMU_DEV_EXTRA \
+    $NET_DEVICE_ARGS \
     -device virtio-serial-pci,disable-legacy=on,disable-modern=off$IOMMU_DEV_EXTRA \
     -device virtconsole,chardev=mux \
     $IOMMU_EXTRA_ARGS \
@@ -79,6 +101,16 @@ if [ "$VSOCK" = "1" ]; then
     fi
 fi
 
+if [ "$GPU" = "1" ]; then
+    MICROVM_QEMU_ARGS="
+        $MICROVM_QEMU_ARGS \
+        -device virtio-gpu-device \
+    "
+    QEMU_ARGS="
+        $QEMU_ARGS \
+        -device virtio-gpu-pci$IOMMU_DEV_EXTRA \
+    "
+fi
 
 if [ "$1" = "microvm" ]; then
     QEMU_ARGS=$MICROVM_QEMU_ARGS